@@ -0,0 +1,69 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use probe_rs::conformance::{Check, CheckOutcome, ConformanceSuite};
+use probe_rs_cli_util::common_options::ProbeOptions;
+
+/// Run the conformance suite on the core `core` of the target, and print its results.
+///
+/// The report is written as JSON to `output`, if given. Fails if any check failed.
+#[allow(clippy::too_many_arguments)]
+pub fn run_conformance(
+    common: &ProbeOptions,
+    core: usize,
+    scratch_address: u64,
+    scratch_size: u64,
+    flash: Option<(u64, u64)>,
+    skip: &[Check],
+    timeout_ms: u64,
+    output: Option<&Path>,
+) -> Result<()> {
+    let mut suite = ConformanceSuite::new(scratch_address..scratch_address + scratch_size)
+        .core(core)
+        .timeout(Duration::from_millis(timeout_ms));
+    if let Some((address, size)) = flash {
+        suite = suite.expendable_flash(address..address + size);
+    }
+    for &check in skip {
+        suite = suite.skip(check);
+    }
+
+    let report = suite.run(|| common.simple_attach())?;
+
+    if let Some(target) = &report.target {
+        println!("Target: {} (core {})", target, report.core);
+    }
+    for result in &report.results {
+        let outcome = match &result.outcome {
+            CheckOutcome::Pass => "pass".to_string(),
+            CheckOutcome::Fail(reason) => format!("FAIL: {}", reason),
+            CheckOutcome::Skip(reason) => format!("skip: {}", reason),
+        };
+        println!(
+            "{:<12} {:>10.3?}  {}",
+            result.check.name(),
+            result.duration,
+            outcome
+        );
+
+        for measurement in &result.measurements {
+            println!(
+                "{:<12} {:>10.3?}  {}",
+                "", measurement.duration, measurement.name
+            );
+        }
+    }
+
+    if let Some(output) = output {
+        std::fs::write(output, report.to_json())
+            .with_context(|| format!("Failed to write the report to {}", output.display()))?;
+    }
+
+    let failed = report.failures().count();
+    if failed > 0 {
+        anyhow::bail!("{} conformance checks failed", failed);
+    }
+
+    Ok(())
+}
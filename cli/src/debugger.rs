@@ -2,14 +2,20 @@ use crate::common::CliError;
 
 use anyhow::anyhow;
 use capstone::{
-    arch::arm::ArchMode as armArchMode, arch::arm64::ArchMode as aarch64ArchMode,
-    arch::riscv::ArchMode as riscvArchMode, prelude::*, Capstone, Endian,
+    arch::arm::ArchMode as armArchMode,
+    arch::arm::{ArmOperandType, ArmShift},
+    arch::arm64::ArchMode as aarch64ArchMode,
+    arch::riscv::ArchMode as riscvArchMode,
+    arch::ArchOperand,
+    prelude::*,
+    Capstone, Endian,
 };
 use num_traits::Num;
 use probe_rs::{
     architecture::arm::Dump,
     debug::{debug_info::DebugInfo, registers::Registers, stack_frame::StackFrame, VariableName},
-    Core, CoreType, InstructionSet, MemoryInterface, RegisterDescription, RegisterId,
+    register_value_hex, Core, CoreType, InstructionFetch, InstructionSet, MemoryInterface,
+    RegisterDescription, RegisterId,
 };
 use std::fs::File;
 use std::{io::prelude::*, time::Duration};
@@ -121,9 +127,11 @@ impl DebugCli {
 
         cli.add_command(Command {
             name: "status",
-            help_text: "Show current status of CPU",
+            help_text: "Show current status of CPU, pass --no-instruction to not read the instruction which caused a fault",
+
+            function: |cli_data, args| {
+                let read_instruction = !args.contains(&"--no-instruction");
 
-            function: |cli_data, _args| {
                 let status = cli_data.core.status()?;
 
                 println!("Status: {:?}", &status);
@@ -171,6 +179,8 @@ impl DebugCli {
                                             // Get reason for hard fault
                                             let hfsr = cli_data.core.read_word_32(0xE000_ED2C)?;
 
+                                            let mut fault_address = None;
+
                                             if hfsr & (1 << 30) == (1 << 30) {
                                                 println!("-> configurable priority exception has been escalated to hard fault!");
 
@@ -194,6 +204,7 @@ impl DebugCli {
                                                         // Read address from BFAR
                                                         let bfar = cli_data.core.read_word_32(0xE000_ED38)?;
                                                         println!("\t Location       - BFAR: {:#010x}", bfar);
+                                                        fault_address = Some(bfar);
                                                     }
                                                 }
 
@@ -202,6 +213,10 @@ impl DebugCli {
                                                 }
 
                                             }
+
+                                            if read_instruction {
+                                                print_faulting_instruction(&mut cli_data.core, fault_address)?;
+                                            }
                                         }
                                     }
                                 }
@@ -482,10 +497,9 @@ impl DebugCli {
                     let value: u64 = cli_data.core.read_core_reg(register)?;
 
                     println!(
-                        "{:10}: {:#0width$x}",
+                        "{:10}: {}",
                         register.name(),
-                        value,
-                        width = register.format_hex_width()
+                        register_value_hex(register, value.into())
                     );
                 }
 
@@ -719,6 +733,172 @@ impl DebugCli {
     }
 }
 
+/// Print the instruction which caused a fault on a Cortex-M core, and for a memory access the
+/// address it accessed, compared with the `fault_address` from BFAR.
+///
+/// This has to be called while the core is halted at the start of the fault handler, so that
+/// the exception frame is still on the stack and the other registers are unchanged.
+fn print_faulting_instruction(core: &mut Core, fault_address: Option<u32>) -> Result<(), CliError> {
+    // EXC_RETURN in LR selects the stack the exception frame was pushed to.
+    let exc_return: u32 = core.read_core_reg(core.registers().return_address())?;
+    let stack_pointer = if exc_return & (1 << 2) == 0 {
+        core.registers().msp()
+    } else {
+        core.registers().psp()
+    }
+    .ok_or_else(|| anyhow!("The core has no banked stack pointers"))?;
+    let frame_address: u32 = core.read_core_reg(stack_pointer)?;
+
+    // r0, r1, r2, r3, r12, lr, pc and xpsr
+    let mut frame = [0u32; 8];
+    if let Err(error) = core.read_32(frame_address as u64, &mut frame) {
+        println!(
+            "\tInstruction     - exception frame at {:#010x} can't be read: {}",
+            frame_address, error
+        );
+        return Ok(());
+    }
+
+    let (address, bytes) = match core.read_instruction(frame[6] as u64)? {
+        InstructionFetch::Read { address, bytes, .. } => (address, bytes),
+        InstructionFetch::Unreadable { address, reason } => {
+            println!(
+                "\tInstruction     - {:#010x} can't be read: {}",
+                address, reason
+            );
+            return Ok(());
+        }
+    };
+
+    let cs = Capstone::new()
+        .arm()
+        .mode(armArchMode::Thumb)
+        .endian(Endian::Little)
+        .detail(true)
+        .build()
+        .map_err(|err| anyhow!("Error creating capstone: {:?}", err))?;
+
+    let instructions = cs
+        .disasm_count(&bytes, address, 1)
+        .map_err(|err| anyhow!("Error disassembling instructions: {}", err))?;
+
+    let instruction = match instructions.iter().next() {
+        Some(instruction) => instruction,
+        None => {
+            println!("\tInstruction     - {:#010x}: {:02x?}", address, bytes);
+            return Ok(());
+        }
+    };
+
+    let mnemonic = instruction.mnemonic().unwrap_or_default();
+    println!(
+        "\tInstruction     - {:#010x}: {} {}",
+        address,
+        mnemonic,
+        instruction.op_str().unwrap_or_default()
+    );
+
+    // The registers which were stacked have been changed by the exception entry.
+    let stacked_sp = frame_address + 0x20 + ((frame[7] >> 9) & 1) * 4;
+    let mut register_value = |name: &str| -> Result<Option<u32>, CliError> {
+        let index = match name {
+            "r0" => return Ok(Some(frame[0])),
+            "r1" => return Ok(Some(frame[1])),
+            "r2" => return Ok(Some(frame[2])),
+            "r3" => return Ok(Some(frame[3])),
+            "r12" | "ip" => return Ok(Some(frame[4])),
+            "sp" => return Ok(Some(stacked_sp)),
+            "pc" => return Ok(Some((address as u32 & !0b11) + 4)),
+            "sb" => 9,
+            "sl" => 10,
+            "fp" => 11,
+            name => match name.strip_prefix('r').and_then(|index| index.parse().ok()) {
+                Some(index) => index,
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(core.read_core_reg(RegisterId(index))?))
+    };
+
+    let detail = cs
+        .insn_detail(instruction)
+        .map_err(|err| anyhow!("Error reading instruction details: {}", err))?;
+
+    for operand in detail.arch_detail().operands() {
+        let operand = match operand {
+            ArchOperand::ArmOperand(operand) => operand,
+            _ => continue,
+        };
+
+        let memory = match operand.op_type {
+            ArmOperandType::Mem(memory) => memory,
+            _ => continue,
+        };
+
+        let mut effective_address = memory.disp() as u32;
+        let mut values = Vec::new();
+
+        for (register, is_index) in [(memory.base(), false), (memory.index(), true)] {
+            if register.0 == 0 {
+                continue;
+            }
+
+            let name = match cs.reg_name(register) {
+                Some(name) => name,
+                None => return Ok(()),
+            };
+            let value = match register_value(&name)? {
+                Some(value) => value,
+                None => return Ok(()),
+            };
+            values.push(format!("{}={:#x}", name, value));
+
+            let offset = if is_index {
+                let shifted = match operand.shift {
+                    ArmShift::Invalid => value,
+                    ArmShift::Lsl(amount) => value << amount,
+                    _ => return Ok(()),
+                };
+
+                if memory.scale() < 0 {
+                    shifted.wrapping_neg()
+                } else {
+                    shifted
+                }
+            } else {
+                value
+            };
+
+            effective_address = effective_address.wrapping_add(offset);
+        }
+
+        let access = if mnemonic.starts_with("st") || mnemonic.starts_with("push") {
+            "write to"
+        } else {
+            "read from"
+        };
+
+        let comparison = match fault_address {
+            Some(fault_address) if fault_address == effective_address => {
+                " (matches BFAR)".to_string()
+            }
+            Some(fault_address) => format!(" (BFAR is {:#010x})", fault_address),
+            None => String::new(),
+        };
+
+        println!(
+            "\t Access         - with {} {} {:#010x}{}",
+            values.join(", "),
+            access,
+            effective_address,
+            comparison
+        );
+    }
+
+    Ok(())
+}
+
 pub struct CliData<'p> {
     pub core: Core<'p>,
     pub debug_info: Option<DebugInfo>,
@@ -1,4 +1,5 @@
 mod common;
+mod conformance;
 mod debugger;
 mod gdb;
 mod info;
@@ -162,6 +163,40 @@ enum Cli {
         #[structopt(parse(try_from_str = parse_u64))]
         loc: u64,
     },
+    /// Run the conformance suite, which qualifies a target and a probe
+    Conformance {
+        #[structopt(flatten)]
+        shared: CoreOptions,
+
+        #[structopt(flatten)]
+        common: ProbeOptions,
+
+        /// The address of the RAM the suite may use. Its contents are restored afterwards.
+        #[structopt(long, parse(try_from_str = parse_u64))]
+        scratch_address: u64,
+        /// The size of the RAM the suite may use, in bytes.
+        #[structopt(long, parse(try_from_str = parse_u64), default_value = "0x1000")]
+        scratch_size: u64,
+
+        /// The address of flash the suite may program. Flashing is only checked if it is given.
+        #[structopt(long, parse(try_from_str = parse_u64), requires = "flash_size")]
+        flash_address: Option<u64>,
+        /// The size of the flash the suite may program, in bytes.
+        #[structopt(long, parse(try_from_str = parse_u64))]
+        flash_size: Option<u64>,
+
+        /// A check which is skipped, e.g. `reset`. Can be given several times.
+        #[structopt(long)]
+        skip: Vec<probe_rs::conformance::Check>,
+
+        /// The timeout of halting and resetting the core, in milliseconds.
+        #[structopt(long, default_value = "500")]
+        timeout_ms: u64,
+
+        /// Write the report as JSON to this file.
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
     #[clap(subcommand)]
     Chip(Chip),
 }
@@ -246,6 +281,26 @@ fn main() -> Result<()> {
             common,
             loc,
         } => trace_u32_on_target(&shared, &common, loc),
+        Cli::Conformance {
+            shared,
+            common,
+            scratch_address,
+            scratch_size,
+            flash_address,
+            flash_size,
+            skip,
+            timeout_ms,
+            output,
+        } => conformance::run_conformance(
+            &common,
+            shared.core,
+            scratch_address,
+            scratch_size,
+            flash_address.zip(flash_size),
+            &skip,
+            timeout_ms,
+            output.as_deref(),
+        ),
         Cli::Chip(Chip::List) => print_families(io::stdout()).map_err(Into::into),
         Cli::Chip(Chip::Info { name }) => print_chip_info(name, io::stdout()),
     }
@@ -334,6 +389,8 @@ fn download_program_fast(
             list_probes: false,
             disable_progressbars,
             disable_double_buffering,
+            allow_boot_sector_erase: false,
+            validate_image: false,
             reset_halt: false,
             log: None,
             restore_unwritten: false,
@@ -32,6 +32,8 @@ pub fn run(
             list_probes: false,
             disable_progressbars: false,
             disable_double_buffering,
+            allow_boot_sector_erase: false,
+            validate_image: false,
             reset_halt: false,
             log: None,
             restore_unwritten: false,
@@ -872,6 +872,7 @@ impl<P: ProtocolAdapter> DebugAdapter<P> {
                         if core_status.is_halted() {
                             if self.halt_after_reset
                                 || core_status == CoreStatus::Halted(HaltReason::Breakpoint)
+                                || core_status == CoreStatus::Halted(HaltReason::SoftwareBreakpoint)
                             {
                                 let event_body = Some(StoppedEventBody {
                                     reason: core_status.short_long_status().0.to_owned(),
@@ -2185,6 +2186,9 @@ impl DapStatus for CoreStatus {
                     "breakpoint",
                     "Core halted due to a breakpoint (software or hardware)",
                 ),
+                HaltReason::SoftwareBreakpoint => {
+                    ("breakpoint", "Core halted due to a software breakpoint")
+                }
                 HaltReason::Exception => (
                     "exception",
                     "Core halted due to an exception, e.g. interupt handler",
@@ -2199,6 +2203,7 @@ impl DapStatus for CoreStatus {
                     "Core halted due to a user (debugger client) request",
                 ),
                 HaltReason::External => ("external", "Core halted due to an external request"),
+                HaltReason::Panic => ("panic", "Core halted at a breakpoint on a panic handler"),
                 _other => ("unrecognized", "Core halted: unrecognized cause"),
             },
             CoreStatus::Unknown => ("unknown", "Core status cannot be determined"),
@@ -822,6 +822,23 @@ impl Debugger {
                                             )
                                             .ok();
                                     }
+                                    probe_rs::flashing::ProgressEvent::SlowOperation {
+                                        operation,
+                                        address,
+                                        time,
+                                        expected,
+                                    } => {
+                                        log::warn!(
+                                            "{:?} at {:#010x} took {:?}, more than the expected {:?}",
+                                            operation,
+                                            address,
+                                            time,
+                                            expected
+                                        );
+                                    }
+                                    probe_rs::flashing::ProgressEvent::Timings { .. } => {}
+                                    probe_rs::flashing::ProgressEvent::DataConsumed { .. } => {}
+                                    probe_rs::flashing::ProgressEvent::ImageFinished { .. } => {}
                                 }
                             })
                         } else {
@@ -66,6 +66,18 @@ pub struct FlashOptions {
         programming with timeout errors, try this option."
     )]
     pub disable_double_buffering: bool,
+    #[structopt(
+        long = "allow-boot-sector-erase",
+        help = "Allow erasing sectors which are marked as boot-critical in the target description,\
+        e.g. option bytes or sectors reserved for a bootloader."
+    )]
+    pub allow_boot_sector_erase: bool,
+    #[structopt(
+        long = "validate-image",
+        help = "Check if the image is likely to boot on the target before flashing it, \
+        e.g. that the vector table is in boot memory. Flashing is refused if an error is found."
+    )]
+    pub validate_image: bool,
     #[structopt(
         name = "reset-halt",
         long = "reset-halt",
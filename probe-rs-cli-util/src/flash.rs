@@ -29,6 +29,8 @@ pub fn run_flash_download(
     download_option.dry_run = opt.probe_options.dry_run;
     download_option.do_chip_erase = do_chip_erase;
     download_option.disable_double_buffering = opt.disable_double_buffering;
+    download_option.allow_boot_sector_erase = opt.allow_boot_sector_erase;
+    download_option.validate_image = opt.validate_image;
 
     if !opt.disable_progressbars {
         // Create progress bars.
@@ -134,6 +136,27 @@ pub fn run_flash_download(
                         fp.finish()
                     };
                 }
+                SlowOperation {
+                    operation,
+                    address,
+                    time,
+                    expected,
+                } => {
+                    log::warn!(
+                        "{:?} at {:#010x} took {:?}, more than the expected {:?}",
+                        operation,
+                        address,
+                        time,
+                        expected
+                    );
+                }
+                Timings { timings } => {
+                    log::info!("Flash operation timings: {:?}", timings);
+                }
+                // Only emitted for streamed images.
+                DataConsumed { .. } => {}
+                // Only emitted for download sets.
+                ImageFinished { .. } => {}
             }
         });
 
@@ -0,0 +1,109 @@
+use crate::{CoreType, InstructionSet};
+
+/// Whether a core has a floating point unit, see [`CoreCapabilities::fpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpuSupport {
+    /// The core has no FPU.
+    None,
+    /// The architecture allows an FPU, but whether a core has one is only known after
+    /// attaching to it.
+    Optional,
+    /// The core has an FPU.
+    Present,
+}
+
+/// What a core supports for debugging.
+///
+/// [`CoreType::capabilities`] describes what the architecture of a core type allows, without
+/// attaching to a core: the facts which hold for every core of the type, and the limits for
+/// the facts which differ between cores, e.g. the number of breakpoint comparators. An
+/// attached core reports the same structure with the values measured on it, so that both
+/// can be compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreCapabilities {
+    /// The number of hardware breakpoints.
+    ///
+    /// For a core type, this is the maximum the architecture allows, or `None` if it doesn't
+    /// limit them.
+    pub hardware_breakpoints: Option<u32>,
+    /// True if breakpoints can be set by replacing an instruction in RAM.
+    pub software_breakpoints: bool,
+    /// The number of data watchpoints.
+    ///
+    /// For a core type, this is the maximum the architecture allows, or `None` if it doesn't
+    /// limit them. On RISC-V, watchpoints and breakpoints share the same triggers.
+    pub watchpoints: Option<u32>,
+    /// True if the core can be halted on a reset or an exception with a vector catch.
+    pub vector_catch: bool,
+    /// The instruction sets the core can execute.
+    pub instruction_sets: Vec<InstructionSet>,
+    /// Whether the core has an FPU.
+    pub fpu: FpuSupport,
+    /// The widths in bits of the memory accesses which are supported natively. Wider
+    /// accesses are split into several accesses.
+    pub native_access_widths: Vec<u8>,
+    /// True if the core has to be halted to access its registers.
+    pub register_access_requires_halt: bool,
+}
+
+impl CoreType {
+    /// Returns what the architecture of the core type supports for debugging.
+    ///
+    /// This doesn't need a target. The values which differ between cores of the same type
+    /// are limits, the values of an attached core can be read from the core.
+    pub fn capabilities(&self) -> CoreCapabilities {
+        let cortex_m = |hardware_breakpoints, watchpoints, fpu| CoreCapabilities {
+            hardware_breakpoints: Some(hardware_breakpoints),
+            software_breakpoints: true,
+            watchpoints: Some(watchpoints),
+            vector_catch: true,
+            instruction_sets: vec![InstructionSet::Thumb2],
+            fpu,
+            native_access_widths: vec![8, 16, 32],
+            register_access_requires_halt: true,
+        };
+
+        match self {
+            // The BPU has up to 4 comparators, the DWT up to 4.
+            CoreType::Armv6m => cortex_m(4, 4, FpuSupport::None),
+            // The FPB has up to 127 code comparators, the DWT up to 15.
+            CoreType::Armv7m => cortex_m(127, 15, FpuSupport::None),
+            CoreType::Armv7em | CoreType::Armv8m => cortex_m(127, 15, FpuSupport::Optional),
+            // DBGDIDR and EDDFR allow up to 16 breakpoint and watchpoint register pairs.
+            CoreType::Armv7a => CoreCapabilities {
+                hardware_breakpoints: Some(16),
+                software_breakpoints: true,
+                watchpoints: Some(16),
+                vector_catch: true,
+                instruction_sets: vec![InstructionSet::A32, InstructionSet::Thumb2],
+                fpu: FpuSupport::Optional,
+                native_access_widths: vec![8, 16, 32],
+                register_access_requires_halt: true,
+            },
+            CoreType::Armv8a => CoreCapabilities {
+                hardware_breakpoints: Some(16),
+                software_breakpoints: true,
+                watchpoints: Some(16),
+                vector_catch: true,
+                instruction_sets: vec![
+                    InstructionSet::A64,
+                    InstructionSet::A32,
+                    InstructionSet::Thumb2,
+                ],
+                fpu: FpuSupport::Optional,
+                native_access_widths: vec![8, 16, 32, 64],
+                register_access_requires_halt: true,
+            },
+            CoreType::Riscv => CoreCapabilities {
+                hardware_breakpoints: None,
+                software_breakpoints: true,
+                watchpoints: None,
+                vector_catch: false,
+                instruction_sets: vec![InstructionSet::RV32],
+                fpu: FpuSupport::Optional,
+                native_access_widths: vec![8, 16, 32],
+                register_access_requires_halt: true,
+            },
+        }
+    }
+}
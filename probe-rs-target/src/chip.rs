@@ -1,5 +1,6 @@
 use super::memory::MemoryRegion;
 use crate::CoreType;
+use core::ops::Range;
 use serde::{Deserialize, Serialize};
 
 /// A single chip variant.
@@ -30,6 +31,25 @@ pub struct Chip {
     ///
     /// [`ChipFamily::flash_algorithms`]: crate::ChipFamily::flash_algorithms
     pub flash_algorithms: Vec<String>,
+    /// Accesses which keep the debug connection alive, for chips which drop
+    /// their debug state if the debugger is idle for too long.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub keepalive: Option<Keepalive>,
+    /// Identifiers of the errata of the chip which affect debugging.
+    ///
+    /// probe-rs applies its workarounds for these errata automatically.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "bincode"), serde(skip_serializing_if = "Vec::is_empty"))]
+    pub errata: Vec<String>,
+    /// Memory regions whose accesses have to be prepared by a mediator, e.g. external
+    /// flash which can only be read while its controller is in memory-mapped mode.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "bincode"), serde(skip_serializing_if = "Vec::is_empty"))]
+    pub mediated_regions: Vec<MediatedRegion>,
 }
 
 impl Chip {
@@ -44,13 +64,105 @@ impl Chip {
                 name: "main".to_string(),
                 core_type,
                 core_access_options: CoreAccessOptions::Arm(ArmCoreAccessOptions::default()),
+                reset_scope: ResetScope::default(),
+                hardware_breakpoints: None,
+                watchpoints: None,
             }],
             memory_map: vec![],
             flash_algorithms: vec![],
+            keepalive: None,
+            errata: vec![],
+            mediated_regions: vec![],
         }
     }
 }
 
+/// An access which keeps the debug connection of a chip alive.
+///
+/// Some chips drop their debug state if the debugger is idle for too long, e.g. because
+/// a hart re-enters a low-power state unless the debug module is accessed periodically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keepalive {
+    /// The time in milliseconds after which an idle connection has to be kept alive.
+    pub interval_ms: u64,
+    /// The access which keeps the connection alive.
+    pub action: KeepaliveAction,
+}
+
+/// The access performed to keep a debug connection alive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeepaliveAction {
+    /// Read the `dmstatus` register of the RISC-V debug module.
+    ReadDmstatus,
+    /// Read the `DHCSR` register of the first core.
+    ReadDhcsr,
+    /// Write a 32-bit value to an address in the memory of the first core.
+    WriteWord {
+        /// The address which is written.
+        address: u64,
+        /// The value which is written.
+        value: u32,
+    },
+}
+
+/// A memory region whose accesses are prepared by a mediator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediatedRegion {
+    /// The address range of the region.
+    pub range: Range<u64>,
+    /// The mediator which prepares the accesses.
+    pub mediator: MediatorKind,
+}
+
+/// A mediator which prepares the accesses to a [`MediatedRegion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MediatorKind {
+    /// The QUADSPI controller of STM32 chips.
+    ///
+    /// Reads switch the controller to memory-mapped mode. Writes are routed through the
+    /// data register in indirect write mode, if a command for them is configured.
+    Stm32Quadspi {
+        /// The address of the registers of the controller.
+        registers: u64,
+        /// The value of the `CCR` register for memory-mapped reads. The functional mode
+        /// bits are set by probe-rs.
+        memory_mapped_ccr: u32,
+        /// The value of the `CCR` register for indirect writes, or `None` if the memory
+        /// can't be written. The functional mode bits are cleared by probe-rs.
+        #[serde(default)]
+        indirect_write_ccr: Option<u32>,
+    },
+}
+
+/// A peripheral which can be stopped while the cores are halted, with a bit in a debug
+/// freeze register, e.g. `DBGMCU_APB1_FZ` on STM32 chips.
+///
+/// The register is modified with a read-modify-write, so other bits in it are preserved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeripheralFreeze {
+    /// The name of the peripheral, e.g. `IWDG`.
+    pub peripheral: String,
+    /// Whether the peripheral is a watchdog, which resets the chip if it keeps running
+    /// while the cores are halted.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub watchdog: bool,
+    /// The address of the 32-bit register with the freeze bit.
+    pub register: u64,
+    /// The position of the freeze bit in the register.
+    pub bit: u8,
+    /// Whether the peripheral is stopped while the bit is cleared, instead of set.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub active_low: bool,
+}
+
 /// An individual core inside a chip
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Core {
@@ -64,6 +176,71 @@ pub struct Core {
 
     /// The AP number to access the core
     pub core_access_options: CoreAccessOptions,
+
+    /// The parts of the chip which are reset when this core is reset.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "ResetScope::is_system")
+    )]
+    pub reset_scope: ResetScope,
+
+    /// The number of hardware breakpoint comparators of the core, if it is known.
+    ///
+    /// Must not exceed the limit of [`CoreType::capabilities`].
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub hardware_breakpoints: Option<u32>,
+
+    /// The number of data watchpoint comparators of the core, if it is known.
+    ///
+    /// Must not exceed the limit of [`CoreType::capabilities`].
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub watchpoints: Option<u32>,
+}
+
+/// The parts of a chip which are affected by a reset of a core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResetScope {
+    /// The reset affects the whole chip, including all other cores.
+    ///
+    /// This is the case for most chips, e.g. on the STM32H7 a `SYSRESETREQ`
+    /// from any core resets the whole system.
+    System,
+    /// Only the core itself is reset.
+    Core,
+    /// All cores in the cluster with the given ID are reset.
+    Cluster(usize),
+}
+
+impl ResetScope {
+    /// Returns true if a reset with this scope affects the whole system.
+    pub fn is_system(&self) -> bool {
+        *self == ResetScope::System
+    }
+
+    /// Returns true if a reset with this scope, issued through a core,
+    /// also resets a core which is configured with the `other` scope.
+    pub fn affects(&self, other: &ResetScope) -> bool {
+        match self {
+            ResetScope::System => true,
+            ResetScope::Core => false,
+            ResetScope::Cluster(cluster) => *other == ResetScope::Cluster(*cluster),
+        }
+    }
+}
+
+impl Default for ResetScope {
+    fn default() -> Self {
+        ResetScope::System
+    }
 }
 
 /// The data required to access a core
@@ -91,5 +268,74 @@ pub struct ArmCoreAccessOptions {
 }
 
 /// The data required to access a Risc-V core
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RiscvCoreAccessOptions {}
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RiscvCoreAccessOptions {
+    /// The addresses at which the core can start executing after a reset.
+    ///
+    /// The first entry is the default reset vector, further entries are alternatives,
+    /// e.g. selected by boot pins. Used to check if an image is bootable.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "bincode"), serde(skip_serializing_if = "Vec::is_empty"))]
+    pub reset_vectors: Vec<u64>,
+    /// Deviations of the Debug Module from the RISC-V debug specification.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "RiscvQuirks::is_empty")
+    )]
+    pub quirks: RiscvQuirks,
+    /// The TAP of the Debug Module in the JTAG scan chain.
+    ///
+    /// Only needed on targets whose ARM and RISC-V cores share one debug port, where the
+    /// TAP is selected when switching between the cores, e.g. by writing a vendor specific
+    /// JTAG mux register.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub jtag_tap: Option<usize>,
+}
+
+/// Deviations of a RISC-V Debug Module from the debug specification, which have to be
+/// worked around when debugging the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct RiscvQuirks {
+    /// The Debug Module sets `allresumeack` late, or not at all, after a resume request.
+    ///
+    /// If set, a hart which reports to be running is considered resumed once this many
+    /// milliseconds have passed, even if `allresumeack` was not observed.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub resume_ack_grace_period_ms: Option<u32>,
+    /// `havereset` is not cleared by `ackhavereset`, or is set again while the hart runs.
+    ///
+    /// If set, `havereset` is only acknowledged after a reset requested by the debugger,
+    /// and ignored otherwise.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub sticky_havereset: bool,
+    /// The Debug Module ignores requests until it was deactivated and activated again, and
+    /// `dmactive` only sticks after it was written twice.
+    ///
+    /// If set, the Debug Module is reset this way when attaching, before it is used.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub reactivate_debug_module: bool,
+}
+
+impl RiscvQuirks {
+    /// Returns true if the Debug Module follows the specification, i.e. no quirks are set.
+    pub fn is_empty(&self) -> bool {
+        *self == RiscvQuirks::default()
+    }
+}
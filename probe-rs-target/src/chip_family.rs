@@ -1,6 +1,6 @@
 use crate::CoreAccessOptions;
 
-use super::chip::Chip;
+use super::chip::{Chip, PeripheralFreeze};
 use super::flash_algorithm::RawFlashAlgorithm;
 use jep106::JEP106Code;
 
@@ -10,7 +10,8 @@ use serde::{Deserialize, Serialize};
 ///
 /// This is used for diagnostics, when
 /// an error related to a target description occurs.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TargetDescriptionSource {
     /// The target description is a generic target description,
     /// which just describes a core type (e.g. M4), without any
@@ -55,7 +56,8 @@ impl CoreType {
 }
 
 /// The architecture family of a specific [`CoreType`].
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Architecture {
     /// An ARM core of one of the specific types [`CoreType::Armv6m`], [`CoreType::Armv7m`], [`CoreType::Armv7em`] or [`CoreType::Armv8m`]
     Arm,
@@ -105,6 +107,19 @@ pub struct ChipFamily {
     pub variants: Vec<Chip>,
     /// This vector holds all available algorithms.
     pub flash_algorithms: Vec<RawFlashAlgorithm>,
+    /// The peripherals of the family which can be stopped while the cores are halted.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "bincode"), serde(skip_serializing_if = "Vec::is_empty"))]
+    pub peripheral_freeze: Vec<PeripheralFreeze>,
+    /// The debug logic of the chips runs on a slow clock after a reset, so that they have
+    /// to be attached at a low speed first, see
+    /// [`AttachOptions::slow_clock_attach`](https://docs.rs/probe-rs/latest/probe_rs/struct.AttachOptions.html#method.slow_clock_attach).
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "std::ops::Not::not")
+    )]
+    pub slow_clock_attach: bool,
 
     #[serde(skip, default = "default_source")]
     /// Source of the target description, used for diagnostics
@@ -138,28 +153,62 @@ impl ChipFamily {
             }
 
             // Check that there is at least one core.
-            if let Some(core) = variant.cores.get(0) {
-                // Make sure that the core types (architectures) are not mixed.
-                let architecture = core.core_type.architecture();
-                if variant
-                    .cores
-                    .iter()
-                    .any(|core| core.core_type.architecture() != architecture)
-                {
-                    return Err(format!(
-                        "definition for variant `{}` contains mixed core architectures",
-                        variant.name
-                    ));
-                }
-            } else {
+            if variant.cores.is_empty() {
                 return Err(format!(
                     "definition for variant `{}` does not contain any cores",
                     variant.name
                 ));
             }
 
+            // Cores of different architectures share the debug port, e.g. through a JTAG mux.
+            // The RISC-V cores of such a target are told apart by their TAP, so either all of
+            // them name it, or none.
+            let riscv_taps: Vec<_> = variant
+                .cores
+                .iter()
+                .filter_map(|core| match &core.core_access_options {
+                    CoreAccessOptions::Riscv(options) => Some(options.jtag_tap),
+                    CoreAccessOptions::Arm(_) => None,
+                })
+                .collect();
+            if riscv_taps.iter().any(Option::is_some) && riscv_taps.iter().any(Option::is_none) {
+                return Err(format!(
+                    "definition for variant `{}` sets the JTAG TAP of some RISC-V cores, but not of all",
+                    variant.name
+                ));
+            }
+
+            for region in &variant.mediated_regions {
+                if region.range.is_empty() {
+                    return Err(format!(
+                        "mediated region {:#x?} of variant `{}` is empty",
+                        region.range, variant.name
+                    ));
+                }
+            }
+
             // Core specific validation logic based on type
             for core in variant.cores.iter() {
+                // Claimed debug resources must fit the architecture of the core
+                let capabilities = core.core_type.capabilities();
+                for (resource, claimed, limit) in [
+                    (
+                        "hardware breakpoints",
+                        core.hardware_breakpoints,
+                        capabilities.hardware_breakpoints,
+                    ),
+                    ("watchpoints", core.watchpoints, capabilities.watchpoints),
+                ] {
+                    if let (Some(claimed), Some(limit)) = (claimed, limit) {
+                        if claimed > limit {
+                            return Err(format!(
+                                "core {} of variant `{}` claims {} {}, but {:?} cores have at most {}",
+                                core.name, variant.name, claimed, resource, core.core_type, limit
+                            ));
+                        }
+                    }
+                }
+
                 // The core access options must match the core type specified
                 match &core.core_access_options {
                     CoreAccessOptions::Arm(options) => {
@@ -200,6 +249,25 @@ impl ChipFamily {
             }
         }
 
+        for (index, freeze) in self.peripheral_freeze.iter().enumerate() {
+            if freeze.bit >= 32 {
+                return Err(format!(
+                    "freeze bit {} of peripheral `{}` is not in a 32-bit register",
+                    freeze.bit, freeze.peripheral
+                ));
+            }
+
+            if self.peripheral_freeze[..index]
+                .iter()
+                .any(|other| other.peripheral == freeze.peripheral)
+            {
+                return Err(format!(
+                    "peripheral `{}` is frozen by more than one bit",
+                    freeze.peripheral
+                ));
+            }
+        }
+
         Ok(())
     }
 }
@@ -32,6 +32,17 @@ pub struct RawFlashAlgorithm {
     pub pc_erase_sector: u64,
     /// Address of the `EraseAll()` entry point. Optional.
     pub pc_erase_all: Option<u64>,
+    /// Address of the `ProgramPageCompressed()` entry point. Optional.
+    ///
+    /// This entry point takes the same arguments as `ProgramPage()`, but the data is
+    /// compressed with LZSS, and the size is the size of the compressed data.
+    /// The decompressed data is always a full page.
+    #[serde(default)]
+    #[cfg_attr(
+        not(feature = "bincode"),
+        serde(skip_serializing_if = "Option::is_none")
+    )]
+    pub pc_program_page_compressed: Option<u64>,
     /// The offset from the start of RAM to the data section.
     pub data_section_offset: u64,
     /// The properties of the flash on the device.
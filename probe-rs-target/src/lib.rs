@@ -11,18 +11,25 @@
 //! This crate contains the schema structs for the YAML target description files.
 //!
 
+mod capabilities;
 mod chip;
 mod chip_family;
 mod flash_algorithm;
 mod flash_properties;
 mod memory;
 
-pub use chip::{ArmCoreAccessOptions, Chip, Core, CoreAccessOptions, RiscvCoreAccessOptions};
+pub use capabilities::{CoreCapabilities, FpuSupport};
+pub use chip::{
+    ArmCoreAccessOptions, Chip, Core, CoreAccessOptions, Keepalive, KeepaliveAction,
+    MediatedRegion, MediatorKind, PeripheralFreeze, ResetScope, RiscvCoreAccessOptions,
+    RiscvQuirks,
+};
 pub use chip_family::{
     Architecture, ChipFamily, CoreType, InstructionSet, TargetDescriptionSource,
 };
 pub use flash_algorithm::RawFlashAlgorithm;
 pub use flash_properties::FlashProperties;
 pub use memory::{
-    MemoryRange, MemoryRegion, NvmRegion, PageInfo, RamRegion, SectorDescription, SectorInfo,
+    GenericRegion, MemoryRange, MemoryRegion, NvmRegion, PageInfo, RamRegion, SectorDescription,
+    SectorInfo,
 };
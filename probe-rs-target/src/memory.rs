@@ -12,6 +12,20 @@ pub struct NvmRegion {
     pub is_boot_memory: bool,
     /// List of cores that can access this region
     pub cores: Vec<String>,
+    /// Address ranges which are critical for booting the chip, e.g. option bytes or
+    /// sectors reserved for a vendor bootloader.
+    ///
+    /// Sectors in these ranges are only erased if explicitly allowed.
+    #[serde(default)]
+    #[cfg_attr(not(feature = "bincode"), serde(skip_serializing_if = "Vec::is_empty"))]
+    pub boot_critical_ranges: Vec<Range<u64>>,
+    /// True if the firmware of the target writes this region itself, e.g. a bootloader, an
+    /// EEPROM emulation or a log in flash.
+    ///
+    /// Its contents may change while the target runs, so probe-rs doesn't rely on earlier
+    /// verifications of it, see `Session::notify_target_flash_write` in probe-rs.
+    #[serde(default)]
+    pub target_writable: bool,
 }
 
 impl NvmRegion {
@@ -21,6 +35,13 @@ impl NvmRegion {
             rom_start: self.range.start,
         }
     }
+
+    /// Returns true if `range` intersects any of the boot-critical ranges of this region.
+    pub fn is_boot_critical(&self, range: &Range<u64>) -> bool {
+        self.boot_critical_ranges
+            .iter()
+            .any(|critical| critical.start < range.end && range.start < critical.end)
+    }
 }
 
 /// Represents a region in RAM.
@@ -34,6 +55,19 @@ pub struct RamRegion {
     pub is_boot_memory: bool,
     /// List of cores that can access this region
     pub cores: Vec<String>,
+    /// True if the region is memory mapped I/O, e.g. a mailbox shared with another bus
+    /// master, whose accesses must never be cached, merged, reordered or widened.
+    ///
+    /// Generic regions are always treated as volatile.
+    #[serde(default)]
+    pub volatile: bool,
+    /// True if the region is protected by ECC, which is computed over aligned 32-bit words.
+    ///
+    /// Writing less than a word to a word whose ECC wasn't initialized yet raises a bus
+    /// fault, so the region is only written with whole words, see
+    /// `Core::ecc_initialize` in probe-rs.
+    #[serde(default)]
+    pub ecc: bool,
 }
 
 /// Represents a generic region.
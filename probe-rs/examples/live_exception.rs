@@ -0,0 +1,46 @@
+//! Print the active exception of a running Cortex-M core at 10 Hz, without halting it.
+
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use probe_rs::{Intrusiveness, Permissions, Probe};
+
+#[derive(clap::Parser)]
+struct Cli {
+    #[clap(long = "chip")]
+    chip: String,
+    #[clap(long = "core", default_value = "0")]
+    core: usize,
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+
+    let probes = Probe::list_all();
+    let probe = probes
+        .get(0)
+        .ok_or_else(|| anyhow::anyhow!("No probe found"))?
+        .open()?;
+
+    let mut session = probe.attach(cli.chip, Permissions::default())?;
+
+    // Make sure that nothing halts the firmware while it is watched.
+    session.set_max_intrusiveness(Intrusiveness::BusOnly);
+
+    let mut core = session.core(cli.core)?;
+
+    loop {
+        let snapshot = core.system_control_snapshot_live()?;
+
+        match snapshot.active_exception() {
+            Some(exception) => println!("Active exception: {}", exception),
+            None => println!("Thread mode"),
+        }
+
+        sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,241 @@
+//! An out-of-tree probe driver.
+//!
+//! The driver provides a single probe, which talks to a mock transport instead of
+//! real hardware. The mock emulates a debug port, which is enough to initialize the
+//! ARM debug interface.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use probe_rs::{
+    plugin::{
+        register_driver, ArmCommunicationInterface, DapProbe, DebugProbe, DebugProbeError,
+        DebugProbeInfo, DebugProbeSelector, DebugProbeType, DpAddress, PortType, ProbeCapabilities,
+        ProbeCreationError, ProbeDriver, RawDapAccess, UninitializedArmProbe, WireProtocol,
+    },
+    Probe,
+};
+
+const VENDOR_ID: u16 = 0x1209;
+const PRODUCT_ID: u16 = 0x0001;
+
+/// A mock transport, which answers DAP register accesses like a Cortex-M debug port.
+#[derive(Debug, Default)]
+struct MockTransport {
+    dp_registers: HashMap<u8, u32>,
+}
+
+impl MockTransport {
+    fn read(&mut self, port: PortType, addr: u8) -> u32 {
+        match (port, addr) {
+            // DPIDR of a Cortex-M4 SW-DP.
+            (PortType::DebugPort, 0x0) => 0x2ba0_1477,
+            // CTRL/STAT: acknowledge the power-up requests.
+            (PortType::DebugPort, 0x4) => {
+                let ctrl = self.dp_registers.get(&0x4).copied().unwrap_or(0);
+                ctrl | (ctrl & 0x5000_0000) << 1
+            }
+            (PortType::DebugPort, addr) => self.dp_registers.get(&addr).copied().unwrap_or(0),
+            // There are no access ports.
+            (PortType::AccessPort, _) => 0,
+        }
+    }
+
+    fn write(&mut self, port: PortType, addr: u8, value: u32) {
+        if port == PortType::DebugPort {
+            self.dp_registers.insert(addr, value);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MockProbe {
+    transport: MockTransport,
+    protocol: Option<WireProtocol>,
+    speed_khz: u32,
+}
+
+impl DebugProbe for MockProbe {
+    fn new_from_selector(
+        selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError> {
+        let selector = selector.into();
+
+        if selector.vendor_id != VENDOR_ID || selector.product_id != PRODUCT_ID {
+            return Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound,
+            ));
+        }
+
+        Ok(Box::new(MockProbe {
+            transport: MockTransport::default(),
+            protocol: None,
+            speed_khz: 1000,
+        }))
+    }
+
+    fn get_name(&self) -> &str {
+        "Mock probe"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.speed_khz = speed_khz;
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        match protocol {
+            WireProtocol::Swd => {
+                self.protocol = Some(protocol);
+                Ok(())
+            }
+            WireProtocol::Jtag => Err(DebugProbeError::UnsupportedProtocol(protocol)),
+        }
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.protocol
+    }
+
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Swd]
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        true
+    }
+
+    fn try_get_arm_interface<'probe>(
+        self: Box<Self>,
+    ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
+    {
+        Ok(Box::new(ArmCommunicationInterface::new(self, false)))
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        Some(self)
+    }
+}
+
+impl RawDapAccess for MockProbe {
+    fn select_dp(&mut self, _dp: DpAddress) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn raw_read_register(&mut self, port: PortType, addr: u8) -> Result<u32, DebugProbeError> {
+        Ok(self.transport.read(port, addr))
+    }
+
+    fn raw_write_register(
+        &mut self,
+        port: PortType,
+        addr: u8,
+        value: u32,
+    ) -> Result<(), DebugProbeError> {
+        self.transport.write(port, addr, value);
+        Ok(())
+    }
+
+    fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn swj_pins(
+        &mut self,
+        _pin_out: u32,
+        _pin_select: u32,
+        _pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe("swj_pins"))
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+}
+
+impl DapProbe for MockProbe {}
+
+struct MockDriver;
+
+impl ProbeDriver for MockDriver {
+    fn name(&self) -> &str {
+        "Mock"
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        ProbeCapabilities::new().swd()
+    }
+
+    fn list_probes(&self) -> Vec<DebugProbeInfo> {
+        vec![DebugProbeInfo::new(
+            "Mock probe",
+            VENDOR_ID,
+            PRODUCT_ID,
+            None,
+            DebugProbeType::Plugin(self.name().to_owned()),
+            None,
+        )]
+    }
+
+    fn open(&self, selector: &DebugProbeSelector) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+        MockProbe::new_from_selector(selector.clone()).map(DebugProbe::into_probe)
+    }
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    register_driver(Box::new(MockDriver));
+
+    let info = Probe::list_all()
+        .into_iter()
+        .find(|info| info.probe_type == DebugProbeType::Plugin("Mock".to_owned()))
+        .expect("The mock probe is always found");
+
+    println!("Found {:?}", info);
+
+    let mut probe = info.open()?;
+    probe.select_protocol(WireProtocol::Swd)?;
+    probe.attach_to_unspecified()?;
+
+    let mut interface = probe
+        .try_into_arm_interface()
+        .map_err(|(_probe, err)| err)?;
+
+    println!("DPIDR: {:#010x}", interface.read_dpidr()?);
+
+    interface.initialize_unspecified()?;
+
+    println!("Debug port powered up");
+
+    Ok(())
+}
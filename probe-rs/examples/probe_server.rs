@@ -0,0 +1,53 @@
+//! Share the first probe found with other hosts, see `probe_rs::remote`.
+//!
+//! Clients open the probe with `Probe::open_remote("<host>:<port>", "<token>")`.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use probe_rs::remote::{AccessPolicy, ProbeServer, ServerConfig};
+use probe_rs::Probe;
+
+#[derive(clap::Parser)]
+struct Cli {
+    #[clap(long = "listen", default_value = "0.0.0.0:5555")]
+    listen: String,
+    #[clap(long = "token")]
+    token: String,
+    /// Seconds after which an idle client loses the lock to a waiting client.
+    #[clap(long = "idle-timeout", default_value = "60")]
+    idle_timeout: u64,
+    /// Seconds after which a client loses the lock to a waiting client, even if it isn't idle.
+    #[clap(long = "slice")]
+    slice: Option<u64>,
+}
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+
+    let probes = Probe::list_all();
+    let probe = probes
+        .get(0)
+        .ok_or_else(|| anyhow::anyhow!("No probe found"))?
+        .open()?;
+
+    let mut config = ServerConfig::new(cli.token);
+    config.idle_timeout = Duration::from_secs(cli.idle_timeout);
+    if let Some(slice) = cli.slice {
+        config.access = AccessPolicy::TimeSliced(Duration::from_secs(slice));
+    }
+
+    let server = ProbeServer::bind(&cli.listen, probe, config)?;
+    println!(
+        "Sharing {} on {}",
+        probes[0].identifier,
+        server.local_addr()?
+    );
+
+    server.run()?;
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! A CMSIS-DAP probe connected through a Unix domain socket.
+//!
+//! The USB device is opened by a forwarder process, e.g. running with the permissions
+//! needed to access it, which exchanges the USB packets with this example over the socket.
+//! Each packet is framed with its length as a little endian `u16`.
+
+#[cfg(unix)]
+mod transport {
+    use std::io::{self, Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    use probe_rs::plugin::ProbeTransport;
+
+    /// A transport which forwards the packets of the probe over a Unix domain socket.
+    pub struct SocketTransport {
+        stream: UnixStream,
+        max_packet_size: Option<usize>,
+    }
+
+    impl SocketTransport {
+        pub fn connect(path: &str, max_packet_size: Option<usize>) -> io::Result<Self> {
+            Ok(Self {
+                stream: UnixStream::connect(path)?,
+                max_packet_size,
+            })
+        }
+    }
+
+    impl ProbeTransport for SocketTransport {
+        fn write(&mut self, data: &[u8], timeout: Duration) -> io::Result<usize> {
+            self.stream.set_write_timeout(Some(timeout))?;
+
+            let length = u16::try_from(data.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Packet too large"))?;
+            self.stream.write_all(&length.to_le_bytes())?;
+            self.stream.write_all(data)?;
+
+            Ok(data.len())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize> {
+            // Only wait for the start of a packet, the rest of it has to follow.
+            self.stream.set_read_timeout(Some(timeout))?;
+            let mut length = [0; 2];
+            self.stream.read_exact(&mut length[..1])?;
+
+            self.stream.set_read_timeout(None)?;
+            self.stream.read_exact(&mut length[1..])?;
+
+            let length = u16::from_le_bytes(length) as usize;
+            if length > buffer.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Packet larger than the buffer",
+                ));
+            }
+
+            self.stream.read_exact(&mut buffer[..length])?;
+
+            Ok(length)
+        }
+
+        fn max_packet_size(&self) -> Option<usize> {
+            self.max_packet_size
+        }
+    }
+}
+
+#[cfg(unix)]
+fn main() -> anyhow::Result<()> {
+    use clap::Parser;
+    use probe_rs::{plugin::TransportKind, Permissions, Probe};
+
+    #[derive(clap::Parser)]
+    struct Cli {
+        /// The socket of the USB forwarder.
+        #[clap(long = "socket")]
+        socket: String,
+        /// Whether the probe is a CMSIS-DAP v1 (HID) probe.
+        #[clap(long = "v1")]
+        v1: bool,
+        /// The maximum packet size of the USB endpoints, if known.
+        #[clap(long = "packet-size")]
+        packet_size: Option<usize>,
+        #[clap(long = "chip")]
+        chip: String,
+    }
+
+    pretty_env_logger::init();
+
+    let cli = Cli::parse();
+
+    let transport = transport::SocketTransport::connect(&cli.socket, cli.packet_size)?;
+    let kind = if cli.v1 {
+        TransportKind::CmsisDapV1
+    } else {
+        TransportKind::CmsisDapV2
+    };
+
+    let mut probe = Probe::from_custom_transport(kind, Box::new(transport))?;
+    probe.set_speed(4000)?;
+
+    let mut session = probe.attach(cli.chip, Permissions::default())?;
+    let mut core = session.core(0)?;
+
+    println!("Core status: {:?}", core.status()?);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("This example requires Unix domain sockets.");
+}
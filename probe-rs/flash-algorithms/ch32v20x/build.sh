@@ -0,0 +1,6 @@
+# Assemble the flash algorithm, and print the `instructions` of the target description.
+llvm-mc --triple=riscv32 -mattr=-c --filetype=obj ch32v20x.s -o ch32v20x.o
+llvm-objcopy ch32v20x.o -O binary ch32v20x.bin
+
+base64 -w 0 ch32v20x.bin
+echo
@@ -0,0 +1,128 @@
+/*
+ * Flash algorithm for the internal flash of the CH32V20x, used as `ch32v20x` in
+ * targets/CH32V2_Series.yaml.
+ *
+ * Written by hand from the description of the flash controller (FPEC) in the CH32V20x
+ * reference manual, using its standard programming mode: half-word writes with CTLR.PG,
+ * 4 KiB page erases with CTLR.PER and a mass erase with CTLR.MER. The fast programming
+ * mode, with its 256 byte pages and extra unlock key, is not used. Regenerate the
+ * `instructions` of the target description with build.sh after changing this file.
+ *
+ * The algorithm was only checked against the disassembly of the assembled code and the
+ * register description of the reference manual, not run on a CH32V203.
+ *
+ * Entry points, at the offsets of the target description:
+ *   0x00 Init        returns 0, or non-zero if CTLR.LOCK is still set after the unlock
+ *   0x2c UnInit      locks the flash again
+ *   0x44 EraseChip   returns 0, or non-zero on a write protection error
+ *   0x74 EraseSector a0 = address of the page, returns like EraseChip
+ *   0xa8 ProgramPage a0 = address, a1 = size in bytes, a2 = data, returns like EraseChip
+ *
+ * The helper `wait` is called with `jal t2`, so that ra is kept without a stack.
+ */
+
+    .equ FLASH,   0x40022000
+    .equ KEYR,    0x04
+    .equ STATR,   0x0c
+    .equ CTLR,    0x10
+    .equ ADDR,    0x14
+
+    .equ KEY1,    0x45670123
+    .equ KEY2,    0xcdef89ab
+
+    .equ PG,      1 << 0
+    .equ PER,     1 << 1
+    .equ MER,     1 << 2
+    .equ STRT,    1 << 6
+    .equ LOCK,    1 << 7
+
+    .equ BSY,     1 << 0
+    .equ WRPRTERR, 1 << 4
+    .equ EOP,     1 << 5
+
+    .option norvc
+    .text
+
+init:                               /* 0x00 */
+    lui     t0, %hi(FLASH)
+    lui     t1, %hi(KEY1)
+    addi    t1, t1, %lo(KEY1)
+    sw      t1, KEYR(t0)
+    lui     t1, %hi(KEY2)
+    addi    t1, t1, %lo(KEY2)
+    sw      t1, KEYR(t0)
+    lw      t1, CTLR(t0)
+    andi    t1, t1, LOCK
+    snez    a0, t1
+    ret
+
+uninit:                             /* 0x2c */
+    lui     t0, %hi(FLASH)
+    lw      t1, CTLR(t0)
+    ori     t1, t1, LOCK
+    sw      t1, CTLR(t0)
+    li      a0, 0
+    ret
+
+erase_chip:                         /* 0x44 */
+    lui     t0, %hi(FLASH)
+    lw      t1, CTLR(t0)
+    ori     t1, t1, MER
+    sw      t1, CTLR(t0)
+    ori     t1, t1, STRT
+    sw      t1, CTLR(t0)
+    jal     t2, wait
+    lw      t1, CTLR(t0)
+    andi    t1, t1, ~(MER | STRT)
+    sw      t1, CTLR(t0)
+    mv      a0, a4
+    ret
+
+erase_sector:                       /* 0x74 */
+    lui     t0, %hi(FLASH)
+    lw      t1, CTLR(t0)
+    ori     t1, t1, PER
+    sw      t1, CTLR(t0)
+    sw      a0, ADDR(t0)
+    ori     t1, t1, STRT
+    sw      t1, CTLR(t0)
+    jal     t2, wait
+    lw      t1, CTLR(t0)
+    andi    t1, t1, ~(PER | STRT)
+    sw      t1, CTLR(t0)
+    mv      a0, a4
+    ret
+
+program_page:                       /* 0xa8 */
+    lui     t0, %hi(FLASH)
+    lw      t1, CTLR(t0)
+    ori     t1, t1, PG
+    sw      t1, CTLR(t0)
+    li      a4, 0
+1:
+    blez    a1, 2f
+    lhu     a3, 0(a2)
+    sh      a3, 0(a0)
+    jal     t2, wait
+    bnez    a4, 2f
+    addi    a0, a0, 2
+    addi    a2, a2, 2
+    addi    a1, a1, -2
+    j       1b
+2:
+    lw      t1, CTLR(t0)
+    andi    t1, t1, ~PG
+    sw      t1, CTLR(t0)
+    mv      a0, a4
+    ret
+
+/* Wait until the flash is idle. Returns the write protection error in a4, and clears
+ * the status flags. */
+wait:                               /* 0xf4 */
+    lw      a4, STATR(t0)
+    andi    a5, a4, BSY
+    bnez    a5, wait
+    andi    a4, a4, WRPRTERR
+    li      a5, WRPRTERR | EOP
+    sw      a5, STATR(t0)
+    jr      t2
@@ -1,19 +1,757 @@
 use anyhow::anyhow;
 
 use super::super::{ApAccess, Register};
-use super::{AddressIncrement, ApRegister, DataSize, CSW, DRW, TAR};
-use crate::architecture::arm::{ap::AccessPort, DpAddress};
+use super::{AddressIncrement, ApRegister, DataSize, CSW, DRW, TAR, TAR2};
+use crate::architecture::arm::core::cache::CacheMaintenance;
+use crate::architecture::arm::{ap::AccessPort, DapError, DpAddress};
+use crate::flashing::FlashAlgorithm;
+use crate::probe::fake_probe::{
+    AccessStalls, BreakpointHits, EccFaults, FirmwareWrites, ForeignResumes, ProbePolls,
+    ProbeTransactions, ReadFaults, TargetResets, VendorCommands, WriteFaults, WriteLog,
+};
 use crate::{
     architecture::arm::dp::{DebugPortError, DpAccess, DpRegister},
-    CommunicationInterface, DebugProbeError,
+    CommunicationInterface, DebugProbeError, VendorCommand, VendorCommandIndex,
+    VendorCommandOrdering,
 };
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct MockMemoryAp {
     pub memory: Vec<u8>,
+    /// The address of the first byte of `memory`.
+    base_address: u64,
+    /// If the AP has the large address extension. Without it, writes to TAR2 are ignored.
+    large_address_extension: bool,
     store: HashMap<u8, u32>,
+    core: Option<MockCore>,
+}
+
+/// A mocked Cortex-M core behind the memory AP.
+///
+/// The core implements the debug registers needed to halt it, run it and access its
+/// registers. Every routine it runs returns to its return address with `0` in `R0`, if a
+/// breakpoint instruction is at that address, instantly unless a delay was configured for the
+/// value of `R0` it was called with. Alternatively, the core can execute a subset of the
+/// Thumb instructions. The MPU has 8
+/// regions, selected by MPU_RNR, and the FPB has 4 comparators. VTOR keeps its value across
+/// a reset. The core can be made to ignore a number of halt requests, but not the reset vector catch. Writes to the cache maintenance registers are recorded, writes to the
+/// addresses of the write faults fail, and reads fail at the interval of the read faults. Partial
+/// writes and reads of uninitialized words of the ECC faults fail. All other addresses are
+/// backed by sparse memory, which reads as `0` until it is written. All successful writes
+/// are logged in the write log. Accesses are delayed by the access stalls. The core can be
+/// made to halt, or to resume, while the probe polls it. The erase and program routines of
+/// a flash algorithm can be emulated, so that the flash is changed like by the real routines.
+/// A resume can be made to halt the core at a breakpoint instead, and the round trips of
+/// the probe to the core are counted. The core can be reset like by a watchdog, which sets
+/// DHCSR.S_RESET_ST until DHCSR is read. It can also be resumed like by another debugger,
+/// which the probe only notices from DFSR when the core halts again. Vendor commands are
+/// logged in the order of the writes, and their responses echo them. The firmware can be
+/// made to set bits of a word right after it was read, by the probe while the core runs or
+/// by an `LDREX`, which clears the exclusive monitor. A `STREX` to the address of a write
+/// fault faults, which halts the core at it.
+#[derive(Debug, Default)]
+pub struct MockCore {
+    memory: HashMap<u32, u32>,
+    registers: HashMap<u32, u32>,
+    /// MPU_RBAR and MPU_RASR of the MPU regions.
+    mpu_regions: [(u32, u32); 8],
+    /// The cache maintenance operations, with their addresses.
+    cache_maintenance: Vec<(CacheMaintenance, u32)>,
+    /// The time routines take to return, by the value of `R0` they are called with.
+    routine_delays: HashMap<u32, Duration>,
+    /// The REV field of FP_CTRL.
+    fpb_revision: u32,
+    /// The addresses whose writes fail with a fault response.
+    write_faults: WriteFaults,
+    /// The interval in which reads fail with a fault response.
+    read_faults: ReadFaults,
+    /// The memory which is protected by ECC.
+    ecc_faults: EccFaults,
+    /// The log of the successful writes.
+    write_log: WriteLog,
+    /// The accesses which take longer than usual.
+    stalls: AccessStalls,
+    /// The polls done by the probe.
+    polls: ProbePolls,
+    /// The breakpoints which are hit when the core is resumed.
+    breakpoint_hits: BreakpointHits,
+    /// The round trips of the probe to the core.
+    transactions: ProbeTransactions,
+    /// The number of halt requests the core ignores.
+    ignored_halt_requests: u32,
+    /// The flash algorithm whose routines are emulated.
+    flash: Option<FlashAlgorithm>,
+    /// The resets which are not issued by the probe.
+    target_resets: TargetResets,
+    /// The resumes which are not issued by the probe.
+    foreign_resumes: ForeignResumes,
+    /// The writes of the firmware, which race with the reads.
+    firmware_writes: FirmwareWrites,
+    /// The address the exclusive monitor is set for by an `LDREX`.
+    exclusive: Option<u32>,
+    /// The log of the executed vendor commands.
+    vendor_commands: VendorCommands,
+    /// How the vendor commands are ordered with the accesses.
+    vendor_command_ordering: VendorCommandOrdering,
+    /// The sticky S_RESET_ST bit of DHCSR.
+    reset_status: bool,
+    /// Whether the code at PC is executed when the core is resumed.
+    execute_code: bool,
+    halted: bool,
+}
+
+impl MockCore {
+    const VTOR: u32 = 0xE000_ED08;
+    const AIRCR: u32 = 0xE000_ED0C;
+    const DHCSR: u32 = 0xE000_EDF0;
+    const DCRSR: u32 = 0xE000_EDF4;
+    const DCRDR: u32 = 0xE000_EDF8;
+    const DEMCR: u32 = 0xE000_EDFC;
+    const DFSR: u32 = 0xE000_ED30;
+    const FP_CTRL: u32 = 0xE000_2000;
+    const FP_COMP0: u32 = 0xE000_2008;
+    const MPU_TYPE: u32 = 0xE000_ED90;
+    const MPU_RNR: u32 = 0xE000_ED98;
+    const MPU_RBAR: u32 = 0xE000_ED9C;
+    const MPU_RASR: u32 = 0xE000_EDA0;
+    const ICIMVAU: u32 = 0xE000_EF58;
+    const DCIMVAC: u32 = 0xE000_EF5C;
+    const DCCMVAC: u32 = 0xE000_EF68;
+    const DCCIMVAC: u32 = 0xE000_EF70;
+
+    const FP_NUM_CODE: u32 = 4;
+
+    /// The `BKPT` instruction, without its immediate.
+    const BKPT: u16 = 0xbe00;
+    /// The number of instructions the core executes, before it is left running.
+    const INSTRUCTION_BUDGET: u32 = 100_000;
+
+    const S_REGRDY: u32 = 1 << 16;
+    const S_HALT: u32 = 1 << 17;
+    const S_RESET_ST: u32 = 1 << 25;
+
+    fn read_word(&self, address: u32) -> u32 {
+        let value = self.memory.get(&address).copied().unwrap_or(0);
+
+        match address {
+            Self::DHCSR => {
+                let status = if self.halted {
+                    Self::S_REGRDY | Self::S_HALT
+                } else {
+                    Self::S_REGRDY
+                };
+                let reset = if self.reset_status {
+                    Self::S_RESET_ST
+                } else {
+                    0
+                };
+
+                value & 0xffff | status | reset
+            }
+            // NUM_CODE and REV are read-only.
+            Self::FP_CTRL => value & 1 | Self::FP_NUM_CODE << 4 | self.fpb_revision << 28,
+            Self::MPU_TYPE => (self.mpu_regions.len() as u32) << 8,
+            Self::MPU_RBAR => self.mpu_regions[self.mpu_region()].0,
+            Self::MPU_RASR => self.mpu_regions[self.mpu_region()].1,
+            _ => value,
+        }
+    }
+
+    /// Read the word at `address`, after a pending reset or resume. Reading DHCSR clears
+    /// S_RESET_ST.
+    fn read(&mut self, address: u32) -> u32 {
+        if self.target_resets.take() {
+            self.reset();
+            self.reset_status = true;
+        }
+
+        // The core runs to the next breakpoint hit, or keeps running.
+        if self.foreign_resumes.take() && self.halted {
+            match self.breakpoint_hits.next() {
+                Some(breakpoint) => {
+                    self.registers.insert(15, breakpoint);
+                    self.halt_at_breakpoint();
+                }
+                None => self.halted = false,
+            }
+        }
+
+        let word = self.read_word(address);
+
+        if !self.halted {
+            self.apply_firmware_write(address);
+        }
+
+        if address == Self::DHCSR {
+            self.reset_status = false;
+        }
+
+        word
+    }
+
+    /// Set the bits of a pending firmware write to the word at `address`, which clears the
+    /// exclusive monitor.
+    fn apply_firmware_write(&mut self, address: u32) {
+        if let Some(bits) = self.firmware_writes.take(address) {
+            let value = self.read_word(address);
+            self.memory.insert(address, value | bits);
+            self.exclusive = None;
+        }
+    }
+
+    /// Reset the core. It halts if the reset vector catch is enabled. The stack pointer and
+    /// the program counter are loaded from the vector table.
+    fn reset(&mut self) {
+        self.halted = self.read_word(Self::DEMCR) & 1 != 0;
+        self.registers.clear();
+
+        let vector_table = self.read_word(Self::VTOR);
+        self.registers.insert(13, self.read_word(vector_table));
+        self.registers
+            .insert(15, self.read_word(vector_table + 4) & !1);
+        self.registers.insert(16, 1 << 24);
+    }
+
+    /// Emulate the routine at PC, if it is a routine of the emulated flash algorithm.
+    ///
+    /// Erased flash reads as the erased byte value, and programming can only clear bits, like
+    /// with NOR flash.
+    fn run_flash_routine(&mut self) {
+        let algorithm = match &self.flash {
+            Some(algorithm) => algorithm.clone(),
+            None => return,
+        };
+
+        let pc = u64::from(self.registers.get(&15).copied().unwrap_or(0) & !1);
+        let argument = |index| self.registers.get(&index).copied().unwrap_or(0);
+        let (address, size, buffer) = (argument(0), argument(1), argument(2));
+
+        let erased = if pc == algorithm.pc_erase_sector {
+            algorithm
+                .sector_info(u64::from(address))
+                .map(|sector| sector.base_address..sector.base_address + sector.size)
+        } else if Some(pc) == algorithm.pc_erase_all {
+            Some(algorithm.flash_properties.address_range.clone())
+        } else {
+            None
+        };
+
+        if let Some(range) = erased {
+            let value = u32::from_ne_bytes([algorithm.flash_properties.erased_byte_value; 4]);
+
+            for address in range.step_by(4) {
+                self.memory.insert(address as u32, value);
+            }
+        } else if pc == algorithm.pc_program_page {
+            for offset in (0..size).step_by(4) {
+                let value = self.read_word(address + offset) & self.read_word(buffer + offset);
+                self.memory.insert(address + offset, value);
+            }
+        }
+    }
+
+    /// Returns `true` if PC is in the code of the emulated flash algorithm, whose routines
+    /// return instantly even if the core executes code.
+    fn in_flash_algorithm(&self) -> bool {
+        let pc = u64::from(self.registers.get(&15).copied().unwrap_or(0) & !1);
+
+        self.flash.as_ref().map_or(false, |algorithm| {
+            let end = algorithm.load_address + 4 * algorithm.instructions.len() as u64;
+            (algorithm.load_address..end).contains(&pc)
+        })
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        (self.read_word(address & !3) >> ((address & 2) * 8)) as u16
+    }
+
+    /// Execute the Thumb code at PC, until the core halts at a `BKPT` instruction, at an
+    /// enabled FPB comparator, or at an instruction which isn't supported. If the core doesn't
+    /// halt within the instruction budget, it is left running.
+    ///
+    /// A `single_step` executes at most one instruction, and halts the core afterwards.
+    fn execute(&mut self, single_step: bool) {
+        // The stack pointer is written as MSP.
+        let sp = if self.registers.contains_key(&17) {
+            17
+        } else {
+            13
+        };
+        let key = |index: usize| if index == 13 { sp } else { index as u32 };
+
+        let mut r = [0; 16];
+        for (index, value) in r.iter_mut().enumerate() {
+            *value = self.registers.get(&key(index)).copied().unwrap_or(0);
+        }
+        r[15] &= !1;
+        let mut xpsr = self.registers.get(&16).copied().unwrap_or(0);
+
+        let budget = if single_step {
+            1
+        } else {
+            Self::INSTRUCTION_BUDGET
+        };
+
+        self.halted = single_step;
+        for _ in 0..budget {
+            // A breakpoint halts the core before the instruction is executed.
+            if self.breakpoint_at(r[15]) {
+                self.halt_at_breakpoint();
+                break;
+            }
+
+            match self.step(&mut r, &mut xpsr) {
+                Some(next) => r[15] = next,
+                None => {
+                    if self.read_halfword(r[15]) & 0xff00 == Self::BKPT {
+                        self.halt_at_breakpoint();
+                    }
+                    self.halted = true;
+                    break;
+                }
+            }
+        }
+
+        for (index, value) in r.iter().enumerate() {
+            self.registers.insert(key(index), *value);
+        }
+        self.registers.insert(16, xpsr);
+    }
+
+    /// Returns `true` if an enabled comparator of the FPB matches the instruction at `address`.
+    fn breakpoint_at(&self, address: u32) -> bool {
+        if self.read_word(Self::FP_CTRL) & 1 == 0 {
+            return false;
+        }
+
+        (0..Self::FP_NUM_CODE).any(|index| {
+            let comparator = self.read_word(Self::FP_COMP0 + 4 * index);
+
+            let matched = if self.fpb_revision == 0 {
+                // The REPLACE field selects the halfword of the word COMP points to.
+                let halfword = match comparator >> 30 {
+                    0b01 => 0,
+                    0b10 => 2,
+                    _ => return false,
+                };
+
+                comparator & 0x1fff_fffc | halfword
+            } else {
+                comparator & !1
+            };
+
+            comparator & 1 != 0 && matched == address
+        })
+    }
+
+    /// Halt the core at a breakpoint, with DFSR.BKPT set.
+    fn halt_at_breakpoint(&mut self) {
+        self.halted = true;
+        let dfsr = self.read_word(Self::DFSR);
+        self.memory.insert(Self::DFSR, dfsr | 0b10);
+    }
+
+    /// Execute the instruction at `r[15]`, and return the address of the next one, or `None`
+    /// if the core halts at it.
+    fn step(&mut self, r: &mut [u32; 16], xpsr: &mut u32) -> Option<u32> {
+        let pc = r[15];
+        let instruction = self.read_halfword(pc);
+        let low = |shift: u16| usize::from(instruction >> shift & 0b111);
+        let imm8 = u32::from(instruction & 0xff);
+        // PC reads as the address of the instruction plus 4.
+        let relative = |offset: i32| (pc + 4).wrapping_add(offset as u32);
+
+        match instruction {
+            // LSLS Rd, Rm, #imm5, which is MOVS Rd, Rm for an immediate of 0.
+            i if i & 0xf800 == 0x0000 => {
+                let (value, shift) = (r[low(3)], u32::from(i >> 6 & 0x1f));
+                // The carry is unchanged by a shift of 0.
+                let carry = if shift == 0 {
+                    None
+                } else {
+                    Some(value >> (32 - shift) & 1 != 0)
+                };
+                r[low(0)] = value << shift;
+                set_flags(xpsr, r[low(0)], carry, None);
+            }
+            // ADDS Rd, Rn, Rm / SUBS Rd, Rn, Rm / ADDS Rd, Rn, #imm3 / SUBS Rd, Rn, #imm3
+            i if i & 0xf800 == 0x1800 => {
+                let operand = if i & 0x400 != 0 {
+                    u32::from(i >> 6 & 0b111)
+                } else {
+                    r[low(6)]
+                };
+                let subtract = i & 0x200 != 0;
+                r[low(0)] = add_or_subtract(xpsr, r[low(3)], operand, subtract);
+            }
+            // MOVS Rd, #imm8
+            i if i & 0xf800 == 0x2000 => {
+                r[low(8)] = imm8;
+                set_flags(xpsr, imm8, None, None);
+            }
+            // CMP Rn, #imm8
+            i if i & 0xf800 == 0x2800 => {
+                add_or_subtract(xpsr, r[low(8)], imm8, true);
+            }
+            // ADDS Rdn, #imm8 / SUBS Rdn, #imm8
+            i if i & 0xf000 == 0x3000 => {
+                r[low(8)] = add_or_subtract(xpsr, r[low(8)], imm8, i & 0x800 != 0);
+            }
+            // ANDS Rdn, Rm
+            i if i & 0xffc0 == 0x4000 => {
+                r[low(0)] &= r[low(3)];
+                set_flags(xpsr, r[low(0)], None, None);
+            }
+            // TST Rn, Rm
+            i if i & 0xffc0 == 0x4200 => {
+                set_flags(xpsr, r[low(0)] & r[low(3)], None, None);
+            }
+            // ORRS Rdn, Rm
+            i if i & 0xffc0 == 0x4300 => {
+                r[low(0)] |= r[low(3)];
+                set_flags(xpsr, r[low(0)], None, None);
+            }
+            // BICS Rdn, Rm
+            i if i & 0xffc0 == 0x4380 => {
+                r[low(0)] &= !r[low(3)];
+                set_flags(xpsr, r[low(0)], None, None);
+            }
+            // CMP Rn, Rm
+            i if i & 0xffc0 == 0x4280 => {
+                add_or_subtract(xpsr, r[low(0)], r[low(3)], true);
+            }
+            // MOV Rd, Rm, which can access the high registers.
+            i if i & 0xff00 == 0x4600 => {
+                let source = usize::from(i >> 3 & 0xf);
+                let value = if source == 15 { pc + 4 } else { r[source] };
+                match usize::from(i >> 4 & 0b1000 | i & 0b111) {
+                    15 => return Some(value & !1),
+                    destination => r[destination] = value,
+                }
+            }
+            // BX Rm
+            i if i & 0xff87 == 0x4700 => return Some(r[usize::from(i >> 3 & 0xf)] & !1),
+            // LDR Rt, [PC, #imm8]
+            i if i & 0xf800 == 0x4800 => {
+                r[low(8)] = self.read_word(((pc + 4) & !3) + imm8 * 4);
+            }
+            // STRB Rt, [Rn, Rm]
+            i if i & 0xfe00 == 0x5400 => {
+                let address = r[low(3)].wrapping_add(r[low(6)]);
+                let shift = (address & 3) * 8;
+                self.write_word(address & !3, r[low(0)] << shift, 0xff << shift);
+            }
+            // LDRB Rt, [Rn, Rm]
+            i if i & 0xfe00 == 0x5c00 => {
+                let address = r[low(3)].wrapping_add(r[low(6)]);
+                r[low(0)] = self.read_word(address & !3) >> ((address & 3) * 8) & 0xff;
+            }
+            // STR Rt, [Rn, #imm5]
+            i if i & 0xf800 == 0x6000 => {
+                let address = r[low(3)] + u32::from(i >> 6 & 0x1f) * 4;
+
+                // The code clears the bits of DFSR by writing ones to them.
+                if address == Self::DFSR {
+                    let dfsr = self.read_word(Self::DFSR);
+                    self.memory.insert(Self::DFSR, dfsr & !r[low(0)]);
+                } else {
+                    self.write_word(address, r[low(0)], !0);
+                }
+            }
+            // LDR Rt, [Rn, #imm5]
+            i if i & 0xf800 == 0x6800 => {
+                let address = r[low(3)] + u32::from(i >> 6 & 0x1f) * 4;
+                r[low(0)] = self.read_word(address);
+            }
+            // ADD Rd, SP, #imm8
+            i if i & 0xf800 == 0xa800 => r[low(8)] = r[13] + imm8 * 4,
+            // PUSH {registers, LR}
+            i if i & 0xfe00 == 0xb400 => {
+                let registers = register_list(i, 14);
+                r[13] -= 4 * registers.len() as u32;
+                for (slot, register) in registers.into_iter().enumerate() {
+                    self.write_word(r[13] + 4 * slot as u32, r[register], !0);
+                }
+            }
+            // POP {registers, PC}
+            i if i & 0xfe00 == 0xbc00 => {
+                let registers = register_list(i, 15);
+                let mut next = pc + 2;
+                for (slot, &register) in registers.iter().enumerate() {
+                    let value = self.read_word(r[13] + 4 * slot as u32);
+                    if register == 15 {
+                        next = value & !1;
+                    } else {
+                        r[register] = value;
+                    }
+                }
+                r[13] += 4 * registers.len() as u32;
+
+                return Some(next);
+            }
+            i if i & 0xff00 == Self::BKPT => return None,
+            // NOP
+            0xbf00 => {}
+            // B<cond> label, the conditions 0b1110 and 0b1111 are UDF and SVC.
+            i if i & 0xf000 == 0xd000 && i >> 8 & 0xf < 0xe => {
+                if condition_passed(*xpsr, i >> 8 & 0xf) {
+                    return Some(relative(i32::from(imm8 as u8 as i8) * 2));
+                }
+            }
+            // B label
+            i if i & 0xf800 == 0xe000 => {
+                return Some(relative((i32::from(i & 0x7ff) << 21) >> 20));
+            }
+            // The 32-bit instructions.
+            i if i >> 11 >= 0b11101 => {
+                let second = self.read_halfword(pc + 2);
+
+                return if self.step_wide(r, i, second) {
+                    Some(pc + 4)
+                } else {
+                    None
+                };
+            }
+            _ => return None,
+        }
+
+        Some(pc + 2)
+    }
+
+    /// Execute the 32-bit instruction of the halfwords `first` and `second`, and return
+    /// `false` if it isn't supported.
+    fn step_wide(&mut self, r: &mut [u32; 16], first: u16, second: u16) -> bool {
+        let base = r[usize::from(first & 0xf)];
+        let offset = u32::from(second & 0xff) * 4;
+
+        match (first, second) {
+            // LDREX Rt, [Rn, #imm8]
+            (f, s) if f & 0xfff0 == 0xe850 && s & 0x0f00 == 0x0f00 => {
+                let address = base + offset;
+                r[usize::from(s >> 12)] = self.read_word(address);
+                self.exclusive = Some(address);
+                self.apply_firmware_write(address);
+            }
+            // STREX Rd, Rt, [Rn, #imm8]
+            (f, s) if f & 0xfff0 == 0xe840 => {
+                let address = base + offset;
+
+                // The store faults, and the core halts at it, like with a vector catch.
+                if self.write_faults.contains(address) {
+                    return false;
+                }
+
+                let exclusive = self.exclusive.take() == Some(address);
+
+                if exclusive {
+                    self.write_word(address, r[usize::from(s >> 12)], !0);
+                }
+                r[usize::from(s >> 8 & 0xf)] = u32::from(!exclusive);
+            }
+            // CLREX
+            (0xf3bf, 0x8f2f) => self.exclusive = None,
+            // DSB, DMB and ISB, the accesses are never reordered.
+            (0xf3bf, 0x8f4f | 0x8f5f | 0x8f6f) => {}
+            // MRS Rd, MSP / MRS Rd, PSP
+            (0xf3ef, s) if s & 0xf0fe == 0x8008 => {
+                r[usize::from(s >> 8 & 0xf)] = if s & 1 == 0 {
+                    r[13]
+                } else {
+                    self.registers.get(&18).copied().unwrap_or(0)
+                };
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Stall an access, if a stall is due.
+    fn delay_access(&self) {
+        let delay = self.stalls.next();
+
+        if !delay.is_zero() {
+            std::thread::sleep(delay);
+        }
+    }
+
+    /// The MPU region selected by MPU_RNR.
+    fn mpu_region(&self) -> usize {
+        self.read_word(Self::MPU_RNR) as usize % self.mpu_regions.len()
+    }
+
+    fn write_word(&mut self, address: u32, value: u32, mask: u32) {
+        let old = self.memory.get(&address).copied().unwrap_or(0);
+        let value = old & !mask | value & mask;
+
+        match address {
+            Self::DHCSR => {
+                // C_HALT or C_STEP halt the core. Resuming a halted core runs the
+                // current routine, which returns instantly, unless the core executes code.
+                let executes = self.execute_code && !self.in_flash_algorithm();
+                let step = value & 0b110 == 0b100;
+                let was_halted = self.halted;
+
+                if step && self.halted && executes {
+                    self.execute(true);
+                } else if value & 0b110 != 0 {
+                    if value & 0b10 != 0 && self.ignored_halt_requests > 0 {
+                        self.ignored_halt_requests -= 1;
+                    } else {
+                        self.halted = true;
+                    }
+                } else if self.halted && executes {
+                    self.execute(false);
+                } else if self.halted {
+                    let argument = self.registers.get(&0).copied().unwrap_or(0);
+                    if let Some(delay) = self.routine_delays.get(&argument) {
+                        std::thread::sleep(*delay);
+                    }
+
+                    self.run_flash_routine();
+                    self.registers.insert(0, 0);
+
+                    // The routine returns to the breakpoint its return address points to.
+                    let return_address = self.registers.get(&14).copied().unwrap_or(0) & !1;
+                    if self.read_halfword(return_address) & 0xff00 == Self::BKPT {
+                        self.registers.insert(15, return_address);
+                    }
+
+                    // The core halts again at the next breakpoint hit, with DFSR.BKPT set.
+                    if let Some(breakpoint) = self.breakpoint_hits.next() {
+                        self.registers.insert(15, breakpoint);
+                        let dfsr = self.read_word(Self::DFSR);
+                        self.memory.insert(Self::DFSR, dfsr | 0b10);
+                    }
+                }
+
+                // A step, or a halt request to a running core, sets DFSR.HALTED.
+                if value & 0b110 != 0 && self.halted && (step || !was_halted) {
+                    let dfsr = self.read_word(Self::DFSR);
+                    self.memory.insert(Self::DFSR, dfsr | 0b1);
+                }
+            }
+            Self::DCRSR => {
+                let register = value & 0x7f;
+
+                if value & (1 << 16) != 0 {
+                    let data = self.read_word(Self::DCRDR);
+                    self.registers.insert(register, data);
+                } else {
+                    let data = self.registers.get(&register).copied().unwrap_or(0);
+                    self.memory.insert(Self::DCRDR, data);
+                }
+            }
+            Self::AIRCR if value & 0b101 != 0 => {
+                // A system reset, or a local reset with VECTRESET.
+                self.reset();
+                return;
+            }
+            // The bits of DFSR are cleared by writing ones to them. Other values are stored,
+            // so that the reason of a halt can be set.
+            Self::DFSR if value & 0b11111 == 0b11111 => {
+                self.memory.insert(address, old & !0b11111);
+                return;
+            }
+            // FP_CTRL is only written if the KEY bit is set.
+            Self::FP_CTRL if value & 0b10 == 0 => return,
+            Self::MPU_RBAR => {
+                let region = self.mpu_region();
+                self.mpu_regions[region].0 = value;
+                return;
+            }
+            Self::MPU_RASR => {
+                let region = self.mpu_region();
+                self.mpu_regions[region].1 = value;
+                return;
+            }
+            Self::ICIMVAU | Self::DCIMVAC | Self::DCCMVAC | Self::DCCIMVAC => {
+                let operation = match address {
+                    Self::ICIMVAU => CacheMaintenance::InvalidateInstruction,
+                    Self::DCIMVAC => CacheMaintenance::Invalidate,
+                    Self::DCCMVAC => CacheMaintenance::Clean,
+                    _ => CacheMaintenance::CleanInvalidate,
+                };
+
+                self.cache_maintenance.push((operation, value));
+                return;
+            }
+            _ => (),
+        }
+
+        self.memory.insert(address, value);
+    }
+}
+
+/// The registers of the register list of a `PUSH` or a `POP`, with `extra` if bit 8 is set.
+fn register_list(instruction: u16, extra: usize) -> Vec<usize> {
+    let mut registers: Vec<usize> = (0..8).filter(|bit| instruction & 1 << bit != 0).collect();
+    if instruction & 0x100 != 0 {
+        registers.push(extra);
+    }
+
+    registers
+}
+
+/// Set the N and Z flags of `xpsr` for `result`, and the C and V flags if given.
+fn set_flags(xpsr: &mut u32, result: u32, carry: Option<bool>, overflow: Option<bool>) {
+    let mut set = |bit: u32, value: bool| *xpsr = *xpsr & !(1 << bit) | u32::from(value) << bit;
+
+    set(31, result >> 31 != 0);
+    set(30, result == 0);
+    if let Some(carry) = carry {
+        set(29, carry);
+    }
+    if let Some(overflow) = overflow {
+        set(28, overflow);
+    }
+}
+
+/// Add `operand` to `value`, or subtract it, and set the flags of `xpsr` like `ADDS` and
+/// `SUBS`.
+fn add_or_subtract(xpsr: &mut u32, value: u32, operand: u32, subtract: bool) -> u32 {
+    // A subtraction adds the complement, with the carry set.
+    let (operand, carry) = if subtract {
+        (!operand, 1)
+    } else {
+        (operand, 0)
+    };
+
+    let unsigned = u64::from(value) + u64::from(operand) + carry;
+    let signed = i64::from(value as i32) + i64::from(operand as i32) + carry as i64;
+    let result = unsigned as u32;
+
+    set_flags(
+        xpsr,
+        result,
+        Some(unsigned >> 32 != 0),
+        Some(i64::from(result as i32) != signed),
+    );
+
+    result
+}
+
+/// Whether the condition `cond` of a conditional branch passes with the flags of `xpsr`.
+fn condition_passed(xpsr: u32, cond: u16) -> bool {
+    let flag = |bit: u32| xpsr >> bit & 1 != 0;
+    let (n, z, c, v) = (flag(31), flag(30), flag(29), flag(28));
+
+    let passed = match cond >> 1 {
+        0 => z,
+        1 => c,
+        2 => n,
+        3 => v,
+        4 => c && !z,
+        5 => n == v,
+        6 => !z && n == v,
+        _ => true,
+    };
+
+    // The odd conditions are the inverse of the even ones.
+    passed != (cond & 1 != 0)
 }
 
 impl MockMemoryAp {
@@ -25,19 +763,247 @@ impl MockMemoryAp {
         let mut store = HashMap::new();
         store.insert(CSW::ADDRESS, 0);
         store.insert(TAR::ADDRESS, 0);
+        store.insert(TAR2::ADDRESS, 0);
         store.insert(DRW::ADDRESS, 0);
         Self {
             memory: (1..=16).collect(),
+            base_address: 0,
+            large_address_extension: false,
             store,
+            core: None,
+        }
+    }
+
+    /// Creates a MockMemoryAp with the large address extension, and the pattern of
+    /// [`MockMemoryAp::with_pattern`] at `base_address`, which can be above 4 GiB.
+    #[cfg(test)]
+    pub fn with_pattern_at(base_address: u64) -> Self {
+        Self {
+            base_address,
+            large_address_extension: true,
+            ..Self::with_pattern()
+        }
+    }
+
+    /// Returns the offset into `memory` of the address in TAR2 and `tar`, if it is above the
+    /// base address.
+    fn memory_offset(&self, tar: u32) -> Option<usize> {
+        let address = (u64::from(self.store[&TAR2::ADDRESS]) << 32) | u64::from(tar);
+
+        address
+            .checked_sub(self.base_address)
+            .and_then(|offset| offset.try_into().ok())
+    }
+
+    /// Creates a MockMemoryAp which accesses a [`MockCore`], instead of a small pattern.
+    pub fn with_mock_core() -> Self {
+        Self {
+            memory: Vec::new(),
+            core: Some(MockCore::default()),
+            ..Self::with_pattern()
+        }
+    }
+
+    /// Make the routines run by the [`MockCore`] with `R0` set to a key of `delays` take the
+    /// given time to return.
+    pub fn set_routine_delays(&mut self, delays: HashMap<u32, Duration>) {
+        if let Some(core) = &mut self.core {
+            core.routine_delays = delays;
         }
     }
+
+    /// Set the REV field of FP_CTRL of the [`MockCore`], which is 0 for an FPB which can only
+    /// break in the code region, and 1 for an FPB which can break at any address.
+    pub fn set_fpb_revision(&mut self, revision: u32) {
+        if let Some(core) = &mut self.core {
+            core.fpb_revision = revision;
+        }
+    }
+
+    /// Make the [`MockCore`] ignore the next `count` halt requests.
+    pub fn set_ignored_halt_requests(&mut self, count: u32) {
+        if let Some(core) = &mut self.core {
+            core.ignored_halt_requests = count;
+        }
+    }
+
+    /// Make the writes to the addresses in `write_faults` fail with a fault response.
+    pub fn set_write_faults(&mut self, write_faults: WriteFaults) {
+        if let Some(core) = &mut self.core {
+            core.write_faults = write_faults;
+        }
+    }
+
+    /// Make the reads of the [`MockCore`] fail with a fault response, as configured by
+    /// `read_faults`.
+    pub fn set_read_faults(&mut self, read_faults: ReadFaults) {
+        if let Some(core) = &mut self.core {
+            core.read_faults = read_faults;
+        }
+    }
+
+    /// Make the ranges of `ecc_faults` of the [`MockCore`] behave like ECC memory.
+    pub fn set_ecc_faults(&mut self, ecc_faults: EccFaults) {
+        if let Some(core) = &mut self.core {
+            core.ecc_faults = ecc_faults;
+        }
+    }
+
+    /// Make the accesses to the [`MockCore`] stall, as configured by `stalls`.
+    pub fn set_access_stalls(&mut self, stalls: AccessStalls) {
+        if let Some(core) = &mut self.core {
+            core.stalls = stalls;
+        }
+    }
+
+    /// Make the [`MockCore`] emulate the erase and program routines of `algorithm`.
+    pub fn set_flash_algorithm(&mut self, algorithm: Option<FlashAlgorithm>) {
+        if let Some(core) = &mut self.core {
+            core.flash = algorithm;
+        }
+    }
+
+    /// Make the [`MockCore`] halt and resume during the polls of the probe, as configured by
+    /// `polls`.
+    pub fn set_polls(&mut self, polls: ProbePolls) {
+        if let Some(core) = &mut self.core {
+            core.polls = polls;
+        }
+    }
+
+    /// Make the [`MockCore`] execute the Thumb code at PC when it is resumed, instead of
+    /// returning from the routine instantly.
+    pub fn set_execute_code(&mut self, execute_code: bool) {
+        if let Some(core) = &mut self.core {
+            core.execute_code = execute_code;
+        }
+    }
+
+    /// Make the [`MockCore`] halt at the breakpoints of `breakpoint_hits` when it is resumed.
+    pub fn set_breakpoint_hits(&mut self, breakpoint_hits: BreakpointHits) {
+        if let Some(core) = &mut self.core {
+            core.breakpoint_hits = breakpoint_hits;
+        }
+    }
+
+    /// Reset the [`MockCore`] at the resets of `target_resets`.
+    pub fn set_target_resets(&mut self, target_resets: TargetResets) {
+        if let Some(core) = &mut self.core {
+            core.target_resets = target_resets;
+        }
+    }
+
+    /// Resume the [`MockCore`] at the resumes of `foreign_resumes`.
+    pub fn set_foreign_resumes(&mut self, foreign_resumes: ForeignResumes) {
+        if let Some(core) = &mut self.core {
+            core.foreign_resumes = foreign_resumes;
+        }
+    }
+
+    /// Make the firmware of the [`MockCore`] write the words of `firmware_writes`.
+    pub fn set_firmware_writes(&mut self, firmware_writes: FirmwareWrites) {
+        if let Some(core) = &mut self.core {
+            core.firmware_writes = firmware_writes;
+        }
+    }
+
+    /// Log the vendor commands in `vendor_commands`, and order them with the accesses to the
+    /// [`MockCore`] as given by `ordering`.
+    pub fn set_vendor_commands(
+        &mut self,
+        vendor_commands: VendorCommands,
+        ordering: VendorCommandOrdering,
+    ) {
+        if let Some(core) = &mut self.core {
+            core.vendor_commands = vendor_commands;
+            core.vendor_command_ordering = ordering;
+        }
+    }
+
+    /// Count the round trips to the [`MockCore`] in `transactions`.
+    pub fn set_transactions(&mut self, transactions: ProbeTransactions) {
+        if let Some(core) = &mut self.core {
+            core.transactions = transactions;
+        }
+    }
+
+    /// Log the successful writes to the [`MockCore`] in `write_log`.
+    pub fn set_write_log(&mut self, write_log: WriteLog) {
+        if let Some(core) = &mut self.core {
+            core.write_log = write_log;
+        }
+    }
+
+    /// Returns the cache maintenance operations performed on the [`MockCore`], in order.
+    pub fn cache_maintenance(&self) -> &[(CacheMaintenance, u32)] {
+        self.core
+            .as_ref()
+            .map_or(&[], |core| core.cache_maintenance.as_slice())
+    }
 }
 
 impl CommunicationInterface for MockMemoryAp {
     fn flush(&mut self) -> Result<(), DebugProbeError> {
+        if let Some(core) = &self.core {
+            core.transactions.flush();
+        }
+
         Ok(())
     }
 
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, DebugProbeError> {
+        let core = match &self.core {
+            Some(core) => core,
+            None => {
+                return Err(DebugProbeError::CommandNotSupportedByProbe(
+                    "vendor commands",
+                ))
+            }
+        };
+
+        match core.vendor_command_ordering {
+            VendorCommandOrdering::Unsupported => {
+                return Err(DebugProbeError::CommandNotSupportedByProbe(
+                    "vendor commands",
+                ))
+            }
+            // The command is sent with the queued writes.
+            VendorCommandOrdering::Batched => core.transactions.write(),
+            // The queued writes are sent first, and the command takes a round trip of its own.
+            VendorCommandOrdering::Flushed => {
+                core.transactions.flush();
+                core.transactions.read();
+            }
+        }
+
+        let writes = core.write_log.entries().len();
+        let index = core.vendor_commands.record(command.clone(), writes);
+
+        Ok(VendorCommandIndex(index))
+    }
+
+    fn vendor_command_response(
+        &mut self,
+        index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        let core = match &self.core {
+            Some(core) => core,
+            None => {
+                return Err(DebugProbeError::CommandNotSupportedByProbe(
+                    "vendor commands",
+                ))
+            }
+        };
+
+        core.transactions.flush();
+        core.vendor_commands
+            .response(index.0)
+            .ok_or_else(|| anyhow!("MockMemoryAp: no vendor command at index {}", index.0).into())
+    }
+
     fn get_arm_communication_interface(
         &mut self,
     ) -> Result<
@@ -66,23 +1032,39 @@ impl ApAccess for MockMemoryAp {
             DRW::ADDRESS => {
                 let drw = self.store[&DRW::ADDRESS];
                 let bit_offset = (address % 4) * 8;
-                let offset = address as usize;
+                let offset = self.memory_offset(address);
                 let csw = CSW::from(csw);
 
-                let (new_drw, offset) = match csw.SIZE {
-                    DataSize::U32 => {
-                        let bytes: [u8; 4] = self
-                            .memory
-                            .get(offset..offset + 4)
+                let (new_drw, offset) = match (&mut self.core, csw.SIZE) {
+                    (Some(core), size) => {
+                        core.delay_access();
+                        core.transactions.read();
+
+                        if core.read_faults.fails(address) || core.ecc_faults.fails_read(address) {
+                            return Err(DapError::FaultResponse.into());
+                        }
+
+                        let word = core.read(address & !0b11);
+                        let (mask, width) = match size {
+                            DataSize::U32 => (0xffff_ffff, 4),
+                            DataSize::U16 => (0xffff << bit_offset, 2),
+                            DataSize::U8 => (0xff << bit_offset, 1),
+                            _ => return Err(anyhow!("MockMemoryAp: unknown width").into()),
+                        };
+
+                        (drw & !mask | word & mask, width)
+                    }
+                    (None, DataSize::U32) => {
+                        let bytes: [u8; 4] = offset
+                            .and_then(|offset| self.memory.get(offset..offset + 4))
                             .map(|v| v.try_into().unwrap())
                             .unwrap_or([0u8; 4]);
 
                         (u32::from_le_bytes(bytes), 4)
                     }
-                    DataSize::U16 => {
-                        let bytes = self
-                            .memory
-                            .get(offset..offset + 2)
+                    (None, DataSize::U16) => {
+                        let bytes = offset
+                            .and_then(|offset| self.memory.get(offset..offset + 2))
                             .map(|v| v.try_into().unwrap())
                             .unwrap_or([0u8; 2]);
                         let value = u16::from_le_bytes(bytes);
@@ -91,21 +1073,24 @@ impl ApAccess for MockMemoryAp {
                             2,
                         )
                     }
-                    DataSize::U8 => {
-                        let value = *self.memory.get(offset).unwrap_or(&0u8);
+                    (None, DataSize::U8) => {
+                        let value = *offset
+                            .and_then(|offset| self.memory.get(offset))
+                            .unwrap_or(&0u8);
                         (
                             drw & !(0xff << bit_offset) | (u32::from(value) << bit_offset),
                             1,
                         )
                     }
-                    _ => return Err(anyhow!("MockMemoryAp: unknown width").into()),
+                    (None, _) => return Err(anyhow!("MockMemoryAp: unknown width").into()),
                 };
 
                 self.store.insert(DRW::ADDRESS, new_drw);
 
                 match csw.AddrInc {
                     AddressIncrement::Single => {
-                        self.store.insert(TAR::ADDRESS, address + offset);
+                        self.store
+                            .insert(TAR::ADDRESS, address.wrapping_add(offset));
                     }
                     AddressIncrement::Off => (),
                     AddressIncrement::Packed => {
@@ -117,6 +1102,7 @@ impl ApAccess for MockMemoryAp {
             }
             CSW::ADDRESS => Ok(R::from(self.store[&R::ADDRESS])),
             TAR::ADDRESS => Ok(R::from(self.store[&R::ADDRESS])),
+            TAR2::ADDRESS => Ok(R::from(self.store[&R::ADDRESS])),
             _ => Err(anyhow!("MockMemoryAp: unknown register").into()),
         }
     }
@@ -153,34 +1139,63 @@ impl ApAccess for MockMemoryAp {
                     DataSize::U8 => 1,
                 };
 
-                if (address + access_width) as usize > self.memory.len() {
-                    // Ignore out-of-bounds write
+                let bit_offset = (address % 4) * 8;
+
+                if let Some(core) = &mut self.core {
+                    core.delay_access();
+
+                    let mask = match access_width {
+                        4 => 0xffff_ffff,
+                        2 => 0xffff << bit_offset,
+                        1 => 0xff << bit_offset,
+                        _ => return Err(anyhow!("MockMemoryAp: unknown width").into()),
+                    };
+
+                    if core.write_faults.contains(address)
+                        || core.ecc_faults.fails_write(address, access_width)
+                    {
+                        return Err(DapError::FaultResponse.into());
+                    }
+
+                    core.write_word(address & !0b11, value, mask);
+                    core.write_log.record(address, value);
+                    core.transactions.write();
+
+                    if csw.AddrInc == AddressIncrement::Single {
+                        self.store.insert(TAR::ADDRESS, address + access_width);
+                    }
+
                     return Ok(());
                 }
 
-                let bit_offset = (address % 4) * 8;
+                let offset = match self.memory_offset(address) {
+                    Some(offset) if offset + access_width as usize <= self.memory.len() => offset,
+                    // Ignore out-of-bounds write
+                    _ => return Ok(()),
+                };
+
                 match csw.SIZE {
                     DataSize::U32 => {
-                        self.memory[address as usize..address as usize + 4]
-                            .copy_from_slice(&value.to_le_bytes());
+                        self.memory[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
                         Ok(4)
                     }
                     DataSize::U16 => {
                         let value = value >> bit_offset;
-                        self.memory[address as usize] = value as u8;
-                        self.memory[address as usize + 1] = (value >> 8) as u8;
+                        self.memory[offset] = value as u8;
+                        self.memory[offset + 1] = (value >> 8) as u8;
                         Ok(2)
                     }
                     DataSize::U8 => {
                         let value = value >> bit_offset;
-                        self.memory[address as usize] = value as u8;
+                        self.memory[offset] = value as u8;
                         Ok(1)
                     }
                     _ => return Err(anyhow!("MockMemoryAp: unknown width").into()),
                 }
-                .map(|offset| match csw.AddrInc {
+                .map(|increment| match csw.AddrInc {
                     AddressIncrement::Single => {
-                        self.store.insert(TAR::ADDRESS, address + offset);
+                        self.store
+                            .insert(TAR::ADDRESS, address.wrapping_add(increment));
                     }
                     AddressIncrement::Off => (),
                     AddressIncrement::Packed => {
@@ -196,6 +1211,16 @@ impl ApAccess for MockMemoryAp {
                 self.store.insert(TAR::ADDRESS, value);
                 Ok(())
             }
+            TAR2::ADDRESS => {
+                // Without the large address extension, TAR2 is RAZ/WI.
+                let value = if self.large_address_extension {
+                    value
+                } else {
+                    0
+                };
+                self.store.insert(TAR2::ADDRESS, value);
+                Ok(())
+            }
             _ => Err(anyhow!("MockMemoryAp: unknown register").into()),
         }
     }
@@ -227,12 +1252,60 @@ impl ApAccess for MockMemoryAp {
         PORT: AccessPort,
         R: ApRegister<PORT>,
     {
-        for value in values {
-            let register_value: R = self.read_ap_register(port.clone())?;
-            *value = register_value.into()
+        let transactions = self
+            .core
+            .as_ref()
+            .map(|core| core.transactions.clone())
+            .unwrap_or_default();
+
+        transactions.block(|| {
+            for value in values {
+                let register_value: R = self.read_ap_register(port.clone())?;
+                *value = register_value.into()
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Mocks a poll of the word at TAR by the probe.
+    ///
+    /// A poll which doesn't match takes the whole timeout, like a probe which reads until the
+    /// timeout passed.
+    fn poll_ap_register<PORT, R>(
+        &mut self,
+        _port: impl Into<PORT>,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<Option<R>, DebugProbeError>
+    where
+        PORT: AccessPort,
+        R: ApRegister<PORT>,
+    {
+        let core = match (&mut self.core, R::ADDRESS) {
+            (Some(core), DRW::ADDRESS) => core,
+            _ => return Err(anyhow!("MockMemoryAp: polling is only mocked for the core").into()),
+        };
+
+        let address = self.store[&TAR::ADDRESS] & !0b11;
+
+        if core.polls.poll() {
+            core.halted = true;
         }
 
-        Ok(())
+        let value = core.read_word(address);
+
+        if value & mask != expected {
+            std::thread::sleep(timeout);
+            return Ok(None);
+        }
+
+        if core.polls.notify() {
+            core.halted = false;
+        }
+
+        Ok(Some(R::from(value)))
     }
 }
 
@@ -4,14 +4,17 @@
 pub mod register_generation;
 pub(crate) mod generic_ap;
 pub(crate) mod memory_ap;
+pub(crate) mod vendor;
 
 use crate::architecture::arm::dp::DebugPortError;
 use crate::DebugProbeError;
+use std::time::Duration;
 
 pub use generic_ap::{ApClass, ApType, GenericAp, IDR};
 pub use memory_ap::{
     AddressIncrement, BaseaddrFormat, DataSize, MemoryAp, BASE, BASE2, CFG, CSW, DRW, TAR, TAR2,
 };
+pub use vendor::{ApprotectStatus, KinetisMdmAp, MdmControl, MdmStatus, NordicCtrlAp, VendorAp};
 
 use super::{ApAddress, DapAccess, DpAddress, Register};
 
@@ -52,6 +55,31 @@ pub enum AccessPortError {
     /// A region ouside of the AP address space was accessed.
     #[error("Out of bounds access")]
     OutOfBounds,
+    /// An access reached above 4 GiB on an AP without the large address extension, whose
+    /// transfer address register is only 32 bits wide.
+    #[error(
+        "Failed to access address 0x{address:08x}, as the AP can't address memory above 4 GiB."
+    )]
+    LargeAddressNotSupported {
+        /// The start address of the access.
+        address: u64,
+    },
+    /// The IDR of an AP doesn't identify it as the expected vendor specific AP.
+    #[error("AP {ap} is not a {name}, its IDR is {idr:#010x}.")]
+    UnexpectedVendorAp {
+        /// The name of the expected AP.
+        name: &'static str,
+        /// The index of the AP.
+        ap: u8,
+        /// The value of the IDR of the AP.
+        idr: u32,
+    },
+    /// None of the APs of the debug port is the requested vendor specific AP.
+    #[error("The target has no {name}.")]
+    VendorApNotFound {
+        /// The name of the requested AP.
+        name: &'static str,
+    },
     /// Some error with the operation of the APs DP occurred.
     #[error("Error while communicating with debug port")]
     DebugPort(#[from] DebugPortError),
@@ -143,6 +171,22 @@ pub trait ApAccess {
     where
         PORT: AccessPort,
         R: ApRegister<PORT>;
+
+    /// Read a register of the access port repeatedly, until `value & mask == expected`, or
+    /// `timeout` passed. The register is polled by the probe, see
+    /// [`ProbeCapabilities::poll_offload`](crate::ProbeCapabilities::poll_offload).
+    ///
+    /// Returns the matching value, or `None` after the timeout.
+    fn poll_ap_register<PORT, R>(
+        &mut self,
+        port: impl Into<PORT>,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<Option<R>, DebugProbeError>
+    where
+        PORT: AccessPort,
+        R: ApRegister<PORT>;
 }
 
 impl<T: DapAccess> ApAccess for T {
@@ -208,6 +252,34 @@ impl<T: DapAccess> ApAccess for T {
 
         self.read_raw_ap_register_repeated(port.into().ap_address(), R::ADDRESS, values)
     }
+
+    fn poll_ap_register<PORT, R>(
+        &mut self,
+        port: impl Into<PORT>,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<Option<R>, DebugProbeError>
+    where
+        PORT: AccessPort,
+        R: ApRegister<PORT>,
+    {
+        log::debug!(
+            "Polling register {}, mask={:#x}, expected={:#x}",
+            R::NAME,
+            mask,
+            expected
+        );
+        let raw_value = self.poll_raw_ap_register(
+            port.into().ap_address(),
+            R::ADDRESS,
+            mask,
+            expected,
+            timeout,
+        )?;
+
+        Ok(raw_value.map(R::from))
+    }
 }
 
 /// Determine if an AP exists with the given AP number.
@@ -3,16 +3,19 @@ use super::{
         valid_access_ports, AccessPort, ApAccess, ApClass, BaseaddrFormat, GenericAp, MemoryAp,
         BASE, BASE2, CFG, CSW, IDR,
     },
-    dp::{Abort, Ctrl, DebugPortError, DebugPortVersion, DpAccess, Select, DPIDR},
+    dp::{
+        Abort, Ctrl, DebugPortError, DebugPortVersion, DpAccess, DpPowerStatus, PowerDomain,
+        Select, DPIDR,
+    },
     memory::{adi_v5_memory_interface::ADIMemoryInterface, Component},
     sequences::{ArmDebugSequence, DefaultArmSequence},
-    ApAddress, DapAccess, DpAddress, PortType, RawDapAccess, SwoAccess, SwoConfig,
+    ApAddress, DapAccess, DpAddress, PortType, RawDapAccess, SwoAccess, SwoConfig, SwoStatus,
 };
 use crate::{
-    architecture::arm::ap::DataSize, CommunicationInterface, DebugProbe, DebugProbeError,
-    Error as ProbeRsError, Memory, Probe,
+    architecture::{arm::ap::DataSize, settle::DelayOrPoll},
+    CommunicationInterface, DebugProbe, DebugProbeError, Error as ProbeRsError, Memory, Probe,
+    VendorCommand, VendorCommandIndex, VendorCommandOrdering, WireProtocol,
 };
-use anyhow::anyhow;
 use jep106::JEP106Code;
 
 use std::{
@@ -22,8 +25,12 @@ use std::{
     time::Duration,
 };
 
+/// The longest wait for the acknowledge of a power request, multiplied by the settle time
+/// factor of the [`DelayOrPoll`] of the interface.
+const POWER_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// An error with the DAP protocol occurred.
-#[derive(Debug, thiserror::Error, Clone, PartialEq)]
+#[derive(Debug, thiserror::Error, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DapError {
     /// An error occurred during SWD communication.
     #[error("An error occurred in the SWD communication between probe and device.")]
@@ -40,6 +47,20 @@ pub enum DapError {
     /// Powerup of the target device failed.
     #[error("Target power-up failed.")]
     TargetPowerUpFailed,
+    /// A power request of the debug port was not acknowledged in time.
+    #[error(
+        "The debug port did not acknowledge the {domain} power request in time: \
+        {ack} did not become {expected} (CTRL/STAT = {ctrl_stat:#010x}).",
+        ack = .domain.acknowledge_name()
+    )]
+    PowerAckTimeout {
+        /// The power domain whose acknowledge did not arrive.
+        domain: PowerDomain,
+        /// The expected value of the acknowledge, `true` for a power-up request.
+        expected: bool,
+        /// The raw value of CTRL/STAT when the wait timed out.
+        ctrl_stat: u32,
+    },
     /// The parity bit on the read request was incorrect.
     #[error("Incorrect parity on READ request.")]
     IncorrectParity,
@@ -70,17 +91,66 @@ pub trait ArmProbeInterface: DapAccess + SwdSequence + SwoAccess + Send {
     /// Returns the number of access ports the debug port has.
     fn num_access_ports(&mut self, dp: DpAddress) -> Result<usize, ProbeRsError>;
 
+    /// Returns the access ports which were discovered so far, without accessing the target.
+    fn access_ports(&self) -> Vec<ApInformation> {
+        Vec::new()
+    }
+
     /// Reads the chip info from the romtable of given debug port.
     fn read_chip_info_from_rom_table(
         &mut self,
         dp: DpAddress,
     ) -> Result<Option<ArmChipInfo>, ProbeRsError>;
 
+    /// Returns the protocol used to communicate with the target, if it is known.
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        None
+    }
+
+    /// Switch the protocol used to communicate with the target, without detaching.
+    ///
+    /// The state of all debug ports is discarded, and the debug ports are started again
+    /// when they are accessed the next time. Interfaces which can't switch the protocol
+    /// while attached return [`DebugProbeError::NotImplemented`].
+    fn switch_protocol(&mut self, _protocol: WireProtocol) -> Result<(), ProbeRsError> {
+        Err(DebugProbeError::NotImplemented("switching the protocol of an attached probe").into())
+    }
+
+    /// Set how the debug ports are powered up when they are started: the acknowledges of the
+    /// power requests are awaited with the timeouts of `delay`, and the power-up of the system
+    /// power domain is only requested if `system_power_request` is set.
+    ///
+    /// This has to be set before the first access. Interfaces which don't start the debug
+    /// ports themselves ignore it.
+    fn set_power_policy(&mut self, _delay: DelayOrPoll, _system_power_request: bool) {}
+
+    /// Read the power requests of the debug port `dp` and their acknowledges from CTRL/STAT.
+    fn dp_power_status(&mut self, _dp: DpAddress) -> Result<DpPowerStatus, ProbeRsError> {
+        Err(DebugProbeError::NotImplemented("reading the power status of a debug port").into())
+    }
+
+    /// Request the power-up of the debug power domain of the debug port `dp` if `on` is set,
+    /// or its power-down otherwise, and wait for the acknowledge.
+    ///
+    /// If the acknowledge doesn't arrive in time, [`DapError::PowerAckTimeout`] is returned.
+    fn request_debug_power(&mut self, _dp: DpAddress, _on: bool) -> Result<(), ProbeRsError> {
+        Err(DebugProbeError::NotImplemented("requesting the power of a debug port").into())
+    }
+
+    /// Request the power-up of the system power domain of the debug port `dp` if `on` is
+    /// set, or its power-down otherwise, and wait for the acknowledge.
+    ///
+    /// If the acknowledge doesn't arrive in time, [`DapError::PowerAckTimeout`] is returned.
+    fn request_system_power(&mut self, _dp: DpAddress, _on: bool) -> Result<(), ProbeRsError> {
+        Err(DebugProbeError::NotImplemented("requesting the power of a debug port").into())
+    }
+
     /// Closes the interface and returns back the generic probe it consumed.
     fn close(self: Box<Self>) -> Probe;
 }
 
 // TODO: Rename trait!
+/// Low-level control of the SWD/JTAG lines, used by the debug sequences.
 pub trait SwdSequence {
     /// Corresponds to the DAP_SWJ_Sequence function from the ARM Debug sequences
     fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), ProbeRsError>;
@@ -94,12 +164,15 @@ pub trait SwdSequence {
     ) -> Result<u32, ProbeRsError>;
 }
 
+/// An ARM debug interface which was not initialized yet.
 pub trait UninitializedArmProbe: SwdSequence {
+    /// Initialize the interface, using `sequence` to power up the debug port.
     fn initialize(
         self: Box<Self>,
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Result<Box<dyn ArmProbeInterface>, ProbeRsError>;
 
+    /// Initialize the interface with the default debug sequence.
     fn initialize_unspecified(self: Box<Self>) -> Result<Box<dyn ArmProbeInterface>, ProbeRsError> {
         self.initialize(DefaultArmSequence::create())
     }
@@ -120,6 +193,10 @@ pub struct Initialized {
     dps: HashMap<DpAddress, DpState>,
     use_overrun_detect: bool,
     sequence: Arc<dyn ArmDebugSequence>,
+    /// Waits for the acknowledges of the power requests.
+    delay: DelayOrPoll,
+    /// Whether the power-up of the system power domain is requested when a DP is started.
+    system_power_request: bool,
 }
 
 impl Initialized {
@@ -129,6 +206,8 @@ impl Initialized {
             dps: HashMap::new(),
             use_overrun_detect,
             sequence,
+            delay: DelayOrPoll::default(),
+            system_power_request: true,
         }
     }
 }
@@ -312,6 +391,52 @@ impl ArmProbeInterface for ArmCommunicationInterface<Initialized> {
         ArmCommunicationInterface::num_access_ports(self, dp)
     }
 
+    fn access_ports(&self) -> Vec<ApInformation> {
+        self.state
+            .dps
+            .values()
+            .flat_map(|dp| dp.ap_information.iter().cloned())
+            .collect()
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.probe.active_protocol()
+    }
+
+    fn switch_protocol(&mut self, protocol: WireProtocol) -> Result<(), ProbeRsError> {
+        // The debug ports are started again lazily, when they are selected the next time.
+        self.state.current_dp = None;
+        self.state.dps.clear();
+
+        self.probe.select_protocol(protocol)?;
+        self.probe.attach()?;
+
+        self.state.sequence.debug_port_setup(&mut self.probe)?;
+
+        Ok(())
+    }
+
+    fn set_power_policy(&mut self, delay: DelayOrPoll, system_power_request: bool) {
+        self.state.delay = delay;
+        self.state.system_power_request = system_power_request;
+    }
+
+    fn dp_power_status(&mut self, dp: DpAddress) -> Result<DpPowerStatus, ProbeRsError> {
+        Ok(ArmCommunicationInterface::dp_power_status(self, dp)?)
+    }
+
+    fn request_debug_power(&mut self, dp: DpAddress, on: bool) -> Result<(), ProbeRsError> {
+        Ok(ArmCommunicationInterface::request_debug_power(
+            self, dp, on,
+        )?)
+    }
+
+    fn request_system_power(&mut self, dp: DpAddress, on: bool) -> Result<(), ProbeRsError> {
+        Ok(ArmCommunicationInterface::request_system_power(
+            self, dp, on,
+        )?)
+    }
+
     fn close(self: Box<Self>) -> Probe {
         Probe::from_attached_probe(RawDapAccess::into_probe(self.probe))
     }
@@ -335,7 +460,10 @@ impl<S: ArmDebugState> SwdSequence for ArmCommunicationInterface<S> {
 }
 
 impl<'interface> ArmCommunicationInterface<Uninitialized> {
-    pub(crate) fn new(probe: Box<dyn DapProbe>, use_overrun_detect: bool) -> Self {
+    /// Create a new interface on top of a probe with raw DAP access.
+    ///
+    /// The returned interface is not initialized yet, see [`UninitializedArmProbe::initialize`].
+    pub fn new(probe: Box<dyn DapProbe>, use_overrun_detect: bool) -> Self {
         let state = Uninitialized { use_overrun_detect };
 
         Self { probe, state }
@@ -401,16 +529,18 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
         &'interface mut self,
         access_port: MemoryAp,
     ) -> Result<Memory<'interface>, ProbeRsError> {
-        let info = self.ap_information(access_port).map_err(|_| {
-            anyhow!(
-                "Failed to get information for AP {:x?}",
-                access_port.ap_address()
-            )
-        })?;
+        let info = self.ap_information(access_port)?;
 
         match info {
             ApInformation::MemoryAp(ap_information) => {
-                let information = ap_information.clone();
+                let mut information = ap_information.clone();
+
+                // Probes which can't transfer single bytes are treated like an AP which only
+                // supports 32 bit accesses, so that byte accesses are assembled from words.
+                if self.probe.capabilities().needs_word_transfers() {
+                    information.only_32bit_data_size = true;
+                }
+
                 let adi_v5_memory_interface = ADIMemoryInterface::<
                     'interface,
                     ArmCommunicationInterface<Initialized>,
@@ -419,10 +549,7 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
 
                 Ok(Memory::new(adi_v5_memory_interface, access_port))
             }
-            ApInformation::Other { address } => Err(ProbeRsError::Other(anyhow!(format!(
-                "AP {:x?} is not a memory AP",
-                address
-            )))),
+            ApInformation::Other { address } => Err(ProbeRsError::NotAMemoryAccessPort(*address)),
         }
     }
 
@@ -562,7 +689,7 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
 
         match state.ap_information.get(addr.ap as usize) {
             Some(res) => Ok(res),
-            None => Err(anyhow!("AP {:x?} does not exist", addr).into()),
+            None => Err(ProbeRsError::AccessPortNotFound(addr)),
         }
     }
 
@@ -572,6 +699,100 @@ impl<'interface> ArmCommunicationInterface<Initialized> {
         let state = self.state.dps.get(&dp).unwrap();
         Ok(state.ap_information.len())
     }
+
+    /// Read the power requests of the debug port `dp` and their acknowledges from CTRL/STAT.
+    pub fn dp_power_status(&mut self, dp: DpAddress) -> Result<DpPowerStatus, DebugProbeError> {
+        let ctrl: Ctrl = self.read_dp_register(dp)?;
+
+        Ok(DpPowerStatus::from(ctrl))
+    }
+
+    /// Request the power-up of the debug power domain of the debug port `dp` if `on` is set,
+    /// or its power-down otherwise, and wait for the acknowledge with the timeouts of the
+    /// power policy, see [`ArmProbeInterface::set_power_policy`].
+    ///
+    /// If the acknowledge doesn't arrive in time, [`DapError::PowerAckTimeout`] is returned.
+    pub fn request_debug_power(&mut self, dp: DpAddress, on: bool) -> Result<(), DebugProbeError> {
+        self.request_power(dp, PowerDomain::Debug, on)
+    }
+
+    /// Request the power-up of the system power domain of the debug port `dp` if `on` is set,
+    /// or its power-down otherwise, and wait for the acknowledge with the timeouts of the
+    /// power policy, see [`ArmProbeInterface::set_power_policy`].
+    ///
+    /// If the acknowledge doesn't arrive in time, [`DapError::PowerAckTimeout`] is returned.
+    pub fn request_system_power(&mut self, dp: DpAddress, on: bool) -> Result<(), DebugProbeError> {
+        self.request_power(dp, PowerDomain::System, on)
+    }
+
+    /// Returns `true` if the power-up of the system power domain is requested when a debug
+    /// port is started, see [`ArmProbeInterface::set_power_policy`].
+    pub fn requests_system_power(&self) -> bool {
+        self.state.system_power_request
+    }
+
+    /// Request the power-up of the debug power domain of `dp`, and of the system power
+    /// domain unless the power policy skips it, like the debug sequences do when they start
+    /// a debug port.
+    pub fn power_up_debug_port(&mut self, dp: DpAddress) -> Result<(), DebugProbeError> {
+        self.request_debug_power(dp, true)?;
+
+        if self.state.system_power_request {
+            self.request_system_power(dp, true)?;
+        }
+
+        Ok(())
+    }
+
+    fn request_power(
+        &mut self,
+        dp: DpAddress,
+        domain: PowerDomain,
+        on: bool,
+    ) -> Result<(), DebugProbeError> {
+        let current: Ctrl = self.read_dp_register(dp)?;
+
+        // Only the requests and the settings are written back, the other bits are status.
+        let mut ctrl = Ctrl(0);
+        ctrl.set_cdbgpwrupreq(current.cdbgpwrupreq());
+        ctrl.set_csyspwrupreq(current.csyspwrupreq());
+        ctrl.set_mask_lane(current.mask_lane());
+        ctrl.set_orun_detect(current.orun_detect());
+        match domain {
+            PowerDomain::Debug => ctrl.set_cdbgpwrupreq(on),
+            PowerDomain::System => ctrl.set_csyspwrupreq(on),
+        }
+        self.write_dp_register(dp, ctrl)?;
+
+        let delay = self.state.delay.clone();
+        let result = delay.poll(POWER_ACK_TIMEOUT, || {
+            let status = self.dp_power_status(dp)?;
+            Ok(status.domain(domain).acknowledged == on)
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(ProbeRsError::Probe(DebugProbeError::Timeout)) => {
+                let ctrl: Ctrl = self.read_dp_register(dp)?;
+
+                log::error!(
+                    "The {} power request of {:x?} was not acknowledged, CTRL/STAT = {:#010x}",
+                    domain,
+                    dp,
+                    ctrl.0
+                );
+
+                Err(DapError::PowerAckTimeout {
+                    domain,
+                    expected: on,
+                    ctrl_stat: ctrl.0,
+                }
+                .into())
+            }
+            Err(ProbeRsError::Probe(error)) => Err(error),
+            Err(error) => Err(DebugProbeError::ArchitectureSpecific(Box::new(error))),
+        }
+    }
 }
 
 impl CommunicationInterface for ArmCommunicationInterface<Initialized> {
@@ -579,6 +800,32 @@ impl CommunicationInterface for ArmCommunicationInterface<Initialized> {
         self.probe.raw_flush()
     }
 
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, DebugProbeError> {
+        match self.probe.capabilities().vendor_commands {
+            VendorCommandOrdering::Unsupported => Err(DebugProbeError::CommandNotSupportedByProbe(
+                "vendor commands",
+            )),
+            VendorCommandOrdering::Batched => self.probe.raw_vendor_command(command),
+            VendorCommandOrdering::Flushed => {
+                // The probe executes the command right away, so it has to send the queued
+                // transfers first to keep the order.
+                self.probe.raw_flush()?;
+                self.probe.raw_vendor_command(command)
+            }
+        }
+    }
+
+    fn vendor_command_response(
+        &mut self,
+        index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        self.probe.raw_flush()?;
+        self.probe.raw_vendor_command_response(index)
+    }
+
     fn get_arm_communication_interface(
         &mut self,
     ) -> Result<&mut ArmCommunicationInterface<Initialized>, ProbeRsError> {
@@ -607,6 +854,13 @@ impl SwoAccess for ArmCommunicationInterface<Initialized> {
             None => Err(ProbeRsError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
         }
     }
+
+    fn swo_status(&mut self) -> Result<SwoStatus, ProbeRsError> {
+        match self.probe.get_swo_interface_mut() {
+            Some(interface) => interface.swo_status(),
+            None => Err(ProbeRsError::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        }
+    }
 }
 
 impl DapAccess for ArmCommunicationInterface<Initialized> {
@@ -651,6 +905,20 @@ impl DapAccess for ArmCommunicationInterface<Initialized> {
         Ok(())
     }
 
+    fn poll_raw_ap_register(
+        &mut self,
+        ap: ApAddress,
+        address: u8,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<Option<u32>, DebugProbeError> {
+        self.select_ap_and_ap_bank(ap, address)?;
+
+        self.probe
+            .raw_poll_register(PortType::AccessPort, address, mask, expected, timeout)
+    }
+
     fn write_raw_ap_register(
         &mut self,
         ap: ApAddress,
@@ -0,0 +1,633 @@
+//! Arm ETM and PTM CoreSight Components
+//!
+//! # Description
+//! This module decodes the ID and configuration registers of the embedded trace macrocell, for
+//! the ETMv3 (including the PTM) and ETMv4 architectures. It only reads registers, enabling and
+//! programming the trace units is not supported yet.
+use super::trace_topology::ComponentRegisters;
+use crate::Error;
+use bitfield::bitfield;
+
+/// The offset of ETMIDR in ETMv3, which is TRCIDR1 in ETMv4.
+///
+/// Both registers have the major architecture version in bits \[11:8\], which is 4 for ETMv4.
+const REGISTER_OFFSET_ID: u32 = 0x1E4;
+
+const REGISTER_OFFSET_ETMCR: u32 = 0x000;
+const REGISTER_OFFSET_ETMCCR: u32 = 0x004;
+const REGISTER_OFFSET_ETMCCER: u32 = 0x1E8;
+const REGISTER_OFFSET_ETMTRACEIDR: u32 = 0x200;
+
+const REGISTER_OFFSET_TRCPRGCTLR: u32 = 0x004;
+const REGISTER_OFFSET_TRCSTATR: u32 = 0x00C;
+const REGISTER_OFFSET_TRCCONFIGR: u32 = 0x010;
+const REGISTER_OFFSET_TRCTRACEIDR: u32 = 0x040;
+const REGISTER_OFFSET_TRCIDR0: u32 = 0x1E0;
+const REGISTER_OFFSET_TRCIDR2: u32 = 0x1E8;
+const REGISTER_OFFSET_TRCIDR3: u32 = 0x1EC;
+const REGISTER_OFFSET_TRCIDR4: u32 = 0x1F0;
+const REGISTER_OFFSET_TRCIDR5: u32 = 0x1F4;
+
+/// The ID and configuration of an embedded trace macrocell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum EtmInfo {
+    /// An ETMv3 trace unit, or a PTM.
+    V3(Etmv3Info),
+    /// An ETMv4 trace unit.
+    V4(Etmv4Info),
+}
+
+impl EtmInfo {
+    /// Reads the ID and configuration registers of the trace unit.
+    ///
+    /// The architecture is determined from the major version in ETMIDR, which is at the same
+    /// offset as TRCIDR1 of an ETMv4 trace unit.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let id = registers.read_register(REGISTER_OFFSET_ID)?;
+
+        if (id >> 8) & 0xf == 4 {
+            Ok(EtmInfo::V4(Etmv4Info::read(registers)?))
+        } else {
+            Ok(EtmInfo::V3(Etmv3Info::read(registers)?))
+        }
+    }
+
+    /// The trace ID the trace unit uses in the formatted trace stream.
+    pub fn trace_id(&self) -> u8 {
+        match self {
+            EtmInfo::V3(info) => info.config.trace_id,
+            EtmInfo::V4(info) => info.config.trace_id,
+        }
+    }
+}
+
+/// The version of a trace architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TraceArchitectureVersion {
+    /// The major version, e.g. `4` for ETMv4.2.
+    pub major: u8,
+    /// The minor version, e.g. `2` for ETMv4.2.
+    pub minor: u8,
+}
+
+impl std::fmt::Display for TraceArchitectureVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+bitfield! {
+    /// ETMIDR, the ID register of an ETMv3 trace unit.
+    #[derive(Clone, Copy)]
+    struct Etmidr(u32);
+    impl Debug;
+
+    implementer, _: 31, 24;
+    major, _: 11, 8;
+    minor, _: 7, 4;
+    revision, _: 3, 0;
+}
+
+bitfield! {
+    /// ETMCCR, the configuration code register of an ETMv3 trace unit.
+    #[derive(Clone, Copy)]
+    struct Etmccr(u32);
+    impl Debug;
+
+    context_id_comparators, _: 25, 24;
+    external_outputs, _: 22, 20;
+    external_inputs, _: 19, 17;
+    sequencer, _: 16;
+    counters, _: 15, 13;
+    memory_map_decoders, _: 12, 8;
+    data_comparators, _: 7, 4;
+    address_comparator_pairs, _: 3, 0;
+}
+
+bitfield! {
+    /// ETMCR, the main control register of an ETMv3 trace unit.
+    #[derive(Clone, Copy)]
+    struct Etmcr(u32);
+    impl Debug;
+
+    timestamp, _: 28;
+    cycle_accurate, _: 12;
+    port_selection, _: 11;
+    programming, _: 10;
+    branch_output, _: 8;
+    power_down, _: 0;
+}
+
+/// The ID and configuration of an ETMv3 trace unit or a PTM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv3Info {
+    /// The trace architecture version.
+    ///
+    /// The PTM implements the program flow trace architecture, which is reported as version
+    /// `1.minor`, with [`Etmv3Info::program_flow_trace`] set.
+    pub version: TraceArchitectureVersion,
+    /// Whether this is a PTM, which only traces program flow.
+    pub program_flow_trace: bool,
+    /// The implementation revision.
+    pub revision: u8,
+    /// The JEP106 identity code of the implementer.
+    pub implementer: u8,
+    /// The resources of the trace unit.
+    pub capabilities: Etmv3Capabilities,
+    /// The current programming of the trace unit.
+    pub config: Etmv3Config,
+}
+
+/// The resources of an ETMv3 trace unit, from ETMCCR and ETMCCER.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv3Capabilities {
+    /// The number of address comparator pairs.
+    pub address_comparator_pairs: u8,
+    /// The number of data value comparators.
+    pub data_comparators: u8,
+    /// The number of memory map decoder inputs.
+    pub memory_map_decoders: u8,
+    /// The number of counters.
+    pub counters: u8,
+    /// Whether the sequencer is present.
+    pub sequencer: bool,
+    /// The number of external inputs.
+    pub external_inputs: u8,
+    /// The number of external outputs.
+    pub external_outputs: u8,
+    /// The number of context ID comparators.
+    pub context_id_comparators: u8,
+    /// Whether timestamps are supported.
+    pub timestamps: bool,
+}
+
+/// The programming of an ETMv3 trace unit, from ETMCR and ETMTRACEIDR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv3Config {
+    /// Whether the trace unit is powered down.
+    pub powered_down: bool,
+    /// Whether the trace unit is being programmed, and does not trace.
+    pub programming: bool,
+    /// Whether the trace port is selected.
+    pub port_selected: bool,
+    /// Whether all branch addresses are output.
+    pub branch_output: bool,
+    /// Whether cycle-accurate tracing is enabled.
+    pub cycle_accurate: bool,
+    /// Whether timestamps are enabled.
+    pub timestamps: bool,
+    /// The trace ID in the formatted trace stream.
+    pub trace_id: u8,
+}
+
+impl Etmv3Info {
+    /// Reads the ID and configuration registers of an ETMv3 trace unit.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let id = Etmidr(registers.read_register(REGISTER_OFFSET_ID)?);
+        let ccr = Etmccr(registers.read_register(REGISTER_OFFSET_ETMCCR)?);
+        let ccer = registers.read_register(REGISTER_OFFSET_ETMCCER)?;
+        let cr = Etmcr(registers.read_register(REGISTER_OFFSET_ETMCR)?);
+        let trace_id = registers.read_register(REGISTER_OFFSET_ETMTRACEIDR)?;
+
+        // A major version of 3 is the program flow trace architecture, PFTv1.
+        let program_flow_trace = id.major() == 3;
+        let version = if program_flow_trace {
+            TraceArchitectureVersion {
+                major: 1,
+                minor: id.minor() as u8,
+            }
+        } else {
+            TraceArchitectureVersion {
+                major: id.major() as u8 + 1,
+                minor: id.minor() as u8,
+            }
+        };
+
+        Ok(Etmv3Info {
+            version,
+            program_flow_trace,
+            revision: id.revision() as u8,
+            implementer: id.implementer() as u8,
+            capabilities: Etmv3Capabilities {
+                address_comparator_pairs: ccr.address_comparator_pairs() as u8,
+                data_comparators: ccr.data_comparators() as u8,
+                memory_map_decoders: ccr.memory_map_decoders() as u8,
+                counters: ccr.counters() as u8,
+                sequencer: ccr.sequencer(),
+                external_inputs: ccr.external_inputs() as u8,
+                external_outputs: ccr.external_outputs() as u8,
+                context_id_comparators: ccr.context_id_comparators() as u8,
+                timestamps: ccer & (1 << 22) != 0,
+            },
+            config: Etmv3Config {
+                powered_down: cr.power_down(),
+                programming: cr.programming(),
+                port_selected: cr.port_selection(),
+                branch_output: cr.branch_output(),
+                cycle_accurate: cr.cycle_accurate(),
+                timestamps: cr.timestamp(),
+                trace_id: (trace_id & 0x7f) as u8,
+            },
+        })
+    }
+}
+
+bitfield! {
+    /// TRCIDR0, the tracing capabilities of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr0(u32);
+    impl Debug;
+
+    tssize, _: 28, 24;
+    numevent, _: 11, 10;
+    retstack, _: 9;
+    trccci, _: 7;
+    trcbb, _: 5;
+    trcdata, _: 4, 3;
+}
+
+bitfield! {
+    /// TRCIDR1, the ID register of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr1(u32);
+    impl Debug;
+
+    designer, _: 31, 24;
+    archmaj, _: 11, 8;
+    archmin, _: 7, 4;
+    revision, _: 3, 0;
+}
+
+bitfield! {
+    /// TRCIDR2, the field sizes of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr2(u32);
+    impl Debug;
+
+    ccsize, _: 28, 25;
+    dvsize, _: 24, 20;
+    dasize, _: 19, 15;
+    vmidsize, _: 14, 10;
+    cidsize, _: 9, 5;
+    iasize, _: 4, 0;
+}
+
+bitfield! {
+    /// TRCIDR3, the processing element options of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr3(u32);
+    impl Debug;
+
+    numproc, _: 30, 28;
+    sysstall, _: 27;
+    stallctl, _: 26;
+    ccitmin, _: 11, 0;
+}
+
+bitfield! {
+    /// TRCIDR4, the resources of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr4(u32);
+    impl Debug;
+
+    numvmidc, _: 31, 28;
+    numcidc, _: 27, 24;
+    numssc, _: 23, 20;
+    numrspair, _: 19, 16;
+    numpc, _: 15, 12;
+    numdvc, _: 7, 4;
+    numacpairs, _: 3, 0;
+}
+
+bitfield! {
+    /// TRCIDR5, the further resources of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcidr5(u32);
+    impl Debug;
+
+    numcntr, _: 30, 28;
+    numseqstate, _: 27, 25;
+    traceidsize, _: 21, 16;
+    numextin, _: 8, 0;
+}
+
+bitfield! {
+    /// TRCCONFIGR, the trace configuration of an ETMv4 trace unit.
+    #[derive(Clone, Copy)]
+    struct Trcconfigr(u32);
+    impl Debug;
+
+    dv, _: 17;
+    da, _: 16;
+    qe, _: 14, 13;
+    rs, _: 12;
+    ts, _: 11;
+    cond, _: 10, 8;
+    vmid, _: 7;
+    cid, _: 6;
+    cci, _: 4;
+    bb, _: 3;
+}
+
+/// The ID and configuration of an ETMv4 trace unit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv4Info {
+    /// The trace architecture version.
+    pub version: TraceArchitectureVersion,
+    /// The implementation revision.
+    pub revision: u8,
+    /// The JEP106 identity code of the designer.
+    pub designer: u8,
+    /// The tracing capabilities and resources of the trace unit.
+    pub capabilities: Etmv4Capabilities,
+    /// The current programming of the trace unit.
+    pub config: Etmv4Config,
+}
+
+/// The capabilities of an ETMv4 trace unit, from TRCIDR0 and TRCIDR2 to TRCIDR5.
+///
+/// Sizes are in bits, and `0` if the element isn't traced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv4Capabilities {
+    /// The size of instruction addresses.
+    pub instruction_address_size: u8,
+    /// The size of context IDs.
+    pub context_id_size: u8,
+    /// The size of virtual machine IDs.
+    pub vmid_size: u8,
+    /// The size of data addresses.
+    pub data_address_size: u8,
+    /// The size of data values.
+    pub data_value_size: u8,
+    /// The size of the cycle counter, `0` if cycle counting isn't supported.
+    pub cycle_count_size: u8,
+    /// The size of the global timestamp.
+    pub timestamp_size: u8,
+    /// Whether the return stack is implemented.
+    pub return_stack: bool,
+    /// Whether branch broadcast tracing is supported.
+    pub branch_broadcast: bool,
+    /// Whether data tracing is supported, for loads or stores.
+    pub data_trace: bool,
+    /// The minimum cycle count threshold.
+    pub min_cycle_count_threshold: u16,
+    /// The number of processing elements traced by the trace unit.
+    pub processing_elements: u8,
+    /// Whether the trace unit can stall the processing element to avoid overflows.
+    pub stall_control: bool,
+    /// Whether the system has support for stalling the processing element.
+    pub system_stall: bool,
+    /// The number of events that can be generated.
+    pub events: u8,
+    /// The number of address comparator pairs.
+    pub address_comparator_pairs: u8,
+    /// The number of data value comparators.
+    pub data_value_comparators: u8,
+    /// The number of processing element comparator inputs.
+    pub pe_comparator_inputs: u8,
+    /// The number of resource selection pairs.
+    pub resource_selection_pairs: u8,
+    /// The number of single-shot comparator controls.
+    pub single_shot_comparators: u8,
+    /// The number of context ID comparators.
+    pub context_id_comparators: u8,
+    /// The number of virtual machine ID comparators.
+    pub vmid_comparators: u8,
+    /// The number of counters.
+    pub counters: u8,
+    /// The number of sequencer states, `0` if there is no sequencer.
+    pub sequencer_states: u8,
+    /// The number of external inputs.
+    pub external_inputs: u16,
+    /// The size of the trace ID, in bits.
+    pub trace_id_size: u8,
+}
+
+/// The programming of an ETMv4 trace unit, from TRCPRGCTLR, TRCSTATR, TRCCONFIGR and
+/// TRCTRACEIDR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Etmv4Config {
+    /// Whether the trace unit is enabled.
+    pub enabled: bool,
+    /// Whether the trace unit is idle.
+    pub idle: bool,
+    /// Whether the programmers' model is stable, and the registers can be read reliably.
+    pub stable: bool,
+    /// Whether branch broadcast mode is enabled.
+    pub branch_broadcast: bool,
+    /// Whether cycle counting is enabled.
+    pub cycle_counting: bool,
+    /// Whether context ID tracing is enabled.
+    pub context_id: bool,
+    /// Whether virtual machine ID tracing is enabled.
+    pub vmid: bool,
+    /// Whether global timestamps are enabled.
+    pub timestamps: bool,
+    /// Whether the return stack is enabled.
+    pub return_stack: bool,
+    /// The Q element mode, TRCCONFIGR.QE.
+    pub q_elements: u8,
+    /// The conditional instruction tracing mode, TRCCONFIGR.COND.
+    pub conditional: u8,
+    /// Whether data address tracing is enabled.
+    pub data_address: bool,
+    /// Whether data value tracing is enabled.
+    pub data_value: bool,
+    /// The trace ID in the formatted trace stream.
+    pub trace_id: u8,
+}
+
+impl Etmv4Info {
+    /// Reads the ID and configuration registers of an ETMv4 trace unit.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let idr0 = Trcidr0(registers.read_register(REGISTER_OFFSET_TRCIDR0)?);
+        let idr1 = Trcidr1(registers.read_register(REGISTER_OFFSET_ID)?);
+        let idr2 = Trcidr2(registers.read_register(REGISTER_OFFSET_TRCIDR2)?);
+        let idr3 = Trcidr3(registers.read_register(REGISTER_OFFSET_TRCIDR3)?);
+        let idr4 = Trcidr4(registers.read_register(REGISTER_OFFSET_TRCIDR4)?);
+        let idr5 = Trcidr5(registers.read_register(REGISTER_OFFSET_TRCIDR5)?);
+
+        let prgctlr = registers.read_register(REGISTER_OFFSET_TRCPRGCTLR)?;
+        let statr = registers.read_register(REGISTER_OFFSET_TRCSTATR)?;
+        let configr = Trcconfigr(registers.read_register(REGISTER_OFFSET_TRCCONFIGR)?);
+        let trace_id = registers.read_register(REGISTER_OFFSET_TRCTRACEIDR)?;
+
+        // The size fields encode the size in bytes.
+        let size = |bytes: u32| (bytes * 8) as u8;
+
+        Ok(Etmv4Info {
+            version: TraceArchitectureVersion {
+                major: idr1.archmaj() as u8,
+                minor: idr1.archmin() as u8,
+            },
+            revision: idr1.revision() as u8,
+            designer: idr1.designer() as u8,
+            capabilities: Etmv4Capabilities {
+                instruction_address_size: size(idr2.iasize()),
+                context_id_size: size(idr2.cidsize()),
+                vmid_size: size(idr2.vmidsize()),
+                data_address_size: size(idr2.dasize()),
+                data_value_size: size(idr2.dvsize()),
+                cycle_count_size: if idr0.trccci() {
+                    idr2.ccsize() as u8 + 12
+                } else {
+                    0
+                },
+                timestamp_size: size(idr0.tssize()),
+                return_stack: idr0.retstack(),
+                branch_broadcast: idr0.trcbb(),
+                data_trace: idr0.trcdata() != 0,
+                min_cycle_count_threshold: idr3.ccitmin() as u16,
+                processing_elements: idr3.numproc() as u8 + 1,
+                stall_control: idr3.stallctl(),
+                system_stall: idr3.sysstall(),
+                events: idr0.numevent() as u8 + 1,
+                address_comparator_pairs: idr4.numacpairs() as u8,
+                data_value_comparators: idr4.numdvc() as u8,
+                pe_comparator_inputs: idr4.numpc() as u8,
+                // Zero means no resource selection pairs, other values are one less than the
+                // number of pairs.
+                resource_selection_pairs: match idr4.numrspair() {
+                    0 => 0,
+                    pairs => pairs as u8 + 1,
+                },
+                single_shot_comparators: idr4.numssc() as u8,
+                context_id_comparators: idr4.numcidc() as u8,
+                vmid_comparators: idr4.numvmidc() as u8,
+                counters: idr5.numcntr() as u8,
+                sequencer_states: idr5.numseqstate() as u8,
+                external_inputs: idr5.numextin() as u16,
+                trace_id_size: idr5.traceidsize() as u8,
+            },
+            config: Etmv4Config {
+                enabled: prgctlr & 1 != 0,
+                idle: statr & 1 != 0,
+                stable: statr & 2 != 0,
+                branch_broadcast: configr.bb(),
+                cycle_counting: configr.cci(),
+                context_id: configr.cid(),
+                vmid: configr.vmid(),
+                timestamps: configr.ts(),
+                return_stack: configr.rs(),
+                q_elements: configr.qe() as u8,
+                conditional: configr.cond() as u8,
+                data_address: configr.da(),
+                data_value: configr.dv(),
+                trace_id: (trace_id & 0x7f) as u8,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::component::RegisterDump;
+
+    /// The ID and configuration registers of the Cortex-M7 ETM of an STM32H743, with
+    /// instruction trace and timestamps enabled.
+    fn cortex_m7_etm() -> RegisterDump {
+        [
+            (0x004, 0x0000_0001),
+            (0x00C, 0x0000_0002),
+            (0x010, 0x0000_0818),
+            (0x040, 0x0000_0010),
+            (0x1E0, 0x2800_0EA1),
+            (0x1E4, 0x4100_F421),
+            (0x1E8, 0x0000_0004),
+            (0x1EC, 0x0F09_0004),
+            (0x1F0, 0x0011_4000),
+            (0x1F4, 0x90C7_0004),
+            (0xFBC, 0x4772_4A13),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn decode_cortex_m7_etm() {
+        let info = EtmInfo::read(&mut cortex_m7_etm()).unwrap();
+
+        let info = match info {
+            EtmInfo::V4(info) => info,
+            other => panic!("Expected an ETMv4 trace unit, got {:?}", other),
+        };
+
+        assert_eq!(
+            info.version,
+            TraceArchitectureVersion { major: 4, minor: 2 }
+        );
+        assert_eq!(info.version.to_string(), "4.2");
+        assert_eq!(info.revision, 1);
+        assert_eq!(info.designer, 0x41);
+
+        let capabilities = &info.capabilities;
+        assert_eq!(capabilities.instruction_address_size, 32);
+        assert_eq!(capabilities.context_id_size, 0);
+        assert_eq!(capabilities.data_address_size, 0);
+        assert_eq!(capabilities.cycle_count_size, 12);
+        assert_eq!(capabilities.timestamp_size, 64);
+        assert!(capabilities.return_stack);
+        assert!(capabilities.branch_broadcast);
+        assert!(!capabilities.data_trace);
+        assert_eq!(capabilities.min_cycle_count_threshold, 4);
+        assert_eq!(capabilities.processing_elements, 1);
+        assert!(capabilities.system_stall);
+        assert!(capabilities.stall_control);
+        assert_eq!(capabilities.events, 4);
+        assert_eq!(capabilities.address_comparator_pairs, 0);
+        assert_eq!(capabilities.pe_comparator_inputs, 4);
+        assert_eq!(capabilities.resource_selection_pairs, 2);
+        assert_eq!(capabilities.single_shot_comparators, 1);
+        assert_eq!(capabilities.counters, 1);
+        assert_eq!(capabilities.sequencer_states, 0);
+        assert_eq!(capabilities.external_inputs, 4);
+        assert_eq!(capabilities.trace_id_size, 7);
+
+        let config = &info.config;
+        assert!(config.enabled);
+        assert!(!config.idle);
+        assert!(config.stable);
+        assert!(config.branch_broadcast);
+        assert!(config.cycle_counting);
+        assert!(config.timestamps);
+        assert!(!config.context_id);
+        assert!(!config.return_stack);
+        assert_eq!(config.trace_id, 0x10);
+    }
+
+    #[test]
+    fn decode_cortex_m4_etm() {
+        // ETMv3.5 of a Cortex-M4, powered up with timestamps and trace ID 2.
+        let mut dump: RegisterDump = [
+            (0x000, 0x1000_0C00),
+            (0x004, 0x8C80_2000),
+            (0x1E4, 0x4114_F250),
+            (0x1E8, 0x1840_0000),
+            (0x200, 0x0000_0002),
+        ]
+        .into_iter()
+        .collect();
+
+        let info = match EtmInfo::read(&mut dump).unwrap() {
+            EtmInfo::V3(info) => info,
+            other => panic!("Expected an ETMv3 trace unit, got {:?}", other),
+        };
+
+        assert_eq!(
+            info.version,
+            TraceArchitectureVersion { major: 3, minor: 5 }
+        );
+        assert!(!info.program_flow_trace);
+        assert_eq!(info.implementer, 0x41);
+        assert_eq!(info.capabilities.address_comparator_pairs, 0);
+        assert_eq!(info.capabilities.counters, 1);
+        assert_eq!(info.capabilities.external_inputs, 0);
+        assert!(info.capabilities.timestamps);
+        assert!(!info.config.powered_down);
+        assert!(info.config.programming);
+        assert!(info.config.port_selected);
+        assert!(info.config.timestamps);
+        assert_eq!(EtmInfo::V3(info).trace_id(), 2);
+    }
+}
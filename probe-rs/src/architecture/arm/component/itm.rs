@@ -4,6 +4,7 @@
 
 use super::super::memory::romtable::CoresightComponent;
 use super::DebugRegister;
+use crate::architecture::arm::swo::{GlobalTimestampFrequency, TimestampPrescaler};
 use crate::architecture::arm::ArmProbeInterface;
 use crate::Error;
 
@@ -83,6 +84,31 @@ impl<'a> Itm<'a> {
 
         Ok(())
     }
+
+    /// Configure the local and global timestamps.
+    ///
+    /// Local timestamps are enabled with the given `prescaler`, or disabled if it is `None`.
+    pub fn configure_timestamps(
+        &mut self,
+        prescaler: Option<TimestampPrescaler>,
+        global: GlobalTimestampFrequency,
+    ) -> Result<(), Error> {
+        let mut value = self
+            .component
+            .read_reg(self.interface, REGISTER_OFFSET_ITM_TCR)?;
+
+        // TSENA, TSPrescale and GTSFREQ
+        value &= !((1 << 1) | (0b11 << 8) | (0b11 << 10));
+
+        if let Some(prescaler) = prescaler {
+            value |= 1 << 1;
+            value |= prescaler.tcr_value() << 8;
+        }
+        value |= global.tcr_value() << 10;
+
+        self.component
+            .write_reg(self.interface, REGISTER_OFFSET_ITM_TCR, value)
+    }
 }
 
 mod register {
@@ -1,20 +1,33 @@
 //! Types and functions for interacting with CoreSight Components
 
 mod dwt;
+mod etm;
 mod itm;
 mod swo;
 mod tpiu;
 mod trace_funnel;
+mod trace_topology;
 
 use super::memory::romtable::{CoresightComponent, PeripheralType, RomTableError};
 use crate::architecture::arm::core::armv6m::Demcr;
 use crate::architecture::arm::{ArmProbeInterface, SwoConfig, SwoMode};
 use crate::{Core, Error, MemoryInterface, MemoryMappedRegister};
 pub use dwt::Dwt;
+pub use etm::{
+    EtmInfo, Etmv3Capabilities, Etmv3Config, Etmv3Info, Etmv4Capabilities, Etmv4Config, Etmv4Info,
+    TraceArchitectureVersion,
+};
 pub use itm::Itm;
 pub use swo::Swo;
 pub use tpiu::Tpiu;
 pub use trace_funnel::TraceFunnel;
+pub(crate) use trace_topology::{find_component_at, trace_topology, LiveComponent};
+pub use trace_topology::{
+    write_unlocked, ComponentRegisters, EtbInfo, FunnelInfo, ItmInfo, LockStatus, RegisterDump,
+    SwoUnitInfo, TpiuInfo, TraceComponentLocation, TraceLink, TracePinProtocol, TraceSink,
+    TraceSinkKind, TraceSource, TraceSourceKind, TraceTopology, REGISTER_OFFSET_LAR,
+    REGISTER_OFFSET_LSR,
+};
 
 /// An error when operating a core ROM table component occurred.
 #[derive(thiserror::Error, Debug)]
@@ -22,6 +35,9 @@ pub enum ComponentError {
     /// Nordic chips do not support setting all TPIU clocks. Try choosing another clock speed.
     #[error("Nordic does not support TPIU CLK value of {0}")]
     NordicUnsupportedTPUICLKValue(u32),
+    /// No CoreSight component is located at the given base address.
+    #[error("No CoreSight component is located at {0:#010x}")]
+    UnknownComponent(u64),
 }
 
 /// A trait to be implemented on debug register types for debug component interfaces.
@@ -147,6 +163,7 @@ pub(crate) fn setup_swv(
     let mut itm = Itm::new(interface, find_component(components, PeripheralType::Itm)?);
     itm.unlock()?;
     itm.tx_enable()?;
+    itm.configure_timestamps(config.local_timestamps(), config.global_timestamps())?;
 
     // Configure DWT
     let mut dwt = Dwt::new(interface, find_component(components, PeripheralType::Dwt)?);
@@ -0,0 +1,683 @@
+//! Discovery of the CoreSight trace topology
+//!
+//! # Description
+//! This module describes the trace sources, funnels and sinks found in the ROM tables of a
+//! target, and decodes their configuration registers. The decoders read through
+//! [`ComponentRegisters`], so they work the same on a live component and on a [`RegisterDump`].
+use super::etm::EtmInfo;
+use crate::architecture::arm::ap::AccessPort;
+use crate::architecture::arm::memory::romtable::{CoresightComponent, PeripheralType};
+use crate::architecture::arm::ArmProbeInterface;
+use crate::Error;
+use bitfield::bitfield;
+use std::collections::BTreeMap;
+
+/// The offset of the lock access register, LAR.
+pub const REGISTER_OFFSET_LAR: u32 = 0xFB0;
+/// The offset of the lock status register, LSR.
+pub const REGISTER_OFFSET_LSR: u32 = 0xFB4;
+/// The key which unlocks a component when written to LAR. Any other value locks it.
+const UNLOCK_KEY: u32 = 0xC5AC_CE55;
+
+/// The architecture ID of an ETMv4 trace unit in DEVARCH.
+const ARCH_ID_ETMV4: u16 = 0x4A13;
+
+const REGISTER_OFFSET_ITM_TCR: u32 = 0xE80;
+
+const REGISTER_OFFSET_FUNNEL_CTRL: u32 = 0x000;
+const REGISTER_OFFSET_DEVID: u32 = 0xFC8;
+
+const REGISTER_OFFSET_TPIU_SSPSR: u32 = 0x000;
+const REGISTER_OFFSET_TPIU_CSPSR: u32 = 0x004;
+const REGISTER_OFFSET_TPIU_ACPR: u32 = 0x010;
+const REGISTER_OFFSET_TPIU_SPPR: u32 = 0x0F0;
+const REGISTER_OFFSET_TPIU_FFSR: u32 = 0x300;
+const REGISTER_OFFSET_TPIU_FFCR: u32 = 0x304;
+
+const REGISTER_OFFSET_SWO_CODR: u32 = 0x010;
+const REGISTER_OFFSET_SWO_SPPR: u32 = 0x0F0;
+
+const REGISTER_OFFSET_ETB_RDP: u32 = 0x004;
+const REGISTER_OFFSET_ETB_CTL: u32 = 0x020;
+
+/// Access to the registers of a single CoreSight component, by their offset from its base
+/// address.
+pub trait ComponentRegisters {
+    /// Reads the register at `offset`.
+    fn read_register(&mut self, offset: u32) -> Result<u32, Error>;
+
+    /// Writes `value` to the register at `offset`.
+    fn write_register(&mut self, offset: u32, value: u32) -> Result<(), Error>;
+}
+
+/// Recorded register values of a CoreSight component, by offset.
+///
+/// Registers which weren't recorded read as zero, like reserved registers of a component.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterDump {
+    registers: BTreeMap<u32, u32>,
+}
+
+impl RegisterDump {
+    /// Creates an empty register dump.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the registers at `offsets` of a component.
+    pub fn record(
+        registers: &mut dyn ComponentRegisters,
+        offsets: impl IntoIterator<Item = u32>,
+    ) -> Result<Self, Error> {
+        offsets
+            .into_iter()
+            .map(|offset| Ok((offset, registers.read_register(offset)?)))
+            .collect()
+    }
+
+    /// Returns the recorded value of the register at `offset`.
+    pub fn get(&self, offset: u32) -> Option<u32> {
+        self.registers.get(&offset).copied()
+    }
+}
+
+impl FromIterator<(u32, u32)> for RegisterDump {
+    fn from_iter<I: IntoIterator<Item = (u32, u32)>>(iter: I) -> Self {
+        RegisterDump {
+            registers: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl ComponentRegisters for RegisterDump {
+    fn read_register(&mut self, offset: u32) -> Result<u32, Error> {
+        Ok(self.get(offset).unwrap_or(0))
+    }
+
+    fn write_register(&mut self, offset: u32, value: u32) -> Result<(), Error> {
+        self.registers.insert(offset, value);
+        Ok(())
+    }
+}
+
+/// The registers of a component on the target.
+pub(crate) struct LiveComponent<'a> {
+    interface: &'a mut Box<dyn ArmProbeInterface>,
+    component: &'a CoresightComponent,
+}
+
+impl<'a> LiveComponent<'a> {
+    pub(crate) fn new(
+        interface: &'a mut Box<dyn ArmProbeInterface>,
+        component: &'a CoresightComponent,
+    ) -> Self {
+        LiveComponent {
+            interface,
+            component,
+        }
+    }
+}
+
+impl ComponentRegisters for LiveComponent<'_> {
+    fn read_register(&mut self, offset: u32) -> Result<u32, Error> {
+        self.component.read_reg(self.interface, offset)
+    }
+
+    fn write_register(&mut self, offset: u32, value: u32) -> Result<(), Error> {
+        self.component.write_reg(self.interface, offset, value)
+    }
+}
+
+bitfield! {
+    /// LSR, the lock status register of a CoreSight component.
+    #[derive(Clone, Copy)]
+    pub struct LockStatus(u32);
+    impl Debug;
+
+    /// Whether LAR is only 8 bits wide.
+    pub eight_bit, _: 2;
+    /// Whether the component is locked, and ignores writes to its other registers.
+    pub locked, _: 1;
+    /// Whether the lock mechanism is implemented for this access.
+    pub implemented, _: 0;
+}
+
+/// Writes `value` to the register at `offset`, unlocking the component for the write.
+///
+/// If the component was locked, it is locked again afterwards, also when the write fails. Reads
+/// aren't affected by the lock. Writes to LAR itself are passed through, so the caller can manage
+/// the lock.
+///
+/// Accesses from an external debugger often bypass the lock, in which case LSR reports it as
+/// not implemented and the write is done directly.
+pub fn write_unlocked(
+    registers: &mut dyn ComponentRegisters,
+    offset: u32,
+    value: u32,
+) -> Result<(), Error> {
+    if offset == REGISTER_OFFSET_LAR {
+        return registers.write_register(offset, value);
+    }
+
+    let status = LockStatus(registers.read_register(REGISTER_OFFSET_LSR)?);
+    if !status.implemented() || !status.locked() {
+        return registers.write_register(offset, value);
+    }
+
+    registers.write_register(REGISTER_OFFSET_LAR, UNLOCK_KEY)?;
+    let result = registers.write_register(offset, value);
+    let relock = registers.write_register(REGISTER_OFFSET_LAR, 0);
+
+    result.and(relock)
+}
+
+/// The trace sources, funnels and sinks of a target.
+///
+/// Only components which are listed in a ROM table and recognised by their peripheral ID are
+/// described.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TraceTopology {
+    /// The components which generate trace.
+    pub sources: Vec<TraceSource>,
+    /// The funnels which merge trace from several sources.
+    pub funnels: Vec<TraceLink>,
+    /// The components which output or capture trace.
+    pub sinks: Vec<TraceSink>,
+}
+
+/// Where a trace component is located.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceComponentLocation {
+    /// The name of the part, from the peripheral ID.
+    pub name: String,
+    /// The access port through which the component is accessed.
+    pub ap: u8,
+    /// The base address of the component.
+    pub address: u64,
+}
+
+/// A component which generates trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceSource {
+    /// Where the source is located.
+    pub location: TraceComponentLocation,
+    /// The kind and configuration of the source.
+    pub kind: TraceSourceKind,
+}
+
+impl TraceSource {
+    /// The trace ID the source uses in the formatted trace stream.
+    pub fn trace_id(&self) -> u8 {
+        match &self.kind {
+            TraceSourceKind::Etm(info) => info.trace_id(),
+            TraceSourceKind::Itm(info) => info.trace_id,
+        }
+    }
+}
+
+/// The kind and configuration of a trace source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum TraceSourceKind {
+    /// An embedded trace macrocell.
+    Etm(EtmInfo),
+    /// An instrumentation trace macrocell.
+    Itm(ItmInfo),
+}
+
+/// A funnel which merges trace from several sources.
+///
+/// Which source is connected to which input port can't be discovered without using the
+/// integration mode of the components, which would disturb tracing. The formatted trace stream
+/// identifies sources by their [`TraceSource::trace_id`] instead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceLink {
+    /// Where the funnel is located.
+    pub location: TraceComponentLocation,
+    /// The configuration of the funnel.
+    pub funnel: FunnelInfo,
+}
+
+/// A component which outputs or captures trace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TraceSink {
+    /// Where the sink is located.
+    pub location: TraceComponentLocation,
+    /// The kind and configuration of the sink.
+    pub kind: TraceSinkKind,
+}
+
+/// The kind and configuration of a trace sink.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum TraceSinkKind {
+    /// A trace port interface unit.
+    Tpiu(TpiuInfo),
+    /// A serial wire output unit.
+    Swo(SwoUnitInfo),
+    /// An embedded trace buffer.
+    Etb(EtbInfo),
+}
+
+bitfield! {
+    /// TCR, the trace control register of the ITM.
+    #[derive(Clone, Copy)]
+    struct ItmTcr(u32);
+    impl Debug;
+
+    busy, _: 23;
+    trace_bus_id, _: 22, 16;
+    txena, _: 3;
+    syncena, _: 2;
+    tsena, _: 1;
+    itmena, _: 0;
+}
+
+/// The configuration of an ITM, from TCR.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ItmInfo {
+    /// Whether the ITM is enabled.
+    pub enabled: bool,
+    /// Whether local timestamps are enabled.
+    pub timestamps: bool,
+    /// Whether synchronization packets are enabled.
+    pub synchronization: bool,
+    /// Whether hardware trace packets of the DWT are forwarded.
+    pub dwt_forwarding: bool,
+    /// Whether the ITM is processing packets.
+    pub busy: bool,
+    /// The trace ID in the formatted trace stream.
+    pub trace_id: u8,
+}
+
+impl ItmInfo {
+    /// Reads the configuration registers of an ITM.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let tcr = ItmTcr(registers.read_register(REGISTER_OFFSET_ITM_TCR)?);
+
+        Ok(ItmInfo {
+            enabled: tcr.itmena(),
+            timestamps: tcr.tsena(),
+            synchronization: tcr.syncena(),
+            dwt_forwarding: tcr.txena(),
+            busy: tcr.busy(),
+            trace_id: tcr.trace_bus_id() as u8,
+        })
+    }
+}
+
+/// The configuration of a trace funnel, from CTRL and DEVID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FunnelInfo {
+    /// The number of input ports.
+    pub ports: u8,
+    /// The input ports which are enabled.
+    pub enabled_ports: Vec<u8>,
+    /// The number of transactions the arbiter performs on an input before switching to the next
+    /// one.
+    pub hold_time: u8,
+}
+
+impl FunnelInfo {
+    /// Reads the configuration registers of a trace funnel.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let ctrl = registers.read_register(REGISTER_OFFSET_FUNNEL_CTRL)?;
+        let devid = registers.read_register(REGISTER_OFFSET_DEVID)?;
+
+        // Older funnels don't implement DEVID, and always have eight ports.
+        let ports = match devid & 0xf {
+            0 => 8,
+            ports => ports as u8,
+        };
+
+        Ok(FunnelInfo {
+            ports,
+            enabled_ports: (0..ports).filter(|port| ctrl & (1 << port) != 0).collect(),
+            hold_time: ((ctrl >> 8) & 0xf) as u8 + 1,
+        })
+    }
+
+    /// Returns whether input `port` is enabled.
+    pub fn is_enabled(&self, port: u8) -> bool {
+        self.enabled_ports.contains(&port)
+    }
+}
+
+/// The protocol used on the trace output pins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TracePinProtocol {
+    /// Synchronous trace on the parallel trace port.
+    Parallel,
+    /// Asynchronous SWO with Manchester encoding.
+    Manchester,
+    /// Asynchronous SWO with NRZ (UART) encoding.
+    Nrz,
+    /// A reserved protocol value.
+    Reserved(u8),
+}
+
+impl From<u32> for TracePinProtocol {
+    fn from(sppr: u32) -> Self {
+        match sppr & 0x3 {
+            0 => TracePinProtocol::Parallel,
+            1 => TracePinProtocol::Manchester,
+            2 => TracePinProtocol::Nrz,
+            value => TracePinProtocol::Reserved(value as u8),
+        }
+    }
+}
+
+bitfield! {
+    /// DEVID of a TPIU.
+    #[derive(Clone, Copy)]
+    struct TpiuDevid(u32);
+    impl Debug;
+
+    nrz, _: 11;
+    manchester, _: 10;
+    parallel_unsupported, _: 9;
+}
+
+/// The configuration of a TPIU.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TpiuInfo {
+    /// The supported widths of the parallel trace port, in bits.
+    pub supported_port_sizes: Vec<u8>,
+    /// The current width of the parallel trace port, in bits.
+    pub port_size: Option<u8>,
+    /// The protocol used on the trace output pins.
+    pub pin_protocol: TracePinProtocol,
+    /// The SWO baud rate prescaler. The baud rate is the trace clock divided by
+    /// `prescaler + 1`.
+    pub prescaler: u16,
+    /// Whether the formatter runs in continuous mode, so the output is formatted with trace IDs.
+    pub continuous_formatting: bool,
+    /// Whether the trigger input is indicated in the trace stream.
+    pub trigger_in: bool,
+    /// Whether the formatter is stopped.
+    pub formatter_stopped: bool,
+    /// Whether the parallel trace port is supported.
+    pub parallel_supported: bool,
+    /// Whether SWO with Manchester encoding is supported.
+    pub manchester_supported: bool,
+    /// Whether SWO with NRZ encoding is supported.
+    pub nrz_supported: bool,
+}
+
+impl TpiuInfo {
+    /// Reads the configuration registers of a TPIU.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let sspsr = registers.read_register(REGISTER_OFFSET_TPIU_SSPSR)?;
+        let cspsr = registers.read_register(REGISTER_OFFSET_TPIU_CSPSR)?;
+        let acpr = registers.read_register(REGISTER_OFFSET_TPIU_ACPR)?;
+        let sppr = registers.read_register(REGISTER_OFFSET_TPIU_SPPR)?;
+        let ffsr = registers.read_register(REGISTER_OFFSET_TPIU_FFSR)?;
+        let ffcr = registers.read_register(REGISTER_OFFSET_TPIU_FFCR)?;
+        let devid = TpiuDevid(registers.read_register(REGISTER_OFFSET_DEVID)?);
+
+        // Bit N of SSPSR and CSPSR stands for a port size of N + 1 bits.
+        Ok(TpiuInfo {
+            supported_port_sizes: (0..32)
+                .filter(|bit| sspsr & (1 << bit) != 0)
+                .map(|bit| bit as u8 + 1)
+                .collect(),
+            port_size: (cspsr != 0).then(|| cspsr.trailing_zeros() as u8 + 1),
+            pin_protocol: sppr.into(),
+            prescaler: (acpr & 0x1fff) as u16,
+            continuous_formatting: ffcr & (1 << 1) != 0,
+            trigger_in: ffcr & (1 << 8) != 0,
+            formatter_stopped: ffsr & (1 << 1) != 0,
+            parallel_supported: !devid.parallel_unsupported(),
+            manchester_supported: devid.manchester(),
+            nrz_supported: devid.nrz(),
+        })
+    }
+}
+
+/// The configuration of a CoreSight SWO unit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SwoUnitInfo {
+    /// The protocol used on the SWO pin.
+    pub pin_protocol: TracePinProtocol,
+    /// The baud rate prescaler. The baud rate is the trace clock divided by `prescaler + 1`.
+    pub prescaler: u16,
+}
+
+impl SwoUnitInfo {
+    /// Reads the configuration registers of a SWO unit.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        let codr = registers.read_register(REGISTER_OFFSET_SWO_CODR)?;
+        let sppr = registers.read_register(REGISTER_OFFSET_SWO_SPPR)?;
+
+        Ok(SwoUnitInfo {
+            pin_protocol: sppr.into(),
+            prescaler: (codr & 0x1fff) as u16,
+        })
+    }
+}
+
+/// The configuration of an embedded trace buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EtbInfo {
+    /// The size of the trace RAM, in words.
+    pub depth: u32,
+    /// Whether trace capture is enabled.
+    pub capturing: bool,
+}
+
+impl EtbInfo {
+    /// Reads the configuration registers of an ETB.
+    pub fn read(registers: &mut dyn ComponentRegisters) -> Result<Self, Error> {
+        Ok(EtbInfo {
+            depth: registers.read_register(REGISTER_OFFSET_ETB_RDP)?,
+            capturing: registers.read_register(REGISTER_OFFSET_ETB_CTL)? & 1 != 0,
+        })
+    }
+}
+
+/// Describes the trace components found in the ROM table `components`.
+pub(crate) fn trace_topology(
+    interface: &mut Box<dyn ArmProbeInterface>,
+    components: &[CoresightComponent],
+) -> Result<TraceTopology, Error> {
+    let mut topology = TraceTopology::default();
+
+    for component in components.iter().flat_map(CoresightComponent::iter) {
+        let id = component.component.id();
+        let peripheral_id = id.peripheral_id();
+
+        let (name, peripheral_type) = match peripheral_id.determine_part() {
+            Some(part) => (part.name(), part.peripheral_type()),
+            // Trace units of newer cores aren't in the part table, but identify themselves with
+            // their architecture ID.
+            None if peripheral_id.arch_id() == ARCH_ID_ETMV4 => {
+                ("ETMv4 trace unit", PeripheralType::Etm)
+            }
+            None => continue,
+        };
+
+        let location = TraceComponentLocation {
+            name: name.to_owned(),
+            ap: component.ap.ap_address().ap,
+            address: id.component_address(),
+        };
+        let mut registers = LiveComponent::new(interface, component);
+
+        match peripheral_type {
+            PeripheralType::Etm => topology.sources.push(TraceSource {
+                location,
+                kind: TraceSourceKind::Etm(EtmInfo::read(&mut registers)?),
+            }),
+            PeripheralType::Itm => topology.sources.push(TraceSource {
+                location,
+                kind: TraceSourceKind::Itm(ItmInfo::read(&mut registers)?),
+            }),
+            PeripheralType::TraceFunnel => topology.funnels.push(TraceLink {
+                location,
+                funnel: FunnelInfo::read(&mut registers)?,
+            }),
+            PeripheralType::Tpiu => topology.sinks.push(TraceSink {
+                location,
+                kind: TraceSinkKind::Tpiu(TpiuInfo::read(&mut registers)?),
+            }),
+            PeripheralType::Swo => topology.sinks.push(TraceSink {
+                location,
+                kind: TraceSinkKind::Swo(SwoUnitInfo::read(&mut registers)?),
+            }),
+            PeripheralType::Etb => topology.sinks.push(TraceSink {
+                location,
+                kind: TraceSinkKind::Etb(EtbInfo::read(&mut registers)?),
+            }),
+            _ => {}
+        }
+    }
+
+    Ok(topology)
+}
+
+/// Finds the component with the base address `address` in the ROM table `components`.
+pub(crate) fn find_component_at(
+    components: &[CoresightComponent],
+    address: u64,
+) -> Result<&CoresightComponent, Error> {
+    components
+        .iter()
+        .flat_map(CoresightComponent::iter)
+        .find(|component| component.component.id().component_address() == address)
+        .ok_or_else(|| {
+            Error::architecture_specific(super::ComponentError::UnknownComponent(address))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The TPIU of an STM32H743, configured for SWO with NRZ encoding by `setup_swv`.
+    fn stm32h7_tpiu() -> RegisterDump {
+        [
+            (0x000, 0x0000_000F),
+            (0x004, 0x0000_0001),
+            (0x010, 0x0000_00C7),
+            (0x0F0, 0x0000_0002),
+            (0x300, 0x0000_0008),
+            (0x304, 0x0000_0102),
+            (0xFB4, 0x0000_0003),
+            (0xFC8, 0x0000_0CA1),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn decode_stm32h7_tpiu() {
+        let info = TpiuInfo::read(&mut stm32h7_tpiu()).unwrap();
+
+        assert_eq!(info.supported_port_sizes, vec![1, 2, 3, 4]);
+        assert_eq!(info.port_size, Some(1));
+        assert_eq!(info.pin_protocol, TracePinProtocol::Nrz);
+        assert_eq!(info.prescaler, 199);
+        assert!(info.continuous_formatting);
+        assert!(info.trigger_in);
+        assert!(!info.formatter_stopped);
+        assert!(info.parallel_supported);
+        assert!(info.manchester_supported);
+        assert!(info.nrz_supported);
+    }
+
+    #[test]
+    fn decode_funnel() {
+        let mut dump: RegisterDump = [(0x000, 0x0000_0301), (0xFC8, 0x0000_0032)]
+            .into_iter()
+            .collect();
+
+        let info = FunnelInfo::read(&mut dump).unwrap();
+
+        assert_eq!(info.ports, 2);
+        assert_eq!(info.enabled_ports, vec![0]);
+        assert!(info.is_enabled(0));
+        assert!(!info.is_enabled(1));
+        assert_eq!(info.hold_time, 4);
+    }
+
+    #[test]
+    fn decode_itm() {
+        let mut dump: RegisterDump = [(0xE80, 0x0001_000B)].into_iter().collect();
+
+        let info = ItmInfo::read(&mut dump).unwrap();
+
+        assert!(info.enabled);
+        assert!(info.timestamps);
+        assert!(!info.synchronization);
+        assert!(info.dwt_forwarding);
+        assert_eq!(info.trace_id, 1);
+    }
+
+    /// A component with a lock, which ignores writes while locked.
+    #[derive(Default)]
+    struct LockedComponent {
+        unlocked: bool,
+        fail_writes: bool,
+        registers: RegisterDump,
+        lar_writes: Vec<u32>,
+    }
+
+    impl ComponentRegisters for LockedComponent {
+        fn read_register(&mut self, offset: u32) -> Result<u32, Error> {
+            match offset {
+                REGISTER_OFFSET_LSR if self.unlocked => Ok(0x1),
+                REGISTER_OFFSET_LSR => Ok(0x3),
+                _ => self.registers.read_register(offset),
+            }
+        }
+
+        fn write_register(&mut self, offset: u32, value: u32) -> Result<(), Error> {
+            if offset == REGISTER_OFFSET_LAR {
+                self.lar_writes.push(value);
+                self.unlocked = value == UNLOCK_KEY;
+                Ok(())
+            } else if self.fail_writes {
+                Err(Error::Other(anyhow::anyhow!("write failed")))
+            } else if self.unlocked {
+                self.registers.write_register(offset, value)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn write_unlocked_relocks() {
+        let mut component = LockedComponent::default();
+
+        write_unlocked(&mut component, 0x304, 0x102).unwrap();
+
+        assert_eq!(component.registers.get(0x304), Some(0x102));
+        assert_eq!(component.lar_writes, vec![UNLOCK_KEY, 0]);
+        assert!(!component.unlocked);
+    }
+
+    #[test]
+    fn write_unlocked_keeps_unlocked_components_unlocked() {
+        let mut component = LockedComponent {
+            unlocked: true,
+            ..Default::default()
+        };
+
+        write_unlocked(&mut component, 0x304, 0x102).unwrap();
+
+        assert_eq!(component.registers.get(0x304), Some(0x102));
+        assert!(component.lar_writes.is_empty());
+        assert!(component.unlocked);
+    }
+
+    #[test]
+    fn write_unlocked_relocks_after_failed_write() {
+        let mut component = LockedComponent {
+            fail_writes: true,
+            ..Default::default()
+        };
+
+        assert!(write_unlocked(&mut component, 0x304, 0x102).is_err());
+        assert_eq!(component.lar_writes, vec![UNLOCK_KEY, 0]);
+        assert!(!component.unlocked);
+    }
+}
@@ -1,18 +1,20 @@
 //! Register types and the core interface for armv6-M
 
+use super::dwt_watchpoint::{self, DwtVersion};
 use super::{CortexMState, Dfsr, ARM_REGISTER_FILE};
 
 use crate::architecture::arm::sequences::ArmDebugSequence;
 use crate::core::{
     RegisterDataType, RegisterDescription, RegisterFile, RegisterKind, RegisterValue,
+    ResetHaltMechanism, StatusCondition, WatchpointConfig,
 };
 use crate::error::Error;
-use crate::memory::{valid_32_address, Memory};
+use crate::memory::{valid_32_address, Memory, WriteWidths};
 use crate::{
     Architecture, CoreInformation, CoreInterface, CoreStatus, CoreType, DebugProbeError,
-    HaltReason, InstructionSet, MemoryInterface, MemoryMappedRegister, RegisterId,
+    HaltEscalation, HaltReason, InstructionSet, MemoryInterface, MemoryMappedRegister, RegisterId,
+    VendorCommand, VendorCommandIndex,
 };
-use anyhow::Result;
 use bitfield::bitfield;
 use std::sync::Arc;
 use std::{
@@ -295,7 +297,7 @@ impl BpCompx {
         } else if bp_val.bp_match() == 0b10 {
             Ok((bp_val.comp() << 2) | 0x2)
         } else {
-            return Err(Error::ArchitectureSpecific(Box::new(DebugProbeError::Other(anyhow::anyhow!("Unsupported breakpoint comparator value {:#08x} for HW breakpoint. Breakpoint must be on half-word boundaries", bp_val.0)))));
+            Err(Error::UnsupportedBreakpointComparator(bp_val.0))
         }
     }
 }
@@ -437,6 +439,7 @@ const PC: RegisterDescription = RegisterDescription {
     id: RegisterId(0b0_1111),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: 0xffff_fffe,
 };
 
 const XPSR: RegisterDescription = RegisterDescription {
@@ -445,6 +448,7 @@ const XPSR: RegisterDescription = RegisterDescription {
     id: RegisterId(0b1_0000),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: 0xffff_fe00,
 };
 
 /// The state of a core that can be used to persist core state across calls to multiple different cores.
@@ -505,6 +509,7 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
 
         while start.elapsed() < timeout {
             let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+            self.state.note_reset(dhcsr_val.s_reset_st());
 
             if dhcsr_val.s_halt() {
                 return Ok(());
@@ -517,6 +522,7 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
     fn core_halted(&mut self) -> Result<bool, Error> {
         // Wait until halted state is active again.
         let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr_val.s_reset_st());
 
         if dhcsr_val.s_halt() {
             Ok(true)
@@ -546,6 +552,7 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
@@ -567,6 +574,14 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         Ok(())
     }
 
+    fn resume_from_hw_breakpoint(&mut self) -> Result<bool, Error> {
+        super::cortex_m::resume_from_hw_breakpoint(&mut self.memory)?;
+
+        self.state.current_state = CoreStatus::Running;
+
+        Ok(true)
+    }
+
     fn step(&mut self) -> Result<CoreInformation, Error> {
         // First check if we stopped on a breakpoint, because this requires special handling before we can continue.
         let was_breakpoint =
@@ -601,6 +616,23 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
+        })
+    }
+
+    fn step_fast(&mut self) -> Result<CoreInformation, Error> {
+        let pc = match super::cortex_m::queued_step(&mut self.memory, &mut self.state)? {
+            Some(pc) => pc,
+            None => {
+                // Complete the step like a regular one.
+                self.wait_for_core_halted(Duration::from_millis(100))?;
+                self.read_core_reg(PC.id)?.try_into()?
+            }
+        };
+
+        Ok(CoreInformation {
+            pc: pc.into(),
+            instruction_set: None,
         })
     }
 
@@ -633,9 +665,33 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetVectorCatch
+    }
+
+    fn reset_vector(&mut self) -> Result<Option<u64>, Error> {
+        super::cortex_m::reset_vector(&mut self.memory).map(Some)
+    }
+
+    fn escalate_halt(&mut self, level: HaltEscalation, timeout: Duration) -> Result<bool, Error> {
+        let halted = super::cortex_m::escalate_halt(
+            &mut self.memory,
+            self.sequence.as_ref(),
+            CoreType::Armv6m,
+            level,
+            timeout,
+        )?;
+
+        // Update core status
+        let _ = self.status()?;
+
+        Ok(halted)
+    }
+
     fn available_breakpoint_units(&mut self) -> Result<u32, Error> {
         let result = self.memory.read_word_32(BpCtrl::ADDRESS)?;
 
@@ -666,7 +722,10 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         // The highest 3 bits of the address have to be zero, otherwise the breakpoint cannot
         // be set at the address.
         if addr >= 0x2000_0000 {
-            return Err(Error::ArchitectureSpecific(Box::new(DebugProbeError::Other(anyhow::anyhow!("Unsupported address {:#08x} for HW breakpoint. Breakpoint must be at address < 0x2000_0000.", addr)))));
+            return Err(Error::UnsupportedBreakpointAddress {
+                address: addr.into(),
+                reason: "breakpoints must be below 0x2000_0000",
+            });
         }
 
         let mut value = BpCompx(0);
@@ -718,8 +777,45 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         Ok(InstructionSet::Thumb2)
     }
 
+    fn take_reset_detected(&mut self) -> bool {
+        self.state.take_reset_detected()
+    }
+
+    fn take_halt_detected(&mut self) -> bool {
+        self.state.take_halt_detected()
+    }
+
+    fn halted_condition(&self) -> Option<StatusCondition> {
+        Some(super::cortex_m::halted_condition())
+    }
+
+    fn poll_condition(
+        &mut self,
+        condition: StatusCondition,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        self.memory.poll_word_32(
+            condition.address,
+            condition.mask,
+            condition.expected,
+            timeout,
+        )
+    }
+
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, Error> {
+        self.memory.enqueue_vendor_command(command)
+    }
+
+    fn vendor_command_response(&mut self, index: VendorCommandIndex) -> Result<Vec<u8>, Error> {
+        self.memory.vendor_command_response(index)
+    }
+
     fn status(&mut self) -> Result<crate::core::CoreStatus, Error> {
         let dhcsr = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr.s_reset_st());
 
         if dhcsr.s_lockup() {
             log::warn!("The core is in locked up status as a result of an unrecoverable exception");
@@ -746,6 +842,10 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
             let dfsr = Dfsr(self.memory.read_word_32(Dfsr::ADDRESS)?);
 
             let reason = dfsr.halt_reason();
+            // The halt after a queued step was noted already.
+            if !self.state.take_step_reason_pending() {
+                self.state.note_halt(reason);
+            }
 
             // Clear bits from Dfsr register
             self.memory
@@ -784,12 +884,16 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
         Ok(CoreStatus::Running)
     }
 
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, Error> {
+        super::cortex_m::register_available(self.core_type(), address, || Ok(false))
+    }
+
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         let val = super::cortex_m::read_core_reg(&mut self.memory, address)?;
         Ok(val.into())
     }
 
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()> {
+    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         super::cortex_m::write_core_reg(&mut self.memory, address, value.try_into()?)?;
         Ok(())
     }
@@ -815,12 +919,38 @@ impl<'probe> CoreInterface for Armv6m<'probe> {
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
         Ok(false)
     }
+
+    fn available_watchpoint_units(&mut self) -> Result<u32, crate::error::Error> {
+        dwt_watchpoint::available_units(&mut self.memory)
+    }
+
+    fn set_watchpoint(
+        &mut self,
+        config: &WatchpointConfig,
+        used: &[usize],
+    ) -> Result<Vec<usize>, crate::error::Error> {
+        dwt_watchpoint::set(&mut self.memory, DwtVersion::Armv6m, config, used)
+    }
+
+    fn clear_watchpoint(&mut self, units: &[usize]) -> Result<(), crate::error::Error> {
+        dwt_watchpoint::clear(&mut self.memory, units)
+    }
+
+    fn matched_watchpoint_units(
+        &mut self,
+        units: &[usize],
+    ) -> Result<Option<Vec<usize>>, crate::error::Error> {
+        dwt_watchpoint::matched(&mut self.memory, units).map(Some)
+    }
 }
 
 impl<'probe> MemoryInterface for Armv6m<'probe> {
     fn supports_native_64bit_access(&mut self) -> bool {
         self.memory.supports_native_64bit_access()
     }
+    fn native_write_widths(&mut self) -> WriteWidths {
+        self.memory.native_write_widths()
+    }
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
         self.memory.read_word_64(address)
     }
@@ -857,6 +987,9 @@ impl<'probe> MemoryInterface for Armv6m<'probe> {
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.memory.write_8(address, data)
     }
+    fn write_barrier(&mut self) -> Result<(), Error> {
+        self.memory.write_barrier()
+    }
     fn flush(&mut self) -> Result<(), Error> {
         self.memory.flush()
     }
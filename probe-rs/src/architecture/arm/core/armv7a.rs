@@ -3,17 +3,17 @@
 use crate::architecture::arm::core::armv7a_debug_regs::*;
 use crate::architecture::arm::core::register;
 use crate::architecture::arm::sequences::ArmDebugSequence;
-use crate::core::{RegisterFile, RegisterValue};
+use crate::core::{RegisterFile, RegisterValue, ResetHaltMechanism};
 use crate::error::Error;
-use crate::memory::{valid_32_address, Memory};
+use crate::memory::{valid_32_address, Memory, WriteWidths};
 use crate::CoreInterface;
 use crate::CoreStatus;
 use crate::DebugProbeError;
 use crate::MemoryInterface;
 use crate::RegisterId;
 use crate::{Architecture, CoreInformation, CoreType, InstructionSet};
-use anyhow::Result;
 
+use super::cache::{self, CacheMaintenance};
 use super::instructions::aarch32::{
     build_bx, build_ldc, build_mcr, build_mov, build_mrc, build_mrs, build_stc,
 };
@@ -282,6 +282,7 @@ impl<'probe> CoreInterface for Armv7a<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
@@ -365,9 +366,14 @@ impl<'probe> CoreInterface for Armv7a<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetVectorCatch
+    }
+
     fn step(&mut self) -> Result<CoreInformation, Error> {
         // Save current breakpoint
         let bp_unit_index = (self.available_breakpoint_units()? - 1) as usize;
@@ -414,9 +420,15 @@ impl<'probe> CoreInterface for Armv7a<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, Error> {
+        // R0-R14, PC and CPSR
+        Ok(address.0 <= 16)
+    }
+
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         let reg_num = address.0;
 
@@ -479,14 +491,14 @@ impl<'probe> CoreInterface for Armv7a<'probe> {
         }
     }
 
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()> {
+    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         let value: u32 = value.try_into()?;
         let reg_num = address.0;
 
         if (reg_num as usize) >= self.state.register_cache.len() {
-            return Err(
-                Error::architecture_specific(Armv7aError::InvalidRegisterNumber(reg_num)).into(),
-            );
+            return Err(Error::architecture_specific(
+                Armv7aError::InvalidRegisterNumber(reg_num),
+            ));
         }
         self.state.register_cache[reg_num as usize] = Some((value.into(), true));
 
@@ -618,9 +630,61 @@ impl<'probe> CoreInterface for Armv7a<'probe> {
     }
 
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
-        Err(crate::error::Error::Other(anyhow::anyhow!(
-            "Fpu detection not yet implemented"
-        )))
+        Err(crate::error::Error::NotImplemented("FPU detection"))
+    }
+
+    fn cache_line_size(&mut self) -> Result<Option<u32>, Error> {
+        // Save r0
+        self.prepare_r0_for_clobber()?;
+
+        // MRC p15, 0, r0, c1, c0, 0 ; Read SCTLR
+        self.execute_instruction(build_mrc(15, 0, 0, 1, 0, 0))?;
+        let sctlr = self.execute_instruction_with_result(build_mcr(14, 0, 0, 0, 5, 0))?;
+
+        // SCTLR.C and SCTLR.I enable the data and instruction caches
+        if sctlr & (1 << 2 | 1 << 12) == 0 {
+            return Ok(None);
+        }
+
+        // MRC p15, 0, r0, c0, c0, 1 ; Read CTR
+        self.execute_instruction(build_mrc(15, 0, 0, 0, 0, 1))?;
+        let ctr = self.execute_instruction_with_result(build_mcr(14, 0, 0, 0, 5, 0))?;
+
+        Ok(Some(cache::line_size(ctr)))
+    }
+
+    fn cache_maintenance(
+        &mut self,
+        operation: CacheMaintenance,
+        address: u64,
+    ) -> Result<(), Error> {
+        let address = valid_32_address(address)?;
+
+        let (ctrl_reg_m, opcode2) = match operation {
+            // DCCMVAC
+            CacheMaintenance::Clean => (10, 1),
+            // DCCIMVAC
+            CacheMaintenance::CleanInvalidate => (14, 1),
+            // DCIMVAC
+            CacheMaintenance::Invalidate => (6, 1),
+            // ICIMVAU
+            CacheMaintenance::InvalidateInstruction => (5, 1),
+        };
+
+        // Save r0
+        self.prepare_r0_for_clobber()?;
+
+        // Load r0 with the address of the cache line
+        self.set_r0(address)?;
+
+        // MCR p15, 0, r0, c7, <ctrl_reg_m>, <opcode2>
+        self.execute_instruction(build_mcr(15, 0, 0, 7, ctrl_reg_m, opcode2))?;
+
+        // MCR p15, 0, r0, c7, c10, 4 ; DSB
+        // Leaving debug state synchronizes the instruction fetches, so no ISB is needed.
+        self.execute_instruction(build_mcr(15, 0, 0, 7, 10, 4))?;
+
+        Ok(())
     }
 }
 
@@ -628,6 +692,14 @@ impl<'probe> MemoryInterface for Armv7a<'probe> {
     fn supports_native_64bit_access(&mut self) -> bool {
         false
     }
+    fn native_write_widths(&mut self) -> WriteWidths {
+        // Byte writes are a read-modify-write of the word which contains the byte.
+        WriteWidths {
+            bits_8: false,
+            bits_32: true,
+            bits_64: self.supports_native_64bit_access(),
+        }
+    }
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
         let mut ret: u64 = self.read_word_32(address)? as u64;
         ret |= (self.read_word_32(address + 4)? as u64) << 32;
@@ -730,8 +802,19 @@ impl<'probe> MemoryInterface for Armv7a<'probe> {
         Ok(())
     }
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        for (i, byte) in data.iter().enumerate() {
-            self.write_word_8(address + ((i as u64) * 4), *byte)?;
+        // Only the bytes before and after the aligned words need a read-modify-write.
+        let head = (((4 - address % 4) % 4) as usize).min(data.len());
+        let words = (data.len() - head) / 4 * 4;
+
+        for (i, byte) in data[..head].iter().enumerate() {
+            self.write_word_8(address + i as u64, *byte)?;
+        }
+        for (i, word) in data[head..head + words].chunks_exact(4).enumerate() {
+            let word = u32::from_le_bytes(word.try_into().unwrap());
+            self.write_word_32(address + (head + i * 4) as u64, word)?;
+        }
+        for (i, byte) in data[head + words..].iter().enumerate() {
+            self.write_word_8(address + (head + words + i) as u64, *byte)?;
         }
 
         Ok(())
@@ -749,6 +832,8 @@ mod test {
         memory::adi_v5_memory_interface::ArmProbe, sequences::DefaultArmSequence, ApAddress,
         DpAddress,
     };
+    use crate::{Core, CoreState};
+    use probe_rs_target::CoreAccessOptions;
 
     use super::*;
 
@@ -1514,6 +1599,75 @@ mod test {
         armv7a.set_hw_breakpoint(0, BP_VALUE).unwrap();
     }
 
+    #[test]
+    fn armv7a_restore_hw_breakpoints_reads_comparators_once() {
+        const BP_COUNT: u32 = 8;
+        const BP_BASE: u64 = 0x2000;
+        let mut probe = MockProbe::new();
+        let mut state = CortexAState::new();
+
+        // Add expectations
+        add_status_expectations(&mut probe, true);
+
+        // The comparators are only read once, before the first breakpoint is set
+        add_idr_expectations(&mut probe, BP_COUNT);
+
+        for unit in 0..BP_COUNT as u64 {
+            probe.expected_read(Dbgbvr::get_mmio_address(TEST_BASE_ADDRESS) + (unit * 4), 0);
+            probe.expected_read(Dbgbcr::get_mmio_address(TEST_BASE_ADDRESS) + (unit * 4), 0);
+        }
+
+        // Afterwards, every breakpoint costs exactly two comparator writes
+        let mut dbgbcr = Dbgbcr(0);
+        dbgbcr.set_hmc(true);
+        dbgbcr.set_pmc(0b11);
+        dbgbcr.set_bas(0b1111);
+        dbgbcr.set_e(true);
+
+        for unit in 0..BP_COUNT as u64 {
+            probe.expected_write(
+                Dbgbvr::get_mmio_address(TEST_BASE_ADDRESS) + (unit * 4),
+                (BP_BASE + unit * 4) as u32,
+            );
+            probe.expected_write(
+                Dbgbcr::get_mmio_address(TEST_BASE_ADDRESS) + (unit * 4),
+                dbgbcr.into(),
+            );
+        }
+
+        let mock_mem = Memory::new(
+            probe,
+            MemoryAp::new(ApAddress {
+                ap: 0,
+                dp: DpAddress::Default,
+            }),
+        );
+
+        let armv7a = Armv7a::new(
+            mock_mem,
+            &mut state,
+            TEST_BASE_ADDRESS,
+            DefaultArmSequence::create(),
+        )
+        .unwrap();
+
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Arm(Default::default()));
+        let mut core = Core::new(armv7a, &mut core_state);
+
+        for unit in 0..BP_COUNT as u64 {
+            core.set_hw_breakpoint(BP_BASE + unit * 4).unwrap();
+        }
+
+        // Answered from the cache, any further access would fail the mock
+        assert_eq!(BP_COUNT, core.available_breakpoint_units().unwrap());
+        assert_eq!(
+            (0..BP_COUNT as u64)
+                .map(|unit| Some(BP_BASE + unit * 4))
+                .collect::<Vec<_>>(),
+            core.hw_breakpoints().unwrap()
+        );
+    }
+
     #[test]
     fn armv7a_clear_hw_breakpoint() {
         let mut probe = MockProbe::new();
@@ -1615,4 +1769,40 @@ mod test {
 
         assert_eq!(0xBA, armv7a.read_word_8(MEMORY_ADDRESS).unwrap());
     }
+
+    #[test]
+    fn armv7a_absent_register_is_rejected_without_access() {
+        let mut probe = MockProbe::new();
+        let mut state = CortexAState::new();
+
+        // Add expectations
+        add_status_expectations(&mut probe, true);
+
+        let mock_mem = Memory::new(
+            probe,
+            MemoryAp::new(ApAddress {
+                ap: 0,
+                dp: DpAddress::Default,
+            }),
+        );
+
+        let armv7a = Armv7a::new(
+            mock_mem,
+            &mut state,
+            TEST_BASE_ADDRESS,
+            DefaultArmSequence::create(),
+        )
+        .unwrap();
+
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Arm(Default::default()));
+        let mut core = Core::new(armv7a, &mut core_state);
+
+        // Any access would fail the mock
+        let error = core.read_core_reg::<u32>(RegisterId(19)).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::RegisterNotAvailable { name, core_variant }
+                if name == "register 0x13" && core_variant == "Armv7a"
+        ));
+    }
 }
@@ -3,18 +3,22 @@
 use crate::architecture::arm::sequences::ArmDebugSequence;
 use crate::core::{
     CoreInformation, CoreInterface, MemoryMappedRegister, RegisterFile, RegisterId, RegisterValue,
+    ResetHaltMechanism, StatusCondition, WatchpointConfig,
 };
 use crate::error::Error;
 use crate::memory::{valid_32_address, Memory};
-use crate::{CoreType, DebugProbeError, InstructionSet};
+use crate::{
+    CoreType, DebugProbeError, HaltEscalation, InstructionSet, VendorCommand, VendorCommandIndex,
+};
 
+use super::cache::{self, CacheMaintenance};
 use super::cortex_m::Cpacr;
+use super::dwt_watchpoint::{self, DwtVersion};
 use super::{register, CortexMState, Dfsr, ARM_REGISTER_FILE};
 use crate::{
     core::{Architecture, CoreStatus, HaltReason},
-    MemoryInterface,
+    MemoryInterface, WriteWidths,
 };
-use anyhow::{anyhow, Result};
 
 use bitfield::bitfield;
 use std::mem::size_of;
@@ -496,7 +500,7 @@ impl FpRev1CompX {
         } else if fp1_val.replace() == 0b10 {
             Ok((fp1_val.comp() << 2) | 0x2)
         } else {
-            return Err(Error::ArchitectureSpecific(Box::new(DebugProbeError::Other(anyhow::anyhow!("Unsupported breakpoint comparator value {:#08x} for HW breakpoint. Breakpoint must be on half-word boundaries", fp1_val.0)))));
+            Err(Error::UnsupportedBreakpointComparator(fp1_val.0))
         }
     }
     /// Get the correct register configuration which enables
@@ -508,7 +512,10 @@ impl FpRev1CompX {
         // The highest 3 bits of the address have to be zero, otherwise the breakpoint cannot
         // be set at the address.
         if address >= 0x2000_0000 {
-            return Err(Error::ArchitectureSpecific(Box::new(DebugProbeError::Other(anyhow::anyhow!("Unsupported address {:#08x} for HW breakpoint. Breakpoint must be at address < 0x2000_0000.", address)))));
+            return Err(Error::UnsupportedBreakpointAddress {
+                address: address.into(),
+                reason: "breakpoints must be below 0x2000_0000",
+            });
         }
 
         let comp_val = (address & 0x1f_ff_ff_fc) >> 2;
@@ -642,6 +649,7 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
 
         while start.elapsed() < timeout {
             let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+            self.state.note_reset(dhcsr_val.s_reset_st());
             if dhcsr_val.s_halt() {
                 // update halted state
                 self.status()?;
@@ -656,6 +664,7 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
     fn core_halted(&mut self) -> Result<bool, Error> {
         // Wait until halted state is active again.
         let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr_val.s_reset_st());
 
         if dhcsr_val.s_halt() {
             Ok(true)
@@ -664,8 +673,45 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         }
     }
 
+    fn take_reset_detected(&mut self) -> bool {
+        self.state.take_reset_detected()
+    }
+
+    fn take_halt_detected(&mut self) -> bool {
+        self.state.take_halt_detected()
+    }
+
+    fn halted_condition(&self) -> Option<StatusCondition> {
+        Some(super::cortex_m::halted_condition())
+    }
+
+    fn poll_condition(
+        &mut self,
+        condition: StatusCondition,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        self.memory.poll_word_32(
+            condition.address,
+            condition.mask,
+            condition.expected,
+            timeout,
+        )
+    }
+
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, Error> {
+        self.memory.enqueue_vendor_command(command)
+    }
+
+    fn vendor_command_response(&mut self, index: VendorCommandIndex) -> Result<Vec<u8>, Error> {
+        self.memory.vendor_command_response(index)
+    }
+
     fn status(&mut self) -> Result<CoreStatus, Error> {
         let dhcsr = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr.s_reset_st());
 
         if dhcsr.s_lockup() {
             log::error!(
@@ -692,6 +738,10 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
             let dfsr = Dfsr(self.memory.read_word_32(Dfsr::ADDRESS)?);
 
             let reason = dfsr.halt_reason();
+            // The halt after a queued step was noted already.
+            if !self.state.take_step_reason_pending() {
+                self.state.note_halt(reason);
+            }
 
             // Clear bits from Dfsr register
             self.memory
@@ -730,12 +780,16 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         Ok(CoreStatus::Running)
     }
 
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, Error> {
+        super::cortex_m::register_available(self.core_type(), address, || self.fpu_support())
+    }
+
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         let val = super::cortex_m::read_core_reg(&mut self.memory, address)?;
         Ok(val.into())
     }
 
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()> {
+    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         super::cortex_m::write_core_reg(&mut self.memory, address, value.try_into()?)?;
 
         Ok(())
@@ -762,6 +816,7 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
@@ -792,6 +847,14 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         Ok(())
     }
 
+    fn resume_from_hw_breakpoint(&mut self) -> Result<bool, Error> {
+        super::cortex_m::resume_from_hw_breakpoint(&mut self.memory)?;
+
+        self.state.current_state = CoreStatus::Running;
+
+        Ok(true)
+    }
+
     fn step(&mut self) -> Result<CoreInformation, Error> {
         // First check if we stopped on a breakpoint, because this requires special handling before we can continue.
         let was_breakpoint =
@@ -836,6 +899,23 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
+        })
+    }
+
+    fn step_fast(&mut self) -> Result<CoreInformation, Error> {
+        let pc = match super::cortex_m::queued_step(&mut self.memory, &mut self.state)? {
+            Some(pc) => pc,
+            None => {
+                // Complete the step like a regular one.
+                self.wait_for_core_halted(Duration::from_millis(100))?;
+                self.read_core_reg(register::PC.id)?.try_into()?
+            }
+        };
+
+        Ok(CoreInformation {
+            pc: pc.into(),
+            instruction_set: None,
         })
     }
 
@@ -871,9 +951,33 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetVectorCatch
+    }
+
+    fn reset_vector(&mut self) -> Result<Option<u64>, Error> {
+        super::cortex_m::reset_vector(&mut self.memory).map(Some)
+    }
+
+    fn escalate_halt(&mut self, level: HaltEscalation, timeout: Duration) -> Result<bool, Error> {
+        let halted = super::cortex_m::escalate_halt(
+            &mut self.memory,
+            self.sequence.as_ref(),
+            CoreType::Armv7m,
+            level,
+            timeout,
+        )?;
+
+        // Update core status
+        let _ = self.status()?;
+
+        Ok(halted)
+    }
+
     fn available_breakpoint_units(&mut self) -> Result<u32, Error> {
         let raw_val = self.memory.read_word_32(FpCtrl::ADDRESS)?;
 
@@ -907,10 +1011,10 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
 
         // First make sure they are asking for a breakpoint on a half-word boundary.
         if (addr & 0x1) > 0 {
-            return Err(Error::Other(anyhow!(
-                "The requested breakpoint address 0x{:08x} is not on a half-word boundary",
-                addr
-            )));
+            return Err(Error::UnsupportedBreakpointAddress {
+                address: addr.into(),
+                reason: "breakpoints must be on a half-word boundary",
+            });
         }
 
         let raw_val = self.memory.read_word_32(FpCtrl::ADDRESS)?;
@@ -923,7 +1027,7 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
             val = FpRev2CompX::breakpoint_configuration(addr).into();
         } else {
             log::warn!("This chip uses FPBU revision {}, which is not yet supported. HW breakpoints are not available.", ctrl_reg.rev());
-            return Err(Error::Other(anyhow!("This chip uses FPBU revision {}, which is not yet supported. HW breakpoints are not available.", ctrl_reg.rev())));
+            return Err(Error::UnsupportedBreakpointUnitRevision(ctrl_reg.rev()));
         }
 
         // This is fine as FpRev1CompX and Rev2CompX are just two different
@@ -988,7 +1092,7 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
                     breakpoint = FpRev2CompX::from(register_value).bpaddr() << 1;
                 } else {
                     log::warn!("This chip uses FPBU revision {}, which is not yet supported. HW breakpoints are not available.", ctrl_reg.rev());
-                    return Err(Error::Other(anyhow!("This chip uses FPBU revision {}, which is not yet supported. HW breakpoints are not available.", ctrl_reg.rev())));
+                    return Err(Error::UnsupportedBreakpointUnitRevision(ctrl_reg.rev()));
                 }
                 breakpoints.push(Some(breakpoint as u64));
             } else {
@@ -1000,7 +1104,49 @@ impl<'probe> CoreInterface for Armv7m<'probe> {
     }
 
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
-        Ok(Cpacr(self.memory.read_word_32(Cpacr::ADDRESS)?).fpu_present())
+        if let Some(present) = self.state.fpu_present {
+            return Ok(present);
+        }
+
+        let present = Cpacr(self.memory.read_word_32(Cpacr::ADDRESS)?).fpu_present();
+        self.state.fpu_present = Some(present);
+
+        Ok(present)
+    }
+
+    fn cache_line_size(&mut self) -> Result<Option<u32>, crate::error::Error> {
+        cache::cortex_m_cache_line_size(&mut self.memory)
+    }
+
+    fn cache_maintenance(
+        &mut self,
+        operation: CacheMaintenance,
+        address: u64,
+    ) -> Result<(), crate::error::Error> {
+        cache::cortex_m_cache_maintenance(&mut self.memory, operation, address)
+    }
+
+    fn available_watchpoint_units(&mut self) -> Result<u32, crate::error::Error> {
+        dwt_watchpoint::available_units(&mut self.memory)
+    }
+
+    fn set_watchpoint(
+        &mut self,
+        config: &WatchpointConfig,
+        used: &[usize],
+    ) -> Result<Vec<usize>, crate::error::Error> {
+        dwt_watchpoint::set(&mut self.memory, DwtVersion::Armv7m, config, used)
+    }
+
+    fn clear_watchpoint(&mut self, units: &[usize]) -> Result<(), crate::error::Error> {
+        dwt_watchpoint::clear(&mut self.memory, units)
+    }
+
+    fn matched_watchpoint_units(
+        &mut self,
+        units: &[usize],
+    ) -> Result<Option<Vec<usize>>, crate::error::Error> {
+        dwt_watchpoint::matched(&mut self.memory, units).map(Some)
     }
 }
 
@@ -1008,6 +1154,9 @@ impl<'probe> MemoryInterface for Armv7m<'probe> {
     fn supports_native_64bit_access(&mut self) -> bool {
         self.memory.supports_native_64bit_access()
     }
+    fn native_write_widths(&mut self) -> WriteWidths {
+        self.memory.native_write_widths()
+    }
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
         self.memory.read_word_64(address)
     }
@@ -1044,6 +1193,9 @@ impl<'probe> MemoryInterface for Armv7m<'probe> {
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.memory.write_8(address, data)
     }
+    fn write_barrier(&mut self) -> Result<(), Error> {
+        self.memory.write_barrier()
+    }
     fn flush(&mut self) -> Result<(), Error> {
         self.memory.flush()
     }
@@ -2,16 +2,15 @@
 
 use crate::architecture::arm::core::armv8a_debug_regs::*;
 use crate::architecture::arm::sequences::ArmDebugSequence;
-use crate::core::{RegisterFile, RegisterValue};
+use crate::core::{RegisterFile, RegisterValue, ResetHaltMechanism};
 use crate::error::Error;
-use crate::memory::{valid_32_address, Memory};
+use crate::memory::{valid_32_address, Memory, WriteWidths};
 use crate::CoreInterface;
 use crate::CoreStatus;
 use crate::DebugProbeError;
 use crate::MemoryInterface;
 use crate::RegisterId;
 use crate::{Architecture, CoreInformation, CoreType, InstructionSet};
-use anyhow::Result;
 
 use super::armv8a_core_regs::AARCH64_REGISTER_FILE;
 use super::CortexAState;
@@ -234,6 +233,18 @@ impl<'probe> Armv8a<'probe> {
         Ok(())
     }
 
+    /// Update the execution state from `edscr` of the halted core. The core can switch
+    /// between AArch32 and AArch64 at an exception level transition while it runs, which
+    /// changes the layout of the register cache.
+    fn update_execution_state(&mut self, edscr: Edscr) {
+        let is_64_bit = edscr.currently_64_bit();
+
+        if is_64_bit != self.state.is_64_bit {
+            self.state.is_64_bit = is_64_bit;
+            self.reset_register_cache();
+        }
+    }
+
     fn reset_register_cache(&mut self) {
         if self.state.is_64_bit {
             // 31 general purpose regs, SP, PC, PSR, 31 FP registers, FPSR, FPCR
@@ -630,6 +641,7 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
         while start.elapsed() < timeout {
             let edscr = Edscr(self.memory.read_word_32(address)?);
             if edscr.halted() {
+                self.update_execution_state(edscr);
                 return Ok(());
             }
             std::thread::sleep(Duration::from_millis(1));
@@ -680,6 +692,7 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
@@ -772,9 +785,14 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetVectorCatch
+    }
+
     fn step(&mut self) -> Result<CoreInformation, Error> {
         // Load EDECR, set SS bit for step mode
         let edecr_address = Edecr::get_mmio_address(self.base_address);
@@ -793,15 +811,29 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
         edecr.set_ss(false);
         self.memory.write_word_32(edecr_address, edecr.into())?;
 
+        // Update core status, the step can have switched between AArch32 and AArch64
+        let _ = self.status()?;
+
         // try to read the program counter
         let pc_value = self.read_core_reg(self.registers().program_counter().id)?;
 
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, Error> {
+        if self.state.is_64_bit {
+            // X0-X30, SP, PC, PSTATE, FPSR and FPCR
+            Ok(matches!(address.0, 0..=33 | 66 | 67))
+        } else {
+            // R0-R14, PC and CPSR
+            Ok(address.0 <= 16)
+        }
+    }
+
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         let reg_num = address.0;
 
@@ -827,18 +859,14 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
         }
     }
 
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()> {
+    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         let reg_num = address.0;
         let current_mode = if self.state.is_64_bit { 64 } else { 32 };
 
         if (reg_num as usize) >= self.state.register_cache.len() {
-            return Err(
-                Error::architecture_specific(Armv8aError::InvalidRegisterNumber(
-                    reg_num,
-                    current_mode,
-                ))
-                .into(),
-            );
+            return Err(Error::architecture_specific(
+                Armv8aError::InvalidRegisterNumber(reg_num, current_mode),
+            ));
         }
         self.state.register_cache[reg_num as usize] = Some((value, true));
 
@@ -944,7 +972,7 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
             let reason = edscr.halt_reason();
 
             self.state.current_state = CoreStatus::Halted(reason);
-            self.state.is_64_bit = edscr.currently_64_bit();
+            self.update_execution_state(edscr);
 
             return Ok(CoreStatus::Halted(reason));
         }
@@ -983,9 +1011,7 @@ impl<'probe> CoreInterface for Armv8a<'probe> {
     }
 
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
-        Err(crate::error::Error::Other(anyhow::anyhow!(
-            "Fpu detection not yet implemented"
-        )))
+        Err(crate::error::Error::NotImplemented("FPU detection"))
     }
 }
 
@@ -993,6 +1019,14 @@ impl<'probe> MemoryInterface for Armv8a<'probe> {
     fn supports_native_64bit_access(&mut self) -> bool {
         self.state.is_64_bit
     }
+    fn native_write_widths(&mut self) -> WriteWidths {
+        // Byte writes are a read-modify-write of the word which contains the byte.
+        WriteWidths {
+            bits_8: false,
+            bits_32: true,
+            bits_64: self.supports_native_64bit_access(),
+        }
+    }
 
     fn read_word_64(&mut self, address: u64) -> Result<u64, Error> {
         if self.state.is_64_bit {
@@ -1090,8 +1124,19 @@ impl<'probe> MemoryInterface for Armv8a<'probe> {
         Ok(())
     }
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
-        for (i, byte) in data.iter().enumerate() {
-            self.write_word_8(address + ((i as u64) * 4), *byte)?;
+        // Only the bytes before and after the aligned words need a read-modify-write.
+        let head = (((4 - address % 4) % 4) as usize).min(data.len());
+        let words = (data.len() - head) / 4 * 4;
+
+        for (i, byte) in data[..head].iter().enumerate() {
+            self.write_word_8(address + i as u64, *byte)?;
+        }
+        for (i, word) in data[head..head + words].chunks_exact(4).enumerate() {
+            let word = u32::from_le_bytes(word.try_into().unwrap());
+            self.write_word_32(address + (head + i * 4) as u64, word)?;
+        }
+        for (i, byte) in data[head + words..].iter().enumerate() {
+            self.write_word_8(address + (head + words + i) as u64, *byte)?;
         }
 
         Ok(())
@@ -1109,6 +1154,8 @@ mod test {
         memory::adi_v5_memory_interface::ArmProbe, sequences::DefaultArmSequence, ApAddress,
         DpAddress,
     };
+    use crate::{Core, CoreState};
+    use probe_rs_target::CoreAccessOptions;
 
     use super::*;
 
@@ -2340,4 +2387,40 @@ mod test {
 
         assert_eq!(0xBA, armv8a.read_word_8(MEMORY_ADDRESS).unwrap());
     }
+
+    #[test]
+    fn armv8a_absent_register_is_rejected_without_access() {
+        let mut probe = MockProbe::new(true);
+        let mut state = CortexAState::new();
+
+        // Add expectations
+        add_status_expectations(&mut probe, true);
+
+        let mock_mem = Memory::new(
+            probe,
+            MemoryAp::new(ApAddress {
+                ap: 0,
+                dp: DpAddress::Default,
+            }),
+        );
+
+        let armv8a = Armv8a::new(
+            mock_mem,
+            &mut state,
+            TEST_BASE_ADDRESS,
+            TEST_CTI_ADDRESS,
+            DefaultArmSequence::create(),
+        )
+        .unwrap();
+
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Arm(Default::default()));
+        let mut core = Core::new(armv8a, &mut core_state);
+
+        // Any access would fail the mock
+        let error = core.write_core_reg(RegisterId(34), 0u64).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::RegisterNotAvailable { core_variant, .. } if core_variant == "Armv8a"
+        ));
+    }
 }
@@ -6,6 +6,7 @@ const SP: RegisterDescription = RegisterDescription {
     id: RegisterId(31),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 64,
+    writable_mask: u64::MAX,
 };
 
 const PC: RegisterDescription = RegisterDescription {
@@ -14,6 +15,7 @@ const PC: RegisterDescription = RegisterDescription {
     id: RegisterId(32),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 64,
+    writable_mask: u64::MAX,
 };
 
 const LR: RegisterDescription = RegisterDescription {
@@ -22,6 +24,7 @@ const LR: RegisterDescription = RegisterDescription {
     id: RegisterId(30),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 64,
+    writable_mask: u64::MAX,
 };
 
 const FP: RegisterDescription = RegisterDescription {
@@ -30,6 +33,7 @@ const FP: RegisterDescription = RegisterDescription {
     id: RegisterId(29),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 64,
+    writable_mask: u64::MAX,
 };
 
 const PSTATE: RegisterDescription = RegisterDescription {
@@ -38,6 +42,7 @@ const PSTATE: RegisterDescription = RegisterDescription {
     id: RegisterId(33),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
 pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
@@ -48,6 +53,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X1",
@@ -55,6 +61,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X2",
@@ -62,6 +69,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(2),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X3",
@@ -69,6 +77,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(3),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X4",
@@ -76,6 +85,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(4),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X5",
@@ -83,6 +93,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(5),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X6",
@@ -90,6 +101,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(6),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X7",
@@ -97,6 +109,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(7),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X8",
@@ -104,6 +117,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(8),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X9",
@@ -111,6 +125,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(9),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X10",
@@ -118,6 +133,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(10),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X11",
@@ -125,6 +141,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(11),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X12",
@@ -132,6 +149,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(12),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X13",
@@ -139,6 +157,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(13),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X14",
@@ -146,6 +165,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(14),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X15",
@@ -153,6 +173,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(15),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X16",
@@ -160,6 +181,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(16),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X17",
@@ -167,6 +189,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(17),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X18",
@@ -174,6 +197,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(18),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X19",
@@ -181,6 +205,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(19),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X20",
@@ -188,6 +213,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(20),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X21",
@@ -195,6 +221,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(21),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X22",
@@ -202,6 +229,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(22),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X23",
@@ -209,6 +237,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(23),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X24",
@@ -216,6 +245,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(24),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X25",
@@ -223,6 +253,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(25),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X26",
@@ -230,6 +261,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(26),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X27",
@@ -237,6 +269,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(27),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X28",
@@ -244,6 +277,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(28),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X29",
@@ -251,6 +285,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(29),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "X30",
@@ -258,6 +293,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(30),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "SP",
@@ -265,6 +301,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(31),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "PC",
@@ -272,6 +309,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(32),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -287,6 +325,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a1",
@@ -294,6 +333,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a2",
@@ -301,6 +341,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(2),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a3",
@@ -308,6 +349,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(3),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a4",
@@ -315,6 +357,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(4),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a5",
@@ -322,6 +365,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(5),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a6",
@@ -329,6 +373,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(6),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a7",
@@ -336,6 +381,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(7),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -346,6 +392,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a1",
@@ -353,6 +400,7 @@ pub static AARCH64_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 64,
+            writable_mask: u64::MAX,
         },
     ],
 
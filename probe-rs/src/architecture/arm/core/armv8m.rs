@@ -1,20 +1,21 @@
 //! Register types and the core interface for armv8-M
 
 use crate::architecture::arm::sequences::ArmDebugSequence;
-use crate::core::RegisterFile;
+use crate::core::{RegisterFile, ResetHaltMechanism, StatusCondition, WatchpointConfig};
 use crate::error::Error;
-use crate::memory::{valid_32_address, Memory};
+use crate::memory::{valid_32_address, Memory, WriteWidths};
 use crate::{
     architecture::arm::core::register, CoreStatus, DebugProbeError, HaltReason, MemoryInterface,
 };
 use crate::{Architecture, CoreInformation};
-use crate::{CoreInterface, CoreType, InstructionSet, MemoryMappedRegister};
-use crate::{RegisterId, RegisterValue};
-use anyhow::Result;
+use crate::{CoreInterface, CoreType, HaltEscalation, InstructionSet, MemoryMappedRegister};
+use crate::{RegisterId, RegisterValue, VendorCommand, VendorCommandIndex};
 
 use bitfield::bitfield;
 
+use super::cache::{self, CacheMaintenance};
 use super::cortex_m::Cpacr;
+use super::dwt_watchpoint::{self, DwtVersion};
 use super::{CortexMState, Dfsr, ARM_REGISTER_FILE};
 use std::sync::Arc;
 use std::{
@@ -82,6 +83,7 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
 
         while start.elapsed() < timeout {
             let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+            self.state.note_reset(dhcsr_val.s_reset_st());
             if dhcsr_val.s_halt() {
                 return Ok(());
             }
@@ -93,6 +95,7 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
     fn core_halted(&mut self) -> Result<bool, Error> {
         // Wait until halted state is active again.
         let dhcsr_val = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr_val.s_reset_st());
 
         if dhcsr_val.s_halt() {
             Ok(true)
@@ -120,6 +123,7 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
@@ -141,6 +145,14 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         Ok(())
     }
 
+    fn resume_from_hw_breakpoint(&mut self) -> Result<bool, Error> {
+        super::cortex_m::resume_from_hw_breakpoint(&mut self.memory)?;
+
+        self.state.current_state = CoreStatus::Running;
+
+        Ok(true)
+    }
+
     fn reset(&mut self) -> Result<(), Error> {
         self.sequence
             .reset_system(&mut self.memory, crate::CoreType::Armv8m, None)
@@ -173,9 +185,33 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
         })
     }
 
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetVectorCatch
+    }
+
+    fn reset_vector(&mut self) -> Result<Option<u64>, Error> {
+        super::cortex_m::reset_vector(&mut self.memory).map(Some)
+    }
+
+    fn escalate_halt(&mut self, level: HaltEscalation, timeout: Duration) -> Result<bool, Error> {
+        let halted = super::cortex_m::escalate_halt(
+            &mut self.memory,
+            self.sequence.as_ref(),
+            CoreType::Armv8m,
+            level,
+            timeout,
+        )?;
+
+        // Update core status
+        let _ = self.status()?;
+
+        Ok(halted)
+    }
+
     fn step(&mut self) -> Result<CoreInformation, Error> {
         // First check if we stopped on a breakpoint, because this requires special handling before we can continue.
         let was_breakpoint =
@@ -212,15 +248,36 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         // get pc
         Ok(CoreInformation {
             pc: pc_value.try_into()?,
+            instruction_set: None,
+        })
+    }
+
+    fn step_fast(&mut self) -> Result<CoreInformation, Error> {
+        let pc = match super::cortex_m::queued_step(&mut self.memory, &mut self.state)? {
+            Some(pc) => pc,
+            None => {
+                // Complete the step like a regular one.
+                self.wait_for_core_halted(Duration::from_millis(100))?;
+                self.read_core_reg(register::PC.id)?.try_into()?
+            }
+        };
+
+        Ok(CoreInformation {
+            pc: pc.into(),
+            instruction_set: None,
         })
     }
 
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, Error> {
+        super::cortex_m::register_available(self.core_type(), address, || self.fpu_support())
+    }
+
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, Error> {
         let value = super::cortex_m::read_core_reg(&mut self.memory, address)?;
         Ok(value.into())
     }
 
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()> {
+    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<(), Error> {
         super::cortex_m::write_core_reg(&mut self.memory, address, value.try_into()?)?;
         Ok(())
     }
@@ -296,8 +353,45 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
         Ok(InstructionSet::Thumb2)
     }
 
+    fn take_reset_detected(&mut self) -> bool {
+        self.state.take_reset_detected()
+    }
+
+    fn take_halt_detected(&mut self) -> bool {
+        self.state.take_halt_detected()
+    }
+
+    fn halted_condition(&self) -> Option<StatusCondition> {
+        Some(super::cortex_m::halted_condition())
+    }
+
+    fn poll_condition(
+        &mut self,
+        condition: StatusCondition,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        self.memory.poll_word_32(
+            condition.address,
+            condition.mask,
+            condition.expected,
+            timeout,
+        )
+    }
+
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, Error> {
+        self.memory.enqueue_vendor_command(command)
+    }
+
+    fn vendor_command_response(&mut self, index: VendorCommandIndex) -> Result<Vec<u8>, Error> {
+        self.memory.vendor_command_response(index)
+    }
+
     fn status(&mut self) -> Result<crate::core::CoreStatus, Error> {
         let dhcsr = Dhcsr(self.memory.read_word_32(Dhcsr::ADDRESS)?);
+        self.state.note_reset(dhcsr.s_reset_st());
 
         if dhcsr.s_lockup() {
             log::warn!("The core is in locked up status as a result of an unrecoverable exception");
@@ -324,6 +418,10 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
             let dfsr = Dfsr(self.memory.read_word_32(Dfsr::ADDRESS)?);
 
             let reason = dfsr.halt_reason();
+            // The halt after a queued step was noted already.
+            if !self.state.take_step_reason_pending() {
+                self.state.note_halt(reason);
+            }
 
             // Clear bits from Dfsr register
             self.memory
@@ -382,7 +480,49 @@ impl<'probe> CoreInterface for Armv8m<'probe> {
     }
 
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
-        Ok(Cpacr(self.memory.read_word_32(Cpacr::ADDRESS)?).fpu_present())
+        if let Some(present) = self.state.fpu_present {
+            return Ok(present);
+        }
+
+        let present = Cpacr(self.memory.read_word_32(Cpacr::ADDRESS)?).fpu_present();
+        self.state.fpu_present = Some(present);
+
+        Ok(present)
+    }
+
+    fn cache_line_size(&mut self) -> Result<Option<u32>, crate::error::Error> {
+        cache::cortex_m_cache_line_size(&mut self.memory)
+    }
+
+    fn cache_maintenance(
+        &mut self,
+        operation: CacheMaintenance,
+        address: u64,
+    ) -> Result<(), crate::error::Error> {
+        cache::cortex_m_cache_maintenance(&mut self.memory, operation, address)
+    }
+
+    fn available_watchpoint_units(&mut self) -> Result<u32, crate::error::Error> {
+        dwt_watchpoint::available_units(&mut self.memory)
+    }
+
+    fn set_watchpoint(
+        &mut self,
+        config: &WatchpointConfig,
+        used: &[usize],
+    ) -> Result<Vec<usize>, crate::error::Error> {
+        dwt_watchpoint::set(&mut self.memory, DwtVersion::Armv8m, config, used)
+    }
+
+    fn clear_watchpoint(&mut self, units: &[usize]) -> Result<(), crate::error::Error> {
+        dwt_watchpoint::clear(&mut self.memory, units)
+    }
+
+    fn matched_watchpoint_units(
+        &mut self,
+        units: &[usize],
+    ) -> Result<Option<Vec<usize>>, crate::error::Error> {
+        dwt_watchpoint::matched(&mut self.memory, units).map(Some)
     }
 }
 
@@ -390,6 +530,9 @@ impl<'probe> MemoryInterface for Armv8m<'probe> {
     fn supports_native_64bit_access(&mut self) -> bool {
         self.memory.supports_native_64bit_access()
     }
+    fn native_write_widths(&mut self) -> WriteWidths {
+        self.memory.native_write_widths()
+    }
     fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
         self.memory.read_word_32(address)
     }
@@ -426,6 +569,9 @@ impl<'probe> MemoryInterface for Armv8m<'probe> {
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.memory.write_8(address, data)
     }
+    fn write_barrier(&mut self) -> Result<(), Error> {
+        self.memory.write_barrier()
+    }
     fn flush(&mut self) -> Result<(), Error> {
         self.memory.flush()
     }
@@ -0,0 +1,212 @@
+//! Cache maintenance for memory accesses which are coherent with the view of the core.
+//!
+//! On a Cortex-M7 or Cortex-M55, accesses through the memory AP go to the bus, past the
+//! caches of the core. A read can return stale data if the core holds a dirty line, and
+//! a write can be hidden by a line which is still in the cache. On ARMv7-A cores, accesses
+//! are executed by the core itself, and see the data cache, but not the instruction cache.
+
+use crate::memory::Memory;
+use crate::{Error, MemoryInterface};
+
+/// CCR: The configuration and control register, with the cache enables.
+const CCR: u64 = 0xE000_ED14;
+
+/// CCR.DC: The data cache is enabled.
+const CCR_DC: u32 = 1 << 16;
+
+/// CCR.IC: The instruction cache is enabled.
+const CCR_IC: u32 = 1 << 17;
+
+/// CTR: The cache type register, with the smallest cache line sizes.
+const CTR: u64 = 0xE000_ED7C;
+
+/// ICIMVAU: Invalidate the instruction cache line by address.
+const ICIMVAU: u64 = 0xE000_EF58;
+
+/// DCIMVAC: Invalidate the data cache line by address.
+const DCIMVAC: u64 = 0xE000_EF5C;
+
+/// DCCMVAC: Clean the data cache line by address.
+const DCCMVAC: u64 = 0xE000_EF68;
+
+/// DCCIMVAC: Clean and invalidate the data cache line by address.
+const DCCIMVAC: u64 = 0xE000_EF70;
+
+/// A maintenance operation on the cache line containing an address.
+///
+/// [`Core::read_coherent`](crate::Core::read_coherent) and
+/// [`Core::write_coherent`](crate::Core::write_coherent) perform the following operations on
+/// each cache line covered by the access:
+///
+/// | Core             | Read        | Write                                                                           |
+/// |------------------|-------------|---------------------------------------------------------------------------------|
+/// | ARMv7-M, ARMv8-M | clean, read | clean and invalidate, write, invalidate, invalidate instruction                 |
+/// | ARMv7-A          | read        | write, clean, invalidate instruction, each operation followed by a DSB          |
+///
+/// On ARMv7-M and ARMv8-M, the operations are performed by writing to the maintenance
+/// registers in the system control block. On ARMv7-A, they are executed as CP15 operations
+/// by the halted core. Cores without caches, or with all caches disabled, only perform the
+/// access. The caches of ARMv8-A cores are not maintained yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMaintenance {
+    /// Write the data cache line back to memory, if it is dirty.
+    Clean,
+    /// Write the data cache line back to memory, if it is dirty, and remove it from the cache.
+    CleanInvalidate,
+    /// Remove the data cache line from the cache, discarding its contents.
+    Invalidate,
+    /// Remove the instruction cache line from the cache.
+    InvalidateInstruction,
+}
+
+/// Returns the smallest line size in bytes of the enabled caches of a Cortex-M core, or
+/// `None` if no cache is enabled.
+///
+/// CCR.DC and CCR.IC read as zero on cores without caches.
+pub(crate) fn cortex_m_cache_line_size(memory: &mut Memory) -> Result<Option<u32>, Error> {
+    if memory.read_word_32(CCR)? & (CCR_DC | CCR_IC) == 0 {
+        return Ok(None);
+    }
+
+    let ctr = memory.read_word_32(CTR)?;
+
+    Ok(Some(line_size(ctr)))
+}
+
+/// Perform `operation` on a Cortex-M core, by writing the address to the maintenance
+/// register in the system control block.
+pub(crate) fn cortex_m_cache_maintenance(
+    memory: &mut Memory,
+    operation: CacheMaintenance,
+    address: u64,
+) -> Result<(), Error> {
+    let register = match operation {
+        CacheMaintenance::Clean => DCCMVAC,
+        CacheMaintenance::CleanInvalidate => DCCIMVAC,
+        CacheMaintenance::Invalidate => DCIMVAC,
+        CacheMaintenance::InvalidateInstruction => ICIMVAU,
+    };
+
+    memory.write_word_32(register, address as u32)
+}
+
+/// Returns the smallest line size in bytes, from the DminLine and IminLine fields of a CTR,
+/// which is the same register on ARMv7-M, ARMv8-M and ARMv7-A.
+pub(crate) fn line_size(ctr: u32) -> u32 {
+    let dmin_line = (ctr >> 16) & 0xf;
+    let imin_line = ctr & 0xf;
+
+    // The fields are the log2 of the number of words.
+    4 << dmin_line.min(imin_line)
+}
+
+/// Returns the start addresses of the cache lines covering `len` bytes at `address`.
+pub(crate) fn cache_lines(address: u64, len: usize, line_size: u32) -> impl Iterator<Item = u64> {
+    let line_size = line_size as u64;
+    let start = address & !(line_size - 1);
+    let end = address + len as u64;
+
+    (start..end).step_by(line_size as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::ap::memory_ap::mock::MockMemoryAp;
+    use crate::architecture::arm::ap::MemoryAp;
+    use crate::architecture::arm::core::{armv7m::Armv7m, CortexMState};
+    use crate::architecture::arm::memory::adi_v5_memory_interface::ADIMemoryInterface;
+    use crate::architecture::arm::sequences::DefaultArmSequence;
+    use crate::architecture::arm::{ApAddress, DpAddress, MemoryApInformation};
+    use crate::core::{CoreAccessOptions, CoreState};
+    use crate::Core;
+
+    /// CTR of a Cortex-M7, with 32 byte cache lines.
+    const CORTEX_M7_CTR: u32 = 0x8303_c003;
+
+    /// Run `f` on a Cortex-M core with the caches enabled if `caches` is set, and return the
+    /// maintenance operations it performed.
+    fn with_core(
+        caches: bool,
+        f: impl FnOnce(&mut Core) -> Result<(), Error>,
+    ) -> Vec<(CacheMaintenance, u32)> {
+        let mut ap = MockMemoryAp::with_mock_core();
+        let address = ApAddress {
+            dp: DpAddress::Default,
+            ap: 0,
+        };
+
+        {
+            let information = MemoryApInformation {
+                address,
+                only_32bit_data_size: false,
+                debug_base_address: 0xf000_0000,
+                supports_hnonsec: false,
+                has_large_data_extension: false,
+                has_large_address_extension: false,
+            };
+            let interface = ADIMemoryInterface::new(&mut ap, &information).unwrap();
+            let mut memory = Memory::new(interface, MemoryAp::new(address));
+
+            if caches {
+                memory.write_word_32(CCR, CCR_DC | CCR_IC).unwrap();
+                memory.write_word_32(CTR, CORTEX_M7_CTR).unwrap();
+            }
+
+            let mut cortex_m_state = CortexMState::new();
+            let mut state = CoreState::new(0, CoreAccessOptions::Arm(Default::default()));
+            let armv7m =
+                Armv7m::new(memory, &mut cortex_m_state, DefaultArmSequence::create()).unwrap();
+            let mut core = Core::new(armv7m, &mut state);
+
+            f(&mut core).unwrap();
+        }
+
+        ap.cache_maintenance().to_vec()
+    }
+
+    #[test]
+    fn cortex_m7_line_size() {
+        assert_eq!(line_size(CORTEX_M7_CTR), 32);
+    }
+
+    #[test]
+    fn coherent_read_cleans_the_lines() {
+        let operations = with_core(true, |core| {
+            let mut data = [0; 40];
+            core.read_coherent(0x2000_0010, &mut data)
+        });
+
+        assert_eq!(
+            operations,
+            [
+                (CacheMaintenance::Clean, 0x2000_0000),
+                (CacheMaintenance::Clean, 0x2000_0020),
+            ]
+        );
+    }
+
+    #[test]
+    fn coherent_write_invalidates_the_lines() {
+        let operations = with_core(true, |core| core.write_coherent(0x2000_0020, &[0xff; 32]));
+
+        assert_eq!(
+            operations,
+            [
+                (CacheMaintenance::CleanInvalidate, 0x2000_0020),
+                (CacheMaintenance::Invalidate, 0x2000_0020),
+                (CacheMaintenance::InvalidateInstruction, 0x2000_0020),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_maintenance_without_caches() {
+        let operations = with_core(false, |core| {
+            core.write_coherent(0x2000_0000, &[0xff; 64])?;
+            core.read_coherent(0x2000_0000, &mut [0; 64])
+        });
+
+        assert!(operations.is_empty());
+    }
+}
@@ -1,8 +1,15 @@
 //! Common functions and data types for Cortex-M core variants
 
-use crate::{DebugProbeError, Error, Memory, MemoryMappedRegister, RegisterId};
+use super::armv7m::Aircr;
+use super::{register, CortexMState, Dfsr};
+use crate::architecture::arm::sequences::ArmDebugSequence;
+use crate::{
+    CoreStatus, CoreType, DebugProbeError, Error, HaltEscalation, HaltReason, Memory,
+    MemoryMappedRegister, RegisterId, StatusCondition,
+};
 
 use bitfield::bitfield;
+use std::thread;
 use std::time::{Duration, Instant};
 
 bitfield! {
@@ -128,6 +135,256 @@ impl MemoryMappedRegister for Cpacr {
     const NAME: &'static str = "CPACR";
 }
 
+/// The address of the Vector Table Offset Register.
+const VTOR: u64 = 0xE000_ED08;
+
+/// Read the reset vector from the vector table VTOR points to.
+///
+/// VTOR is optional on ARMv6-M, the vector table is at address 0 if it can't be read.
+pub(crate) fn reset_vector(memory: &mut Memory) -> Result<u64, Error> {
+    let vector_table = match memory.read_word_32(VTOR) {
+        Ok(vtor) => vtor & !0x7f,
+        Err(error) => {
+            log::debug!(
+                "Failed to read VTOR, assuming the vector table is at 0: {}",
+                error
+            );
+            0
+        }
+    };
+
+    let reset_vector = memory.read_word_32(vector_table as u64 + 4)?;
+
+    // Clear the Thumb bit.
+    Ok((reset_vector & !1) as u64)
+}
+
+/// The condition on DHCSR which holds while a Cortex-M core is halted, see
+/// [`CoreInterface::halted_condition`](crate::CoreInterface::halted_condition).
+pub(crate) fn halted_condition() -> StatusCondition {
+    // S_HALT
+    let s_halt = 1 << 17;
+
+    StatusCondition {
+        address: Dhcsr::ADDRESS,
+        mask: s_halt,
+        expected: s_halt,
+    }
+}
+
+/// Halt a Cortex-M core which ignored a halt request, see
+/// [`CoreInterface::escalate_halt`](crate::CoreInterface::escalate_halt).
+///
+/// For [`HaltEscalation::CoreReset`], ARMv7-M cores are reset with `VECTRESET`, which only
+/// resets the core. ARMv6-M and ARMv8-M have no local reset, so the system is reset.
+pub(crate) fn escalate_halt(
+    memory: &mut Memory,
+    sequence: &dyn ArmDebugSequence,
+    core_type: CoreType,
+    level: HaltEscalation,
+    timeout: Duration,
+) -> Result<bool, Error> {
+    match level {
+        HaltEscalation::PersistentHaltRequest => {
+            let mut dhcsr = Dhcsr(0);
+            dhcsr.set_c_debugen(true);
+            dhcsr.set_c_halt(true);
+            dhcsr.enable_write();
+
+            // A core can miss a single request, e.g. while the clock of its debug logic is
+            // gated, so the request is written again until the core halts.
+            poll_halted(memory, timeout, |memory| {
+                memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())
+            })
+        }
+        HaltEscalation::CoreReset => {
+            sequence.reset_catch_set(memory, core_type, None)?;
+
+            let reset = match core_type {
+                CoreType::Armv7m | CoreType::Armv7em => {
+                    let mut aircr = Aircr(0);
+                    aircr.vectkey();
+                    aircr.set_vectreset(true);
+
+                    memory.write_word_32(Aircr::ADDRESS, aircr.into())
+                }
+                _ => sequence.reset_system(memory, core_type, None),
+            };
+
+            let result = reset.and_then(|_| poll_halted(memory, timeout, |_| Ok(())));
+
+            sequence.reset_catch_clear(memory, core_type, None)?;
+
+            result
+        }
+        _ => Ok(false),
+    }
+}
+
+/// The address of FP_CTRL, or BP_CTRL on ARMv6-M. Its KEY and ENABLE bits are the same on
+/// all Cortex-M variants.
+const FP_CTRL: u64 = 0xE000_2000;
+
+/// Resume a Cortex-M core which is halted at one of its hardware breakpoints, see
+/// [`CoreInterface::resume_from_hw_breakpoint`](crate::CoreInterface::resume_from_hw_breakpoint).
+///
+/// The instruction at the breakpoint is stepped with the breakpoint unit disabled, which
+/// only takes one read to check that the step is done. The other accesses are writes,
+/// which are not flushed here, so that the probe can send them with the next read of the
+/// core status.
+pub(crate) fn resume_from_hw_breakpoint(memory: &mut Memory) -> Result<(), Error> {
+    // FP_CTRL.KEY
+    let key = 1 << 1;
+    // FP_CTRL.ENABLE
+    let enable = 1 << 0;
+
+    memory.write_word_32(FP_CTRL, key)?;
+
+    // C_MASKINTS may only be changed while the core is halted, so it is set before the step,
+    // and cleared before the core is resumed.
+    let mut dhcsr = Dhcsr(0);
+    dhcsr.set_c_debugen(true);
+    dhcsr.set_c_halt(true);
+    dhcsr.set_c_maskints(true);
+    dhcsr.enable_write();
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    dhcsr.set_c_halt(false);
+    dhcsr.set_c_step(true);
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    poll_halted(memory, Duration::from_millis(100), |_| Ok(()))?;
+
+    memory.write_word_32(Dfsr::ADDRESS, Dfsr::clear_all().into())?;
+    memory.write_word_32(FP_CTRL, key | enable)?;
+
+    let mut dhcsr = Dhcsr(0);
+    dhcsr.set_c_debugen(true);
+    dhcsr.set_c_halt(true);
+    dhcsr.enable_write();
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    dhcsr.set_c_halt(false);
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())
+}
+
+/// Step the core with all accesses queued before a single block read of DHCSR, DCRSR and
+/// DCRDR, so that a probe which queues its accesses steps in one round trip, see
+/// [`CoreInterface::step_fast`](crate::CoreInterface::step_fast).
+///
+/// The program counter is selected right after the step, which assumes that the core
+/// halted again before the selection arrives. DHCSR shows whether this held: if the core
+/// didn't halt with the register ready, e.g. because it is sleeping, `None` is returned,
+/// and the step has to be completed like a regular one. Otherwise the value of the program
+/// counter is returned.
+///
+/// The breakpoint unit is disabled during the step, so that a breakpoint at the current
+/// instruction doesn't halt the core again. DFSR is cleared before the step, so that it
+/// only shows the reason of the halt after it, which is read with the next status.
+pub(crate) fn queued_step(
+    memory: &mut Memory,
+    state: &mut CortexMState,
+) -> Result<Option<u32>, Error> {
+    // FP_CTRL.KEY
+    let key = 1 << 1;
+    // FP_CTRL.ENABLE
+    let enable = 1 << 0;
+
+    let breakpoints = state.hw_breakpoints_enabled
+        || state.current_state == CoreStatus::Halted(HaltReason::Breakpoint);
+
+    if breakpoints {
+        memory.write_word_32(FP_CTRL, key)?;
+    }
+    memory.write_word_32(Dfsr::ADDRESS, Dfsr::clear_all().into())?;
+
+    // C_MASKINTS may only be changed while the core is halted, so it is set before the step.
+    let mut dhcsr = Dhcsr(0);
+    dhcsr.set_c_debugen(true);
+    dhcsr.set_c_halt(true);
+    dhcsr.set_c_maskints(true);
+    dhcsr.enable_write();
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    dhcsr.set_c_halt(false);
+    dhcsr.set_c_step(true);
+    memory.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+    let mut dcrsr = Dcrsr(0);
+    dcrsr.set_regwnr(false);
+    dcrsr.set_regsel(register::PC.id.into());
+    memory.write_word_32(Dcrsr::ADDRESS, dcrsr.into())?;
+
+    if breakpoints {
+        memory.write_word_32(FP_CTRL, key | enable)?;
+        state.hw_breakpoints_enabled = true;
+    }
+
+    // DCRSR is write-only, its value is ignored.
+    let mut words = [0; 3];
+    memory.read_32(Dhcsr::ADDRESS, &mut words)?;
+
+    let dhcsr = Dhcsr(words[0]);
+    state.note_reset(dhcsr.s_reset_st());
+
+    if !dhcsr.s_halt() || !dhcsr.s_regrdy() || dhcsr.s_lockup() {
+        log::debug!(
+            "The core didn't halt with the program counter ready after a queued step: {:?}",
+            dhcsr
+        );
+        return Ok(None);
+    }
+
+    state.note_queued_step();
+
+    Ok(Some(words[2]))
+}
+
+/// Send `request` to the core until it is halted, or `timeout` elapsed.
+fn poll_halted(
+    memory: &mut Memory,
+    timeout: Duration,
+    mut request: impl FnMut(&mut Memory) -> Result<(), Error>,
+) -> Result<bool, Error> {
+    let start = Instant::now();
+
+    loop {
+        request(memory)?;
+
+        if Dhcsr(memory.read_word_32(Dhcsr::ADDRESS)?).s_halt() {
+            return Ok(true);
+        }
+
+        if start.elapsed() >= timeout {
+            return Err(Error::Probe(DebugProbeError::Timeout));
+        }
+
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// Whether the DCRSR register selector `addr` selects a register which exists on cores of
+/// the type `core_type`.
+///
+/// The FPU registers exist if `fpu_present` returns `true`, it is only called for them.
+pub(crate) fn register_available(
+    core_type: CoreType,
+    addr: RegisterId,
+    fpu_present: impl FnOnce() -> Result<bool, Error>,
+) -> Result<bool, Error> {
+    match (core_type, addr.0) {
+        // R0-R12, SP, LR, DebugReturnAddress, xPSR, MSP, PSP, and CONTROL with the masks
+        (_, 0x00..=0x12 | 0x14) => Ok(true),
+        // The banked stack pointers, stack limits and CONTROL of the Security Extension
+        (CoreType::Armv8m, 0x18..=0x1f | 0x22 | 0x23) => Ok(true),
+        // FPSCR and S0-S31
+        (CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m, 0x21 | 0x40..=0x5f) => {
+            fpu_present()
+        }
+        _ => Ok(false),
+    }
+}
+
 pub(crate) fn read_core_reg(memory: &mut Memory, addr: RegisterId) -> Result<u32, Error> {
     // Write the DCRSR value to select the register we want to read.
     let mut dcrsr_val = Dcrsr(0);
@@ -176,3 +433,40 @@ fn wait_for_core_register_transfer(memory: &mut Memory, timeout: Duration) -> Re
     }
     Err(Error::Probe(DebugProbeError::Timeout))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn no_fpu_access() -> Result<bool, Error> {
+        panic!("The FPU was checked for a register which doesn't need it");
+    }
+
+    #[test]
+    fn armv6m_registers() {
+        for (register, available) in [(0x12, true), (0x14, true), (0x13, false), (0x18, false)] {
+            assert_eq!(
+                register_available(CoreType::Armv6m, RegisterId(register), no_fpu_access).unwrap(),
+                available
+            );
+        }
+
+        // ARMv6-M has no FPU.
+        assert!(!register_available(CoreType::Armv6m, RegisterId(0x21), no_fpu_access).unwrap());
+    }
+
+    #[test]
+    fn fpu_registers_depend_on_fpu() {
+        for fpu in [false, true] {
+            for register in [0x21, 0x40, 0x5f] {
+                assert_eq!(
+                    register_available(CoreType::Armv7em, RegisterId(register), || Ok(fpu))
+                        .unwrap(),
+                    fpu
+                );
+            }
+        }
+
+        assert!(!register_available(CoreType::Armv7m, RegisterId(0x60), no_fpu_access).unwrap());
+    }
+}
@@ -0,0 +1,468 @@
+//! Data watchpoints with the DWT comparators of Cortex-M cores.
+//!
+//! The layout of the comparators differs between the architecture versions:
+//!
+//! - ARMv6-M and ARMv7-M: a comparator watches a power of two bytes, which are aligned to their
+//!   size, with the address mask in `DWT_MASK`. On ARMv7-M, a data value comparator is linked
+//!   to an address comparator by its index, but only some comparators, often only comparator
+//!   1, support data value matching.
+//! - ARMv8-M: a comparator watches 1, 2 or 4 bytes. Larger ranges are watched with an address
+//!   comparator followed by a limit comparator, and a value with an address comparator followed
+//!   by a linked data value comparator. The comparators which support limits and linked values
+//!   are implementation defined as well.
+//!
+//! Which comparators support a feature is found by programming it and reading it back.
+
+use super::armv7m::Demcr;
+use super::hit_count::{comp_address, function_address, mask_address, DWT_BASE, FUNCTION_MATCHED};
+use crate::{
+    Error, Memory, MemoryInterface, MemoryMappedRegister, WatchpointConfig, WatchpointKind,
+    WatchpointQualifier,
+};
+
+/// DWT_FUNCTION (ARMv7-M): Compare the data value instead of the address.
+const FUNCTION_DATAVMATCH: u32 = 1 << 8;
+
+/// DWT_FUNCTION (ARMv8-M): Generate a debug event, which halts the core.
+const ACTION_DEBUG_EVENT: u32 = 0b01 << 4;
+
+/// DWT_FUNCTION (ARMv8-M): The MATCH field.
+const MATCH_MASK: u32 = 0b1111;
+
+/// DWT_FUNCTION (ARMv8-M): Data address limit.
+const MATCH_ADDRESS_LIMIT: u32 = 0b0111;
+
+/// DWT_FUNCTION (ARMv8-M): Linked data value.
+const MATCH_LINKED_VALUE: u32 = 0b1011;
+
+/// The version of the DWT, which is the one of the architecture of the core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DwtVersion {
+    Armv6m,
+    Armv7m,
+    Armv8m,
+}
+
+/// The settings of a single comparator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Comparator {
+    comp: u32,
+    mask: u32,
+    function: u32,
+}
+
+/// Returns the number of DWT comparators.
+pub(crate) fn available_units(memory: &mut Memory) -> Result<u32, Error> {
+    enable_dwt(memory)?;
+
+    Ok(memory.read_word_32(DWT_BASE)? >> 28)
+}
+
+/// Program the watchpoint `config` into comparators which aren't `used`, see
+/// [`CoreInterface::set_watchpoint`](crate::CoreInterface::set_watchpoint).
+pub(crate) fn set(
+    memory: &mut Memory,
+    version: DwtVersion,
+    config: &WatchpointConfig,
+    used: &[usize],
+) -> Result<Vec<usize>, Error> {
+    check_qualifiers(version, config)?;
+
+    if config.address + config.len > 1 << 32 {
+        return Err(Error::InvalidWatchpoint {
+            address: config.address,
+            len: config.len,
+            reason: "the DWT only watches 32-bit addresses",
+        });
+    }
+    let address = config.address as u32;
+
+    let available = available_units(memory)? as usize;
+    let free: Vec<usize> = (0..available).filter(|unit| !used.contains(unit)).collect();
+
+    match (version, config.masked_value()) {
+        (DwtVersion::Armv6m | DwtVersion::Armv7m, None) => {
+            let comparator = masked_address_comparator(config, address)?;
+            let unit = *free.first().ok_or(Error::WatchpointsExhausted)?;
+
+            program(memory, version, unit, comparator)?;
+
+            Ok(vec![unit])
+        }
+        (DwtVersion::Armv7m, Some((value, _, _))) => {
+            let linked = Comparator {
+                function: 0,
+                ..masked_address_comparator(config, address)?
+            };
+
+            if free.len() < 2 {
+                return Err(Error::WatchpointsExhausted);
+            }
+
+            for &value_unit in &free {
+                // Unwrapping is fine, there are at least two free comparators.
+                let address_unit = *free.iter().find(|&&unit| unit != value_unit).unwrap();
+
+                program(memory, version, address_unit, linked)?;
+                program(
+                    memory,
+                    version,
+                    value_unit,
+                    value_comparator(config, value as u32, address_unit),
+                )?;
+
+                let function = memory.read_word_32(function_address(value_unit))?;
+                if function & FUNCTION_DATAVMATCH != 0 {
+                    return Ok(vec![address_unit, value_unit]);
+                }
+
+                clear(memory, &[address_unit, value_unit])?;
+            }
+
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::Value,
+                reason: "no DWT comparator supports data value matching",
+            })
+        }
+        (DwtVersion::Armv8m, value) => {
+            let comparators = armv8m_comparators(config, address, value.map(|(value, ..)| value));
+
+            let count = comparators.len();
+            let mut unsupported = false;
+
+            for start in 0..available.saturating_sub(count - 1) {
+                let units: Vec<usize> = (start..start + count).collect();
+                if units.iter().any(|unit| used.contains(unit)) {
+                    continue;
+                }
+
+                for (&unit, &comparator) in units.iter().zip(&comparators) {
+                    program(memory, version, unit, comparator)?;
+                }
+
+                // The last comparator has the feature which might not be supported.
+                let last = units[count - 1];
+                let expected = comparators[count - 1].function & MATCH_MASK;
+                if memory.read_word_32(function_address(last))? & MATCH_MASK == expected {
+                    return Ok(units);
+                }
+
+                unsupported = true;
+                clear(memory, &units)?;
+            }
+
+            if !unsupported {
+                Err(Error::WatchpointsExhausted)
+            } else if value.is_some() {
+                Err(Error::UnsupportedWatchpointQualifier {
+                    qualifier: WatchpointQualifier::Value,
+                    reason: "no DWT comparator supports linked data value matching",
+                })
+            } else {
+                Err(Error::InvalidWatchpoint {
+                    address: config.address,
+                    len: config.len,
+                    reason: "no DWT comparator supports address limits",
+                })
+            }
+        }
+        (DwtVersion::Armv6m, Some(_)) => unreachable!("rejected by check_qualifiers"),
+    }
+}
+
+/// Disable the comparators `units`.
+pub(crate) fn clear(memory: &mut Memory, units: &[usize]) -> Result<(), Error> {
+    for &unit in units {
+        memory.write_word_32(function_address(unit), 0)?;
+        memory.write_word_32(comp_address(unit), 0)?;
+    }
+
+    memory.flush()
+}
+
+/// Returns which of `units` matched since they were last read. Reading clears the flag.
+pub(crate) fn matched(memory: &mut Memory, units: &[usize]) -> Result<Vec<usize>, Error> {
+    let mut matched = Vec::new();
+
+    for &unit in units {
+        if memory.read_word_32(function_address(unit))? & FUNCTION_MATCHED != 0 {
+            matched.push(unit);
+        }
+    }
+
+    Ok(matched)
+}
+
+/// Set DEMCR.TRCENA, without it the DWT can't be accessed.
+fn enable_dwt(memory: &mut Memory) -> Result<(), Error> {
+    let mut demcr = Demcr(memory.read_word_32(Demcr::ADDRESS)?);
+
+    if !demcr.trcena() {
+        demcr.set_trcena(true);
+        memory.write_word_32(Demcr::ADDRESS, demcr.into())?;
+    }
+
+    Ok(())
+}
+
+fn program(
+    memory: &mut Memory,
+    version: DwtVersion,
+    unit: usize,
+    comparator: Comparator,
+) -> Result<(), Error> {
+    memory.write_word_32(function_address(unit), 0)?;
+    memory.write_word_32(comp_address(unit), comparator.comp)?;
+    if version != DwtVersion::Armv8m {
+        memory.write_word_32(mask_address(unit), comparator.mask)?;
+    }
+
+    // Reading the function register clears a stale MATCHED flag.
+    memory.write_word_32(function_address(unit), comparator.function)?;
+    memory.read_word_32(function_address(unit))?;
+
+    Ok(())
+}
+
+/// Reject the qualifiers the DWT can't implement at all.
+fn check_qualifiers(version: DwtVersion, config: &WatchpointConfig) -> Result<(), Error> {
+    if config.access_size.is_some() {
+        return Err(Error::UnsupportedWatchpointQualifier {
+            qualifier: WatchpointQualifier::AccessSize,
+            reason: "the DWT of Cortex-M cores doesn't qualify accesses by their size",
+        });
+    }
+
+    if let Some((_, _, whole)) = config.masked_value() {
+        if version == DwtVersion::Armv6m {
+            return Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::Value,
+                reason: "the DWT of ARMv6-M cores doesn't compare data values",
+            });
+        }
+
+        if config.len > 4 {
+            return Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::Value,
+                reason: "the DWT compares values of at most 4 bytes",
+            });
+        }
+
+        if !whole {
+            return Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::ValueMask,
+                reason: "the DWT only compares whole values",
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The ARMv6-M and ARMv7-M FUNCTION value of a watchpoint of `kind`.
+fn watchpoint_function(kind: WatchpointKind) -> u32 {
+    match kind {
+        WatchpointKind::Read => 0b0101,
+        WatchpointKind::Write => 0b0110,
+        WatchpointKind::ReadWrite => 0b0111,
+    }
+}
+
+/// The ARMv8-M MATCH value of a data address comparator of `kind`.
+fn address_match(kind: WatchpointKind) -> u32 {
+    match kind {
+        WatchpointKind::ReadWrite => 0b0100,
+        WatchpointKind::Write => 0b0101,
+        WatchpointKind::Read => 0b0110,
+    }
+}
+
+/// The DATAVSIZE field for a value or an access of `len` bytes.
+fn data_size(len: u64) -> u32 {
+    match len {
+        1 => 0b00,
+        2 => 0b01,
+        _ => 0b10,
+    }
+}
+
+/// `value` repeated to fill a word, as the DWT expects values smaller than a word.
+fn replicate(value: u32, len: u64) -> u32 {
+    match len {
+        1 => (value & 0xff) * 0x0101_0101,
+        2 => (value & 0xffff) * 0x0001_0001,
+        _ => value,
+    }
+}
+
+/// An ARMv6-M or ARMv7-M comparator which watches `config` with an address mask.
+fn masked_address_comparator(config: &WatchpointConfig, address: u32) -> Result<Comparator, Error> {
+    if !config.len.is_power_of_two() || config.address % config.len != 0 {
+        return Err(Error::InvalidWatchpoint {
+            address: config.address,
+            len: config.len,
+            reason: "the DWT only watches a power of two bytes, which are aligned to their size",
+        });
+    }
+
+    Ok(Comparator {
+        comp: address,
+        mask: config.len.trailing_zeros(),
+        function: watchpoint_function(config.kind),
+    })
+}
+
+/// An ARMv7-M data value comparator, which is linked to the address comparator `linked`.
+fn value_comparator(config: &WatchpointConfig, value: u32, linked: usize) -> Comparator {
+    let linked = linked as u32 & 0xf;
+
+    Comparator {
+        comp: replicate(value, config.len),
+        mask: 0,
+        function: watchpoint_function(config.kind)
+            | FUNCTION_DATAVMATCH
+            | data_size(config.len) << 10
+            | linked << 12
+            | linked << 16,
+    }
+}
+
+/// The consecutive ARMv8-M comparators which implement `config`.
+fn armv8m_comparators(
+    config: &WatchpointConfig,
+    address: u32,
+    value: Option<u64>,
+) -> Vec<Comparator> {
+    let single = matches!(config.len, 1 | 2 | 4) && config.address % config.len == 0;
+    let address_match = address_match(config.kind);
+
+    match value {
+        Some(value) => vec![
+            Comparator {
+                comp: address,
+                mask: 0,
+                function: address_match | data_size(config.len) << 10,
+            },
+            Comparator {
+                comp: replicate(value as u32, config.len),
+                mask: 0,
+                function: MATCH_LINKED_VALUE | ACTION_DEBUG_EVENT | data_size(config.len) << 10,
+            },
+        ],
+        None if single => vec![Comparator {
+            comp: address,
+            mask: 0,
+            function: address_match | ACTION_DEBUG_EVENT | data_size(config.len) << 10,
+        }],
+        None => vec![
+            Comparator {
+                comp: address,
+                mask: 0,
+                function: address_match,
+            },
+            Comparator {
+                comp: address + (config.len as u32 - 1),
+                mask: 0,
+                function: MATCH_ADDRESS_LIMIT | ACTION_DEBUG_EVENT,
+            },
+        ],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn masked_comparators_watch_aligned_powers_of_two() {
+        let config = WatchpointConfig::new(0x2000_0010, 16, WatchpointKind::Write);
+
+        assert_eq!(
+            masked_address_comparator(&config, 0x2000_0010).unwrap(),
+            Comparator {
+                comp: 0x2000_0010,
+                mask: 4,
+                function: 0b0110
+            }
+        );
+
+        let config = WatchpointConfig::new(0x2000_0004, 8, WatchpointKind::Write);
+        assert!(matches!(
+            masked_address_comparator(&config, 0x2000_0004),
+            Err(Error::InvalidWatchpoint { .. })
+        ));
+    }
+
+    #[test]
+    fn value_comparators_are_linked_to_the_address_comparator() {
+        let config = WatchpointConfig::new(0x2000_0000, 1, WatchpointKind::Read).value(0x5a, 0xff);
+
+        assert_eq!(
+            value_comparator(&config, 0x5a, 2),
+            Comparator {
+                comp: 0x5a5a_5a5a,
+                mask: 0,
+                function: 0b0101 | FUNCTION_DATAVMATCH | 2 << 12 | 2 << 16
+            }
+        );
+    }
+
+    #[test]
+    fn armv8m_ranges_take_a_limit_comparator() {
+        let config = WatchpointConfig::new(0x2000_0000, 0x20, WatchpointKind::ReadWrite);
+
+        assert_eq!(
+            armv8m_comparators(&config, 0x2000_0000, None),
+            [
+                Comparator {
+                    comp: 0x2000_0000,
+                    mask: 0,
+                    function: 0b0100
+                },
+                Comparator {
+                    comp: 0x2000_001f,
+                    mask: 0,
+                    function: MATCH_ADDRESS_LIMIT | ACTION_DEBUG_EVENT
+                }
+            ]
+        );
+
+        let config = WatchpointConfig::new(0x2000_0002, 2, WatchpointKind::Write);
+        assert_eq!(
+            armv8m_comparators(&config, 0x2000_0002, None),
+            [Comparator {
+                comp: 0x2000_0002,
+                mask: 0,
+                function: 0b0101 | ACTION_DEBUG_EVENT | 0b01 << 10
+            }]
+        );
+    }
+
+    #[test]
+    fn unsupported_qualifiers_are_named() {
+        let sized = WatchpointConfig::new(0x2000_0000, 4, WatchpointKind::Write).access_size(1);
+        assert!(matches!(
+            check_qualifiers(DwtVersion::Armv7m, &sized),
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::AccessSize,
+                ..
+            })
+        ));
+
+        let value = WatchpointConfig::new(0x2000_0000, 4, WatchpointKind::Write).value(0, 0xff);
+        assert!(matches!(
+            check_qualifiers(DwtVersion::Armv7m, &value),
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::ValueMask,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_qualifiers(DwtVersion::Armv6m, &value),
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::Value,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,553 @@
+//! Decoding of the exception frames which Cortex-M cores stack on exception entry.
+//!
+//! On exception entry, the core pushes R0-R3, R12, LR, the return address and xPSR to the
+//! stack which was active, followed by S0-S15 and FPSCR if the floating-point context was
+//! active. LR is set to an EXC_RETURN value, which describes the stack and the format of the
+//! frame, so it is used to find the frame while the core is halted at the start of the handler,
+//! e.g. after a vector catch.
+//!
+//! The frame is also used to read the floating-point state of the interrupted context, see
+//! [`Core::read_all_fpu_state`].
+
+use bitfield::bitfield;
+use std::fmt;
+
+use crate::{
+    Core, CoreType, Error, FpuState, FpuValue, FpuValueSource, MemoryInterface, RegisterId,
+    RegisterValue,
+};
+
+use super::register;
+
+/// CFSR: The configurable fault status register, with the UsageFault status in bits 31:16.
+const CFSR: u64 = 0xE000_ED28;
+
+/// UFSR.STKOF: A stack limit violation caused the UsageFault, ARMv8-M only.
+const CFSR_STKOF: u32 = 1 << 20;
+
+/// FPCCR: The floating-point context control register.
+const FPCCR: u64 = 0xE000_EF34;
+
+/// FPCCR.LSPACT: Lazy state preservation is active, the space for the floating-point
+/// registers is reserved in the frame, but they were not written yet.
+const FPCCR_LSPACT: u32 = 1;
+
+/// FPCAR: The address of the space for the floating-point registers in the frame.
+const FPCAR: u64 = 0xE000_EF38;
+
+/// The DCRSR selectors of MSPLIM_S, PSPLIM_S, MSPLIM_NS and PSPLIM_NS.
+const MSPLIM_S: RegisterId = RegisterId(0x1c);
+const PSPLIM_S: RegisterId = RegisterId(0x1d);
+const MSPLIM_NS: RegisterId = RegisterId(0x1e);
+const PSPLIM_NS: RegisterId = RegisterId(0x1f);
+
+/// The DCRSR selector of FPSCR, and of S0.
+const FPSCR: RegisterId = RegisterId(0x21);
+const S0: u16 = 0x40;
+
+/// The size of the basic frame, R0-R3, R12, LR, the return address and xPSR.
+const BASIC_FRAME_WORDS: usize = 8;
+
+/// The size of the extended frame, the basic frame followed by S0-S15, FPSCR and a reserved word.
+const EXTENDED_FRAME_WORDS: usize = BASIC_FRAME_WORDS + 18;
+
+/// The size of the additional state context, which is stacked below the frame when a
+/// Non-secure exception interrupts Secure code on ARMv8-M: the integrity signature, a reserved
+/// word and R4-R11.
+const ADDITIONAL_STATE_CONTEXT: u64 = 10 * 4;
+
+/// xPSR bit 9: The stack was aligned to 8 bytes by inserting a padding word above the frame.
+const XPSR_STACK_ALIGN: u32 = 1 << 9;
+
+/// The names of the registers of the basic frame, in the order they are stacked.
+const BASIC_FRAME_NAMES: [&str; BASIC_FRAME_WORDS] =
+    ["R0", "R1", "R2", "R3", "R12", "LR", "PC", "xPSR"];
+
+bitfield! {
+    /// An EXC_RETURN value, as stored in LR on exception entry (see armv8-M Architecture
+    /// Reference Manual D1.2.95).
+    ///
+    /// On ARMv6-M and ARMv7-M, only bits 4:0 are defined, all other bits are set.
+    #[derive(Copy, Clone, PartialEq, Eq)]
+    pub struct ExcReturn(u32);
+    impl Debug;
+    /// Bits 31:24, which are `0xff` in all EXC_RETURN values.
+    pub u8, prefix, _: 31, 24;
+    /// The frame was stacked to a Secure stack, ARMv8-M only.
+    pub s, _: 6;
+    /// The default rules for stacking the callee-saved registers were followed, i.e. the
+    /// additional state context was not stacked, ARMv8-M only.
+    pub dcrs, _: 5;
+    /// The frame is a basic frame without the floating-point registers.
+    pub ftype, _: 4;
+    /// The exception returns to Thread mode, not to another exception handler.
+    pub mode, _: 3;
+    /// The frame was stacked to the process stack, not to the main stack.
+    pub spsel, _: 2;
+    /// The exception was taken to the Secure state, ARMv8-M only.
+    pub es, _: 0;
+}
+
+impl ExcReturn {
+    /// Returns `true` if the value is an EXC_RETURN value, and not a return address.
+    pub fn is_valid(&self) -> bool {
+        self.prefix() == 0xff
+    }
+}
+
+impl From<u32> for ExcReturn {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<ExcReturn> for u32 {
+    fn from(value: ExcReturn) -> Self {
+        value.0
+    }
+}
+
+/// The stack an exception frame was stacked to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameStack {
+    /// The main stack, MSP.
+    Main,
+    /// The process stack, PSP.
+    Process,
+}
+
+impl fmt::Display for FrameStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameStack::Main => write!(f, "main stack"),
+            FrameStack::Process => write!(f, "process stack"),
+        }
+    }
+}
+
+/// The floating-point registers of an extended exception frame.
+///
+/// Like the registers of the [`ExceptionFrame`], each value is `None` if it could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FpFrame {
+    /// S0-S15.
+    pub s: [Option<u32>; 16],
+    /// FPSCR.
+    pub fpscr: Option<u32>,
+    /// Lazy state preservation was active, so the space for the registers was reserved in
+    /// the frame, but they were not stacked yet. The values were read from the registers of
+    /// the FPU instead, which still hold the values of the interrupted context.
+    pub lazy: bool,
+}
+
+/// A stack limit violation on exception entry, which only ARMv8-M cores detect.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackLimitViolation {
+    /// The stack limit of the stack of the frame, from MSPLIM or PSPLIM.
+    pub limit: u32,
+}
+
+/// An exception frame, as stacked by a Cortex-M core on exception entry, see
+/// [`Core::exception_frame`].
+///
+/// Each stacked register is `None` if the memory of the stack could not be read, e.g. because
+/// the stack pointer was corrupted, see [`ExceptionFrame::missing_registers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExceptionFrame {
+    /// The number of the active exception, from IPSR.
+    pub exception: u32,
+    /// The EXC_RETURN value, which describes the frame.
+    pub exc_return: ExcReturn,
+    /// The stack the frame was stacked to.
+    pub stack: FrameStack,
+    /// The address of the basic frame, i.e. of the stacked R0.
+    pub address: u64,
+    /// The stacked R0.
+    pub r0: Option<u32>,
+    /// The stacked R1.
+    pub r1: Option<u32>,
+    /// The stacked R2.
+    pub r2: Option<u32>,
+    /// The stacked R3.
+    pub r3: Option<u32>,
+    /// The stacked R12.
+    pub r12: Option<u32>,
+    /// The stacked LR of the interrupted context.
+    pub lr: Option<u32>,
+    /// The return address, i.e. the PC of the interrupted context.
+    pub pc: Option<u32>,
+    /// The stacked xPSR.
+    pub xpsr: Option<u32>,
+    /// The floating-point registers, if the frame is an extended frame.
+    pub fp: Option<FpFrame>,
+    /// The stack limit violation which occurred while the frame was stacked.
+    ///
+    /// If this is set, the frame was not stacked completely, and its values are not reliable.
+    pub stack_limit_violation: Option<StackLimitViolation>,
+}
+
+impl ExceptionFrame {
+    /// Returns `true` if the exception interrupted another exception handler.
+    ///
+    /// The frame of the interrupted handler is on the main stack at
+    /// [`context_stack_pointer`](Self::context_stack_pointer). The stacked LR is the LR of the
+    /// interrupted handler, which is its EXC_RETURN value unless the handler changed LR.
+    pub fn is_nested(&self) -> bool {
+        !self.exc_return.mode()
+    }
+
+    /// The stack pointer of the interrupted context, i.e. the address above the frame,
+    /// including the padding word for alignment.
+    ///
+    /// This is `None` if the stacked xPSR could not be read, as it records the alignment.
+    pub fn context_stack_pointer(&self) -> Option<u64> {
+        let words = if self.fp.is_some() {
+            EXTENDED_FRAME_WORDS
+        } else {
+            BASIC_FRAME_WORDS
+        };
+        let end = self.address + words as u64 * 4;
+
+        self.xpsr.map(|xpsr| {
+            if xpsr & XPSR_STACK_ALIGN != 0 {
+                end + 4
+            } else {
+                end
+            }
+        })
+    }
+
+    /// The names of the stacked registers which could not be read.
+    pub fn missing_registers(&self) -> Vec<&'static str> {
+        let mut missing = BASIC_FRAME_NAMES
+            .iter()
+            .zip(self.basic_frame())
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+
+        if let Some(fp) = &self.fp {
+            const S_NAMES: [&str; 16] = [
+                "S0", "S1", "S2", "S3", "S4", "S5", "S6", "S7", "S8", "S9", "S10", "S11", "S12",
+                "S13", "S14", "S15",
+            ];
+
+            missing.extend(
+                S_NAMES
+                    .iter()
+                    .zip(fp.s)
+                    .filter(|(_, value)| value.is_none())
+                    .map(|(name, _)| *name),
+            );
+
+            if fp.fpscr.is_none() {
+                missing.push("FPSCR");
+            }
+        }
+
+        missing
+    }
+
+    /// Returns `true` if all stacked registers could be read.
+    pub fn is_complete(&self) -> bool {
+        self.missing_registers().is_empty()
+    }
+
+    fn basic_frame(&self) -> [Option<u32>; BASIC_FRAME_WORDS] {
+        [
+            self.r0, self.r1, self.r2, self.r3, self.r12, self.lr, self.pc, self.xpsr,
+        ]
+    }
+}
+
+/// Read the words at `address`, in one block if possible, or one by one, so that the words
+/// which can be read are returned if a part of the memory is inaccessible.
+fn read_words(core: &mut Core, address: u64, words: &mut [Option<u32>]) {
+    let mut block = vec![0; words.len()];
+
+    match core.read_32(address, &mut block) {
+        Ok(()) => {
+            for (word, value) in words.iter_mut().zip(block) {
+                *word = Some(value);
+            }
+        }
+        Err(error) => {
+            log::debug!(
+                "Failed to read the exception frame at {:#010x}: {}",
+                address,
+                error
+            );
+
+            for (index, word) in words.iter_mut().enumerate() {
+                *word = address
+                    .checked_add(index as u64 * 4)
+                    .and_then(|address| core.read_word_32(address).ok());
+            }
+        }
+    }
+}
+
+/// Read and decode the frame of the active exception, with the EXC_RETURN value in LR.
+pub(crate) fn exception_frame(core: &mut Core) -> Result<ExceptionFrame, Error> {
+    if !core.core_type().is_cortex_m() {
+        return Err(Error::ArchitectureRequired(&[
+            "ARMv6-M", "ARMv7-M", "ARMv8-M",
+        ]));
+    }
+
+    let lr: u32 = core.read_core_reg(register::LR.id)?;
+    let exc_return = ExcReturn(lr);
+
+    if !exc_return.is_valid() {
+        return Err(Error::NoExceptionReturn(lr));
+    }
+
+    let xpsr: u32 = core.read_core_reg(register::XPSR.id)?;
+
+    let stack = if exc_return.spsel() {
+        FrameStack::Process
+    } else {
+        FrameStack::Main
+    };
+    let stack_pointer: u32 = match stack {
+        FrameStack::Main => core.read_core_reg(register::MSP.id)?,
+        FrameStack::Process => core.read_core_reg(register::PSP.id)?,
+    };
+
+    let armv8m = core.core_type() == CoreType::Armv8m;
+
+    let mut address = stack_pointer as u64;
+    if armv8m && exc_return.s() && !exc_return.dcrs() {
+        address += ADDITIONAL_STATE_CONTEXT;
+    }
+
+    // ARMv6-M has no FPU, and its EXC_RETURN values always have FType set.
+    let extended = !exc_return.ftype() && core.core_type() != CoreType::Armv6m;
+
+    let mut words = [None; EXTENDED_FRAME_WORDS];
+    let len = if extended {
+        EXTENDED_FRAME_WORDS
+    } else {
+        BASIC_FRAME_WORDS
+    };
+    read_words(core, address, &mut words[..len]);
+
+    let fp = if extended {
+        let fp_address = address + BASIC_FRAME_WORDS as u64 * 4;
+        let lazy = core.read_word_32(FPCCR)? & FPCCR_LSPACT != 0
+            && core.read_word_32(FPCAR)? as u64 & !0b111 == fp_address;
+
+        let mut s = [None; 16];
+        let fpscr;
+
+        if lazy {
+            for (index, value) in s.iter_mut().enumerate() {
+                *value = Some(core.read_core_reg(RegisterId(S0 + index as u16))?);
+            }
+            fpscr = Some(core.read_core_reg(FPSCR)?);
+        } else {
+            s.copy_from_slice(&words[BASIC_FRAME_WORDS..BASIC_FRAME_WORDS + 16]);
+            fpscr = words[BASIC_FRAME_WORDS + 16];
+        }
+
+        Some(FpFrame { s, fpscr, lazy })
+    } else {
+        None
+    };
+
+    let stack_limit_violation = if armv8m {
+        let limit_register = match (stack, exc_return.s()) {
+            (FrameStack::Main, true) => MSPLIM_S,
+            (FrameStack::Process, true) => PSPLIM_S,
+            (FrameStack::Main, false) => MSPLIM_NS,
+            (FrameStack::Process, false) => PSPLIM_NS,
+        };
+        let limit: u32 = core.read_core_reg(limit_register)?;
+        let stkof = core.read_word_32(CFSR)? & CFSR_STKOF != 0;
+
+        // A violation stops the stacking at the limit.
+        if (stkof && stack_pointer <= limit) || stack_pointer < limit {
+            Some(StackLimitViolation { limit })
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    Ok(ExceptionFrame {
+        exception: xpsr & 0x1ff,
+        exc_return,
+        stack,
+        address,
+        r0: words[0],
+        r1: words[1],
+        r2: words[2],
+        r3: words[3],
+        r12: words[4],
+        lr: words[5],
+        pc: words[6],
+        xpsr: words[7],
+        fp,
+        stack_limit_violation,
+    })
+}
+
+/// Read the floating-point state, with the values of S0-S15 and FPSCR of the interrupted
+/// context if the core is halted in an exception handler with an extended frame.
+pub(crate) fn read_fpu_state(core: &mut Core) -> Result<FpuState, Error> {
+    if !core.fpu_support()? {
+        return Err(Error::FpuNotPresent);
+    }
+
+    let ids: Vec<RegisterId> = std::iter::once(FPSCR)
+        .chain((0..32).map(|index| RegisterId(S0 + index)))
+        .collect();
+    let values = core.read_core_regs(&ids)?;
+
+    // The sources of FPSCR and S0-S15, and the values to replace theirs with.
+    let mut stacked: Vec<(FpuValueSource, Option<u32>)> = Vec::new();
+
+    if core.read_word_32(FPCCR)? & FPCCR_LSPACT != 0 {
+        let address = (core.read_word_32(FPCAR)? & !0b111) as u64;
+
+        stacked.push((
+            FpuValueSource::PendingLazyState {
+                address: address + 16 * 4,
+            },
+            None,
+        ));
+        for index in 0..16 {
+            stacked.push((
+                FpuValueSource::PendingLazyState {
+                    address: address + index * 4,
+                },
+                None,
+            ));
+        }
+    } else {
+        let frame = match exception_frame(core) {
+            Ok(frame) => Some(frame),
+            // The core isn't at the start of an exception handler, the registers are read.
+            Err(Error::NoExceptionReturn(_)) => None,
+            Err(error) => return Err(error),
+        };
+
+        if let Some(frame) = frame {
+            if let Some(fp) = frame.fp {
+                let address = frame.address + BASIC_FRAME_WORDS as u64 * 4;
+
+                stacked.push((
+                    FpuValueSource::ExceptionFrame {
+                        address: address + 16 * 4,
+                    },
+                    fp.fpscr,
+                ));
+                for (index, value) in fp.s.iter().enumerate() {
+                    stacked.push((
+                        FpuValueSource::ExceptionFrame {
+                            address: address + index as u64 * 4,
+                        },
+                        *value,
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut state = ids
+        .iter()
+        .zip(values)
+        .enumerate()
+        .map(|(index, (id, value))| match stacked.get(index) {
+            Some((source @ FpuValueSource::PendingLazyState { .. }, _)) => {
+                FpuValue::new(*id, value, *source)
+            }
+            Some((source, Some(stacked))) => {
+                FpuValue::new(*id, RegisterValue::U32(*stacked), *source)
+            }
+            // The stacked value could not be read, so the register is the best there is.
+            _ => FpuValue::new(*id, value, FpuValueSource::Register),
+        });
+
+    let status = state.next().expect("FPSCR is always read");
+
+    Ok(FpuState {
+        status,
+        registers: state.collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(exc_return: u32, xpsr: Option<u32>, fp: bool) -> ExceptionFrame {
+        ExceptionFrame {
+            exception: 3,
+            exc_return: ExcReturn(exc_return),
+            stack: FrameStack::Process,
+            address: 0x2000_0fc0,
+            r0: Some(0),
+            r1: Some(1),
+            r2: None,
+            r3: Some(3),
+            r12: Some(12),
+            lr: Some(0x0800_0123),
+            pc: Some(0x0800_0456),
+            xpsr,
+            fp: fp.then(|| FpFrame {
+                s: [Some(0); 16],
+                fpscr: None,
+                lazy: false,
+            }),
+            stack_limit_violation: None,
+        }
+    }
+
+    #[test]
+    fn exc_return_fields() {
+        // Thread mode, process stack, basic frame.
+        let thread = ExcReturn(0xffff_fffd);
+        assert!(thread.is_valid());
+        assert!(thread.mode());
+        assert!(thread.spsel());
+        assert!(thread.ftype());
+
+        // Handler mode, main stack, extended frame.
+        let handler = ExcReturn(0xffff_ffe1);
+        assert!(!handler.mode());
+        assert!(!handler.spsel());
+        assert!(!handler.ftype());
+
+        // A return address is not an EXC_RETURN value.
+        assert!(!ExcReturn(0x0800_0123).is_valid());
+    }
+
+    #[test]
+    fn context_stack_pointer_includes_alignment() {
+        let aligned = frame(0xffff_fffd, Some(0x0100_0000), false);
+        assert_eq!(aligned.context_stack_pointer(), Some(0x2000_0fe0));
+        assert!(!aligned.is_nested());
+
+        let padded = frame(0xffff_fffd, Some(0x0100_0200), false);
+        assert_eq!(padded.context_stack_pointer(), Some(0x2000_0fe4));
+
+        let extended = frame(0xffff_ffe1, Some(0x0100_0000), true);
+        assert_eq!(extended.context_stack_pointer(), Some(0x2000_1028));
+        assert!(extended.is_nested());
+
+        assert_eq!(
+            frame(0xffff_fffd, None, false).context_stack_pointer(),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_registers_are_named() {
+        let frame = frame(0xffff_ffed, None, true);
+
+        assert_eq!(frame.missing_registers(), vec!["R2", "xPSR", "FPSCR"]);
+        assert!(!frame.is_complete());
+    }
+}
@@ -0,0 +1,422 @@
+//! Counting how often instructions are executed, using the DWT comparators of Cortex-M cores.
+//!
+//! A DWT comparator is set up to match the address of an instruction. Depending on the
+//! capabilities of the DWT, one of two modes is used:
+//!
+//! - [`HitCountMode::Sampled`]: the comparator only flags a match in its `MATCHED` bit,
+//!   without affecting the core. The flag is polled, and cleared by reading it. Several
+//!   hits between two polls are only counted once, so the count is a lower bound.
+//! - [`HitCountMode::Intrusive`]: the comparator halts the core on a match. The hit is
+//!   counted, the instruction is stepped over and the core is resumed. The count is exact,
+//!   but the target is slowed down considerably by every hit.
+//!
+//! If more addresses are requested than comparators exist, the addresses are split into
+//! groups, which are monitored in turn, see [`HitCountReport`].
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use super::armv7m::Demcr;
+use crate::{Core, CoreStatus, CoreType, Error, HaltReason, MemoryInterface, MemoryMappedRegister};
+
+/// The base address of the DWT, which is the same on all Cortex-M cores.
+pub(super) const DWT_BASE: u64 = 0xE000_1000;
+
+/// The longest time a group of addresses is monitored before switching to the next group.
+const MAX_SLOT: Duration = Duration::from_millis(100);
+
+/// DWT_CTRL: Trace sampling and exception tracing are not supported.
+const CTRL_NOTRCPKT: u32 = 1 << 27;
+
+/// DWT_CTRL: No external match signals (CMPMATCH) are supported.
+const CTRL_NOEXTTRIG: u32 = 1 << 26;
+
+/// DWT_FUNCTION: The comparator matched since the register was last read.
+pub(super) const FUNCTION_MATCHED: u32 = 1 << 24;
+
+/// How the hits of an address were counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitCountMode {
+    /// The match flag of the comparator was polled without affecting the core.
+    ///
+    /// Several hits between two polls are counted once, so the count is a lower bound.
+    Sampled,
+    /// The core was halted on every hit, and resumed after counting it.
+    ///
+    /// The count is exact, but the timing of the target is affected.
+    Intrusive,
+}
+
+/// The number of times an address was executed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressHits {
+    /// The address of the instruction.
+    pub address: u64,
+    /// The number of hits which were counted.
+    pub hits: u64,
+    /// How the hits were counted.
+    pub mode: HitCountMode,
+    /// The time during which the address was monitored.
+    pub observed: Duration,
+    /// The number of times the match flag was polled, in [`HitCountMode::Sampled`] mode.
+    pub samples: u64,
+}
+
+impl AddressHits {
+    /// Extrapolate the number of hits to `duration`.
+    ///
+    /// This assumes that the hits are evenly distributed over time, which is only a rough
+    /// estimate if the address was not monitored during the whole duration.
+    pub fn estimated_hits(&self, duration: Duration) -> f64 {
+        if self.observed.is_zero() {
+            return 0.0;
+        }
+
+        self.hits as f64 * duration.as_secs_f64() / self.observed.as_secs_f64()
+    }
+}
+
+/// The result of counting the hits of several addresses.
+///
+/// If there are more addresses than comparators, the addresses are split into groups of
+/// at most [`comparators`](HitCountReport::comparators) addresses. The groups are monitored
+/// in turn, each for at most [`slot`](HitCountReport::slot), until the requested duration
+/// has passed. Each address is then only observed for a fraction of the duration, which is
+/// reported in [`AddressHits::observed`], and the counts are a statistical sample.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HitCountReport {
+    /// The hits of each address, in the order in which the addresses were requested.
+    pub addresses: Vec<AddressHits>,
+    /// The total time spent counting.
+    pub duration: Duration,
+    /// The number of DWT comparators which were used.
+    pub comparators: usize,
+    /// The number of groups the addresses were split into.
+    pub groups: usize,
+    /// The time each group was monitored before switching to the next one.
+    pub slot: Duration,
+    /// True if counting was stopped early, because the core halted for another reason.
+    pub interrupted: bool,
+}
+
+pub(super) fn comp_address(unit: usize) -> u64 {
+    DWT_BASE + 0x20 + 0x10 * unit as u64
+}
+
+pub(super) fn mask_address(unit: usize) -> u64 {
+    DWT_BASE + 0x24 + 0x10 * unit as u64
+}
+
+pub(super) fn function_address(unit: usize) -> u64 {
+    DWT_BASE + 0x28 + 0x10 * unit as u64
+}
+
+/// Returns the value of the DWT_FUNCTION register for an instruction address match in `mode`,
+/// or `None` if the mode is not supported by the DWT.
+fn function_value(core_type: CoreType, ctrl: u32, mode: HitCountMode) -> Option<u32> {
+    match (core_type, mode) {
+        // ARMv8-M: MATCH = instruction address, DATAVSIZE = halfword, ACTION = trigger only / debug event.
+        (CoreType::Armv8m, HitCountMode::Sampled) => Some(0b01 << 10 | 0b0010),
+        (CoreType::Armv8m, HitCountMode::Intrusive) => Some(0b01 << 10 | 0b01 << 4 | 0b0010),
+        // ARMv7-M: emit a PC sample packet, or a CMPMATCH event, either of which sets MATCHED.
+        (CoreType::Armv7m | CoreType::Armv7em, HitCountMode::Sampled) => {
+            if ctrl & CTRL_NOTRCPKT == 0 {
+                Some(0b0001)
+            } else if ctrl & CTRL_NOEXTTRIG == 0 {
+                Some(0b1000)
+            } else {
+                None
+            }
+        }
+        // ARMv6-M only supports watchpoints, which halt the core.
+        (CoreType::Armv6m, HitCountMode::Sampled) => None,
+        (_, HitCountMode::Intrusive) => Some(0b0100),
+        (_, HitCountMode::Sampled) => None,
+    }
+}
+
+/// Split `count` addresses into groups of at most `comparators` addresses.
+fn schedule(count: usize, comparators: usize) -> Vec<Range<usize>> {
+    (0..count)
+        .step_by(comparators)
+        .map(|start| start..(start + comparators).min(count))
+        .collect()
+}
+
+/// Returns how long each of `groups` groups is monitored at once.
+fn slot_duration(duration: Duration, groups: usize) -> Duration {
+    if groups <= 1 {
+        duration
+    } else {
+        (duration / groups as u32).min(MAX_SLOT)
+    }
+}
+
+/// The DWT state which is modified while counting, so that it can be restored afterwards.
+struct SavedDwtState {
+    demcr: u32,
+    comparators: Vec<[u32; 3]>,
+}
+
+impl SavedDwtState {
+    fn save(core: &mut Core, demcr: u32, comparators: usize) -> Result<Self, Error> {
+        let comparators = (0..comparators)
+            .map(|unit| {
+                Ok([
+                    core.read_word_32(comp_address(unit))?,
+                    core.read_word_32(mask_address(unit))?,
+                    core.read_word_32(function_address(unit))?,
+                ])
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(Self { demcr, comparators })
+    }
+
+    fn restore(&self, core: &mut Core) -> Result<(), Error> {
+        for (unit, [comp, mask, function]) in self.comparators.iter().enumerate() {
+            core.write_word_32(function_address(unit), 0)?;
+            core.write_word_32(comp_address(unit), *comp)?;
+            core.write_word_32(mask_address(unit), *mask)?;
+            core.write_word_32(function_address(unit), *function & !FUNCTION_MATCHED)?;
+        }
+
+        core.write_word_32(Demcr::ADDRESS, self.demcr)
+    }
+}
+
+/// Count how often each of `addresses` is executed during `duration`.
+pub(crate) fn count_address_hits(
+    core: &mut Core,
+    addresses: &[u64],
+    duration: Duration,
+) -> Result<HitCountReport, Error> {
+    let core_type = core.core_type();
+
+    if !core_type.is_cortex_m() {
+        return Err(Error::ArchitectureRequired(&[
+            "ARMv6-M", "ARMv7-M", "ARMv8-M",
+        ]));
+    }
+
+    // Make sure the DWT is enabled, otherwise its registers can't be accessed.
+    let original_demcr = core.read_word_32(Demcr::ADDRESS)?;
+    let mut demcr = Demcr(original_demcr);
+    demcr.set_trcena(true);
+    core.write_word_32(Demcr::ADDRESS, demcr.into())?;
+
+    let ctrl = core.read_word_32(DWT_BASE)?;
+    let comparators = (ctrl >> 28) as usize;
+
+    if comparators == 0 {
+        core.write_word_32(Demcr::ADDRESS, original_demcr)?;
+
+        return Err(Error::NoDwtComparators);
+    }
+
+    let saved = SavedDwtState::save(core, original_demcr, comparators)?;
+
+    let result = monitor(core, core_type, ctrl, comparators, addresses, duration);
+
+    let restored = saved.restore(core);
+
+    let report = result?;
+    restored?;
+
+    Ok(report)
+}
+
+fn monitor(
+    core: &mut Core,
+    core_type: CoreType,
+    ctrl: u32,
+    comparators: usize,
+    addresses: &[u64],
+    duration: Duration,
+) -> Result<HitCountReport, Error> {
+    let mode = if function_value(core_type, ctrl, HitCountMode::Sampled).is_some() {
+        HitCountMode::Sampled
+    } else {
+        HitCountMode::Intrusive
+    };
+
+    // Unwrapping is fine, intrusive mode is supported by all Cortex-M cores.
+    let function = function_value(core_type, ctrl, mode).unwrap();
+
+    log::debug!(
+        "Counting hits of {} addresses in {:?} mode, using {} comparators",
+        addresses.len(),
+        mode,
+        comparators
+    );
+
+    let groups = schedule(addresses.len(), comparators);
+    let slot = slot_duration(duration, groups.len());
+
+    let mut hits = addresses
+        .iter()
+        .map(|&address| AddressHits {
+            address,
+            hits: 0,
+            mode,
+            observed: Duration::ZERO,
+            samples: 0,
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let mut interrupted = false;
+
+    'rounds: while start.elapsed() < duration {
+        for group in &groups {
+            let remaining = duration.saturating_sub(start.elapsed());
+
+            if remaining.is_zero() {
+                break 'rounds;
+            }
+
+            for (unit, index) in group.clone().enumerate() {
+                core.write_word_32(function_address(unit), 0)?;
+                core.write_word_32(comp_address(unit), addresses[index] as u32)?;
+                if core_type != CoreType::Armv8m {
+                    core.write_word_32(mask_address(unit), 0)?;
+                }
+
+                // Reading the function register clears a stale MATCHED flag.
+                core.write_word_32(function_address(unit), function)?;
+                core.read_word_32(function_address(unit))?;
+            }
+
+            let slot_start = Instant::now();
+            let slot = slot.min(remaining);
+
+            while slot_start.elapsed() < slot && !interrupted {
+                match mode {
+                    HitCountMode::Sampled => {
+                        for (unit, index) in group.clone().enumerate() {
+                            let value = core.read_word_32(function_address(unit))?;
+
+                            hits[index].samples += 1;
+
+                            if value & FUNCTION_MATCHED != 0 {
+                                hits[index].hits += 1;
+                            }
+                        }
+                    }
+                    HitCountMode::Intrusive => match core.status()? {
+                        CoreStatus::Halted(HaltReason::Watchpoint) => {
+                            for (unit, index) in group.clone().enumerate() {
+                                if core.read_word_32(function_address(unit))? & FUNCTION_MATCHED
+                                    != 0
+                                {
+                                    hits[index].hits += 1;
+                                }
+
+                                core.write_word_32(function_address(unit), 0)?;
+                            }
+
+                            // Step over the instruction, so that it doesn't match again.
+                            core.step()?;
+
+                            for unit in 0..group.len() {
+                                core.write_word_32(function_address(unit), function)?;
+                            }
+
+                            core.run()?;
+                        }
+                        CoreStatus::Halted(reason) => {
+                            log::warn!(
+                                "The core halted ({:?}), stopping to count address hits",
+                                reason
+                            );
+                            interrupted = true;
+                        }
+                        _ => (),
+                    },
+                }
+            }
+
+            let observed = slot_start.elapsed();
+
+            for index in group.clone() {
+                hits[index].observed += observed;
+            }
+
+            for unit in 0..group.len() {
+                core.write_word_32(function_address(unit), 0)?;
+            }
+
+            if interrupted {
+                break 'rounds;
+            }
+        }
+    }
+
+    Ok(HitCountReport {
+        addresses: hits,
+        duration: start.elapsed(),
+        comparators: comparators.min(addresses.len()),
+        groups: groups.len(),
+        slot,
+        interrupted,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn addresses_are_grouped_by_comparators() {
+        assert_eq!(schedule(3, 4), vec![0..3]);
+        assert_eq!(schedule(10, 4), vec![0..4, 4..8, 8..10]);
+        assert_eq!(schedule(0, 4), vec![]);
+    }
+
+    #[test]
+    fn multiplexed_slots_are_limited() {
+        let duration = Duration::from_secs(2);
+
+        assert_eq!(slot_duration(duration, 1), duration);
+        assert_eq!(slot_duration(duration, 2), MAX_SLOT);
+        assert_eq!(
+            slot_duration(Duration::from_millis(90), 3),
+            Duration::from_millis(30)
+        );
+    }
+
+    #[test]
+    fn mode_depends_on_dwt_capabilities() {
+        let no_trace = CTRL_NOTRCPKT | CTRL_NOEXTTRIG;
+
+        assert_eq!(
+            function_value(CoreType::Armv7em, 0, HitCountMode::Sampled),
+            Some(0b0001)
+        );
+        assert_eq!(
+            function_value(CoreType::Armv7m, no_trace, HitCountMode::Sampled),
+            None
+        );
+        assert_eq!(
+            function_value(CoreType::Armv6m, 0, HitCountMode::Sampled),
+            None
+        );
+        assert_eq!(
+            function_value(CoreType::Armv6m, 0, HitCountMode::Intrusive),
+            Some(0b0100)
+        );
+    }
+
+    #[test]
+    fn partial_observation_is_extrapolated() {
+        let hits = AddressHits {
+            address: 0x1000,
+            hits: 10,
+            mode: HitCountMode::Sampled,
+            observed: Duration::from_millis(250),
+            samples: 100,
+        };
+
+        assert_eq!(hits.estimated_hits(Duration::from_secs(1)), 40.0);
+    }
+}
@@ -17,8 +17,14 @@ pub mod armv8m;
 pub(crate) mod armv7a_debug_regs;
 pub(crate) mod armv8a_core_regs;
 pub(crate) mod armv8a_debug_regs;
+pub(crate) mod cache;
 pub(crate) mod cortex_m;
+pub(crate) mod dwt_watchpoint;
+pub(crate) mod exception_frame;
+pub(crate) mod hit_count;
 pub(crate) mod instructions;
+pub(crate) mod mpu;
+pub(crate) mod scb;
 
 /// Core information data which is downloaded from the target, represents its state and can be used for debugging.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +58,7 @@ pub(crate) mod register {
         id: RegisterId(15),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: 0xffff_fffe,
     };
 
     pub const XPSR: RegisterDescription = RegisterDescription {
@@ -60,6 +67,7 @@ pub(crate) mod register {
         id: RegisterId(0b1_0000),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: 0xffff_fe00,
     };
 
     pub const SP: RegisterDescription = RegisterDescription {
@@ -68,6 +76,7 @@ pub(crate) mod register {
         id: RegisterId(13),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: u64::MAX,
     };
 
     pub const LR: RegisterDescription = RegisterDescription {
@@ -76,6 +85,7 @@ pub(crate) mod register {
         id: RegisterId(14),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: u64::MAX,
     };
 
     pub const MSP: RegisterDescription = RegisterDescription {
@@ -84,6 +94,7 @@ pub(crate) mod register {
         id: RegisterId(0b10001),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: u64::MAX,
     };
 
     pub const PSP: RegisterDescription = RegisterDescription {
@@ -92,6 +103,7 @@ pub(crate) mod register {
         id: RegisterId(0b10010),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: u64::MAX,
     };
 
     // CONTROL bits [31:24], FAULTMASK bits [23:16],
@@ -102,6 +114,7 @@ pub(crate) mod register {
         id: RegisterId(0b10100),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: 0x0701_ff01,
     };
 
     pub const FP: RegisterDescription = RegisterDescription {
@@ -110,6 +123,7 @@ pub(crate) mod register {
         id: RegisterId(7),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: u64::MAX,
     };
 
     pub const FPSCR: RegisterDescription = RegisterDescription {
@@ -118,6 +132,7 @@ pub(crate) mod register {
         id: RegisterId(33),
         _type: RegisterDataType::UnsignedInteger,
         size_in_bits: 32,
+        writable_mask: 0xf7c0_009f,
     };
 }
 
@@ -129,6 +144,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R1",
@@ -136,6 +152,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R2",
@@ -143,6 +160,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(2),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R3",
@@ -150,6 +168,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(3),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R4",
@@ -157,6 +176,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(4),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R5",
@@ -164,6 +184,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(5),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R6",
@@ -171,6 +192,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(6),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R7",
@@ -178,6 +200,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(7),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R8",
@@ -185,6 +208,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(8),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R9",
@@ -192,6 +216,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(9),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R10",
@@ -199,6 +224,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(10),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R11",
@@ -206,6 +232,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(11),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R12",
@@ -213,6 +240,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(12),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R13",
@@ -220,6 +248,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(13),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R14",
@@ -227,6 +256,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(14),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "R15",
@@ -234,6 +264,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(15),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: 0xffff_fffe,
         },
     ],
 
@@ -249,6 +280,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a2",
@@ -256,6 +288,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a3",
@@ -263,6 +296,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(2),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a4",
@@ -270,6 +304,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(3),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -280,6 +315,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(0),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a2",
@@ -287,6 +323,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(1),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -303,6 +340,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(64),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S1",
@@ -310,6 +348,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(65),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S2",
@@ -317,6 +356,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(66),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S3",
@@ -324,6 +364,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(67),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S4",
@@ -331,6 +372,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(68),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S5",
@@ -338,6 +380,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(69),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S6",
@@ -345,6 +388,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(70),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S7",
@@ -352,6 +396,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(71),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S8",
@@ -359,6 +404,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(72),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S9",
@@ -366,6 +412,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(73),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S10",
@@ -373,6 +420,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(74),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S11",
@@ -380,6 +428,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(75),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S12",
@@ -387,6 +436,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(76),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S13",
@@ -394,6 +444,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(77),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S14",
@@ -401,6 +452,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(78),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S15",
@@ -408,6 +460,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(79),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S16",
@@ -415,6 +468,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(80),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S17",
@@ -422,6 +476,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(81),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S18",
@@ -429,6 +484,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(82),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S19",
@@ -436,6 +492,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(83),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S20",
@@ -443,6 +500,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(84),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S21",
@@ -450,6 +508,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(85),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S22",
@@ -457,6 +516,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(86),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S23",
@@ -464,6 +524,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(87),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S24",
@@ -471,6 +532,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(88),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S25",
@@ -478,6 +540,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(89),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S26",
@@ -485,6 +548,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(90),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S27",
@@ -492,6 +556,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(91),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S28",
@@ -499,6 +564,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(92),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S29",
@@ -506,6 +572,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(93),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S30",
@@ -513,6 +580,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(94),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "S31",
@@ -520,6 +588,7 @@ static ARM_REGISTER_FILE: RegisterFile = RegisterFile {
             id: RegisterId(95),
             _type: RegisterDataType::FloatingPoint,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ]),
 };
@@ -603,6 +672,18 @@ pub struct CortexMState {
     hw_breakpoints_enabled: bool,
 
     current_state: CoreStatus,
+
+    /// Whether the core has an FPU, if it was determined already.
+    fpu_present: Option<bool>,
+
+    /// Whether `DHCSR.S_RESET_ST` was set in a read of DHCSR, which clears it.
+    reset_detected: bool,
+
+    /// Whether DFSR showed a new halt while the core was known to be halted already.
+    halt_detected: bool,
+
+    /// Whether the core halted after a queued step, whose reason wasn't read from DFSR yet.
+    step_reason_pending: bool,
 }
 
 impl CortexMState {
@@ -611,6 +692,10 @@ impl CortexMState {
             initialized: false,
             hw_breakpoints_enabled: false,
             current_state: CoreStatus::Unknown,
+            fpu_present: None,
+            reset_detected: false,
+            halt_detected: false,
+            step_reason_pending: false,
         }
     }
 
@@ -618,9 +703,61 @@ impl CortexMState {
         self.initialized = true;
     }
 
-    fn initialized(&self) -> bool {
+    pub(crate) fn initialized(&self) -> bool {
         self.initialized
     }
+
+    /// The last known status of the core.
+    pub(crate) fn current_state(&self) -> CoreStatus {
+        self.current_state
+    }
+
+    /// The register file of the core.
+    pub(crate) fn registers(&self) -> &'static RegisterFile {
+        &ARM_REGISTER_FILE
+    }
+
+    /// Remember that a read of DHCSR showed a reset, if `s_reset_st` is set.
+    pub(crate) fn note_reset(&mut self, s_reset_st: bool) {
+        self.reset_detected |= s_reset_st;
+    }
+
+    /// Returns whether a reset was noted since this was last called.
+    pub(crate) fn take_reset_detected(&mut self) -> bool {
+        std::mem::take(&mut self.reset_detected)
+    }
+
+    /// Record the halt `reason` read from DFSR while the core is halted, before it is
+    /// cleared.
+    ///
+    /// The bits of DFSR are sticky and cleared after each read, so a reason while the core
+    /// was halted at the previous read as well means that it ran and halted again in between.
+    pub(crate) fn note_halt(&mut self, reason: HaltReason) {
+        if self.current_state.is_halted() && reason != HaltReason::Unknown {
+            self.halt_detected = true;
+        }
+    }
+
+    /// Returns whether a new halt was noted since this was last called.
+    pub(crate) fn take_halt_detected(&mut self) -> bool {
+        std::mem::take(&mut self.halt_detected)
+    }
+
+    /// Record the halt after a queued step, see [`cortex_m::queued_step`].
+    ///
+    /// Its reason is left in DFSR, and read with the next status of the core. Until then,
+    /// the core is assumed to be halted by the step.
+    pub(crate) fn note_queued_step(&mut self) {
+        self.note_halt(HaltReason::Request);
+        self.current_state = CoreStatus::Halted(HaltReason::Request);
+        self.step_reason_pending = true;
+    }
+
+    /// Returns whether the reason of the halt after a queued step is still in DFSR, because
+    /// no status was read since the step.
+    pub(crate) fn take_step_reason_pending(&mut self) -> bool {
+        std::mem::take(&mut self.step_reason_pending)
+    }
 }
 
 #[derive(Debug)]
@@ -649,7 +786,22 @@ impl CortexAState {
         self.initialized = true;
     }
 
-    fn initialized(&self) -> bool {
+    pub(crate) fn initialized(&self) -> bool {
         self.initialized
     }
+
+    /// The last known status of the core.
+    pub(crate) fn current_state(&self) -> CoreStatus {
+        self.current_state
+    }
+
+    /// The register file of the core, which depends on whether it was last seen in a 64-bit
+    /// mode.
+    pub(crate) fn registers(&self) -> &'static RegisterFile {
+        if self.is_64_bit {
+            &armv8a_core_regs::AARCH64_REGISTER_FILE
+        } else {
+            &ARM_REGISTER_FILE
+        }
+    }
 }
@@ -0,0 +1,389 @@
+//! Inspection of the memory protection unit (MPU) of Cortex-M cores.
+//!
+//! Accesses through the memory AP bypass the MPU, but code executed by the core, e.g. flash
+//! algorithms, is subject to it. The regions are decoded from the PMSAv7 registers on
+//! ARMv6-M and ARMv7-M, and from the PMSAv8 registers on ARMv8-M.
+
+use std::{fmt, ops::Range};
+
+use crate::{Core, CoreType, Error, MemoryInterface};
+
+/// MPU_TYPE: The number of supported regions.
+const MPU_TYPE: u64 = 0xE000_ED90;
+
+/// MPU_CTRL: Enables the MPU.
+const MPU_CTRL: u64 = 0xE000_ED94;
+
+/// MPU_RNR: Selects the region accessed through MPU_RBAR and MPU_RASR or MPU_RLAR.
+const MPU_RNR: u64 = 0xE000_ED98;
+
+/// MPU_RBAR: The base address of the selected region.
+const MPU_RBAR: u64 = 0xE000_ED9C;
+
+/// MPU_RASR on ARMv6-M and ARMv7-M, MPU_RLAR on ARMv8-M.
+const MPU_RASR_RLAR: u64 = 0xE000_EDA0;
+
+/// MPU_CTRL.ENABLE
+const CTRL_ENABLE: u32 = 1;
+
+/// CFSR: The configurable fault status register, with the MemManage status in bits 7:0.
+const CFSR: u64 = 0xE000_ED28;
+
+/// MMFAR: The address of the access which caused a MemManage fault.
+const MMFAR: u64 = 0xE000_ED34;
+
+/// MMFSR.MMARVALID: MMFAR holds a valid address.
+const MMFSR_MMARVALID: u32 = 1 << 7;
+
+/// The access permitted by an MPU region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MpuPermission {
+    /// Accesses fault.
+    NoAccess,
+    /// Only reads are permitted.
+    ReadOnly,
+    /// Reads and writes are permitted.
+    ReadWrite,
+}
+
+impl fmt::Display for MpuPermission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MpuPermission::NoAccess => write!(f, "no access"),
+            MpuPermission::ReadOnly => write!(f, "read-only"),
+            MpuPermission::ReadWrite => write!(f, "read-write"),
+        }
+    }
+}
+
+/// A region of the MPU.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpuRegion {
+    /// The number of the region.
+    pub number: usize,
+    /// Whether the region is enabled.
+    pub enabled: bool,
+    /// The addresses covered by the region.
+    pub range: Range<u64>,
+    /// The subregions which are disabled, one bit for each eighth of the region.
+    ///
+    /// Subregions only exist on ARMv6-M and ARMv7-M, on ARMv8-M this is always `0`.
+    pub disabled_subregions: u8,
+    /// The access permitted to privileged code.
+    pub privileged: MpuPermission,
+    /// The access permitted to unprivileged code.
+    pub unprivileged: MpuPermission,
+    /// Whether instruction fetches from the region fault.
+    pub execute_never: bool,
+}
+
+impl MpuRegion {
+    /// Decode a PMSAv7 region, as used by ARMv6-M and ARMv7-M.
+    fn from_pmsav7(number: usize, rbar: u32, rasr: u32) -> Self {
+        // A region has a size of 2^(SIZE + 1) bytes, and is aligned to its size.
+        let size = 1u64 << (((rasr >> 1) & 0x1f) + 1).max(5);
+        let base = rbar as u64 & !0x1f & !(size - 1);
+
+        let (privileged, unprivileged) = match (rasr >> 24) & 0b111 {
+            0b001 => (MpuPermission::ReadWrite, MpuPermission::NoAccess),
+            0b010 => (MpuPermission::ReadWrite, MpuPermission::ReadOnly),
+            0b011 => (MpuPermission::ReadWrite, MpuPermission::ReadWrite),
+            0b101 => (MpuPermission::ReadOnly, MpuPermission::NoAccess),
+            0b110 | 0b111 => (MpuPermission::ReadOnly, MpuPermission::ReadOnly),
+            // 0b100 is reserved.
+            _ => (MpuPermission::NoAccess, MpuPermission::NoAccess),
+        };
+
+        Self {
+            number,
+            enabled: rasr & 1 != 0,
+            range: base..base + size,
+            disabled_subregions: (rasr >> 8) as u8,
+            privileged,
+            unprivileged,
+            execute_never: rasr & (1 << 28) != 0,
+        }
+    }
+
+    /// Decode a PMSAv8 region, as used by ARMv8-M.
+    fn from_pmsav8(number: usize, rbar: u32, rlar: u32) -> Self {
+        let base = (rbar & !0x1f) as u64;
+        let limit = (rlar | 0x1f) as u64;
+
+        let (privileged, unprivileged) = match (rbar >> 1) & 0b11 {
+            0b00 => (MpuPermission::ReadWrite, MpuPermission::NoAccess),
+            0b01 => (MpuPermission::ReadWrite, MpuPermission::ReadWrite),
+            0b10 => (MpuPermission::ReadOnly, MpuPermission::NoAccess),
+            _ => (MpuPermission::ReadOnly, MpuPermission::ReadOnly),
+        };
+
+        Self {
+            number,
+            enabled: rlar & 1 != 0,
+            range: base..limit + 1,
+            disabled_subregions: 0,
+            privileged,
+            unprivileged,
+            execute_never: rbar & 1 != 0,
+        }
+    }
+
+    /// Whether `address` is covered by the region, taking disabled subregions into account.
+    ///
+    /// This doesn't check whether the region is enabled.
+    pub fn contains(&self, address: u64) -> bool {
+        if !self.range.contains(&address) {
+            return false;
+        }
+
+        // Subregions are only supported for regions of 256 bytes and more.
+        let size = self.range.end - self.range.start;
+        if size < 256 {
+            return true;
+        }
+
+        let subregion = (address - self.range.start) / (size / 8);
+
+        self.disabled_subregions & (1 << subregion) == 0
+    }
+}
+
+impl fmt::Display for MpuRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MPU region {} ({:#010x}..{:#010x}, privileged {}, unprivileged {}{})",
+            self.number,
+            self.range.start,
+            self.range.end,
+            self.privileged,
+            self.unprivileged,
+            if self.execute_never {
+                ", execute never"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+/// The cause of a MemManage fault, as reported by the MMFSR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemManageFault {
+    /// The raw value of the MMFSR, bits 7:0 of the CFSR.
+    pub status: u8,
+    /// The address of the faulting data access, if the core recorded it.
+    ///
+    /// Instruction access violations don't record an address.
+    pub address: Option<u64>,
+    /// The enabled MPU region covering `address`.
+    ///
+    /// This is `None` if the address wasn't recorded, or if no region covers it, i.e. the
+    /// access hit the background region.
+    pub region: Option<MpuRegion>,
+}
+
+impl MemManageFault {
+    /// An instruction fetch from a location which doesn't permit execution (IACCVIOL).
+    pub fn instruction_access_violation(&self) -> bool {
+        self.status & (1 << 0) != 0
+    }
+
+    /// A load or store at a location which doesn't permit it (DACCVIOL).
+    pub fn data_access_violation(&self) -> bool {
+        self.status & (1 << 1) != 0
+    }
+
+    /// Unstacking for an exception return caused the fault (MUNSTKERR).
+    pub fn unstacking_error(&self) -> bool {
+        self.status & (1 << 3) != 0
+    }
+
+    /// Stacking for an exception entry caused the fault (MSTKERR).
+    pub fn stacking_error(&self) -> bool {
+        self.status & (1 << 4) != 0
+    }
+}
+
+impl fmt::Display for MemManageFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cause = if self.instruction_access_violation() {
+            "instruction access violation"
+        } else if self.data_access_violation() {
+            "data access violation"
+        } else if self.unstacking_error() {
+            "unstacking error"
+        } else if self.stacking_error() {
+            "stacking error"
+        } else {
+            "fault"
+        };
+
+        write!(f, "MemManage {}", cause)?;
+
+        if let Some(address) = self.address {
+            write!(f, " at {:#010x}", address)?;
+
+            match &self.region {
+                Some(region) => write!(f, " in {}", region)?,
+                None => write!(f, " outside of all enabled MPU regions")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_cortex_m(core: &Core) -> Result<(), Error> {
+    if core.core_type().is_cortex_m() {
+        Ok(())
+    } else {
+        Err(Error::ArchitectureRequired(&[
+            "ARMv6-M", "ARMv7-M", "ARMv8-M",
+        ]))
+    }
+}
+
+/// Read all regions of the MPU.
+pub(crate) fn mpu_regions(core: &mut Core) -> Result<Vec<MpuRegion>, Error> {
+    check_cortex_m(core)?;
+
+    let count = ((core.read_word_32(MPU_TYPE)? >> 8) & 0xff) as usize;
+
+    let pmsav8 = core.core_type() == CoreType::Armv8m;
+    let original_rnr = core.read_word_32(MPU_RNR)?;
+
+    let regions = (0..count)
+        .map(|number| {
+            core.write_word_32(MPU_RNR, number as u32)?;
+            let rbar = core.read_word_32(MPU_RBAR)?;
+            let attributes = core.read_word_32(MPU_RASR_RLAR)?;
+
+            Ok(if pmsav8 {
+                MpuRegion::from_pmsav8(number, rbar, attributes)
+            } else {
+                MpuRegion::from_pmsav7(number, rbar, attributes)
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>();
+
+    // The firmware might be in the middle of configuring a region.
+    if count > 0 {
+        core.write_word_32(MPU_RNR, original_rnr)?;
+    }
+
+    regions
+}
+
+/// Disable the MPU, if the core has one and it is enabled.
+///
+/// Returns the previous value of MPU_CTRL, which has to be passed to [`restore_mpu`].
+pub(crate) fn disable_mpu(core: &mut Core) -> Result<Option<u32>, Error> {
+    if !core.core_type().is_cortex_m() {
+        return Ok(None);
+    }
+
+    let ctrl = core.read_word_32(MPU_CTRL)?;
+
+    if ctrl & CTRL_ENABLE == 0 {
+        return Ok(None);
+    }
+
+    log::debug!("Disabling the MPU, MPU_CTRL = {:#010x}", ctrl);
+    core.write_word_32(MPU_CTRL, ctrl & !CTRL_ENABLE)?;
+
+    Ok(Some(ctrl))
+}
+
+/// Restore the value of MPU_CTRL saved by [`disable_mpu`].
+pub(crate) fn restore_mpu(core: &mut Core, ctrl: Option<u32>) -> Result<(), Error> {
+    if let Some(ctrl) = ctrl {
+        log::debug!("Restoring MPU_CTRL = {:#010x}", ctrl);
+        core.write_word_32(MPU_CTRL, ctrl)?;
+    }
+
+    Ok(())
+}
+
+/// Read the cause of a MemManage fault, and look up the MPU region of the faulting address.
+pub(crate) fn mem_manage_fault(core: &mut Core) -> Result<Option<MemManageFault>, Error> {
+    check_cortex_m(core)?;
+
+    // ARMv6-M has no configurable fault status, all faults escalate to HardFault.
+    if core.core_type() == CoreType::Armv6m {
+        return Ok(None);
+    }
+
+    let mmfsr = core.read_word_32(CFSR)? & 0xff;
+
+    if mmfsr == 0 {
+        return Ok(None);
+    }
+
+    let address = if mmfsr & MMFSR_MMARVALID != 0 {
+        Some(core.read_word_32(MMFAR)? as u64)
+    } else {
+        None
+    };
+
+    let region = match address {
+        // On ARMv6-M and ARMv7-M, the region with the highest number takes precedence.
+        Some(address) => mpu_regions(core)?
+            .into_iter()
+            .rev()
+            .find(|region| region.enabled && region.contains(address)),
+        None => None,
+    };
+
+    Ok(Some(MemManageFault {
+        status: mmfsr as u8,
+        address,
+        region,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pmsav7_region() {
+        // 64 KiB at 0x2000_0000, full access, execute never, upper half disabled.
+        let region = MpuRegion::from_pmsav7(2, 0x2000_0012, 0x1300_f01f);
+
+        assert_eq!(region.number, 2);
+        assert!(region.enabled);
+        assert_eq!(region.range, 0x2000_0000..0x2001_0000);
+        assert_eq!(region.privileged, MpuPermission::ReadWrite);
+        assert_eq!(region.unprivileged, MpuPermission::ReadWrite);
+        assert!(region.execute_never);
+
+        assert!(region.contains(0x2000_7fff));
+        assert!(!region.contains(0x2000_8000));
+        assert!(!region.contains(0x2001_0000));
+    }
+
+    #[test]
+    fn pmsav7_region_base_is_aligned_to_size() {
+        // A 4 GiB region, privileged read-only.
+        let region = MpuRegion::from_pmsav7(0, 0x1234_5600, 0x0500_003e);
+
+        assert!(!region.enabled);
+        assert_eq!(region.range, 0..0x1_0000_0000);
+        assert_eq!(region.privileged, MpuPermission::ReadOnly);
+        assert_eq!(region.unprivileged, MpuPermission::NoAccess);
+    }
+
+    #[test]
+    fn pmsav8_region() {
+        // 0x0800_0000..0x0800_1000, read-only for everyone.
+        let region = MpuRegion::from_pmsav8(5, 0x0800_0006, 0x0800_0fe3);
+
+        assert!(region.enabled);
+        assert_eq!(region.range, 0x0800_0000..0x0800_1000);
+        assert_eq!(region.privileged, MpuPermission::ReadOnly);
+        assert_eq!(region.unprivileged, MpuPermission::ReadOnly);
+        assert!(!region.execute_never);
+        assert!(region.contains(0x0800_0fff));
+    }
+}
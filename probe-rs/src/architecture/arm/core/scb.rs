@@ -0,0 +1,256 @@
+//! Registers of the system control space (SCS) of Cortex-M cores, which can be read while the
+//! core runs.
+//!
+//! The SCS at `0xE000_E000` is memory-mapped, so its registers are read through the memory AP
+//! like any other device memory, without halting the core. This is unlike the core registers,
+//! which are transferred through DCRSR and DCRDR, which needs a halted core.
+
+use bitfield::bitfield;
+
+use crate::{Core, CoreType, Error, MemoryInterface, MemoryMappedRegister};
+
+/// SYST_RVR: The reload value of the SysTick timer.
+const SYST_RVR: u64 = 0xE000_E014;
+
+/// The mask of the 24 bit values of the SysTick timer.
+const SYSTICK_MASK: u32 = 0x00ff_ffff;
+
+bitfield! {
+    /// Interrupt Control and State Register, ICSR (see armv7-M Architecture Reference Manual B3.2.4)
+    #[derive(Copy, Clone)]
+    pub struct Icsr(u32);
+    impl Debug;
+    /// The NMI is pending.
+    pub nmipendset, _: 31;
+    /// PendSV is pending.
+    pub pendsvset, _: 28;
+    /// SysTick is pending.
+    pub pendstset, _: 26;
+    /// An external interrupt, other than NMI or a fault, is pending.
+    pub isrpending, _: 22;
+    /// The exception number of the highest priority pending exception, `0` if none is pending.
+    pub vectpending, _: 20, 12;
+    /// There is no active exception other than the one in VECTACTIVE.
+    ///
+    /// Not implemented on ARMv6-M.
+    pub rettobase, _: 11;
+    /// The exception number of the active exception, `0` in Thread mode.
+    pub vectactive, _: 8, 0;
+}
+
+impl From<u32> for Icsr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Icsr> for u32 {
+    fn from(value: Icsr) -> Self {
+        value.0
+    }
+}
+
+impl MemoryMappedRegister for Icsr {
+    const ADDRESS: u64 = 0xE000_ED04;
+    const NAME: &'static str = "ICSR";
+}
+
+bitfield! {
+    /// System Handler Control and State Register, SHCSR (see armv7-M Architecture Reference Manual B3.2.13)
+    ///
+    /// ARMv6-M only implements SVCALLPENDED.
+    #[derive(Copy, Clone)]
+    pub struct Shcsr(u32);
+    impl Debug;
+    /// The UsageFault exception is enabled.
+    pub usgfaultena, _: 18;
+    /// The BusFault exception is enabled.
+    pub busfaultena, _: 17;
+    /// The MemManage exception is enabled.
+    pub memfaultena, _: 16;
+    /// SVCall is pending.
+    pub svcallpended, _: 15;
+    /// BusFault is pending.
+    pub busfaultpended, _: 14;
+    /// MemManage is pending.
+    pub memfaultpended, _: 13;
+    /// UsageFault is pending.
+    pub usgfaultpended, _: 12;
+    /// SysTick is active.
+    pub systickact, _: 11;
+    /// PendSV is active.
+    pub pendsvact, _: 10;
+    /// The DebugMonitor is active.
+    pub monitoract, _: 8;
+    /// SVCall is active.
+    pub svcallact, _: 7;
+    /// UsageFault is active.
+    pub usgfaultact, _: 3;
+    /// BusFault is active.
+    pub busfaultact, _: 1;
+    /// MemManage is active.
+    pub memfaultact, _: 0;
+}
+
+impl From<u32> for Shcsr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Shcsr> for u32 {
+    fn from(value: Shcsr) -> Self {
+        value.0
+    }
+}
+
+impl MemoryMappedRegister for Shcsr {
+    const ADDRESS: u64 = 0xE000_ED24;
+    const NAME: &'static str = "SHCSR";
+}
+
+bitfield! {
+    /// Configurable Fault Status Register, CFSR (see armv7-M Architecture Reference Manual B3.2.15)
+    ///
+    /// Not implemented on ARMv6-M.
+    #[derive(Copy, Clone)]
+    pub struct Cfsr(u32);
+    impl Debug;
+    /// The UsageFault Status Register, UFSR.
+    pub u16, ufsr, _: 31, 16;
+    /// The BusFault Status Register, BFSR.
+    pub u8, bfsr, _: 15, 8;
+    /// The MemManage Status Register, MMFSR.
+    pub u8, mmfsr, _: 7, 0;
+}
+
+impl From<u32> for Cfsr {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Cfsr> for u32 {
+    fn from(value: Cfsr) -> Self {
+        value.0
+    }
+}
+
+impl MemoryMappedRegister for Cfsr {
+    const ADDRESS: u64 = 0xE000_ED28;
+    const NAME: &'static str = "CFSR";
+}
+
+/// The exception state of a Cortex-M core, read while it runs, see
+/// [`Core::system_control_snapshot_live`].
+///
+/// The registers are read without halting the core, so each value is only a sample of the
+/// moment it was read. Exceptions which are taken and return between two samples are not
+/// seen, and the registers can be from slightly different moments.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemControlSnapshot {
+    /// ICSR, with the active and the pending exception.
+    pub icsr: Icsr,
+    /// SHCSR, with the state of the system handlers.
+    pub shcsr: Shcsr,
+    /// CFSR, with the status of the configurable faults, or `None` on ARMv6-M.
+    pub cfsr: Option<Cfsr>,
+    /// The reload value of the SysTick timer, from SYST_RVR.
+    pub systick_reload: u32,
+    /// The current value of the SysTick timer, from SYST_CVR.
+    pub systick_current: u32,
+}
+
+impl SystemControlSnapshot {
+    /// The exception number of the active exception, or `None` in Thread mode.
+    pub fn active_exception(&self) -> Option<u32> {
+        match self.icsr.vectactive() {
+            0 => None,
+            exception => Some(exception),
+        }
+    }
+
+    /// The exception number of the highest priority pending exception, if one is pending.
+    pub fn pending_exception(&self) -> Option<u32> {
+        match self.icsr.vectpending() {
+            0 => None,
+            exception => Some(exception),
+        }
+    }
+}
+
+/// Read the exception state of a Cortex-M core, in two block reads.
+///
+/// SYST_CSR isn't read, as a read can clear its COUNTFLAG.
+pub(crate) fn system_control_snapshot(core: &mut Core) -> Result<SystemControlSnapshot, Error> {
+    if !core.core_type().is_cortex_m() {
+        return Err(Error::ArchitectureRequired(&[
+            "ARMv6-M", "ARMv7-M", "ARMv8-M",
+        ]));
+    }
+
+    let (icsr, shcsr, cfsr) = if core.core_type() == CoreType::Armv6m {
+        // The registers between ICSR and SHCSR are reserved on ARMv6-M.
+        (
+            core.read_mm_register::<Icsr>()?,
+            core.read_mm_register::<Shcsr>()?,
+            None,
+        )
+    } else {
+        // ICSR to CFSR, all of which are free of side effects when read.
+        let mut block = [0u32; 10];
+        core.read_32(Icsr::ADDRESS, &mut block)?;
+
+        (Icsr(block[0]), Shcsr(block[8]), Some(Cfsr(block[9])))
+    };
+
+    // SYST_RVR and SYST_CVR.
+    let mut systick = [0u32; 2];
+    core.read_32(SYST_RVR, &mut systick)?;
+
+    Ok(SystemControlSnapshot {
+        icsr,
+        shcsr,
+        cfsr,
+        systick_reload: systick[0] & SYSTICK_MASK,
+        systick_current: systick[1] & SYSTICK_MASK,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn icsr_exception_numbers() {
+        // SysTick (15) is active, an external interrupt (16 + 3) is pending.
+        let snapshot = SystemControlSnapshot {
+            icsr: Icsr(0x0041_300f),
+            shcsr: Shcsr(0),
+            cfsr: None,
+            systick_reload: 0,
+            systick_current: 0,
+        };
+
+        assert_eq!(snapshot.active_exception(), Some(15));
+        assert_eq!(snapshot.pending_exception(), Some(19));
+        assert!(snapshot.icsr.isrpending());
+
+        let idle = SystemControlSnapshot {
+            icsr: Icsr(0),
+            ..snapshot
+        };
+
+        assert_eq!(idle.active_exception(), None);
+        assert_eq!(idle.pending_exception(), None);
+    }
+
+    #[test]
+    fn cfsr_fields() {
+        let cfsr = Cfsr(0x0200_8200);
+
+        assert_eq!(cfsr.ufsr(), 0x0200);
+        assert_eq!(cfsr.bfsr(), 0x82);
+        assert_eq!(cfsr.mmfsr(), 0);
+    }
+}
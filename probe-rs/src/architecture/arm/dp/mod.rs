@@ -279,6 +279,87 @@ impl Register for Ctrl {
     const NAME: &'static str = "CTRL/STAT";
 }
 
+/// A power domain whose power-up is requested through CTRL/STAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PowerDomain {
+    /// The debug power domain, requested with CDBGPWRUPREQ.
+    Debug,
+    /// The system power domain, requested with CSYSPWRUPREQ.
+    System,
+}
+
+impl PowerDomain {
+    /// The name of the acknowledge signal of the power domain.
+    pub fn acknowledge_name(&self) -> &'static str {
+        match self {
+            PowerDomain::Debug => "CDBGPWRUPACK",
+            PowerDomain::System => "CSYSPWRUPACK",
+        }
+    }
+}
+
+impl Display for PowerDomain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PowerDomain::Debug => f.write_str("debug"),
+            PowerDomain::System => f.write_str("system"),
+        }
+    }
+}
+
+/// The request and acknowledge bits of a power domain in CTRL/STAT.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PowerRequest {
+    /// The power-up of the domain is requested.
+    pub requested: bool,
+    /// The power controller acknowledges that the domain is powered up.
+    pub acknowledged: bool,
+}
+
+/// The power-up requests of a debug port, decoded from CTRL/STAT, see
+/// [`ArmCommunicationInterface::dp_power_status`](super::ArmCommunicationInterface::dp_power_status).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DpPowerStatus {
+    /// CDBGPWRUPREQ and CDBGPWRUPACK.
+    pub debug: PowerRequest,
+    /// CSYSPWRUPREQ and CSYSPWRUPACK.
+    pub system: PowerRequest,
+    /// The raw value of CTRL/STAT.
+    pub ctrl_stat: u32,
+}
+
+impl DpPowerStatus {
+    /// The request and acknowledge bits of `domain`.
+    pub fn domain(&self, domain: PowerDomain) -> PowerRequest {
+        match domain {
+            PowerDomain::Debug => self.debug,
+            PowerDomain::System => self.system,
+        }
+    }
+
+    /// Returns `true` if the debug power domain is acknowledged, and the system power domain
+    /// as well if `system_power` is set.
+    pub fn is_powered_up(&self, system_power: bool) -> bool {
+        self.debug.acknowledged && (self.system.acknowledged || !system_power)
+    }
+}
+
+impl From<Ctrl> for DpPowerStatus {
+    fn from(ctrl: Ctrl) -> Self {
+        Self {
+            debug: PowerRequest {
+                requested: ctrl.cdbgpwrupreq(),
+                acknowledged: ctrl.cdbgpwrupack(),
+            },
+            system: PowerRequest {
+                requested: ctrl.csyspwrupreq(),
+                acknowledged: ctrl.csyspwrupack(),
+            },
+            ctrl_stat: ctrl.0,
+        }
+    }
+}
+
 bitfield! {
     /// SELECT, AP Select register (see ADI v5.2 B2.2.9)
     #[derive(Clone)]
@@ -7,10 +7,11 @@ use crate::architecture::arm::ArmCommunicationInterface;
 use crate::architecture::arm::{
     communication_interface::Initialized, dp::DpAccess, MemoryApInformation,
 };
-use crate::{CommunicationInterface, Error};
+use crate::{CommunicationInterface, DebugProbeError, Error, VendorCommand, VendorCommandIndex};
 use scroll::{Pread, Pwrite, LE};
 use std::convert::TryInto;
 use std::ops::Range;
+use std::time::Duration;
 
 pub trait ArmProbe: SwdSequence {
     fn read_8(&mut self, ap: MemoryAp, address: u64, data: &mut [u8]) -> Result<(), Error>;
@@ -25,6 +26,49 @@ pub trait ArmProbe: SwdSequence {
 
     fn supports_native_64bit_access(&mut self) -> bool;
 
+    /// Returns true if 8-bit writes are done with a single 8-bit access, instead of a
+    /// read-modify-write of the word which contains the byte.
+    fn supports_native_8bit_writes(&mut self) -> bool {
+        true
+    }
+
+    /// Read the 32 bit word at `address` repeatedly, until `value & mask == expected`, or
+    /// `timeout` passed. Returns true if the word matched before the timeout.
+    ///
+    /// The word is polled by the probe, so this is only supported by probes with the
+    /// [`poll_offload`](crate::ProbeCapabilities::poll_offload) capability.
+    fn poll_word_32(
+        &mut self,
+        _ap: MemoryAp,
+        _address: u64,
+        _mask: u32,
+        _expected: u32,
+        _timeout: Duration,
+    ) -> Result<bool, Error> {
+        Err(Error::Probe(DebugProbeError::CommandNotSupportedByProbe(
+            "polling a memory word",
+        )))
+    }
+
+    /// Queue `command` behind the accesses issued before it, see
+    /// [`CommunicationInterface::enqueue_vendor_command`].
+    fn enqueue_vendor_command(
+        &mut self,
+        _command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, Error> {
+        Err(Error::Probe(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        )))
+    }
+
+    /// Returns the response of the vendor command at `index`, see
+    /// [`CommunicationInterface::vendor_command_response`].
+    fn vendor_command_response(&mut self, _index: VendorCommandIndex) -> Result<Vec<u8>, Error> {
+        Err(Error::Probe(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        )))
+    }
+
     fn get_arm_communication_interface(
         &mut self,
     ) -> Result<&mut ArmCommunicationInterface<Initialized>, Error>;
@@ -55,6 +99,10 @@ where
     // cached on a lower level, where the other Memory AP information is
     // stored.
     cached_csw_value: Option<CSW>,
+
+    // Cached value of the upper word of the TAR register, which only changes
+    // when an access crosses a 4 GiB boundary.
+    cached_tar2_value: Option<u32>,
 }
 
 impl<'interface, AP> ADIMemoryInterface<'interface, AP>
@@ -71,6 +119,7 @@ where
             only_32bit_data_size: ap_information.only_32bit_data_size,
             supports_hnonsec: ap_information.supports_hnonsec,
             cached_csw_value: None,
+            cached_tar2_value: None,
             has_large_address_extension: ap_information.has_large_address_extension,
             has_large_data_extension: ap_information.has_large_data_extension,
         })
@@ -138,18 +187,39 @@ where
         let address_lower = address as u32;
         let address_upper = (address >> 32) as u32;
 
+        if !self.has_large_address_extension && address_upper != 0 {
+            return Err(AccessPortError::LargeAddressNotSupported { address });
+        }
+
         let tar = TAR {
             address: address_lower,
         };
         self.write_ap_register(access_port, tar)?;
 
-        if self.has_large_address_extension {
+        if self.has_large_address_extension && self.cached_tar2_value != Some(address_upper) {
             let tar = TAR2 {
                 address: address_upper,
             };
             self.write_ap_register(access_port, tar)?;
-        } else if address_upper != 0 {
-            return Err(AccessPortError::OutOfBounds);
+
+            self.cached_tar2_value = Some(address_upper);
+        }
+
+        Ok(())
+    }
+
+    /// Check that the `len` bytes at `address` can be addressed by the AP.
+    ///
+    /// Without the large address extension, the TAR register is only 32 bits wide,
+    /// so a transfer which crosses the 4 GiB boundary would wrap around to address 0.
+    /// Such a transfer is rejected before any memory is accessed.
+    fn check_address_range(&self, address: u64, len: usize) -> Result<(), AccessPortError> {
+        let end = address
+            .checked_add(len as u64)
+            .ok_or(AccessPortError::OutOfBounds)?;
+
+        if !self.has_large_address_extension && end > 1 << 32 {
+            return Err(AccessPortError::LargeAddressNotSupported { address });
         }
 
         Ok(())
@@ -271,6 +341,40 @@ where
         Ok(result.data)
     }
 
+    /// Read the 32bit word at `addr` repeatedly, until `value & mask == expected`, or
+    /// `timeout` passed. The reads are done by the probe.
+    ///
+    /// Returns true if the word matched before the timeout. The address has to be word
+    /// aligned.
+    pub fn poll_word_32(
+        &mut self,
+        access_port: MemoryAp,
+        address: u64,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<bool, AccessPortError> {
+        if (address % 4) != 0 {
+            return Err(AccessPortError::alignment_error(address, 4));
+        }
+
+        // Every read has to access the same address.
+        let csw = CSW {
+            AddrInc: AddressIncrement::Off,
+            ..self.build_csw_register(DataSize::U32)
+        };
+
+        self.write_csw_register(access_port, csw)?;
+        self.write_tar_register(access_port, address)?;
+
+        let result: Option<DRW> = self
+            .interface
+            .poll_ap_register(access_port, mask, expected, timeout)
+            .map_err(AccessPortError::register_read_error::<DRW, _>)?;
+
+        Ok(result.is_some())
+    }
+
     /// Read an 8bit word at `addr`.
     pub fn read_word_8(
         &mut self,
@@ -319,6 +423,8 @@ where
             return Err(AccessPortError::alignment_error(start_address, 4));
         }
 
+        self.check_address_range(start_address, data.len() * 4)?;
+
         // Second we read in 32 bit reads until we have less than 32 bits left to read.
         let csw = self.build_csw_register(DataSize::U32);
         self.write_csw_register(access_port, csw)?;
@@ -535,6 +641,8 @@ where
             return Err(AccessPortError::alignment_error(start_address, 4));
         }
 
+        self.check_address_range(start_address, data.len() * 4)?;
+
         log::debug!(
             "Write block with total size {} bytes to address {:#08x}",
             data.len() * 4,
@@ -629,6 +737,29 @@ where
         let aligned = aligned_range(address, data.len())?;
         let aligned_len = (aligned.end - aligned.start) as usize;
 
+        if !self.only_32bit_data_size {
+            // Write the bytes before and after the aligned words with 8-bit accesses, so that
+            // the words which contain them are never read.
+            let head = (((4 - address % 4) % 4) as usize).min(data.len());
+            let words = (data.len() - head) / 4 * 4;
+
+            for (offset, byte) in data[..head].iter().enumerate() {
+                self.write_word_8(access_port, address + offset as u64, *byte)?;
+            }
+
+            let buf32: Vec<u32> = data[head..head + words]
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                .collect();
+            self.write_32(access_port, address + head as u64, &buf32)?;
+
+            for (offset, byte) in data[head + words..].iter().enumerate() {
+                self.write_word_8(access_port, address + (head + words + offset) as u64, *byte)?;
+            }
+
+            return Ok(());
+        }
+
         // Create buffer with aligned size
         let mut buf8 = vec![0u8; aligned_len];
 
@@ -688,6 +819,10 @@ where
         self.has_large_data_extension
     }
 
+    fn supports_native_8bit_writes(&mut self) -> bool {
+        !self.only_32bit_data_size
+    }
+
     fn read_8(&mut self, ap: MemoryAp, address: u64, data: &mut [u8]) -> Result<(), Error> {
         if data.len() == 1 {
             data[0] = self.read_word_8(ap, address)?;
@@ -716,6 +851,28 @@ where
         Ok(())
     }
 
+    fn poll_word_32(
+        &mut self,
+        ap: MemoryAp,
+        address: u64,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        Ok(self.poll_word_32(ap, address, mask, expected, timeout)?)
+    }
+
+    fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, Error> {
+        Ok(self.interface.enqueue_vendor_command(command)?)
+    }
+
+    fn vendor_command_response(&mut self, index: VendorCommandIndex) -> Result<Vec<u8>, Error> {
+        Ok(self.interface.vendor_command_response(index)?)
+    }
+
     fn write_8(&mut self, ap: MemoryAp, address: u64, data: &[u8]) -> Result<(), Error> {
         if data.len() == 1 {
             self.write_word_8(ap, address, data[0])?;
@@ -778,7 +935,10 @@ fn aligned_range(address: u64, len: usize) -> Result<Range<u64>, AccessPortError
 
 #[cfg(test)]
 mod tests {
-    use crate::architecture::arm::{ap::AccessPort, ApAddress, DpAddress, MemoryApInformation};
+    use crate::architecture::arm::{
+        ap::{AccessPort, AccessPortError},
+        ApAddress, DpAddress, MemoryApInformation,
+    };
 
     use super::super::super::ap::memory_ap::mock::MockMemoryAp;
     use super::super::super::ap::memory_ap::MemoryAp;
@@ -793,13 +953,21 @@ mod tests {
         /// Creates a new MemoryInterface for given AccessPort.
         fn new_mock(
             mock: &'interface mut MockMemoryAp,
+        ) -> ADIMemoryInterface<'interface, MockMemoryAp> {
+            Self::new_mock_with_large_addresses(mock, false)
+        }
+
+        /// Creates a new MemoryInterface for an AP with or without the large address extension.
+        fn new_mock_with_large_addresses(
+            mock: &'interface mut MockMemoryAp,
+            has_large_address_extension: bool,
         ) -> ADIMemoryInterface<'interface, MockMemoryAp> {
             let ap_information = MemoryApInformation {
                 address: DUMMY_AP.ap_address(),
                 only_32bit_data_size: false,
                 supports_hnonsec: false,
                 debug_base_address: 0xf000_0000,
-                has_large_address_extension: false,
+                has_large_address_extension,
                 has_large_data_extension: false,
             };
 
@@ -1011,6 +1179,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn access_above_4gib_with_large_address_extension() {
+        let mut mock = MockMemoryAp::with_pattern_at(0x1_0000_0000);
+        let mut mi = ADIMemoryInterface::new_mock_with_large_addresses(&mut mock, true);
+
+        mi.write_32(DUMMY_AP, 0x1_0000_0000, &DATA32[..2])
+            .expect("write_32 failed");
+        assert_eq!(mi.mock_memory()[..8], DATA8[..8]);
+
+        assert_eq!(
+            mi.read_word_32(DUMMY_AP, 0x1_0000_0004)
+                .expect("read_word_32 failed"),
+            DATA32[1]
+        );
+
+        // A transfer across the 4 GiB boundary continues above it.
+        let mut data = [0u32; 4];
+        mi.read_32(DUMMY_AP, 0xffff_fff8, &mut data)
+            .expect("read_32 failed");
+        assert_eq!(data, [0, 0, DATA32[0], DATA32[1]]);
+    }
+
+    #[test]
+    fn access_above_4gib_without_large_address_extension_should_error() {
+        let mut mock = MockMemoryAp::with_pattern();
+        let mut mi = ADIMemoryInterface::new_mock_with_large_addresses(&mut mock, false);
+
+        let expected = Vec::from(mi.mock_memory());
+
+        assert!(matches!(
+            mi.write_word_32(DUMMY_AP, 0x1_0000_0000, DATA32[0]),
+            Err(AccessPortError::LargeAddressNotSupported {
+                address: 0x1_0000_0000
+            })
+        ));
+
+        // A transfer across the 4 GiB boundary is rejected before anything is written,
+        // instead of wrapping around to address 0.
+        assert!(matches!(
+            mi.write_32(DUMMY_AP, 0xffff_fffc, &DATA32[..2]),
+            Err(AccessPortError::LargeAddressNotSupported {
+                address: 0xffff_fffc
+            })
+        ));
+        assert!(matches!(
+            mi.read_32(DUMMY_AP, 0xffff_fffc, &mut [0u32; 2]),
+            Err(AccessPortError::LargeAddressNotSupported { .. })
+        ));
+
+        assert_eq!(mi.mock_memory(), expected.as_slice());
+    }
+
     use super::aligned_range;
 
     #[test]
@@ -649,6 +649,16 @@ impl PeripheralID {
         self.PART
     }
 
+    /// Returns the DEVTYPE of the peripheral.
+    pub fn dev_type(&self) -> u8 {
+        self.dev_type
+    }
+
+    /// Returns the architecture ID of the peripheral, from DEVARCH.
+    pub fn arch_id(&self) -> u16 {
+        self.arch_id
+    }
+
     /// Uses the available data to match it againts a table of known components.
     /// If the component is known, some info about it is returned.
     /// If it is not known, None is returned.
@@ -11,9 +11,10 @@ pub mod swo;
 mod traits;
 
 pub use communication_interface::{
-    ApInformation, ArmChipInfo, ArmCommunicationInterface, DapError, MemoryApInformation, Register,
+    ApInformation, ArmChipInfo, ArmCommunicationInterface, DapError, DapProbe, MemoryApInformation,
+    Register, SwdSequence, UninitializedArmProbe,
 };
-pub use swo::{SwoAccess, SwoConfig, SwoMode, SwoReader};
+pub use swo::{SwoAccess, SwoConfig, SwoMode, SwoReader, SwoStatus};
 pub use traits::*;
 
 pub use self::core::armv6m;
@@ -21,6 +22,13 @@ pub use self::core::armv7a;
 pub use self::core::armv7m;
 pub use self::core::armv8a;
 pub use self::core::armv8m;
+pub use self::core::cache::CacheMaintenance;
+pub use self::core::exception_frame::{
+    ExcReturn, ExceptionFrame, FpFrame, FrameStack, StackLimitViolation,
+};
+pub use self::core::hit_count::{AddressHits, HitCountMode, HitCountReport};
+pub use self::core::mpu::{MemManageFault, MpuPermission, MpuRegion};
+pub use self::core::scb::{Cfsr, Icsr, Shcsr, SystemControlSnapshot};
 pub use self::core::Dump;
 
 pub use communication_interface::ArmProbeInterface;
@@ -13,6 +13,7 @@ use std::{
 use probe_rs_target::CoreType;
 
 use crate::architecture::arm::core::armv7a_debug_regs::Armv7DebugRegister;
+use crate::architecture::settle::DelayOrPoll;
 use crate::{
     architecture::arm::{ArmProbeInterface, DapError},
     core::MemoryMappedRegister,
@@ -434,6 +435,19 @@ pub trait ArmDebugSequence: Send + Sync {
         }
     }
 
+    /// Enable a faster clock for the debug logic of a target which runs it on a slow clock
+    /// after a reset. This is not part of the [ARM SVD Debug Description].
+    ///
+    /// Executed while attaching at [`SLOW_CLOCK_SPEED_KHZ`](crate::SLOW_CLOCK_SPEED_KHZ),
+    /// after a line reset, before the speed is negotiated upward. See
+    /// [`AttachOptions::slow_clock_attach`](crate::AttachOptions::slow_clock_attach).
+    ///
+    /// [ARM SVD Debug Description]: http://www.keil.com/pack/doc/cmsis/Pack/html/debug_description.html
+    fn debug_clock_setup(&self, _interface: &mut dyn DapProbe) -> Result<(), crate::Error> {
+        // Empty by default, attaching at a low speed is enough for most targets.
+        Ok(())
+    }
+
     /// Prepare the target debug port for connection. This is based on the
     /// `DebugPortSetup` function from the [ARM SVD Debug Description].
     ///
@@ -502,29 +516,11 @@ pub trait ArmDebugSequence: Send + Sync {
 
         interface.write_dp_register(dp, Select(0))?;
 
-        let ctrl = interface.read_dp_register::<Ctrl>(dp)?;
-
-        let powered_down = !(ctrl.csyspwrupack() && ctrl.cdbgpwrupack());
+        let system_power = interface.requests_system_power();
+        let powered_down = !interface.dp_power_status(dp)?.is_powered_up(system_power);
 
         if powered_down {
-            let mut ctrl = Ctrl(0);
-            ctrl.set_cdbgpwrupreq(true);
-            ctrl.set_csyspwrupreq(true);
-            interface.write_dp_register(dp, ctrl)?;
-
-            let start = Instant::now();
-            let mut timeout = true;
-            while start.elapsed() < Duration::from_micros(100_0000) {
-                let ctrl = interface.read_dp_register::<Ctrl>(dp)?;
-                if ctrl.csyspwrupack() && ctrl.cdbgpwrupack() {
-                    timeout = false;
-                    break;
-                }
-            }
-
-            if timeout {
-                return Err(DebugProbeError::Timeout);
-            }
+            interface.power_up_debug_port(dp)?;
 
             // TODO: Handle JTAG Specific part
 
@@ -533,12 +529,11 @@ pub trait ArmDebugSequence: Send + Sync {
             // Init AP Transfer Mode, Transaction Counter, and Lane Mask (Normal Transfer Mode, Include all Byte Lanes)
             let mut ctrl = Ctrl(0);
             ctrl.set_cdbgpwrupreq(true);
-            ctrl.set_csyspwrupreq(true);
+            ctrl.set_csyspwrupreq(system_power);
             ctrl.set_mask_lane(0b1111);
             interface.write_dp_register(dp, ctrl)?;
 
-            let ctrl_reg: Ctrl = interface.read_dp_register(dp)?;
-            if !(ctrl_reg.csyspwrupack() && ctrl_reg.cdbgpwrupack()) {
+            if !interface.dp_power_status(dp)?.is_powered_up(system_power) {
                 log::error!("Debug power request failed");
                 return Err(DapError::TargetPowerUpFailed.into());
             }
@@ -660,12 +655,15 @@ pub trait ArmDebugSequence: Send + Sync {
     /// `DebugDeviceUnlock` function from the [ARM SVD Debug Description].
     ///
     /// [ARM SVD Debug Description]: http://www.keil.com/pack/doc/cmsis/Pack/html/debug_description.html#debugDeviceUnlock
+    ///
+    /// The `delay` handle should be used to wait for the target after power state changes.
     #[doc(alias = "DebugDeviceUnlock")]
     fn debug_device_unlock(
         &self,
         _interface: &mut Box<dyn ArmProbeInterface>,
         _default_ap: MemoryAp,
         _permissions: &crate::Permissions,
+        _delay: &DelayOrPoll,
     ) -> Result<(), crate::Error> {
         // Empty by default
         Ok(())
@@ -1,24 +1,23 @@
 //! Sequences for the nRF53.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::ArmDebugSequence;
-use crate::architecture::arm::ap::{MemoryAp, CSW};
-use crate::architecture::arm::{
-    communication_interface::Initialized, ApAddress, ArmCommunicationInterface, ArmProbeInterface,
-    DapAccess,
-};
+use crate::architecture::arm::ap::{AccessPort, MemoryAp, NordicCtrlAp, VendorAp, CSW};
+use crate::architecture::arm::{ApAddress, ArmProbeInterface, Register};
+use crate::architecture::settle::DelayOrPoll;
 
 /// The sequence handle for the nRF5340.
 pub struct Nrf5340(());
 
 impl Nrf5340 {
-    const ERASEALL: u8 = 0x04;
-    const ERASEALLSTATUS: u8 = 0x08;
-
     const APPLICATION_RESET_S_NETWORK_FORCEOFF_REGISTER: u32 = 0x50005614;
     const RELEASE_FORCEOFF: u32 = 0;
 
+    /// The maximum time an erase all operation is expected to take.
+    const ERASEALL_TIMEOUT: Duration = Duration::from_secs(15);
+
     /// Create a new sequence handle for the nRF5340.
     pub fn create() -> Arc<dyn ArmDebugSequence> {
         Arc::new(Self(()))
@@ -28,10 +27,12 @@ impl Nrf5340 {
     /// The `ap_address` must be of the ahb ap of the core.
     fn is_core_unlocked(
         &self,
-        arm_interface: &mut ArmCommunicationInterface<Initialized>,
+        arm_interface: &mut dyn ArmProbeInterface,
         ap_address: ApAddress,
     ) -> Result<bool, crate::Error> {
-        let csw: CSW = arm_interface.read_raw_ap_register(ap_address, 0x00)?.into();
+        let csw: CSW = arm_interface
+            .read_raw_ap_register(ap_address, CSW::ADDRESS)?
+            .into();
         Ok(csw.DeviceEn != 0)
     }
 
@@ -39,15 +40,17 @@ impl Nrf5340 {
     /// The `ap_address` must be of the ctrl ap of the core.
     fn unlock_core(
         &self,
-        arm_interface: &mut ArmCommunicationInterface<Initialized>,
+        arm_interface: &mut dyn ArmProbeInterface,
         ap_address: ApAddress,
         permissions: &crate::Permissions,
+        delay: &DelayOrPoll,
     ) -> Result<(), crate::Error> {
         permissions.erase_all()?;
 
-        arm_interface.write_raw_ap_register(ap_address, Self::ERASEALL, 1)?;
-        while arm_interface.read_raw_ap_register(ap_address, Self::ERASEALLSTATUS)? != 0 {}
-        Ok(())
+        let mut ctrl_ap = NordicCtrlAp::open(arm_interface, ap_address)?;
+
+        ctrl_ap.eraseall(true)?;
+        delay.poll(Self::ERASEALL_TIMEOUT, || Ok(!ctrl_ap.eraseall_busy()?))
     }
 
     /// Sets the network core to active running.
@@ -67,14 +70,13 @@ impl ArmDebugSequence for Nrf5340 {
         interface: &mut Box<dyn ArmProbeInterface>,
         default_ap: MemoryAp,
         permissions: &crate::Permissions,
+        delay: &DelayOrPoll,
     ) -> Result<(), crate::Error> {
-        let mut interface = interface.memory_interface(default_ap)?;
-
         // TODO: Eraseprotect is not considered. If enabled, the debugger must set up the same keys as the firmware does
         // TODO: Approtect and Secure Approtect are not considered. If enabled, the debugger must set up the same keys as the firmware does
         // These keys should be queried from the user if required and once that mechanism is implemented
 
-        let ap_address = interface.get_ap();
+        let ap_address = default_ap.ap_address();
 
         let core_aps = [(0, 2), (1, 3)];
 
@@ -89,7 +91,7 @@ impl ArmDebugSequence for Nrf5340 {
             };
 
             log::info!("Checking if core {} is unlocked", core_ahb_ap);
-            if self.is_core_unlocked(interface.get_arm_interface()?, core_ahb_ap_address)? {
+            if self.is_core_unlocked(interface.as_mut(), core_ahb_ap_address)? {
                 log::info!("Core {} is already unlocked", core_ahb_ap);
                 continue;
             }
@@ -98,19 +100,16 @@ impl ArmDebugSequence for Nrf5340 {
                 "Core {} is locked. Erase procedure will be started to unlock it.",
                 core_ahb_ap
             );
-            self.unlock_core(
-                interface.get_arm_interface()?,
-                core_ctrl_ap_address,
-                permissions,
-            )?;
+            self.unlock_core(interface.as_mut(), core_ctrl_ap_address, permissions, delay)?;
 
-            if !self.is_core_unlocked(interface.get_arm_interface()?, core_ahb_ap_address)? {
+            if !self.is_core_unlocked(interface.as_mut(), core_ahb_ap_address)? {
                 return Err(crate::Error::ArchitectureSpecific(
                     format!("Could not unlock core {}", core_ahb_ap).into(),
                 ));
             }
         }
 
+        let mut interface = interface.memory_interface(default_ap)?;
         self.set_network_core_running(&mut interface)?;
 
         Ok(())
@@ -39,33 +39,11 @@ impl ArmDebugSequence for LPC55S69 {
 
         interface.write_dp_register(dp, Select(0))?;
 
-        let ctrl = interface.read_dp_register::<Ctrl>(dp)?;
-
-        let powered_down = !(ctrl.csyspwrupack() && ctrl.cdbgpwrupack());
+        let system_power = interface.requests_system_power();
+        let powered_down = !interface.dp_power_status(dp)?.is_powered_up(system_power);
 
         if powered_down {
-            let mut ctrl = Ctrl(0);
-            ctrl.set_cdbgpwrupreq(true);
-            ctrl.set_csyspwrupreq(true);
-
-            interface.write_dp_register(dp, ctrl)?;
-
-            let start = Instant::now();
-
-            let mut timeout = true;
-
-            while start.elapsed() < Duration::from_micros(100_0000) {
-                let ctrl = interface.read_dp_register::<Ctrl>(dp)?;
-
-                if ctrl.csyspwrupack() && ctrl.cdbgpwrupack() {
-                    timeout = false;
-                    break;
-                }
-            }
-
-            if timeout {
-                return Err(DebugProbeError::Timeout);
-            }
+            interface.power_up_debug_port(dp)?;
 
             // TODO: Handle JTAG Specific part
 
@@ -75,7 +53,7 @@ impl ArmDebugSequence for LPC55S69 {
             let mut ctrl = Ctrl(0);
 
             ctrl.set_cdbgpwrupreq(true);
-            ctrl.set_csyspwrupreq(true);
+            ctrl.set_csyspwrupreq(system_power);
 
             ctrl.set_mask_lane(0b1111);
 
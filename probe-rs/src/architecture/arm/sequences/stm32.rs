@@ -1,10 +1,14 @@
 //! Sequences for STM32 devices
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::ArmDebugSequence;
 use crate::{
-    architecture::arm::{ap::MemoryAp, ApAddress, ArmProbeInterface, DpAddress},
+    architecture::{
+        arm::{ap::MemoryAp, ApAddress, ArmProbeInterface, DpAddress},
+        settle::DelayOrPoll,
+    },
     Memory,
 };
 
@@ -48,6 +52,25 @@ impl Stm32h7 {
 
         Ok(())
     }
+
+    /// Wait until the debug clocks of the D1 and D3 domains are running after they were enabled.
+    fn wait_for_debug_components(
+        &self,
+        memory: &mut Memory<'_>,
+        delay: &DelayOrPoll,
+    ) -> Result<(), crate::Error> {
+        let mut clocks = dbgmcu::Control(0);
+        clocks.enable_d1_clock(true);
+        clocks.enable_d3_clock(true);
+
+        delay.poll_register(
+            memory,
+            dbgmcu::Control::ABSOLUTE_ADDRESS,
+            clocks.0,
+            clocks.0,
+            Duration::from_millis(100),
+        )
+    }
 }
 
 mod dbgmcu {
@@ -76,6 +99,9 @@ mod dbgmcu {
         /// The offset of the Control register in the DBGMCU block.
         const ADDRESS: u64 = 0x04;
 
+        /// The address of the Control register.
+        pub const ABSOLUTE_ADDRESS: u64 = DBGMCU + Self::ADDRESS;
+
         /// Read the control register from memory.
         pub fn read(memory: &mut Memory<'_>) -> Result<Self, crate::Error> {
             let contents = memory.read_word_32(DBGMCU + Self::ADDRESS)?;
@@ -95,6 +121,7 @@ impl ArmDebugSequence for Stm32h7 {
         interface: &mut Box<dyn ArmProbeInterface>,
         _default_ap: MemoryAp,
         _permissions: &crate::Permissions,
+        delay: &DelayOrPoll,
     ) -> Result<(), crate::Error> {
         // Power up the debug components through AP2, which is the defualt AP debug port.
         let ap = MemoryAp::new(ApAddress {
@@ -104,6 +131,7 @@ impl ArmDebugSequence for Stm32h7 {
 
         let mut memory = interface.memory_interface(ap)?;
         self.enable_debug_components(&mut memory, true)?;
+        self.wait_for_debug_components(&mut memory, delay)?;
 
         Ok(())
     }
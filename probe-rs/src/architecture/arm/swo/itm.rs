@@ -0,0 +1,487 @@
+//! Decoding of ITM packets.
+//!
+//! The [`ItmDecoder`] splits a raw SWO byte stream, as returned by
+//! [`SwoAccess::read_swo`](super::SwoAccess::read_swo), into [`TracePacket`]s. Data which
+//! was lost on the way to the host, e.g. because the capture buffer of the probe overran,
+//! leaves the decoder in the middle of an unknown packet. After such a gap, the decoder
+//! discards all data until the next synchronization packet.
+
+use super::TimestampEvent;
+
+/// The minimum number of zero bytes before the final `0x80` of a synchronization packet.
+///
+/// A synchronization packet consists of at least 47 zero bits followed by a one bit.
+const SYNC_ZERO_BYTES: usize = 5;
+
+/// A decoded ITM packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TracePacket {
+    /// A synchronization packet.
+    Sync,
+    /// The ITM dropped packets, because its output FIFO was full.
+    Overflow,
+    /// Data was lost between the target and the host, e.g. because the capture buffer of
+    /// the probe overran. The packets following the marker start at the next
+    /// synchronization packet.
+    Overrun,
+    /// A packet written by software to a stimulus port.
+    Instrumentation {
+        /// The stimulus port, within the current stimulus port page.
+        port: u8,
+        /// The 1, 2 or 4 byte payload.
+        payload: Vec<u8>,
+    },
+    /// A packet emitted by a hardware source, e.g. the DWT.
+    Hardware {
+        /// The discriminator identifying the hardware source.
+        discriminator: u8,
+        /// The 1, 2 or 4 byte payload.
+        payload: Vec<u8>,
+    },
+    /// A local timestamp.
+    LocalTimestamp {
+        /// The number of timestamp clock cycles since the previous local timestamp.
+        delta: u32,
+    },
+    /// A global timestamp.
+    GlobalTimestamp {
+        /// The transmitted bits of the global timestamp.
+        value: u64,
+        /// Whether the packet contains the upper bits of the timestamp, starting at bit 26,
+        /// instead of the lower bits.
+        upper_bits: bool,
+    },
+    /// An extension packet, e.g. selecting the stimulus port page.
+    Extension {
+        /// Whether the packet was emitted by a hardware source.
+        hardware: bool,
+        /// The extension information.
+        value: u32,
+    },
+}
+
+impl TracePacket {
+    /// The timestamp related event of the packet, to be passed to a
+    /// [`TimestampCorrelator`](super::TimestampCorrelator).
+    ///
+    /// After lost data, the deltas since the previous local timestamp are unknown, so both
+    /// [`TracePacket::Overflow`] and [`TracePacket::Overrun`] are reported as an overflow.
+    pub fn timestamp_event(&self) -> Option<TimestampEvent> {
+        match self {
+            TracePacket::LocalTimestamp { delta } => Some(TimestampEvent::Local { delta: *delta }),
+            TracePacket::Overflow | TracePacket::Overrun => Some(TimestampEvent::Overflow),
+            _ => None,
+        }
+    }
+}
+
+/// A packet which is spread over multiple bytes, and not complete yet.
+#[derive(Debug, Clone)]
+enum Partial {
+    /// The zero bytes of a synchronization packet.
+    Sync { zeros: usize },
+    /// A source packet with a fixed size payload.
+    Source { header: u8, payload: Vec<u8> },
+    /// A packet with continuation bytes, which carry 7 bits each.
+    Continued {
+        header: u8,
+        value: u64,
+        bytes: usize,
+    },
+}
+
+/// A decoder for an ITM byte stream.
+///
+/// The decoder assumes that the stream starts at a packet boundary, which is the case if the
+/// ITM was enabled after the SWO capture started. Packets can be split over multiple calls
+/// to [`ItmDecoder::decode`].
+#[derive(Debug, Clone)]
+pub struct ItmDecoder {
+    synchronized: bool,
+    /// The number of consecutive zero bytes, while waiting for a synchronization packet.
+    zeros: usize,
+    partial: Option<Partial>,
+}
+
+impl Default for ItmDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ItmDecoder {
+    /// Create a new decoder.
+    pub fn new() -> Self {
+        Self {
+            synchronized: true,
+            zeros: 0,
+            partial: None,
+        }
+    }
+
+    /// Whether the decoder is synchronized to the packet boundaries of the stream.
+    pub fn is_synchronized(&self) -> bool {
+        self.synchronized
+    }
+
+    /// Report that data was lost before the data passed to the next call of
+    /// [`ItmDecoder::decode`].
+    ///
+    /// The decoder discards the packet it is in the middle of, and all data until the next
+    /// synchronization packet. Returns the [`TracePacket::Overrun`] marker, which should be
+    /// inserted into the decoded stream at the gap.
+    pub fn overrun(&mut self) -> TracePacket {
+        self.desynchronize();
+        TracePacket::Overrun
+    }
+
+    /// Decode `data`, and return all packets which were completed by it.
+    pub fn decode(&mut self, data: &[u8]) -> Vec<TracePacket> {
+        let mut packets = Vec::new();
+
+        for &byte in data {
+            if !self.synchronized {
+                self.search_sync(byte, &mut packets);
+                continue;
+            }
+
+            let result = match self.partial.take() {
+                Some(partial) => self.continue_packet(partial, byte),
+                None => self.start_packet(byte),
+            };
+
+            match result {
+                Ok(Some(packet)) => packets.push(packet),
+                Ok(None) => (),
+                Err(()) => {
+                    log::debug!(
+                        "Invalid ITM data {:#04x}, waiting for the next synchronization packet",
+                        byte
+                    );
+                    self.desynchronize();
+                }
+            }
+        }
+
+        packets
+    }
+
+    fn desynchronize(&mut self) {
+        self.synchronized = false;
+        self.zeros = 0;
+        self.partial = None;
+    }
+
+    fn search_sync(&mut self, byte: u8, packets: &mut Vec<TracePacket>) {
+        match byte {
+            0x00 => self.zeros += 1,
+            0x80 if self.zeros >= SYNC_ZERO_BYTES => {
+                self.synchronized = true;
+                self.zeros = 0;
+                packets.push(TracePacket::Sync);
+            }
+            _ => self.zeros = 0,
+        }
+    }
+
+    /// Decode a packet header.
+    fn start_packet(&mut self, header: u8) -> Result<Option<TracePacket>, ()> {
+        if header & 0b11 != 0 {
+            self.partial = Some(Partial::Source {
+                header,
+                payload: Vec::with_capacity(4),
+            });
+            return Ok(None);
+        }
+
+        match header {
+            0x00 => {
+                self.partial = Some(Partial::Sync { zeros: 1 });
+                Ok(None)
+            }
+            0x70 => Ok(Some(TracePacket::Overflow)),
+            // Local timestamp, format 2, with the delta in the header.
+            _ if header & 0b1000_1111 == 0 => Ok(Some(TracePacket::LocalTimestamp {
+                delta: ((header >> 4) & 0b111) as u32,
+            })),
+            // Local timestamp, format 1.
+            _ if header & 0b1100_1111 == 0b1100_0000 => {
+                self.partial = Some(Partial::Continued {
+                    header,
+                    value: 0,
+                    bytes: 0,
+                });
+                Ok(None)
+            }
+            // Global timestamps.
+            0x94 | 0xb4 => {
+                self.partial = Some(Partial::Continued {
+                    header,
+                    value: 0,
+                    bytes: 0,
+                });
+                Ok(None)
+            }
+            // Extension.
+            _ if header & 0b1011 == 0b1000 => {
+                if header & 0x80 == 0 {
+                    return Ok(Some(extension(header, 0)));
+                }
+
+                self.partial = Some(Partial::Continued {
+                    header,
+                    value: 0,
+                    bytes: 0,
+                });
+                Ok(None)
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Add a byte to a packet which is spread over multiple bytes.
+    fn continue_packet(&mut self, partial: Partial, byte: u8) -> Result<Option<TracePacket>, ()> {
+        match partial {
+            Partial::Sync { zeros } => match byte {
+                0x00 => {
+                    self.partial = Some(Partial::Sync { zeros: zeros + 1 });
+                    Ok(None)
+                }
+                0x80 if zeros >= SYNC_ZERO_BYTES => Ok(Some(TracePacket::Sync)),
+                _ => Err(()),
+            },
+            Partial::Source {
+                header,
+                mut payload,
+            } => {
+                payload.push(byte);
+
+                let size = match header & 0b11 {
+                    0b01 => 1,
+                    0b10 => 2,
+                    _ => 4,
+                };
+
+                if payload.len() < size {
+                    self.partial = Some(Partial::Source { header, payload });
+                    return Ok(None);
+                }
+
+                let address = header >> 3;
+
+                Ok(Some(if header & 0b100 == 0 {
+                    TracePacket::Instrumentation {
+                        port: address,
+                        payload,
+                    }
+                } else {
+                    TracePacket::Hardware {
+                        discriminator: address,
+                        payload,
+                    }
+                }))
+            }
+            Partial::Continued {
+                header,
+                value,
+                bytes,
+            } => {
+                let (max_bytes, last_mask) = match header {
+                    0x94 => (4, 0x1f),
+                    0xb4 => (6, 0x7f),
+                    _ => (4, 0x7f),
+                };
+
+                let last = byte & 0x80 == 0 || bytes + 1 == max_bytes;
+                let payload = if bytes + 1 == max_bytes {
+                    byte & last_mask
+                } else {
+                    byte & 0x7f
+                };
+                let value = value | (payload as u64) << (7 * bytes);
+
+                if !last {
+                    self.partial = Some(Partial::Continued {
+                        header,
+                        value,
+                        bytes: bytes + 1,
+                    });
+                    return Ok(None);
+                }
+
+                // The last byte of a local timestamp or extension must not have the
+                // continuation bit set.
+                if byte & 0x80 != 0 && header != 0x94 && header != 0xb4 {
+                    return Err(());
+                }
+
+                Ok(Some(match header {
+                    0x94 => TracePacket::GlobalTimestamp {
+                        value,
+                        upper_bits: false,
+                    },
+                    0xb4 => TracePacket::GlobalTimestamp {
+                        value,
+                        upper_bits: true,
+                    },
+                    _ if header & 0b1000 == 0 => TracePacket::LocalTimestamp {
+                        delta: value as u32,
+                    },
+                    _ => extension(header, value),
+                }))
+            }
+        }
+    }
+}
+
+/// An extension packet, with the information from the header and the continuation bytes.
+fn extension(header: u8, continued: u64) -> TracePacket {
+    TracePacket::Extension {
+        hardware: header & 0b100 != 0,
+        value: ((header >> 4) & 0b111) as u32 | (continued << 3) as u32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SYNC: [u8; 6] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x80];
+
+    /// A stream with a synchronization packet, followed by instrumentation and timestamp
+    /// packets.
+    fn stream() -> Vec<u8> {
+        let mut stream = SYNC.to_vec();
+        for i in 0..4u8 {
+            // 4 byte write to stimulus port 1.
+            stream.extend([0x0b, i, 0x00, 0x00, 0xff]);
+            // Local timestamp, format 1, with a 2 byte delta.
+            stream.extend([0xc0, 0x81, 0x02]);
+        }
+        stream
+    }
+
+    fn instrumentation(i: u8) -> TracePacket {
+        TracePacket::Instrumentation {
+            port: 1,
+            payload: vec![i, 0x00, 0x00, 0xff],
+        }
+    }
+
+    const TIMESTAMP: TracePacket = TracePacket::LocalTimestamp { delta: 0x101 };
+
+    #[test]
+    fn decode_stream() {
+        let mut decoder = ItmDecoder::new();
+
+        let packets = decoder.decode(&stream());
+
+        assert_eq!(packets.len(), 9);
+        assert_eq!(packets[0], TracePacket::Sync);
+        assert_eq!(packets[1], instrumentation(0));
+        assert_eq!(packets[2], TIMESTAMP);
+        assert_eq!(packets[7], instrumentation(3));
+    }
+
+    #[test]
+    fn packets_split_between_reads() {
+        let mut decoder = ItmDecoder::new();
+
+        let mut packets = Vec::new();
+        for chunk in stream().chunks(3) {
+            packets.extend(decoder.decode(chunk));
+        }
+
+        assert_eq!(packets, ItmDecoder::new().decode(&stream()));
+    }
+
+    #[test]
+    fn resync_after_mid_packet_gap() {
+        let mut decoder = ItmDecoder::new();
+        let stream = stream();
+
+        // The capture buffer overran in the middle of the first instrumentation packet,
+        // and the data continues in the middle of the second one. Without the overrun,
+        // the remaining payload bytes would be decoded as packet headers.
+        let mut packets = decoder.decode(&stream[..8]);
+        packets.push(decoder.overrun());
+        assert!(!decoder.is_synchronized());
+        packets.extend(decoder.decode(&stream[16..]));
+
+        // The data after the gap is discarded until the next synchronization packet.
+        assert_eq!(packets, vec![TracePacket::Sync, TracePacket::Overrun]);
+
+        let mut next = stream.clone();
+        next.extend(stream[6..14].iter());
+        let packets = decoder.decode(&next);
+
+        assert!(decoder.is_synchronized());
+        assert_eq!(packets[0], TracePacket::Sync);
+        assert_eq!(packets[1], instrumentation(0));
+        assert_eq!(packets[9..], [instrumentation(0), TIMESTAMP]);
+    }
+
+    #[test]
+    fn invalid_data_resynchronizes() {
+        let mut decoder = ItmDecoder::new();
+
+        // 0x84 is a reserved header, the zero bytes before it are not a synchronization
+        // packet.
+        let mut data = vec![0x84, 0x00, 0x00, 0x80, 0x0b, 0x01, 0x02, 0x03, 0x04];
+        data.extend(stream());
+
+        let packets = decoder.decode(&data);
+
+        assert_eq!(packets[0], TracePacket::Sync);
+        assert_eq!(packets.len(), 9);
+    }
+
+    #[test]
+    fn short_packets() {
+        let mut decoder = ItmDecoder::new();
+
+        #[rustfmt::skip]
+        let data = [
+            // Local timestamp, format 2.
+            0x30,
+            // Overflow.
+            0x70,
+            // 1 byte write by the DWT.
+            0x15, 0xaa,
+            // Stimulus port page 2.
+            0x28,
+            // Global timestamp, lower bits.
+            0x94, 0x81, 0x80, 0x80, 0x61,
+            // Global timestamp, upper bits.
+            0xb4, 0x81, 0x80, 0x80, 0x01,
+        ];
+
+        let packets = decoder.decode(&data);
+
+        assert_eq!(
+            packets,
+            vec![
+                TracePacket::LocalTimestamp { delta: 3 },
+                TracePacket::Overflow,
+                TracePacket::Hardware {
+                    discriminator: 2,
+                    payload: vec![0xaa]
+                },
+                TracePacket::Extension {
+                    hardware: false,
+                    value: 2
+                },
+                TracePacket::GlobalTimestamp {
+                    value: 0x20_0001,
+                    upper_bits: false
+                },
+                TracePacket::GlobalTimestamp {
+                    value: 0x20_0001,
+                    upper_bits: true
+                },
+            ]
+        );
+
+        assert_eq!(packets[1].timestamp_event(), Some(TimestampEvent::Overflow));
+    }
+}
@@ -1,5 +1,13 @@
 //! SWO tracing related functions.
 
+mod itm;
+mod timestamp;
+
+pub use itm::{ItmDecoder, TracePacket};
+pub use timestamp::{
+    GlobalTimestampFrequency, TimestampCorrelator, TimestampEvent, TimestampPrescaler,
+};
+
 use crate::architecture::arm::communication_interface::ArmProbeInterface;
 use crate::Error;
 
@@ -32,6 +40,12 @@ pub struct SwoConfig {
     /// This is required to use ETM over SWO, but otherwise
     /// adds overhead if only DWT/ITM data is used.
     tpiu_continuous_formatting: bool,
+
+    /// The prescaler of the ITM local timestamps, or `None` if they are disabled.
+    local_timestamps: Option<TimestampPrescaler>,
+
+    /// How often the ITM emits global timestamps.
+    global_timestamps: GlobalTimestampFrequency,
 }
 
 impl SwoConfig {
@@ -45,6 +59,8 @@ impl SwoConfig {
             baud: 1_000_000,
             tpiu_clk,
             tpiu_continuous_formatting: false,
+            local_timestamps: Some(TimestampPrescaler::Div1),
+            global_timestamps: GlobalTimestampFrequency::Every8192Cycles,
         }
     }
 
@@ -78,6 +94,22 @@ impl SwoConfig {
         self
     }
 
+    /// Set the prescaler of the ITM local timestamps, or disable them with `None`.
+    ///
+    /// By default, local timestamps are enabled without prescaling.
+    pub fn set_local_timestamps(mut self, prescaler: Option<TimestampPrescaler>) -> Self {
+        self.local_timestamps = prescaler;
+        self
+    }
+
+    /// Set how often the ITM emits global timestamps.
+    ///
+    /// By default, a global timestamp is emitted every 8192 cycles.
+    pub fn set_global_timestamps(mut self, frequency: GlobalTimestampFrequency) -> Self {
+        self.global_timestamps = frequency;
+        self
+    }
+
     /// The SWO mode.
     pub fn mode(&self) -> SwoMode {
         self.mode
@@ -102,6 +134,26 @@ impl SwoConfig {
     pub fn tpiu_continuous_formatting(&self) -> bool {
         self.tpiu_continuous_formatting
     }
+
+    /// The prescaler of the ITM local timestamps, or `None` if they are disabled.
+    pub fn local_timestamps(&self) -> Option<TimestampPrescaler> {
+        self.local_timestamps
+    }
+
+    /// How often the ITM emits global timestamps.
+    pub fn global_timestamps(&self) -> GlobalTimestampFrequency {
+        self.global_timestamps
+    }
+
+    /// The frequency of the ITM local timestamp clock in Hz, or `None` if local
+    /// timestamps are disabled.
+    ///
+    /// This assumes that the TPIU clock is the processor clock, which clocks the
+    /// timestamp counter.
+    pub fn timestamp_frequency(&self) -> Option<u32> {
+        self.local_timestamps
+            .map(|prescaler| self.tpiu_clk / prescaler.divisor())
+    }
 }
 
 /// An interface to operate SWO to be implemented on drivers that support SWO.
@@ -146,10 +198,35 @@ pub trait SwoAccess {
     fn swo_buffer_size(&mut self) -> Option<usize> {
         None
     }
+
+    /// Request the status of the probe SWO capture buffer.
+    ///
+    /// The overrun flag reports whether data was lost since the previous call, and is
+    /// cleared by this call.
+    ///
+    /// The default implementation only reports the buffer size.
+    fn swo_status(&mut self) -> Result<SwoStatus, Error> {
+        Ok(SwoStatus {
+            buffer_size: self.swo_buffer_size(),
+            ..Default::default()
+        })
+    }
+}
+
+/// The status of the probe SWO capture buffer.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SwoStatus {
+    /// The number of bytes in the capture buffer which were not read yet, if the probe
+    /// reports it.
+    pub buffered: Option<usize>,
+    /// The size of the capture buffer, if known.
+    pub buffer_size: Option<usize>,
+    /// Whether SWO data was lost because the capture buffer was full.
+    pub overrun: bool,
 }
 
 /// Helper function to compute a poll interval from a SwoConfig and SWO buffer size.
-pub(crate) fn poll_interval_from_buf_size(
+pub fn poll_interval_from_buf_size(
     config: &SwoConfig,
     buf_size: usize,
 ) -> Option<std::time::Duration> {
@@ -166,10 +243,22 @@ pub(crate) fn poll_interval_from_buf_size(
     Some(std::time::Duration::from_millis(time_to_full_ms as u64 / 4))
 }
 
+/// A callback which is invoked when the capture buffer reaches the high watermark.
+type WatermarkCallback<'a> = Box<dyn FnMut(&SwoStatus) + 'a>;
+
 /// A reader interface to pull SWO data from the underlying driver.
+///
+/// Besides reading the raw SWO data through [`std::io::Read`], the reader can decode it
+/// into ITM packets with [`SwoReader::read_packets`], which marks data lost in the probe
+/// with [`TracePacket::Overrun`].
 pub struct SwoReader<'a> {
     interface: &'a mut Box<dyn ArmProbeInterface>,
     buf: Vec<u8>,
+    decoder: ItmDecoder,
+    /// An overrun which was reported by [`SwoReader::status`], but not marked in the decoded
+    /// stream yet.
+    overrun: bool,
+    high_watermark: Option<(usize, WatermarkCallback<'a>)>,
 }
 
 impl<'a> SwoReader<'a> {
@@ -177,10 +266,66 @@ impl<'a> SwoReader<'a> {
         Self {
             interface,
             buf: Vec::new(),
+            decoder: ItmDecoder::new(),
+            overrun: false,
+            high_watermark: None,
         }
     }
-}
 
+    /// Request the status of the probe SWO capture buffer.
+    ///
+    /// The fill level is only reported by probes whose protocol exposes it, e.g.
+    /// CMSIS-DAP. Overruns are reported by CMSIS-DAP and J-Link probes.
+    pub fn status(&mut self) -> Result<SwoStatus, Error> {
+        let status = self.interface.swo_status()?;
+        self.overrun |= status.overrun;
+        Ok(status)
+    }
+
+    /// Invoke `callback` whenever [`SwoReader::read_packets`] finds `level` or more bytes
+    /// in the capture buffer of the probe, or an overrun.
+    ///
+    /// This allows applications to throttle the target when the host falls behind, e.g.
+    /// by disabling noisy stimulus ports in `ITM_TER`. The reader borrows the probe, so the
+    /// callback should only record the request, and the application should access the
+    /// target after dropping the reader.
+    ///
+    /// If the probe doesn't report the fill level of its buffer, the size of the data
+    /// returned by the last read is used instead.
+    pub fn set_high_watermark(&mut self, level: usize, callback: impl FnMut(&SwoStatus) + 'a) {
+        self.high_watermark = Some((level, Box::new(callback)));
+    }
+
+    /// Read available SWO data, and decode it into ITM packets.
+    ///
+    /// If the probe reported an overrun, a [`TracePacket::Overrun`] is inserted before the
+    /// data which was read with it, and the packets continue after the next synchronization
+    /// packet. Packets which are split between reads are returned once they are complete.
+    pub fn read_packets(&mut self) -> Result<Vec<TracePacket>, Error> {
+        let mut data = std::mem::take(&mut self.buf);
+        data.append(&mut self.interface.read_swo()?);
+
+        let status = self.status()?;
+
+        if let Some((level, callback)) = &mut self.high_watermark {
+            let fill = status.buffered.unwrap_or(data.len());
+            if fill >= *level || status.overrun {
+                callback(&status);
+            }
+        }
+
+        let mut packets = Vec::new();
+
+        if std::mem::take(&mut self.overrun) {
+            log::warn!("SWO data was lost, the probe capture buffer overran");
+            packets.push(self.decoder.overrun());
+        }
+
+        packets.extend(self.decoder.decode(&data));
+
+        Ok(packets)
+    }
+}
 impl<'a> std::io::Read for SwoReader<'a> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         use core::cmp;
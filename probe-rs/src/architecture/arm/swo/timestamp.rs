@@ -0,0 +1,357 @@
+//! Timestamps of trace packets.
+//!
+//! The ITM emits local timestamp packets, which contain the number of timestamp clock
+//! cycles since the previous local timestamp. The [`TimestampCorrelator`] turns these
+//! deltas into absolute timestamps on the host time axis.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The prescaler of the ITM local timestamp counter.
+///
+/// The counter is clocked by the processor clock, divided by the prescaler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimestampPrescaler {
+    /// No prescaling.
+    Div1,
+    /// Divide by 4.
+    Div4,
+    /// Divide by 16.
+    Div16,
+    /// Divide by 64.
+    Div64,
+}
+
+impl TimestampPrescaler {
+    /// The divisor applied to the processor clock.
+    pub fn divisor(self) -> u32 {
+        match self {
+            TimestampPrescaler::Div1 => 1,
+            TimestampPrescaler::Div4 => 4,
+            TimestampPrescaler::Div16 => 16,
+            TimestampPrescaler::Div64 => 64,
+        }
+    }
+
+    /// The value of the `TSPrescale` field of `ITM_TCR`.
+    pub(crate) fn tcr_value(self) -> u32 {
+        match self {
+            TimestampPrescaler::Div1 => 0b00,
+            TimestampPrescaler::Div4 => 0b01,
+            TimestampPrescaler::Div16 => 0b10,
+            TimestampPrescaler::Div64 => 0b11,
+        }
+    }
+}
+
+/// How often the ITM emits global timestamp packets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GlobalTimestampFrequency {
+    /// No global timestamps are emitted.
+    Disabled,
+    /// A global timestamp is emitted approximately every 128 cycles.
+    Every128Cycles,
+    /// A global timestamp is emitted approximately every 8192 cycles.
+    Every8192Cycles,
+    /// A global timestamp is emitted after every packet, if the output FIFO is empty.
+    EveryPacket,
+}
+
+impl GlobalTimestampFrequency {
+    /// The value of the `GTSFREQ` field of `ITM_TCR`.
+    pub(crate) fn tcr_value(self) -> u32 {
+        match self {
+            GlobalTimestampFrequency::Disabled => 0b00,
+            GlobalTimestampFrequency::Every128Cycles => 0b01,
+            GlobalTimestampFrequency::Every8192Cycles => 0b10,
+            GlobalTimestampFrequency::EveryPacket => 0b11,
+        }
+    }
+}
+
+/// A decoded timestamp related trace packet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimestampEvent {
+    /// A local timestamp packet, with the number of timestamp clock cycles since the
+    /// previous local timestamp.
+    Local {
+        /// The raw timestamp delta.
+        delta: u32,
+    },
+    /// An overflow packet. Packets were lost, so the deltas since the previous local
+    /// timestamp are unknown.
+    Overflow,
+}
+
+/// The default number of timestamps used to estimate the timestamp clock.
+const DEFAULT_WINDOW: usize = 256;
+
+/// The maximum deviation of the estimated timestamp clock from its nominal frequency.
+const MAX_DEVIATION: f64 = 0.05;
+
+/// Converts local timestamp deltas into absolute timestamps on the host time axis.
+///
+/// The timestamp clock is assumed to run at its nominal frequency, e.g. the one returned by
+/// [`Session::trace_timebase`](crate::Session::trace_timebase). Because the clocks of the
+/// target and the host drift apart, the frequency and offset of the timestamp clock are
+/// continuously estimated from the times at which the host received the timestamps.
+///
+/// The returned timestamps are monotonic. They include the average latency between the
+/// target and the host, which is the same for all timestamps.
+#[derive(Debug, Clone)]
+pub struct TimestampCorrelator {
+    /// The estimated duration of a timestamp clock cycle, in seconds.
+    period: f64,
+    /// The nominal duration of a timestamp clock cycle, in seconds.
+    nominal_period: f64,
+    /// The timestamp clock cycles since the first timestamp.
+    ticks: u64,
+    /// The most recent timestamps, with the host time at which they were received.
+    samples: VecDeque<(u64, f64)>,
+    window: usize,
+    /// Set after an overflow, until the next local timestamp.
+    lost: bool,
+    last: Option<f64>,
+}
+
+impl TimestampCorrelator {
+    /// Create a correlator for a timestamp clock with the nominal frequency `frequency` in Hz.
+    pub fn new(frequency: u32) -> Self {
+        let period = 1.0 / frequency.max(1) as f64;
+
+        Self {
+            period,
+            nominal_period: period,
+            ticks: 0,
+            samples: VecDeque::new(),
+            window: DEFAULT_WINDOW,
+            lost: false,
+            last: None,
+        }
+    }
+
+    /// Estimate the timestamp clock from the most recent `samples` timestamps.
+    ///
+    /// A larger window averages out more of the latency jitter, a smaller one follows
+    /// changes of the drift more quickly. The default is 256.
+    #[must_use]
+    pub fn window(self, samples: usize) -> Self {
+        Self {
+            window: samples.max(2),
+            ..self
+        }
+    }
+
+    /// The estimated frequency of the timestamp clock, in Hz.
+    pub fn estimated_frequency(&self) -> f64 {
+        1.0 / self.period
+    }
+
+    /// Process a timestamp `event`, which the host received at `received`.
+    ///
+    /// `received` can be measured from any epoch, as long as it is the same for all
+    /// events. For a local timestamp, the absolute timestamp relative to the same epoch
+    /// is returned.
+    pub fn push(&mut self, event: TimestampEvent, received: Duration) -> Option<Duration> {
+        let delta = match event {
+            TimestampEvent::Local { delta } => delta,
+            TimestampEvent::Overflow => {
+                self.lost = true;
+                return None;
+            }
+        };
+
+        let received = received.as_secs_f64();
+
+        self.ticks += delta as u64;
+
+        if self.lost {
+            // The deltas of the lost timestamps are unknown. Continue from the clock
+            // cycles the current estimate expects at the time of reception, and start a
+            // new estimate of the offset with the frequency estimated so far.
+            if let Some(expected) = self.expected_ticks(received) {
+                self.ticks = self.ticks.max(expected);
+            }
+            self.samples.clear();
+            self.lost = false;
+        }
+
+        self.samples.push_back((self.ticks, received));
+        while self.samples.len() > self.window {
+            self.samples.pop_front();
+        }
+
+        self.estimate_period();
+
+        let (mean_ticks, mean_received) = self.means();
+        let timestamp = mean_received + (self.ticks as f64 - mean_ticks) * self.period;
+
+        // Never go back in time, even if the estimate changed.
+        let timestamp = match self.last {
+            Some(last) => timestamp.max(last),
+            None => timestamp,
+        }
+        .max(0.0);
+
+        self.last = Some(timestamp);
+
+        Some(Duration::from_secs_f64(timestamp))
+    }
+
+    /// The timestamp clock cycles expected at host time `received`.
+    fn expected_ticks(&self, received: f64) -> Option<u64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let (mean_ticks, mean_received) = self.means();
+        let ticks = mean_ticks + (received - mean_received) / self.period;
+
+        Some(ticks.max(0.0) as u64)
+    }
+
+    fn means(&self) -> (f64, f64) {
+        let count = self.samples.len() as f64;
+        let (ticks, received) = self
+            .samples
+            .iter()
+            .fold((0.0, 0.0), |(ticks, received), (t, r)| {
+                (ticks + *t as f64, received + r)
+            });
+
+        (ticks / count, received / count)
+    }
+
+    /// Estimate the period of the timestamp clock with a linear regression of the host
+    /// reception times over the clock cycles.
+    fn estimate_period(&mut self) {
+        if self.samples.len() < 2 {
+            return;
+        }
+
+        let (mean_ticks, mean_received) = self.means();
+
+        let (covariance, variance) =
+            self.samples
+                .iter()
+                .fold((0.0, 0.0), |(covariance, variance), (ticks, received)| {
+                    let ticks = *ticks as f64 - mean_ticks;
+                    (
+                        covariance + ticks * (received - mean_received),
+                        variance + ticks * ticks,
+                    )
+                });
+
+        if variance == 0.0 {
+            return;
+        }
+
+        let min = self.nominal_period * (1.0 - MAX_DEVIATION);
+        let max = self.nominal_period * (1.0 + MAX_DEVIATION);
+
+        self.period = (covariance / variance).clamp(min, max);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A synthetic stream of local timestamps, emitted every millisecond by a timestamp
+    /// clock running at `frequency` Hz, and received with a latency between 0.5 and 1.5 ms.
+    fn stream(frequency: f64, count: usize) -> Vec<(TimestampEvent, Duration, f64)> {
+        let mut random = 0x1234_5678u32;
+        let mut previous = 0u64;
+
+        (1..=count)
+            .map(|i| {
+                let time = i as f64 * 0.001;
+                let ticks = (time * frequency) as u64;
+
+                // xorshift, for a reproducible jitter.
+                random ^= random << 13;
+                random ^= random >> 17;
+                random ^= random << 5;
+                let latency = 0.0005 + (random % 1000) as f64 * 0.000_001;
+
+                let event = TimestampEvent::Local {
+                    delta: (ticks - previous) as u32,
+                };
+                previous = ticks;
+
+                (event, Duration::from_secs_f64(time + latency), time)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drift_is_corrected() {
+        // The clock runs 0.5% faster than its nominal frequency, so that after 2 seconds,
+        // the nominal frequency would be off by 10 ms.
+        let mut correlator = TimestampCorrelator::new(1_000_000);
+        let mut last = Duration::ZERO;
+
+        for (i, (event, received, time)) in stream(1_005_000.0, 2000).into_iter().enumerate() {
+            let timestamp = correlator.push(event, received).unwrap();
+
+            assert!(timestamp >= last);
+            last = timestamp;
+
+            if i >= 500 {
+                // The average latency of 1 ms is included in the timestamp.
+                let error = timestamp.as_secs_f64() - (time + 0.001);
+                assert!(error.abs() < 0.0005, "error {} at {}", error, i);
+            }
+        }
+
+        let estimated = correlator.estimated_frequency();
+        assert!((estimated - 1_005_000.0).abs() < 1_000.0, "{}", estimated);
+    }
+
+    #[test]
+    fn overflow_resynchronizes() {
+        let mut correlator = TimestampCorrelator::new(1_000_000);
+        let mut last = Duration::ZERO;
+
+        for (i, (event, received, time)) in stream(1_002_000.0, 2000).into_iter().enumerate() {
+            // The packets between 1000 and 1100 are lost.
+            if (1000..1100).contains(&i) {
+                if i == 1000 {
+                    assert_eq!(correlator.push(TimestampEvent::Overflow, received), None);
+                }
+                continue;
+            }
+
+            let timestamp = correlator.push(event, received).unwrap();
+
+            assert!(timestamp >= last);
+            last = timestamp;
+
+            if i >= 1400 {
+                let error = timestamp.as_secs_f64() - (time + 0.001);
+                assert!(error.abs() < 0.0005, "error {} at {}", error, i);
+            }
+        }
+    }
+
+    #[test]
+    fn timestamps_are_monotonic() {
+        let mut correlator = TimestampCorrelator::new(1_000_000).window(4);
+
+        // A late reception followed by an early one must not move time backwards.
+        let first = correlator
+            .push(
+                TimestampEvent::Local { delta: 1000 },
+                Duration::from_millis(10),
+            )
+            .unwrap();
+        let second = correlator
+            .push(
+                TimestampEvent::Local { delta: 1000 },
+                Duration::from_millis(2),
+            )
+            .unwrap();
+
+        assert!(second >= first);
+    }
+}
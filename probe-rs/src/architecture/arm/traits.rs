@@ -1,7 +1,9 @@
-use crate::{DebugProbe, DebugProbeError};
+use std::time::Duration;
+
+use crate::{DebugProbe, DebugProbeError, VendorCommand, VendorCommandIndex};
 
 /// The type of port we are using.
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum PortType {
     /// Debug Port (e.g. SWD or JTAG)
     DebugPort,
@@ -29,7 +31,7 @@ bitfield::bitfield! {
 }
 
 /// Debug port address.
-#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Serialize, Deserialize)]
 pub enum DpAddress {
     /// Access the single DP on the bus, assuming there is only one.
     /// Will cause corruption if multiple are present.
@@ -120,6 +122,54 @@ pub trait RawDapAccess {
         Ok(())
     }
 
+    /// Read a DAP register repeatedly, until `value & mask == expected`, or `timeout` passed.
+    ///
+    /// Returns the matching value, or `None` after the timeout. The probe reads the register
+    /// on its own, without a round trip to the host for each read.
+    ///
+    /// This is only implemented by probes with the
+    /// [`poll_offload`](crate::ProbeCapabilities::poll_offload) capability.
+    ///
+    /// Only the lowest 4 bits of `addr` are used. Bank switching is the caller's responsibility.
+    fn raw_poll_register(
+        &mut self,
+        _port: PortType,
+        _addr: u8,
+        _mask: u32,
+        _expected: u32,
+        _timeout: Duration,
+    ) -> Result<Option<u32>, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "polling a register",
+        ))
+    }
+
+    /// Queue `command` behind the register transfers issued before it, and return the index
+    /// of its response, see [`RawDapAccess::raw_vendor_command_response`].
+    ///
+    /// This is only implemented by probes whose
+    /// [`vendor_commands`](crate::ProbeCapabilities::vendor_commands) capability isn't
+    /// `Unsupported`. With `Flushed`, the caller flushes the queued transfers first.
+    fn raw_vendor_command(
+        &mut self,
+        _command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        ))
+    }
+
+    /// Returns the response of the vendor command at `index`. The caller flushes the queued
+    /// transfers first.
+    fn raw_vendor_command_response(
+        &mut self,
+        _index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        ))
+    }
+
     /// Send a specific output sequence over JTAG or SWD.
     ///
     /// This can only be used for output, and should be used to generate
@@ -224,4 +274,24 @@ pub trait DapAccess {
         }
         Ok(())
     }
+
+    /// Read an Access Port register repeatedly, until `value & mask == expected`, or
+    /// `timeout` passed, see [`RawDapAccess::raw_poll_register`].
+    ///
+    /// Returns the matching value, or `None` after the timeout.
+    ///
+    /// Highest 4 bits of `addr` are interpreted as the bank number, implementations
+    /// will do bank switching if necessary.
+    fn poll_raw_ap_register(
+        &mut self,
+        _ap: ApAddress,
+        _addr: u8,
+        _mask: u32,
+        _expected: u32,
+        _timeout: Duration,
+    ) -> Result<Option<u32>, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "polling a register",
+        ))
+    }
 }
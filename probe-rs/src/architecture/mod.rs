@@ -2,3 +2,5 @@
 
 pub mod arm;
 pub mod riscv;
+pub mod route;
+pub mod settle;
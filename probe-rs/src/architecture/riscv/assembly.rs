@@ -20,6 +20,16 @@ pub const fn sw(offset: u32, base: u32, width: u32, source: u32) -> u32 {
     offset_upper << 25 | source << 20 | base << 15 | width << 12 | offset_lower << 7 | opcode
 }
 
+/// Assemble a `ld` instruction, which loads 64 bits on a hart with 64-bit registers.
+pub fn ld(offset: u16, base: u8, destination: u8) -> u32 {
+    lw(offset, base, 0b011, destination)
+}
+
+/// Assemble a `sd` instruction, which stores 64 bits on a hart with 64-bit registers.
+pub const fn sd(offset: u32, base: u32, source: u32) -> u32 {
+    sw(offset, base, 0b011, source)
+}
+
 /// Assemble a `addi` instruction.
 pub fn addi(source: u8, destination: u8, immediate: u16) -> u32 {
     let opcode = 0b001_0011;
@@ -28,6 +38,119 @@ pub fn addi(source: u8, destination: u8, immediate: u16) -> u32 {
     i_type_instruction(opcode, source, function, destination, immediate)
 }
 
+/// Assemble a `bne` instruction, which branches by `offset` bytes if the registers differ.
+pub fn bne(source1: u8, source2: u8, offset: i16) -> u32 {
+    let opcode = 0b110_0011;
+    let funct3 = 0b001;
+
+    assert!(source1 <= 0x1f && source2 <= 0x1f);
+    assert!((-4096..4096).contains(&offset) && offset % 2 == 0);
+
+    let immediate = offset as u32 & 0x1fff;
+
+    ((immediate >> 12) & 0b1) << 31
+        | ((immediate >> 5) & 0b11_1111) << 25
+        | (source2 as u32) << 20
+        | (source1 as u32) << 15
+        | funct3 << 12
+        | ((immediate >> 1) & 0b1111) << 8
+        | ((immediate >> 11) & 0b1) << 7
+        | opcode
+}
+
+/// Assemble a `fmv.x.w` instruction, which moves the lower 32 bits of the FPR `source` to
+/// the GPR `destination`.
+pub fn fmv_x_w(destination: u8, source: u8) -> u32 {
+    fmv(0b111_0000, destination, source)
+}
+
+/// Assemble a `fmv.x.d` instruction, which moves the 64 bits of the FPR `source` to the GPR
+/// `destination`. Only harts with 64-bit registers have it.
+pub fn fmv_x_d(destination: u8, source: u8) -> u32 {
+    fmv(0b111_0001, destination, source)
+}
+
+/// Assemble a `fmv.w.x` instruction, which moves the lower 32 bits of the GPR `source` to
+/// the FPR `destination`.
+pub fn fmv_w_x(destination: u8, source: u8) -> u32 {
+    fmv(0b111_1000, destination, source)
+}
+
+/// Assemble a `fmv.d.x` instruction, which moves the 64 bits of the GPR `source` to the FPR
+/// `destination`. Only harts with 64-bit registers have it.
+pub fn fmv_d_x(destination: u8, source: u8) -> u32 {
+    fmv(0b111_1001, destination, source)
+}
+
+/// Assemble one of the `fmv` instructions, which move the bits of a register between the
+/// GPRs and the FPRs without a conversion.
+fn fmv(funct7: u32, destination: u8, source: u8) -> u32 {
+    let opcode = 0b101_0011;
+
+    assert!(destination <= 0x1f && source <= 0x1f);
+
+    funct7 << 25 | (source as u32) << 15 | (destination as u32) << 7 | opcode
+}
+
+/// Assemble a `lr.w` instruction, which loads the word at `base` and reserves it for a
+/// `sc.w`.
+pub fn lr_w(destination: u8, base: u8) -> u32 {
+    amo(0b00010, destination, base, 0)
+}
+
+/// Assemble a `sc.w` instruction, which stores `source` at `base` if the reservation of the
+/// preceding `lr.w` still holds, and writes 0 to `destination` if it did.
+pub fn sc_w(destination: u8, source: u8, base: u8) -> u32 {
+    amo(0b00011, destination, base, source)
+}
+
+/// Assemble a `amoor.w` instruction, which sets the bits of `source` in the word at `base`,
+/// and loads the previous value of the word into `destination`.
+pub fn amoor_w(destination: u8, source: u8, base: u8) -> u32 {
+    amo(0b01000, destination, base, source)
+}
+
+/// Assemble a `amoand.w` instruction, which clears the bits of the word at `base` which are
+/// clear in `source`, and loads the previous value of the word into `destination`.
+pub fn amoand_w(destination: u8, source: u8, base: u8) -> u32 {
+    amo(0b01100, destination, base, source)
+}
+
+/// Assemble one of the word-sized instructions of the A extension, without the `aq` and
+/// `rl` bits.
+fn amo(funct5: u32, destination: u8, base: u8, source: u8) -> u32 {
+    let opcode = 0b010_1111;
+    let funct3 = 0b010;
+
+    assert!(destination <= 0x1f && base <= 0x1f && source <= 0x1f);
+
+    funct5 << 27
+        | (source as u32) << 20
+        | (base as u32) << 15
+        | funct3 << 12
+        | (destination as u32) << 7
+        | opcode
+}
+
+/// Assemble an `or` instruction.
+pub fn or(destination: u8, source1: u8, source2: u8) -> u32 {
+    r_type_instruction(0b110, destination, source1, source2)
+}
+
+/// Assemble an `and` instruction.
+pub fn and(destination: u8, source1: u8, source2: u8) -> u32 {
+    r_type_instruction(0b111, destination, source1, source2)
+}
+
+/// Assemble one of the register-register instructions of the base ISA with a `funct7` of 0.
+fn r_type_instruction(funct3: u32, rd: u8, rs1: u8, rs2: u8) -> u32 {
+    let opcode = 0b011_0011;
+
+    assert!(rd <= 0x1f && rs1 <= 0x1f && rs2 <= 0x1f);
+
+    (rs2 as u32) << 20 | (rs1 as u32) << 15 | funct3 << 12 | (rd as u32) << 7 | opcode
+}
+
 // We need to perform the csrr instruction, which reads a CSR.
 // This is a pseudo instruction, which actually is encoded as a
 // csrrs instruction, with the rs1 register being x0,
@@ -77,7 +200,10 @@ fn i_type_instruction(opcode: u8, rs1: u8, funct3: u8, rd: u8, imm: u16) -> u32
 
 #[cfg(test)]
 mod test {
-    use super::{csrr, csrw, lw, sw};
+    use super::{
+        amoand_w, amoor_w, and, bne, csrr, csrw, fmv_d_x, fmv_w_x, fmv_x_d, fmv_x_w, ld, lr_w, lw,
+        or, sc_w, sd, sw,
+    };
 
     #[test]
     fn assemble_csrr() {
@@ -126,4 +252,67 @@ mod test {
 
         assert_eq!(assembled, expected);
     }
+
+    #[test]
+    fn assemble_ld() {
+        // Assembly output of assembly 'ld      s1, 0(s0)'
+        //
+        let expected = 0x00043483;
+
+        let assembled = ld(0, 8, 9);
+
+        assert_eq!(assembled, expected);
+    }
+
+    #[test]
+    fn assemble_sd() {
+        // Assembly output of assembly 'sd      s1, 0(s0)'
+        //
+        let expected = 0x00943023;
+
+        let assembled = sd(0, 8, 9);
+
+        assert_eq!(assembled, expected);
+    }
+
+    #[test]
+    fn assemble_bne() {
+        // Assembly output of assembly 'bne     a0, a1, -16'
+        //
+        let expected = 0xfeb518e3;
+
+        let assembled = bne(10, 11, -16);
+
+        assert_eq!(assembled, expected);
+    }
+
+    #[test]
+    fn assemble_fmv() {
+        // Assembly output of assembly 'fmv.x.w a0, fa0', 'fmv.x.d a0, fa0', 'fmv.w.x fa0, a0'
+        // and 'fmv.d.x fa0, a0'
+        //
+        assert_eq!(fmv_x_w(10, 10), 0xe0050553);
+        assert_eq!(fmv_x_d(10, 10), 0xe2050553);
+        assert_eq!(fmv_w_x(10, 10), 0xf0050553);
+        assert_eq!(fmv_d_x(10, 10), 0xf2050553);
+    }
+
+    #[test]
+    fn assemble_atomics() {
+        // Assembly output of assembly 'lr.w s1, (s0)', 'sc.w a0, a1, (s0)',
+        // 'amoor.w s1, s1, (s0)' and 'amoand.w s1, s1, (s0)'
+        //
+        assert_eq!(lr_w(9, 8), 0x100424af);
+        assert_eq!(sc_w(10, 11, 8), 0x18b4252f);
+        assert_eq!(amoor_w(9, 9, 8), 0x409424af);
+        assert_eq!(amoand_w(9, 9, 8), 0x609424af);
+    }
+
+    #[test]
+    fn assemble_or_and() {
+        // Assembly output of assembly 'or a1, s1, a0' and 'and a1, s1, a0'
+        //
+        assert_eq!(or(11, 9, 10), 0x00a4e5b3);
+        assert_eq!(and(11, 9, 10), 0x00a4f5b3);
+    }
 }
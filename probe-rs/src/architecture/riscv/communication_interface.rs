@@ -16,7 +16,9 @@ use crate::{
 use crate::{MemoryInterface, Probe};
 
 use crate::{probe::JTAGAccess, Error as ProbeRsError, RegisterId};
+use crate::{AtomicOperation, Deadline, DebugModuleDescriptor, Intrusiveness, TargetOperation};
 
+use crate::config::RiscvQuirks;
 use crate::memory::valid_32_address;
 
 use bitfield::bitfield;
@@ -64,6 +66,23 @@ pub enum RiscvError {
     /// The given trigger type is not available for the address breakpoint.
     #[error("Unexpected trigger type {0} for address breakpoint.")]
     UnexpectedTriggerType(u32),
+    /// The probe returned fewer bytes than the JTAG register has.
+    #[error("The probe returned {actual} bytes for a JTAG register of {expected} bytes.")]
+    InvalidJtagResponse {
+        /// The number of bytes of the register.
+        expected: usize,
+        /// The number of bytes returned by the probe.
+        actual: usize,
+    },
+    /// Some of the selected harts are running while others are halted.
+    #[error("Some harts are running while some are halted, this should not happen.")]
+    InconsistentHartStatus,
+    /// The debug module did not become active after `dmactive` was set.
+    #[error("The debug module could not be activated.")]
+    DebugModuleInactive,
+    /// The reservation of a `lr.w` was lost before the `sc.w` in every attempt.
+    #[error("The reservation of an atomic operation was lost {0} times in a row.")]
+    ReservationLost(usize),
 }
 
 impl From<RiscvError> for ProbeRsError {
@@ -115,8 +134,8 @@ impl AbstractCommandErrorKind {
             4 => HaltResume,
             5 => Bus,
             6 => _Reserved,
-            7 => Other,
-            _ => panic!("cmderr is a 3 bit value, values higher than 7 should not occur."),
+            // cmderr is a 3 bit value, a value which doesn't fit is treated like 7.
+            _ => Other,
         }
     }
 }
@@ -187,10 +206,15 @@ pub struct RiscvCommunicationInterfaceState {
     /// Number of data registers for abstract commands
     data_register_count: u8,
 
-    nscratch: u8,
+    /// The `hartinfo` register of the hart.
+    hartinfo: HartInformation,
 
     supports_autoexec: bool,
 
+    /// Whether `aarpostincrement` can be used to read runs of registers with autoexec. This
+    /// is assumed until a batched read which uses it fails.
+    supports_aarpostincrement: bool,
+
     /// Pointer to the configuration string
     confstrptr: Option<u128>,
 
@@ -205,11 +229,41 @@ pub struct RiscvCommunicationInterfaceState {
     /// describes, if the given register can be read / written with an
     /// abstract command
     abstract_cmd_register_info: HashMap<RegisterId, CoreRegisterAbstractCmdSupport>,
+
+    /// The `misa` register of the hart, if it was read already.
+    misa: Option<u32>,
+
+    /// The XLEN of the hart, the width of its GPRs as the `aarsize` of abstract commands, if
+    /// it was determined already, see [`RiscvCommunicationInterface::determine_xlen`].
+    xlen: Option<RiscvBusAccess>,
+
+    /// The number of address bits of the system bus.
+    sbasize: u8,
+
+    /// The cause in `dcsr` when the hart was last seen halted, or `None` if it was seen
+    /// running since.
+    halt_cause: Option<u32>,
+
+    /// Whether the cause in `dcsr` changed while the hart was seen halted.
+    halt_detected: bool,
+
+    /// The most intrusive operation the session allows, which decides whether the program
+    /// buffer may be used for memory accesses.
+    max_intrusiveness: Intrusiveness,
+
+    /// Timeout for polling the debug module, e.g. until an abstract command is finished.
+    timeout: Duration,
+
+    /// The deadline of the timed operation which is running, which bounds the polls.
+    deadline: Option<Deadline>,
 }
 
 /// Timeout for RISCV operations.
 const RISCV_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// The number of times a `lr.w` and `sc.w` are executed, before an atomic operation gives up.
+const RESERVATION_ATTEMPTS: usize = 16;
+
 impl RiscvCommunicationInterfaceState {
     /// Create a new interface state.
     pub fn new() -> Self {
@@ -226,10 +280,12 @@ impl RiscvCommunicationInterfaceState {
             // Set to the minimum here, will be set to the correct value below
             data_register_count: 1,
 
-            nscratch: 0,
+            hartinfo: HartInformation::default(),
 
             supports_autoexec: false,
 
+            supports_aarpostincrement: true,
+
             confstrptr: None,
 
             // Assume maximum value, will be determined exactly alter.
@@ -241,16 +297,185 @@ impl RiscvCommunicationInterfaceState {
             memory_access_info: HashMap::new(),
 
             abstract_cmd_register_info: HashMap::new(),
+
+            misa: None,
+
+            xlen: None,
+
+            sbasize: 0,
+
+            halt_cause: None,
+
+            halt_detected: false,
+
+            max_intrusiveness: Intrusiveness::default(),
+
+            timeout: RISCV_TIMEOUT,
+
+            deadline: None,
         }
     }
 
     /// Get the memory access method which should be used for an
     /// access with the specified width.
-    fn memory_access_method(&mut self, access_width: RiscvBusAccess) -> MemoryAccessMethod {
-        *self
+    ///
+    /// Accesses through the program buffer use the resources of the hart, so they fail if the
+    /// maximum intrusiveness of the session doesn't allow that.
+    fn memory_access_method(
+        &mut self,
+        access_width: RiscvBusAccess,
+    ) -> Result<MemoryAccessMethod, ProbeRsError> {
+        let method = *self
             .memory_access_info
             .entry(access_width)
-            .or_insert(MemoryAccessMethod::ProgramBuffer)
+            .or_insert(MemoryAccessMethod::ProgramBuffer);
+
+        if matches!(method, MemoryAccessMethod::ProgramBuffer) {
+            self.max_intrusiveness
+                .permit(TargetOperation::HartMemoryAccess)?;
+        }
+
+        Ok(method)
+    }
+
+    /// Returns the cheapest way to write `count` values with the program buffer.
+    fn progbuf_write(&self, count: usize) -> ProgbufWrite {
+        let mut cheapest = ProgbufWrite::WordAtATime;
+
+        if let Some((address, words)) = self.hartinfo.shadowed_data() {
+            // Only the shadowed registers which can also be written over DMI can be staged.
+            let words = words.min(self.data_register_count).min(12);
+            let program_len = if self.implicit_ebreak {
+                SHADOWED_WRITE_PROGRAM_LEN
+            } else {
+                SHADOWED_WRITE_PROGRAM_LEN + 1
+            };
+
+            if words >= 2 && program_len <= self.progbuf_size as usize {
+                for autoexec in [false, true] {
+                    if autoexec && !self.supports_autoexec {
+                        continue;
+                    }
+
+                    let candidate = ProgbufWrite::Shadowed {
+                        address,
+                        words,
+                        autoexec,
+                    };
+                    if candidate.cost(count) < cheapest.cost(count) {
+                        cheapest = candidate;
+                    }
+                }
+            }
+        }
+
+        cheapest
+    }
+}
+
+/// The fields of the `hartinfo` register, see [`Hartinfo`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct HartInformation {
+    /// The number of `dscratch` registers.
+    nscratch: u8,
+    /// True if the data registers are shadowed in the memory map of the hart, false if they
+    /// are shadowed in CSRs.
+    data_access: bool,
+    /// The number of 32-bit words which shadow the data registers.
+    data_size: u8,
+    /// The address of the shadowed data registers, a CSR number or a signed 12-bit address
+    /// in the memory map, relative to zero.
+    data_address: u16,
+}
+
+impl HartInformation {
+    fn new(hartinfo: &Hartinfo) -> Self {
+        Self {
+            nscratch: hartinfo.nscratch() as u8,
+            data_access: hartinfo.dataaccess(),
+            data_size: hartinfo.datasize() as u8,
+            data_address: hartinfo.dataaddr() as u16,
+        }
+    }
+
+    /// Returns the address and the number of words of the data registers, if they are
+    /// shadowed in the memory map of the hart.
+    fn shadowed_data(&self) -> Option<(i16, u8)> {
+        if !self.data_access || self.data_size == 0 {
+            return None;
+        }
+
+        // Sign extend the 12-bit address.
+        let address = ((self.data_address << 4) as i16) >> 4;
+
+        Some((address, self.data_size))
+    }
+}
+
+/// The number of instructions of the program which stores the values staged in the
+/// shadowed data registers, see [`ProgbufWrite::Shadowed`].
+const SHADOWED_WRITE_PROGRAM_LEN: usize = 6;
+
+/// The approximate number of DMI operations of an abstract command which accesses a register.
+const REGISTER_ACCESS_COST: usize = 6;
+
+/// The shortest run of consecutive registers which is read with autoexec. Shorter runs take
+/// fewer DMI operations with a command for each register.
+const AUTOEXEC_MIN_REGISTERS: usize = 4;
+
+/// The number of registers at the start of `registers` whose numbers are consecutive.
+fn consecutive_registers(registers: &[RegisterId]) -> usize {
+    registers
+        .windows(2)
+        .take_while(|pair| pair[1].0 == pair[0].0.wrapping_add(1))
+        .count()
+        + 1
+}
+
+/// How a block of values is written with the program buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProgbufWrite {
+    /// Each value is passed in `data0`, and stored by its own execution of the program
+    /// buffer.
+    WordAtATime,
+    /// Up to `words` values are staged in the data registers, which are shadowed at
+    /// `address` in the memory map of the hart, and stored by a single execution of the
+    /// program buffer. With `autoexec`, the execution is started by the write of the last
+    /// staged value.
+    Shadowed {
+        address: i16,
+        words: u8,
+        autoexec: bool,
+    },
+}
+
+impl ProgbufWrite {
+    /// The estimated number of DMI operations and executions of the program buffer to write
+    /// `count` values.
+    fn cost(&self, count: usize) -> usize {
+        match *self {
+            // A write of data0 and of the command, which is executed.
+            ProgbufWrite::WordAtATime => 3 * count,
+            ProgbufWrite::Shadowed {
+                words, autoexec, ..
+            } => {
+                let words = words as usize;
+                let batches = (count + words - 1) / words;
+
+                // A write of each value, and of the command for each batch, which is executed.
+                // `a0` and `a1` are saved and restored as well, and the end of the staged
+                // values is written to `a1`.
+                let cost = count + 2 * batches + 5 * REGISTER_ACCESS_COST;
+
+                if autoexec && count > words {
+                    // The command is only written for the first of the full batches, and
+                    // `abstractauto` is set and cleared.
+                    cost + 2 - (count / words - 1)
+                } else {
+                    cost
+                }
+            }
+        }
     }
 }
 
@@ -272,7 +497,18 @@ pub struct RiscvCommunicationInterface {
 impl<'probe> RiscvCommunicationInterface {
     /// Creates a new RISC-V communication interface with a given probe driver.
     pub fn new(probe: Box<dyn JTAGAccess>) -> Result<Self, (Box<dyn JTAGAccess>, DebugProbeError)> {
-        let state = RiscvCommunicationInterfaceState::new();
+        Self::with_timeout(probe, RISCV_TIMEOUT)
+    }
+
+    /// Creates a new RISC-V communication interface, which polls the debug module for at
+    /// most `timeout` before an operation fails.
+    pub(crate) fn with_timeout(
+        probe: Box<dyn JTAGAccess>,
+        timeout: Duration,
+    ) -> Result<Self, (Box<dyn JTAGAccess>, DebugProbeError)> {
+        let mut state = RiscvCommunicationInterfaceState::new();
+        state.timeout = timeout;
+
         let dtm = Dtm::new(probe).map_err(|(probe, e)| match e {
             RiscvError::DebugProbe(err) => (probe, err),
             other_error => (
@@ -284,12 +520,68 @@ impl<'probe> RiscvCommunicationInterface {
         let mut s = Self { dtm, state };
 
         if let Err(err) = s.enter_debug_mode() {
-            return Err((s.dtm.probe, DebugProbeError::from(anyhow!(err))));
+            return Err((
+                s.dtm.probe,
+                DebugProbeError::ArchitectureSpecific(Box::new(err)),
+            ));
         }
 
         Ok(s)
     }
 
+    /// Set the most intrusive operation the session allows, see
+    /// [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness).
+    pub(crate) fn set_max_intrusiveness(&mut self, max_intrusiveness: Intrusiveness) {
+        self.state.max_intrusiveness = max_intrusiveness;
+    }
+
+    /// Bound the polls of the debug module by `deadline`, see
+    /// [`CoreInterface::set_deadline`](crate::CoreInterface::set_deadline).
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        self.state.deadline = deadline;
+    }
+
+    /// Returns the number of DMI accesses which were completed so far, see
+    /// [`CoreInterface::completed_operations`](crate::CoreInterface::completed_operations).
+    pub(crate) fn completed_operations(&self) -> u64 {
+        self.dtm.completed_operations()
+    }
+
+    /// Returns the timeout for polling the debug module, which is shortened to the time
+    /// remaining until the deadline of the running operation.
+    pub(crate) fn timeout(&self) -> Duration {
+        match &self.state.deadline {
+            Some(deadline) => deadline.budget(self.state.timeout),
+            None => self.state.timeout,
+        }
+    }
+
+    /// Describes the capabilities of the Debug Module, as they were read while connecting.
+    pub(crate) fn debug_module(&self) -> DebugModuleDescriptor {
+        let version = match self.state.debug_version {
+            DebugModuleVersion::NoModule => "none".to_string(),
+            DebugModuleVersion::Version0_11 => "0.11".to_string(),
+            DebugModuleVersion::Version0_13 => "0.13".to_string(),
+            DebugModuleVersion::NonConforming => "non_conforming".to_string(),
+            DebugModuleVersion::Unknown(version) => format!("unknown ({})", version),
+        };
+
+        let shadowed_data = self.state.hartinfo.shadowed_data();
+
+        DebugModuleDescriptor {
+            version,
+            progbuf_size: self.state.progbuf_size,
+            implicit_ebreak: self.state.implicit_ebreak,
+            data_registers: self.state.data_register_count,
+            scratch_registers: self.state.hartinfo.nscratch,
+            shadowed_data_words: shadowed_data.map(|(_, words)| words).unwrap_or(0),
+            shadowed_data_address: shadowed_data.map(|(address, _)| address).unwrap_or(0),
+            supports_autoexec: self.state.supports_autoexec,
+            hartsellen: self.state.hartsellen,
+            harts: self.state.num_harts,
+        }
+    }
+
     /// Deassert the target reset.
     pub fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
         self.dtm.target_reset_deassert()
@@ -300,6 +592,34 @@ impl<'probe> RiscvCommunicationInterface {
         self.dtm.read_idcode()
     }
 
+    /// Work around the quirks of the Debug Module which have to be handled before it is used.
+    pub(crate) fn apply_quirks(&mut self, quirks: RiscvQuirks) -> Result<(), RiscvError> {
+        if quirks.reactivate_debug_module {
+            log::debug!("Reactivating the debug module");
+
+            self.write_dm_register(Dmcontrol(0))?;
+
+            let mut control = Dmcontrol(0);
+            control.set_dmactive(true);
+
+            // The first write of `dmactive` doesn't stick.
+            self.write_dm_register(control)?;
+            self.write_dm_register(control)?;
+
+            let control: Dmcontrol = self.read_dm_register()?;
+
+            if !control.dmactive() {
+                return Err(RiscvError::DebugModuleInactive);
+            }
+
+            // The writes while entering debug mode may have been ignored, so the debug
+            // module has to be examined again.
+            self.enter_debug_mode()?;
+        }
+
+        Ok(())
+    }
+
     fn enter_debug_mode(&mut self) -> Result<(), RiscvError> {
         // We need a jtag interface
 
@@ -354,7 +674,9 @@ impl<'probe> RiscvCommunicationInterface {
 
         let control: Dmcontrol = self.read_dm_register()?;
 
-        self.state.hartsellen = control.hartsel().count_ones() as u8;
+        // The implemented bits of hartsel are the lowest ones, so a readback
+        // with gaps in it is cut off at the first gap.
+        self.state.hartsellen = control.hartsel().trailing_ones() as u8;
 
         log::debug!("HARTSELLEN: {}", self.state.hartsellen);
 
@@ -364,8 +686,14 @@ impl<'probe> RiscvCommunicationInterface {
 
         let mut num_harts = 1;
 
+        let start_time = Instant::now();
+
         // Hart 0 exists on every chip
         for hart_index in 1..max_hart_index {
+            if start_time.elapsed() > self.timeout() {
+                return Err(RiscvError::Timeout);
+            }
+
             let mut control = Dmcontrol(0);
             control.set_dmactive(true);
             control.set_hartsel(hart_index);
@@ -397,7 +725,8 @@ impl<'probe> RiscvCommunicationInterface {
         // registers for abstract commands
         let abstractcs: Abstractcs = self.read_dm_register()?;
 
-        self.state.progbuf_size = abstractcs.progbufsize() as u8;
+        // The specification allows at most 16 words, a larger value can't be used.
+        self.state.progbuf_size = abstractcs.progbufsize().min(16) as u8;
         log::debug!("Program buffer size: {}", self.state.progbuf_size);
 
         self.state.data_register_count = abstractcs.datacount() as u8;
@@ -409,8 +738,15 @@ impl<'probe> RiscvCommunicationInterface {
         // determine more information about hart
         let hartinfo: Hartinfo = self.read_dm_register()?;
 
-        self.state.nscratch = hartinfo.nscratch() as u8;
-        log::debug!("Number of dscratch registers: {}", self.state.nscratch);
+        self.state.hartinfo = HartInformation::new(&hartinfo);
+        log::debug!(
+            "Number of dscratch registers: {}",
+            self.state.hartinfo.nscratch
+        );
+        log::debug!(
+            "Shadowed data registers: {:?}",
+            self.state.hartinfo.shadowed_data()
+        );
 
         // determine if autoexec works
         let mut abstractauto = Abstractauto(0);
@@ -435,6 +771,8 @@ impl<'probe> RiscvCommunicationInterface {
         // the system bus access conforms to the debug
         // specification 13.2.
         if sbcs.sbversion() == 1 {
+            self.state.sbasize = sbcs.sbasize() as u8;
+
             // When possible, we use system bus access for memory access
 
             if sbcs.sbaccess8() {
@@ -473,6 +811,15 @@ impl<'probe> RiscvCommunicationInterface {
             );
         }
 
+        // The XLEN can only be determined while the hart is halted. A running hart is
+        // examined when it is halted.
+        let status: Dmstatus = self.read_dm_register()?;
+        if status.allhalted() {
+            if let Err(error) = self.determine_xlen() {
+                log::debug!("Failed to determine XLEN, assuming 32 bits: {}", error);
+            }
+        }
+
         Ok(())
     }
 
@@ -491,17 +838,26 @@ impl<'probe> RiscvCommunicationInterface {
         Ok(register_value)
     }
 
+    /// Read the `dmstatus` register of the debug module.
+    pub(crate) fn read_dmstatus(&mut self) -> Result<Dmstatus, RiscvError> {
+        self.read_dm_register()
+    }
+
     /// Read from a DM register
     ///
     /// Use the [`read_dm_register`] function if possible.
     fn read_dm_register_untyped(&mut self, address: u64) -> Result<u32, RiscvError> {
         // Prepare the read by sending a read request with the register address
-        self.dtm
-            .dmi_register_access_with_timeout(address, 0, DmiOperation::Read, RISCV_TIMEOUT)?;
+        self.dtm.dmi_register_access_with_timeout(
+            address,
+            0,
+            DmiOperation::Read,
+            self.timeout(),
+        )?;
 
         // Read back the response from the previous request.
         self.dtm
-            .dmi_register_access_with_timeout(0, 0, DmiOperation::NoOp, RISCV_TIMEOUT)
+            .dmi_register_access_with_timeout(0, 0, DmiOperation::NoOp, self.timeout())
     }
 
     pub(super) fn write_dm_register<R: DebugRegister>(
@@ -528,7 +884,7 @@ impl<'probe> RiscvCommunicationInterface {
             address,
             value,
             DmiOperation::Write,
-            RISCV_TIMEOUT,
+            self.timeout(),
         )?;
 
         Ok(())
@@ -591,8 +947,22 @@ impl<'probe> RiscvCommunicationInterface {
         Ok(())
     }
 
+    /// Split `address` into the values of `sbaddress1` and `sbaddress0`, if the system bus
+    /// has enough address bits for it.
+    fn sbaddress(&self, address: u64) -> Result<(u32, u32), RiscvError> {
+        let high = (address >> 32) as u32;
+
+        if high != 0 && self.state.sbasize <= 32 {
+            return Err(RiscvError::SystemBusAccess);
+        }
+
+        Ok((high, address as u32))
+    }
+
     /// Perform a single read from a memory location, using system bus access.
-    fn perform_memory_read_sysbus<V: RiscvValue>(&mut self, address: u32) -> Result<V, RiscvError> {
+    fn perform_memory_read_sysbus<V: RiscvValue>(&mut self, address: u64) -> Result<V, RiscvError> {
+        let (high, low) = self.sbaddress(address)?;
+
         let mut sbcs = Sbcs(0);
 
         sbcs.set_sbaccess(V::WIDTH as u32);
@@ -600,7 +970,12 @@ impl<'probe> RiscvCommunicationInterface {
 
         self.write_dm_register(sbcs)?;
 
-        self.write_dm_register(Sbaddress0(address))?;
+        // The write of `sbaddress0` starts the read, so it is written last. `sbaddress1` is
+        // always written on a wide bus, as it keeps the value of the previous access.
+        if self.state.sbasize > 32 {
+            self.write_dm_register(Sbaddress1(high))?;
+        }
+        self.write_dm_register(Sbaddress0(low))?;
         let data = self.read_large_dtm_register::<V, Sbdata>()?;
 
         // Check that the read was succesful
@@ -618,9 +993,11 @@ impl<'probe> RiscvCommunicationInterface {
     /// Only reads up to a width of 32 bits are currently supported.
     fn perform_memory_read_multiple_sysbus<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &mut [V],
     ) -> Result<(), RiscvError> {
+        let (high, low) = self.sbaddress(address)?;
+
         let mut sbcs = Sbcs(0);
 
         sbcs.set_sbaccess(V::WIDTH as u32);
@@ -632,7 +1009,10 @@ impl<'probe> RiscvCommunicationInterface {
 
         self.schedule_write_dm_register(sbcs)?;
 
-        self.schedule_write_dm_register(Sbaddress0(address))?;
+        if self.state.sbasize > 32 {
+            self.schedule_write_dm_register(Sbaddress1(high))?;
+        }
+        self.schedule_write_dm_register(Sbaddress0(low))?;
 
         let data_len = data.len();
 
@@ -679,19 +1059,19 @@ impl<'probe> RiscvCommunicationInterface {
     /// Only reads up to a width of 32 bits are currently supported.
     fn perform_memory_read_progbuf<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
     ) -> Result<V, RiscvError> {
         // assemble
         //  lb s1, 0(s0)
 
         // Backup register s0
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
 
         let lw_command: u32 = assembly::lw(0, 8, V::WIDTH as u8, 8);
 
         self.setup_program_buffer(&[lw_command])?;
 
-        self.write_dm_register(Data0(address))?;
+        self.write_xlen_argument(address)?;
 
         // Write s0, then execute program buffer
         let mut command = AccessRegisterCommand(0);
@@ -699,8 +1079,8 @@ impl<'probe> RiscvCommunicationInterface {
         command.set_transfer(true);
         command.set_write(true);
 
-        // registers are 32 bit, so we have size 2 here
-        command.set_aarsize(RiscvBusAccess::A32);
+        // The registers are written with all XLEN bits.
+        command.set_aarsize(self.xlen());
         command.set_postexec(true);
 
         // register s0, ie. 0x1008
@@ -720,19 +1100,54 @@ impl<'probe> RiscvCommunicationInterface {
         let value = self.abstract_cmd_register_read(&register::S0)?;
 
         // Restore s0 register
-        self.abstract_cmd_register_write(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
 
         Ok(V::from_register_value(value))
     }
 
+    /// Perform a 64-bit memory read from a single location using the program buffer, with an
+    /// `ld` instruction, which only harts with 64-bit registers have.
+    fn perform_memory_read_progbuf_64(&mut self, address: u64) -> Result<u64, RiscvError> {
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+
+        self.setup_program_buffer(&[assembly::ld(0, 8, 8)])?;
+
+        self.write_xlen_argument(address)?;
+
+        // Write s0, then execute program buffer
+        let mut command = AccessRegisterCommand(0);
+        command.set_cmd_type(0);
+        command.set_transfer(true);
+        command.set_write(true);
+        command.set_aarsize(RiscvBusAccess::A64);
+        command.set_postexec(true);
+        command.set_regno((register::S0).id.0 as u32);
+
+        self.write_dm_register(command)?;
+
+        let status: Abstractcs = self.read_dm_register()?;
+
+        if status.cmderr() != 0 {
+            return Err(RiscvError::AbstractCommand(
+                AbstractCommandErrorKind::parse(status.cmderr() as u8),
+            ));
+        }
+
+        let value = self.abstract_cmd_register_read_xlen(&register::S0)?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+
+        Ok(value)
+    }
+
     fn perform_memory_read_multiple_progbuf<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &mut [V],
     ) -> Result<(), RiscvError> {
         // Backup registers s0 and s1
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
-        let s1 = self.abstract_cmd_register_read(&register::S1)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+        let s1 = self.abstract_cmd_register_read_xlen(&register::S1)?;
 
         // Load a word from address in register 8 (S0), with offset 0, into register 9 (S9)
         let lw_command: u32 = assembly::lw(0, 8, V::WIDTH as u8, 9);
@@ -742,7 +1157,7 @@ impl<'probe> RiscvCommunicationInterface {
             assembly::addi(8, 8, V::WIDTH.byte_width() as u16),
         ])?;
 
-        self.write_dm_register(Data0(address))?;
+        self.write_xlen_argument(address)?;
 
         // Write s0, then execute program buffer
         let mut command = AccessRegisterCommand(0);
@@ -750,8 +1165,8 @@ impl<'probe> RiscvCommunicationInterface {
         command.set_transfer(true);
         command.set_write(true);
 
-        // registers are 32 bit, so we have size 2 here
-        command.set_aarsize(RiscvBusAccess::A32);
+        // The registers are written with all XLEN bits.
+        command.set_aarsize(self.xlen());
         command.set_postexec(true);
 
         // register s0, ie. 0x1008
@@ -767,7 +1182,7 @@ impl<'probe> RiscvCommunicationInterface {
             command.set_transfer(true);
             command.set_write(false);
 
-            // registers are 32 bit, so we have size 2 here
+            // The value is in the lower 32 bits, which can be read on every hart.
             command.set_aarsize(RiscvBusAccess::A32);
             command.set_postexec(true);
 
@@ -793,8 +1208,8 @@ impl<'probe> RiscvCommunicationInterface {
             ));
         }
 
-        self.abstract_cmd_register_write(&register::S0, s0)?;
-        self.abstract_cmd_register_write(&register::S1, s1)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S1, s1)?;
 
         Ok(())
     }
@@ -802,9 +1217,11 @@ impl<'probe> RiscvCommunicationInterface {
     /// Memory write using system bus
     fn perform_memory_write_sysbus<V: RiscvValue>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &[V],
     ) -> Result<(), RiscvError> {
+        let (high, low) = self.sbaddress(address)?;
+
         let mut sbcs = Sbcs(0);
 
         // Set correct access width
@@ -813,7 +1230,10 @@ impl<'probe> RiscvCommunicationInterface {
 
         self.schedule_write_dm_register(sbcs)?;
 
-        self.schedule_write_dm_register(Sbaddress0(address))?;
+        if self.state.sbasize > 32 {
+            self.schedule_write_dm_register(Sbaddress1(high))?;
+        }
+        self.schedule_write_dm_register(Sbaddress0(low))?;
 
         for value in data {
             self.schedule_write_large_dtm_register::<V, Sbdata>(*value)?;
@@ -843,7 +1263,7 @@ impl<'probe> RiscvCommunicationInterface {
     /// Only writes up to a width of 32 bits are currently supported.
     fn perform_memory_write_progbuf<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: V,
     ) -> Result<(), RiscvError> {
         log::debug!(
@@ -853,18 +1273,19 @@ impl<'probe> RiscvCommunicationInterface {
         );
 
         // Backup registers s0 and s1
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
-        let s1 = self.abstract_cmd_register_read(&register::S1)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+        let s1 = self.abstract_cmd_register_read_xlen(&register::S1)?;
 
         let sw_command = assembly::sw(0, 8, V::WIDTH as u32, 9);
 
         self.setup_program_buffer(&[sw_command])?;
 
         // write address into s0
-        self.abstract_cmd_register_write(&register::S0, address)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, address)?;
 
         // write data into data 0
-        self.write_dm_register(Data0(data.into()))?;
+        let value: u32 = data.into();
+        self.write_xlen_argument(value.into())?;
 
         // Write s1, then execute program buffer
         let mut command = AccessRegisterCommand(0);
@@ -872,8 +1293,8 @@ impl<'probe> RiscvCommunicationInterface {
         command.set_transfer(true);
         command.set_write(true);
 
-        // registers are 32 bit, so we have size 2 here
-        command.set_aarsize(RiscvBusAccess::A32);
+        // The registers are written with all XLEN bits.
+        command.set_aarsize(self.xlen());
         command.set_postexec(true);
 
         // register s1, ie. 0x1009
@@ -897,8 +1318,55 @@ impl<'probe> RiscvCommunicationInterface {
 
         // Restore register s0 and s1
 
-        self.abstract_cmd_register_write(&register::S0, s0)?;
-        self.abstract_cmd_register_write(&register::S1, s1)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S1, s1)?;
+
+        Ok(())
+    }
+
+    /// Perform a 64-bit memory write to a single location using the program buffer, with an
+    /// `sd` instruction, which only harts with 64-bit registers have.
+    fn perform_memory_write_progbuf_64(
+        &mut self,
+        address: u64,
+        data: u64,
+    ) -> Result<(), RiscvError> {
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+        let s1 = self.abstract_cmd_register_read_xlen(&register::S1)?;
+
+        self.setup_program_buffer(&[assembly::sd(0, 8, 9)])?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, address)?;
+
+        self.write_large_dtm_register::<u64, Arg0>(data)?;
+
+        // Write s1, then execute program buffer
+        let mut command = AccessRegisterCommand(0);
+        command.set_cmd_type(0);
+        command.set_transfer(true);
+        command.set_write(true);
+        command.set_aarsize(RiscvBusAccess::A64);
+        command.set_postexec(true);
+        command.set_regno((register::S1).id.0 as u32);
+
+        self.write_dm_register(command)?;
+
+        let status: Abstractcs = self.read_dm_register()?;
+
+        if status.cmderr() != 0 {
+            let error = AbstractCommandErrorKind::parse(status.cmderr() as u8);
+
+            log::error!(
+                "Executing the abstract command for write_word_64 failed: {:?} ({:x?})",
+                error,
+                status,
+            );
+
+            return Err(RiscvError::AbstractCommand(error));
+        }
+
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S1, s1)?;
 
         Ok(())
     }
@@ -907,11 +1375,11 @@ impl<'probe> RiscvCommunicationInterface {
     /// Only writes up to a width of 32 bits are currently supported.
     fn perform_memory_write_multiple_progbuf<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &[V],
     ) -> Result<(), RiscvError> {
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
-        let s1 = self.abstract_cmd_register_read(&register::S1)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+        let s1 = self.abstract_cmd_register_read_xlen(&register::S1)?;
 
         // Setup program buffer for multiple writes
         // Store value from register s9 into memory,
@@ -924,11 +1392,12 @@ impl<'probe> RiscvCommunicationInterface {
         ])?;
 
         // write address into s0
-        self.abstract_cmd_register_write(&register::S0, address)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, address)?;
 
         for value in data {
             // write address into data 0
-            self.write_dm_register(Data0((*value).into()))?;
+            let value: u32 = (*value).into();
+            self.write_xlen_argument(value.into())?;
 
             // Write s0, then execute program buffer
             let mut command = AccessRegisterCommand(0);
@@ -936,8 +1405,8 @@ impl<'probe> RiscvCommunicationInterface {
             command.set_transfer(true);
             command.set_write(true);
 
-            // registers are 32 bit, so we have size 2 here
-            command.set_aarsize(RiscvBusAccess::A32);
+            // The registers are written with all XLEN bits.
+            command.set_aarsize(self.xlen());
             command.set_postexec(true);
 
             // register s1
@@ -966,8 +1435,129 @@ impl<'probe> RiscvCommunicationInterface {
 
         // Restore register s0 and s1
 
-        self.abstract_cmd_register_write(&register::S0, s0)?;
-        self.abstract_cmd_register_write(&register::S1, s1)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S1, s1)?;
+
+        Ok(())
+    }
+
+    /// Perform multiple memory writes to consecutive locations using the program buffer,
+    /// staging up to `words` values at a time in the data registers which are shadowed at
+    /// `shadow` in the memory map of the hart, see [`ProgbufWrite::Shadowed`].
+    ///
+    /// Only writes up to a width of 32 bits are supported, each staged value takes a whole
+    /// data register.
+    fn perform_memory_write_multiple_shadowed<V: RiscvValue32>(
+        &mut self,
+        address: u64,
+        data: &[V],
+        shadow: i16,
+        words: u8,
+        autoexec: bool,
+    ) -> Result<(), RiscvError> {
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+        let s1 = self.abstract_cmd_register_read_xlen(&register::S1)?;
+        let a0 = self.abstract_cmd_register_read_xlen(&register::A0)?;
+        let a1 = self.abstract_cmd_register_read_xlen(&register::A1)?;
+
+        // Point a0 to the shadowed data registers, and copy the staged values from there to
+        // the address in s0, until a0 reaches the end of the staged values in a1.
+        self.setup_program_buffer(&[
+            assembly::addi(0, 10, shadow as u16 & 0xfff),
+            assembly::lw(0, 10, RiscvBusAccess::A32 as u8, 9),
+            assembly::sw(0, 8, V::WIDTH as u32, 9),
+            assembly::addi(8, 8, V::WIDTH.byte_width() as u16),
+            assembly::addi(10, 10, 4),
+            assembly::bne(10, 11, -16),
+        ])?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, address)?;
+
+        // The last batch may stage fewer values, which moves the end in a1.
+        let full_len = data.len() - data.len() % words as usize;
+        let (full, rest) = data.split_at(full_len);
+
+        if !full.is_empty() {
+            self.write_shadowed_batches(full, shadow, words as usize, autoexec)?;
+        }
+        if !rest.is_empty() {
+            self.write_shadowed_batches(rest, shadow, rest.len(), false)?;
+        }
+
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S1, s1)?;
+        self.abstract_cmd_register_write_xlen(&register::A0, a0)?;
+        self.abstract_cmd_register_write_xlen(&register::A1, a1)?;
+
+        Ok(())
+    }
+
+    /// Stage `data` in batches of `batch_len` values in the shadowed data registers, and
+    /// execute the program buffer after each batch, see
+    /// [`Self::perform_memory_write_multiple_shadowed`].
+    ///
+    /// All batches are sent to the probe at once.
+    fn write_shadowed_batches<V: RiscvValue32>(
+        &mut self,
+        data: &[V],
+        shadow: i16,
+        batch_len: usize,
+        autoexec: bool,
+    ) -> Result<(), RiscvError> {
+        // `addi` sign extends the address of the shadowed registers in a0 to XLEN bits.
+        let staged_end = shadow as i64 + 4 * batch_len as i64;
+        self.abstract_cmd_register_write_xlen(&register::A1, staged_end as u64)?;
+
+        // Only execute the program buffer, without a register transfer.
+        let mut postexec = AccessRegisterCommand(0);
+        postexec.set_postexec(true);
+
+        // The first batch writes the command, after which autoexec repeats it.
+        let autoexec = autoexec && data.len() > batch_len;
+
+        for (index, batch) in data.chunks(batch_len).enumerate() {
+            for (register, value) in batch.iter().enumerate() {
+                self.schedule_write_dm_register_untyped(
+                    (Data0::ADDRESS as usize + register) as u64,
+                    (*value).into(),
+                )?;
+            }
+
+            if index == 0 || !autoexec {
+                self.schedule_write_dm_register(AccessRegisterCommand(postexec.0))?;
+            }
+
+            if index == 0 && autoexec {
+                let mut abstractauto = Abstractauto(0);
+                abstractauto.set_autoexecdata(1 << (batch_len - 1));
+                self.schedule_write_dm_register(abstractauto)?;
+            }
+        }
+
+        if autoexec {
+            self.schedule_write_dm_register(Abstractauto(0))?;
+        }
+
+        // Errors are sticky, so we can just check at the end if everything worked.
+        let status_index = self.schedule_read_dm_register::<Abstractcs>()?;
+        let result = self.execute()?;
+
+        let status = match result[status_index] {
+            CommandResult::U32(status) => Abstractcs(status),
+            _ => panic!("Internal error occurred."),
+        };
+
+        if status.cmderr() != 0 {
+            let error = AbstractCommandErrorKind::parse(status.cmderr() as u8);
+
+            log::error!(
+                "Executing the abstract command for a shadowed write failed: {:?} ({:x?})",
+                error,
+                status,
+            );
+
+            return Err(RiscvError::AbstractCommand(error));
+        }
 
         Ok(())
     }
@@ -1013,7 +1603,7 @@ impl<'probe> RiscvCommunicationInterface {
                 break;
             }
 
-            if start_time.elapsed() > RISCV_TIMEOUT {
+            if start_time.elapsed() > self.timeout() {
                 return Err(RiscvError::Timeout);
             }
         }
@@ -1059,13 +1649,138 @@ impl<'probe> RiscvCommunicationInterface {
         entry.unset(rw);
     }
 
+    /// The `misa` register of the hart, if it was read already.
+    pub(crate) fn misa(&self) -> Option<u32> {
+        self.state.misa
+    }
+
+    /// Remember the `misa` register of the hart, which doesn't change while debugging.
+    pub(crate) fn set_misa(&mut self, misa: u32) {
+        self.state.misa = Some(misa);
+    }
+
+    /// The XLEN of the hart, the width of its GPRs, as the `aarsize` of abstract commands.
+    ///
+    /// Until it was determined, see [`Self::determine_xlen`], a hart with 32-bit registers is
+    /// assumed.
+    pub(crate) fn xlen(&self) -> RiscvBusAccess {
+        self.state.xlen.unwrap_or(RiscvBusAccess::A32)
+    }
+
+    /// Determine the XLEN of the halted hart, by reading `s0` with 64 bits as the debug
+    /// specification suggests. A hart with 32-bit registers rejects the read.
+    ///
+    /// The XLEN doesn't change while debugging, so it is only determined once.
+    pub(crate) fn determine_xlen(&mut self) -> Result<RiscvBusAccess, RiscvError> {
+        if let Some(xlen) = self.state.xlen {
+            return Ok(xlen);
+        }
+
+        let mut command = AccessRegisterCommand(0);
+        command.set_cmd_type(0);
+        command.set_transfer(true);
+        command.set_aarsize(RiscvBusAccess::A64);
+        command.set_regno((register::S0).id.0 as u32);
+
+        let xlen = match self.execute_abstract_command(command.0) {
+            Ok(()) => RiscvBusAccess::A64,
+            Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
+                // cmderr is sticky, so it is cleared for the next command.
+                let mut abstractcs_clear = Abstractcs(0);
+                abstractcs_clear.set_cmderr(0x7);
+                self.write_dm_register(abstractcs_clear)?;
+
+                RiscvBusAccess::A32
+            }
+            Err(error) => return Err(error),
+        };
+
+        log::debug!("XLEN: {}", 8 * xlen.byte_width());
+        self.state.xlen = Some(xlen);
+
+        Ok(xlen)
+    }
+
+    /// The width with which the register `regno` is accessed: XLEN for the GPRs and the
+    /// CSRs, and for the FPRs 64 bits with the D extension, and 32 bits otherwise.
+    ///
+    /// The width of the FPRs is only known once `misa` was read.
+    pub(crate) fn register_width(&self, regno: RegisterId) -> RiscvBusAccess {
+        match regno.0 {
+            0x1020..=0x103f => match self.state.misa {
+                Some(misa) if misa & super::MISA_D != 0 => RiscvBusAccess::A64,
+                _ => RiscvBusAccess::A32,
+            },
+            _ => self.xlen(),
+        }
+    }
+
+    /// Write `value` to the argument of an abstract command which writes a register with
+    /// XLEN bits.
+    fn write_xlen_argument(&mut self, value: u64) -> Result<(), RiscvError> {
+        match self.xlen() {
+            RiscvBusAccess::A64 => self.write_large_dtm_register::<u64, Arg0>(value),
+            _ => self.write_dm_register(Data0(value as u32)),
+        }
+    }
+
+    /// Returns `address` if the hart can access it: every address on a hart with 64-bit
+    /// registers, and only 32-bit addresses otherwise.
+    fn valid_address(&self, address: u64) -> Result<u64, crate::Error> {
+        match self.xlen() {
+            RiscvBusAccess::A64 => Ok(address),
+            _ => Ok(valid_32_address(address)?.into()),
+        }
+    }
+
+    /// Remember the cause in `dcsr` of the halted hart, or `None` if the hart is running.
+    ///
+    /// The cause is only written when the hart halts, so a different cause than at the
+    /// previous status read, while the hart was halted at both, means that it ran and halted
+    /// again in between. `dcsr` has no sticky evidence of halts, so a halt with the same
+    /// cause is missed.
+    pub(crate) fn note_halt_cause(&mut self, cause: Option<u32>) {
+        let previous = std::mem::replace(&mut self.state.halt_cause, cause);
+
+        if let (Some(previous), Some(cause)) = (previous, cause) {
+            self.state.halt_detected |= previous != cause;
+        }
+    }
+
+    /// Returns whether a new halt was noted since this was last called.
+    pub(crate) fn take_halt_detected(&mut self) -> bool {
+        std::mem::take(&mut self.state.halt_detected)
+    }
+
     // Read a core register using an abstract command
     pub(crate) fn abstract_cmd_register_read(
         &mut self,
         regno: impl Into<RegisterId>,
     ) -> Result<u32, RiscvError> {
+        self.abstract_cmd_register_read_sized(regno.into())
+    }
+
+    /// Read a core register using an abstract command, with its width, see
+    /// [`Self::register_width`].
+    pub(crate) fn abstract_cmd_register_read_xlen(
+        &mut self,
+        regno: impl Into<RegisterId>,
+    ) -> Result<u64, RiscvError> {
         let regno = regno.into();
 
+        match self.register_width(regno) {
+            RiscvBusAccess::A64 => self.abstract_cmd_register_read_sized(regno),
+            _ => self
+                .abstract_cmd_register_read_sized::<u32>(regno)
+                .map(u64::from),
+        }
+    }
+
+    /// Read a core register using an abstract command, with the width of `V`.
+    fn abstract_cmd_register_read_sized<V: RiscvValue>(
+        &mut self,
+        regno: RegisterId,
+    ) -> Result<V, RiscvError> {
         // Check if the register was already tried via abstract cmd
         if !self.check_abstract_cmd_register_support(regno, CoreRegisterAbstractCmdSupport::READ) {
             return Err(RiscvError::AbstractCommand(
@@ -1077,7 +1792,7 @@ impl<'probe> RiscvCommunicationInterface {
         let mut command = AccessRegisterCommand(0);
         command.set_cmd_type(0);
         command.set_transfer(true);
-        command.set_aarsize(RiscvBusAccess::A32);
+        command.set_aarsize(V::WIDTH);
 
         command.set_regno(regno.0 as u32);
 
@@ -1094,9 +1809,237 @@ impl<'probe> RiscvCommunicationInterface {
             Err(e) => return Err(e),
         }
 
-        let register_value: Data0 = self.read_dm_register()?;
+        self.read_large_dtm_register::<V, Arg0>()
+    }
+
+    /// Schedule a read of a core register using an abstract command.
+    ///
+    /// The command is started and `data0` is read back without polling the busy flag
+    /// of `abstractcs` in between. The result is only valid if `abstractcs.cmderr` is
+    /// still zero after the batch has been executed, which has to be checked by the caller.
+    fn schedule_abstract_cmd_register_read(
+        &mut self,
+        regno: RegisterId,
+    ) -> Result<DeferredResultIndex, RiscvError> {
+        let mut command = AccessRegisterCommand(0);
+        command.set_cmd_type(0);
+        command.set_transfer(true);
+        command.set_aarsize(RiscvBusAccess::A32);
+
+        command.set_regno(regno.0 as u32);
+
+        self.schedule_write_dm_register(command)?;
+
+        Ok(self.schedule_read_dm_register::<Data0>()?)
+    }
+
+    /// Schedule the reads of the `count` registers starting at `first` with a single abstract
+    /// command. `aarpostincrement` advances the command to the next register, and with
+    /// `abstractauto`, each read of `data0` executes it again, so that every register only
+    /// costs a single DMI operation.
+    ///
+    /// If the command fails, `abstractauto` is not cleared by the write at the end of the run,
+    /// because writes to it are ignored while `abstractcs.cmderr` is set. The caller has to
+    /// clear it after clearing the error.
+    fn schedule_abstract_cmd_register_read_run(
+        &mut self,
+        first: RegisterId,
+        count: usize,
+    ) -> Result<Vec<DeferredResultIndex>, RiscvError> {
+        let mut command = AccessRegisterCommand(0);
+        command.set_cmd_type(0);
+        command.set_transfer(true);
+        command.set_aarsize(RiscvBusAccess::A32);
+        command.set_aarpostincrement(true);
+
+        command.set_regno(first.0 as u32);
+
+        self.schedule_write_dm_register(command)?;
+
+        let mut abstractauto = Abstractauto(0);
+        abstractauto.set_autoexecdata(1);
+        self.schedule_write_dm_register(abstractauto)?;
+
+        let mut results = Vec::with_capacity(count);
+
+        // Every read but the last one executes the command for the next register.
+        for _ in 1..count {
+            results.push(self.schedule_read_dm_register::<Data0>()?);
+        }
+
+        self.schedule_write_dm_register(Abstractauto(0))?;
+        results.push(self.schedule_read_dm_register::<Data0>()?);
+
+        Ok(results)
+    }
+
+    /// Read multiple core registers using abstract commands.
+    ///
+    /// All reads are executed in a single batch. If the Debug Module supports autoexec, runs
+    /// of consecutive registers are read with a single abstract command, see
+    /// [`Self::schedule_abstract_cmd_register_read_run`]. Only if an abstract command
+    /// fails, the registers from that command on are read again one by one, with polling of
+    /// the busy flag. The values read before it are kept.
+    pub(crate) fn abstract_cmd_register_read_batch(
+        &mut self,
+        regnos: &[RegisterId],
+    ) -> Result<Vec<u32>, RiscvError> {
+        // Registers which are known to be unsupported are never batched.
+        if regnos.iter().any(|regno| {
+            !self.check_abstract_cmd_register_support(*regno, CoreRegisterAbstractCmdSupport::READ)
+        }) {
+            return Err(RiscvError::AbstractCommand(
+                AbstractCommandErrorKind::NotSupported,
+            ));
+        }
+
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_ackhavereset(true);
+        dmcontrol.set_dmactive(true);
+        self.schedule_write_dm_register(dmcontrol)?;
+
+        // Clear any previous command error, cmderr is write-1-to-clear.
+        let mut abstractcs_clear = Abstractcs(0);
+        abstractcs_clear.set_cmderr(0x7);
+        self.schedule_write_dm_register(abstractcs_clear)?;
+
+        let autoexec = self.state.supports_autoexec && self.state.supports_aarpostincrement;
+        let mut used_autoexec = false;
+
+        // `abstractcs` is read after each command, so that the registers before the first
+        // failed command don't have to be read again.
+        let mut read_results = Vec::with_capacity(regnos.len());
+        let mut statuses = Vec::new();
+        let mut remaining = regnos;
+        while let Some(&first) = remaining.first() {
+            let run = consecutive_registers(remaining);
+            let start = regnos.len() - remaining.len();
+
+            if autoexec && run >= AUTOEXEC_MIN_REGISTERS {
+                read_results.extend(self.schedule_abstract_cmd_register_read_run(first, run)?);
+                used_autoexec = true;
+                remaining = &remaining[run..];
+            } else {
+                read_results.push(self.schedule_abstract_cmd_register_read(first)?);
+                remaining = &remaining[1..];
+            }
+
+            statuses.push((start, self.schedule_read_dm_register::<Abstractcs>()?));
+        }
+
+        let result = self.execute()?;
+
+        let word = |index: DeferredResultIndex| match result[index] {
+            CommandResult::U32(data) => data,
+            _ => panic!("Internal error occurred."),
+        };
+
+        // Once cmderr is set, no further commands are executed, so the values from the
+        // first failed command on are invalid.
+        let failed = statuses
+            .iter()
+            .find(|&&(_, abstractcs)| Abstractcs(word(abstractcs)).cmderr() != 0);
+
+        if let Some(&(start, abstractcs)) = failed {
+            log::debug!(
+                "Batched register read failed at {:?} ({:?}), reading the rest individually",
+                regnos[start],
+                Abstractcs(word(abstractcs))
+            );
+
+            let mut values: Vec<u32> = read_results[..start].iter().map(|&idx| word(idx)).collect();
+
+            // Clear the error, cmderr is write-1-to-clear.
+            let mut abstractcs_clear = Abstractcs(0);
+            abstractcs_clear.set_cmderr(0x7);
+            self.write_dm_register(abstractcs_clear)?;
+
+            if used_autoexec {
+                // While cmderr is set, writes to `abstractauto` are ignored, so the write at
+                // the end of the run may not have disabled autoexec. Otherwise, every read of
+                // `data0` below would execute the command again.
+                self.write_dm_register(Abstractauto(0))?;
+
+                // `aarpostincrement` is optional, so it isn't used again.
+                self.state.supports_aarpostincrement = false;
+            }
+
+            // Read the remaining registers using the polling path.
+            for regno in &regnos[start..] {
+                values.push(self.abstract_cmd_register_read(*regno)?);
+            }
+
+            return Ok(values);
+        }
+
+        Ok(read_results.iter().map(|&idx| word(idx)).collect())
+    }
+
+    /// Read multiple core registers of different sizes using abstract commands.
+    ///
+    /// All reads are executed in a single batch. Registers which are read with 64 bits, e.g.
+    /// the floating point registers of a hart with the D extension, are transferred in
+    /// `data0` and `data1`. If an abstract command fails, its error is returned.
+    pub(crate) fn abstract_cmd_register_read_batch_sized(
+        &mut self,
+        registers: &[(RegisterId, RiscvBusAccess)],
+    ) -> Result<Vec<u64>, RiscvError> {
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_ackhavereset(true);
+        dmcontrol.set_dmactive(true);
+        self.schedule_write_dm_register(dmcontrol)?;
+
+        // Clear any previous command error, cmderr is write-1-to-clear.
+        let mut abstractcs_clear = Abstractcs(0);
+        abstractcs_clear.set_cmderr(0x7);
+        self.schedule_write_dm_register(abstractcs_clear)?;
+
+        let mut read_results = Vec::with_capacity(registers.len());
+        for (regno, size) in registers {
+            let mut command = AccessRegisterCommand(0);
+            command.set_cmd_type(0);
+            command.set_transfer(true);
+            command.set_aarsize(*size);
+            command.set_regno(regno.0 as u32);
+
+            self.schedule_write_dm_register(command)?;
+
+            let low = self.schedule_read_dm_register::<Data0>()?;
+            let high = if *size == RiscvBusAccess::A64 {
+                Some(self.schedule_read_dm_register::<Data1>()?)
+            } else {
+                None
+            };
+            read_results.push((low, high));
+        }
+
+        let abstractcs_result = self.schedule_read_dm_register::<Abstractcs>()?;
+
+        let result = self.execute()?;
+
+        let abstractcs = match result[abstractcs_result] {
+            CommandResult::U32(res) => Abstractcs(res),
+            _ => panic!("Internal error occurred."),
+        };
+
+        if abstractcs.cmderr() != 0 {
+            return Err(RiscvError::AbstractCommand(
+                AbstractCommandErrorKind::parse(abstractcs.cmderr() as u8),
+            ));
+        }
+
+        let word = |index| match result[index] {
+            CommandResult::U32(data) => u64::from(data),
+            _ => panic!("Internal error occurred."),
+        };
 
-        Ok(register_value.into())
+        Ok(read_results
+            .iter()
+            .map(|&(low, high)| match high {
+                Some(high) => (word(high) << 32) | word(low),
+                None => word(low),
+            })
+            .collect())
     }
 
     pub(crate) fn abstract_cmd_register_write<V: RiscvValue>(
@@ -1138,11 +2081,32 @@ impl<'probe> RiscvCommunicationInterface {
         }
     }
 
+    /// Write a core register using an abstract command, with its width, see
+    /// [`Self::register_width`].
+    pub(crate) fn abstract_cmd_register_write_xlen(
+        &mut self,
+        regno: impl Into<RegisterId>,
+        value: u64,
+    ) -> Result<(), RiscvError> {
+        let regno = regno.into();
+
+        match self.register_width(regno) {
+            RiscvBusAccess::A64 => self.abstract_cmd_register_write(regno, value),
+            _ => self.abstract_cmd_register_write(regno, value as u32),
+        }
+    }
+
     /// Read the CSR progbuf register.
     pub fn read_csr_progbuf(&mut self, address: u16) -> Result<u32, RiscvError> {
+        self.read_csr_progbuf_xlen(address)
+            .map(|value| value as u32)
+    }
+
+    /// Read a CSR with the program buffer, with all XLEN bits.
+    pub(crate) fn read_csr_progbuf_xlen(&mut self, address: u16) -> Result<u64, RiscvError> {
         log::debug!("Reading CSR {:#04x}", address);
 
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
 
         // Read csr value into register 8 (s0)
         let csrr_cmd = assembly::csrr(8, address);
@@ -1156,23 +2120,32 @@ impl<'probe> RiscvCommunicationInterface {
         self.execute_abstract_command(postexec_cmd.0)?;
 
         // read the s0 value
-        let reg_value = self.abstract_cmd_register_read(&register::S0)?;
+        let reg_value = self.abstract_cmd_register_read_xlen(&register::S0)?;
 
         // restore original value in s0
-        self.abstract_cmd_register_write(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
 
         Ok(reg_value)
     }
 
     /// Write the CSR progbuf register.
     pub fn write_csr_progbuf(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+        self.write_csr_progbuf_xlen(address, value.into())
+    }
+
+    /// Write a CSR with the program buffer, with all XLEN bits.
+    pub(crate) fn write_csr_progbuf_xlen(
+        &mut self,
+        address: u16,
+        value: u64,
+    ) -> Result<(), RiscvError> {
         log::debug!("Writing CSR {:#04x}={}", address, value);
 
         // Backup register s0
-        let s0 = self.abstract_cmd_register_read(&register::S0)?;
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
 
         // Write value into s0
-        self.abstract_cmd_register_write(&register::S0, value)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, value)?;
 
         // Built the CSRW command to write into the program buffer
         let csrw_cmd = assembly::csrw(address, 8);
@@ -1186,11 +2159,182 @@ impl<'probe> RiscvCommunicationInterface {
 
         // command: transfer, regno = 0x1008
         // restore original value in s0
-        self.abstract_cmd_register_write(&register::S0, s0)?;
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
 
         Ok(())
     }
 
+    /// Read a FPR with the program buffer, by moving it to `s0` with `fmv.x.w`, or with
+    /// `fmv.x.d` if it is 64 bits wide.
+    ///
+    /// A hart with 32-bit registers can't move a 64-bit FPR to a GPR, so the read fails with
+    /// [`AbstractCommandErrorKind::NotSupported`] then.
+    ///
+    /// `fmv` raises an exception while `mstatus.FS` is Off, so the caller turns the FPU on
+    /// first.
+    pub(crate) fn read_fpr_progbuf(&mut self, regno: RegisterId) -> Result<u64, RiscvError> {
+        log::debug!("Reading FPR {:#06x}", regno.0);
+
+        let index = (regno.0 - 0x1020) as u8;
+        let (fmv, mask) = match (self.register_width(regno), self.xlen()) {
+            (RiscvBusAccess::A64, RiscvBusAccess::A64) => (assembly::fmv_x_d(8, index), u64::MAX),
+            (RiscvBusAccess::A64, _) => {
+                return Err(RiscvError::AbstractCommand(
+                    AbstractCommandErrorKind::NotSupported,
+                ))
+            }
+            // `fmv.x.w` sign extends the value to XLEN bits.
+            _ => (assembly::fmv_x_w(8, index), 0xffff_ffff),
+        };
+
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+
+        self.setup_program_buffer(&[fmv])?;
+
+        // command: postexec
+        let mut postexec_cmd = AccessRegisterCommand(0);
+        postexec_cmd.set_postexec(true);
+
+        self.execute_abstract_command(postexec_cmd.0)?;
+
+        let value = self.abstract_cmd_register_read_xlen(&register::S0)?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+
+        Ok(value & mask)
+    }
+
+    /// Write a FPR with the program buffer, by moving `value` from `s0` with `fmv.w.x`, or
+    /// with `fmv.d.x` if it is 64 bits wide, see [`Self::read_fpr_progbuf`].
+    pub(crate) fn write_fpr_progbuf(
+        &mut self,
+        regno: RegisterId,
+        value: u64,
+    ) -> Result<(), RiscvError> {
+        log::debug!("Writing FPR {:#06x}={}", regno.0, value);
+
+        let index = (regno.0 - 0x1020) as u8;
+        let fmv = match (self.register_width(regno), self.xlen()) {
+            (RiscvBusAccess::A64, RiscvBusAccess::A64) => assembly::fmv_d_x(index, 8),
+            (RiscvBusAccess::A64, _) => {
+                return Err(RiscvError::AbstractCommand(
+                    AbstractCommandErrorKind::NotSupported,
+                ))
+            }
+            _ => assembly::fmv_w_x(index, 8),
+        };
+
+        let s0 = self.abstract_cmd_register_read_xlen(&register::S0)?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, value)?;
+
+        self.setup_program_buffer(&[fmv])?;
+
+        // command: postexec
+        let mut postexec_cmd = AccessRegisterCommand(0);
+        postexec_cmd.set_postexec(true);
+
+        self.execute_abstract_command(postexec_cmd.0)?;
+
+        self.abstract_cmd_register_write_xlen(&register::S0, s0)?;
+
+        Ok(())
+    }
+
+    /// Perform `operation` on the word at `address` with the instructions of the A extension
+    /// in the program buffer, and return the previous value of the word.
+    ///
+    /// A fetch-or or fetch-and is a single `amoor.w` or `amoand.w`. A compare-and-swap, and
+    /// the AMOs which raise an exception, e.g. in memory which only supports reservations,
+    /// use a `lr.w` and a `sc.w`, which are executed again until the reservation held. The
+    /// registers used by the program buffer are restored afterwards.
+    pub(crate) fn atomic_progbuf(
+        &mut self,
+        address: u64,
+        operation: AtomicOperation,
+    ) -> Result<u32, RiscvError> {
+        log::debug!("Atomic {:?} at {:#010x} using progbuf", operation, address);
+
+        let registers = [&register::S0, &register::S1, &register::A0, &register::A1];
+
+        let mut saved = Vec::with_capacity(registers.len());
+        for register in registers {
+            saved.push(self.abstract_cmd_register_read_xlen(register)?);
+        }
+
+        let result = self.perform_atomic_progbuf(address, operation);
+
+        for (register, value) in registers.into_iter().zip(saved) {
+            self.abstract_cmd_register_write_xlen(register, value)?;
+        }
+
+        result
+    }
+
+    /// Perform `operation` with the program buffer, with the address in `s0`, see
+    /// [`Self::atomic_progbuf`]. The previous value is loaded into `s1`.
+    fn perform_atomic_progbuf(
+        &mut self,
+        address: u64,
+        operation: AtomicOperation,
+    ) -> Result<u32, RiscvError> {
+        // `lr.w` sign extends the word on a hart with 64-bit registers, so the operand is
+        // sign extended as well, for the comparison of a compare-and-swap.
+        let (amo, operand, new) = match operation {
+            AtomicOperation::Or(bits) => (Some(assembly::amoor_w(9, 9, 8)), bits, 0),
+            AtomicOperation::And(bits) => (Some(assembly::amoand_w(9, 9, 8)), bits, 0),
+            AtomicOperation::CompareAndSwap { expected, new } => (None, expected, new),
+        };
+        let operand = operand as i32 as u64;
+
+        let mut postexec_cmd = AccessRegisterCommand(0);
+        postexec_cmd.set_postexec(true);
+
+        self.abstract_cmd_register_write_xlen(&register::S0, address)?;
+
+        if let Some(amo) = amo {
+            self.abstract_cmd_register_write_xlen(&register::S1, operand)?;
+            self.setup_program_buffer(&[amo])?;
+
+            match self.execute_abstract_command(postexec_cmd.0) {
+                Ok(()) => {
+                    let previous = self.abstract_cmd_register_read_xlen(&register::S1)?;
+                    return Ok(previous as u32);
+                }
+                Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::Exception)) => {
+                    log::debug!("The AMO raised an exception, retrying with lr.w and sc.w");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        let update = match operation {
+            AtomicOperation::Or(_) => assembly::or(11, 9, 10),
+            AtomicOperation::And(_) => assembly::and(11, 9, 10),
+            // The `sc.w` is skipped if the word doesn't have the expected value.
+            AtomicOperation::CompareAndSwap { .. } => assembly::bne(9, 10, 8),
+        };
+        self.setup_program_buffer(&[assembly::lr_w(9, 8), update, assembly::sc_w(10, 11, 8)])?;
+
+        for _ in 0..RESERVATION_ATTEMPTS {
+            self.abstract_cmd_register_write_xlen(&register::A0, operand)?;
+            self.abstract_cmd_register_write_xlen(&register::A1, u64::from(new))?;
+
+            self.execute_abstract_command(postexec_cmd.0)?;
+
+            let previous = self.abstract_cmd_register_read_xlen(&register::S1)? as u32;
+
+            // `a0` is only written by the `sc.w`, with 0 if it stored the word.
+            if operation.apply(previous).is_none()
+                || self.abstract_cmd_register_read_xlen(&register::A0)? == 0
+            {
+                return Ok(previous);
+            }
+        }
+
+        Err(RiscvError::ReservationLost(RESERVATION_ATTEMPTS))
+    }
+
     fn read_large_dtm_register<V, R>(&mut self) -> Result<V, RiscvError>
     where
         V: RiscvValue,
@@ -1207,8 +2351,8 @@ impl<'probe> RiscvCommunicationInterface {
         V::write_to_register::<R>(self, value)
     }
 
-    fn read_word<V: RiscvValue32>(&mut self, address: u32) -> Result<V, crate::Error> {
-        let result = match self.state.memory_access_method(V::WIDTH) {
+    fn read_word<V: RiscvValue32>(&mut self, address: u64) -> Result<V, crate::Error> {
+        let result = match self.state.memory_access_method(V::WIDTH)? {
             MemoryAccessMethod::ProgramBuffer => self.perform_memory_read_progbuf(address)?,
             MemoryAccessMethod::SystemBus => self.perform_memory_read_sysbus(address)?,
             MemoryAccessMethod::AbstractCommand => {
@@ -1221,12 +2365,16 @@ impl<'probe> RiscvCommunicationInterface {
 
     fn read_multiple<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &mut [V],
     ) -> Result<(), crate::Error> {
         log::debug!("read_32 from {:#08x}", address);
 
-        match self.state.memory_access_method(RiscvBusAccess::A32) {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        match self.state.memory_access_method(RiscvBusAccess::A32)? {
             MemoryAccessMethod::ProgramBuffer => {
                 self.perform_memory_read_multiple_progbuf(address, data)?;
             }
@@ -1241,8 +2389,8 @@ impl<'probe> RiscvCommunicationInterface {
         Ok(())
     }
 
-    fn write_word<V: RiscvValue32>(&mut self, address: u32, data: V) -> Result<(), crate::Error> {
-        match self.state.memory_access_method(V::WIDTH) {
+    fn write_word<V: RiscvValue32>(&mut self, address: u64, data: V) -> Result<(), crate::Error> {
+        match self.state.memory_access_method(V::WIDTH)? {
             MemoryAccessMethod::ProgramBuffer => {
                 self.perform_memory_write_progbuf(address, data)?
             }
@@ -1257,14 +2405,27 @@ impl<'probe> RiscvCommunicationInterface {
 
     fn write_multiple<V: RiscvValue32>(
         &mut self,
-        address: u32,
+        address: u64,
         data: &[V],
     ) -> Result<(), crate::Error> {
-        match self.state.memory_access_method(V::WIDTH) {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        match self.state.memory_access_method(V::WIDTH)? {
             MemoryAccessMethod::SystemBus => self.perform_memory_write_sysbus(address, data)?,
-            MemoryAccessMethod::ProgramBuffer => {
-                self.perform_memory_write_multiple_progbuf(address, data)?
-            }
+            MemoryAccessMethod::ProgramBuffer => match self.state.progbuf_write(data.len()) {
+                ProgbufWrite::WordAtATime => {
+                    self.perform_memory_write_multiple_progbuf(address, data)?
+                }
+                ProgbufWrite::Shadowed {
+                    address: shadow,
+                    words,
+                    autoexec,
+                } => self.perform_memory_write_multiple_shadowed(
+                    address, data, shadow, words, autoexec,
+                )?,
+            },
             MemoryAccessMethod::AbstractCommand => {
                 unimplemented!("Memory access using abstract commands is not implemted")
             }
@@ -1273,13 +2434,38 @@ impl<'probe> RiscvCommunicationInterface {
         Ok(())
     }
 
+    /// The method for a 64-bit access with a single transfer, or `None` if it is split into
+    /// two 32-bit accesses.
+    ///
+    /// The system bus is used if it supports 64-bit accesses, and the program buffer if the
+    /// hart has 64-bit registers, unless 32-bit accesses use the system bus.
+    fn memory_access_method_64(&mut self) -> Result<Option<MemoryAccessMethod>, crate::Error> {
+        let info = &self.state.memory_access_info;
+        let system_bus_64 = matches!(
+            info.get(&RiscvBusAccess::A64),
+            Some(MemoryAccessMethod::SystemBus)
+        );
+        let system_bus_32 = matches!(
+            info.get(&RiscvBusAccess::A32),
+            Some(MemoryAccessMethod::SystemBus)
+        );
+
+        if system_bus_64 {
+            Ok(Some(MemoryAccessMethod::SystemBus))
+        } else if self.xlen() == RiscvBusAccess::A64 && !system_bus_32 {
+            Ok(Some(self.state.memory_access_method(RiscvBusAccess::A64)?))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Destruct the interface and return the stored probe driver.
     pub fn close(self) -> Probe {
         Probe::from_attached_probe(self.dtm.probe.into_probe())
     }
 
     pub(super) fn execute(&mut self) -> Result<Vec<CommandResult>, DebugProbeError> {
-        self.dtm.execute()
+        self.dtm.execute(self.timeout())
     }
 
     pub(super) fn schedule_write_dm_register<R: DebugRegister>(
@@ -1702,92 +2888,110 @@ impl RiscvValue for u128 {
 
 impl MemoryInterface for RiscvCommunicationInterface {
     fn supports_native_64bit_access(&mut self) -> bool {
-        false
+        matches!(self.memory_access_method_64(), Ok(Some(_)))
     }
 
     fn read_word_64(&mut self, address: u64) -> Result<u64, crate::error::Error> {
-        let address = valid_32_address(address)?;
-        let mut ret = self.read_word::<u32>(address)? as u64;
-        ret |= (self.read_word::<u32>(address + 4)? as u64) << 32;
+        let address = self.valid_address(address)?;
 
-        Ok(ret)
+        match self.memory_access_method_64()? {
+            Some(MemoryAccessMethod::SystemBus) => {
+                Ok(self.perform_memory_read_sysbus::<u64>(address)?)
+            }
+            Some(_) => Ok(self.perform_memory_read_progbuf_64(address)?),
+            None => {
+                let mut ret = self.read_word::<u32>(address)? as u64;
+                ret |= (self.read_word::<u32>(address + 4)? as u64) << 32;
+
+                Ok(ret)
+            }
+        }
     }
 
     fn read_word_32(&mut self, address: u64) -> Result<u32, crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         self.read_word(address)
     }
 
     fn read_word_8(&mut self, address: u64) -> Result<u8, crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("read_word_8 from {:#08x}", address);
         self.read_word(address)
     }
 
     fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), crate::error::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("read_64 from {:#08x}", address);
 
         for (i, d) in data.iter_mut().enumerate() {
-            *d = self.read_word_64((address + (i as u32 * 8)).into())?;
+            *d = self.read_word_64(address + i as u64 * 8)?;
         }
 
         Ok(())
     }
 
     fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("read_32 from {:#08x}", address);
         self.read_multiple(address, data)
     }
 
     /// Read 8-bit values from target memory.
     fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("read_8 from {:#08x}", address);
 
         self.read_multiple(address, data)
     }
 
     fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), crate::error::Error> {
-        let address = valid_32_address(address)?;
-        let low_word = data as u32;
-        let high_word = (data >> 32) as u32;
+        let address = self.valid_address(address)?;
 
-        self.write_word(address, low_word)?;
-        self.write_word(address + 4, high_word)
+        match self.memory_access_method_64()? {
+            Some(MemoryAccessMethod::SystemBus) => {
+                Ok(self.perform_memory_write_sysbus(address, &[data])?)
+            }
+            Some(_) => Ok(self.perform_memory_write_progbuf_64(address, data)?),
+            None => {
+                let low_word = data as u32;
+                let high_word = (data >> 32) as u32;
+
+                self.write_word(address, low_word)?;
+                self.write_word(address + 4, high_word)
+            }
+        }
     }
 
     fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         self.write_word(address, data)
     }
 
     fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         self.write_word(address, data)
     }
 
     fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), crate::error::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("write_64 to {:#08x}", address);
 
         for (i, d) in data.iter().enumerate() {
-            self.write_word_64((address + (i as u32 * 8)).into(), *d)?;
+            self.write_word_64(address + i as u64 * 8, *d)?;
         }
 
         Ok(())
     }
 
     fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("write_32 to {:#08x}", address);
 
         self.write_multiple(address, data)
     }
 
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), crate::Error> {
-        let address = valid_32_address(address)?;
+        let address = self.valid_address(address)?;
         log::debug!("write_8 to {:#08x}", address);
 
         self.write_multiple(address, data)
@@ -1,7 +1,4 @@
-use std::{
-    convert::TryInto,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
 use bitfield::bitfield;
 
@@ -26,6 +23,10 @@ pub struct Dtm {
 
     /// Number of address bits in the DMI register
     abits: u32,
+
+    /// Number of DMI accesses which were completed, including the ones the Debug Module
+    /// answered as busy.
+    completed_operations: u64,
 }
 
 impl Dtm {
@@ -38,7 +39,10 @@ impl Dtm {
             Err(e) => return Err((probe, e.into())),
         };
 
-        let dtmcs = Dtmcs(u32::from_le_bytes((&dtmcs_raw[..]).try_into().unwrap()));
+        let dtmcs = match register_value(&dtmcs_raw) {
+            Ok(value) => Dtmcs(value),
+            Err(e) => return Err((probe, e)),
+        };
 
         log::debug!("Dtmcs: {:?}", dtmcs);
 
@@ -59,9 +63,15 @@ impl Dtm {
             probe,
             abits,
             queued_commands: Vec::new(),
+            completed_operations: 0,
         })
     }
 
+    /// Returns the number of DMI accesses which were completed so far.
+    pub fn completed_operations(&self) -> u64 {
+        self.completed_operations
+    }
+
     pub fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
         self.probe.target_reset_deassert()
     }
@@ -69,7 +79,7 @@ impl Dtm {
     pub fn read_idcode(&mut self) -> Result<u32, DebugProbeError> {
         let value = self.probe.read_register(0x1, 32)?;
 
-        Ok(u32::from_le_bytes((&value[..]).try_into().unwrap()))
+        register_value(&value).map_err(|e| DebugProbeError::ArchitectureSpecific(Box::new(e)))
     }
 
     /// Clear the sticky error state (field *op* in the DMI register)
@@ -88,33 +98,67 @@ impl Dtm {
         Ok(())
     }
 
-    pub fn execute(&mut self) -> Result<Vec<CommandResult>, DebugProbeError> {
-        let cmds = self.queued_commands.clone();
-        self.queued_commands = Vec::new();
+    /// Execute all queued commands.
+    ///
+    /// Commands which fail because the DMI is busy are retried, until `timeout` expires.
+    pub fn execute(&mut self, timeout: Duration) -> Result<Vec<CommandResult>, DebugProbeError> {
+        let cmds = std::mem::take(&mut self.queued_commands);
 
-        match self.probe.write_register_batch(&cmds) {
-            Ok(r) => Ok(r),
-            Err(e) => match e.error {
-                DebugProbeError::ArchitectureSpecific(ref ae) => {
-                    match ae.downcast_ref::<RiscvError>() {
-                        Some(RiscvError::DmiTransfer(DmiOperationStatus::RequestInProgress)) => {
-                            self.reset()
-                                .map_err(|e| DebugProbeError::ArchitectureSpecific(Box::new(e)))?;
+        // Probes with a small command queue can only execute a limited number of commands at once.
+        let batch_size = self
+            .probe
+            .max_queued_operations()
+            .unwrap_or(cmds.len())
+            .max(1);
 
-                            // queue up the remaining commands when we retry
-                            self.queued_commands
-                                .extend_from_slice(&cmds[e.results.len()..]);
+        let mut results = Vec::with_capacity(cmds.len());
 
-                            self.probe.set_idle_cycles(self.probe.get_idle_cycles() + 1);
+        let start_time = Instant::now();
+
+        while results.len() < cmds.len() {
+            let batch_end = cmds.len().min(results.len() + batch_size);
 
-                            self.execute()
+            match self
+                .probe
+                .write_register_batch(&cmds[results.len()..batch_end])
+            {
+                Ok(r) => {
+                    self.completed_operations += r.len() as u64;
+                    results.extend(r);
+                }
+                Err(e) => match e.error {
+                    DebugProbeError::ArchitectureSpecific(ref ae) => {
+                        match ae.downcast_ref::<RiscvError>() {
+                            Some(RiscvError::DmiTransfer(
+                                DmiOperationStatus::RequestInProgress,
+                            )) => {
+                                self.reset().map_err(|e| {
+                                    DebugProbeError::ArchitectureSpecific(Box::new(e))
+                                })?;
+
+                                // retry the remaining commands
+                                self.completed_operations += e.results.len() as u64 + 1;
+                                results.extend(e.results);
+
+                                self.probe.set_idle_cycles(
+                                    self.probe.get_idle_cycles().saturating_add(1),
+                                );
+
+                                if start_time.elapsed() > timeout {
+                                    return Err(DebugProbeError::ArchitectureSpecific(Box::new(
+                                        RiscvError::Timeout,
+                                    )));
+                                }
+                            }
+                            _ => return Err(e.error),
                         }
-                        _ => Err(e.error),
                     }
-                }
-                _ => Err(e.error),
-            },
+                    _ => return Err(e.error),
+                },
+            }
         }
+
+        Ok(results)
     }
 
     pub fn schedule_dmi_register_access(
@@ -135,11 +179,7 @@ impl Dtm {
             address: DMI_ADDRESS,
             data: bytes.to_vec(),
             transform: |response_bytes| {
-                let response_value: u128 =
-                    response_bytes.iter().enumerate().fold(0, |acc, elem| {
-                        let (byte_offset, value) = elem;
-                        acc + ((*value as u128) << (8 * byte_offset))
-                    });
+                let response_value = dmi_response_value(&response_bytes);
 
                 // Verify that the transfer was ok
                 let op = (response_value & DMI_OP_MASK) as u8;
@@ -180,11 +220,9 @@ impl Dtm {
         let bit_size = self.abits + DMI_ADDRESS_BIT_OFFSET;
 
         let response_bytes = self.probe.write_register(DMI_ADDRESS, &bytes, bit_size)?;
+        self.completed_operations += 1;
 
-        let response_value: u128 = response_bytes.iter().enumerate().fold(0, |acc, elem| {
-            let (byte_offset, value) = elem;
-            acc + ((*value as u128) << (8 * byte_offset))
-        });
+        let response_value = dmi_response_value(&response_bytes);
 
         // Verify that the transfer was ok
         let op = (response_value & DMI_OP_MASK) as u8;
@@ -228,6 +266,36 @@ impl Dtm {
     }
 }
 
+/// Convert the bytes shifted out of a 32 bit JTAG register into its value.
+fn register_value(response: &[u8]) -> Result<u32, RiscvError> {
+    let mut bytes = [0u8; 4];
+
+    match response.get(..bytes.len()) {
+        Some(response) => bytes.copy_from_slice(response),
+        None => {
+            return Err(RiscvError::InvalidJtagResponse {
+                expected: bytes.len(),
+                actual: response.len(),
+            })
+        }
+    }
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Convert the bytes shifted out of the `dmi` register into its value.
+///
+/// The register is at most 128 bits wide, additional bytes returned by the probe are ignored.
+fn dmi_response_value(response: &[u8]) -> u128 {
+    response
+        .iter()
+        .take(16)
+        .enumerate()
+        .fold(0, |acc, (byte_offset, value)| {
+            acc | ((*value as u128) << (8 * byte_offset))
+        })
+}
+
 bitfield! {
     /// The `dtmcs` register is
     struct Dtmcs(u32);
@@ -0,0 +1,1043 @@
+//! A mock Debug Module, used to test the RISC-V communication interface without hardware.
+//!
+//! The mock implements [`JTAGAccess`] and models the subset of the RISC-V debug
+//! specification v0.13.2 which is used by probe-rs: the `dtmcs` and `dmi` JTAG registers,
+//! and a single hart with abstract command support for register access. The program buffer
+//! is written, but not executed, so that memory accesses through it only cost the same DMI
+//! operations as on a real Debug Module. The size of the program buffer, the data registers
+//! and their shadow in the memory map of the hart can be configured.
+//!
+//! If [`MockDebugModuleState::execute`] is set, the hart executes: the program buffer is
+//! executed after an abstract command with `postexec` set, with the loads, stores, CSR
+//! accesses and atomic instructions probe-rs uses, and a resumed hart runs until it reaches
+//! an `ebreak` or a trigger. The instructions of the code the hart runs are only decoded for
+//! their length.
+//!
+//! If [`MockDebugModuleState::system_bus`] is set, the Debug Module has a system bus master
+//! with 64 address bits, which accesses the same memory with 8 to 64 bits.
+//!
+//! The responses can be corrupted with a [`Corruption`], to test that the interface handles a
+//! faulty or hostile Debug Module without panicking.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::assembly::EBREAK;
+use crate::probe::{
+    BatchExecutionError, CommandResult, DebugProbe, DebugProbeSelector, JTAGAccess,
+    JtagWriteCommand,
+};
+use crate::{DebugProbeError, WireProtocol};
+
+/// `C.EBREAK`, the compressed `ebreak`.
+const C_EBREAK: u16 = 0x9002;
+
+/// The bit of the E base ISA in `misa`.
+const MISA_E: u32 = 1 << 4;
+
+/// The number of instructions a resumed hart executes before it is left running, and the
+/// number of instructions of the program buffer which are executed at most.
+const INSTRUCTION_BUDGET: usize = 256;
+
+const DTMCS_ADDRESS: u32 = 0x10;
+const DMI_ADDRESS: u32 = 0x11;
+
+/// Number of address bits in the mocked `dmi` register.
+const ABITS: u32 = 7;
+
+/// State of the mocked Debug Module, shared between the mock probe and the test.
+#[derive(Debug, Default)]
+pub(crate) struct MockDebugModuleState {
+    /// Number of probe round trips, i.e. single register accesses and executed batches.
+    pub transactions: usize,
+    /// Number of DMI operations which were not a NoOp.
+    pub dmi_operations: usize,
+    /// Core registers of the single hart, indexed by register number.
+    pub hart_registers: HashMap<u16, u32>,
+    /// Core registers of the single hart which are 64 bits wide, e.g. the floating point
+    /// registers with the D extension, indexed by register number. They can be read with 32
+    /// or 64 bits, the latter in `data0` and `data1`.
+    pub wide_registers: HashMap<u16, u64>,
+    /// The hart has 64-bit registers: the GPRs and CSRs can be accessed with 64 bits, in
+    /// `data0` and `data1`. If it executes, `ld` and `sd` use all 64 bits of the registers,
+    /// and the addresses of all loads and stores have 64 bits. The other instructions only
+    /// use the lower 32 bits, and sign extend their result.
+    pub rv64: bool,
+    /// Number of data0 accesses for which the abstract command is reported as busy.
+    pub busy_reads: usize,
+    /// Number of commands which are executed before `busy_reads` applies.
+    pub busy_after: usize,
+    /// The maximum number of writes in a batch, as reported to the DTM.
+    pub max_queued_operations: Option<usize>,
+    /// The number of writes in the largest batch which was executed.
+    pub largest_batch: usize,
+    /// Number of `dmstatus` reads after a resume request, before `allresumeack` is set.
+    /// `None` models a Debug Module which never sets it.
+    pub resume_ack_after: Option<usize>,
+    /// The hart has been reset, and `havereset` was not acknowledged yet.
+    pub havereset: bool,
+    /// `ackhavereset` does not clear `havereset`.
+    pub sticky_havereset: bool,
+    /// Number of writes to `dmcontrol` with `ackhavereset` set.
+    pub havereset_acks: usize,
+    /// The hart is running.
+    pub running: bool,
+    /// `resumereq` is set in `dmcontrol`, some Debug Modules only clear it when it is written.
+    pub resumereq: bool,
+    /// Number of writes to `dmcontrol` with `haltreq` set which the hart ignores, like a hart
+    /// whose clock is gated. A reset with `ndmreset` halts the hart anyway.
+    pub ignored_halt_requests: usize,
+    /// Corrupts the responses of the JTAG registers.
+    pub corruption: Option<Corruption>,
+    /// Abstract commands never finish, like a hart which is stuck in a bus access.
+    pub stalled_commands: bool,
+    /// Number of writes to `dmcontrol` which cleared `dmactive`.
+    pub deactivations: usize,
+    /// The time each probe round trip takes, like a probe with a high latency.
+    pub latency: Duration,
+    /// The instructions written to the program buffer, in order.
+    pub program_buffer_writes: Vec<u32>,
+    /// The `hartinfo` register. No data registers are shadowed by default.
+    pub hartinfo: u32,
+    /// The size of the program buffer and the number of data registers, if they differ from
+    /// the two program buffer words and the single data register of the default.
+    pub abstract_sizes: Option<(u32, u32)>,
+    /// `abstractauto` is implemented, so that writes of the data registers, and reads of
+    /// `data0`, can execute the last command again. The `regno` of a command with
+    /// `aarpostincrement` is incremented after each execution.
+    pub autoexec: bool,
+    /// Number of abstract commands which were executed.
+    pub executed_commands: usize,
+    /// The hart executes the program buffer and its code, see the module documentation.
+    pub execute: bool,
+    /// The memory of the hart, by address, which is accessed by the program buffer and holds
+    /// the code the hart runs, if `execute` is set, and which is accessed by the system bus.
+    /// Bytes which weren't written read as zero.
+    pub memory: HashMap<u64, u8>,
+    /// The Debug Module has a system bus master, see the module documentation.
+    pub system_bus: bool,
+    /// The number of `mcontrol` triggers of the hart, which are selected with `tselect` and
+    /// halt a hart which executes at their address.
+    pub triggers: usize,
+    /// The bits which another hart sets in the word of the next `lr.w`s, one entry per
+    /// `lr.w`, right after it was loaded. The write breaks the reservation.
+    pub concurrent_writes: Vec<u32>,
+    /// AMOs raise an exception, like in memory which only supports `lr.w` and `sc.w`.
+    pub amo_exceptions: bool,
+    /// The FPRs of the hart, by index, which abstract commands can't access. The `fmv`
+    /// instructions of the program buffer move them while `mstatus.FS` isn't Off, and
+    /// raise an exception otherwise. Moving a value to a FPR sets `mstatus.FS` to Dirty.
+    pub fp_registers: HashMap<u32, u64>,
+
+    dmcontrol: u32,
+    command: u32,
+    abstractauto: u32,
+    data0: u32,
+    data1: u32,
+    cmderr: u32,
+    busy: bool,
+    /// Value shifted out on the next DMI access.
+    pending_response: u32,
+    resumeack: bool,
+    /// Remaining `dmstatus` reads until `allresumeack` is set.
+    resume_ack_countdown: Option<usize>,
+    /// The contents of the program buffer.
+    program_buffer: [u32; 16],
+    /// The selected trigger.
+    tselect: u32,
+    /// `tdata1` and `tdata2` of the triggers which were written, by index.
+    trigger_data: HashMap<u32, (u32, u32)>,
+    /// The address reserved by the last `lr.w`, until the next `sc.w`.
+    reservation: Option<u64>,
+    /// The writable fields of `sbcs`, and `sberror`.
+    sbcs: u32,
+    /// `sbaddress1` and `sbaddress0`.
+    sbaddress: u64,
+    /// `sbdata1` and `sbdata0`.
+    sbdata: u64,
+}
+
+impl MockDebugModuleState {
+    fn dm_read(&mut self, address: u8) -> u32 {
+        match address {
+            // dmstatus: version 0.13, authenticated, implicit ebreak after the program buffer
+            0x11 => {
+                if let Some(countdown) = &mut self.resume_ack_countdown {
+                    if *countdown == 0 {
+                        self.resumeack = true;
+                        self.resume_ack_countdown = None;
+                    } else {
+                        *countdown -= 1;
+                    }
+                }
+
+                let mut status = 2 | (1 << 7) | (1 << 22);
+                status |= if self.running {
+                    (1 << 10) | (1 << 11)
+                } else {
+                    (1 << 8) | (1 << 9)
+                };
+                if self.resumeack {
+                    status |= (1 << 16) | (1 << 17);
+                }
+                if self.havereset {
+                    status |= (1 << 18) | (1 << 19);
+                }
+                status
+            }
+            // dmcontrol: only a single hart, hartsel is not writable
+            0x10 => self.dmcontrol & !(0x3ff_ffc0),
+            // abstractcs: two progbuf words and one data register, unless configured otherwise
+            0x16 => {
+                let (progbuf_size, data_count) = self.abstract_sizes.unwrap_or((2, 1));
+                (progbuf_size << 24) | (self.cmderr << 8) | ((self.busy as u32) << 12) | data_count
+            }
+            0x12 => self.hartinfo,
+            0x18 => self.abstractauto,
+            0x04 => {
+                let value = self.data0;
+
+                if self.busy {
+                    self.cmderr = 1;
+                }
+
+                if self.abstractauto & 1 != 0 {
+                    self.execute_command(self.command);
+                }
+
+                value
+            }
+            0x05 => self.data1,
+            // sbcs: version 1, 64 address bits, accesses of 8 to 64 bits
+            0x38 if self.system_bus => 1 << 29 | 64 << 5 | 0b1111 | self.sbcs,
+            0x39 => self.sbaddress as u32,
+            0x3a => (self.sbaddress >> 32) as u32,
+            0x3c => {
+                let value = self.sbdata as u32;
+
+                // sbreadondata
+                if self.sbcs & (1 << 15) != 0 {
+                    self.system_bus_read();
+                }
+
+                value
+            }
+            0x3d => (self.sbdata >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    /// The number of bytes of a system bus access, selected by `sbcs.sbaccess`.
+    fn system_bus_access_len(&self) -> u32 {
+        1 << ((self.sbcs >> 17) & 0b111)
+    }
+
+    /// Read `sbdata` at `sbaddress`, and increment the address if `sbautoincrement` is set.
+    fn system_bus_read(&mut self) {
+        let len = self.system_bus_access_len();
+        self.sbdata = self.read_memory(self.sbaddress, len);
+        self.system_bus_increment();
+    }
+
+    /// Write `sbdata` to `sbaddress`, and increment the address if `sbautoincrement` is set.
+    fn system_bus_write(&mut self) {
+        let len = self.system_bus_access_len();
+        self.write_memory(self.sbaddress, len, self.sbdata);
+        self.system_bus_increment();
+    }
+
+    fn system_bus_increment(&mut self) {
+        if self.sbcs & (1 << 16) != 0 {
+            self.sbaddress = self
+                .sbaddress
+                .wrapping_add(u64::from(self.system_bus_access_len()));
+        }
+    }
+
+    fn dm_write(&mut self, address: u8, value: u32) {
+        match address {
+            0x10 => {
+                self.dmcontrol = value;
+
+                if value & 1 == 0 {
+                    self.deactivations += 1;
+                }
+
+                if value & (1 << 28) != 0 {
+                    self.havereset_acks += 1;
+                    if !self.sticky_havereset {
+                        self.havereset = false;
+                    }
+                }
+
+                self.resumereq = value & (1 << 30) != 0;
+
+                if value & (1 << 1) != 0 {
+                    // ndmreset, the hart halts after the reset if haltreq is set.
+                    self.havereset = true;
+                    self.running = value & (1 << 31) == 0;
+                } else if value & (1 << 31) != 0 {
+                    if self.ignored_halt_requests > 0 {
+                        self.ignored_halt_requests -= 1;
+                    } else {
+                        self.running = false;
+                    }
+                } else if value & (1 << 30) != 0 && !self.running {
+                    self.running = true;
+                    self.resumeack = false;
+                    self.resume_ack_countdown = self.resume_ack_after;
+
+                    if self.execute {
+                        self.run();
+                    }
+                }
+            }
+            // cmderr is write-1-to-clear
+            0x16 => self.cmderr &= !((value >> 8) & 0x7),
+            0x04..=0x0f => {
+                match address {
+                    0x04 => self.data0 = value,
+                    0x05 => self.data1 = value,
+                    _ => (),
+                }
+
+                if self.abstractauto & (1 << (address - 0x04)) != 0 {
+                    self.execute_command(self.command);
+                }
+            }
+            0x17 => {
+                self.command = value;
+                self.execute_command(value);
+            }
+            // Writes are ignored while cmderr is set.
+            0x18 if self.autoexec && self.cmderr == 0 => self.abstractauto = value,
+            0x20..=0x2f => {
+                self.program_buffer[address as usize - 0x20] = value;
+                self.program_buffer_writes.push(value);
+            }
+            // sbreadonaddr, sbaccess, sbautoincrement and sbreadondata, sberror is
+            // write-1-to-clear
+            0x38 if self.system_bus => {
+                let sberror = self.sbcs & !(value & (0b111 << 12)) & (0b111 << 12);
+                self.sbcs = value & (0b11_1111 << 15) | sberror;
+            }
+            0x39 => {
+                self.sbaddress = self.sbaddress & !0xffff_ffff | u64::from(value);
+
+                // sbreadonaddr
+                if self.sbcs & (1 << 20) != 0 {
+                    self.system_bus_read();
+                }
+            }
+            0x3a => self.sbaddress = u64::from(value) << 32 | self.sbaddress & 0xffff_ffff,
+            0x3c => {
+                self.sbdata = self.sbdata & !0xffff_ffff | u64::from(value);
+                self.system_bus_write();
+            }
+            0x3d => self.sbdata = u64::from(value) << 32 | self.sbdata & 0xffff_ffff,
+            _ => (),
+        }
+    }
+
+    fn execute_command(&mut self, command: u32) {
+        if self.cmderr != 0 {
+            return;
+        }
+
+        if self.busy {
+            self.cmderr = 1;
+            return;
+        }
+
+        let cmd_type = command >> 24;
+        let postexec = command & (1 << 18) != 0;
+        let transfer = command & (1 << 17) != 0;
+        let write = command & (1 << 16) != 0;
+        let regno = (command & 0xffff) as u16;
+        let aarsize = (command >> 20) & 0b111;
+
+        if cmd_type != 0 {
+            self.cmderr = 2;
+            return;
+        }
+
+        if transfer && self.is_trigger_register(regno) {
+            if write {
+                self.write_trigger_register(regno, self.data0);
+            } else {
+                self.data0 = self.read_trigger_register(regno);
+            }
+        } else if transfer {
+            if write && aarsize == 3 {
+                // Registers which are only 32 bits wide can't be written with 64 bits.
+                if !self.rv64 && !self.wide_registers.contains_key(&regno) {
+                    self.cmderr = 2;
+                    return;
+                }
+
+                let value = u64::from(self.data1) << 32 | u64::from(self.data0);
+                self.wide_registers.insert(regno, value);
+                self.hart_registers.remove(&regno);
+            } else if write {
+                self.hart_registers.insert(regno, self.data0);
+                self.wide_registers.remove(&regno);
+            } else if let Some(value) = self.wide_registers.get(&regno) {
+                self.data0 = *value as u32;
+                if aarsize == 3 {
+                    self.data1 = (*value >> 32) as u32;
+                }
+            } else {
+                match self.hart_registers.get(&regno) {
+                    // Registers which are only 32 bits wide can't be read with 64 bits.
+                    Some(value) if aarsize != 3 => self.data0 = *value,
+                    Some(value) if self.rv64 => {
+                        self.data0 = *value;
+                        self.data1 = 0;
+                    }
+                    _ => {
+                        self.cmderr = 2;
+                        return;
+                    }
+                }
+            }
+        }
+
+        if postexec && self.execute {
+            if let Err(cmderr) = self.execute_program_buffer() {
+                self.cmderr = cmderr;
+                return;
+            }
+        }
+
+        if transfer && command & (1 << 19) != 0 {
+            self.command = command & !0xffff | u32::from(regno.wrapping_add(1));
+        }
+
+        self.executed_commands += 1;
+
+        if self.stalled_commands {
+            self.busy = true;
+        } else if self.busy_after > 0 {
+            self.busy_after -= 1;
+        } else if self.busy_reads > 0 {
+            self.busy_reads -= 1;
+            self.busy = true;
+        }
+    }
+
+    /// Returns true if `regno` is one of the trigger CSRs, which are only modelled if the
+    /// hart has triggers.
+    fn is_trigger_register(&self, regno: u16) -> bool {
+        self.triggers > 0 && matches!(regno, 0x7a0..=0x7a2 | 0x7a4)
+    }
+
+    fn read_trigger_register(&self, regno: u16) -> u32 {
+        let (tdata1, tdata2) = self
+            .trigger_data
+            .get(&self.tselect)
+            .copied()
+            .unwrap_or((2 << 28, 0));
+
+        match regno {
+            0x7a0 => self.tselect,
+            0x7a1 => tdata1,
+            0x7a2 => tdata2,
+            // tinfo: only `mcontrol` triggers are supported.
+            _ => 1 << 2,
+        }
+    }
+
+    fn write_trigger_register(&mut self, regno: u16, value: u32) {
+        if regno == 0x7a0 {
+            // Selecting a trigger which doesn't exist keeps the selected one.
+            if (value as usize) < self.triggers {
+                self.tselect = value;
+            }
+            return;
+        }
+
+        let data = self
+            .trigger_data
+            .entry(self.tselect)
+            .or_insert((2 << 28, 0));
+
+        match regno {
+            // The type of a trigger is read-only.
+            0x7a1 => data.0 = 2 << 28 | value & 0x0fff_ffff,
+            0x7a2 => data.1 = value,
+            _ => (),
+        }
+    }
+
+    fn read_csr(&self, csr: u16) -> Option<u32> {
+        if self.is_trigger_register(csr) {
+            Some(self.read_trigger_register(csr))
+        } else {
+            self.hart_registers.get(&csr).copied()
+        }
+    }
+
+    fn write_csr(&mut self, csr: u16, value: u32) {
+        if self.is_trigger_register(csr) {
+            self.write_trigger_register(csr, value);
+        } else {
+            self.hart_registers.insert(csr, value);
+        }
+    }
+
+    fn gpr(&self, index: u32) -> u32 {
+        self.gpr64(index) as u32
+    }
+
+    /// All bits of a GPR, which were written with 64 bits on an RV64 hart.
+    fn gpr64(&self, index: u32) -> u64 {
+        let regno = 0x1000 + index as u16;
+
+        if index == 0 {
+            0
+        } else if let Some(value) = self.wide_registers.get(&regno) {
+            *value
+        } else {
+            self.hart_registers
+                .get(&regno)
+                .copied()
+                .map(u64::from)
+                .unwrap_or(0)
+        }
+    }
+
+    fn set_gpr(&mut self, index: u32, value: u32) {
+        if self.rv64 {
+            self.set_gpr64(index, value as i32 as u64);
+        } else if index != 0 {
+            self.hart_registers.insert(0x1000 + index as u16, value);
+        }
+    }
+
+    fn set_gpr64(&mut self, index: u32, value: u64) {
+        if index != 0 {
+            self.wide_registers.insert(0x1000 + index as u16, value);
+            self.hart_registers.remove(&(0x1000 + index as u16));
+        }
+    }
+
+    /// The address in `rs1` plus `offset`, with 64 bits on an RV64 hart.
+    fn address(&self, rs1: u32, offset: u32) -> u64 {
+        if self.rv64 {
+            self.gpr64(rs1).wrapping_add(offset as i32 as u64)
+        } else {
+            u64::from(self.gpr(rs1).wrapping_add(offset))
+        }
+    }
+
+    fn read_memory(&self, address: u64, len: u32) -> u64 {
+        (0..len).fold(0, |value, offset| {
+            let byte = self
+                .memory
+                .get(&address.wrapping_add(u64::from(offset)))
+                .copied()
+                .unwrap_or(0);
+
+            value | u64::from(byte) << (8 * offset)
+        })
+    }
+
+    fn write_memory(&mut self, address: u64, len: u32, value: u64) {
+        for offset in 0..len {
+            self.memory.insert(
+                address.wrapping_add(u64::from(offset)),
+                (value >> (8 * offset)) as u8,
+            );
+        }
+    }
+
+    /// Execute the program buffer, until an `ebreak` or its end. Returns the `cmderr` of an
+    /// instruction which raises an exception, like an instruction which isn't supported, or
+    /// which uses one of the registers x16 to x31 on a RV32E hart.
+    fn execute_program_buffer(&mut self) -> Result<(), u32> {
+        const EXCEPTION: u32 = 3;
+
+        let rv32e = matches!(self.hart_registers.get(&0x301), Some(misa) if misa & MISA_E != 0);
+
+        let mut index = 0;
+        for _ in 0..INSTRUCTION_BUDGET {
+            let instruction = match self.program_buffer.get(index) {
+                Some(&instruction) if instruction != EBREAK => instruction,
+                // The implicit ebreak after the program buffer.
+                _ => return Ok(()),
+            };
+
+            let opcode = instruction & 0x7f;
+            let rd = (instruction >> 7) & 0x1f;
+            let funct3 = (instruction >> 12) & 0b111;
+            let rs1 = (instruction >> 15) & 0x1f;
+            let rs2 = (instruction >> 20) & 0x1f;
+            let immediate = ((instruction as i32) >> 20) as u32;
+
+            // Stores and branches have no destination register, but a second source.
+            let registers = if matches!(opcode, 0x23 | 0x63) {
+                [rs1, rs2]
+            } else {
+                [rd, rs1]
+            };
+            if rv32e && registers.iter().any(|&register| register > 15) {
+                return Err(EXCEPTION);
+            }
+
+            index += 1;
+
+            match (opcode, funct3) {
+                // Loads, sign extended for lb and lh.
+                (0x03, 0b000 | 0b001 | 0b010 | 0b100 | 0b101) => {
+                    let address = self.address(rs1, immediate);
+                    let len = 1 << (funct3 & 0b11);
+                    let value = match (funct3, self.read_memory(address, len) as u32) {
+                        (0b000, value) => value as i8 as u32,
+                        (0b001, value) => value as i16 as u32,
+                        (_, value) => value,
+                    };
+                    self.set_gpr(rd, value);
+                }
+                // ld
+                (0x03, 0b011) if self.rv64 => {
+                    let address = self.address(rs1, immediate);
+                    self.set_gpr64(rd, self.read_memory(address, 8));
+                }
+                // Stores
+                (0x23, 0b000 | 0b001 | 0b010) => {
+                    let offset = ((instruction as i32) >> 25 << 5) as u32 | rd;
+                    let address = self.address(rs1, offset);
+                    self.write_memory(address, 1 << funct3, u64::from(self.gpr(rs2)));
+                }
+                // sd
+                (0x23, 0b011) if self.rv64 => {
+                    let offset = ((instruction as i32) >> 25 << 5) as u32 | rd;
+                    let address = self.address(rs1, offset);
+                    self.write_memory(address, 8, self.gpr64(rs2));
+                }
+                // addi
+                (0x13, 0b000) => self.set_gpr(rd, self.gpr(rs1).wrapping_add(immediate)),
+                // bne
+                (0x63, 0b001) => {
+                    if self.gpr(rs1) != self.gpr(rs2) {
+                        let offset = ((instruction as i32) >> 31 << 12) as u32
+                            | (instruction >> 20) & 0x7e0
+                            | (instruction >> 7) & 0x1e
+                            | (instruction << 4) & 0x800;
+                        let target = (4 * (index as u32 - 1)).wrapping_add(offset);
+                        index = target as usize / 4;
+                    }
+                }
+                // or and and
+                (0x33, 0b110 | 0b111) if instruction >> 25 == 0 => {
+                    let value = if funct3 == 0b110 {
+                        self.gpr(rs1) | self.gpr(rs2)
+                    } else {
+                        self.gpr(rs1) & self.gpr(rs2)
+                    };
+                    self.set_gpr(rd, value);
+                }
+                // lr.w and sc.w
+                (0x2f, 0b010) if matches!(instruction >> 27, 0b00010 | 0b00011) => {
+                    let address = self.address(rs1, 0);
+
+                    if instruction >> 27 == 0b00010 {
+                        self.set_gpr(rd, self.read_memory(address, 4) as u32);
+                        self.reservation = Some(address);
+
+                        if !self.concurrent_writes.is_empty() {
+                            let bits = self.concurrent_writes.remove(0);
+                            let value = self.read_memory(address, 4) | u64::from(bits);
+                            self.write_memory(address, 4, value);
+                            self.reservation = None;
+                        }
+                    } else if self.reservation.take() == Some(address) {
+                        self.write_memory(address, 4, u64::from(self.gpr(rs2)));
+                        self.set_gpr(rd, 0);
+                    } else {
+                        self.set_gpr(rd, 1);
+                    }
+                }
+                // amoor.w and amoand.w
+                (0x2f, 0b010) if matches!(instruction >> 27, 0b01000 | 0b01100) => {
+                    if self.amo_exceptions {
+                        return Err(EXCEPTION);
+                    }
+
+                    let address = self.address(rs1, 0);
+                    let previous = self.read_memory(address, 4) as u32;
+                    let value = if instruction >> 27 == 0b01000 {
+                        previous | self.gpr(rs2)
+                    } else {
+                        previous & self.gpr(rs2)
+                    };
+                    self.write_memory(address, 4, u64::from(value));
+                    self.set_gpr(rd, previous);
+                }
+                // fmv.x.w, fmv.x.d, fmv.w.x and fmv.d.x
+                (0x53, 0b000)
+                    if rs2 == 0
+                        && matches!(
+                            instruction >> 25,
+                            0b111_0000 | 0b111_0001 | 0b111_1000 | 0b111_1001
+                        ) =>
+                {
+                    let mstatus = self.read_csr(0x300).unwrap_or(0);
+                    let double = instruction & (1 << 25) != 0;
+
+                    if (mstatus >> 13) & 0b11 == 0 || (double && !self.rv64) {
+                        return Err(EXCEPTION);
+                    }
+
+                    if instruction & (1 << 28) == 0 {
+                        let value = self.fp_registers.get(&rs1).copied().unwrap_or(0);
+                        if double {
+                            self.set_gpr64(rd, value);
+                        } else {
+                            self.set_gpr(rd, value as u32);
+                        }
+                    } else {
+                        let value = if double {
+                            self.gpr64(rs1)
+                        } else {
+                            u64::from(self.gpr(rs1))
+                        };
+                        self.fp_registers.insert(rd, value);
+                        self.write_csr(0x300, mstatus | 0b11 << 13);
+                    }
+                }
+                // csrrw and csrrs
+                (0x73, 0b001 | 0b010) => {
+                    let csr = (instruction >> 20) as u16;
+                    let value = self.read_csr(csr).ok_or(EXCEPTION)?;
+                    let written = if funct3 == 0b001 {
+                        Some(self.gpr(rs1))
+                    } else if rs1 != 0 {
+                        Some(value | self.gpr(rs1))
+                    } else {
+                        None
+                    };
+
+                    if let Some(written) = written {
+                        self.write_csr(csr, written);
+                    }
+                    self.set_gpr(rd, value);
+                }
+                _ => return Err(EXCEPTION),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the resumed hart from `dpc`: a single instruction if `dcsr.step` is set, and
+    /// otherwise until it reaches an `ebreak` or the address of a trigger, or its instruction
+    /// budget is used up. The halt cause is recorded in `dcsr`.
+    fn run(&mut self) {
+        let dcsr = self.hart_registers.get(&0x7b0).copied().unwrap_or(0);
+        let step = dcsr & (1 << 2) != 0;
+        let mut pc = self.hart_registers.get(&0x7b1).copied().unwrap_or(0);
+
+        let mut cause = None;
+        for _ in 0..INSTRUCTION_BUDGET {
+            let first_halfword = self.read_memory(u64::from(pc), 2) as u16;
+
+            if first_halfword == C_EBREAK || self.read_memory(u64::from(pc), 4) == u64::from(EBREAK)
+            {
+                cause = Some(1);
+                break;
+            }
+
+            if !step && self.trigger_at(pc) {
+                cause = Some(2);
+                break;
+            }
+
+            pc = pc.wrapping_add(if first_halfword & 0b11 != 0b11 { 2 } else { 4 });
+
+            if step {
+                cause = Some(4);
+                break;
+            }
+        }
+
+        self.hart_registers.insert(0x7b1, pc);
+
+        if let Some(cause) = cause {
+            self.hart_registers
+                .insert(0x7b0, dcsr & !(0b111 << 6) | cause << 6);
+            self.running = false;
+        }
+    }
+
+    /// Returns true if an execute trigger matches the instruction at `address`.
+    fn trigger_at(&self, address: u32) -> bool {
+        self.trigger_data
+            .values()
+            .any(|&(tdata1, tdata2)| tdata1 & (1 << 2) != 0 && tdata2 == address)
+    }
+
+    fn dmi_access(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut raw = [0u8; 16];
+        raw[..data.len()].copy_from_slice(data);
+        let request = u128::from_le_bytes(raw);
+
+        let op = (request & 0x3) as u8;
+        let value = (request >> 2) as u32;
+        let address = (request >> 34) as u8;
+
+        let response = self.pending_response;
+
+        match op {
+            1 => {
+                self.dmi_operations += 1;
+                self.pending_response = self.dm_read(address);
+            }
+            2 => {
+                self.dmi_operations += 1;
+                self.dm_write(address, value);
+                self.pending_response = 0;
+            }
+            _ => {
+                // A NoOp gives the hart time to finish a pending command.
+                self.busy &= self.stalled_commands;
+            }
+        }
+
+        let (response, op) = match &mut self.corruption {
+            Some(corruption) => (corruption.value(response), corruption.value(0) & 0x3),
+            None => (response, 0),
+        };
+
+        let bytes = (((response as u128) << 2) | op as u128)
+            .to_le_bytes()
+            .to_vec();
+
+        match &mut self.corruption {
+            Some(corruption) => corruption.length(bytes),
+            None => bytes,
+        }
+    }
+}
+
+/// Deterministic corruption of the responses of the mocked Debug Module.
+///
+/// The corrupted responses only depend on the seed, so that a failure can be replayed.
+#[derive(Debug, Clone)]
+pub(crate) struct Corruption {
+    /// State of the xorshift generator, never zero.
+    state: u64,
+    /// One in `one_in` responses is corrupted.
+    one_in: u64,
+}
+
+impl Corruption {
+    /// Corrupt one in `one_in` responses, randomly chosen based on `seed`.
+    pub fn new(seed: u64, one_in: u64) -> Self {
+        Self {
+            state: seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1,
+            one_in: one_in.max(1),
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn hit(&mut self) -> bool {
+        self.next() % self.one_in == 0
+    }
+
+    /// Returns `value`, or a random value if the response is corrupted.
+    fn value(&mut self, value: u32) -> u32 {
+        if self.hit() {
+            self.next() as u32
+        } else {
+            value
+        }
+    }
+
+    /// Returns `response`, truncated or extended if the response is corrupted.
+    fn length(&mut self, mut response: Vec<u8>) -> Vec<u8> {
+        if self.hit() {
+            response.resize((self.next() % 24) as usize, 0xff);
+        }
+        response
+    }
+}
+
+/// A JTAG probe connected to a mocked RISC-V Debug Module.
+#[derive(Debug)]
+pub(crate) struct MockDebugModule {
+    state: Arc<Mutex<MockDebugModuleState>>,
+    idle_cycles: u8,
+}
+
+impl MockDebugModule {
+    /// Create a new mock, and return a handle to its state for inspection by the test.
+    pub fn new() -> (Self, Arc<Mutex<MockDebugModuleState>>) {
+        let state = Arc::new(Mutex::new(MockDebugModuleState::default()));
+
+        (
+            Self {
+                state: state.clone(),
+                idle_cycles: 0,
+            },
+            state,
+        )
+    }
+
+    /// Delay every probe round trip by `latency`.
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Wait for the latency of a round trip.
+    fn round_trip(state: &mut MockDebugModuleState) {
+        state.transactions += 1;
+
+        if !state.latency.is_zero() {
+            std::thread::sleep(state.latency);
+        }
+    }
+}
+
+impl DebugProbe for MockDebugModule {
+    fn new_from_selector(
+        _selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError>
+    where
+        Self: Sized,
+    {
+        unimplemented!()
+    }
+
+    fn get_name(&self) -> &str {
+        "Mock RISC-V Debug Module"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        1000
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        Ok(speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Ok(())
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        match protocol {
+            WireProtocol::Jtag => Ok(()),
+            other => Err(DebugProbeError::UnsupportedProtocol(other)),
+        }
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(WireProtocol::Jtag)
+    }
+
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Jtag]
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+}
+
+impl JTAGAccess for MockDebugModule {
+    fn read_register(&mut self, address: u32, _len: u32) -> Result<Vec<u8>, DebugProbeError> {
+        let mut state = self.state.lock().unwrap();
+        Self::round_trip(&mut state);
+
+        let value = match address {
+            // dtmcs: version 1, ABITS address bits, no idle cycles required
+            DTMCS_ADDRESS => (ABITS << 4) | 1,
+            // IDCODE
+            _ => 0x1000_563d,
+        };
+
+        match &mut state.corruption {
+            Some(corruption) => {
+                let value = corruption.value(value);
+                Ok(corruption.length(value.to_le_bytes().to_vec()))
+            }
+            None => Ok(value.to_le_bytes().to_vec()),
+        }
+    }
+
+    fn set_idle_cycles(&mut self, idle_cycles: u8) {
+        self.idle_cycles = idle_cycles;
+    }
+
+    fn get_idle_cycles(&self) -> u8 {
+        self.idle_cycles
+    }
+
+    fn set_ir_len(&mut self, _len: u32) {}
+
+    fn max_queued_operations(&self) -> Option<usize> {
+        self.state.lock().unwrap().max_queued_operations
+    }
+
+    fn write_register(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        _len: u32,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        let mut state = self.state.lock().unwrap();
+        Self::round_trip(&mut state);
+
+        match address {
+            DMI_ADDRESS => Ok(state.dmi_access(data)),
+            _ => Ok(vec![0; 4]),
+        }
+    }
+
+    fn write_register_batch(
+        &mut self,
+        writes: &[JtagWriteCommand],
+    ) -> Result<Vec<CommandResult>, BatchExecutionError> {
+        let mut state = self.state.lock().unwrap();
+        // The whole batch is sent to the probe at once.
+        Self::round_trip(&mut state);
+        state.largest_batch = state.largest_batch.max(writes.len());
+
+        let mut results = Vec::new();
+
+        for write in writes {
+            let response = state.dmi_access(&write.data);
+
+            match (write.transform)(response) {
+                Ok(result) => results.push(result),
+                Err(e) => return Err(BatchExecutionError::new(e, results)),
+            }
+        }
+
+        Ok(results)
+    }
+}
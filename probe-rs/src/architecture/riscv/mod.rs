@@ -3,40 +3,114 @@
 #![allow(clippy::inconsistent_digit_grouping)]
 
 use crate::core::Architecture;
-use crate::{CoreInterface, CoreType, InstructionSet};
-use anyhow::{anyhow, Result};
+use crate::{CoreInterface, CoreType, Deadline, HaltEscalation, InstructionSet};
 use communication_interface::{
-    AbstractCommandErrorKind, DebugRegister, RiscvCommunicationInterface, RiscvError,
+    AbstractCommandErrorKind, DebugRegister, RiscvBusAccess, RiscvCommunicationInterface,
+    RiscvError,
 };
 
-use crate::core::{CoreInformation, RegisterFile, RegisterValue};
+use crate::architecture::settle::DelayOrPoll;
+use crate::config::RiscvQuirks;
+use crate::core::{
+    consecutive_units, AtomicOperation, CoreInformation, RegisterFile, RegisterValue,
+    ResetHaltMechanism, WatchpointConfig, WatchpointKind, WatchpointQualifier,
+};
 use crate::memory::valid_32_address;
-use crate::{CoreStatus, Error, HaltReason, MemoryInterface, RegisterId};
+use crate::{
+    CoreStatus, DebugProbeError, Error, FpuState, FpuValue, FpuValueSource, HaltReason,
+    MemoryInterface, RegisterId,
+};
 
 use bitfield::bitfield;
-use register::RISCV_REGISTERS;
+pub(crate) use register::{RISCV_E_REGISTERS, RISCV_REGISTERS};
+use sequences::RiscvDebugSequence;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 #[macro_use]
 mod register;
 pub(crate) mod assembly;
 mod dtm;
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) mod mock;
 
 pub mod communication_interface;
 pub mod sequences;
 
+/// How long to wait for a hart to acknowledge a resume request.
+const RESUME_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The bits of the D and F extensions in `misa`.
+const MISA_D: u32 = 1 << 3;
+const MISA_F: u32 = 1 << 5;
+
+/// The bit of the E base ISA in `misa`.
+const MISA_E: u32 = 1 << 4;
+
+/// The bit of the C extension in `misa`, which allows instructions aligned to 2 bytes.
+const MISA_C: u32 = 1 << 2;
+
+/// The bit of the A extension in `misa`, with the atomic instructions.
+const MISA_A: u32 = 1 << 0;
+
+/// The `mstatus` CSR, and the offset of its FS field, which switches the FPU off if it is 0.
+const MSTATUS: u16 = 0x300;
+const MSTATUS_FS: u32 = 13;
+
+/// The abstract register numbers of `fcsr`, and of f0.
+const FCSR: RegisterId = RegisterId(0x003);
+const F0: u16 = 0x1020;
+
 /// A interface to operate RISC-V cores.
 pub struct Riscv32<'probe> {
     interface: &'probe mut RiscvCommunicationInterface,
+    sequence: Arc<dyn RiscvDebugSequence>,
+    quirks: RiscvQuirks,
 }
 
 impl<'probe> Riscv32<'probe> {
     /// Create a new RISC-V interface.
-    pub fn new(interface: &'probe mut RiscvCommunicationInterface) -> Self {
-        Self { interface }
+    pub fn new(
+        interface: &'probe mut RiscvCommunicationInterface,
+        sequence: Arc<dyn RiscvDebugSequence>,
+        quirks: RiscvQuirks,
+    ) -> Self {
+        Self {
+            interface,
+            sequence,
+            quirks,
+        }
+    }
+
+    /// Read `dmstatus`, and acknowledge `havereset` if it is set, so that it is not
+    /// mistaken for a new reset later on.
+    fn read_dmstatus(&mut self) -> Result<Dmstatus, RiscvError> {
+        let status: Dmstatus = self.interface.read_dm_register()?;
+
+        if status.anyhavereset() && !self.quirks.sticky_havereset {
+            log::debug!("Acknowledging havereset");
+
+            let mut dmcontrol = Dmcontrol(0);
+            dmcontrol.set_dmactive(true);
+            dmcontrol.set_ackhavereset(true);
+
+            self.interface.write_dm_register(dmcontrol)?;
+        }
+
+        Ok(status)
     }
 
     fn read_csr(&mut self, address: u16) -> Result<u32, RiscvError> {
+        self.read_register(address).map(|value| value as u32)
+    }
+
+    fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+        self.write_register(address, value.into())
+    }
+
+    /// Read a register with its width, see
+    /// [`RiscvCommunicationInterface::register_width`].
+    fn read_register(&mut self, address: u16) -> Result<u64, RiscvError> {
         // We need to use the "Access Register Command",
         // which has cmdtype 0
 
@@ -47,26 +121,117 @@ impl<'probe> Riscv32<'probe> {
 
         // always try to read register with abstract command, fallback to program buffer,
         // if not supported
-        match self.interface.abstract_cmd_register_read(address) {
+        match self.interface.abstract_cmd_register_read_xlen(address) {
             Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
                 log::debug!("Could not read core register {:#x} with abstract command, falling back to program buffer", address);
-                self.interface.read_csr_progbuf(address)
+                if is_fpr(address) {
+                    self.with_fpu_enabled(|core| {
+                        core.interface.read_fpr_progbuf(RegisterId(address))
+                    })
+                } else {
+                    self.interface.read_csr_progbuf_xlen(address)
+                }
             }
             other => other,
         }
     }
 
-    fn write_csr(&mut self, address: u16, value: u32) -> Result<(), RiscvError> {
+    /// Write a register with its width, see
+    /// [`RiscvCommunicationInterface::register_width`].
+    fn write_register(&mut self, address: u16, value: u64) -> Result<(), RiscvError> {
         log::debug!("Writing CSR {:#x}", address);
 
-        match self.interface.abstract_cmd_register_write(address, value) {
+        match self
+            .interface
+            .abstract_cmd_register_write_xlen(address, value)
+        {
             Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
                 log::debug!("Could not write core register {:#x} with abstract command, falling back to program buffer", address);
-                self.interface.write_csr_progbuf(address, value)
+                if is_fpr(address) {
+                    self.with_fpu_enabled(|core| {
+                        core.interface.write_fpr_progbuf(RegisterId(address), value)
+                    })
+                } else {
+                    self.interface.write_csr_progbuf_xlen(address, value)
+                }
             }
             other => other,
         }
     }
+
+    /// Run `access` to the FPRs with the program buffer while `mstatus.FS` isn't Off, as the
+    /// `fmv` instructions raise an illegal instruction exception otherwise. If the FPU was off,
+    /// it is turned on for the access, and `mstatus` is restored afterwards.
+    fn with_fpu_enabled<T>(
+        &mut self,
+        access: impl FnOnce(&mut Self) -> Result<T, RiscvError>,
+    ) -> Result<T, RiscvError> {
+        let mstatus = self.read_register(MSTATUS)?;
+
+        if (mstatus >> MSTATUS_FS) & 0b11 != 0 {
+            return access(self);
+        }
+
+        // Initial
+        self.write_register(MSTATUS, mstatus | 1 << MSTATUS_FS)?;
+
+        let result = access(self);
+
+        self.write_register(MSTATUS, mstatus)?;
+
+        result
+    }
+
+    /// Read `misa`, which is only read from the hart once.
+    fn misa(&mut self) -> Result<u32, RiscvError> {
+        if let Some(misa) = self.interface.misa() {
+            return Ok(misa);
+        }
+
+        let misa = self.read_csr(0x301)?;
+        self.interface.set_misa(misa);
+
+        Ok(misa)
+    }
+
+    /// Whether the hart has floating point registers, according to the F and D extension
+    /// bits of `misa`.
+    fn fp_registers_present(&mut self) -> Result<bool, RiscvError> {
+        let misa = self.misa()?;
+
+        // `misa` reads as zero if it isn't implemented, the extensions are unknown then.
+        Ok(misa == 0 || misa & (MISA_D | MISA_F) != 0)
+    }
+
+    /// Fails with [`Error::FpuNotPresent`] if `address` is a FPR, and the hart has neither the
+    /// F nor the D extension. A FPR is only accessed after `misa` was read, which determines its
+    /// width.
+    fn check_fpr(&mut self, address: RegisterId) -> Result<(), Error> {
+        if is_fpr(address.0) && !self.fp_registers_present()? {
+            return Err(Error::FpuNotPresent);
+        }
+
+        Ok(())
+    }
+
+    /// Whether the hart implements the RV32E base ISA, which only has the GPRs x0 to x15.
+    fn is_rv32e(&mut self) -> Result<bool, RiscvError> {
+        // If `misa` isn't implemented, RV32I is assumed.
+        Ok(self.misa()? & MISA_E != 0)
+    }
+
+    /// The alignment of the instructions of the hart: 2 bytes with the C extension, and 4
+    /// bytes without it.
+    fn instruction_alignment(&mut self) -> Result<u32, RiscvError> {
+        let misa = self.misa()?;
+
+        // If `misa` isn't implemented, the C extension is assumed.
+        if misa != 0 && misa & MISA_C == 0 {
+            Ok(4)
+        } else {
+            Ok(2)
+        }
+    }
 }
 
 impl<'probe> CoreInterface for Riscv32<'probe> {
@@ -87,7 +252,7 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
     }
 
     fn core_halted(&mut self) -> Result<bool, crate::Error> {
-        let dmstatus: Dmstatus = self.interface.read_dm_register()?;
+        let dmstatus = self.read_dmstatus()?;
 
         Ok(dmstatus.allhalted())
     }
@@ -116,9 +281,22 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
         self.interface.write_dm_register(dmcontrol)?;
 
+        // The width of the registers can only be determined while halted.
+        if let Err(e) = self.interface.determine_xlen() {
+            log::debug!("Failed to determine XLEN, assuming 32 bits: {}", e);
+        }
+
         let pc = self.read_core_reg(register::RISCV_REGISTERS.program_counter.id)?;
 
-        Ok(CoreInformation { pc: pc.try_into()? })
+        // The register file depends on the base ISA, which can only be read while halted.
+        if let Err(e) = self.misa() {
+            log::debug!("Failed to read misa, assuming RV32I: {}", e);
+        }
+
+        Ok(CoreInformation {
+            pc: pc.try_into()?,
+            instruction_set: None,
+        })
     }
 
     fn run(&mut self) -> Result<(), crate::Error> {
@@ -131,20 +309,41 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
         self.interface.write_dm_register(dmcontrol)?;
 
-        // check if request has been acknowleged
-        let status: Dmstatus = self.interface.read_dm_register()?;
+        // wait until the request has been acknowledged
+        let grace_period = self
+            .quirks
+            .resume_ack_grace_period_ms
+            .map(|ms| Duration::from_millis(ms.into()));
+        let start = Instant::now();
 
-        if !status.allresumeack() {
-            return Err(RiscvError::RequestNotAcknowledged.into());
-        };
+        let result = DelayOrPoll::new(1).poll(RESUME_TIMEOUT, || {
+            let status = self.read_dmstatus()?;
+
+            if status.allresumeack() {
+                return Ok(true);
+            }
 
-        // clear resume request
+            match grace_period {
+                Some(grace_period) if status.allrunning() && start.elapsed() >= grace_period => {
+                    log::debug!("Hart is running, but the resume request was not acknowledged");
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        });
+
+        // clear resume request, some Debug Modules don't do this on their own
         let mut dmcontrol = Dmcontrol(0);
         dmcontrol.set_dmactive(true);
 
         self.interface.write_dm_register(dmcontrol)?;
 
-        Ok(())
+        match result {
+            Err(Error::Probe(DebugProbeError::Timeout)) => {
+                Err(RiscvError::RequestNotAcknowledged.into())
+            }
+            other => other,
+        }
     }
 
     fn reset(&mut self) -> Result<(), crate::Error> {
@@ -207,67 +406,75 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
     fn reset_and_halt(
         &mut self,
-        _timeout: Duration,
+        timeout: Duration,
     ) -> Result<crate::core::CoreInformation, crate::Error> {
-        log::debug!("Resetting core, setting hartreset bit");
-
-        let mut dmcontrol = Dmcontrol(0);
-        dmcontrol.set_dmactive(true);
-        dmcontrol.set_hartreset(true);
-        dmcontrol.set_haltreq(true);
-
-        self.interface.write_dm_register(dmcontrol)?;
-
-        // Read back register to verify reset is supported
-        let readback: Dmcontrol = self.interface.read_dm_register()?;
+        self.sequence.reset_and_halt(self.interface, timeout)?;
 
-        if readback.hartreset() {
-            log::debug!("Clearing hartreset bit");
-            // Reset is performed by setting the bit high, and then low again
-            let mut dmcontrol = Dmcontrol(0);
-            dmcontrol.set_dmactive(true);
-            dmcontrol.set_haltreq(true);
-            dmcontrol.set_hartreset(false);
+        let pc = self.read_core_reg(RegisterId(0x7b1))?;
 
-            self.interface.write_dm_register(dmcontrol)?;
-        } else {
-            // Hartreset is not supported, whole core needs to be reset
-            //
-            // TODO: Cache this
-            log::debug!("Hartreset bit not supported, using ndmreset");
-            let mut dmcontrol = Dmcontrol(0);
-            dmcontrol.set_dmactive(true);
-            dmcontrol.set_ndmreset(true);
-            dmcontrol.set_haltreq(true);
+        Ok(CoreInformation {
+            pc: pc.try_into()?,
+            instruction_set: None,
+        })
+    }
 
-            self.interface.write_dm_register(dmcontrol)?;
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::ResetHaltRequest
+    }
 
-            log::debug!("Clearing ndmreset bit");
-            let mut dmcontrol = Dmcontrol(0);
-            dmcontrol.set_dmactive(true);
-            dmcontrol.set_ndmreset(false);
-            dmcontrol.set_haltreq(true);
+    fn escalate_halt(
+        &mut self,
+        level: HaltEscalation,
+        timeout: Duration,
+    ) -> Result<bool, crate::Error> {
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_dmactive(true);
+        dmcontrol.set_haltreq(true);
 
-            self.interface.write_dm_register(dmcontrol)?;
+        match level {
+            HaltEscalation::PersistentHaltRequest => {
+                // The halt request stays set while the hart is polled, so that a hart which
+                // only wakes up briefly, e.g. from WFI with a gated clock, still sees it.
+                self.interface.write_dm_register(dmcontrol)?;
+            }
+            HaltEscalation::CoreReset => {
+                // The pending halt request halts the hart after the reset, and resethaltreq
+                // halts it before the first instruction on Debug Modules which support it.
+                let dmstatus: Dmstatus = self.interface.read_dm_register()?;
+                dmcontrol.set_resethaltreq(dmstatus.hasresethaltreq());
+
+                log::debug!("Pulsing ndmreset to halt the hart");
+                dmcontrol.set_ndmreset(true);
+                self.interface.write_dm_register(dmcontrol)?;
+
+                dmcontrol.set_resethaltreq(false);
+                dmcontrol.set_ndmreset(false);
+                self.interface.write_dm_register(dmcontrol)?;
+            }
+            _ => return Ok(false),
         }
 
-        // check that cores have reset
-        let readback: Dmstatus = self.interface.read_dm_register()?;
-
-        if !(readback.allhavereset() && readback.allhalted()) {
-            return Err(RiscvError::RequestNotAcknowledged.into());
-        }
+        let result = self.wait_for_core_halted(timeout);
 
-        // acknowledge the reset, clear the halt request
+        // Clear the requests, and acknowledge the reset.
         let mut dmcontrol = Dmcontrol(0);
         dmcontrol.set_dmactive(true);
-        dmcontrol.set_ackhavereset(true);
+        if level == HaltEscalation::CoreReset {
+            dmcontrol.set_clrresethaltreq(true);
+            dmcontrol.set_ackhavereset(true);
+        }
 
         self.interface.write_dm_register(dmcontrol)?;
 
-        let pc = self.read_core_reg(RegisterId(0x7b1))?;
+        result.map(|_| true)
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        self.interface.set_deadline(deadline);
+    }
 
-        Ok(CoreInformation { pc: pc.try_into()? })
+    fn completed_operations(&mut self) -> Option<u64> {
+        Some(self.interface.completed_operations())
     }
 
     fn step(&mut self) -> Result<crate::core::CoreInformation, crate::Error> {
@@ -290,18 +497,105 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
         self.write_csr(0x7b0, dcsr.0)?;
 
-        Ok(CoreInformation { pc: pc.try_into()? })
+        Ok(CoreInformation {
+            pc: pc.try_into()?,
+            instruction_set: None,
+        })
+    }
+
+    fn register_available(&mut self, address: crate::RegisterId) -> Result<bool, crate::Error> {
+        // The register numbers of the abstract commands, see the debug specification 0.13,
+        // section 3.6.1.1.
+        match address.0 {
+            // CSRs, GPRs, and the non-standard registers
+            0x0000..=0x0fff | 0x1000..=0x100f | 0xc000..=0xffff => Ok(true),
+            // GPRs which are missing on RV32E harts
+            0x1010..=0x101f => Ok(!self.is_rv32e()?),
+            // FPRs
+            0x1020..=0x103f => Ok(self.fp_registers_present()?),
+            _ => Ok(false),
+        }
     }
 
     fn read_core_reg(&mut self, address: crate::RegisterId) -> Result<RegisterValue, crate::Error> {
-        self.read_csr(address.0)
-            .map(|v| v.into())
-            .map_err(|e| e.into())
+        self.check_fpr(address)?;
+
+        let size = self.interface.register_width(address);
+        let value = self.read_register(address.0)?;
+
+        Ok(register_value(size, value))
+    }
+
+    fn read_core_regs(
+        &mut self,
+        addresses: &[crate::RegisterId],
+    ) -> Result<Vec<RegisterValue>, crate::Error> {
+        for address in addresses {
+            self.check_fpr(*address)?;
+        }
+
+        // Registers of 64 bits, i.e. all registers of RV64 harts, and the FPRs with the D
+        // extension, are read with their width.
+        if addresses
+            .iter()
+            .any(|&address| self.interface.register_width(address) == RiscvBusAccess::A64)
+        {
+            let registers: Vec<(RegisterId, RiscvBusAccess)> = addresses
+                .iter()
+                .map(|&address| (address, self.interface.register_width(address)))
+                .collect();
+
+            return match self
+                .interface
+                .abstract_cmd_register_read_batch_sized(&registers)
+            {
+                Ok(values) => Ok(registers
+                    .iter()
+                    .zip(values)
+                    .map(|(&(_, size), value)| register_value(size, value))
+                    .collect()),
+                Err(RiscvError::AbstractCommand(_)) => {
+                    log::debug!("Could not read core registers with abstract commands, reading them individually");
+
+                    addresses
+                        .iter()
+                        .map(|address| self.read_core_reg(*address))
+                        .collect()
+                }
+                Err(e) => Err(e.into()),
+            };
+        }
+
+        match self.interface.abstract_cmd_register_read_batch(addresses) {
+            Ok(values) => Ok(values.into_iter().map(|v| v.into()).collect()),
+            Err(RiscvError::AbstractCommand(AbstractCommandErrorKind::NotSupported)) => {
+                log::debug!("Could not read core registers with abstract commands, reading them individually");
+
+                addresses
+                    .iter()
+                    .map(|address| self.read_core_reg(*address))
+                    .collect()
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
-    fn write_core_reg(&mut self, address: crate::RegisterId, value: RegisterValue) -> Result<()> {
-        let value: u32 = value.try_into()?;
-        self.write_csr(address.0, value).map_err(|e| e.into())
+    fn write_core_reg(
+        &mut self,
+        address: crate::RegisterId,
+        value: RegisterValue,
+    ) -> Result<(), Error> {
+        self.check_fpr(address)?;
+
+        let value: u64 = match self.interface.register_width(address) {
+            RiscvBusAccess::A64 => value.try_into()?,
+            _ => {
+                let value: u32 = value.try_into()?;
+                value.into()
+            }
+        };
+
+        self.write_register(address.0, value).map_err(Error::from)
     }
 
     fn available_breakpoint_units(&mut self) -> Result<u32, crate::Error> {
@@ -315,8 +609,14 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
         let mut tselect_index = 0;
 
+        let start = Instant::now();
+
         // These steps follow the debug specification 0.13, section 5.1 Enumeration
         loop {
+            if start.elapsed() > self.interface.timeout() {
+                return Err(RiscvError::Timeout.into());
+            }
+
             log::debug!("Trying tselect={}", tselect_index);
             if let Err(e) = self.write_csr(tselect, tselect_index) {
                 match e {
@@ -382,6 +682,15 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
     fn set_hw_breakpoint(&mut self, bp_unit_index: usize, addr: u64) -> Result<(), crate::Error> {
         let addr = valid_32_address(addr)?;
 
+        // The trigger matches the address exactly, so it would never match an address which
+        // can't hold an instruction.
+        if addr % self.instruction_alignment()? != 0 {
+            return Err(Error::UnsupportedBreakpointAddress {
+                address: addr.into(),
+                reason: "the address is not aligned to an instruction of this core",
+            });
+        }
+
         // select requested trigger
         let tselect = 0x7a0;
         let tdata1 = 0x7a1;
@@ -442,7 +751,11 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
     }
 
     fn registers(&self) -> &'static RegisterFile {
-        &RISCV_REGISTERS
+        // Until `misa` was read, the hart is assumed to be a RV32I hart.
+        match self.interface.misa() {
+            Some(misa) if misa & MISA_E != 0 => &RISCV_E_REGISTERS,
+            _ => &RISCV_REGISTERS,
+        }
     }
 
     fn hw_breakpoints_enabled(&self) -> bool {
@@ -463,15 +776,20 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
         Ok(InstructionSet::RV32)
     }
 
+    fn take_halt_detected(&mut self) -> bool {
+        self.interface.take_halt_detected()
+    }
+
     fn status(&mut self) -> Result<crate::core::CoreStatus, crate::Error> {
         // TODO: We should use hartsum to determine if any hart is halted
         //       quickly
 
-        let status: Dmstatus = self.interface.read_dm_register()?;
+        let status = self.read_dmstatus()?;
 
         if status.allhalted() {
             // determine reason for halt
             let dcsr = Dcsr(self.read_core_reg(RegisterId::from(0x7b0))?.try_into()?);
+            self.interface.note_halt_cause(Some(dcsr.cause()));
 
             let reason = match dcsr.cause() {
                 // An ebreak instruction was hit
@@ -490,12 +808,10 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
 
             Ok(CoreStatus::Halted(reason))
         } else if status.allrunning() {
+            self.interface.note_halt_cause(None);
             Ok(CoreStatus::Running)
         } else {
-            Err(
-                anyhow!("Some cores are running while some are halted, this should not happen.")
-                    .into(),
-            )
+            Err(RiscvError::InconsistentHartStatus.into())
         }
     }
 
@@ -520,15 +836,13 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
             // The trigger must be active in at least a single mode
             let trigger_any_mode_active = tdata_value.m() || tdata_value.s() || tdata_value.u();
 
-            let trigger_any_action_enabled =
-                tdata_value.execute() || tdata_value.store() || tdata_value.load();
-
             // Only return if the trigger if it is for an execution debug action in all modes.
+            // Load and store triggers are watchpoints, see `set_watchpoint`.
             if tdata_value.type_() == 0b10
                 && tdata_value.action() == 1
                 && tdata_value.match_() == 0
                 && trigger_any_mode_active
-                && trigger_any_action_enabled
+                && tdata_value.execute()
             {
                 let breakpoint = self.read_csr(tdata2)?;
                 breakpoints.push(Some(breakpoint as u64));
@@ -541,9 +855,188 @@ impl<'probe> CoreInterface for Riscv32<'probe> {
     }
 
     fn fpu_support(&mut self) -> Result<bool, crate::error::Error> {
-        Err(crate::error::Error::Other(anyhow::anyhow!(
-            "Fpu detection not yet implemented"
-        )))
+        // Without `misa`, the presence of the FPRs is unknown.
+        match self.misa()? {
+            0 => Err(Error::NotImplemented("FPU detection without misa")),
+            misa => Ok(misa & (MISA_D | MISA_F) != 0),
+        }
+    }
+
+    fn read_fpu_state(&mut self) -> Result<FpuState, crate::Error> {
+        if !self.fp_registers_present()? {
+            return Err(Error::FpuNotPresent);
+        }
+
+        // Any access to the FPU while it is off raises an illegal instruction exception.
+        if (self.read_csr(MSTATUS)? >> MSTATUS_FS) & 0b11 == 0 {
+            return Err(Error::FpuDisabled("mstatus.FS is Off"));
+        }
+
+        // With the D extension, the registers are 64 bits wide. If `misa` isn't implemented,
+        // 32 bits are read, which every width of the registers supports.
+        let size = if self.misa()? & MISA_D != 0 {
+            RiscvBusAccess::A64
+        } else {
+            RiscvBusAccess::A32
+        };
+
+        let registers: Vec<(RegisterId, RiscvBusAccess)> =
+            std::iter::once((FCSR, RiscvBusAccess::A32))
+                .chain((0..32).map(|index| (RegisterId(F0 + index), size)))
+                .collect();
+        let values = self
+            .interface
+            .abstract_cmd_register_read_batch_sized(&registers)?;
+
+        let mut state = registers.iter().zip(values).map(|(&(id, size), value)| {
+            FpuValue::new(id, register_value(size, value), FpuValueSource::Register)
+        });
+
+        let status = state.next().expect("fcsr is always read");
+
+        Ok(FpuState {
+            status,
+            registers: state.collect(),
+        })
+    }
+
+    fn atomic_32(
+        &mut self,
+        address: u64,
+        operation: AtomicOperation,
+    ) -> Result<Option<u32>, crate::Error> {
+        // Without `misa`, the A extension is unknown.
+        if self.misa()? & MISA_A == 0 {
+            return Ok(None);
+        }
+
+        match self.interface.atomic_progbuf(address, operation) {
+            Ok(previous) => Ok(Some(previous)),
+            Err(RiscvError::ProgramBufferTooSmall) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn available_watchpoint_units(&mut self) -> Result<u32, crate::Error> {
+        self.available_breakpoint_units()
+    }
+
+    fn watchpoints_share_breakpoint_units(&self) -> bool {
+        true
+    }
+
+    fn set_watchpoint(
+        &mut self,
+        config: &WatchpointConfig,
+        used: &[usize],
+    ) -> Result<Vec<usize>, crate::Error> {
+        let tselect = 0x7a0;
+        let tdata1 = 0x7a1;
+        let tdata2 = 0x7a2;
+
+        let available = self.available_breakpoint_units()? as usize;
+
+        // The maximum NAPOT range is the same for all triggers of a hart in practice, so it is
+        // read from the topmost free one, where the watchpoint will be set.
+        let top = (0..available)
+            .rev()
+            .find(|unit| !used.contains(unit))
+            .ok_or(Error::WatchpointsExhausted)?;
+        self.write_csr(tselect, top as u32)?;
+        let maskmax = Mcontrol(self.read_csr(tdata1)?).maskmax();
+
+        let triggers = watchpoint_triggers(config, maskmax)?;
+        let units = consecutive_units(triggers.len(), available, used, true)
+            .ok_or(Error::WatchpointsExhausted)?;
+
+        for (&unit, (trigger, _)) in units.iter().zip(&triggers) {
+            self.write_csr(tselect, unit as u32)?;
+
+            let trigger_type = Mcontrol(self.read_csr(tdata1)?).type_();
+            if trigger_type != 0b10 {
+                return Err(RiscvError::UnexpectedTriggerType(trigger_type).into());
+            }
+
+            // Triggers which don't support a chain or a match type read it back differently.
+            self.write_csr(tdata1, trigger.0)?;
+            let readback = Mcontrol(self.read_csr(tdata1)?);
+
+            if (
+                readback.chain(),
+                readback.select(),
+                readback.match_(),
+                readback.sizelo(),
+            ) != (
+                trigger.chain(),
+                trigger.select(),
+                trigger.match_(),
+                trigger.sizelo(),
+            ) {
+                self.clear_watchpoint(&units)?;
+
+                return Err(if readback.select() != trigger.select() {
+                    Error::UnsupportedWatchpointQualifier {
+                        qualifier: WatchpointQualifier::Value,
+                        reason: "the triggers don't compare data values",
+                    }
+                } else if readback.sizelo() != trigger.sizelo() {
+                    Error::UnsupportedWatchpointQualifier {
+                        qualifier: WatchpointQualifier::AccessSize,
+                        reason: "the triggers don't compare the access size",
+                    }
+                } else {
+                    Error::InvalidWatchpoint {
+                        address: config.address,
+                        len: config.len,
+                        reason: "the triggers can't be chained to watch this range",
+                    }
+                });
+            }
+        }
+
+        // The comparison values are written last, once the whole chain is known to work.
+        for (&unit, (_, value)) in units.iter().zip(&triggers) {
+            self.write_csr(tselect, unit as u32)?;
+            self.write_csr(tdata2, *value)?;
+        }
+
+        Ok(units)
+    }
+
+    fn clear_watchpoint(&mut self, units: &[usize]) -> Result<(), crate::Error> {
+        for &unit in units {
+            self.clear_hw_breakpoint(unit)?;
+        }
+
+        Ok(())
+    }
+
+    fn matched_watchpoint_units(
+        &mut self,
+        units: &[usize],
+    ) -> Result<Option<Vec<usize>>, crate::Error> {
+        let tselect = 0x7a0;
+        let tdata1 = 0x7a1;
+
+        let mut matched = Vec::new();
+        for &unit in units {
+            self.write_csr(tselect, unit as u32)?;
+
+            let mut trigger = Mcontrol(self.read_csr(tdata1)?);
+            if trigger.hit() {
+                matched.push(unit);
+
+                trigger.set_hit(false);
+                self.write_csr(tdata1, trigger.0)?;
+            }
+        }
+
+        // The hit bit is optional, so no hit at all means that the hart can't tell.
+        Ok(if matched.is_empty() {
+            None
+        } else {
+            Some(matched)
+        })
     }
 }
 
@@ -587,6 +1080,9 @@ impl<'probe> MemoryInterface for Riscv32<'probe> {
     fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
         self.interface.write_8(address, data)
     }
+    fn write_barrier(&mut self) -> Result<(), Error> {
+        self.interface.write_barrier()
+    }
     fn flush(&mut self) -> Result<(), Error> {
         self.interface.flush()
     }
@@ -818,3 +1314,1550 @@ bitfield! {
     store, set_store: 1;
     load, set_load: 0;
 }
+
+/// Whether `address` is one of the FPRs f0 to f31.
+fn is_fpr(address: u16) -> bool {
+    matches!(address, 0x1020..=0x103f)
+}
+
+/// The value of a register which was read with the width `size`.
+fn register_value(size: RiscvBusAccess, value: u64) -> RegisterValue {
+    if size == RiscvBusAccess::A64 {
+        RegisterValue::U64(value)
+    } else {
+        RegisterValue::U32(value as u32)
+    }
+}
+
+/// Returns the chain of triggers, as the values of `tdata1` and `tdata2`, which implements the
+/// watchpoint `config`. `maskmax` is the largest naturally aligned range a trigger matches,
+/// as a power of two.
+///
+/// The address is matched by a single trigger if possible, or by a pair of triggers which
+/// match the start and the end of the range otherwise. A value is matched by another trigger
+/// at the end of the chain, which compares the data instead of the address.
+fn watchpoint_triggers(
+    config: &WatchpointConfig,
+    maskmax: u32,
+) -> Result<Vec<(Mcontrol, u32)>, Error> {
+    let address = valid_32_address(config.address)?;
+
+    let sizelo = match config.access_size {
+        None => 0,
+        Some(1) => 1,
+        Some(2) => 2,
+        Some(4) => 3,
+        Some(_) => {
+            return Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::AccessSize,
+                reason: "the triggers of RV32 harts compare accesses of up to 4 bytes",
+            })
+        }
+    };
+
+    let trigger = |match_| {
+        let mut trigger = Mcontrol(0);
+
+        trigger.set_type(0b10);
+        trigger.set_dmode(true);
+        // Enter debug mode
+        trigger.set_action(1);
+        trigger.set_match(match_);
+        trigger.set_sizelo(sizelo);
+        trigger.set_m(true);
+        trigger.set_s(true);
+        trigger.set_u(true);
+
+        match config.kind {
+            WatchpointKind::Read => trigger.set_load(true),
+            WatchpointKind::Write => trigger.set_store(true),
+            WatchpointKind::ReadWrite => {
+                trigger.set_load(true);
+                trigger.set_store(true);
+            }
+        }
+
+        trigger
+    };
+
+    let napot = config.len.is_power_of_two()
+        && config.address % config.len == 0
+        && config.len <= 1 << maskmax;
+
+    let mut triggers = if config.len == 1 {
+        // Match exactly the value in tdata2
+        vec![(trigger(0), address)]
+    } else if napot {
+        // Match the top bits of the address, down to the lowest zero bit of tdata2
+        vec![(trigger(1), address | ((config.len as u32 - 1) >> 1))]
+    } else {
+        let end = config
+            .address
+            .checked_add(config.len)
+            .and_then(|end| u32::try_from(end).ok())
+            .ok_or(Error::InvalidWatchpoint {
+                address: config.address,
+                len: config.len,
+                reason: "the triggers of RV32 harts only watch 32-bit addresses",
+            })?;
+
+        // Match addresses greater than or equal to the start, and less than the end
+        vec![(trigger(2), address), (trigger(3), end)]
+    };
+
+    if let Some((value, mask, whole)) = config.masked_value() {
+        let value_trigger = if whole && config.len <= 4 {
+            // Match exactly the data value
+            (trigger(0), value as u32)
+        } else if config.len <= 2 {
+            // Match the lower half of tdata2, masked by its upper half
+            (trigger(4), (mask as u32) << 16 | value as u32)
+        } else {
+            return Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: if whole {
+                    WatchpointQualifier::Value
+                } else {
+                    WatchpointQualifier::ValueMask
+                },
+                reason: "the triggers of RV32 harts compare values of up to 4 bytes, and masks of up to 2 bytes",
+            });
+        };
+
+        triggers.push(value_trigger);
+        triggers.last_mut().unwrap().0.set_select(true);
+    }
+
+    // All but the last trigger of a chain have to match for it to fire.
+    let last = triggers.len() - 1;
+    for (trigger, _) in &mut triggers[..last] {
+        trigger.set_chain(true);
+    }
+
+    Ok(triggers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::mock::MockDebugModule;
+    use super::sequences::DefaultRiscvSequence;
+    use super::*;
+    use crate::core::RegisterDataType;
+    use crate::{Core, CoreState, HaltAttemptOutcome};
+    use probe_rs_target::CoreAccessOptions;
+
+    /// Registers x16 to x31, as used by the abstract commands.
+    fn test_registers() -> Vec<RegisterId> {
+        (0x1010..0x1020).map(RegisterId).collect()
+    }
+
+    fn mock_interface() -> (
+        RiscvCommunicationInterface,
+        std::sync::Arc<std::sync::Mutex<mock::MockDebugModuleState>>,
+    ) {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            for regno in test_registers() {
+                state
+                    .hart_registers
+                    .insert(regno.0, 0xcafe_0000 | regno.0 as u32);
+            }
+        }
+
+        let interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        (interface, state)
+    }
+
+    #[test]
+    fn read_core_regs_is_batched() {
+        let (mut interface, state) = mock_interface();
+        let registers = test_registers();
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        state.lock().unwrap().transactions = 0;
+        for register in &registers {
+            core.read_core_reg(*register).unwrap();
+        }
+        let single_transactions = std::mem::take(&mut state.lock().unwrap().transactions);
+
+        let values = core.read_core_regs(&registers).unwrap();
+        let batch_transactions = state.lock().unwrap().transactions;
+
+        for (register, value) in registers.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+
+        assert!(single_transactions >= 80);
+        assert!(batch_transactions <= 20);
+    }
+
+    #[test]
+    fn read_core_regs_uses_autoexec_for_consecutive_registers() {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.autoexec = true;
+            for regno in test_registers() {
+                state
+                    .hart_registers
+                    .insert(regno.0, 0xcafe_0000 | regno.0 as u32);
+            }
+        }
+
+        let mut interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+        let registers = test_registers();
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        state.lock().unwrap().dmi_operations = 0;
+        for register in &registers {
+            core.read_core_reg(*register).unwrap();
+        }
+        let single_operations = std::mem::take(&mut state.lock().unwrap().dmi_operations);
+
+        let values = core.read_core_regs(&registers).unwrap();
+        let batch_operations = std::mem::take(&mut state.lock().unwrap().dmi_operations);
+
+        for (register, value) in registers.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+
+        // A single DMI operation for each register, instead of a command and a read.
+        assert!(batch_operations <= registers.len() + 8);
+        assert!(batch_operations * 3 < single_operations);
+
+        // Registers which aren't consecutive are read with a command each.
+        let scattered = [registers[0], registers[2], registers[4], registers[6]];
+        let values = core.read_core_regs(&scattered).unwrap();
+        for (register, value) in scattered.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+    }
+
+    #[test]
+    fn read_core_regs_respects_queue_depth() {
+        let (mut interface, state) = mock_interface();
+        let registers = test_registers();
+
+        state.lock().unwrap().max_queued_operations = Some(4);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let values = core.read_core_regs(&registers).unwrap();
+
+        for (register, value) in registers.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+
+        assert_eq!(state.lock().unwrap().largest_batch, 4);
+    }
+
+    fn target_quirks(name: &str) -> RiscvQuirks {
+        let target = crate::config::get_target_by_name(name).unwrap();
+
+        match &target.cores[0].core_access_options {
+            crate::config::CoreAccessOptions::Riscv(options) => options.quirks,
+            crate::config::CoreAccessOptions::Arm(_) => panic!("{} is not a RISC-V target", name),
+        }
+    }
+
+    #[test]
+    fn run_waits_for_resume_ack() {
+        let (mut interface, state) = mock_interface();
+
+        state.lock().unwrap().resume_ack_after = Some(3);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        core.run().unwrap();
+
+        let state = state.lock().unwrap();
+        assert!(state.running);
+        assert!(!state.resumereq);
+    }
+
+    #[test]
+    fn run_without_resume_ack_is_reported() {
+        let (mut interface, state) = mock_interface();
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let error = core.run().unwrap_err();
+
+        assert!(
+            matches!(
+                &error,
+                Error::ArchitectureSpecific(e)
+                    if matches!(e.downcast_ref(), Some(RiscvError::RequestNotAcknowledged))
+            ),
+            "{:?}",
+            error
+        );
+        assert!(!state.lock().unwrap().resumereq);
+    }
+
+    #[test]
+    fn run_gd32vf103_without_resume_ack() {
+        let (mut interface, state) = mock_interface();
+
+        // The GD32VF103 resumes, but doesn't set allresumeack.
+        let quirks = target_quirks("GD32VF103CBT6");
+        assert!(quirks.resume_ack_grace_period_ms.is_some());
+
+        let mut core = Riscv32::new(&mut interface, DefaultRiscvSequence::create(), quirks);
+
+        core.run().unwrap();
+
+        let state = state.lock().unwrap();
+        assert!(state.running);
+        assert!(!state.resumereq);
+    }
+
+    #[test]
+    fn havereset_is_acknowledged() {
+        let (mut interface, state) = mock_interface();
+
+        state.lock().unwrap().havereset = true;
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert!(core.core_halted().unwrap());
+
+        let state = state.lock().unwrap();
+        assert!(!state.havereset);
+        assert_eq!(state.havereset_acks, 1);
+    }
+
+    #[test]
+    fn run_esp32c3_with_sticky_havereset() {
+        let (mut interface, state) = mock_interface();
+
+        // On the ESP32-C3, havereset stays set after it was acknowledged.
+        {
+            let mut state = state.lock().unwrap();
+            state.havereset = true;
+            state.sticky_havereset = true;
+            state.resume_ack_after = Some(1);
+        }
+
+        let quirks = target_quirks("esp32c3");
+        assert!(quirks.sticky_havereset);
+
+        let mut core = Riscv32::new(&mut interface, DefaultRiscvSequence::create(), quirks);
+
+        core.run().unwrap();
+        assert_eq!(core.status().unwrap(), CoreStatus::Running);
+
+        let state = state.lock().unwrap();
+        assert!(state.running);
+        assert_eq!(state.havereset_acks, 0);
+    }
+
+    #[test]
+    fn force_halt_resets_a_hart_which_ignores_halt_requests() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.running = true;
+            state.ignored_halt_requests = usize::MAX;
+            state.hart_registers.insert(0x7b1, 0x2001_0000);
+        }
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        let mut core = Core::new(riscv, &mut core_state);
+
+        let timeout = Duration::from_millis(10);
+
+        // A caller which has to keep the registers gives up after the halt requests.
+        match core.force_halt(timeout, HaltEscalation::PersistentHaltRequest) {
+            Err(Error::ForceHaltFailed { attempts }) => {
+                let levels: Vec<_> = attempts.iter().map(|attempt| attempt.level).collect();
+                assert_eq!(
+                    levels,
+                    [
+                        HaltEscalation::HaltRequest,
+                        HaltEscalation::PersistentHaltRequest
+                    ]
+                );
+                assert!(attempts
+                    .iter()
+                    .all(|attempt| attempt.outcome == HaltAttemptOutcome::TimedOut));
+            }
+            other => panic!("Expected the force halt to fail, got {:?}", other),
+        }
+        assert!(state.lock().unwrap().running);
+
+        let report = core
+            .force_halt(timeout, HaltEscalation::HardwareReset)
+            .unwrap();
+
+        assert_eq!(report.level, HaltEscalation::CoreReset);
+        assert_eq!(report.attempts.len(), 3);
+        assert_eq!(report.pc, 0x2001_0000);
+        assert!(!report.registers_preserved);
+
+        let state = state.lock().unwrap();
+        assert!(!state.running);
+        assert!(!state.havereset);
+    }
+
+    #[test]
+    fn halts_between_status_reads_are_detected_from_the_cause() {
+        let (mut interface, state) = mock_interface();
+
+        // Halted on request.
+        state.lock().unwrap().hart_registers.insert(0x7b0, 3 << 6);
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        let mut core = Core::new(riscv, &mut core_state);
+
+        assert_eq!(
+            core.status().unwrap(),
+            CoreStatus::Halted(HaltReason::Request)
+        );
+        assert_eq!(core.halt_generation(), 1);
+        core.status().unwrap();
+        assert_eq!(core.halt_generation(), 1);
+
+        // The hart was resumed by someone else, and hit a breakpoint before the next read.
+        state.lock().unwrap().hart_registers.insert(0x7b0, 1 << 6);
+
+        assert_eq!(
+            core.status().unwrap(),
+            CoreStatus::Halted(HaltReason::Breakpoint)
+        );
+        assert_eq!(core.halt_generation(), 2);
+    }
+
+    #[test]
+    fn fpu_state_is_read_in_one_batch() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            // RV32IFD, with the FPU in the Initial state.
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_D | MISA_F | 1 << 8);
+            state.hart_registers.insert(0x300, 1 << MSTATUS_FS);
+            state.hart_registers.insert(0x003, 0x20);
+            for index in 0..32 {
+                state
+                    .wide_registers
+                    .insert(F0 + index, 0x4000_0000_0000_0000 | u64::from(index));
+            }
+        }
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let commands = state.lock().unwrap().executed_commands;
+        let fpu = core.read_fpu_state().unwrap();
+
+        assert_eq!(
+            fpu.status,
+            FpuValue::new(FCSR, RegisterValue::U32(0x20), FpuValueSource::Register)
+        );
+        assert_eq!(fpu.registers.len(), 32);
+        assert_eq!(fpu.registers[31].id, RegisterId(0x103f));
+        assert_eq!(
+            fpu.registers[31].value,
+            RegisterValue::U64(0x4000_0000_0000_001f)
+        );
+
+        // `misa` and `mstatus`, and then fcsr and f0-f31.
+        assert_eq!(state.lock().unwrap().executed_commands - commands, 35);
+    }
+
+    #[test]
+    fn fpu_state_is_not_read_while_the_fpu_is_off() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_F | 1 << 8);
+            state.hart_registers.insert(0x300, 0);
+        }
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let commands = state.lock().unwrap().executed_commands;
+
+        match core.read_fpu_state() {
+            Err(Error::FpuDisabled(reason)) => assert!(reason.contains("mstatus.FS"), "{}", reason),
+            other => panic!("Expected the FPU to be off, got {:?}", other),
+        }
+
+        // Only `misa` and `mstatus` were read.
+        assert_eq!(state.lock().unwrap().executed_commands - commands, 2);
+    }
+
+    #[test]
+    fn fpu_support_follows_misa() {
+        let (mut interface, state) = mock_interface();
+        state
+            .lock()
+            .unwrap()
+            .hart_registers
+            .insert(0x301, 1 << 30 | MISA_F | 1 << 8);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert!(core.fpu_support().unwrap());
+        assert_eq!(core.registers().fpscr().unwrap().id, FCSR);
+        assert_eq!(core.registers().fpu_registers().unwrap().count(), 32);
+        assert_eq!(
+            core.registers().fpu_register(8).unwrap().data_type(),
+            RegisterDataType::FloatingPoint
+        );
+
+        let (mut interface, state) = mock_interface();
+        state.lock().unwrap().hart_registers.insert(0x301, 1 << 8);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert!(!core.fpu_support().unwrap());
+
+        // The FPRs of a hart without the F and D extensions are rejected before any access.
+        let commands = state.lock().unwrap().executed_commands;
+        assert!(matches!(
+            core.read_core_reg(RegisterId(0x1028)),
+            Err(Error::FpuNotPresent)
+        ));
+        assert!(matches!(
+            core.write_core_reg(RegisterId(0x1028), RegisterValue::U32(0)),
+            Err(Error::FpuNotPresent)
+        ));
+        assert_eq!(state.lock().unwrap().executed_commands, commands);
+    }
+
+    #[test]
+    fn fprs_fall_back_to_the_program_buffer() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            // RV32IF, whose Debug Module can't access the FPRs with abstract commands.
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_F | 1 << 8);
+            // The FPU is off, so `fmv` raises an exception unless it is turned on.
+            state.hart_registers.insert(0x300, 0);
+            state.hart_registers.insert(0x1008, 0x1234);
+            state.fp_registers.insert(8, 0x3f80_0000);
+            state.execute = true;
+        }
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert_eq!(
+            core.read_core_reg(RegisterId(0x1028)).unwrap(),
+            RegisterValue::U32(0x3f80_0000)
+        );
+
+        let instructions = std::mem::take(&mut state.lock().unwrap().program_buffer_writes);
+        assert!(instructions.contains(&assembly::fmv_x_w(8, 8)));
+
+        core.write_core_reg(RegisterId(0x1029), RegisterValue::U32(0x4000_0000))
+            .unwrap();
+        assert_eq!(
+            core.read_core_reg(RegisterId(0x1029)).unwrap(),
+            RegisterValue::U32(0x4000_0000)
+        );
+
+        {
+            let state = state.lock().unwrap();
+            assert_eq!(state.fp_registers[&9], 0x4000_0000);
+            // s0 and mstatus are restored after each access.
+            assert_eq!(state.hart_registers[&0x1008], 0x1234);
+            assert_eq!(state.hart_registers[&0x300], 0);
+        }
+
+        // With the D extension, a RV32 hart can't move the 64-bit FPRs to a GPR.
+        let (mut interface, state) = mock_interface();
+        state
+            .lock()
+            .unwrap()
+            .hart_registers
+            .insert(0x301, 1 << 30 | MISA_D | MISA_F | 1 << 8);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert!(matches!(
+            core.read_core_reg(RegisterId(0x1028)),
+            Err(Error::ArchitectureSpecific(_))
+        ));
+    }
+
+    #[test]
+    fn halt_gives_up_at_its_timeout_if_the_debug_module_stalls() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.running = true;
+            state.stalled_commands = true;
+            state.hart_registers.insert(0x7b1, 0x2001_0000);
+        }
+
+        // The interface polls a busy Debug Module for seconds on its own.
+        assert!(interface.timeout() >= Duration::from_secs(1));
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        let mut core = Core::new(riscv, &mut core_state);
+
+        let timeout = Duration::from_millis(50);
+        let start = Instant::now();
+
+        // The hart halts, but reading its program counter never finishes.
+        let error = core.halt(timeout).unwrap_err();
+
+        assert!(matches!(
+            &error,
+            Error::ArchitectureSpecific(error)
+                if matches!(error.downcast_ref::<RiscvError>(), Some(RiscvError::Timeout))
+        ));
+        assert!(
+            start.elapsed() < timeout + Deadline::MIN_BUDGET * 5,
+            "halt took {:?}",
+            start.elapsed()
+        );
+        assert!(!state.lock().unwrap().running);
+    }
+
+    #[test]
+    fn read_core_regs_busy_falls_back_to_polling() {
+        let (mut interface, state) = mock_interface();
+        let registers = test_registers();
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        // The fourth command is still busy when data0 is read.
+        {
+            let mut state = state.lock().unwrap();
+            state.busy_after = 3;
+            state.busy_reads = 1;
+            state.executed_commands = 0;
+        }
+
+        let values = core.read_core_regs(&registers).unwrap();
+
+        for (register, value) in registers.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+
+        // The batch stops at the busy command, and only the registers from it on are read
+        // again.
+        assert_eq!(
+            state.lock().unwrap().executed_commands,
+            4 + registers.len() - 3
+        );
+    }
+
+    #[test]
+    fn failed_register_run_disables_autoexec() {
+        let (probe, state) = MockDebugModule::new();
+
+        // A command of the run is still busy when data0 is read, so the write which disables
+        // autoexec at the end of the run is ignored.
+        {
+            let mut state = state.lock().unwrap();
+            state.autoexec = true;
+            state.busy_reads = 4;
+            for regno in test_registers() {
+                state
+                    .hart_registers
+                    .insert(regno.0, 0xcafe_0000 | regno.0 as u32);
+            }
+        }
+
+        let mut interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+        let registers = test_registers();
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let values = core.read_core_regs(&registers).unwrap();
+
+        for (register, value) in registers.iter().zip(values) {
+            assert_eq!(value, RegisterValue::U32(0xcafe_0000 | register.0 as u32));
+        }
+
+        drop(core);
+        let abstractauto: communication_interface::Abstractauto =
+            interface.read_dm_register().unwrap();
+        assert_eq!(abstractauto.autoexecdata(), 0);
+    }
+
+    #[test]
+    fn absent_registers_are_rejected_without_access() {
+        let (mut interface, state) = mock_interface();
+
+        // RV32I, without the F and D extensions.
+        state.lock().unwrap().hart_registers.insert(0x301, 1 << 8);
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        let mut core = Core::new(riscv, &mut core_state);
+
+        // The presence of the FPRs is read from misa once.
+        let error = core.read_core_reg::<u32>(RegisterId(0x1028)).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::RegisterNotAvailable { name, .. } if name == "f8"
+        ));
+
+        state.lock().unwrap().transactions = 0;
+
+        for register in [RegisterId(0x1028), RegisterId(0x1040)] {
+            let error = core.read_core_reg::<u32>(register).unwrap_err();
+            assert!(matches!(error, Error::RegisterNotAvailable { .. }));
+
+            let error = core.write_core_reg(register, 0u32).unwrap_err();
+            assert!(matches!(error, Error::RegisterNotAvailable { .. }));
+        }
+
+        assert_eq!(state.lock().unwrap().transactions, 0);
+    }
+
+    #[test]
+    fn ch32v203_debug_module_is_reactivated() {
+        let (mut interface, state) = mock_interface();
+
+        let quirks = target_quirks("CH32V203C8T6");
+        assert!(quirks.reactivate_debug_module);
+
+        interface.apply_quirks(quirks).unwrap();
+
+        assert_eq!(state.lock().unwrap().deactivations, 1);
+
+        // The debug module is examined again afterwards.
+        assert_eq!(interface.debug_module().version, "0.13");
+    }
+
+    #[test]
+    fn rv32e_harts_only_have_sixteen_gprs() {
+        let (mut interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.running = true;
+            state.hart_registers.insert(0x7b1, 0x2000_0000);
+            state.hart_registers.insert(0x100f, 0x1234_5678);
+            // RV32EC, like the CH32V003.
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_E | 1 << 2);
+        }
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        let mut core = Core::new(riscv, &mut core_state);
+
+        // The base ISA is only known once the hart was halted.
+        assert_eq!(core.registers().registers().count(), 32);
+
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        let registers = core.registers();
+        assert_eq!(registers.registers().count(), 16);
+        assert_eq!(registers.argument_registers.len(), 6);
+
+        let error = core.read_core_reg::<u32>(RegisterId(0x1010)).unwrap_err();
+        assert!(matches!(error, Error::RegisterNotAvailable { .. }));
+
+        assert_eq!(
+            core.read_core_reg::<u32>(RegisterId(0x100f)).unwrap(),
+            0x1234_5678
+        );
+    }
+
+    /// Timeout of the interface while fuzzing, short so that runs which time out are fast.
+    const FUZZ_TIMEOUT: Duration = Duration::from_millis(10);
+
+    /// Run `operation`, and check that it gives up within the timeouts, even if the Debug
+    /// Module keeps reporting that it is busy.
+    fn bounded<T>(seed: u64, name: &str, operation: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = operation();
+
+        assert!(
+            start.elapsed() < FUZZ_TIMEOUT * 20,
+            "{} took {:?} with seed {}",
+            name,
+            start.elapsed(),
+            seed
+        );
+
+        result
+    }
+
+    #[test]
+    fn byte_writes_are_single_stores() {
+        let (mut interface, state) = mock_interface();
+        {
+            let mut state = state.lock().unwrap();
+            for regno in [0x1008, 0x1009] {
+                state.hart_registers.insert(regno, 0);
+            }
+        }
+
+        assert!(interface.native_write_widths().bits_8);
+
+        interface.write_word_8(0x4000_0001, 0x5a).unwrap();
+        interface.write_8(0x4000_0003, &[1, 2, 3]).unwrap();
+
+        // Only `sb` instructions were run, no load of the words which contain the bytes.
+        let instructions = std::mem::take(&mut state.lock().unwrap().program_buffer_writes);
+        let is_load = |instruction: &u32| instruction & 0x7f == 0b000_0011;
+        let is_store_byte = |instruction: &u32| instruction & 0x707f == 0b010_0011;
+        assert!(!instructions.iter().any(is_load));
+        assert!(instructions.iter().any(is_store_byte));
+    }
+
+    /// Write 1 KiB through the program buffer, and return the number of probe round trips,
+    /// DMI operations and executed abstract commands.
+    fn progbuf_write_cost(
+        interface: &mut RiscvCommunicationInterface,
+        state: &std::sync::Mutex<mock::MockDebugModuleState>,
+    ) -> (usize, usize, usize) {
+        {
+            let mut state = state.lock().unwrap();
+            for regno in 0x1008..=0x100b {
+                state.hart_registers.insert(regno, 0);
+            }
+            state.transactions = 0;
+            state.dmi_operations = 0;
+            state.executed_commands = 0;
+        }
+
+        interface
+            .write_32(0x2000_0000, &[0x5a5a_5a5a; 256])
+            .unwrap();
+
+        let state = state.lock().unwrap();
+        (
+            state.transactions,
+            state.dmi_operations,
+            state.executed_commands,
+        )
+    }
+
+    /// A Debug Module with eight program buffer words, whose twelve data registers are
+    /// shadowed at 0x380 in the memory map of the hart.
+    fn shadowed_interface(
+        autoexec: bool,
+    ) -> (
+        RiscvCommunicationInterface,
+        std::sync::Arc<std::sync::Mutex<mock::MockDebugModuleState>>,
+    ) {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.abstract_sizes = Some((8, 12));
+            state.hartinfo = (1 << 16) | (12 << 12) | 0x380;
+            state.autoexec = autoexec;
+        }
+
+        let interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        (interface, state)
+    }
+
+    #[test]
+    fn shadowed_data_registers_stage_several_words() {
+        let (mut interface, state) = mock_interface();
+        let (word_transactions, word_operations, word_commands) =
+            progbuf_write_cost(&mut interface, &state);
+        assert!(word_commands >= 256);
+
+        let (mut interface, state) = shadowed_interface(false);
+        let debug_module = interface.debug_module();
+        assert_eq!(debug_module.shadowed_data_words, 12);
+        assert_eq!(debug_module.shadowed_data_address, 0x380);
+
+        let (transactions, operations, commands) = progbuf_write_cost(&mut interface, &state);
+
+        // 22 executions of the program buffer store the 256 words, twelve at a time.
+        assert!(commands < 40, "{} commands", commands);
+        assert!(operations * 3 < word_operations * 2);
+        assert!(transactions * 4 < word_transactions);
+
+        let instructions = std::mem::take(&mut state.lock().unwrap().program_buffer_writes);
+        assert!(instructions.contains(&assembly::bne(10, 11, -16)));
+
+        // With autoexec, staging the last word starts the execution.
+        let (mut interface, state) = shadowed_interface(true);
+        let (_, autoexec_operations, autoexec_commands) =
+            progbuf_write_cost(&mut interface, &state);
+
+        assert_eq!(autoexec_commands, commands);
+        assert!(autoexec_operations + 15 < operations);
+    }
+
+    #[test]
+    fn small_writes_keep_the_word_at_a_time_path() {
+        let (mut interface, state) = shadowed_interface(false);
+        state.lock().unwrap().hart_registers.insert(0x1008, 0);
+        state.lock().unwrap().hart_registers.insert(0x1009, 0);
+
+        interface.write_32(0x2000_0000, &[1, 2]).unwrap();
+
+        // The set-up of the shadowed path costs more than it saves for two words.
+        let instructions = std::mem::take(&mut state.lock().unwrap().program_buffer_writes);
+        assert!(!instructions.contains(&assembly::bne(10, 11, -16)));
+    }
+
+    /// Drive the interface against a Debug Module whose responses are corrupted based on `seed`.
+    ///
+    /// Returns false if the interface gave up already while entering debug mode.
+    fn fuzz_run(seed: u64) -> bool {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.corruption = Some(mock::Corruption::new(seed, 8));
+            for regno in test_registers().into_iter().chain([RegisterId(0x1008)]) {
+                state.hart_registers.insert(regno.0, regno.0 as u32);
+            }
+        }
+
+        let mut interface = match bounded(seed, "enter_debug_mode", || {
+            RiscvCommunicationInterface::with_timeout(Box::new(probe), FUZZ_TIMEOUT)
+        }) {
+            Ok(interface) => interface,
+            // Giving up on a hostile Debug Module is fine, as long as there's no panic.
+            Err(_) => return false,
+        };
+
+        let mut data = [0u32; 4];
+        let _ = bounded(seed, "read_word_32", || interface.read_word_32(0x2000_0000));
+        let _ = bounded(seed, "read_32", || {
+            interface.read_32(0x2000_0000, &mut data)
+        });
+        let _ = bounded(seed, "read_8", || {
+            interface.read_8(0x2000_0001, &mut [0; 3])
+        });
+        let _ = bounded(seed, "write_32", || interface.write_32(0x2000_0000, &data));
+        let _ = bounded(seed, "write_word_8", || {
+            interface.write_word_8(0x2000_0003, 0xaa)
+        });
+        let _ = bounded(seed, "abstract_cmd_register_read", || {
+            interface.abstract_cmd_register_read(RegisterId(0x1008))
+        });
+        let _ = bounded(seed, "abstract_cmd_register_read_batch", || {
+            interface.abstract_cmd_register_read_batch(&test_registers())
+        });
+        let _ = bounded(seed, "abstract_cmd_register_write", || {
+            interface.abstract_cmd_register_write(RegisterId(0x1008), 0x1234u32)
+        });
+        let _ = bounded(seed, "read_csr_progbuf", || {
+            interface.read_csr_progbuf(0x301)
+        });
+
+        true
+    }
+
+    #[test]
+    fn hostile_debug_module_never_panics() {
+        let mut attached = 0;
+
+        for seed in 0..256 {
+            match std::panic::catch_unwind(|| fuzz_run(seed)) {
+                Ok(true) => attached += 1,
+                Ok(false) => (),
+                Err(panic) => {
+                    eprintln!("Replay with `fuzz_run({})`", seed);
+                    std::panic::resume_unwind(panic);
+                }
+            }
+        }
+
+        // Most runs have to get past entering debug mode, to exercise the other operations.
+        assert!(attached > 64, "Only {} runs entered debug mode", attached);
+    }
+
+    #[test]
+    fn watchpoints_use_napot_triggers_for_aligned_ranges() {
+        let config = WatchpointConfig::new(0x8000_0010, 8, WatchpointKind::Write);
+        let triggers = watchpoint_triggers(&config, 31).unwrap();
+
+        assert_eq!(triggers.len(), 1);
+        let (trigger, tdata2) = &triggers[0];
+        assert_eq!(trigger.match_(), 1);
+        assert!(trigger.store() && !trigger.load() && !trigger.execute());
+        assert!(!trigger.chain());
+        assert_eq!(*tdata2, 0x8000_0013);
+
+        // Ranges larger than maskmax take a chain of two triggers.
+        let triggers = watchpoint_triggers(&config, 2).unwrap();
+        assert_eq!(
+            triggers
+                .iter()
+                .map(|(trigger, tdata2)| (trigger.match_(), trigger.chain(), *tdata2))
+                .collect::<Vec<_>>(),
+            [(2, true, 0x8000_0010), (3, false, 0x8000_0018)]
+        );
+    }
+
+    #[test]
+    fn watched_values_are_chained_after_the_address() {
+        let config = WatchpointConfig::new(0x8000_0000, 2, WatchpointKind::Read)
+            .value(0x1234, 0xff)
+            .access_size(2);
+        let triggers = watchpoint_triggers(&config, 31).unwrap();
+
+        assert_eq!(triggers.len(), 2);
+        let (address, _) = &triggers[0];
+        assert!(address.chain() && !address.select());
+        let (value, tdata2) = &triggers[1];
+        assert!(!value.chain() && value.select());
+        assert_eq!(value.match_(), 4);
+        assert_eq!(value.sizelo(), 2);
+        assert_eq!(*tdata2, 0x00ff_0034);
+
+        let config = WatchpointConfig::new(0x8000_0000, 4, WatchpointKind::Read).value(1, 0xff);
+        assert!(matches!(
+            watchpoint_triggers(&config, 31),
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::ValueMask,
+                ..
+            })
+        ));
+
+        let config = WatchpointConfig::new(0x8000_0000, 4, WatchpointKind::Read).access_size(8);
+        assert!(matches!(
+            watchpoint_triggers(&config, 31),
+            Err(Error::UnsupportedWatchpointQualifier {
+                qualifier: WatchpointQualifier::AccessSize,
+                ..
+            })
+        ));
+    }
+
+    /// The start of the code of the hart of [`ch32v003_interface`].
+    const CODE: u32 = 0x2000_0100;
+
+    /// An interface to a hart like the one of the CH32V003: RV32EC, with eight program
+    /// buffer words, two data registers and four triggers, and no system bus access, so that
+    /// memory is accessed through the program buffer. The hart executes, and is halted at
+    /// [`CODE`].
+    fn ch32v003_interface() -> (
+        RiscvCommunicationInterface,
+        std::sync::Arc<std::sync::Mutex<mock::MockDebugModuleState>>,
+    ) {
+        let (interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.execute = true;
+            state.abstract_sizes = Some((8, 2));
+            state.triggers = 4;
+            state.resume_ack_after = Some(0);
+            state.running = true;
+
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_E | MISA_C);
+            // xdebugver 4, machine mode
+            state.hart_registers.insert(0x7b0, 4 << 28 | 0b11);
+            state.hart_registers.insert(0x7b1, CODE);
+            for regno in 0x1001..0x1010 {
+                state.hart_registers.insert(regno, 0);
+            }
+        }
+
+        (interface, state)
+    }
+
+    #[test]
+    fn ch32v003_breakpoints_and_steps_follow_compressed_instructions() {
+        let (mut interface, state) = ch32v003_interface();
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        core_state.set_ram_ranges(vec![0x2000_0000..0x2000_0800]);
+        let mut core = Core::new(riscv, &mut core_state);
+
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        // c.nop; addi x0, x0, 0; c.nop; c.nop
+        let code = [0x01, 0x00, 0x13, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00];
+        core.write_8(CODE as u64, &code).unwrap();
+
+        let mut read = [0; 10];
+        core.read_8(CODE as u64, &mut read).unwrap();
+        assert_eq!(read, code);
+        assert_eq!(core.read_word_32(CODE as u64 + 4).unwrap(), 0x0001_0000);
+
+        // The program buffer only used registers which exist on RV32E harts, otherwise the
+        // hart would have raised an exception.
+        assert_eq!(state.lock().unwrap().memory[&u64::from(CODE + 2)], 0x13);
+
+        // The 32-bit instruction is aligned to 2 bytes only.
+        assert_eq!(core.step().unwrap().pc, CODE as u64 + 2);
+        assert_eq!(core.step().unwrap().pc, CODE as u64 + 6);
+
+        // A C.EBREAK replaces the compressed instruction, and leaves the next one intact.
+        core.set_sw_breakpoint(CODE as u64 + 8).unwrap();
+        let mut read = [0; 4];
+        core.read_8(CODE as u64 + 6, &mut read).unwrap();
+        assert_eq!(read, [0x01, 0x00, 0x02, 0x90]);
+
+        core.run().unwrap();
+        core.wait_for_core_halted(Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(
+            core.status().unwrap(),
+            CoreStatus::Halted(HaltReason::SoftwareBreakpoint)
+        );
+        assert_eq!(
+            core.read_core_reg::<u32>(RegisterId(0x7b1)).unwrap(),
+            CODE + 8
+        );
+        core.clear_sw_breakpoint(CODE as u64 + 8).unwrap();
+
+        // Instructions are 2 bytes apart, and breakpoints have to be aligned to them.
+        for result in [
+            core.set_hw_breakpoint(CODE as u64 + 1),
+            core.set_sw_breakpoint(CODE as u64 + 1),
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::UnsupportedBreakpointAddress { .. })
+            ));
+        }
+
+        core.write_core_reg(RegisterId(0x7b1), CODE).unwrap();
+        core.set_hw_breakpoint(CODE as u64 + 6).unwrap();
+
+        core.run().unwrap();
+        core.wait_for_core_halted(Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(
+            core.status().unwrap(),
+            CoreStatus::Halted(HaltReason::Breakpoint)
+        );
+        assert_eq!(
+            core.read_core_reg::<u32>(RegisterId(0x7b1)).unwrap(),
+            CODE + 6
+        );
+    }
+
+    #[test]
+    fn breakpoints_are_aligned_to_4_bytes_without_the_c_extension() {
+        let (mut interface, state) = ch32v003_interface();
+        // RV32E
+        state
+            .lock()
+            .unwrap()
+            .hart_registers
+            .insert(0x301, 1 << 30 | MISA_E);
+
+        let riscv = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+        let mut core_state = CoreState::new(0, CoreAccessOptions::Riscv(Default::default()));
+        core_state.set_ram_ranges(vec![0x2000_0000..0x2000_0800]);
+        let mut core = Core::new(riscv, &mut core_state);
+
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        for result in [
+            core.set_hw_breakpoint(CODE as u64 + 2),
+            core.set_sw_breakpoint(CODE as u64 + 2),
+        ] {
+            assert!(matches!(
+                result,
+                Err(Error::UnsupportedBreakpointAddress { .. })
+            ));
+        }
+
+        core.set_hw_breakpoint(CODE as u64 + 4).unwrap();
+    }
+
+    /// A hart with 64-bit registers, whose `s0` holds a value with all 64 bits set.
+    fn rv64_interface() -> (
+        RiscvCommunicationInterface,
+        std::sync::Arc<std::sync::Mutex<mock::MockDebugModuleState>>,
+    ) {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.rv64 = true;
+            state.wide_registers.insert(0x1008, 0x1234_5678_9abc_def0);
+            state.hart_registers.insert(0x1009, 0x5555);
+        }
+
+        let interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        (interface, state)
+    }
+
+    #[test]
+    fn xlen_is_determined_at_attach() {
+        let (mut interface, _) = mock_interface();
+        assert_eq!(interface.xlen(), RiscvBusAccess::A32);
+        assert!(!interface.supports_native_64bit_access());
+
+        // Only addresses of 32 bits are valid on a RV32 hart.
+        assert!(interface.read_word_32(0x1_0000_0000).is_err());
+
+        let (mut interface, _) = rv64_interface();
+        assert_eq!(interface.xlen(), RiscvBusAccess::A64);
+        assert!(interface.supports_native_64bit_access());
+    }
+
+    #[test]
+    fn rv64_registers_are_read_and_written_with_64_bits() {
+        let (mut interface, state) = rv64_interface();
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert_eq!(
+            core.read_core_reg(RegisterId(0x1008)).unwrap(),
+            RegisterValue::U64(0x1234_5678_9abc_def0)
+        );
+        assert_eq!(
+            core.read_core_regs(&[RegisterId(0x1008), RegisterId(0x1009)])
+                .unwrap(),
+            [
+                RegisterValue::U64(0x1234_5678_9abc_def0),
+                RegisterValue::U64(0x5555)
+            ]
+        );
+
+        core.write_core_reg(
+            RegisterId(0x1009),
+            RegisterValue::U64(0xffff_0000_0000_0001),
+        )
+        .unwrap();
+        assert_eq!(
+            state.lock().unwrap().wide_registers[&0x1009],
+            0xffff_0000_0000_0001
+        );
+    }
+
+    #[test]
+    fn rv64_program_buffer_uses_ld_and_sd() {
+        let (mut interface, state) = rv64_interface();
+        state.lock().unwrap().execute = true;
+
+        interface
+            .write_word_64(0x1_0000_0008, 0xdead_beef_0000_0001)
+            .unwrap();
+        assert_eq!(
+            interface.read_word_64(0x1_0000_0008).unwrap(),
+            0xdead_beef_0000_0001
+        );
+        assert_eq!(interface.read_word_64(0x1_0000_0000).unwrap(), 0);
+
+        let instructions = std::mem::take(&mut state.lock().unwrap().program_buffer_writes);
+        assert!(instructions.contains(&assembly::ld(0, 8, 8)));
+        assert!(instructions.contains(&assembly::sd(0, 8, 9)));
+
+        // The word was stored above 4 GiB, in little endian.
+        let state = state.lock().unwrap();
+        let word: [u8; 8] =
+            std::array::from_fn(|offset| state.memory[&(0x1_0000_0008 + offset as u64)]);
+        assert_eq!(u64::from_le_bytes(word), 0xdead_beef_0000_0001);
+
+        // All 64 bits of the registers which were used are restored.
+        assert_eq!(state.wide_registers[&0x1008], 0x1234_5678_9abc_def0);
+        assert_eq!(state.wide_registers[&0x1009], 0x5555);
+    }
+
+    #[test]
+    fn system_bus_address_is_written_whole() {
+        let (probe, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.rv64 = true;
+            state.system_bus = true;
+        }
+
+        let mut interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        interface.write_word_32(0x1_0000_0000, 0x1111_1111).unwrap();
+        interface.write_word_32(0x2000_0000, 0x2222_2222).unwrap();
+        interface
+            .write_32(0x2000_0004, &[0x3333_3333, 0x4444_4444])
+            .unwrap();
+
+        // `sbaddress1` of the previous access doesn't select the memory above 4 GiB.
+        assert_eq!(interface.read_word_32(0x1_0000_0000).unwrap(), 0x1111_1111);
+        assert_eq!(interface.read_word_32(0x2000_0000).unwrap(), 0x2222_2222);
+        assert_eq!(interface.read_word_32(0x1_0000_0004).unwrap(), 0);
+
+        let mut words = [0; 2];
+        interface.read_32(0x2000_0004, &mut words).unwrap();
+        assert_eq!(words, [0x3333_3333, 0x4444_4444]);
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.memory[&0x1_0000_0000], 0x11);
+        assert_eq!(state.memory[&0x2000_0000], 0x22);
+        assert_eq!(state.memory[&0x2000_0008], 0x44);
+        assert!(!state.memory.contains_key(&0x1_2000_0000));
+        assert!(state.program_buffer_writes.is_empty());
+    }
+
+    /// The address of the word the atomic operations modify.
+    const ATOMIC_WORD: u32 = 0x8000_0100;
+
+    /// An interface to a RV32IA hart, which executes its program buffer of four words, with
+    /// `word` at [`ATOMIC_WORD`] in its memory.
+    fn atomic_interface(
+        word: u32,
+    ) -> (
+        RiscvCommunicationInterface,
+        std::sync::Arc<std::sync::Mutex<mock::MockDebugModuleState>>,
+    ) {
+        let (interface, state) = mock_interface();
+
+        {
+            let mut state = state.lock().unwrap();
+            state.execute = true;
+            state.abstract_sizes = Some((4, 1));
+            state
+                .hart_registers
+                .insert(0x301, 1 << 30 | MISA_A | 1 << 8);
+            for regno in 0x1008..0x1010 {
+                state
+                    .hart_registers
+                    .insert(regno, 0x1000 + u32::from(regno));
+            }
+            for (offset, byte) in word.to_le_bytes().into_iter().enumerate() {
+                state
+                    .memory
+                    .insert(u64::from(ATOMIC_WORD) + offset as u64, byte);
+            }
+        }
+
+        (interface, state)
+    }
+
+    fn atomic_word(state: &mock::MockDebugModuleState) -> u32 {
+        u32::from_le_bytes(std::array::from_fn(|offset| {
+            state.memory[&(u64::from(ATOMIC_WORD) + offset as u64)]
+        }))
+    }
+
+    #[test]
+    fn fetch_or_and_fetch_and_are_single_amos() {
+        let (mut interface, state) = atomic_interface(0x0000_00f0);
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let previous = core
+            .atomic_32(u64::from(ATOMIC_WORD), AtomicOperation::Or(0x0f00))
+            .unwrap();
+        assert_eq!(previous, Some(0x0000_00f0));
+
+        let previous = core
+            .atomic_32(u64::from(ATOMIC_WORD), AtomicOperation::And(0x0f0f))
+            .unwrap();
+        assert_eq!(previous, Some(0x0000_0ff0));
+
+        let state = state.lock().unwrap();
+        assert_eq!(atomic_word(&state), 0x0000_0f00);
+        assert!(state
+            .program_buffer_writes
+            .contains(&assembly::amoor_w(9, 9, 8)));
+        assert!(!state.program_buffer_writes.contains(&assembly::lr_w(9, 8)));
+
+        // The registers used by the program buffer are restored.
+        for regno in 0x1008..0x100c {
+            assert_eq!(state.hart_registers[&regno], 0x1000 + u32::from(regno));
+        }
+    }
+
+    #[test]
+    fn compare_and_swap_retries_a_lost_reservation() {
+        let (mut interface, state) = atomic_interface(1);
+        // Another hart sets a bit of the word, right after the first `lr.w`.
+        state.lock().unwrap().concurrent_writes = vec![0x100];
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let swap = AtomicOperation::CompareAndSwap {
+            expected: 1,
+            new: 2,
+        };
+        let previous = core.atomic_32(u64::from(ATOMIC_WORD), swap).unwrap();
+
+        // The second attempt sees the changed word, which isn't swapped.
+        assert_eq!(previous, Some(0x101));
+        assert_eq!(atomic_word(&state.lock().unwrap()), 0x101);
+
+        let swap = AtomicOperation::CompareAndSwap {
+            expected: 0x101,
+            new: 2,
+        };
+        let previous = core.atomic_32(u64::from(ATOMIC_WORD), swap).unwrap();
+
+        assert_eq!(previous, Some(0x101));
+        assert_eq!(atomic_word(&state.lock().unwrap()), 2);
+    }
+
+    #[test]
+    fn amos_which_raise_an_exception_fall_back_to_reservations() {
+        let (mut interface, state) = atomic_interface(0x10);
+        {
+            let mut state = state.lock().unwrap();
+            state.amo_exceptions = true;
+            state.concurrent_writes = vec![0x1];
+        }
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        let previous = core
+            .atomic_32(u64::from(ATOMIC_WORD), AtomicOperation::Or(0x100))
+            .unwrap();
+
+        // The bit set by the other hart is kept.
+        assert_eq!(previous, Some(0x11));
+        assert_eq!(atomic_word(&state.lock().unwrap()), 0x111);
+    }
+
+    #[test]
+    fn harts_without_the_a_extension_have_no_atomics() {
+        let (mut interface, state) = atomic_interface(0);
+        state
+            .lock()
+            .unwrap()
+            .hart_registers
+            .insert(0x301, 1 << 30 | 1 << 8);
+
+        let mut core = Riscv32::new(
+            &mut interface,
+            DefaultRiscvSequence::create(),
+            RiscvQuirks::default(),
+        );
+
+        assert_eq!(
+            core.atomic_32(u64::from(ATOMIC_WORD), AtomicOperation::Or(1))
+                .unwrap(),
+            None
+        );
+        assert_eq!(atomic_word(&state.lock().unwrap()), 0);
+    }
+}
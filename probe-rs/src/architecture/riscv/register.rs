@@ -59,6 +59,7 @@ static PC: RegisterDescription = RegisterDescription {
     id: RegisterId(0x7b1),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: 0xffff_fffe,
 };
 
 static RA: RegisterDescription = RegisterDescription {
@@ -68,6 +69,7 @@ static RA: RegisterDescription = RegisterDescription {
     id: RegisterId(0x1001),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
 static SP: RegisterDescription = RegisterDescription {
@@ -77,6 +79,7 @@ static SP: RegisterDescription = RegisterDescription {
     id: RegisterId(0x1002),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
 static FP: RegisterDescription = RegisterDescription {
@@ -86,6 +89,7 @@ static FP: RegisterDescription = RegisterDescription {
     id: RegisterId(0x1008),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
 pub static S0: RegisterDescription = RegisterDescription {
@@ -95,6 +99,7 @@ pub static S0: RegisterDescription = RegisterDescription {
     id: RegisterId(0x1008),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
 pub static S1: RegisterDescription = RegisterDescription {
@@ -104,9 +109,301 @@ pub static S1: RegisterDescription = RegisterDescription {
     id: RegisterId(0x1009),
     _type: RegisterDataType::UnsignedInteger,
     size_in_bits: 32,
+    writable_mask: u64::MAX,
 };
 
-pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
+pub static A0: RegisterDescription = RegisterDescription {
+    name: "a0",
+    _kind: RegisterKind::General,
+    /// This is a CSR register
+    id: RegisterId(0x100A),
+    _type: RegisterDataType::UnsignedInteger,
+    size_in_bits: 32,
+    writable_mask: u64::MAX,
+};
+
+pub static A1: RegisterDescription = RegisterDescription {
+    name: "a1",
+    _kind: RegisterKind::General,
+    /// This is a CSR register
+    id: RegisterId(0x100B),
+    _type: RegisterDataType::UnsignedInteger,
+    size_in_bits: 32,
+    writable_mask: u64::MAX,
+};
+
+/// The `fcsr` CSR, with the rounding mode and the exception flags of the F extension.
+static FCSR: RegisterDescription = RegisterDescription {
+    name: "fcsr",
+    _kind: RegisterKind::Fp,
+    id: RegisterId(0x003),
+    _type: RegisterDataType::UnsignedInteger,
+    size_in_bits: 32,
+    writable_mask: 0xff,
+};
+
+/// The FPRs f0 to f31, which are only present with the F or D extension. They are described
+/// with the 32 bits of the F extension, with the D extension their values have 64 bits.
+static FP_REGISTERS: [RegisterDescription; 32] = [
+    RegisterDescription {
+        name: "f0",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1020),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f1",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1021),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f2",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1022),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f3",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1023),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f4",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1024),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f5",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1025),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f6",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1026),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f7",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1027),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f8",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1028),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f9",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1029),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f10",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102A),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f11",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102B),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f12",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102C),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f13",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102D),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f14",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102E),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f15",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x102F),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f16",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1030),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f17",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1031),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f18",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1032),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f19",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1033),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f20",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1034),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f21",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1035),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f22",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1036),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f23",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1037),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f24",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1038),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f25",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x1039),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f26",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103A),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f27",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103B),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f28",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103C),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f29",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103D),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f30",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103E),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+    RegisterDescription {
+        name: "f31",
+        _kind: RegisterKind::Fp,
+        id: RegisterId(0x103F),
+        _type: RegisterDataType::FloatingPoint,
+        size_in_bits: 32,
+        writable_mask: u64::MAX,
+    },
+];
+
+pub(crate) static RISCV_REGISTERS: RegisterFile = RegisterFile {
     platform_registers: &[
         RegisterDescription {
             name: "x0",
@@ -114,6 +411,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1000),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: 0,
         },
         RegisterDescription {
             name: "x1",
@@ -121,6 +419,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1001),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x2",
@@ -128,6 +427,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1002),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x3",
@@ -135,6 +435,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1003),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x4",
@@ -142,6 +443,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1004),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x5",
@@ -149,6 +451,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1005),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x6",
@@ -156,6 +459,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1006),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x7",
@@ -163,6 +467,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1007),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x8",
@@ -170,6 +475,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1008),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x9",
@@ -177,6 +483,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1009),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x10",
@@ -184,6 +491,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100A),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x11",
@@ -191,6 +499,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100B),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x12",
@@ -198,6 +507,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100C),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x13",
@@ -205,6 +515,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100D),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x14",
@@ -212,6 +523,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100E),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x15",
@@ -219,6 +531,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100F),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x16",
@@ -226,6 +539,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1010),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x17",
@@ -233,6 +547,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1011),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x18",
@@ -240,6 +555,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1012),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x19",
@@ -247,6 +563,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1013),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x20",
@@ -254,6 +571,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1014),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x21",
@@ -261,6 +579,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1015),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x22",
@@ -268,6 +587,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1016),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x23",
@@ -275,6 +595,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1017),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x24",
@@ -282,6 +603,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1018),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x25",
@@ -289,6 +611,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1019),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x26",
@@ -296,6 +619,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101A),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x27",
@@ -303,6 +627,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101B),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x28",
@@ -310,6 +635,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101C),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x29",
@@ -317,6 +643,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101D),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x30",
@@ -324,6 +651,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101E),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "x31",
@@ -331,6 +659,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x101F),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -349,6 +678,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100A),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a1",
@@ -356,6 +686,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100B),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a2",
@@ -363,6 +694,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100C),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a3",
@@ -370,6 +702,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100D),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a4",
@@ -377,6 +710,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100E),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a5",
@@ -384,6 +718,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100F),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a6",
@@ -391,6 +726,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1010),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a7",
@@ -398,6 +734,227 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x1011),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+    ],
+
+    result_registers: &[
+        RegisterDescription {
+            name: "a0",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100A),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a1",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100B),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+    ],
+
+    psp: None,
+    msp: None,
+    extra: None,
+    psr: None,
+    fp_registers: Some(&FP_REGISTERS),
+    fp_status: Some(&FCSR),
+};
+
+/// The registers of RV32E harts, which only have the GPRs x0 to x15, and pass the arguments
+/// of functions in a0 to a5.
+pub(crate) static RISCV_E_REGISTERS: RegisterFile = RegisterFile {
+    platform_registers: &[
+        RegisterDescription {
+            name: "x0",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1000),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: 0,
+        },
+        RegisterDescription {
+            name: "x1",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1001),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x2",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1002),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x3",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1003),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x4",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1004),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x5",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1005),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x6",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1006),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x7",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1007),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x8",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1008),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x9",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x1009),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x10",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100A),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x11",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100B),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x12",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100C),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x13",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100D),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x14",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100E),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "x15",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100F),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+    ],
+
+    program_counter: &PC,
+
+    return_address: &RA,
+
+    stack_pointer: &SP,
+
+    frame_pointer: &FP,
+
+    argument_registers: &[
+        RegisterDescription {
+            name: "a0",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100A),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a1",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100B),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a2",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100C),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a3",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100D),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a4",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100E),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
+        },
+        RegisterDescription {
+            name: "a5",
+            _kind: RegisterKind::General,
+            id: RegisterId(0x100F),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -408,6 +965,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100A),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
         RegisterDescription {
             name: "a1",
@@ -415,6 +973,7 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
             id: RegisterId(0x100B),
             _type: RegisterDataType::UnsignedInteger,
             size_in_bits: 32,
+            writable_mask: u64::MAX,
         },
     ],
 
@@ -422,7 +981,6 @@ pub(super) static RISCV_REGISTERS: RegisterFile = RegisterFile {
     msp: None,
     extra: None,
     psr: None,
-    // TODO: Add FPU registers
-    fp_registers: None,
-    fp_status: None,
+    fp_registers: Some(&FP_REGISTERS),
+    fp_status: Some(&FCSR),
 };
@@ -1,8 +1,14 @@
 //! Sequences for the ESP32C3.
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::RiscvDebugSequence;
+use crate::architecture::riscv::communication_interface::{
+    RiscvCommunicationInterface, RiscvError,
+};
+use crate::architecture::riscv::{Dmcontrol, Dmstatus};
+use crate::architecture::settle::DelayOrPoll;
 use crate::MemoryInterface;
 
 /// The debug sequence implementation for the ESP32C3.
@@ -15,16 +21,32 @@ impl ESP32C3 {
     }
 }
 
-impl RiscvDebugSequence for ESP32C3 {
-    fn on_connect(
+/// The RTC_CNTL_OPTIONS0 register of the RTC controller.
+const RTC_CNTL_OPTIONS0: u64 = 0x6000_8000;
+
+/// Resets the whole system, except for the RTC domain.
+const RTC_CNTL_SW_SYS_RST: u32 = 1 << 31;
+
+impl ESP32C3 {
+    fn disable_watchdogs(
         &self,
-        interface: &mut crate::architecture::riscv::communication_interface::RiscvCommunicationInterface,
+        interface: &mut RiscvCommunicationInterface,
+        delay: &DelayOrPoll,
     ) -> Result<(), crate::Error> {
         log::info!("Disabling esp32c3 watchdogs...");
         // disable super wdt
         interface.write_word_32(0x600080B0, 0x8F1D312Au32)?; // write protection off
         let current = interface.read_word_32(0x600080AC)?;
         interface.write_word_32(0x600080AC, current | 1 << 31)?; // set RTC_CNTL_SWD_AUTO_FEED_EN
+
+        // the RTC registers are in a slow clock domain, wait until the write has taken effect
+        delay.poll_register(
+            interface,
+            0x600080AC,
+            1 << 31,
+            1 << 31,
+            Duration::from_millis(10),
+        )?;
         interface.write_word_32(0x600080B0, 0x0)?; // write protection on
 
         // tg0 wdg
@@ -45,3 +67,84 @@ impl RiscvDebugSequence for ESP32C3 {
         Ok(())
     }
 }
+
+impl RiscvDebugSequence for ESP32C3 {
+    fn on_connect(
+        &self,
+        interface: &mut RiscvCommunicationInterface,
+        delay: &DelayOrPoll,
+    ) -> Result<(), crate::Error> {
+        self.disable_watchdogs(interface, delay)
+    }
+
+    fn reset_and_halt(
+        &self,
+        interface: &mut RiscvCommunicationInterface,
+        timeout: Duration,
+    ) -> Result<(), crate::Error> {
+        // The ESP32C3 implements neither `hartreset` nor `resethaltreq`, and `ndmreset` doesn't
+        // reset the peripherals. Instead, the system is reset through the RTC controller, while
+        // a halt request is pending, so that the core halts right after the reset.
+        log::debug!("Resetting esp32c3 through RTC_CNTL_SW_SYS_RST");
+
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_dmactive(true);
+        dmcontrol.set_haltreq(true);
+
+        // The core has to be halted to access the system bus.
+        interface.write_dm_register(dmcontrol)?;
+        wait_for_status(interface, timeout, Dmstatus::allhalted)?;
+
+        if let Err(e) = interface.write_word_32(RTC_CNTL_OPTIONS0, RTC_CNTL_SW_SYS_RST) {
+            // The reset can interrupt the access before it is acknowledged.
+            log::debug!("Error while triggering the reset, ignoring: {}", e);
+        }
+
+        // The halt request might be lost during the reset, so it is repeated until the core
+        // is halted again.
+        let start = Instant::now();
+        loop {
+            // The debug module might not respond while the system is reset.
+            let _ = interface.write_dm_register(dmcontrol);
+
+            match interface.read_dm_register::<Dmstatus>() {
+                Ok(status) if status.allhavereset() && status.allhalted() => break,
+                Ok(_) => (),
+                Err(e) => log::debug!("Error while waiting for the reset, ignoring: {}", e),
+            }
+
+            if start.elapsed() > timeout {
+                return Err(RiscvError::Timeout.into());
+            }
+        }
+
+        // acknowledge the reset, clear the halt request
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_dmactive(true);
+        dmcontrol.set_ackhavereset(true);
+
+        interface.write_dm_register(dmcontrol)?;
+
+        // The watchdogs are enabled again by the reset.
+        self.disable_watchdogs(interface, &DelayOrPoll::new(1))
+    }
+}
+
+/// Poll `dmstatus` until `condition` is true.
+fn wait_for_status(
+    interface: &mut RiscvCommunicationInterface,
+    timeout: Duration,
+    condition: fn(&Dmstatus) -> bool,
+) -> Result<(), crate::Error> {
+    let start = Instant::now();
+
+    while start.elapsed() < timeout {
+        let status: Dmstatus = interface.read_dm_register()?;
+
+        if condition(&status) {
+            return Ok(());
+        }
+    }
+
+    Err(RiscvError::Timeout.into())
+}
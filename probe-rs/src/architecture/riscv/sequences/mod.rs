@@ -1,7 +1,10 @@
 //! Debug sequences to operate special requirements RISC-V targets.
 
-use super::communication_interface::RiscvCommunicationInterface;
+use super::communication_interface::{RiscvCommunicationInterface, RiscvError};
+use super::{Dmcontrol, Dmstatus};
+use crate::architecture::settle::DelayOrPoll;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub mod esp32c3;
 
@@ -10,7 +13,81 @@ pub mod esp32c3;
 /// Should be implemented on a custom handle for chips that require special sequence code.
 pub trait RiscvDebugSequence: Send + Sync {
     /// Executed when the probe establishes a connection to the target.
-    fn on_connect(&self, _interface: &mut RiscvCommunicationInterface) -> Result<(), crate::Error> {
+    ///
+    /// The `delay` handle should be used to wait for the target after power state changes.
+    fn on_connect(
+        &self,
+        _interface: &mut RiscvCommunicationInterface,
+        _delay: &DelayOrPoll,
+    ) -> Result<(), crate::Error> {
+        Ok(())
+    }
+
+    /// Reset the core, and halt it before it executes the first instruction.
+    ///
+    /// The default implementation resets the hart with `hartreset`, or the whole system with
+    /// `ndmreset` if `hartreset` is not supported, while a halt request is pending.
+    fn reset_and_halt(
+        &self,
+        interface: &mut RiscvCommunicationInterface,
+        _timeout: Duration,
+    ) -> Result<(), crate::Error> {
+        log::debug!("Resetting core, setting hartreset bit");
+
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_dmactive(true);
+        dmcontrol.set_hartreset(true);
+        dmcontrol.set_haltreq(true);
+
+        interface.write_dm_register(dmcontrol)?;
+
+        // Read back register to verify reset is supported
+        let readback: Dmcontrol = interface.read_dm_register()?;
+
+        if readback.hartreset() {
+            log::debug!("Clearing hartreset bit");
+            // Reset is performed by setting the bit high, and then low again
+            let mut dmcontrol = Dmcontrol(0);
+            dmcontrol.set_dmactive(true);
+            dmcontrol.set_haltreq(true);
+            dmcontrol.set_hartreset(false);
+
+            interface.write_dm_register(dmcontrol)?;
+        } else {
+            // Hartreset is not supported, whole core needs to be reset
+            //
+            // TODO: Cache this
+            log::debug!("Hartreset bit not supported, using ndmreset");
+            let mut dmcontrol = Dmcontrol(0);
+            dmcontrol.set_dmactive(true);
+            dmcontrol.set_ndmreset(true);
+            dmcontrol.set_haltreq(true);
+
+            interface.write_dm_register(dmcontrol)?;
+
+            log::debug!("Clearing ndmreset bit");
+            let mut dmcontrol = Dmcontrol(0);
+            dmcontrol.set_dmactive(true);
+            dmcontrol.set_ndmreset(false);
+            dmcontrol.set_haltreq(true);
+
+            interface.write_dm_register(dmcontrol)?;
+        }
+
+        // check that cores have reset
+        let readback: Dmstatus = interface.read_dm_register()?;
+
+        if !(readback.allhavereset() && readback.allhalted()) {
+            return Err(RiscvError::RequestNotAcknowledged.into());
+        }
+
+        // acknowledge the reset, clear the halt request
+        let mut dmcontrol = Dmcontrol(0);
+        dmcontrol.set_dmactive(true);
+        dmcontrol.set_ackhavereset(true);
+
+        interface.write_dm_register(dmcontrol)?;
+
         Ok(())
     }
 }
@@ -0,0 +1,94 @@
+//! Routes from the probe to the debug interfaces of targets which mix ARM and RISC-V cores.
+//!
+//! On such a target, e.g. an ARM application core with a RISC-V coprocessor, the cores share
+//! one debug port, and a vendor specific mux connects it either to the ARM debug port or to
+//! the TAP of the RISC-V Debug Module. The [`Session`](crate::Session) switches the probe
+//! between the two debug interfaces whenever a core behind the other one is accessed, and
+//! the [`RouteSequence`] of the target selects the route in the mux.
+
+use std::fmt;
+use std::sync::Arc;
+
+use probe_rs_target::{Architecture, CoreAccessOptions};
+
+use crate::config::Core;
+use crate::Probe;
+
+/// The debug interface through which a core is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceRoute {
+    /// The ARM debug port. All ARM cores share it, they are told apart by their access port.
+    Arm,
+    /// The RISC-V Debug Module, at the TAP `jtag_tap` of the JTAG scan chain if the target has
+    /// several of them.
+    Riscv {
+        /// The TAP of the Debug Module.
+        jtag_tap: Option<usize>,
+    },
+}
+
+impl InterfaceRoute {
+    /// Returns the route to the core `core`.
+    pub fn of(core: &Core) -> Self {
+        match &core.core_access_options {
+            CoreAccessOptions::Arm(_) => InterfaceRoute::Arm,
+            CoreAccessOptions::Riscv(options) => InterfaceRoute::Riscv {
+                jtag_tap: options.jtag_tap,
+            },
+        }
+    }
+
+    /// Returns the architecture of the cores behind the route.
+    pub fn architecture(&self) -> Architecture {
+        match self {
+            InterfaceRoute::Arm => Architecture::Arm,
+            InterfaceRoute::Riscv { .. } => Architecture::Riscv,
+        }
+    }
+}
+
+impl fmt::Display for InterfaceRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterfaceRoute::Arm => f.write_str("ARM debug port"),
+            InterfaceRoute::Riscv { jtag_tap: None } => f.write_str("RISC-V Debug Module"),
+            InterfaceRoute::Riscv {
+                jtag_tap: Some(tap),
+            } => write!(f, "RISC-V Debug Module at TAP {}", tap),
+        }
+    }
+}
+
+/// Switches the shared debug port of a target between the routes to its cores.
+///
+/// Should be implemented on a custom handle for chips which mix ARM and RISC-V cores behind a
+/// vendor specific mux.
+pub trait RouteSequence: Send + Sync {
+    /// Connect the debug port to `to`, after the debug interface of `from` was closed.
+    ///
+    /// The probe is turned into the debug interface of `to` afterwards, so the mux can be
+    /// written with the raw access of the probe, e.g. with
+    /// [`Probe::try_as_dap_probe`](crate::Probe::try_as_dap_probe). The default
+    /// implementation does nothing, which fits targets whose debug port reaches all cores
+    /// without a mux.
+    fn select_route(
+        &self,
+        _probe: &mut Probe,
+        _from: InterfaceRoute,
+        _to: InterfaceRoute,
+    ) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// The route sequence of targets without a mux.
+pub struct DefaultRouteSequence(pub(crate) ());
+
+impl DefaultRouteSequence {
+    /// Creates a new default route sequence.
+    pub fn create() -> Arc<dyn RouteSequence> {
+        Arc::new(Self(()))
+    }
+}
+
+impl RouteSequence for DefaultRouteSequence {}
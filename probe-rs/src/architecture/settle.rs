@@ -0,0 +1,175 @@
+//! Timing helpers for debug sequences.
+//!
+//! After changing the power state of a target, e.g. by enabling a debug power domain,
+//! the target often needs some time until it is accessible again. The [`DelayOrPoll`]
+//! handle is passed to the debug sequences, so that they can wait for the target in a
+//! uniform way, which can be tuned by the user through the [`AttachOptions`].
+//!
+//! [`AttachOptions`]: crate::AttachOptions
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{DebugProbeError, MemoryInterface};
+
+/// The first interval between two polls. The interval is doubled after every poll,
+/// so that fast targets are not slowed down, and slow targets are not flooded with requests.
+const INITIAL_POLL_INTERVAL: Duration = Duration::from_micros(10);
+
+/// The longest interval between two polls.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Statistics about the time spent waiting for the target to settle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SettleStatistics {
+    /// The total time spent in [`DelayOrPoll::settle`] and the polling functions.
+    pub settle_time: Duration,
+    /// The number of polls which have been performed.
+    pub polls: usize,
+    /// The number of polls which timed out.
+    pub timeouts: usize,
+}
+
+/// A handle for debug sequences to wait for the target, either for a fixed time or by polling.
+///
+/// All clones of a handle share the same [`SettleStatistics`].
+#[derive(Debug, Clone)]
+pub struct DelayOrPoll {
+    /// All settle times and poll timeouts are multiplied by this factor.
+    settle_time_factor: u32,
+    statistics: Arc<Mutex<SettleStatistics>>,
+}
+
+impl DelayOrPoll {
+    /// Create a new handle. All settle times and poll timeouts are multiplied with `settle_time_factor`.
+    pub fn new(settle_time_factor: u32) -> Self {
+        Self {
+            settle_time_factor: settle_time_factor.max(1),
+            statistics: Arc::new(Mutex::new(SettleStatistics::default())),
+        }
+    }
+
+    /// Wait for the given duration, to give the target time to settle.
+    pub fn settle(&self, duration: Duration) {
+        let duration = duration * self.settle_time_factor;
+
+        thread::sleep(duration);
+
+        self.statistics.lock().unwrap().settle_time += duration;
+    }
+
+    /// Poll until `condition` returns true, or `max_wait` has elapsed.
+    ///
+    /// The condition is checked immediately, and afterwards with increasing intervals.
+    /// If the condition is not met in time, a [`DebugProbeError::Timeout`] is returned.
+    pub fn poll<F>(&self, max_wait: Duration, mut condition: F) -> Result<(), crate::Error>
+    where
+        F: FnMut() -> Result<bool, crate::Error>,
+    {
+        let max_wait = max_wait * self.settle_time_factor;
+        let start = Instant::now();
+        let mut interval = INITIAL_POLL_INTERVAL;
+        let mut polls = 0;
+
+        let result = loop {
+            polls += 1;
+
+            match condition() {
+                Ok(true) => break Ok(()),
+                Ok(false) => (),
+                Err(e) => break Err(e),
+            }
+
+            if start.elapsed() > max_wait {
+                break Err(DebugProbeError::Timeout.into());
+            }
+
+            thread::sleep(interval);
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        };
+
+        let mut statistics = self.statistics.lock().unwrap();
+        statistics.settle_time += start.elapsed();
+        statistics.polls += polls;
+
+        if matches!(result, Err(crate::Error::Probe(DebugProbeError::Timeout))) {
+            statistics.timeouts += 1;
+        }
+
+        result
+    }
+
+    /// Poll the 32-bit register at `address` until the bits selected by `mask`
+    /// are equal to `expected`, or `max_wait` has elapsed.
+    pub fn poll_register(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+        mask: u32,
+        expected: u32,
+        max_wait: Duration,
+    ) -> Result<(), crate::Error> {
+        self.poll(max_wait, || {
+            Ok(memory.read_word_32(address)? & mask == expected & mask)
+        })
+        .map_err(|e| {
+            log::warn!(
+                "Register {:#010x} did not reach the expected value {:#010x} (mask {:#010x}): {}",
+                address,
+                expected,
+                mask,
+                e
+            );
+            e
+        })
+    }
+
+    /// Returns the statistics about the time spent waiting for the target.
+    pub fn statistics(&self) -> SettleStatistics {
+        *self.statistics.lock().unwrap()
+    }
+}
+
+impl Default for DelayOrPoll {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn poll_until_condition_is_met() {
+        let delay = DelayOrPoll::new(1);
+        let mut remaining = 3;
+
+        delay
+            .poll(Duration::from_secs(1), || {
+                remaining -= 1;
+                Ok(remaining == 0)
+            })
+            .unwrap();
+
+        let statistics = delay.statistics();
+        assert_eq!(statistics.polls, 3);
+        assert_eq!(statistics.timeouts, 0);
+    }
+
+    #[test]
+    fn poll_timeout_is_counted() {
+        let delay = DelayOrPoll::new(1);
+
+        let result = delay.poll(Duration::from_millis(1), || Ok(false));
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::Probe(DebugProbeError::Timeout))
+        ));
+        assert_eq!(delay.clone().statistics().timeouts, 1);
+    }
+}
@@ -0,0 +1,307 @@
+//! Planning what attaching to a target does to its cores, see
+//! [`Session::attach_with_plan`](crate::Session::attach_with_plan).
+//!
+//! By default, attaching enables debugging of all cores and removes the hardware breakpoints
+//! left over from an earlier session, and attaching under reset also resets and halts the
+//! first core. An [`AttachPlan`] chooses this per core instead, e.g. to halt one core while
+//! another one keeps running untouched. A plan which would reset a core it should leave
+//! untouched, because the core shares the reset of another core which is reset, is rejected
+//! before anything is sent to the target.
+//!
+//! What was done while attaching, and where the session had to deviate from the plan, is
+//! reported by [`Session::attach_report`](crate::Session::attach_report).
+
+use std::collections::BTreeMap;
+
+use probe_rs_target::ResetScope;
+
+use crate::{Architecture, AttachMethod, Error, Target};
+
+/// What attaching does to a core, see [`AttachPlan::core`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreDirective {
+    /// Enable debugging of the core, and remove the hardware breakpoints left over from an
+    /// earlier session.
+    Initialize {
+        /// Halt the core after it was initialized, instead of leaving it running.
+        halt: bool,
+    },
+    /// Don't access the core while attaching. The core can be accessed later on, but it
+    /// isn't set up by the session.
+    LeaveUntouched,
+    /// Initialize the core, reset it and halt it at its reset vector.
+    ResetAndHalt,
+    /// Initialize the core like [`CoreDirective::Initialize`] without halting it, when it is
+    /// accessed for the first time.
+    DeferUntilFirstUse,
+}
+
+impl CoreDirective {
+    /// Returns true if the core is set up while attaching.
+    pub(crate) fn initializes(&self) -> bool {
+        matches!(
+            self,
+            CoreDirective::Initialize { .. } | CoreDirective::ResetAndHalt
+        )
+    }
+}
+
+/// What attaching does to the cores of a target, see
+/// [`Session::attach_with_plan`](crate::Session::attach_with_plan).
+///
+/// The cores without a directive are attached like with [`Probe::attach`] or
+/// [`Probe::attach_under_reset`], so the plans returned by [`AttachPlan::new`] and
+/// [`AttachPlan::under_reset`] attach exactly like these.
+///
+/// [`Probe::attach`]: crate::Probe::attach
+/// [`Probe::attach_under_reset`]: crate::Probe::attach_under_reset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachPlan {
+    directives: BTreeMap<usize, CoreDirective>,
+    under_reset: bool,
+    power_up_debug_domains: bool,
+    vector_catch: bool,
+    freeze_on_halt: bool,
+}
+
+impl AttachPlan {
+    /// The plan of a normal attach: all cores are initialized, and the first core of a RISC-V
+    /// target is halted.
+    pub fn new() -> Self {
+        Self {
+            directives: BTreeMap::new(),
+            under_reset: false,
+            power_up_debug_domains: true,
+            vector_catch: true,
+            freeze_on_halt: true,
+        }
+    }
+
+    /// The plan of an attach under reset: like [`AttachPlan::new`], but the first core of an
+    /// ARM target is reset through the reset line, and halted at its reset vector.
+    pub fn under_reset() -> Self {
+        Self {
+            under_reset: true,
+            ..Self::new()
+        }
+    }
+
+    /// Attach to the core `core` as `directive` says.
+    pub fn core(mut self, core: usize, directive: CoreDirective) -> Self {
+        self.directives.insert(core, directive);
+        self
+    }
+
+    /// Run the setup of the debug sequence of the target which powers up its debug domains
+    /// and unlocks the debug access, e.g. the debug components of an STM32H7. Enabled by
+    /// default.
+    pub fn power_up_debug_domains(mut self, enabled: bool) -> Self {
+        self.power_up_debug_domains = enabled;
+        self
+    }
+
+    /// Catch the reset vector when a core is reset through the reset line, so that it halts
+    /// before it executes any code. Without it, the core is halted after the reset is
+    /// released. Enabled by default.
+    pub fn vector_catch(mut self, enabled: bool) -> Self {
+        self.vector_catch = enabled;
+        self
+    }
+
+    /// Stop the peripherals selected with
+    /// [`AttachOptions::freeze_peripherals_on_halt`](crate::AttachOptions::freeze_peripherals_on_halt)
+    /// while the cores are halted. Enabled by default.
+    pub fn freeze_on_halt(mut self, enabled: bool) -> Self {
+        self.freeze_on_halt = enabled;
+        self
+    }
+
+    /// Returns the directive for the core `core` of `target`.
+    pub fn directive(&self, target: &Target, core: usize) -> CoreDirective {
+        if let Some(directive) = self.directives.get(&core) {
+            return *directive;
+        }
+
+        match (core, target.architecture()) {
+            (0, Architecture::Arm) if self.under_reset => CoreDirective::ResetAndHalt,
+            (0, Architecture::Riscv) => CoreDirective::Initialize { halt: true },
+            _ => CoreDirective::Initialize { halt: false },
+        }
+    }
+
+    /// Check that the plan can be executed on `target` without a reset of a core which
+    /// should be left untouched.
+    ///
+    /// The first core of an ARM target is reset through the reset line, which resets the
+    /// whole system. Other cores are reset through their debug registers, which resets the
+    /// cores in their [`ResetScope`].
+    pub fn validate(&self, target: &Target) -> Result<(), Error> {
+        if let Some(&core) = self
+            .directives
+            .keys()
+            .find(|&&core| core >= target.cores.len())
+        {
+            return Err(Error::InvalidAttachPlan {
+                core,
+                reason: "the target has no such core".to_string(),
+            });
+        }
+
+        for (reset, config) in target.cores.iter().enumerate() {
+            if self.directive(target, reset) != CoreDirective::ResetAndHalt {
+                continue;
+            }
+
+            let scope = self.reset_scope(target, reset);
+
+            for (other, other_config) in target.cores.iter().enumerate() {
+                if other == reset || !scope.affects(&other_config.reset_scope) {
+                    continue;
+                }
+
+                if !self.directive(target, other).initializes() {
+                    return Err(Error::InvalidAttachPlan {
+                        core: other,
+                        reason: format!(
+                            "the reset of core {} ({}) also resets it, but it must not be touched while attaching",
+                            reset, config.name
+                        ),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The cores which are reset with the core `core`.
+    pub(crate) fn reset_with(&self, target: &Target, core: usize) -> Vec<usize> {
+        let scope = self.reset_scope(target, core);
+
+        target
+            .cores
+            .iter()
+            .enumerate()
+            .filter(|(other, config)| *other != core && scope.affects(&config.reset_scope))
+            .map(|(other, _)| other)
+            .collect()
+    }
+
+    /// The scope of the reset which resets and halts the core `core`.
+    fn reset_scope(&self, target: &Target, core: usize) -> ResetScope {
+        if self.resets_through_reset_line(target, core) {
+            ResetScope::System
+        } else {
+            target.cores[core].reset_scope
+        }
+    }
+
+    /// Returns true if the core `core` is reset through the reset line while attaching.
+    pub(crate) fn resets_through_reset_line(&self, target: &Target, core: usize) -> bool {
+        core == 0
+            && target.architecture() == Architecture::Arm
+            && self.directive(target, core) == CoreDirective::ResetAndHalt
+    }
+
+    /// The attach method used to detect the target, if it isn't specified.
+    pub(crate) fn attach_method(&self) -> AttachMethod {
+        if self.under_reset {
+            AttachMethod::UnderReset
+        } else {
+            AttachMethod::Normal
+        }
+    }
+
+    pub(crate) fn powers_up_debug_domains(&self) -> bool {
+        self.power_up_debug_domains
+    }
+
+    pub(crate) fn catches_vector(&self) -> bool {
+        self.vector_catch
+    }
+
+    pub(crate) fn freezes_on_halt(&self) -> bool {
+        self.freeze_on_halt
+    }
+}
+
+impl Default for AttachPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where attaching had to deviate from the [`AttachPlan`], see [`AttachReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttachDeviation {
+    /// The probe can't control the reset line, so the core was reset through its debug
+    /// registers after attaching.
+    ResetLineUnavailable {
+        /// The index of the core.
+        core: usize,
+    },
+    /// The debug sequence can't drive the reset line through the probe, so the standard
+    /// reset of the probe was used.
+    StandardProbeReset,
+    /// The core was reset by the reset of another core, although it was only to be
+    /// initialized.
+    ResetByOtherCore {
+        /// The index of the core.
+        core: usize,
+        /// The index of the core whose reset also reset this core.
+        reset_by: usize,
+    },
+    /// The core was reset through its debug registers, which always catches the reset
+    /// vector, although the vector catch was disabled.
+    VectorCaught {
+        /// The index of the core.
+        core: usize,
+    },
+    /// The peripherals can't be frozen while the cores are halted, because no core was
+    /// initialized to freeze them through.
+    FreezeNotApplied,
+    /// The setup of the cores which writes to the target is deferred until the firmware was
+    /// verified, see [`GuardPolicy::NonIntrusiveReads`](crate::GuardPolicy::NonIntrusiveReads).
+    DeferredByGuard,
+}
+
+/// What was done while attaching, returned by
+/// [`Session::attach_report`](crate::Session::attach_report).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachReport {
+    /// The directives which were executed, by core index.
+    pub directives: Vec<CoreDirective>,
+    /// True if the debug domains were powered up by the debug sequence of the target.
+    pub debug_domains_powered_up: bool,
+    /// True if the reset vector was caught while attaching under reset.
+    pub vector_caught: bool,
+    /// True if the selected peripherals are frozen while the cores are halted.
+    pub freeze_applied: bool,
+    /// Where attaching deviated from the plan, in the order the deviations happened.
+    pub deviations: Vec<AttachDeviation>,
+}
+
+impl AttachReport {
+    pub(crate) fn new(plan: &AttachPlan, target: &Target) -> Self {
+        Self {
+            directives: (0..target.cores.len())
+                .map(|core| plan.directive(target, core))
+                .collect(),
+            debug_domains_powered_up: false,
+            vector_caught: false,
+            freeze_applied: false,
+            deviations: Vec::new(),
+        }
+    }
+
+    /// Record that the core `core` was reset by the reset of the core `reset_by`.
+    pub(crate) fn record_reset(&mut self, core: usize, reset_by: usize) {
+        let deviation = AttachDeviation::ResetByOtherCore { core, reset_by };
+
+        if matches!(self.directives[core], CoreDirective::Initialize { .. })
+            && !self.deviations.contains(&deviation)
+        {
+            self.deviations.push(deviation);
+        }
+    }
+}
@@ -21,19 +21,29 @@
 //! To add a target at runtime, the [add_target_from_yaml] file can
 //! be used to read targets from a YAML file.
 //!
+//! ## Target packs
+//!
+//! Several target descriptions, including their flash algorithms, can be distributed as
+//! a single [target pack](pack), which is loaded with [load_pack]. The targets of a pack
+//! take precedence over the built-in ones.
+//!
 
 mod chip_info;
+pub mod pack;
 mod registry;
 mod target;
 
 pub use probe_rs_target::{
-    Chip, ChipFamily, Core, CoreType, FlashProperties, InstructionSet, MemoryRange, MemoryRegion,
-    NvmRegion, PageInfo, RamRegion, RawFlashAlgorithm, SectorDescription, SectorInfo,
-    TargetDescriptionSource,
+    ArmCoreAccessOptions, Chip, ChipFamily, Core, CoreAccessOptions, CoreCapabilities, CoreType,
+    FlashProperties, FpuSupport, GenericRegion, InstructionSet, Keepalive, KeepaliveAction,
+    MediatedRegion, MediatorKind, MemoryRange, MemoryRegion, NvmRegion, PageInfo, PeripheralFreeze,
+    RamRegion, RawFlashAlgorithm, ResetScope, RiscvCoreAccessOptions, RiscvQuirks,
+    SectorDescription, SectorInfo, TargetDescriptionSource,
 };
 
 pub use registry::{
-    add_target_from_yaml, families, get_target_by_name, search_chips, RegistryError,
+    add_target_from_yaml, families, get_target_by_name, installed_packs, load_pack, search_chips,
+    InstalledPack, PackSource, RegistryError,
 };
 pub use target::{DebugSequence, Target, TargetParseError, TargetSelector};
 
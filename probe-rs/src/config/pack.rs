@@ -0,0 +1,386 @@
+//! Target packs
+//!
+//! A target pack bundles one or more [`ChipFamily`] descriptions, including their flash
+//! algorithms, errata, mediated regions, peripheral freeze maps and other quirks, into a
+//! single file which can be loaded at runtime with [`load_pack`](super::load_pack).
+//!
+//! ## Format
+//!
+//! All integers are little endian.
+//!
+//! | Offset | Size | Content                                         |
+//! |--------|------|-------------------------------------------------|
+//! | 0      | 8    | The magic bytes `PRSPACK\0`                     |
+//! | 8      | 4    | The format version, see [`PACK_FORMAT_VERSION`] |
+//! | 12     | 4    | The length `n` of the manifest                  |
+//! | 16     | `n`  | The [`PackManifest`], as JSON                   |
+//! | 16 + n | ...  | The payload of the entries                      |
+//!
+//! Each [`PackEntry`] of the manifest locates its content in the payload, and carries its
+//! SHA-256 hash. Families are stored as YAML target descriptions, but without the
+//! instructions of their flash algorithms. These are stored as separate entries, named
+//! `<family>/<algorithm>`.
+
+use super::{ChipFamily, RawFlashAlgorithm, Target, TargetDescriptionSource};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+
+/// The magic bytes at the start of every target pack.
+pub const PACK_MAGIC: &[u8; 8] = b"PRSPACK\0";
+
+/// The version of the target pack format written by [`create`].
+///
+/// Packs with a different version are rejected.
+pub const PACK_FORMAT_VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 16;
+
+/// An error which occurs when creating or reading a target pack.
+#[derive(Debug, thiserror::Error)]
+pub enum PackError {
+    /// The data does not start with the magic bytes of a target pack.
+    #[error("The data is not a target pack.")]
+    NotAPack,
+    /// The pack was written in a format version which is not supported.
+    #[error(
+        "The target pack has format version {version}, but only version {supported} is supported."
+    )]
+    UnsupportedVersion {
+        /// The format version of the pack.
+        version: u32,
+        /// The format version supported by this version of probe-rs.
+        supported: u32,
+    },
+    /// The pack ends before its manifest.
+    #[error("The target pack is truncated.")]
+    Truncated,
+    /// The manifest of the pack could not be read.
+    #[error("The manifest of the target pack is invalid: {0}")]
+    InvalidManifest(String),
+    /// An entry of the pack lies outside of its payload.
+    #[error("The entry '{entry}' lies outside of the target pack.")]
+    EntryOutOfBounds {
+        /// The name of the entry.
+        entry: String,
+    },
+    /// The content of an entry does not match the hash in the manifest.
+    #[error("The content of the entry '{entry}' does not match its hash.")]
+    HashMismatch {
+        /// The name of the entry.
+        entry: String,
+    },
+    /// The content of an entry could not be read.
+    #[error("The entry '{entry}' is invalid: {reason}")]
+    InvalidEntry {
+        /// The name of the entry.
+        entry: String,
+        /// Why the entry is invalid.
+        reason: String,
+    },
+    /// A family of the pack is not a valid target description.
+    #[error("The family '{family}' is invalid: {reason}")]
+    InvalidFamily {
+        /// The name of the family.
+        family: String,
+        /// Why the family is invalid.
+        reason: String,
+    },
+    /// An IO error which occurred when reading a pack from a file.
+    #[error("An IO error was encountered")]
+    Io(#[from] std::io::Error),
+}
+
+/// The kind of content of a [`PackEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackEntryKind {
+    /// A [`ChipFamily`], as a YAML target description.
+    Family,
+    /// The instructions of a flash algorithm of a family.
+    FlashAlgorithm,
+}
+
+/// An entry in the [`PackManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackEntry {
+    /// The kind of content of the entry.
+    pub kind: PackEntryKind,
+    /// The name of the family, or `<family>/<algorithm>` for flash algorithms.
+    pub name: String,
+    /// The offset of the content, from the start of the payload.
+    pub offset: u64,
+    /// The length of the content in bytes.
+    pub len: u64,
+    /// The SHA-256 hash of the content, as lower case hex digits.
+    pub sha256: String,
+}
+
+/// The manifest of a target pack, which describes its content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackManifest {
+    /// The format version of the pack.
+    pub format_version: u32,
+    /// The name of the pack.
+    pub name: String,
+    /// The version of the pack, chosen by its author.
+    pub version: String,
+    /// The entries of the pack, in the order of their content.
+    pub entries: Vec<PackEntry>,
+}
+
+/// A target pack which was read and validated by [`read`].
+#[derive(Debug, Clone)]
+pub struct TargetPack {
+    /// The manifest of the pack.
+    pub manifest: PackManifest,
+    /// The families of the pack, including the instructions of their flash algorithms.
+    pub families: Vec<ChipFamily>,
+}
+
+/// Create a target pack named `name` in `version`, which contains `families`.
+///
+/// The families are validated like the ones read by [`read`], so that only packs which can
+/// be loaded are created.
+pub fn create(
+    name: impl Into<String>,
+    version: impl Into<String>,
+    families: &[ChipFamily],
+) -> Result<Vec<u8>, PackError> {
+    let mut entries = Vec::new();
+    let mut payload = Vec::new();
+
+    let mut add_entry = |kind, name: String, content: &[u8]| {
+        entries.push(PackEntry {
+            kind,
+            name,
+            offset: payload.len() as u64,
+            len: content.len() as u64,
+            sha256: sha256(content),
+        });
+        payload.extend_from_slice(content);
+    };
+
+    for family in families {
+        validate_family(family)?;
+
+        let mut stripped = family.clone();
+        for algorithm in &mut stripped.flash_algorithms {
+            algorithm.instructions = vec![];
+        }
+
+        let yaml = serde_yaml::to_string(&stripped).map_err(|e| PackError::InvalidFamily {
+            family: family.name.clone(),
+            reason: e.to_string(),
+        })?;
+        add_entry(PackEntryKind::Family, family.name.clone(), yaml.as_bytes());
+
+        for algorithm in &family.flash_algorithms {
+            add_entry(
+                PackEntryKind::FlashAlgorithm,
+                algorithm_entry_name(family, algorithm),
+                &algorithm.instructions,
+            );
+        }
+    }
+
+    let manifest = PackManifest {
+        format_version: PACK_FORMAT_VERSION,
+        name: name.into(),
+        version: version.into(),
+        entries,
+    };
+    let manifest =
+        serde_json::to_vec(&manifest).map_err(|e| PackError::InvalidManifest(e.to_string()))?;
+
+    let mut pack = Vec::with_capacity(HEADER_LEN + manifest.len() + payload.len());
+    pack.extend_from_slice(PACK_MAGIC);
+    pack.extend_from_slice(&PACK_FORMAT_VERSION.to_le_bytes());
+    pack.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+    pack.extend_from_slice(&manifest);
+    pack.extend_from_slice(&payload);
+
+    Ok(pack)
+}
+
+/// Read the target pack in `bytes`.
+///
+/// The hashes of all entries are checked, and every family is validated the same way as
+/// the families of the built-in targets, before the pack is returned.
+pub fn read(bytes: &[u8]) -> Result<TargetPack, PackError> {
+    if bytes.len() < PACK_MAGIC.len() || &bytes[..PACK_MAGIC.len()] != PACK_MAGIC {
+        return Err(PackError::NotAPack);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(PackError::Truncated);
+    }
+
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    if version != PACK_FORMAT_VERSION {
+        return Err(PackError::UnsupportedVersion {
+            version,
+            supported: PACK_FORMAT_VERSION,
+        });
+    }
+
+    let manifest_len = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+    let manifest = bytes
+        .get(HEADER_LEN..HEADER_LEN + manifest_len)
+        .ok_or(PackError::Truncated)?;
+    let manifest: PackManifest =
+        serde_json::from_slice(manifest).map_err(|e| PackError::InvalidManifest(e.to_string()))?;
+    if manifest.format_version != version {
+        return Err(PackError::InvalidManifest(format!(
+            "the manifest has format version {}, but the header has {}",
+            manifest.format_version, version
+        )));
+    }
+
+    let payload = &bytes[HEADER_LEN + manifest_len..];
+    let content = |entry: &PackEntry| -> Result<&[u8], PackError> {
+        let content = usize::try_from(entry.offset)
+            .ok()
+            .zip(usize::try_from(entry.len).ok())
+            .and_then(|(offset, len)| payload.get(offset..offset.checked_add(len)?))
+            .ok_or_else(|| PackError::EntryOutOfBounds {
+                entry: entry.name.clone(),
+            })?;
+
+        if sha256(content) != entry.sha256 {
+            return Err(PackError::HashMismatch {
+                entry: entry.name.clone(),
+            });
+        }
+
+        Ok(content)
+    };
+
+    let mut families = Vec::new();
+    for entry in &manifest.entries {
+        let content = content(entry)?;
+
+        if entry.kind != PackEntryKind::Family {
+            continue;
+        }
+
+        let mut family: ChipFamily =
+            serde_yaml::from_slice(content).map_err(|e| PackError::InvalidEntry {
+                entry: entry.name.clone(),
+                reason: e.to_string(),
+            })?;
+        if family.name != entry.name {
+            return Err(PackError::InvalidEntry {
+                entry: entry.name.clone(),
+                reason: format!("it contains the family '{}'", family.name),
+            });
+        }
+        family.source = TargetDescriptionSource::External;
+
+        for index in 0..family.flash_algorithms.len() {
+            let name = algorithm_entry_name(&family, &family.flash_algorithms[index]);
+            let instructions = manifest
+                .entries
+                .iter()
+                .find(|entry| entry.kind == PackEntryKind::FlashAlgorithm && entry.name == name)
+                .ok_or_else(|| PackError::InvalidEntry {
+                    entry: entry.name.clone(),
+                    reason: format!(
+                        "the instructions of the flash algorithm '{}' are missing",
+                        name
+                    ),
+                })?;
+
+            family.flash_algorithms[index].instructions = content(instructions)?.to_vec();
+        }
+
+        validate_family(&family)?;
+        families.push(family);
+    }
+
+    Ok(TargetPack { manifest, families })
+}
+
+/// Validate `family` with all checks which are applied when a target is created from it.
+pub(crate) fn validate_family(family: &ChipFamily) -> Result<(), PackError> {
+    let invalid = |reason: String| PackError::InvalidFamily {
+        family: family.name.clone(),
+        reason,
+    };
+
+    family.validate().map_err(invalid)?;
+
+    for variant in &family.variants {
+        Target::new(family, &variant.name)
+            .map_err(|e| invalid(format!("variant '{}': {}", variant.name, e)))?;
+    }
+
+    Ok(())
+}
+
+fn algorithm_entry_name(family: &ChipFamily, algorithm: &RawFlashAlgorithm) -> String {
+    format!("{}/{}", family.name, algorithm.name)
+}
+
+fn sha256(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn family() -> ChipFamily {
+        serde_yaml::from_str(
+            r#"
+name: test
+variants:
+  - name: test_chip
+    cores:
+      - name: main
+        type: armv6m
+        core_access_options:
+          Arm:
+            ap: 0x0
+            psel: 0x0
+    memory_map: []
+    flash_algorithms: []
+flash_algorithms: []
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn the_header_describes_the_manifest() {
+        let pack = create("test", "1.0.0", &[family()]).unwrap();
+
+        assert_eq!(&pack[..8], PACK_MAGIC);
+        assert_eq!(pack[8..12], PACK_FORMAT_VERSION.to_le_bytes());
+
+        let manifest_len = u32::from_le_bytes(pack[12..16].try_into().unwrap()) as usize;
+        let manifest: PackManifest = serde_json::from_slice(&pack[16..16 + manifest_len]).unwrap();
+        assert_eq!(manifest.name, "test");
+        assert_eq!(manifest.entries.len(), 1);
+        assert_eq!(manifest.entries[0].kind, PackEntryKind::Family);
+        assert_eq!(manifest.entries[0].sha256.len(), 64);
+    }
+
+    #[test]
+    fn newer_formats_are_rejected() {
+        let mut pack = create("test", "1.0.0", &[family()]).unwrap();
+        pack[8..12].copy_from_slice(&2u32.to_le_bytes());
+
+        assert!(matches!(
+            read(&pack),
+            Err(PackError::UnsupportedVersion {
+                version: 2,
+                supported: 1
+            })
+        ));
+    }
+}
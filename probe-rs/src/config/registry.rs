@@ -1,11 +1,12 @@
 //! Internal target registry
 
+use super::pack::{self, PackError, TargetPack};
 use super::{Chip, ChipFamily, ChipInfo, Core, Target, TargetDescriptionSource};
 use crate::config::CoreType;
 use once_cell::sync::Lazy;
-use probe_rs_target::{CoreAccessOptions, RiscvCoreAccessOptions};
+use probe_rs_target::{CoreAccessOptions, ResetScope, RiscvCoreAccessOptions};
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 static REGISTRY: Lazy<Arc<Mutex<Registry>>> =
@@ -38,6 +39,57 @@ pub enum RegistryError {
     /// An invalid [`ChipFamily`] was encountered.
     #[error("Invalid chip family definition ({})", .0.name)]
     InvalidChipFamilyDefinition(ChipFamily, String),
+    /// A target pack could not be loaded.
+    #[error("The target pack could not be loaded")]
+    Pack(#[from] PackError),
+}
+
+/// Where a target pack is loaded from, see [`load_pack`].
+#[derive(Debug, Clone)]
+pub enum PackSource {
+    /// A pack file.
+    Path(PathBuf),
+    /// A pack which is already in memory.
+    Bytes(Vec<u8>),
+}
+
+impl From<&Path> for PackSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_owned())
+    }
+}
+
+impl From<PathBuf> for PackSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<Vec<u8>> for PackSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<&[u8]> for PackSource {
+    fn from(bytes: &[u8]) -> Self {
+        Self::Bytes(bytes.to_vec())
+    }
+}
+
+/// A target pack which was loaded into the registry, see [`installed_packs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledPack {
+    /// The name of the pack.
+    pub name: String,
+    /// The version of the pack.
+    pub version: String,
+    /// The format version of the pack.
+    pub format_version: u32,
+    /// The names of the families of the pack.
+    pub families: Vec<String>,
+    /// The names of the targets which were already known, and are now described by the pack.
+    pub overridden: Vec<String>,
 }
 
 fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
@@ -52,6 +104,8 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
             ],
 
             flash_algorithms: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: TargetDescriptionSource::Generic,
         },
         ChipFamily {
@@ -59,6 +113,8 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
             manufacturer: None,
             variants: vec![Chip::generic_arm("Cortex-M3", CoreType::Armv7m)],
             flash_algorithms: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: TargetDescriptionSource::Generic,
         },
         ChipFamily {
@@ -69,6 +125,8 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
                 Chip::generic_arm("Cortex-M7", CoreType::Armv7em),
             ],
             flash_algorithms: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: TargetDescriptionSource::Generic,
         },
         ChipFamily {
@@ -81,6 +139,8 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
                 Chip::generic_arm("Cortex-M55", CoreType::Armv8m),
             ],
             flash_algorithms: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: TargetDescriptionSource::Generic,
         },
         ChipFamily {
@@ -92,12 +152,20 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
                 cores: vec![Core {
                     name: "core".to_owned(),
                     core_type: CoreType::Riscv,
-                    core_access_options: CoreAccessOptions::Riscv(RiscvCoreAccessOptions {}),
+                    core_access_options: CoreAccessOptions::Riscv(RiscvCoreAccessOptions::default()),
+                    reset_scope: ResetScope::default(),
+                    hardware_breakpoints: None,
+                    watchpoints: None,
                 }],
                 memory_map: vec![],
                 flash_algorithms: vec![],
+                keepalive: None,
+                errata: vec![],
+                mediated_regions: vec![],
             }],
             flash_algorithms: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: TargetDescriptionSource::Generic,
         },
     ]);
@@ -107,6 +175,8 @@ fn add_generic_targets(vec: &mut Vec<ChipFamily>) {
 struct Registry {
     /// All the available chips.
     families: Vec<ChipFamily>,
+    /// The target packs which were loaded.
+    packs: Vec<InstalledPack>,
 }
 
 impl Registry {
@@ -123,7 +193,10 @@ impl Registry {
         // Additionally, validation for existing targets is done in the tests `validate_generic_targets` and
         // `validate_builtin` as well, to ensure we do not ship broken target definitions.
 
-        Self { families }
+        Self {
+            families,
+            packs: vec![],
+        }
     }
 
     #[cfg(not(feature = "builtin-targets"))]
@@ -135,7 +208,10 @@ impl Registry {
         // Additionally, validation for existing targets is done in the tests `validate_generic_targets` and
         // `validate_builtin` as well, to ensure we do not ship broken target definitions.
 
-        Self { families }
+        Self {
+            families,
+            packs: vec![],
+        }
     }
 
     fn families(&self) -> &Vec<ChipFamily> {
@@ -263,7 +339,15 @@ impl Registry {
 
     fn get_target(&self, family: &ChipFamily, chip: &Chip) -> Result<Target, RegistryError> {
         // The validity of the given `ChipFamily` is checked in the constructor.
-        Target::new(family, &chip.name)
+        let mut target = Target::new(family, &chip.name)?;
+
+        target.overridden_by = self
+            .packs
+            .iter()
+            .find(|pack| pack.overridden.contains(&chip.name))
+            .map(|pack| pack.name.clone());
+
+        Ok(target)
     }
 
     fn add_target_from_yaml(&mut self, path_to_yaml: &Path) -> Result<(), RegistryError> {
@@ -285,6 +369,75 @@ impl Registry {
 
         Ok(())
     }
+
+    /// Add the families of `pack`, which take precedence over any target of the same name.
+    fn load_pack(&mut self, pack: TargetPack) -> InstalledPack {
+        let previous = self
+            .packs
+            .iter()
+            .position(|installed| installed.name == pack.manifest.name)
+            .map(|index| self.packs.remove(index));
+
+        let mut overridden = Vec::new();
+        for variant in pack.families.iter().flat_map(|family| &family.variants) {
+            let mut replaced = false;
+            for family in &mut self.families {
+                let count = family.variants.len();
+                family
+                    .variants
+                    .retain(|old| !old.name.eq_ignore_ascii_case(&variant.name));
+
+                // Replacing a target of the previous version of the pack is no override.
+                let from_previous = matches!(
+                    &previous,
+                    Some(previous) if previous.families.contains(&family.name)
+                );
+                replaced |= family.variants.len() != count && !from_previous;
+            }
+
+            // Targets which the previous version of the pack overrode are still overridden.
+            let overrode = matches!(
+                &previous,
+                Some(previous) if previous.overridden.contains(&variant.name)
+            );
+
+            if overrode {
+                overridden.push(variant.name.clone());
+            } else if replaced {
+                log::warn!(
+                    "The target {} is overridden by the target pack {}",
+                    variant.name,
+                    pack.manifest.name
+                );
+                overridden.push(variant.name.clone());
+            }
+        }
+        self.families.retain(|family| !family.variants.is_empty());
+
+        // A target which was overridden by another pack, is now overridden by this one.
+        for installed in &mut self.packs {
+            installed
+                .overridden
+                .retain(|name| !overridden.contains(name));
+        }
+
+        let installed = InstalledPack {
+            name: pack.manifest.name,
+            version: pack.manifest.version,
+            format_version: pack.manifest.format_version,
+            families: pack
+                .families
+                .iter()
+                .map(|family| family.name.clone())
+                .collect(),
+            overridden,
+        };
+
+        self.families.extend(pack.families);
+        self.packs.push(installed.clone());
+
+        installed
+    }
 }
 
 /// Get a target from the internal registry based on its name.
@@ -308,6 +461,26 @@ pub fn add_target_from_yaml(path_to_yaml: &Path) -> Result<(), RegistryError> {
     REGISTRY.lock().unwrap().add_target_from_yaml(path_to_yaml)
 }
 
+/// Load a target pack, created by [`pack::create`], into the internal target registry.
+///
+/// The pack is fully validated before any of its targets is added. Targets of the pack take
+/// precedence over any known target of the same name, e.g. a built-in one, which is logged
+/// as a warning and reported in the warnings of every [`Session`](crate::Session) with
+/// such a target.
+pub fn load_pack(source: impl Into<PackSource>) -> Result<InstalledPack, RegistryError> {
+    let pack = match source.into() {
+        PackSource::Path(path) => pack::read(&std::fs::read(path).map_err(PackError::Io)?)?,
+        PackSource::Bytes(bytes) => pack::read(&bytes)?,
+    };
+
+    Ok(REGISTRY.lock().unwrap().load_pack(pack))
+}
+
+/// Get a list of all target packs which were loaded into the internal registry.
+pub fn installed_packs() -> Vec<InstalledPack> {
+    REGISTRY.lock().unwrap().packs.clone()
+}
+
 /// Get a list of all families which are contained in the internal
 /// registry.
 pub fn families() -> Result<Vec<ChipFamily>, RegistryError> {
@@ -395,4 +568,37 @@ mod tests {
             .collect::<Result<Vec<_>, _>>()
             .unwrap();
     }
+
+    #[test]
+    fn debug_resources_are_validated_against_the_architecture() {
+        let family = |watchpoints: u32| -> ChipFamily {
+            serde_yaml::from_str(&format!(
+                r#"
+name: test
+variants:
+  - name: test_chip
+    cores:
+      - name: main
+        type: armv6m
+        core_access_options:
+          Arm:
+            ap: 0x0
+            psel: 0x0
+        hardware_breakpoints: 4
+        watchpoints: {}
+    memory_map: []
+    flash_algorithms: []
+flash_algorithms: []
+"#,
+                watchpoints
+            ))
+            .unwrap()
+        };
+
+        assert!(family(2).validate().is_ok());
+
+        let error = family(16).validate().unwrap_err();
+        assert!(error.contains("claims 16 watchpoints"), "{}", error);
+        assert!(error.contains("at most 4"), "{}", error);
+    }
 }
@@ -1,12 +1,16 @@
 use probe_rs_target::{Architecture, ChipFamily};
 
-use super::{Core, MemoryRegion, RawFlashAlgorithm, RegistryError, TargetDescriptionSource};
+use super::{
+    Core, Keepalive, MediatedRegion, MemoryRegion, PeripheralFreeze, RawFlashAlgorithm,
+    RegistryError, TargetDescriptionSource,
+};
 use crate::architecture::arm::sequences::{
     nrf53::Nrf5340, nxp::LPC55S69, stm32::Stm32h7, ArmDebugSequence,
 };
 use crate::architecture::riscv::sequences::esp32c3::ESP32C3;
 use crate::architecture::riscv::sequences::{DefaultRiscvSequence, RiscvDebugSequence};
-use crate::flashing::FlashLoader;
+use crate::architecture::route::{DefaultRouteSequence, RouteSequence};
+use crate::flashing::{FlashDownloadSet, FlashLoader};
 use std::sync::Arc;
 
 use crate::architecture::arm::sequences::DefaultArmSequence;
@@ -26,8 +30,39 @@ pub struct Target {
     /// Source of the target description. Used for diagnostics.
     pub(crate) source: TargetDescriptionSource,
 
+    /// The name of the target pack which overrides the previously known description of
+    /// the target, see [`load_pack`](crate::config::load_pack).
+    pub(crate) overridden_by: Option<String>,
+
     /// Debug sequences for the given target.
+    ///
+    /// On targets which mix ARM and RISC-V cores, these are the sequences of the architecture
+    /// of the first core, see [`Target::debug_sequence_for`].
     pub debug_sequence: DebugSequence,
+
+    /// Debug sequences for the cores whose architecture differs from the one of the first
+    /// core, on targets which mix ARM and RISC-V cores.
+    pub secondary_debug_sequence: Option<DebugSequence>,
+
+    /// Switches the debug port between the routes to the cores of a target which mixes ARM
+    /// and RISC-V cores.
+    pub route_sequence: Arc<dyn RouteSequence>,
+
+    /// Accesses which keep the debug connection alive, if the target needs them.
+    pub keepalive: Option<Keepalive>,
+
+    /// Identifiers of the errata of the target which affect debugging.
+    pub errata: Vec<String>,
+
+    /// Memory regions whose accesses have to be prepared by a mediator.
+    pub mediated_regions: Vec<MediatedRegion>,
+
+    /// The peripherals which can be stopped while the cores are halted.
+    pub peripheral_freeze: Vec<PeripheralFreeze>,
+
+    /// The debug logic of the target runs on a slow clock after a reset, see
+    /// [`AttachOptions::slow_clock_attach`](crate::AttachOptions::slow_clock_attach).
+    pub slow_clock_attach: bool,
 }
 
 impl std::fmt::Debug for Target {
@@ -54,9 +89,9 @@ impl Target {
     /// Use (crate::registry::Registry::get_target)[`Registry::get_target`] instead.
     /// This will ensure that the used target is valid.
     ///
-    /// The user has to make sure that all the cores have the same [`Architecture`].
-    /// In any case, this function will always just use the architecture of the first core in any further functionality.
-    /// In practice we have never encountered a [`Chip`] with mixed architectures so this should not be of issue.
+    /// The cores can have different [`Architecture`]s, the target is attached through the
+    /// debug interface of the first core, and the others are reached by switching the route,
+    /// see [`InterfaceRoute`](crate::architecture::route::InterfaceRoute).
     ///
     /// Furthermore, the user has to ensure that any [`Core`] in `flash_algorithms[n].cores` is present in `cores` as well.
     pub(crate) fn new(
@@ -83,11 +118,9 @@ impl Target {
             flash_algorithms.push(algo.clone());
         }
 
-        // We always just take the architecture of the first core which is okay if there is no mixed architectures.
-        let mut debug_sequence = match chip.cores[0].core_type.architecture() {
-            Architecture::Arm => DebugSequence::Arm(DefaultArmSequence::create()),
-            Architecture::Riscv => DebugSequence::Riscv(DefaultRiscvSequence::create()),
-        };
+        // The target is attached through the architecture of the first core.
+        let architecture = chip.cores[0].core_type.architecture();
+        let mut debug_sequence = DebugSequence::default_for(architecture);
 
         if chip.name.starts_with("LPC55S16") || chip.name.starts_with("LPC55S69") {
             log::warn!("Using custom sequence for LPC55S16/LPC55S69");
@@ -103,30 +136,60 @@ impl Target {
             debug_sequence = DebugSequence::Arm(Stm32h7::create());
         }
 
+        let secondary_debug_sequence = chip
+            .cores
+            .iter()
+            .map(|core| core.core_type.architecture())
+            .find(|&other| other != architecture)
+            .map(DebugSequence::default_for);
+
         Ok(Target {
             name: chip.name.clone(),
             cores: chip.cores.clone(),
             flash_algorithms,
             source: family.source.clone(),
+            overridden_by: None,
             memory_map: chip.memory_map.clone(),
             debug_sequence,
+            secondary_debug_sequence,
+            route_sequence: DefaultRouteSequence::create(),
+            keepalive: chip.keepalive,
+            errata: chip.errata.clone(),
+            mediated_regions: chip.mediated_regions.clone(),
+            peripheral_freeze: family.peripheral_freeze.clone(),
+            slow_clock_attach: family.slow_clock_attach,
         })
     }
 
     /// Get the architecture of the target
+    ///
+    /// On targets which mix ARM and RISC-V cores, this is the architecture of the first core,
+    /// whose debug interface the target is attached through.
     pub fn architecture(&self) -> Architecture {
-        let target_arch = self.cores[0].core_type.architecture();
+        self.cores[0].core_type.architecture()
+    }
 
-        // This should be ensured when a `ChipFamily` is loaded.
-        assert!(
-            self.cores
-                .iter()
-                .map(|core| core.core_type.architecture())
-                .all(|core_arch| core_arch == target_arch),
-            "Not all cores of the target are of the same architecture. Probe-rs doesn't support this (yet). If you see this, it is a bug. Please file an issue."
-        );
+    /// Returns true if the target mixes cores of different architectures.
+    pub fn is_heterogeneous(&self) -> bool {
+        self.cores
+            .iter()
+            .any(|core| core.core_type.architecture() != self.architecture())
+    }
 
-        target_arch
+    /// Get the debug sequences of the cores with the architecture `architecture`.
+    ///
+    /// Falls back to the default sequences of the architecture, if the target has none for
+    /// it, e.g. because a core was added to the target after it was loaded.
+    pub fn debug_sequence_for(&self, architecture: Architecture) -> DebugSequence {
+        [
+            Some(&self.debug_sequence),
+            self.secondary_debug_sequence.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|sequence| sequence.architecture() == architecture)
+        .cloned()
+        .unwrap_or_else(|| DebugSequence::default_for(architecture))
     }
 
     /// Source description of this target.
@@ -140,6 +203,12 @@ impl Target {
         FlashLoader::new(self.memory_map.clone(), self.source.clone())
     }
 
+    /// Create a [FlashDownloadSet] for this target, which can be used to program several
+    /// images in one operation.
+    pub fn flash_download_set(&self) -> FlashDownloadSet {
+        FlashDownloadSet::new(self.memory_map.clone(), self.source.clone())
+    }
+
     /// Gets a [RawFlashAlgorithm] by name.
     pub(crate) fn flash_algorithm_by_name(&self, name: &str) -> Option<&RawFlashAlgorithm> {
         self.flash_algorithms.iter().find(|a| a.name == name)
@@ -217,3 +286,21 @@ pub enum DebugSequence {
     /// A RISC-V debug sequence.
     Riscv(Arc<dyn RiscvDebugSequence>),
 }
+
+impl DebugSequence {
+    /// The default debug sequences of `architecture`.
+    fn default_for(architecture: Architecture) -> Self {
+        match architecture {
+            Architecture::Arm => DebugSequence::Arm(DefaultArmSequence::create()),
+            Architecture::Riscv => DebugSequence::Riscv(DefaultRiscvSequence::create()),
+        }
+    }
+
+    /// The architecture of the targets the sequences are for.
+    pub fn architecture(&self) -> Architecture {
+        match self {
+            DebugSequence::Arm(_) => Architecture::Arm,
+            DebugSequence::Riscv(_) => Architecture::Riscv,
+        }
+    }
+}
@@ -0,0 +1,1462 @@
+//! A conformance suite, which qualifies a new target or probe on real hardware, see
+//! [`ConformanceSuite`].
+//!
+//! The suite exercises the operations the rest of probe-rs relies on: attaching, halting,
+//! resuming and stepping the core, the registers, memory accesses of every width and
+//! alignment, the ordering of batched writes, breakpoints, resets and flashing. Each
+//! [`Check`] is scored as passed, failed or skipped, with the time it took, and the
+//! [`ConformanceReport`] can be serialized to JSON, so that the results of different
+//! targets and probes can be compared.
+//!
+//! The suite only writes to the scratch RAM and the expendable flash it is given. Their
+//! contents, and the registers of the core, are restored after the checks. The core is left
+//! halted.
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::MemoryRegion;
+use crate::flashing::{DownloadOptions, FlashError};
+use crate::{
+    Core, CoreStatus, Error, HaltLocation, HaltReason, InstructionSet, MemoryInterface,
+    RegisterDescription, RegisterValue, Session, Target, WatchpointConfig, WatchpointKind,
+    WriteCoalescer,
+};
+
+/// The smallest scratch RAM the suite accepts, in bytes.
+const MIN_SCRATCH_SIZE: u64 = 256;
+
+/// The size of the blocks of the memory check, in bytes.
+const BLOCK_SIZE: u64 = 1024;
+
+/// The size of the window in which single values are written, in bytes.
+const WINDOW: usize = 32;
+
+/// The value of the bytes around single values, which must not change.
+const GUARD: u8 = 0xa5;
+
+/// How often the core is resumed and halted by [`Check::HaltResume`].
+const RESUME_CYCLES: usize = 10;
+
+/// The checks which access the core, and are run while its context is saved.
+const CORE_CHECKS: [Check; 7] = [
+    Check::HaltResume,
+    Check::Step,
+    Check::Registers,
+    Check::Memory,
+    Check::Batching,
+    Check::Breakpoints,
+    Check::Watchpoints,
+];
+
+/// A check of the [`ConformanceSuite`].
+///
+/// The checks are run in the order of [`Check::ALL`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// Attach to the target, and access the core.
+    Attach,
+    /// Resume and halt the core repeatedly, and measure how long both take.
+    HaltResume,
+    /// Step single instructions.
+    Step,
+    /// Write every register of the register file, and read it back.
+    Registers,
+    /// Access the scratch RAM with every width and alignment, including its last bytes and
+    /// a block which crosses a 1 KiB boundary.
+    Memory,
+    /// Check that queued and coalesced writes are executed in order, and that reads see all
+    /// writes which were issued before them.
+    Batching,
+    /// Halt at hardware and software breakpoints in the scratch RAM.
+    Breakpoints,
+    /// Set and clear a data watchpoint on the scratch RAM.
+    Watchpoints,
+    /// Reset the core with and without halting it, and reset the whole system.
+    Reset,
+    /// Erase, program and verify the expendable flash.
+    Flash,
+    /// Attach again, after the first session was closed.
+    Reattach,
+}
+
+impl Check {
+    /// All checks, in the order in which they are run.
+    pub const ALL: [Check; 11] = [
+        Check::Attach,
+        Check::HaltResume,
+        Check::Step,
+        Check::Registers,
+        Check::Memory,
+        Check::Batching,
+        Check::Breakpoints,
+        Check::Watchpoints,
+        Check::Reset,
+        Check::Flash,
+        Check::Reattach,
+    ];
+
+    /// The name of the check, as it is used in the report and by [`Check::from_str`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Attach => "attach",
+            Check::HaltResume => "halt-resume",
+            Check::Step => "step",
+            Check::Registers => "registers",
+            Check::Memory => "memory",
+            Check::Batching => "batching",
+            Check::Breakpoints => "breakpoints",
+            Check::Watchpoints => "watchpoints",
+            Check::Reset => "reset",
+            Check::Flash => "flash",
+            Check::Reattach => "reattach",
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Check {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Check::ALL
+            .into_iter()
+            .find(|check| check.name() == name)
+            .ok_or_else(|| Error::UnknownConformanceCheck(name.to_string()))
+    }
+}
+
+/// The outcome of a [`Check`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "kebab-case")]
+pub enum CheckOutcome {
+    /// The check passed.
+    Pass,
+    /// The check failed, for the given reason.
+    Fail(String),
+    /// The check was skipped, for the given reason.
+    Skip(String),
+}
+
+/// A duration measured by a [`Check`], e.g. the longest halt of the core.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Measurement {
+    /// What was measured.
+    pub name: String,
+    /// The measured duration.
+    #[serde(rename = "duration_us", with = "micros")]
+    pub duration: Duration,
+}
+
+impl Measurement {
+    fn new(name: &str, duration: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            duration,
+        }
+    }
+}
+
+/// The result of a [`Check`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckResult {
+    /// The check.
+    pub check: Check,
+    /// Whether the check passed.
+    pub outcome: CheckOutcome,
+    /// The time the check took.
+    #[serde(rename = "duration_us", with = "micros")]
+    pub duration: Duration,
+    /// The durations the check measured.
+    pub measurements: Vec<Measurement>,
+}
+
+/// The results of a run of the [`ConformanceSuite`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConformanceReport {
+    /// The name of the target, if it could be attached.
+    pub target: Option<String>,
+    /// The index of the checked core.
+    pub core: usize,
+    /// The results of the checks, in the order of [`Check::ALL`].
+    pub results: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    /// Returns the result of `check`.
+    pub fn result(&self, check: Check) -> Option<&CheckResult> {
+        self.results.iter().find(|result| result.check == check)
+    }
+
+    /// Returns the results of the checks which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results
+            .iter()
+            .filter(|result| matches!(result.outcome, CheckOutcome::Fail(_)))
+    }
+
+    /// Returns `true` if no check failed.
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Serialize the report to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A conformance report is always serializable")
+    }
+}
+
+/// A suite of checks, which qualifies a target and a probe for probe-rs.
+///
+/// The suite needs a range of RAM it can use as scratch memory, which must be at least
+/// 256 bytes long and start at an 8-byte boundary. The flash is only checked if a range of
+/// expendable flash is given with [`ConformanceSuite::expendable_flash`]. Neither range is
+/// changed permanently: their contents are restored after the checks, and flash sectors
+/// which are only partially covered by the expendable flash keep their other bytes.
+///
+/// ```no_run
+/// use probe_rs::conformance::{Check, ConformanceSuite};
+/// use probe_rs::{Permissions, Probe};
+///
+/// let report = ConformanceSuite::new(0x2000_0000..0x2000_1000)
+///     .expendable_flash(0x0803_f800..0x0804_0000)
+///     .skip(Check::Reset)
+///     .run(|| {
+///         let probe = Probe::list_all()[0].open()?;
+///         probe.attach("nrf52840", Permissions::default())
+///     })?;
+///
+/// println!("{}", report.to_json());
+/// # Ok::<(), probe_rs::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConformanceSuite {
+    scratch_ram: Range<u64>,
+    expendable_flash: Option<Range<u64>>,
+    core: usize,
+    skipped: Vec<Check>,
+    timeout: Duration,
+}
+
+impl ConformanceSuite {
+    /// Create a suite which uses `scratch_ram` as scratch memory, and checks core 0.
+    pub fn new(scratch_ram: Range<u64>) -> Self {
+        Self {
+            scratch_ram,
+            expendable_flash: None,
+            core: 0,
+            skipped: Vec::new(),
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    /// Check the flash, by erasing and programming `range`.
+    pub fn expendable_flash(mut self, range: Range<u64>) -> Self {
+        self.expendable_flash = Some(range);
+        self
+    }
+
+    /// Check the core with the index `core`.
+    pub fn core(mut self, core: usize) -> Self {
+        self.core = core;
+        self
+    }
+
+    /// Skip `check`, e.g. because the target can't be reset.
+    ///
+    /// Skipping [`Check::Attach`] only means that it isn't scored, the target is still
+    /// attached.
+    pub fn skip(mut self, check: Check) -> Self {
+        self.skipped.push(check);
+        self
+    }
+
+    /// The time the core is given to halt, 500 ms by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the checks, with a session created by `attach`.
+    ///
+    /// `attach` is called again for [`Check::Reattach`], after the first session was
+    /// dropped. If the target can't be attached, all other checks are skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the scratch RAM isn't inside a RAM region of the core, or the expendable flash isn't
+    /// inside one of its flash regions, [`Error::InvalidConformanceRange`] is returned before
+    /// the core is accessed. Failures of the checks are only recorded in the report.
+    pub fn run<E: fmt::Display>(
+        &self,
+        mut attach: impl FnMut() -> Result<Session, E>,
+    ) -> Result<ConformanceReport, Error> {
+        let mut runner = Runner {
+            skipped: &self.skipped,
+            results: Vec::new(),
+        };
+
+        let started = Instant::now();
+        let attached = attach_session(&mut attach, self.core);
+        let duration = started.elapsed();
+
+        let mut session = match attached {
+            Ok(session) => {
+                runner.push(Check::Attach, Ok(()), duration, Vec::new());
+                session
+            }
+            Err(failure) => {
+                let reason = format!("The target could not be attached: {}", failure.reason());
+                runner.push(Check::Attach, Err(failure), duration, Vec::new());
+
+                for &check in &Check::ALL[1..] {
+                    runner.push(
+                        check,
+                        Err(Failure::Skip(reason.clone())),
+                        Duration::ZERO,
+                        Vec::new(),
+                    );
+                }
+
+                return Ok(runner.report(None, self.core));
+            }
+        };
+
+        self.validate(session.target())?;
+
+        let setup = Setup {
+            core: self.core,
+            scratch: self.scratch_ram.clone(),
+            expendable_flash: self.expendable_flash.clone(),
+            memory_map: session.target().memory_map.clone(),
+            timeout: self.timeout,
+        };
+        let target = session.target().name.clone();
+
+        let snapshot = session.core(setup.core).and_then(|mut core| {
+            core.halt(setup.timeout)?;
+            core.save_context(&[setup.scratch.clone()])
+        });
+
+        match snapshot {
+            Ok(snapshot) => {
+                runner.record(Check::HaltResume, |measurements| {
+                    check_halt_resume(&mut setup.halted_core(&mut session)?, &setup, measurements)
+                });
+                runner.record(Check::Step, |measurements| {
+                    check_step(&mut setup.halted_core(&mut session)?, &setup, measurements)
+                });
+                runner.record(Check::Registers, |_| {
+                    check_registers(&mut setup.halted_core(&mut session)?)
+                });
+                runner.record(Check::Memory, |_| {
+                    check_memory(&mut setup.halted_core(&mut session)?, &setup)
+                });
+                runner.record(Check::Batching, |_| {
+                    check_batching(&mut setup.halted_core(&mut session)?, &setup)
+                });
+                runner.record(Check::Breakpoints, |measurements| {
+                    check_breakpoints(&mut setup.halted_core(&mut session)?, &setup, measurements)
+                });
+                runner.record(Check::Watchpoints, |_| {
+                    check_watchpoints(&mut setup.halted_core(&mut session)?, &setup)
+                });
+
+                let restored = setup.halted_core(&mut session).and_then(|mut core| {
+                    core.clear_all_hw_breakpoints()?;
+                    core.clear_all_sw_breakpoints()?;
+                    core.clear_all_watchpoints()?;
+                    core.restore_context(&snapshot)
+                });
+
+                match restored {
+                    Ok(report) if !report.is_complete() => {
+                        log::warn!(
+                            "The context of the core was not restored completely: {:?}",
+                            report
+                        )
+                    }
+                    Ok(_) => (),
+                    Err(error) => {
+                        log::warn!("Failed to restore the context of the core: {}", error)
+                    }
+                }
+            }
+            Err(error) => {
+                let reason = format!("The core could not be halted: {}", error);
+
+                for check in CORE_CHECKS {
+                    runner.record(check, |_| Err(Failure::Fail(reason.clone())));
+                }
+            }
+        }
+
+        runner.record(Check::Reset, |measurements| {
+            check_reset(&mut session, &setup, measurements)
+        });
+        runner.record(Check::Flash, |measurements| {
+            check_flash(&mut session, &setup, measurements)
+        });
+
+        drop(session);
+
+        runner.record(Check::Reattach, |measurements| {
+            let started = Instant::now();
+            let mut session = attach_session(&mut attach, setup.core)?;
+            measurements.push(Measurement::new("attach", started.elapsed()));
+
+            let mut core = setup.halted_core(&mut session)?;
+            core.read_word_32(setup.scratch.start)?;
+
+            Ok(())
+        });
+
+        Ok(runner.report(Some(target), self.core))
+    }
+
+    /// Check that the ranges of the suite are in the memory map of the core.
+    fn validate(&self, target: &Target) -> Result<(), Error> {
+        let core_name = &target
+            .cores
+            .get(self.core)
+            .ok_or(Error::CoreNotFound(self.core))?
+            .name;
+        let accessible = |cores: &Vec<String>| cores.is_empty() || cores.contains(core_name);
+        let contains = |region: &Range<u64>, range: &Range<u64>| {
+            region.start <= range.start && range.end <= region.end
+        };
+        let invalid = |range: &Range<u64>, reason| Error::InvalidConformanceRange {
+            start: range.start,
+            end: range.end,
+            reason,
+        };
+
+        let scratch = &self.scratch_ram;
+        if scratch.start % 8 != 0 {
+            return Err(invalid(
+                scratch,
+                "the scratch RAM must start at an 8-byte boundary",
+            ));
+        }
+        if scratch.end < scratch.start + MIN_SCRATCH_SIZE {
+            return Err(invalid(
+                scratch,
+                "the scratch RAM must be at least 256 bytes long",
+            ));
+        }
+        if !target.memory_map.iter().any(|region| match region {
+            MemoryRegion::Ram(region) => {
+                accessible(&region.cores) && contains(&region.range, scratch)
+            }
+            _ => false,
+        }) {
+            return Err(invalid(
+                scratch,
+                "the scratch RAM must be inside a RAM region of the core",
+            ));
+        }
+
+        if let Some(flash) = &self.expendable_flash {
+            if flash.is_empty() {
+                return Err(invalid(flash, "the expendable flash must not be empty"));
+            }
+            if !target.memory_map.iter().any(|region| match region {
+                MemoryRegion::Nvm(region) => {
+                    accessible(&region.cores) && contains(&region.range, flash)
+                }
+                _ => false,
+            }) {
+                return Err(invalid(
+                    flash,
+                    "the expendable flash must be inside a flash region of the core",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Serializes durations as whole microseconds.
+mod micros {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_micros() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_micros)
+    }
+}
+
+/// Why a check didn't pass.
+#[derive(Debug)]
+enum Failure {
+    Fail(String),
+    Skip(String),
+}
+
+impl Failure {
+    fn reason(&self) -> &str {
+        match self {
+            Failure::Fail(reason) | Failure::Skip(reason) => reason,
+        }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(error: Error) -> Self {
+        Failure::Fail(error.to_string())
+    }
+}
+
+impl From<FlashError> for Failure {
+    fn from(error: FlashError) -> Self {
+        Failure::Fail(error.to_string())
+    }
+}
+
+/// Fail with `reason` unless `condition` holds.
+fn ensure(condition: bool, reason: impl FnOnce() -> String) -> Result<(), Failure> {
+    if condition {
+        Ok(())
+    } else {
+        Err(Failure::Fail(reason()))
+    }
+}
+
+/// Records the results of the checks.
+struct Runner<'a> {
+    skipped: &'a [Check],
+    results: Vec<CheckResult>,
+}
+
+impl Runner<'_> {
+    /// Run `check`, unless it is skipped.
+    fn record(
+        &mut self,
+        check: Check,
+        run: impl FnOnce(&mut Vec<Measurement>) -> Result<(), Failure>,
+    ) {
+        if self.skipped.contains(&check) {
+            self.push(check, Ok(()), Duration::ZERO, Vec::new());
+            return;
+        }
+
+        let mut measurements = Vec::new();
+        let started = Instant::now();
+        let result = run(&mut measurements);
+
+        self.push(check, result, started.elapsed(), measurements);
+    }
+
+    /// Record the `result` of `check`, or that it is skipped.
+    fn push(
+        &mut self,
+        check: Check,
+        result: Result<(), Failure>,
+        duration: Duration,
+        measurements: Vec<Measurement>,
+    ) {
+        let outcome = if self.skipped.contains(&check) {
+            CheckOutcome::Skip("Skipped by the configuration".to_string())
+        } else {
+            match result {
+                Ok(()) => CheckOutcome::Pass,
+                Err(Failure::Fail(reason)) => CheckOutcome::Fail(reason),
+                Err(Failure::Skip(reason)) => CheckOutcome::Skip(reason),
+            }
+        };
+
+        log::info!("Conformance check {}: {:?}", check, outcome);
+
+        self.results.push(CheckResult {
+            check,
+            outcome,
+            duration,
+            measurements,
+        });
+    }
+
+    fn report(self, target: Option<String>, core: usize) -> ConformanceReport {
+        ConformanceReport {
+            target,
+            core,
+            results: self.results,
+        }
+    }
+}
+
+/// Attach with `attach`, and check that the core can be accessed.
+fn attach_session<E: fmt::Display>(
+    attach: &mut impl FnMut() -> Result<Session, E>,
+    core: usize,
+) -> Result<Session, Failure> {
+    let mut session =
+        attach().map_err(|error| Failure::Fail(format!("Failed to attach: {}", error)))?;
+    session.core(core)?;
+
+    Ok(session)
+}
+
+/// The validated configuration of a run of the suite.
+struct Setup {
+    core: usize,
+    scratch: Range<u64>,
+    expendable_flash: Option<Range<u64>>,
+    memory_map: Vec<MemoryRegion>,
+    timeout: Duration,
+}
+
+impl Setup {
+    /// The checked core, which is halted.
+    fn halted_core<'session>(
+        &self,
+        session: &'session mut Session,
+    ) -> Result<Core<'session>, Error> {
+        let mut core = session.core(self.core)?;
+        core.halt(self.timeout)?;
+
+        Ok(core)
+    }
+
+    /// Load the [`TestProgram`] into the scratch RAM, and prepare the core to execute it.
+    fn load_program(&self, core: &mut Core<'_>) -> Result<TestProgram, Error> {
+        let program = TestProgram::for_instruction_set(core.instruction_set()?);
+
+        core.write_coherent(self.scratch.start, &program.code)?;
+        core.prepare_execution(
+            self.scratch.start | program.interworking_bit,
+            Some(self.scratch.end & !7),
+        )?;
+
+        Ok(program)
+    }
+}
+
+/// A program which executes two `NOP`s, and then loops endlessly.
+struct TestProgram {
+    code: Vec<u8>,
+    instruction_size: u64,
+    /// Bit 0 of the entry point, which selects the Thumb state on ARM.
+    interworking_bit: u64,
+}
+
+impl TestProgram {
+    fn for_instruction_set(instruction_set: InstructionSet) -> Self {
+        if instruction_set == InstructionSet::Thumb2 {
+            // nop; nop; b .
+            let code = [0xbf00u16, 0xbf00, 0xe7fe];
+
+            return Self {
+                code: code.iter().flat_map(|half| half.to_le_bytes()).collect(),
+                instruction_size: 2,
+                interworking_bit: 1,
+            };
+        }
+
+        let code: [u32; 3] = match instruction_set {
+            // nop; nop; b .
+            InstructionSet::A32 => [0xe320_f000, 0xe320_f000, 0xeaff_fffe],
+            InstructionSet::A64 => [0xd503_201f, 0xd503_201f, 0x1400_0000],
+            // nop; nop; j .
+            _ => [0x0000_0013, 0x0000_0013, 0x0000_006f],
+        };
+
+        Self {
+            code: code.iter().flat_map(|word| word.to_le_bytes()).collect(),
+            instruction_size: 4,
+            interworking_bit: 0,
+        }
+    }
+
+    /// The address of the instruction with `index`, if the program is loaded at `base`.
+    fn instruction(&self, base: u64, index: u64) -> u64 {
+        base + index * self.instruction_size
+    }
+}
+
+fn read_pc(core: &mut Core<'_>) -> Result<u64, Error> {
+    let pc = core.registers().program_counter();
+    core.read_core_reg(pc)
+}
+
+fn check_halt_resume(
+    core: &mut Core<'_>,
+    setup: &Setup,
+    measurements: &mut Vec<Measurement>,
+) -> Result<(), Failure> {
+    let program = setup.load_program(core)?;
+    let endless_loop = program.instruction(setup.scratch.start, 2);
+
+    let mut resume = Duration::ZERO;
+    let mut halt = Duration::ZERO;
+
+    for _ in 0..RESUME_CYCLES {
+        let started = Instant::now();
+        core.run()?;
+        resume = resume.max(started.elapsed());
+
+        ensure(!core.core_halted()?, || {
+            "The core is still halted after it was resumed".to_string()
+        })?;
+
+        let started = Instant::now();
+        let pc = core.halt(setup.timeout)?.pc;
+        halt = halt.max(started.elapsed());
+
+        ensure(core.core_halted()?, || {
+            "The core is not halted after it was halted".to_string()
+        })?;
+        ensure(pc == endless_loop, || {
+            format!(
+                "The core halted at {:#010x} instead of the loop at {:#010x}",
+                pc, endless_loop
+            )
+        })?;
+    }
+
+    measurements.push(Measurement::new("max-resume", resume));
+    measurements.push(Measurement::new("max-halt", halt));
+
+    Ok(())
+}
+
+fn check_step(
+    core: &mut Core<'_>,
+    setup: &Setup,
+    measurements: &mut Vec<Measurement>,
+) -> Result<(), Failure> {
+    let program = setup.load_program(core)?;
+
+    let mut step = Duration::ZERO;
+
+    // The third step executes the loop, which jumps to itself.
+    for index in 1..=3 {
+        let started = Instant::now();
+        let pc = core.step()?.pc;
+        step = step.max(started.elapsed());
+
+        let expected = program.instruction(setup.scratch.start, index.min(2));
+        ensure(pc == expected, || {
+            format!(
+                "Step {} stopped at {:#010x} instead of {:#010x}",
+                index, pc, expected
+            )
+        })?;
+        ensure(read_pc(core)? == expected, || {
+            format!("The program counter doesn't match the step {}", index)
+        })?;
+    }
+
+    measurements.push(Measurement::new("max-step", step));
+
+    Ok(())
+}
+
+fn check_registers(core: &mut Core<'_>) -> Result<(), Failure> {
+    let registers = core.registers();
+    // Registers which don't only hold data: they are written back with their own value.
+    let special: Vec<_> = [
+        Some(registers.program_counter()),
+        Some(registers.stack_pointer()),
+        registers.msp(),
+        registers.psp(),
+        registers.psr(),
+        registers.extra,
+        registers.fp_status,
+    ]
+    .iter()
+    .flatten()
+    .map(|description| description.id)
+    .collect();
+
+    let mut available: Vec<(&RegisterDescription, u64)> = Vec::new();
+    for description in registers.context_registers() {
+        match core.read_core_reg::<u64>(description) {
+            Ok(value) => available.push((description, value)),
+            Err(Error::RegisterNotAvailable { .. }) => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    // A batch of reads returns the same values as single reads.
+    let ids: Vec<_> = available
+        .iter()
+        .map(|(description, _)| description.id)
+        .collect();
+    let batch = core.read_core_regs(&ids)?;
+    for ((description, value), read) in available.iter().zip(batch) {
+        let read: u64 = read.try_into()?;
+        ensure(read == *value, || {
+            format!(
+                "{} reads as {:#x} in a batch, but as {:#x} on its own",
+                description.name(),
+                read,
+                value
+            )
+        })?;
+    }
+
+    for &(description, original) in &available {
+        let size_mask = if description.size_in_bits() >= 64 {
+            u64::MAX
+        } else {
+            (1 << description.size_in_bits()) - 1
+        };
+
+        // Registers which only hold data can be written with any value, even if some of their
+        // bits are fixed, like in the zero register of RISC-V.
+        let patterns = if !special.contains(&description.id) {
+            vec![0x5555_5555_5555_5555, 0xaaaa_aaaa_aaaa_aaaa, original]
+        } else {
+            vec![original]
+        };
+
+        for pattern in patterns {
+            let pattern = pattern & size_mask;
+            let value = if description.size_in_bits() > 32 {
+                RegisterValue::U64(pattern)
+            } else {
+                RegisterValue::U32(pattern as u32)
+            };
+            core.write_core_reg(description.id, value)?;
+
+            let mask = description.writable_mask();
+            let expected = (original & !mask | pattern & mask) & size_mask;
+            let read = core.read_core_reg::<u64>(description)? & size_mask;
+
+            ensure(read == expected, || {
+                format!(
+                    "{} reads as {:#x} after writing {:#x}, instead of {:#x}",
+                    description.name(),
+                    read,
+                    pattern,
+                    expected
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `len` bytes which differ from their neighbours, and for different `seed`s.
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|index| (index as u8).wrapping_mul(31) ^ (index >> 8) as u8 ^ seed)
+        .collect()
+}
+
+/// Fail if `actual`, which was read at `address`, differs from `expected`.
+fn compare(what: &str, address: u64, actual: &[u8], expected: &[u8]) -> Result<(), Failure> {
+    match actual.iter().zip(expected).position(|(a, e)| a != e) {
+        Some(offset) => Err(Failure::Fail(format!(
+            "{}: the byte at {:#010x} reads as {:#04x} instead of {:#04x}",
+            what,
+            address + offset as u64,
+            actual[offset],
+            expected[offset]
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Write `data` to `address` with accesses of `width` bits.
+fn write_block(core: &mut Core<'_>, address: u64, data: &[u8], width: u8) -> Result<(), Error> {
+    match width {
+        8 => core.write_8(address, data),
+        32 => {
+            let words: Vec<u32> = data
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            core.write_32(address, &words)
+        }
+        _ => {
+            let words: Vec<u64> = data
+                .chunks_exact(8)
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            core.write_64(address, &words)
+        }
+    }
+}
+
+/// Read `len` bytes at `address` with accesses of `width` bits.
+fn read_block(core: &mut Core<'_>, address: u64, len: usize, width: u8) -> Result<Vec<u8>, Error> {
+    match width {
+        8 => {
+            let mut data = vec![0; len];
+            core.read_8(address, &mut data)?;
+            Ok(data)
+        }
+        32 => {
+            let mut words = vec![0; len / 4];
+            core.read_32(address, &mut words)?;
+            Ok(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+        }
+        _ => {
+            let mut words = vec![0; len / 8];
+            core.read_64(address, &mut words)?;
+            Ok(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+        }
+    }
+}
+
+/// Fill the window at `base` with guard bytes, write `value` at `offset` with `write`, and
+/// check that only the bytes of `value` changed.
+fn check_in_window(
+    core: &mut Core<'_>,
+    base: u64,
+    offset: usize,
+    value: &[u8],
+    what: &str,
+    write: impl FnOnce(&mut Core<'_>, u64) -> Result<(), Error>,
+) -> Result<(), Failure> {
+    core.write_8(base, &[GUARD; WINDOW])?;
+    write(core, base + offset as u64)?;
+
+    let mut expected = [GUARD; WINDOW];
+    expected[offset..offset + value.len()].copy_from_slice(value);
+
+    let read = read_block(core, base, WINDOW, 8)?;
+    compare(what, base, &read, &expected)
+}
+
+fn check_memory(core: &mut Core<'_>, setup: &Setup) -> Result<(), Failure> {
+    let scratch = &setup.scratch;
+    let base = scratch.start;
+
+    // Blocks written with each access width, and read back with each width.
+    let len = (scratch.end - base).min(BLOCK_SIZE) as usize & !7;
+    for (seed, write_width) in [8, 32, 64].into_iter().enumerate() {
+        let data = pattern(len, seed as u8);
+        write_block(core, base, &data, write_width)?;
+
+        for read_width in [8, 32, 64] {
+            let read = read_block(core, base, len, read_width)?;
+            let what = format!(
+                "A block written with {}-bit accesses and read with {}-bit accesses",
+                write_width, read_width
+            );
+            compare(&what, base, &read, &data)?;
+        }
+    }
+
+    // Blocks of bytes at every alignment.
+    for offset in 0..8 {
+        for len in 1..=9 {
+            let data = pattern(len, offset as u8);
+            let what = format!("{} bytes written at offset {}", len, offset);
+            check_in_window(core, base, offset, &data, &what, |core, address| {
+                core.write_8(address, &data)
+            })?;
+
+            let mut read = vec![0; len];
+            core.read(base + offset as u64, &mut read)?;
+            let what = format!("{} bytes read at offset {}", len, offset);
+            compare(&what, base + offset as u64, &read, &data)?;
+        }
+    }
+
+    // Single values of each width at every aligned offset.
+    for offset in 0..WINDOW {
+        let what = format!("A byte written at offset {}", offset);
+        check_in_window(core, base, offset, &[0x3c], &what, |core, address| {
+            core.write_word_8(address, 0x3c)
+        })?;
+    }
+    for offset in (0..WINDOW).step_by(4) {
+        let value: u32 = 0x1234_5678;
+        let what = format!("A 32-bit word written at offset {}", offset);
+        check_in_window(
+            core,
+            base,
+            offset,
+            &value.to_le_bytes(),
+            &what,
+            |core, address| core.write_word_32(address, value),
+        )?;
+    }
+    for offset in (0..WINDOW).step_by(8) {
+        let value: u64 = 0x0123_4567_89ab_cdef;
+        let what = format!("A 64-bit word written at offset {}", offset);
+        check_in_window(
+            core,
+            base,
+            offset,
+            &value.to_le_bytes(),
+            &what,
+            |core, address| core.write_word_64(address, value),
+        )?;
+    }
+
+    let data = pattern(WINDOW, 0x5a);
+    core.write_8(base, &data)?;
+    for offset in 0..WINDOW {
+        let read = core.read_word_8(base + offset as u64)?;
+        compare(
+            &format!("A byte read at offset {}", offset),
+            base + offset as u64,
+            &[read],
+            &data[offset..offset + 1],
+        )?;
+    }
+    for offset in (0..WINDOW).step_by(4) {
+        let read = core.read_word_32(base + offset as u64)?;
+        compare(
+            &format!("A 32-bit word read at offset {}", offset),
+            base + offset as u64,
+            &read.to_le_bytes(),
+            &data[offset..offset + 4],
+        )?;
+    }
+    for offset in (0..WINDOW).step_by(8) {
+        let read = core.read_word_64(base + offset as u64)?;
+        compare(
+            &format!("A 64-bit word read at offset {}", offset),
+            base + offset as u64,
+            &read.to_le_bytes(),
+            &data[offset..offset + 8],
+        )?;
+    }
+
+    // Misaligned word accesses are rejected, and don't change memory.
+    core.write_8(base, &[GUARD; WINDOW])?;
+    ensure(core.write_word_32(base + 2, 0).is_err(), || {
+        "A misaligned 32-bit write was accepted".to_string()
+    })?;
+    ensure(core.read_word_32(base + 1).is_err(), || {
+        "A misaligned 32-bit read was accepted".to_string()
+    })?;
+    let read = read_block(core, base, WINDOW, 8)?;
+    compare("A misaligned write", base, &read, &[GUARD; WINDOW])?;
+
+    // The last bytes of the scratch RAM.
+    let last = scratch.end - 8;
+    let value: u64 = 0xfedc_ba98_7654_3210;
+    core.write_word_64(last, value)?;
+    compare(
+        "The last 64-bit word",
+        last,
+        &core.read_word_64(last)?.to_le_bytes(),
+        &value.to_le_bytes(),
+    )?;
+    core.write_word_8(scratch.end - 1, 0x69)?;
+    compare(
+        "The last byte",
+        scratch.end - 1,
+        &[core.read_word_8(scratch.end - 1)?],
+        &[0x69],
+    )?;
+
+    // A block which crosses a 1 KiB boundary, at which the address auto-increment of many
+    // memory APs wraps.
+    let boundary = (base / 0x400 + 1) * 0x400;
+    if boundary - 64 >= base && boundary + 64 <= scratch.end {
+        let data = pattern(128, 0x96);
+        write_block(core, boundary - 64, &data, 32)?;
+
+        for width in [8, 32] {
+            let read = read_block(core, boundary - 64, data.len(), width)?;
+            let what = format!(
+                "A block across a 1 KiB boundary read with {}-bit accesses",
+                width
+            );
+            compare(&what, boundary - 64, &read, &data)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `bytes` at `offset` of the `model` of the window.
+fn put(model: &mut [u8], offset: usize, bytes: &[u8]) {
+    model[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn check_batching(core: &mut Core<'_>, setup: &Setup) -> Result<(), Failure> {
+    let base = setup.scratch.start;
+    let mut model = pattern(WINDOW, 0x11);
+    core.write_8(base, &model)?;
+
+    // Overlapping writes of mixed widths, which are queued without a flush, are executed in
+    // order, and a read sees all of them.
+    core.write_word_32(base, 0x1122_3344)?;
+    put(&mut model, 0, &0x1122_3344u32.to_le_bytes());
+    core.write_word_8(base + 1, 0xaa)?;
+    put(&mut model, 1, &[0xaa]);
+    core.write_word_64(base + 8, 0x0102_0304_0506_0708)?;
+    put(&mut model, 8, &0x0102_0304_0506_0708u64.to_le_bytes());
+    core.write_32(base + 12, &[0xdead_beef])?;
+    put(&mut model, 12, &0xdead_beefu32.to_le_bytes());
+    core.write_word_8(base + 15, 0x55)?;
+    put(&mut model, 15, &[0x55]);
+    core.write_8(base + 17, &[1, 2, 3])?;
+    put(&mut model, 17, &[1, 2, 3]);
+
+    let read = read_block(core, base, WINDOW, 8)?;
+    compare("Queued writes", base, &read, &model)?;
+    core.flush()?;
+
+    // Writes which are coalesced keep their order across a barrier, and reads through the
+    // coalescer see the queued writes.
+    let mut coalescer = WriteCoalescer::new(&mut *core, &setup.memory_map);
+    coalescer.write_word_32(base + 16, 0xcafe_f00d)?;
+    put(&mut model, 16, &0xcafe_f00du32.to_le_bytes());
+    coalescer.write_word_8(base + 18, 0x42)?;
+    put(&mut model, 18, &[0x42]);
+    coalescer.write_barrier()?;
+    coalescer.write_word_32(base + 20, 0x7777_7777)?;
+    put(&mut model, 20, &0x7777_7777u32.to_le_bytes());
+    coalescer.write_word_8(base + 17, 0x99)?;
+    put(&mut model, 17, &[0x99]);
+
+    let mut read = vec![0; WINDOW];
+    coalescer.read_8(base, &mut read)?;
+    compare(
+        "Coalesced writes read through the coalescer",
+        base,
+        &read,
+        &model,
+    )?;
+
+    coalescer.write_8(base + 24, &[8, 7, 6, 5, 4, 3, 2, 1])?;
+    put(&mut model, 24, &[8, 7, 6, 5, 4, 3, 2, 1]);
+    coalescer.write_word_8(base + 31, 0xee)?;
+    put(&mut model, 31, &[0xee]);
+    coalescer.flush()?;
+    drop(coalescer);
+
+    let read = read_block(core, base, WINDOW, 8)?;
+    compare("Coalesced writes after a flush", base, &read, &model)
+}
+
+fn check_breakpoints(
+    core: &mut Core<'_>,
+    setup: &Setup,
+    measurements: &mut Vec<Measurement>,
+) -> Result<(), Failure> {
+    let mut checked = false;
+
+    if core.available_breakpoint_units()? > 0 {
+        let program = setup.load_program(core)?;
+        let address = program.instruction(setup.scratch.start, 1);
+
+        match core.set_hw_breakpoint(address) {
+            Ok(()) => {
+                let duration = run_to_breakpoint(core, setup, address, HaltReason::Breakpoint)?;
+                measurements.push(Measurement::new("hardware-breakpoint", duration));
+
+                core.clear_hw_breakpoint(address)?;
+                run_past_breakpoint(core, setup, &program)?;
+                checked = true;
+            }
+            // FPBv1 units can't break in RAM, for example.
+            Err(Error::UnsupportedBreakpointAddress { .. }) => (),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    if core.capabilities()?.software_breakpoints {
+        let program = setup.load_program(core)?;
+        let address = program.instruction(setup.scratch.start, 1);
+
+        core.set_sw_breakpoint(address)?;
+        let duration = run_to_breakpoint(core, setup, address, HaltReason::SoftwareBreakpoint)?;
+        measurements.push(Measurement::new("software-breakpoint", duration));
+
+        core.clear_sw_breakpoint(address)?;
+        let read = read_block(core, setup.scratch.start, program.code.len(), 8)?;
+        compare(
+            "The code after the software breakpoint was cleared",
+            setup.scratch.start,
+            &read,
+            &program.code,
+        )?;
+        run_past_breakpoint(core, setup, &program)?;
+        checked = true;
+    }
+
+    if !checked {
+        return Err(Failure::Skip(
+            "No breakpoint can be set in the scratch RAM".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_watchpoints(core: &mut Core<'_>, setup: &Setup) -> Result<(), Failure> {
+    if core.available_watchpoint_units()? == 0 {
+        return Err(Failure::Skip(
+            "The core has no watchpoint comparators".to_string(),
+        ));
+    }
+
+    let config = WatchpointConfig::new(setup.scratch.start, 4, WatchpointKind::Write);
+    core.set_watchpoint(config)?;
+
+    let set = core.watchpoints().to_vec();
+    ensure(set.len() == 1 && set[0].config == config, || {
+        format!("The watchpoints {:?} were set instead of {:?}", set, config)
+    })?;
+
+    core.clear_watchpoint(config.address)?;
+
+    ensure(core.watchpoints().is_empty(), || {
+        "The watchpoint is still set after it was cleared".to_string()
+    })
+}
+
+/// Run the prepared program, and check that the core halts at the breakpoint at `address`,
+/// with the halt `reason`.
+fn run_to_breakpoint(
+    core: &mut Core<'_>,
+    setup: &Setup,
+    address: u64,
+    reason: HaltReason,
+) -> Result<Duration, Failure> {
+    let started = Instant::now();
+    core.run()?;
+    core.wait_for_core_halted(setup.timeout)?;
+    let duration = started.elapsed();
+
+    let pc = read_pc(core)?;
+    ensure(pc == address, || {
+        format!(
+            "The core halted at {:#010x} instead of the breakpoint at {:#010x}",
+            pc, address
+        )
+    })?;
+
+    let status = core.status()?;
+    ensure(status == CoreStatus::Halted(reason), || {
+        format!(
+            "The core halted at the breakpoint with the status {:?}",
+            status
+        )
+    })?;
+
+    Ok(duration)
+}
+
+/// Resume the core after its breakpoint was cleared, and check that it reaches the loop.
+fn run_past_breakpoint(
+    core: &mut Core<'_>,
+    setup: &Setup,
+    program: &TestProgram,
+) -> Result<(), Failure> {
+    core.run()?;
+    let pc = core.halt(setup.timeout)?.pc;
+
+    let endless_loop = program.instruction(setup.scratch.start, 2);
+    ensure(pc == endless_loop, || {
+        format!(
+            "The core halted at {:#010x} instead of the loop at {:#010x} after the breakpoint was cleared",
+            pc, endless_loop
+        )
+    })
+}
+
+fn check_reset(
+    session: &mut Session,
+    setup: &Setup,
+    measurements: &mut Vec<Measurement>,
+) -> Result<(), Failure> {
+    let started = Instant::now();
+    let report = session.reset_and_halt_core(setup.core, setup.timeout)?;
+    measurements.push(Measurement::new("reset-and-halt", started.elapsed()));
+
+    ensure(report.location != HaltLocation::Elsewhere, || {
+        format!(
+            "The core halted at {:#010x} after the reset, instead of at its reset vector",
+            report.pc
+        )
+    })?;
+
+    let mut core = session.core(setup.core)?;
+    ensure(core.core_halted()?, || {
+        "The core is not halted after a reset with halt".to_string()
+    })?;
+
+    let started = Instant::now();
+    core.reset()?;
+    measurements.push(Measurement::new("reset", started.elapsed()));
+
+    ensure(!core.core_halted()?, || {
+        "The core is halted after a reset without halt".to_string()
+    })?;
+    core.halt(setup.timeout)?;
+    drop(core);
+
+    let started = Instant::now();
+    session.reset_system(setup.timeout)?;
+    measurements.push(Measurement::new("reset-system", started.elapsed()));
+
+    ensure(session.core(setup.core)?.core_halted()?, || {
+        "The core is not halted after a reset of the system".to_string()
+    })
+}
+
+/// The bytes around `range`, inside of the flash region which contains it.
+fn flash_neighbours(memory_map: &[MemoryRegion], range: &Range<u64>) -> Vec<Range<u64>> {
+    let region = memory_map.iter().find_map(|region| match region {
+        MemoryRegion::Nvm(region)
+            if region.range.start <= range.start && range.end <= region.range.end =>
+        {
+            Some(region.range.clone())
+        }
+        _ => None,
+    });
+
+    match region {
+        Some(region) => vec![
+            range.start.saturating_sub(16).max(region.start)..range.start,
+            range.end..(range.end + 16).min(region.end),
+        ],
+        None => Vec::new(),
+    }
+}
+
+fn check_flash(
+    session: &mut Session,
+    setup: &Setup,
+    measurements: &mut Vec<Measurement>,
+) -> Result<(), Failure> {
+    let range = match &setup.expendable_flash {
+        Some(range) => range.clone(),
+        None => return Err(Failure::Skip("No expendable flash was given".to_string())),
+    };
+    let len = (range.end - range.start) as usize;
+
+    let read = |session: &mut Session, range: &Range<u64>| -> Result<Vec<u8>, Error> {
+        let mut data = vec![0; (range.end - range.start) as usize];
+        setup.halted_core(session)?.read_8(range.start, &mut data)?;
+        Ok(data)
+    };
+
+    let neighbours = flash_neighbours(&setup.memory_map, &range);
+    let mut before = Vec::new();
+    for neighbour in &neighbours {
+        before.push(read(session, neighbour)?);
+    }
+
+    let original = read(session, &range)?;
+    let first = pattern(len, 0xc3);
+    // Programming the inverted pattern needs an erase, because NOR flash can only be
+    // programmed from the erased value.
+    let second: Vec<u8> = first.iter().map(|byte| !byte).collect();
+
+    for (name, data) in [
+        ("program", &first),
+        ("reprogram", &second),
+        ("restore", &original),
+    ] {
+        let mut loader = session.target().flash_loader();
+        loader.add_data(range.start, data)?;
+
+        let mut options = DownloadOptions::new();
+        options.keep_unwritten_bytes = true;
+        options.verify = true;
+
+        let started = Instant::now();
+        loader.commit(session, options)?;
+        measurements.push(Measurement::new(name, started.elapsed()));
+
+        let what = format!("The flash after the {}", name);
+        compare(&what, range.start, &read(session, &range)?, data)?;
+    }
+
+    for (neighbour, before) in neighbours.iter().zip(before) {
+        compare(
+            "The flash next to the expendable flash",
+            neighbour.start,
+            &read(session, neighbour)?,
+            &before,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Check, CheckOutcome, CheckResult, ConformanceReport, Measurement};
+
+    #[test]
+    fn checks_are_parsed_by_name() {
+        for check in Check::ALL {
+            assert_eq!(check.name().parse::<Check>().unwrap(), check);
+        }
+
+        assert!("halt".parse::<Check>().is_err());
+    }
+
+    #[test]
+    fn report_is_serialized_with_durations_in_microseconds() {
+        let report = ConformanceReport {
+            target: Some("nrf52840".to_string()),
+            core: 0,
+            results: vec![
+                CheckResult {
+                    check: Check::HaltResume,
+                    outcome: CheckOutcome::Pass,
+                    duration: Duration::from_millis(12),
+                    measurements: vec![Measurement::new("max-halt", Duration::from_micros(350))],
+                },
+                CheckResult {
+                    check: Check::Watchpoints,
+                    outcome: CheckOutcome::Skip("not supported".to_string()),
+                    duration: Duration::ZERO,
+                    measurements: Vec::new(),
+                },
+            ],
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "target": "nrf52840",
+                "core": 0,
+                "results": [
+                    {
+                        "check": "halt-resume",
+                        "outcome": { "status": "pass" },
+                        "duration_us": 12000,
+                        "measurements": [{ "name": "max-halt", "duration_us": 350 }],
+                    },
+                    {
+                        "check": "watchpoints",
+                        "outcome": { "status": "skip", "reason": "not supported" },
+                        "duration_us": 0,
+                        "measurements": [],
+                    },
+                ],
+            })
+        );
+        assert!(report.passed());
+        assert_eq!(
+            serde_json::from_value::<ConformanceReport>(json).unwrap(),
+            report
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Translation between the addresses firmware was linked at and the addresses it runs at.
+//!
+//! Firmware which is copied to RAM by a bootloader, executed from an offset in flash, or
+//! which is position independent, runs at other addresses than the ones in its ELF file.
+//! The [`AddressMap`] of a core, set up with [`Core::set_load_offset`](crate::Core::set_load_offset),
+//! translates the addresses of the ELF file, the link addresses, to the addresses the core
+//! executes, the load addresses.
+
+use std::ops::Range;
+
+use crate::Error;
+
+/// A range of link addresses, which is loaded at another base address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressMapping {
+    /// The link addresses, as in the ELF file.
+    pub link_range: Range<u64>,
+    /// The load address of the start of `link_range`.
+    pub load_base: u64,
+}
+
+impl AddressMapping {
+    /// Returns the load addresses of the link range.
+    pub fn load_range(&self) -> Range<u64> {
+        self.load_base..self.load_base + (self.link_range.end - self.link_range.start)
+    }
+
+    fn to_load_address(&self, link_address: u64) -> Option<u64> {
+        self.link_range
+            .contains(&link_address)
+            .then(|| link_address - self.link_range.start + self.load_base)
+    }
+
+    fn to_link_address(&self, load_address: u64) -> Option<u64> {
+        self.load_range()
+            .contains(&load_address)
+            .then(|| load_address - self.load_base + self.link_range.start)
+    }
+}
+
+/// The link ranges of a core which are loaded at other addresses.
+///
+/// Addresses outside of all link ranges are not translated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressMap {
+    mappings: Vec<AddressMapping>,
+}
+
+impl AddressMap {
+    /// Returns the mappings, ordered by their link address.
+    pub fn mappings(&self) -> &[AddressMapping] {
+        &self.mappings
+    }
+
+    /// Returns true if no address is translated.
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Returns the address the core executes `link_address` at.
+    pub fn to_load_address(&self, link_address: u64) -> u64 {
+        self.mappings
+            .iter()
+            .find_map(|mapping| mapping.to_load_address(link_address))
+            .unwrap_or(link_address)
+    }
+
+    /// Returns the link address of `load_address`.
+    pub fn to_link_address(&self, load_address: u64) -> u64 {
+        self.mappings
+            .iter()
+            .find_map(|mapping| mapping.to_link_address(load_address))
+            .unwrap_or(load_address)
+    }
+
+    /// Returns the link addresses of the load addresses `range`, assuming it is loaded as
+    /// a whole like its start.
+    pub(crate) fn to_link_range(&self, range: &Range<u64>) -> Range<u64> {
+        let start = self.to_link_address(range.start);
+
+        start..start + (range.end - range.start)
+    }
+
+    /// Load `link_range` at `load_base`.
+    ///
+    /// Fails if the link range is empty, or overlaps the link range of another mapping.
+    pub(crate) fn insert(&mut self, link_range: Range<u64>, load_base: u64) -> Result<(), Error> {
+        if link_range.is_empty() {
+            return Err(Error::InvalidAddressMapping {
+                link_range,
+                reason: "The link range is empty".to_owned(),
+            });
+        }
+
+        if let Some(existing) = self.mappings.iter().find(|mapping| {
+            mapping.link_range.start < link_range.end && link_range.start < mapping.link_range.end
+        }) {
+            return Err(Error::InvalidAddressMapping {
+                reason: format!("It overlaps the link range {:#010x?}", existing.link_range),
+                link_range,
+            });
+        }
+
+        let index = self
+            .mappings
+            .partition_point(|mapping| mapping.link_range.start < link_range.start);
+        self.mappings.insert(
+            index,
+            AddressMapping {
+                link_range,
+                load_base,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Remove all mappings.
+    pub(crate) fn clear(&mut self) {
+        self.mappings.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn addresses_are_translated_in_both_directions() {
+        let mut map = AddressMap::default();
+        map.insert(0x0800_0000..0x0801_0000, 0x2400_0000).unwrap();
+        map.insert(0x0000_0000..0x0000_0400, 0x2000_0000).unwrap();
+
+        assert_eq!(map.mappings()[0].link_range, 0x0000_0000..0x0000_0400);
+
+        assert_eq!(map.to_load_address(0x0800_1234), 0x2400_1234);
+        assert_eq!(map.to_link_address(0x2400_1234), 0x0800_1234);
+        assert_eq!(map.to_load_address(0x0000_0100), 0x2000_0100);
+
+        // Addresses outside of the mappings are not translated.
+        assert_eq!(map.to_load_address(0x0801_0000), 0x0801_0000);
+        assert_eq!(map.to_link_address(0x2000_0400), 0x2000_0400);
+    }
+
+    #[test]
+    fn overlapping_link_ranges_are_rejected() {
+        let mut map = AddressMap::default();
+        map.insert(0x0800_0000..0x0801_0000, 0x2400_0000).unwrap();
+
+        assert!(map.insert(0x0800_ff00..0x0802_0000, 0x2500_0000).is_err());
+        assert!(map.insert(0x0900_0000..0x0900_0000, 0x2500_0000).is_err());
+        assert_eq!(map.mappings().len(), 1);
+    }
+}
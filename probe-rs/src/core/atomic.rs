@@ -0,0 +1,239 @@
+//! Atomic read-modify-writes of words in memory, e.g. of a mailbox which the debugger shares
+//! with the firmware, see [`Core::compare_and_swap_32`](crate::Core::compare_and_swap_32).
+//!
+//! The firmware of a halted core can't race with the debugger, so the word is modified with a
+//! plain read and write then. A running core is halted briefly, to modify the word with its
+//! own atomic instructions, and resumed afterwards:
+//!
+//! - Cortex-M cores with exclusive accesses, i.e. all but ARMv6-M, run a routine which
+//!   repeats `LDREX` and `STREX` until the store was exclusive.
+//! - RISC-V harts with the A extension execute an AMO, or `lr.w` and `sc.w`, in the program
+//!   buffer, see [`CoreInterface::atomic_32`](super::CoreInterface::atomic_32).
+//!
+//! The words of other running cores are only modified with a plain read and write, which
+//! loses the writes of the firmware in between, if that was allowed with
+//! [`Core::set_force_non_atomic`](crate::Core::set_force_non_atomic).
+
+use std::time::Duration;
+
+use super::{RoutineArgument, RoutineCall, TargetRoutine};
+use crate::intrusiveness::TargetOperation;
+use crate::{Core, CoreType, Error, MemoryInterface};
+
+/// The time the core may take to halt for an atomic operation.
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A compare-and-swap with `LDREX` and `STREX`, which is called with the address, the
+/// expected and the new value, and returns the previous value.
+const THUMB_COMPARE_AND_SWAP: [u16; 14] = [
+    0xe850, 0x3f00, // 1:  ldrex r3, [r0]
+    0x428b, //             cmp r3, r1
+    0xd105, //             bne 2f
+    0xe840, 0x2400, //     strex r4, r2, [r0]
+    0x2c00, //             cmp r4, #0
+    0xd1f7, //             bne 1b
+    0x0018, //             movs r0, r3
+    0x4770, //             bx lr
+    0xf3bf, 0x8f2f, // 2:  clrex
+    0x0018, //             movs r0, r3
+    0x4770, //             bx lr
+];
+
+/// A fetch-or with `LDREX` and `STREX`, which is called with the address and the bits to
+/// set, and returns the previous value. The `ORRS` is replaced by [`THUMB_ANDS`] for a
+/// fetch-and.
+const THUMB_FETCH_OR: [u16; 10] = [
+    0xe850, 0x3f00, // 1:  ldrex r3, [r0]
+    0x001a, //             movs r2, r3
+    0x430a, //             orrs r2, r1
+    0xe840, 0x2400, //     strex r4, r2, [r0]
+    0x2c00, //             cmp r4, #0
+    0xd1f7, //             bne 1b
+    0x0018, //             movs r0, r3
+    0x4770, //             bx lr
+];
+
+/// `ANDS r2, r1`, the operation of a fetch-and.
+const THUMB_ANDS: u16 = 0x400a;
+
+/// The scratch memory the routines are loaded into: the trap and the flag word of the
+/// routine engine, followed by the longest routine.
+const SCRATCH_SIZE: u64 = (8 + 2 * THUMB_COMPARE_AND_SWAP.len() as u64 + 7) & !7;
+
+/// A read-modify-write of a word in memory, which a core performs atomically, see
+/// [`Core::compare_and_swap_32`](crate::Core::compare_and_swap_32).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtomicOperation {
+    /// Write `new` if the word is `expected`.
+    CompareAndSwap {
+        /// The value the word must have.
+        expected: u32,
+        /// The value which is written.
+        new: u32,
+    },
+    /// Set the bits of the value in the word.
+    Or(u32),
+    /// Clear the bits of the word which are clear in the value.
+    And(u32),
+}
+
+impl AtomicOperation {
+    /// The value the operation writes to a word whose value is `current`, or `None` if it
+    /// leaves the word unchanged.
+    pub fn apply(self, current: u32) -> Option<u32> {
+        match self {
+            AtomicOperation::CompareAndSwap { expected, new } if current == expected => Some(new),
+            AtomicOperation::CompareAndSwap { .. } => None,
+            AtomicOperation::Or(bits) => Some(current | bits),
+            AtomicOperation::And(bits) => Some(current & bits),
+        }
+    }
+}
+
+/// Perform `operation` on the word at `address`, and return the previous value of the word.
+pub(super) fn run(
+    core: &mut Core<'_>,
+    address: u64,
+    operation: AtomicOperation,
+) -> Result<u32, Error> {
+    if address % 4 != 0 {
+        return Err(Error::UnalignedAtomic(address));
+    }
+
+    core.require(TargetOperation::WriteMemory)?;
+
+    if core.core_halted()? {
+        return read_modify_write(core, address, operation);
+    }
+
+    // The plain accesses of the core translate the address themselves.
+    let target = core.memory_address(address);
+
+    let exclusives = matches!(
+        core.core_type(),
+        CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m
+    );
+
+    if exclusives || core.core_type() == CoreType::Riscv {
+        // The core is halted and resumed, and its registers are restored.
+        core.require(TargetOperation::WriteRegister)?;
+        core.require(TargetOperation::Resume)?;
+        core.halt(HALT_TIMEOUT)?;
+
+        let previous = if exclusives {
+            run_exclusive_routine(core, target, operation).map(Some)
+        } else {
+            core.inner.atomic_32(target, operation)
+        };
+
+        // The core was running, so it is resumed even if the operation failed.
+        let resumed = core.run();
+        let previous = previous?;
+        resumed?;
+
+        if let Some(previous) = previous {
+            return Ok(previous);
+        }
+    }
+
+    if !core.state.force_non_atomic {
+        return Err(Error::AtomicUnsupported);
+    }
+
+    log::warn!(
+        "Modifying the word at {:#010x} of the running core without atomic instructions",
+        address
+    );
+
+    read_modify_write(core, address, operation)
+}
+
+/// Perform `operation` with a plain read and write of the word at `address`.
+fn read_modify_write(
+    core: &mut Core<'_>,
+    address: u64,
+    operation: AtomicOperation,
+) -> Result<u32, Error> {
+    let previous = core.read_word_32(address)?;
+
+    if let Some(value) = operation.apply(previous) {
+        core.write_word_32(address, value)?;
+    }
+
+    Ok(previous)
+}
+
+/// Perform `operation` with a routine which uses the exclusive accesses of a Cortex-M core.
+///
+/// The routine is loaded at the start of the first RAM region, whose contents are restored
+/// afterwards, like the registers of the halted core. Both are restored even if the routine
+/// fails, e.g. because it faulted.
+fn run_exclusive_routine(
+    core: &mut Core<'_>,
+    address: u64,
+    operation: AtomicOperation,
+) -> Result<u32, Error> {
+    let address = u32::try_from(address).map_err(|_| Error::ValueTooLarge(address))?;
+
+    let (code, arguments) = match operation {
+        AtomicOperation::CompareAndSwap { expected, new } => (
+            THUMB_COMPARE_AND_SWAP.to_vec(),
+            vec![address, expected, new],
+        ),
+        AtomicOperation::Or(bits) => (THUMB_FETCH_OR.to_vec(), vec![address, bits]),
+        AtomicOperation::And(bits) => {
+            let mut code = THUMB_FETCH_OR.to_vec();
+            code[3] = THUMB_ANDS;
+            (code, vec![address, bits])
+        }
+    };
+
+    let start = core
+        .state
+        .ram_ranges
+        .first()
+        .map(|range| range.start)
+        .ok_or(Error::NoScratchMemory)?;
+    let scratch = start..start + SCRATCH_SIZE;
+
+    let mut saved = vec![0; SCRATCH_SIZE as usize];
+    core.read_8(start, &mut saved)?;
+    let snapshot = core.take_snapshot()?;
+
+    let bytes: Vec<u8> = code.iter().flat_map(|half| half.to_le_bytes()).collect();
+    let routine = TargetRoutine::from_bytes(&bytes, 0).stack_size(0);
+    let call = arguments
+        .into_iter()
+        .fold(RoutineCall::new().scratch(scratch), |call, argument| {
+            call.argument(RoutineArgument::Value(argument))
+        });
+
+    let output = core.run_routine(&routine, &call);
+
+    let restored = core.restore_snapshot(&snapshot);
+    let written = core.write_8(start, &saved);
+
+    let output = output?;
+    restored?;
+    written?;
+
+    Ok(output.result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::AtomicOperation;
+
+    #[test]
+    fn operations_are_applied_to_the_current_value() {
+        let swap = AtomicOperation::CompareAndSwap {
+            expected: 1,
+            new: 2,
+        };
+
+        assert_eq!(swap.apply(1), Some(2));
+        assert_eq!(swap.apply(3), None);
+        assert_eq!(AtomicOperation::Or(0x100).apply(1), Some(0x101));
+        assert_eq!(AtomicOperation::And(0xff).apply(0x1234), Some(0x34));
+    }
+}
@@ -0,0 +1,405 @@
+//! Planning and applying sets of breakpoints.
+//!
+//! [`Core::plan_breakpoints`](crate::Core::plan_breakpoints) decides how each breakpoint of a
+//! set will be implemented, without touching the core. [`Core::apply_breakpoints`](crate::Core::apply_breakpoints)
+//! applies such a plan, and removes the breakpoints it set again if any of them fails, so
+//! that either the whole set or none of it is applied.
+//!
+//! A request can have a skip count, see [`BreakpointRequest::skip_count`]. Neither the
+//! breakpoint unit of Cortex-M cores nor the triggers of RISC-V cores can count the hits of
+//! an instruction address, so the hits are skipped by probe-rs while it waits for the core
+//! to halt, with as few probe transactions per hit as possible.
+
+use std::ops::Range;
+
+/// The way a breakpoint is implemented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointMechanism {
+    /// A hardware breakpoint comparator of the core.
+    Hardware {
+        /// The index of the comparator.
+        unit: usize,
+    },
+    /// A breakpoint instruction which replaces the instruction at the address in RAM.
+    Software,
+}
+
+/// Which mechanisms may be used to implement a breakpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointPolicy {
+    /// Only use a hardware breakpoint comparator.
+    HardwareOnly,
+    /// Use a hardware breakpoint comparator if one is left, and a software breakpoint if
+    /// the address is in RAM otherwise.
+    PreferHardware,
+}
+
+/// A breakpoint which should be set by [`Core::apply_breakpoints`](crate::Core::apply_breakpoints).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakpointRequest {
+    /// The address of the breakpoint.
+    pub address: u64,
+    /// The mechanisms which may be used to implement the breakpoint.
+    pub policy: BreakpointPolicy,
+    /// The number of hits after which the breakpoint halts the core, see
+    /// [`BreakpointRequest::skip_count`].
+    pub skip_count: u32,
+}
+
+impl BreakpointRequest {
+    /// A breakpoint at `address`, which falls back to a software breakpoint if no
+    /// hardware breakpoint comparator is left.
+    pub fn new(address: u64) -> Self {
+        Self {
+            address,
+            policy: BreakpointPolicy::PreferHardware,
+            skip_count: 0,
+        }
+    }
+
+    /// A breakpoint at `address`, which only uses a hardware breakpoint comparator.
+    pub fn hardware(address: u64) -> Self {
+        Self {
+            address,
+            policy: BreakpointPolicy::HardwareOnly,
+            skip_count: 0,
+        }
+    }
+
+    /// Skip the first `count` hits of the breakpoint, so that the core only halts at the
+    /// hit after them.
+    ///
+    /// The hits are skipped by [`Core::wait_for_core_halted`](crate::Core::wait_for_core_halted):
+    /// a halt of the core at the address of the breakpoint counts as a hit, the count is
+    /// decremented and the core resumed, without reading any other register or reporting
+    /// the hit. A hit of a hardware breakpoint is skipped with a handful of probe
+    /// transactions. For a hit of a software breakpoint, the original instruction is
+    /// restored for a single step, which takes a lot more.
+    ///
+    /// The remaining count can be read with
+    /// [`Core::breakpoint_skip_count`](crate::Core::breakpoint_skip_count).
+    pub fn skip_count(mut self, count: u32) -> Self {
+        self.skip_count = count;
+        self
+    }
+}
+
+/// The skip count of a breakpoint, see [`BreakpointRequest::skip_count`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BreakpointSkipCount {
+    /// The number of hits which are still skipped.
+    pub remaining: u32,
+    /// The number of hits which were skipped so far.
+    pub skipped: u32,
+}
+
+/// The reason why a breakpoint could not be set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakpointFailure {
+    /// No hardware breakpoint comparator is left, and the policy of the request doesn't
+    /// allow a software breakpoint.
+    NoComparatorLeft,
+    /// No hardware breakpoint comparator is left, and the address is not in RAM, so no
+    /// software breakpoint can be set either.
+    ///
+    /// Breakpoints in flash are not supported.
+    NotInRam,
+    /// No hardware breakpoint comparator is left, and the maximum intrusiveness of the
+    /// session doesn't allow software breakpoints, see
+    /// [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness).
+    SoftwareNotAllowed,
+    /// Setting the breakpoint failed with an error.
+    Error(String),
+    /// The breakpoint was not set, or was removed again, because another breakpoint of the
+    /// same call could not be set.
+    Aborted,
+}
+
+/// How a breakpoint of a [`BreakpointPlan`] will be set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlannedBreakpoint {
+    /// The breakpoint will be set with `mechanism`.
+    Set {
+        /// The mechanism which will be used.
+        mechanism: BreakpointMechanism,
+    },
+    /// A breakpoint is already set at the address, and is left as it is.
+    Existing {
+        /// The mechanism of the existing breakpoint.
+        mechanism: BreakpointMechanism,
+    },
+    /// An earlier request of the plan has the same address.
+    Duplicate {
+        /// The index of the earlier request.
+        duplicate_of: usize,
+    },
+    /// The breakpoint can't be set.
+    Unsatisfiable {
+        /// The reason why the breakpoint can't be set.
+        reason: BreakpointFailure,
+    },
+}
+
+/// The result of [`Core::plan_breakpoints`](crate::Core::plan_breakpoints), with one entry per request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointPlan {
+    pub(crate) requests: Vec<BreakpointRequest>,
+    pub(crate) breakpoints: Vec<PlannedBreakpoint>,
+}
+
+impl BreakpointPlan {
+    /// Returns how each request will be set, in the order of the requests.
+    pub fn breakpoints(&self) -> &[PlannedBreakpoint] {
+        &self.breakpoints
+    }
+
+    /// Returns `true` if all requests can be set, i.e. applying the plan will only fail
+    /// if accessing the core fails.
+    pub fn is_satisfiable(&self) -> bool {
+        !self
+            .breakpoints
+            .iter()
+            .any(|breakpoint| matches!(breakpoint, PlannedBreakpoint::Unsatisfiable { .. }))
+    }
+
+    /// Returns the number of breakpoints which will be newly set as software breakpoints.
+    pub fn software_breakpoints(&self) -> usize {
+        self.breakpoints
+            .iter()
+            .filter(|breakpoint| {
+                matches!(
+                    breakpoint,
+                    PlannedBreakpoint::Set {
+                        mechanism: BreakpointMechanism::Software
+                    }
+                )
+            })
+            .count()
+    }
+}
+
+/// The outcome of a single request of [`Core::apply_breakpoints`](crate::Core::apply_breakpoints).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakpointOutcome {
+    /// The breakpoint is set, either by this call or already before it.
+    Applied {
+        /// The mechanism of the breakpoint.
+        mechanism: BreakpointMechanism,
+    },
+    /// The breakpoint is not set.
+    Failed {
+        /// The reason why the breakpoint is not set.
+        reason: BreakpointFailure,
+    },
+    /// The breakpoint was skipped, because an earlier request has the same address.
+    Skipped {
+        /// The index of the earlier request.
+        duplicate_of: usize,
+    },
+}
+
+/// The result of [`Core::apply_breakpoints`](crate::Core::apply_breakpoints), with one outcome per request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointApplyReport {
+    /// The outcome of each request, in the order of the requests.
+    pub outcomes: Vec<BreakpointOutcome>,
+}
+
+impl BreakpointApplyReport {
+    /// Returns `true` if all requests were applied, or skipped as duplicates.
+    pub fn is_complete(&self) -> bool {
+        !self
+            .outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, BreakpointOutcome::Failed { .. }))
+    }
+}
+
+/// Decide how each of the `requests` is set, given the current contents of the hardware
+/// breakpoint `comparators`, the addresses of the software breakpoints which are set, and
+/// the RAM the core can access. If `software_allowed` is false, no new software breakpoints
+/// are planned.
+///
+/// Requests which can only be set with a comparator get one first, so that a request
+/// which could fall back to a software breakpoint doesn't take the comparator it needs.
+pub(crate) fn plan(
+    requests: &[BreakpointRequest],
+    comparators: &[Option<u64>],
+    sw_breakpoints: &[u64],
+    ram: &[Range<u64>],
+    software_allowed: bool,
+) -> BreakpointPlan {
+    let in_ram = |address: u64| ram.iter().any(|range| range.contains(&address));
+
+    let mut breakpoints: Vec<Option<PlannedBreakpoint>> = vec![None; requests.len()];
+
+    for (index, request) in requests.iter().enumerate() {
+        if let Some(duplicate_of) = requests[..index]
+            .iter()
+            .position(|earlier| earlier.address == request.address)
+        {
+            breakpoints[index] = Some(PlannedBreakpoint::Duplicate { duplicate_of });
+        } else if let Some(unit) = comparators
+            .iter()
+            .position(|&comparator| comparator == Some(request.address))
+        {
+            breakpoints[index] = Some(PlannedBreakpoint::Existing {
+                mechanism: BreakpointMechanism::Hardware { unit },
+            });
+        } else if sw_breakpoints.contains(&request.address) {
+            breakpoints[index] = Some(PlannedBreakpoint::Existing {
+                mechanism: BreakpointMechanism::Software,
+            });
+        }
+    }
+
+    let mut free_units = comparators
+        .iter()
+        .enumerate()
+        .filter(|(_, comparator)| comparator.is_none())
+        .map(|(unit, _)| unit);
+
+    let needs_hardware = |request: &BreakpointRequest| {
+        request.policy == BreakpointPolicy::HardwareOnly
+            || !in_ram(request.address)
+            || !software_allowed
+    };
+
+    // First assign the comparators to the requests which can't use a software breakpoint,
+    // then to the others.
+    for hardware_only in [true, false] {
+        for (index, request) in requests.iter().enumerate() {
+            if breakpoints[index].is_some() || needs_hardware(request) != hardware_only {
+                continue;
+            }
+
+            breakpoints[index] = match free_units.next() {
+                Some(unit) => Some(PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Hardware { unit },
+                }),
+                None if hardware_only => Some(PlannedBreakpoint::Unsatisfiable {
+                    reason: if request.policy == BreakpointPolicy::HardwareOnly {
+                        BreakpointFailure::NoComparatorLeft
+                    } else if !in_ram(request.address) {
+                        BreakpointFailure::NotInRam
+                    } else {
+                        BreakpointFailure::SoftwareNotAllowed
+                    },
+                }),
+                None => Some(PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Software,
+                }),
+            };
+        }
+    }
+
+    BreakpointPlan {
+        requests: requests.to_vec(),
+        breakpoints: breakpoints.into_iter().flatten().collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RAM: Range<u64> = 0x2000_0000..0x2001_0000;
+
+    #[test]
+    fn comparators_are_assigned_to_hardware_only_requests_first() {
+        let requests = [
+            BreakpointRequest::new(0x2000_0100),
+            BreakpointRequest::new(0x0800_0100),
+            BreakpointRequest::hardware(0x2000_0200),
+            BreakpointRequest::new(0x0800_0200),
+        ];
+
+        let plan = plan(&requests, &[None, Some(0x0800_0000)], &[], &[RAM], true);
+
+        assert_eq!(
+            plan.breakpoints(),
+            [
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Software
+                },
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Hardware { unit: 0 }
+                },
+                PlannedBreakpoint::Unsatisfiable {
+                    reason: BreakpointFailure::NoComparatorLeft
+                },
+                PlannedBreakpoint::Unsatisfiable {
+                    reason: BreakpointFailure::NotInRam
+                },
+            ]
+        );
+        assert!(!plan.is_satisfiable());
+        assert_eq!(plan.software_breakpoints(), 1);
+    }
+
+    #[test]
+    fn existing_and_duplicate_breakpoints_are_detected() {
+        let requests = [
+            BreakpointRequest::new(0x0800_0000),
+            BreakpointRequest::new(0x2000_0000),
+            BreakpointRequest::new(0x0800_0000),
+            BreakpointRequest::hardware(0x0800_0100),
+        ];
+
+        let plan = plan(
+            &requests,
+            &[Some(0x0800_0000), None],
+            &[0x2000_0000],
+            &[RAM],
+            true,
+        );
+
+        assert_eq!(
+            plan.breakpoints(),
+            [
+                PlannedBreakpoint::Existing {
+                    mechanism: BreakpointMechanism::Hardware { unit: 0 }
+                },
+                PlannedBreakpoint::Existing {
+                    mechanism: BreakpointMechanism::Software
+                },
+                PlannedBreakpoint::Duplicate { duplicate_of: 0 },
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Hardware { unit: 1 }
+                },
+            ]
+        );
+        assert!(plan.is_satisfiable());
+    }
+
+    #[test]
+    fn software_breakpoints_can_be_disallowed() {
+        let requests = [
+            BreakpointRequest::new(0x2000_0100),
+            BreakpointRequest::new(0x2000_0200),
+        ];
+
+        let with_comparator = plan(&requests, &[None], &[0x2000_0200], &[RAM], false);
+
+        assert_eq!(
+            with_comparator.breakpoints(),
+            [
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Hardware { unit: 0 }
+                },
+                PlannedBreakpoint::Existing {
+                    mechanism: BreakpointMechanism::Software
+                },
+            ]
+        );
+
+        let without_comparator = plan(&requests[..1], &[Some(0x0800_0000)], &[], &[RAM], false);
+
+        assert_eq!(
+            without_comparator.breakpoints(),
+            [PlannedBreakpoint::Unsatisfiable {
+                reason: BreakpointFailure::SoftwareNotAllowed
+            }]
+        );
+    }
+}
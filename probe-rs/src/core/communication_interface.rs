@@ -1,6 +1,6 @@
 use crate::{
     architecture::arm::{communication_interface::Initialized, ArmCommunicationInterface},
-    DebugProbeError, Error,
+    DebugProbeError, Error, VendorCommand, VendorCommandIndex,
 };
 
 /// A helper trait to get more specific interfaces.
@@ -8,6 +8,27 @@ pub trait CommunicationInterface {
     /// Flush all remaining commands if the target driver implements batching.
     fn flush(&mut self) -> Result<(), DebugProbeError>;
 
+    /// Queue `command` behind the commands issued before it, see
+    /// [`Core::enqueue_probe_command`](crate::Core::enqueue_probe_command).
+    fn enqueue_vendor_command(
+        &mut self,
+        _command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        ))
+    }
+
+    /// Flush the queued commands, and return the response of the vendor command at `index`.
+    fn vendor_command_response(
+        &mut self,
+        _index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        Err(DebugProbeError::CommandNotSupportedByProbe(
+            "vendor commands",
+        ))
+    }
+
     /// Tries to get the underlying [`ArmCommunicationInterface`].
     fn get_arm_communication_interface(
         &mut self,
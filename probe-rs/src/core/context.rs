@@ -0,0 +1,309 @@
+//! Saving the context of a core and restoring it later, see [`Core::save_context`].
+//!
+//! [`Core::save_context`]: crate::Core::save_context
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{CoreType, Error, RegisterId, RegisterValue};
+
+/// The value of a register in a [`ContextSnapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedRegister {
+    /// The register.
+    pub id: u16,
+    /// The size of the register in bits.
+    pub size_in_bits: u16,
+    /// The value of the register.
+    pub value: u64,
+}
+
+impl SavedRegister {
+    /// The value, in the width the core expects for the register.
+    pub(crate) fn register_value(&self) -> RegisterValue {
+        if self.size_in_bits <= 32 {
+            RegisterValue::U32(self.value as u32)
+        } else {
+            RegisterValue::U64(self.value)
+        }
+    }
+}
+
+/// A range of memory in a [`ContextSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedMemory {
+    /// The start address of the range.
+    pub address: u64,
+    /// The contents of the range.
+    pub data: Vec<u8>,
+}
+
+/// The registers of a core, and optionally some ranges of its memory, taken by
+/// [`Core::save_context`] to be restored later with [`Core::restore_context`].
+///
+/// A snapshot can be serialized, e.g. to restore it in a later invocation of a tool. It
+/// contains a SHA-256 checksum of its contents, which is verified before it is restored.
+///
+/// [`Core::save_context`]: crate::Core::save_context
+/// [`Core::restore_context`]: crate::Core::restore_context
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextSnapshot {
+    core_type: CoreType,
+    registers: Vec<SavedRegister>,
+    memory: Vec<SavedMemory>,
+    checksum: [u8; 32],
+}
+
+impl ContextSnapshot {
+    pub(crate) fn new(
+        core_type: CoreType,
+        registers: Vec<SavedRegister>,
+        memory: Vec<SavedMemory>,
+    ) -> Self {
+        let checksum = checksum(core_type, &registers, &memory);
+
+        Self {
+            core_type,
+            registers,
+            memory,
+            checksum,
+        }
+    }
+
+    /// The type of the core the snapshot was taken on.
+    pub fn core_type(&self) -> CoreType {
+        self.core_type
+    }
+
+    /// The saved registers, in the order in which they are restored.
+    pub fn registers(&self) -> &[SavedRegister] {
+        &self.registers
+    }
+
+    /// The saved ranges of memory.
+    pub fn memory(&self) -> &[SavedMemory] {
+        &self.memory
+    }
+
+    /// Returns [`Error::ContextSnapshotCorrupted`] if the checksum doesn't match the contents
+    /// of the snapshot.
+    pub fn verify(&self) -> Result<(), Error> {
+        if checksum(self.core_type, &self.registers, &self.memory) == self.checksum {
+            Ok(())
+        } else {
+            Err(Error::ContextSnapshotCorrupted)
+        }
+    }
+}
+
+fn checksum(core_type: CoreType, registers: &[SavedRegister], memory: &[SavedMemory]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    hasher.update(format!("{:?}", core_type).as_bytes());
+
+    hasher.update((registers.len() as u64).to_le_bytes());
+    for register in registers {
+        hasher.update(register.id.to_le_bytes());
+        hasher.update(register.size_in_bits.to_le_bytes());
+        hasher.update(register.value.to_le_bytes());
+    }
+
+    hasher.update((memory.len() as u64).to_le_bytes());
+    for range in memory {
+        hasher.update(range.address.to_le_bytes());
+        hasher.update((range.data.len() as u64).to_le_bytes());
+        hasher.update(&range.data);
+    }
+
+    hasher.finalize().into()
+}
+
+/// The registers of a halted core, taken by [`Core::take_snapshot`] to be restored with
+/// [`Core::restore_snapshot`], e.g. around a call of a function in the target which uses
+/// the registers as scratch registers.
+///
+/// Unlike a [`ContextSnapshot`], it only lives in memory, and holds the values as they were
+/// read, without a checksum and without the special registers of the core.
+///
+/// [`Core::take_snapshot`]: crate::Core::take_snapshot
+/// [`Core::restore_snapshot`]: crate::Core::restore_snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoreSnapshot {
+    /// The registers of [`RegisterFile::registers`](crate::RegisterFile::registers) which
+    /// exist on the core.
+    pub registers: Vec<(RegisterId, RegisterValue)>,
+    /// The floating-point status register and the floating-point registers, if the core has
+    /// an FPU.
+    pub fpu_registers: Option<Vec<(RegisterId, RegisterValue)>>,
+    /// The program counter when the snapshot was taken.
+    pub pc: RegisterValue,
+}
+
+/// Why a register wasn't restored by [`Core::restore_context`](crate::Core::restore_context).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreFailure {
+    /// Writing the register failed.
+    WriteFailed(String),
+    /// The register was written, but reading it back to verify it failed.
+    ReadBackFailed(String),
+    /// The register reads back differently, but only in bits which can't be written, see
+    /// [`RegisterDescription::writable_mask`](crate::RegisterDescription::writable_mask).
+    ///
+    /// This is expected for some status registers, e.g. if the core took an exception
+    /// since the snapshot was taken.
+    ReadOnlyBitsDiffer {
+        /// The value which was read back.
+        actual: u64,
+    },
+    /// The register reads back differently in bits which can be written.
+    Mismatch {
+        /// The value which was read back.
+        actual: u64,
+    },
+}
+
+impl RestoreFailure {
+    /// Compare the `actual` value of a register with the `expected` one, considering only
+    /// the lowest `size_in_bits` bits.
+    pub(crate) fn compare(
+        expected: u64,
+        actual: u64,
+        size_in_bits: u16,
+        writable_mask: u64,
+    ) -> Option<Self> {
+        let size_mask = match size_in_bits {
+            0..=63 => (1 << size_in_bits) - 1,
+            _ => u64::MAX,
+        };
+
+        let differing = (expected ^ actual) & size_mask;
+
+        if differing == 0 {
+            None
+        } else if differing & writable_mask == 0 {
+            Some(RestoreFailure::ReadOnlyBitsDiffer { actual })
+        } else {
+            Some(RestoreFailure::Mismatch { actual })
+        }
+    }
+}
+
+/// A register which wasn't restored by [`Core::restore_context`](crate::Core::restore_context).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterRestoreFailure {
+    /// The register.
+    pub id: RegisterId,
+    /// The saved value of the register.
+    pub expected: u64,
+    /// Why the register wasn't restored.
+    pub failure: RestoreFailure,
+}
+
+/// The result of [`Core::restore_context`](crate::Core::restore_context).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ContextRestoreReport {
+    /// The registers which weren't restored, in the order in which they were restored.
+    pub registers: Vec<RegisterRestoreFailure>,
+    /// The start addresses of the saved ranges of memory which read back differently.
+    pub memory: Vec<u64>,
+}
+
+impl ContextRestoreReport {
+    /// Returns true if all registers and all ranges of memory were restored.
+    ///
+    /// Registers which only differ in read-only bits count as restored.
+    pub fn is_complete(&self) -> bool {
+        self.memory.is_empty()
+            && self.registers.iter().all(|register| {
+                matches!(register.failure, RestoreFailure::ReadOnlyBitsDiffer { .. })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snapshot() -> ContextSnapshot {
+        ContextSnapshot::new(
+            CoreType::Armv7em,
+            vec![
+                SavedRegister {
+                    id: 0,
+                    size_in_bits: 32,
+                    value: 0x1234_5678,
+                },
+                SavedRegister {
+                    id: 15,
+                    size_in_bits: 32,
+                    value: 0x0800_0100,
+                },
+            ],
+            vec![SavedMemory {
+                address: 0x2000_0000,
+                data: vec![1, 2, 3, 4],
+            }],
+        )
+    }
+
+    #[test]
+    fn checksum_detects_changes() {
+        let snapshot = snapshot();
+        assert!(snapshot.verify().is_ok());
+
+        let mut changed = snapshot.clone();
+        changed.registers[1].value += 2;
+        assert!(matches!(
+            changed.verify(),
+            Err(Error::ContextSnapshotCorrupted)
+        ));
+
+        let mut changed = snapshot.clone();
+        changed.memory[0].data[3] = 0;
+        assert!(changed.verify().is_err());
+
+        let mut changed = snapshot;
+        changed.core_type = CoreType::Armv6m;
+        assert!(changed.verify().is_err());
+    }
+
+    #[test]
+    fn snapshot_survives_serialization() {
+        let snapshot = snapshot();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let deserialized: ContextSnapshot = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized, snapshot);
+        assert!(deserialized.verify().is_ok());
+    }
+
+    #[test]
+    fn read_only_bits_are_distinguished() {
+        // The exception number of the xPSR is read-only.
+        let xpsr_mask = 0xffff_fe00;
+
+        assert_eq!(
+            RestoreFailure::compare(0x0100_0000, 0x0100_0000, 32, xpsr_mask),
+            None
+        );
+        assert_eq!(
+            RestoreFailure::compare(0x0100_0000, 0x0100_0003, 32, xpsr_mask),
+            Some(RestoreFailure::ReadOnlyBitsDiffer {
+                actual: 0x0100_0003
+            })
+        );
+        assert_eq!(
+            RestoreFailure::compare(0x0100_0000, 0x2100_0003, 32, xpsr_mask),
+            Some(RestoreFailure::Mismatch {
+                actual: 0x2100_0003
+            })
+        );
+
+        // Bits above the size of the register are ignored.
+        assert_eq!(
+            RestoreFailure::compare(0x1, 0xffff_ffff_0000_0001, 32, u64::MAX),
+            None
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Halting cores which ignore normal halt requests, see [`Core::force_halt`](crate::Core::force_halt).
+
+use crate::{Error, TargetOperation};
+
+/// A mechanism to halt a core, from the least to the most destructive one.
+///
+/// Levels are ordered, so that the highest level a caller allows can be compared with `<=`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HaltEscalation {
+    /// Level 1: a normal halt request, as sent by [`Core::halt`](crate::Core::halt).
+    HaltRequest = 1,
+    /// Level 2: a halt request which is repeated until the core halts.
+    ///
+    /// On Cortex-M, `C_DEBUGEN` and `C_HALT` are written to DHCSR over and over. On RISC-V,
+    /// `haltreq` stays set while the hart is polled.
+    PersistentHaltRequest = 2,
+    /// Level 3: a reset of the core, which halts it before it executes the first instruction.
+    ///
+    /// On Cortex-M, the reset vector catch of DEMCR is set, and the core is reset with
+    /// `VECTRESET` on ARMv7-M, or with a system reset on cores without a local reset. On
+    /// RISC-V, `ndmreset` is pulsed with `resethaltreq` and `haltreq` set.
+    CoreReset = 3,
+    /// Level 4: a reset through the reset pin of the probe, which halts the core after the
+    /// reset. Only available if the probe can set its pins, see
+    /// [`Session::force_halt_core`](crate::Session::force_halt_core).
+    HardwareReset = 4,
+}
+
+impl HaltEscalation {
+    /// All levels, in the order in which they are tried.
+    pub const ALL: [HaltEscalation; 4] = [
+        HaltEscalation::HaltRequest,
+        HaltEscalation::PersistentHaltRequest,
+        HaltEscalation::CoreReset,
+        HaltEscalation::HardwareReset,
+    ];
+
+    /// The number of the level, from 1 to 4.
+    pub fn level(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Whether the registers of the core keep the values they had while the core was running.
+    ///
+    /// This is only the case for the levels which don't reset the core.
+    pub fn preserves_registers(&self) -> bool {
+        *self <= HaltEscalation::PersistentHaltRequest
+    }
+
+    /// The operation which is checked against the maximum intrusiveness of the session.
+    pub(crate) fn operation(&self) -> TargetOperation {
+        if self.preserves_registers() {
+            TargetOperation::Halt
+        } else {
+            TargetOperation::Reset
+        }
+    }
+}
+
+/// What happened when a core was halted with one [`HaltEscalation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltAttemptOutcome {
+    /// The core halted.
+    Halted,
+    /// The core was still running when the timeout elapsed.
+    TimedOut,
+    /// The core or the probe doesn't support the level.
+    Unsupported,
+    /// The level failed with an error, e.g. because the core stopped responding.
+    Failed(String),
+}
+
+/// A level of [`HaltEscalation`] which was tried, with its outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HaltAttempt {
+    /// The level which was tried.
+    pub level: HaltEscalation,
+    /// What happened.
+    pub outcome: HaltAttemptOutcome,
+}
+
+impl HaltAttempt {
+    /// The attempt of `level`, from the result of a mechanism which returns whether it is
+    /// supported.
+    pub(crate) fn from_result(level: HaltEscalation, result: Result<bool, Error>) -> Self {
+        let outcome = match result {
+            Ok(true) => HaltAttemptOutcome::Halted,
+            Ok(false) => HaltAttemptOutcome::Unsupported,
+            Err(error) if super::is_timeout(&error) => HaltAttemptOutcome::TimedOut,
+            Err(error) => HaltAttemptOutcome::Failed(error.to_string()),
+        };
+
+        Self { level, outcome }
+    }
+}
+
+/// The result of [`Core::force_halt`](crate::Core::force_halt).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForceHaltReport {
+    /// The level which halted the core.
+    pub level: HaltEscalation,
+    /// All levels which were tried, in order, the last one being `level`.
+    pub attempts: Vec<HaltAttempt>,
+    /// The program counter after the core halted.
+    pub pc: u64,
+    /// Whether the registers still have the values of the running firmware. If not, the core
+    /// was reset, and is halted at its reset vector.
+    pub registers_preserved: bool,
+}
@@ -0,0 +1,57 @@
+//! A coherent snapshot of the floating-point state of a core, see [`Core::read_all_fpu_state`].
+//!
+//! [`Core::read_all_fpu_state`]: crate::Core::read_all_fpu_state
+
+use crate::{RegisterId, RegisterValue};
+
+/// Where the value of a register in an [`FpuState`] was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpuValueSource {
+    /// The value was read from the register.
+    Register,
+    /// The value was read from the floating-point part of the frame of the active exception,
+    /// at `address`, where the core stacked the value of the interrupted context.
+    ///
+    /// The register itself can hold a value of the exception handler.
+    ExceptionFrame {
+        /// The address of the stacked value.
+        address: u64,
+    },
+    /// The value was read from the register, which still holds the value of the interrupted
+    /// context.
+    ///
+    /// Lazy state preservation is pending: the space at `address` in the frame of the active
+    /// exception is reserved for the value, but the core only writes it there once the
+    /// exception handler uses the FPU, so the memory doesn't hold the value yet.
+    PendingLazyState {
+        /// The address reserved for the value.
+        address: u64,
+    },
+}
+
+/// The value of a floating-point register in an [`FpuState`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FpuValue {
+    /// The register.
+    pub id: RegisterId,
+    /// The value of the register.
+    pub value: RegisterValue,
+    /// Where the value was read from.
+    pub source: FpuValueSource,
+}
+
+impl FpuValue {
+    pub(crate) fn new(id: RegisterId, value: RegisterValue, source: FpuValueSource) -> Self {
+        Self { id, value, source }
+    }
+}
+
+/// The floating-point registers of a core, read together, see
+/// [`Core::read_all_fpu_state`](crate::Core::read_all_fpu_state).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FpuState {
+    /// The status and control register, FPSCR on a Cortex-M core, or `fcsr` on a RISC-V hart.
+    pub status: FpuValue,
+    /// The data registers in order, S0-S31 on a Cortex-M core, or f0-f31 on a RISC-V hart.
+    pub registers: Vec<FpuValue>,
+}
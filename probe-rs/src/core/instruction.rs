@@ -0,0 +1,202 @@
+//! Reading a single instruction from the memory of a core, see [`Core::read_instruction`].
+//!
+//! [`Core::read_instruction`]: crate::Core::read_instruction
+
+use crate::{Error, InstructionSet};
+
+/// The result of [`Core::read_instruction`](crate::Core::read_instruction).
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionFetch {
+    /// The instruction was read.
+    Read {
+        /// The address of the instruction.
+        address: u64,
+        /// The instruction set the instruction was read for.
+        instruction_set: InstructionSet,
+        /// The encoding of the instruction, in the order in which it is stored in memory.
+        ///
+        /// Its length depends on the instruction, e.g. 2 or 4 bytes for Thumb-2.
+        bytes: Vec<u8>,
+    },
+    /// The instruction couldn't be read, e.g. because a prefetch abort was caused by an
+    /// address which isn't mapped.
+    Unreadable {
+        /// The address of the instruction.
+        address: u64,
+        /// Why the instruction couldn't be read.
+        reason: String,
+    },
+}
+
+impl InstructionFetch {
+    /// The address of the instruction.
+    pub fn address(&self) -> u64 {
+        match self {
+            InstructionFetch::Read { address, .. }
+            | InstructionFetch::Unreadable { address, .. } => *address,
+        }
+    }
+
+    /// The encoding of the instruction, if it could be read.
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            InstructionFetch::Read { bytes, .. } => Some(bytes),
+            InstructionFetch::Unreadable { .. } => None,
+        }
+    }
+
+    /// Turns a failed read of the instruction at `address` into [`InstructionFetch::Unreadable`].
+    ///
+    /// An operation which isn't allowed in the session is still an error, because the
+    /// instruction wasn't even tried to be read.
+    pub(crate) fn unreadable(address: u64, error: Error) -> Result<Self, Error> {
+        match error {
+            Error::IntrusivenessExceeded { .. } => Err(error),
+            error => Ok(InstructionFetch::Unreadable {
+                address,
+                reason: error.to_string(),
+            }),
+        }
+    }
+}
+
+/// The length in bytes of an instruction, determined from its first halfword.
+pub(crate) fn instruction_length(instruction_set: InstructionSet, first_halfword: u16) -> usize {
+    match instruction_set {
+        // 32-bit Thumb instructions start with 0b11101, 0b11110 or 0b11111.
+        InstructionSet::Thumb2 => match first_halfword >> 11 {
+            0b11101 | 0b11110 | 0b11111 => 4,
+            _ => 2,
+        },
+        InstructionSet::A32 | InstructionSet::A64 => 4,
+        // See the section "Base Instruction-Length Encoding" of the RISC-V ISA specification.
+        InstructionSet::RV32 => {
+            if first_halfword & 0b11 != 0b11 {
+                2
+            } else if first_halfword & 0b1_1100 != 0b1_1100 {
+                4
+            } else if first_halfword & 0b11_1111 == 0b01_1111 {
+                6
+            } else if first_halfword & 0b111_1111 == 0b011_1111 {
+                8
+            } else {
+                // Longer encodings are reserved, only the first word is read.
+                4
+            }
+        }
+    }
+}
+
+/// The breakpoint instruction which replaces the instruction with the first halfword
+/// `first_halfword`, in the order in which it is stored in memory.
+///
+/// On RISC-V, a compressed instruction is replaced by `C.EBREAK`, so that the instruction
+/// which follows it isn't overwritten, and all other instructions by `EBREAK`. A Thumb
+/// `BKPT` is 16 bits wide, and halts the core before a 32-bit instruction it replaces the
+/// first half of.
+pub(crate) fn breakpoint_instruction(
+    instruction_set: InstructionSet,
+    first_halfword: u16,
+) -> &'static [u8] {
+    match instruction_set {
+        // BKPT #0
+        InstructionSet::Thumb2 => &[0x00, 0xbe],
+        // BKPT #0
+        InstructionSet::A32 => &[0x70, 0x00, 0x20, 0xe1],
+        // BRK #0
+        InstructionSet::A64 => &[0x00, 0x00, 0x20, 0xd4],
+        InstructionSet::RV32 if instruction_length(instruction_set, first_halfword) == 2 => {
+            // C.EBREAK
+            &[0x02, 0x90]
+        }
+        // EBREAK
+        InstructionSet::RV32 => &[0x73, 0x00, 0x10, 0x00],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thumb_instructions_are_split_by_their_first_halfword() {
+        // str r1, [r0, #4]
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0x6041), 2);
+        // bkpt
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0xbe00), 2);
+        // b.n, which starts with 0b11100
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0xe7fe), 2);
+        // str.w r1, [r0, #4]
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0xf8c0), 4);
+        // bl
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0xf000), 4);
+        // ldrd
+        assert_eq!(instruction_length(InstructionSet::Thumb2, 0xe9d0), 4);
+    }
+
+    #[test]
+    fn compressed_risc_v_instructions_are_detected() {
+        // c.sw a1, 4(a0)
+        assert_eq!(instruction_length(InstructionSet::RV32, 0xc14c), 2);
+        // sw a1, 4(a0)
+        assert_eq!(instruction_length(InstructionSet::RV32, 0x2223), 4);
+        assert_eq!(instruction_length(InstructionSet::RV32, 0x001f), 6);
+        assert_eq!(instruction_length(InstructionSet::RV32, 0x003f), 8);
+        assert_eq!(instruction_length(InstructionSet::A32, 0x0000), 4);
+    }
+
+    #[test]
+    fn only_intrusiveness_errors_are_propagated() {
+        assert_eq!(
+            InstructionFetch::unreadable(0x4, Error::Other(anyhow::anyhow!("fault"))).unwrap(),
+            InstructionFetch::Unreadable {
+                address: 0x4,
+                reason: "fault".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn breakpoint_on_a_32_bit_instruction_in_compressed_code_is_32_bit() {
+        let code: [u16; 6] = [
+            0x4505, // c.li a0, 1
+            0x0505, // c.addi a0, 1
+            0x55b7, 0x1234, // lui a1, 0x12345
+            0x952e, // c.add a0, a1
+            0x8082, // c.ret
+        ];
+
+        let mut widths = Vec::new();
+        let mut offset = 0;
+        while offset < code.len() {
+            let breakpoint = breakpoint_instruction(InstructionSet::RV32, code[offset]);
+            assert_eq!(
+                breakpoint.len(),
+                instruction_length(InstructionSet::RV32, code[offset])
+            );
+
+            widths.push(breakpoint.len());
+            offset += breakpoint.len() / 2;
+        }
+
+        assert_eq!(widths, [2, 2, 4, 2, 2]);
+        assert_eq!(
+            breakpoint_instruction(InstructionSet::RV32, code[2]),
+            [0x73, 0x00, 0x10, 0x00]
+        );
+        assert_eq!(
+            breakpoint_instruction(InstructionSet::RV32, code[0]),
+            [0x02, 0x90]
+        );
+    }
+
+    #[test]
+    fn thumb_breakpoint_is_16_bit() {
+        // str.w r1, [r0, #4]
+        assert_eq!(
+            breakpoint_instruction(InstructionSet::Thumb2, 0xf8c0),
+            [0x00, 0xbe]
+        );
+        assert_eq!(breakpoint_instruction(InstructionSet::A32, 0x0000).len(), 4);
+    }
+}
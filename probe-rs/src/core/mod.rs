@@ -1,19 +1,96 @@
+mod address_map;
+mod atomic;
+mod breakpoints;
 pub(crate) mod communication_interface;
-
-use crate::{CoreType, InstructionSet};
+mod context;
+mod force_halt;
+mod fpu_state;
+mod instruction;
+mod poison;
+pub(crate) mod routine;
+mod search;
+mod watchpoints;
+
+use crate::{CoreCapabilities, CoreType, FpuSupport, InstructionSet};
+pub use address_map::{AddressMap, AddressMapping};
+pub use atomic::AtomicOperation;
+pub use breakpoints::{
+    BreakpointApplyReport, BreakpointFailure, BreakpointMechanism, BreakpointOutcome,
+    BreakpointPlan, BreakpointPolicy, BreakpointRequest, BreakpointSkipCount, PlannedBreakpoint,
+};
 pub use communication_interface::CommunicationInterface;
+pub use context::{
+    ContextRestoreReport, ContextSnapshot, CoreSnapshot, RegisterRestoreFailure, RestoreFailure,
+    SavedMemory, SavedRegister,
+};
+pub use force_halt::{ForceHaltReport, HaltAttempt, HaltAttemptOutcome, HaltEscalation};
+pub use fpu_state::{FpuState, FpuValue, FpuValueSource};
+pub use instruction::InstructionFetch;
+pub use poison::{PoisonResetPolicy, PoisonViolation};
 pub use probe_rs_target::{Architecture, CoreAccessOptions};
+pub use routine::{RoutineArgument, RoutineCall, RoutineCompletion, RoutineOutput, TargetRoutine};
+pub use search::{MemorySearchIter, SearchOptions};
+pub(crate) use watchpoints::consecutive_units;
+pub use watchpoints::{
+    WatchKind, Watchpoint, WatchpointConfig, WatchpointKind, WatchpointQualifier,
+};
 
 use crate::architecture::{
-    arm::core::CortexAState, arm::core::CortexMState,
-    riscv::communication_interface::RiscvCommunicationInterface,
+    arm::core::CortexAState,
+    arm::core::CortexMState,
+    arm::{
+        AddressHits, CacheMaintenance, ExceptionFrame, HitCountReport, MemManageFault, MpuRegion,
+        SystemControlSnapshot,
+    },
+    riscv::communication_interface::{RiscvCommunicationInterface, RiscvError},
+    riscv::RISCV_REGISTERS,
 };
+use crate::drain::Drains;
+use crate::errata::CoreErrata;
 use crate::error;
+use crate::freeze::PeripheralFreezes;
+use crate::memory::{
+    ecc, AccessDirection, EccMemory, Endianness, FromTargetBytes, MediatedRegions, Mediation,
+    PartialRead, RetryPolicy, VolatileRanges, WriteWidths,
+};
+use crate::operation_journal::{JournalOperation, JournalRecorder, JournalValue, JournalWidth};
+use crate::panic_hooks::PANIC_BREAKPOINT_GROUP;
+use crate::transfer::{TransferDirection, Transfers};
 use crate::Target;
-use crate::{Error, Memory, MemoryInterface};
-use anyhow::{anyhow, Result};
+use crate::{
+    Deadline, DebugProbeError, Error, HealthEvent, HealthLog, InterruptHandle, Intrusiveness,
+    Memory, MemoryInterface, TargetOperation, VendorCommand, VendorCommandIndex,
+};
+use poison::PoisonList;
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::ops::Range;
 use std::time::Duration;
 
+/// The Thumb state bit of the Cortex-M EPSR.
+const XPSR_THUMB: u32 = 1 << 24;
+
+/// The SPSEL bit of the Cortex-M CONTROL register, in the combined CONTROL/FAULTMASK/BASEPRI/PRIMASK register.
+const CONTROL_SPSEL: u32 = 1 << 25;
+
+/// The CPSR of ARMv7-A and ARMv8-A cores in AArch32 state.
+const CPSR: RegisterId = RegisterId(16);
+
+/// The Thumb state bit of the CPSR.
+const CPSR_THUMB: u32 = 1 << 5;
+
+/// The RISC-V `misa` CSR.
+const MISA: u16 = 0x301;
+
+/// The compressed instructions extension bit of `misa`.
+const MISA_C: u32 = 1 << 2;
+
+/// The RISC-V `dcsr` CSR.
+const DCSR: u16 = 0x7b0;
+
+/// The `prv` field of `dcsr`, set to machine mode.
+const DCSR_PRV_MACHINE: u32 = 0b11;
+
 /// A memory mapped register, for instance ARM debug registers (DHCSR, etc).
 pub trait MemoryMappedRegister: Clone + From<u32> + Into<u32> + Sized + std::fmt::Debug {
     /// The register's address in the target memory.
@@ -27,6 +104,91 @@ pub trait MemoryMappedRegister: Clone + From<u32> + Into<u32> + Sized + std::fmt
 pub struct CoreInformation {
     /// The current Program Counter.
     pub pc: u64,
+    /// The instruction set the core operates in at the halt, see [`Core::instruction_set`].
+    ///
+    /// It is `None` if the session doesn't allow to read registers.
+    pub instruction_set: Option<InstructionSet>,
+}
+
+/// The mechanism which halted a core after a reset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResetHaltMechanism {
+    /// The reset vector catch of the core, e.g. `DEMCR.VC_CORERESET` on Cortex-M.
+    ResetVectorCatch,
+    /// A halt request which was pending during the reset, e.g. `haltreq` of the RISC-V
+    /// Debug Module.
+    ResetHaltRequest,
+    /// A hardware breakpoint at the reset vector.
+    ResetVectorBreakpoint,
+    /// A halt request after the reset, once the core was already running.
+    HaltAfterReset,
+}
+
+impl ResetHaltMechanism {
+    /// Whether the mechanism halts the core before it executes the first instruction.
+    pub fn is_precise(&self) -> bool {
+        !matches!(self, ResetHaltMechanism::HaltAfterReset)
+    }
+}
+
+/// Where a core halted after a reset, relative to its reset vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltLocation {
+    /// The core halted at its reset vector, before executing the first instruction.
+    ResetVector,
+    /// The core halted inside a ROM of the chip, e.g. a vendor bootloader, which runs before
+    /// the reset vector is used.
+    Rom {
+        /// The name of the memory region of the ROM.
+        region: Option<String>,
+    },
+    /// The core halted somewhere else, after already executing code.
+    Elsewhere,
+    /// The reset vector of the core is unknown, so it can't be determined where the core halted.
+    Unknown,
+}
+
+impl HaltLocation {
+    /// Determine where `pc` is, relative to the `reset_vectors` of the core and the ROM regions
+    /// of the chip.
+    pub(crate) fn from_pc(pc: u64, reset_vectors: &[u64], roms: &[RomRegion]) -> Self {
+        if reset_vectors.contains(&pc) {
+            HaltLocation::ResetVector
+        } else if let Some(rom) = roms.iter().find(|rom| rom.range.contains(&pc)) {
+            HaltLocation::Rom {
+                region: rom.name.clone(),
+            }
+        } else if reset_vectors.is_empty() {
+            HaltLocation::Unknown
+        } else {
+            HaltLocation::Elsewhere
+        }
+    }
+}
+
+/// A region of the memory map which contains code of the chip vendor, which can run
+/// before the reset vector.
+#[derive(Debug, Clone)]
+pub(crate) struct RomRegion {
+    pub name: Option<String>,
+    pub range: Range<u64>,
+}
+
+/// The result of [`Session::reset_and_halt_core`](crate::Session::reset_and_halt_core).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResetHaltReport {
+    /// The program counter of the halted core.
+    pub pc: u64,
+    /// The reset vector of the core, if it is known.
+    ///
+    /// If the core has multiple reset vectors, this is the one it halted at, or the first one.
+    pub reset_vector: Option<u64>,
+    /// Where the core halted.
+    pub location: HaltLocation,
+    /// The mechanism which halted the core.
+    pub mechanism: ResetHaltMechanism,
+    /// The mechanisms which were tried before, but didn't halt the core at its reset vector.
+    pub ineffective: Vec<ResetHaltMechanism>,
 }
 
 /// The type of data stored in a register
@@ -46,6 +208,7 @@ pub struct RegisterDescription {
     pub(crate) id: RegisterId,
     pub(crate) _type: RegisterDataType,
     pub(crate) size_in_bits: usize,
+    pub(crate) writable_mask: u64,
 }
 
 impl RegisterDescription {
@@ -70,6 +233,14 @@ impl RegisterDescription {
         (self.size_in_bits + 7) / 8
     }
 
+    /// Get the bits of this register which can be written by the debugger.
+    ///
+    /// The other bits are read-only, e.g. the exception number in the xPSR of Cortex-M cores,
+    /// so writing them has no effect.
+    pub fn writable_mask(&self) -> u64 {
+        self.writable_mask
+    }
+
     /// Get the width to format this register as a hex string
     /// Assumes a format string like {:#0<width>x}
     pub fn format_hex_width(&self) -> usize {
@@ -143,9 +314,7 @@ impl TryInto<u32> for RegisterValue {
     fn try_into(self) -> Result<u32, Self::Error> {
         match self {
             Self::U32(v) => Ok(v),
-            Self::U64(v) => v
-                .try_into()
-                .map_err(|_| crate::Error::Other(anyhow!("Value '{}' too large for u32", v))),
+            Self::U64(v) => v.try_into().map_err(|_| crate::Error::ValueTooLarge(v)),
         }
     }
 }
@@ -161,6 +330,30 @@ impl TryInto<u64> for RegisterValue {
     }
 }
 
+impl std::fmt::Display for RegisterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::U32(v) => std::fmt::Display::fmt(v, f),
+            Self::U64(v) => std::fmt::Display::fmt(v, f),
+        }
+    }
+}
+
+impl std::fmt::LowerHex for RegisterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::U32(v) => std::fmt::LowerHex::fmt(v, f),
+            Self::U64(v) => std::fmt::LowerHex::fmt(v, f),
+        }
+    }
+}
+
+/// Format the value of a register as a hex string, which is padded to the width of the
+/// register, see [`RegisterDescription::format_hex_width`].
+pub fn register_value_hex(desc: &RegisterDescription, val: RegisterValue) -> String {
+    format!("{:#0width$x}", val, width = desc.format_hex_width())
+}
+
 /// Register description for a core.
 #[derive(Debug, PartialEq)]
 pub struct RegisterFile {
@@ -310,6 +503,77 @@ impl RegisterFile {
     pub fn get_fpu_register(&self, index: usize) -> Option<&RegisterDescription> {
         self.fp_registers.map(|r| r.get(index)).flatten()
     }
+
+    /// Returns the description of the register `id`, if it is part of this register file.
+    pub(crate) fn find(&self, id: RegisterId) -> Option<&RegisterDescription> {
+        let special = [
+            Some(self.program_counter),
+            self.msp,
+            self.psp,
+            self.extra,
+            self.psr,
+            self.fp_status,
+        ];
+
+        self.platform_registers
+            .iter()
+            .chain(special.into_iter().flatten())
+            .chain(self.fp_registers.into_iter().flatten())
+            .find(|register| register.id == id)
+    }
+
+    /// Returns the registers which make up the context of a core, see
+    /// [`Core::save_context`], in the order in which they have to be restored.
+    ///
+    /// The special registers come first, because some of them select which of the banked
+    /// stack pointers the general purpose registers access. The program counter comes last.
+    /// Not all of the registers exist on every core.
+    pub(crate) fn context_registers(&self) -> Vec<&'static RegisterDescription> {
+        let special = [self.extra, self.psr, self.fp_status, self.msp, self.psp];
+
+        let mut registers: Vec<&'static RegisterDescription> = Vec::new();
+
+        for register in special
+            .into_iter()
+            .flatten()
+            .chain(self.fp_registers.into_iter().flatten())
+            .chain(self.platform_registers)
+            .chain([self.program_counter])
+        {
+            // The program counter is restored last, even if it's a platform register.
+            if register.id == self.program_counter.id && register != self.program_counter {
+                continue;
+            }
+
+            if !registers.iter().any(|saved| saved.id == register.id) {
+                registers.push(register);
+            }
+        }
+
+        registers
+    }
+}
+
+/// A condition on a memory-mapped status register of a core, which holds if
+/// `value & mask == expected`.
+///
+/// A probe with the [`poll_offload`](crate::ProbeCapabilities::poll_offload) capability polls
+/// the register on its own until the condition holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusCondition {
+    /// The address of the register.
+    pub address: u64,
+    /// The bits of the register which are compared.
+    pub mask: u32,
+    /// The value of the compared bits.
+    pub expected: u32,
+}
+
+impl StatusCondition {
+    /// Returns true if the condition holds for the register `value`.
+    pub fn holds(&self, value: u32) -> bool {
+        value & self.mask == self.expected
+    }
 }
 
 /// A generic interface to control a MCU core.
@@ -325,6 +589,69 @@ pub trait CoreInterface: MemoryInterface {
     /// Returns the current status of the core.
     fn status(&mut self) -> Result<CoreStatus, error::Error>;
 
+    /// Returns true if a reset of the core was seen while its status was read since this
+    /// was last called, e.g. in `DHCSR.S_RESET_ST` of a Cortex-M core.
+    ///
+    /// The default implementation returns false.
+    fn take_reset_detected(&mut self) -> bool {
+        false
+    }
+
+    /// Returns true if the core halted again since its status was last read while it was
+    /// halted, although it was never seen running in between, e.g. from the sticky bits of
+    /// DFSR on a Cortex-M core.
+    ///
+    /// This is best effort: a core without sticky evidence of its halts can resume and halt
+    /// again unnoticed between two reads of its status. The default implementation returns
+    /// false.
+    fn take_halt_detected(&mut self) -> bool {
+        false
+    }
+
+    /// The condition on a status register which holds while the core is halted, if the
+    /// core has one which a probe can poll.
+    ///
+    /// The default implementation returns `None`.
+    fn halted_condition(&self) -> Option<StatusCondition> {
+        None
+    }
+
+    /// Let the probe poll the register of `condition` until it holds, or `timeout` passed.
+    ///
+    /// Returns true if the condition held before the timeout. This is only supported by
+    /// probes with the [`poll_offload`](crate::ProbeCapabilities::poll_offload) capability.
+    fn poll_condition(
+        &mut self,
+        _condition: StatusCondition,
+        _timeout: Duration,
+    ) -> Result<bool, error::Error> {
+        Err(error::Error::Probe(DebugProbeError::NotImplemented(
+            "polling by the probe",
+        )))
+    }
+
+    /// Queue `command` behind the accesses to the core issued before it, see
+    /// [`Core::enqueue_probe_command`].
+    fn enqueue_vendor_command(
+        &mut self,
+        _command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, error::Error> {
+        Err(error::Error::Probe(DebugProbeError::NotImplemented(
+            "vendor commands",
+        )))
+    }
+
+    /// Returns the response of the vendor command at `index`, see
+    /// [`Core::probe_command_response`].
+    fn vendor_command_response(
+        &mut self,
+        _index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, error::Error> {
+        Err(error::Error::Probe(DebugProbeError::NotImplemented(
+            "vendor commands",
+        )))
+    }
+
     /// Try to halt the core. This function ensures the core is actually halted, and
     /// returns a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) otherwise.
     fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error>;
@@ -332,6 +659,18 @@ pub trait CoreInterface: MemoryInterface {
     /// Continue to execute instructions.
     fn run(&mut self) -> Result<(), error::Error>;
 
+    /// Continue to execute instructions, while the core is halted at one of its hardware
+    /// breakpoints, with as few probe transactions as possible.
+    ///
+    /// This is used to skip the hits of a breakpoint, see [`BreakpointRequest::skip_count`].
+    /// The writes which resume the core don't have to be flushed. Returns `false` if the core
+    /// has no such fast path, in which case [`CoreInterface::run`] is used instead.
+    ///
+    /// The default implementation returns `false`.
+    fn resume_from_hw_breakpoint(&mut self) -> Result<bool, error::Error> {
+        Ok(false)
+    }
+
     /// Reset the core, and then continue to execute instructions. If the core
     /// should be halted after reset, use the [`reset_and_halt`] function.
     ///
@@ -344,14 +683,116 @@ pub trait CoreInterface: MemoryInterface {
     /// [`reset`]: Core::reset
     fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error>;
 
+    /// The mechanism which [`CoreInterface::reset_and_halt`] uses to halt the core.
+    ///
+    /// The default implementation returns [`ResetHaltMechanism::HaltAfterReset`].
+    fn reset_halt_mechanism(&self) -> ResetHaltMechanism {
+        ResetHaltMechanism::HaltAfterReset
+    }
+
+    /// Read the address at which the core starts executing after a reset, if it can be
+    /// determined from the core.
+    ///
+    /// The default implementation returns `None`.
+    fn reset_vector(&mut self) -> Result<Option<u64>, error::Error> {
+        Ok(None)
+    }
+
+    /// Halt a core which ignored a normal halt request, with the escalation `level`.
+    ///
+    /// Only [`HaltEscalation::PersistentHaltRequest`] and [`HaltEscalation::CoreReset`] are
+    /// passed. Returns `Ok(true)` once the core is halted, `Ok(false)` if the core doesn't
+    /// support the level, and a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout)
+    /// if it is still running after `timeout`.
+    ///
+    /// The default implementation supports no level.
+    fn escalate_halt(
+        &mut self,
+        level: HaltEscalation,
+        timeout: Duration,
+    ) -> Result<bool, error::Error> {
+        let _ = (level, timeout);
+        Ok(false)
+    }
+
+    /// Bound the inner operations of the core, e.g. the polls of a debug module, by
+    /// `deadline`, until it is set to `None` again, see [`Deadline`].
+    ///
+    /// The default implementation ignores the deadline, for cores whose inner operations are
+    /// bounded by the transfers of the probe.
+    fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        let _ = deadline;
+    }
+
+    /// Returns the number of low-level operations, e.g. DMI accesses on RISC-V, which the
+    /// interface of the core completed so far, to measure the progress of memory transfers
+    /// whose cost per byte varies.
+    ///
+    /// The default implementation returns `None`, for cores which don't count them.
+    fn completed_operations(&mut self) -> Option<u64> {
+        None
+    }
+
     /// Steps one instruction and then enters halted state again.
     fn step(&mut self) -> Result<CoreInformation, error::Error>;
 
+    /// Steps one instruction like [`CoreInterface::step`], with as few round trips to the
+    /// probe as possible, see [`Core::step_fast`].
+    ///
+    /// The default implementation calls [`CoreInterface::step`]. Architectures which can
+    /// queue the accesses of a step should override this.
+    fn step_fast(&mut self) -> Result<CoreInformation, error::Error> {
+        self.step()
+    }
+
+    /// Returns whether the register `address` exists on this core.
+    ///
+    /// This is checked before every register access through [`Core`], so the register itself
+    /// must not be accessed. Information needed to decide, like whether the core has an FPU,
+    /// should be cached. The default implementation returns `true`.
+    fn register_available(&mut self, address: RegisterId) -> Result<bool, error::Error> {
+        let _ = address;
+        Ok(true)
+    }
+
     /// Read the value of a core register.
     fn read_core_reg(&mut self, address: RegisterId) -> Result<RegisterValue, error::Error>;
 
+    /// Read the values of multiple core registers.
+    ///
+    /// The default implementation reads the registers one by one. Architectures which
+    /// can batch register accesses should override this.
+    fn read_core_regs(
+        &mut self,
+        addresses: &[RegisterId],
+    ) -> Result<Vec<RegisterValue>, error::Error> {
+        addresses
+            .iter()
+            .map(|address| self.read_core_reg(*address))
+            .collect()
+    }
+
     /// Write the value of a core register.
-    fn write_core_reg(&mut self, address: RegisterId, value: RegisterValue) -> Result<()>;
+    fn write_core_reg(
+        &mut self,
+        address: RegisterId,
+        value: RegisterValue,
+    ) -> Result<(), error::Error>;
+
+    /// Write the values of multiple core registers, in order.
+    ///
+    /// The default implementation writes the registers one by one. Architectures which
+    /// can batch register accesses should override this.
+    fn write_core_regs(
+        &mut self,
+        registers: &[(RegisterId, RegisterValue)],
+    ) -> Result<(), error::Error> {
+        for (address, value) in registers {
+            self.write_core_reg(*address, *value)?;
+        }
+
+        Ok(())
+    }
 
     /// Returns all the available breakpoint units of the core.
     fn available_breakpoint_units(&mut self) -> Result<u32, error::Error>;
@@ -391,169 +832,1174 @@ pub trait CoreInterface: MemoryInterface {
     /// This must be queried while halted as this is a runtime
     /// decision for some core types.
     fn fpu_support(&mut self) -> Result<bool, error::Error>;
-}
 
-impl<'probe> MemoryInterface for Core<'probe> {
-    fn supports_native_64bit_access(&mut self) -> bool {
-        self.inner.supports_native_64bit_access()
+    /// Read the floating-point status register and all floating-point registers together,
+    /// see [`Core::read_all_fpu_state`].
+    ///
+    /// This isn't used for Cortex-M cores, whose state depends on their exception frame.
+    /// The default implementation returns [`Error::NotImplemented`](error::Error::NotImplemented).
+    fn read_fpu_state(&mut self) -> Result<FpuState, error::Error> {
+        Err(error::Error::NotImplemented(
+            "reading the floating-point state",
+        ))
     }
 
-    fn read_word_64(&mut self, address: u64) -> Result<u64, Error> {
-        self.inner.read_word_64(address)
+    /// Returns the smallest line size in bytes of the enabled caches of the core, or `None`
+    /// if the core has no caches, or all of them are disabled.
+    fn cache_line_size(&mut self) -> Result<Option<u32>, error::Error> {
+        Ok(None)
     }
 
-    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
-        self.inner.read_word_32(address)
+    /// Perform `operation` on the word at `address` with the atomic instructions of the
+    /// halted core, and return the previous value of the word, see
+    /// [`Core::compare_and_swap_32`].
+    ///
+    /// Returns `None` if the core can't execute them for the debugger, e.g. because it lacks
+    /// them. The default implementation returns `None`.
+    fn atomic_32(
+        &mut self,
+        address: u64,
+        operation: AtomicOperation,
+    ) -> Result<Option<u32>, error::Error> {
+        let _ = (address, operation);
+        Ok(None)
     }
 
-    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
-        self.inner.read_word_8(address)
+    /// Perform a cache maintenance `operation` on the cache line containing `address`.
+    ///
+    /// This is only called if [`CoreInterface::cache_line_size`] returned a line size.
+    fn cache_maintenance(
+        &mut self,
+        _operation: CacheMaintenance,
+        _address: u64,
+    ) -> Result<(), error::Error> {
+        Err(error::Error::Probe(DebugProbeError::NotImplemented(
+            "cache maintenance",
+        )))
     }
 
-    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), Error> {
-        self.inner.read_64(address, data)
+    /// Returns the number of data watchpoint comparators of the core.
+    fn available_watchpoint_units(&mut self) -> Result<u32, error::Error> {
+        Ok(0)
     }
 
-    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
-        self.inner.read_32(address, data)
+    /// Returns `true` if the watchpoint comparators are the hardware breakpoint units, like
+    /// the triggers of RISC-V cores.
+    fn watchpoints_share_breakpoint_units(&self) -> bool {
+        false
     }
 
-    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
-        self.inner.read_8(address, data)
+    /// Program the watchpoint `config` into comparators which aren't `used`, and return the
+    /// comparators which implement it.
+    ///
+    /// If the core can't implement a qualifier of `config`, nothing is changed, and
+    /// [`Error::UnsupportedWatchpointQualifier`] is returned.
+    fn set_watchpoint(
+        &mut self,
+        _config: &WatchpointConfig,
+        _used: &[usize],
+    ) -> Result<Vec<usize>, error::Error> {
+        Err(error::Error::NotImplemented("setting a watchpoint"))
     }
 
-    fn write_word_64(&mut self, addr: u64, data: u64) -> Result<(), Error> {
-        self.inner.write_word_64(addr, data)
+    /// Disable the watchpoint comparators `units`.
+    fn clear_watchpoint(&mut self, _units: &[usize]) -> Result<(), error::Error> {
+        Err(error::Error::NotImplemented("clearing a watchpoint"))
     }
 
-    fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), Error> {
-        self.inner.write_word_32(addr, data)
+    /// Returns which of the watchpoint comparators `units` matched since they were last
+    /// checked, or `None` if the core can't tell.
+    fn matched_watchpoint_units(
+        &mut self,
+        _units: &[usize],
+    ) -> Result<Option<Vec<usize>>, error::Error> {
+        Ok(None)
     }
+}
 
-    fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), Error> {
-        self.inner.write_word_8(addr, data)
+/// The interval in which a wait for a core to halt checks whether it was interrupted.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The size in bytes of the chunks in which large memory transfers are split, so that
+/// they can be interrupted.
+const INTERRUPTIBLE_CHUNK_SIZE: usize = 0x1000;
+
+/// Returns `true` if `error` is the timeout of a wait for a core to halt.
+fn is_timeout(error: &Error) -> bool {
+    match error {
+        Error::Probe(DebugProbeError::Timeout) => true,
+        Error::ArchitectureSpecific(error) => matches!(
+            error.downcast_ref::<RiscvError>(),
+            Some(RiscvError::Timeout)
+        ),
+        _ => false,
     }
+}
 
-    fn write_64(&mut self, addr: u64, data: &[u64]) -> Result<(), Error> {
-        self.inner.write_64(addr, data)
+impl<'probe> Core<'probe> {
+    /// Returns the address a memory access to `address` goes to, which is the load address if
+    /// the accesses are translated, see [`Core::set_translate_memory_accesses`].
+    fn memory_address(&self, address: u64) -> u64 {
+        if self.state.translate_memory_accesses {
+            self.state.address_map.to_load_address(address)
+        } else {
+            address
+        }
     }
 
-    fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), Error> {
-        self.inner.write_32(addr, data)
+    /// Returns an error if `operation` exceeds the maximum intrusiveness of the session.
+    pub(crate) fn require(&self, operation: TargetOperation) -> Result<(), Error> {
+        self.state.max_intrusiveness.permit(operation)
     }
 
-    fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
-        self.inner.write_8(addr, data)
+    /// Check the intrusiveness of a read of `len` bytes at `address`, apply the
+    /// workarounds of the active errata, and prepare the mediator of a mediated region.
+    ///
+    /// Returns true if the read has to be routed through the mediator.
+    fn before_read(&mut self, address: u64, len: usize) -> Result<bool, Error> {
+        self.require(self.state.read_operation(address, len))?;
+
+        self.state
+            .errata
+            .before_read(&mut self.inner.as_mut(), address, len)?;
+
+        self.mediate(AccessDirection::Read, address, len)
     }
 
-    fn flush(&mut self) -> Result<(), Error> {
-        self.inner.flush()
+    /// Check the intrusiveness of a write of `len` bytes at `address`, and prepare the
+    /// mediator of a mediated region.
+    ///
+    /// Returns true if the write has to be routed through the mediator.
+    fn before_write(&mut self, address: u64, len: usize) -> Result<bool, Error> {
+        self.require(TargetOperation::WriteMemory)?;
+        self.mediate(AccessDirection::Write, address, len)
     }
-}
 
-/// A generic core state which caches the generic parts of the core state.
-#[derive(Debug)]
-pub struct CoreState {
-    id: usize,
+    /// Refuse a write of `len` bytes at `address` to device memory, if the core would write
+    /// some of the bytes with a read-modify-write of the words which contain them.
+    fn require_native_write(&mut self, address: u64, len: usize) -> Result<(), Error> {
+        let partial_words = len != 0 && (address % 4 != 0 || len % 4 != 0);
 
-    /// Information needed to access the core
-    core_access_options: CoreAccessOptions,
-}
+        if partial_words
+            && self.state.is_device_memory(address, len)
+            && !self.inner.native_write_widths().bits_8
+        {
+            return Err(Error::WouldRequireReadModifyWrite { address, len });
+        }
 
-impl CoreState {
-    /// Creates a new core state from the core ID.
-    pub fn new(id: usize, core_access_options: CoreAccessOptions) -> Self {
-        Self {
-            id,
-            core_access_options,
+        Ok(())
+    }
+
+    /// Write the little endian `bytes` at `address` to memory which is protected by ECC,
+    /// with whole, aligned words only.
+    ///
+    /// The words which are only partially written are read, modified and written back. This
+    /// requires them to be initialized, see [`Core::ecc_initialize`], as reading a word
+    /// whose ECC wasn't initialized faults.
+    fn write_ecc(&mut self, address: u64, bytes: &[u8]) -> Result<(), Error> {
+        let partial_words = ecc::partial_words(address, bytes.len());
+        for word in &partial_words {
+            if !self.state.ecc_memory.uninitialized(word.clone()).is_empty() {
+                return Err(Error::EccUninitialized {
+                    address,
+                    len: bytes.len(),
+                });
+            }
         }
+
+        let words = ecc::word_aligned(address..address + bytes.len() as u64);
+        let mut buffer = vec![0; (words.end - words.start) as usize];
+        for word in partial_words {
+            let value = self
+                .access_with_retry("read", word.start, 4, |core| core.read_word_32(word.start))?;
+            let offset = (word.start - words.start) as usize;
+            buffer[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        let offset = (address - words.start) as usize;
+        buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+        let data: Vec<u32> = buffer
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+        self.write_interruptible(words.start, &data, |core, address, data| {
+            core.write_32(address, data)
+        })?;
+
+        self.state.ecc_memory.mark_initialized(words);
+
+        Ok(())
     }
 
-    /// Returns the core ID.
+    /// Prepare the mediator of the region which contains the `len` bytes at `address`, if
+    /// it isn't prepared for `direction` already.
+    fn mediate(
+        &mut self,
+        direction: AccessDirection,
+        address: u64,
+        len: usize,
+    ) -> Result<bool, Error> {
+        if self
+            .state
+            .mediation
+            .needs_preparation(direction, address, len)
+        {
+            // Preparing a mediator reconfigures the controller of the region.
+            self.require(TargetOperation::WriteMemory)?;
+        }
 
-    pub fn id(&self) -> usize {
-        self.id
+        self.state
+            .mediation
+            .prepare(&mut self.inner.as_mut(), direction, address, len)
     }
-}
 
-/// The architecture specific core state.
-#[derive(Debug)]
-pub enum SpecificCoreState {
-    /// The state of an ARMv6-M core.
-    Armv6m(CortexMState),
-    /// The state of an ARMv7-A core.
-    Armv7a(CortexAState),
-    /// The state of an ARMv7-M core.
-    Armv7m(CortexMState),
-    /// The state of an ARMv7-EM core.
-    Armv7em(CortexMState),
-    /// The state of an ARMv8-A core.
-    Armv8a(CortexAState),
-    /// The state of an ARMv8-M core.
-    Armv8m(CortexMState),
-    /// The state of an RISC-V core.
-    Riscv,
-}
+    /// Read `data` at `address` through the mediator of its region.
+    fn read_mediated<T: FromTargetBytes>(
+        &mut self,
+        address: u64,
+        data: &mut [T],
+    ) -> Result<(), Error> {
+        let mut bytes = vec![0; data.len() * T::SIZE];
 
-impl SpecificCoreState {
-    pub(crate) fn from_core_type(typ: CoreType) -> Self {
-        match typ {
-            CoreType::Armv6m => SpecificCoreState::Armv6m(CortexMState::new()),
-            CoreType::Armv7a => SpecificCoreState::Armv7a(CortexAState::new()),
-            CoreType::Armv7m => SpecificCoreState::Armv7m(CortexMState::new()),
-            CoreType::Armv7em => SpecificCoreState::Armv7m(CortexMState::new()),
-            CoreType::Armv8a => SpecificCoreState::Armv8a(CortexAState::new()),
-            CoreType::Armv8m => SpecificCoreState::Armv8m(CortexMState::new()),
-            CoreType::Riscv => SpecificCoreState::Riscv,
+        self.state
+            .mediation
+            .read(&mut self.inner.as_mut(), address, &mut bytes)?;
+
+        for (value, bytes) in data.iter_mut().zip(bytes.chunks_exact(T::SIZE)) {
+            *value = T::from_target_bytes(bytes, Endianness::Little);
         }
+
+        Ok(())
     }
 
-    pub(crate) fn core_type(&self) -> CoreType {
-        match self {
-            SpecificCoreState::Armv6m(_) => CoreType::Armv6m,
-            SpecificCoreState::Armv7a(_) => CoreType::Armv7a,
-            SpecificCoreState::Armv7m(_) => CoreType::Armv7m,
-            SpecificCoreState::Armv7em(_) => CoreType::Armv7em,
-            SpecificCoreState::Armv8a(_) => CoreType::Armv8a,
-            SpecificCoreState::Armv8m(_) => CoreType::Armv8m,
-            SpecificCoreState::Riscv => CoreType::Riscv,
-        }
+    /// Write the little endian `bytes` at `address` through the mediator of its region.
+    fn write_mediated(&mut self, address: u64, bytes: &[u8]) -> Result<(), Error> {
+        self.state
+            .mediation
+            .write(&mut self.inner.as_mut(), address, bytes)
     }
 
-    pub(crate) fn attach_arm<'probe, 'target: 'probe>(
-        &'probe mut self,
-        state: &'probe mut CoreState,
-        memory: Memory<'probe>,
-        target: &'target Target,
-    ) -> Result<Core<'probe>, Error> {
-        let debug_sequence = match &target.debug_sequence {
-            crate::config::DebugSequence::Arm(sequence) => sequence.clone(),
-            crate::config::DebugSequence::Riscv(_) => {
-                return Err(Error::UnableToOpenProbe(
-                    "Core architecture and Probe mismatch.",
-                ))
-            }
+    /// Apply the workarounds of the active errata, the selected peripheral freezes and the
+    /// reset policy of the poisoned regions after the core was reset.
+    ///
+    /// The reset also reset the controllers of the mediated regions, so the prepared access
+    /// is dropped without restoring it.
+    fn after_reset(&mut self) -> Result<(), Error> {
+        self.state.mediation.forget();
+        self.state.instruction_set_stale = true;
+        self.inner.take_reset_detected();
+        self.state.errata.after_reset(&mut self.inner.as_mut())?;
+        self.state
+            .peripheral_freezes
+            .apply(&mut self.inner.as_mut())?;
+        self.poison_after_reset()
+    }
+
+    /// Handle a reset of the core which wasn't issued by probe-rs, e.g. by a watchdog.
+    ///
+    /// The reset is recorded in the health log, and the errata workarounds and peripheral
+    /// freezes are applied again. A reset while the core was halted is most likely caused
+    /// by a watchdog which kept running, which is pointed out with a warning.
+    fn unexpected_reset(&mut self, was_halted: bool) -> Result<(), Error> {
+        let freezes = &self.state.peripheral_freezes;
+
+        let details = if !was_halted {
+            "The core was reset while it was running".to_string()
+        } else if !freezes.can_freeze_watchdogs() {
+            log::warn!(
+                "Core {} was reset while it was halted, most likely by a watchdog which kept \
+                 running. The target has no freeze bits to stop its watchdogs while the core is \
+                 halted, so the watchdog has to be disabled in the firmware for debugging.",
+                self.state.id
+            );
+            "The core was reset while it was halted, and the watchdogs can't be frozen".to_string()
+        } else if !freezes.watchdogs_frozen() {
+            log::warn!(
+                "Core {} was reset while it was halted, most likely by a watchdog which kept \
+                 running. Stop the watchdogs while the core is halted with \
+                 `Session::freeze_peripherals_on_halt`.",
+                self.state.id
+            );
+            "The core was reset while it was halted, and the watchdogs are not frozen".to_string()
+        } else {
+            "The core was reset while it was halted, although the watchdogs are frozen".to_string()
         };
 
-        let options = match &state.core_access_options {
-            CoreAccessOptions::Arm(options) => options,
-            CoreAccessOptions::Riscv(_) => {
-                return Err(Error::UnableToOpenProbe(
-                    "Core architecture and Probe mismatch.",
-                ))
+        self.state.health_log.record(
+            HealthEvent::UnexpectedReset,
+            Some(self.state.id),
+            "status",
+            details,
+        );
+
+        self.after_reset()
+    }
+
+    /// Run an `operation` on the `len` bytes at `address` with the retry policy of the core.
+    ///
+    /// Reads are only repeated in RAM and flash, and writes only in RAM, as accesses to
+    /// device memory and volatile RAM can have side effects. Accesses which had to be
+    /// retried are recorded in the health log, with the policy and the number of attempts.
+    fn access_with_retry<R>(
+        &mut self,
+        operation: &'static str,
+        address: u64,
+        len: usize,
+        mut access: impl FnMut(&mut (dyn CoreInterface + 'probe)) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let policy = self.state.retry_policy;
+        let volatile = &self.state.volatile_ranges;
+        let repeatable = volatile.is_plain_memory(&self.state.ram_ranges, address, len)
+            || (operation == "read"
+                && volatile.is_plain_memory(&self.state.nvm_ranges, address, len));
+
+        let inner = &mut self.inner;
+        let (result, attempts) = policy.run(repeatable, || access(inner.as_mut()));
+
+        if attempts > 1 {
+            let (event, outcome) = match result {
+                Ok(_) => (HealthEvent::RetrySucceeded, "succeeded"),
+                Err(_) => (HealthEvent::RetriesExhausted, "failed"),
+            };
+
+            self.state.health_log.record(
+                event,
+                Some(self.state.id),
+                operation,
+                format!(
+                    "{} bytes at {:#010x} {} after {} attempts ({})",
+                    len, address, outcome, attempts, policy
+                ),
+            );
+        }
+
+        match result {
+            Err(error)
+                if error.link_failure().is_none()
+                    && self.state.ecc_memory.overlaps(address, len) =>
+            {
+                Err(Error::EccFault {
+                    address,
+                    len,
+                    source: Box::new(error),
+                })
             }
-        };
+            result => result,
+        }
+    }
 
-        Ok(match self {
-            SpecificCoreState::Armv6m(s) => Core::new(
-                crate::architecture::arm::armv6m::Armv6m::new(memory, s, debug_sequence)?,
+    /// Returns a [`DebugProbeError::Timeout`] if the deadline of the running timed operation
+    /// has passed, see [`Core::with_timeout`].
+    fn check_deadline(&self) -> Result<(), Error> {
+        match &self.state.deadline {
+            Some(deadline) => deadline.check(),
+            None => Ok(()),
+        }
+    }
+
+    /// Run `operation` with a deadline `timeout` from now, or the deadline of the enclosing
+    /// timed operation if that is earlier.
+    ///
+    /// The deadline is passed to the inner operations of the core while `operation` runs.
+    fn scoped_deadline<R>(
+        &mut self,
+        timeout: Duration,
+        operation: impl FnOnce(&mut Self, Deadline) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let outer = self.state.deadline;
+        let deadline = Deadline::after(timeout).earliest(outer);
+
+        self.state.deadline = Some(deadline);
+        self.inner.set_deadline(Some(deadline));
+
+        let result = operation(self, deadline);
+
+        self.state.deadline = outer;
+        self.inner.set_deadline(outer);
+
+        result
+    }
+
+    /// Read `data` in chunks, with a cancellation point between the chunks.
+    ///
+    /// Each chunk is retried on its own, see [`Core::with_retry_policy`], and the progress
+    /// of the transfer is reported after it, see
+    /// [`Session::set_transfer_progress`](crate::Session::set_transfer_progress).
+    fn read_interruptible<T>(
+        &mut self,
+        address: u64,
+        data: &mut [T],
+        mut read: impl FnMut(&mut (dyn CoreInterface + 'probe), u64, &mut [T]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let size = std::mem::size_of::<T>();
+        let mut transfer = self.state.transfers.start(
+            self.state.id,
+            TransferDirection::Read,
+            address,
+            std::mem::size_of_val(data),
+            self.inner.completed_operations(),
+        );
+
+        let mut offset = 0;
+        while offset < data.len() {
+            if offset > 0 {
+                self.state.interrupt.check()?;
+                self.check_deadline()?;
+                self.service_drains_opportunistically();
+            }
+
+            let chunk_len = (transfer.chunk_len(INTERRUPTIBLE_CHUNK_SIZE) / size)
+                .max(1)
+                .min(data.len() - offset);
+            let chunk = &mut data[offset..offset + chunk_len];
+
+            let address = address + (offset * size) as u64;
+            let len = std::mem::size_of_val(chunk);
+
+            self.access_with_retry("read", address, len, |core| {
+                read(core, address, &mut *chunk)
+            })?;
+
+            transfer.chunk_done(len, self.inner.completed_operations());
+            offset += chunk_len;
+        }
+
+        transfer.finish();
+
+        Ok(())
+    }
+
+    /// Write `data` in chunks, with a cancellation point between the chunks.
+    ///
+    /// Each chunk is retried on its own, see [`Core::with_retry_policy`], and the progress
+    /// of the transfer is reported after it, see
+    /// [`Session::set_transfer_progress`](crate::Session::set_transfer_progress).
+    fn write_interruptible<T>(
+        &mut self,
+        address: u64,
+        data: &[T],
+        mut write: impl FnMut(&mut (dyn CoreInterface + 'probe), u64, &[T]) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let size = std::mem::size_of::<T>();
+        let mut transfer = self.state.transfers.start(
+            self.state.id,
+            TransferDirection::Write,
+            address,
+            std::mem::size_of_val(data),
+            self.inner.completed_operations(),
+        );
+
+        let mut offset = 0;
+        while offset < data.len() {
+            if offset > 0 {
+                self.state.interrupt.check()?;
+                self.check_deadline()?;
+                self.service_drains_opportunistically();
+            }
+
+            let chunk_len = (transfer.chunk_len(INTERRUPTIBLE_CHUNK_SIZE) / size)
+                .max(1)
+                .min(data.len() - offset);
+            let chunk = &data[offset..offset + chunk_len];
+
+            let address = address + (offset * size) as u64;
+            let len = std::mem::size_of_val(chunk);
+
+            self.access_with_retry("write", address, len, |core| write(core, address, chunk))?;
+
+            transfer.chunk_done(len, self.inner.completed_operations());
+            offset += chunk_len;
+        }
+
+        transfer.finish();
+
+        Ok(())
+    }
+}
+
+impl<'probe> MemoryInterface for Core<'probe> {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        self.inner.supports_native_64bit_access()
+    }
+
+    fn native_write_widths(&mut self) -> WriteWidths {
+        self.inner.native_write_widths()
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, Error> {
+        self.journaled(
+            |core| {
+                let address = core.memory_address(address);
+                if core.before_read(address, 8)? {
+                    let mut value = [0];
+                    core.read_mediated(address, &mut value)?;
+                    return Ok(value[0]);
+                }
+                core.access_with_retry("read", address, 8, |core| core.read_word_64(address))
+            },
+            |id, value| {
+                let values = value.map(|value| vec![*value]);
+                JournalOperation::read_memory(id, address, JournalWidth::U64, 1, values)
+            },
+        )
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+        self.journaled(
+            |core| {
+                let address = core.memory_address(address);
+                if core.before_read(address, 4)? {
+                    let mut value = [0];
+                    core.read_mediated(address, &mut value)?;
+                    return Ok(value[0]);
+                }
+                core.access_with_retry("read", address, 4, |core| core.read_word_32(address))
+            },
+            |id, value| {
+                let values = value.map(|value| vec![u64::from(*value)]);
+                JournalOperation::read_memory(id, address, JournalWidth::U32, 1, values)
+            },
+        )
+    }
+
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+        self.journaled(
+            |core| {
+                let address = core.memory_address(address);
+                if core.before_read(address, 1)? {
+                    let mut value = [0];
+                    core.read_mediated(address, &mut value)?;
+                    return Ok(value[0]);
+                }
+                core.access_with_retry("read", address, 1, |core| core.read_word_8(address))
+            },
+            |id, value| {
+                let values = value.map(|value| vec![u64::from(*value)]);
+                JournalOperation::read_memory(id, address, JournalWidth::U8, 1, values)
+            },
+        )
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), Error> {
+        let recording = self.state.journal.enter();
+        let result = self.read_64_unjournaled(address, data);
+        self.state.journal.exit(recording, &result, |read| {
+            let values = read.map(|_| data.to_vec());
+            JournalOperation::read_memory(
+                self.state.id,
+                address,
+                JournalWidth::U64,
+                data.len(),
+                values,
+            )
+        });
+        result
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        let recording = self.state.journal.enter();
+        let result = self.read_32_unjournaled(address, data);
+        self.state.journal.exit(recording, &result, |read| {
+            let values = read.map(|_| data.iter().copied().map(u64::from).collect());
+            JournalOperation::read_memory(
+                self.state.id,
+                address,
+                JournalWidth::U32,
+                data.len(),
+                values,
+            )
+        });
+        result
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        let recording = self.state.journal.enter();
+        let result = self.read_8_unjournaled(address, data);
+        self.state.journal.exit(recording, &result, |read| {
+            let values = read.map(|_| data.iter().copied().map(u64::from).collect());
+            JournalOperation::read_memory(
+                self.state.id,
+                address,
+                JournalWidth::U8,
+                data.len(),
+                values,
+            )
+        });
+        result
+    }
+
+    fn write_word_64(&mut self, addr: u64, data: u64) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, 8)? {
+                    return core.write_mediated(addr, &data.to_le_bytes());
+                }
+                if core.state.ecc_memory.overlaps(addr, 8) {
+                    return core.write_ecc(addr, &data.to_le_bytes());
+                }
+                core.access_with_retry("write", addr, 8, |core| core.write_word_64(addr, data))
+            },
+            |id, _| JournalOperation::write_memory(id, addr, JournalWidth::U64, vec![data]),
+        )
+    }
+
+    fn write_word_32(&mut self, addr: u64, data: u32) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, 4)? {
+                    return core.write_mediated(addr, &data.to_le_bytes());
+                }
+                if core.state.ecc_memory.overlaps(addr, 4) {
+                    return core.write_ecc(addr, &data.to_le_bytes());
+                }
+                core.access_with_retry("write", addr, 4, |core| core.write_word_32(addr, data))
+            },
+            |id, _| JournalOperation::write_memory(id, addr, JournalWidth::U32, vec![data.into()]),
+        )
+    }
+
+    fn write_word_8(&mut self, addr: u64, data: u8) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, 1)? {
+                    return core.write_mediated(addr, &data.to_le_bytes());
+                }
+                if core.state.ecc_memory.overlaps(addr, 1) {
+                    return core.write_ecc(addr, &[data]);
+                }
+                core.require_native_write(addr, 1)?;
+                core.access_with_retry("write", addr, 1, |core| core.write_word_8(addr, data))
+            },
+            |id, _| JournalOperation::write_memory(id, addr, JournalWidth::U8, vec![data.into()]),
+        )
+    }
+
+    fn write_64(&mut self, addr: u64, data: &[u64]) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, std::mem::size_of_val(data))? {
+                    let bytes: Vec<u8> =
+                        data.iter().flat_map(|value| value.to_le_bytes()).collect();
+                    return core.write_mediated(addr, &bytes);
+                }
+                if core
+                    .state
+                    .ecc_memory
+                    .overlaps(addr, std::mem::size_of_val(data))
+                {
+                    let bytes: Vec<u8> =
+                        data.iter().flat_map(|value| value.to_le_bytes()).collect();
+                    return core.write_ecc(addr, &bytes);
+                }
+                core.write_interruptible(addr, data, |core, address, data| {
+                    core.write_64(address, data)
+                })
+            },
+            |id, _| JournalOperation::write_memory(id, addr, JournalWidth::U64, data.to_vec()),
+        )
+    }
+
+    fn write_32(&mut self, addr: u64, data: &[u32]) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, std::mem::size_of_val(data))? {
+                    let bytes: Vec<u8> =
+                        data.iter().flat_map(|value| value.to_le_bytes()).collect();
+                    return core.write_mediated(addr, &bytes);
+                }
+                if core
+                    .state
+                    .ecc_memory
+                    .overlaps(addr, std::mem::size_of_val(data))
+                {
+                    let bytes: Vec<u8> =
+                        data.iter().flat_map(|value| value.to_le_bytes()).collect();
+                    return core.write_ecc(addr, &bytes);
+                }
+                core.write_interruptible(addr, data, |core, address, data| {
+                    core.write_32(address, data)
+                })
+            },
+            |id, _| {
+                let values = data.iter().copied().map(u64::from).collect();
+                JournalOperation::write_memory(id, addr, JournalWidth::U32, values)
+            },
+        )
+    }
+
+    fn write_8(&mut self, addr: u64, data: &[u8]) -> Result<(), Error> {
+        self.journaled(
+            |core| {
+                let addr = core.memory_address(addr);
+                if core.before_write(addr, std::mem::size_of_val(data))? {
+                    return core.write_mediated(addr, data);
+                }
+                if core.state.ecc_memory.overlaps(addr, data.len()) {
+                    return core.write_ecc(addr, data);
+                }
+                core.require_native_write(addr, data.len())?;
+                core.write_interruptible(addr, data, |core, address, data| {
+                    core.write_8(address, data)
+                })
+            },
+            |id, _| {
+                let values = data.iter().copied().map(u64::from).collect();
+                JournalOperation::write_memory(id, addr, JournalWidth::U8, values)
+            },
+        )
+    }
+
+    fn write_barrier(&mut self) -> Result<(), Error> {
+        self.inner.write_barrier()
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+impl<'probe> Core<'probe> {
+    /// Perform `operation`, and record it in the operation journal of the session as the
+    /// operation returned by `entry`, if the journal is started and no other operation is
+    /// running. `entry` is called with the index of the core, and the value of a
+    /// successful `operation`.
+    fn journaled<R>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<R, Error>,
+        entry: impl FnOnce(usize, Option<&R>) -> JournalOperation,
+    ) -> Result<R, Error> {
+        let recording = self.state.journal.enter();
+        let result = operation(self);
+        let id = self.state.id;
+        self.state
+            .journal
+            .exit(recording, &result, |value| entry(id, value));
+        result
+    }
+
+    fn read_64_unjournaled(&mut self, address: u64, data: &mut [u64]) -> Result<(), Error> {
+        let address = self.memory_address(address);
+        if self.before_read(address, std::mem::size_of_val(data))? {
+            return self.read_mediated(address, data);
+        }
+        self.read_interruptible(address, data, |core, address, data| {
+            core.read_64(address, data)
+        })
+    }
+
+    fn read_32_unjournaled(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        let address = self.memory_address(address);
+        if self.before_read(address, std::mem::size_of_val(data))? {
+            return self.read_mediated(address, data);
+        }
+        self.read_interruptible(address, data, |core, address, data| {
+            core.read_32(address, data)
+        })
+    }
+
+    fn read_8_unjournaled(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        let address = self.memory_address(address);
+        if self.before_read(address, std::mem::size_of_val(data))? {
+            return self.read_mediated(address, data);
+        }
+        self.read_interruptible(address, data, |core, address, data| {
+            core.read_8(address, data)
+        })
+    }
+}
+
+/// A generic core state which caches the generic parts of the core state.
+#[derive(Debug)]
+pub struct CoreState {
+    id: usize,
+
+    /// Information needed to access the core
+    core_access_options: CoreAccessOptions,
+
+    /// Set if a reset of this core also resets other cores of the chip.
+    reset_affects_other_cores: bool,
+
+    /// Interrupts the operations running on this core.
+    interrupt: InterruptHandle,
+
+    /// The drains of the session, which are serviced at the cancellation points.
+    drains: Drains,
+
+    /// The progress and statistics of the memory transfers of the session.
+    transfers: Transfers,
+
+    /// Records the operations of the core while the operation journal of the session is
+    /// started.
+    journal: JournalRecorder,
+
+    /// The deadline of the timed operation which is running on this core, see
+    /// [`Core::with_timeout`].
+    deadline: Option<Deadline>,
+
+    /// The cached contents of the hardware breakpoint comparators, if they were read.
+    hw_breakpoints: Option<Vec<Option<u64>>>,
+
+    /// The workarounds for the errata which affect the core.
+    errata: CoreErrata,
+
+    /// Named groups of hardware breakpoints, which are cleared together.
+    breakpoint_groups: BTreeMap<String, Vec<u64>>,
+
+    /// The software breakpoints, by their address.
+    sw_breakpoints: BTreeMap<u64, SoftwareBreakpoint>,
+
+    /// The RAM the core can access, in which software breakpoints can be set.
+    ram_ranges: Vec<Range<u64>>,
+
+    /// The flash the core can access.
+    nvm_ranges: Vec<Range<u64>>,
+
+    /// The memory whose accesses are never repeated, see
+    /// [`Session::mark_volatile`](crate::Session::mark_volatile).
+    volatile_ranges: VolatileRanges,
+
+    /// The RAM which is protected by ECC, with the words of it which were initialized.
+    ecc_memory: EccMemory,
+
+    /// The memory whose accesses are prepared by a mediator, with the prepared access.
+    mediation: Mediation,
+
+    /// The retry policy of the memory accesses, see [`Core::with_retry_policy`].
+    retry_policy: RetryPolicy,
+
+    /// The log in which retried memory accesses are recorded.
+    health_log: HealthLog,
+
+    /// The most intrusive operation the core may perform, see
+    /// [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness).
+    max_intrusiveness: Intrusiveness,
+
+    /// The translation between the link and the load addresses of the firmware.
+    address_map: AddressMap,
+
+    /// Whether the memory accesses of the core are translated with the address map.
+    translate_memory_accesses: bool,
+
+    /// The link addresses of the breakpoints which were set by their link address, by
+    /// their load address.
+    breakpoint_link_addresses: BTreeMap<u64, u64>,
+
+    /// Whether the probe polls the status of the core while waiting for it to halt.
+    poll_offload: bool,
+
+    /// The skip counts of the breakpoints, by their address.
+    breakpoint_skip_counts: BTreeMap<u64, BreakpointSkipCount>,
+
+    /// The peripherals which are stopped while the cores are halted, see
+    /// [`Session::freeze_peripherals_on_halt`](crate::Session::freeze_peripherals_on_halt).
+    peripheral_freezes: PeripheralFreezes,
+
+    /// Whether the core was halted when probe-rs last read or changed its status.
+    halted: bool,
+
+    /// The number of halts of the core which were detected, see [`Core::halt_generation`].
+    halt_generation: u64,
+
+    /// The instruction set the core operated in when it was last derived.
+    instruction_set: Option<InstructionSet>,
+
+    /// Whether the instruction set has to be derived again, because the core ran or a
+    /// register was written since.
+    instruction_set_stale: bool,
+
+    /// The watchpoints which are set, in the order in which they were set.
+    watchpoints: Vec<Watchpoint>,
+
+    /// The watchpoint comparators which matched before the current halt, once they were
+    /// checked, see [`Core::triggered_watchpoints`].
+    matched_watchpoint_units: Option<Option<Vec<usize>>>,
+
+    /// The poisoned regions of memory, see [`Core::poison_region`].
+    poison: PoisonList,
+
+    /// Whether the words of a running core without atomic instructions may be modified with
+    /// a plain read and write, see [`Core::set_force_non_atomic`].
+    force_non_atomic: bool,
+}
+
+/// A software breakpoint which is set.
+#[derive(Debug, Clone)]
+struct SoftwareBreakpoint {
+    /// The instruction which was replaced.
+    original: Vec<u8>,
+    /// The breakpoint instruction which replaced it.
+    ///
+    /// It is kept, so that the same instruction is written again when a hit is skipped, even
+    /// if the core switched to another instruction set since.
+    instruction: Vec<u8>,
+}
+
+impl CoreState {
+    /// Creates a new core state from the core ID.
+    pub fn new(id: usize, core_access_options: CoreAccessOptions) -> Self {
+        Self {
+            id,
+            core_access_options,
+            reset_affects_other_cores: false,
+            interrupt: InterruptHandle::new(),
+            drains: Drains::default(),
+            transfers: Transfers::default(),
+            journal: JournalRecorder::default(),
+            deadline: None,
+            hw_breakpoints: None,
+            errata: CoreErrata::default(),
+            breakpoint_groups: BTreeMap::new(),
+            sw_breakpoints: BTreeMap::new(),
+            ram_ranges: Vec::new(),
+            nvm_ranges: Vec::new(),
+            volatile_ranges: VolatileRanges::default(),
+            ecc_memory: EccMemory::default(),
+            mediation: Mediation::default(),
+            retry_policy: RetryPolicy::default(),
+            health_log: HealthLog::default(),
+            max_intrusiveness: Intrusiveness::default(),
+            address_map: AddressMap::default(),
+            translate_memory_accesses: false,
+            breakpoint_link_addresses: BTreeMap::new(),
+            poll_offload: false,
+            breakpoint_skip_counts: BTreeMap::new(),
+            peripheral_freezes: PeripheralFreezes::default(),
+            halted: false,
+            halt_generation: 0,
+            instruction_set: None,
+            instruction_set_stale: true,
+            watchpoints: Vec::new(),
+            matched_watchpoint_units: None,
+            poison: PoisonList::default(),
+            force_non_atomic: false,
+        }
+    }
+
+    /// Returns the core ID.
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn set_reset_affects_other_cores(&mut self, affects_other_cores: bool) {
+        self.reset_affects_other_cores = affects_other_cores;
+    }
+
+    pub(crate) fn set_interrupt_handle(&mut self, interrupt: InterruptHandle) {
+        self.interrupt = interrupt;
+    }
+
+    pub(crate) fn set_drains(&mut self, drains: Drains) {
+        self.drains = drains;
+    }
+
+    pub(crate) fn set_transfers(&mut self, transfers: Transfers) {
+        self.transfers = transfers;
+    }
+
+    pub(crate) fn set_journal(&mut self, journal: JournalRecorder) {
+        self.journal = journal;
+    }
+
+    pub(crate) fn set_errata(&mut self, errata: CoreErrata) {
+        self.errata = errata;
+    }
+
+    pub(crate) fn set_poll_offload(&mut self, poll_offload: bool) {
+        self.poll_offload = poll_offload;
+    }
+
+    pub(crate) fn set_ram_ranges(&mut self, ram_ranges: Vec<Range<u64>>) {
+        self.ram_ranges = ram_ranges;
+    }
+
+    pub(crate) fn set_nvm_ranges(&mut self, nvm_ranges: Vec<Range<u64>>) {
+        self.nvm_ranges = nvm_ranges;
+    }
+
+    pub(crate) fn set_volatile_ranges(&mut self, volatile_ranges: VolatileRanges) {
+        self.volatile_ranges = volatile_ranges;
+    }
+
+    pub(crate) fn set_ecc_memory(&mut self, ecc_memory: EccMemory) {
+        self.ecc_memory = ecc_memory;
+    }
+
+    pub(crate) fn set_mediated_regions(&mut self, regions: MediatedRegions) {
+        self.mediation.set_regions(regions);
+    }
+
+    pub(crate) fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub(crate) fn set_health_log(&mut self, health_log: HealthLog) {
+        self.health_log = health_log;
+    }
+
+    pub(crate) fn set_peripheral_freezes(&mut self, peripheral_freezes: PeripheralFreezes) {
+        self.peripheral_freezes = peripheral_freezes;
+    }
+
+    pub(crate) fn set_max_intrusiveness(&mut self, max_intrusiveness: Intrusiveness) {
+        self.max_intrusiveness = max_intrusiveness;
+    }
+
+    /// Returns the operation a read of `len` bytes at `address` is.
+    ///
+    /// Reads of RAM and flash aren't visible to the target, while reads of device memory,
+    /// including volatile RAM, can have side effects.
+    pub(crate) fn read_operation(&self, address: u64, len: usize) -> TargetOperation {
+        if self.is_device_memory(address, len) {
+            TargetOperation::ReadDeviceMemory
+        } else {
+            TargetOperation::ReadMemory
+        }
+    }
+
+    /// Returns true if any of the `len` bytes at `address` is device memory, i.e. neither
+    /// plain RAM nor flash. Volatile RAM is device memory.
+    pub(crate) fn is_device_memory(&self, address: u64, len: usize) -> bool {
+        !(self
+            .volatile_ranges
+            .is_plain_memory(&self.ram_ranges, address, len)
+            || self
+                .volatile_ranges
+                .is_plain_memory(&self.nvm_ranges, address, len))
+    }
+
+    pub(crate) fn address_map(&self) -> &AddressMap {
+        &self.address_map
+    }
+
+    /// Discard the cached hardware breakpoints, e.g. because the core was reset.
+    pub(crate) fn invalidate_hw_breakpoints(&mut self) {
+        self.hw_breakpoints = None;
+    }
+}
+
+/// The architecture specific core state.
+#[derive(Debug)]
+pub enum SpecificCoreState {
+    /// The state of an ARMv6-M core.
+    Armv6m(CortexMState),
+    /// The state of an ARMv7-A core.
+    Armv7a(CortexAState),
+    /// The state of an ARMv7-M core.
+    Armv7m(CortexMState),
+    /// The state of an ARMv7-EM core.
+    Armv7em(CortexMState),
+    /// The state of an ARMv8-A core.
+    Armv8a(CortexAState),
+    /// The state of an ARMv8-M core.
+    Armv8m(CortexMState),
+    /// The state of an RISC-V core.
+    Riscv,
+}
+
+impl SpecificCoreState {
+    pub(crate) fn from_core_type(typ: CoreType) -> Self {
+        match typ {
+            CoreType::Armv6m => SpecificCoreState::Armv6m(CortexMState::new()),
+            CoreType::Armv7a => SpecificCoreState::Armv7a(CortexAState::new()),
+            CoreType::Armv7m => SpecificCoreState::Armv7m(CortexMState::new()),
+            CoreType::Armv7em => SpecificCoreState::Armv7m(CortexMState::new()),
+            CoreType::Armv8a => SpecificCoreState::Armv8a(CortexAState::new()),
+            CoreType::Armv8m => SpecificCoreState::Armv8m(CortexMState::new()),
+            CoreType::Riscv => SpecificCoreState::Riscv,
+        }
+    }
+
+    pub(crate) fn core_type(&self) -> CoreType {
+        match self {
+            SpecificCoreState::Armv6m(_) => CoreType::Armv6m,
+            SpecificCoreState::Armv7a(_) => CoreType::Armv7a,
+            SpecificCoreState::Armv7m(_) => CoreType::Armv7m,
+            SpecificCoreState::Armv7em(_) => CoreType::Armv7em,
+            SpecificCoreState::Armv8a(_) => CoreType::Armv8a,
+            SpecificCoreState::Armv8m(_) => CoreType::Armv8m,
+            SpecificCoreState::Riscv => CoreType::Riscv,
+        }
+    }
+
+    /// The register file of the core, as far as it is known without accessing the core.
+    pub(crate) fn registers(&self) -> &'static RegisterFile {
+        match self {
+            SpecificCoreState::Armv6m(state)
+            | SpecificCoreState::Armv7m(state)
+            | SpecificCoreState::Armv7em(state)
+            | SpecificCoreState::Armv8m(state) => state.registers(),
+            SpecificCoreState::Armv7a(state) | SpecificCoreState::Armv8a(state) => {
+                state.registers()
+            }
+            SpecificCoreState::Riscv => &RISCV_REGISTERS,
+        }
+    }
+
+    /// Whether the core was initialized by probe-rs, and its last known status, or `None` if
+    /// they aren't tracked for the type of the core.
+    pub(crate) fn attach_state(&self) -> Option<(bool, CoreStatus)> {
+        match self {
+            SpecificCoreState::Armv6m(state)
+            | SpecificCoreState::Armv7m(state)
+            | SpecificCoreState::Armv7em(state)
+            | SpecificCoreState::Armv8m(state) => {
+                Some((state.initialized(), state.current_state()))
+            }
+            SpecificCoreState::Armv7a(state) | SpecificCoreState::Armv8a(state) => {
+                Some((state.initialized(), state.current_state()))
+            }
+            SpecificCoreState::Riscv => None,
+        }
+    }
+
+    pub(crate) fn attach_arm<'probe, 'target: 'probe>(
+        &'probe mut self,
+        state: &'probe mut CoreState,
+        memory: Memory<'probe>,
+        target: &'target Target,
+    ) -> Result<Core<'probe>, Error> {
+        let core = state.id();
+        // The session routes the probe to the debug interface of the core before it is
+        // attached, so a mismatch means that the core was routed wrongly.
+        let mismatch = Error::CoreArchitectureMismatch {
+            core,
+            architecture: Architecture::Arm,
+        };
+
+        let debug_sequence = match target.debug_sequence_for(Architecture::Arm) {
+            crate::config::DebugSequence::Arm(sequence) => sequence,
+            crate::config::DebugSequence::Riscv(_) => return Err(mismatch),
+        };
+
+        let options = match &state.core_access_options {
+            CoreAccessOptions::Arm(options) => options,
+            CoreAccessOptions::Riscv(_) => return Err(mismatch),
+        };
+
+        let required = |address: Option<u64>, name: &str| {
+            address.ok_or_else(|| Error::InvalidCoreAccessOptions {
+                core,
+                reason: format!("the {} is required for this core, but not specified", name),
+            })
+        };
+
+        Ok(match self {
+            SpecificCoreState::Armv6m(s) => Core::new(
+                crate::architecture::arm::armv6m::Armv6m::new(memory, s, debug_sequence)?,
                 state,
             ),
             SpecificCoreState::Armv7a(s) => Core::new(
                 crate::architecture::arm::armv7a::Armv7a::new(
                     memory,
                     s,
-                    options.debug_base.expect("base_address not specified"),
+                    required(options.debug_base, "debug base address")?,
                     debug_sequence,
                 )?,
                 state,
@@ -566,8 +2012,8 @@ impl SpecificCoreState {
                 crate::architecture::arm::armv8a::Armv8a::new(
                     memory,
                     s,
-                    options.debug_base.expect("base_address not specified"),
-                    options.cti_base.expect("cti_address not specified"),
+                    required(options.debug_base, "debug base address")?,
+                    required(options.cti_base, "CTI base address")?,
                     debug_sequence,
                 )?,
                 state,
@@ -576,263 +2022,3154 @@ impl SpecificCoreState {
                 crate::architecture::arm::armv8m::Armv8m::new(memory, s, debug_sequence)?,
                 state,
             ),
-            _ => {
-                return Err(Error::UnableToOpenProbe(
-                    "Core architecture and Probe mismatch.",
-                ))
+            _ => return Err(mismatch),
+        })
+    }
+
+    pub(crate) fn attach_riscv<'probe>(
+        &self,
+        state: &'probe mut CoreState,
+        interface: &'probe mut RiscvCommunicationInterface,
+        target: &Target,
+    ) -> Result<Core<'probe>, Error> {
+        let mismatch = Error::CoreArchitectureMismatch {
+            core: state.id(),
+            architecture: Architecture::Riscv,
+        };
+
+        let debug_sequence = match target.debug_sequence_for(Architecture::Riscv) {
+            crate::config::DebugSequence::Riscv(sequence) => sequence,
+            crate::config::DebugSequence::Arm(_) => return Err(mismatch),
+        };
+
+        let quirks = match &state.core_access_options {
+            CoreAccessOptions::Riscv(options) => options.quirks,
+            CoreAccessOptions::Arm(_) => Default::default(),
+        };
+
+        Ok(match self {
+            SpecificCoreState::Riscv => Core::new(
+                crate::architecture::riscv::Riscv32::new(interface, debug_sequence, quirks),
+                state,
+            ),
+            _ => return Err(mismatch),
+        })
+    }
+}
+
+/// Generic core handle representing a physical core on an MCU.
+///
+/// This should be considere as a temporary view of the core which locks the debug probe driver to as single consumer by borrowing it.
+///
+/// As soon as you did your atomic task (e.g. halt the core, read the core state and all other debug relevant info) you should drop this object,
+/// to allow potential other shareholders of the session struct to grab a core handle too.
+pub struct Core<'probe> {
+    inner: Box<dyn CoreInterface + 'probe>,
+    state: &'probe mut CoreState,
+}
+
+impl<'probe> Core<'probe> {
+    /// Create a new [`Core`].
+    pub fn new(core: impl CoreInterface + 'probe, state: &'probe mut CoreState) -> Core<'probe> {
+        Self {
+            inner: Box::new(core),
+            state,
+        }
+    }
+
+    /// Creates a new [`CoreState`]
+    pub fn create_state(id: usize, options: CoreAccessOptions) -> CoreState {
+        CoreState::new(id, options)
+    }
+
+    /// Returns the ID of this core.
+    pub fn id(&self) -> usize {
+        self.state.id
+    }
+
+    /// Wait until the core is halted. If the core does not halt on its own,
+    /// a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) error will be returned.
+    ///
+    /// The wait returns at most one inner-operation quantum after `timeout`, see [`Deadline`].
+    ///
+    /// The wait can be interrupted with an [`InterruptHandle`], in which case
+    /// [`Error::Interrupted`] is returned.
+    ///
+    /// If the probe has the [`poll_offload`](crate::ProbeCapabilities::poll_offload)
+    /// capability, the probe polls the status of the core, and the host only reads it once
+    /// the probe reports the halt.
+    ///
+    /// Hits of breakpoints with a skip count are skipped while waiting, see
+    /// [`BreakpointRequest::skip_count`]. The status of the core is then polled by the host,
+    /// even if the probe could poll it.
+    ///
+    /// Intrusiveness: [`ReadStatus`](TargetOperation::ReadStatus), and
+    /// [`Resume`](TargetOperation::Resume) to skip a breakpoint hit.
+    pub fn wait_for_core_halted(&mut self, timeout: Duration) -> Result<(), error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::ReadStatus)?;
+                core.wait_for_halt(timeout)?;
+
+                // The core can have switched to another instruction set while it ran.
+                core.note_halted();
+                core.state.instruction_set_stale = true;
+
+                Ok(())
+            },
+            |id, _| JournalOperation::WaitForCoreHalted {
+                core: id,
+                timeout_ms: timeout.as_millis() as u64,
+            },
+        )
+    }
+
+    /// Wait until the core is halted, see [`Core::wait_for_core_halted`].
+    fn wait_for_halt(&mut self, timeout: Duration) -> Result<(), error::Error> {
+        if self
+            .state
+            .breakpoint_skip_counts
+            .values()
+            .any(|skip_count| skip_count.remaining > 0)
+        {
+            return self.scoped_deadline(timeout, |core, deadline| {
+                core.wait_skipping_breakpoint_hits(deadline)
+            });
+        }
+
+        let offloaded = self
+            .inner
+            .halted_condition()
+            .filter(|_| self.state.poll_offload);
+
+        self.scoped_deadline(timeout, |core, deadline| {
+            if let Some(condition) = offloaded {
+                return core.wait_for_offloaded_halt(condition, deadline);
+            }
+
+            loop {
+                match core
+                    .inner
+                    .wait_for_core_halted(deadline.remaining().min(INTERRUPT_POLL_INTERVAL))
+                {
+                    Err(error) if is_timeout(&error) && !deadline.has_passed() => {
+                        core.state.interrupt.check()?;
+                        core.service_drains_opportunistically();
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+
+    /// Wait until the core is halted, and resume it each time it halts at a breakpoint
+    /// whose hits are still skipped.
+    ///
+    /// This is the host side fast path of skip counts. A halt is detected with a single read
+    /// of the status, and the hit identified by the program counter, without reading any
+    /// other register. The full status is only read once the core halts for another reason.
+    fn wait_skipping_breakpoint_hits(&mut self, deadline: Deadline) -> Result<(), error::Error> {
+        loop {
+            if self.inner.core_halted()? {
+                if !self.skip_breakpoint_hit()? {
+                    self.inner.status()?;
+                    return Ok(());
+                }
+
+                continue;
+            }
+
+            if deadline.has_passed() {
+                return Err(error::Error::Probe(DebugProbeError::Timeout));
+            }
+
+            self.state.interrupt.check()?;
+            self.service_drains_opportunistically();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Resume the halted core if it is at a breakpoint whose hits are still skipped, and
+    /// count the hit. Returns `false` if the halt is not a skipped hit.
+    fn skip_breakpoint_hit(&mut self) -> Result<bool, error::Error> {
+        let pc_id = self.registers().program_counter().id;
+        let pc: u64 = self.inner.read_core_reg(pc_id)?.try_into()?;
+
+        let skip_count = match self.state.breakpoint_skip_counts.get(&pc) {
+            Some(&skip_count) if skip_count.remaining > 0 => skip_count,
+            _ => return Ok(false),
+        };
+
+        self.require(TargetOperation::Resume)?;
+        self.release_access_mediators()?;
+
+        self.state.breakpoint_skip_counts.insert(
+            pc,
+            BreakpointSkipCount {
+                remaining: skip_count.remaining - 1,
+                skipped: skip_count.skipped + 1,
+            },
+        );
+
+        log::trace!("Skipping hit of the breakpoint at {:#010x}", pc);
+
+        if let Some(breakpoint) = self.state.sw_breakpoints.get(&pc).cloned() {
+            // The original instruction is executed in a single step, so that the core
+            // doesn't halt at the breakpoint instruction again.
+            self.write_8(pc, &breakpoint.original)?;
+            self.inner.step()?;
+            self.write_8(pc, &breakpoint.instruction)?;
+            self.flush()?;
+
+            return self.inner.run().map(|_| true);
+        }
+
+        let hardware = self
+            .state
+            .hw_breakpoints
+            .as_ref()
+            .map_or(false, |breakpoints| breakpoints.contains(&Some(pc)));
+
+        if !hardware || !self.inner.resume_from_hw_breakpoint()? {
+            self.inner.run()?;
+        }
+
+        Ok(true)
+    }
+
+    /// Wait until the probe reports that `condition` holds, and the core is halted.
+    ///
+    /// The core can resume between the report of the probe and the read of its status, e.g.
+    /// if a debug monitor continues it from a breakpoint. The status is read after each
+    /// report, and the wait goes on if the core is running again.
+    fn wait_for_offloaded_halt(
+        &mut self,
+        condition: StatusCondition,
+        deadline: Deadline,
+    ) -> Result<(), error::Error> {
+        loop {
+            let reported = self
+                .inner
+                .poll_condition(condition, deadline.remaining().min(INTERRUPT_POLL_INTERVAL))?;
+
+            if reported {
+                if self.inner.status()?.is_halted() {
+                    return Ok(());
+                }
+
+                log::debug!("The core resumed after the probe reported that it halted");
+            }
+
+            if deadline.has_passed() {
+                return Err(error::Error::Probe(DebugProbeError::Timeout));
+            }
+
+            self.state.interrupt.check()?;
+            self.service_drains_opportunistically();
+        }
+    }
+
+    /// Run `operation` with a deadline `timeout` from now.
+    ///
+    /// The timed operations which `operation` calls, e.g. [`Core::halt`], give up at the
+    /// deadline even if their own timeout is longer, and large memory transfers return a
+    /// [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) between two chunks once
+    /// the deadline has passed. Calls can be nested, the earliest deadline applies.
+    ///
+    /// `operation` returns at most one inner-operation quantum after `timeout`, e.g. the
+    /// transfer of one chunk, see [`Deadline`].
+    pub fn with_timeout<R>(
+        &mut self,
+        timeout: Duration,
+        operation: impl FnOnce(&mut Self) -> Result<R, error::Error>,
+    ) -> Result<R, error::Error> {
+        self.scoped_deadline(timeout, |core, _| operation(core))
+    }
+
+    /// Wait until the core is halted, without a cancellation point.
+    ///
+    /// This is used to wait for routines which have to complete to leave the target in a
+    /// consistent state, e.g. the routines of a flash algorithm.
+    pub(crate) fn wait_for_core_halted_uninterruptible(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), error::Error> {
+        self.inner.wait_for_core_halted(timeout)?;
+
+        self.note_halted();
+        self.state.instruction_set_stale = true;
+
+        Ok(())
+    }
+
+    /// A cancellation point of an operation on this core.
+    ///
+    /// Returns [`Error::Interrupted`] if the operation was interrupted with an [`InterruptHandle`].
+    pub(crate) fn check_interrupt(&self) -> Result<(), error::Error> {
+        self.state.interrupt.check()
+    }
+
+    /// Service the drains of this core which are due, within the budget of a cancellation
+    /// point, see [`Session::register_drain`](crate::Session::register_drain).
+    fn service_drains_opportunistically(&mut self) {
+        let drains = self.state.drains.clone();
+        let deadline = self.state.deadline;
+
+        drains.service_opportunistically(self, deadline);
+    }
+
+    /// Service all drains of this core, see [`Session::service_drains`](crate::Session::service_drains).
+    pub(crate) fn service_drains(&mut self) -> Result<(), error::Error> {
+        let drains = self.state.drains.clone();
+
+        drains.service_all(self)
+    }
+
+    /// Check if the core is halted. If the core does not halt on its own,
+    /// a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) error will be returned.
+    ///
+    /// Intrusiveness: [`ReadStatus`](TargetOperation::ReadStatus).
+    pub fn core_halted(&mut self) -> Result<bool, error::Error> {
+        self.require(TargetOperation::ReadStatus)?;
+        self.inner.core_halted()
+    }
+
+    /// Try to halt the core. This function ensures the core is actually halted, and
+    /// returns a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) otherwise.
+    ///
+    /// The halt returns at most one inner-operation quantum after `timeout`, including the
+    /// polls of the debug module which follow the halt request, see [`Deadline`].
+    ///
+    /// Intrusiveness: [`Halt`](TargetOperation::Halt).
+    pub fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::Halt)?;
+                let info = core.scoped_deadline(timeout, |core, deadline| {
+                    core.inner.halt(deadline.remaining())
+                })?;
+
+                core.record_halt(info)
+            },
+            |id, _| JournalOperation::Halt {
+                core: id,
+                timeout_ms: timeout.as_millis() as u64,
+            },
+        )
+    }
+
+    /// Continue to execute instructions.
+    ///
+    /// Intrusiveness: [`Resume`](TargetOperation::Resume).
+    pub fn run(&mut self) -> Result<(), error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::Resume)?;
+                core.release_access_mediators()?;
+                core.discard_watchpoint_matches()?;
+                core.inner.run()?;
+                core.state.halted = false;
+                core.state.instruction_set_stale = true;
+
+                Ok(())
+            },
+            |id, _| JournalOperation::Run { core: id },
+        )
+    }
+
+    /// Restore the controllers of the mediated regions, which were prepared for the
+    /// accesses of the core, see [`AccessMediator`](crate::AccessMediator).
+    ///
+    /// Preparations are kept across accesses, and released automatically before the core
+    /// runs or steps, so this only has to be called if the firmware must see the
+    /// controller in its own state while the core stays halted.
+    pub fn release_access_mediators(&mut self) -> Result<(), error::Error> {
+        self.state.mediation.release(&mut self.inner.as_mut())
+    }
+
+    /// Reset the core, and then continue to execute instructions. If the core
+    /// should be halted after reset, use the [`reset_and_halt`] function.
+    ///
+    /// [`reset_and_halt`]: Core::reset_and_halt
+    ///
+    /// Depending on the target, this can also reset other cores of the chip.
+    /// In that case, [`Session::reset_system`] should be used instead.
+    ///
+    /// [`Session::reset_system`]: crate::Session::reset_system
+    ///
+    /// Intrusiveness: [`Reset`](TargetOperation::Reset).
+    pub fn reset(&mut self) -> Result<(), error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::Reset)?;
+                core.warn_if_reset_affects_other_cores();
+                core.state.invalidate_hw_breakpoints();
+                core.inner.reset()?;
+                core.after_reset()
+            },
+            |id, _| JournalOperation::Reset { core: id },
+        )
+    }
+
+    /// Reset the core, and then immediately halt. To continue execution after
+    /// reset, use the [`reset`] function.
+    ///
+    /// [`reset`]: Core::reset
+    ///
+    /// Depending on the target, this can also reset other cores of the chip.
+    /// In that case, [`Session::reset_system`] should be used instead.
+    ///
+    /// [`Session::reset_system`]: crate::Session::reset_system
+    ///
+    /// The wait for the halt returns at most one inner-operation quantum after `timeout`,
+    /// see [`Deadline`].
+    ///
+    /// Intrusiveness: [`Reset`](TargetOperation::Reset).
+    pub fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::Reset)?;
+                core.warn_if_reset_affects_other_cores();
+                core.reset_and_halt_within(timeout)
+            },
+            |id, _| JournalOperation::ResetAndHalt {
+                core: id,
+                timeout_ms: timeout.as_millis() as u64,
+            },
+        )
+    }
+
+    /// Reset the core and halt it by the deadline `timeout` from now.
+    fn reset_and_halt_within(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, error::Error> {
+        self.state.invalidate_hw_breakpoints();
+        let info = self.scoped_deadline(timeout, |core, deadline| {
+            core.inner.reset_and_halt(deadline.remaining())
+        })?;
+        self.after_reset()?;
+
+        // The core ran from the reset, so its halt is a new one.
+        self.state.halted = false;
+        self.record_halt(info)
+    }
+
+    /// Reset the core and halt it, as part of a reset coordinated by the [`Session`].
+    ///
+    /// [`Session`]: crate::Session
+    pub(crate) fn reset_and_halt_coordinated(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<CoreInformation, error::Error> {
+        self.require(TargetOperation::Reset)?;
+        self.reset_and_halt_within(timeout)
+    }
+
+    /// Reset the core and halt it, and determine where it halted relative to its reset vector.
+    ///
+    /// `roms` are the regions of the memory map which contain code of the chip vendor. If the
+    /// core was halted by a precise mechanism, but ran before it halted, the reset is repeated
+    /// once with a breakpoint at the reset vector.
+    pub(crate) fn reset_and_halt_report(
+        &mut self,
+        timeout: Duration,
+        roms: &[RomRegion],
+    ) -> Result<ResetHaltReport, error::Error> {
+        let mut mechanism = self.inner.reset_halt_mechanism();
+        let mut ineffective = Vec::new();
+
+        let mut pc = self.reset_and_halt(timeout)?.pc;
+
+        if !self.core_halted()? {
+            log::warn!("The core did not halt after the reset, halting it now.");
+            ineffective.push(mechanism);
+            mechanism = ResetHaltMechanism::HaltAfterReset;
+            pc = self.halt(timeout)?.pc;
+        }
+
+        let reset_vectors = self.reset_vectors()?;
+        let mut location = HaltLocation::from_pc(pc, &reset_vectors, roms);
+
+        if location == HaltLocation::Elsewhere && self.inner.reset_halt_mechanism().is_precise() {
+            log::warn!(
+                "The core halted at {:#010x} after the reset instead of the reset vector, retrying with a breakpoint at the reset vector.",
+                pc
+            );
+
+            if let Some(halted) = self.reset_and_halt_at_breakpoints(&reset_vectors, timeout)? {
+                ineffective.push(mechanism);
+
+                mechanism = if halted {
+                    ResetHaltMechanism::ResetVectorBreakpoint
+                } else {
+                    ineffective.push(ResetHaltMechanism::ResetVectorBreakpoint);
+                    ResetHaltMechanism::HaltAfterReset
+                };
+
+                pc = self.read_core_reg(self.registers().program_counter())?;
+                location = HaltLocation::from_pc(pc, &reset_vectors, roms);
+            }
+        }
+
+        let reset_vector = reset_vectors
+            .iter()
+            .find(|&&vector| vector == pc)
+            .or_else(|| reset_vectors.first())
+            .copied();
+
+        Ok(ResetHaltReport {
+            pc,
+            reset_vector,
+            location,
+            mechanism,
+            ineffective,
+        })
+    }
+
+    /// The addresses at which the core can start executing after a reset.
+    ///
+    /// For RISC-V cores, these are the reset vectors of the target description. Other cores
+    /// are asked for their reset vector.
+    fn reset_vectors(&mut self) -> Result<Vec<u64>, error::Error> {
+        match &self.state.core_access_options {
+            CoreAccessOptions::Riscv(options) => Ok(options.reset_vectors.clone()),
+            CoreAccessOptions::Arm(_) => Ok(self.inner.reset_vector()?.into_iter().collect()),
+        }
+    }
+
+    /// Reset the core with a breakpoint at each of the `reset_vectors`, and wait until it halts.
+    ///
+    /// Returns `None` if the breakpoints could not be set. Otherwise, the core is halted
+    /// afterwards, and the result is whether it was halted by a breakpoint.
+    fn reset_and_halt_at_breakpoints(
+        &mut self,
+        reset_vectors: &[u64],
+        timeout: Duration,
+    ) -> Result<Option<bool>, error::Error> {
+        let existing = self.cached_hw_breakpoints()?;
+
+        let mut added = Vec::new();
+        for &vector in reset_vectors {
+            if existing.contains(&Some(vector)) {
+                continue;
+            }
+
+            if let Err(error) = self.set_hw_breakpoint(vector) {
+                log::warn!(
+                    "Failed to set a breakpoint at the reset vector {:#010x}: {}",
+                    vector,
+                    error
+                );
+
+                for vector in added {
+                    self.clear_hw_breakpoint(vector)?;
+                }
+
+                return Ok(None);
+            }
+
+            added.push(vector);
+        }
+
+        self.reset()?;
+
+        let halted = match self.wait_for_core_halted(timeout) {
+            Ok(()) => true,
+            Err(error) if is_timeout(&error) => {
+                self.halt(timeout)?;
+                false
+            }
+            Err(error) => return Err(error),
+        };
+
+        for vector in added {
+            self.clear_hw_breakpoint(vector)?;
+        }
+
+        Ok(Some(halted))
+    }
+
+    fn warn_if_reset_affects_other_cores(&self) {
+        if self.state.reset_affects_other_cores {
+            log::warn!(
+                "Resetting core {} also resets other cores of the target. Use `Session::reset_system` to restore the state of all cores after the reset.",
+                self.state.id
+            );
+        }
+    }
+
+    /// Halt a core which ignores normal halt requests, escalating through the levels of
+    /// [`HaltEscalation`] up to `max_level`.
+    ///
+    /// The levels are tried in order, each with `timeout`, until the core halts. Each level
+    /// gives up at most one inner-operation quantum after its timeout, see [`Deadline`].
+    ///
+    /// The report states which level was needed, and whether the registers survived it: they
+    /// do for the halt requests of levels 1 and 2, but not for the resets of levels 3 and 4.
+    /// If the core doesn't halt with any of the allowed levels, [`Error::ForceHaltFailed`]
+    /// lists what was tried.
+    ///
+    /// Callers which must not lose the state of the core pass
+    /// [`HaltEscalation::PersistentHaltRequest`]. Level 4 needs control of the probe, so it is
+    /// only tried by [`Session::force_halt_core`](crate::Session::force_halt_core).
+    ///
+    /// Intrusiveness: [`Halt`](TargetOperation::Halt) for levels 1 and 2,
+    /// [`Reset`](TargetOperation::Reset) for the levels above.
+    pub fn force_halt(
+        &mut self,
+        timeout: Duration,
+        max_level: HaltEscalation,
+    ) -> Result<ForceHaltReport, error::Error> {
+        let mut attempts = Vec::new();
+
+        match self.escalate_halt(timeout, max_level, &mut attempts)? {
+            Some(level) => self.force_halt_report(level, attempts),
+            None => Err(Error::ForceHaltFailed { attempts }),
+        }
+    }
+
+    /// Try the levels of [`HaltEscalation`] up to `max_level`, except for the hardware reset,
+    /// and record them in `attempts`. Returns the level which halted the core.
+    pub(crate) fn escalate_halt(
+        &mut self,
+        timeout: Duration,
+        max_level: HaltEscalation,
+        attempts: &mut Vec<HaltAttempt>,
+    ) -> Result<Option<HaltEscalation>, error::Error> {
+        for level in HaltEscalation::ALL {
+            if level > max_level || level == HaltEscalation::HardwareReset {
+                break;
+            }
+
+            self.require(level.operation())?;
+
+            if !level.preserves_registers() {
+                self.warn_if_reset_affects_other_cores();
+                self.state.invalidate_hw_breakpoints();
+            }
+
+            let result = self.scoped_deadline(timeout, |core, deadline| match level {
+                HaltEscalation::HaltRequest => core.inner.halt(deadline.remaining()).map(|_| true),
+                _ => core.inner.escalate_halt(level, deadline.remaining()),
+            });
+
+            let attempt = match result {
+                Err(error @ (Error::Interrupted | Error::TargetLost(_))) => return Err(error),
+                result => HaltAttempt::from_result(level, result),
+            };
+
+            log::debug!("Force halt level {}: {:?}", level.level(), attempt.outcome);
+
+            let halted = attempt.outcome == HaltAttemptOutcome::Halted;
+            attempts.push(attempt);
+
+            if halted {
+                return Ok(Some(level));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The report of [`Core::force_halt`], once `level` halted the core.
+    pub(crate) fn force_halt_report(
+        &mut self,
+        level: HaltEscalation,
+        attempts: Vec<HaltAttempt>,
+    ) -> Result<ForceHaltReport, error::Error> {
+        let registers_preserved = level.preserves_registers();
+
+        if !registers_preserved {
+            self.after_reset()?;
+        }
+
+        let pc = self.read_core_reg(self.registers().program_counter())?;
+
+        Ok(ForceHaltReport {
+            level,
+            attempts,
+            pc,
+            registers_preserved,
+        })
+    }
+
+    /// Steps one instruction and then enters halted state again.
+    ///
+    /// If the core is halted at a software breakpoint, the instruction it replaced is
+    /// executed: the instruction is restored for the step, and the breakpoint is set again
+    /// afterwards.
+    ///
+    /// Intrusiveness: [`Step`](TargetOperation::Step).
+    pub fn step(&mut self) -> Result<CoreInformation, error::Error> {
+        self.step_with(|inner| inner.step())
+    }
+
+    /// Steps one instruction like [`Core::step`], for interactive stepping, where the latency
+    /// of each step matters.
+    ///
+    /// Where the architecture allows it, the accesses of the step are queued before a single
+    /// read, assuming that the core halts again right away. The read shows whether this held,
+    /// and if it didn't, e.g. because the core is sleeping, the step is completed like a
+    /// regular one. On a Cortex-M core, a probe which queues its accesses steps in a single
+    /// round trip. Other cores step like with [`Core::step`].
+    ///
+    /// The reason of the halt after the step is read with the next [`Core::status`].
+    ///
+    /// Intrusiveness: [`Step`](TargetOperation::Step).
+    pub fn step_fast(&mut self) -> Result<CoreInformation, error::Error> {
+        self.step_with(|inner| inner.step_fast())
+    }
+
+    fn step_with(
+        &mut self,
+        step: impl FnOnce(&mut (dyn CoreInterface + 'probe)) -> Result<CoreInformation, error::Error>,
+    ) -> Result<CoreInformation, error::Error> {
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::Step)?;
+                core.release_access_mediators()?;
+                core.discard_watchpoint_matches()?;
+                let info = core.step_over_sw_breakpoint(step)?;
+
+                // The core ran a single instruction, so its halt is a new one.
+                core.state.halted = false;
+                core.record_halt(info)
+            },
+            |id, _| JournalOperation::Step { core: id },
+        )
+    }
+
+    /// Run `step`, with the instruction a software breakpoint at the program counter replaced
+    /// restored for the step.
+    fn step_over_sw_breakpoint(
+        &mut self,
+        step: impl FnOnce(&mut (dyn CoreInterface + 'probe)) -> Result<CoreInformation, error::Error>,
+    ) -> Result<CoreInformation, error::Error> {
+        if self.state.sw_breakpoints.is_empty() {
+            return step(self.inner.as_mut());
+        }
+
+        let pc_id = self.registers().program_counter().id;
+        let pc: u64 = self.inner.read_core_reg(pc_id)?.try_into()?;
+
+        let breakpoint = match self.state.sw_breakpoints.get(&pc).cloned() {
+            Some(breakpoint) => breakpoint,
+            None => return step(self.inner.as_mut()),
+        };
+
+        self.write_8(pc, &breakpoint.original)?;
+        self.flush()?;
+
+        let info = step(self.inner.as_mut());
+
+        // The breakpoint is set again even if the step failed.
+        self.write_8(pc, &breakpoint.instruction)?;
+        self.flush()?;
+
+        info
+    }
+
+    /// Returns the current status of the core.
+    ///
+    /// A core which halted at a breakpoint on a panic handler reports
+    /// [`HaltReason::Panic`] instead of [`HaltReason::Breakpoint`], and a core which halted
+    /// at a software breakpoint set by probe-rs reports [`HaltReason::SoftwareBreakpoint`].
+    /// This requires reading the program counter, so it is only done if the session allows
+    /// to read registers.
+    ///
+    /// If the status shows that the core was reset by the target itself, e.g. by a watchdog,
+    /// the reset is recorded in the health log as [`HealthEvent::UnexpectedReset`], and the
+    /// peripheral freezes are applied again.
+    ///
+    /// Each halt which is detected starts a new [halt generation](Core::halt_generation).
+    ///
+    /// Intrusiveness: [`ReadStatus`](TargetOperation::ReadStatus).
+    pub fn status(&mut self) -> Result<CoreStatus, error::Error> {
+        // The status is polled, so neither it nor the registers it reads are recorded in the
+        // operation journal.
+        self.state.journal.suspend();
+        let status = self.read_status();
+        self.state.journal.leave();
+
+        status
+    }
+
+    /// The number of halts of the core which were detected during the session.
+    ///
+    /// The generation is increased each time the core is seen to enter the halted state,
+    /// whether it was halted by probe-rs, e.g. with [`Core::halt`] or [`Core::step`], or it
+    /// halted on its own, e.g. at a breakpoint or a vector catch, and the halt was seen in
+    /// [`Core::status`] or while waiting for the halt. A tool which polls the status can
+    /// compare the generation of two polls which both show the core halted, to tell whether
+    /// the core ran and halted again in between.
+    ///
+    /// The halts in between two polls are detected on a best effort basis: a Cortex-M core
+    /// records them in the sticky bits of DFSR, and for a RISC-V hart a change of the
+    /// cause in `dcsr` is detected. Other cores, and a RISC-V hart which halts again for
+    /// the same cause, can run and halt again unnoticed between two polls. The hits of a
+    /// breakpoint which are skipped, see [`BreakpointRequest::skip_count`], are not counted.
+    ///
+    /// This doesn't access the core.
+    pub fn halt_generation(&self) -> u64 {
+        self.state.halt_generation
+    }
+
+    /// Queue `command` for the probe, behind the accesses to the core issued before it and
+    /// in front of the ones issued after it, e.g. to toggle a GPIO of the probe at an exact
+    /// point of a sequence of writes.
+    ///
+    /// The command is opaque to probe-rs. Its response is returned by
+    /// [`Core::probe_command_response`] with the returned index. How the command is ordered
+    /// depends on the [`vendor_commands`](crate::ProbeCapabilities::vendor_commands)
+    /// capability of the probe: a probe which batches them sends the command with the other
+    /// queued transfers, and for a probe which executes them right away, the transfers
+    /// queued before the command are flushed first, which costs a round trip.
+    ///
+    /// This is only supported on ARM cores with a probe which executes vendor commands.
+    ///
+    /// Intrusiveness: [`VendorCommand`](TargetOperation::VendorCommand).
+    pub fn enqueue_probe_command(
+        &mut self,
+        command: VendorCommand,
+    ) -> Result<VendorCommandIndex, error::Error> {
+        self.require(TargetOperation::VendorCommand)?;
+        self.inner.enqueue_vendor_command(&command)
+    }
+
+    /// Flush the queued accesses, and return the response of the command queued with
+    /// [`Core::enqueue_probe_command`] at `index`.
+    ///
+    /// Intrusiveness: [`VendorCommand`](TargetOperation::VendorCommand).
+    pub fn probe_command_response(
+        &mut self,
+        index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, error::Error> {
+        self.require(TargetOperation::VendorCommand)?;
+        self.inner.vendor_command_response(index)
+    }
+
+    fn read_status(&mut self) -> Result<CoreStatus, error::Error> {
+        self.require(TargetOperation::ReadStatus)?;
+        let status = self.inner.status()?;
+
+        let was_halted = std::mem::replace(&mut self.state.halted, status.is_halted());
+        let halt_detected = self.inner.take_halt_detected();
+        let reset_detected = self.inner.take_reset_detected();
+        if reset_detected {
+            self.unexpected_reset(was_halted)?;
+        }
+
+        // A core which was halted before can only have halted again if it was seen to,
+        // which includes a halt by the reset vector catch after a reset.
+        let new_halt = status.is_halted() && (!was_halted || halt_detected || reset_detected);
+        if new_halt {
+            self.state.halt_generation += 1;
+        }
+
+        if new_halt && self.require(TargetOperation::ReadRegister).is_ok() {
+            self.derive_instruction_set()?;
+        }
+
+        if status != CoreStatus::Halted(HaltReason::Breakpoint)
+            || self.require(TargetOperation::ReadRegister).is_err()
+            || (self
+                .breakpoint_group_load_addresses(PANIC_BREAKPOINT_GROUP)
+                .is_empty()
+                && self.state.sw_breakpoints.is_empty())
+        {
+            return Ok(status);
+        }
+
+        let pc: u64 = self.read_core_reg(self.registers().program_counter())?;
+
+        if self
+            .breakpoint_group_load_addresses(PANIC_BREAKPOINT_GROUP)
+            .contains(&pc)
+        {
+            Ok(CoreStatus::Halted(HaltReason::Panic))
+        } else if self.state.sw_breakpoints.contains_key(&pc) {
+            Ok(CoreStatus::Halted(HaltReason::SoftwareBreakpoint))
+        } else {
+            Ok(status)
+        }
+    }
+
+    /// Read the value of a core register.
+    ///
+    /// # Errors
+    ///
+    /// If the register doesn't exist on this core, [`Error::RegisterNotAvailable`] is returned
+    /// without accessing the core.
+    ///
+    /// If `T` isn't large enough to hold the register value an error will be raised.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_core_reg<T>(&mut self, address: impl Into<RegisterId>) -> Result<T, error::Error>
+    where
+        RegisterValue: TryInto<T, Error = error::Error>,
+    {
+        self.require(TargetOperation::ReadRegister)?;
+        let address = address.into();
+        self.check_register_available(address)?;
+
+        self.read_core_reg_unchecked(address)
+    }
+
+    /// Read the value of a core register, without checking whether it exists on this core.
+    ///
+    /// Depending on the core, reading a register which doesn't exist returns an arbitrary
+    /// value or times out. This is intended for experiments during the bring-up of a core.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_core_reg_unchecked<T>(
+        &mut self,
+        address: impl Into<RegisterId>,
+    ) -> Result<T, error::Error>
+    where
+        RegisterValue: TryInto<T, Error = error::Error>,
+    {
+        let address = address.into();
+        let value = self.journaled(
+            |core| {
+                core.require(TargetOperation::ReadRegister)?;
+                core.inner.read_core_reg(address)
+            },
+            |id, value| JournalOperation::ReadRegister {
+                core: id,
+                register: address.0,
+                value: value.map(|value| JournalValue::from(*value)),
+            },
+        )?;
+
+        value.try_into()
+    }
+
+    /// Read the values of multiple core registers.
+    ///
+    /// On RISC-V, the reads are batched, which is considerably faster than reading the registers
+    /// one by one. ARM cores read the registers one by one, because every access to `DCRDR`
+    /// has to wait for `DHCSR.S_REGRDY`.
+    ///
+    /// # Errors
+    ///
+    /// If one of the registers doesn't exist on this core, [`Error::RegisterNotAvailable`] is
+    /// returned without accessing the core.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_core_regs(
+        &mut self,
+        addresses: &[RegisterId],
+    ) -> Result<Vec<RegisterValue>, error::Error> {
+        self.require(TargetOperation::ReadRegister)?;
+
+        for &address in addresses {
+            self.check_register_available(address)?;
+        }
+
+        self.inner.read_core_regs(addresses)
+    }
+
+    /// Read all registers of the core which exist on it, see [`RegisterFile::registers`].
+    ///
+    /// The registers are read with [`Core::read_core_regs`], which is a lot faster than reading
+    /// them one by one on a RISC-V hart.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_all_registers(&mut self) -> Result<Vec<(RegisterId, RegisterValue)>, Error> {
+        self.require(TargetOperation::ReadRegister)?;
+
+        let mut ids = Vec::new();
+        for register in self.registers().registers() {
+            if self.inner.register_available(register.id)? {
+                ids.push(register.id);
+            }
+        }
+
+        let values = self.inner.read_core_regs(&ids)?;
+
+        Ok(ids.into_iter().zip(values).collect())
+    }
+
+    /// Write the value of a core register.
+    ///
+    /// # Errors
+    ///
+    /// If the register doesn't exist on this core, [`Error::RegisterNotAvailable`] is returned
+    /// without accessing the core.
+    ///
+    /// If T is too large to write to the target register an error will be raised.
+    ///
+    /// Intrusiveness: [`WriteRegister`](TargetOperation::WriteRegister).
+    pub fn write_core_reg<T>(&mut self, address: RegisterId, value: T) -> Result<(), error::Error>
+    where
+        T: Into<RegisterValue>,
+    {
+        let value: RegisterValue = value.into();
+        self.journaled(
+            |core| {
+                core.require(TargetOperation::WriteRegister)?;
+                core.check_register_available(address)?;
+
+                // The register can select the instruction set, e.g. CPSR.T.
+                core.state.instruction_set_stale = true;
+                core.inner.write_core_reg(address, value)
+            },
+            |id, _| JournalOperation::WriteRegister {
+                core: id,
+                register: address.0,
+                value: JournalValue::from(value),
+            },
+        )
+    }
+
+    /// Write the values of multiple core registers, in order.
+    ///
+    /// On some architectures, this is considerably faster than writing the registers one by
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// If one of the registers doesn't exist on this core, [`Error::RegisterNotAvailable`] is
+    /// returned without accessing the core.
+    ///
+    /// Intrusiveness: [`WriteRegister`](TargetOperation::WriteRegister).
+    pub fn write_core_regs(
+        &mut self,
+        registers: &[(RegisterId, RegisterValue)],
+    ) -> Result<(), error::Error> {
+        self.require(TargetOperation::WriteRegister)?;
+
+        for &(address, _) in registers {
+            self.check_register_available(address)?;
+        }
+
+        self.state.instruction_set_stale = true;
+        self.inner.write_core_regs(registers)
+    }
+
+    /// Take a snapshot of the registers of the halted core, to restore them later with
+    /// [`Core::restore_snapshot`].
+    ///
+    /// The registers of [`RegisterFile::registers`] are read in one batch, see
+    /// [`Core::read_all_registers`], and the floating-point registers in another one, if
+    /// [`Core::fpu_support`] reports an FPU. Cores which can't detect an FPU are snapshotted
+    /// without the floating-point registers. To save the special registers and some memory
+    /// as well, or to persist the snapshot, use [`Core::save_context`].
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn take_snapshot(&mut self) -> Result<CoreSnapshot, Error> {
+        let registers = self.read_all_registers()?;
+
+        let pc_id = self.registers().program_counter().id;
+        let pc = match registers.iter().find(|(id, _)| *id == pc_id) {
+            Some(&(_, value)) => value,
+            None => self.read_core_regs(&[pc_id])?[0],
+        };
+
+        let file = self.registers();
+
+        // Cores which can't detect their FPU don't describe its registers either.
+        let fpu_present = match self.fpu_support() {
+            Ok(present) => present && file.fpu_registers().is_some(),
+            Err(Error::NotImplemented(_)) => false,
+            Err(error) => return Err(error),
+        };
+
+        let fpu_registers = if fpu_present {
+            let mut ids = Vec::new();
+            for register in file
+                .fpscr()
+                .into_iter()
+                .chain(file.fpu_registers().into_iter().flatten())
+            {
+                if self.inner.register_available(register.id)? {
+                    ids.push(register.id);
+                }
+            }
+
+            let values = self.read_core_regs(&ids)?;
+            Some(ids.into_iter().zip(values).collect())
+        } else {
+            None
+        };
+
+        Ok(CoreSnapshot {
+            registers,
+            fpu_registers,
+            pc,
+        })
+    }
+
+    /// Restore a `snapshot` taken with [`Core::take_snapshot`].
+    ///
+    /// The registers are written in batches, see [`Core::write_core_regs`], with the
+    /// program counter last, so that the core resumes where the snapshot was taken.
+    ///
+    /// Intrusiveness: [`WriteRegister`](TargetOperation::WriteRegister).
+    pub fn restore_snapshot(&mut self, snapshot: &CoreSnapshot) -> Result<(), Error> {
+        let pc_id = self.registers().program_counter().id;
+
+        let registers: Vec<(RegisterId, RegisterValue)> = snapshot
+            .registers
+            .iter()
+            .copied()
+            .filter(|(id, _)| *id != pc_id)
+            .collect();
+        self.write_core_regs(&registers)?;
+
+        if let Some(fpu_registers) = &snapshot.fpu_registers {
+            self.write_core_regs(fpu_registers)?;
+        }
+
+        self.write_core_regs(&[(pc_id, snapshot.pc)])
+    }
+
+    /// Save the registers of the core, and the ranges of memory in `memory`, so that they can
+    /// be restored later with [`Core::restore_context`].
+    ///
+    /// All registers of the core which exist on it are read in one batch, see
+    /// [`Core::read_core_regs`]. The core has to be halted. Only small ranges of memory should
+    /// be saved, e.g. the variables an experiment modifies.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn save_context(&mut self, memory: &[Range<u64>]) -> Result<ContextSnapshot, Error> {
+        let mut descriptions = Vec::new();
+
+        for description in self.registers().context_registers() {
+            if self.inner.register_available(description.id)? {
+                descriptions.push(description);
+            }
+        }
+
+        let ids: Vec<RegisterId> = descriptions.iter().map(|register| register.id).collect();
+        let values = self.read_core_regs(&ids)?;
+
+        let mut registers = Vec::with_capacity(values.len());
+
+        for (description, value) in descriptions.iter().zip(values) {
+            registers.push(SavedRegister {
+                id: description.id.0,
+                size_in_bits: description.size_in_bits as u16,
+                value: value.try_into()?,
+            });
+        }
+
+        let mut saved_memory = Vec::with_capacity(memory.len());
+
+        for range in memory {
+            let mut data = vec![0; range.end.saturating_sub(range.start) as usize];
+            self.read_8(range.start, &mut data)?;
+
+            saved_memory.push(SavedMemory {
+                address: range.start,
+                data,
+            });
+        }
+
+        Ok(ContextSnapshot::new(
+            self.core_type(),
+            registers,
+            saved_memory,
+        ))
+    }
+
+    /// Restore a `snapshot` taken with [`Core::save_context`].
+    ///
+    /// The memory is restored first, then the registers are written in one batch, in the
+    /// order of the snapshot. If the batch fails, the registers are written one by one.
+    /// Afterwards, everything is read back, and the registers and ranges of memory which
+    /// weren't restored are listed in the report.
+    ///
+    /// # Errors
+    ///
+    /// A snapshot whose checksum doesn't match is rejected with
+    /// [`Error::ContextSnapshotCorrupted`], and a snapshot of another type of core with
+    /// [`Error::ContextCoreMismatch`], before the core is accessed.
+    ///
+    /// Intrusiveness: [`WriteRegister`](TargetOperation::WriteRegister).
+    pub fn restore_context(
+        &mut self,
+        snapshot: &ContextSnapshot,
+    ) -> Result<ContextRestoreReport, Error> {
+        snapshot.verify()?;
+
+        if snapshot.core_type() != self.core_type() {
+            return Err(Error::ContextCoreMismatch {
+                snapshot: snapshot.core_type(),
+                core: self.core_type(),
+            });
+        }
+
+        self.require(TargetOperation::WriteRegister)?;
+        self.require(TargetOperation::WriteMemory)?;
+
+        let mut report = ContextRestoreReport::default();
+
+        for range in snapshot.memory() {
+            self.write_8(range.address, &range.data)?;
+        }
+
+        let writes: Vec<(RegisterId, RegisterValue)> = snapshot
+            .registers()
+            .iter()
+            .map(|register| (RegisterId(register.id), register.register_value()))
+            .collect();
+
+        let mut written = Vec::with_capacity(writes.len());
+
+        if let Err(error) = self.write_core_regs(&writes) {
+            log::debug!(
+                "Restoring the registers in one batch failed ({}), writing them one by one",
+                error
+            );
+
+            for (register, &(id, value)) in snapshot.registers().iter().zip(&writes) {
+                match self.write_core_reg(id, value) {
+                    Ok(()) => written.push(register),
+                    Err(error) => report.registers.push(RegisterRestoreFailure {
+                        id,
+                        expected: register.value,
+                        failure: RestoreFailure::WriteFailed(error.to_string()),
+                    }),
+                }
+            }
+        } else {
+            written.extend(snapshot.registers());
+        }
+
+        let ids: Vec<RegisterId> = written
+            .iter()
+            .map(|register| RegisterId(register.id))
+            .collect();
+
+        // If the batch fails, read the registers one by one to find the ones which fail.
+        let values: Vec<Result<u64, Error>> = match self.read_core_regs(&ids) {
+            Ok(values) => values.into_iter().map(|value| value.try_into()).collect(),
+            Err(_) => ids.iter().map(|&id| self.read_core_reg(id)).collect(),
+        };
+
+        for (register, value) in written.into_iter().zip(values) {
+            let id = RegisterId(register.id);
+
+            let failure = match value {
+                Ok(actual) => {
+                    let writable_mask = self
+                        .registers()
+                        .find(id)
+                        .map_or(u64::MAX, |description| description.writable_mask);
+
+                    RestoreFailure::compare(
+                        register.value,
+                        actual,
+                        register.size_in_bits,
+                        writable_mask,
+                    )
+                }
+                Err(error) => Some(RestoreFailure::ReadBackFailed(error.to_string())),
+            };
+
+            if let Some(failure) = failure {
+                report.registers.push(RegisterRestoreFailure {
+                    id,
+                    expected: register.value,
+                    failure,
+                });
+            }
+        }
+
+        for range in snapshot.memory() {
+            let mut data = vec![0; range.data.len()];
+            self.read_8(range.address, &mut data)?;
+
+            if data != range.data {
+                report.memory.push(range.address);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Return [`Error::RegisterNotAvailable`] if the register `address` doesn't exist on
+    /// this core.
+    fn check_register_available(&mut self, address: RegisterId) -> Result<(), error::Error> {
+        if self.inner.register_available(address)? {
+            return Ok(());
+        }
+
+        Err(Error::RegisterNotAvailable {
+            name: self.register_name(address),
+            core_variant: format!("{:?}", self.core_type()),
+        })
+    }
+
+    /// The name of the register `address`, or a name derived from its number if it isn't
+    /// part of the register file of the core.
+    fn register_name(&self, address: RegisterId) -> String {
+        if let Some(register) = self.registers().find(address) {
+            return register.name().to_owned();
+        }
+
+        match (self.architecture(), address.0) {
+            (Architecture::Riscv, n @ 0x0000..=0x0fff) => format!("csr {:#05x}", n),
+            (Architecture::Riscv, n @ 0x1000..=0x101f) => format!("x{}", n - 0x1000),
+            (Architecture::Riscv, n @ 0x1020..=0x103f) => format!("f{}", n - 0x1020),
+            (_, n) => format!("register {:#x}", n),
+        }
+    }
+
+    /// Map the link addresses `link_range` of the firmware to the addresses starting at
+    /// `load_base`, at which the core executes them.
+    ///
+    /// This is needed if the firmware is relocated, e.g. copied to RAM by a bootloader, or
+    /// executed at an offset in flash. The symbol-based APIs, like
+    /// [`Session::break_on_panic`](crate::Session::break_on_panic) and
+    /// [`Core::set_hw_breakpoint_at_link_address`], and
+    /// [`Session::validate_image`](crate::Session::validate_image) translate the addresses of
+    /// the ELF file with these mappings. Other methods take load addresses, unless
+    /// [`Core::set_translate_memory_accesses`] is enabled for memory accesses.
+    ///
+    /// Several link ranges can be mapped, but they must not overlap.
+    pub fn set_load_offset(
+        &mut self,
+        link_range: Range<u64>,
+        load_base: u64,
+    ) -> Result<(), error::Error> {
+        self.state.address_map.insert(link_range, load_base)
+    }
+
+    /// Remove all mappings set with [`Core::set_load_offset`].
+    pub fn clear_load_offsets(&mut self) {
+        self.state.address_map.clear();
+    }
+
+    /// Returns the mappings between link and load addresses set with [`Core::set_load_offset`].
+    pub fn address_map(&self) -> &AddressMap {
+        &self.state.address_map
+    }
+
+    /// Translate the addresses of the memory accesses through the [`MemoryInterface`] of this
+    /// core from link to load addresses, so that the data of the ELF file can be accessed at
+    /// its link addresses.
+    ///
+    /// This is disabled by default. While it is enabled, load addresses which are in a link
+    /// range of the address map can't be accessed.
+    pub fn set_translate_memory_accesses(&mut self, translate: bool) {
+        self.state.translate_memory_accesses = translate;
+    }
+
+    /// Initialize the ECC of the memory in `range` by writing `pattern` to all of its words.
+    ///
+    /// The range is widened to whole words. Writing less than a word to memory which is
+    /// protected by ECC faults until the word was written as a whole, so the cores widen such
+    /// writes with a read-modify-write of the word, which is only done for the words which
+    /// were initialized during the session. The scratch memory of routines and flash
+    /// algorithms, and the RAM sections of loaded images are initialized automatically.
+    pub fn ecc_initialize(&mut self, range: Range<u64>, pattern: u32) -> Result<(), Error> {
+        let words = ecc::word_aligned(range);
+        let data = vec![pattern; ((words.end - words.start) / ecc::ECC_WORD_SIZE) as usize];
+
+        self.write_32(words.start, &data)
+    }
+
+    /// Record that the ECC of the memory in `range` was initialized outside of the session,
+    /// e.g. by the firmware, so that writes of less than a word to it are done with a
+    /// read-modify-write, see [`Core::ecc_initialize`].
+    pub fn ecc_mark_initialized(&mut self, range: Range<u64>) {
+        let start = self.memory_address(range.start);
+        let end = start + (range.end - range.start);
+
+        self.state.ecc_memory.mark_initialized(start..end);
+    }
+
+    /// Initialize the words of `range` which are protected by ECC and weren't initialized
+    /// yet, before `range` is leased as scratch memory.
+    pub(crate) fn prepare_ecc_lease(&mut self, range: Range<u64>) -> Result<(), Error> {
+        for words in self.state.ecc_memory.uninitialized(range) {
+            self.ecc_initialize(words, 0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Initialize the words which are protected by ECC and are only partially covered by a
+    /// write of `len` bytes at `address`, so that the write can be widened to them.
+    pub(crate) fn prepare_ecc_write(&mut self, address: u64, len: usize) -> Result<(), Error> {
+        for word in ecc::partial_words(address, len) {
+            if !self.state.ecc_memory.uninitialized(word.clone()).is_empty() {
+                self.ecc_initialize(word, 0)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the retry policy of the memory accesses of the core, which is the policy of the
+    /// session unless it is overridden with [`Core::with_retry_policy`].
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.state.retry_policy
+    }
+
+    /// Run `f` with `policy` as the retry policy of the memory accesses of the core.
+    ///
+    /// Only reads of RAM and flash, and writes to RAM are retried, as accesses to device
+    /// memory can have side effects. Block transfers are retried in chunks, so that a single
+    /// failed chunk doesn't repeat the whole transfer. The policy of the session, see
+    /// [`AttachOptions::retry_policy`](crate::AttachOptions::retry_policy), is restored
+    /// when `f` returns.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use probe_rs::{MemoryInterface, Permissions, RetryPolicy, Session};
+    /// # let mut session = Session::auto_attach("nrf51822", Permissions::default())?;
+    /// let mut core = session.core(0)?;
+    ///
+    /// // Drop a sample of a watched variable instead of waiting for it.
+    /// let sample = core.with_retry_policy(RetryPolicy::watch_window(), |core| {
+    ///     core.read_word_32(0x2000_0000)
+    /// });
+    /// # Ok::<(), probe_rs::Error>(())
+    /// ```
+    pub fn with_retry_policy<R>(
+        &mut self,
+        policy: RetryPolicy,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let previous = std::mem::replace(&mut self.state.retry_policy, policy);
+        let result = f(self);
+        self.state.retry_policy = previous;
+
+        result
+    }
+
+    /// Returns all the available breakpoint units of the core.
+    pub fn available_breakpoint_units(&mut self) -> Result<u32, error::Error> {
+        Ok(self.cached_hw_breakpoints()?.len() as u32)
+    }
+
+    /// Enables breakpoints on this core. If a breakpoint is set, it will halt as soon as it is hit.
+    fn enable_breakpoints(&mut self, state: bool) -> Result<(), error::Error> {
+        self.inner.enable_breakpoints(state)
+    }
+
+    /// Returns a list of all the registers of this core.
+    pub fn registers(&self) -> &'static RegisterFile {
+        self.inner.registers()
+    }
+
+    /// Returns the hardware breakpoint comparators, which are read from the core on first use.
+    ///
+    /// Afterwards, the cache is updated when probe-rs sets or clears a breakpoint, and is only
+    /// read again after events which can change the comparators, like a reset.
+    ///
+    /// If the watchpoints share the comparators with the breakpoints, the comparators from the
+    /// first one used by a watchpoint on are left out, see [`Core::set_watchpoint`].
+    fn cached_hw_breakpoints(&mut self) -> Result<Vec<Option<u64>>, error::Error> {
+        let mut breakpoints = match &self.state.hw_breakpoints {
+            Some(breakpoints) => breakpoints.clone(),
+            None => {
+                let breakpoints = self.inner.hw_breakpoints()?;
+                self.state.hw_breakpoints = Some(breakpoints.clone());
+                breakpoints
+            }
+        };
+
+        if self.inner.watchpoints_share_breakpoint_units() {
+            if let Some(unit) = self.watchpoint_units().into_iter().min() {
+                breakpoints.truncate(unit);
+            }
+        }
+
+        Ok(breakpoints)
+    }
+
+    /// Read the hardware breakpoint comparators from the core again.
+    ///
+    /// The comparators are cached, so this has to be called if they might have been
+    /// changed by something else than probe-rs, e.g. by the firmware.
+    pub fn refresh_breakpoints(&mut self) -> Result<(), error::Error> {
+        self.state.invalidate_hw_breakpoints();
+        self.cached_hw_breakpoints()?;
+
+        Ok(())
+    }
+
+    /// Set a hardware breakpoint
+    ///
+    /// This function will try to set a hardware breakpoint att `address`.
+    ///
+    /// The amount of hardware breakpoints which are supported is chip specific,
+    /// and can be queried using the `get_available_breakpoint_units` function.
+    ///
+    /// Intrusiveness: [`HardwareBreakpoint`](TargetOperation::HardwareBreakpoint).
+    pub fn set_hw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.journaled(
+            |core| core.set_hw_breakpoint_unjournaled(address),
+            |id, _| JournalOperation::SetHwBreakpoint {
+                core: id,
+                address: JournalValue::Literal(address),
+            },
+        )
+    }
+
+    fn set_hw_breakpoint_unjournaled(&mut self, address: u64) -> Result<(), error::Error> {
+        self.require(TargetOperation::HardwareBreakpoint)?;
+
+        if !self.inner.hw_breakpoints_enabled() {
+            self.enable_breakpoints(true)?;
+        }
+
+        if let Err(error) = self.set_hw_breakpoint_with_cache(address) {
+            // The cache might be stale, so read the comparators again before giving up.
+            log::debug!(
+                "Setting HW breakpoint at {:#010x} failed: {}. Retrying with refreshed breakpoints.",
+                address,
+                error
+            );
+
+            self.refresh_breakpoints()?;
+            self.set_hw_breakpoint_with_cache(address)?;
+        }
+
+        self.state
+            .errata
+            .after_breakpoint_set(&mut self.inner.as_mut(), address)
+    }
+
+    fn set_hw_breakpoint_with_cache(&mut self, address: u64) -> Result<(), error::Error> {
+        let breakpoints = self.cached_hw_breakpoints()?;
+
+        // If there is a breakpoint set already, return its bp_unit_index, else find the next free index.
+        let breakpoint_comparator_index = breakpoints
+            .iter()
+            .position(|&bp| bp == Some(address))
+            .or_else(|| breakpoints.iter().position(Option::is_none))
+            .ok_or(error::Error::HardwareBreakpointsExhausted)?;
+
+        log::debug!(
+            "Trying to set HW breakpoint #{} with comparator address  {:#08x}",
+            breakpoint_comparator_index,
+            address
+        );
+
+        // Actually set the breakpoint. Even if it has been set, set it again so it will be active.
+        if let Err(error) = self
+            .inner
+            .set_hw_breakpoint(breakpoint_comparator_index, address)
+        {
+            self.state.invalidate_hw_breakpoints();
+            return Err(error);
+        }
+
+        if let Some(breakpoints) = &mut self.state.hw_breakpoints {
+            breakpoints[breakpoint_comparator_index] = Some(address);
+        }
+
+        Ok(())
+    }
+
+    /// Set a hardware breakpoint
+    ///
+    /// This function will try to clear a hardware breakpoint at `address` if there exists a breakpoint at that address.
+    ///
+    /// Intrusiveness: [`HardwareBreakpoint`](TargetOperation::HardwareBreakpoint).
+    pub fn clear_hw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.journaled(
+            |core| core.clear_hw_breakpoint_unjournaled(address),
+            |id, _| JournalOperation::ClearHwBreakpoint {
+                core: id,
+                address: JournalValue::Literal(address),
+            },
+        )
+    }
+
+    fn clear_hw_breakpoint_unjournaled(&mut self, address: u64) -> Result<(), error::Error> {
+        self.require(TargetOperation::HardwareBreakpoint)?;
+
+        let mut bp_position = self
+            .cached_hw_breakpoints()?
+            .iter()
+            .position(|&bp| bp == Some(address));
+
+        if bp_position.is_none() {
+            // The breakpoint might have been set behind our back.
+            self.refresh_breakpoints()?;
+            bp_position = self
+                .cached_hw_breakpoints()?
+                .iter()
+                .position(|&bp| bp == Some(address));
+        }
+
+        log::debug!(
+            "Will clear HW breakpoint    #{} with comparator address    {:#08x}",
+            bp_position.unwrap_or(usize::MAX),
+            address
+        );
+
+        match bp_position {
+            Some(bp_position) => self.clear_hw_breakpoint_unit(bp_position),
+            None => Err(error::Error::BreakpointNotFound(address)),
+        }
+    }
+
+    fn clear_hw_breakpoint_unit(&mut self, unit_index: usize) -> Result<(), error::Error> {
+        if let Err(error) = self.inner.clear_hw_breakpoint(unit_index) {
+            self.state.invalidate_hw_breakpoints();
+            return Err(error);
+        }
+
+        if let Some(breakpoints) = &mut self.state.hw_breakpoints {
+            if let Some(address) = breakpoints[unit_index].take() {
+                self.state.breakpoint_link_addresses.remove(&address);
+                self.state.breakpoint_skip_counts.remove(&address);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a hardware breakpoint at the address `link_address` from the ELF file of the
+    /// firmware is executed at, and return that load address.
+    ///
+    /// The address is translated with the address map of the core, see
+    /// [`Core::set_load_offset`]. The link address is kept, and reported by
+    /// [`Core::breakpoint_link_address`] and [`Core::breakpoint_group`].
+    pub fn set_hw_breakpoint_at_link_address(
+        &mut self,
+        link_address: u64,
+    ) -> Result<u64, error::Error> {
+        let load_address = self.state.address_map.to_load_address(link_address);
+
+        self.set_hw_breakpoint(load_address)?;
+        self.state
+            .breakpoint_link_addresses
+            .insert(load_address, link_address);
+
+        Ok(load_address)
+    }
+
+    /// Returns the link address of the breakpoint at `load_address`, e.g. of the program
+    /// counter of a core which halted at a breakpoint.
+    ///
+    /// This is the address the breakpoint was set with by
+    /// [`Core::set_hw_breakpoint_at_link_address`], or the address translated with the
+    /// address map of the core for other addresses.
+    pub fn breakpoint_link_address(&self, load_address: u64) -> u64 {
+        match self.state.breakpoint_link_addresses.get(&load_address) {
+            Some(&link_address) => link_address,
+            None => self.state.address_map.to_link_address(load_address),
+        }
+    }
+
+    /// Returns the link addresses of the hardware breakpoints in the breakpoint group `name`.
+    ///
+    /// The addresses are the same as the addresses the breakpoints are set at, unless the
+    /// firmware is loaded at other addresses, see [`Core::set_load_offset`].
+    pub fn breakpoint_group(&self, name: &str) -> Vec<u64> {
+        self.breakpoint_group_load_addresses(name)
+            .iter()
+            .map(|&address| self.breakpoint_link_address(address))
+            .collect()
+    }
+
+    /// Returns the addresses the hardware breakpoints in the breakpoint group `name` are set at.
+    fn breakpoint_group_load_addresses(&self, name: &str) -> &[u64] {
+        self.state
+            .breakpoint_groups
+            .get(name)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Add the hardware breakpoint at the load address `address` to the breakpoint group `name`.
+    pub(crate) fn add_to_breakpoint_group(&mut self, name: &str, address: u64) {
+        let group = self
+            .state
+            .breakpoint_groups
+            .entry(name.to_owned())
+            .or_default();
+
+        if !group.contains(&address) {
+            group.push(address);
+        }
+    }
+
+    /// Clear the hardware breakpoints in the breakpoint group `name`, and remove the group.
+    ///
+    /// Breakpoints which were already cleared, e.g. with [`Core::clear_hw_breakpoint`],
+    /// are skipped.
+    ///
+    /// Intrusiveness: [`HardwareBreakpoint`](TargetOperation::HardwareBreakpoint).
+    pub fn clear_breakpoint_group(&mut self, name: &str) -> Result<(), error::Error> {
+        self.require(TargetOperation::HardwareBreakpoint)?;
+
+        let addresses = match self.state.breakpoint_groups.remove(name) {
+            Some(addresses) => addresses,
+            None => return Ok(()),
+        };
+
+        for address in addresses {
+            let unit_index = self
+                .cached_hw_breakpoints()?
+                .iter()
+                .position(|&bp| bp == Some(address));
+
+            if let Some(unit_index) = unit_index {
+                self.clear_hw_breakpoint_unit(unit_index)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the addresses of all hardware breakpoints which are currently set.
+    ///
+    /// A value of `None` indicates that the breakpoint unit at that position is unused.
+    pub(crate) fn hw_breakpoints(&mut self) -> Result<Vec<Option<u64>>, error::Error> {
+        self.cached_hw_breakpoints()
+    }
+
+    /// Clear all hardware breakpoints
+    ///
+    /// This function will clear all HW breakpoints which are configured on the target,
+    /// regardless if they are set by probe-rs, AND regardless if they are enabled or not.
+    /// Also used as a helper function in [`Session::drop`](crate::session::Session).
+    ///
+    /// Intrusiveness: [`HardwareBreakpoint`](TargetOperation::HardwareBreakpoint).
+    pub fn clear_all_hw_breakpoints(&mut self) -> Result<(), error::Error> {
+        self.require(TargetOperation::HardwareBreakpoint)?;
+
+        self.state.breakpoint_groups.clear();
+        self.state.breakpoint_link_addresses.clear();
+        self.refresh_breakpoints()?;
+
+        for (unit_index, breakpoint) in self.cached_hw_breakpoints()?.into_iter().enumerate() {
+            if breakpoint.is_some() {
+                self.clear_hw_breakpoint_unit(unit_index)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the hardware breakpoint comparator `unit_index` to `address`.
+    ///
+    /// Fails if the comparator is in use, e.g. because the plan it was assigned in is stale.
+    fn set_hw_breakpoint_unit(
+        &mut self,
+        unit_index: usize,
+        address: u64,
+    ) -> Result<(), error::Error> {
+        if let Some(Some(existing)) = self.cached_hw_breakpoints()?.get(unit_index) {
+            return Err(error::Error::HardwareBreakpointInUse {
+                unit: unit_index,
+                address: *existing,
+            });
+        }
+
+        if let Err(error) = self.inner.set_hw_breakpoint(unit_index, address) {
+            self.state.invalidate_hw_breakpoints();
+            return Err(error);
+        }
+
+        if let Some(breakpoints) = &mut self.state.hw_breakpoints {
+            breakpoints[unit_index] = Some(address);
+        }
+
+        self.state
+            .errata
+            .after_breakpoint_set(&mut self.inner.as_mut(), address)
+    }
+
+    /// The alignment of the instructions of a RISC-V core: 2 bytes with the C extension, and
+    /// 4 bytes without it. If `misa` can't be read, or isn't implemented, the C extension is
+    /// assumed.
+    fn riscv_instruction_alignment(&mut self) -> u64 {
+        match self.read_core_reg::<u32>(RegisterId(MISA)) {
+            Ok(misa) if misa != 0 && misa & MISA_C == 0 => 4,
+            _ => 2,
+        }
+    }
+
+    /// Returns the breakpoint instruction which replaces the instruction at `address`.
+    ///
+    /// The instruction set is the one of the code at `address`: on cores which switch
+    /// between A32 and Thumb, code which isn't aligned to 4 bytes is Thumb code. The width of
+    /// the breakpoint instruction is selected by the instruction at `address`, see
+    /// [`instruction::breakpoint_instruction`].
+    fn breakpoint_instruction(&mut self, address: u64) -> Result<Vec<u8>, error::Error> {
+        let instruction_set = match self.instruction_set()? {
+            InstructionSet::A32 if address & 0b11 != 0 => InstructionSet::Thumb2,
+            instruction_set => instruction_set,
+        };
+
+        let mut first_halfword = [0u8; 2];
+        self.read_8(address, &mut first_halfword)?;
+
+        Ok(
+            instruction::breakpoint_instruction(
+                instruction_set,
+                u16::from_le_bytes(first_halfword),
+            )
+            .to_vec(),
+        )
+    }
+
+    /// Set a software breakpoint at `address`, which has to be in RAM.
+    ///
+    /// The instruction at `address` is replaced by a breakpoint instruction, and restored when
+    /// the breakpoint is cleared. To resume a core which halted at a software breakpoint, the
+    /// breakpoint has to be cleared first, otherwise the core halts at it again. A
+    /// [step](Core::step) executes the replaced instruction, and keeps the breakpoint.
+    ///
+    /// On RISC-V cores, `dcsr` is configured so that the breakpoint instruction halts the core,
+    /// so the core has to be halted. `address` has to be aligned to 2 bytes, or to 4 bytes on
+    /// cores without the C extension.
+    ///
+    /// Intrusiveness: [`SoftwareBreakpoint`](TargetOperation::SoftwareBreakpoint).
+    pub fn set_sw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.require(TargetOperation::SoftwareBreakpoint)?;
+
+        if self.state.sw_breakpoints.contains_key(&address) {
+            return Ok(());
+        }
+
+        if !self
+            .state
+            .ram_ranges
+            .iter()
+            .any(|range| range.contains(&address))
+        {
+            return Err(error::Error::SoftwareBreakpointNotInRam(address));
+        }
+
+        if self.architecture() == Architecture::Riscv {
+            if address % self.riscv_instruction_alignment() != 0 {
+                return Err(error::Error::UnsupportedBreakpointAddress {
+                    address,
+                    reason: "the address is not aligned to an instruction of this core",
+                });
+            }
+
+            // Ensure ebreak enters debug mode in all privilege modes.
+            let dcsr: u32 = self.read_core_reg(RegisterId(DCSR))?;
+            self.write_core_reg(RegisterId(DCSR), dcsr | (1 << 15) | (1 << 13) | (1 << 12))?;
+        }
+
+        let instruction = self.breakpoint_instruction(address)?;
+
+        let mut original = vec![0u8; instruction.len()];
+        self.read_8(address, &mut original)?;
+        self.write_8(address, &instruction)?;
+        self.flush()?;
+
+        log::debug!("Set SW breakpoint at {:#010x}", address);
+
+        self.state.sw_breakpoints.insert(
+            address,
+            SoftwareBreakpoint {
+                original,
+                instruction,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Clear the software breakpoint at `address`, and restore the instruction it replaced.
+    ///
+    /// Intrusiveness: [`SoftwareBreakpoint`](TargetOperation::SoftwareBreakpoint).
+    pub fn clear_sw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.require(TargetOperation::SoftwareBreakpoint)?;
+
+        let breakpoint = self
+            .state
+            .sw_breakpoints
+            .get(&address)
+            .cloned()
+            .ok_or(error::Error::BreakpointNotFound(address))?;
+
+        self.write_8(address, &breakpoint.original)?;
+        self.flush()?;
+
+        log::debug!("Cleared SW breakpoint at {:#010x}", address);
+
+        self.state.sw_breakpoints.remove(&address);
+        self.state.breakpoint_skip_counts.remove(&address);
+
+        Ok(())
+    }
+
+    /// Returns the addresses of the software breakpoints which are set.
+    pub fn sw_breakpoints(&self) -> Vec<u64> {
+        self.state.sw_breakpoints.keys().copied().collect()
+    }
+
+    /// Clear all software breakpoints set by probe-rs.
+    pub fn clear_all_sw_breakpoints(&mut self) -> Result<(), error::Error> {
+        for address in self.sw_breakpoints() {
+            self.clear_sw_breakpoint(address)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decide how each of the `requests` would be set by [`Core::apply_breakpoints`], without
+    /// changing any breakpoint.
+    ///
+    /// This can be used to show the user which breakpoints will be software breakpoints, or
+    /// can't be set at all, before applying them with [`Core::apply_breakpoint_plan`].
+    ///
+    /// No software breakpoints are planned if the maximum intrusiveness of the session doesn't
+    /// allow them.
+    pub fn plan_breakpoints(
+        &mut self,
+        requests: &[BreakpointRequest],
+    ) -> Result<BreakpointPlan, error::Error> {
+        let comparators = self.cached_hw_breakpoints()?;
+
+        Ok(breakpoints::plan(
+            requests,
+            &comparators,
+            &self.sw_breakpoints(),
+            &self.state.ram_ranges,
+            self.require(TargetOperation::SoftwareBreakpoint).is_ok(),
+        ))
+    }
+
+    /// Set all of the `requests`, or none of them.
+    ///
+    /// The whole set is planned with [`Core::plan_breakpoints`] first, and nothing is changed if
+    /// any request can't be set. Otherwise the breakpoints are set, and if setting one of them
+    /// fails, the breakpoints which were set by this call are removed again.
+    ///
+    /// The report contains the outcome of each request. An error is only returned if the
+    /// comparators can't be read, or if removing the breakpoints after a failure fails,
+    /// in which case the breakpoints are in an unknown state.
+    pub fn apply_breakpoints(
+        &mut self,
+        requests: &[BreakpointRequest],
+    ) -> Result<BreakpointApplyReport, error::Error> {
+        let plan = self.plan_breakpoints(requests)?;
+        self.apply_breakpoint_plan(&plan)
+    }
+
+    /// Apply a plan created with [`Core::plan_breakpoints`], see [`Core::apply_breakpoints`].
+    ///
+    /// If a hardware breakpoint comparator the plan assigned was used in the meantime,
+    /// setting that breakpoint fails, and the plan is rolled back.
+    ///
+    /// If the plan sets a breakpoint with a mechanism the maximum intrusiveness of the session
+    /// doesn't allow, [`Error::IntrusivenessExceeded`] is returned before any breakpoint is set.
+    pub fn apply_breakpoint_plan(
+        &mut self,
+        plan: &BreakpointPlan,
+    ) -> Result<BreakpointApplyReport, error::Error> {
+        let planned = plan.requests.iter().zip(&plan.breakpoints);
+
+        for (_, breakpoint) in planned.clone() {
+            match breakpoint {
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Hardware { .. },
+                } => self.require(TargetOperation::HardwareBreakpoint)?,
+                PlannedBreakpoint::Set {
+                    mechanism: BreakpointMechanism::Software,
+                } => self.require(TargetOperation::SoftwareBreakpoint)?,
+                _ => {}
+            }
+        }
+
+        let mut set = Vec::new();
+        let mut failure = None;
+
+        if plan.is_satisfiable() {
+            for (index, (request, breakpoint)) in planned.clone().enumerate() {
+                let mechanism = match breakpoint {
+                    PlannedBreakpoint::Set { mechanism } => *mechanism,
+                    _ => continue,
+                };
+
+                if let Err(error) = self.set_planned_breakpoint(request.address, mechanism) {
+                    log::warn!(
+                        "Failed to set breakpoint at {:#010x}: {}. Removing the {} breakpoints set before it.",
+                        request.address,
+                        error,
+                        set.len()
+                    );
+
+                    failure = Some((index, error.to_string()));
+                    break;
+                }
+
+                set.push((request.address, mechanism));
+            }
+        }
+
+        if failure.is_some() {
+            for &(address, mechanism) in set.iter().rev() {
+                match mechanism {
+                    BreakpointMechanism::Hardware { .. } => self.clear_hw_breakpoint(address)?,
+                    BreakpointMechanism::Software => self.clear_sw_breakpoint(address)?,
+                }
+            }
+        }
+
+        let aborted = failure.is_some() || !plan.is_satisfiable();
+
+        let outcomes = planned
+            .enumerate()
+            .map(|(index, (_, breakpoint))| match breakpoint {
+                PlannedBreakpoint::Set { mechanism } if !aborted => BreakpointOutcome::Applied {
+                    mechanism: *mechanism,
+                },
+                PlannedBreakpoint::Set { .. } => BreakpointOutcome::Failed {
+                    reason: match &failure {
+                        Some((failed, error)) if *failed == index => {
+                            BreakpointFailure::Error(error.clone())
+                        }
+                        _ => BreakpointFailure::Aborted,
+                    },
+                },
+                PlannedBreakpoint::Existing { mechanism } => BreakpointOutcome::Applied {
+                    mechanism: *mechanism,
+                },
+                PlannedBreakpoint::Duplicate { duplicate_of } => BreakpointOutcome::Skipped {
+                    duplicate_of: *duplicate_of,
+                },
+                PlannedBreakpoint::Unsatisfiable { reason } => BreakpointOutcome::Failed {
+                    reason: reason.clone(),
+                },
+            })
+            .collect();
+
+        if !aborted {
+            for (request, breakpoint) in plan.requests.iter().zip(&plan.breakpoints) {
+                if matches!(breakpoint, PlannedBreakpoint::Duplicate { .. }) {
+                    continue;
+                }
+
+                if request.skip_count > 0 {
+                    self.state.breakpoint_skip_counts.insert(
+                        request.address,
+                        BreakpointSkipCount {
+                            remaining: request.skip_count,
+                            skipped: 0,
+                        },
+                    );
+                } else {
+                    self.state.breakpoint_skip_counts.remove(&request.address);
+                }
+            }
+        }
+
+        Ok(BreakpointApplyReport { outcomes })
+    }
+
+    /// Returns the skip count of the breakpoint at `address`, if it was set with one, see
+    /// [`BreakpointRequest::skip_count`].
+    ///
+    /// The skip count is kept after the last hit was skipped, until the breakpoint is cleared.
+    pub fn breakpoint_skip_count(&self, address: u64) -> Option<BreakpointSkipCount> {
+        self.state.breakpoint_skip_counts.get(&address).copied()
+    }
+
+    fn set_planned_breakpoint(
+        &mut self,
+        address: u64,
+        mechanism: BreakpointMechanism,
+    ) -> Result<(), error::Error> {
+        match mechanism {
+            BreakpointMechanism::Hardware { unit } => {
+                if !self.inner.hw_breakpoints_enabled() {
+                    self.enable_breakpoints(true)?;
+                }
+
+                self.set_hw_breakpoint_unit(unit, address)
+            }
+            BreakpointMechanism::Software => self.set_sw_breakpoint(address),
+        }
+    }
+
+    /// Returns the number of data watchpoint comparators of the core.
+    ///
+    /// A watchpoint takes one or several of them, see [`Core::set_watchpoint`].
+    pub fn available_watchpoint_units(&mut self) -> Result<u32, error::Error> {
+        self.inner.available_watchpoint_units()
+    }
+
+    /// Set a data watchpoint, which halts the core when it accesses the watched memory, and
+    /// the access matches the qualifiers of `config`.
+    ///
+    /// A watchpoint on the same memory as an existing one replaces it. The number of
+    /// comparators a watchpoint takes depends on the core and on the qualifiers:
+    ///
+    /// - Cortex-M: the DWT watches a power of two bytes which are aligned to their size with
+    ///   one comparator. ARMv8-M watches other ranges with a pair of comparators. A value is
+    ///   matched by linking a data value comparator to the address comparator, if the DWT
+    ///   supports it. Accesses can't be qualified by their size.
+    /// - RISC-V: a trigger watches a single byte or a naturally aligned power of two bytes,
+    ///   other ranges take a chain of two triggers. A value takes another chained trigger,
+    ///   and the access size is a qualifier of the triggers. The triggers are shared with the
+    ///   hardware breakpoints, so a watchpoint reduces
+    ///   [`Core::available_breakpoint_units`].
+    ///
+    /// If the core can't implement a qualifier, the watchpoint isn't set and
+    /// [`Error::UnsupportedWatchpointQualifier`] names it. If not enough comparators are
+    /// left, [`Error::WatchpointsExhausted`] is returned.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn set_watchpoint(&mut self, config: WatchpointConfig) -> Result<(), error::Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+        config.validate()?;
+
+        if let Some(index) = self.state.watchpoints.iter().position(|watchpoint| {
+            watchpoint.config.address == config.address && watchpoint.config.len == config.len
+        }) {
+            let existing = self.state.watchpoints.remove(index);
+            self.inner.clear_watchpoint(&existing.units)?;
+        }
+
+        let mut used = self.watchpoint_units();
+
+        if self.inner.watchpoints_share_breakpoint_units() {
+            // The breakpoints are left below the watchpoints, see `cached_hw_breakpoints`.
+            if let Some(last) = self
+                .cached_hw_breakpoints()?
+                .iter()
+                .rposition(Option::is_some)
+            {
+                used.extend(0..=last);
+            }
+        }
+
+        let units = self.inner.set_watchpoint(&config, &used)?;
+        log::debug!("Set watchpoint {:?} with comparators {:?}", config, units);
+
+        self.state.watchpoints.push(Watchpoint { config, units });
+
+        Ok(())
+    }
+
+    /// Clear the watchpoints whose watched memory starts at `address`.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn clear_watchpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        let (cleared, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.state.watchpoints)
+            .into_iter()
+            .partition(|watchpoint| watchpoint.config.address == address);
+        self.state.watchpoints = kept;
+
+        if cleared.is_empty() {
+            return Err(error::Error::WatchpointNotFound(address));
+        }
+
+        for watchpoint in cleared {
+            self.inner.clear_watchpoint(&watchpoint.units)?;
+        }
+
+        Ok(())
+    }
+
+    /// Clear all watchpoints which were set with [`Core::set_watchpoint`].
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn clear_all_watchpoints(&mut self) -> Result<(), error::Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        for watchpoint in std::mem::take(&mut self.state.watchpoints) {
+            self.inner.clear_watchpoint(&watchpoint.units)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the watchpoints which are set, with all their qualifiers and the comparators
+    /// which implement them.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.state.watchpoints
+    }
+
+    /// Set a watchpoint without qualifiers on the `size` bytes at `address`, picking free
+    /// comparators like [`Core::set_hw_breakpoint`] does.
+    ///
+    /// This is [`Core::set_watchpoint`] with [`WatchpointConfig::new`].
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn set_hw_watchpoint(
+        &mut self,
+        address: u64,
+        size: u32,
+        kind: WatchKind,
+    ) -> Result<(), error::Error> {
+        self.set_watchpoint(WatchpointConfig::new(address, u64::from(size), kind))
+    }
+
+    /// Clear the watchpoints whose watched memory starts at `address`.
+    ///
+    /// This is [`Core::clear_watchpoint`].
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn clear_hw_watchpoint(&mut self, address: u64) -> Result<(), error::Error> {
+        self.clear_watchpoint(address)
+    }
+
+    /// Returns the watchpoints which are set.
+    ///
+    /// This is [`Core::watchpoints`].
+    pub fn hw_watchpoints(&self) -> &[Watchpoint] {
+        self.watchpoints()
+    }
+
+    /// Returns the watchpoints which halted the core, e.g. after its status was
+    /// [`HaltReason::Watchpoint`].
+    ///
+    /// The comparators are checked once per halt, so that a hit is attributed to the
+    /// watchpoint whose comparator matched, even if several watchpoints are set. If the core
+    /// can't tell which of its comparators matched, all watchpoints are returned.
+    ///
+    /// Intrusiveness: [`ReadStatus`](TargetOperation::ReadStatus).
+    pub fn triggered_watchpoints(&mut self) -> Result<Vec<Watchpoint>, error::Error> {
+        self.require(TargetOperation::ReadStatus)?;
+
+        if self.state.watchpoints.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let matched = match &self.state.matched_watchpoint_units {
+            Some(matched) => matched.clone(),
+            None => {
+                let matched = self
+                    .inner
+                    .matched_watchpoint_units(&self.watchpoint_units())?;
+
+                // A running core can still match, so only the matches of a halt are kept.
+                if self.state.halted {
+                    self.state.matched_watchpoint_units = Some(matched.clone());
+                }
+
+                matched
+            }
+        };
+
+        let watchpoints = self.state.watchpoints.iter();
+
+        Ok(match matched {
+            Some(units) => watchpoints
+                .filter(|watchpoint| watchpoint.units.iter().any(|unit| units.contains(unit)))
+                .cloned()
+                .collect(),
+            None => watchpoints.cloned().collect(),
+        })
+    }
+
+    /// The comparators which are used by the watchpoints.
+    fn watchpoint_units(&self) -> Vec<usize> {
+        self.state
+            .watchpoints
+            .iter()
+            .flat_map(|watchpoint| watchpoint.units.iter().copied())
+            .collect()
+    }
+
+    /// Check the watchpoint comparators before the core runs, unless that was done during the
+    /// current halt, so that the matches of the next halt aren't mixed with older ones.
+    fn discard_watchpoint_matches(&mut self) -> Result<(), error::Error> {
+        if self.state.matched_watchpoint_units.take().is_none()
+            && !self.state.watchpoints.is_empty()
+        {
+            self.inner
+                .matched_watchpoint_units(&self.watchpoint_units())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the architecture of the core.
+    pub fn architecture(&self) -> Architecture {
+        self.inner.architecture()
+    }
+
+    /// Returns the core type of the core
+    pub fn core_type(&self) -> CoreType {
+        self.inner.core_type()
+    }
+
+    /// Determine the instruction set the core is operating in
+    /// This must be queried while halted as this is a runtime
+    /// decision for some core types
+    ///
+    /// Cores can switch between instruction sets at runtime, e.g. a Cortex-A core between
+    /// A32 and Thumb, or an ARMv8-A core between AArch32 and AArch64 at an exception level
+    /// transition. The instruction set is therefore derived again every time the core halts,
+    /// e.g. from CPSR.T or the execution state, and after a register was written. Between
+    /// these, the derived value is returned without accessing the core.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn instruction_set(&mut self) -> Result<InstructionSet, error::Error> {
+        self.require(TargetOperation::ReadRegister)?;
+
+        match self.state.instruction_set {
+            Some(instruction_set) if self.state.halted && !self.state.instruction_set_stale => {
+                Ok(instruction_set)
+            }
+            _ => self.derive_instruction_set(),
+        }
+    }
+
+    /// Derive the instruction set the core operates in, and log a switch to another one.
+    fn derive_instruction_set(&mut self) -> Result<InstructionSet, error::Error> {
+        let instruction_set = self.inner.instruction_set()?;
+        self.state.instruction_set_stale = false;
+
+        match self.state.instruction_set.replace(instruction_set) {
+            Some(previous) if previous != instruction_set => log::debug!(
+                "Core {} switched from {:?} to {:?}",
+                self.state.id,
+                previous,
+                instruction_set
+            ),
+            _ => (),
+        }
+
+        Ok(instruction_set)
+    }
+
+    /// Record that the core halted, which starts a new halt generation, unless the core was
+    /// already halted and its architecture didn't detect another halt since.
+    fn note_halted(&mut self) {
+        let halt_detected = self.inner.take_halt_detected();
+
+        if !std::mem::replace(&mut self.state.halted, true) || halt_detected {
+            self.state.halt_generation += 1;
+        }
+    }
+
+    /// Record that the core halted, and derive its instruction set for `info`, if the
+    /// session allows to read registers.
+    fn record_halt(&mut self, info: CoreInformation) -> Result<CoreInformation, error::Error> {
+        self.note_halted();
+
+        let instruction_set = if self.require(TargetOperation::ReadRegister).is_ok() {
+            Some(self.derive_instruction_set()?)
+        } else {
+            self.state.instruction_set_stale = true;
+            None
+        };
+
+        Ok(CoreInformation {
+            instruction_set,
+            ..info
+        })
+    }
+
+    /// Read the instruction at `address`, e.g. the one which caused a fault.
+    ///
+    /// The instruction is read for the instruction set the core is currently operating in. For
+    /// Thumb-2 and RISC-V, where instructions have different lengths, the first halfword is read
+    /// to determine the length of the instruction. If the instruction can't be read, e.g.
+    /// because `address` isn't mapped, [`InstructionFetch::Unreadable`] is returned, so that a
+    /// fault can still be reported.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_instruction(&mut self, address: u64) -> Result<InstructionFetch, error::Error> {
+        let instruction_set = self.instruction_set()?;
+
+        // The lowest bit of a Thumb address selects the instruction set.
+        let address = match instruction_set {
+            InstructionSet::Thumb2 => address & !1,
+            _ => address,
+        };
+
+        let mut bytes = vec![0; 2];
+        if let Err(error) = self.read_8(address, &mut bytes) {
+            return InstructionFetch::unreadable(address, error);
+        }
+
+        let length = instruction::instruction_length(
+            instruction_set,
+            u16::from_le_bytes([bytes[0], bytes[1]]),
+        );
+
+        if length > bytes.len() {
+            bytes.resize(length, 0);
+
+            if let Err(error) = self.read_8(address + 2, &mut bytes[2..]) {
+                return InstructionFetch::unreadable(address, error);
+            }
+        }
+
+        Ok(InstructionFetch::Read {
+            address,
+            instruction_set,
+            bytes,
+        })
+    }
+
+    /// Determine if an FPU is present.
+    /// This must be queried while halted as this is a runtime
+    /// decision for some core types.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn fpu_support(&mut self) -> Result<bool, error::Error> {
+        self.require(TargetOperation::ReadRegister)?;
+        self.inner.fpu_support()
+    }
+
+    /// Read the floating-point status register and all floating-point registers of the
+    /// halted core together, with where each value was read from.
+    ///
+    /// Reading the registers one by one with [`Core::read_core_reg`] is not enough for fault
+    /// reports, as the values can belong to different contexts:
+    ///
+    /// - On a Cortex-M core which is halted in an exception handler, with the EXC_RETURN value
+    ///   still in LR, e.g. after a vector catch, the values of S0-S15 and FPSCR are taken
+    ///   from the extended frame of the exception, so that they are the values of the
+    ///   interrupted context, even if the handler used the FPU since. If lazy state
+    ///   preservation is pending (FPCCR.LSPACT), the frame doesn't hold the values yet, but
+    ///   the registers still do, which is recorded as
+    ///   [`FpuValueSource::PendingLazyState`]. S16-S31 are always read from the registers.
+    /// - On a RISC-V hart, `fcsr` and f0-f31 are read in a single batch of abstract commands,
+    ///   with 64 bits if the hart implements the D extension. If `mstatus.FS` switches the
+    ///   FPU off, [`Error::FpuDisabled`](error::Error::FpuDisabled) is returned without
+    ///   accessing the registers, as that would raise an exception on the hart.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn read_all_fpu_state(&mut self) -> Result<FpuState, error::Error> {
+        self.require(TargetOperation::ReadRegister)?;
+
+        if !self.inner.core_halted()? {
+            return Err(error::Error::CoreNotHalted(self.state.id));
+        }
+
+        if self.core_type().is_cortex_m() {
+            crate::architecture::arm::core::exception_frame::read_fpu_state(self)
+        } else {
+            self.inner.read_fpu_state()
+        }
+    }
+
+    /// Returns what the core supports for debugging, measured on the core.
+    ///
+    /// This is the structure of [`CoreType::capabilities`], with the number of hardware
+    /// breakpoints, the presence of an FPU and the native access widths of this core. On
+    /// Cortex-M cores, the number of watchpoints is read from the DWT as well. Values which
+    /// can't be measured on this core type are the limits of the architecture, so the result
+    /// can be compared with the capabilities of the core type.
+    ///
+    /// Intrusiveness: [`ReadRegister`](TargetOperation::ReadRegister).
+    pub fn capabilities(&mut self) -> Result<CoreCapabilities, error::Error> {
+        let core_type = self.core_type();
+        let mut capabilities = core_type.capabilities();
+
+        capabilities.hardware_breakpoints = Some(self.available_breakpoint_units()?);
+
+        capabilities.fpu = match self.fpu_support() {
+            Ok(true) => FpuSupport::Present,
+            Ok(false) => FpuSupport::None,
+            // FPU detection is not implemented for all core types.
+            Err(error::Error::NotImplemented(_)) => capabilities.fpu,
+            Err(error) => return Err(error),
+        };
+
+        if core_type.is_cortex_m() {
+            // NUMCOMP of DWT_CTRL
+            let dwt_ctrl = self.read_word_32(0xE000_1000)?;
+            capabilities.watchpoints = Some(dwt_ctrl >> 28);
+        }
+
+        if !self.supports_native_64bit_access() {
+            capabilities
+                .native_access_widths
+                .retain(|&width| width != 64);
+        } else if !capabilities.native_access_widths.contains(&64) {
+            capabilities.native_access_widths.push(64);
+        }
+
+        Ok(capabilities)
+    }
+
+    /// Prepare the halted core to start executing at `entry`, with the stack pointer set to
+    /// `stack_pointer`, e.g. to run an image which was loaded into RAM. The core is not resumed.
+    ///
+    /// `entry` is an interworking address like the reset vector: on ARM, bit 0 selects the
+    /// Thumb state, so the entry points of Cortex-M code must have bit 0 set. Depending on the
+    /// architecture, the following state is set up as well:
+    ///
+    /// - Cortex-M: the stack pointer is written to MSP, and MSP is selected as the current stack
+    ///   pointer. EPSR.T is set, without it the core faults on the first instruction.
+    /// - ARMv7-A and ARMv8-A in AArch32 state: CPSR.T is set according to bit 0 of `entry`.
+    /// - RISC-V: `entry` is written to `dpc`, and the core resumes in machine mode.
+    ///
+    /// The registers are read back afterwards. If `entry` can't be executed by the core, or the
+    /// core would not start at `entry`, [`Error::InvalidEntryPoint`] is returned.
+    ///
+    /// Intrusiveness: [`WriteRegister`](TargetOperation::WriteRegister).
+    pub fn prepare_execution(
+        &mut self,
+        entry: u64,
+        stack_pointer: Option<u64>,
+    ) -> Result<(), error::Error> {
+        self.require(TargetOperation::WriteRegister)?;
+
+        let invalid = |reason: String| Error::InvalidEntryPoint {
+            address: entry,
+            reason,
+        };
+
+        let regs = self.registers();
+        let pc = regs.program_counter().id;
+        let mut sp = regs.stack_pointer().id;
+
+        let address = match self.core_type() {
+            CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m => {
+                if entry & 1 == 0 {
+                    return Err(invalid(
+                        "Cortex-M cores can only execute Thumb code, bit 0 of the address must be set".into(),
+                    ));
+                }
+
+                if let Some(msp) = regs.msp() {
+                    sp = msp.id;
+                }
+
+                // Use MSP as the current stack pointer, like after a reset.
+                if let Some(extra) = regs.extra {
+                    let value: u32 = self.read_core_reg(extra.id)?;
+                    if value & CONTROL_SPSEL != 0 {
+                        self.write_core_reg(extra.id, value & !CONTROL_SPSEL)?;
+                    }
+                }
+
+                if let Some(psr) = regs.psr() {
+                    let xpsr: u32 = self.read_core_reg(psr.id)?;
+                    self.write_core_reg(psr.id, xpsr | XPSR_THUMB)?;
+                }
+
+                entry & !1
+            }
+            CoreType::Armv8a if self.instruction_set()? == InstructionSet::A64 => {
+                if entry & 0b11 != 0 {
+                    return Err(invalid("A64 code must be aligned to 4 bytes".into()));
+                }
+
+                entry
             }
-        })
-    }
+            CoreType::Armv7a | CoreType::Armv8a => {
+                let thumb = entry & 1 != 0;
+
+                if !thumb && entry & 0b10 != 0 {
+                    return Err(invalid(
+                        "ARM code must be aligned to 4 bytes, for Thumb code bit 0 of the address must be set".into(),
+                    ));
+                }
+
+                let cpsr: u32 = self.read_core_reg(CPSR)?;
+                let cpsr = if thumb {
+                    cpsr | CPSR_THUMB
+                } else {
+                    cpsr & !CPSR_THUMB
+                };
+                self.write_core_reg(CPSR, cpsr)?;
+
+                entry & !1
+            }
+            CoreType::Riscv => {
+                let alignment = self.riscv_instruction_alignment();
 
-    pub(crate) fn attach_riscv<'probe>(
-        &self,
-        state: &'probe mut CoreState,
-        interface: &'probe mut RiscvCommunicationInterface,
-    ) -> Result<Core<'probe>, Error> {
-        Ok(match self {
-            SpecificCoreState::Riscv => {
-                Core::new(crate::architecture::riscv::Riscv32::new(interface), state)
+                if entry & (alignment - 1) != 0 {
+                    return Err(invalid(format!(
+                        "RISC-V code must be aligned to {} bytes on this core",
+                        alignment
+                    )));
+                }
+
+                // Resume in machine mode.
+                let dcsr: u32 = self.read_core_reg(RegisterId(DCSR))?;
+                self.write_core_reg(RegisterId(DCSR), dcsr | DCSR_PRV_MACHINE)?;
+
+                entry
             }
-            _ => {
-                return Err(Error::UnableToOpenProbe(
-                    "Core architecture and Probe mismatch.",
-                ))
+        };
+
+        if let Some(stack_pointer) = stack_pointer {
+            self.write_core_reg(sp, stack_pointer)?;
+        }
+        self.write_core_reg(pc, address)?;
+
+        // Verify that the core will actually start at the entry point.
+        let actual: u64 = self.read_core_reg(pc)?;
+        if actual != address {
+            return Err(invalid(format!(
+                "the program counter reads back as {:#010x}",
+                actual
+            )));
+        }
+
+        if let Some(stack_pointer) = stack_pointer {
+            let actual: u64 = self.read_core_reg(sp)?;
+            if actual != stack_pointer {
+                return Err(invalid(format!(
+                    "the stack pointer reads back as {:#010x} instead of {:#010x}",
+                    actual, stack_pointer
+                )));
             }
-        })
+        }
+
+        Ok(())
     }
-}
 
-/// Generic core handle representing a physical core on an MCU.
-///
-/// This should be considere as a temporary view of the core which locks the debug probe driver to as single consumer by borrowing it.
-///
-/// As soon as you did your atomic task (e.g. halt the core, read the core state and all other debug relevant info) you should drop this object,
-/// to allow potential other shareholders of the session struct to grab a core handle too.
-pub struct Core<'probe> {
-    inner: Box<dyn CoreInterface + 'probe>,
-    state: &'probe mut CoreState,
-}
+    /// Read a NUL terminated string of at most `max_len` bytes from `address`.
+    ///
+    /// The string is read in small chunks, so that little memory after the terminator is accessed.
+    /// If the end of the accessible memory is reached before the terminator is found, the part of
+    /// the string which could be read is returned, together with [`ReadEnd::Inaccessible`].
+    /// Other errors, e.g. of the probe, are returned as errors.
+    ///
+    /// [`ReadEnd::Inaccessible`]: crate::ReadEnd::Inaccessible
+    pub fn read_c_string(
+        &mut self,
+        address: u64,
+        max_len: usize,
+    ) -> Result<PartialRead<CString>, error::Error> {
+        crate::memory::read_c_string(self, address, max_len)
+    }
 
-impl<'probe> Core<'probe> {
-    /// Create a new [`Core`].
-    pub fn new(core: impl CoreInterface + 'probe, state: &'probe mut CoreState) -> Core<'probe> {
-        Self {
-            inner: Box::new(core),
-            state,
+    /// Read a buffer from `address`, which is prefixed by its length.
+    ///
+    /// The length is stored in the first `len_width` bytes, which can be 1, 2, 4 or 8. At most
+    /// `max_len` bytes of the buffer are read, longer buffers end with [`ReadEnd::LimitReached`].
+    /// If the end of the accessible memory is reached before the end of the buffer, the part
+    /// of the buffer which could be read is returned, together with [`ReadEnd::Inaccessible`].
+    ///
+    /// [`ReadEnd::LimitReached`]: crate::ReadEnd::LimitReached
+    /// [`ReadEnd::Inaccessible`]: crate::ReadEnd::Inaccessible
+    pub fn read_slice_prefixed(
+        &mut self,
+        address: u64,
+        len_width: usize,
+        max_len: usize,
+    ) -> Result<PartialRead<Vec<u8>>, error::Error> {
+        let endianness = self.endianness();
+        crate::memory::read_slice_prefixed(self, address, len_width, max_len, endianness)
+    }
+
+    /// Search `range` for `pattern`, and return the addresses of the matches in ascending
+    /// order.
+    ///
+    /// The range is read lazily in chunks, which overlap by `pattern.len() - 1` bytes, so
+    /// that matches which straddle two chunks are found. Stopping the iteration stops the
+    /// reads. Volatile memory, see [`Session::mark_volatile`], is skipped, and matches never
+    /// straddle it. The search can be interrupted between the chunks, and is bounded by the
+    /// deadline of the enclosing timed operation.
+    ///
+    /// An empty pattern matches nowhere.
+    ///
+    /// [`Session::mark_volatile`]: crate::Session::mark_volatile
+    pub fn search_memory<'core>(
+        &'core mut self,
+        range: Range<u64>,
+        pattern: &'core [u8],
+        options: SearchOptions,
+    ) -> MemorySearchIter<'core, 'probe> {
+        MemorySearchIter::new(self, range, pattern, options)
+    }
+
+    /// Fill the memory in `range` with `pattern`, and register it as poisoned, so that
+    /// [`Core::check_poison`] reports when it is overwritten.
+    ///
+    /// The pattern repeats at the word boundaries, so that an aligned word of the region
+    /// reads as `pattern`. A region replaces the parts of earlier regions it overlaps. The
+    /// regions are kept across resets of the core, as selected with
+    /// [`Core::set_poison_reset_policy`]. If [`Core::set_poison_watch`] is enabled, the
+    /// watchpoint is moved to the new region.
+    pub fn poison_region(&mut self, range: Range<u64>, pattern: u32) -> Result<(), Error> {
+        let region = self.state.poison.insert(range, pattern);
+        self.write_8(region.range.start, &region.expected())?;
+
+        if self.state.poison.watch {
+            self.watch_poison(Some(region.range))?;
         }
+
+        Ok(())
     }
 
-    /// Creates a new [`CoreState`]
-    pub fn create_state(id: usize, options: CoreAccessOptions) -> CoreState {
-        CoreState::new(id, options)
+    /// Stop detecting overwrites of the poisoned memory in `range`, e.g. when a freed block
+    /// of the heap is allocated again. The parts of the regions outside of `range` stay
+    /// poisoned. The watchpoint of [`Core::set_poison_watch`] is cleared if it watched the
+    /// memory.
+    pub fn unpoison_region(&mut self, range: Range<u64>) -> Result<(), Error> {
+        self.state.poison.remove(range.clone());
+
+        let watched = self.state.poison.watched.clone();
+        if let Some(watched) = watched {
+            if watched.start < range.end && range.start < watched.end {
+                self.watch_poison(None)?;
+            }
+        }
+
+        Ok(())
     }
 
-    /// Returns the ID of this core.
-    pub fn id(&self) -> usize {
-        self.state.id
+    /// Compare the poisoned regions with their pattern, and return the regions which were
+    /// overwritten, with the first byte which differs and their current bytes.
+    ///
+    /// The first violation of a region is recorded in the health log, as
+    /// [`HealthEvent::PoisonViolated`]. probe-rs has no listeners for events of the target,
+    /// and the health log is the record of a session which a tool already inspects after an
+    /// error, so the violation is delivered there, next to the anomalies which may have
+    /// caused it. A region stays registered after a violation, so that it is reported again
+    /// by the next check, until it is poisoned again or unpoisoned.
+    pub fn check_poison(&mut self) -> Result<Vec<PoisonViolation>, Error> {
+        let mut violations = Vec::new();
+
+        for index in 0..self.state.poison.regions().len() {
+            let region = self.state.poison.regions()[index].clone();
+            let mut current = vec![0; (region.range.end - region.range.start) as usize];
+            self.read_8(region.range.start, &mut current)?;
+
+            let violation = match region.check(current) {
+                Some(violation) => violation,
+                None => continue,
+            };
+
+            if !region.reported {
+                self.state.poison.regions_mut()[index].reported = true;
+                self.state.health_log.record(
+                    HealthEvent::PoisonViolated,
+                    Some(self.state.id),
+                    "check_poison",
+                    format!(
+                        "The poisoned memory at {:#010x}..{:#010x} was overwritten at {:#010x}",
+                        violation.range.start,
+                        violation.range.end,
+                        violation.address()
+                    ),
+                );
+            }
+
+            violations.push(violation);
+        }
+
+        Ok(violations)
     }
 
-    /// Wait until the core is halted. If the core does not halt on its own,
-    /// a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) error will be returned.
-    pub fn wait_for_core_halted(&mut self, timeout: Duration) -> Result<(), error::Error> {
-        self.inner.wait_for_core_halted(timeout)
+    /// Select what happens to the poisoned regions when the core is reset.
+    ///
+    /// By default, the regions are filled with their pattern again.
+    pub fn set_poison_reset_policy(&mut self, policy: PoisonResetPolicy) {
+        self.state.poison.reset_policy = policy;
     }
 
-    /// Check if the core is halted. If the core does not halt on its own,
-    /// a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) error will be returned.
-    pub fn core_halted(&mut self) -> Result<bool, error::Error> {
-        self.inner.core_halted()
+    /// Watch the most recently poisoned region with a write watchpoint, so that the core
+    /// halts at the store which overwrites it, instead of the overwrite being found by the
+    /// next [`Core::check_poison`].
+    ///
+    /// The watchpoint is only set if a comparator is free, and if the core can watch the
+    /// region, see [`Core::set_watchpoint`]. Otherwise the region is only checked. Disabling
+    /// the watch clears the watchpoint.
+    pub fn set_poison_watch(&mut self, enabled: bool) -> Result<(), Error> {
+        self.state.poison.watch = enabled;
+
+        let latest = if enabled {
+            self.state
+                .poison
+                .regions()
+                .last()
+                .map(|region| region.range.clone())
+        } else {
+            None
+        };
+
+        self.watch_poison(latest)
     }
 
-    /// Try to halt the core. This function ensures the core is actually halted, and
-    /// returns a [`DebugProbeError::Timeout`](crate::DebugProbeError::Timeout) otherwise.
-    pub fn halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.halt(timeout)
+    /// Returns the poisoned region which is watched by a watchpoint, see
+    /// [`Core::set_poison_watch`].
+    pub fn watched_poison(&self) -> Option<Range<u64>> {
+        self.state.poison.watched.clone()
     }
 
-    /// Continue to execute instructions.
-    pub fn run(&mut self) -> Result<(), error::Error> {
-        self.inner.run()
+    /// Move the watchpoint of the poisoned regions to `range`, or clear it.
+    fn watch_poison(&mut self, range: Option<Range<u64>>) -> Result<(), Error> {
+        if let Some(watched) = self.state.poison.watched.take() {
+            match self.clear_watchpoint(watched.start) {
+                Ok(()) | Err(Error::WatchpointNotFound(_)) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        let range = match range {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let config =
+            WatchpointConfig::new(range.start, range.end - range.start, WatchpointKind::Write);
+        match self.set_watchpoint(config) {
+            Ok(()) => self.state.poison.watched = Some(range),
+            Err(
+                error @ (Error::WatchpointsExhausted
+                | Error::InvalidWatchpoint { .. }
+                | Error::UnsupportedWatchpointQualifier { .. }),
+            ) => {
+                log::debug!(
+                    "The poisoned memory at {:#010x}..{:#010x} is only checked: {}",
+                    range.start,
+                    range.end,
+                    error
+                );
+            }
+            Err(error) => return Err(error),
+        }
+
+        Ok(())
     }
 
-    /// Reset the core, and then continue to execute instructions. If the core
-    /// should be halted after reset, use the [`reset_and_halt`] function.
+    /// Apply the reset policy of the poisoned regions, after the core was reset.
+    fn poison_after_reset(&mut self) -> Result<(), Error> {
+        match self.state.poison.reset_policy {
+            PoisonResetPolicy::Reapply => {
+                for index in 0..self.state.poison.regions().len() {
+                    let region = self.state.poison.regions()[index].clone();
+                    self.write_8(region.range.start, &region.expected())?;
+                    self.state.poison.regions_mut()[index].reported = false;
+                }
+
+                Ok(())
+            }
+            PoisonResetPolicy::Forget => {
+                self.state.poison.clear();
+                self.watch_poison(None)
+            }
+            PoisonResetPolicy::VerifyOnly => Ok(()),
+        }
+    }
+
+    /// Run `routine` with the arguments, input and output of `call`, and return its result.
     ///
-    /// [`reset_and_halt`]: Core::reset_and_halt
-    pub fn reset(&mut self) -> Result<(), error::Error> {
-        self.inner.reset()
+    /// The routine is loaded into the scratch memory of the call, the first RAM region of
+    /// the core by default, whose contents are overwritten. The trap the routine returns to,
+    /// the code and the input are written in one transfer, and the registers in one batch.
+    /// The core is halted first if it runs, and stays halted afterwards.
+    ///
+    /// If the routine doesn't complete within the timeout of the call, the core is halted
+    /// and [`Error::RoutineTimedOut`] is returned. If it halts before it completed, e.g.
+    /// because of a fault, [`Error::RoutineCrashed`] is returned. The wait can't be
+    /// interrupted, as an abandoned routine can leave the target in an undefined state.
+    ///
+    /// The routines of flash algorithms are run by the same engine.
+    ///
+    /// Intrusiveness: [`Resume`](TargetOperation::Resume).
+    pub fn run_routine(
+        &mut self,
+        routine: &TargetRoutine,
+        call: &RoutineCall,
+    ) -> Result<RoutineOutput, error::Error> {
+        routine::run(self, routine, call)
     }
 
-    /// Reset the core, and then immediately halt. To continue execution after
-    /// reset, use the [`reset`] function.
+    /// Write `new` to the word at `address` if it is `expected`, atomically with respect to
+    /// the firmware, and return the previous value of the word.
     ///
-    /// [`reset`]: Core::reset
-    pub fn reset_and_halt(&mut self, timeout: Duration) -> Result<CoreInformation, error::Error> {
-        self.inner.reset_and_halt(timeout)
+    /// The swap took place if the returned value is `expected`. This is meant for mailboxes
+    /// which the debugger shares with the firmware, whose flags both sides update.
+    ///
+    /// A halted core can't race with the debugger, so the word is simply read and written.
+    /// A running core is halted briefly, and modifies the word with its own atomic
+    /// instructions: Cortex-M cores other than ARMv6-M run a routine with `LDREX` and
+    /// `STREX` at the start of the first RAM region, whose contents and the registers are
+    /// restored afterwards, and RISC-V harts with the A extension use `lr.w` and `sc.w` in
+    /// the program buffer. If the operation fails, the core is left halted.
+    ///
+    /// Other running cores are refused with [`Error::AtomicUnsupported`], unless non-atomic
+    /// operations were allowed with [`Core::set_force_non_atomic`]. The address has to be
+    /// aligned to 4 bytes.
+    ///
+    /// Intrusiveness: [`WriteMemory`](TargetOperation::WriteMemory), and
+    /// [`WriteRegister`](TargetOperation::WriteRegister) for a running core.
+    pub fn compare_and_swap_32(
+        &mut self,
+        address: u64,
+        expected: u32,
+        new: u32,
+    ) -> Result<u32, error::Error> {
+        atomic::run(
+            self,
+            address,
+            AtomicOperation::CompareAndSwap { expected, new },
+        )
     }
 
-    /// Steps one instruction and then enters halted state again.
-    pub fn step(&mut self) -> Result<CoreInformation, error::Error> {
-        self.inner.step()
+    /// Set the bits of `bits` in the word at `address`, atomically with respect to the
+    /// firmware, and return the previous value of the word.
+    ///
+    /// RISC-V harts with the A extension use `amoor.w`. Otherwise, this works like
+    /// [`Core::compare_and_swap_32`].
+    pub fn fetch_or_32(&mut self, address: u64, bits: u32) -> Result<u32, error::Error> {
+        atomic::run(self, address, AtomicOperation::Or(bits))
     }
 
-    /// Returns the current status of the core.
-    pub fn status(&mut self) -> Result<CoreStatus, error::Error> {
-        self.inner.status()
+    /// Clear the bits of the word at `address` which are clear in `bits`, atomically with
+    /// respect to the firmware, and return the previous value of the word.
+    ///
+    /// RISC-V harts with the A extension use `amoand.w`. Otherwise, this works like
+    /// [`Core::compare_and_swap_32`].
+    pub fn fetch_and_32(&mut self, address: u64, bits: u32) -> Result<u32, error::Error> {
+        atomic::run(self, address, AtomicOperation::And(bits))
     }
 
-    /// Read the value of a core register.
+    /// Allow the atomic operations, like [`Core::compare_and_swap_32`], to modify the words of
+    /// a running core without atomic instructions, e.g. of an ARMv6-M core, with a plain read
+    /// and write.
     ///
-    /// # Errors
+    /// This is not atomic: a write of the firmware between the read and the write is lost.
+    /// It is disabled by default.
+    pub fn set_force_non_atomic(&mut self, force: bool) {
+        self.state.force_non_atomic = force;
+    }
+
+    /// Read a plain-old-data value from `address`.
     ///
-    /// If `T` isn't large enough to hold the register value an error will be raised.
-    pub fn read_core_reg<T>(&mut self, address: impl Into<RegisterId>) -> Result<T, error::Error>
-    where
-        RegisterValue: TryInto<T, Error = error::Error>,
-    {
-        let value = self.inner.read_core_reg(address.into())?;
+    /// See [`FromTargetBytes`] for how to read custom types.
+    pub fn read_value<T: FromTargetBytes>(&mut self, address: u64) -> Result<T, error::Error> {
+        let endianness = self.endianness();
+        crate::memory::read_value(self, address, endianness)
+    }
 
-        value.try_into()
+    /// Write the byte `data` at `address`, with a read-modify-write of the word which contains
+    /// it if the core can't write single bytes, see [`MemoryInterface::native_write_widths`].
+    ///
+    /// Unlike [`MemoryInterface::write_word_8`], which refuses this with
+    /// [`Error::WouldRequireReadModifyWrite`], the read-modify-write is also done on device
+    /// memory. Reading and writing the whole word can have side effects there, e.g. on
+    /// write-1-to-clear status bits or FIFOs, so only use this if the other bytes of the word
+    /// are known to tolerate it.
+    pub fn write_word_8_rmw(&mut self, address: u64, data: u8) -> Result<(), error::Error> {
+        let address = self.memory_address(address);
+        if self.before_write(address, 1)? {
+            return self.write_mediated(address, &[data]);
+        }
+        self.access_with_retry("write", address, 1, |core| core.write_word_8(address, data))
     }
 
-    /// Write the value of a core register.
+    /// Count how often the instruction at `address` is executed during `duration`, without
+    /// halting the core if possible.
     ///
-    /// # Errors
+    /// This is only supported on Cortex-M cores. The returned [`AddressHits`] report whether
+    /// the hits were sampled, or counted intrusively by halting the core on every hit. All
+    /// modified DWT registers are restored afterwards.
     ///
-    /// If T is too large to write to the target register an error will be raised.
-    pub fn write_core_reg<T>(&mut self, address: RegisterId, value: T) -> Result<(), error::Error>
-    where
-        T: Into<RegisterValue>,
-    {
-        Ok(self.inner.write_core_reg(address, value.into())?)
+    /// [`AddressHits`]: crate::architecture::arm::AddressHits
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn count_address_hits(
+        &mut self,
+        address: u64,
+        duration: Duration,
+    ) -> Result<AddressHits, error::Error> {
+        let mut report = self.count_multiple_address_hits(&[address], duration)?;
+
+        Ok(report.addresses.remove(0))
     }
 
-    /// Returns all the available breakpoint units of the core.
-    pub fn available_breakpoint_units(&mut self) -> Result<u32, error::Error> {
-        self.inner.available_breakpoint_units()
+    /// Count how often each of the instructions at `addresses` is executed during `duration`.
+    ///
+    /// If there are more addresses than DWT comparators, the comparators are shared by
+    /// monitoring groups of addresses in turn, see [`HitCountReport`].
+    ///
+    /// [`HitCountReport`]: crate::architecture::arm::HitCountReport
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn count_multiple_address_hits(
+        &mut self,
+        addresses: &[u64],
+        duration: Duration,
+    ) -> Result<HitCountReport, error::Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+        crate::architecture::arm::core::hit_count::count_address_hits(self, addresses, duration)
     }
 
-    /// Enables breakpoints on this core. If a breakpoint is set, it will halt as soon as it is hit.
-    fn enable_breakpoints(&mut self, state: bool) -> Result<(), error::Error> {
-        self.inner.enable_breakpoints(state)
+    /// Read the memory-mapped register `R`, e.g. [`Icsr`](crate::architecture::arm::Icsr).
+    ///
+    /// Memory-mapped registers, like the ones of the system control space of Cortex-M cores,
+    /// are read through the memory access port, so they can be read while the core runs.
+    /// Core registers are different, they are transferred through the debug registers of the
+    /// core, which needs a halted core, see [`Core::read_core_reg`].
+    ///
+    /// Intrusiveness: [`ReadDeviceMemory`](TargetOperation::ReadDeviceMemory), which sessions
+    /// limited to [`Intrusiveness::BusOnly`] allow.
+    pub fn read_mm_register<R: MemoryMappedRegister>(&mut self) -> Result<R, error::Error> {
+        Ok(R::from(self.read_word_32(R::ADDRESS)?))
     }
 
-    /// Returns a list of all the registers of this core.
-    pub fn registers(&self) -> &'static RegisterFile {
-        self.inner.registers()
+    /// Read the exception state of a Cortex-M core without halting it: ICSR, SHCSR, CFSR and
+    /// the reload and current values of the SysTick timer, in two block reads.
+    ///
+    /// All these registers are free of side effects when read. SYST_CSR is left out, because
+    /// a read can clear its COUNTFLAG. As the core keeps running, ICSR only shows the active and
+    /// pending exception of the moment it is read, see [`SystemControlSnapshot`].
+    ///
+    /// [`SystemControlSnapshot`]: crate::architecture::arm::SystemControlSnapshot
+    ///
+    /// Intrusiveness: [`ReadDeviceMemory`](TargetOperation::ReadDeviceMemory).
+    pub fn system_control_snapshot_live(&mut self) -> Result<SystemControlSnapshot, error::Error> {
+        crate::architecture::arm::core::scb::system_control_snapshot(self)
     }
 
-    /// Find the index of the next available HW breakpoint comparator.
-    fn find_free_breakpoint_comparator_index(&mut self) -> Result<usize, error::Error> {
-        let mut next_available_hw_breakpoint = 0;
-        for breakpoint in self.inner.hw_breakpoints()? {
-            if breakpoint.is_none() {
-                return Ok(next_available_hw_breakpoint);
-            } else {
-                next_available_hw_breakpoint += 1;
-            }
-        }
-        Err(error::Error::Other(anyhow!(
-            "No available hardware breakpoints"
-        )))
+    /// Read the regions of the memory protection unit (MPU).
+    ///
+    /// This is only supported on Cortex-M cores. All implemented regions are returned, including
+    /// the disabled ones. If the core has no MPU, the list is empty.
+    pub fn mpu_regions(&mut self) -> Result<Vec<MpuRegion>, error::Error> {
+        crate::architecture::arm::core::mpu::mpu_regions(self)
     }
 
-    /// Set a hardware breakpoint
+    /// Run `f` with the memory protection unit (MPU) disabled.
     ///
-    /// This function will try to set a hardware breakpoint att `address`.
+    /// Memory accesses through the debug probe bypass the MPU, but code executed by the core,
+    /// e.g. a routine loaded into RAM, is subject to it. The MPU is disabled before `f` is run,
+    /// and MPU_CTRL is restored afterwards, also if `f` fails. Because this changes the
+    /// behavior of the target while `f` runs, it should only be used on request of the user.
     ///
-    /// The amount of hardware breakpoints which are supported is chip specific,
-    /// and can be queried using the `get_available_breakpoint_units` function.
-    pub fn set_hw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
-        if !self.inner.hw_breakpoints_enabled() {
-            self.enable_breakpoints(true)?;
-        }
+    /// On cores without an MPU, or if the MPU is disabled already, `f` is run directly.
+    pub fn with_mpu_disabled<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, error::Error>,
+    ) -> Result<T, error::Error> {
+        use crate::architecture::arm::core::mpu;
 
-        // If there is a breakpoint set already, return its bp_unit_index, else find the next free index.
-        let breakpoint_comparator_index = match self
-            .inner
-            .hw_breakpoints()?
-            .iter()
-            .position(|&bp| bp == Some(address))
-        {
-            Some(breakpoint_comparator_index) => breakpoint_comparator_index,
-            None => self.find_free_breakpoint_comparator_index()?,
-        };
+        let ctrl = mpu::disable_mpu(self)?;
 
-        log::debug!(
-            "Trying to set HW breakpoint #{} with comparator address  {:#08x}",
-            breakpoint_comparator_index,
-            address
-        );
+        let result = f(self);
 
-        // Actually set the breakpoint. Even if it has been set, set it again so it will be active.
-        self.inner
-            .set_hw_breakpoint(breakpoint_comparator_index, address)?;
-        Ok(())
+        let restored = mpu::restore_mpu(self, ctrl);
+
+        let value = result?;
+        restored?;
+
+        Ok(value)
     }
 
-    /// Set a hardware breakpoint
+    /// Read the cause of a pending or past MemManage fault, if there is one.
     ///
-    /// This function will try to clear a hardware breakpoint at `address` if there exists a breakpoint at that address.
-    pub fn clear_hw_breakpoint(&mut self, address: u64) -> Result<(), error::Error> {
-        let bp_position = self
-            .inner
-            .hw_breakpoints()?
-            .iter()
-            .position(|bp| bp.is_some() && bp.unwrap() == address);
+    /// This is only supported on Cortex-M cores. If the fault status contains the address of the
+    /// faulting access, the [`MemManageFault`] is annotated with the MPU region covering it.
+    /// ARMv6-M cores have no MemManage faults, and always return `None`.
+    ///
+    /// [`MemManageFault`]: crate::architecture::arm::MemManageFault
+    pub fn mem_manage_fault(&mut self) -> Result<Option<MemManageFault>, error::Error> {
+        crate::architecture::arm::core::mpu::mem_manage_fault(self)
+    }
 
-        log::debug!(
-            "Will clear HW breakpoint    #{} with comparator address    {:#08x}",
-            bp_position.unwrap_or(usize::MAX),
-            address
-        );
+    /// Read and decode the exception frame of the active exception of a halted Cortex-M core.
+    ///
+    /// The frame is found with the EXC_RETURN value in LR, so the core has to be halted before
+    /// the handler changed LR, e.g. at its first instruction after a vector catch. The stack
+    /// and the format of the frame are taken from EXC_RETURN, including the floating-point
+    /// registers of an extended frame and the alignment padding recorded in the stacked xPSR.
+    /// For nested exceptions, [`ExceptionFrame::context_stack_pointer`] is the stack pointer of
+    /// the interrupted handler, whose frame follows.
+    ///
+    /// If the memory of the stack can't be read, the registers which could be read are
+    /// returned, see [`ExceptionFrame::missing_registers`]. On ARMv8-M, a stack limit violation
+    /// during stacking is reported in [`ExceptionFrame::stack_limit_violation`].
+    ///
+    /// [`ExceptionFrame`]: crate::architecture::arm::ExceptionFrame
+    /// [`ExceptionFrame::context_stack_pointer`]: crate::architecture::arm::ExceptionFrame::context_stack_pointer
+    /// [`ExceptionFrame::missing_registers`]: crate::architecture::arm::ExceptionFrame::missing_registers
+    /// [`ExceptionFrame::stack_limit_violation`]: crate::architecture::arm::ExceptionFrame::stack_limit_violation
+    pub fn exception_frame(&mut self) -> Result<ExceptionFrame, error::Error> {
+        crate::architecture::arm::core::exception_frame::exception_frame(self)
+    }
 
-        match bp_position {
-            Some(bp_position) => {
-                self.inner.clear_hw_breakpoint(bp_position)?;
-                Ok(())
-            }
-            None => Err(error::Error::Other(anyhow!(
-                "No breakpoint found at address {:#010x}",
-                address
-            ))),
+    /// Read a block of 8bit words at `address`, as the core sees them.
+    ///
+    /// On cores whose memory accesses go past the data cache, i.e. Cortex-M7 and Cortex-M55,
+    /// the cache lines covering the block are cleaned first, so that data the core hasn't
+    /// written back yet is read. On cores without enabled caches, this is the same as
+    /// [`MemoryInterface::read`]. The maintenance sequence of each architecture is described
+    /// in [`CacheMaintenance`].
+    pub fn read_coherent(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+        let line_size = match self.inner.cache_line_size()? {
+            Some(line_size) => line_size,
+            None => return self.read(address, data),
+        };
+
+        if !self.accesses_through_core() {
+            self.maintain_cache(CacheMaintenance::Clean, address, data.len(), line_size)?;
         }
+
+        self.read(address, data)
     }
 
-    /// Clear all hardware breakpoints
+    /// Write a block of 8bit words to `address`, so that the core sees them.
     ///
-    /// This function will clear all HW breakpoints which are configured on the target,
-    /// regardless if they are set by probe-rs, AND regardless if they are enabled or not.
-    /// Also used as a helper function in [`Session::drop`](crate::session::Session).
-    pub fn clear_all_hw_breakpoints(&mut self) -> Result<(), error::Error> {
-        for breakpoint in (self.inner.hw_breakpoints()?).into_iter().flatten() {
-            self.clear_hw_breakpoint(breakpoint)?
+    /// On cores whose memory accesses go past the data cache, i.e. Cortex-M7 and Cortex-M55,
+    /// the cache lines covering the block are cleaned and invalidated before the write, so
+    /// that no dirty line overwrites the written data, and invalidated again afterwards. On
+    /// all cores with caches, the instruction cache lines are invalidated, so that written
+    /// code is executed. On cores without enabled caches, this is the same as
+    /// [`MemoryInterface::write_8`]. The maintenance sequence of each architecture is
+    /// described in [`CacheMaintenance`].
+    pub fn write_coherent(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+        let line_size = match self.inner.cache_line_size()? {
+            Some(line_size) => line_size,
+            None => return self.write_8(address, data),
+        };
+
+        if self.accesses_through_core() {
+            self.write_8(address, data)?;
+            self.maintain_cache(CacheMaintenance::Clean, address, data.len(), line_size)?;
+        } else {
+            self.maintain_cache(
+                CacheMaintenance::CleanInvalidate,
+                address,
+                data.len(),
+                line_size,
+            )?;
+            self.write_8(address, data)?;
+            self.maintain_cache(CacheMaintenance::Invalidate, address, data.len(), line_size)?;
         }
-        Ok(())
-    }
 
-    /// Returns the architecture of the core.
-    pub fn architecture(&self) -> Architecture {
-        self.inner.architecture()
+        self.maintain_cache(
+            CacheMaintenance::InvalidateInstruction,
+            address,
+            data.len(),
+            line_size,
+        )
     }
 
-    /// Returns the core type of the core
-    pub fn core_type(&self) -> CoreType {
-        self.inner.core_type()
+    /// Returns `true` if memory accesses are executed by the core, and see its data cache.
+    fn accesses_through_core(&self) -> bool {
+        matches!(self.core_type(), CoreType::Armv7a | CoreType::Armv8a)
     }
 
-    /// Determine the instruction set the core is operating in
-    /// This must be queried while halted as this is a runtime
-    /// decision for some core types
-    pub fn instruction_set(&mut self) -> Result<InstructionSet, error::Error> {
-        self.inner.instruction_set()
+    /// Perform `operation` on each cache line covering `len` bytes at `address`.
+    ///
+    /// The maintenance operations change the contents of the caches, so they need the
+    /// intrusiveness of a memory write.
+    fn maintain_cache(
+        &mut self,
+        operation: CacheMaintenance,
+        address: u64,
+        len: usize,
+        line_size: u32,
+    ) -> Result<(), error::Error> {
+        self.require(TargetOperation::WriteMemory)?;
+
+        for line in crate::architecture::arm::core::cache::cache_lines(address, len, line_size) {
+            self.inner.cache_maintenance(operation, line)?;
+        }
+
+        Ok(())
     }
 
-    /// Determine if an FPU is present.
-    /// This must be queried while halted as this is a runtime
-    /// decision for some core types.
-    pub fn fpu_support(&mut self) -> Result<bool, error::Error> {
-        self.inner.fpu_support()
+    /// Returns the byte order used by the core.
+    pub fn endianness(&self) -> Endianness {
+        // All cores currently supported by probe-rs run in little endian mode.
+        Endianness::Little
     }
 }
 
@@ -879,8 +5216,14 @@ pub enum HaltReason {
     /// are set.
     Multiple,
     /// Core halted due to a breakpoint, either
-    /// a *soft* or a *hard* breakpoint.
+    /// a *hard* breakpoint, or a breakpoint instruction which wasn't set by probe-rs.
     Breakpoint,
+    /// Core halted at a software breakpoint, which was set with
+    /// [`Core::set_sw_breakpoint`] or [`Core::apply_breakpoints`].
+    ///
+    /// The breakpoint instruction has to be cleared before the core is resumed, otherwise it
+    /// halts at it again.
+    SoftwareBreakpoint,
     /// Core halted due to an exception, e.g. an
     /// an interrupt.
     Exception,
@@ -892,8 +5235,82 @@ pub enum HaltReason {
     Request,
     /// External halt request
     External,
+    /// Core halted at a breakpoint on a panic handler.
+    ///
+    /// These breakpoints are set with
+    /// [`Session::break_on_panic`](crate::Session::break_on_panic).
+    Panic,
     /// Unknown reason for halt.
     ///
     /// This can happen for example when the core is already halted when we connect.
     Unknown,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn halt_location_from_pc() {
+        let roms = [RomRegion {
+            name: Some("Bootloader".to_owned()),
+            range: 0x1fff_0000..0x1fff_7000,
+        }];
+
+        assert_eq!(
+            HaltLocation::from_pc(0x0800_0100, &[0x0800_0100], &roms),
+            HaltLocation::ResetVector
+        );
+        assert_eq!(
+            HaltLocation::from_pc(0x1fff_0400, &[0x0800_0100], &roms),
+            HaltLocation::Rom {
+                region: Some("Bootloader".to_owned())
+            }
+        );
+        assert_eq!(
+            HaltLocation::from_pc(0x0800_2000, &[0x0800_0100], &roms),
+            HaltLocation::Elsewhere
+        );
+        assert_eq!(
+            HaltLocation::from_pc(0x0800_2000, &[], &roms),
+            HaltLocation::Unknown
+        );
+    }
+
+    #[test]
+    fn register_values_are_formatted_like_their_integers() {
+        assert_eq!(RegisterValue::U32(1234).to_string(), "1234");
+        assert_eq!(
+            format!("{:x}", RegisterValue::U64(0xdead_beef_0000)),
+            "deadbeef0000"
+        );
+        assert_eq!(format!("{:#010x}", RegisterValue::U32(0x2a)), "0x0000002a");
+
+        let pc = RegisterDescription {
+            name: "PC",
+            _kind: RegisterKind::PC,
+            id: RegisterId(15),
+            _type: RegisterDataType::UnsignedInteger,
+            size_in_bits: 32,
+            writable_mask: !0,
+        };
+        let wide = RegisterDescription {
+            size_in_bits: 64,
+            ..pc.clone()
+        };
+
+        assert_eq!(
+            register_value_hex(&pc, RegisterValue::U32(0x2a)),
+            "0x0000002a"
+        );
+        // The width follows the register, not the variant of the value.
+        assert_eq!(
+            register_value_hex(&pc, RegisterValue::U64(0x2a)),
+            "0x0000002a"
+        );
+        assert_eq!(
+            register_value_hex(&wide, RegisterValue::U32(0x2a)),
+            "0x000000000000002a"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Poisoned memory, whose overwrites are detected like those of an address sanitizer, see
+//! [`Core::poison_region`].
+//!
+//! A poisoned region is filled with a pattern which the firmware never writes, e.g. a freed
+//! block of the heap or the guard area below the stack of a task. A later
+//! [`Core::check_poison`] compares the regions with their pattern, and reports the regions
+//! which were overwritten since. With [`Core::set_poison_watch`], the most recently poisoned
+//! region is also watched by a write watchpoint, if a comparator is free, so that the core
+//! halts at the store which overwrites it.
+//!
+//! The stack guards of an RTOS whose task stacks are known, e.g. from its task control
+//! blocks, are monitored while the firmware runs:
+//!
+//! ```no_run
+//! # use probe_rs::{Core, Error, PoisonResetPolicy};
+//! # fn monitor(core: &mut Core, task_stacks: &[(&str, u64)]) -> Result<(), Error> {
+//! const GUARD_SIZE: u64 = 64;
+//!
+//! // The tasks are created again after a reset, with their guards.
+//! core.set_poison_reset_policy(PoisonResetPolicy::Reapply);
+//!
+//! // The stacks grow downwards, so the guard is at the lowest address of a stack.
+//! for (_, stack_bottom) in task_stacks {
+//!     core.poison_region(*stack_bottom..*stack_bottom + GUARD_SIZE, 0xDEAD_BEEF)?;
+//! }
+//!
+//! loop {
+//!     for violation in core.check_poison()? {
+//!         let (task, _) = task_stacks
+//!             .iter()
+//!             .find(|(_, bottom)| *bottom == violation.range.start)
+//!             .unwrap();
+//!         println!(
+//!             "The stack of {} overflowed, down to {:#010x}",
+//!             task,
+//!             violation.address()
+//!         );
+//!     }
+//!     std::thread::sleep(std::time::Duration::from_millis(100));
+//! }
+//! # }
+//! ```
+//!
+//! [`Core::poison_region`]: crate::Core::poison_region
+//! [`Core::check_poison`]: crate::Core::check_poison
+//! [`Core::set_poison_watch`]: crate::Core::set_poison_watch
+
+use std::ops::Range;
+
+/// What happens to the poisoned regions when the core is reset, see
+/// [`Core::set_poison_reset_policy`](crate::Core::set_poison_reset_policy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoisonResetPolicy {
+    /// Fill the regions with their pattern again, e.g. for stack guards, which the firmware
+    /// sets up again after the reset.
+    #[default]
+    Reapply,
+    /// Forget the regions, e.g. for freed blocks of the heap, which are meaningless after the
+    /// reset.
+    Forget,
+    /// Keep the regions without filling them again, so that [`Core::check_poison`] reports
+    /// the regions which the reset or the startup code overwrote.
+    ///
+    /// [`Core::check_poison`]: crate::Core::check_poison
+    VerifyOnly,
+}
+
+/// A region of memory which was overwritten since it was poisoned, see
+/// [`Core::check_poison`](crate::Core::check_poison).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoisonViolation {
+    /// The poisoned region.
+    pub range: Range<u64>,
+    /// The offset of the first byte of the region which differs from the pattern.
+    pub offset: u64,
+    /// The current bytes of the whole region.
+    pub current: Vec<u8>,
+}
+
+impl PoisonViolation {
+    /// The address of the first byte of the region which differs from the pattern.
+    pub fn address(&self) -> u64 {
+        self.range.start + self.offset
+    }
+}
+
+/// A poisoned region of memory.
+#[derive(Debug, Clone)]
+pub(crate) struct PoisonRegion {
+    pub(crate) range: Range<u64>,
+    pattern: u32,
+    /// Whether a violation of the region was recorded in the health log already.
+    pub(crate) reported: bool,
+}
+
+impl PoisonRegion {
+    /// The bytes the region holds while it is intact.
+    ///
+    /// The pattern repeats at the word boundaries, so that an aligned word of the region
+    /// reads as the pattern, also if the region starts in the middle of a word.
+    pub(crate) fn expected(&self) -> Vec<u8> {
+        let pattern = self.pattern.to_le_bytes();
+
+        self.range
+            .clone()
+            .map(|address| pattern[(address % 4) as usize])
+            .collect()
+    }
+
+    /// Compare the `current` bytes of the region with the pattern.
+    pub(crate) fn check(&self, current: Vec<u8>) -> Option<PoisonViolation> {
+        let offset = self
+            .expected()
+            .iter()
+            .zip(&current)
+            .position(|(expected, current)| expected != current)?;
+
+        Some(PoisonViolation {
+            range: self.range.clone(),
+            offset: offset as u64,
+            current,
+        })
+    }
+}
+
+/// The poisoned regions of a core.
+#[derive(Debug, Default)]
+pub(crate) struct PoisonList {
+    regions: Vec<PoisonRegion>,
+    pub(crate) reset_policy: PoisonResetPolicy,
+    /// Whether the most recently poisoned region is watched by a watchpoint.
+    pub(crate) watch: bool,
+    /// The region which is watched by a watchpoint.
+    pub(crate) watched: Option<Range<u64>>,
+}
+
+impl PoisonList {
+    /// The poisoned regions, in the order in which they were poisoned.
+    pub(crate) fn regions(&self) -> &[PoisonRegion] {
+        &self.regions
+    }
+
+    pub(crate) fn regions_mut(&mut self) -> &mut [PoisonRegion] {
+        &mut self.regions
+    }
+
+    /// Register `range` as poisoned with `pattern`, replacing the parts of other regions it
+    /// overlaps, and return the region.
+    pub(crate) fn insert(&mut self, range: Range<u64>, pattern: u32) -> PoisonRegion {
+        self.remove(range.clone());
+
+        let region = PoisonRegion {
+            range,
+            pattern,
+            reported: false,
+        };
+        self.regions.push(region.clone());
+
+        region
+    }
+
+    /// Remove `range` from the poisoned regions, keeping the parts of the regions it doesn't
+    /// cover.
+    pub(crate) fn remove(&mut self, range: Range<u64>) {
+        let mut kept = Vec::with_capacity(self.regions.len());
+
+        for region in std::mem::take(&mut self.regions) {
+            if region.range.end <= range.start || range.end <= region.range.start {
+                kept.push(region);
+                continue;
+            }
+
+            for part in [region.range.start..range.start, range.end..region.range.end] {
+                if part.start < part.end {
+                    kept.push(PoisonRegion {
+                        range: part,
+                        ..region.clone()
+                    });
+                }
+            }
+        }
+
+        self.regions = kept;
+    }
+
+    /// Forget all regions.
+    pub(crate) fn clear(&mut self) {
+        self.regions.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pattern_repeats_at_word_boundaries() {
+        let mut list = PoisonList::default();
+        let region = list.insert(0x1002..0x1007, 0x4433_2211);
+
+        assert_eq!(region.expected(), [0x33, 0x44, 0x11, 0x22, 0x33]);
+    }
+
+    #[test]
+    fn check_reports_the_first_differing_byte() {
+        let mut list = PoisonList::default();
+        let region = list.insert(0x1000..0x1008, 0xDEAD_BEEF);
+
+        assert_eq!(region.check(region.expected()), None);
+
+        let mut current = region.expected();
+        current[5] = 0;
+        current[6] = 0;
+
+        let violation = region.check(current.clone()).unwrap();
+        assert_eq!(violation.offset, 5);
+        assert_eq!(violation.address(), 0x1005);
+        assert_eq!(violation.current, current);
+    }
+
+    #[test]
+    fn removing_a_range_keeps_the_rest_of_the_regions() {
+        let mut list = PoisonList::default();
+        list.insert(0x1000..0x1100, 0);
+        list.insert(0x2000..0x2100, 0);
+        list.remove(0x1040..0x1080);
+        list.insert(0x20F0..0x2200, 0);
+
+        let ranges: Vec<_> = list.regions().iter().map(|r| r.range.clone()).collect();
+        assert_eq!(
+            ranges,
+            [
+                0x1000..0x1040,
+                0x1080..0x1100,
+                0x2000..0x20F0,
+                0x20F0..0x2200
+            ]
+        );
+    }
+}
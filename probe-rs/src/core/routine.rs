@@ -0,0 +1,652 @@
+//! Running routines on a core, e.g. the vendor blobs which initialize external memory or
+//! program OTP, see [`Core::run_routine`](crate::Core::run_routine).
+//!
+//! The routines of flash algorithms are run by the same engine, with [`start`] and [`wait`].
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use object::{Object, ObjectSegment, ObjectSymbol};
+
+use crate::architecture::riscv::assembly::EBREAK;
+use crate::{
+    Architecture, Core, DebugProbeError, Error, InstructionSet, MemoryInterface, RegisterId,
+    RegisterValue,
+};
+
+use super::DCSR;
+
+/// The default size of the stack of a routine.
+const DEFAULT_STACK_SIZE: u64 = 0x400;
+
+/// The default time a routine may take to complete.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The time the core may take to halt after a routine timed out, or set its flag.
+const HALT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The interval in which the flag of a routine is polled.
+const FLAG_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The size of the trap and the flag word in front of the code, see [`Layout`].
+const HEADER_SIZE: u64 = 8;
+
+/// The `BKPT 0xAB` instruction, with which Thumb code makes a semihosting call.
+const THUMB_SEMIHOSTING_CALL: u16 = 0xBEAB;
+/// `slli x0, x0, 0x1f`, in front of the `ebreak` of a RISC-V semihosting call.
+const RISCV_SEMIHOSTING_ENTRY: u32 = 0x01f0_1013;
+/// `srai x0, x0, 7`, after the `ebreak` of a RISC-V semihosting call.
+const RISCV_SEMIHOSTING_EXIT: u32 = 0x4070_5013;
+
+/// The semihosting operation which ends the program, with a reason code.
+const SYS_EXIT: u32 = 0x18;
+/// The semihosting operation which ends the program, with a reason code and an exit code.
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+/// The reason code of a program which ended normally.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+/// How a routine signals that it completed, see [`TargetRoutine::completion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineCompletion {
+    /// The routine returns to a breakpoint instruction, which the return address points to.
+    ///
+    /// The result is the value of the first result register, e.g. `R0`.
+    Return,
+    /// The routine writes `value` to a flag word, whose address is passed with
+    /// [`RoutineArgument::FlagAddress`]. The core is halted once the flag is set.
+    ///
+    /// The result is the value of the first result register when the core was halted.
+    MemoryFlag {
+        /// The value which signals the completion.
+        value: u32,
+    },
+    /// The routine exits with the semihosting operation `SYS_EXIT` or `SYS_EXIT_EXTENDED`,
+    /// on Cortex-M or RISC-V cores.
+    ///
+    /// The result is the exit code of `SYS_EXIT_EXTENDED`. For `SYS_EXIT`, it is `0` if the
+    /// reason is `ADP_Stopped_ApplicationExit`, and the reason code otherwise.
+    SemihostingExit,
+}
+
+/// An argument of a routine, see [`RoutineCall::argument`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineArgument {
+    /// A plain value.
+    Value(u32),
+    /// The address of the input block, see [`RoutineCall::input`].
+    InputAddress,
+    /// The address of the output block, see [`RoutineCall::output`].
+    OutputAddress,
+    /// The address of the flag word of [`RoutineCompletion::MemoryFlag`].
+    FlagAddress,
+}
+
+/// A position-independent routine, which can be run on a core with [`Core::run_routine`].
+///
+/// Vendors ship such routines for tasks beyond flashing, e.g. the initialization of external
+/// SDRAM, OTP programming or secure provisioning. The routine is loaded into scratch memory,
+/// and called with up to four arguments. By default, it completes by returning, see
+/// [`RoutineCompletion`], and gets a stack of 1 KiB.
+///
+/// [`Core::run_routine`]: crate::Core::run_routine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetRoutine {
+    code: Vec<u8>,
+    entry_offset: u64,
+    completion: RoutineCompletion,
+    stack_size: u64,
+}
+
+impl TargetRoutine {
+    /// A routine of the machine code in `code`, which starts at `entry_offset`.
+    pub fn from_bytes(code: &[u8], entry_offset: u64) -> Self {
+        Self {
+            code: code.to_vec(),
+            entry_offset,
+            completion: RoutineCompletion::Return,
+            stack_size: DEFAULT_STACK_SIZE,
+        }
+    }
+
+    /// A routine of the loadable segments of the ELF file `elf`, which starts at the symbol
+    /// `entry`.
+    ///
+    /// The segments are combined into one block, from the lowest to the highest address,
+    /// which is loaded at another address than it is linked at, so the code must be
+    /// position-independent.
+    pub fn from_elf(elf: &[u8], entry: &str) -> Result<Self, Error> {
+        let file = object::File::parse(elf).map_err(|e| Error::InvalidElf(e.to_string()))?;
+
+        let mut segments = Vec::new();
+        for segment in file.segments() {
+            let data = segment
+                .data()
+                .map_err(|e| Error::InvalidElf(format!("Failed to read a segment: {}", e)))?;
+
+            if !data.is_empty() {
+                segments.push((segment.address(), data));
+            }
+        }
+
+        let start = segments
+            .iter()
+            .map(|(address, _)| *address)
+            .min()
+            .ok_or_else(|| Error::InvalidElf("The file contains no loadable data".into()))?;
+        let end = segments
+            .iter()
+            .map(|(address, data)| address + data.len() as u64)
+            .max()
+            .unwrap_or(start);
+
+        let mut code = vec![0; (end - start) as usize];
+        for (address, data) in segments {
+            let offset = (address - start) as usize;
+            code[offset..offset + data.len()].copy_from_slice(data);
+        }
+
+        let symbol = file
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(entry))
+            .ok_or_else(|| Error::RoutineEntryNotFound(entry.to_owned()))?;
+
+        // The address of Thumb functions has bit 0 set.
+        let address = match file.architecture() {
+            object::Architecture::Arm => symbol.address() & !1,
+            _ => symbol.address(),
+        };
+
+        if !(start..end).contains(&address) {
+            return Err(Error::InvalidElf(format!(
+                "The entry point `{}` at {:#010x} is not in the loadable data",
+                entry, address
+            )));
+        }
+
+        Ok(Self::from_bytes(&code, address - start))
+    }
+
+    /// Set how the routine signals that it completed.
+    pub fn completion(mut self, completion: RoutineCompletion) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    /// Reserve `size` bytes of the scratch memory for the stack of the routine.
+    pub fn stack_size(mut self, size: u64) -> Self {
+        self.stack_size = size;
+        self
+    }
+
+    /// The machine code of the routine.
+    pub fn code(&self) -> &[u8] {
+        &self.code
+    }
+
+    /// The offset of the entry point in the code.
+    pub fn entry_offset(&self) -> u64 {
+        self.entry_offset
+    }
+}
+
+/// The arguments, input and output of a run of a [`TargetRoutine`], see
+/// [`Core::run_routine`](crate::Core::run_routine).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutineCall {
+    arguments: Vec<RoutineArgument>,
+    input: Vec<u8>,
+    output_len: usize,
+    timeout: Duration,
+    scratch: Option<Range<u64>>,
+}
+
+impl Default for RoutineCall {
+    fn default() -> Self {
+        Self {
+            arguments: Vec::new(),
+            input: Vec::new(),
+            output_len: 0,
+            timeout: DEFAULT_TIMEOUT,
+            scratch: None,
+        }
+    }
+}
+
+impl RoutineCall {
+    /// A call without arguments, input or output, which may take 1 s.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass `argument` in the next argument register.
+    pub fn argument(mut self, argument: RoutineArgument) -> Self {
+        self.arguments.push(argument);
+        self
+    }
+
+    /// Load `input` into the scratch memory, see [`RoutineArgument::InputAddress`].
+    pub fn input(mut self, input: &[u8]) -> Self {
+        self.input = input.to_vec();
+        self
+    }
+
+    /// Reserve `len` bytes of the scratch memory for the output of the routine, which are
+    /// read back once it completed, see [`RoutineArgument::OutputAddress`].
+    pub fn output(mut self, len: usize) -> Self {
+        self.output_len = len;
+        self
+    }
+
+    /// Give up if the routine didn't complete after `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Load the routine into `range`, instead of the first RAM region of the core.
+    pub fn scratch(mut self, range: Range<u64>) -> Self {
+        self.scratch = Some(range);
+        self
+    }
+}
+
+/// The result of a run of a [`TargetRoutine`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutineOutput {
+    /// The result of the routine, see [`RoutineCompletion`].
+    pub result: u32,
+    /// The output block of the routine, see [`RoutineCall::output`].
+    pub output: Vec<u8>,
+}
+
+/// The addresses of the parts of a routine in the scratch memory.
+///
+/// The trap, which the routine returns to, and the flag word come first, followed by the
+/// code, the input and the output. The stack is last. All parts are aligned to 8 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Layout {
+    trap: u64,
+    flag: u64,
+    code: u64,
+    input: u64,
+    output: u64,
+    stack_top: u64,
+}
+
+impl Layout {
+    fn new(
+        scratch: &Range<u64>,
+        routine: &TargetRoutine,
+        call: &RoutineCall,
+    ) -> Result<Self, Error> {
+        let align = |address: u64| (address + 7) & !7;
+
+        let trap = align(scratch.start);
+        let code = trap + HEADER_SIZE;
+        let input = align(code + routine.code.len() as u64);
+        let output = align(input + call.input.len() as u64);
+        let stack_top = align(align(output + call.output_len as u64) + routine.stack_size);
+
+        if stack_top > scratch.end {
+            return Err(Error::RoutineDoesNotFit {
+                required: stack_top - scratch.start,
+                available: scratch.end.saturating_sub(scratch.start),
+            });
+        }
+
+        Ok(Self {
+            trap,
+            flag: trap + 4,
+            code,
+            input,
+            output,
+            stack_top,
+        })
+    }
+}
+
+/// Load `routine` into the scratch memory of `call`, run it and read its output.
+pub(super) fn run(
+    core: &mut Core<'_>,
+    routine: &TargetRoutine,
+    call: &RoutineCall,
+) -> Result<RoutineOutput, Error> {
+    let scratch = match &call.scratch {
+        Some(scratch) => scratch.clone(),
+        None => core
+            .state
+            .ram_ranges
+            .first()
+            .cloned()
+            .ok_or(Error::NoScratchMemory)?,
+    };
+    let layout = Layout::new(&scratch, routine, call)?;
+
+    let regs = core.registers();
+    let supported = (0..4)
+        .take_while(|&index| regs.get_argument_register(index).is_some())
+        .count();
+    if call.arguments.len() > supported {
+        return Err(Error::TooManyRoutineArguments {
+            count: call.arguments.len(),
+            supported,
+        });
+    }
+
+    if !core.core_halted()? {
+        core.halt(HALT_TIMEOUT)?;
+    }
+
+    let flag_value = match routine.completion {
+        RoutineCompletion::MemoryFlag { value } => value,
+        _ => 0,
+    };
+
+    // The code and the stack of the routine may write less than a word to memory which is
+    // protected by ECC.
+    core.prepare_ecc_lease(layout.trap..layout.stack_top)?;
+
+    // The trap, the flag, the code and the input are loaded with a single write.
+    let mut image = Vec::with_capacity((layout.output - layout.trap) as usize);
+    image.extend_from_slice(&trap(core.instruction_set()?).to_le_bytes());
+    image.extend_from_slice(&(!flag_value).to_le_bytes());
+    image.extend_from_slice(&routine.code);
+    image.resize((layout.input - layout.trap) as usize, 0);
+    image.extend_from_slice(&call.input);
+    core.write_8(layout.trap, &image)?;
+
+    let arguments = call
+        .arguments
+        .iter()
+        .map(|argument| match argument {
+            RoutineArgument::Value(value) => Ok(Some(*value)),
+            RoutineArgument::InputAddress => address_argument(layout.input).map(Some),
+            RoutineArgument::OutputAddress => address_argument(layout.output).map(Some),
+            RoutineArgument::FlagAddress => address_argument(layout.flag).map(Some),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    log::debug!(
+        "Running a routine of {} bytes at {:#010x}",
+        routine.code.len(),
+        layout.code
+    );
+
+    start(
+        core,
+        &Invocation {
+            entry: layout.code + routine.entry_offset,
+            arguments: &arguments,
+            static_base: None,
+            stack_pointer: Some(layout.stack_top),
+            return_address: layout.trap,
+        },
+    )?;
+
+    let completion = match routine.completion {
+        RoutineCompletion::Return => Completion::Return {
+            address: layout.trap,
+        },
+        RoutineCompletion::MemoryFlag { value } => Completion::MemoryFlag {
+            address: layout.flag,
+            value,
+        },
+        RoutineCompletion::SemihostingExit => Completion::SemihostingExit,
+    };
+    let result = wait(core, &completion, call.timeout)?;
+
+    let mut output = vec![0; call.output_len];
+    if !output.is_empty() {
+        core.read_8(layout.output, &mut output)?;
+    }
+
+    Ok(RoutineOutput { result, output })
+}
+
+/// The breakpoint instruction a routine returns to, for the current instruction set.
+fn trap(instruction_set: InstructionSet) -> u32 {
+    match instruction_set {
+        // Two `BKPT #0`, the word may be fetched as a whole.
+        InstructionSet::Thumb2 => 0xBE00_BE00,
+        // `BKPT #0`
+        InstructionSet::A32 => 0xE120_0070,
+        // `BRK #0`
+        InstructionSet::A64 => 0xD420_0000,
+        InstructionSet::RV32 => EBREAK,
+    }
+}
+
+fn address_argument(address: u64) -> Result<u32, Error> {
+    u32::try_from(address).map_err(|_| Error::ValueTooLarge(address))
+}
+
+/// A call of a routine which is loaded already.
+pub(crate) struct Invocation<'a> {
+    /// The address of the first instruction of the routine.
+    pub entry: u64,
+    /// The values of the argument registers, which are left as they are if `None`.
+    pub arguments: &'a [Option<u32>],
+    /// The static base of the routine, which is written to R9.
+    pub static_base: Option<u32>,
+    /// The initial stack pointer, which is left as it is if `None`.
+    pub stack_pointer: Option<u64>,
+    /// The address of the breakpoint instruction the routine returns to.
+    pub return_address: u64,
+}
+
+/// How the completion of a running routine is detected, see [`wait`].
+pub(crate) enum Completion {
+    /// The core halts at the breakpoint at `address`.
+    Return { address: u64 },
+    /// The word at `address` is set to `value`.
+    MemoryFlag { address: u64, value: u32 },
+    /// The core halts at a semihosting call which exits.
+    SemihostingExit,
+}
+
+/// Start the routine of `invocation` on the halted `core`.
+///
+/// The registers are written in one batch, after the core was prepared to execute the
+/// routine with [`Core::prepare_execution`].
+pub(crate) fn start(core: &mut Core<'_>, invocation: &Invocation<'_>) -> Result<(), Error> {
+    log::debug!(
+        "Calling the routine at {:#010x} with {:x?}",
+        invocation.entry,
+        invocation.arguments
+    );
+
+    let regs = core.registers();
+    let thumb = core.instruction_set()? == InstructionSet::Thumb2;
+
+    // The entry point and the return address are interworking addresses, Cortex-M cores
+    // only execute Thumb code.
+    let interworking = |address: u64| if thumb { address | 1 } else { address };
+
+    core.prepare_execution(interworking(invocation.entry), invocation.stack_pointer)?;
+
+    let mut registers: Vec<(RegisterId, RegisterValue)> = invocation
+        .arguments
+        .iter()
+        .enumerate()
+        .filter_map(|(index, value)| Some((regs.argument_register(index).id, (*value)?.into())))
+        .collect();
+
+    if let Some(static_base) = invocation.static_base {
+        registers.push((regs.platform_register(9).id, static_base.into()));
+    }
+
+    let return_address = interworking(invocation.return_address);
+    registers.push((
+        regs.return_address().id,
+        address_argument(return_address)?.into(),
+    ));
+
+    core.write_core_regs(&registers)?;
+
+    if core.architecture() == Architecture::Riscv {
+        // Ensure ebreak enters debug mode, this is necessary for soft breakpoints to work.
+        let dcsr: u32 = core.read_core_reg(RegisterId(DCSR))?;
+
+        core.write_core_reg(RegisterId(DCSR), dcsr | (1 << 15) | (1 << 13) | (1 << 12))?;
+    }
+
+    core.run()
+}
+
+/// Wait until the routine which runs on `core` completed, and return its result.
+///
+/// A routine which is abandoned while it runs can leave the target in an undefined state,
+/// so the wait can't be interrupted. If the routine doesn't complete within `timeout`, the
+/// core is halted, and [`Error::RoutineTimedOut`] is returned. If the core halts anywhere
+/// else than at the completion of the routine, e.g. because of a fault,
+/// [`Error::RoutineCrashed`] is returned.
+pub(crate) fn wait(
+    core: &mut Core<'_>,
+    completion: &Completion,
+    timeout: Duration,
+) -> Result<u32, Error> {
+    log::debug!("Waiting for routine call completion.");
+
+    let completed = match completion {
+        Completion::MemoryFlag { address, value } => {
+            wait_for_flag(core, *address, *value, timeout)?
+        }
+        _ => match core.wait_for_core_halted_uninterruptible(timeout) {
+            Ok(()) => true,
+            Err(Error::Probe(DebugProbeError::Timeout)) => false,
+            Err(error) => return Err(error),
+        },
+    };
+
+    if !completed {
+        let halted = core.halt(HALT_TIMEOUT)?;
+        return Err(Error::RoutineTimedOut {
+            timeout,
+            pc: halted.pc,
+        });
+    }
+
+    let regs = core.registers();
+    let pc: u64 = core.read_core_reg(regs.program_counter().id)?;
+
+    match completion {
+        Completion::Return { address } if pc != *address => Err(Error::RoutineCrashed { pc }),
+        Completion::SemihostingExit => {
+            semihosting_exit_code(core, pc)?.ok_or(Error::RoutineCrashed { pc })
+        }
+        _ => core.read_core_reg(regs.result_register(0).id),
+    }
+}
+
+/// Poll the flag at `address` until it is set to `value`, and halt the core afterwards.
+/// Returns `false` if the flag wasn't set within `timeout`.
+fn wait_for_flag(
+    core: &mut Core<'_>,
+    address: u64,
+    value: u32,
+    timeout: Duration,
+) -> Result<bool, Error> {
+    let start = Instant::now();
+
+    loop {
+        if core.read_word_32(address)? == value {
+            if !core.core_halted()? {
+                core.halt(HALT_TIMEOUT)?;
+            }
+
+            return Ok(true);
+        }
+
+        // A core which halts before it set the flag crashed.
+        if core.core_halted()? {
+            let pc = core.read_core_reg(core.registers().program_counter().id)?;
+            return Err(Error::RoutineCrashed { pc });
+        }
+
+        if start.elapsed() >= timeout {
+            return Ok(false);
+        }
+
+        std::thread::sleep(FLAG_POLL_INTERVAL);
+    }
+}
+
+/// Returns the exit code, if the core is halted at `pc` at a semihosting call which exits.
+fn semihosting_exit_code(core: &mut Core<'_>, pc: u64) -> Result<Option<u32>, Error> {
+    let is_call = match core.instruction_set()? {
+        InstructionSet::Thumb2 => {
+            let mut instruction = [0; 2];
+            core.read_8(pc, &mut instruction)?;
+
+            u16::from_le_bytes(instruction) == THUMB_SEMIHOSTING_CALL
+        }
+        InstructionSet::RV32 => match pc.checked_sub(4) {
+            Some(start) => {
+                let mut instructions = [0; 3];
+                core.read_32(start, &mut instructions)?;
+
+                instructions == [RISCV_SEMIHOSTING_ENTRY, EBREAK, RISCV_SEMIHOSTING_EXIT]
+            }
+            None => false,
+        },
+        InstructionSet::A32 | InstructionSet::A64 => false,
+    };
+
+    if !is_call {
+        return Ok(None);
+    }
+
+    let regs = core.registers();
+    let operation: u32 = core.read_core_reg(regs.argument_register(0).id)?;
+    let parameter: u32 = core.read_core_reg(regs.argument_register(1).id)?;
+
+    let exit_code = match operation {
+        SYS_EXIT if parameter == ADP_STOPPED_APPLICATION_EXIT => 0,
+        SYS_EXIT => parameter,
+        // The parameter block holds the reason and the exit code.
+        SYS_EXIT_EXTENDED => core.read_word_32(u64::from(parameter) + 4)?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(exit_code))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parts_are_aligned() {
+        let routine = TargetRoutine::from_bytes(&[0; 6], 0).stack_size(0x100);
+        let call = RoutineCall::new().input(&[0; 3]).output(4);
+
+        let layout = Layout::new(&(0x2000_0004..0x2000_1000), &routine, &call).unwrap();
+
+        assert_eq!(
+            layout,
+            Layout {
+                trap: 0x2000_0008,
+                flag: 0x2000_000c,
+                code: 0x2000_0010,
+                input: 0x2000_0018,
+                output: 0x2000_0020,
+                stack_top: 0x2000_0128,
+            }
+        );
+    }
+
+    #[test]
+    fn routines_which_do_not_fit_are_rejected() {
+        let routine = TargetRoutine::from_bytes(&[0; 0x100], 0);
+
+        let error = Layout::new(&(0x2000_0000..0x2000_0400), &routine, &RoutineCall::new());
+
+        assert!(matches!(
+            error,
+            Err(Error::RoutineDoesNotFit {
+                required: 0x508,
+                available: 0x400
+            })
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+//! Searching the memory of a core for a byte pattern, see [`Core::search_memory`].
+//!
+//! [`Core::search_memory`]: crate::Core::search_memory
+
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use super::INTERRUPTIBLE_CHUNK_SIZE;
+use crate::{Core, Error, MemoryInterface};
+
+/// The options of a memory search, see [`Core::search_memory`](crate::Core::search_memory).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SearchOptions {
+    mask: Option<Vec<u8>>,
+    alignment: u64,
+}
+
+impl SearchOptions {
+    /// Match the pattern exactly, at any address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only compare the bits of the pattern which are set in `mask`, e.g. `0x00` for a byte
+    /// of the pattern which matches any byte.
+    ///
+    /// The mask must have the length of the pattern, otherwise the search fails with
+    /// [`Error::SearchMaskMismatch`].
+    pub fn mask(mut self, mask: &[u8]) -> Self {
+        self.mask = Some(mask.to_vec());
+        self
+    }
+
+    /// Only report matches at addresses which are a multiple of `alignment`.
+    pub fn alignment(mut self, alignment: u64) -> Self {
+        self.alignment = alignment;
+        self
+    }
+}
+
+/// The addresses at which a pattern was found, see
+/// [`Core::search_memory`](crate::Core::search_memory).
+///
+/// The memory is read lazily, chunk by chunk, so dropping the iterator after the first match
+/// doesn't read the rest of the range. An error ends the search.
+pub struct MemorySearchIter<'core, 'probe> {
+    core: &'core mut Core<'probe>,
+    pattern: &'core [u8],
+    options: SearchOptions,
+    /// The parts of the range which aren't volatile, and are still to be searched.
+    segments: VecDeque<Range<u64>>,
+    /// The address of the next byte which is read from the current segment.
+    next_read: u64,
+    /// The end of the current segment.
+    segment_end: u64,
+    /// The memory which was read, starting at `buffer_start`.
+    buffer: Vec<u8>,
+    buffer_start: u64,
+    /// The offset in `buffer` at which the pattern is compared next.
+    position: usize,
+    /// Whether any chunk was read yet, so that the cancellation point is skipped before the
+    /// first one.
+    started: bool,
+    done: bool,
+}
+
+impl<'core, 'probe> MemorySearchIter<'core, 'probe> {
+    pub(super) fn new(
+        core: &'core mut Core<'probe>,
+        range: Range<u64>,
+        pattern: &'core [u8],
+        options: SearchOptions,
+    ) -> Self {
+        let segments = plain_segments(range, core.state.volatile_ranges.ranges());
+
+        Self {
+            core,
+            pattern,
+            options,
+            segments,
+            next_read: 0,
+            segment_end: 0,
+            buffer: Vec::with_capacity(INTERRUPTIBLE_CHUNK_SIZE + pattern.len()),
+            buffer_start: 0,
+            position: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    /// Whether the pattern matches `window`, which has the length of the pattern.
+    fn matches(&self, window: &[u8]) -> bool {
+        match &self.options.mask {
+            Some(mask) => window
+                .iter()
+                .zip(self.pattern)
+                .zip(mask)
+                .all(|((byte, expected), mask)| (byte ^ expected) & mask == 0),
+            None => window == self.pattern,
+        }
+    }
+
+    /// Compare the pattern at the remaining offsets of the buffer, and return the address of
+    /// the first match.
+    fn scan_buffer(&mut self) -> Option<u64> {
+        let alignment = self.options.alignment.max(1);
+
+        while self.position + self.pattern.len() <= self.buffer.len() {
+            let address = self.buffer_start + self.position as u64;
+            let misalignment = address % alignment;
+
+            if misalignment != 0 {
+                self.position += (alignment - misalignment) as usize;
+                continue;
+            }
+
+            let window = &self.buffer[self.position..self.position + self.pattern.len()];
+            let found = self.matches(window);
+            self.position += 1;
+
+            if found {
+                return Some(address);
+            }
+        }
+
+        None
+    }
+
+    /// Read the next chunk of the current segment, or start the next segment. Returns
+    /// `false` once the whole range was searched.
+    fn read_chunk(&mut self) -> Result<bool, Error> {
+        if self.next_read == self.segment_end {
+            let segment = match self.segments.pop_front() {
+                Some(segment) => segment,
+                None => return Ok(false),
+            };
+
+            // A match can't straddle two segments, they are separated by volatile memory.
+            self.buffer.clear();
+            self.buffer_start = segment.start;
+            self.position = 0;
+            self.next_read = segment.start;
+            self.segment_end = segment.end;
+        } else {
+            // Keep the bytes at which a match may still start, which are the last
+            // `pattern.len() - 1` bytes of the buffer at most.
+            let keep_from = self.position.min(self.buffer.len());
+            self.buffer.drain(..keep_from);
+            self.buffer_start += keep_from as u64;
+            self.position -= keep_from;
+        }
+
+        if self.started {
+            self.core.state.interrupt.check()?;
+            self.core.check_deadline()?;
+        }
+        self.started = true;
+
+        // The chunks are aligned, so that they are read with aligned accesses.
+        let chunk = INTERRUPTIBLE_CHUNK_SIZE as u64;
+        let chunk_end = (self.next_read / chunk + 1) * chunk;
+        let len = (chunk_end.min(self.segment_end) - self.next_read) as usize;
+
+        let start = self.buffer.len();
+        self.buffer.resize(start + len, 0);
+        self.core.read(self.next_read, &mut self.buffer[start..])?;
+        self.next_read += len as u64;
+
+        Ok(true)
+    }
+}
+
+impl Iterator for MemorySearchIter<'_, '_> {
+    type Item = Result<u64, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if let Some(mask) = &self.options.mask {
+            if mask.len() != self.pattern.len() {
+                self.done = true;
+                return Some(Err(Error::SearchMaskMismatch {
+                    pattern: self.pattern.len(),
+                    mask: mask.len(),
+                }));
+            }
+        }
+
+        if self.pattern.is_empty() {
+            self.done = true;
+            return None;
+        }
+
+        loop {
+            if let Some(address) = self.scan_buffer() {
+                return Some(Ok(address));
+            }
+
+            match self.read_chunk() {
+                Ok(true) => {}
+                Ok(false) => {
+                    self.done = true;
+                    return None;
+                }
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// The parts of `range` which don't overlap any of `volatile`.
+fn plain_segments(range: Range<u64>, volatile: &[Range<u64>]) -> VecDeque<Range<u64>> {
+    let mut excluded: Vec<_> = volatile
+        .iter()
+        .filter(|excluded| excluded.start < range.end && range.start < excluded.end)
+        .cloned()
+        .collect();
+    excluded.sort_by_key(|excluded| excluded.start);
+
+    let mut segments = VecDeque::new();
+    let mut start = range.start;
+
+    for excluded in excluded {
+        if start < excluded.start {
+            segments.push_back(start..excluded.start);
+        }
+        start = start.max(excluded.end);
+    }
+
+    if start < range.end {
+        segments.push_back(start..range.end);
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod test {
+    use super::plain_segments;
+
+    #[test]
+    fn volatile_ranges_are_excluded() {
+        let segments = plain_segments(0x100..0x200, &[0x180..0x190, 0x0..0x110, 0x188..0x1a0]);
+
+        assert_eq!(Vec::from(segments), [0x110..0x180, 0x1a0..0x200]);
+    }
+
+    #[test]
+    fn ranges_without_volatile_memory_are_searched_whole() {
+        let segments = plain_segments(0x100..0x200, &[0x200..0x300]);
+
+        assert_eq!(Vec::from(segments), [0x100..0x200]);
+    }
+}
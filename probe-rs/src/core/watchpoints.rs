@@ -0,0 +1,217 @@
+//! Data watchpoints, which halt the core when it accesses memory.
+//!
+//! A plain watchpoint halts the core on every access to its address range. Qualifiers
+//! restrict it further, to accesses which read or write a given value, see
+//! [`WatchpointConfig::value`], or to accesses of a given size, see
+//! [`WatchpointConfig::access_size`]. Depending on the core, a watchpoint takes one or
+//! several comparators, e.g. the DWT of a Cortex-M core links an address comparator and a
+//! data value comparator to match a value.
+//!
+//! Qualifiers the core can't implement are never ignored: the watchpoint isn't set, and
+//! [`Error::UnsupportedWatchpointQualifier`](crate::Error::UnsupportedWatchpointQualifier)
+//! names the qualifier.
+
+use std::fmt;
+
+use crate::Error;
+
+/// The accesses a watchpoint halts the core on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchpointKind {
+    /// Reads of the watched memory.
+    Read,
+    /// Writes of the watched memory.
+    Write,
+    /// Reads and writes of the watched memory.
+    ReadWrite,
+}
+
+/// Another name of [`WatchpointKind`], used by [`Core::set_hw_watchpoint`](crate::Core::set_hw_watchpoint).
+pub type WatchKind = WatchpointKind;
+
+/// A qualifier of a [`WatchpointConfig`], which restricts the accesses it halts the core on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchpointQualifier {
+    /// The value which is read or written, see [`WatchpointConfig::value`].
+    Value,
+    /// A mask of the value which doesn't cover the whole value, so that only some bits are
+    /// compared.
+    ValueMask,
+    /// The size of the access, see [`WatchpointConfig::access_size`].
+    AccessSize,
+}
+
+impl fmt::Display for WatchpointQualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            WatchpointQualifier::Value => "data value",
+            WatchpointQualifier::ValueMask => "masked data value",
+            WatchpointQualifier::AccessSize => "access size",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// A data watchpoint, see [`Core::set_watchpoint`](crate::Core::set_watchpoint).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchpointConfig {
+    /// The first address of the watched memory.
+    pub address: u64,
+    /// The number of watched bytes.
+    pub len: u64,
+    /// The accesses which halt the core.
+    pub kind: WatchpointKind,
+    /// Only halt the core if the value which is accessed matches, as `(value, mask)`: an access
+    /// matches if the bits set in the mask are the same in the accessed value and in `value`.
+    ///
+    /// The value is compared for the whole watched memory, so `len` must be the size of the
+    /// value, e.g. 4 for a word.
+    pub value: Option<(u64, u64)>,
+    /// Only halt the core on accesses of this size in bytes.
+    pub access_size: Option<u8>,
+}
+
+impl WatchpointConfig {
+    /// A watchpoint on the `len` bytes at `address`, without qualifiers.
+    pub fn new(address: u64, len: u64, kind: WatchpointKind) -> Self {
+        Self {
+            address,
+            len,
+            kind,
+            value: None,
+            access_size: None,
+        }
+    }
+
+    /// Only halt the core if the bits set in `mask` are the same in the accessed value and
+    /// in `value`.
+    pub fn value(mut self, value: u64, mask: u64) -> Self {
+        self.value = Some((value, mask));
+        self
+    }
+
+    /// Only halt the core on accesses of `size` bytes.
+    pub fn access_size(mut self, size: u8) -> Self {
+        self.access_size = Some(size);
+        self
+    }
+
+    /// The mask which covers all bytes of the watched memory.
+    pub(crate) fn len_mask(&self) -> u64 {
+        if self.len >= 8 {
+            u64::MAX
+        } else {
+            (1 << (8 * self.len)) - 1
+        }
+    }
+
+    /// The value and the mask of the value qualifier, and whether the mask covers the whole
+    /// value.
+    pub(crate) fn masked_value(&self) -> Option<(u64, u64, bool)> {
+        self.value.map(|(value, mask)| {
+            let mask = mask & self.len_mask();
+
+            (value & mask, mask, mask == self.len_mask())
+        })
+    }
+
+    /// Check that the watchpoint describes accesses which can happen at all.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        let invalid = |reason| Error::InvalidWatchpoint {
+            address: self.address,
+            len: self.len,
+            reason,
+        };
+
+        if self.len == 0 {
+            return Err(invalid("the watched memory is empty"));
+        }
+
+        if self.address.checked_add(self.len).is_none() {
+            return Err(invalid("the watched memory ends beyond the address space"));
+        }
+
+        if let Some(size) = self.access_size {
+            if !matches!(size, 1 | 2 | 4 | 8) {
+                return Err(invalid("the access size must be 1, 2, 4 or 8 bytes"));
+            }
+        }
+
+        if self.value.is_some() && !matches!(self.len, 1 | 2 | 4 | 8) {
+            return Err(invalid(
+                "a watched value must be 1, 2, 4 or 8 bytes long, like the watched memory",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// A watchpoint which is set, with the comparators which implement it, see
+/// [`Core::watchpoints`](crate::Core::watchpoints).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The watchpoint as it was requested, with all qualifiers.
+    pub config: WatchpointConfig,
+    /// The comparators which implement the watchpoint.
+    pub units: Vec<usize>,
+}
+
+/// Returns `count` consecutive comparators out of `available` which are not `used`, the
+/// lowest ones if `from_top` is false, the highest ones otherwise.
+///
+/// Comparators which are linked, like a pair of an address and a limit comparator, or a
+/// chain of RISC-V triggers, have to be consecutive.
+pub(crate) fn consecutive_units(
+    count: usize,
+    available: usize,
+    used: &[usize],
+    from_top: bool,
+) -> Option<Vec<usize>> {
+    let mut runs = (0..=available.checked_sub(count)?)
+        .map(|start| (start..start + count).collect::<Vec<_>>())
+        .filter(|units| units.iter().all(|unit| !used.contains(unit)));
+
+    if from_top {
+        runs.next_back()
+    } else {
+        runs.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn value_masks_are_limited_to_the_watched_memory() {
+        let config = WatchpointConfig::new(0x2000_0000, 2, WatchpointKind::Write)
+            .value(0x1234_5678, u64::MAX);
+        assert_eq!(config.masked_value(), Some((0x5678, 0xffff, true)));
+
+        let config = WatchpointConfig::new(0x2000_0000, 4, WatchpointKind::Write).value(1, 0xff);
+        assert_eq!(config.masked_value(), Some((1, 0xff, false)));
+    }
+
+    #[test]
+    fn values_must_fill_the_watched_memory() {
+        let config = WatchpointConfig::new(0x2000_0000, 3, WatchpointKind::Write).value(0, 0xff);
+
+        assert!(matches!(
+            config.validate(),
+            Err(Error::InvalidWatchpoint { len: 3, .. })
+        ));
+        assert!(WatchpointConfig::new(0x2000_0000, 3, WatchpointKind::Read)
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn linked_comparators_are_consecutive() {
+        assert_eq!(consecutive_units(2, 4, &[1], false), Some(vec![2, 3]));
+        assert_eq!(consecutive_units(1, 4, &[3], true), Some(vec![2]));
+        assert_eq!(consecutive_units(2, 4, &[1, 2], false), None);
+        assert_eq!(consecutive_units(1, 0, &[], false), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! Deadlines of blocking operations.
+//!
+//! The timeout of a blocking operation, e.g. [`Core::halt`](crate::Core::halt), is turned
+//! into a [`Deadline`] when the operation starts. The inner operations, like the polls of a
+//! RISC-V Debug Module or the chunks of a large memory transfer, derive their own timeouts
+//! from the time which remains until the deadline, instead of using fixed timeouts which
+//! could exceed it. Nested deadlines compose: an operation never runs past the deadline of
+//! an enclosing [`Core::with_timeout`](crate::Core::with_timeout).
+//!
+//! The guarantee of a timed operation is that it returns at most one inner-operation quantum
+//! after its deadline. The quantum is the longer of [`Deadline::MIN_BUDGET`], which every
+//! inner operation gets so that an operation can still clean up after its deadline, e.g.
+//! clear a halt request, and the transfer of a single chunk of a large memory access.
+
+use std::time::{Duration, Instant};
+
+use crate::{DebugProbeError, Error};
+
+/// The point in time at which a blocking operation gives up, see the [module](self) docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    /// The minimum timeout of an inner operation, even if the deadline has passed.
+    pub const MIN_BUDGET: Duration = Duration::from_millis(10);
+
+    /// The deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        let now = Instant::now();
+
+        // Timeouts which don't fit into an `Instant` don't pass in practice.
+        Self(
+            now.checked_add(timeout)
+                .unwrap_or_else(|| now + Duration::from_secs(60 * 60 * 24 * 365)),
+        )
+    }
+
+    /// The point in time of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// The time which remains until the deadline, zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    /// Returns `true` once the deadline has passed.
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+
+    /// The timeout of an inner operation whose own timeout is `timeout`: the time which
+    /// remains until the deadline, but at least [`Deadline::MIN_BUDGET`], and at most
+    /// `timeout`.
+    pub fn budget(&self, timeout: Duration) -> Duration {
+        self.remaining().max(Self::MIN_BUDGET).min(timeout)
+    }
+
+    /// The earlier of this deadline and `other`.
+    pub fn earliest(self, other: Option<Deadline>) -> Self {
+        match other {
+            Some(other) => self.min(other),
+            None => self,
+        }
+    }
+
+    /// Returns a [`DebugProbeError::Timeout`] once the deadline has passed.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.has_passed() {
+            Err(Error::Probe(DebugProbeError::Timeout))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn budget_is_bounded_by_the_deadline() {
+        let deadline = Deadline::after(Duration::from_secs(60));
+
+        assert_eq!(
+            deadline.budget(Duration::from_secs(5)),
+            Duration::from_secs(5)
+        );
+        assert!(deadline.budget(Duration::from_secs(120)) <= Duration::from_secs(60));
+        assert!(deadline.check().is_ok());
+    }
+
+    #[test]
+    fn passed_deadline_keeps_the_minimum_budget() {
+        let deadline = Deadline::after(Duration::ZERO);
+
+        assert!(deadline.has_passed());
+        assert_eq!(deadline.remaining(), Duration::ZERO);
+        assert_eq!(
+            deadline.budget(Duration::from_secs(5)),
+            Deadline::MIN_BUDGET
+        );
+        assert_eq!(
+            deadline.budget(Duration::from_millis(1)),
+            Duration::from_millis(1)
+        );
+        assert!(matches!(
+            deadline.check(),
+            Err(Error::Probe(DebugProbeError::Timeout))
+        ));
+    }
+
+    #[test]
+    fn earliest_deadline_wins() {
+        let early = Deadline::after(Duration::from_millis(10));
+        let late = Deadline::after(Duration::from_secs(10));
+
+        assert_eq!(late.earliest(Some(early)), early);
+        assert_eq!(early.earliest(Some(late)), early);
+        assert_eq!(late.earliest(None), late);
+    }
+}
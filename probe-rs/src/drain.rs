@@ -0,0 +1,529 @@
+//! Draining circular buffers of the target while it is debugged, see
+//! [`Session::register_drain`](crate::Session::register_drain).
+//!
+//! While a core is halted at a breakpoint, producers which don't depend on it keep running,
+//! e.g. a DMA which writes the samples of a sensor into a circular buffer, or another core
+//! which logs into an RTT channel. Once the buffer is full, they overwrite the data which
+//! wasn't read yet. A drain reads the new data of such a buffer according to its
+//! [`CircularBuffer`] layout, and pushes it to a [`DrainSink`].
+//!
+//! The session services the drains opportunistically, whenever it waits anyway:
+//!
+//! - while waiting for a core to halt,
+//! - between the chunks of large memory transfers, at their cancellation points,
+//!
+//! and on [`Session::service_drains`](crate::Session::service_drains). The opportunistic
+//! servicing is limited to a time budget per cancellation point, see
+//! [`Session::set_drain_budget`](crate::Session::set_drain_budget), so that it never starves
+//! the operation which is running, and errors of a drain are only recorded in its
+//! [`DrainStatus`].
+//!
+//! A drain only writes the tail pointer of the buffer back if the maximum intrusiveness of
+//! the session allows writing memory, see
+//! [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness). Otherwise, it
+//! keeps track of the data it read on its own.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
+
+use crate::{Core, Deadline, Error, MemoryInterface, TargetOperation};
+
+/// The largest number of bytes read from one drain per service, so that each read is a
+/// single chunk without cancellation points of its own.
+const MAX_DRAIN_LEN: u64 = 0x1000;
+
+/// The time the drains may take at one cancellation point by default.
+const DEFAULT_SERVICE_BUDGET: Duration = Duration::from_millis(2);
+
+/// How the head and the tail pointer of a [`CircularBuffer`] count, as 32-bit words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferPointers {
+    /// The pointers are byte offsets from the base of the buffer, and wrap at its size. One
+    /// element always stays free, like in an RTT channel.
+    Offset,
+    /// The pointers are addresses in the buffer, and wrap at its end.
+    Address,
+    /// The pointers count the elements which were produced and consumed, and only wrap at
+    /// 2^32. Only these allow to tell how much data was lost when the producer lapped the
+    /// consumer.
+    Counter,
+}
+
+/// The layout of a circular buffer in the memory of the target, which is drained by the
+/// session, see [`Session::register_drain`](crate::Session::register_drain).
+///
+/// The producer advances the head pointer, the drain advances the tail pointer. A producer
+/// which overwrites data which wasn't drained yet is detected if it counts with
+/// [`BufferPointers::Counter`], or if it advances the tail pointer itself when it laps the
+/// consumer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircularBuffer {
+    /// The core through which the buffer is read.
+    pub core: usize,
+    /// The address of the first byte of the buffer.
+    pub base: u64,
+    /// The size of the buffer in bytes.
+    pub size: u64,
+    /// The size of an element in bytes. Only whole elements are drained.
+    pub element_size: u64,
+    /// The address of the head pointer, which is advanced by the producer.
+    pub head: u64,
+    /// The address of the tail pointer, which is advanced by the drain. Without it, the drain
+    /// starts at the head when it is serviced for the first time.
+    pub tail: Option<u64>,
+    /// How the pointers count.
+    pub pointers: BufferPointers,
+}
+
+impl CircularBuffer {
+    /// A buffer of `size` bytes at `base`, whose head pointer is at `head`, and whose pointers
+    /// are byte offsets. The buffer is read through core 0.
+    pub fn new(base: u64, size: u64, head: u64) -> Self {
+        Self {
+            core: 0,
+            base,
+            size,
+            element_size: 1,
+            head,
+            tail: None,
+            pointers: BufferPointers::Offset,
+        }
+    }
+
+    /// Read the buffer through the core `core`.
+    pub fn core(mut self, core: usize) -> Self {
+        self.core = core;
+        self
+    }
+
+    /// Advance the tail pointer at `address` when the buffer is drained.
+    pub fn tail(mut self, address: u64) -> Self {
+        self.tail = Some(address);
+        self
+    }
+
+    /// Only drain whole elements of `size` bytes.
+    pub fn element_size(mut self, size: u64) -> Self {
+        self.element_size = size;
+        self
+    }
+
+    /// Set how the pointers count.
+    pub fn pointers(mut self, pointers: BufferPointers) -> Self {
+        self.pointers = pointers;
+        self
+    }
+
+    /// Returns an error if the buffer can't be drained.
+    fn validate(&self) -> Result<(), Error> {
+        let reason = if self.size == 0 {
+            "the buffer is empty"
+        } else if self.element_size == 0 || self.size % self.element_size != 0 {
+            "the size of the buffer is not a multiple of the element size"
+        } else if self.size > u64::from(u32::MAX) {
+            "the buffer is larger than its 32-bit pointers can address"
+        } else {
+            return Ok(());
+        };
+
+        Err(Error::InvalidCircularBuffer {
+            base: self.base,
+            reason,
+        })
+    }
+}
+
+/// The receiver of the data of a drain, see
+/// [`Session::register_drain`](crate::Session::register_drain).
+pub trait DrainSink: Send {
+    /// Called with the data which was drained, in the order in which it was produced.
+    fn drained(&mut self, data: &[u8]);
+
+    /// Called when the producer overwrote data which was not drained yet, before the data
+    /// which follows the gap is passed to [`DrainSink::drained`]. `lost` is the number of
+    /// bytes which were lost, if it is known.
+    fn overflowed(&mut self, lost: Option<u64>) {
+        let _ = lost;
+    }
+}
+
+impl DrainSink for std::sync::mpsc::Sender<Vec<u8>> {
+    fn drained(&mut self, data: &[u8]) {
+        // The data of a receiver which was dropped is discarded.
+        let _ = self.send(data.to_vec());
+    }
+}
+
+/// Identifies a drain of a session, returned by
+/// [`Session::register_drain`](crate::Session::register_drain).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DrainId(usize);
+
+/// What a drain did so far, see [`Session::drain_status`](crate::Session::drain_status).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DrainStatus {
+    /// The number of times the drain was serviced.
+    pub services: u64,
+    /// The number of bytes which were drained.
+    pub drained: u64,
+    /// The number of times the producer lapped the drain.
+    pub overflows: u64,
+    /// The number of bytes which were lost to overflows, as far as it is known.
+    pub lost: u64,
+    /// The error of the last service, if it failed.
+    pub last_error: Option<String>,
+}
+
+/// A registered drain, with the position up to which it read the buffer.
+struct DrainTask {
+    id: DrainId,
+    buffer: CircularBuffer,
+    sink: Box<dyn DrainSink>,
+    min_interval: Duration,
+    last_serviced: Option<Instant>,
+    /// The value of the tail pointer up to which the buffer was drained.
+    consumer: Option<u64>,
+    /// The value the drain wrote to the tail pointer of the target last.
+    written_tail: Option<u64>,
+    status: DrainStatus,
+}
+
+impl DrainTask {
+    /// Returns true if the drain is serviced opportunistically at `now`.
+    fn due(&self, now: Instant) -> bool {
+        match self.last_serviced {
+            Some(last) => now.saturating_duration_since(last) >= self.min_interval,
+            None => true,
+        }
+    }
+
+    /// The byte offset from the base of the buffer of the pointer `value`, which was read
+    /// from `address`.
+    fn offset(&self, address: u64, value: u64) -> Result<u64, Error> {
+        let buffer = &self.buffer;
+
+        let offset = match buffer.pointers {
+            BufferPointers::Offset => Some(value),
+            BufferPointers::Address => value.checked_sub(buffer.base),
+            BufferPointers::Counter => Some(value * buffer.element_size % buffer.size),
+        };
+
+        offset
+            .filter(|offset| *offset < buffer.size)
+            .ok_or(Error::InvalidDrainPointer { address, value })
+    }
+
+    /// Read the new data of the buffer through `core`, and push it to the sink.
+    fn service(&mut self, core: &mut Core<'_>) -> Result<(), Error> {
+        let buffer = self.buffer.clone();
+        let tail_address = buffer.tail.unwrap_or(buffer.head);
+
+        let head = u64::from(core.read_word_32(buffer.head)?);
+        let target_tail = match buffer.tail {
+            Some(address) => Some(u64::from(core.read_word_32(address)?)),
+            None => None,
+        };
+
+        let mut tail = self.consumer.or(target_tail).unwrap_or(head);
+        let mut overflowed = false;
+        let mut lost = None;
+
+        // A producer which laps the consumer moves the tail pointer past the lost data.
+        if let (Some(written), Some(target_tail)) = (self.written_tail, target_tail) {
+            if written != target_tail {
+                overflowed = true;
+                tail = target_tail;
+            }
+        }
+
+        let (start, available) = match buffer.pointers {
+            BufferPointers::Offset | BufferPointers::Address => {
+                let head = self.offset(buffer.head, head)?;
+                let start = self.offset(tail_address, tail)?;
+
+                (start, (head + buffer.size - start) % buffer.size)
+            }
+            BufferPointers::Counter => {
+                let capacity = buffer.size / buffer.element_size;
+                let mut pending = head.wrapping_sub(tail) & u64::from(u32::MAX);
+
+                if pending > capacity {
+                    overflowed = true;
+                    lost = Some((pending - capacity) * buffer.element_size);
+                    tail = (tail + pending - capacity) & u64::from(u32::MAX);
+                    pending = capacity;
+                }
+
+                (
+                    self.offset(tail_address, tail)?,
+                    pending * buffer.element_size,
+                )
+            }
+        };
+
+        let len = available.min(MAX_DRAIN_LEN.max(buffer.element_size));
+        let len = len - len % buffer.element_size;
+
+        let mut data = vec![0; len as usize];
+        let first = len.min(buffer.size - start) as usize;
+        core.read_8(buffer.base + start, &mut data[..first])?;
+        if first < data.len() {
+            core.read_8(buffer.base, &mut data[first..])?;
+        }
+
+        let consumed = match buffer.pointers {
+            BufferPointers::Offset => (start + len) % buffer.size,
+            BufferPointers::Address => buffer.base + (start + len) % buffer.size,
+            BufferPointers::Counter => (tail + len / buffer.element_size) & u64::from(u32::MAX),
+        };
+        self.consumer = Some(consumed);
+
+        match buffer.tail {
+            Some(address) if len > 0 => {
+                if core.require(TargetOperation::WriteMemory).is_ok() {
+                    core.write_word_32(address, consumed as u32)?;
+                    self.written_tail = Some(consumed);
+                } else {
+                    // The tail pointer of the target stays behind, so it can't tell whether
+                    // the producer lapped the drain.
+                    self.written_tail = None;
+                }
+            }
+            _ => {
+                if self.written_tail.is_some() {
+                    self.written_tail = Some(consumed);
+                }
+            }
+        }
+
+        if overflowed {
+            log::warn!(
+                "The producer of the buffer at {:#010x} overwrote data before it was drained",
+                buffer.base
+            );
+
+            self.status.overflows += 1;
+            self.status.lost += lost.unwrap_or(0);
+            self.sink.overflowed(lost);
+        }
+
+        if !data.is_empty() {
+            self.status.drained += len;
+            self.sink.drained(&data);
+        }
+
+        Ok(())
+    }
+}
+
+/// The drains of a session, which are shared with its cores.
+#[derive(Clone, Default)]
+pub(crate) struct Drains(Arc<Mutex<DrainTable>>);
+
+struct DrainTable {
+    next_id: usize,
+    tasks: Vec<DrainTask>,
+    /// The index of the drain which is serviced first at the next cancellation point, so
+    /// that all drains get their turn even if the budget is exhausted.
+    cursor: usize,
+    budget: Duration,
+}
+
+impl Default for DrainTable {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            tasks: Vec::new(),
+            cursor: 0,
+            budget: DEFAULT_SERVICE_BUDGET,
+        }
+    }
+}
+
+impl fmt::Debug for Drains {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The drains are locked while they are serviced.
+        let tasks = self.0.try_lock().map(|table| table.tasks.len()).ok();
+
+        f.debug_struct("Drains").field("tasks", &tasks).finish()
+    }
+}
+
+impl Drains {
+    fn lock(&self) -> MutexGuard<'_, DrainTable> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn register(
+        &self,
+        buffer: CircularBuffer,
+        sink: Box<dyn DrainSink>,
+        min_interval: Duration,
+    ) -> Result<DrainId, Error> {
+        buffer.validate()?;
+
+        let mut table = self.lock();
+        let id = DrainId(table.next_id);
+        table.next_id += 1;
+
+        table.tasks.push(DrainTask {
+            id,
+            buffer,
+            sink,
+            min_interval,
+            last_serviced: None,
+            consumer: None,
+            written_tail: None,
+            status: DrainStatus::default(),
+        });
+
+        Ok(id)
+    }
+
+    pub(crate) fn unregister(&self, id: DrainId) -> Option<DrainStatus> {
+        let mut table = self.lock();
+        let index = table.tasks.iter().position(|task| task.id == id)?;
+
+        table.cursor = 0;
+        Some(table.tasks.remove(index).status)
+    }
+
+    pub(crate) fn status(&self, id: DrainId) -> Option<DrainStatus> {
+        self.lock()
+            .tasks
+            .iter()
+            .find(|task| task.id == id)
+            .map(|task| task.status.clone())
+    }
+
+    pub(crate) fn set_budget(&self, budget: Duration) {
+        self.lock().budget = budget;
+    }
+
+    /// The cores through which drains are read.
+    pub(crate) fn cores(&self) -> Vec<usize> {
+        let mut cores: Vec<_> = self
+            .lock()
+            .tasks
+            .iter()
+            .map(|task| task.buffer.core)
+            .collect();
+        cores.sort_unstable();
+        cores.dedup();
+
+        cores
+    }
+
+    /// Service the drains of `core` which are due, within the budget, and no later than
+    /// `deadline`. Errors are recorded in the status of the drain which failed.
+    pub(crate) fn service_opportunistically(
+        &self,
+        core: &mut Core<'_>,
+        deadline: Option<Deadline>,
+    ) {
+        // The drains are already being serviced further up the stack.
+        let mut table = match self.0.try_lock() {
+            Ok(table) => table,
+            Err(TryLockError::WouldBlock) => return,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        };
+
+        let budget = match deadline {
+            Some(deadline) => table.budget.min(deadline.remaining()),
+            None => table.budget,
+        };
+
+        // Errors are recorded in the status, and never fail the operation which is running.
+        let _ = service(&mut table, core, Some(budget));
+    }
+
+    /// Service all drains of `core`, regardless of their interval.
+    pub(crate) fn service_all(&self, core: &mut Core<'_>) -> Result<(), Error> {
+        service(&mut self.lock(), core, None)
+    }
+}
+
+/// Service the drains of `core` in `table`. Without a `budget`, all drains are serviced
+/// and the first error is returned.
+fn service(
+    table: &mut DrainTable,
+    core: &mut Core<'_>,
+    budget: Option<Duration>,
+) -> Result<(), Error> {
+    let count = table.tasks.len();
+    let start = Instant::now();
+
+    for step in 0..count {
+        if matches!(budget, Some(budget) if start.elapsed() >= budget) {
+            break;
+        }
+
+        let index = (table.cursor + step) % count;
+        let task = &mut table.tasks[index];
+
+        if task.buffer.core != core.id() || (budget.is_some() && !task.due(Instant::now())) {
+            continue;
+        }
+
+        let result = task.service(core);
+
+        task.last_serviced = Some(Instant::now());
+        task.status.services += 1;
+        task.status.last_error = result.as_ref().err().map(|e| e.to_string());
+        table.cursor = (index + 1) % count;
+
+        if let Err(error) = result {
+            log::debug!(
+                "Draining the buffer at {:#010x} failed: {}",
+                task.buffer.base,
+                error
+            );
+
+            if budget.is_none() {
+                return Err(error);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buffers_which_can_not_be_drained_are_rejected() {
+        assert!(CircularBuffer::new(0x2000_0000, 64, 0x2000_1000)
+            .validate()
+            .is_ok());
+        assert!(CircularBuffer::new(0x2000_0000, 0, 0x2000_1000)
+            .validate()
+            .is_err());
+        assert!(CircularBuffer::new(0x2000_0000, 64, 0x2000_1000)
+            .element_size(3)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn drains_are_identified_until_they_are_unregistered() {
+        let drains = Drains::default();
+        let (sender, _receiver) = std::sync::mpsc::channel();
+
+        let id = drains
+            .register(
+                CircularBuffer::new(0x2000_0000, 64, 0x2000_1000).core(1),
+                Box::new(sender),
+                Duration::ZERO,
+            )
+            .unwrap();
+
+        assert_eq!(drains.cores(), [1]);
+        assert_eq!(drains.status(id), Some(DrainStatus::default()));
+        assert_eq!(drains.unregister(id), Some(DrainStatus::default()));
+        assert_eq!(drains.status(id), None);
+        assert!(drains.cores().is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Workarounds for errata of chips which affect debugging.
+//!
+//! Target descriptions list the identifiers of the errata of a chip. When a session is
+//! opened, the workarounds of the listed errata are activated for the affected cores, and
+//! then applied at well-defined points: before memory of an NVM region is read, after a
+//! reset, and after a hardware breakpoint is set.
+
+use std::ops::Range;
+
+use crate::{Core, CoreType, Error, MemoryInterface};
+
+/// A known erratum which affects debugging.
+#[derive(Debug)]
+pub struct Erratum {
+    /// The identifier of the erratum, which is used in target descriptions.
+    pub id: &'static str,
+    /// What goes wrong, and how it is worked around.
+    pub description: &'static str,
+    /// The types of the affected cores.
+    pub cores: &'static [CoreType],
+    /// The affected core revisions, e.g. `r0p1`, or an empty list if all revisions are affected.
+    pub revisions: &'static [&'static str],
+    workaround: Workaround,
+}
+
+/// A hook of a workaround, which accesses the memory of the core.
+type Hook = fn(&mut dyn MemoryInterface) -> Result<(), Error>;
+
+/// A hook of a workaround, which is called for an address.
+type AddressHook = fn(&mut dyn MemoryInterface, u64) -> Result<(), Error>;
+
+/// The hooks of a workaround. Hooks which are not needed are `None`.
+#[derive(Debug, Clone, Copy)]
+struct Workaround {
+    /// Called before memory at the given address in an NVM region is read.
+    before_nvm_read: Option<AddressHook>,
+    /// Called after the core was reset.
+    after_reset: Option<Hook>,
+    /// Called after a hardware breakpoint was set at the given address.
+    after_breakpoint_set: Option<AddressHook>,
+}
+
+const CORTEX_M: &[CoreType] = &[
+    CoreType::Armv6m,
+    CoreType::Armv7m,
+    CoreType::Armv7em,
+    CoreType::Armv8m,
+];
+
+/// All errata with a workaround.
+static ERRATA: &[Erratum] = &[
+    Erratum {
+        id: "stm32-low-power-debug",
+        description: "The clocks of the core, including DWT CYCCNT, and the debug connection \
+                      stop in Sleep, Stop and Standby mode. DBGMCU_CR is set after every reset \
+                      to keep them running.",
+        cores: CORTEX_M,
+        revisions: &[],
+        workaround: Workaround {
+            after_reset: Some(stm32_low_power_debug),
+            ..EMPTY_WORKAROUND
+        },
+    },
+    Erratum {
+        id: "fpb-enable-lost",
+        description: "Writing an FPB comparator clears FP_CTRL.ENABLE, so breakpoints are not \
+                      hit. FP_CTRL.ENABLE is set again after every breakpoint.",
+        cores: CORTEX_M,
+        revisions: &["r0p0", "r0p1"],
+        workaround: Workaround {
+            after_breakpoint_set: Some(fpb_enable_lost),
+            ..EMPTY_WORKAROUND
+        },
+    },
+    Erratum {
+        id: "riscv-sysbus-stale-read",
+        description: "The first system bus read from an NVM region returns the data of the \
+                      previous read. A dummy read is issued before NVM is read.",
+        cores: &[CoreType::Riscv],
+        revisions: &[],
+        workaround: Workaround {
+            before_nvm_read: Some(riscv_sysbus_stale_read),
+            ..EMPTY_WORKAROUND
+        },
+    },
+];
+
+const EMPTY_WORKAROUND: Workaround = Workaround {
+    before_nvm_read: None,
+    after_reset: None,
+    after_breakpoint_set: None,
+};
+
+fn stm32_low_power_debug(memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+    const DBGMCU_CR: u64 = 0xE004_2004;
+    const DBG_SLEEP_STOP_STANDBY: u32 = 0b111;
+
+    let value = memory.read_word_32(DBGMCU_CR)?;
+    memory.write_word_32(DBGMCU_CR, value | DBG_SLEEP_STOP_STANDBY)
+}
+
+fn fpb_enable_lost(memory: &mut dyn MemoryInterface, _address: u64) -> Result<(), Error> {
+    const FP_CTRL: u64 = 0xE000_2000;
+    const KEY_ENABLE: u32 = 0b11;
+
+    memory.write_word_32(FP_CTRL, KEY_ENABLE)
+}
+
+fn riscv_sysbus_stale_read(memory: &mut dyn MemoryInterface, address: u64) -> Result<(), Error> {
+    memory.read_word_32(address & !0b11).map(|_| ())
+}
+
+impl Erratum {
+    /// All errata which probe-rs has a workaround for.
+    pub fn all() -> &'static [Erratum] {
+        ERRATA
+    }
+
+    /// Find the erratum with the identifier `id`.
+    pub fn find(id: &str) -> Option<&'static Erratum> {
+        ERRATA.iter().find(|erratum| erratum.id == id)
+    }
+
+    /// Whether a core of type `core_type` with the revision `revision` is affected.
+    ///
+    /// If the revision is unknown, only errata which affect all revisions apply.
+    fn affects(&self, core_type: CoreType, revision: Option<&str>) -> bool {
+        self.cores.contains(&core_type)
+            && (self.revisions.is_empty()
+                || matches!(revision, Some(revision) if self.revisions.contains(&revision)))
+    }
+}
+
+/// An erratum whose workaround is applied to a core of the session.
+#[derive(Debug, Clone, Copy)]
+pub struct ActiveErratum {
+    /// The index of the core.
+    pub core: usize,
+    /// The erratum.
+    pub erratum: &'static Erratum,
+}
+
+/// The workarounds which are active for a core.
+#[derive(Debug, Default)]
+pub(crate) struct CoreErrata {
+    errata: Vec<&'static Erratum>,
+    /// The NVM regions of the memory map, for the `before_nvm_read` hooks.
+    nvm_ranges: Vec<Range<u64>>,
+}
+
+impl CoreErrata {
+    pub(crate) fn new(errata: Vec<&'static Erratum>, nvm_ranges: Vec<Range<u64>>) -> Self {
+        Self { errata, nvm_ranges }
+    }
+
+    pub(crate) fn before_read(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+        len: usize,
+    ) -> Result<(), Error> {
+        let end = address + len as u64;
+
+        let nvm = self
+            .nvm_ranges
+            .iter()
+            .find(|range| range.start < end && address < range.end);
+
+        if let Some(nvm) = nvm {
+            for hook in self.hooks(|workaround| workaround.before_nvm_read) {
+                hook(memory, address.max(nvm.start))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn after_reset(&self, memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        for hook in self.hooks(|workaround| workaround.after_reset) {
+            hook(memory)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn after_breakpoint_set(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+    ) -> Result<(), Error> {
+        for hook in self.hooks(|workaround| workaround.after_breakpoint_set) {
+            hook(memory, address)?;
+        }
+
+        Ok(())
+    }
+
+    fn hooks<'a, T: 'a>(
+        &'a self,
+        hook: impl Fn(&Workaround) -> Option<T> + 'a,
+    ) -> impl Iterator<Item = T> + 'a {
+        self.errata
+            .iter()
+            .filter_map(move |erratum| hook(&erratum.workaround))
+    }
+}
+
+/// Determine which of the errata with the identifiers `ids` affect `core`.
+///
+/// Unknown identifiers are ignored with a warning.
+pub(crate) fn affecting(core: &mut Core, ids: &[String]) -> Result<Vec<&'static Erratum>, Error> {
+    let mut errata = Vec::new();
+    let mut revision = None;
+
+    for id in ids {
+        let erratum = match Erratum::find(id) {
+            Some(erratum) => erratum,
+            None => {
+                log::warn!("Ignoring unknown erratum '{}' of the target.", id);
+                continue;
+            }
+        };
+
+        if !erratum.revisions.is_empty() && revision.is_none() {
+            revision = Some(core_revision(core)?);
+        }
+
+        if erratum.affects(
+            core.core_type(),
+            revision.as_ref().and_then(Option::as_deref),
+        ) {
+            errata.push(erratum);
+        } else {
+            log::debug!(
+                "Erratum '{}' does not affect core {} ({:?}, revision {:?})",
+                id,
+                core.id(),
+                core.core_type(),
+                revision
+            );
+        }
+    }
+
+    Ok(errata)
+}
+
+/// Read the revision of `core`, e.g. `r0p1`, if it can be determined.
+fn core_revision(core: &mut Core) -> Result<Option<String>, Error> {
+    const CPUID: u64 = 0xE000_ED00;
+
+    if !CORTEX_M.contains(&core.core_type()) {
+        return Ok(None);
+    }
+
+    let cpuid = core.read_word_32(CPUID)?;
+
+    Ok(Some(format!("r{}p{}", (cpuid >> 20) & 0xf, cpuid & 0xf)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::mock::MockMemory;
+
+    const NVM: Range<u64> = 0x0800_0000..0x0810_0000;
+
+    /// Memory whose NVM returns the data of the previous read on the
+    /// first read after another memory was read.
+    fn stale_sysbus() -> MockMemory {
+        let mut memory = MockMemory::default();
+        memory.set_stale_reads(NVM);
+        memory.write_word_32(0x2000_0000, 0x1111_1111).unwrap();
+        memory.write_word_32(0x0800_0000, 0x2222_2222).unwrap();
+        memory.read_word_32(0x2000_0000).unwrap();
+        memory
+    }
+
+    #[test]
+    fn riscv_sysbus_stale_read_workaround() {
+        let errata = CoreErrata::new(
+            vec![Erratum::find("riscv-sysbus-stale-read").unwrap()],
+            vec![NVM],
+        );
+
+        // Without the workaround, the data of the previous read is returned.
+        let mut memory = stale_sysbus();
+        assert_eq!(memory.read_word_32(0x0800_0000).unwrap(), 0x1111_1111);
+
+        let mut memory = stale_sysbus();
+        errata.before_read(&mut memory, 0x0800_0000, 4).unwrap();
+        assert_eq!(memory.read_word_32(0x0800_0000).unwrap(), 0x2222_2222);
+    }
+
+    #[test]
+    fn erratum_affects_revisions() {
+        let erratum = Erratum::find("fpb-enable-lost").unwrap();
+
+        assert!(erratum.affects(CoreType::Armv7em, Some("r0p1")));
+        assert!(!erratum.affects(CoreType::Armv7em, Some("r1p0")));
+        assert!(!erratum.affects(CoreType::Armv7em, None));
+        assert!(!erratum.affects(CoreType::Riscv, Some("r0p1")));
+
+        let erratum = Erratum::find("stm32-low-power-debug").unwrap();
+        assert!(erratum.affects(CoreType::Armv6m, None));
+    }
+
+    #[test]
+    fn errata_ids_are_unique() {
+        for (index, erratum) in ERRATA.iter().enumerate() {
+            assert!(
+                ERRATA[index + 1..]
+                    .iter()
+                    .all(|other| other.id != erratum.id),
+                "Duplicate erratum '{}'",
+                erratum.id
+            );
+        }
+    }
+}
@@ -1,7 +1,15 @@
 #![warn(missing_docs)]
 
-use crate::DebugProbeError;
-use crate::{architecture::arm::ap::AccessPortError, config::RegistryError};
+use crate::architecture::arm::{ap::AccessPortError, ApAddress, DapError};
+use crate::architecture::riscv::communication_interface::{AbstractCommandErrorKind, RiscvError};
+use crate::architecture::route::InterfaceRoute;
+use crate::config::RegistryError;
+use crate::{
+    link, Architecture, CloseReport, CoreType, DebugProbeError, FingerprintMismatch, HaltAttempt,
+    HealthLogEntry, Intrusiveness, LinkFailure, TargetOperation, TeardownFailure,
+    WatchpointQualifier,
+};
+use std::ops::Range;
 
 /// The overarching error type which contains all possible errors as variants.
 #[derive(thiserror::Error, Debug)]
@@ -24,15 +32,442 @@ pub enum Error {
     /// The requested feature requires one of the architectures specified by this error.
     #[error("This feature requires one of the following architectures: {0:?}")]
     ArchitectureRequired(&'static [&'static str]),
+    /// The core was attached through the debug interface of another architecture.
+    #[error("Core {core} can't be reached through the {architecture:?} debug interface")]
+    CoreArchitectureMismatch {
+        /// The index of the core.
+        core: usize,
+        /// The architecture of the debug interface.
+        architecture: Architecture,
+    },
+    /// Switching the probe to the debug interface of another core failed, and the previous
+    /// debug interface couldn't be restored.
+    #[error("The debug interface was lost while switching to the {0}")]
+    DebugInterfaceLost(InterfaceRoute),
+    /// The [`AttachPlan`](crate::AttachPlan) can't be executed on the target.
+    #[error("The attach plan can't be executed for core {core}: {reason}")]
+    InvalidAttachPlan {
+        /// The index of the core.
+        core: usize,
+        /// Why the plan can't be executed.
+        reason: String,
+    },
     /// An operation was not performed because the required permissions were not given.
     ///
     /// This can for example happen when the core is locked and needs to be erased to be unlocked.
     /// Then the correct permission needs to be given to automatically unlock the core to prevent accidental erases.
     #[error("An operation could not be performed because it lacked the permission to do so: {0}")]
     MissingPermissions(String),
+    /// The connection to the target was lost.
+    ///
+    /// This happens e.g. when a keepalive access fails because the target dropped its debug
+    /// state. The session can't be used anymore, and the target has to be attached again.
+    #[error("The connection to the target was lost")]
+    TargetLost(#[source] Box<Error>),
+    /// The operation was interrupted with an [`InterruptHandle`](crate::InterruptHandle).
+    ///
+    /// The operation was stopped at a cancellation point, and the session can still be used.
+    #[error("The operation was interrupted")]
+    Interrupted,
+    /// The core can't start executing at the given address.
+    #[error("The core can't start executing at {address:#010x}: {reason}")]
+    InvalidEntryPoint {
+        /// The entry address.
+        address: u64,
+        /// Why the core can't start at the address.
+        reason: String,
+    },
+    /// The access options of a core, which were overridden when attaching, are invalid.
+    #[error("Invalid access options for core {core}: {reason}")]
+    InvalidCoreAccessOptions {
+        /// The index of the core.
+        core: usize,
+        /// Why the options are invalid.
+        reason: String,
+    },
+    /// The register doesn't exist on the core, e.g. an FPU register of a core without an FPU.
+    #[error("The register {name} is not available on this {core_variant} core")]
+    RegisterNotAvailable {
+        /// The name of the register, or its number if the register is unknown.
+        name: String,
+        /// The variant of the core, e.g. `Armv6m`.
+        core_variant: String,
+    },
+    /// A mapping of the address map of a core is invalid, see
+    /// [`Core::set_load_offset`](crate::Core::set_load_offset).
+    #[error("The link range {link_range:#010x?} can't be mapped: {reason}")]
+    InvalidAddressMapping {
+        /// The link range of the mapping.
+        link_range: Range<u64>,
+        /// Why the mapping is invalid.
+        reason: String,
+    },
+    /// Attaching with [`AttachOptions::auto_speed`](crate::AttachOptions::auto_speed) failed,
+    /// because the link to the target failed even at the minimum speed.
+    ///
+    /// This is usually not caused by the protocol clock, but e.g. by the wiring or the power
+    /// supply of the target.
+    #[error("The target did not communicate reliably even at {speed_khz} kHz ({failure:?})")]
+    SpeedNegotiationFailed {
+        /// The lowest speed which was tried.
+        speed_khz: u32,
+        /// The kind of failure at the lowest speed.
+        failure: LinkFailure,
+        /// The error at the lowest speed.
+        source: Box<Error>,
+    },
+    /// Closing a session with [`Session::close`](crate::Session::close) failed, because some
+    /// steps of the teardown failed.
+    ///
+    /// The other steps were still performed, see `report`.
+    #[error("The session could not be closed cleanly, {} teardown steps failed", .failures.len())]
+    CloseFailed {
+        /// The steps which failed, in order.
+        failures: Vec<TeardownFailure>,
+        /// What was done despite the failures.
+        report: CloseReport,
+    },
+    /// The firmware of the target doesn't match the fingerprints of the guard of
+    /// [`Session::attach_guarded`](crate::Session::attach_guarded).
+    #[error("The firmware of the target doesn't match {} of the expected fingerprints", .mismatches.len())]
+    FirmwareMismatch {
+        /// The fingerprints which didn't match, with the memory of their regions.
+        mismatches: Vec<FingerprintMismatch>,
+    },
+    /// The operation is more intrusive than the maximum intrusiveness of the session, see
+    /// [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness).
+    ///
+    /// Nothing was sent to the target.
+    #[error("Refused {operation}, which is {required:?} intrusive, because the session allows at most {allowed:?}")]
+    IntrusivenessExceeded {
+        /// The operation which was refused.
+        operation: TargetOperation,
+        /// The intrusiveness of the operation.
+        required: Intrusiveness,
+        /// The maximum intrusiveness of the session.
+        allowed: Intrusiveness,
+    },
+    /// A [`ContextSnapshot`](crate::ContextSnapshot) was changed or damaged after it was
+    /// taken, so that its checksum doesn't match its contents.
+    #[error("The checksum of the context snapshot doesn't match its contents")]
+    ContextSnapshotCorrupted,
+    /// A [`ContextSnapshot`](crate::ContextSnapshot) can't be restored, because it was taken
+    /// on another type of core.
+    #[error("A context snapshot of a {snapshot:?} core can't be restored to a {core:?} core")]
+    ContextCoreMismatch {
+        /// The type of the core the snapshot was taken on.
+        snapshot: CoreType,
+        /// The type of the core the snapshot should be restored to.
+        core: CoreType,
+    },
+    /// [`Core::force_halt`](crate::Core::force_halt) couldn't halt the core with any of the
+    /// escalation levels it was allowed to use.
+    #[error("The core did not halt with any of {} escalation levels", .attempts.len())]
+    ForceHaltFailed {
+        /// The levels which were tried, in order.
+        attempts: Vec<HaltAttempt>,
+    },
+    /// A [`SystemDescription`](crate::SystemDescription) couldn't be loaded, because it isn't
+    /// valid JSON, or doesn't match the schema of its version.
+    #[error("The system description is invalid: {0}")]
+    InvalidSystemDescription(String),
+    /// A [`SystemDescription`](crate::SystemDescription) couldn't be loaded, because it was
+    /// written with a version of the schema which isn't supported.
+    #[error(
+        "The system description has version {version}, but only version {supported} is supported"
+    )]
+    UnsupportedSystemDescriptionVersion {
+        /// The version of the description.
+        version: u64,
+        /// The version which is supported.
+        supported: u64,
+    },
+    /// An [`OperationJournal`](crate::OperationJournal) couldn't be loaded or replayed,
+    /// because it doesn't match the schema of its version, or refers to a variable which
+    /// isn't defined.
+    #[error("The operation journal is invalid: {0}")]
+    InvalidOperationJournal(String),
+    /// An [`OperationJournal`](crate::OperationJournal) couldn't be loaded, because it was
+    /// written with a version of the schema which isn't supported.
+    #[error(
+        "The operation journal has version {version}, but only version {supported} is supported"
+    )]
+    UnsupportedOperationJournalVersion {
+        /// The version of the journal.
+        version: u64,
+        /// The version which is supported.
+        supported: u64,
+    },
+    /// The operation is not implemented for this type of core.
+    #[error("{0} is not implemented for this core")]
+    NotImplemented(&'static str),
+    /// The frame of the active exception can't be found, because LR doesn't hold an
+    /// EXC_RETURN value.
+    #[error("LR holds {0:#010x} instead of an EXC_RETURN value, so the core is not halted at the start of an exception handler")]
+    NoExceptionReturn(u32),
+    /// The core has to be halted for the operation, but it is running.
+    #[error("Core {0} is running, it has to be halted first")]
+    CoreNotHalted(usize),
+    /// The core has no floating-point unit.
+    #[error("The core has no floating-point unit")]
+    FpuNotPresent,
+    /// The floating-point unit of the core is switched off by the setting in the error, so
+    /// its registers can't be read without changing the state of the core.
+    #[error("The floating-point unit is switched off ({0}), so its registers can't be read")]
+    FpuDisabled(&'static str),
+    /// All hardware breakpoint units of the core are in use.
+    #[error("No hardware breakpoint unit is available")]
+    HardwareBreakpointsExhausted,
+    /// The hardware breakpoint unit is already used for a breakpoint at another address.
+    #[error("Hardware breakpoint #{unit} is already in use for {address:#010x}")]
+    HardwareBreakpointInUse {
+        /// The index of the breakpoint unit.
+        unit: usize,
+        /// The address of the existing breakpoint.
+        address: u64,
+    },
+    /// A breakpoint can't be set at the address.
+    #[error("A breakpoint can't be set at {address:#010x}: {reason}")]
+    UnsupportedBreakpointAddress {
+        /// The address of the breakpoint.
+        address: u64,
+        /// Why the breakpoint can't be set there.
+        reason: &'static str,
+    },
+    /// The core has no DWT comparators, so addresses can't be watched.
+    #[error("The core has no DWT comparators")]
+    NoDwtComparators,
+    /// The breakpoint unit of the core has a revision which isn't supported.
+    #[error("The breakpoint unit has revision {0}, which is not supported")]
+    UnsupportedBreakpointUnitRevision(u32),
+    /// A breakpoint comparator of the core holds a value which can't be interpreted.
+    #[error("The breakpoint comparator value {0:#010x} is not supported")]
+    UnsupportedBreakpointComparator(u32),
+    /// No breakpoint is set at the address.
+    #[error("No breakpoint found at address {0:#010x}")]
+    BreakpointNotFound(u64),
+    /// The watchpoint can't be set as requested.
+    #[error("A watchpoint on {len} bytes at {address:#010x} can't be set: {reason}")]
+    InvalidWatchpoint {
+        /// The first watched address.
+        address: u64,
+        /// The number of watched bytes.
+        len: u64,
+        /// Why the watchpoint can't be set.
+        reason: &'static str,
+    },
+    /// The circular buffer can't be drained as described.
+    #[error("The circular buffer at {base:#010x} can't be drained: {reason}")]
+    InvalidCircularBuffer {
+        /// The base address of the buffer.
+        base: u64,
+        /// Why the buffer can't be drained.
+        reason: &'static str,
+    },
+    /// A pointer of a drained circular buffer points outside of the buffer.
+    #[error("The pointer at {address:#010x} of a drained buffer is {value:#x}, which is outside of the buffer")]
+    InvalidDrainPointer {
+        /// The address of the pointer.
+        address: u64,
+        /// The value of the pointer.
+        value: u64,
+    },
+    /// The core can't restrict a watchpoint by the qualifier.
+    #[error("This core can't qualify watchpoints by {qualifier}: {reason}")]
+    UnsupportedWatchpointQualifier {
+        /// The qualifier which isn't supported.
+        qualifier: WatchpointQualifier,
+        /// Why the qualifier isn't supported.
+        reason: &'static str,
+    },
+    /// Not enough watchpoint comparators of the core are left for the watchpoint.
+    #[error("Not enough watchpoint comparators are available")]
+    WatchpointsExhausted,
+    /// No watchpoint is set at the address.
+    #[error("No watchpoint found at address {0:#010x}")]
+    WatchpointNotFound(u64),
+    /// Software breakpoints can only be set in RAM.
+    #[error("Software breakpoints can only be set in RAM, but {0:#010x} is not in RAM")]
+    SoftwareBreakpointNotInRam(u64),
+    /// A register value doesn't fit into 32 bits.
+    #[error("The value {0:#x} doesn't fit into 32 bits")]
+    ValueTooLarge(u64),
+    /// The address is outside of the address space of the core.
+    #[error("Address {0:#010x} is out of range")]
+    AddressOutOfRange(u64),
+    /// The length of a buffer for a memory access isn't a multiple of the access width.
+    #[error("The length {len} of the buffer is not a multiple of {multiple}")]
+    InvalidBufferLength {
+        /// The length of the buffer in bytes.
+        len: usize,
+        /// The access width in bytes.
+        multiple: usize,
+    },
+    /// The access port doesn't exist.
+    #[error("AP {0:x?} does not exist")]
+    AccessPortNotFound(ApAddress),
+    /// The access port isn't a memory AP, which is needed for the operation.
+    #[error("AP {0:x?} is not a memory AP")]
+    NotAMemoryAccessPort(ApAddress),
+    /// The memory AP has no debug base address, so its components can't be found.
+    #[error("AP {0:x?} has a debug base address of 0")]
+    NoDebugBaseAddress(ApAddress),
+    /// The mediator of a memory region doesn't support the access.
+    #[error("The mediator of the memory at {address:#010x} doesn't support {access}")]
+    MediatedAccessUnsupported {
+        /// The address of the access.
+        address: u64,
+        /// The kind of access which isn't supported.
+        access: &'static str,
+    },
+    /// Writing the bytes at the address would require a read-modify-write of the words which
+    /// contain them, because the core can't write single bytes, and the memory is device
+    /// memory where reading and writing the whole words can have side effects, see
+    /// [`MemoryInterface::native_write_widths`](crate::MemoryInterface::native_write_widths).
+    ///
+    /// Nothing was written. [`Core::write_word_8_rmw`](crate::Core::write_word_8_rmw) does
+    /// the read-modify-write anyway.
+    #[error(
+        "Writing {len} bytes at {address:#010x} would require a read-modify-write of device memory"
+    )]
+    WouldRequireReadModifyWrite {
+        /// The address of the write.
+        address: u64,
+        /// The number of bytes of the write.
+        len: usize,
+    },
+    /// A write of less than a word to memory which is protected by ECC would have required a
+    /// read-modify-write of a word whose ECC wasn't initialized during the session.
+    ///
+    /// The word has to be initialized with
+    /// [`Core::ecc_initialize`](crate::Core::ecc_initialize) first, or marked as initialized
+    /// with [`Core::ecc_mark_initialized`](crate::Core::ecc_mark_initialized).
+    #[error("Writing {len} bytes at {address:#010x} would require a read-modify-write of memory whose ECC isn't initialized")]
+    EccUninitialized {
+        /// The address of the write.
+        address: u64,
+        /// The number of bytes of the write.
+        len: usize,
+    },
+    /// An access to memory which is protected by ECC faulted.
+    ///
+    /// Accesses to words whose ECC wasn't initialized fault on most targets.
+    #[error("The access to {len} bytes at {address:#010x} faulted in memory which is protected by ECC, it may have to be initialized with `Core::ecc_initialize` first")]
+    EccFault {
+        /// The address of the access.
+        address: u64,
+        /// The number of bytes of the access.
+        len: usize,
+        /// The error of the access.
+        #[source]
+        source: Box<Error>,
+    },
+    /// A peripheral was selected to be stopped while the cores are halted, but the target
+    /// has no freeze bit for it.
+    #[error("The target has no freeze bit for peripheral `{0}`")]
+    UnknownPeripheralFreeze(String),
+    /// The mask of a memory search doesn't have the length of the pattern.
+    #[error("The mask of {mask} bytes doesn't match the pattern of {pattern} bytes")]
+    SearchMaskMismatch {
+        /// The length of the pattern in bytes.
+        pattern: usize,
+        /// The length of the mask in bytes.
+        mask: usize,
+    },
+    /// The core has no RAM to run a routine in, and no scratch memory was given.
+    #[error("The core has no RAM to run the routine in")]
+    NoScratchMemory,
+    /// A routine doesn't fit into its scratch memory, with its input, output and stack.
+    #[error("The routine needs {required} bytes of scratch memory, but only {available} bytes are available")]
+    RoutineDoesNotFit {
+        /// The number of bytes the routine needs.
+        required: u64,
+        /// The size of the scratch memory in bytes.
+        available: u64,
+    },
+    /// A routine was called with more arguments than can be passed in registers.
+    #[error("The routine was called with {count} arguments, but only {supported} are supported")]
+    TooManyRoutineArguments {
+        /// The number of arguments.
+        count: usize,
+        /// The number of arguments which can be passed.
+        supported: usize,
+    },
+    /// A routine didn't complete in time, and was halted.
+    #[error("The routine didn't complete within {timeout:?}, it was halted at {pc:#010x}")]
+    RoutineTimedOut {
+        /// The time the routine was given.
+        timeout: std::time::Duration,
+        /// The program counter of the halted core.
+        pc: u64,
+    },
+    /// The core halted before a routine completed, e.g. because of a fault.
+    #[error("The routine stopped at {pc:#010x} before it completed")]
+    RoutineCrashed {
+        /// The program counter of the halted core.
+        pc: u64,
+    },
+    /// The entry point of a routine isn't a symbol of its ELF file.
+    #[error("The entry point `{0}` of the routine was not found")]
+    RoutineEntryNotFound(String),
+    /// An atomic operation was requested on a word which isn't aligned to 4 bytes.
+    #[error("The atomic operation at {0:#010x} is not aligned to a word")]
+    UnalignedAtomic(u64),
+    /// The running core has no atomic instructions which the debugger can use, and
+    /// non-atomic read-modify-writes weren't allowed, see
+    /// [`Core::set_force_non_atomic`](crate::Core::set_force_non_atomic).
+    #[error("The core can't modify memory atomically while it is running")]
+    AtomicUnsupported,
+    /// A file couldn't be read.
+    #[error("Failed to read {path:?}")]
+    FileRead {
+        /// The path of the file.
+        path: std::path::PathBuf,
+        /// The error of the read.
+        #[source]
+        source: std::io::Error,
+    },
+    /// A file couldn't be written.
+    #[error("Failed to write {path:?}")]
+    FileWrite {
+        /// The path of the file.
+        path: std::path::PathBuf,
+        /// The error of the write.
+        #[source]
+        source: std::io::Error,
+    },
+    /// A firmware image isn't a valid ELF file.
+    #[error("Invalid ELF file: {0}")]
+    InvalidElf(String),
+    /// A check of the conformance suite was selected by a name which doesn't exist.
+    #[error("There is no conformance check named `{0}`")]
+    UnknownConformanceCheck(String),
+    /// A range of memory given to the conformance suite can't be used for its checks.
+    #[error(
+        "The range {start:#010x}..{end:#010x} can't be used by the conformance suite: {reason}"
+    )]
+    InvalidConformanceRange {
+        /// The start address of the range.
+        start: u64,
+        /// The end address of the range, exclusive.
+        end: u64,
+        /// Why the range can't be used.
+        reason: &'static str,
+    },
     /// Any other error occurred.
     #[error(transparent)]
     Other(#[from] anyhow::Error),
+    /// An error occurred after probe-rs had recovered from other anomalies during the session.
+    ///
+    /// The most recent anomalies are attached, as they are often the actual cause of the error.
+    #[error("The operation failed after recovering from {} anomalies", .entries.len())]
+    WithHealthLog {
+        /// The error which occurred.
+        #[source]
+        error: Box<Error>,
+        /// The most recent entries of the health log of the session.
+        entries: Vec<HealthLogEntry>,
+    },
 }
 
 impl Error {
@@ -40,6 +475,56 @@ impl Error {
     pub fn architecture_specific(e: impl std::error::Error + Send + Sync + 'static) -> Self {
         Self::ArchitectureSpecific(Box::new(e))
     }
+
+    /// Returns the kind of link failure which caused this error, if it was caused by one.
+    ///
+    /// Link failures can be caused by a protocol clock which is too fast. Other errors, e.g.
+    /// a probe which can't be opened, or a target which isn't supported, return `None`.
+    pub fn link_failure(&self) -> Option<LinkFailure> {
+        link::classify(self)
+    }
+
+    /// Returns `true` if this error was caused by an access to memory which faulted, e.g.
+    /// because nothing is mapped at the address, rather than by the probe or the link.
+    pub(crate) fn is_access_fault(&self) -> bool {
+        access_fault(self)
+    }
+}
+
+/// Returns `true` if `error` was caused by an access to memory which faulted, see
+/// [`Error::is_access_fault`].
+fn access_fault(error: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(error) = error.downcast_ref::<DapError>() {
+        return *error == DapError::FaultResponse;
+    }
+
+    if let Some(error) = error.downcast_ref::<RiscvError>() {
+        return matches!(
+            error,
+            RiscvError::SystemBusAccess
+                | RiscvError::AbstractCommand(AbstractCommandErrorKind::Exception)
+        );
+    }
+
+    // Transparent errors don't report the error they wrap as their source.
+    match error.downcast_ref::<DebugProbeError>() {
+        Some(DebugProbeError::Other(error)) => return error.chain().any(access_fault),
+        _ => (),
+    }
+
+    match error.downcast_ref::<Error>() {
+        Some(Error::EccFault { .. }) => return true,
+        Some(Error::Other(error)) => return error.chain().any(access_fault),
+        // A lost target isn't a fault of the access, even if the access faulted as well.
+        Some(Error::TargetLost(_)) => return false,
+        Some(Error::WithHealthLog { error, .. }) => return access_fault(&**error),
+        _ => (),
+    }
+
+    match error.source() {
+        Some(source) => access_fault(source),
+        None => false,
+    }
 }
 
 impl From<AccessPortError> for Error {
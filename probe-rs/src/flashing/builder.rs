@@ -258,17 +258,13 @@ impl FlashBuilder {
             })
     }
 
-    /// Layouts the contents of a flash memory according to the contents of the flash loader.
-    pub(super) fn build_sectors_and_pages(
+    /// Returns the sectors of `region` which have to be erased to program the contents of the flash loader.
+    pub(super) fn sectors(
         &self,
         region: &NvmRegion,
         flash_algorithm: &FlashAlgorithm,
-        include_empty_pages: bool,
-    ) -> Result<FlashLayout, FlashError> {
+    ) -> Vec<FlashSector> {
         let mut sectors: Vec<FlashSector> = Vec::new();
-        let mut pages: Vec<FlashPage> = Vec::new();
-        let mut fills: Vec<FlashFill> = Vec::new();
-        let mut data_blocks: Vec<FlashDataBlockSpan> = Vec::new();
 
         for info in flash_algorithm.iter_sectors() {
             let range = info.base_address..info.base_address + info.size;
@@ -294,6 +290,21 @@ impl FlashBuilder {
             })
         }
 
+        sectors
+    }
+
+    /// Layouts the contents of a flash memory according to the contents of the flash loader.
+    pub(super) fn build_sectors_and_pages(
+        &self,
+        region: &NvmRegion,
+        flash_algorithm: &FlashAlgorithm,
+        include_empty_pages: bool,
+    ) -> Result<FlashLayout, FlashError> {
+        let sectors = self.sectors(region, flash_algorithm);
+        let mut pages: Vec<FlashPage> = Vec::new();
+        let mut fills: Vec<FlashFill> = Vec::new();
+        let mut data_blocks: Vec<FlashDataBlockSpan> = Vec::new();
+
         for info in flash_algorithm.iter_pages() {
             let page_end = info.base_address + info.size as u64;
             let range = info.base_address..page_end;
@@ -393,6 +404,8 @@ mod tests {
             is_boot_memory: true,
             range: 0..1 << 16,
             cores: vec!["main".into()],
+            boot_critical_ranges: vec![],
+            target_writable: false,
         };
 
         (region, flash_algorithm)
@@ -421,6 +434,8 @@ mod tests {
             is_boot_memory: true,
             range: 0..1 << 16,
             cores: vec!["main".into()],
+            boot_critical_ranges: vec![],
+            target_writable: false,
         };
 
         (region, flash_algorithm)
@@ -0,0 +1,230 @@
+//! LZSS compression of flash pages.
+//!
+//! Flash algorithms can provide a `ProgramPageCompressed()` entry point, which
+//! decompresses the page data on the target before programming it. This reduces the
+//! amount of data which has to be transferred, which is significant for probes with
+//! a high latency, e.g. probes connected over the network.
+//!
+//! The compressed stream consists of groups of up to eight items. Each group starts
+//! with a flag byte, whose bits describe the items of the group, starting with the
+//! least significant bit:
+//!
+//! - A set bit means that the item is a single literal byte.
+//! - A cleared bit means that the item is a back reference, stored as a 16-bit little endian
+//!   value `(distance - 1) << 4 | (length - 3)`. `length` bytes are copied from `distance`
+//!   bytes before the current output position, one byte at a time, so the source and
+//!   destination may overlap.
+//!
+//! The stream ends when all input bytes have been consumed.
+
+/// The maximum distance of a back reference.
+const WINDOW_SIZE: usize = 1 << 12;
+
+/// The shortest match which is encoded as a back reference.
+const MIN_MATCH: usize = 3;
+
+/// The longest match which can be encoded as a back reference.
+const MAX_MATCH: usize = MIN_MATCH + 0xf;
+
+const HASH_SIZE: usize = 1 << 12;
+
+/// The number of previous positions which are checked for a match.
+const MAX_CHAIN_LENGTH: usize = 128;
+
+const NO_POSITION: usize = usize::MAX;
+
+/// Compress `data` using the LZSS format described in the [module documentation](self).
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + data.len() / 8 + 1);
+
+    let mut head = vec![NO_POSITION; HASH_SIZE];
+    let mut previous = vec![NO_POSITION; data.len()];
+
+    let mut flag_index = 0;
+    let mut flag_bit = 8;
+
+    let mut position = 0;
+
+    while position < data.len() {
+        if flag_bit == 8 {
+            flag_index = output.len();
+            output.push(0);
+            flag_bit = 0;
+        }
+
+        let (length, distance) = longest_match(data, position, &head, &previous);
+
+        let length = if length >= MIN_MATCH {
+            let reference = ((distance - 1) << 4 | (length - MIN_MATCH)) as u16;
+            output.extend_from_slice(&reference.to_le_bytes());
+            length
+        } else {
+            output[flag_index] |= 1 << flag_bit;
+            output.push(data[position]);
+            1
+        };
+
+        flag_bit += 1;
+
+        for p in position..position + length {
+            insert_position(data, p, &mut head, &mut previous);
+        }
+
+        position += length;
+    }
+
+    output
+}
+
+/// Decompress data which was compressed with [`compress`].
+///
+/// Returns `None` if the data is not a valid compressed stream.
+pub fn decompress(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len() * 2);
+    let mut input = data.iter().copied();
+
+    while let Some(flags) = input.next() {
+        for bit in 0..8 {
+            if flags & (1 << bit) != 0 {
+                match input.next() {
+                    Some(byte) => output.push(byte),
+                    None => return Some(output),
+                }
+            } else {
+                let low = match input.next() {
+                    Some(byte) => byte,
+                    None => return Some(output),
+                };
+                let reference = u16::from_le_bytes([low, input.next()?]) as usize;
+
+                let distance = (reference >> 4) + 1;
+                let length = (reference & 0xf) + MIN_MATCH;
+
+                let start = output.len().checked_sub(distance)?;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+    }
+
+    Some(output)
+}
+
+fn hash(data: &[u8], position: usize) -> Option<usize> {
+    let bytes = data.get(position..position + MIN_MATCH)?;
+
+    Some(
+        ((bytes[0] as usize) << 8 ^ (bytes[1] as usize) << 4 ^ bytes[2] as usize) & (HASH_SIZE - 1),
+    )
+}
+
+/// Add `position` to the hash chains, so that later data can refer to it.
+fn insert_position(data: &[u8], position: usize, head: &mut [usize], previous: &mut [usize]) {
+    if let Some(hash) = hash(data, position) {
+        previous[position] = head[hash];
+        head[hash] = position;
+    }
+}
+
+/// Find the longest match for the data at `position` in the window before it.
+///
+/// Returns the length and distance of the match.
+fn longest_match(
+    data: &[u8],
+    position: usize,
+    head: &[usize],
+    previous: &[usize],
+) -> (usize, usize) {
+    let hash = match hash(data, position) {
+        Some(hash) => hash,
+        None => return (0, 0),
+    };
+
+    let max_length = MAX_MATCH.min(data.len() - position);
+
+    let mut best = (0, 0);
+    let mut candidate = head[hash];
+
+    for _ in 0..MAX_CHAIN_LENGTH {
+        if candidate == NO_POSITION || position - candidate > WINDOW_SIZE {
+            break;
+        }
+
+        let length = (0..max_length)
+            .take_while(|&i| data[candidate + i] == data[position + i])
+            .count();
+
+        if length > best.0 {
+            best = (length, position - candidate);
+
+            if length == max_length {
+                break;
+            }
+        }
+
+        candidate = previous[candidate];
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pseudo_random_data(len: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn erased_page_compresses_well() {
+        let page = vec![0xff; 4096];
+
+        let compressed = compress(&page);
+
+        assert!(compressed.len() < page.len() / 8);
+        assert_eq!(decompress(&compressed).unwrap(), page);
+    }
+
+    #[test]
+    fn round_trip_is_byte_exact() {
+        let mut page = Vec::new();
+        page.extend_from_slice(&pseudo_random_data(1000));
+        page.extend_from_slice(b"probe-rs probe-rs probe-rs probe-rs");
+        page.extend_from_slice(&[0; 300]);
+        page.extend_from_slice(&pseudo_random_data(1000)[200..700]);
+        page.extend_from_slice(&[0xff; 7]);
+
+        for len in [0, 1, 2, 3, 17, 1024, page.len()] {
+            let data = &page[..len];
+            assert_eq!(decompress(&compress(data)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn incompressible_data_round_trip() {
+        let data = pseudo_random_data(8192);
+
+        let compressed = compress(&data);
+
+        // Every literal costs an additional bit.
+        assert!(compressed.len() <= data.len() + data.len() / 8 + 1);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn invalid_reference_is_rejected() {
+        // A back reference as the first item can't refer to any data.
+        assert_eq!(decompress(&[0x00, 0x00, 0x00]), None);
+    }
+}
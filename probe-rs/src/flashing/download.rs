@@ -115,6 +115,44 @@ pub struct DownloadOptions<'progress> {
     pub verify: bool,
     /// Disable double buffering when loading flash.
     pub disable_double_buffering: bool,
+    /// Allow erasing sectors which are marked as boot-critical in the target description,
+    /// e.g. option bytes or sectors reserved for a vendor bootloader.
+    ///
+    /// Without this flag, flashing data which would require such a sector to be erased fails.
+    pub allow_boot_sector_erase: bool,
+    /// Check if the image is likely to boot on the target before flashing it,
+    /// see [`FlashLoader::validate`](super::FlashLoader::validate).
+    ///
+    /// Warnings are logged, and the download is refused if an error is found.
+    pub validate_image: bool,
+    /// Disable the MPU of the core while the flash algorithm runs, and restore it afterwards.
+    ///
+    /// The flash algorithm is executed by the core, so it faults if the firmware configured
+    /// the MPU to deny access to the RAM or the flash controller. This only matters if the
+    /// MPU is still enabled after the core was reset, e.g. by a boot ROM.
+    pub disable_mpu: bool,
+    /// Report sector erases and page programming which take longer than this threshold
+    /// with a [`ProgressEvent::SlowOperation`] and in the health log of the session.
+    ///
+    /// The durations of all operations are reported in any case, see [`ProgressEvent::Timings`].
+    pub slow_operation_threshold: Option<SlowOperationThreshold>,
+    /// Directives for ranges of the flash which are not programmed like the rest of the image,
+    /// e.g. a bootloader which has to be preserved, or a page which is owned by the device
+    /// provisioning.
+    ///
+    /// The directives must not overlap each other. They are checked before anything is erased,
+    /// and the download fails with [`FlashError::LayoutConflict`](super::FlashError::LayoutConflict)
+    /// if they contradict the image or the other options. They only apply to flash, not to RAM.
+    pub layout: Vec<LayoutDirective>,
+    /// Program the flash sector by sector, and record each verified sector in a journal,
+    /// so that a download which was interrupted, e.g. by a power loss, can be continued.
+    ///
+    /// A later download of the same image skips the sectors which the journal records as
+    /// verified. The journal is only used for an image with the same contents, and it must not
+    /// overlap the image. A chip erase can't be combined with a journal. The progress is
+    /// reported for each sector on its own, including the writes of a journal in flash. See
+    /// [`JournalLocation`](super::JournalLocation) for where the journal is kept.
+    pub journal: Option<JournalLocation>,
 }
 
 impl<'progress> DownloadOptions<'progress> {
@@ -154,12 +192,7 @@ pub fn download_file_with_options<P: AsRef<Path>>(
     };
 
     let mut loader = session.target().flash_loader();
-
-    match format {
-        Format::Bin(options) => loader.load_bin_data(&mut file, options),
-        Format::Elf => loader.load_elf_data(&mut file),
-        Format::Hex => loader.load_hex_data(&mut file),
-    }?;
+    loader.load(&mut file, format)?;
 
     loader
         .commit(session, options)
@@ -278,6 +311,13 @@ pub(super) fn extract_from_elf<'data>(
     Ok(extracted_sections)
 }
 
+/// Returns the entry point of an ELF file.
+pub(super) fn elf_entry_point(elf_data: &[u8]) -> Result<u64, FileDownloadError> {
+    let elf_header = FileHeader32::<Endianness>::parse(elf_data)?;
+
+    Ok(elf_header.e_entry(elf_header.endian()?).into())
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
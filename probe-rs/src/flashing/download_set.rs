@@ -0,0 +1,425 @@
+//! Downloading several images in one planned operation, see [`FlashDownloadSet`].
+//!
+//! A product often consists of several images at different addresses, e.g. a bootloader, an
+//! application and a file system. Downloading them one by one loads the flash algorithm for
+//! each of them, and erases a sector which is shared by two images twice, which loses the
+//! part of the first image in it. A [`FlashDownloadSet`] combines the images into one
+//! [`FlashLoader`] instead, after checking that they don't contradict each other.
+
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::Path;
+
+use probe_rs_target::{MemoryRegion, TargetDescriptionSource};
+
+use super::builder::FlashBuilder;
+use super::{DownloadOptions, FileDownloadError, FlashError, FlashLoader, Format};
+use crate::session::Session;
+
+/// Several images which are downloaded in one planned operation.
+///
+/// The images are combined into a single plan: a sector which contains data of two images is
+/// erased once and programmed with the data of both, and each flash algorithm is loaded once.
+/// The images may overlap if they contain the same data in the overlap, otherwise the download
+/// fails with [`FlashError::ImagesConflict`] before anything is erased.
+///
+/// The [`DownloadOptions`] apply to the combined plan, e.g. a journal records the progress of
+/// all images, and the layout directives apply to the combined data.
+pub struct FlashDownloadSet {
+    memory_map: Vec<MemoryRegion>,
+    source: TargetDescriptionSource,
+    images: Vec<Image>,
+}
+
+/// An image of a [`FlashDownloadSet`].
+struct Image {
+    name: String,
+    loader: FlashLoader,
+}
+
+impl FlashDownloadSet {
+    /// Create a new set without images.
+    pub fn new(memory_map: Vec<MemoryRegion>, source: TargetDescriptionSource) -> Self {
+        Self {
+            memory_map,
+            source,
+            images: Vec::new(),
+        }
+    }
+
+    /// The loader of the image `name`, which is added if it doesn't exist yet.
+    fn image(&mut self, name: &str) -> &mut FlashLoader {
+        let index = match self.images.iter().position(|image| image.name == name) {
+            Some(index) => index,
+            None => {
+                self.images.push(Image {
+                    name: name.to_owned(),
+                    loader: FlashLoader::new(self.memory_map.clone(), self.source.clone()),
+                });
+                self.images.len() - 1
+            }
+        };
+
+        &mut self.images[index].loader
+    }
+
+    /// Add `data` at `address` to the image `name`.
+    ///
+    /// The image is created if it doesn't exist yet. The images are reported in the order in
+    /// which they were created.
+    pub fn add_data(&mut self, name: &str, address: u64, data: &[u8]) -> Result<(), FlashError> {
+        self.image(name).add_data(address, data)
+    }
+
+    /// Read the image `name` from `file` in the given `format`.
+    ///
+    /// The image is created if it doesn't exist yet.
+    pub fn add_image<T: Read + Seek>(
+        &mut self,
+        name: &str,
+        file: &mut T,
+        format: Format,
+    ) -> Result<(), FileDownloadError> {
+        self.image(name).load(file, format)
+    }
+
+    /// Read the image `name` from the file at `path` in the given `format`.
+    ///
+    /// The image is created if it doesn't exist yet.
+    pub fn add_file<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        path: P,
+        format: Format,
+    ) -> Result<(), FileDownloadError> {
+        let mut file = File::open(path.as_ref())?;
+
+        self.add_image(name, &mut file, format)
+    }
+
+    /// The names of the images, in the order in which they were added.
+    pub fn image_names(&self) -> impl Iterator<Item = &str> {
+        self.images.iter().map(|image| image.name.as_str())
+    }
+
+    /// Combine the images into a single [`FlashLoader`].
+    ///
+    /// Fails with [`FlashError::ImagesConflict`] if two images contain different data at the
+    /// same address. The entry point is taken from the first image which has one, so the
+    /// image which boots, e.g. the bootloader, should be added first.
+    pub fn loader(&self) -> Result<FlashLoader, FlashError> {
+        let mut combined = FlashBuilder::new();
+        // The image which contributed each part of the combined data.
+        let mut owners: Vec<(Range<u64>, usize)> = Vec::new();
+
+        for (index, image) in self.images.iter().enumerate() {
+            for (&address, data) in &image.loader.builder.data {
+                let range = address..address + data.len() as u64;
+                let mut gaps = Vec::new();
+                let mut next = range.start;
+
+                for (existing, existing_data) in combined.data_in_range(&range) {
+                    let offset = (existing - address) as usize;
+                    let ours = &data[offset..offset + existing_data.len()];
+
+                    if let Some(position) = ours.iter().zip(existing_data).position(|(a, b)| a != b)
+                    {
+                        let conflict = existing + position as u64;
+                        // The combined data is only added together with its owner.
+                        let (_, first) = owners
+                            .iter()
+                            .find(|(owned, _)| owned.contains(&conflict))
+                            .unwrap();
+
+                        return Err(FlashError::ImagesConflict {
+                            first: self.images[*first].name.clone(),
+                            second: image.name.clone(),
+                            address: conflict,
+                        });
+                    }
+
+                    if next < existing {
+                        gaps.push(next..existing);
+                    }
+                    next = existing + existing_data.len() as u64;
+                }
+
+                if next < range.end {
+                    gaps.push(next..range.end);
+                }
+
+                for gap in gaps {
+                    let start = (gap.start - address) as usize;
+                    let end = (gap.end - address) as usize;
+
+                    combined.add_data(gap.start, &data[start..end])?;
+                    owners.push((gap, index));
+                }
+            }
+        }
+
+        let mut loader = FlashLoader::new(self.memory_map.clone(), self.source.clone());
+        loader.builder = combined;
+        loader.set_entry_point(
+            self.images
+                .iter()
+                .find_map(|image| image.loader.entry_point()),
+        );
+
+        Ok(loader)
+    }
+
+    /// Download all images to the target.
+    ///
+    /// Returns the outcome of each image, in the order in which they were added. They are also
+    /// reported with [`ProgressEvent::ImageFinished`](super::ProgressEvent::ImageFinished)
+    /// once the download is over. If the download fails, [`FlashError::DownloadSetFailed`]
+    /// reports which images were completed before the failure.
+    pub fn commit(
+        &self,
+        session: &mut Session,
+        options: DownloadOptions<'_>,
+    ) -> Result<Vec<ImageOutcome>, FlashError> {
+        let loader = self.loader()?;
+
+        let progress = options.progress;
+        let dry_run = options.dry_run;
+        let verify = options.verify;
+
+        let mut tracker = DownloadTracker::default();
+        let result = loader.commit_tracked(session, options, &mut tracker);
+
+        let images: Vec<_> = self
+            .images
+            .iter()
+            .map(|image| {
+                let ranges: Vec<_> = image
+                    .loader
+                    .builder
+                    .data
+                    .iter()
+                    .map(|(&address, data)| address..address + data.len() as u64)
+                    .collect();
+
+                let status = match &result {
+                    Ok(()) if dry_run => ImageStatus::NotProgrammed,
+                    // Ranges which were skipped by a layout directive are verified as well.
+                    Ok(()) if verify || tracker.all_verified(&ranges) => ImageStatus::Verified,
+                    Ok(()) => ImageStatus::Programmed,
+                    Err(error) => tracker.status(&ranges, error),
+                };
+
+                let point_in_time = ranges
+                    .iter()
+                    .any(|range| session.target_writable_flash().is_target_writable(range));
+
+                ImageOutcome {
+                    name: image.name.clone(),
+                    ranges,
+                    status,
+                    point_in_time,
+                }
+            })
+            .collect();
+
+        if let Some(progress) = progress {
+            for outcome in &images {
+                progress.image_finished(outcome.clone());
+            }
+        }
+
+        match result {
+            Ok(()) => Ok(images),
+            Err(error) => Err(FlashError::DownloadSetFailed {
+                images,
+                source: Box::new(error),
+            }),
+        }
+    }
+}
+
+/// The outcome of an image of a [`FlashDownloadSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageOutcome {
+    /// The name of the image.
+    pub name: String,
+    /// The address ranges of the data of the image.
+    pub ranges: Vec<Range<u64>>,
+    /// Whether the image was programmed.
+    pub status: ImageStatus,
+    /// True if the image overlaps flash which the firmware of the target writes itself, see
+    /// [`Session::mark_target_writable_flash`]. Its verification only holds at the time it
+    /// was done.
+    pub point_in_time: bool,
+}
+
+/// Whether an image of a [`FlashDownloadSet`] was programmed, see [`ImageOutcome`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageStatus {
+    /// All data of the image was programmed and read back.
+    Verified,
+    /// All data of the image was programmed, but it wasn't read back, see
+    /// [`DownloadOptions::verify`].
+    Programmed,
+    /// The download failed while the image was programmed or verified.
+    Failed {
+        /// The address at which the download failed, e.g. of the sector which couldn't be
+        /// erased. If the failure has no address, the first address of the image which
+        /// wasn't programmed.
+        address: u64,
+    },
+    /// No data of the image was programmed, because the download failed before it reached
+    /// the image, or because it was a dry run.
+    NotProgrammed,
+}
+
+/// Records which parts of a download were completed, so that the outcome of each image of a
+/// [`FlashDownloadSet`] can be told apart.
+#[derive(Debug, Default)]
+pub(super) struct DownloadTracker {
+    programmed: Vec<Range<u64>>,
+    verified: Vec<Range<u64>>,
+    /// The range which is being programmed or verified.
+    current: Option<Range<u64>>,
+    /// The address at which the data read back differs from the data of the image.
+    failed_at: Option<u64>,
+}
+
+impl DownloadTracker {
+    /// Start programming or verifying `range`.
+    pub(super) fn begin(&mut self, range: Range<u64>) {
+        self.current = Some(range);
+    }
+
+    /// The range passed to [`DownloadTracker::begin`] was programmed.
+    pub(super) fn programmed(&mut self) {
+        if let Some(range) = self.current.take() {
+            self.programmed.push(range);
+        }
+    }
+
+    /// The range passed to [`DownloadTracker::begin`] was programmed and read back.
+    pub(super) fn verified(&mut self) {
+        if let Some(range) = self.current.take() {
+            self.programmed.push(range.clone());
+            self.verified.push(range);
+        }
+    }
+
+    /// The data read back differs from the data of the image at `address`.
+    pub(super) fn failed_at(&mut self, address: u64) {
+        self.failed_at = Some(address);
+    }
+
+    /// Whether all of `ranges` were read back.
+    fn all_verified(&self, ranges: &[Range<u64>]) -> bool {
+        ranges
+            .iter()
+            .all(|range| first_uncovered(&self.verified, range).is_none())
+    }
+
+    /// The status of the image with the data in `ranges`, after the download failed with
+    /// `error`.
+    fn status(&self, ranges: &[Range<u64>], error: &FlashError) -> ImageStatus {
+        if self.all_verified(ranges) {
+            return ImageStatus::Verified;
+        }
+
+        let unprogrammed = ranges
+            .iter()
+            .find_map(|range| first_uncovered(&self.programmed, range));
+
+        let unprogrammed = match unprogrammed {
+            Some(address) => address,
+            None => return ImageStatus::Programmed,
+        };
+
+        let in_flight = self.current.as_ref().map_or(false, |current| {
+            ranges
+                .iter()
+                .any(|range| range.start < current.end && current.start < range.end)
+        });
+        let partially_programmed = ranges.iter().any(|range| {
+            self.programmed
+                .iter()
+                .any(|programmed| range.start < programmed.end && programmed.start < range.end)
+        });
+
+        if !in_flight && !partially_programmed {
+            return ImageStatus::NotProgrammed;
+        }
+
+        let address = match error {
+            FlashError::EraseFailed { sector_address, .. } => Some(*sector_address),
+            FlashError::PageWrite { page_address, .. } => Some(*page_address),
+            _ => self.failed_at,
+        };
+
+        ImageStatus::Failed {
+            address: address.filter(|_| in_flight).unwrap_or(unprogrammed),
+        }
+    }
+}
+
+/// The first address of `range` which isn't contained in any of `covered`.
+fn first_uncovered(covered: &[Range<u64>], range: &Range<u64>) -> Option<u64> {
+    let mut address = range.start;
+
+    while address < range.end {
+        match covered.iter().find(|covered| covered.contains(&address)) {
+            Some(covered) => address = covered.end,
+            None => return Some(address),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn flash_error() -> FlashError {
+        FlashError::EraseFailed {
+            sector_address: 0x1000,
+            source: "erase failed".into(),
+        }
+    }
+
+    #[test]
+    fn images_before_the_failure_are_complete() {
+        let mut tracker = DownloadTracker::default();
+        tracker.begin(0x0..0x1000);
+        tracker.verified();
+        tracker.begin(0x1000..0x2000);
+
+        let error = flash_error();
+
+        assert_eq!(tracker.status(&[0x0..0x800], &error), ImageStatus::Verified);
+        assert_eq!(
+            tracker.status(&[0x800..0x1800], &error),
+            ImageStatus::Failed { address: 0x1000 }
+        );
+        assert_eq!(
+            tracker.status(&[0x3000..0x4000], &error),
+            ImageStatus::NotProgrammed
+        );
+    }
+
+    #[test]
+    fn partially_programmed_images_fail_at_their_first_gap() {
+        let mut tracker = DownloadTracker::default();
+        tracker.begin(0x0..0x1000);
+        tracker.programmed();
+
+        assert_eq!(
+            tracker.status(&[0x800..0x1800], &FlashError::Verify),
+            ImageStatus::Failed { address: 0x1000 }
+        );
+        assert_eq!(
+            tracker.status(&[0x0..0x800], &FlashError::Verify),
+            ImageStatus::Programmed
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::{NvmRegion, RamRegion, TargetDescriptionSource};
 use crate::error;
+use crate::flashing::{ImageIssue, ImageOutcome, JournalError, LayoutConflict, ReservedRam};
 use std::ops::Range;
 
 /// Describes any error that happened during the or in preparation for the flashing procedure.
@@ -151,4 +152,99 @@ pub enum FlashError {
     /// The register value supplied for this flash algorithm is out of the supported range.
     #[error("The register value {0:08X?} is out of the supported range.")]
     RegisterValueNotSupported(u64),
+    /// Data has to be written to a part of an NVM region which is not covered by any flash algorithm.
+    #[error("No flash algorithm covers the addresses {range:#010x?} of {region:?}. Algorithms considered: {algorithms:?}.")]
+    NoFlashAlgorithmForRange {
+        /// The address range which is not covered by any flash algorithm.
+        range: Range<u64>,
+        /// The region which contains `range`.
+        region: Box<NvmRegion>,
+        /// The names of the flash algorithms which cover other parts of `region`.
+        algorithms: Vec<String>,
+    },
+    /// Flashing the data requires erasing a sector which is marked as boot-critical.
+    #[error("Erasing the boot-critical sector {sector_address:#010x} ({sector_size} bytes) with flash algorithm '{algorithm}' requires `DownloadOptions::allow_boot_sector_erase`.")]
+    BootSectorEraseNotAllowed {
+        /// The address of the sector which would be erased.
+        sector_address: u64,
+        /// The size of the sector which would be erased.
+        sector_size: u64,
+        /// The name of the flash algorithm which would erase the sector.
+        algorithm: String,
+    },
+    /// A directive of `DownloadOptions::layout` contradicts another directive, the image or the other options.
+    #[error("The layout directive for {range:#010x?} can't be applied: {conflict}.")]
+    LayoutConflict {
+        /// The range of the directive which can't be applied.
+        range: Range<u64>,
+        /// The reason why the directive can't be applied.
+        conflict: LayoutConflict,
+    },
+    /// The RAM the flash algorithm needs while it runs overlaps RAM which the download uses
+    /// otherwise, and no other RAM is large enough for the algorithm.
+    #[error("The flash algorithm '{algorithm}' needs the RAM at {footprint:#010x?}, which overlaps {reserved}. Leave enough RAM free for the algorithm, or set its `load_address` in the target description to unused RAM.")]
+    FlashAlgorithmRamConflict {
+        /// The name of the flash algorithm.
+        algorithm: String,
+        /// The code, stack and page buffers of the flash algorithm.
+        footprint: Range<u64>,
+        /// The RAM which the flash algorithm overlaps.
+        reserved: ReservedRam,
+    },
+    /// Reading a streamed image from its source failed.
+    #[error("Failed to read the flash image from its source.")]
+    StreamRead(#[source] std::io::Error),
+    /// A streaming download failed. The data before the failure was programmed completely.
+    #[error("The streaming download failed after programming {programmed:#010x?}.")]
+    StreamingFailed {
+        /// The addresses which were programmed with the data of the stream before the failure.
+        programmed: Range<u64>,
+        /// The source error of this error.
+        #[source]
+        source: Box<FlashError>,
+    },
+    /// The image failed the validation requested with `DownloadOptions::validate_image`.
+    #[error("The image is not bootable on this target: {}", .issues.iter().filter(|issue| issue.is_error()).map(|issue| issue.kind.to_string()).collect::<Vec<_>>().join(", "))]
+    ImageValidationFailed {
+        /// All issues which were found, including warnings.
+        issues: Vec<ImageIssue>,
+    },
+    /// The journal requested with `DownloadOptions::journal` can't be used with this download.
+    #[error("The programming journal can't be used: {0}.")]
+    Journal(JournalError),
+    /// Reading or writing the journal file of `DownloadOptions::journal` failed.
+    #[error("Failed to access the programming journal file.")]
+    JournalFile(#[source] std::io::Error),
+    /// Two images of a [`FlashDownloadSet`](crate::flashing::FlashDownloadSet) overlap, and
+    /// contain different data in the overlap.
+    #[error("The images `{first}` and `{second}` contain different data at {address:#010x}.")]
+    ImagesConflict {
+        /// The name of the image which was added first.
+        first: String,
+        /// The name of the image which was added later.
+        second: String,
+        /// The first address at which the images differ.
+        address: u64,
+    },
+    /// The download of a [`FlashDownloadSet`](crate::flashing::FlashDownloadSet) failed.
+    #[error("The download of the images failed.")]
+    DownloadSetFailed {
+        /// The outcome of each image, in the order in which they were added.
+        images: Vec<ImageOutcome>,
+        /// The source error of this error.
+        #[source]
+        source: Box<FlashError>,
+    },
+}
+
+impl FlashError {
+    /// Returns `true` if flashing was interrupted with an [`InterruptHandle`](crate::InterruptHandle).
+    pub fn is_interrupted(&self) -> bool {
+        match self {
+            FlashError::Core(error::Error::Interrupted) => true,
+            FlashError::StreamingFailed { source, .. }
+            | FlashError::DownloadSetFailed { source, .. } => source.is_interrupted(),
+            _ => false,
+        }
+    }
 }
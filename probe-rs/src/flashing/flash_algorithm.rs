@@ -4,6 +4,27 @@ use super::FlashError;
 use crate::core::Architecture;
 use crate::{architecture::riscv, Target};
 use std::convert::TryInto;
+use std::ops::Range;
+
+/// RAM which a flash algorithm must not use, because the download uses it otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReservedRam {
+    /// The image contains data for the range.
+    #[error("the data of the image at {0:#010x?}")]
+    ImageData(Range<u64>),
+    /// The contents of the range are kept with a [`LayoutPolicy::Keep`](super::LayoutPolicy::Keep) directive.
+    #[error("the range {0:#010x?}, which should be kept")]
+    KeptRange(Range<u64>),
+}
+
+impl ReservedRam {
+    /// The reserved addresses.
+    pub fn range(&self) -> &Range<u64> {
+        match self {
+            ReservedRam::ImageData(range) | ReservedRam::KeptRange(range) => range,
+        }
+    }
+}
 
 /// A flash algorithm, which has been assembled for a specific
 /// chip.
@@ -31,6 +52,11 @@ pub struct FlashAlgorithm {
     pub pc_erase_sector: u64,
     /// Address of the `EraseAll()` entry point. Optional.
     pub pc_erase_all: Option<u64>,
+    /// Address of the `ProgramPageCompressed()` entry point. Optional.
+    ///
+    /// If present, page data is compressed with [`compress`](super::compress) before it is
+    /// transferred to the target.
+    pub pc_program_page_compressed: Option<u64>,
     /// Initial value of the R9 register for calling flash algo entry points, which
     /// determines where the position-independent data resides.
     pub static_base: u64,
@@ -219,6 +245,8 @@ impl FlashAlgorithm {
 
         // Try to find a stack size that fits with at least one page of data.
         for i in 0..Self::FLASH_ALGO_STACK_SIZE / Self::FLASH_ALGO_STACK_DECREMENT {
+            offset = 0;
+
             // Load address
             addr_load = raw
                 .load_address
@@ -271,6 +299,7 @@ impl FlashAlgorithm {
             pc_program_page: code_start + raw.pc_program_page,
             pc_erase_sector: code_start + raw.pc_erase_sector,
             pc_erase_all: raw.pc_erase_all.map(|v| code_start + v),
+            pc_program_page_compressed: raw.pc_program_page_compressed.map(|v| code_start + v),
             static_base: code_start + raw.data_section_offset,
             begin_stack: addr_stack,
             begin_data: page_buffers[0],
@@ -278,13 +307,191 @@ impl FlashAlgorithm {
             flash_properties: raw.flash_properties.clone(),
         })
     }
+
+    /// Constructs a complete flash algorithm like [`assemble_from_raw`](Self::assemble_from_raw),
+    /// in the first of the `rams` by default, but without using any of the `reserved` RAM.
+    ///
+    /// If the default placement overlaps reserved RAM, the algorithm is moved to the largest
+    /// free part of the `rams` which fits it. An algorithm with a fixed load address can't be
+    /// moved. `rams` must not be empty.
+    pub(super) fn assemble_avoiding(
+        raw: &RawFlashAlgorithm,
+        rams: &[&RamRegion],
+        target: &Target,
+        reserved: &[ReservedRam],
+    ) -> Result<Self, FlashError> {
+        let algorithm = Self::assemble_from_raw(raw, rams[0], target)?;
+        let footprint = algorithm.footprint();
+
+        let conflict = match reserved
+            .iter()
+            .find(|reserved| overlaps(reserved.range(), &footprint))
+        {
+            Some(conflict) => conflict,
+            None => return Ok(algorithm),
+        };
+
+        if raw.load_address.is_none() {
+            let mut windows: Vec<_> = rams
+                .iter()
+                .flat_map(|ram| {
+                    free_windows(&ram.range, reserved).map(move |range| RamRegion {
+                        range,
+                        ..(*ram).clone()
+                    })
+                })
+                .collect();
+            windows.sort_by_key(|window| std::cmp::Reverse(window.range.end - window.range.start));
+
+            for window in windows {
+                let moved = Self::assemble_from_raw(raw, &window, target)?;
+
+                if moved.footprint().end <= window.range.end {
+                    log::info!(
+                        "Moved the flash algorithm '{}' from {:#010x?} to {:#010x?}, because it overlaps {}.",
+                        raw.name,
+                        footprint,
+                        moved.footprint(),
+                        conflict
+                    );
+                    return Ok(moved);
+                }
+            }
+        }
+
+        Err(FlashError::FlashAlgorithmRamConflict {
+            algorithm: raw.name.clone(),
+            footprint,
+            reserved: conflict.clone(),
+        })
+    }
+
+    /// The RAM the flash algorithm uses while it runs: its code, its stack and its page buffers.
+    pub fn footprint(&self) -> Range<u64> {
+        let end = self
+            .page_buffers
+            .iter()
+            .map(|buffer| buffer + self.flash_properties.page_size as u64)
+            .fold(self.begin_stack, u64::max);
+
+        self.load_address..end
+    }
+}
+
+fn overlaps(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// The parts of `range` which aren't `reserved`, in ascending order.
+fn free_windows<'a>(
+    range: &Range<u64>,
+    reserved: &'a [ReservedRam],
+) -> impl Iterator<Item = Range<u64>> + 'a {
+    let mut taken: Vec<_> = reserved
+        .iter()
+        .map(ReservedRam::range)
+        .filter(|reserved| overlaps(reserved, range))
+        .cloned()
+        .collect();
+    taken.sort_by_key(|reserved| reserved.start);
+
+    let mut start = range.start;
+    let end = range.end;
+    let mut taken = taken.into_iter();
+
+    std::iter::from_fn(move || {
+        for reserved in taken.by_ref() {
+            let free = start..reserved.start.min(end);
+            start = start.max(reserved.end);
+
+            if !free.is_empty() {
+                return Some(free);
+            }
+        }
+
+        let free = start..end;
+        start = end;
+
+        if free.is_empty() {
+            None
+        } else {
+            Some(free)
+        }
+    })
 }
 
 #[cfg(test)]
 mod test {
-    use probe_rs_target::{FlashProperties, SectorDescription, SectorInfo};
+    use probe_rs_target::{
+        FlashProperties, MemoryRegion, RamRegion, SectorDescription, SectorInfo,
+    };
+
+    use super::{free_windows, ReservedRam};
+    use crate::config::get_target_by_name;
+    use crate::flashing::{FlashAlgorithm, FlashError};
+    use crate::Target;
+
+    fn ram(target: &Target) -> &RamRegion {
+        target
+            .memory_map
+            .iter()
+            .find_map(|region| match region {
+                MemoryRegion::Ram(ram) => Some(ram),
+                _ => None,
+            })
+            .unwrap()
+    }
 
-    use crate::flashing::FlashAlgorithm;
+    #[test]
+    fn free_windows_skip_reserved_ranges() {
+        let reserved = [
+            ReservedRam::KeptRange(0x1800..0x2000),
+            ReservedRam::ImageData(0x0..0x1100),
+            ReservedRam::ImageData(0x1400..0x1900),
+        ];
+
+        let windows: Vec<_> = free_windows(&(0x1000..0x3000), &reserved).collect();
+        assert_eq!(windows, [0x1100..0x1400, 0x2000..0x3000]);
+    }
+
+    #[test]
+    fn algorithm_is_moved_away_from_reserved_ram() {
+        let target = get_target_by_name("stm32wb55ccux").unwrap();
+        let raw = &target.flash_algorithms[0];
+        let ram = ram(&target);
+
+        let reserved = [ReservedRam::ImageData(0x2000_0000..0x2000_0800)];
+        let algorithm = FlashAlgorithm::assemble_avoiding(raw, &[ram], &target, &reserved).unwrap();
+
+        assert!(algorithm.footprint().start >= 0x2000_0800);
+        assert!(algorithm.footprint().end <= ram.range.end);
+        assert_eq!(algorithm.page_buffers.len(), 2);
+
+        // Without a conflict, the algorithm is placed like before.
+        let algorithm = FlashAlgorithm::assemble_avoiding(raw, &[ram], &target, &[]).unwrap();
+        assert_eq!(algorithm.load_address, ram.range.start);
+    }
+
+    #[test]
+    fn algorithm_with_fixed_load_address_is_not_moved() {
+        let target = get_target_by_name("stm32wb55ccux").unwrap();
+        let mut raw = target.flash_algorithms[0].clone();
+        raw.load_address = Some(0x2000_0020);
+        let ram = ram(&target);
+
+        let reserved = [ReservedRam::ImageData(0x2000_0100..0x2000_0200)];
+        match FlashAlgorithm::assemble_avoiding(&raw, &[ram], &target, &reserved) {
+            Err(FlashError::FlashAlgorithmRamConflict {
+                footprint,
+                reserved: conflict,
+                ..
+            }) => {
+                assert_eq!(footprint.start, 0x2000_0000);
+                assert_eq!(conflict, reserved[0]);
+            }
+            other => panic!("Unexpected result {:?}", other.map(|_| ())),
+        }
+    }
 
     #[test]
     fn flash_sector_single_size() {
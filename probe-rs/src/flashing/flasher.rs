@@ -1,16 +1,22 @@
-use probe_rs_target::{MemoryRegion, RawFlashAlgorithm};
+use probe_rs_target::{MemoryRegion, RamRegion, RawFlashAlgorithm};
 
+use super::timing::TimingMonitor;
 use super::{
-    FlashAlgorithm, FlashBuilder, FlashError, FlashFill, FlashLayout, FlashPage, FlashProgress,
+    compress, FlashAlgorithm, FlashBuilder, FlashError, FlashFill, FlashLayout, FlashOperation,
+    FlashPage, FlashProgress, SlowOperationThreshold,
 };
+use crate::architecture::arm::core::mpu;
 use crate::config::NvmRegion;
 use crate::memory::MemoryInterface;
 use crate::{
-    core::{Architecture, RegisterFile},
+    core::routine::{self, Completion, Invocation},
     session::Session,
-    Core, InstructionSet, RegisterId,
+    Core, Target,
+};
+use std::{
+    fmt::Debug,
+    time::{Duration, Instant},
 };
-use std::{fmt::Debug, time::Duration};
 
 pub(super) trait Operation {
     fn operation() -> u32;
@@ -55,6 +61,8 @@ pub(super) struct Flasher<'session> {
     session: &'session mut Session,
     core_index: usize,
     flash_algorithm: FlashAlgorithm,
+    disable_mpu: bool,
+    timings: TimingMonitor,
 }
 
 impl<'session> Flasher<'session> {
@@ -66,30 +74,34 @@ impl<'session> Flasher<'session> {
         let target = session.target();
 
         // Find a RAM region from which we can run the algo.
-        let mm = &target.memory_map;
-        let core_name = &target.cores[core_index].name;
-        let ram = mm
-            .iter()
-            .filter_map(|mm| match mm {
-                MemoryRegion::Ram(ram) => Some(ram),
-                _ => None,
-            })
-            .find(|ram| {
-                // The RAM must be accessible from the core we're going to run the algo on.
-                ram.cores.contains(core_name)
-            })
-            .ok_or(FlashError::NoRamDefined {
-                name: session.target().name.clone(),
-            })?;
+        let ram =
+            *Self::algorithm_rams(target, core_index)
+                .first()
+                .ok_or(FlashError::NoRamDefined {
+                    name: session.target().name.clone(),
+                })?;
 
         log::info!("chosen RAM to run the algo: {:x?}", ram);
 
         let flash_algorithm = FlashAlgorithm::assemble_from_raw(raw_flash_algorithm, ram, target)?;
 
+        Self::with_algorithm(session, core_index, flash_algorithm)
+    }
+
+    /// Loads the `flash_algorithm`, which was already assembled for the RAM of the core.
+    pub(super) fn with_algorithm(
+        session: &'session mut Session,
+        core_index: usize,
+        flash_algorithm: FlashAlgorithm,
+    ) -> Result<Self, FlashError> {
+        let timings = TimingMonitor::new(session.health_log().clone(), core_index);
+
         let mut this = Self {
             session,
             core_index,
             flash_algorithm,
+            disable_mpu: false,
+            timings,
         };
 
         this.load()?;
@@ -97,6 +109,22 @@ impl<'session> Flasher<'session> {
         Ok(this)
     }
 
+    /// The RAM regions from which a flash algorithm can run on the core `core_index`.
+    pub(super) fn algorithm_rams(target: &Target, core_index: usize) -> Vec<&RamRegion> {
+        let core_name = &target.cores[core_index].name;
+
+        target
+            .memory_map
+            .iter()
+            .filter_map(|mm| match mm {
+                MemoryRegion::Ram(ram) => Some(ram),
+                _ => None,
+            })
+            // The RAM must be accessible from the core we're going to run the algo on.
+            .filter(|ram| ram.cores.contains(core_name))
+            .collect()
+    }
+
     pub(super) fn flash_algorithm(&self) -> &FlashAlgorithm {
         &self.flash_algorithm
     }
@@ -105,6 +133,24 @@ impl<'session> Flasher<'session> {
         self.flash_algorithm.page_buffers.len() > 1
     }
 
+    /// Disable the MPU of the core while the flash algorithm runs.
+    pub(super) fn set_disable_mpu(&mut self, disable_mpu: bool) {
+        self.disable_mpu = disable_mpu;
+    }
+
+    /// Report erase and program operations which exceed `threshold` as slow.
+    pub(super) fn set_slow_operation_threshold(
+        &mut self,
+        threshold: Option<SlowOperationThreshold>,
+    ) {
+        self.timings.set_threshold(threshold);
+    }
+
+    /// The durations of the erase and program operations so far.
+    pub(super) fn timings(&self) -> &TimingMonitor {
+        &self.timings
+    }
+
     fn load(&mut self) -> Result<(), FlashError> {
         log::debug!("Initializing the flash algorithm.");
         let algo = &mut self.flash_algorithm;
@@ -133,6 +179,9 @@ impl<'session> Flasher<'session> {
             algo.load_address
         );
 
+        // The algorithm may write less than a word to its stack and its page buffers.
+        core.prepare_ecc_lease(algo.footprint())
+            .map_err(FlashError::Core)?;
         core.write_32(algo.load_address as u64, algo.instructions.as_slice())
             .map_err(FlashError::Core)?;
 
@@ -167,19 +216,32 @@ impl<'session> Flasher<'session> {
         clock: Option<u32>,
     ) -> Result<ActiveFlasher<'_, O>, FlashError> {
         // Attach to memory and core.
-        let core = self
+        let mut core = self
             .session
             .core(self.core_index)
             .map_err(FlashError::Core)?;
 
+        // The flash algorithm runs on the core, so the MPU applies to it.
+        let mpu_ctrl = if self.disable_mpu {
+            mpu::disable_mpu(&mut core).map_err(FlashError::Core)?
+        } else {
+            None
+        };
+
         log::debug!("Preparing Flasher for operation {}", O::operation_name());
         let mut flasher = ActiveFlasher::<O> {
             core,
             flash_algorithm: self.flash_algorithm.clone(),
+            mpu_ctrl,
+            running: false,
+            timings: &mut self.timings,
             _operation: core::marker::PhantomData,
         };
 
-        flasher.init(clock)?;
+        if let Err(error) = flasher.init(clock) {
+            flasher.restore_mpu()?;
+            return Err(error);
+        }
 
         Ok(flasher)
     }
@@ -190,9 +252,8 @@ impl<'session> Flasher<'session> {
     {
         // TODO: Fix those values (None, None).
         let mut active = self.init(None)?;
-        let r = f(&mut active)?;
-        active.uninit()?;
-        Ok(r)
+        let r = f(&mut active);
+        active.finish(r)
     }
 
     pub(super) fn run_program<T, F>(&mut self, f: F) -> Result<T, FlashError>
@@ -201,9 +262,8 @@ impl<'session> Flasher<'session> {
     {
         // TODO: Fix those values (None, None).
         let mut active = self.init(None)?;
-        let r = f(&mut active)?;
-        active.uninit()?;
-        Ok(r)
+        let r = f(&mut active);
+        active.finish(r)
     }
 
     pub(super) fn run_verify<T, F>(&mut self, f: F) -> Result<T, FlashError>
@@ -212,9 +272,8 @@ impl<'session> Flasher<'session> {
     {
         // TODO: Fix those values (None, None).
         let mut active = self.init(None)?;
-        let r = f(&mut active)?;
-        active.uninit()?;
-        Ok(r)
+        let r = f(&mut active);
+        active.finish(r)
     }
 
     pub(super) fn is_chip_erase_supported(&self) -> bool {
@@ -259,7 +318,12 @@ impl<'session> Flasher<'session> {
             for fill in fills {
                 let t = std::time::Instant::now();
                 let page = &mut flash_layout.pages_mut()[fill.page_index()];
-                let result = self.fill_page(page, &fill);
+                let result = self
+                    .session
+                    .interrupt_handle()
+                    .check()
+                    .map_err(FlashError::Core)
+                    .and_then(|()| self.fill_page(page, &fill));
 
                 // If we encounter an error, catch it, gracefully report the failure and return the error.
                 if result.is_err() {
@@ -310,6 +374,11 @@ impl<'session> Flasher<'session> {
         })
     }
 
+    /// Reads the current contents of the flash at `address`.
+    pub(super) fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError> {
+        self.run_verify(|active| active.core.read(address, data).map_err(FlashError::Core))
+    }
+
     /// Programs the pages given in `flash_layout` into the flash.
     fn program_simple(
         &mut self,
@@ -318,18 +387,31 @@ impl<'session> Flasher<'session> {
     ) -> Result<(), FlashError> {
         progress.started_programming();
 
-        let mut t = std::time::Instant::now();
         let result = self.run_program(|active| {
+            let mut transferred = 0;
+
             for page in flash_layout.pages() {
-                active
-                    .program_page(page.address(), page.data())
-                    .map_err(|error| FlashError::PageWrite {
-                        page_address: page.address(),
-                        source: Box::new(error),
-                    })?;
-                progress.page_programmed(page.size(), t.elapsed());
-                t = std::time::Instant::now();
+                active.check_interrupt()?;
+
+                let (transfer, time) =
+                    active
+                        .program_page(page.address(), page.data())
+                        .map_err(|error| {
+                            if error.is_interrupted() {
+                                error
+                            } else {
+                                FlashError::PageWrite {
+                                    page_address: page.address(),
+                                    source: Box::new(error),
+                                }
+                            }
+                        })?;
+                active.page_programmed(progress, page, transfer.size, time);
+                transferred += transfer.size as u64;
             }
+
+            log_transferred(flash_layout, transferred);
+
             Ok(())
         });
 
@@ -350,18 +432,25 @@ impl<'session> Flasher<'session> {
     ) -> Result<(), FlashError> {
         progress.started_erasing();
 
-        let mut t = std::time::Instant::now();
         let result = self.run_erase(|active| {
             for sector in flash_layout.sectors() {
-                active
-                    .erase_sector(sector.address())
-                    .map_err(|e| FlashError::EraseFailed {
-                        sector_address: sector.address(),
-                        source: Box::new(e),
-                    })?;
-
-                progress.sector_erased(sector.size(), t.elapsed());
-                t = std::time::Instant::now();
+                active.check_interrupt()?;
+
+                let time =
+                    active
+                        .erase_sector(sector.address())
+                        .map_err(|e| FlashError::EraseFailed {
+                            sector_address: sector.address(),
+                            source: Box::new(e),
+                        })?;
+
+                progress.sector_erased(sector.address(), sector.size(), time);
+                active.record_timing(
+                    progress,
+                    FlashOperation::SectorErase,
+                    sector.address(),
+                    time,
+                );
             }
             Ok(())
         });
@@ -393,59 +482,74 @@ impl<'session> Flasher<'session> {
 
         progress.started_programming();
 
-        let mut t = std::time::Instant::now();
         let result = self.run_program(|active| {
-            let mut last_page_address = 0;
+            let mut transferred = 0;
+            // The page which is being programmed, the size of its transfer, and the start of
+            // the program routine.
+            let mut pending: Option<(&FlashPage, u32, Instant)> = None;
             for page in flash_layout.pages() {
+                active.check_interrupt()?;
+
                 // At the start of each loop cycle load the next page buffer into RAM.
-                active.load_page_buffer(page.address(), page.data(), current_buf)?;
+                let transfer = active.load_page_buffer(page.address(), page.data(), current_buf)?;
+                transferred += transfer.size as u64;
 
                 // Then wait for the active RAM -> Flash copy process to finish.
                 // Also check if it finished properly. If it didn't, return an error.
+                if let Some((programmed, size, started)) = pending.take() {
+                    let result =
+                        active
+                            .wait_for_completion(Duration::from_secs(2))
+                            .map_err(|error| FlashError::PageWrite {
+                                page_address: programmed.address(),
+                                source: Box::new(error),
+                            })?;
+
+                    if result != 0 {
+                        return Err(FlashError::RoutineCallFailed {
+                            name: "program_page",
+                            error_code: result,
+                        });
+                    }
+
+                    active.page_programmed(progress, programmed, size, started.elapsed());
+                }
+
+                // Start the next copy process.
+                let started = Instant::now();
+                active.start_program_page_with_buffer(page.address(), current_buf, &transfer)?;
+                pending = Some((page, transfer.size, started));
+
+                // Swap the buffers
+                if current_buf == 1 {
+                    current_buf = 0;
+                } else {
+                    current_buf = 1;
+                }
+            }
+
+            if let Some((programmed, size, started)) = pending {
                 let result =
                     active
                         .wait_for_completion(Duration::from_secs(2))
                         .map_err(|error| FlashError::PageWrite {
-                            page_address: last_page_address,
+                            page_address: programmed.address(),
                             source: Box::new(error),
                         })?;
 
-                last_page_address = page.address();
-                progress.page_programmed(page.size(), t.elapsed());
-                t = std::time::Instant::now();
                 if result != 0 {
                     return Err(FlashError::RoutineCallFailed {
-                        name: "program_page",
+                        name: "wait_for_completion",
                         error_code: result,
                     });
                 }
 
-                // Start the next copy process.
-                active.start_program_page_with_buffer(page.address(), current_buf)?;
-
-                // Swap the buffers
-                if current_buf == 1 {
-                    current_buf = 0;
-                } else {
-                    current_buf = 1;
-                }
+                active.page_programmed(progress, programmed, size, started.elapsed());
             }
 
-            let result = active
-                .wait_for_completion(Duration::from_secs(2))
-                .map_err(|error| FlashError::PageWrite {
-                    page_address: last_page_address,
-                    source: Box::new(error),
-                })?;
+            log_transferred(flash_layout, transferred);
 
-            if result != 0 {
-                Err(FlashError::RoutineCallFailed {
-                    name: "wait_for_completion",
-                    error_code: result,
-                })
-            } else {
-                Ok(0)
-            }
+            Ok(0)
         });
 
         if result.is_ok() {
@@ -459,6 +563,27 @@ impl<'session> Flasher<'session> {
     }
 }
 
+/// Log how much data was transferred to program the pages in `flash_layout`.
+fn log_transferred(flash_layout: &FlashLayout, transferred: u64) {
+    let total: u64 = flash_layout.pages().iter().map(|p| p.size() as u64).sum();
+
+    if transferred < total {
+        log::info!(
+            "Transferred {} bytes of compressed data for {} bytes of pages",
+            transferred,
+            total
+        );
+    }
+}
+
+/// The data of a page, as it is transferred to the target.
+pub(super) struct PageTransfer {
+    /// The entry point which programs the transferred data.
+    entry_point: u64,
+    /// The number of bytes transferred to the target.
+    pub(super) size: u32,
+}
+
 struct Registers {
     pc: u32,
     r0: Option<u32>,
@@ -487,13 +612,21 @@ fn into_reg(val: u64) -> Result<u32, FlashError> {
 
 impl From<FlashError> for crate::Error {
     fn from(err: FlashError) -> Self {
-        Self::ArchitectureSpecific(Box::new(err))
+        match err {
+            FlashError::Core(crate::Error::Interrupted) => Self::Interrupted,
+            err => Self::ArchitectureSpecific(Box::new(err)),
+        }
     }
 }
 
 pub(super) struct ActiveFlasher<'probe, O: Operation> {
     core: Core<'probe>,
     flash_algorithm: FlashAlgorithm,
+    /// The value of MPU_CTRL, if the MPU was disabled while the flash algorithm runs.
+    mpu_ctrl: Option<u32>,
+    /// Whether a routine was started, and wasn't waited for yet.
+    running: bool,
+    timings: &'probe mut TimingMonitor,
     _operation: core::marker::PhantomData<O>,
 }
 
@@ -531,6 +664,70 @@ impl<'probe, O: Operation> ActiveFlasher<'probe, O> {
         Ok(())
     }
 
+    /// A cancellation point between two routine calls.
+    pub(super) fn check_interrupt(&self) -> Result<(), FlashError> {
+        self.core.check_interrupt().map_err(FlashError::Core)
+    }
+
+    /// Record the duration of an `operation` at `address`, and report it if it is slow.
+    pub(super) fn record_timing(
+        &mut self,
+        progress: &FlashProgress,
+        operation: FlashOperation,
+        address: u64,
+        time: Duration,
+    ) {
+        let properties = &self.flash_algorithm.flash_properties;
+        let timeout = match operation {
+            FlashOperation::SectorErase => properties.erase_sector_timeout,
+            FlashOperation::PageProgram => properties.program_page_timeout,
+        };
+
+        self.timings.record(
+            progress,
+            operation,
+            address,
+            time,
+            Duration::from_millis(timeout as u64),
+        );
+    }
+
+    /// Finish the operation with `result`.
+    ///
+    /// If the operation was interrupted, the routine which might still be running is
+    /// completed, and the flash algorithm is uninitialized before the interrupt is returned.
+    /// If the MPU was disabled, it is restored in any case.
+    fn finish<T>(&mut self, result: Result<T, FlashError>) -> Result<T, FlashError> {
+        let result = match result {
+            Err(error) if error.is_interrupted() => {
+                let completed = if self.running {
+                    self.wait_for_completion(Duration::from_secs(2))
+                        .map(drop)
+                        .map_err(FlashError::Core)
+                } else {
+                    Ok(())
+                };
+
+                completed.and_then(|_| self.uninit()).and(Err(error))
+            }
+            result => result.and_then(|r| {
+                self.uninit()?;
+                Ok(r)
+            }),
+        };
+
+        let restored = self.restore_mpu();
+
+        let r = result?;
+        restored?;
+        Ok(r)
+    }
+
+    /// Restore the MPU, if it was disabled while the flash algorithm runs.
+    fn restore_mpu(&mut self) -> Result<(), FlashError> {
+        mpu::restore_mpu(&mut self.core, self.mpu_ctrl.take()).map_err(FlashError::Core)
+    }
+
     // pub(super) fn session_mut(&mut self) -> &mut Session {
     //     &mut self.session
     // }
@@ -578,82 +775,41 @@ impl<'probe, O: Operation> ActiveFlasher<'probe, O> {
         log::debug!("Calling routine {:?}, init={})", &registers, init);
 
         let algo = &self.flash_algorithm;
-        let regs: &'static RegisterFile = self.core.registers();
-
-        let registers = [
-            (regs.program_counter(), Some(registers.pc)),
-            (regs.argument_register(0), registers.r0),
-            (regs.argument_register(1), registers.r1),
-            (regs.argument_register(2), registers.r2),
-            (regs.argument_register(3), registers.r3),
-            (
-                regs.platform_register(9),
-                if init {
-                    Some(into_reg(algo.static_base)?)
-                } else {
-                    None
-                },
-            ),
-            (
-                regs.stack_pointer(),
-                if init {
-                    Some(into_reg(algo.begin_stack)?)
-                } else {
-                    None
-                },
-            ),
-            (
-                regs.return_address(),
-                // For ARM Cortex-M cores, we have to add 1 to the return address,
-                // to ensure that we stay in Thumb mode.
-                if self.core.instruction_set()? == InstructionSet::Thumb2 {
-                    Some(into_reg(algo.load_address + 1)?)
-                } else {
-                    Some(into_reg(algo.load_address)?)
-                },
-            ),
-        ];
-
-        for (description, value) in &registers {
-            if let Some(v) = value {
-                self.core.write_core_reg(description.id, *v)?;
-                log::debug!(
-                    "content of {} {:#x}: 0x{:08x} should be: 0x{:08x}",
-                    description.name,
-                    description.id.0,
-                    {
-                        let value: u32 = self.core.read_core_reg(description.id)?;
-                        value
-                    },
-                    *v
-                );
-            }
-        }
-
-        if self.core.architecture() == Architecture::Riscv {
-            // Ensure ebreak enters debug mode, this is necessary for soft breakpoints to work.
-            let dcsr: u32 = self.core.read_core_reg(RegisterId::from(0x7b0))?;
-
-            self.core.write_core_reg(
-                RegisterId::from(0x7b0),
-                dcsr | (1 << 15) | (1 << 13) | (1 << 12),
-            )?;
-        }
+        let (static_base, stack_pointer) = if init {
+            (Some(into_reg(algo.static_base)?), Some(algo.begin_stack))
+        } else {
+            (None, None)
+        };
 
-        // Resume target operation.
-        self.core.run()?;
+        routine::start(
+            &mut self.core,
+            &Invocation {
+                entry: registers.pc.into(),
+                arguments: &[registers.r0, registers.r1, registers.r2, registers.r3],
+                static_base,
+                stack_pointer,
+                // The flash algorithm starts with a breakpoint instruction.
+                return_address: algo.load_address,
+            },
+        )?;
+        self.running = true;
 
         Ok(())
     }
 
     pub(super) fn wait_for_completion(&mut self, timeout: Duration) -> Result<u32, crate::Error> {
-        log::debug!("Waiting for routine call completion.");
-        let regs = self.core.registers();
-
-        self.core.wait_for_core_halted(timeout)?;
+        // A routine which is abandoned while it runs can leave the flash in an
+        // undefined state, so interrupts are only handled between routine calls.
+        let result = routine::wait(
+            &mut self.core,
+            &Completion::Return {
+                address: self.flash_algorithm.load_address,
+            },
+            timeout,
+        );
+        self.running = false;
 
-        let r: u32 = self.core.read_core_reg(regs.result_register(0).id)?;
-        Ok(r)
+        result
     }
 }
 
@@ -695,9 +851,10 @@ impl<'probe> ActiveFlasher<'probe, Erase> {
         }
     }
 
-    pub(super) fn erase_sector(&mut self, address: u64) -> Result<(), FlashError> {
+    /// Erase the sector at `address`, and return the time the erase routine took.
+    pub(super) fn erase_sector(&mut self, address: u64) -> Result<Duration, FlashError> {
         log::info!("Erasing sector at address 0x{:08x}", address);
-        let t1 = std::time::Instant::now();
+        let t1 = Instant::now();
 
         let result = self
             .call_function_and_wait(
@@ -717,10 +874,11 @@ impl<'probe> ActiveFlasher<'probe, Erase> {
                 sector_address: address,
                 source: Box::new(error),
             })?;
+        let time = t1.elapsed();
         log::info!(
             "Done erasing sector. Result is {}. This took {:?}",
             result,
-            t1.elapsed()
+            time
         );
 
         if result != 0 {
@@ -729,32 +887,65 @@ impl<'probe> ActiveFlasher<'probe, Erase> {
                 error_code: result,
             })
         } else {
-            Ok(())
+            Ok(time)
         }
     }
 }
 
 impl<'p> ActiveFlasher<'p, Program> {
-    pub(super) fn program_page(&mut self, address: u64, bytes: &[u8]) -> Result<(), FlashError> {
-        let t1 = std::time::Instant::now();
+    /// Prepare the data of a page for the transfer to the target.
+    ///
+    /// If the flash algorithm can decompress data, and the compressed page is smaller,
+    /// the compressed data is returned, padded to a multiple of 4 bytes.
+    fn prepare_page(&self, bytes: &[u8]) -> (PageTransfer, Vec<u8>) {
+        if let Some(entry_point) = self.flash_algorithm.pc_program_page_compressed {
+            let mut data = compress(bytes);
+
+            if data.len() < bytes.len() {
+                let size = data.len() as u32;
+                data.resize((data.len() + 3) & !3, 0);
+
+                return (PageTransfer { entry_point, size }, data);
+            }
+        }
+
+        (
+            PageTransfer {
+                entry_point: self.flash_algorithm.pc_program_page,
+                size: bytes.len() as u32,
+            },
+            bytes.to_vec(),
+        )
+    }
+
+    /// Program the page at `address`, and return the transfer and the time the program
+    /// routine took.
+    pub(super) fn program_page(
+        &mut self,
+        address: u64,
+        bytes: &[u8],
+    ) -> Result<(PageTransfer, Duration), FlashError> {
+        let (transfer, data) = self.prepare_page(bytes);
 
         log::info!(
-            "Flashing page at address {:#08x} with size: {}",
+            "Flashing page at address {:#08x} with size: {} ({} bytes transferred)",
             address,
-            bytes.len()
+            bytes.len(),
+            transfer.size
         );
 
         // Transfer the bytes to RAM.
         self.core
-            .write_8(self.flash_algorithm.begin_data as u64, bytes)
+            .write_8(self.flash_algorithm.begin_data as u64, &data)
             .map_err(FlashError::Core)?;
 
+        let t1 = Instant::now();
         let result = self
             .call_function_and_wait(
                 &Registers {
-                    pc: into_reg(self.flash_algorithm.pc_program_page)?,
+                    pc: into_reg(transfer.entry_point)?,
                     r0: Some(into_reg(address)?),
-                    r1: Some(bytes.len() as u32),
+                    r1: Some(transfer.size),
                     r2: Some(into_reg(self.flash_algorithm.begin_data)?),
                     r3: None,
                 },
@@ -767,7 +958,8 @@ impl<'p> ActiveFlasher<'p, Program> {
                 page_address: address,
                 source: Box::new(error),
             })?;
-        log::info!("Flashing took: {:?}", t1.elapsed());
+        let time = t1.elapsed();
+        log::info!("Flashing took: {:?}", time);
 
         if result != 0 {
             Err(FlashError::PageWrite {
@@ -778,14 +970,27 @@ impl<'p> ActiveFlasher<'p, Program> {
                 }),
             })
         } else {
-            Ok(())
+            Ok((transfer, time))
         }
     }
 
+    /// Report that `page` was programmed with a transfer of `size` bytes in `time`.
+    fn page_programmed(
+        &mut self,
+        progress: &FlashProgress,
+        page: &FlashPage,
+        size: u32,
+        time: Duration,
+    ) {
+        progress.page_programmed(page.address(), page.size(), size, time);
+        self.record_timing(progress, FlashOperation::PageProgram, page.address(), time);
+    }
+
     pub(super) fn start_program_page_with_buffer(
         &mut self,
         address: u64,
         buffer_number: usize,
+        transfer: &PageTransfer,
     ) -> Result<(), FlashError> {
         // Ensure the buffer number is valid, otherwise there is a bug somewhere
         // in the flashing code.
@@ -797,9 +1002,9 @@ impl<'p> ActiveFlasher<'p, Program> {
 
         self.call_function(
             &Registers {
-                pc: into_reg(self.flash_algorithm.pc_program_page)?,
+                pc: into_reg(transfer.entry_point)?,
                 r0: Some(into_reg(address)?),
-                r1: Some(self.flash_algorithm.flash_properties.page_size),
+                r1: Some(transfer.size),
                 r2: Some(into_reg(
                     self.flash_algorithm.page_buffers[buffer_number as usize],
                 )?),
@@ -820,7 +1025,9 @@ impl<'p> ActiveFlasher<'p, Program> {
         _address: u64,
         bytes: &[u8],
         buffer_number: usize,
-    ) -> Result<(), FlashError> {
+    ) -> Result<PageTransfer, FlashError> {
+        let (transfer, data) = self.prepare_page(bytes);
+
         let algo = &self.flash_algorithm;
 
         // Ensure the buffer number is valid, otherwise there is a bug somewhere
@@ -833,22 +1040,23 @@ impl<'p> ActiveFlasher<'p, Program> {
 
         // TODO: Prevent security settings from locking the device.
         // Transfer the buffer bytes to RAM.
-        let words: Vec<u32> = bytes
+        let words: Vec<u32> = data
             .chunks_exact(core::mem::size_of::<u32>())
             .map(|a| u32::from_le_bytes([a[0], a[1], a[2], a[3]]))
             .collect();
 
-        let t1 = std::time::Instant::now();
+        let t1 = Instant::now();
         self.core
             .write_32(algo.page_buffers[buffer_number], &words)
             .map_err(FlashError::Core)?;
 
         log::info!(
-            "Took {:?} to download {} byte page into ram",
+            "Took {:?} to download {} byte page into ram ({} bytes transferred)",
             t1.elapsed(),
-            bytes.len()
+            bytes.len(),
+            transfer.size
         );
 
-        Ok(())
+        Ok(transfer)
     }
 }
@@ -0,0 +1,507 @@
+//! The journal of a journaled download, see [`DownloadOptions::journal`](super::DownloadOptions::journal).
+//!
+//! The journal records the address and a hash of the contents of each sector which was
+//! programmed and verified. It is written after each sector, so a download which is
+//! interrupted at any point loses at most the sector it was programming.
+//!
+//! # Format
+//!
+//! All values are little-endian.
+//!
+//! | Offset | Size      | Contents                                              |
+//! |--------|-----------|-------------------------------------------------------|
+//! | 0      | 4         | The magic `PRSJ`                                      |
+//! | 4      | 2         | The version of the format, currently 1                |
+//! | 6      | 2         | Reserved, 0                                           |
+//! | 8      | 4         | The sequence number of the copy                       |
+//! | 12     | 32        | The SHA-256 hash of the image                         |
+//! | 44     | 4         | The number of entries                                 |
+//! | 48     | 16 each   | The entries: the address of a sector, and the first 8 bytes of the SHA-256 hash of its contents |
+//! | ...    | 8         | The first 8 bytes of the SHA-256 hash of all bytes before |
+//!
+//! In flash, the journal area is split into two slots. Each update is written to the slot
+//! which doesn't hold the latest copy, with the next sequence number, so an update which is
+//! interrupted leaves the previous copy intact. The valid copy with the highest sequence
+//! number is used.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::builder::FlashBuilder;
+use super::{FlashAlgorithm, FlashError};
+
+const MAGIC: [u8; 4] = *b"PRSJ";
+
+/// The version of the format, which is increased with every incompatible change.
+const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 48;
+const ENTRY_LEN: usize = 16;
+const CHECKSUM_LEN: usize = 8;
+
+/// Where the journal of a journaled download is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JournalLocation {
+    /// In the flash of the target, in a range which is reserved for the journal.
+    ///
+    /// The range is split into two slots of equal size, and each slot must consist of whole
+    /// sectors. It must be programmed by the same flash algorithm as the image.
+    Flash(Range<u64>),
+    /// In a file on the host, for targets whose flash can't spare two sectors.
+    ///
+    /// The file is replaced as a whole on each update, so it is consistent even if the host
+    /// dies while writing it.
+    File(PathBuf),
+}
+
+/// The reason why the journal of a download can't be used.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum JournalError {
+    /// The image has data in the journal.
+    #[error("the image has data in the journal at {0:#010x?}")]
+    DataInJournal(Range<u64>),
+    /// The journal is not in the flash of the target.
+    #[error("it is not in the flash of the target")]
+    NotInFlash,
+    /// The slots of the journal are not made of whole sectors.
+    #[error("its slots are not made of whole sectors")]
+    UnalignedSlots,
+    /// The journal is not programmed by the flash algorithm of the image.
+    #[error("it is not in the range of the flash algorithm '{0}'")]
+    OutsideAlgorithm(String),
+    /// The image has more sectors than a slot of the journal can record.
+    #[error("a slot can record {capacity} sectors, but the image has {sectors}")]
+    Full {
+        /// The number of sectors of the image.
+        sectors: usize,
+        /// The number of sectors a slot can record.
+        capacity: usize,
+    },
+    /// A chip erase would erase the journal.
+    #[error("a chip erase would erase it")]
+    ChipErase,
+}
+
+/// The journal of a download, with the sectors which were verified.
+#[derive(Debug)]
+pub(super) struct Journal {
+    location: JournalLocation,
+    image_hash: [u8; 32],
+    /// The sequence number of the latest copy.
+    sequence: u32,
+    /// The slot of the latest copy, for a journal in flash.
+    slot: Option<usize>,
+    /// The hashes of the verified sectors, by their address.
+    sectors: BTreeMap<u64, [u8; 8]>,
+}
+
+impl Journal {
+    /// Check that a journal at `location` can be used to download the image in `builder`.
+    pub(super) fn check(
+        location: &JournalLocation,
+        builder: &FlashBuilder,
+        do_chip_erase: bool,
+    ) -> Result<(), JournalError> {
+        let range = match location {
+            JournalLocation::Flash(range) => range,
+            JournalLocation::File(_) => return Ok(()),
+        };
+
+        if do_chip_erase {
+            return Err(JournalError::ChipErase);
+        }
+
+        if let Some((address, data)) = builder.data_in_range(range).next() {
+            return Err(JournalError::DataInJournal(
+                address..address + data.len() as u64,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that the slots of a journal at `location` are programmed by `algorithm`, and
+    /// made of whole sectors of it.
+    pub(super) fn check_slots(
+        location: &JournalLocation,
+        algorithm: &FlashAlgorithm,
+    ) -> Result<(), JournalError> {
+        let (range, slots) = match (location, slots(location)) {
+            (JournalLocation::Flash(range), Some(slots)) => (range, slots),
+            _ => return Ok(()),
+        };
+
+        let address_range = &algorithm.flash_properties.address_range;
+        if !(address_range.start <= range.start && range.end <= address_range.end) {
+            return Err(JournalError::OutsideAlgorithm(algorithm.name.clone()));
+        }
+
+        let aligned = |address| {
+            address == address_range.end
+                || algorithm
+                    .sector_info(address)
+                    .map_or(false, |sector| sector.base_address == address)
+        };
+
+        if slots
+            .iter()
+            .any(|slot| slot.is_empty() || !aligned(slot.start) || !aligned(slot.end))
+        {
+            return Err(JournalError::UnalignedSlots);
+        }
+
+        Ok(())
+    }
+
+    /// Check that a journal at `location` can record `sectors` sectors.
+    pub(super) fn check_capacity(
+        location: &JournalLocation,
+        sectors: usize,
+    ) -> Result<(), JournalError> {
+        if let Some(slots) = slots(location) {
+            let len = (slots[0].end - slots[0].start) as usize;
+            let capacity = len.saturating_sub(HEADER_LEN + CHECKSUM_LEN) / ENTRY_LEN;
+
+            if sectors > capacity {
+                return Err(JournalError::Full { sectors, capacity });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load the journal at `location`, with `read` to read the flash.
+    ///
+    /// If the journal was written for another image, or no valid copy is found, the
+    /// returned journal has no verified sectors.
+    pub(super) fn load(
+        location: &JournalLocation,
+        image_hash: [u8; 32],
+        mut read: impl FnMut(u64, &mut [u8]) -> Result<(), FlashError>,
+    ) -> Result<Self, FlashError> {
+        let mut journal = Journal {
+            location: location.clone(),
+            image_hash,
+            sequence: 0,
+            slot: None,
+            sectors: BTreeMap::new(),
+        };
+
+        let latest = match &journal.location {
+            JournalLocation::Flash(_) => {
+                let mut latest = None;
+
+                for (index, slot) in slots(&journal.location).unwrap().iter().enumerate() {
+                    let mut bytes = vec![0; (slot.end - slot.start) as usize];
+                    read(slot.start, &mut bytes)?;
+
+                    if let Some(copy) = decode(&bytes) {
+                        if latest
+                            .as_ref()
+                            .map_or(true, |(_, latest): &(usize, Snapshot)| {
+                                copy.sequence > latest.sequence
+                            })
+                        {
+                            latest = Some((index, copy));
+                        }
+                    }
+                }
+
+                latest.map(|(index, copy)| (Some(index), copy))
+            }
+            JournalLocation::File(path) => match std::fs::read(path) {
+                Ok(bytes) => decode(&bytes).map(|copy| (None, copy)),
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+                Err(error) => return Err(FlashError::JournalFile(error)),
+            },
+        };
+
+        if let Some((slot, copy)) = latest {
+            journal.sequence = copy.sequence;
+            journal.slot = slot;
+
+            if copy.image_hash == image_hash {
+                log::debug!(
+                    "The journal records {} verified sectors",
+                    copy.sectors.len()
+                );
+                journal.sectors = copy.sectors;
+            } else {
+                log::info!("The journal was written for another image, and is discarded");
+            }
+        }
+
+        Ok(journal)
+    }
+
+    /// Returns true if the journal records the sector at `address` as verified with the
+    /// contents of hash `hash`.
+    pub(super) fn is_verified(&self, address: u64, hash: [u8; 8]) -> bool {
+        self.sectors.get(&address) == Some(&hash)
+    }
+
+    /// Record the sector at `address` as verified with the contents of hash `hash`.
+    ///
+    /// A journal in flash is written with `program`, which erases the given range of the
+    /// flash and programs it with the given bytes.
+    pub(super) fn record(
+        &mut self,
+        address: u64,
+        hash: [u8; 8],
+        program: impl FnOnce(Range<u64>, &[u8]) -> Result<(), FlashError>,
+    ) -> Result<(), FlashError> {
+        self.sectors.insert(address, hash);
+
+        let sequence = self.sequence.wrapping_add(1);
+        let bytes = encode(sequence, &self.image_hash, &self.sectors);
+
+        match &self.location {
+            JournalLocation::Flash(_) => {
+                let slot = self.slot.map_or(0, |slot| 1 - slot);
+                let range = slots(&self.location).unwrap()[slot].clone();
+
+                program(range, &bytes)?;
+
+                self.slot = Some(slot);
+            }
+            JournalLocation::File(path) => {
+                let mut temporary = path.clone().into_os_string();
+                temporary.push(".tmp");
+
+                write_atomically(path, Path::new(&temporary), &bytes)
+                    .map_err(FlashError::JournalFile)?;
+            }
+        }
+
+        self.sequence = sequence;
+
+        Ok(())
+    }
+}
+
+/// Returns the two slots of a journal in flash.
+fn slots(location: &JournalLocation) -> Option<[Range<u64>; 2]> {
+    match location {
+        JournalLocation::Flash(range) => {
+            let middle = range.start + (range.end - range.start) / 2;
+            Some([range.start..middle, middle..middle + (middle - range.start)])
+        }
+        JournalLocation::File(_) => None,
+    }
+}
+
+/// Write `bytes` to `temporary`, and replace `path` with it.
+fn write_atomically(path: &Path, temporary: &Path, bytes: &[u8]) -> Result<(), std::io::Error> {
+    use std::io::Write;
+
+    let mut file = std::fs::File::create(temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    std::fs::rename(temporary, path)
+}
+
+/// A decoded copy of the journal.
+struct Snapshot {
+    sequence: u32,
+    image_hash: [u8; 32],
+    sectors: BTreeMap<u64, [u8; 8]>,
+}
+
+fn encode(sequence: u32, image_hash: &[u8; 32], sectors: &BTreeMap<u64, [u8; 8]>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + sectors.len() * ENTRY_LEN + CHECKSUM_LEN);
+
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&sequence.to_le_bytes());
+    bytes.extend_from_slice(image_hash);
+    bytes.extend_from_slice(&(sectors.len() as u32).to_le_bytes());
+
+    for (address, hash) in sectors {
+        bytes.extend_from_slice(&address.to_le_bytes());
+        bytes.extend_from_slice(hash);
+    }
+
+    let checksum = truncated_hash(&bytes);
+    bytes.extend_from_slice(&checksum);
+
+    bytes
+}
+
+/// Decode a copy of the journal, which can be followed by padding.
+///
+/// Returns `None` if the bytes don't hold a complete copy of the current version, e.g.
+/// because they are erased, or their write was interrupted.
+fn decode(bytes: &[u8]) -> Option<Snapshot> {
+    let header = bytes.get(..HEADER_LEN)?;
+
+    if header[0..4] != MAGIC || u16::from_le_bytes(header[4..6].try_into().unwrap()) != VERSION {
+        return None;
+    }
+
+    let sequence = u32::from_le_bytes(header[8..12].try_into().unwrap());
+    let image_hash = header[12..44].try_into().unwrap();
+    let count = u32::from_le_bytes(header[44..48].try_into().unwrap()) as usize;
+
+    let len = count
+        .checked_mul(ENTRY_LEN)?
+        .checked_add(HEADER_LEN + CHECKSUM_LEN)?;
+    let copy = bytes.get(..len)?;
+    let (contents, checksum) = copy.split_at(len - CHECKSUM_LEN);
+
+    if truncated_hash(contents) != checksum {
+        return None;
+    }
+
+    let sectors = contents[HEADER_LEN..]
+        .chunks_exact(ENTRY_LEN)
+        .map(|entry| {
+            (
+                u64::from_le_bytes(entry[..8].try_into().unwrap()),
+                entry[8..].try_into().unwrap(),
+            )
+        })
+        .collect();
+
+    Some(Snapshot {
+        sequence,
+        image_hash,
+        sectors,
+    })
+}
+
+fn truncated_hash(bytes: &[u8]) -> [u8; 8] {
+    Sha256::digest(bytes)[..8].try_into().unwrap()
+}
+
+/// Returns the hash of all data of the image in `builder`.
+pub(super) fn image_hash(builder: &FlashBuilder) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+
+    for (address, data) in &builder.data {
+        hasher.update(address.to_le_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    }
+
+    hasher.finalize().into()
+}
+
+/// Returns the hash of the data of the image in `builder` which is in `range`.
+pub(super) fn sector_hash(builder: &FlashBuilder, range: &Range<u64>) -> [u8; 8] {
+    let mut hasher = Sha256::new();
+
+    for (address, data) in builder.data_in_range(range) {
+        hasher.update(address.to_le_bytes());
+        hasher.update((data.len() as u64).to_le_bytes());
+        hasher.update(data);
+    }
+
+    hasher.finalize()[..8].try_into().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sectors() -> BTreeMap<u64, [u8; 8]> {
+        [(0x0800_0000, [1; 8]), (0x0800_1000, [2; 8])]
+            .into_iter()
+            .collect()
+    }
+
+    fn flash_journal() -> Journal {
+        Journal {
+            location: JournalLocation::Flash(0x1000..0x3000),
+            image_hash: [7; 32],
+            sequence: 0,
+            slot: None,
+            sectors: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn copy_round_trips() {
+        let mut bytes = encode(3, &[7; 32], &sectors());
+        // Erased flash after the copy.
+        bytes.extend_from_slice(&[0xff; 64]);
+
+        let copy = decode(&bytes).unwrap();
+
+        assert_eq!(copy.sequence, 3);
+        assert_eq!(copy.image_hash, [7; 32]);
+        assert_eq!(copy.sectors, sectors());
+    }
+
+    #[test]
+    fn incomplete_or_foreign_copies_are_rejected() {
+        let bytes = encode(3, &[7; 32], &sectors());
+
+        // An interrupted write, which left the end erased.
+        let mut interrupted = bytes.clone();
+        let len = interrupted.len();
+        interrupted[len - 12..].fill(0xff);
+        assert!(decode(&interrupted).is_none());
+
+        // Another version of the format.
+        let mut other_version = bytes.clone();
+        other_version[4] = 2;
+        assert!(decode(&other_version).is_none());
+
+        assert!(decode(&[0xff; 4096]).is_none());
+        assert!(decode(&bytes[..HEADER_LEN]).is_none());
+    }
+
+    #[test]
+    fn updates_alternate_between_the_slots() {
+        let mut journal = flash_journal();
+        let mut flash = vec![0xff; 0x2000];
+
+        for (index, address) in [0x0800_0000, 0x0800_1000, 0x0800_2000]
+            .into_iter()
+            .enumerate()
+        {
+            journal
+                .record(address, [index as u8; 8], |range, bytes| {
+                    let slot = &mut flash[(range.start - 0x1000) as usize..][..0x1000];
+                    slot.fill(0xff);
+                    slot[..bytes.len()].copy_from_slice(bytes);
+                    Ok(())
+                })
+                .unwrap();
+        }
+
+        assert_eq!(journal.slot, Some(0));
+        assert_eq!(journal.sequence, 3);
+
+        // The latest copy is interrupted, so the previous one is used.
+        flash[0x10..0x20].fill(0xff);
+
+        let loaded = Journal::load(&journal.location, [7; 32], |address, data| {
+            let offset = (address - 0x1000) as usize;
+            data.copy_from_slice(&flash[offset..offset + data.len()]);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(loaded.slot, Some(1));
+        assert_eq!(loaded.sequence, 2);
+        assert!(loaded.is_verified(0x0800_1000, [1; 8]));
+        assert!(!loaded.is_verified(0x0800_2000, [2; 8]));
+
+        // A journal of another image is discarded.
+        let other = Journal::load(&journal.location, [8; 32], |address, data| {
+            let offset = (address - 0x1000) as usize;
+            data.copy_from_slice(&flash[offset..offset + data.len()]);
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(other.sectors.is_empty());
+        assert_eq!(other.sequence, 2);
+    }
+}
@@ -0,0 +1,534 @@
+use std::ops::Range;
+
+use probe_rs_target::NvmRegion;
+
+use super::builder::FlashBuilder;
+use super::{DownloadOptions, FlashAlgorithm, FlashError};
+
+/// How a range of flash is treated when an image is programmed, see [`LayoutDirective`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutPolicy {
+    /// The data of the image in the range is not programmed, as if the image had no data there.
+    Skip,
+    /// The contents of the flash in the range are preserved.
+    ///
+    /// If a sector which contains the range has to be erased to program the image, the range
+    /// is read before the erase and programmed again afterwards. The image must not contain
+    /// data in the range.
+    Keep,
+    /// The range is programmed completely, and the gaps between the data of the image are
+    /// padded with the given byte instead of the erased value of the flash.
+    Fill(u8),
+    /// The data of the image in the range is only programmed if the flash is blank there.
+    ///
+    /// If the flash contains anything else than the erased value, its contents are preserved
+    /// like with [`LayoutPolicy::Keep`].
+    ProgramOnlyIfBlank,
+}
+
+/// A policy for a range of flash, see [`DownloadOptions::layout`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayoutDirective {
+    /// The addresses to which the policy applies.
+    pub range: Range<u64>,
+    /// The policy for the addresses.
+    pub policy: LayoutPolicy,
+}
+
+impl LayoutDirective {
+    /// Creates a directive which applies `policy` to the addresses in `range`.
+    pub fn new(range: Range<u64>, policy: LayoutPolicy) -> Self {
+        Self { range, policy }
+    }
+}
+
+/// The reason why a [`LayoutDirective`] can't be applied, see [`FlashError::LayoutConflict`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LayoutConflict {
+    /// The range of the directive is empty.
+    #[error("the range is empty")]
+    EmptyRange,
+    /// The range of the directive overlaps the range of another directive.
+    #[error("it overlaps the directive for {0:#010x?}")]
+    Overlap(Range<u64>),
+    /// The image contains data in a range which is kept.
+    #[error("the image contains data at {0:#010x?}, which should be kept")]
+    DataInKeptRange(Range<u64>),
+    /// The contents of the flash can't be preserved, because the whole chip is erased.
+    #[error("the contents of the flash can't be preserved with a chip erase")]
+    ChipErase,
+}
+
+/// Checks that the directives of `options` neither overlap each other, nor contradict the
+/// image in `builder` or the other options.
+///
+/// This is done before anything is erased.
+pub(super) fn check_layout(
+    builder: &FlashBuilder,
+    options: &DownloadOptions<'_>,
+) -> Result<(), FlashError> {
+    for (index, directive) in options.layout.iter().enumerate() {
+        let conflict = |conflict| FlashError::LayoutConflict {
+            range: directive.range.clone(),
+            conflict,
+        };
+
+        if directive.range.is_empty() {
+            return Err(conflict(LayoutConflict::EmptyRange));
+        }
+
+        if let Some(other) = options.layout[index + 1..].iter().find(|other| {
+            other.range.start < directive.range.end && directive.range.start < other.range.end
+        }) {
+            return Err(conflict(LayoutConflict::Overlap(other.range.clone())));
+        }
+
+        let preserves_flash = matches!(
+            directive.policy,
+            LayoutPolicy::Keep | LayoutPolicy::ProgramOnlyIfBlank
+        );
+
+        if preserves_flash && options.do_chip_erase {
+            return Err(conflict(LayoutConflict::ChipErase));
+        }
+
+        if directive.policy == LayoutPolicy::Keep {
+            if let Some((address, data)) = builder.data_in_range(&directive.range).next() {
+                return Err(conflict(LayoutConflict::DataInKeptRange(
+                    address..address + data.len() as u64,
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl FlashBuilder {
+    /// Applies the directives which don't depend on the contents of the flash.
+    ///
+    /// The data in skipped ranges is removed, and the gaps in filled ranges are padded.
+    pub(super) fn plan_layout(&self, directives: &[LayoutDirective]) -> FlashBuilder {
+        let mut builder = FlashBuilder {
+            data: self.data.clone(),
+        };
+
+        for directive in directives {
+            match directive.policy {
+                LayoutPolicy::Skip => builder.remove_range(&directive.range),
+                LayoutPolicy::Fill(value) => builder.fill_range(&directive.range, value),
+                LayoutPolicy::Keep | LayoutPolicy::ProgramOnlyIfBlank => (),
+            }
+        }
+
+        builder
+    }
+
+    /// Applies the directives which depend on the contents of the flash to the data in `region`,
+    /// and returns the data which has to be programmed.
+    ///
+    /// The contents of the flash are read with `read`. If `erase` is `true`, kept ranges in
+    /// sectors which are erased are merged with the data, so that they are programmed again.
+    pub(super) fn resolve_layout(
+        &self,
+        region: &NvmRegion,
+        flash_algorithm: &FlashAlgorithm,
+        directives: &[LayoutDirective],
+        erase: bool,
+        mut read: impl FnMut(u64, &mut [u8]) -> Result<(), FlashError>,
+    ) -> Result<FlashBuilder, FlashError> {
+        let mut builder = FlashBuilder::new();
+        for (address, data) in self.data_in_range(&region.range) {
+            builder.data.insert(address, data.to_vec());
+        }
+
+        let mut kept = Vec::new();
+
+        for directive in directives {
+            let range = directive.range.start.max(region.range.start)
+                ..directive.range.end.min(region.range.end);
+
+            if range.is_empty() {
+                continue;
+            }
+
+            match directive.policy {
+                LayoutPolicy::Keep => kept.push(range),
+                LayoutPolicy::ProgramOnlyIfBlank if builder.has_data_in_range(&range) => {
+                    let mut contents = vec![0; (range.end - range.start) as usize];
+                    read(range.start, &mut contents)?;
+
+                    let erased = flash_algorithm.flash_properties.erased_byte_value;
+                    if contents.iter().any(|&byte| byte != erased) {
+                        log::info!(
+                            "Flash at {:#010x?} is not blank, its contents are kept.",
+                            range
+                        );
+                        builder.remove_range(&range);
+                        kept.push(range);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if !erase || kept.is_empty() {
+            return Ok(builder);
+        }
+
+        // Read the kept contents of all sectors which are erased before anything is merged,
+        // as merging doesn't add sectors.
+        let mut merged = Vec::new();
+        for sector in builder.sectors(region, flash_algorithm) {
+            let sector_range = sector.address()..sector.address() + sector.size();
+
+            for range in &kept {
+                let range = range.start.max(sector_range.start)..range.end.min(sector_range.end);
+
+                if !range.is_empty() {
+                    let mut contents = vec![0; (range.end - range.start) as usize];
+                    read(range.start, &mut contents)?;
+                    merged.push((range.start, contents));
+                }
+            }
+        }
+
+        for (address, contents) in merged {
+            builder.data.insert(address, contents);
+        }
+
+        Ok(builder)
+    }
+
+    /// Removes the data in `range`, splitting the chunks which cross its boundaries.
+    fn remove_range(&mut self, range: &Range<u64>) {
+        let data = std::mem::take(&mut self.data);
+
+        for (address, chunk) in data {
+            let end = address + chunk.len() as u64;
+
+            if end <= range.start || address >= range.end {
+                self.data.insert(address, chunk);
+                continue;
+            }
+
+            if address < range.start {
+                let len = (range.start - address) as usize;
+                self.data.insert(address, chunk[..len].to_vec());
+            }
+
+            if end > range.end {
+                let offset = (range.end - address) as usize;
+                self.data.insert(range.end, chunk[offset..].to_vec());
+            }
+        }
+    }
+
+    /// Adds chunks of `value` to all gaps between the data in `range`.
+    fn fill_range(&mut self, range: &Range<u64>, value: u8) {
+        let mut gaps = Vec::new();
+        let mut gap_start = range.start;
+
+        for (address, data) in self.data_in_range(range) {
+            if address > gap_start {
+                gaps.push(gap_start..address);
+            }
+            gap_start = address + data.len() as u64;
+        }
+
+        if gap_start < range.end {
+            gaps.push(gap_start..range.end);
+        }
+
+        for gap in gaps {
+            self.data
+                .insert(gap.start, vec![value; (gap.end - gap.start) as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use probe_rs_target::{FlashProperties, SectorDescription};
+
+    use super::*;
+
+    const ERASED: u8 = 0xff;
+
+    /// A flash of 4 sectors of 4 KiB, with pages of 1 KiB.
+    fn demo_flash() -> (NvmRegion, FlashAlgorithm) {
+        let flash_algorithm = FlashAlgorithm {
+            flash_properties: FlashProperties {
+                address_range: 0..0x4000,
+                page_size: 0x400,
+                erased_byte_value: ERASED,
+                program_page_timeout: 200,
+                erase_sector_timeout: 200,
+                sectors: vec![SectorDescription {
+                    size: 0x1000,
+                    address: 0,
+                }],
+            },
+            ..Default::default()
+        };
+
+        let region = NvmRegion {
+            name: Some("FLASH".into()),
+            is_boot_memory: true,
+            range: 0..0x4000,
+            cores: vec!["main".into()],
+            boot_critical_ranges: vec![],
+            target_writable: false,
+        };
+
+        (region, flash_algorithm)
+    }
+
+    /// The current contents of the flash, which are read by the flasher.
+    struct Flash {
+        contents: Vec<u8>,
+        reads: Vec<Range<u64>>,
+    }
+
+    impl Flash {
+        fn erased() -> Self {
+            Self {
+                contents: vec![ERASED; 0x4000],
+                reads: Vec::new(),
+            }
+        }
+
+        fn read(&mut self, address: u64, data: &mut [u8]) -> Result<(), FlashError> {
+            let start = address as usize;
+            data.copy_from_slice(&self.contents[start..start + data.len()]);
+            self.reads.push(address..address + data.len() as u64);
+            Ok(())
+        }
+    }
+
+    fn builder(chunks: &[(u64, &[u8])]) -> FlashBuilder {
+        let mut builder = FlashBuilder::new();
+        for (address, data) in chunks {
+            builder.add_data(*address, data).unwrap();
+        }
+        builder
+    }
+
+    /// The contents of `builder` as a flat image, with erased gaps.
+    fn image(builder: &FlashBuilder, range: Range<u64>) -> Vec<u8> {
+        let mut image = vec![ERASED; (range.end - range.start) as usize];
+        for (address, data) in builder.data_in_range(&range) {
+            let offset = (address - range.start) as usize;
+            image[offset..offset + data.len()].copy_from_slice(data);
+        }
+        image
+    }
+
+    #[test]
+    fn keep_preserves_bootloader_in_shared_sector() {
+        let (region, flash_algorithm) = demo_flash();
+
+        // A bootloader in the first 2 KiB of sector 0, and an app which starts in the same sector.
+        let mut flash = Flash::erased();
+        flash.contents[..0x800].fill(0xb0);
+
+        let app = builder(&[(0x800, &[0xa5; 0x1000])]);
+        let directives = [LayoutDirective::new(0..0x800, LayoutPolicy::Keep)];
+
+        let mut options = DownloadOptions::new();
+        options.layout = directives.to_vec();
+        check_layout(&app, &options).unwrap();
+
+        let resolved = app
+            .plan_layout(&directives)
+            .resolve_layout(&region, &flash_algorithm, &directives, true, |a, d| {
+                flash.read(a, d)
+            })
+            .unwrap();
+
+        // The bootloader is read before the sector is erased, and programmed again with the app.
+        assert_eq!(flash.reads, [0..0x800]);
+
+        let layout = resolved
+            .build_sectors_and_pages(&region, &flash_algorithm, false)
+            .unwrap();
+        let sectors: Vec<_> = layout.sectors().iter().map(|s| s.address()).collect();
+        assert_eq!(sectors, [0x0000, 0x1000]);
+
+        let pages: Vec<_> = layout.pages().iter().map(|p| p.address()).collect();
+        assert_eq!(pages, [0x0000, 0x0400, 0x0800, 0x0c00, 0x1000, 0x1400]);
+
+        let programmed = image(&resolved, 0..0x2000);
+        assert!(programmed[..0x800].iter().all(|&byte| byte == 0xb0));
+        assert!(programmed[0x800..0x1800].iter().all(|&byte| byte == 0xa5));
+    }
+
+    #[test]
+    fn keep_without_erase_reads_nothing() {
+        let (region, flash_algorithm) = demo_flash();
+        let mut flash = Flash::erased();
+
+        let app = builder(&[(0x800, &[0xa5; 0x100])]);
+        let directives = [
+            LayoutDirective::new(0..0x800, LayoutPolicy::Keep),
+            // Kept ranges in sectors which aren't erased don't have to be programmed again.
+            LayoutDirective::new(0x3000..0x4000, LayoutPolicy::Keep),
+        ];
+
+        let resolved = app
+            .resolve_layout(&region, &flash_algorithm, &directives, false, |a, d| {
+                flash.read(a, d)
+            })
+            .unwrap();
+        assert!(flash.reads.is_empty());
+        assert_eq!(resolved.data, app.data);
+
+        app.resolve_layout(&region, &flash_algorithm, &directives, true, |a, d| {
+            flash.read(a, d)
+        })
+        .unwrap();
+        assert_eq!(flash.reads, [0..0x800]);
+    }
+
+    #[test]
+    fn skip_provisioning_page_inside_image() {
+        let (region, flash_algorithm) = demo_flash();
+
+        let image_data: Vec<u8> = (0..0x1000).map(|i| i as u8).collect();
+        let app = builder(&[(0, &image_data)]);
+        let directives = [LayoutDirective::new(0x400..0x800, LayoutPolicy::Skip)];
+
+        let planned = app.plan_layout(&directives);
+        assert_eq!(
+            planned.data,
+            BTreeMap::from([
+                (0x000, image_data[..0x400].to_vec()),
+                (0x800, image_data[0x800..].to_vec()),
+            ])
+        );
+
+        // The provisioning page is not programmed.
+        let layout = planned
+            .build_sectors_and_pages(&region, &flash_algorithm, false)
+            .unwrap();
+        let pages: Vec<_> = layout.pages().iter().map(|p| p.address()).collect();
+        assert_eq!(pages, [0x0000, 0x0800, 0x0c00]);
+
+        // With restored unwritten bytes, it is read back like any other gap.
+        let layout = planned
+            .build_sectors_and_pages(&region, &flash_algorithm, true)
+            .unwrap();
+        assert_eq!(layout.fills().len(), 1);
+        assert_eq!(layout.fills()[0].address(), 0x400);
+        assert_eq!(layout.fills()[0].size(), 0x400);
+    }
+
+    #[test]
+    fn fill_pads_gaps_in_range() {
+        let app = builder(&[(0x100, &[1; 0x10]), (0x200, &[2; 0x10])]);
+        let directives = [LayoutDirective::new(0..0x400, LayoutPolicy::Fill(0))];
+
+        let planned = app.plan_layout(&directives);
+        let programmed = image(&planned, 0..0x400);
+
+        assert!(programmed[..0x100].iter().all(|&byte| byte == 0));
+        assert!(programmed[0x100..0x110].iter().all(|&byte| byte == 1));
+        assert!(programmed[0x110..0x200].iter().all(|&byte| byte == 0));
+        assert!(programmed[0x200..0x210].iter().all(|&byte| byte == 2));
+        assert!(programmed[0x210..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn program_only_if_blank() {
+        let (region, flash_algorithm) = demo_flash();
+
+        let app = builder(&[(0, &[0xa5; 0x1000])]);
+        let directives = [LayoutDirective::new(
+            0xc00..0x1000,
+            LayoutPolicy::ProgramOnlyIfBlank,
+        )];
+
+        // A blank configuration page is programmed with the data of the image.
+        let mut flash = Flash::erased();
+        let resolved = app
+            .resolve_layout(&region, &flash_algorithm, &directives, true, |a, d| {
+                flash.read(a, d)
+            })
+            .unwrap();
+        assert_eq!(image(&resolved, 0..0x1000), vec![0xa5; 0x1000]);
+
+        // A provisioned one is kept.
+        flash.contents[0xc00..0xc10].fill(0x42);
+        let resolved = app
+            .resolve_layout(&region, &flash_algorithm, &directives, true, |a, d| {
+                flash.read(a, d)
+            })
+            .unwrap();
+        let programmed = image(&resolved, 0..0x1000);
+        assert_eq!(programmed[..0xc00], [0xa5; 0xc00]);
+        assert_eq!(programmed[0xc00..], flash.contents[0xc00..0x1000]);
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let app = builder(&[(0x800, &[0xa5; 0x1000])]);
+
+        let conflict = |layout: Vec<LayoutDirective>, do_chip_erase| {
+            let mut options = DownloadOptions::new();
+            options.layout = layout;
+            options.do_chip_erase = do_chip_erase;
+
+            match check_layout(&app, &options) {
+                Err(FlashError::LayoutConflict { range, conflict }) => Some((range, conflict)),
+                Err(other) => panic!("Unexpected error {:?}", other),
+                Ok(()) => None,
+            }
+        };
+
+        // The image overlaps the kept bootloader.
+        assert_eq!(
+            conflict(
+                vec![LayoutDirective::new(0..0x1000, LayoutPolicy::Keep)],
+                false
+            ),
+            Some((0..0x1000, LayoutConflict::DataInKeptRange(0x800..0x1000)))
+        );
+
+        assert_eq!(
+            conflict(
+                vec![
+                    LayoutDirective::new(0..0x800, LayoutPolicy::Keep),
+                    LayoutDirective::new(0x400..0xc00, LayoutPolicy::Skip),
+                ],
+                false
+            ),
+            Some((0..0x800, LayoutConflict::Overlap(0x400..0xc00)))
+        );
+
+        assert_eq!(
+            conflict(
+                vec![LayoutDirective::new(0..0x800, LayoutPolicy::Keep)],
+                true
+            ),
+            Some((0..0x800, LayoutConflict::ChipErase))
+        );
+
+        assert_eq!(
+            conflict(
+                vec![LayoutDirective::new(0x800..0x800, LayoutPolicy::Fill(0))],
+                false
+            ),
+            Some((0x800..0x800, LayoutConflict::EmptyRange))
+        );
+
+        assert_eq!(
+            conflict(
+                vec![LayoutDirective::new(0..0x800, LayoutPolicy::Keep)],
+                false
+            ),
+            None
+        );
+    }
+}
@@ -7,11 +7,16 @@ use std::io::{Read, Seek, SeekFrom};
 use std::ops::Range;
 
 use super::builder::FlashBuilder;
+use super::download_set::DownloadTracker;
+use super::journal::{self, Journal};
+use super::layout::check_layout;
+use super::timing::TimingMonitor;
 use super::{
-    extract_from_elf, BinOptions, DownloadOptions, FileDownloadError, FlashError, FlashProgress,
-    Flasher,
+    elf_entry_point, extract_from_elf, BinOptions, DownloadOptions, FileDownloadError,
+    FlashAlgorithm, FlashError, FlashProgress, Flasher, Format, ImageIssue, JournalError,
+    JournalLocation, LayoutPolicy, ReservedRam,
 };
-use crate::memory::MemoryInterface;
+use crate::memory::{MemoryInterface, TargetWritableFlash};
 use crate::session::Session;
 use crate::Target;
 
@@ -23,7 +28,10 @@ use crate::Target;
 /// Region crossing data chunks are allowed as long as the regions are contiguous.
 pub struct FlashLoader {
     memory_map: Vec<MemoryRegion>,
-    builder: FlashBuilder,
+    pub(super) builder: FlashBuilder,
+
+    /// The address at which execution of the image starts, if known.
+    entry_point: Option<u64>,
 
     /// Source of the flash description,
     /// used for diagnostics.
@@ -36,6 +44,7 @@ impl FlashLoader {
         Self {
             memory_map,
             builder: FlashBuilder::new(),
+            entry_point: None,
             source,
         }
     }
@@ -74,6 +83,21 @@ impl FlashLoader {
         self.builder.add_data(address, data)
     }
 
+    /// Returns the address at which execution of the image starts, if it is known.
+    ///
+    /// The entry point is taken from ELF and HEX files, for other data it can be set
+    /// with [set_entry_point()](FlashLoader::set_entry_point).
+    pub fn entry_point(&self) -> Option<u64> {
+        self.entry_point
+    }
+
+    /// Set the address at which execution of the image starts.
+    ///
+    /// This is only used to check if the image is bootable, see [validate()](FlashLoader::validate).
+    pub fn set_entry_point(&mut self, entry_point: Option<u64>) {
+        self.entry_point = entry_point;
+    }
+
     pub(super) fn get_region_for_address(
         memory_map: &[MemoryRegion],
         address: u64,
@@ -137,11 +161,15 @@ impl FlashLoader {
                 ExtendedSegmentAddress(address) => {
                     base_address = (address as u64) * 16;
                 }
-                StartSegmentAddress { .. } => (),
+                StartSegmentAddress { cs, ip } => {
+                    self.entry_point = Some((cs as u64) * 16 + ip as u64);
+                }
                 ExtendedLinearAddress(address) => {
                     base_address = (address as u64) << 16;
                 }
-                StartLinearAddress(_) => (),
+                StartLinearAddress(address) => {
+                    self.entry_point = Some(address as u64);
+                }
             };
         }
         Ok(())
@@ -186,9 +214,24 @@ impl FlashLoader {
             self.add_data(data.address.into(), data.data)?;
         }
 
+        self.entry_point = Some(elf_entry_point(&elf_buffer)?);
+
         Ok(())
     }
 
+    /// Reads the data from `file` in the given `format` and adds it to the loader.
+    pub(super) fn load<T: Read + Seek>(
+        &mut self,
+        file: &mut T,
+        format: Format,
+    ) -> Result<(), FileDownloadError> {
+        match format {
+            Format::Bin(options) => self.load_bin_data(file, options),
+            Format::Elf => self.load_elf_data(file),
+            Format::Hex => self.load_hex_data(file),
+        }
+    }
+
     /// Writes all the stored data chunks to flash.
     ///
     /// Requires a session with an attached target that has a known flash algorithm.
@@ -198,6 +241,43 @@ impl FlashLoader {
         &self,
         session: &mut Session,
         options: DownloadOptions<'_>,
+    ) -> Result<(), FlashError> {
+        self.commit_tracked(session, options, &mut DownloadTracker::default())
+    }
+
+    /// Writes all the stored data chunks to flash, and records in `tracker` which parts of
+    /// the data were programmed and verified.
+    pub(super) fn commit_tracked(
+        &self,
+        session: &mut Session,
+        options: DownloadOptions<'_>,
+        tracker: &mut DownloadTracker,
+    ) -> Result<(), FlashError> {
+        if options.layout.is_empty() {
+            return self.commit_planned(session, options, tracker);
+        }
+
+        check_layout(&self.builder, &options)?;
+
+        // Skipped and filled ranges are known without reading the flash,
+        // so they are applied to the whole image before anything else is done.
+        let planned = FlashLoader {
+            memory_map: self.memory_map.clone(),
+            builder: self.builder.plan_layout(&options.layout),
+            entry_point: self.entry_point,
+            source: self.source.clone(),
+        };
+
+        planned.commit_planned(session, options, tracker)
+    }
+
+    /// Writes all the stored data chunks to flash, after the skip and fill directives
+    /// of `options.layout` were applied.
+    fn commit_planned(
+        &self,
+        session: &mut Session,
+        options: DownloadOptions<'_>,
+        tracker: &mut DownloadTracker,
     ) -> Result<(), FlashError> {
         log::debug!("committing FlashLoader!");
 
@@ -224,6 +304,18 @@ impl FlashLoader {
             );
         }
 
+        if options.validate_image {
+            let issues = session.validate_image(self);
+
+            for issue in &issues {
+                log::warn!("Image validation: {}", issue);
+            }
+
+            if issues.iter().any(ImageIssue::is_error) {
+                return Err(FlashError::ImageValidationFailed { issues });
+            }
+        }
+
         // Iterate over all memory regions, and program their data.
 
         if self.memory_map != session.target().memory_map {
@@ -258,22 +350,57 @@ impl FlashLoader {
                     continue;
                 }
 
-                let algo = Self::get_flash_algorithm_for_region(region, session.target())?;
+                // Multi-bank regions can require a different algorithm for each bank.
+                for (region, algo) in self.split_region_by_algorithm(region, session.target())? {
+                    self.check_boot_critical_sectors(&region, algo, &options)?;
 
-                let entry = algos
-                    .entry((
-                        algo.name.clone(),
-                        region
-                            .cores
-                            .first()
-                            .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?
-                            .clone(),
-                    ))
-                    .or_default();
-                entry.push(region.clone());
+                    log::debug!(
+                        "     -- using algorithm: {} for {:08x}-{:08x}",
+                        algo.name,
+                        region.range.start,
+                        region.range.end
+                    );
+
+                    let entry = algos
+                        .entry((
+                            algo.name.clone(),
+                            region
+                                .cores
+                                .first()
+                                .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?
+                                .clone(),
+                        ))
+                        .or_default();
+                    entry.push(region);
+                }
+            }
+        }
+
+        let journal_region = match &options.journal {
+            Some(location) => self.check_journal(location, &algos, session.target(), &options)?,
+            None => None,
+        };
+
+        // The flash algorithms are placed before anything is erased, so that they don't
+        // overwrite RAM which is used otherwise.
+        let reserved = self.reserved_ram(&options);
+        let mut assembled = HashMap::new();
+        for (algo_name, core_name) in algos.keys() {
+            let target = session.target();
 
-                log::debug!("     -- using algorithm: {}", algo.name);
+            // This can't fail, algo_name comes from the target.
+            let algo = target.flash_algorithm_by_name(algo_name).unwrap();
+            let core = target.core_index_by_name(core_name).unwrap();
+
+            let rams = Flasher::algorithm_rams(target, core);
+            if rams.is_empty() {
+                return Err(FlashError::NoRamDefined {
+                    name: target.name.clone(),
+                });
             }
+
+            let algorithm = FlashAlgorithm::assemble_avoiding(algo, &rams, target, &reserved)?;
+            assembled.insert((algo_name.clone(), core_name.clone()), algorithm);
         }
 
         if options.dry_run {
@@ -288,21 +415,55 @@ impl FlashLoader {
             return Ok(());
         }
 
+        let mut journal =
+            match &options.journal {
+                Some(location) => {
+                    let journal_core =
+                        match &journal_region {
+                            Some(region) => Some(
+                                session
+                                    .target()
+                                    .core_index_by_name(region.cores.first().ok_or_else(|| {
+                                        FlashError::NoNvmCoreAccess(region.clone())
+                                    })?)
+                                    .unwrap(),
+                            ),
+                            None => None,
+                        };
+
+                    Some(Journal::load(
+                        location,
+                        journal::image_hash(&self.builder),
+                        |address, data| {
+                            // Only a journal in flash is read from the target.
+                            let mut core = session
+                                .core(journal_core.unwrap_or_default())
+                                .map_err(FlashError::Core)?;
+                            core.read(address, data).map_err(FlashError::Core)
+                        },
+                    )?)
+                }
+                None => None,
+            };
+
+        // Sectors in flash which the firmware writes are programmed even if the journal
+        // records them as verified.
+        let target_writable = session.target_writable_flash().clone();
+
+        let mut timings = TimingMonitor::new(session.health_log().clone(), 0);
+
+        // The data which was actually written, including preserved contents of the flash.
+        let mut written = FlashBuilder::new();
+
         // Iterate all flash algorithms we need to use.
         for ((algo_name, core_name), regions) in algos {
             log::debug!("Flashing ranges for algo: {}", algo_name);
 
-            // This can't fail, algo_name comes from the target.
-            let algo = session.target().flash_algorithm_by_name(&algo_name);
-            let algo = algo.unwrap().clone();
-
-            let core = session
-                .target()
-                .cores
-                .iter()
-                .position(|c| c.name == core_name)
-                .unwrap();
-            let mut flasher = Flasher::new(session, core, &algo)?;
+            let core = session.target().core_index_by_name(&core_name).unwrap();
+            let algorithm = assembled.remove(&(algo_name, core_name)).unwrap();
+            let mut flasher = Flasher::with_algorithm(session, core, algorithm)?;
+            flasher.set_disable_mpu(options.disable_mpu);
+            flasher.set_slow_operation_threshold(options.slow_operation_threshold);
 
             let mut do_chip_erase = options.do_chip_erase;
 
@@ -336,16 +497,66 @@ impl FlashLoader {
                     region.range.end - region.range.start
                 );
 
+                let resolved;
+                let builder = if options.layout.is_empty() {
+                    &self.builder
+                } else {
+                    // Kept ranges are read before their sectors are erased.
+                    let flash_algorithm = flasher.flash_algorithm().clone();
+                    resolved = self.builder.resolve_layout(
+                        &region,
+                        &flash_algorithm,
+                        &options.layout,
+                        !(options.skip_erase || do_chip_erase),
+                        |address, data| flasher.read(address, data),
+                    )?;
+                    &resolved
+                };
+
                 // Program the data.
-                flasher.program(
-                    &region,
-                    &self.builder,
-                    options.keep_unwritten_bytes,
-                    do_use_double_buffering,
-                    options.skip_erase || do_chip_erase,
-                    options.progress.unwrap_or(&FlashProgress::new(|_| {})),
-                )?;
+                match &mut journal {
+                    Some(journal) => Self::program_journaled(
+                        &mut flasher,
+                        &region,
+                        builder,
+                        journal,
+                        journal_region.as_ref(),
+                        &target_writable,
+                        &options,
+                        do_use_double_buffering,
+                        tracker,
+                    )?,
+                    None => {
+                        tracker.begin(region.range.clone());
+                        flasher.program(
+                            &region,
+                            builder,
+                            options.keep_unwritten_bytes,
+                            do_use_double_buffering,
+                            options.skip_erase || do_chip_erase,
+                            options.progress.unwrap_or(&FlashProgress::new(|_| {})),
+                        )?;
+                        tracker.programmed();
+                    }
+                }
+
+                if options.verify {
+                    for (address, data) in builder.data_in_range(&region.range) {
+                        written.data.insert(address, data.to_vec());
+                    }
+                }
             }
+
+            timings.extend(flasher.timings());
+        }
+
+        if journal.is_some() {
+            // The journal now records the contents which the firmware wrote.
+            session.target_writable_flash_mut().clear_written();
+        }
+
+        if let Some(progress) = options.progress {
+            progress.timings(timings.summary());
         }
 
         log::debug!("committing RAM!");
@@ -382,8 +593,16 @@ impl FlashLoader {
                         data.len()
                     );
                     // Write data to memory.
+                    tracker.begin(address..address + data.len() as u64);
+                    core.prepare_ecc_write(address, data.len())
+                        .map_err(FlashError::Core)?;
                     core.write_8(address as u64, data)
                         .map_err(FlashError::Core)?;
+                    tracker.programmed();
+
+                    if options.verify {
+                        written.data.insert(address, data.to_vec());
+                    }
                 }
 
                 if !some {
@@ -394,7 +613,7 @@ impl FlashLoader {
 
         if options.verify {
             log::debug!("Verifying!");
-            for (&address, data) in &self.builder.data {
+            for (&address, data) in &written.data {
                 log::debug!(
                     "    data: {:08x}-{:08x} ({} bytes)",
                     address,
@@ -416,19 +635,297 @@ impl FlashLoader {
                 let core_index = session.target().core_index_by_name(core_name).unwrap();
                 let mut core = session.core(core_index).map_err(FlashError::Core)?;
 
+                tracker.begin(address..address + data.len() as u64);
+
                 let mut written_data = vec![0; data.len()];
                 core.read(address as u64, &mut written_data)
                     .map_err(FlashError::Core)?;
 
-                if data != &written_data {
+                if let Some(offset) = data.iter().zip(&written_data).position(|(a, b)| a != b) {
+                    tracker.failed_at(address + offset as u64);
+                    return Err(FlashError::Verify);
+                }
+
+                tracker.verified();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The RAM which the flash algorithms must not use: the data of the image in RAM, and
+    /// the ranges of RAM which are kept with `options.layout`.
+    fn reserved_ram(&self, options: &DownloadOptions<'_>) -> Vec<ReservedRam> {
+        let mut reserved = Vec::new();
+
+        for region in &self.memory_map {
+            if let MemoryRegion::Ram(region) = region {
+                for (address, data) in self.builder.data_in_range(&region.range) {
+                    reserved.push(ReservedRam::ImageData(address..address + data.len() as u64));
+                }
+
+                for directive in &options.layout {
+                    if directive.policy == LayoutPolicy::Keep
+                        && directive.range.start < region.range.end
+                        && region.range.start < directive.range.end
+                    {
+                        reserved.push(ReservedRam::KeptRange(directive.range.clone()));
+                    }
+                }
+            }
+        }
+
+        reserved
+    }
+
+    /// Program the data of `builder` in `region` sector by sector, and record each sector
+    /// in `journal` once it was verified.
+    ///
+    /// Sectors which the journal records as verified with the same contents are skipped,
+    /// unless the firmware may have written them since, see [`TargetWritableFlash`].
+    #[allow(clippy::too_many_arguments)]
+    fn program_journaled(
+        flasher: &mut Flasher,
+        region: &NvmRegion,
+        builder: &FlashBuilder,
+        journal: &mut Journal,
+        journal_region: Option<&NvmRegion>,
+        target_writable: &TargetWritableFlash,
+        options: &DownloadOptions<'_>,
+        double_buffering: bool,
+        tracker: &mut DownloadTracker,
+    ) -> Result<(), FlashError> {
+        let silent = FlashProgress::new(|_| {});
+        let progress = options.progress.unwrap_or(&silent);
+
+        let flash_algorithm = flasher.flash_algorithm().clone();
+
+        for sector in builder.sectors(region, &flash_algorithm) {
+            let range = sector.address()..sector.address() + sector.size();
+            let hash = journal::sector_hash(builder, &range);
+
+            if target_writable.is_untrusted(&range) {
+                log::debug!(
+                    "    sector {:08x} may have been written by the target, programming it",
+                    sector.address()
+                );
+            } else if journal.is_verified(sector.address(), hash) {
+                log::debug!(
+                    "    sector {:08x} was verified before, skipping",
+                    sector.address()
+                );
+                tracker.begin(range);
+                tracker.verified();
+                continue;
+            }
+
+            tracker.begin(range.clone());
+
+            let mut part = FlashBuilder::new();
+            for (address, data) in builder.data_in_range(&range) {
+                part.data.insert(address, data.to_vec());
+            }
+
+            flasher.program(
+                region,
+                &part,
+                options.keep_unwritten_bytes,
+                double_buffering,
+                options.skip_erase,
+                progress,
+            )?;
+
+            for (&address, data) in &part.data {
+                let mut written_data = vec![0; data.len()];
+                flasher.read(address, &mut written_data)?;
+
+                if let Some(offset) = data.iter().zip(&written_data).position(|(a, b)| a != b) {
+                    tracker.failed_at(address + offset as u64);
                     return Err(FlashError::Verify);
                 }
             }
+
+            journal.record(sector.address(), hash, |slot, bytes| {
+                // `check_journal` makes sure that a journal in flash has a region.
+                let mut slot_region = journal_region.unwrap().clone();
+                slot_region.range = slot.clone();
+
+                let mut contents = FlashBuilder::new();
+                contents.data.insert(slot.start, bytes.to_vec());
+
+                flasher.program(
+                    &slot_region,
+                    &contents,
+                    false,
+                    double_buffering,
+                    false,
+                    progress,
+                )
+            })?;
+
+            tracker.verified();
         }
 
         Ok(())
     }
 
+    /// Make sure that the journal at `location` can be used to program the regions of `algos`.
+    ///
+    /// Returns the part of the NVM region which holds a journal in flash.
+    fn check_journal(
+        &self,
+        location: &JournalLocation,
+        algos: &HashMap<(String, String), Vec<NvmRegion>>,
+        target: &Target,
+        options: &DownloadOptions<'_>,
+    ) -> Result<Option<NvmRegion>, FlashError> {
+        Journal::check(location, &self.builder, options.do_chip_erase)
+            .map_err(FlashError::Journal)?;
+
+        let mut sectors = 0;
+        for ((algo_name, _), regions) in algos {
+            // This can't fail, algo_name comes from the target.
+            let algorithm = layout_algorithm(target.flash_algorithm_by_name(algo_name).unwrap());
+
+            Journal::check_slots(location, &algorithm).map_err(FlashError::Journal)?;
+
+            for region in regions {
+                sectors += self.builder.sectors(region, &algorithm).len();
+            }
+        }
+
+        Journal::check_capacity(location, sectors).map_err(FlashError::Journal)?;
+
+        match location {
+            JournalLocation::Flash(range) => {
+                let region = self.memory_map.iter().find_map(|region| match region {
+                    MemoryRegion::Nvm(region) if region.range.contains_range(range) => {
+                        let mut region = region.clone();
+                        region.range = range.clone();
+                        Some(region)
+                    }
+                    _ => None,
+                });
+
+                region
+                    .map(Some)
+                    .ok_or(FlashError::Journal(JournalError::NotInFlash))
+            }
+            JournalLocation::File(_) => Ok(None),
+        }
+    }
+
+    /// Split the given NvmRegion into the parts which contain data, and find the flash algorithm for each of them.
+    ///
+    /// If a single flash algorithm covers the whole region, the region is returned unchanged.
+    /// Otherwise, the region is split at the boundaries of the flash algorithms' address ranges.
+    ///
+    /// Errors when there is data in a part of the region which is not covered by any flash algorithm.
+    fn split_region_by_algorithm<'a>(
+        &self,
+        region: &NvmRegion,
+        target: &'a Target,
+    ) -> Result<Vec<(NvmRegion, &'a RawFlashAlgorithm)>, FlashError> {
+        let intersecting = target
+            .flash_algorithms
+            .iter()
+            .filter(|&fa| {
+                let range = &fa.flash_properties.address_range;
+                range.start < region.range.end && region.range.start < range.end
+            })
+            .collect::<Vec<_>>();
+
+        let algorithm_covers_region = intersecting.iter().any(|fa| {
+            fa.flash_properties
+                .address_range
+                .contains_range(&region.range)
+        });
+
+        if intersecting.is_empty() || algorithm_covers_region {
+            let algo = Self::get_flash_algorithm_for_region(region, target)?;
+            return Ok(vec![(region.clone(), algo)]);
+        }
+
+        let mut boundaries = vec![region.range.start, region.range.end];
+        for fa in &intersecting {
+            let range = &fa.flash_properties.address_range;
+            boundaries.push(range.start.max(region.range.start));
+            boundaries.push(range.end.min(region.range.end));
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut parts = Vec::new();
+        for window in boundaries.windows(2) {
+            let range = window[0]..window[1];
+
+            if !self.builder.has_data_in_range(&range) {
+                continue;
+            }
+
+            let mut part = region.clone();
+            part.range = range.clone();
+
+            match Self::get_flash_algorithm_for_region(&part, target) {
+                Ok(algo) => parts.push((part, algo)),
+                Err(FlashError::NoFlashLoaderAlgorithmAttached { .. }) => {
+                    return Err(FlashError::NoFlashAlgorithmForRange {
+                        range,
+                        region: Box::new(region.clone()),
+                        algorithms: intersecting.iter().map(|fa| fa.name.clone()).collect(),
+                    })
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(parts)
+    }
+
+    /// Make sure that no boot-critical sector is erased, unless this was explicitly allowed.
+    fn check_boot_critical_sectors(
+        &self,
+        region: &NvmRegion,
+        algorithm: &RawFlashAlgorithm,
+        options: &DownloadOptions<'_>,
+    ) -> Result<(), FlashError> {
+        if options.allow_boot_sector_erase || options.skip_erase {
+            return Ok(());
+        }
+
+        let layout_algorithm = layout_algorithm(algorithm);
+
+        let sectors = if options.do_chip_erase && algorithm.pc_erase_all.is_some() {
+            // A chip erase affects every sector of the algorithm, not only the ones with data.
+            layout_algorithm
+                .iter_sectors()
+                .map(|info| info.base_address..info.base_address + info.size)
+                .collect::<Vec<_>>()
+        } else {
+            self.builder
+                .sectors(region, &layout_algorithm)
+                .iter()
+                .map(|sector| sector.address()..sector.address() + sector.size())
+                .collect::<Vec<_>>()
+        };
+
+        let boot_critical_sector = sectors.into_iter().find(|sector| {
+            self.memory_map.iter().any(|region| match region {
+                MemoryRegion::Nvm(region) => region.is_boot_critical(sector),
+                _ => false,
+            })
+        });
+
+        match boot_critical_sector {
+            Some(sector) => Err(FlashError::BootSectorEraseNotAllowed {
+                sector_address: sector.start,
+                sector_size: sector.end - sector.start,
+                algorithm: algorithm.name.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
     /// Try to find a flash algorithm for the given NvmRegion.
     /// Errors when:
     /// - there's no algo for the region.
@@ -474,3 +971,174 @@ impl FlashLoader {
         }
     }
 }
+
+/// Returns a flash algorithm with only the flash properties of `algorithm`,
+/// which are all that is required to determine the sectors.
+fn layout_algorithm(algorithm: &RawFlashAlgorithm) -> FlashAlgorithm {
+    FlashAlgorithm {
+        name: algorithm.name.clone(),
+        flash_properties: algorithm.flash_properties.clone(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use probe_rs_target::{FlashProperties, SectorDescription};
+
+    use super::*;
+    use crate::architecture::arm::sequences::DefaultArmSequence;
+    use crate::architecture::route::DefaultRouteSequence;
+    use crate::config::DebugSequence;
+
+    fn algorithm(name: &str, address_range: Range<u64>) -> RawFlashAlgorithm {
+        RawFlashAlgorithm {
+            name: name.into(),
+            flash_properties: FlashProperties {
+                address_range,
+                page_size: 0x400,
+                erased_byte_value: 0xff,
+                program_page_timeout: 200,
+                erase_sector_timeout: 200,
+                sectors: vec![SectorDescription {
+                    size: 0x1000,
+                    address: 0,
+                }],
+            },
+            ..Default::default()
+        }
+    }
+
+    fn dual_bank_target(flash_algorithms: Vec<RawFlashAlgorithm>) -> Target {
+        Target {
+            name: "dual bank".into(),
+            cores: vec![],
+            flash_algorithms,
+            memory_map: vec![MemoryRegion::Nvm(NvmRegion {
+                name: Some("FLASH".into()),
+                range: 0..0x10000,
+                is_boot_memory: true,
+                cores: vec!["main".into()],
+                boot_critical_ranges: vec![0xf000..0x10000],
+                target_writable: false,
+            })],
+            source: TargetDescriptionSource::BuiltIn,
+            overridden_by: None,
+            debug_sequence: DebugSequence::Arm(DefaultArmSequence::create()),
+            secondary_debug_sequence: None,
+            route_sequence: DefaultRouteSequence::create(),
+            keepalive: None,
+            errata: vec![],
+            mediated_regions: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
+        }
+    }
+
+    fn region(target: &Target) -> &NvmRegion {
+        match &target.memory_map[0] {
+            MemoryRegion::Nvm(region) => region,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn multi_bank_region_is_split_per_algorithm() {
+        let target = dual_bank_target(vec![
+            algorithm("bank1", 0..0x8000),
+            algorithm("bank2", 0x8000..0x10000),
+        ]);
+
+        let mut loader = target.flash_loader();
+        loader.add_data(0x7ff0, &[0xaa; 0x20]).unwrap();
+
+        let parts = loader
+            .split_region_by_algorithm(region(&target), &target)
+            .unwrap();
+
+        let parts = parts
+            .iter()
+            .map(|(region, algo)| (region.range.clone(), algo.name.as_str()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            parts,
+            vec![(0..0x8000, "bank1"), (0x8000..0x10000, "bank2")]
+        );
+    }
+
+    #[test]
+    fn data_outside_of_all_algorithms_is_rejected() {
+        let target = dual_bank_target(vec![
+            algorithm("bank1", 0..0x8000),
+            algorithm("bank2", 0xc000..0x10000),
+        ]);
+
+        let mut loader = target.flash_loader();
+        loader.add_data(0x9000, &[0xaa; 4]).unwrap();
+
+        let result = loader.split_region_by_algorithm(region(&target), &target);
+
+        match result {
+            Err(FlashError::NoFlashAlgorithmForRange {
+                range, algorithms, ..
+            }) => {
+                assert_eq!(range, 0x8000..0xc000);
+                assert_eq!(algorithms, vec!["bank1", "bank2"]);
+            }
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn boot_critical_sector_requires_permission() {
+        let target = dual_bank_target(vec![algorithm("flash", 0..0x10000)]);
+
+        let mut loader = target.flash_loader();
+        loader.add_data(0xf800, &[0xaa; 4]).unwrap();
+
+        let region = region(&target);
+        let algo = &target.flash_algorithms[0];
+
+        let result = loader.check_boot_critical_sectors(region, algo, &DownloadOptions::new());
+        assert!(matches!(
+            result,
+            Err(FlashError::BootSectorEraseNotAllowed {
+                sector_address: 0xf000,
+                sector_size: 0x1000,
+                ..
+            })
+        ));
+
+        let mut options = DownloadOptions::new();
+        options.allow_boot_sector_erase = true;
+        assert!(loader
+            .check_boot_critical_sectors(region, algo, &options)
+            .is_ok());
+    }
+
+    #[test]
+    fn chip_erase_includes_boot_critical_sectors() {
+        let mut algo = algorithm("flash", 0..0x10000);
+        algo.pc_erase_all = Some(0x100);
+        let target = dual_bank_target(vec![algo]);
+
+        let mut loader = target.flash_loader();
+        loader.add_data(0x0, &[0xaa; 4]).unwrap();
+
+        let mut options = DownloadOptions::new();
+        assert!(loader
+            .check_boot_critical_sectors(region(&target), &target.flash_algorithms[0], &options)
+            .is_ok());
+
+        options.do_chip_erase = true;
+        assert!(matches!(
+            loader.check_boot_critical_sectors(
+                region(&target),
+                &target.flash_algorithms[0],
+                &options
+            ),
+            Err(FlashError::BootSectorEraseNotAllowed { .. })
+        ));
+    }
+}
@@ -44,22 +44,36 @@
 //!
 
 mod builder;
+mod compression;
 mod download;
+mod download_set;
 mod erase;
 mod error;
 mod flash_algorithm;
 mod flasher;
+mod journal;
+mod layout;
 mod loader;
 mod progress;
+mod streaming;
+mod timing;
+mod validate;
 mod visualizer;
 
 use builder::*;
 use flasher::*;
 
+pub use compression::*;
 pub use download::*;
+pub use download_set::{FlashDownloadSet, ImageOutcome, ImageStatus};
 pub use erase::*;
 pub use error::*;
 pub use flash_algorithm::*;
+pub use journal::{JournalError, JournalLocation};
+pub use layout::{LayoutConflict, LayoutDirective, LayoutPolicy};
 pub use loader::*;
 pub use progress::*;
+pub use streaming::*;
+pub use timing::{FlashOperation, FlashTimings, OperationTimings, SlowOperationThreshold};
+pub use validate::*;
 pub use visualizer::*;
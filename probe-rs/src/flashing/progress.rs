@@ -1,4 +1,4 @@
-use super::FlashLayout;
+use super::{FlashLayout, FlashOperation, FlashTimings, ImageOutcome};
 use std::time::Duration;
 
 /// A structure to manage the flashing procedure progress reporting.
@@ -31,6 +31,11 @@ impl FlashProgress {
         (self.handler)(event);
     }
 
+    /// Signalize that data of a streamed image was read from its source.
+    pub(super) fn data_consumed(&self, size: u64) {
+        self.emit(ProgressEvent::DataConsumed { size });
+    }
+
     /// Signalize that the flashing algorithm was set up and is initialized.
     pub(super) fn initialized(&self, flash_layout: FlashLayout) {
         self.emit(ProgressEvent::Initialized { flash_layout });
@@ -52,13 +57,54 @@ impl FlashProgress {
     }
 
     /// Signalize that the page programming procedure has made progress.
-    pub(super) fn page_programmed(&self, size: u32, time: Duration) {
-        self.emit(ProgressEvent::PageProgrammed { size, time });
+    pub(super) fn page_programmed(
+        &self,
+        address: u64,
+        size: u32,
+        transferred: u32,
+        time: Duration,
+    ) {
+        self.emit(ProgressEvent::PageProgrammed {
+            address,
+            size,
+            transferred,
+            time,
+        });
     }
 
     /// Signalize that the sector erasing procedure has made progress.
-    pub(super) fn sector_erased(&self, size: u64, time: Duration) {
-        self.emit(ProgressEvent::SectorErased { size, time });
+    pub(super) fn sector_erased(&self, address: u64, size: u64, time: Duration) {
+        self.emit(ProgressEvent::SectorErased {
+            address,
+            size,
+            time,
+        });
+    }
+
+    /// Signalize that an operation took longer than expected.
+    pub(super) fn slow_operation(
+        &self,
+        operation: FlashOperation,
+        address: u64,
+        time: Duration,
+        expected: Duration,
+    ) {
+        self.emit(ProgressEvent::SlowOperation {
+            operation,
+            address,
+            time,
+            expected,
+        });
+    }
+
+    /// Signalize that the download finished, with the statistics of its operations.
+    pub(super) fn timings(&self, timings: FlashTimings) {
+        self.emit(ProgressEvent::Timings { timings });
+    }
+
+    /// Signalize that the download of an image of a download set is over.
+    pub(super) fn image_finished(&self, outcome: ImageOutcome) {
+        self.emit(ProgressEvent::ImageFinished { outcome });
     }
 
     /// Signalize that the page filling procedure has made progress.
@@ -112,11 +158,28 @@ impl FlashProgress {
 /// * `StartedProgramming`
 /// * `PageProgrammed` for every page
 /// * `FinishedProgramming`
+/// * `Timings`, once all regions were programmed
+///
+/// `SlowOperation` follows a `SectorErased` or `PageProgrammed` event if the operation
+/// exceeded the [`SlowOperationThreshold`](super::SlowOperationThreshold).
 ///
 /// If an erorr occurs in any stage, one of the `Failed*` event will be returned,
 /// and no further events will be returned.
+///
+/// When an image is streamed with [`erase_and_program_streaming`](super::erase_and_program_streaming),
+/// each sector is flashed on its own: `DataConsumed` is followed by the events above for every
+/// sector, unless the sector was skipped because it was unchanged.
+///
+/// When a [`FlashDownloadSet`](super::FlashDownloadSet) is downloaded, the events above are
+/// reported for the combined images, followed by `ImageFinished` for every image, even if
+/// the download failed.
 #[derive(Debug)]
 pub enum ProgressEvent {
+    /// Data of a streamed image was read from its source.
+    DataConsumed {
+        /// The number of bytes which were read.
+        size: u64,
+    },
     /// The flash layout has been built and the flashing procedure was initialized.
     Initialized {
         /// The layout of the flash contents as it will be used by the flash procedure.
@@ -142,9 +205,11 @@ pub enum ProgressEvent {
     StartedErasing,
     /// A sector has been erased successfully.
     SectorErased {
+        /// The address of the sector.
+        address: u64,
         /// The size of the sector in bytes.
         size: u64,
-        /// The time it took to erase this sector.
+        /// The time the erase routine of the flash algorithm took for this sector.
         time: Duration,
     },
     /// Erasing of the flash has failed.
@@ -155,13 +220,44 @@ pub enum ProgressEvent {
     StartedProgramming,
     /// A flash page has been programmed successfully.
     PageProgrammed {
+        /// The address of this page.
+        address: u64,
         /// The size of this page in bytes.
         size: u32,
-        /// The time it took to program this page.
+        /// The number of bytes transferred to the target for this page.
+        ///
+        /// This is smaller than `size` if the page was compressed.
+        transferred: u32,
+        /// The time the program routine of the flash algorithm took for this page.
+        ///
+        /// With double buffering, this includes the transfer of the next page, which
+        /// happens while this page is programmed.
         time: Duration,
     },
     /// Programming of the flash failed.
     FailedProgramming,
     /// Programming of the flash has finished successfully.
     FinishedProgramming,
+    /// An erase or program operation took longer than expected.
+    SlowOperation {
+        /// The kind of the operation.
+        operation: FlashOperation,
+        /// The address of the sector or page.
+        address: u64,
+        /// The time the operation took.
+        time: Duration,
+        /// The time which was exceeded, derived from the earlier operations of the same
+        /// kind, or from the timeout of the flash algorithm.
+        expected: Duration,
+    },
+    /// The download has finished successfully.
+    Timings {
+        /// The statistics of the durations of the erase and program operations.
+        timings: FlashTimings,
+    },
+    /// The download of an image of a [`FlashDownloadSet`](super::FlashDownloadSet) is over.
+    ImageFinished {
+        /// Whether the image was programmed.
+        outcome: ImageOutcome,
+    },
 }
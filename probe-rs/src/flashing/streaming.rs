@@ -0,0 +1,236 @@
+//! Streaming downloads of large flash images.
+//!
+//! Images for external flash, e.g. QSPI flash with tens of megabytes, don't need to be
+//! loaded into host memory completely. [`erase_and_program_streaming`] reads the image
+//! from an [`io::Read`](std::io::Read) source one sector at a time, and erases and
+//! programs each sector as soon as its data has arrived.
+
+use std::io::{ErrorKind, Read};
+
+use probe_rs_target::{MemoryRegion, NvmRegion, TargetDescriptionSource};
+
+use super::builder::FlashBuilder;
+use super::{FlashError, FlashLoader, FlashProgress, Flasher};
+use crate::session::Session;
+
+/// Options for [`erase_and_program_streaming`].
+///
+/// The options mirror the ones of [`DownloadOptions`](super::DownloadOptions) which apply
+/// to a streamed image.
+#[derive(Default)]
+#[non_exhaustive]
+pub struct StreamingOptions<'progress> {
+    /// An optional progress reporter which is used if this argument is set to `Some(...)`.
+    ///
+    /// The events of the flashing procedure are reported for every sector, after
+    /// [`ProgressEvent::DataConsumed`](super::ProgressEvent::DataConsumed) reported
+    /// the data read for it.
+    pub progress: Option<&'progress FlashProgress>,
+    /// Restore the bytes of the first and last sector which are not covered by the image,
+    /// see [`DownloadOptions::keep_unwritten_bytes`](super::DownloadOptions::keep_unwritten_bytes).
+    pub keep_unwritten_bytes: bool,
+    /// Read each sector before erasing it, and skip it if the flash already contains the data.
+    pub skip_unchanged_sectors: bool,
+    /// Disable double buffering when loading flash.
+    pub disable_double_buffering: bool,
+    /// Allow erasing sectors which are marked as boot-critical in the target description.
+    pub allow_boot_sector_erase: bool,
+    /// Disable the MPU of the core while the flash algorithm runs, see
+    /// [`DownloadOptions::disable_mpu`](super::DownloadOptions::disable_mpu).
+    pub disable_mpu: bool,
+}
+
+impl<'progress> StreamingOptions<'progress> {
+    /// StreamingOptions with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Erase and program the image read from `source` to the flash at `address`.
+///
+/// The image is read one sector at a time, and each sector is erased and programmed before
+/// the next one is read, so at most a sector of the image is held in memory. The image has to
+/// fit into a single flash region.
+///
+/// Returns the number of bytes which were programmed. If the download fails, e.g. because
+/// `source` returned an error, [`FlashError::StreamingFailed`] reports the part of the image
+/// which was programmed completely before the failure.
+pub fn erase_and_program_streaming(
+    session: &mut Session,
+    address: u64,
+    mut source: impl Read,
+    options: StreamingOptions<'_>,
+) -> Result<u64, FlashError> {
+    let no_progress = FlashProgress::new(|_| {});
+    let progress = options.progress.unwrap_or(&no_progress);
+
+    let target = session.target();
+    let region = target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Nvm(region) if region.range.contains(&address) => Some(region.clone()),
+            _ => None,
+        })
+        .ok_or_else(|| FlashError::NoSuitableNvm {
+            start: address,
+            end: address + 1,
+            description_source: target.source.clone(),
+        })?;
+
+    let algo = FlashLoader::get_flash_algorithm_for_region(&region, target)?.clone();
+
+    let core_name = region
+        .cores
+        .first()
+        .ok_or_else(|| FlashError::NoNvmCoreAccess(region.clone()))?;
+    let core_index = target.core_index_by_name(core_name).unwrap();
+
+    let description_source = target.source.clone();
+
+    let mut flasher = Flasher::new(session, core_index, &algo)?;
+    flasher.set_disable_mpu(options.disable_mpu);
+    let double_buffering =
+        flasher.double_buffering_supported() && !options.disable_double_buffering;
+
+    let mut download = StreamingDownload {
+        flasher,
+        region,
+        description_source,
+        options: &options,
+        double_buffering,
+        progress,
+    };
+
+    let mut programmed = address..address;
+
+    loop {
+        match download.program_next_sector(&mut source, programmed.end) {
+            Ok(0) => break,
+            Ok(size) => programmed.end += size,
+            Err(error) => {
+                log::warn!(
+                    "Streaming download failed, programmed {:#010x}..{:#010x}",
+                    programmed.start,
+                    programmed.end
+                );
+
+                return Err(FlashError::StreamingFailed {
+                    programmed,
+                    source: Box::new(error),
+                });
+            }
+        }
+    }
+
+    progress.timings(download.flasher.timings().summary());
+
+    Ok(programmed.end - programmed.start)
+}
+
+/// The state of a streaming download.
+struct StreamingDownload<'session, 'options, 'progress> {
+    flasher: Flasher<'session>,
+    region: NvmRegion,
+    description_source: TargetDescriptionSource,
+    options: &'options StreamingOptions<'progress>,
+    double_buffering: bool,
+    progress: &'options FlashProgress,
+}
+
+impl StreamingDownload<'_, '_, '_> {
+    /// Read the data for the sector containing `address` from `source`, and program it.
+    ///
+    /// Returns the number of bytes which were programmed, or `0` at the end of the stream.
+    fn program_next_sector(
+        &mut self,
+        source: &mut impl Read,
+        address: u64,
+    ) -> Result<u64, FlashError> {
+        let sector = self.flasher.flash_algorithm().sector_info(address);
+
+        // The image may continue after the end of the region or flash algorithm, which is
+        // only an error if there actually is more data.
+        let sector = match sector {
+            Some(sector) if self.region.range.contains(&address) => sector,
+            _ => {
+                let mut byte = [0];
+                return match read_chunk(source, &mut byte)? {
+                    0 => Ok(0),
+                    _ => Err(FlashError::NoSuitableNvm {
+                        start: address,
+                        end: address + 1,
+                        description_source: self.description_source.clone(),
+                    }),
+                };
+            }
+        };
+
+        let sector_range = sector.base_address..sector.base_address + sector.size;
+        let end = sector_range.end.min(self.region.range.end);
+
+        let mut data = vec![0; (end - address) as usize];
+        let size = read_chunk(source, &mut data)?;
+        data.truncate(size);
+
+        if size == 0 {
+            return Ok(0);
+        }
+
+        self.progress.data_consumed(size as u64);
+
+        if self.options.skip_unchanged_sectors {
+            let mut current = vec![0; size];
+            self.flasher.read(address, &mut current)?;
+
+            if current == data {
+                log::debug!("Skipping unchanged sector at {:#010x}", sector.base_address);
+                return Ok(size as u64);
+            }
+        }
+
+        if !self.options.allow_boot_sector_erase && self.region.is_boot_critical(&sector_range) {
+            return Err(FlashError::BootSectorEraseNotAllowed {
+                sector_address: sector.base_address,
+                sector_size: sector.size,
+                algorithm: self.flasher.flash_algorithm().name.clone(),
+            });
+        }
+
+        let mut builder = FlashBuilder::new();
+        builder.add_data(address, &data)?;
+
+        let mut sector_region = self.region.clone();
+        sector_region.range = sector_range.start.max(self.region.range.start)..end;
+
+        self.flasher.program(
+            &sector_region,
+            &builder,
+            self.options.keep_unwritten_bytes,
+            self.double_buffering,
+            false,
+            self.progress,
+        )?;
+
+        Ok(size as u64)
+    }
+}
+
+/// Fill `buffer` from `source`, until the buffer is full or the stream ends.
+///
+/// Returns the number of bytes which were read.
+fn read_chunk(source: &mut impl Read, buffer: &mut [u8]) -> Result<usize, FlashError> {
+    let mut size = 0;
+
+    while size < buffer.len() {
+        match source.read(&mut buffer[size..]) {
+            Ok(0) => break,
+            Ok(read) => size += read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => (),
+            Err(error) => return Err(FlashError::StreamRead(error)),
+        }
+    }
+
+    Ok(size)
+}
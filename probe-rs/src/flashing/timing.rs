@@ -0,0 +1,264 @@
+//! Timing of the individual erase and program operations.
+//!
+//! Ageing or marginal flash shows up first as single sectors or pages which take much longer
+//! than the others. The duration of each call of a flash algorithm routine is reported with
+//! its [`ProgressEvent`](super::ProgressEvent), and operations which exceed the
+//! [`SlowOperationThreshold`] are reported as slow.
+
+use std::time::Duration;
+
+use super::FlashProgress;
+use crate::{HealthEvent, HealthLog};
+
+/// The number of earlier operations of the same kind needed to compare an operation to
+/// their median.
+const MIN_PEERS: usize = 3;
+
+/// A kind of flash operation which is timed individually.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashOperation {
+    /// The erase of a single sector.
+    SectorErase,
+    /// The programming of a single page.
+    PageProgram,
+}
+
+/// The limits above which a flash operation is reported as slow, see
+/// [`DownloadOptions::slow_operation_threshold`](super::DownloadOptions::slow_operation_threshold).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowOperationThreshold {
+    /// An operation is slow if it takes longer than `median_factor` times the median of the
+    /// earlier operations of the same kind.
+    ///
+    /// This is only checked once at least three earlier operations were timed.
+    pub median_factor: f64,
+    /// An operation is slow if it takes longer than this fraction of the timeout the flash
+    /// algorithm declares for it.
+    pub timeout_fraction: Option<f64>,
+}
+
+impl Default for SlowOperationThreshold {
+    fn default() -> Self {
+        Self {
+            median_factor: 4.0,
+            timeout_fraction: Some(0.5),
+        }
+    }
+}
+
+/// The durations of all operations of one kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationTimings {
+    /// The number of operations.
+    pub count: usize,
+    /// The duration of the fastest operation.
+    pub min: Duration,
+    /// The median duration.
+    pub median: Duration,
+    /// The duration of the slowest operation.
+    pub max: Duration,
+}
+
+impl OperationTimings {
+    fn from_durations(durations: &[Duration]) -> Option<Self> {
+        let mut sorted = durations.to_vec();
+        sorted.sort_unstable();
+
+        Some(Self {
+            count: sorted.len(),
+            min: *sorted.first()?,
+            median: median(&sorted)?,
+            max: *sorted.last()?,
+        })
+    }
+}
+
+/// The durations of the operations of a download, by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlashTimings {
+    /// The durations of the sector erases, if any sector was erased.
+    pub sector_erase: Option<OperationTimings>,
+    /// The durations of the page programming, if any page was programmed.
+    pub page_program: Option<OperationTimings>,
+}
+
+/// Returns the median of the `sorted` durations, or the lower one of the two middle values.
+fn median(sorted: &[Duration]) -> Option<Duration> {
+    if sorted.is_empty() {
+        None
+    } else {
+        Some(sorted[(sorted.len() - 1) / 2])
+    }
+}
+
+/// Collects the durations of the operations of a download, and reports slow ones.
+#[derive(Debug)]
+pub(super) struct TimingMonitor {
+    threshold: Option<SlowOperationThreshold>,
+    health_log: HealthLog,
+    core_index: usize,
+    sector_erase: Vec<Duration>,
+    page_program: Vec<Duration>,
+}
+
+impl TimingMonitor {
+    pub(super) fn new(health_log: HealthLog, core_index: usize) -> Self {
+        Self {
+            threshold: None,
+            health_log,
+            core_index,
+            sector_erase: Vec::new(),
+            page_program: Vec::new(),
+        }
+    }
+
+    pub(super) fn set_threshold(&mut self, threshold: Option<SlowOperationThreshold>) {
+        self.threshold = threshold;
+    }
+
+    fn durations(&self, operation: FlashOperation) -> &[Duration] {
+        match operation {
+            FlashOperation::SectorErase => &self.sector_erase,
+            FlashOperation::PageProgram => &self.page_program,
+        }
+    }
+
+    /// Record an `operation` at `address` which took `time`.
+    ///
+    /// If it exceeds the threshold, it is reported as a [`ProgressEvent::SlowOperation`](super::ProgressEvent::SlowOperation)
+    /// and in the health log.
+    pub(super) fn record(
+        &mut self,
+        progress: &FlashProgress,
+        operation: FlashOperation,
+        address: u64,
+        time: Duration,
+        timeout: Duration,
+    ) {
+        let reference = self
+            .threshold
+            .and_then(|threshold| self.slow_reference(threshold, operation, time, timeout));
+
+        match operation {
+            FlashOperation::SectorErase => self.sector_erase.push(time),
+            FlashOperation::PageProgram => self.page_program.push(time),
+        }
+
+        if let Some(reference) = reference {
+            let details = format!(
+                "{:?} at {:#010x} took {:?}, more than the expected {:?}",
+                operation, address, time, reference
+            );
+
+            log::warn!("{}", details);
+            self.health_log.record(
+                HealthEvent::SlowFlashOperation,
+                Some(self.core_index),
+                "flash",
+                details,
+            );
+
+            progress.slow_operation(operation, address, time, reference);
+        }
+    }
+
+    /// Returns the limit `time` exceeds, if the operation is slow.
+    fn slow_reference(
+        &self,
+        threshold: SlowOperationThreshold,
+        operation: FlashOperation,
+        time: Duration,
+        timeout: Duration,
+    ) -> Option<Duration> {
+        let peers = self.durations(operation);
+
+        if peers.len() >= MIN_PEERS {
+            let mut sorted = peers.to_vec();
+            sorted.sort_unstable();
+
+            let limit = median(&sorted)?.mul_f64(threshold.median_factor);
+            if time > limit {
+                return Some(limit);
+            }
+        }
+
+        threshold
+            .timeout_fraction
+            .map(|fraction| timeout.mul_f64(fraction))
+            .filter(|&limit| time > limit)
+    }
+
+    /// Add the durations recorded by `other`.
+    pub(super) fn extend(&mut self, other: &TimingMonitor) {
+        self.sector_erase.extend_from_slice(&other.sector_erase);
+        self.page_program.extend_from_slice(&other.page_program);
+    }
+
+    /// Returns the statistics of all recorded durations.
+    pub(super) fn summary(&self) -> FlashTimings {
+        FlashTimings {
+            sector_erase: OperationTimings::from_durations(&self.sector_erase),
+            page_program: OperationTimings::from_durations(&self.page_program),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::flashing::ProgressEvent;
+
+    #[test]
+    fn operations_slower_than_the_median_are_reported() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let progress = {
+            let events = events.clone();
+            FlashProgress::new(move |event| events.borrow_mut().push(event))
+        };
+
+        let health_log = HealthLog::new(8);
+        let mut monitor = TimingMonitor::new(health_log.clone(), 0);
+        monitor.set_threshold(Some(SlowOperationThreshold {
+            median_factor: 3.0,
+            timeout_fraction: None,
+        }));
+
+        let timeout = Duration::from_secs(1);
+        for (index, millis) in [10, 12, 11, 40, 25].into_iter().enumerate() {
+            monitor.record(
+                &progress,
+                FlashOperation::SectorErase,
+                0x1000 * index as u64,
+                Duration::from_millis(millis),
+                timeout,
+            );
+        }
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            ProgressEvent::SlowOperation {
+                operation: FlashOperation::SectorErase,
+                address: 0x3000,
+                ..
+            }
+        ));
+        assert_eq!(health_log.entries().len(), 1);
+
+        assert_eq!(
+            monitor.summary(),
+            FlashTimings {
+                sector_erase: Some(OperationTimings {
+                    count: 5,
+                    min: Duration::from_millis(10),
+                    median: Duration::from_millis(12),
+                    max: Duration::from_millis(40),
+                }),
+                page_program: None,
+            }
+        );
+    }
+}
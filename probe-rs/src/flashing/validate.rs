@@ -0,0 +1,539 @@
+//! Checks if an image is likely to boot on a target.
+//!
+//! Flashing an image which was linked for the wrong target or memory layout
+//! usually succeeds, and the mistake is only noticed when the target does not boot.
+//! [`FlashLoader::validate`] checks the staged data against the memory map and the
+//! reset behaviour of the cores, and reports everything which looks suspicious.
+
+use std::{fmt, ops::Range};
+
+use probe_rs_target::{CoreAccessOptions, CoreType, MemoryRegion};
+
+use super::FlashLoader;
+use crate::{AddressMap, Target};
+
+/// How severe an [`ImageIssue`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IssueSeverity {
+    /// The image might still boot, e.g. because it is started by a bootloader.
+    Warning,
+    /// The image will most likely not boot.
+    Error,
+}
+
+/// The kind of problem found in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ImageIssueKind {
+    /// The image contains no data in any boot memory region of the target.
+    NoDataInBootMemory,
+    /// The image contains data in the boot memory, but not at its start,
+    /// where the core expects the vector table.
+    NoVectorTable {
+        /// The address at which the vector table was expected.
+        address: u64,
+    },
+    /// The initial stack pointer in the vector table does not point into RAM.
+    StackPointerNotInRam {
+        /// The initial stack pointer.
+        stack_pointer: u64,
+    },
+    /// The reset handler in the vector table is not a Thumb address.
+    ResetHandlerNotThumb {
+        /// The address of the reset handler.
+        reset_handler: u64,
+    },
+    /// The reset handler in the vector table does not point into the image.
+    ResetHandlerOutsideImage {
+        /// The address of the reset handler.
+        reset_handler: u64,
+    },
+    /// The entry point of the image is not the reset handler from the vector table.
+    EntryPointNotResetHandler {
+        /// The entry point of the image.
+        entry_point: u64,
+        /// The address of the reset handler.
+        reset_handler: u64,
+    },
+    /// The entry point of the image is not one of the reset vectors of the core.
+    EntryPointNotResetVector {
+        /// The entry point of the image.
+        entry_point: u64,
+        /// The reset vectors declared in the target description.
+        reset_vectors: Vec<u64>,
+    },
+    /// The entry point of the image is not in a boot memory region.
+    EntryPointNotInBootMemory {
+        /// The entry point of the image.
+        entry_point: u64,
+    },
+    /// The image contains data in a boot-critical range of an NVM region.
+    DataInBootCriticalRange {
+        /// The boot-critical range.
+        range: Range<u64>,
+    },
+}
+
+impl fmt::Display for ImageIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageIssueKind::NoDataInBootMemory => {
+                write!(f, "The image contains no data in the boot memory")
+            }
+            ImageIssueKind::NoVectorTable { address } => write!(
+                f,
+                "The image contains no vector table at the boot address {:#010x}",
+                address
+            ),
+            ImageIssueKind::StackPointerNotInRam { stack_pointer } => write!(
+                f,
+                "The initial stack pointer {:#010x} does not point into RAM",
+                stack_pointer
+            ),
+            ImageIssueKind::ResetHandlerNotThumb { reset_handler } => write!(
+                f,
+                "The reset handler {:#010x} is not a Thumb address",
+                reset_handler
+            ),
+            ImageIssueKind::ResetHandlerOutsideImage { reset_handler } => write!(
+                f,
+                "The reset handler {:#010x} does not point into the image",
+                reset_handler
+            ),
+            ImageIssueKind::EntryPointNotResetHandler {
+                entry_point,
+                reset_handler,
+            } => write!(
+                f,
+                "The entry point {:#010x} is not the reset handler {:#010x}",
+                entry_point, reset_handler
+            ),
+            ImageIssueKind::EntryPointNotResetVector {
+                entry_point,
+                reset_vectors,
+            } => write!(
+                f,
+                "The entry point {:#010x} is not a reset vector of the core ({:#010x?})",
+                entry_point, reset_vectors
+            ),
+            ImageIssueKind::EntryPointNotInBootMemory { entry_point } => write!(
+                f,
+                "The entry point {:#010x} is not in the boot memory",
+                entry_point
+            ),
+            ImageIssueKind::DataInBootCriticalRange { range } => write!(
+                f,
+                "The image contains data in the boot-critical range {:#010x?}",
+                range
+            ),
+        }
+    }
+}
+
+/// A problem which might prevent an image from booting, found by [`FlashLoader::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageIssue {
+    /// How severe the problem is.
+    pub severity: IssueSeverity,
+    /// The kind of problem.
+    pub kind: ImageIssueKind,
+}
+
+impl ImageIssue {
+    fn warning(kind: ImageIssueKind) -> Self {
+        Self {
+            severity: IssueSeverity::Warning,
+            kind,
+        }
+    }
+
+    fn error(kind: ImageIssueKind) -> Self {
+        Self {
+            severity: IssueSeverity::Error,
+            kind,
+        }
+    }
+
+    /// Returns true if the issue will most likely prevent the image from booting.
+    pub fn is_error(&self) -> bool {
+        self.severity == IssueSeverity::Error
+    }
+}
+
+impl fmt::Display for ImageIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            IssueSeverity::Warning => "warning",
+            IssueSeverity::Error => "error",
+        };
+
+        write!(f, "{}: {}", severity, self.kind)
+    }
+}
+
+impl FlashLoader {
+    /// Check if the staged data is likely to boot on `target`.
+    ///
+    /// On Cortex-M cores, the vector table is expected at the start of a boot memory region,
+    /// with the initial stack pointer in RAM and the reset handler inside the image.
+    /// On RISC-V cores, the entry point of the image has to be one of the reset vectors
+    /// from the target description, or lie in a boot memory region if none are declared.
+    /// For all cores, data in boot-critical ranges is reported.
+    pub fn validate(&self, target: &Target) -> Vec<ImageIssue> {
+        self.validate_with_address_map(target, &AddressMap::default())
+    }
+
+    /// Check if the staged data is likely to boot on `target`, if it is executed at the
+    /// load addresses of `address_map`.
+    ///
+    /// The staged data is at link addresses, and the addresses of the target, like the boot
+    /// memory and the reset vectors, are load addresses. See [`FlashLoader::validate`] for
+    /// the performed checks.
+    pub fn validate_with_address_map(
+        &self,
+        target: &Target,
+        address_map: &AddressMap,
+    ) -> Vec<ImageIssue> {
+        let mut issues = Vec::new();
+
+        let boot_memory = target
+            .memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Nvm(region) if region.is_boot_memory => Some(region.range.clone()),
+                MemoryRegion::Ram(region) if region.is_boot_memory => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if target.cores.iter().any(|core| core.core_type.is_cortex_m()) {
+            self.validate_vector_table(target, address_map, &boot_memory, &mut issues);
+        }
+
+        for core in &target.cores {
+            if let (CoreType::Riscv, CoreAccessOptions::Riscv(options)) =
+                (&core.core_type, &core.core_access_options)
+            {
+                let entry_point = match self.entry_point() {
+                    Some(entry_point) => address_map.to_load_address(entry_point),
+                    None => continue,
+                };
+
+                if !options.reset_vectors.is_empty() {
+                    if !options.reset_vectors.contains(&entry_point) {
+                        issues.push(ImageIssue::error(
+                            ImageIssueKind::EntryPointNotResetVector {
+                                entry_point,
+                                reset_vectors: options.reset_vectors.clone(),
+                            },
+                        ));
+                    }
+                } else if !boot_memory.iter().any(|range| range.contains(&entry_point)) {
+                    issues.push(ImageIssue::warning(
+                        ImageIssueKind::EntryPointNotInBootMemory { entry_point },
+                    ));
+                }
+            }
+        }
+
+        for region in &target.memory_map {
+            if let MemoryRegion::Nvm(region) = region {
+                for range in &region.boot_critical_ranges {
+                    if self
+                        .builder
+                        .has_data_in_range(&address_map.to_link_range(range))
+                    {
+                        issues.push(ImageIssue::warning(
+                            ImageIssueKind::DataInBootCriticalRange {
+                                range: range.clone(),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+
+        issues
+    }
+
+    fn validate_vector_table(
+        &self,
+        target: &Target,
+        address_map: &AddressMap,
+        boot_memory: &[Range<u64>],
+        issues: &mut Vec<ImageIssue>,
+    ) {
+        // The boot memory as link addresses, in which the staged data is.
+        let linked_boot_memory: Vec<_> = boot_memory
+            .iter()
+            .map(|range| address_map.to_link_range(range))
+            .collect();
+
+        if !linked_boot_memory
+            .iter()
+            .any(|range| self.builder.has_data_in_range(range))
+        {
+            issues.push(ImageIssue::error(ImageIssueKind::NoDataInBootMemory));
+            return;
+        }
+
+        // The first two entries of the vector table are the initial stack pointer and the reset handler.
+        let vector_table = linked_boot_memory.iter().find_map(|range| {
+            Some((
+                self.read_word(range.start)?,
+                self.read_word(range.start + 4)?,
+            ))
+        });
+
+        let (stack_pointer, reset_handler) = match vector_table {
+            Some((stack_pointer, reset_handler)) => (stack_pointer as u64, reset_handler as u64),
+            None => {
+                // The image might be an application which is started by a bootloader.
+                for (range, linked) in boot_memory.iter().zip(&linked_boot_memory) {
+                    if self.builder.has_data_in_range(linked) {
+                        issues.push(ImageIssue::warning(ImageIssueKind::NoVectorTable {
+                            address: range.start,
+                        }));
+                    }
+                }
+                return;
+            }
+        };
+
+        // The stack grows downwards, so the initial stack pointer may point to the end of a RAM region.
+        let in_ram = target.memory_map.iter().any(|region| match region {
+            MemoryRegion::Ram(region) => {
+                region.range.start < stack_pointer && stack_pointer <= region.range.end
+            }
+            _ => false,
+        });
+
+        if !in_ram {
+            issues.push(ImageIssue::error(ImageIssueKind::StackPointerNotInRam {
+                stack_pointer,
+            }));
+        }
+
+        if reset_handler & 1 == 0 {
+            issues.push(ImageIssue::error(ImageIssueKind::ResetHandlerNotThumb {
+                reset_handler,
+            }));
+        }
+
+        let handler_address = reset_handler & !1;
+
+        if !self
+            .builder
+            .has_data_in_range(&(handler_address..handler_address + 2))
+        {
+            issues.push(ImageIssue::error(
+                ImageIssueKind::ResetHandlerOutsideImage { reset_handler },
+            ));
+        }
+
+        if let Some(entry_point) = self.entry_point() {
+            if entry_point & !1 != handler_address {
+                issues.push(ImageIssue::warning(
+                    ImageIssueKind::EntryPointNotResetHandler {
+                        entry_point,
+                        reset_handler,
+                    },
+                ));
+            }
+        }
+    }
+
+    /// Read a little endian word from the staged data, if all four bytes are present.
+    fn read_word(&self, address: u64) -> Option<u32> {
+        let mut bytes = Vec::with_capacity(4);
+
+        for (_, data) in self.builder.data_in_range(&(address..address + 4)) {
+            bytes.extend_from_slice(data);
+        }
+
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use probe_rs_target::{
+        Core, CoreAccessOptions, NvmRegion, RamRegion, ResetScope, RiscvCoreAccessOptions,
+        TargetDescriptionSource,
+    };
+
+    use super::*;
+    use crate::architecture::arm::sequences::DefaultArmSequence;
+    use crate::architecture::route::DefaultRouteSequence;
+    use crate::config::DebugSequence;
+
+    fn target(core_type: CoreType, core_access_options: CoreAccessOptions) -> Target {
+        let memory_map = vec![
+            MemoryRegion::Nvm(NvmRegion {
+                name: None,
+                range: 0x0800_0000..0x0801_0000,
+                is_boot_memory: true,
+                cores: vec!["main".into()],
+                boot_critical_ranges: vec![0x0800_f000..0x0801_0000],
+                target_writable: false,
+            }),
+            MemoryRegion::Ram(RamRegion {
+                name: None,
+                range: 0x2000_0000..0x2000_4000,
+                is_boot_memory: false,
+                cores: vec!["main".into()],
+                volatile: false,
+                ecc: false,
+            }),
+        ];
+
+        Target {
+            name: "test".into(),
+            cores: vec![Core {
+                name: "main".into(),
+                core_type,
+                core_access_options,
+                reset_scope: ResetScope::default(),
+                hardware_breakpoints: None,
+                watchpoints: None,
+            }],
+            flash_algorithms: vec![],
+            memory_map,
+            source: TargetDescriptionSource::BuiltIn,
+            overridden_by: None,
+            debug_sequence: DebugSequence::Arm(DefaultArmSequence::create()),
+            secondary_debug_sequence: None,
+            route_sequence: DefaultRouteSequence::create(),
+            keepalive: None,
+            errata: vec![],
+            mediated_regions: vec![],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
+        }
+    }
+
+    fn vector_table(stack_pointer: u32, reset_handler: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&stack_pointer.to_le_bytes());
+        data.extend_from_slice(&reset_handler.to_le_bytes());
+        data.extend_from_slice(&[0; 0x100]);
+        data
+    }
+
+    #[test]
+    fn valid_cortex_m_image_has_no_issues() {
+        let target = target(
+            CoreType::Armv7em,
+            CoreAccessOptions::Arm(Default::default()),
+        );
+
+        let mut loader = target.flash_loader();
+        loader
+            .add_data(0x0800_0000, &vector_table(0x2000_4000, 0x0800_0101))
+            .unwrap();
+
+        assert_eq!(loader.validate(&target), vec![]);
+    }
+
+    #[test]
+    fn image_linked_for_ram_is_rejected() {
+        let target = target(
+            CoreType::Armv7em,
+            CoreAccessOptions::Arm(Default::default()),
+        );
+
+        let mut loader = target.flash_loader();
+        loader
+            .add_data(0x2000_0000, &vector_table(0x2000_4000, 0x2000_0101))
+            .unwrap();
+
+        let issues = loader.validate(&target);
+
+        assert_eq!(
+            issues,
+            vec![ImageIssue::error(ImageIssueKind::NoDataInBootMemory)]
+        );
+    }
+
+    #[test]
+    fn relocated_image_is_validated_at_its_load_addresses() {
+        let target = target(
+            CoreType::Armv7em,
+            CoreAccessOptions::Arm(Default::default()),
+        );
+
+        let mut loader = target.flash_loader();
+        loader
+            .add_data(0x0001_0000, &vector_table(0x2000_4000, 0x0001_0101))
+            .unwrap();
+
+        let mut address_map = AddressMap::default();
+        address_map
+            .insert(0x0001_0000..0x0002_0000, 0x0800_0000)
+            .unwrap();
+
+        assert_eq!(
+            loader.validate(&target),
+            vec![ImageIssue::error(ImageIssueKind::NoDataInBootMemory)]
+        );
+        assert_eq!(
+            loader.validate_with_address_map(&target, &address_map),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn invalid_vector_table_is_reported() {
+        let target = target(CoreType::Armv6m, CoreAccessOptions::Arm(Default::default()));
+
+        let mut loader = target.flash_loader();
+        loader
+            .add_data(0x0800_0000, &vector_table(0x0800_1000, 0x0900_0000))
+            .unwrap();
+        loader.add_data(0x0800_f000, &[0; 4]).unwrap();
+
+        let kinds = loader
+            .validate(&target)
+            .into_iter()
+            .map(|issue| issue.kind)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            kinds,
+            vec![
+                ImageIssueKind::StackPointerNotInRam {
+                    stack_pointer: 0x0800_1000
+                },
+                ImageIssueKind::ResetHandlerNotThumb {
+                    reset_handler: 0x0900_0000
+                },
+                ImageIssueKind::ResetHandlerOutsideImage {
+                    reset_handler: 0x0900_0000
+                },
+                ImageIssueKind::DataInBootCriticalRange {
+                    range: 0x0800_f000..0x0801_0000
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn riscv_entry_point_is_checked_against_reset_vectors() {
+        let target = target(
+            CoreType::Riscv,
+            CoreAccessOptions::Riscv(RiscvCoreAccessOptions {
+                reset_vectors: vec![0x0800_0000, 0x0800_8000],
+                ..Default::default()
+            }),
+        );
+
+        let mut loader = target.flash_loader();
+        loader.add_data(0x0800_0000, &[0; 0x100]).unwrap();
+
+        loader.set_entry_point(Some(0x0800_8000));
+        assert_eq!(loader.validate(&target), vec![]);
+
+        loader.set_entry_point(Some(0x0800_0004));
+        assert!(loader.validate(&target)[0].is_error());
+    }
+}
@@ -0,0 +1,272 @@
+//! Stopping peripherals, most importantly watchdogs, while the cores are halted.
+//!
+//! Many chips have debug freeze registers, e.g. `DBGMCU_APB1_FZ` on STM32 chips, with a bit
+//! per peripheral which stops it while the cores are halted. Without it, a watchdog which
+//! was started by the firmware resets the chip while it sits at a breakpoint. The target
+//! descriptions list these bits, and [`Session::freeze_peripherals_on_halt`] selects which
+//! peripherals are stopped. The selection is written to the target immediately, and again
+//! after every reset of a core.
+//!
+//! [`Session::freeze_peripherals_on_halt`]: crate::Session::freeze_peripherals_on_halt
+
+use std::collections::BTreeMap;
+
+use crate::config::PeripheralFreeze;
+use crate::{Error, MemoryInterface};
+
+/// The peripherals which are stopped while the cores are halted, see
+/// [`Session::freeze_peripherals_on_halt`](crate::Session::freeze_peripherals_on_halt).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FreezeSelection {
+    /// No peripheral is stopped.
+    None,
+    /// All watchdogs are stopped.
+    Watchdogs,
+    /// All peripherals with a freeze bit are stopped.
+    All,
+    /// The peripherals with the given names are stopped, e.g. `IWDG` and `TIM2`.
+    Peripherals(Vec<String>),
+}
+
+impl FreezeSelection {
+    /// Whether `freeze` is selected.
+    fn selects(&self, freeze: &PeripheralFreeze) -> bool {
+        match self {
+            FreezeSelection::None => false,
+            FreezeSelection::Watchdogs => freeze.watchdog,
+            FreezeSelection::All => true,
+            FreezeSelection::Peripherals(names) => names.contains(&freeze.peripheral),
+        }
+    }
+}
+
+/// Whether a peripheral is stopped while the cores are halted, as read from the target,
+/// see [`Session::frozen_peripherals`](crate::Session::frozen_peripherals).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrozenPeripheral {
+    /// The name of the peripheral.
+    pub peripheral: String,
+    /// Whether the peripheral is a watchdog.
+    pub watchdog: bool,
+    /// Whether the peripheral is stopped while the cores are halted.
+    pub frozen: bool,
+}
+
+/// The freeze bits of a target, with the selected peripherals.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeripheralFreezes {
+    definitions: Vec<PeripheralFreeze>,
+    /// The selected peripherals, or `None` if the freeze registers are left as they are.
+    selection: Option<FreezeSelection>,
+}
+
+impl PeripheralFreezes {
+    pub(crate) fn new(definitions: &[PeripheralFreeze]) -> Self {
+        Self {
+            definitions: definitions.to_vec(),
+            selection: None,
+        }
+    }
+
+    /// Select the peripherals which are stopped. This doesn't write to the target.
+    ///
+    /// Fails with [`Error::UnknownPeripheralFreeze`] if a peripheral of the selection has
+    /// no freeze bit.
+    pub(crate) fn select(&mut self, selection: FreezeSelection) -> Result<(), Error> {
+        if let FreezeSelection::Peripherals(names) = &selection {
+            if let Some(unknown) = names.iter().find(|name| {
+                !self
+                    .definitions
+                    .iter()
+                    .any(|freeze| &freeze.peripheral == *name)
+            }) {
+                return Err(Error::UnknownPeripheralFreeze(unknown.clone()));
+            }
+        }
+
+        if self
+            .definitions
+            .iter()
+            .all(|freeze| !selection.selects(freeze))
+            && selection != FreezeSelection::None
+        {
+            log::warn!(
+                "The target has no freeze bits for the peripherals of {:?}, they keep running \
+                 while the cores are halted.",
+                selection
+            );
+        }
+
+        self.selection = Some(selection);
+
+        Ok(())
+    }
+
+    /// The selected peripherals, if any were selected.
+    pub(crate) fn selection(&self) -> Option<&FreezeSelection> {
+        self.selection.as_ref()
+    }
+
+    /// Whether the target has a freeze bit for any of its watchdogs.
+    pub(crate) fn can_freeze_watchdogs(&self) -> bool {
+        self.definitions.iter().any(|freeze| freeze.watchdog)
+    }
+
+    /// Whether any watchdog is selected.
+    pub(crate) fn watchdogs_frozen(&self) -> bool {
+        match &self.selection {
+            Some(selection) => self
+                .definitions
+                .iter()
+                .any(|freeze| freeze.watchdog && selection.selects(freeze)),
+            None => false,
+        }
+    }
+
+    /// Write the selection to the freeze registers of the target. Nothing is written if no
+    /// selection was made.
+    ///
+    /// The bits of the peripherals which are not selected are returned to their running
+    /// state, and all other bits of the registers are preserved.
+    pub(crate) fn apply(&self, memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        let selection = match &self.selection {
+            Some(selection) => selection,
+            None => return Ok(()),
+        };
+
+        // The bits which are set and cleared, by register.
+        let mut registers = BTreeMap::<u64, (u32, u32)>::new();
+
+        for freeze in &self.definitions {
+            let (set, clear) = registers.entry(freeze.register).or_default();
+
+            if selection.selects(freeze) != freeze.active_low {
+                *set |= 1 << freeze.bit;
+            } else {
+                *clear |= 1 << freeze.bit;
+            }
+        }
+
+        for (register, (set, clear)) in registers {
+            let value = memory.read_word_32(register)?;
+            let frozen = value & !clear | set;
+
+            if frozen != value {
+                memory.write_word_32(register, frozen)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read which peripherals are stopped while the cores are halted from the target.
+    pub(crate) fn read(
+        &self,
+        memory: &mut dyn MemoryInterface,
+    ) -> Result<Vec<FrozenPeripheral>, Error> {
+        let mut registers = BTreeMap::new();
+
+        for freeze in &self.definitions {
+            if !registers.contains_key(&freeze.register) {
+                registers.insert(freeze.register, memory.read_word_32(freeze.register)?);
+            }
+        }
+
+        Ok(self
+            .definitions
+            .iter()
+            .map(|freeze| FrozenPeripheral {
+                peripheral: freeze.peripheral.clone(),
+                watchdog: freeze.watchdog,
+                frozen: (registers[&freeze.register] >> freeze.bit & 1 == 1) != freeze.active_low,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::mock::MockMemory;
+
+    const FZ: u64 = 0xE004_2008;
+    const WDT_CONFIG: u64 = 0x4001_050C;
+
+    fn freeze(peripheral: &str, watchdog: bool, register: u64, bit: u8) -> PeripheralFreeze {
+        PeripheralFreeze {
+            peripheral: peripheral.to_owned(),
+            watchdog,
+            register,
+            bit,
+            active_low: false,
+        }
+    }
+
+    fn freezes() -> PeripheralFreezes {
+        PeripheralFreezes::new(&[
+            freeze("TIM2", false, FZ, 0),
+            freeze("WWDG", true, FZ, 11),
+            freeze("IWDG", true, FZ, 12),
+            PeripheralFreeze {
+                active_low: true,
+                ..freeze("WDT", true, WDT_CONFIG, 3)
+            },
+        ])
+    }
+
+    #[test]
+    fn selected_peripherals_are_frozen() {
+        let mut memory = MockMemory::default();
+        memory.write_word_32(FZ, 1 << 20).unwrap();
+        memory.write_word_32(WDT_CONFIG, 0b1001).unwrap();
+
+        let mut freezes = freezes();
+        freezes.select(FreezeSelection::Watchdogs).unwrap();
+        freezes.apply(&mut memory).unwrap();
+
+        // Other bits of the registers are preserved.
+        assert_eq!(
+            memory.read_word_32(FZ).unwrap(),
+            1 << 20 | 1 << 12 | 1 << 11
+        );
+        assert_eq!(memory.read_word_32(WDT_CONFIG).unwrap(), 0b0001);
+
+        let frozen: Vec<_> = freezes
+            .read(&mut memory)
+            .unwrap()
+            .into_iter()
+            .filter(|peripheral| peripheral.frozen)
+            .map(|peripheral| peripheral.peripheral)
+            .collect();
+        assert_eq!(frozen, ["WWDG", "IWDG", "WDT"]);
+
+        freezes
+            .select(FreezeSelection::Peripherals(vec!["TIM2".to_owned()]))
+            .unwrap();
+        freezes.apply(&mut memory).unwrap();
+
+        assert_eq!(memory.read_word_32(FZ).unwrap(), 1 << 20 | 1);
+        assert_eq!(memory.read_word_32(WDT_CONFIG).unwrap(), 0b1001);
+        assert!(!freezes.watchdogs_frozen());
+    }
+
+    #[test]
+    fn unknown_peripherals_are_rejected() {
+        let mut freezes = freezes();
+
+        assert!(matches!(
+            freezes.select(FreezeSelection::Peripherals(vec!["RTC".to_owned()])),
+            Err(Error::UnknownPeripheralFreeze(name)) if name == "RTC"
+        ));
+        assert_eq!(freezes.selection(), None);
+    }
+
+    #[test]
+    fn nothing_is_written_without_a_selection() {
+        let mut memory = MockMemory::default();
+
+        freezes().apply(&mut memory).unwrap();
+
+        assert_eq!(memory.read_word_32(FZ).unwrap(), 0);
+    }
+}
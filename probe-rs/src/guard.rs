@@ -0,0 +1,162 @@
+//! Verifying the firmware of a target before it is debugged, see
+//! [`Session::attach_guarded`](crate::Session::attach_guarded).
+//!
+//! When attaching to units in the field, a unit which runs another firmware than the expected
+//! one must not be disturbed. An [`AttachGuard`] lists fingerprints of the expected firmware,
+//! e.g. of its version string or build ID. Until all of them match, the session only allows
+//! non-intrusive reads of the memory, and all other accesses fail with
+//! [`Error::MissingPermissions`].
+
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Session};
+
+/// The expected SHA-256 hash of a region of memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// The start address of the region.
+    pub address: u64,
+    /// The length of the region in bytes.
+    pub length: usize,
+    /// The expected SHA-256 hash of the region.
+    pub sha256: [u8; 32],
+}
+
+impl Fingerprint {
+    /// Expect the `length` bytes at `address` to have the SHA-256 hash `sha256`.
+    pub fn new(address: u64, length: usize, sha256: [u8; 32]) -> Self {
+        Self {
+            address,
+            length,
+            sha256,
+        }
+    }
+
+    /// Expect the memory at `address` to contain `expected`, e.g. a version string.
+    pub fn of_bytes(address: u64, expected: &[u8]) -> Self {
+        Self::new(address, expected.len(), sha256(expected))
+    }
+}
+
+/// A fingerprint which didn't match the memory of the target, see
+/// [`Error::FirmwareMismatch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintMismatch {
+    /// The fingerprint which didn't match.
+    pub fingerprint: Fingerprint,
+    /// The SHA-256 hash of the memory.
+    pub observed_sha256: [u8; 32],
+    /// The memory of the region, e.g. to report the version of the firmware which runs on the
+    /// target.
+    pub observed: Vec<u8>,
+}
+
+/// What a guarded session may do before the fingerprints are verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GuardPolicy {
+    /// Only non-intrusive reads of the memory are allowed. The cores are not halted, and the
+    /// setup of the session which writes to the target, e.g. clearing breakpoints left over
+    /// from an earlier session, is deferred until the fingerprints are verified.
+    #[default]
+    NonIntrusiveReads,
+    /// The session is set up like with [`Probe::attach`](crate::Probe::attach), and only
+    /// the API of the session is locked until the fingerprints are verified.
+    Attach,
+}
+
+/// The fingerprints of the firmware a session may debug, see
+/// [`Session::attach_guarded`](crate::Session::attach_guarded).
+///
+/// # Example
+///
+/// ```
+/// use probe_rs::AttachGuard;
+///
+/// // Only debug units which run version 2.3.0 of the firmware.
+/// let guard = AttachGuard::new().fingerprint_bytes(0x0800_0200, b"2.3.0\0");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AttachGuard {
+    fingerprints: Vec<Fingerprint>,
+    core: usize,
+    policy: GuardPolicy,
+}
+
+impl AttachGuard {
+    /// Constructs a new guard without fingerprints, which reads through core 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect the memory of the target to match `fingerprint`.
+    #[must_use]
+    pub fn fingerprint(mut self, fingerprint: Fingerprint) -> Self {
+        self.fingerprints.push(fingerprint);
+        self
+    }
+
+    /// Expect the memory at `address` to contain `expected`, see [`Fingerprint::of_bytes`].
+    #[must_use]
+    pub fn fingerprint_bytes(self, address: u64, expected: &[u8]) -> Self {
+        self.fingerprint(Fingerprint::of_bytes(address, expected))
+    }
+
+    /// Read the fingerprints through the memory access port of the core with the index
+    /// `core_index`, instead of core 0.
+    #[must_use]
+    pub fn core(self, core_index: usize) -> Self {
+        Self {
+            core: core_index,
+            ..self
+        }
+    }
+
+    /// Set what the session may do before the fingerprints are verified.
+    #[must_use]
+    pub fn policy(self, policy: GuardPolicy) -> Self {
+        Self { policy, ..self }
+    }
+
+    /// Returns the fingerprints of the guard.
+    pub fn fingerprints(&self) -> &[Fingerprint] {
+        &self.fingerprints
+    }
+
+    pub(crate) fn guard_policy(&self) -> GuardPolicy {
+        self.policy
+    }
+
+    /// Read the fingerprinted regions of `session`, and return the fingerprints which don't match.
+    pub(crate) fn verify(&self, session: &mut Session) -> Result<Vec<FingerprintMismatch>, Error> {
+        let mut mismatches = Vec::new();
+
+        for fingerprint in &self.fingerprints {
+            let mut observed = vec![0; fingerprint.length];
+            session.read_non_intrusive(self.core, fingerprint.address, &mut observed)?;
+
+            let observed_sha256 = sha256(&observed);
+
+            if observed_sha256 == fingerprint.sha256 {
+                log::debug!("Fingerprint at {:#010x} matches", fingerprint.address);
+            } else {
+                log::warn!(
+                    "Fingerprint at {:#010x} doesn't match, the memory contains {:02x?}",
+                    fingerprint.address,
+                    observed
+                );
+
+                mismatches.push(FingerprintMismatch {
+                    fingerprint: fingerprint.clone(),
+                    observed_sha256,
+                    observed,
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
@@ -0,0 +1,257 @@
+//! A log of anomalies which were recovered from during a session.
+//!
+//! Many errors, e.g. a WAIT response from the target or a sticky error flag, are
+//! handled transparently by probe-rs. When an error eventually escapes to the user,
+//! these earlier anomalies are often the actual cause. The [`HealthLog`] keeps the
+//! most recent of them, so that they can be inspected after the fact.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// The default number of entries kept in the health log.
+pub const DEFAULT_HEALTH_LOG_CAPACITY: usize = 100;
+
+/// The number of entries attached to an error which escapes to the user.
+pub(crate) const ATTACHED_ENTRIES: usize = 20;
+
+/// The kind of anomaly which was recovered from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum HealthEvent {
+    /// An operation succeeded after it had to be retried, e.g. because of a WAIT response.
+    RetrySucceeded,
+    /// A memory access still failed after all attempts of its
+    /// [`RetryPolicy`](crate::RetryPolicy) were used up.
+    RetriesExhausted,
+    /// A sticky error flag was set, and was cleared.
+    StickyErrorCleared,
+    /// The communication with the target was out of sync, and had to be resynchronized,
+    /// e.g. with a line reset or by re-issuing a batch of commands.
+    Resynchronized,
+    /// A core was temporarily not available.
+    CoreUnavailable,
+    /// A reset of the target was detected, which was not issued by probe-rs.
+    UnexpectedReset,
+    /// The protocol speed was reduced while attaching, because the link to the target
+    /// failed at a higher speed.
+    SpeedReduced,
+    /// Attaching only succeeded at a low speed first, which indicates a target whose debug
+    /// logic runs on a slow clock after a reset, see
+    /// [`AttachOptions::slow_clock_attach`](crate::AttachOptions::slow_clock_attach).
+    SlowClockDetected,
+    /// A flash operation took much longer than expected, which can indicate ageing or
+    /// marginal flash.
+    SlowFlashOperation,
+    /// A step of the teardown of a session failed when the session was dropped without
+    /// [`Session::close`](crate::Session::close).
+    TeardownFailed,
+    /// The description of the target comes from a target pack, which overrides the
+    /// previously known description, see [`load_pack`](crate::config::load_pack).
+    TargetOverridden,
+    /// A poisoned region of memory was overwritten, see
+    /// [`Core::check_poison`](crate::Core::check_poison).
+    PoisonViolated,
+}
+
+/// A single entry in the [`HealthLog`].
+#[derive(Debug, Clone)]
+pub struct HealthLogEntry {
+    /// The time at which the anomaly was recorded.
+    pub timestamp: SystemTime,
+    /// The kind of anomaly.
+    pub event: HealthEvent,
+    /// The index of the affected core, if the anomaly is specific to a core.
+    pub core: Option<usize>,
+    /// The operation during which the anomaly happened.
+    pub operation: &'static str,
+    /// Additional details about the anomaly.
+    pub details: String,
+}
+
+impl fmt::Display for HealthLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let timestamp = self
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        write!(
+            f,
+            "[{}.{:03}] {:?} during {}",
+            timestamp.as_secs(),
+            timestamp.subsec_millis(),
+            self.event,
+            self.operation
+        )?;
+
+        if let Some(core) = self.core {
+            write!(f, " on core {}", core)?;
+        }
+
+        if !self.details.is_empty() {
+            write!(f, ": {}", self.details)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct HealthLogState {
+    capacity: usize,
+    entries: VecDeque<HealthLogEntry>,
+}
+
+/// A bounded log of recovered anomalies.
+///
+/// All clones of a handle share the same entries. Once the capacity is reached,
+/// the oldest entries are dropped. Nothing is allocated until the first entry is recorded.
+#[derive(Debug, Clone)]
+pub struct HealthLog {
+    state: Arc<Mutex<HealthLogState>>,
+}
+
+impl HealthLog {
+    /// Create a new health log which keeps the last `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HealthLogState {
+                capacity,
+                entries: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Record a recovered anomaly.
+    pub fn record(
+        &self,
+        event: HealthEvent,
+        core: Option<usize>,
+        operation: &'static str,
+        details: impl Into<String>,
+    ) {
+        let details = details.into();
+
+        log::debug!(
+            "Recovered from {:?} during {}: {}",
+            event,
+            operation,
+            details
+        );
+
+        let mut state = self.state.lock().unwrap();
+
+        if state.capacity == 0 {
+            return;
+        }
+
+        if state.entries.len() == state.capacity {
+            state.entries.pop_front();
+        }
+
+        state.entries.push_back(HealthLogEntry {
+            timestamp: SystemTime::now(),
+            event,
+            core,
+            operation,
+            details,
+        });
+    }
+
+    /// Returns all entries, starting with the oldest one.
+    pub fn entries(&self) -> Vec<HealthLogEntry> {
+        self.last(usize::MAX)
+    }
+
+    /// Returns the last `count` entries, starting with the oldest one.
+    pub fn last(&self, count: usize) -> Vec<HealthLogEntry> {
+        let state = self.state.lock().unwrap();
+        let skip = state.entries.len().saturating_sub(count);
+
+        state.entries.iter().skip(skip).cloned().collect()
+    }
+
+    /// Returns true if no anomaly was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().entries.is_empty()
+    }
+
+    /// Returns the maximum number of entries kept in the log.
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().capacity
+    }
+
+    /// Remove all entries from the log.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().entries.clear();
+    }
+
+    /// Attach the most recent entries to `error`, if there are any.
+    pub(crate) fn attach_to(&self, error: crate::Error) -> crate::Error {
+        if self.is_empty() || matches!(error, crate::Error::WithHealthLog { .. }) {
+            return error;
+        }
+
+        crate::Error::WithHealthLog {
+            error: Box::new(error),
+            entries: self.last(ATTACHED_ENTRIES),
+        }
+    }
+}
+
+impl Default for HealthLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_HEALTH_LOG_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn oldest_entries_are_dropped() {
+        let log = HealthLog::new(2);
+
+        for operation in ["first", "second", "third"] {
+            log.record(HealthEvent::RetrySucceeded, None, operation, "");
+        }
+
+        let operations = log
+            .entries()
+            .iter()
+            .map(|entry| entry.operation)
+            .collect::<Vec<_>>();
+
+        assert_eq!(operations, vec!["second", "third"]);
+    }
+
+    #[test]
+    fn entries_are_attached_to_errors() {
+        let log = HealthLog::default();
+
+        let error = log.attach_to(crate::Error::CoreNotFound(1));
+        assert!(matches!(error, crate::Error::CoreNotFound(1)));
+
+        log.clone().record(
+            HealthEvent::StickyErrorCleared,
+            Some(0),
+            "read",
+            "STICKYERR",
+        );
+
+        match log.attach_to(crate::Error::CoreNotFound(1)) {
+            crate::Error::WithHealthLog { error, entries } => {
+                assert!(matches!(*error, crate::Error::CoreNotFound(1)));
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].event, HealthEvent::StickyErrorCleared);
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+}
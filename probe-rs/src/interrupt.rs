@@ -0,0 +1,78 @@
+//! Interrupting long-running operations.
+//!
+//! Operations like flashing or waiting for a core to halt can take a long time. They can be
+//! interrupted with an [`InterruptHandle`], e.g. from a Ctrl-C handler running on another
+//! thread, while the operation itself holds the [`Session`](crate::Session).
+//!
+//! An interrupted operation stops at the next cancellation point, cleans up, and returns
+//! [`Error::Interrupted`]. Cancellation points are:
+//!
+//! - the boundaries between the chunks of large memory transfers,
+//! - the polling loop while waiting for a core to halt,
+//! - the boundaries between the sectors and pages which are erased or programmed when flashing.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::Error;
+
+/// A handle to interrupt the operation which is currently running on a [`Session`](crate::Session).
+///
+/// The handle is cheap to clone, and can be sent to other threads.
+///
+/// An interrupt is reported by exactly one operation. If it is requested while no operation
+/// is running, the next operation which reaches a cancellation point is interrupted.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interrupt the current operation.
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if an interrupt was requested, which was not reported by an operation yet.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::SeqCst)
+    }
+
+    /// A cancellation point.
+    ///
+    /// Returns [`Error::Interrupted`] if an interrupt was requested, and marks it as reported.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.interrupted.swap(false, Ordering::SeqCst) {
+            log::info!("Operation interrupted");
+            Err(Error::Interrupted)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interrupt_is_reported_once() {
+        let handle = InterruptHandle::new();
+        let other = handle.clone();
+
+        assert!(handle.check().is_ok());
+
+        other.interrupt();
+        assert!(handle.is_interrupted());
+
+        assert!(matches!(handle.check(), Err(Error::Interrupted)));
+        assert!(!other.is_interrupted());
+        assert!(handle.check().is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+//! Limiting how much a session may disturb the target, see
+//! [`Session::set_max_intrusiveness`](crate::Session::set_max_intrusiveness).
+//!
+//! Every operation on a target disturbs it to some degree. Reading RAM through the debug
+//! port is invisible to the firmware, while reading a register requires the core to be halted,
+//! and a reset restarts the firmware. The operations of a [`Core`](crate::Core) and a
+//! [`Session`](crate::Session) are grouped into [`TargetOperation`]s, each of which has an
+//! [`Intrusiveness`]. When the session has a maximum intrusiveness, operations above it fail
+//! with [`Error::IntrusivenessExceeded`] before anything is sent to the target.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// How much an operation disturbs the target, from not at all to destroying its state.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Intrusiveness {
+    /// The operation isn't visible to the target, e.g. reading RAM through a MEM-AP.
+    None,
+    /// The operation uses the bus of the target, which can have side effects, e.g. reading a
+    /// FIFO of a peripheral.
+    BusOnly,
+    /// The operation uses resources of a hart, e.g. its program buffer, and halts it briefly.
+    HartResources,
+    /// The operation requires the core to be halted, e.g. reading a register.
+    RequiresHalt,
+    /// The operation changes the state of the target, e.g. writing memory.
+    ChangesState,
+    /// The operation restarts the firmware or erases the target, e.g. a reset.
+    #[default]
+    Destructive,
+}
+
+impl Intrusiveness {
+    /// Returns an error if `operation` is more intrusive than this level allows.
+    pub(crate) fn permit(self, operation: TargetOperation) -> Result<(), Error> {
+        let required = operation.intrusiveness();
+
+        if required <= self {
+            Ok(())
+        } else {
+            Err(Error::IntrusivenessExceeded {
+                operation,
+                required,
+                allowed: self,
+            })
+        }
+    }
+}
+
+/// A group of operations on a target, with the same [`Intrusiveness`].
+///
+/// The documentation of each public operation names the group it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TargetOperation {
+    /// Reading RAM or flash.
+    ReadMemory,
+    /// Reading the status of a core, or other registers of the debug logic.
+    ReadStatus,
+    /// Reading memory which isn't RAM or flash, e.g. the registers of a peripheral.
+    ReadDeviceMemory,
+    /// Accessing memory through the program buffer of a RISC-V hart.
+    HartMemoryAccess,
+    /// Halting a core.
+    Halt,
+    /// Resuming a halted core.
+    Resume,
+    /// Reading a register of a core.
+    ReadRegister,
+    /// Setting or clearing a hardware breakpoint.
+    HardwareBreakpoint,
+    /// Executing a single instruction.
+    Step,
+    /// Writing memory.
+    WriteMemory,
+    /// Writing a register of a core.
+    WriteRegister,
+    /// Setting or clearing a software breakpoint, which replaces an instruction in memory.
+    SoftwareBreakpoint,
+    /// Configuring the trace or the watchpoint units.
+    ConfigureTrace,
+    /// Resetting a core or the whole target.
+    Reset,
+    /// Accessing a vendor specific access port, which can reset or erase the target.
+    VendorAccessPort,
+    /// Sending a vendor specific command to the probe, whose effect on the target is unknown.
+    VendorCommand,
+}
+
+impl TargetOperation {
+    /// Returns how much the operation disturbs the target.
+    pub fn intrusiveness(self) -> Intrusiveness {
+        match self {
+            TargetOperation::ReadMemory | TargetOperation::ReadStatus => Intrusiveness::None,
+            TargetOperation::ReadDeviceMemory => Intrusiveness::BusOnly,
+            TargetOperation::HartMemoryAccess => Intrusiveness::HartResources,
+            TargetOperation::Halt
+            | TargetOperation::Resume
+            | TargetOperation::ReadRegister
+            | TargetOperation::HardwareBreakpoint => Intrusiveness::RequiresHalt,
+            TargetOperation::Step
+            | TargetOperation::WriteMemory
+            | TargetOperation::WriteRegister
+            | TargetOperation::SoftwareBreakpoint
+            | TargetOperation::ConfigureTrace => Intrusiveness::ChangesState,
+            TargetOperation::Reset
+            | TargetOperation::VendorAccessPort
+            | TargetOperation::VendorCommand => Intrusiveness::Destructive,
+        }
+    }
+}
+
+impl fmt::Display for TargetOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            TargetOperation::ReadMemory => "reading memory",
+            TargetOperation::ReadStatus => "reading the status of a core",
+            TargetOperation::ReadDeviceMemory => "reading device memory",
+            TargetOperation::HartMemoryAccess => "accessing memory through the program buffer",
+            TargetOperation::Halt => "halting a core",
+            TargetOperation::Resume => "resuming a core",
+            TargetOperation::ReadRegister => "reading a register",
+            TargetOperation::HardwareBreakpoint => "changing a hardware breakpoint",
+            TargetOperation::Step => "stepping a core",
+            TargetOperation::WriteMemory => "writing memory",
+            TargetOperation::WriteRegister => "writing a register",
+            TargetOperation::SoftwareBreakpoint => "changing a software breakpoint",
+            TargetOperation::ConfigureTrace => "configuring the trace",
+            TargetOperation::Reset => "resetting the target",
+            TargetOperation::VendorAccessPort => "accessing a vendor specific access port",
+            TargetOperation::VendorCommand => "sending a vendor specific probe command",
+        };
+
+        f.write_str(description)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn operations_above_the_level_are_rejected() {
+        assert!(Intrusiveness::None
+            .permit(TargetOperation::ReadMemory)
+            .is_ok());
+        assert!(Intrusiveness::Destructive
+            .permit(TargetOperation::Reset)
+            .is_ok());
+
+        match Intrusiveness::BusOnly.permit(TargetOperation::HartMemoryAccess) {
+            Err(Error::IntrusivenessExceeded {
+                operation,
+                required,
+                allowed,
+            }) => {
+                assert_eq!(operation, TargetOperation::HartMemoryAccess);
+                assert_eq!(required, Intrusiveness::HartResources);
+                assert_eq!(allowed, Intrusiveness::BusOnly);
+            }
+            other => panic!("Expected the intrusiveness to be exceeded, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Keeping the debug connection alive while a session is idle.
+//!
+//! Some targets drop their debug state if the debugger doesn't access them for a while,
+//! e.g. harts which re-enter a low-power state unless the debug module is accessed
+//! periodically. For these targets, a [`Keepalive`] can be configured in the target
+//! description or with [`AttachOptions::keepalive`](crate::AttachOptions::keepalive).
+//!
+//! When the session was idle for longer than the configured interval, the keepalive access
+//! is performed before the next operation. Sessions which are idle for a long time can
+//! additionally use a [`KeepaliveThread`], if the `keepalive-thread` feature is enabled.
+
+use std::time::{Duration, Instant};
+
+use crate::config::{Keepalive, KeepaliveAction};
+
+#[cfg(feature = "keepalive-thread")]
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+#[cfg(feature = "keepalive-thread")]
+use crate::Session;
+
+/// Tracks when the target was last accessed.
+#[derive(Debug)]
+pub(crate) struct KeepaliveState {
+    config: Option<Keepalive>,
+    last_activity: Instant,
+}
+
+impl KeepaliveState {
+    pub(crate) fn new(config: Option<Keepalive>) -> Self {
+        Self {
+            config,
+            last_activity: Instant::now(),
+        }
+    }
+
+    /// The time after which an idle connection has to be kept alive, if any.
+    pub(crate) fn interval(&self) -> Option<Duration> {
+        self.config
+            .map(|config| Duration::from_millis(config.interval_ms))
+    }
+
+    /// Returns the access which has to be performed at `now`, before the target is
+    /// accessed again, if the connection was idle for too long.
+    pub(crate) fn due(&self, now: Instant) -> Option<KeepaliveAction> {
+        let config = self.config?;
+
+        if now.saturating_duration_since(self.last_activity)
+            >= Duration::from_millis(config.interval_ms)
+        {
+            Some(config.action)
+        } else {
+            None
+        }
+    }
+
+    /// Record that the target was accessed at `now`.
+    pub(crate) fn touch(&mut self, now: Instant) {
+        self.last_activity = now;
+    }
+}
+
+/// A background thread which keeps the connection of a shared session alive.
+///
+/// The thread periodically locks the session, and performs the keepalive access if the
+/// session was idle for longer than the configured interval. It stops when the handle is
+/// dropped, when the session is dropped, or when a keepalive access fails.
+#[cfg(feature = "keepalive-thread")]
+#[derive(Debug)]
+pub struct KeepaliveThread {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "keepalive-thread")]
+impl KeepaliveThread {
+    /// Start a keepalive thread for `session`.
+    ///
+    /// Returns `None` if no keepalive is configured for the session.
+    pub fn spawn(session: &Arc<Mutex<Session>>) -> Option<Self> {
+        let interval = session.lock().ok()?.keepalive_interval()?;
+
+        let session = Arc::downgrade(session);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let handle = thread::Builder::new()
+            .name("probe-rs keepalive".to_owned())
+            .spawn(move || {
+                // Check twice per interval, so that the connection is never idle for
+                // much longer than the interval.
+                while !thread_stop.load(Ordering::Relaxed) {
+                    thread::park_timeout(interval / 2);
+
+                    if thread_stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    let session = match session.upgrade() {
+                        Some(session) => session,
+                        None => break,
+                    };
+
+                    let mut session = match session.lock() {
+                        Ok(session) => session,
+                        Err(_) => break,
+                    };
+
+                    if let Err(error) = session.keep_alive() {
+                        log::warn!("Stopping keepalive thread: {}", error);
+                        break;
+                    }
+                }
+            })
+            .ok()?;
+
+        Some(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stop the thread, and wait until it has finished.
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            handle.thread().unpark();
+
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(feature = "keepalive-thread")]
+impl Drop for KeepaliveThread {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn no_keepalive_without_config() {
+        let state = KeepaliveState::new(None);
+
+        assert_eq!(state.interval(), None);
+        assert_eq!(state.due(Instant::now() + Duration::from_secs(3600)), None);
+    }
+
+    #[test]
+    fn keepalive_is_due_after_interval() {
+        let mut state = KeepaliveState::new(Some(Keepalive {
+            interval_ms: 500,
+            action: KeepaliveAction::ReadDmstatus,
+        }));
+
+        let start = Instant::now();
+        state.touch(start);
+
+        assert_eq!(state.due(start + Duration::from_millis(499)), None);
+        assert_eq!(
+            state.due(start + Duration::from_millis(500)),
+            Some(KeepaliveAction::ReadDmstatus)
+        );
+
+        state.touch(start + Duration::from_millis(600));
+        assert_eq!(state.due(start + Duration::from_millis(1000)), None);
+    }
+}
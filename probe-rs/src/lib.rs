@@ -69,34 +69,130 @@ extern crate serde;
 
 /// All the interface bits for the different architectures.
 pub mod architecture;
+#[warn(missing_docs)]
+mod attach_plan;
 pub mod config;
+#[warn(missing_docs)]
+pub mod conformance;
 
 #[warn(missing_docs)]
 mod core;
+#[warn(missing_docs)]
+mod deadline;
 pub mod debug;
+#[warn(missing_docs)]
+mod drain;
+#[warn(missing_docs)]
+mod errata;
 mod error;
 #[warn(missing_docs)]
 pub mod flashing;
 #[warn(missing_docs)]
+mod freeze;
+#[warn(missing_docs)]
+mod guard;
+#[warn(missing_docs)]
+mod health;
+#[warn(missing_docs)]
+mod interrupt;
+#[warn(missing_docs)]
+mod intrusiveness;
+#[warn(missing_docs)]
+mod keepalive;
+#[warn(missing_docs)]
+mod link;
+#[warn(missing_docs)]
 mod memory;
 #[warn(missing_docs)]
+mod operation_journal;
+#[warn(missing_docs)]
+mod panic_hooks;
+#[warn(missing_docs)]
 mod probe;
+#[cfg(feature = "remote")]
+#[warn(missing_docs)]
+pub mod remote;
 #[warn(missing_docs)]
 mod session;
+#[warn(missing_docs)]
+mod system_description;
+#[warn(missing_docs)]
+mod teardown;
+#[warn(missing_docs)]
+mod transfer;
 
-pub use crate::config::{CoreType, InstructionSet, Target};
+pub use crate::attach_plan::{AttachDeviation, AttachPlan, AttachReport, CoreDirective};
+pub use crate::config::{CoreCapabilities, CoreType, FpuSupport, InstructionSet, Target};
 pub use crate::core::{
-    Architecture, BreakpointId, CommunicationInterface, Core, CoreInformation, CoreInterface,
-    CoreState, CoreStatus, HaltReason, MemoryMappedRegister, RegisterDescription, RegisterFile,
-    RegisterId, RegisterValue, SpecificCoreState,
+    register_value_hex, AddressMap, AddressMapping, Architecture, AtomicOperation,
+    BreakpointApplyReport, BreakpointFailure, BreakpointId, BreakpointMechanism, BreakpointOutcome,
+    BreakpointPlan, BreakpointPolicy, BreakpointRequest, BreakpointSkipCount,
+    CommunicationInterface, ContextRestoreReport, ContextSnapshot, Core, CoreInformation,
+    CoreInterface, CoreSnapshot, CoreState, CoreStatus, ForceHaltReport, FpuState, FpuValue,
+    FpuValueSource, HaltAttempt, HaltAttemptOutcome, HaltEscalation, HaltLocation, HaltReason,
+    InstructionFetch, MemoryMappedRegister, MemorySearchIter, PlannedBreakpoint, PoisonResetPolicy,
+    PoisonViolation, RegisterDescription, RegisterFile, RegisterId, RegisterRestoreFailure,
+    RegisterValue, ResetHaltMechanism, ResetHaltReport, RestoreFailure, RoutineArgument,
+    RoutineCall, RoutineCompletion, RoutineOutput, SavedMemory, SavedRegister, SearchOptions,
+    SpecificCoreState, StatusCondition, TargetRoutine, WatchKind, Watchpoint, WatchpointConfig,
+    WatchpointKind, WatchpointQualifier,
 };
+pub use crate::deadline::Deadline;
+pub use crate::drain::{BufferPointers, CircularBuffer, DrainId, DrainSink, DrainStatus};
+pub use crate::errata::{ActiveErratum, Erratum};
 pub use crate::error::Error;
-pub use crate::memory::{Memory, MemoryInterface};
+pub use crate::freeze::{FreezeSelection, FrozenPeripheral};
+pub use crate::guard::{AttachGuard, Fingerprint, FingerprintMismatch, GuardPolicy};
+pub use crate::health::{HealthEvent, HealthLog, HealthLogEntry};
+pub use crate::interrupt::InterruptHandle;
+pub use crate::intrusiveness::{Intrusiveness, TargetOperation};
+#[cfg(feature = "keepalive-thread")]
+pub use crate::keepalive::KeepaliveThread;
+pub use crate::link::{LinkFailure, SlowClockAttach, SLOW_CLOCK_SPEED_KHZ};
+pub use crate::memory::{
+    AccessDirection, AccessMediator, Endianness, FromTargetBytes, MediatedRegions, Memory,
+    MemoryInterface, PartialRead, PreparedAccess, ReadEnd, RetryPolicy, Stm32Quadspi,
+    TargetWritableFlash, VolatileRanges, WriteCoalescer, WriteWidths,
+};
+
+#[doc(hidden)]
+pub use crate::memory::align_up;
+pub use crate::operation_journal::{
+    Divergence, DivergenceKind, JournalEntry, JournalFormat, JournalOperation, JournalOutcome,
+    JournalValue, JournalWidth, OperationJournal, ReplayOptions, ReplayPolicy, ReplayReport,
+    OPERATION_JOURNAL_VERSION,
+};
+pub use crate::panic_hooks::{PanicBreakOptions, PanicHook, PANIC_BREAKPOINT_GROUP};
+pub use crate::probe::plugin;
 pub use crate::probe::{
-    AttachMethod, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType,
-    Probe, ProbeCreationError, WireProtocol,
+    plugin::ProbeCapabilities, AttachMethod, DebugProbe, DebugProbeError, DebugProbeInfo,
+    DebugProbeSelector, DebugProbeType, FirmwareAdjustment, FirmwareFeature, FirmwareLimitation,
+    Probe, ProbeCreationError, VendorCommand, VendorCommandIndex, VendorCommandOrdering,
+    WireProtocol,
+};
+pub use crate::session::{AttachOptions, CoreAccessOptionsOverride, Permissions, Session};
+pub use crate::system_description::{
+    AccessPortDescriptor, CoreAccessDescriptor, CoreAttachState, CoreDescriptor,
+    CoreStatusDescriptor, DebugInterfaceDescriptor, DebugModuleDescriptor, ErratumDescriptor,
+    ProbeDescriptor, RegionDescriptor, RegionKind, RegisterFileSummary, SessionSettings,
+    SessionWarning, SystemDescription, TargetIdentity, SYSTEM_DESCRIPTION_VERSION,
+};
+pub use crate::teardown::{
+    CloseReport, CoreCloseReport, DetachMode, TeardownFailure, TeardownStep,
+};
+pub use crate::transfer::{
+    TransferDirection, TransferEstimate, TransferEvent, TransferProgress, TransferStatistics,
+    TransferTotals,
 };
-pub use crate::session::{Permissions, Session};
 
-// TODO: Hide behind feature
 pub use crate::probe::fake_probe::FakeProbe;
+
+/// Handles to inject faults into the mocked core of a [`FakeProbe`], and to observe what
+/// probe-rs did to it, for tests.
+#[cfg(feature = "test-utils")]
+pub mod test_utils {
+    pub use crate::probe::fake_probe::{
+        AccessStalls, BreakpointHits, EccFaults, FirmwareWrites, ForeignResumes, ProbePolls,
+        ProbeTransactions, ReadFaults, TargetResets, VendorCommands, WriteFaults, WriteLog,
+    };
+}
@@ -0,0 +1,427 @@
+//! Classifying failures of the link to the target, and negotiating the protocol speed.
+//!
+//! Many attach failures are caused by a protocol clock which is too fast for the target or
+//! the wiring, e.g. missing acknowledges, parity errors or a debug module which is busy all
+//! the time. [`Error::link_failure`] recognizes these signatures. With
+//! [`AttachOptions::auto_speed`](crate::AttachOptions::auto_speed), attaching halves the
+//! speed on these failures, until the target communicates reliably or the minimum speed
+//! is reached.
+//!
+//! Some targets run their debug logic on a slow clock after a reset, and only communicate
+//! at any speed after they were attached at a low speed first. With
+//! [`AttachOptions::slow_clock_attach`](crate::AttachOptions::slow_clock_attach), they are
+//! attached at [`SLOW_CLOCK_SPEED_KHZ`] first, and the speed is negotiated upward afterwards.
+
+use std::error::Error as StdError;
+
+use crate::architecture::arm::sequences::ArmDebugSequence;
+use crate::architecture::arm::{DapError, DapProbe, PortType, RawDapAccess};
+use crate::architecture::riscv::communication_interface::RiscvError;
+use crate::{
+    Architecture, DebugProbe, DebugProbeError, Error, HealthEvent, HealthLog, Probe, WireProtocol,
+};
+
+/// The number of reads in the verification burst at the negotiated speed.
+const VERIFICATION_READS: usize = 16;
+
+/// The address of the DPIDR register of an ARM debug port.
+const DPIDR: u8 = 0x0;
+
+/// The speed at which targets with a slow debug clock are attached first.
+pub const SLOW_CLOCK_SPEED_KHZ: u32 = 100;
+
+/// A failure of the link between the probe and the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFailure {
+    /// The target didn't respond, e.g. it didn't acknowledge a request.
+    ///
+    /// This is caused by a clock which is too fast, but also by wrong wiring or an unpowered
+    /// target.
+    NoResponse,
+    /// The data was corrupted, e.g. the parity was wrong or a value read twice differs.
+    SignalIntegrity,
+    /// The target stayed busy, e.g. it kept answering with WAIT, or the RISC-V debug
+    /// module kept reporting operations in progress.
+    Busy,
+}
+
+/// Returns the kind of link failure which caused `error`, see [`Error::link_failure`].
+pub(crate) fn classify(error: &(dyn StdError + 'static)) -> Option<LinkFailure> {
+    if let Some(error) = error.downcast_ref::<DapError>() {
+        return match error {
+            DapError::NoAcknowledge => Some(LinkFailure::NoResponse),
+            DapError::IncorrectParity | DapError::SwdProtocol => Some(LinkFailure::SignalIntegrity),
+            DapError::WaitResponse => Some(LinkFailure::Busy),
+            DapError::FaultResponse
+            | DapError::TargetPowerUpFailed
+            | DapError::PowerAckTimeout { .. } => None,
+        };
+    }
+
+    if let Some(error) = error.downcast_ref::<RiscvError>() {
+        match error {
+            RiscvError::Timeout => return Some(LinkFailure::Busy),
+            RiscvError::DmiTransfer(_) => return Some(LinkFailure::SignalIntegrity),
+            _ => (),
+        }
+    }
+
+    if error.downcast_ref::<VerificationFailed>().is_some() {
+        return Some(LinkFailure::SignalIntegrity);
+    }
+
+    // Transparent errors don't report the error they wrap as their source.
+    match error.downcast_ref::<DebugProbeError>() {
+        Some(DebugProbeError::TargetNotFound) => return Some(LinkFailure::NoResponse),
+        Some(DebugProbeError::Other(error)) => return error.chain().find_map(classify),
+        _ => (),
+    }
+
+    match error.downcast_ref::<Error>() {
+        Some(Error::Other(error)) => return error.chain().find_map(classify),
+        // Boxed errors don't report the error in the box as their source either.
+        Some(Error::TargetLost(error)) | Some(Error::WithHealthLog { error, .. }) => {
+            return classify(&**error)
+        }
+        _ => (),
+    }
+
+    error.source().and_then(classify)
+}
+
+/// A value read during the verification burst differs from the first one.
+#[derive(Debug, thiserror::Error)]
+#[error("Read {read:#010x} instead of {expected:#010x} during the verification of the link")]
+struct VerificationFailed {
+    expected: u32,
+    read: u32,
+}
+
+/// The speed range for [`AttachOptions::auto_speed`](crate::AttachOptions::auto_speed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AutoSpeed {
+    pub start_khz: u32,
+    pub min_khz: u32,
+}
+
+/// The two phases of the attach to a target whose debug logic runs on a slow clock, see
+/// [`AttachOptions::slow_clock_attach`](crate::AttachOptions::slow_clock_attach).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowClockAttach {
+    /// The speed at which the link was verified first, and the debug clock was set up.
+    pub initial_speed_khz: u32,
+    /// The speed which was negotiated afterwards.
+    pub final_speed_khz: u32,
+    /// Whether the slow clock was detected, because attaching failed at the negotiated
+    /// speeds, instead of being configured for the target or in the attach options.
+    pub detected: bool,
+}
+
+/// Attach at [`SLOW_CLOCK_SPEED_KHZ`], set up the debug clock with the debug sequence of the
+/// target, and negotiate the speed upward from `auto_speed.start_khz` afterwards.
+///
+/// If the link fails at all speeds above [`SLOW_CLOCK_SPEED_KHZ`], the target is used at
+/// that speed. The probe is detached afterwards, like after [`negotiate_speed`].
+pub(crate) fn slow_clock_attach(
+    mut probe: Probe,
+    architecture: Architecture,
+    sequence: Option<&dyn ArmDebugSequence>,
+    auto_speed: AutoSpeed,
+    health_log: &HealthLog,
+) -> (Probe, Result<SlowClockAttach, Error>) {
+    let initial_speed_khz = match probe.set_speed_unchecked(SLOW_CLOCK_SPEED_KHZ) {
+        Ok(speed_khz) => speed_khz,
+        Err(error) => return (probe, Err(error.into())),
+    };
+
+    let (returned, result) = verify_link(probe, architecture, sequence);
+    probe = returned;
+
+    if let Err(error) = result {
+        return (probe, Err(error));
+    }
+
+    log::debug!(
+        "Attached at {} kHz, negotiating the speed of the set up debug clock",
+        initial_speed_khz
+    );
+
+    let auto_speed = AutoSpeed {
+        start_khz: auto_speed.start_khz.max(SLOW_CLOCK_SPEED_KHZ),
+        min_khz: SLOW_CLOCK_SPEED_KHZ,
+    };
+
+    let (returned, result) = negotiate_speed(probe, architecture, auto_speed, health_log);
+    probe = returned;
+
+    let final_speed_khz = match result {
+        Ok(speed_khz) => speed_khz,
+        Err(Error::SpeedNegotiationFailed { .. }) => {
+            log::debug!(
+                "The link failed above {} kHz, staying at the initial speed",
+                initial_speed_khz
+            );
+
+            if let Err(error) = probe.set_speed_unchecked(SLOW_CLOCK_SPEED_KHZ) {
+                return (probe, Err(error.into()));
+            }
+
+            let (returned, result) = verify_link(probe, architecture, None);
+            probe = returned;
+
+            if let Err(error) = result {
+                return (probe, Err(error));
+            }
+
+            initial_speed_khz
+        }
+        Err(error) => return (probe, Err(error)),
+    };
+
+    (
+        probe,
+        Ok(SlowClockAttach {
+            initial_speed_khz,
+            final_speed_khz,
+            detected: false,
+        }),
+    )
+}
+
+/// Retry attaching with [`slow_clock_attach`] after the speed negotiation of `auto_speed`
+/// failed, and record a [`HealthEvent::SlowClockDetected`] if that succeeds.
+///
+/// Returns `None` if the slow attach failed as well, so that the caller can return the
+/// error of the speed negotiation.
+pub(crate) fn detect_slow_clock(
+    probe: Probe,
+    target_name: &str,
+    architecture: Architecture,
+    sequence: Option<&dyn ArmDebugSequence>,
+    auto_speed: AutoSpeed,
+    health_log: &HealthLog,
+) -> (Probe, Option<SlowClockAttach>) {
+    let (probe, result) = slow_clock_attach(probe, architecture, sequence, auto_speed, health_log);
+
+    let attach = match result {
+        Ok(attach) => attach,
+        Err(error) => {
+            log::debug!(
+                "Attaching at {} kHz failed as well: {}",
+                SLOW_CLOCK_SPEED_KHZ,
+                error
+            );
+            return (probe, None);
+        }
+    };
+
+    log::warn!(
+        "{} only communicates after it was attached at {} kHz. Set `slow_clock_attach: true` for its family in the target description to attach this way right away.",
+        target_name,
+        attach.initial_speed_khz
+    );
+
+    health_log.record(
+        HealthEvent::SlowClockDetected,
+        None,
+        "attach",
+        format!(
+            "Attaching failed down to {} kHz, but succeeded at {} kHz, and at {} kHz afterwards. Consider setting `slow_clock_attach: true` for {}",
+            auto_speed.min_khz, attach.initial_speed_khz, attach.final_speed_khz, target_name
+        ),
+    );
+
+    let attach = SlowClockAttach {
+        detected: true,
+        ..attach
+    };
+
+    (probe, Some(attach))
+}
+
+/// Find the highest speed, starting at `auto_speed.start_khz` and halving it on each link
+/// failure, at which the target communicates reliably.
+///
+/// The start speed is limited to the highest speed of the probe, if it is known. The probe
+/// is detached afterwards, and configured to the negotiated speed, which is returned. Errors
+/// which are not link failures are returned right away. The probe is returned in any case,
+/// so that attaching can be retried, e.g. with [`slow_clock_attach`].
+pub(crate) fn negotiate_speed(
+    mut probe: Probe,
+    architecture: Architecture,
+    auto_speed: AutoSpeed,
+    health_log: &HealthLog,
+) -> (Probe, Result<u32, Error>) {
+    let mut speed_khz = match probe.capabilities().max_speed_khz {
+        Some(max_khz) if max_khz < auto_speed.start_khz => {
+            log::debug!(
+                "Starting the speed negotiation at {} kHz, the highest speed of the probe",
+                max_khz
+            );
+            max_khz
+        }
+        _ => auto_speed.start_khz,
+    };
+    let mut failures = Vec::new();
+
+    loop {
+        let actual_khz = match probe.set_speed_unchecked(speed_khz) {
+            Ok(actual_khz) => actual_khz,
+            Err(error) => return (probe, Err(error.into())),
+        };
+
+        let (returned, result) = verify_link(probe, architecture, None);
+        probe = returned;
+
+        let error = match result {
+            Ok(()) => break,
+            Err(error) => error,
+        };
+
+        let failure = match error.link_failure() {
+            Some(failure) => failure,
+            None => return (probe, Err(error)),
+        };
+
+        log::debug!(
+            "The link failed at {} kHz with {:?}: {}",
+            actual_khz,
+            failure,
+            error
+        );
+
+        let next_khz = speed_khz / 2;
+        if next_khz < auto_speed.min_khz || next_khz == 0 {
+            let error = Error::SpeedNegotiationFailed {
+                speed_khz: actual_khz,
+                failure,
+                source: Box::new(error),
+            };
+            return (probe, Err(error));
+        }
+
+        failures.push(format!("{:?} at {} kHz", failure, actual_khz));
+        speed_khz = next_khz;
+    }
+
+    let speed_khz = probe.speed_khz();
+    log::info!("Negotiated a protocol speed of {} kHz", speed_khz);
+
+    if !failures.is_empty() {
+        health_log.record(
+            HealthEvent::SpeedReduced,
+            None,
+            "attach",
+            format!(
+                "Reduced the speed to {} kHz after {}",
+                speed_khz,
+                failures.join(", ")
+            ),
+        );
+    }
+
+    (probe, Ok(speed_khz))
+}
+
+/// Attach at the current speed and read an ID register of the target repeatedly.
+///
+/// On ARM targets, the DPIDR is read after a line reset, if the probe gives raw access to the
+/// debug port. Other probes only attach. On RISC-V targets, the IDCODE is read after the
+/// debug module was set up.
+///
+/// If `sequence` is given, its [`ArmDebugSequence::debug_clock_setup`] is run after the
+/// line reset, before the reads.
+fn verify_link(
+    mut probe: Probe,
+    architecture: Architecture,
+    sequence: Option<&dyn ArmDebugSequence>,
+) -> (Probe, Result<(), Error>) {
+    if let Err(error) = probe.inner_attach() {
+        return (probe, Err(error.into()));
+    }
+
+    match architecture {
+        Architecture::Arm => {
+            let result = match probe.try_as_dap_probe() {
+                Some(dap_probe) => line_reset(dap_probe)
+                    .and_then(|()| match sequence {
+                        Some(sequence) => sequence.debug_clock_setup(dap_probe),
+                        None => Ok(()),
+                    })
+                    .and_then(|()| {
+                        read_repeatedly(|| dap_probe.raw_read_register(PortType::DebugPort, DPIDR))
+                    }),
+                None => Ok(()),
+            };
+
+            let result = result.and(probe.inner_detach().map_err(Error::from));
+            (probe, result)
+        }
+        Architecture::Riscv => match probe.try_into_riscv_interface() {
+            Ok(mut interface) => {
+                let result = read_repeatedly(|| interface.read_idcode());
+
+                let mut probe = interface.close();
+                let result = result.and(probe.inner_detach().map_err(Error::from));
+                (probe, result)
+            }
+            Err((probe, error)) => (probe, Err(error.into())),
+        },
+    }
+}
+
+/// Reset the SWD or JTAG state machine of the debug port, so that the DPIDR can be read.
+fn line_reset(probe: &mut dyn DapProbe) -> Result<(), Error> {
+    probe.swj_sequence(51, 0x0007_FFFF_FFFF_FFFF)?;
+
+    match probe.active_protocol() {
+        Some(WireProtocol::Jtag) => probe.swj_sequence(16, 0xE73C)?,
+        _ => probe.swj_sequence(16, 0xE79E)?,
+    }
+
+    probe.swj_sequence(51, 0x0007_FFFF_FFFF_FFFF)?;
+    probe.swj_sequence(3, 0x00)?;
+
+    Ok(())
+}
+
+/// Read a register which always has the same value [`VERIFICATION_READS`] times, and check
+/// that all reads return the same value.
+fn read_repeatedly(mut read: impl FnMut() -> Result<u32, DebugProbeError>) -> Result<(), Error> {
+    let expected = read()?;
+
+    for _ in 1..VERIFICATION_READS {
+        let value = read()?;
+
+        if value != expected {
+            return Err(Error::architecture_specific(VerificationFailed {
+                expected,
+                read: value,
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn link_failures_are_classified() {
+        let no_ack: Error = DebugProbeError::from(DapError::NoAcknowledge).into();
+        assert_eq!(no_ack.link_failure(), Some(LinkFailure::NoResponse));
+
+        let busy = Error::from(RiscvError::Timeout);
+        assert_eq!(busy.link_failure(), Some(LinkFailure::Busy));
+
+        let wrapped = Error::from(DebugProbeError::from(anyhow::anyhow!(
+            DapError::IncorrectParity
+        )));
+        assert_eq!(wrapped.link_failure(), Some(LinkFailure::SignalIntegrity));
+
+        let unrelated: Error = DebugProbeError::UnsupportedSpeed(50_000).into();
+        assert_eq!(unrelated.link_failure(), None);
+    }
+}
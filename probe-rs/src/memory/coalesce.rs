@@ -0,0 +1,488 @@
+//! Coalescing of memory writes.
+
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::{config::MemoryRegion, error, MemoryInterface, VolatileRanges, WriteWidths};
+
+/// A write to device memory, which is replayed with the original access width.
+#[derive(Debug)]
+enum DeviceWrite {
+    Bits8(u64, Vec<u8>),
+    Bits32(u64, Vec<u32>),
+    Bits64(u64, Vec<u64>),
+}
+
+#[derive(Debug)]
+enum Segment {
+    /// Writes to RAM, which can be merged and reordered among themselves.
+    ///
+    /// Contains the last value written to each byte.
+    Ram(BTreeMap<u64, u8>),
+    /// A single write to device memory.
+    Device(DeviceWrite),
+    /// An ordering constraint, passed on to the underlying interface.
+    Barrier,
+}
+
+/// A [`MemoryInterface`] which queues writes to RAM, and merges them into as few
+/// block writes as possible when it is flushed.
+///
+/// Queued writes can be reordered, e.g. a write to a lower address is issued before a
+/// write to a higher address which was queued earlier. When the order matters, a
+/// [`MemoryInterface::write_barrier`] can be inserted: writes are never merged or
+/// reordered across a barrier. The barrier doesn't flush the queue.
+///
+/// Only addresses inside a RAM region of the memory map which aren't volatile, see
+/// [`VolatileRanges`], are treated as RAM. All other addresses are considered device memory.
+/// Writes to device memory are never merged or reordered, neither among themselves nor with
+/// any other write, and keep their original access width.
+///
+/// Reads flush all queued writes first. Queued writes are also flushed when the
+/// coalescer is dropped, but errors can only be observed with an explicit
+/// [`MemoryInterface::flush`].
+#[derive(Debug)]
+pub struct WriteCoalescer<M: MemoryInterface> {
+    inner: M,
+    ram: Vec<Range<u64>>,
+    volatile: VolatileRanges,
+    segments: Vec<Segment>,
+}
+
+impl<M: MemoryInterface> WriteCoalescer<M> {
+    /// Create a new coalescer, which writes to `inner`.
+    ///
+    /// The RAM regions of `memory_map` determine which writes can be coalesced.
+    pub fn new(inner: M, memory_map: &[MemoryRegion]) -> Self {
+        Self::with_volatile_ranges(inner, memory_map, VolatileRanges::new(memory_map))
+    }
+
+    /// Create a new coalescer, which writes to `inner`, and never coalesces writes to
+    /// `volatile`, e.g. the [`Session::volatile_ranges`](crate::Session::volatile_ranges)
+    /// including the ranges marked at runtime.
+    pub fn with_volatile_ranges(
+        inner: M,
+        memory_map: &[MemoryRegion],
+        volatile: VolatileRanges,
+    ) -> Self {
+        let ram = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(region) => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            inner,
+            ram,
+            volatile,
+            segments: Vec::new(),
+        }
+    }
+
+    fn is_ram(&self, address: u64, len: usize) -> bool {
+        self.volatile.is_plain_memory(&self.ram, address, len)
+    }
+
+    fn queue_ram(&mut self, address: u64, bytes: impl IntoIterator<Item = u8>) {
+        if !matches!(self.segments.last(), Some(Segment::Ram(_))) {
+            self.segments.push(Segment::Ram(BTreeMap::new()));
+        }
+
+        if let Some(Segment::Ram(pending)) = self.segments.last_mut() {
+            for (offset, byte) in bytes.into_iter().enumerate() {
+                pending.insert(address + offset as u64, byte);
+            }
+        }
+    }
+
+    fn queue_8(&mut self, address: u64, data: &[u8]) {
+        if self.is_ram(address, data.len()) {
+            self.queue_ram(address, data.iter().copied());
+        } else {
+            self.segments
+                .push(Segment::Device(DeviceWrite::Bits8(address, data.to_vec())));
+        }
+    }
+
+    fn queue_32(&mut self, address: u64, data: &[u32]) {
+        if self.is_ram(address, data.len() * 4) {
+            self.queue_ram(address, data.iter().flat_map(|word| word.to_le_bytes()));
+        } else {
+            self.segments
+                .push(Segment::Device(DeviceWrite::Bits32(address, data.to_vec())));
+        }
+    }
+
+    fn queue_64(&mut self, address: u64, data: &[u64]) {
+        if self.is_ram(address, data.len() * 8) {
+            self.queue_ram(address, data.iter().flat_map(|word| word.to_le_bytes()));
+        } else {
+            self.segments
+                .push(Segment::Device(DeviceWrite::Bits64(address, data.to_vec())));
+        }
+    }
+
+    /// Issue all queued writes to the underlying interface, without flushing it.
+    fn issue(&mut self) -> Result<(), error::Error> {
+        for segment in std::mem::take(&mut self.segments) {
+            match segment {
+                Segment::Ram(pending) => {
+                    for (address, data) in contiguous_runs(&pending) {
+                        self.volatile
+                            .debug_assert_plain("WriteCoalescer", address, data.len());
+                        write_run(&mut self.inner, address, &data)?;
+                    }
+                }
+                Segment::Device(DeviceWrite::Bits8(address, data)) => {
+                    self.inner.write_8(address, &data)?
+                }
+                Segment::Device(DeviceWrite::Bits32(address, data)) => {
+                    self.inner.write_32(address, &data)?
+                }
+                Segment::Device(DeviceWrite::Bits64(address, data)) => {
+                    self.inner.write_64(address, &data)?
+                }
+                Segment::Barrier => self.inner.write_barrier()?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Split the pending bytes into runs of consecutive addresses.
+fn contiguous_runs(pending: &BTreeMap<u64, u8>) -> Vec<(u64, Vec<u8>)> {
+    let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+
+    for (&address, &byte) in pending {
+        match runs.last_mut() {
+            Some((start, data)) if *start + data.len() as u64 == address => data.push(byte),
+            _ => runs.push((address, vec![byte])),
+        }
+    }
+
+    runs
+}
+
+/// Write a run of bytes, using 32-bit accesses for the word aligned part.
+fn write_run(
+    memory: &mut impl MemoryInterface,
+    address: u64,
+    data: &[u8],
+) -> Result<(), error::Error> {
+    let head = (((4 - address % 4) % 4) as usize).min(data.len());
+    let words = (data.len() - head) / 4;
+    let tail = head + words * 4;
+
+    if head > 0 {
+        memory.write_8(address, &data[..head])?;
+    }
+
+    if words > 0 {
+        let words = data[head..tail]
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect::<Vec<_>>();
+
+        memory.write_32(address + head as u64, &words)?;
+    }
+
+    if tail < data.len() {
+        memory.write_8(address + tail as u64, &data[tail..])?;
+    }
+
+    Ok(())
+}
+
+impl<M: MemoryInterface> MemoryInterface for WriteCoalescer<M> {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        self.inner.supports_native_64bit_access()
+    }
+
+    fn native_write_widths(&mut self) -> WriteWidths {
+        self.inner.native_write_widths()
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, error::Error> {
+        self.issue()?;
+        self.inner.read_word_64(address)
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error> {
+        self.issue()?;
+        self.inner.read_word_32(address)
+    }
+
+    fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error> {
+        self.issue()?;
+        self.inner.read_word_8(address)
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), error::Error> {
+        self.issue()?;
+        self.inner.read_64(address, data)
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error> {
+        self.issue()?;
+        self.inner.read_32(address, data)
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+        self.issue()?;
+        self.inner.read_8(address, data)
+    }
+
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), error::Error> {
+        self.queue_64(address, &[data]);
+        Ok(())
+    }
+
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), error::Error> {
+        self.queue_32(address, &[data]);
+        Ok(())
+    }
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), error::Error> {
+        self.queue_8(address, &[data]);
+        Ok(())
+    }
+
+    fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), error::Error> {
+        self.queue_64(address, data);
+        Ok(())
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), error::Error> {
+        self.queue_32(address, data);
+        Ok(())
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+        self.queue_8(address, data);
+        Ok(())
+    }
+
+    fn write_barrier(&mut self) -> Result<(), error::Error> {
+        if !matches!(self.segments.last(), None | Some(Segment::Barrier)) {
+            self.segments.push(Segment::Barrier);
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), error::Error> {
+        self.issue()?;
+        self.inner.flush()
+    }
+}
+
+impl<M: MemoryInterface> Drop for WriteCoalescer<M> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            log::warn!("Failed to flush coalesced writes: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RamRegion;
+
+    #[derive(Debug, PartialEq)]
+    enum Op {
+        Write8(u64, Vec<u8>),
+        Write32(u64, Vec<u32>),
+        Barrier,
+    }
+
+    /// A mock memory interface which records all writes.
+    #[derive(Default)]
+    struct RecordingMemory {
+        ops: Vec<Op>,
+    }
+
+    impl MemoryInterface for RecordingMemory {
+        fn supports_native_64bit_access(&mut self) -> bool {
+            false
+        }
+
+        fn read_word_64(&mut self, _address: u64) -> Result<u64, error::Error> {
+            Ok(0)
+        }
+
+        fn read_word_32(&mut self, _address: u64) -> Result<u32, error::Error> {
+            Ok(0)
+        }
+
+        fn read_word_8(&mut self, _address: u64) -> Result<u8, error::Error> {
+            Ok(0)
+        }
+
+        fn read_64(&mut self, _address: u64, _data: &mut [u64]) -> Result<(), error::Error> {
+            Ok(())
+        }
+
+        fn read_32(&mut self, _address: u64, _data: &mut [u32]) -> Result<(), error::Error> {
+            Ok(())
+        }
+
+        fn read_8(&mut self, _address: u64, _data: &mut [u8]) -> Result<(), error::Error> {
+            Ok(())
+        }
+
+        fn write_word_64(&mut self, _address: u64, _data: u64) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), error::Error> {
+            self.write_32(address, &[data])
+        }
+
+        fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), error::Error> {
+            self.write_8(address, &[data])
+        }
+
+        fn write_64(&mut self, _address: u64, _data: &[u64]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), error::Error> {
+            self.ops.push(Op::Write32(address, data.to_vec()));
+            Ok(())
+        }
+
+        fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+            self.ops.push(Op::Write8(address, data.to_vec()));
+            Ok(())
+        }
+
+        fn write_barrier(&mut self) -> Result<(), error::Error> {
+            self.ops.push(Op::Barrier);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), error::Error> {
+            Ok(())
+        }
+    }
+
+    /// Only `0x2000_0000..0x2001_0000` is RAM, everything else is device memory.
+    fn memory_map() -> Vec<MemoryRegion> {
+        vec![MemoryRegion::Ram(RamRegion {
+            name: None,
+            range: 0x2000_0000..0x2001_0000,
+            is_boot_memory: false,
+            cores: vec!["main".into()],
+            volatile: false,
+            ecc: false,
+        })]
+    }
+
+    #[test]
+    fn adjacent_writes_are_merged() {
+        let mut memory = RecordingMemory::default();
+
+        {
+            let mut coalescer = WriteCoalescer::new(&mut memory, &memory_map());
+
+            coalescer.write_word_32(0x2000_0104, 2).unwrap();
+            coalescer.write_word_32(0x2000_0100, 1).unwrap();
+            coalescer.write_8(0x2000_0108, &[3, 0, 0, 0, 4]).unwrap();
+            coalescer.flush().unwrap();
+        }
+
+        assert_eq!(
+            memory.ops,
+            vec![
+                Op::Write32(0x2000_0100, vec![1, 2, 3]),
+                Op::Write8(0x2000_010c, vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn doorbell_is_written_after_barrier() {
+        let mut memory = RecordingMemory::default();
+
+        {
+            let mut coalescer = WriteCoalescer::new(&mut memory, &memory_map());
+
+            // The doorbell is located directly before the descriptors, so without the
+            // barrier, it would be merged into the same block write, and written first.
+            coalescer.write_word_32(0x2000_0100, 0x11).unwrap();
+            coalescer.write_word_32(0x2000_0104, 0x22).unwrap();
+            coalescer.write_barrier().unwrap();
+            coalescer.write_word_32(0x2000_00fc, 1).unwrap();
+            coalescer.flush().unwrap();
+        }
+
+        assert_eq!(
+            memory.ops,
+            vec![
+                Op::Write32(0x2000_0100, vec![0x11, 0x22]),
+                Op::Barrier,
+                Op::Write32(0x2000_00fc, vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn device_writes_are_not_coalesced() {
+        let mut memory = RecordingMemory::default();
+
+        {
+            let mut coalescer = WriteCoalescer::new(&mut memory, &memory_map());
+
+            coalescer.write_word_32(0x4000_0004, 2).unwrap();
+            coalescer.write_word_32(0x4000_0000, 1).unwrap();
+            coalescer.write_word_32(0x2000_0000, 3).unwrap();
+            coalescer.write_word_8(0x4000_0008, 4).unwrap();
+            coalescer.write_word_32(0x2000_0004, 5).unwrap();
+        }
+
+        assert_eq!(
+            memory.ops,
+            vec![
+                Op::Write32(0x4000_0004, vec![2]),
+                Op::Write32(0x4000_0000, vec![1]),
+                Op::Write32(0x2000_0000, vec![3]),
+                Op::Write8(0x4000_0008, vec![4]),
+                Op::Write32(0x2000_0004, vec![5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn volatile_writes_are_not_coalesced() {
+        let mut memory = RecordingMemory::default();
+
+        let mut volatile = VolatileRanges::new(&memory_map());
+        volatile.mark(0x2000_0200..0x2000_0208);
+
+        {
+            let mut coalescer =
+                WriteCoalescer::with_volatile_ranges(&mut memory, &memory_map(), volatile);
+
+            // A mailbox in RAM, whose status word must be written after its data.
+            coalescer.write_word_32(0x2000_0204, 2).unwrap();
+            coalescer.write_word_32(0x2000_0200, 1).unwrap();
+            coalescer.write_word_32(0x2000_0104, 4).unwrap();
+            coalescer.write_word_32(0x2000_0100, 3).unwrap();
+            // Partially volatile, so it is written as is.
+            coalescer.write_8(0x2000_01fe, &[5, 6, 7, 8]).unwrap();
+        }
+
+        assert_eq!(
+            memory.ops,
+            vec![
+                Op::Write32(0x2000_0204, vec![2]),
+                Op::Write32(0x2000_0200, vec![1]),
+                Op::Write32(0x2000_0100, vec![3, 4]),
+                Op::Write8(0x2000_01fe, vec![5, 6, 7, 8]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Memory which is protected by ECC, see [`EccMemory`].
+
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::config::MemoryRegion;
+
+/// The size of the words over which the ECC of an ECC region is computed.
+pub(crate) const ECC_WORD_SIZE: u64 = 4;
+
+/// The RAM regions of a target which are protected by ECC, and the words of them which were
+/// initialized during the session.
+///
+/// Writing less than a word to a word whose ECC wasn't initialized raises a bus fault, so
+/// the cores only write these regions with whole, aligned words. A write of less than a word
+/// is widened to the word with a read-modify-write, which is only done once the word was
+/// written as a whole during the session, see
+/// [`Core::ecc_initialize`](crate::Core::ecc_initialize).
+///
+/// The regions are shared by all cores of a session, as a word initialized by one core is
+/// initialized for all of them.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EccMemory(Arc<Mutex<Vec<EccRegion>>>);
+
+#[derive(Debug)]
+struct EccRegion {
+    range: Range<u64>,
+    /// The initialized words, as sorted, disjoint and word aligned ranges.
+    initialized: Vec<Range<u64>>,
+}
+
+impl EccMemory {
+    /// The ECC regions of `memory_map`.
+    pub(crate) fn new(memory_map: &[MemoryRegion]) -> Self {
+        let regions = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(region) if region.ecc => Some(EccRegion {
+                    range: region.range.clone(),
+                    initialized: Vec::new(),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Self(Arc::new(Mutex::new(regions)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<EccRegion>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns true if any of the `len` bytes at `address` is protected by ECC.
+    pub(crate) fn overlaps(&self, address: u64, len: usize) -> bool {
+        let end = address + len as u64;
+
+        self.lock()
+            .iter()
+            .any(|region| region.range.start < end && address < region.range.end)
+    }
+
+    /// Returns the words protected by ECC which contain any of the bytes of `range` and
+    /// weren't initialized yet, as word aligned ranges.
+    pub(crate) fn uninitialized(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        let range = word_aligned(range);
+        let mut uninitialized = Vec::new();
+
+        for region in self.lock().iter() {
+            let start = range.start.max(region.range.start);
+            let end = range.end.min(region.range.end);
+            if start >= end {
+                continue;
+            }
+
+            let mut next = start;
+            for initialized in &region.initialized {
+                if initialized.end <= next {
+                    continue;
+                }
+                if initialized.start >= end {
+                    break;
+                }
+                if initialized.start > next {
+                    uninitialized.push(word_aligned(next..initialized.start));
+                }
+                next = initialized.end;
+            }
+            if next < end {
+                uninitialized.push(word_aligned(next..end));
+            }
+        }
+
+        uninitialized
+    }
+
+    /// Record that the words protected by ECC which lie entirely in `range` were written.
+    pub(crate) fn mark_initialized(&self, range: Range<u64>) {
+        let start = align_up(range.start);
+        let end = range.end & !(ECC_WORD_SIZE - 1);
+
+        for region in self.lock().iter_mut() {
+            let start = start.max(region.range.start);
+            let end = end.min(region.range.end);
+            if start >= end {
+                continue;
+            }
+
+            let mut merged = start..end;
+            let mut initialized = Vec::with_capacity(region.initialized.len() + 1);
+            for range in region.initialized.drain(..) {
+                if range.end < merged.start || merged.end < range.start {
+                    initialized.push(range);
+                } else {
+                    merged = merged.start.min(range.start)..merged.end.max(range.end);
+                }
+            }
+            initialized.push(merged);
+            initialized.sort_by_key(|range| range.start);
+
+            region.initialized = initialized;
+        }
+    }
+}
+
+/// Returns the words which are only partially covered by a write of `len` bytes at
+/// `address`, at most the first and the last one.
+pub(crate) fn partial_words(address: u64, len: usize) -> Vec<Range<u64>> {
+    let end = address + len as u64;
+    let mut words = Vec::new();
+
+    if len == 0 {
+        return words;
+    }
+
+    let first = address & !(ECC_WORD_SIZE - 1);
+    if address != first || end < first + ECC_WORD_SIZE {
+        words.push(first..first + ECC_WORD_SIZE);
+    }
+
+    let last = (end - 1) & !(ECC_WORD_SIZE - 1);
+    if end != last + ECC_WORD_SIZE && (words.is_empty() || last != first) {
+        words.push(last..last + ECC_WORD_SIZE);
+    }
+
+    words
+}
+
+/// Widen `range` to whole words.
+pub(crate) fn word_aligned(range: Range<u64>) -> Range<u64> {
+    range.start & !(ECC_WORD_SIZE - 1)..align_up(range.end)
+}
+
+fn align_up(address: u64) -> u64 {
+    (address + ECC_WORD_SIZE - 1) & !(ECC_WORD_SIZE - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::RamRegion;
+
+    fn ecc_memory() -> EccMemory {
+        EccMemory::new(&[MemoryRegion::Ram(RamRegion {
+            name: None,
+            range: 0x2000_0000..0x2000_1000,
+            is_boot_memory: false,
+            cores: vec!["main".into()],
+            volatile: false,
+            ecc: true,
+        })])
+    }
+
+    #[test]
+    fn initialized_words_are_tracked() {
+        let ecc = ecc_memory();
+        assert!(ecc.overlaps(0x1fff_fffc, 8));
+        assert!(!ecc.overlaps(0x2000_1000, 4));
+
+        assert_eq!(
+            ecc.uninitialized(0x2000_0002..0x2000_0006),
+            [0x2000_0000..0x2000_0008]
+        );
+
+        // Only the whole words of a write are initialized.
+        ecc.mark_initialized(0x2000_0002..0x2000_000a);
+        assert_eq!(
+            ecc.uninitialized(0x2000_0000..0x2000_0010),
+            [0x2000_0000..0x2000_0004, 0x2000_0008..0x2000_0010]
+        );
+
+        ecc.mark_initialized(0x2000_0008..0x2000_0010);
+        ecc.mark_initialized(0x1fff_fff0..0x2000_0004);
+        assert!(ecc.uninitialized(0x2000_0000..0x2000_0010).is_empty());
+        assert_eq!(
+            ecc.uninitialized(0x2000_0000..0x2000_2000),
+            [0x2000_0010..0x2000_1000]
+        );
+    }
+
+    #[test]
+    fn partial_words_are_the_edges_of_a_write() {
+        assert!(partial_words(0x2000_0000, 8).is_empty());
+        assert_eq!(partial_words(0x2000_0001, 1), [0x2000_0000..0x2000_0004]);
+        assert_eq!(
+            partial_words(0x2000_0002, 8),
+            [0x2000_0000..0x2000_0004, 0x2000_0008..0x2000_000c]
+        );
+        assert_eq!(partial_words(0x2000_0000, 6), [0x2000_0004..0x2000_0008]);
+    }
+}
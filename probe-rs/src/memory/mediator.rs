@@ -0,0 +1,447 @@
+//! Memory regions whose accesses have to be prepared, see [`AccessMediator`].
+//!
+//! External memories are often only accessible while their controller is in a specific
+//! state, e.g. QSPI flash which reads as garbage unless the controller is in memory-mapped
+//! mode. A mediator prepares the controller before the first access to its region, and
+//! restores it when the region is no longer accessed. Preparations are kept across
+//! accesses, so that a dump in many chunks prepares the controller only once.
+
+use std::{
+    fmt,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::config::{MediatedRegion, MediatorKind};
+use crate::{DebugProbeError, Error, MemoryInterface};
+
+/// The direction of an access to a mediated region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessDirection {
+    /// Reading the region.
+    Read,
+    /// Writing the region.
+    Write,
+}
+
+/// Prepares the accesses to a memory region, e.g. by switching its controller to
+/// memory-mapped mode.
+///
+/// Mediators are associated with a region in the target description, or at runtime with
+/// [`Session::add_access_mediator`](crate::Session::add_access_mediator). The memory
+/// layer of a core calls them before an access to the region. The returned
+/// [`PreparedAccess`] is kept for further accesses in the same direction, and released
+/// before the region is accessed in the other direction, before the core runs, or with
+/// [`Core::release_access_mediators`](crate::Core::release_access_mediators).
+pub trait AccessMediator: fmt::Debug + Send + Sync {
+    /// Prepare reads of `region`.
+    fn prepare_read(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error>;
+
+    /// Prepare writes to `region`.
+    fn prepare_write(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error>;
+}
+
+/// A prepared access to a mediated region, returned by an [`AccessMediator`].
+pub trait PreparedAccess: fmt::Debug + Send {
+    /// True if the accesses are routed through [`PreparedAccess::read`] and
+    /// [`PreparedAccess::write`], e.g. through the registers of the controller, instead
+    /// of accessing the region on the bus.
+    fn routes_accesses(&self) -> bool {
+        false
+    }
+
+    /// Read `data` at `address` through the controller.
+    fn read(
+        &mut self,
+        _memory: &mut dyn MemoryInterface,
+        address: u64,
+        _data: &mut [u8],
+    ) -> Result<(), Error> {
+        Err(Error::MediatedAccessUnsupported {
+            address,
+            access: "routed reads",
+        })
+    }
+
+    /// Write `data` at `address` through the controller.
+    fn write(
+        &mut self,
+        _memory: &mut dyn MemoryInterface,
+        address: u64,
+        _data: &[u8],
+    ) -> Result<(), Error> {
+        Err(Error::MediatedAccessUnsupported {
+            address,
+            access: "routed writes",
+        })
+    }
+
+    /// Restore the state the controller had before the access was prepared.
+    fn release(self: Box<Self>, memory: &mut dyn MemoryInterface) -> Result<(), Error>;
+}
+
+/// The mediated regions of a target, see [`AccessMediator`].
+#[derive(Debug, Clone, Default)]
+pub struct MediatedRegions {
+    regions: Vec<(Range<u64>, Arc<dyn AccessMediator>)>,
+}
+
+impl MediatedRegions {
+    /// The regions of a target description, with the mediators implemented by probe-rs.
+    pub fn new(regions: &[MediatedRegion]) -> Self {
+        Self {
+            regions: regions
+                .iter()
+                .map(|region| (region.range.clone(), mediator(&region.mediator)))
+                .collect(),
+        }
+    }
+
+    /// Mediate the accesses to `range` with `mediator`.
+    ///
+    /// Regions which are added later take precedence over earlier ones.
+    pub fn add(&mut self, range: Range<u64>, mediator: Arc<dyn AccessMediator>) {
+        if !range.is_empty() {
+            self.regions.push((range, mediator));
+        }
+    }
+
+    /// The ranges of the mediated regions, in the order in which they were added.
+    pub fn ranges(&self) -> impl Iterator<Item = &Range<u64>> {
+        self.regions.iter().map(|(range, _)| range)
+    }
+
+    /// The index of the region which contains any of the `len` bytes at `address`.
+    fn find(&self, address: u64, len: usize) -> Option<usize> {
+        let end = address + len as u64;
+
+        self.regions
+            .iter()
+            .rposition(|(range, _)| range.start < end && address < range.end)
+    }
+}
+
+/// The mediator implemented by probe-rs for `kind`.
+fn mediator(kind: &MediatorKind) -> Arc<dyn AccessMediator> {
+    match *kind {
+        MediatorKind::Stm32Quadspi {
+            registers,
+            memory_mapped_ccr,
+            indirect_write_ccr,
+        } => Arc::new(Stm32Quadspi {
+            registers,
+            memory_mapped_ccr,
+            indirect_write_ccr,
+        }),
+    }
+}
+
+/// The prepared access which is kept between the accesses of a core.
+#[derive(Debug)]
+struct ActiveAccess {
+    region: usize,
+    direction: AccessDirection,
+    access: Box<dyn PreparedAccess>,
+}
+
+/// The mediated regions of a core, with the access which is currently prepared.
+#[derive(Debug, Default)]
+pub(crate) struct Mediation {
+    regions: MediatedRegions,
+    active: Option<ActiveAccess>,
+}
+
+impl Mediation {
+    pub(crate) fn set_regions(&mut self, regions: MediatedRegions) {
+        self.regions = regions;
+    }
+
+    /// Returns true if an access to the `len` bytes at `address` in `direction` needs a
+    /// preparation which isn't active yet.
+    pub(crate) fn needs_preparation(
+        &self,
+        direction: AccessDirection,
+        address: u64,
+        len: usize,
+    ) -> bool {
+        match self.regions.find(address, len) {
+            Some(region) => !matches!(
+                &self.active,
+                Some(active) if active.region == region && active.direction == direction
+            ),
+            None => false,
+        }
+    }
+
+    /// Prepare an access to the `len` bytes at `address`, unless it is already prepared.
+    ///
+    /// Returns true if the access has to be routed through [`Mediation::read`] or
+    /// [`Mediation::write`]. Accesses outside of the mediated regions are left alone.
+    pub(crate) fn prepare(
+        &mut self,
+        memory: &mut dyn MemoryInterface,
+        direction: AccessDirection,
+        address: u64,
+        len: usize,
+    ) -> Result<bool, Error> {
+        let region = match self.regions.find(address, len) {
+            Some(region) => region,
+            None => return Ok(false),
+        };
+
+        if !self.needs_preparation(direction, address, len) {
+            return Ok(self.routes_accesses());
+        }
+
+        self.release(memory)?;
+
+        let (range, mediator) = &self.regions.regions[region];
+        let access = match direction {
+            AccessDirection::Read => mediator.prepare_read(memory, range)?,
+            AccessDirection::Write => mediator.prepare_write(memory, range)?,
+        };
+
+        self.active = Some(ActiveAccess {
+            region,
+            direction,
+            access,
+        });
+
+        Ok(self.routes_accesses())
+    }
+
+    fn routes_accesses(&self) -> bool {
+        matches!(&self.active, Some(active) if active.access.routes_accesses())
+    }
+
+    /// Read `data` at `address` through the prepared access.
+    pub(crate) fn read(
+        &mut self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), Error> {
+        match &mut self.active {
+            Some(active) => active.access.read(memory, address, data),
+            None => memory.read_8(address, data),
+        }
+    }
+
+    /// Write `data` at `address` through the prepared access.
+    pub(crate) fn write(
+        &mut self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        match &mut self.active {
+            Some(active) => active.access.write(memory, address, data),
+            None => memory.write_8(address, data),
+        }
+    }
+
+    /// Release the prepared access, if there is one.
+    pub(crate) fn release(&mut self, memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        match self.active.take() {
+            Some(active) => active.access.release(memory),
+            None => Ok(()),
+        }
+    }
+
+    /// Drop the prepared access without restoring the controller, because it was reset.
+    pub(crate) fn forget(&mut self) {
+        self.active = None;
+    }
+}
+
+/// The mediator of the QUADSPI controller of STM32 chips, e.g. the STM32F7, L4 and H7.
+///
+/// Reads switch the controller to memory-mapped mode with the configured `CCR` value. If
+/// the controller already is in memory-mapped mode, it is left alone. Writes are routed
+/// through the data register in indirect write mode, as the memory-mapped region is read
+/// only. After the accesses, the previous `CCR` value is restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stm32Quadspi {
+    /// The address of the registers of the controller.
+    pub registers: u64,
+    /// The value of `CCR` for memory-mapped reads. The functional mode bits are set.
+    pub memory_mapped_ccr: u32,
+    /// The value of `CCR` for indirect writes, or `None` if the memory can't be written.
+    /// The functional mode bits are cleared.
+    pub indirect_write_ccr: Option<u32>,
+}
+
+impl Stm32Quadspi {
+    const CR: u64 = 0x00;
+    const SR: u64 = 0x08;
+    const FCR: u64 = 0x0c;
+    const DLR: u64 = 0x10;
+    const CCR: u64 = 0x14;
+    const AR: u64 = 0x18;
+    const DR: u64 = 0x20;
+
+    const CR_ABORT: u32 = 1 << 1;
+    const SR_TCF: u32 = 1 << 1;
+    const SR_FTF: u32 = 1 << 2;
+    const SR_BUSY: u32 = 1 << 5;
+    const FCR_CTCF: u32 = 1 << 1;
+
+    const FMODE_MASK: u32 = 0b11 << 26;
+    const FMODE_MEMORY_MAPPED: u32 = 0b11 << 26;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Abort the current operation, which is the only way to leave memory-mapped mode, and
+    /// wait until the controller is idle.
+    fn abort(&self, memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        let cr = memory.read_word_32(self.registers + Self::CR)?;
+        memory.write_word_32(self.registers + Self::CR, cr | Self::CR_ABORT)?;
+
+        self.wait_for_status(memory, Self::SR_BUSY, 0)
+    }
+
+    /// Wait until the bits of `mask` in `SR` have the value `expected`.
+    fn wait_for_status(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        mask: u32,
+        expected: u32,
+    ) -> Result<(), Error> {
+        let start = Instant::now();
+
+        while start.elapsed() < Self::TIMEOUT {
+            if memory.read_word_32(self.registers + Self::SR)? & mask == expected {
+                return Ok(());
+            }
+        }
+
+        Err(Error::Probe(DebugProbeError::Timeout))
+    }
+
+    fn prepare(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+        ccr: Option<u32>,
+    ) -> Result<Box<dyn PreparedAccess>, Error> {
+        let previous_ccr = memory.read_word_32(self.registers + Self::CCR)?;
+
+        let mut access = QuadspiAccess {
+            quadspi: *self,
+            memory_start: region.start,
+            previous_ccr: None,
+            write_ccr: None,
+        };
+
+        match ccr {
+            // Reads in memory-mapped mode don't need any change.
+            None if previous_ccr & Self::FMODE_MASK == Self::FMODE_MEMORY_MAPPED => {}
+            None => {
+                self.abort(memory)?;
+                access.previous_ccr = Some(previous_ccr);
+
+                memory.write_word_32(
+                    self.registers + Self::CCR,
+                    self.memory_mapped_ccr | Self::FMODE_MEMORY_MAPPED,
+                )?;
+            }
+            Some(write_ccr) => {
+                self.abort(memory)?;
+                access.previous_ccr = Some(previous_ccr);
+                access.write_ccr = Some(write_ccr & !Self::FMODE_MASK);
+            }
+        }
+
+        Ok(Box::new(access))
+    }
+}
+
+impl AccessMediator for Stm32Quadspi {
+    fn prepare_read(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error> {
+        self.prepare(memory, region, None)
+    }
+
+    fn prepare_write(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error> {
+        match self.indirect_write_ccr {
+            Some(ccr) => self.prepare(memory, region, Some(ccr)),
+            None => Err(Error::MediatedAccessUnsupported {
+                address: region.start,
+                access: "writes",
+            }),
+        }
+    }
+}
+
+/// An access prepared by [`Stm32Quadspi`].
+#[derive(Debug)]
+struct QuadspiAccess {
+    quadspi: Stm32Quadspi,
+    /// The address at which the memory is mapped, which is address 0 of the memory.
+    memory_start: u64,
+    /// The `CCR` value to restore, if it was changed.
+    previous_ccr: Option<u32>,
+    /// The `CCR` value for routed writes, without the functional mode bits.
+    write_ccr: Option<u32>,
+}
+
+impl PreparedAccess for QuadspiAccess {
+    fn routes_accesses(&self) -> bool {
+        self.write_ccr.is_some()
+    }
+
+    fn write(
+        &mut self,
+        memory: &mut dyn MemoryInterface,
+        address: u64,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let (write_ccr, registers) = match self.write_ccr {
+            Some(ccr) if !data.is_empty() => (ccr, self.quadspi.registers),
+            _ => return Ok(()),
+        };
+
+        memory.write_word_32(registers + Stm32Quadspi::DLR, data.len() as u32 - 1)?;
+        memory.write_word_32(registers + Stm32Quadspi::CCR, write_ccr)?;
+        memory.write_word_32(
+            registers + Stm32Quadspi::AR,
+            (address - self.memory_start) as u32,
+        )?;
+
+        for byte in data {
+            self.quadspi
+                .wait_for_status(memory, Stm32Quadspi::SR_FTF, Stm32Quadspi::SR_FTF)?;
+            memory.write_word_8(registers + Stm32Quadspi::DR, *byte)?;
+        }
+
+        self.quadspi
+            .wait_for_status(memory, Stm32Quadspi::SR_TCF, Stm32Quadspi::SR_TCF)?;
+        memory.write_word_32(registers + Stm32Quadspi::FCR, Stm32Quadspi::FCR_CTCF)
+    }
+
+    fn release(self: Box<Self>, memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        match self.previous_ccr {
+            Some(ccr) => {
+                self.quadspi.abort(memory)?;
+                memory.write_word_32(self.quadspi.registers + Stm32Quadspi::CCR, ccr)
+            }
+            None => Ok(()),
+        }
+    }
+}
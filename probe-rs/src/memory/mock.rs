@@ -0,0 +1,135 @@
+//! A mocked memory for the tests of code which only needs a [`MemoryInterface`].
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::MemoryInterface;
+use crate::error::Error;
+
+/// Sparse memory, which reads as `0` until it is written.
+///
+/// The reads of a range can be made stale, see [`MockMemory::set_stale_reads`].
+#[derive(Debug, Default)]
+pub(crate) struct MockMemory {
+    memory: HashMap<u64, u8>,
+    /// The range whose reads are stale.
+    stale: Option<Range<u64>>,
+    /// The data of the previous read.
+    last_read: Vec<u8>,
+    /// The previous read was in the stale range.
+    stale_selected: bool,
+}
+
+impl MockMemory {
+    /// Make the first read of `range` after a read of other memory return the data of that
+    /// read, like the flash behind the system bus of some RISC-V chips.
+    pub(crate) fn set_stale_reads(&mut self, range: Range<u64>) {
+        self.stale = Some(range);
+    }
+
+    /// Read `len` bytes at `address` with a single access.
+    fn access(&mut self, address: u64, len: usize) -> Vec<u8> {
+        let value: Vec<u8> = (address..address + len as u64)
+            .map(|address| self.memory.get(&address).copied().unwrap_or(0))
+            .collect();
+
+        let is_stale = matches!(&self.stale, Some(stale) if stale.contains(&address));
+        let mut result = if is_stale && !self.stale_selected {
+            self.last_read.clone()
+        } else {
+            value.clone()
+        };
+        result.resize(len, 0);
+
+        self.stale_selected = is_stale;
+        self.last_read = value;
+
+        result
+    }
+
+    fn store(&mut self, address: u64, data: &[u8]) {
+        for (address, byte) in (address..).zip(data) {
+            self.memory.insert(address, *byte);
+        }
+    }
+}
+
+impl MemoryInterface for MockMemory {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        true
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, Error> {
+        let bytes = self.access(address, 8);
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, Error> {
+        let bytes = self.access(address, 4);
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn read_word_8(&mut self, address: u64) -> Result<u8, Error> {
+        Ok(self.access(address, 1)[0])
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), Error> {
+        let bytes = self.access(address, data.len() * 8);
+        for (word, bytes) in data.iter_mut().zip(bytes.chunks_exact(8)) {
+            *word = u64::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), Error> {
+        let bytes = self.access(address, data.len() * 4);
+        for (word, bytes) in data.iter_mut().zip(bytes.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), Error> {
+        let bytes = self.access(address, data.len());
+        data.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), Error> {
+        self.store(address, &data.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), Error> {
+        self.store(address, &data.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), Error> {
+        self.store(address, &[data]);
+        Ok(())
+    }
+
+    fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), Error> {
+        for (index, word) in (0..).zip(data) {
+            self.write_word_64(address + index * 8, *word)?;
+        }
+        Ok(())
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), Error> {
+        for (index, word) in (0..).zip(data) {
+            self.write_word_32(address + index * 4, *word)?;
+        }
+        Ok(())
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), Error> {
+        self.store(address, data);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
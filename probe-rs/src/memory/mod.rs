@@ -5,11 +5,45 @@ use crate::architecture::arm::{
 };
 use crate::{
     architecture::arm::{communication_interface::Initialized, ArmCommunicationInterface},
-    error,
+    error, VendorCommand, VendorCommandIndex,
 };
 
-use anyhow::anyhow;
-use anyhow::Result;
+use std::ops::Range;
+use std::time::Duration;
+
+mod coalesce;
+pub(crate) mod ecc;
+mod mediator;
+#[cfg(test)]
+pub(crate) mod mock;
+mod retry;
+mod target_bytes;
+mod target_writable;
+mod volatile;
+
+pub use coalesce::WriteCoalescer;
+pub(crate) use ecc::EccMemory;
+pub(crate) use mediator::Mediation;
+pub use mediator::{
+    AccessDirection, AccessMediator, MediatedRegions, PreparedAccess, Stm32Quadspi,
+};
+pub use retry::RetryPolicy;
+pub use target_bytes::{align_up, Endianness, FromTargetBytes, PartialRead, ReadEnd};
+pub(crate) use target_bytes::{read_c_string, read_slice_prefixed, read_value};
+pub use target_writable::TargetWritableFlash;
+pub use volatile::VolatileRanges;
+
+/// The widths of the writes a [`MemoryInterface`] does with a single access of that width,
+/// see [`MemoryInterface::native_write_widths`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteWidths {
+    /// 8-bit writes are native.
+    pub bits_8: bool,
+    /// 32-bit writes are native.
+    pub bits_32: bool,
+    /// 64-bit writes are native.
+    pub bits_64: bool,
+}
 
 /// An interface to be implemented for drivers that allow target memory access.
 pub trait MemoryInterface {
@@ -20,6 +54,19 @@ pub trait MemoryInterface {
     /// picking the fastest bulk data transfer method.
     fn supports_native_64bit_access(&mut self) -> bool;
 
+    /// The widths of the writes which are done with a single access of that width.
+    ///
+    /// Writes of other widths are emulated, e.g. a byte write with a read-modify-write of the
+    /// word which contains it, which is invisible to the caller but not to write-sensitive
+    /// registers. By default, byte and word writes are native.
+    fn native_write_widths(&mut self) -> WriteWidths {
+        WriteWidths {
+            bits_8: true,
+            bits_32: true,
+            bits_64: self.supports_native_64bit_access(),
+        }
+    }
+
     /// Read a 64bit word of at `address`.
     ///
     /// The address where the read should be performed at has to be word aligned.
@@ -60,9 +107,10 @@ pub trait MemoryInterface {
         // provide an implementation that avoids heap allocation and endian
         // conversions. Must be overridden for big endian targets.
         if data.len() % 8 != 0 {
-            return Err(error::Error::Other(anyhow!(
-                "Call to read_mem_64bit with data.len() not a multiple of 8"
-            )));
+            return Err(error::Error::InvalidBufferLength {
+                len: data.len(),
+                multiple: 8,
+            });
         }
         let mut buffer = vec![0u64; data.len() / 8];
         self.read_64(address, &mut buffer)?;
@@ -80,9 +128,10 @@ pub trait MemoryInterface {
         // provide an implementation that avoids heap allocation and endian
         // conversions. Must be overridden for big endian targets.
         if data.len() % 4 != 0 {
-            return Err(error::Error::Other(anyhow!(
-                "Call to read_mem_32bit with data.len() not a multiple of 4"
-            )));
+            return Err(error::Error::InvalidBufferLength {
+                len: data.len(),
+                multiple: 4,
+            });
         }
         let mut buffer = vec![0u32; data.len() / 4];
         self.read_32(address, &mut buffer)?;
@@ -150,16 +199,33 @@ pub trait MemoryInterface {
     /// can be called.  Takes no arguments, but may return failure if a batched
     /// operation fails.
     fn flush(&mut self) -> Result<(), error::Error>;
+
+    /// Insert an ordering constraint between the writes issued before and after this call.
+    ///
+    /// All writes issued before the barrier reach the target before any write issued after it,
+    /// and no writes are merged across the barrier. Unlike [`MemoryInterface::flush`], this
+    /// doesn't force queued writes to be executed immediately.
+    ///
+    /// Interfaces which never reorder or merge writes don't have to do anything. This is the
+    /// case for the transfer queues of the probes, which always execute transfers in order.
+    /// See [`WriteCoalescer`] for an interface which reorders writes.
+    fn write_barrier(&mut self) -> Result<(), error::Error> {
+        Ok(())
+    }
 }
 
 impl<T> MemoryInterface for &mut T
 where
-    T: MemoryInterface,
+    T: MemoryInterface + ?Sized,
 {
     fn supports_native_64bit_access(&mut self) -> bool {
         (*self).supports_native_64bit_access()
     }
 
+    fn native_write_widths(&mut self) -> WriteWidths {
+        (*self).native_write_widths()
+    }
+
     fn read_word_64(&mut self, address: u64) -> Result<u64, error::Error> {
         (*self).read_word_64(address)
     }
@@ -208,6 +274,10 @@ where
         (*self).write_8(address, data)
     }
 
+    fn write_barrier(&mut self) -> Result<(), error::Error> {
+        (*self).write_barrier()
+    }
+
     fn flush(&mut self) -> Result<(), error::Error> {
         (*self).flush()
     }
@@ -257,6 +327,41 @@ impl<'probe> Memory<'probe> {
         Ok(buff[0])
     }
 
+    /// Reads the 32 bit word at `address` repeatedly, until `value & mask == expected`, or
+    /// `timeout` passed.
+    ///
+    /// The word is polled by the probe, see
+    /// [`ProbeCapabilities::poll_offload`](crate::ProbeCapabilities::poll_offload). Returns
+    /// true if the word matched before the timeout.
+    pub fn poll_word_32(
+        &mut self,
+        address: u64,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<bool, error::Error> {
+        self.inner
+            .poll_word_32(self.ap_sel, address, mask, expected, timeout)
+    }
+
+    /// Queue `command` behind the accesses issued before it, see
+    /// [`Core::enqueue_probe_command`](crate::Core::enqueue_probe_command).
+    pub fn enqueue_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, error::Error> {
+        self.inner.enqueue_vendor_command(command)
+    }
+
+    /// Returns the response of the vendor command at `index`, see
+    /// [`Core::probe_command_response`](crate::Core::probe_command_response).
+    pub fn vendor_command_response(
+        &mut self,
+        index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, error::Error> {
+        self.inner.vendor_command_response(index)
+    }
+
     /// Reads `data.len()` 64 bit words from `address` into `data`.
     pub fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), error::Error> {
         self.inner.read_64(self.ap_sel, address, data)
@@ -309,6 +414,15 @@ impl<'probe> Memory<'probe> {
         self.inner.flush()
     }
 
+    /// Insert an ordering constraint between the writes issued before and after this call.
+    ///
+    /// See [`MemoryInterface::write_barrier`].
+    pub fn write_barrier(&mut self) -> Result<(), error::Error> {
+        // The DAP transfers are queued and executed strictly in order, so the
+        // barrier is already satisfied.
+        Ok(())
+    }
+
     /// Tries to borrow the underlying [`ArmCommunicationInterface`].
     pub fn get_arm_interface(
         &mut self,
@@ -327,13 +441,94 @@ impl<'probe> Memory<'probe> {
     }
 }
 
+impl MemoryInterface for Memory<'_> {
+    fn supports_native_64bit_access(&mut self) -> bool {
+        Memory::supports_native_64bit_access(self)
+    }
+
+    fn native_write_widths(&mut self) -> WriteWidths {
+        WriteWidths {
+            bits_8: self.inner.supports_native_8bit_writes(),
+            bits_32: true,
+            bits_64: self.inner.supports_native_64bit_access(),
+        }
+    }
+
+    fn read_word_64(&mut self, address: u64) -> Result<u64, error::Error> {
+        Memory::read_word_64(self, address)
+    }
+
+    fn read_word_32(&mut self, address: u64) -> Result<u32, error::Error> {
+        Memory::read_word_32(self, address)
+    }
+
+    fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error> {
+        Memory::read_word_8(self, address)
+    }
+
+    fn read_64(&mut self, address: u64, data: &mut [u64]) -> Result<(), error::Error> {
+        Memory::read_64(self, address, data)
+    }
+
+    fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), error::Error> {
+        Memory::read_32(self, address, data)
+    }
+
+    fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+        Memory::read_8(self, address, data)
+    }
+
+    fn write_word_64(&mut self, address: u64, data: u64) -> Result<(), error::Error> {
+        Memory::write_word_64(self, address, data)
+    }
+
+    fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), error::Error> {
+        Memory::write_word_32(self, address, data)
+    }
+
+    fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), error::Error> {
+        Memory::write_word_8(self, address, data)
+    }
+
+    fn write_64(&mut self, address: u64, data: &[u64]) -> Result<(), error::Error> {
+        Memory::write_64(self, address, data)
+    }
+
+    fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), error::Error> {
+        Memory::write_32(self, address, data)
+    }
+
+    fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), error::Error> {
+        Memory::write_8(self, address, data)
+    }
+
+    fn write_barrier(&mut self) -> Result<(), error::Error> {
+        Memory::write_barrier(self)
+    }
+
+    fn flush(&mut self) -> Result<(), error::Error> {
+        Memory::flush(self)
+    }
+}
+
 // Helper functions to validate address space constraints
 
 /// Validate that an input address is valid for 32-bit only systems
 pub(crate) fn valid_32_address(address: u64) -> Result<u32, error::Error> {
     let address: u32 = address
         .try_into()
-        .map_err(|_| anyhow!("Address {:#08x} out of range", address))?;
+        .map_err(|_| error::Error::AddressOutOfRange(address))?;
 
     Ok(address)
 }
+
+/// Returns true if the `len` bytes at `address` lie within one of `ranges`.
+///
+/// This is used to tell RAM, whose accesses can be merged or repeated, from device memory.
+pub(crate) fn within(ranges: &[Range<u64>], address: u64, len: usize) -> bool {
+    let end = address + len as u64;
+
+    ranges
+        .iter()
+        .any(|range| range.start <= address && end <= range.end)
+}
@@ -0,0 +1,179 @@
+//! Retrying failed memory accesses, see [`Core::with_retry_policy`](crate::Core::with_retry_policy).
+//!
+//! The probe drivers already repeat transfers which fail because of a WAIT response or a
+//! parity error. A [`RetryPolicy`] works above them, and decides whether a memory access
+//! which still failed is repeated as a whole. Only accesses which can be repeated without
+//! side effects are retried: reads of RAM and flash, and writes to RAM. Accesses to all
+//! other addresses are treated as accesses to device memory, and are never repeated.
+
+use std::{fmt, thread, time::Duration};
+
+use crate::Error;
+
+/// How often a failed memory access is attempted, see
+/// [`Core::with_retry_policy`](crate::Core::with_retry_policy).
+///
+/// The default policy attempts every access once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts of an access, including the first one.
+    pub max_attempts: u32,
+    /// The delay before the first retry. It is doubled for every further retry.
+    pub backoff: Duration,
+    /// Give up as soon as the link to the target fails, instead of using up the remaining
+    /// attempts. Each attempt on a dead link only ends with a timeout.
+    pub give_up_fast: bool,
+}
+
+impl RetryPolicy {
+    /// A policy which attempts every access once.
+    pub const fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+            give_up_fast: false,
+        }
+    }
+
+    /// A policy which attempts every access up to `max_attempts` times, and waits `backoff`
+    /// before the first retry.
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff,
+            give_up_fast: false,
+        }
+    }
+
+    /// A policy for pollers, e.g. of a watch window, which rather drop a sample than wait for
+    /// it. Every access is attempted once.
+    pub const fn watch_window() -> Self {
+        Self {
+            give_up_fast: true,
+            ..Self::none()
+        }
+    }
+
+    /// A policy for accesses which have to succeed, e.g. verifying flashed memory. Every
+    /// access is attempted up to 5 times.
+    pub const fn verify() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: Duration::from_millis(1),
+            give_up_fast: false,
+        }
+    }
+
+    /// Run `access` until it succeeds or the policy gives up, and return its result with the
+    /// number of attempts.
+    ///
+    /// If `repeatable` is false, the access has side effects, and it is attempted only once.
+    pub(crate) fn run<T>(
+        &self,
+        repeatable: bool,
+        mut access: impl FnMut() -> Result<T, Error>,
+    ) -> (Result<T, Error>, u32) {
+        let mut attempts = 0;
+        let mut backoff = self.backoff;
+
+        loop {
+            attempts += 1;
+
+            match access() {
+                Err(error)
+                    if repeatable && attempts < self.max_attempts && self.is_retryable(&error) =>
+                {
+                    log::debug!(
+                        "Memory access failed in attempt {} of {}: {}",
+                        attempts,
+                        self.max_attempts,
+                        error
+                    );
+
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                result => return (result, attempts),
+            }
+        }
+    }
+
+    fn is_retryable(&self, error: &Error) -> bool {
+        match error {
+            Error::Interrupted => false,
+            Error::TargetLost(_) => !self.give_up_fast,
+            error => !(self.give_up_fast && error.link_failure().is_some()),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl fmt::Display for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "up to {} attempts", self.max_attempts)?;
+
+        if !self.backoff.is_zero() {
+            write!(f, ", {:?} backoff", self.backoff)?;
+        }
+
+        if self.give_up_fast {
+            write!(f, ", give up fast")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn failing(failures: u32) -> impl FnMut() -> Result<u32, Error> {
+        let mut calls = 0;
+
+        move || {
+            calls += 1;
+
+            if calls <= failures {
+                Err(Error::Other(anyhow::anyhow!("Fault response")))
+            } else {
+                Ok(calls)
+            }
+        }
+    }
+
+    #[test]
+    fn repeatable_accesses_are_retried() {
+        let policy = RetryPolicy::new(3, Duration::ZERO);
+
+        let (result, attempts) = policy.run(true, failing(2));
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts, 3);
+
+        let (result, attempts) = policy.run(true, failing(3));
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn accesses_with_side_effects_are_attempted_once() {
+        let (result, attempts) = RetryPolicy::verify().run(false, failing(1));
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn interrupted_accesses_are_not_retried() {
+        let (result, attempts) =
+            RetryPolicy::verify().run(true, || -> Result<(), Error> { Err(Error::Interrupted) });
+
+        assert!(matches!(result, Err(Error::Interrupted)));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,538 @@
+//! Helpers to read values, strings and other data structures from target memory.
+
+use std::convert::TryInto;
+use std::ffi::CString;
+
+use super::MemoryInterface;
+use crate::error;
+
+/// Reads of strings and buffers are split into chunks of this size. The chunks are aligned,
+/// so that they never cross the boundary of a memory region.
+const CHUNK_SIZE: u64 = 16;
+
+/// The byte order used by a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    /// The least significant byte is stored at the lowest address.
+    Little,
+    /// The most significant byte is stored at the lowest address.
+    Big,
+}
+
+/// A type which can be read from target memory, like a plain-old-data `repr(C)` struct.
+///
+/// Implementations are provided for the primitive integer types and arrays of them.
+/// For structs, the implementation can be generated with the [`impl_from_target_bytes`] macro.
+///
+/// [`impl_from_target_bytes`]: crate::impl_from_target_bytes
+pub trait FromTargetBytes: Sized {
+    /// The size of the value in target memory, in bytes.
+    const SIZE: usize;
+
+    /// The alignment of the value in target memory, in bytes.
+    const ALIGN: usize;
+
+    /// Convert the bytes read from the target into a value.
+    ///
+    /// `bytes` is always exactly [`FromTargetBytes::SIZE`] bytes long.
+    fn from_target_bytes(bytes: &[u8], endianness: Endianness) -> Self;
+}
+
+macro_rules! impl_from_target_bytes_primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl FromTargetBytes for $ty {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+                const ALIGN: usize = std::mem::size_of::<$ty>();
+
+                fn from_target_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+                    let bytes = bytes.try_into().unwrap();
+
+                    match endianness {
+                        Endianness::Little => <$ty>::from_le_bytes(bytes),
+                        Endianness::Big => <$ty>::from_be_bytes(bytes),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_target_bytes_primitive!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl<T: FromTargetBytes, const N: usize> FromTargetBytes for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+    const ALIGN: usize = T::ALIGN;
+
+    fn from_target_bytes(bytes: &[u8], endianness: Endianness) -> Self {
+        let values: Vec<T> = bytes
+            .chunks_exact(T::SIZE)
+            .map(|chunk| T::from_target_bytes(chunk, endianness))
+            .collect();
+
+        match values.try_into() {
+            Ok(array) => array,
+            Err(_) => unreachable!("The number of chunks is always N"),
+        }
+    }
+}
+
+/// Round `value` up to the next multiple of `align`.
+#[doc(hidden)]
+pub const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) / align * align
+}
+
+/// Implement [`FromTargetBytes`] for a plain-old-data struct, following the `repr(C)` layout rules.
+///
+/// All fields of the struct have to be listed in declaration order, and their types have to
+/// implement [`FromTargetBytes`] as well.
+///
+/// # Example
+///
+/// ```
+/// use probe_rs::impl_from_target_bytes;
+///
+/// #[repr(C)]
+/// struct Header {
+///     magic: u32,
+///     version: u8,
+///     flags: [u8; 3],
+///     length: u32,
+/// }
+///
+/// impl_from_target_bytes!(Header {
+///     magic: u32,
+///     version: u8,
+///     flags: [u8; 3],
+///     length: u32,
+/// });
+/// ```
+#[macro_export]
+macro_rules! impl_from_target_bytes {
+    ($name:ident { $($field:ident : $ty:ty),* $(,)? }) => {
+        impl $crate::FromTargetBytes for $name {
+            const SIZE: usize = {
+                let mut size = 0;
+                $(
+                    size = $crate::align_up(size, <$ty as $crate::FromTargetBytes>::ALIGN)
+                        + <$ty as $crate::FromTargetBytes>::SIZE;
+                )*
+                $crate::align_up(size, <Self as $crate::FromTargetBytes>::ALIGN)
+            };
+
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(
+                    if <$ty as $crate::FromTargetBytes>::ALIGN > align {
+                        align = <$ty as $crate::FromTargetBytes>::ALIGN;
+                    }
+                )*
+                align
+            };
+
+            fn from_target_bytes(bytes: &[u8], endianness: $crate::Endianness) -> Self {
+                let mut offset = 0;
+                $(
+                    offset = $crate::align_up(offset, <$ty as $crate::FromTargetBytes>::ALIGN);
+                    let $field = <$ty as $crate::FromTargetBytes>::from_target_bytes(
+                        &bytes[offset..offset + <$ty as $crate::FromTargetBytes>::SIZE],
+                        endianness,
+                    );
+                    offset += <$ty as $crate::FromTargetBytes>::SIZE;
+                )*
+                let _ = offset;
+
+                Self { $($field),* }
+            }
+        }
+    };
+}
+
+/// The result of a read which can stop early, because the end of accessible memory was reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartialRead<T> {
+    /// The data which was read.
+    pub data: T,
+    /// Describes why the read ended.
+    pub end: ReadEnd,
+}
+
+impl<T> PartialRead<T> {
+    /// Returns true if all requested data was read.
+    pub fn is_complete(&self) -> bool {
+        self.end == ReadEnd::Complete
+    }
+}
+
+/// Describes why a read of variable length data ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadEnd {
+    /// All requested data was read, e.g. the NUL terminator of a string was found.
+    Complete,
+    /// The maximum length was reached before the data ended.
+    LimitReached,
+    /// The memory at `address` could not be read.
+    Inaccessible {
+        /// The first address which could not be read.
+        address: u64,
+    },
+}
+
+/// Read up to `len` bytes, stopping early if `stop` returns true for a chunk.
+///
+/// Returns the bytes read, and the address of the first byte which could not be read because
+/// the access faulted. Any other error, e.g. of the probe, is returned.
+fn read_chunked(
+    memory: &mut dyn MemoryInterface,
+    address: u64,
+    len: usize,
+    mut stop: impl FnMut(&[u8]) -> bool,
+) -> Result<(Vec<u8>, Option<u64>), error::Error> {
+    let mut data = Vec::new();
+    let end = address
+        .checked_add(len as u64)
+        .ok_or(error::Error::AddressOutOfRange(address))?;
+    let mut current = address;
+
+    while current < end {
+        let chunk_end = (current / CHUNK_SIZE + 1)
+            .saturating_mul(CHUNK_SIZE)
+            .min(end);
+        let mut chunk = vec![0u8; (chunk_end - current) as usize];
+
+        match memory.read_8(current, &mut chunk) {
+            Ok(()) => data.extend_from_slice(&chunk),
+            Err(error) if error.is_access_fault() => {
+                // Read the remaining bytes of the chunk one by one, to find
+                // the exact address where the accessible memory ends.
+                for offset in 0..chunk.len() {
+                    match memory.read_word_8(current + offset as u64) {
+                        Ok(byte) => data.push(byte),
+                        Err(error) if error.is_access_fault() => {
+                            return Ok((data, Some(current + offset as u64)))
+                        }
+                        Err(error) => return Err(error),
+                    }
+                }
+                // All bytes could be read one by one, continue normally.
+            }
+            Err(error) => return Err(error),
+        }
+
+        if stop(&data[(current - address) as usize..]) {
+            break;
+        }
+
+        current = chunk_end;
+    }
+
+    Ok((data, None))
+}
+
+/// Read a NUL terminated string of at most `max_len` bytes, excluding the terminator.
+pub(crate) fn read_c_string(
+    memory: &mut dyn MemoryInterface,
+    address: u64,
+    max_len: usize,
+) -> Result<PartialRead<CString>, error::Error> {
+    // Read one more byte than the limit, to find a terminator right after it.
+    let len = max_len
+        .checked_add(1)
+        .ok_or(error::Error::AddressOutOfRange(address))?;
+    let (mut data, inaccessible) = read_chunked(memory, address, len, |chunk| chunk.contains(&0))?;
+
+    let end = match data.iter().position(|&b| b == 0) {
+        Some(nul) => {
+            data.truncate(nul);
+            ReadEnd::Complete
+        }
+        None => match inaccessible {
+            Some(address) => ReadEnd::Inaccessible { address },
+            None => {
+                data.truncate(max_len);
+                ReadEnd::LimitReached
+            }
+        },
+    };
+
+    Ok(PartialRead {
+        // Note(unwrap): The data is truncated at the first NUL byte.
+        data: CString::new(data).unwrap(),
+        end,
+    })
+}
+
+/// Read a buffer which is prefixed by its length, stored in `len_width` bytes.
+///
+/// At most `max_len` bytes of the buffer are read, whatever the length prefix says.
+pub(crate) fn read_slice_prefixed(
+    memory: &mut dyn MemoryInterface,
+    address: u64,
+    len_width: usize,
+    max_len: usize,
+    endianness: Endianness,
+) -> Result<PartialRead<Vec<u8>>, error::Error> {
+    let mut len_bytes = vec![0u8; len_width];
+    memory.read_8(address, &mut len_bytes)?;
+
+    let len = match len_width {
+        1 => u8::from_target_bytes(&len_bytes, endianness) as u64,
+        2 => u16::from_target_bytes(&len_bytes, endianness) as u64,
+        4 => u32::from_target_bytes(&len_bytes, endianness) as u64,
+        8 => u64::from_target_bytes(&len_bytes, endianness),
+        _ => {
+            return Err(error::Error::Other(anyhow::anyhow!(
+                "Unsupported length prefix width of {} bytes",
+                len_width
+            )))
+        }
+    };
+
+    let data_address = address
+        .checked_add(len_width as u64)
+        .ok_or(error::Error::AddressOutOfRange(address))?;
+    let len = usize::try_from(len).unwrap_or(usize::MAX);
+    let truncated = len > max_len;
+
+    let (data, inaccessible) = read_chunked(memory, data_address, len.min(max_len), |_| false)?;
+
+    let end = match inaccessible {
+        Some(address) => ReadEnd::Inaccessible { address },
+        None if truncated => ReadEnd::LimitReached,
+        None => ReadEnd::Complete,
+    };
+
+    Ok(PartialRead { data, end })
+}
+
+/// Read a value of type `T` from target memory.
+pub(crate) fn read_value<T: FromTargetBytes>(
+    memory: &mut dyn MemoryInterface,
+    address: u64,
+    endianness: Endianness,
+) -> Result<T, error::Error> {
+    let mut bytes = vec![0u8; T::SIZE];
+    memory.read_8(address, &mut bytes)?;
+
+    Ok(T::from_target_bytes(&bytes, endianness))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::DapError;
+
+    /// Memory which is readable from 0x1000 to 0x1040.
+    struct TestMemory {
+        data: Vec<u8>,
+        /// The probe fails on all accesses.
+        disconnected: bool,
+    }
+
+    const BASE: u64 = 0x1000;
+
+    impl TestMemory {
+        fn new(data: &[u8]) -> Self {
+            let mut memory = vec![0xaa; 0x40];
+            memory[..data.len()].copy_from_slice(data);
+            Self {
+                data: memory,
+                disconnected: false,
+            }
+        }
+
+        fn range(&self, address: u64, len: usize) -> Result<std::ops::Range<usize>, error::Error> {
+            if self.disconnected {
+                return Err(error::Error::Other(anyhow::anyhow!(
+                    "The probe was disconnected"
+                )));
+            }
+
+            if address < BASE || address + len as u64 > BASE + self.data.len() as u64 {
+                return Err(error::Error::architecture_specific(DapError::FaultResponse));
+            }
+
+            let start = (address - BASE) as usize;
+            Ok(start..start + len)
+        }
+    }
+
+    impl MemoryInterface for TestMemory {
+        fn supports_native_64bit_access(&mut self) -> bool {
+            false
+        }
+
+        fn read_word_64(&mut self, _address: u64) -> Result<u64, error::Error> {
+            unimplemented!()
+        }
+
+        fn read_word_32(&mut self, _address: u64) -> Result<u32, error::Error> {
+            unimplemented!()
+        }
+
+        fn read_word_8(&mut self, address: u64) -> Result<u8, error::Error> {
+            let range = self.range(address, 1)?;
+            Ok(self.data[range.start])
+        }
+
+        fn read_64(&mut self, _address: u64, _data: &mut [u64]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn read_32(&mut self, _address: u64, _data: &mut [u32]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), error::Error> {
+            let range = self.range(address, data.len())?;
+            data.copy_from_slice(&self.data[range]);
+            Ok(())
+        }
+
+        fn write_word_64(&mut self, _address: u64, _data: u64) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, _address: u64, _data: u32) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_8(&mut self, _address: u64, _data: u8) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_64(&mut self, _address: u64, _data: &[u64]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, _address: u64, _data: &[u32]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn write_8(&mut self, _address: u64, _data: &[u8]) -> Result<(), error::Error> {
+            unimplemented!()
+        }
+
+        fn flush(&mut self) -> Result<(), error::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn c_string() {
+        let mut memory = TestMemory::new(b"\0\0\0probe-rs\0");
+
+        let string = read_c_string(&mut memory, BASE + 3, 64).unwrap();
+
+        assert_eq!(string.data.as_bytes(), b"probe-rs");
+        assert!(string.is_complete());
+    }
+
+    #[test]
+    fn c_string_limit() {
+        let mut memory = TestMemory::new(b"probe-rs\0");
+
+        let string = read_c_string(&mut memory, BASE, 5).unwrap();
+
+        assert_eq!(string.data.as_bytes(), b"probe");
+        assert_eq!(string.end, ReadEnd::LimitReached);
+    }
+
+    #[test]
+    fn c_string_at_end_of_memory() {
+        let mut memory = TestMemory::new(&[]);
+
+        let string = read_c_string(&mut memory, BASE + 0x3d, 64).unwrap();
+
+        assert_eq!(string.data.as_bytes(), &[0xaa; 3]);
+        assert_eq!(
+            string.end,
+            ReadEnd::Inaccessible {
+                address: BASE + 0x40
+            }
+        );
+    }
+
+    #[test]
+    fn slice_prefixed() {
+        let mut memory = TestMemory::new(&[3, 0, 1, 2, 3]);
+
+        let slice = read_slice_prefixed(&mut memory, BASE, 2, 64, Endianness::Little).unwrap();
+
+        assert_eq!(slice.data, vec![1, 2, 3]);
+        assert!(slice.is_complete());
+    }
+
+    #[test]
+    fn slice_prefixed_limit() {
+        let mut memory = TestMemory::new(&[0xff, 0xff, 0xff, 0xff, 1, 2, 3]);
+
+        let slice = read_slice_prefixed(&mut memory, BASE, 4, 2, Endianness::Little).unwrap();
+
+        assert_eq!(slice.data, vec![1, 2]);
+        assert_eq!(slice.end, ReadEnd::LimitReached);
+    }
+
+    #[test]
+    fn probe_errors_are_returned() {
+        let mut memory = TestMemory::new(b"probe-rs\0");
+        memory.disconnected = true;
+
+        assert!(matches!(
+            read_c_string(&mut memory, BASE, 64),
+            Err(error::Error::Other(_))
+        ));
+    }
+
+    #[test]
+    fn end_of_address_space() {
+        let mut memory = TestMemory::new(&[]);
+
+        assert!(matches!(
+            read_c_string(&mut memory, u64::MAX - 4, 64),
+            Err(error::Error::AddressOutOfRange(_))
+        ));
+        assert!(matches!(
+            read_c_string(&mut memory, BASE, usize::MAX),
+            Err(error::Error::AddressOutOfRange(_))
+        ));
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Header {
+        magic: u32,
+        version: u8,
+        length: u16,
+        id: u64,
+    }
+
+    crate::impl_from_target_bytes!(Header {
+        magic: u32,
+        version: u8,
+        length: u16,
+        id: u64,
+    });
+
+    #[test]
+    fn repr_c_struct() {
+        assert_eq!(Header::SIZE, 16);
+        assert_eq!(Header::ALIGN, 8);
+
+        let mut memory = TestMemory::new(&[
+            0xef, 0xbe, 0xad, 0xde, 1, 0, 0x10, 0, 8, 7, 6, 5, 4, 3, 2, 1,
+        ]);
+
+        let header: Header = read_value(&mut memory, BASE, Endianness::Little).unwrap();
+
+        assert_eq!(
+            header,
+            Header {
+                magic: 0xdeadbeef,
+                version: 1,
+                length: 0x10,
+                id: 0x0102030405060708,
+            }
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Flash which the firmware of the target writes itself, see [`TargetWritableFlash`].
+
+use std::ops::Range;
+
+use crate::config::MemoryRegion;
+
+/// The flash of a target whose contents may change while the target runs, because its
+/// firmware writes it, e.g. a bootloader, an EEPROM emulation or a log in flash.
+///
+/// Earlier verifications of this flash aren't relied on: the sectors of a download which
+/// overlap it are always programmed, even if the download journal records them as verified,
+/// and the verification of an image which overlaps it only holds at the time it was done,
+/// see [`ImageOutcome::point_in_time`](crate::flashing::ImageOutcome::point_in_time).
+///
+/// NVM regions which are marked as `target_writable` in the target description are always
+/// target-writable. Further ranges can be marked at runtime with
+/// [`Session::mark_target_writable_flash`](crate::Session::mark_target_writable_flash), and
+/// single writes of the firmware are reported with
+/// [`Session::notify_target_flash_write`](crate::Session::notify_target_flash_write).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetWritableFlash {
+    ranges: Vec<Range<u64>>,
+    /// Ranges which the firmware wrote since the last journaled download.
+    written: Vec<Range<u64>>,
+}
+
+impl TargetWritableFlash {
+    /// The target-writable flash of `memory_map`.
+    pub fn new(memory_map: &[MemoryRegion]) -> Self {
+        let ranges = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Nvm(region) if region.target_writable => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            ranges,
+            written: Vec::new(),
+        }
+    }
+
+    /// Mark `range` as target-writable.
+    pub fn mark(&mut self, range: Range<u64>) {
+        if !range.is_empty() {
+            self.ranges.push(range);
+        }
+    }
+
+    /// Record that the firmware wrote `range`.
+    pub fn notify_write(&mut self, range: Range<u64>) {
+        if !range.is_empty() {
+            self.written.push(range);
+        }
+    }
+
+    /// The target-writable ranges, in the order in which they were marked.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// The ranges which the firmware wrote since the last journaled download, in the order
+    /// in which they were reported.
+    pub fn written(&self) -> &[Range<u64>] {
+        &self.written
+    }
+
+    /// Returns true if `range` overlaps target-writable flash.
+    pub fn is_target_writable(&self, range: &Range<u64>) -> bool {
+        overlaps(&self.ranges, range)
+    }
+
+    /// Returns true if earlier verifications of `range` can't be relied on, because it
+    /// overlaps target-writable flash, or the firmware wrote it since the last journaled
+    /// download.
+    pub fn is_untrusted(&self, range: &Range<u64>) -> bool {
+        self.is_target_writable(range) || overlaps(&self.written, range)
+    }
+
+    /// Forget the reported writes, once a journaled download recorded the current contents.
+    pub(crate) fn clear_written(&mut self) {
+        self.written.clear();
+    }
+}
+
+fn overlaps(ranges: &[Range<u64>], range: &Range<u64>) -> bool {
+    ranges
+        .iter()
+        .any(|other| other.start < range.end && range.start < other.end)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::NvmRegion;
+
+    fn memory_map() -> Vec<MemoryRegion> {
+        vec![
+            MemoryRegion::Nvm(NvmRegion {
+                name: None,
+                range: 0x0800_0000..0x0803_0000,
+                is_boot_memory: true,
+                cores: vec!["main".into()],
+                boot_critical_ranges: Vec::new(),
+                target_writable: false,
+            }),
+            MemoryRegion::Nvm(NvmRegion {
+                name: Some("EEPROM".into()),
+                range: 0x0803_0000..0x0804_0000,
+                is_boot_memory: false,
+                cores: vec!["main".into()],
+                boot_critical_ranges: Vec::new(),
+                target_writable: true,
+            }),
+        ]
+    }
+
+    #[test]
+    fn marked_regions_are_target_writable() {
+        let mut flash = TargetWritableFlash::new(&memory_map());
+        flash.mark(0x0800_4000..0x0800_5000);
+
+        assert!(!flash.is_target_writable(&(0x0800_0000..0x0800_4000)));
+        assert!(flash.is_target_writable(&(0x0800_3000..0x0800_4001)));
+        assert!(flash.is_target_writable(&(0x0803_f000..0x0804_0000)));
+        assert!(!flash.is_target_writable(&(0x0804_0000..0x0804_1000)));
+    }
+
+    #[test]
+    fn written_ranges_are_untrusted_until_cleared() {
+        let mut flash = TargetWritableFlash::new(&memory_map());
+        flash.notify_write(0x0800_1000..0x0800_1004);
+
+        assert!(!flash.is_target_writable(&(0x0800_1000..0x0800_2000)));
+        assert!(flash.is_untrusted(&(0x0800_1000..0x0800_2000)));
+        assert!(!flash.is_untrusted(&(0x0800_2000..0x0800_3000)));
+
+        flash.clear_written();
+        assert!(!flash.is_untrusted(&(0x0800_1000..0x0800_2000)));
+        assert!(flash.is_untrusted(&(0x0803_0000..0x0803_1000)));
+    }
+}
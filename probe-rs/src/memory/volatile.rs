@@ -0,0 +1,142 @@
+//! Address ranges which must be accessed exactly as requested, see [`VolatileRanges`].
+
+use std::ops::Range;
+
+use crate::config::MemoryRegion;
+
+/// The address ranges of a target which are volatile, i.e. memory mapped I/O whose accesses
+/// must never be cached, merged, reordered or widened.
+///
+/// This is the single place where the layers which repeat, merge or combine memory accesses
+/// check whether they may do so, e.g. the [`WriteCoalescer`](crate::WriteCoalescer) and the
+/// [`RetryPolicy`](crate::RetryPolicy) of a core. All generic regions of the memory map, and
+/// RAM regions which are marked as `volatile` in the target description, are volatile.
+/// Further ranges can be marked at runtime with
+/// [`Session::mark_volatile`](crate::Session::mark_volatile).
+///
+/// Addresses outside of the memory map are treated as device memory by all layers anyway.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VolatileRanges {
+    ranges: Vec<Range<u64>>,
+}
+
+impl VolatileRanges {
+    /// The volatile ranges of `memory_map`.
+    pub fn new(memory_map: &[MemoryRegion]) -> Self {
+        let ranges = memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Ram(region) if region.volatile => Some(region.range.clone()),
+                MemoryRegion::Generic(region) => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect();
+
+        Self { ranges }
+    }
+
+    /// Mark `range` as volatile.
+    pub fn mark(&mut self, range: Range<u64>) {
+        if !range.is_empty() {
+            self.ranges.push(range);
+        }
+    }
+
+    /// The volatile ranges, in the order in which they were marked.
+    pub fn ranges(&self) -> &[Range<u64>] {
+        &self.ranges
+    }
+
+    /// Returns true if any of the `len` bytes at `address` is volatile.
+    pub fn is_volatile(&self, address: u64, len: usize) -> bool {
+        let end = address + len as u64;
+
+        self.ranges
+            .iter()
+            .any(|range| range.start < end && address < range.end)
+    }
+
+    /// Returns true if the `len` bytes at `address` lie within one of `memory`, and none of
+    /// them is volatile, so that accesses to them can be repeated, merged or reordered.
+    pub(crate) fn is_plain_memory(&self, memory: &[Range<u64>], address: u64, len: usize) -> bool {
+        super::within(memory, address, len) && !self.is_volatile(address, len)
+    }
+
+    /// Assert that `layer` is allowed to merge, reorder or cache an access to the `len`
+    /// bytes at `address`.
+    ///
+    /// Layers check [`VolatileRanges::is_plain_memory`] before they queue an access, so this
+    /// only fires in debug builds, including all tests, if a layer skipped the check.
+    pub(crate) fn debug_assert_plain(&self, layer: &str, address: u64, len: usize) {
+        debug_assert!(
+            !self.is_volatile(address, len),
+            "{} merged or cached a volatile access of {} bytes at {:#010x}",
+            layer,
+            len,
+            address
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{GenericRegion, RamRegion};
+
+    fn memory_map() -> Vec<MemoryRegion> {
+        vec![
+            MemoryRegion::Ram(RamRegion {
+                name: None,
+                range: 0x2000_0000..0x2001_0000,
+                is_boot_memory: false,
+                cores: vec!["main".into()],
+                volatile: false,
+                ecc: false,
+            }),
+            MemoryRegion::Ram(RamRegion {
+                name: Some("MAILBOX".into()),
+                range: 0x2003_0000..0x2003_0100,
+                is_boot_memory: false,
+                cores: vec!["main".into()],
+                volatile: true,
+                ecc: false,
+            }),
+            MemoryRegion::Generic(GenericRegion {
+                name: Some("PERIPHERALS".into()),
+                range: 0x4000_0000..0x5000_0000,
+                cores: vec!["main".into()],
+            }),
+        ]
+    }
+
+    #[test]
+    fn device_and_marked_regions_are_volatile() {
+        let volatile = VolatileRanges::new(&memory_map());
+
+        assert!(!volatile.is_volatile(0x2000_0000, 4));
+        assert!(volatile.is_volatile(0x2003_0000, 4));
+        assert!(volatile.is_volatile(0x4000_1000, 4));
+    }
+
+    #[test]
+    fn partially_volatile_accesses_are_volatile() {
+        let mut volatile = VolatileRanges::new(&memory_map());
+        volatile.mark(0x2000_0100..0x2000_0104);
+
+        assert!(volatile.is_volatile(0x2000_00fc, 8));
+        assert!(volatile.is_volatile(0x2000_0103, 1));
+        assert!(!volatile.is_volatile(0x2000_00fc, 4));
+        assert!(!volatile.is_volatile(0x2000_0104, 4));
+
+        let ram = [0x2000_0000..0x2001_0000];
+        assert!(volatile.is_plain_memory(&ram, 0x2000_0000, 0x100));
+        assert!(!volatile.is_plain_memory(&ram, 0x2000_0000, 0x101));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "merged or cached a volatile access")]
+    fn merging_a_volatile_access_is_caught() {
+        VolatileRanges::new(&memory_map()).debug_assert_plain("test", 0x4000_0000, 4);
+    }
+}
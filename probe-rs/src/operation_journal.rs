@@ -0,0 +1,1105 @@
+//! A journal of the operations performed on the cores of a session, which can be replayed
+//! on another target, see
+//! [`Session::start_operation_journal`](crate::Session::start_operation_journal) and
+//! [`Session::replay_journal`](crate::Session::replay_journal).
+//!
+//! While a journal is recorded, the following operations on a [`Core`] of the session
+//! append a [`JournalEntry`] with their arguments, their outcome and their duration:
+//!
+//! - halting, running, stepping and resetting a core, and waiting for it to halt,
+//! - reading and writing memory,
+//! - reading and writing core registers,
+//! - setting and clearing hardware breakpoints.
+//!
+//! The accesses which such an operation performs on its own behalf are part of it, and not
+//! recorded separately. Other functions, e.g. [`Core::read_value`] or flashing, are recorded
+//! as the operations they consist of, so that they are replayed the same way. Only
+//! [`Core::status`], which is polled, isn't recorded at all.
+//!
+//! The journal is meant to be read and edited by humans, e.g. to turn the recording of a
+//! board bring-up into a script for the next revision of the board. Addresses and values
+//! are written in hex, and addresses can refer to named [variables](OperationJournal::variables)
+//! which are defined in the header of the journal, such as `rcc + 0x18`. Reads are recorded
+//! with the values they returned, and are checked against them when the journal is
+//! replayed.
+//!
+//! The journal is serialized with serde, as YAML or JSON, and its schema is versioned with
+//! [`OPERATION_JOURNAL_VERSION`], independently of the version of probe-rs.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Core, Error, MemoryInterface, RegisterId, RegisterValue, Session};
+
+/// The version of the schema of an [`OperationJournal`].
+pub const OPERATION_JOURNAL_VERSION: u64 = 1;
+
+/// The operations performed on the cores of a session, returned by
+/// [`Session::stop_operation_journal`](crate::Session::stop_operation_journal).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationJournal {
+    /// The version of the schema, [`OPERATION_JOURNAL_VERSION`].
+    pub format_version: u64,
+    /// The name of the target the journal was recorded on.
+    ///
+    /// It is only informational, a journal can be replayed on any target.
+    pub target: String,
+    /// The named variables the values of the entries can refer to, see [`JournalValue`].
+    ///
+    /// The values of the variables can be overridden when the journal is replayed, see
+    /// [`ReplayOptions::variable`].
+    #[serde(default)]
+    pub variables: BTreeMap<String, JournalValue>,
+    /// The operations, in the order in which they were performed.
+    pub entries: Vec<JournalEntry>,
+}
+
+impl OperationJournal {
+    /// Create an empty journal for the target named `target`.
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            format_version: OPERATION_JOURNAL_VERSION,
+            target: target.into(),
+            variables: BTreeMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Define the variable `name` as the start of `region`, and refer to it in all addresses
+    /// of the entries which are in `region`.
+    ///
+    /// E.g. with the region `0x4002_1000..0x4002_1400` defined as `rcc`, a write to
+    /// `0x4002_1018` is written as a write to `rcc + 0x18`.
+    pub fn define_variable(&mut self, name: impl Into<String>, region: Range<u64>) {
+        let name = name.into();
+
+        for entry in &mut self.entries {
+            if let Some(address) = entry.operation.address_mut() {
+                if let JournalValue::Literal(value) = *address {
+                    if region.contains(&value) {
+                        *address = JournalValue::Variable {
+                            name: name.clone(),
+                            offset: value - region.start,
+                        };
+                    }
+                }
+            }
+        }
+
+        self.variables
+            .insert(name, JournalValue::Literal(region.start));
+    }
+
+    /// Parse a journal in the given `format`.
+    ///
+    /// Fails with [`Error::UnsupportedOperationJournalVersion`] if the journal was written
+    /// with another version of the schema, and with [`Error::InvalidOperationJournal`] if it
+    /// doesn't match the schema.
+    pub fn parse(text: &str, format: JournalFormat) -> Result<Self, Error> {
+        let header: JournalHeader = format.deserialize(text)?;
+
+        let version = header
+            .format_version
+            .ok_or_else(|| Error::InvalidOperationJournal("missing format_version".to_string()))?;
+
+        if version != OPERATION_JOURNAL_VERSION {
+            return Err(Error::UnsupportedOperationJournalVersion {
+                version,
+                supported: OPERATION_JOURNAL_VERSION,
+            });
+        }
+
+        format.deserialize(text)
+    }
+
+    /// Serialize the journal in the given `format`.
+    pub fn serialize(&self, format: JournalFormat) -> String {
+        match format {
+            JournalFormat::Json => serde_json::to_string_pretty(self)
+                .expect("An operation journal is always serializable"),
+            JournalFormat::Yaml => {
+                serde_yaml::to_string(self).expect("An operation journal is always serializable")
+            }
+        }
+    }
+
+    /// Load a journal from the file at `path`, in the format given by its extension, see
+    /// [`JournalFormat::from_path`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|source| Error::FileRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        Self::parse(&text, JournalFormat::from_path(path))
+    }
+
+    /// Save the journal to the file at `path`, in the format given by its extension, see
+    /// [`JournalFormat::from_path`].
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        std::fs::write(path, self.serialize(JournalFormat::from_path(path))).map_err(|source| {
+            Error::FileWrite {
+                path: path.to_path_buf(),
+                source,
+            }
+        })
+    }
+
+    /// Returns the values of the variables, with the overrides of `options`.
+    fn resolved_variables(&self, options: &ReplayOptions) -> Result<BTreeMap<String, u64>, Error> {
+        let mut variables = BTreeMap::new();
+        for (name, value) in &self.variables {
+            match value {
+                JournalValue::Literal(value) => {
+                    variables.insert(name.clone(), *value);
+                }
+                JournalValue::Variable { .. } => {
+                    return Err(Error::InvalidOperationJournal(format!(
+                        "the variable `{}` must be defined as a number",
+                        name
+                    )))
+                }
+            }
+        }
+
+        for (name, value) in &options.variables {
+            variables.insert(name.clone(), *value);
+        }
+
+        Ok(variables)
+    }
+}
+
+/// The part of the journal which is read before the rest, to check its version.
+#[derive(Deserialize)]
+struct JournalHeader {
+    format_version: Option<u64>,
+}
+
+/// The serialization format of an [`OperationJournal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalFormat {
+    /// JSON.
+    Json,
+    /// YAML.
+    Yaml,
+}
+
+impl JournalFormat {
+    /// Returns the format of a file by its extension: JSON for `.json`, and YAML otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => JournalFormat::Json,
+            _ => JournalFormat::Yaml,
+        }
+    }
+
+    fn deserialize<T: serde::de::DeserializeOwned>(self, text: &str) -> Result<T, Error> {
+        match self {
+            JournalFormat::Json => serde_json::from_str(text)
+                .map_err(|error| Error::InvalidOperationJournal(error.to_string())),
+            JournalFormat::Yaml => serde_yaml::from_str(text)
+                .map_err(|error| Error::InvalidOperationJournal(error.to_string())),
+        }
+    }
+}
+
+/// An operation in an [`OperationJournal`], with its outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The operation and its arguments.
+    pub operation: JournalOperation,
+    /// Whether the operation succeeded.
+    pub outcome: JournalOutcome,
+    /// The time the operation took, in microseconds.
+    #[serde(default)]
+    pub duration_us: u64,
+}
+
+/// The outcome of a [`JournalEntry`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOutcome {
+    /// The operation succeeded.
+    Ok,
+    /// The operation failed with this error.
+    Error(String),
+}
+
+impl JournalOutcome {
+    fn of<R>(result: &Result<R, Error>) -> Self {
+        match result {
+            Ok(_) => JournalOutcome::Ok,
+            Err(error) => JournalOutcome::Error(error.to_string()),
+        }
+    }
+}
+
+/// The width of the accesses of a memory operation in an [`OperationJournal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalWidth {
+    /// 8-bit accesses.
+    U8,
+    /// 32-bit accesses.
+    U32,
+    /// 64-bit accesses.
+    U64,
+}
+
+impl JournalWidth {
+    fn max_value(self) -> u64 {
+        match self {
+            JournalWidth::U8 => u8::MAX.into(),
+            JournalWidth::U32 => u32::MAX.into(),
+            JournalWidth::U64 => u64::MAX,
+        }
+    }
+}
+
+/// An operation in an [`OperationJournal`].
+///
+/// Memory operations on a single value are replayed with the word accesses of
+/// [`MemoryInterface`], e.g. [`MemoryInterface::write_word_32`], and on several values with
+/// the block accesses, e.g. [`MemoryInterface::write_32`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalOperation {
+    /// [`Core::halt`].
+    Halt {
+        /// The index of the core.
+        core: usize,
+        /// The timeout in milliseconds.
+        timeout_ms: u64,
+    },
+    /// [`Core::run`].
+    Run {
+        /// The index of the core.
+        core: usize,
+    },
+    /// [`Core::step`].
+    Step {
+        /// The index of the core.
+        core: usize,
+    },
+    /// [`Core::reset`].
+    Reset {
+        /// The index of the core.
+        core: usize,
+    },
+    /// [`Core::reset_and_halt`].
+    ResetAndHalt {
+        /// The index of the core.
+        core: usize,
+        /// The timeout in milliseconds.
+        timeout_ms: u64,
+    },
+    /// [`Core::wait_for_core_halted`].
+    WaitForCoreHalted {
+        /// The index of the core.
+        core: usize,
+        /// The timeout in milliseconds.
+        timeout_ms: u64,
+    },
+    /// A read of memory.
+    ReadMemory {
+        /// The index of the core.
+        core: usize,
+        /// The start address.
+        address: JournalValue,
+        /// The width of the accesses.
+        width: JournalWidth,
+        /// The number of values which were read.
+        count: usize,
+        /// The values which were read, or `None` if the read failed.
+        ///
+        /// When the journal is replayed, the values which are read have to match them, see
+        /// [`ReplayOptions::assert_reads`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        values: Option<Vec<JournalValue>>,
+    },
+    /// A write of memory.
+    WriteMemory {
+        /// The index of the core.
+        core: usize,
+        /// The start address.
+        address: JournalValue,
+        /// The width of the accesses.
+        width: JournalWidth,
+        /// The values which are written.
+        values: Vec<JournalValue>,
+    },
+    /// [`Core::read_core_reg`].
+    ReadRegister {
+        /// The index of the core.
+        core: usize,
+        /// The register.
+        register: u16,
+        /// The value which was read, or `None` if the read failed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value: Option<JournalValue>,
+    },
+    /// [`Core::write_core_reg`].
+    WriteRegister {
+        /// The index of the core.
+        core: usize,
+        /// The register.
+        register: u16,
+        /// The value which is written.
+        value: JournalValue,
+    },
+    /// [`Core::set_hw_breakpoint`].
+    SetHwBreakpoint {
+        /// The index of the core.
+        core: usize,
+        /// The address of the breakpoint.
+        address: JournalValue,
+    },
+    /// [`Core::clear_hw_breakpoint`].
+    ClearHwBreakpoint {
+        /// The index of the core.
+        core: usize,
+        /// The address of the breakpoint.
+        address: JournalValue,
+    },
+}
+
+impl JournalOperation {
+    pub(crate) fn read_memory(
+        core: usize,
+        address: u64,
+        width: JournalWidth,
+        count: usize,
+        values: Option<Vec<u64>>,
+    ) -> Self {
+        JournalOperation::ReadMemory {
+            core,
+            address: JournalValue::Literal(address),
+            width,
+            count,
+            values: values.map(literals),
+        }
+    }
+
+    pub(crate) fn write_memory(
+        core: usize,
+        address: u64,
+        width: JournalWidth,
+        values: Vec<u64>,
+    ) -> Self {
+        JournalOperation::WriteMemory {
+            core,
+            address: JournalValue::Literal(address),
+            width,
+            values: literals(values),
+        }
+    }
+
+    /// Returns the index of the core the operation is performed on.
+    pub fn core(&self) -> usize {
+        match *self {
+            JournalOperation::Halt { core, .. }
+            | JournalOperation::Run { core }
+            | JournalOperation::Step { core }
+            | JournalOperation::Reset { core }
+            | JournalOperation::ResetAndHalt { core, .. }
+            | JournalOperation::WaitForCoreHalted { core, .. }
+            | JournalOperation::ReadMemory { core, .. }
+            | JournalOperation::WriteMemory { core, .. }
+            | JournalOperation::ReadRegister { core, .. }
+            | JournalOperation::WriteRegister { core, .. }
+            | JournalOperation::SetHwBreakpoint { core, .. }
+            | JournalOperation::ClearHwBreakpoint { core, .. } => core,
+        }
+    }
+
+    fn address_mut(&mut self) -> Option<&mut JournalValue> {
+        match self {
+            JournalOperation::ReadMemory { address, .. }
+            | JournalOperation::WriteMemory { address, .. }
+            | JournalOperation::SetHwBreakpoint { address, .. }
+            | JournalOperation::ClearHwBreakpoint { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Check that all values of the operation can be resolved, and fit their width, so that
+    /// a journal is rejected before any of its operations is replayed.
+    fn check(&self, variables: &BTreeMap<String, u64>) -> Result<(), Error> {
+        match self {
+            JournalOperation::ReadMemory {
+                address,
+                width,
+                count,
+                values,
+                ..
+            } => {
+                address.resolve(variables)?;
+                if let Some(values) = values {
+                    if values.len() != *count {
+                        return Err(Error::InvalidOperationJournal(format!(
+                            "a read of {} values has {} values",
+                            count,
+                            values.len()
+                        )));
+                    }
+                    resolve_all(values, *width, variables)?;
+                }
+            }
+            JournalOperation::WriteMemory {
+                address,
+                width,
+                values,
+                ..
+            } => {
+                address.resolve(variables)?;
+                resolve_all(values, *width, variables)?;
+            }
+            JournalOperation::ReadRegister { value, .. } => {
+                if let Some(value) = value {
+                    value.resolve(variables)?;
+                }
+            }
+            JournalOperation::WriteRegister { value, .. } => {
+                value.resolve(variables)?;
+            }
+            JournalOperation::SetHwBreakpoint { address, .. }
+            | JournalOperation::ClearHwBreakpoint { address, .. } => {
+                address.resolve(variables)?;
+            }
+            JournalOperation::Halt { .. }
+            | JournalOperation::Run { .. }
+            | JournalOperation::Step { .. }
+            | JournalOperation::Reset { .. }
+            | JournalOperation::ResetAndHalt { .. }
+            | JournalOperation::WaitForCoreHalted { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    /// Perform the operation on `core`, and return the values it read, if it is a read.
+    fn replay(
+        &self,
+        core: &mut Core<'_>,
+        variables: &BTreeMap<String, u64>,
+    ) -> Result<Option<Vec<u64>>, Error> {
+        match self {
+            JournalOperation::Halt { timeout_ms, .. } => {
+                core.halt(Duration::from_millis(*timeout_ms))?;
+            }
+            JournalOperation::Run { .. } => core.run()?,
+            JournalOperation::Step { .. } => {
+                core.step()?;
+            }
+            JournalOperation::Reset { .. } => core.reset()?,
+            JournalOperation::ResetAndHalt { timeout_ms, .. } => {
+                core.reset_and_halt(Duration::from_millis(*timeout_ms))?;
+            }
+            JournalOperation::WaitForCoreHalted { timeout_ms, .. } => {
+                core.wait_for_core_halted(Duration::from_millis(*timeout_ms))?;
+            }
+            JournalOperation::ReadMemory {
+                address,
+                width,
+                count,
+                ..
+            } => {
+                let address = address.resolve(variables)?;
+                return read_memory(core, address, *width, *count).map(Some);
+            }
+            JournalOperation::WriteMemory {
+                address,
+                width,
+                values,
+                ..
+            } => {
+                let address = address.resolve(variables)?;
+                let values = resolve_all(values, *width, variables)?;
+                write_memory(core, address, *width, &values)?;
+            }
+            JournalOperation::ReadRegister { register, .. } => {
+                let value: u64 = core.read_core_reg(RegisterId(*register))?;
+                return Ok(Some(vec![value]));
+            }
+            JournalOperation::WriteRegister {
+                register, value, ..
+            } => {
+                let id = RegisterId(*register);
+                let value = value.resolve(variables)?;
+
+                let size_in_bits = core
+                    .registers()
+                    .registers()
+                    .find(|description| RegisterId::from(*description) == id)
+                    .map(|description| description.size_in_bits());
+
+                let value = match size_in_bits {
+                    Some(bits) if bits <= 32 => RegisterValue::U32(
+                        u32::try_from(value).map_err(|_| Error::ValueTooLarge(value))?,
+                    ),
+                    _ => RegisterValue::U64(value),
+                };
+                core.write_core_reg(id, value)?;
+            }
+            JournalOperation::SetHwBreakpoint { address, .. } => {
+                core.set_hw_breakpoint(address.resolve(variables)?)?;
+            }
+            JournalOperation::ClearHwBreakpoint { address, .. } => {
+                core.clear_hw_breakpoint(address.resolve(variables)?)?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the values which were recorded for a read.
+    fn recorded_values(
+        &self,
+        variables: &BTreeMap<String, u64>,
+    ) -> Result<Option<Vec<u64>>, Error> {
+        match self {
+            JournalOperation::ReadMemory {
+                values: Some(values),
+                width,
+                ..
+            } => resolve_all(values, *width, variables).map(Some),
+            JournalOperation::ReadRegister {
+                value: Some(value), ..
+            } => Ok(Some(vec![value.resolve(variables)?])),
+            _ => Ok(None),
+        }
+    }
+}
+
+fn literals(values: Vec<u64>) -> Vec<JournalValue> {
+    values.into_iter().map(JournalValue::Literal).collect()
+}
+
+fn resolve_all(
+    values: &[JournalValue],
+    width: JournalWidth,
+    variables: &BTreeMap<String, u64>,
+) -> Result<Vec<u64>, Error> {
+    values
+        .iter()
+        .map(|value| {
+            let value = value.resolve(variables)?;
+            if value > width.max_value() {
+                return Err(Error::InvalidOperationJournal(format!(
+                    "the value {:#x} doesn't fit into {:?}",
+                    value, width
+                )));
+            }
+            Ok(value)
+        })
+        .collect()
+}
+
+fn read_memory(
+    core: &mut Core<'_>,
+    address: u64,
+    width: JournalWidth,
+    count: usize,
+) -> Result<Vec<u64>, Error> {
+    match (width, count) {
+        (JournalWidth::U8, 1) => Ok(vec![core.read_word_8(address)?.into()]),
+        (JournalWidth::U32, 1) => Ok(vec![core.read_word_32(address)?.into()]),
+        (JournalWidth::U64, 1) => Ok(vec![core.read_word_64(address)?]),
+        (JournalWidth::U8, _) => {
+            let mut data = vec![0; count];
+            core.read_8(address, &mut data)?;
+            Ok(data.into_iter().map(u64::from).collect())
+        }
+        (JournalWidth::U32, _) => {
+            let mut data = vec![0; count];
+            core.read_32(address, &mut data)?;
+            Ok(data.into_iter().map(u64::from).collect())
+        }
+        (JournalWidth::U64, _) => {
+            let mut data = vec![0; count];
+            core.read_64(address, &mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+/// Write `values`, which were checked to fit into `width`.
+fn write_memory(
+    core: &mut Core<'_>,
+    address: u64,
+    width: JournalWidth,
+    values: &[u64],
+) -> Result<(), Error> {
+    match (width, values) {
+        (JournalWidth::U8, [value]) => core.write_word_8(address, *value as u8),
+        (JournalWidth::U32, [value]) => core.write_word_32(address, *value as u32),
+        (JournalWidth::U64, [value]) => core.write_word_64(address, *value),
+        (JournalWidth::U8, _) => {
+            let data: Vec<u8> = values.iter().map(|value| *value as u8).collect();
+            core.write_8(address, &data)
+        }
+        (JournalWidth::U32, _) => {
+            let data: Vec<u32> = values.iter().map(|value| *value as u32).collect();
+            core.write_32(address, &data)
+        }
+        (JournalWidth::U64, _) => core.write_64(address, values),
+    }
+}
+
+/// A number in an [`OperationJournal`], which can refer to a variable.
+///
+/// It is written as a string, in hex for literals, e.g. `"0x40021018"`, and as the name of
+/// a variable with an optional offset for variables, e.g. `"rcc + 0x18"`. Plain numbers are
+/// accepted as literals as well.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawJournalValue", into = "String")]
+pub enum JournalValue {
+    /// A number.
+    Literal(u64),
+    /// The value of a variable, plus an offset.
+    Variable {
+        /// The name of the variable.
+        name: String,
+        /// The offset which is added to the value of the variable.
+        offset: u64,
+    },
+}
+
+impl JournalValue {
+    /// Returns the number, with the value of the variable taken from `variables`.
+    pub fn resolve(&self, variables: &BTreeMap<String, u64>) -> Result<u64, Error> {
+        match self {
+            JournalValue::Literal(value) => Ok(*value),
+            JournalValue::Variable { name, offset } => {
+                let value = variables.get(name).ok_or_else(|| {
+                    Error::InvalidOperationJournal(format!(
+                        "the variable `{}` is not defined",
+                        name
+                    ))
+                })?;
+
+                value
+                    .checked_add(*offset)
+                    .ok_or_else(|| Error::InvalidOperationJournal(format!("`{}` overflows", self)))
+            }
+        }
+    }
+}
+
+impl fmt::Display for JournalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalValue::Literal(value) => write!(f, "{:#x}", value),
+            JournalValue::Variable { name, offset: 0 } => write!(f, "{}", name),
+            JournalValue::Variable { name, offset } => write!(f, "{} + {:#x}", name, offset),
+        }
+    }
+}
+
+impl FromStr for JournalValue {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidOperationJournal(format!("`{}` is not a valid value", s));
+        let s = s.trim();
+
+        if s.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(s)
+                .map(JournalValue::Literal)
+                .ok_or_else(invalid);
+        }
+
+        let (name, offset) = match s.find('+') {
+            Some(plus) => (
+                s[..plus].trim(),
+                parse_number(s[plus + 1..].trim()).ok_or_else(invalid)?,
+            ),
+            None => (s, 0),
+        };
+
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(invalid());
+        }
+
+        Ok(JournalValue::Variable {
+            name: name.to_string(),
+            offset,
+        })
+    }
+}
+
+/// Parse a decimal number, or a hex number with the prefix `0x`, which can contain `_`.
+fn parse_number(s: &str) -> Option<u64> {
+    let digits = s.replace('_', "");
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        digits.parse().ok()
+    }
+}
+
+impl From<RegisterValue> for JournalValue {
+    fn from(value: RegisterValue) -> Self {
+        match value {
+            RegisterValue::U32(value) => JournalValue::Literal(value.into()),
+            RegisterValue::U64(value) => JournalValue::Literal(value),
+        }
+    }
+}
+
+impl From<JournalValue> for String {
+    fn from(value: JournalValue) -> Self {
+        value.to_string()
+    }
+}
+
+/// A [`JournalValue`] as it is deserialized.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawJournalValue {
+    Number(u64),
+    Text(String),
+}
+
+impl TryFrom<RawJournalValue> for JournalValue {
+    type Error = Error;
+
+    fn try_from(value: RawJournalValue) -> Result<Self, Self::Error> {
+        match value {
+            RawJournalValue::Number(value) => Ok(JournalValue::Literal(value)),
+            RawJournalValue::Text(text) => text.parse(),
+        }
+    }
+}
+
+/// What happens when a replayed operation diverges from the journal, see
+/// [`ReplayOptions::continue_on_divergence`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayPolicy {
+    /// The replay stops at the first divergence.
+    StopOnDivergence,
+    /// The replay continues, and reports all divergences.
+    ContinueAndReport,
+}
+
+/// Options of [`Session::replay_journal`](crate::Session::replay_journal).
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    policy: ReplayPolicy,
+    assert_reads: bool,
+    variables: BTreeMap<String, u64>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplayOptions {
+    /// Create options which stop at the first divergence, and check the values of reads.
+    pub fn new() -> Self {
+        Self {
+            policy: ReplayPolicy::StopOnDivergence,
+            assert_reads: true,
+            variables: BTreeMap::new(),
+        }
+    }
+
+    /// Continue after operations which diverge from the journal, and report all of them.
+    pub fn continue_on_divergence(mut self) -> Self {
+        self.policy = ReplayPolicy::ContinueAndReport;
+        self
+    }
+
+    /// Whether the values which are read have to match the values in the journal. This is
+    /// the default.
+    ///
+    /// Without it, reads are performed, but their values are not checked.
+    pub fn assert_reads(mut self, assert_reads: bool) -> Self {
+        self.assert_reads = assert_reads;
+        self
+    }
+
+    /// Set the variable `name` to `value`, instead of its value in the journal.
+    pub fn variable(mut self, name: impl Into<String>, value: u64) -> Self {
+        self.variables.insert(name.into(), value);
+        self
+    }
+}
+
+/// The result of [`Session::replay_journal`](crate::Session::replay_journal).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// The number of entries which were replayed.
+    pub executed: usize,
+    /// The entries whose replay diverged from the journal, in order.
+    pub divergences: Vec<Divergence>,
+}
+
+impl ReplayReport {
+    /// Returns `true` if all entries were replayed as they were recorded.
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// An entry of a journal whose replay diverged from it, in a [`ReplayReport`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The index of the entry in the journal.
+    pub index: usize,
+    /// The operation of the entry.
+    pub operation: JournalOperation,
+    /// How the replay diverged.
+    pub kind: DivergenceKind,
+}
+
+/// How the replay of a [`Divergence`] diverged from the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// The operation succeeded in the journal, but failed with this error.
+    Failed(String),
+    /// The operation failed in the journal, but succeeded.
+    Succeeded,
+    /// The read returned other values than in the journal.
+    ValueMismatch {
+        /// The values in the journal.
+        expected: Vec<u64>,
+        /// The values which were read.
+        actual: Vec<u64>,
+    },
+}
+
+/// Replay `journal` on the cores of `session`.
+pub(crate) fn replay(
+    session: &mut Session,
+    journal: &OperationJournal,
+    options: &ReplayOptions,
+) -> Result<ReplayReport, Error> {
+    let variables = journal.resolved_variables(options)?;
+    for entry in &journal.entries {
+        entry.operation.check(&variables)?;
+    }
+
+    let mut report = ReplayReport::default();
+
+    for (index, entry) in journal.entries.iter().enumerate() {
+        let result = session
+            .core(entry.operation.core())
+            .and_then(|mut core| entry.operation.replay(&mut core, &variables));
+        report.executed += 1;
+
+        let kind = match (&entry.outcome, result) {
+            (JournalOutcome::Ok, Err(error)) => Some(DivergenceKind::Failed(error.to_string())),
+            (JournalOutcome::Error(_), Ok(_)) => Some(DivergenceKind::Succeeded),
+            (JournalOutcome::Ok, Ok(Some(actual))) if options.assert_reads => {
+                match entry.operation.recorded_values(&variables)? {
+                    Some(expected) if expected != actual => {
+                        Some(DivergenceKind::ValueMismatch { expected, actual })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        if let Some(kind) = kind {
+            log::warn!(
+                "Entry {} of the operation journal diverged: {:?}",
+                index,
+                kind
+            );
+            report.divergences.push(Divergence {
+                index,
+                operation: entry.operation.clone(),
+                kind,
+            });
+
+            if options.policy == ReplayPolicy::StopOnDivergence {
+                break;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Records the operations of the cores of a session into a journal, while it is started.
+///
+/// It is shared by the session and its cores. Operations which are called while another
+/// operation is running are part of it, and not recorded.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JournalRecorder {
+    state: Arc<Mutex<RecorderState>>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderState {
+    recording: bool,
+    /// The number of operations which are running.
+    depth: usize,
+    entries: Vec<JournalEntry>,
+}
+
+/// An operation which is recorded, from [`JournalRecorder::enter`].
+pub(crate) struct Recording {
+    start: Instant,
+}
+
+impl JournalRecorder {
+    fn lock(&self) -> MutexGuard<'_, RecorderState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Start to record, discarding the entries of an earlier recording.
+    pub(crate) fn start(&self) {
+        let mut state = self.lock();
+        state.recording = true;
+        state.entries.clear();
+    }
+
+    /// Stop recording, and return the recorded entries.
+    pub(crate) fn stop(&self) -> Vec<JournalEntry> {
+        let mut state = self.lock();
+        state.recording = false;
+        std::mem::take(&mut state.entries)
+    }
+
+    /// Enter an operation, which is recorded if a recording is running and no other
+    /// operation is. It has to be left with [`JournalRecorder::exit`].
+    pub(crate) fn enter(&self) -> Option<Recording> {
+        let mut state = self.lock();
+        state.depth += 1;
+
+        if state.recording && state.depth == 1 {
+            Some(Recording {
+                start: Instant::now(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Enter an operation which isn't recorded, and neither are the operations it calls. It
+    /// has to be left with [`JournalRecorder::leave`].
+    pub(crate) fn suspend(&self) {
+        self.lock().depth += 1;
+    }
+
+    /// Leave an operation without recording it.
+    pub(crate) fn leave(&self) {
+        let mut state = self.lock();
+        state.depth = state.depth.saturating_sub(1);
+    }
+
+    /// Leave an operation, and record it with its `result` if it was entered as a
+    /// [`Recording`]. `operation` is only called if it is recorded, with the value of a
+    /// successful `result`.
+    pub(crate) fn exit<R>(
+        &self,
+        recording: Option<Recording>,
+        result: &Result<R, Error>,
+        operation: impl FnOnce(Option<&R>) -> JournalOperation,
+    ) {
+        let entry = recording.map(|recording| JournalEntry {
+            operation: operation(result.as_ref().ok()),
+            outcome: JournalOutcome::of(result),
+            duration_us: recording.start.elapsed().as_micros() as u64,
+        });
+
+        let mut state = self.lock();
+        state.depth = state.depth.saturating_sub(1);
+        if let Some(entry) = entry {
+            state.entries.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_are_parsed_and_written_in_hex() {
+        let value: JournalValue = "rcc + 0x18".parse().unwrap();
+        assert_eq!(
+            value,
+            JournalValue::Variable {
+                name: "rcc".to_string(),
+                offset: 0x18
+            }
+        );
+        assert_eq!(value.to_string(), "rcc + 0x18");
+
+        assert_eq!(
+            "0x4002_1000".parse::<JournalValue>().unwrap(),
+            JournalValue::Literal(0x4002_1000)
+        );
+        assert_eq!(JournalValue::Literal(16).to_string(), "0x10");
+        assert!("rcc + x".parse::<JournalValue>().is_err());
+        assert!("r-c".parse::<JournalValue>().is_err());
+
+        let mut variables = BTreeMap::new();
+        assert!(value.resolve(&variables).is_err());
+        variables.insert("rcc".to_string(), 0x4002_1000);
+        assert_eq!(value.resolve(&variables).unwrap(), 0x4002_1018);
+    }
+
+    #[test]
+    fn journals_round_trip_through_both_formats() {
+        let mut journal = OperationJournal::new("stm32wb55ccux");
+        journal.entries.push(JournalEntry {
+            operation: JournalOperation::write_memory(0, 0x4002_1018, JournalWidth::U32, vec![1]),
+            outcome: JournalOutcome::Ok,
+            duration_us: 12,
+        });
+        journal.entries.push(JournalEntry {
+            operation: JournalOperation::Halt {
+                core: 0,
+                timeout_ms: 100,
+            },
+            outcome: JournalOutcome::Error("timeout".to_string()),
+            duration_us: 100_000,
+        });
+        journal.define_variable("rcc", 0x4002_1000..0x4002_1400);
+
+        for format in [JournalFormat::Json, JournalFormat::Yaml] {
+            let text = journal.serialize(format);
+            assert!(text.contains("rcc + 0x18"), "{}", text);
+            assert_eq!(OperationJournal::parse(&text, format).unwrap(), journal);
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut journal = OperationJournal::new("stm32wb55ccux");
+        journal.format_version = OPERATION_JOURNAL_VERSION + 1;
+        let text = journal.serialize(JournalFormat::Json);
+
+        assert!(matches!(
+            OperationJournal::parse(&text, JournalFormat::Json),
+            Err(Error::UnsupportedOperationJournalVersion { version, .. })
+                if version == OPERATION_JOURNAL_VERSION + 1
+        ));
+        assert!(matches!(
+            OperationJournal::parse("{}", JournalFormat::Json),
+            Err(Error::InvalidOperationJournal(_))
+        ));
+    }
+}
@@ -0,0 +1,252 @@
+//! Breakpoints on the panic handlers of a firmware.
+//!
+//! [`Session::break_on_panic`](crate::Session::break_on_panic) looks up the symbols which
+//! indicate a panic in the ELF file of the firmware, and sets hardware breakpoints on them.
+//! The breakpoints form the breakpoint group [`PANIC_BREAKPOINT_GROUP`] of the core, and a
+//! core which halts at one of them reports [`HaltReason::Panic`](crate::HaltReason::Panic).
+
+use object::{Object, ObjectSection, ObjectSymbol};
+
+use crate::{Core, Error};
+
+/// The name of the breakpoint group with the breakpoints on the panic handlers.
+pub const PANIC_BREAKPOINT_GROUP: &str = "panic";
+
+/// The symbol of the HardFault handler of Cortex-M firmware.
+const HARD_FAULT: &str = "HardFault";
+
+/// The offset of the HardFault handler in the vector table of a Cortex-M core.
+const HARD_FAULT_VECTOR_OFFSET: u64 = 0xC;
+
+/// Options for [`Session::break_on_panic`](crate::Session::break_on_panic).
+///
+/// This struct should be created using [`PanicBreakOptions::new()`], and can be configured
+/// by setting the fields directly.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PanicBreakOptions {
+    /// The core to set the breakpoints on.
+    pub core: usize,
+    /// The symbols which indicate a panic.
+    ///
+    /// Demangled Rust paths, e.g. `core::panicking::panic`, match the mangled symbols of
+    /// the legacy mangling scheme, regardless of their hash.
+    pub symbols: Vec<String>,
+    /// Whether to break in the HardFault handler of Cortex-M cores.
+    ///
+    /// If the ELF file has no `HardFault` symbol, the handler is read from the vector table.
+    pub hard_fault: bool,
+}
+
+impl PanicBreakOptions {
+    /// Break on `rust_begin_unwind`, `core::panicking::panic`, `abort` and the HardFault
+    /// handler of core 0.
+    pub fn new() -> Self {
+        Self {
+            core: 0,
+            symbols: vec![
+                "rust_begin_unwind".to_owned(),
+                "core::panicking::panic".to_owned(),
+                "abort".to_owned(),
+            ],
+            hard_fault: true,
+        }
+    }
+}
+
+impl Default for PanicBreakOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A symbol which indicates a panic, and whether a breakpoint was set on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicHook {
+    /// The symbol, as given in [`PanicBreakOptions::symbols`], or `HardFault`.
+    pub symbol: String,
+    /// The address of the symbol, or `None` if it was not found in the ELF file.
+    pub address: Option<u64>,
+    /// The address the breakpoint was set at, which differs from `address` if the firmware
+    /// is loaded at other addresses than it was linked at, see
+    /// [`Core::set_load_offset`](crate::Core::set_load_offset).
+    pub load_address: Option<u64>,
+    /// Whether a breakpoint was set at the address.
+    ///
+    /// This is `false` if the symbol was not found, or if no hardware breakpoint was left.
+    pub armed: bool,
+}
+
+/// Look up the addresses of the symbols which indicate a panic in `elf`.
+pub(crate) fn resolve(
+    elf: &[u8],
+    options: &PanicBreakOptions,
+    cortex_m: bool,
+) -> Result<Vec<PanicHook>, Error> {
+    let file = object::File::parse(elf).map_err(|e| Error::InvalidElf(e.to_string()))?;
+
+    // The address of Thumb functions has bit 0 set.
+    let code_address = |address: u64| match file.architecture() {
+        object::Architecture::Arm => address & !1,
+        _ => address,
+    };
+
+    let find_symbol = |name: &str| {
+        file.symbols()
+            .find(|symbol| match symbol.name() {
+                Ok(symbol) => symbol == name || demangle(symbol).as_deref() == Some(name),
+                Err(_) => false,
+            })
+            .map(|symbol| code_address(symbol.address()))
+    };
+
+    let mut hooks: Vec<PanicHook> = options
+        .symbols
+        .iter()
+        .map(|symbol| PanicHook {
+            symbol: symbol.clone(),
+            address: find_symbol(symbol),
+            load_address: None,
+            armed: false,
+        })
+        .collect();
+
+    if options.hard_fault && cortex_m {
+        let address = match find_symbol(HARD_FAULT) {
+            Some(address) => Some(address),
+            None => hard_fault_vector(&file)?.map(code_address),
+        };
+
+        hooks.push(PanicHook {
+            symbol: HARD_FAULT.to_owned(),
+            address,
+            load_address: None,
+            armed: false,
+        });
+    }
+
+    Ok(hooks)
+}
+
+/// Read the HardFault handler from the `.vector_table` section of Cortex-M firmware.
+fn hard_fault_vector(file: &object::File) -> Result<Option<u64>, Error> {
+    let section = match file.section_by_name(".vector_table") {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+
+    let data = section
+        .data()
+        .map_err(|e| Error::InvalidElf(format!("Failed to read the vector table: {}", e)))?;
+
+    let offset = HARD_FAULT_VECTOR_OFFSET as usize;
+
+    Ok(data
+        .get(offset..offset + 4)
+        .map(|vector| u32::from_le_bytes(vector.try_into().unwrap()) as u64))
+}
+
+/// Demangle a Rust symbol of the legacy mangling scheme into its path, without the hash.
+///
+/// Returns `None` if `symbol` is not mangled this way.
+fn demangle(symbol: &str) -> Option<String> {
+    let mut rest = symbol
+        .strip_prefix("_ZN")
+        .or_else(|| symbol.strip_prefix("__ZN"))?;
+
+    let mut components = Vec::new();
+
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len: usize = rest[..digits].parse().ok()?;
+        let component = rest.get(digits..digits + len)?;
+
+        components.push(component);
+        rest = &rest[digits + len..];
+    }
+
+    // The last component is the hash, e.g. `h0123456789abcdef`.
+    if let Some(hash) = components.last() {
+        if hash.len() == 17
+            && hash.starts_with('h')
+            && hash[1..].chars().all(|c| c.is_ascii_hexdigit())
+        {
+            components.pop();
+        }
+    }
+
+    Some(components.join("::"))
+}
+
+/// Set breakpoints on the `hooks` which were found, as the panic breakpoint group of `core`.
+///
+/// The addresses of the hooks are translated with the address map of the core.
+pub(crate) fn arm(core: &mut Core, hooks: &mut [PanicHook]) -> Result<(), Error> {
+    core.clear_breakpoint_group(PANIC_BREAKPOINT_GROUP)?;
+
+    for hook in hooks.iter_mut() {
+        let address = match hook.address {
+            Some(address) => address,
+            None => {
+                log::warn!(
+                    "Not breaking on panics in '{}', it was not found",
+                    hook.symbol
+                );
+                continue;
+            }
+        };
+
+        match core.set_hw_breakpoint_at_link_address(address) {
+            Ok(load_address) => {
+                core.add_to_breakpoint_group(PANIC_BREAKPOINT_GROUP, load_address);
+                hook.load_address = Some(load_address);
+                hook.armed = true;
+            }
+            Err(error) => log::warn!(
+                "Not breaking on panics in '{}' at {:#010x}: {}",
+                hook.symbol,
+                address,
+                error
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn demangle_legacy_symbols() {
+        assert_eq!(
+            demangle("_ZN4core9panicking5panic17hf5453aa8b85e9985E").as_deref(),
+            Some("core::panicking::panic")
+        );
+        assert_eq!(demangle("rust_begin_unwind"), None);
+        assert_eq!(demangle("_ZN4core9panicking"), None);
+    }
+
+    #[test]
+    fn resolve_cortex_m_panic_symbols() {
+        let elf = std::fs::read("tests/inlined-function").unwrap();
+
+        let hooks = resolve(&elf, &PanicBreakOptions::new(), true).unwrap();
+
+        let addresses: Vec<_> = hooks
+            .iter()
+            .map(|hook| (hook.symbol.as_str(), hook.address))
+            .collect();
+
+        assert_eq!(
+            addresses,
+            [
+                ("rust_begin_unwind", Some(0x1326)),
+                ("core::panicking::panic", Some(0x1010)),
+                ("abort", None),
+                ("HardFault", Some(0x1456)),
+            ]
+        );
+    }
+}
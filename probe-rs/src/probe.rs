@@ -1,13 +1,21 @@
+//! Debug probes and their drivers.
+//!
+//! Out-of-tree drivers for additional kinds of probes can be added with the [`plugin`] module.
+//! Probes which were opened by the user can be driven over a [`plugin::ProbeTransport`].
+
 pub(crate) mod cmsisdap;
 pub(crate) mod espusbjtag;
 pub(crate) mod fake_probe;
+pub(crate) mod firmware;
 #[cfg(feature = "ftdi")]
 pub(crate) mod ftdi;
 pub(crate) mod jlink;
+pub mod plugin;
 pub(crate) mod stlink;
+pub(crate) mod transport;
+pub(crate) mod wchlink;
 
 use crate::error::Error;
-use crate::Session;
 use crate::{
     architecture::arm::communication_interface::UninitializedArmProbe,
     config::{RegistryError, TargetSelector},
@@ -21,12 +29,18 @@ use crate::{
         },
         riscv::communication_interface::RiscvCommunicationInterface,
     },
-    Permissions,
+    AttachOptions, AttachPlan, Permissions,
 };
+use crate::{HealthLog, Session};
 use jlink::list_jlink_devices;
 use std::{convert::TryFrom, fmt};
 
 use self::espusbjtag::list_espjtag_devices;
+use self::plugin::{ProbeCapabilities, ProbeDriver};
+use self::transport::{ProbeTransport, TransportKind};
+use self::wchlink::list_wchlink_devices;
+
+pub use self::firmware::{FirmwareAdjustment, FirmwareFeature, FirmwareLimitation};
 
 /// Used to log warnings when the measured target voltage is
 /// lower than 1.4V, if at all measureable.
@@ -35,7 +49,7 @@ const LOW_TARGET_VOLTAGE_WARNING_THRESHOLD: f32 = 1.4;
 /// The protocol that is to be used by the probe when communicating with the target.
 ///
 /// For ARM select `Swd` and for RISC-V select `Jtag`.
-#[derive(Copy, Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, serde::Serialize, serde::Deserialize)]
 pub enum WireProtocol {
     /// Serial Wire Debug is ARMs proprietary standard for communicating with ARM cores.
     /// You can find specifics in the [`ARM Debug Interface v5.2`](https://developer.arm.com/documentation/ihi0031/f/?lang=en) specification.
@@ -75,7 +89,9 @@ impl std::str::FromStr for WireProtocol {
 /// which batched command actually encountered the error.
 #[derive(Copy, Clone, Debug)]
 pub enum BatchCommand {
+    /// Read a register.
     Read(PortType, u16),
+    /// Write a value to a register.
     Write(PortType, u16, u32),
 }
 
@@ -92,6 +108,18 @@ impl fmt::Display for BatchCommand {
     }
 }
 
+fn format_protocols(protocols: &[WireProtocol]) -> String {
+    if protocols.is_empty() {
+        return "none".to_owned();
+    }
+
+    protocols
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// This error occurs whenever the debug probe logic encounters an error while operating the relevant debug probe.
 #[derive(thiserror::Error, Debug)]
 pub enum DebugProbeError {
@@ -111,6 +139,14 @@ pub enum DebugProbeError {
     /// The selected wire protocol is not supported with given probe.
     #[error("Probe does not support {0}")]
     UnsupportedProtocol(WireProtocol),
+    /// The requested wire protocol is not one of the protocols supported by the probe.
+    #[error("Probe does not support {requested}, supported protocols: {}", format_protocols(.supported))]
+    ProtocolNotSupported {
+        /// The protocol which was requested.
+        requested: WireProtocol,
+        /// The protocols supported by the probe.
+        supported: Vec<WireProtocol>,
+    },
     // TODO: This is core specific, so should probably be moved there.
     /// A timeout occurred during an operation.
     #[error("Operation timed out")]
@@ -164,6 +200,27 @@ pub enum DebugProbeError {
     /// The hardware breakpoint could not be set because all breakpoint units are in use.
     #[error("Unable to set hardware breakpoint, all available breakpoint units are in use.")]
     BreakpointUnitsExceeded,
+    /// The probe lacks a capability the operation requires, see [`Probe::capabilities`].
+    #[error("This operation requires {capability}, which the probe {probe} does not support")]
+    MissingCapability {
+        /// The required capability, e.g. `SWO capture`.
+        capability: String,
+        /// The name of the probe, with its firmware version if it is known.
+        probe: String,
+    },
+    /// The firmware of the probe is too old for a feature, see
+    /// [`Probe::firmware_adjustments`].
+    #[error("{feature} requires {firmware} firmware {minimum} or later; detected {detected}")]
+    FirmwareTooOld {
+        /// The feature which was used.
+        feature: FirmwareFeature,
+        /// The kind of firmware, e.g. `ST-Link`.
+        firmware: String,
+        /// The first version of the firmware which supports the feature.
+        minimum: String,
+        /// The version of the firmware of the probe.
+        detected: String,
+    },
     /// Some other error occurred.
     #[error(transparent)]
     Other(#[from] anyhow::Error),
@@ -243,11 +300,46 @@ impl Probe {
         }
     }
 
+    /// Create a [`Probe`] which speaks the protocol `kind` over a `transport` opened by the user,
+    /// e.g. a USB device which was opened by a permission broker.
+    ///
+    /// The probe is initialized like a probe opened with [`Probe::open`], so everything else,
+    /// e.g. setting the speed or attaching to a target, works the same.
+    pub fn from_custom_transport(
+        kind: TransportKind,
+        transport: Box<dyn ProbeTransport>,
+    ) -> Result<Self, DebugProbeError> {
+        let probe = match kind {
+            TransportKind::CmsisDapV1 => cmsisdap::CmsisDap::new_from_transport(transport, true)?,
+            TransportKind::CmsisDapV2 => cmsisdap::CmsisDap::new_from_transport(transport, false)?,
+        };
+
+        Ok(Probe::from_specific_probe(probe))
+    }
+
+    /// Open a probe which is shared by a [`ProbeServer`](crate::remote::ProbeServer) at
+    /// `address`, with the shared `token`.
+    ///
+    /// The probe is used like a local probe, see the [`remote`](crate::remote) module for
+    /// how it is shared with other clients. Use [`RemoteProbe::connect`](crate::remote::RemoteProbe::connect)
+    /// to get the statistics of the connection.
+    #[cfg(feature = "remote")]
+    pub fn open_remote(
+        address: impl std::net::ToSocketAddrs,
+        token: &str,
+    ) -> Result<Self, DebugProbeError> {
+        let probe = crate::remote::RemoteProbe::connect(address, token)?;
+
+        Ok(Probe::from_specific_probe(Box::new(probe)))
+    }
+
     /// Get a list of all debug probes found.
     /// This can be used to select the debug probe which
     /// should be used.
+    ///
+    /// This includes the probes found by drivers registered with [`plugin::register_driver`].
     pub fn list_all() -> Vec<DebugProbeInfo> {
-        let mut list = cmsisdap::tools::list_cmsisdap_devices();
+        let mut list = cmsisdap::CmsisDapDriver.list_probes();
         #[cfg(feature = "ftdi")]
         {
             list.extend(ftdi::list_ftdi_devices());
@@ -258,6 +350,10 @@ impl Probe {
 
         list.extend(list_espjtag_devices());
 
+        list.extend(list_wchlink_devices());
+
+        list.extend(plugin::list_registered_probes());
+
         list
     }
 
@@ -265,7 +361,14 @@ impl Probe {
     /// [`Probe::list_all()`] function to get the information
     /// about all probes available.
     pub fn open(selector: impl Into<DebugProbeSelector> + Clone) -> Result<Self, DebugProbeError> {
-        match cmsisdap::CmsisDap::new_from_selector(selector.clone()) {
+        let selector = selector.into();
+
+        match plugin::open_registered(&selector) {
+            Ok(link) => return Ok(Probe::from_specific_probe(link)),
+            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+            Err(e) => return Err(e),
+        };
+        match cmsisdap::CmsisDapDriver.open(&selector) {
             Ok(link) => return Ok(Probe::from_specific_probe(link)),
             Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
             Err(e) => return Err(e),
@@ -286,7 +389,12 @@ impl Probe {
             Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
             Err(e) => return Err(e),
         };
-        match espusbjtag::EspUsbJtag::new_from_selector(selector) {
+        match espusbjtag::EspUsbJtag::new_from_selector(selector.clone()) {
+            Ok(link) => return Ok(Probe::from_specific_probe(link)),
+            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+            Err(e) => return Err(e),
+        };
+        match wchlink::WchLink::new_from_selector(selector) {
             Ok(link) => return Ok(Probe::from_specific_probe(link)),
             Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
             Err(e) => return Err(e),
@@ -308,13 +416,40 @@ impl Probe {
     ///
     /// If this doesn't work, you might want to try [`Probe::attach_under_reset`]
     pub fn attach(
-        mut self,
+        self,
         target: impl Into<TargetSelector>,
         permissions: Permissions,
     ) -> Result<Session, Error> {
+        self.attach_with_options(target, permissions, AttachOptions::default())
+    }
+
+    /// Attach to the chip, using the given [`AttachOptions`].
+    ///
+    /// See [`Probe::attach`] for details.
+    pub fn attach_with_options(
+        self,
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        options: AttachOptions,
+    ) -> Result<Session, Error> {
+        self.attach_planned(target.into(), permissions, options, AttachPlan::new())
+    }
+
+    /// Attach to the chip as `plan` says, see [`Session::attach_with_plan`].
+    pub(crate) fn attach_planned(
+        mut self,
+        target: TargetSelector,
+        permissions: Permissions,
+        options: AttachOptions,
+        plan: AttachPlan,
+    ) -> Result<Session, Error> {
+        if let Some(protocol) = options.protocol {
+            self.select_protocol(protocol)?;
+        }
+
         self.attached = true;
 
-        Session::new(self, target.into(), AttachMethod::Normal, permissions)
+        Session::new(self, target, plan, permissions, options)
     }
 
     /// Attach to a target without knowing what target you have at hand.
@@ -348,26 +483,125 @@ impl Probe {
     /// This is necessary if the chip is not responding to the SWD reset sequence.
     /// For example this can happen if the chip has the SWDIO pin remapped.
     pub fn attach_under_reset(
-        mut self,
+        self,
         target: impl Into<TargetSelector>,
         permissions: Permissions,
     ) -> Result<Session, Error> {
-        self.attached = true;
+        self.attach_under_reset_with_options(target, permissions, AttachOptions::default())
+    }
+
+    /// Attach to the chip under hard-reset, using the given [`AttachOptions`].
+    ///
+    /// See [`Probe::attach_under_reset`] for details.
+    pub fn attach_under_reset_with_options(
+        self,
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        options: AttachOptions,
+    ) -> Result<Session, Error> {
+        if !self.capabilities().reset_control {
+            return Err(DebugProbeError::MissingCapability {
+                capability: "control of the reset line".to_owned(),
+                probe: self.description(),
+            }
+            .into());
+        }
 
         // The session will de-assert reset after connecting to the debug interface.
-        Session::new(self, target.into(), AttachMethod::UnderReset, permissions)
+        self.attach_planned(
+            target.into(),
+            permissions,
+            options,
+            AttachPlan::under_reset(),
+        )
+    }
+
+    /// The probe driver, e.g. to share the probe with a [`ProbeServer`](crate::remote::ProbeServer).
+    #[cfg(feature = "remote")]
+    pub(crate) fn into_inner(self) -> Box<dyn DebugProbe> {
+        self.inner
     }
 
     pub(crate) fn inner_attach(&mut self) -> Result<(), DebugProbeError> {
         self.inner.attach()
     }
 
+    pub(crate) fn inner_detach(&mut self) -> Result<(), DebugProbeError> {
+        self.inner.detach()
+    }
+
+    /// Configure the protocol speed, even if the probe is marked as attached.
+    ///
+    /// Used while attaching, before the probe actually attached to the target.
+    pub(crate) fn set_speed_unchecked(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        self.inner.set_speed(speed_khz)
+    }
+
+    pub(crate) fn set_health_log(&mut self, health_log: HealthLog) {
+        self.inner.set_health_log(health_log)
+    }
+
     /// Selects the transport protocol to be used by the debug probe.
+    ///
+    /// If the probe doesn't support `protocol`, [`DebugProbeError::ProtocolNotSupported`]
+    /// is returned, which lists the protocols the probe supports. The protocol can only be
+    /// selected before attaching, use [`Session::switch_protocol`] to switch the protocol
+    /// of an attached session.
     pub fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
-        if !self.attached {
-            self.inner.select_protocol(protocol)
-        } else {
-            Err(DebugProbeError::Attached)
+        if self.attached {
+            return Err(DebugProbeError::Attached);
+        }
+
+        let supported = self.inner.supported_protocols();
+        if !supported.contains(&protocol) {
+            return Err(DebugProbeError::ProtocolNotSupported {
+                requested: protocol,
+                supported,
+            });
+        }
+
+        self.inner.select_protocol(protocol)
+    }
+
+    /// Get the transport protocol currently in active use by the probe.
+    ///
+    /// Returns `None` if the probe has not selected a protocol yet.
+    pub fn active_protocol(&self) -> Option<WireProtocol> {
+        self.inner.active_protocol()
+    }
+
+    /// Get the transport protocols supported by the probe.
+    pub fn supported_protocols(&self) -> Vec<WireProtocol> {
+        self.inner.supported_protocols()
+    }
+
+    /// Get the protocols, features and limits of the probe.
+    ///
+    /// Operations which need a missing capability, e.g. SWO capture or attaching under
+    /// reset, fail with [`DebugProbeError::MissingCapability`] before they touch the target.
+    pub fn capabilities(&self) -> ProbeCapabilities {
+        self.inner.capabilities()
+    }
+
+    /// Get the version of the firmware of the probe, if the probe reports it.
+    pub fn firmware_version(&self) -> Option<String> {
+        self.inner.firmware_version()
+    }
+
+    /// Get the adjustments of the capabilities of the probe to the version of its firmware,
+    /// e.g. features which the firmware doesn't support yet, or known bugs of it which are
+    /// worked around.
+    ///
+    /// The unavailable features are already removed from [`Probe::capabilities`].
+    pub fn firmware_adjustments(&self) -> Vec<FirmwareAdjustment> {
+        self.inner.firmware_adjustments()
+    }
+
+    /// Returns the name of the probe, with its firmware version if it is known.
+    pub(crate) fn description(&self) -> String {
+        match self.firmware_version() {
+            Some(version) => format!("{} (firmware {})", self.get_name(), version),
+            None => self.get_name(),
         }
     }
 
@@ -554,6 +788,14 @@ pub trait DebugProbe: Send + fmt::Debug {
     /// Get the transport protocol currently in active use by the debug probe.
     fn active_protocol(&self) -> Option<WireProtocol>;
 
+    /// Get the transport protocols supported by the debug probe.
+    ///
+    /// The default implementation returns both SWD and JTAG, probes which only support
+    /// one of them have to override it.
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Swd, WireProtocol::Jtag]
+    }
+
     /// Check if the proble offers an interface to debug ARM chips.
     fn has_arm_interface(&self) -> bool {
         false
@@ -616,6 +858,44 @@ pub trait DebugProbe: Send + fmt::Debug {
     fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
         Ok(None)
     }
+
+    /// Set the log in which the probe records the errors it recovered from,
+    /// e.g. transfers which succeeded after a retry.
+    ///
+    /// Probes which don't recover from errors can ignore this.
+    fn set_health_log(&mut self, _health_log: HealthLog) {}
+
+    /// Get the protocols, features and limits of the probe, see [`Probe::capabilities`].
+    ///
+    /// The default implementation derives the protocols from [`DebugProbe::supported_protocols`]
+    /// and SWO capture from [`DebugProbe::get_swo_interface`], and assumes that the probe
+    /// controls the reset line. Probes should override it to report their limits.
+    fn capabilities(&self) -> ProbeCapabilities {
+        let protocols = self.supported_protocols();
+
+        ProbeCapabilities {
+            swd: protocols.contains(&WireProtocol::Swd),
+            jtag: protocols.contains(&WireProtocol::Jtag),
+            swo: self.get_swo_interface().is_some(),
+            reset_control: true,
+            ..ProbeCapabilities::default()
+        }
+    }
+
+    /// Get the version of the firmware of the probe, if the probe reports it.
+    fn firmware_version(&self) -> Option<String> {
+        None
+    }
+
+    /// Get the adjustments of the capabilities to the firmware version, see
+    /// [`Probe::firmware_adjustments`].
+    ///
+    /// Probes whose firmware versions differ in their features derive them from the table
+    /// of their driver, and remove the unavailable features from
+    /// [`DebugProbe::capabilities`].
+    fn firmware_adjustments(&self) -> Vec<FirmwareAdjustment> {
+        Vec::new()
+    }
 }
 
 /// Denotes the type of a given [`DebugProbe`].
@@ -631,6 +911,12 @@ pub enum DebugProbeType {
     JLink,
     /// Built in RISC-V ESP JTAG debug probe
     EspJtag,
+    /// WCH-Link in its RISC-V mode
+    WchLink,
+    /// A probe found by an out-of-tree driver, identified by the name of the driver.
+    ///
+    /// See [`plugin::ProbeDriver`].
+    Plugin(String),
 }
 
 /// Gathers some information about a debug probe which was found during a scan.
@@ -694,10 +980,13 @@ impl DebugProbeInfo {
     }
 }
 
+/// An error which occurs when a [`DebugProbeSelector`] is parsed from a string.
 #[derive(thiserror::Error, Debug)]
 pub enum DebugProbeSelectorParseError {
+    /// The VID or PID is not a valid hexadecimal number.
     #[error("The VID or PID could not be parsed: {0}")]
     ParseInt(#[from] std::num::ParseIntError),
+    /// The string is not in the form `VID:PID:<Serial>`.
     #[error("Please use a string in the form `VID:PID:<Serial>` where Serial is optional.")]
     Format,
 }
@@ -794,6 +1083,7 @@ impl fmt::Display for DebugProbeSelector {
 /// This trait should be implemented by all probes which offer low-level access to
 /// the JTAG protocol, i.e. directo control over the bytes sent and received.
 pub trait JTAGAccess: DebugProbe {
+    /// Read a JTAG register with a length of `len` bits.
     fn read_register(&mut self, address: u32, len: u32) -> Result<Vec<u8>, DebugProbeError>;
 
     /// For Riscv, and possibly other interfaces, the JTAG interface has to remain in
@@ -820,6 +1110,18 @@ pub trait JTAGAccess: DebugProbe {
         len: u32,
     ) -> Result<Vec<u8>, DebugProbeError>;
 
+    /// The maximum number of register writes the probe can execute in a single batch.
+    ///
+    /// Callers split larger batches before passing them to [`JTAGAccess::write_register_batch`].
+    /// `None` means that the number of writes is not limited.
+    fn max_queued_operations(&self) -> Option<usize> {
+        None
+    }
+
+    /// Execute a batch of register writes.
+    ///
+    /// If a write fails, the results of the successful writes before it are returned
+    /// in the error.
     fn write_register_batch(
         &mut self,
         writes: &[JtagWriteCommand],
@@ -840,24 +1142,75 @@ pub trait JTAGAccess: DebugProbe {
     }
 }
 
+/// The index of a result in a batch of JTAG commands.
 pub type DeferredResultIndex = usize;
 
+/// A command in the native protocol of a probe, which probe-rs doesn't interpret, e.g. a
+/// CMSIS-DAP vendor command which toggles a GPIO of the probe.
+///
+/// The command is executed in order with the other commands of the probe, see
+/// [`Core::enqueue_probe_command`](crate::Core::enqueue_probe_command).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VendorCommand {
+    /// The bytes of the command, as the probe expects them.
+    pub bytes: Vec<u8>,
+}
+
+impl VendorCommand {
+    /// A command consisting of `bytes`.
+    pub fn new(bytes: impl Into<Vec<u8>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+}
+
+/// The index of the response of an enqueued [`VendorCommand`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VendorCommandIndex(pub DeferredResultIndex);
+
+/// How a probe orders [`VendorCommand`]s with its other commands, see
+/// [`ProbeCapabilities::vendor_commands`](crate::ProbeCapabilities::vendor_commands).
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum VendorCommandOrdering {
+    /// The probe doesn't execute vendor commands.
+    #[default]
+    Unsupported,
+    /// The probe queues vendor commands with its register transfers, and executes them in
+    /// order.
+    Batched,
+    /// The probe executes vendor commands right away, so the transfers queued before a
+    /// command are flushed first, which costs a round trip if there are any.
+    Flushed,
+}
+
+/// A JTAG register write, used in [`JTAGAccess::write_register_batch`].
 #[derive(Debug, Clone)]
 pub struct JtagWriteCommand {
+    /// The address of the register.
     pub address: u32,
+    /// The data which is written.
     pub data: Vec<u8>,
+    /// The length of the register in bits.
     pub len: u32,
+    /// Converts the data shifted out of the register into the result of the command.
     pub transform: fn(Vec<u8>) -> Result<CommandResult, DebugProbeError>,
 }
 
+/// An error which occured while executing a batch of JTAG commands.
 #[derive(thiserror::Error, Debug)]
 pub struct BatchExecutionError {
+    /// The error of the failed command.
     #[source]
     pub error: DebugProbeError,
+    /// The results of the commands which were executed successfully before the error.
     pub results: Vec<CommandResult>,
 }
 
 impl BatchExecutionError {
+    /// Create a new error from the error of the failed command and the previous results.
     pub fn new(error: DebugProbeError, results: Vec<CommandResult>) -> BatchExecutionError {
         BatchExecutionError { error, results }
     }
@@ -877,10 +1230,15 @@ impl std::fmt::Display for BatchExecutionError {
 /// Results generated by `JtagCommand`s
 #[derive(Debug, Clone)]
 pub enum CommandResult {
+    /// The command has no result.
     None,
+    /// An 8-bit value.
     U8(u8),
+    /// A 16-bit value.
     U16(u16),
+    /// A 32-bit value.
     U32(u32),
+    /// A sequence of bytes.
     VecU8(Vec<u8>),
 }
 
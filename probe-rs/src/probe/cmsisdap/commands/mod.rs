@@ -5,6 +5,7 @@ pub mod swo;
 pub mod transfer;
 
 use crate::probe::cmsisdap::commands::general::info::PacketSizeCommand;
+use crate::probe::transport::{self, ProbeTransport};
 use crate::DebugProbeError;
 use std::str::Utf8Error;
 use std::time::Duration;
@@ -30,8 +31,12 @@ pub enum CmsisDapError {
     SwoModeNotAvailable,
     #[error("USB Error reading SWO data.")]
     SwoReadError(#[source] rusb::Error),
+    #[error("Error reading SWO data from the transport.")]
+    SwoTransportError(#[source] std::io::Error),
     #[error("Could not determine a suitable packet size for this probe")]
     NoPacketSize,
+    #[error("A vendor command must start with an ID from 0x80 to 0x9F, and fit in a packet")]
+    InvalidVendorCommand,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -40,6 +45,8 @@ pub enum SendError {
     HidApi(#[from] hidapi::HidError),
     #[error("Error in the USB access")]
     UsbError(rusb::Error),
+    #[error("Error in the access to the transport")]
+    TransportError(#[source] std::io::Error),
     #[error("Not enough data in response from probe")]
     NotEnoughData,
     #[error("Status can only be 0x00 or 0xFF")]
@@ -93,11 +100,47 @@ pub enum CmsisDapDevice {
         max_packet_size: usize,
         swo_ep: Option<(u8, usize)>,
     },
+
+    /// CMSIS-DAP v1 or v2 over a transport which was opened by the user.
+    /// Stores the transport, whether it exchanges HID reports, and the packet size.
+    Custom {
+        transport: Box<dyn ProbeTransport>,
+        hid: bool,
+        packet_size: usize,
+    },
 }
 
 impl CmsisDapDevice {
+    /// Open a CMSIS-DAP v1 or v2 device over a `transport` which was opened by the user.
+    pub(crate) fn from_transport(transport: Box<dyn ProbeTransport>, hid: bool) -> Self {
+        // Start with the packet size of the transport. We'll request the actual size
+        // to use from the probe later.
+        let packet_size = transport
+            .max_packet_size()
+            .unwrap_or(transport::DEFAULT_PACKET_SIZE);
+
+        CmsisDapDevice::Custom {
+            transport,
+            hid,
+            packet_size,
+        }
+    }
+
+    /// The size of the reports which have to be written completely, for HID devices.
+    fn report_size(&self) -> Option<usize> {
+        match self {
+            CmsisDapDevice::V1 { report_size, .. } => Some(*report_size),
+            CmsisDapDevice::Custom {
+                hid: true,
+                packet_size,
+                ..
+            } => Some(*packet_size),
+            _ => None,
+        }
+    }
+
     /// Read from the probe into `buf`, returning the number of bytes read on success.
-    fn read(&self, buf: &mut [u8]) -> Result<usize, SendError> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, SendError> {
         match self {
             CmsisDapDevice::V1 { handle, .. } => match handle.read_timeout(buf, 100)? {
                 // Timeout is not indicated by error, but by returning 0 read bytes
@@ -108,11 +151,18 @@ impl CmsisDapDevice {
                 let timeout = Duration::from_millis(100);
                 Ok(handle.read_bulk(*in_ep, buf, timeout)?)
             }
+            CmsisDapDevice::Custom { transport, .. } => {
+                let result = transport.read(buf, Duration::from_millis(100));
+                if transport::is_timeout(&result) {
+                    return Err(SendError::Timeout);
+                }
+                result.map_err(SendError::TransportError)
+            }
         }
     }
 
     /// Write `buf` to the probe, returning the number of bytes written on success.
-    fn write(&self, buf: &[u8]) -> Result<usize, SendError> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, SendError> {
         match self {
             CmsisDapDevice::V1 { handle, .. } => Ok(handle.write(buf)?),
             CmsisDapDevice::V2 { handle, out_ep, .. } => {
@@ -120,13 +170,19 @@ impl CmsisDapDevice {
                 // Skip first byte as it's set to 0 for HID transfers
                 Ok(handle.write_bulk(*out_ep, &buf[1..], timeout)?)
             }
+            CmsisDapDevice::Custom { transport, .. } => {
+                // Skip first byte as it's set to 0 for HID transfers
+                transport
+                    .write(&buf[1..], Duration::from_millis(100))
+                    .map_err(SendError::TransportError)
+            }
         }
     }
 
     /// Drain any pending data from the probe, ensuring future responses are
     /// synchronised to requests. Swallows any errors, which are expected if
     /// there is no pending data to read.
-    pub(super) fn drain(&self) {
+    pub(super) fn drain(&mut self) {
         log::debug!("Draining probe of any pending data.");
 
         match self {
@@ -135,7 +191,7 @@ impl CmsisDapDevice {
                 report_size,
                 ..
             } => loop {
-                let mut discard = vec![0u8; report_size + 1];
+                let mut discard = vec![0u8; *report_size + 1];
                 match handle.read_timeout(&mut discard, 1) {
                     Ok(n) if n != 0 => continue,
                     _ => break,
@@ -157,6 +213,21 @@ impl CmsisDapDevice {
                     }
                 }
             }
+
+            CmsisDapDevice::Custom {
+                transport,
+                packet_size,
+                ..
+            } => {
+                let timeout = Duration::from_millis(1);
+                let mut discard = vec![0u8; *packet_size];
+                loop {
+                    match transport.read(&mut discard, timeout) {
+                        Ok(n) if n != 0 => continue,
+                        _ => break,
+                    }
+                }
+            }
         }
     }
 
@@ -179,6 +250,12 @@ impl CmsisDapDevice {
             } => {
                 *max_packet_size = packet_size;
             }
+            CmsisDapDevice::Custom {
+                packet_size: ref mut size,
+                ..
+            } => {
+                *size = packet_size;
+            }
         }
     }
 
@@ -220,6 +297,7 @@ impl CmsisDapDevice {
         match self {
             CmsisDapDevice::V1 { .. } => false,
             CmsisDapDevice::V2 { swo_ep, .. } => swo_ep.is_some(),
+            CmsisDapDevice::Custom { transport, hid, .. } => !hid && transport.has_swo_stream(),
         }
     }
 
@@ -228,7 +306,7 @@ impl CmsisDapDevice {
     /// Returns SWOModeNotAvailable if this device does not support SWO streaming.
     ///
     /// On timeout, returns a zero-length buffer.
-    pub(super) fn read_swo_stream(&self, timeout: Duration) -> Result<Vec<u8>, CmsisDapError> {
+    pub(super) fn read_swo_stream(&mut self, timeout: Duration) -> Result<Vec<u8>, CmsisDapError> {
         match self {
             CmsisDapDevice::V1 { .. } => Err(CmsisDapError::SwoModeNotAvailable),
             CmsisDapDevice::V2 { handle, swo_ep, .. } => match swo_ep {
@@ -248,6 +326,26 @@ impl CmsisDapDevice {
                 }
                 None => Err(CmsisDapError::SwoModeNotAvailable),
             },
+            CmsisDapDevice::Custom {
+                transport,
+                packet_size,
+                ..
+            } => {
+                if !transport.has_swo_stream() {
+                    return Err(CmsisDapError::SwoModeNotAvailable);
+                }
+
+                let mut buf = vec![0u8; *packet_size];
+                let result = transport.read_swo(&mut buf, timeout);
+                if transport::is_timeout(&result) {
+                    buf.truncate(0);
+                    return Ok(buf);
+                }
+
+                let n = result.map_err(CmsisDapError::SwoTransportError)?;
+                buf.truncate(n);
+                Ok(buf)
+            }
         }
     }
 }
@@ -307,6 +405,8 @@ pub enum CommandId {
     UartControl = 0x22,
     UartStatus = 0x23,
     UartTransfer = 0x21,
+    /// A vendor command, whose ID is one of 0x80 to 0x9F.
+    Vendor = 0x80,
 }
 
 pub(crate) trait Request {
@@ -336,6 +436,55 @@ fn send_command_inner<Req: Request>(
     device: &mut CmsisDapDevice,
     request: Req,
 ) -> Result<Req::Response, SendError> {
+    let response = exchange(device, Req::COMMAND_ID as u8, |buffer| {
+        request.to_bytes(buffer)
+    })?;
+
+    request.parse_response(&response[1..])
+}
+
+/// Send the vendor command `bytes`, which start with the ID of the command, and return the
+/// response of the probe, which starts with the same ID.
+///
+/// The CMSIS-DAP specification reserves the IDs 0x80 to 0x9F for vendor commands, whose
+/// request and response are defined by the firmware of the probe.
+pub(crate) fn send_vendor_command(
+    device: &mut CmsisDapDevice,
+    bytes: &[u8],
+) -> Result<Vec<u8>, CmsisDapError> {
+    let (id, request) = match bytes.split_first() {
+        Some((&id, request)) if (0x80..=0x9F).contains(&id) => (id, request),
+        _ => return Err(CmsisDapError::InvalidVendorCommand),
+    };
+
+    let mut too_long = false;
+    let response = exchange(device, id, |buffer| {
+        if request.len() > buffer.len() {
+            too_long = true;
+            return Ok(0);
+        }
+
+        buffer[..request.len()].copy_from_slice(request);
+        Ok(request.len())
+    });
+
+    if too_long {
+        return Err(CmsisDapError::InvalidVendorCommand);
+    }
+
+    response.map_err(|source| CmsisDapError::Send {
+        command_id: CommandId::Vendor,
+        source,
+    })
+}
+
+/// Send the command `command_id`, whose request is written by `to_bytes`, and return the
+/// response, which starts with the command ID.
+fn exchange(
+    device: &mut CmsisDapDevice,
+    command_id: u8,
+    to_bytes: impl FnOnce(&mut [u8]) -> Result<usize, SendError>,
+) -> Result<Vec<u8>, SendError> {
     // Size the buffer for the maximum packet size.
     // On v1, we always send this full-sized report, while
     // on v2 we can truncate to just the required data.
@@ -345,19 +494,20 @@ fn send_command_inner<Req: Request>(
         CmsisDapDevice::V2 {
             max_packet_size, ..
         } => *max_packet_size + 1,
+        CmsisDapDevice::Custom { packet_size, .. } => *packet_size + 1,
     };
     let mut buffer = vec![0; buffer_len];
 
     // Leave byte 0 as the HID report, and write the command and request to the buffer.
-    buffer[1] = Req::COMMAND_ID as u8;
-    let mut size = request.to_bytes(&mut buffer[2..])? + 2;
+    buffer[1] = command_id;
+    let mut size = to_bytes(&mut buffer[2..])? + 2;
 
     // For HID devices we must write a full report every time,
     // so set the transfer size to the report size, plus one
     // byte for the HID report ID. On v2 devices, we just
     // write the exact required size every time.
-    if let CmsisDapDevice::V1 { report_size, .. } = device {
-        size = *report_size + 1;
+    if let Some(report_size) = device.report_size() {
+        size = report_size + 1;
     }
 
     // Send buffer to the device.
@@ -366,17 +516,17 @@ fn send_command_inner<Req: Request>(
 
     // Read back response.
     let bytes_read = device.read(&mut buffer)?;
-    let response_data = &buffer[..bytes_read];
-    trace_buffer("Receive buffer", response_data);
+    buffer.truncate(bytes_read);
+    trace_buffer("Receive buffer", &buffer);
 
-    if response_data.is_empty() {
+    if buffer.is_empty() {
         return Err(SendError::NotEnoughData);
     }
 
-    if response_data[0] == Req::COMMAND_ID as u8 {
-        request.parse_response(&response_data[1..])
+    if buffer[0] == command_id {
+        Ok(buffer)
     } else {
-        Err(SendError::CommandIdMismatch(response_data[0]))
+        Err(SendError::CommandIdMismatch(buffer[0]))
     }
 }
 
@@ -131,10 +131,7 @@ impl Request for StatusRequest {
                 .try_into()
                 .map_err(|_| SendError::NotEnoughData)?,
         );
-        Ok(StatusResponse {
-            _status: status,
-            _count: count,
-        })
+        Ok(StatusResponse { status, count })
     }
 }
 
@@ -142,7 +139,7 @@ impl Request for StatusRequest {
 pub struct TraceStatus {
     pub(crate) _active: bool,
     pub(crate) error: bool,
-    pub(crate) _overrun: bool,
+    pub(crate) overrun: bool,
 }
 
 impl From<u8> for TraceStatus {
@@ -150,15 +147,15 @@ impl From<u8> for TraceStatus {
         Self {
             _active: value & (1 << 0) != 0,
             error: value & (1 << 6) != 0,
-            _overrun: value & (1 << 7) != 0,
+            overrun: value & (1 << 7) != 0,
         }
     }
 }
 
 #[derive(Debug)]
 pub struct StatusResponse {
-    pub(crate) _status: TraceStatus,
-    pub(crate) _count: u32,
+    pub(crate) status: TraceStatus,
+    pub(crate) count: u32,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,83 @@
+//! The CMSIS-DAP versions which limit the features of a probe.
+//!
+//! CMSIS-DAP probes report the version of the protocol they implement as their firmware
+//! version, so the features are limited by the protocol version.
+
+use crate::probe::firmware::{
+    self, FirmwareAdjustment, FirmwareFeature, FirmwareLimitation, FirmwareRule,
+};
+
+/// A version of the CMSIS-DAP protocol, as its major, minor and patch version.
+pub(super) type CmsisDapFirmware = (u16, u16, u16);
+
+const RULES: &[FirmwareRule<CmsisDapFirmware>] = &[FirmwareRule {
+    versions: (0, 0, 0)..(1, 1, 0),
+    feature: FirmwareFeature::Swo,
+    limitation: FirmwareLimitation::Unavailable,
+    reason: "the SWO commands were added to the protocol in version 1.1.0",
+}];
+
+/// Parses the firmware version a probe reports, e.g. `2.1.0`, or `1.10` for version 1.1.0
+/// in the notation of the first versions of the protocol.
+///
+/// Returns `None` for versions in other notations, e.g. of the firmware of a vendor.
+pub(super) fn parse(version: &str) -> Option<CmsisDapFirmware> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?;
+
+    let (minor, patch) = match parts.next() {
+        Some(patch) => (minor.parse().ok()?, patch.parse().ok()?),
+        None if minor.len() == 2 => (minor[..1].parse().ok()?, minor[1..].parse().ok()?),
+        None => (minor.parse().ok()?, 0),
+    };
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((major, minor, patch))
+}
+
+/// Returns the notation of `version`, e.g. `1.1.0`.
+fn name(version: &CmsisDapFirmware) -> String {
+    format!("{}.{}.{}", version.0, version.1, version.2)
+}
+
+/// Returns the adjustments of the features of a probe which reports the firmware `version`.
+///
+/// Versions which can't be parsed aren't adjusted.
+pub(super) fn adjustments(version: &str) -> Vec<FirmwareAdjustment> {
+    match parse(version) {
+        Some(version) => firmware::adjustments("CMSIS-DAP", RULES, &version, name),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions_are_parsed_in_both_notations() {
+        assert_eq!(parse("2.1.0"), Some((2, 1, 0)));
+        assert_eq!(parse("1.10"), Some((1, 1, 0)));
+        assert_eq!(parse("1.0"), Some((1, 0, 0)));
+        assert_eq!(parse("0254"), None);
+        assert_eq!(parse("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn swo_requires_version_1_1() {
+        let adjustments = adjustments("1.0");
+
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(
+            adjustments[0].error().to_string(),
+            "SWO capture requires CMSIS-DAP firmware 1.1.0 or later; detected 1.0.0"
+        );
+
+        assert!(super::adjustments("1.10").is_empty());
+        assert!(super::adjustments("0254").is_empty());
+    }
+}
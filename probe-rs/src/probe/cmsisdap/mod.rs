@@ -1,23 +1,28 @@
 pub mod commands;
+mod firmware;
 pub mod tools;
 
 use crate::{
-    architecture::arm::{
-        communication_interface::DapProbe,
-        communication_interface::UninitializedArmProbe,
-        dp::{Abort, Ctrl},
-        swo::poll_interval_from_buf_size,
-        ArmCommunicationInterface, DapError, DpAddress, Pins, PortType, RawDapAccess, Register,
-        SwoAccess, SwoConfig, SwoMode,
-    },
+    architecture::arm::dp::{Abort, Ctrl},
     probe::{
         cmsisdap::commands::{
-            general::info::{CapabilitiesCommand, PacketCountCommand, SWOTraceBufferSizeCommand},
+            general::info::{
+                CapabilitiesCommand, FirmwareVersionCommand, PacketCountCommand,
+                SWOTraceBufferSizeCommand,
+            },
             CmsisDapError,
         },
-        BatchCommand,
+        firmware::{FirmwareAdjustment, FirmwareFeature, FirmwareLimitation},
+        plugin::{
+            poll_interval_from_buf_size, ArmCommunicationInterface, BatchCommand, DapError,
+            DapProbe, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DpAddress,
+            Pins, PortType, ProbeCapabilities, ProbeDriver, RawDapAccess, Register, SwoAccess,
+            SwoConfig, SwoMode, SwoStatus, UninitializedArmProbe, VendorCommand,
+            VendorCommandIndex, VendorCommandOrdering, WireProtocol,
+        },
+        transport::ProbeTransport,
     },
-    DebugProbe, DebugProbeError, DebugProbeSelector, Error as ProbeRsError, WireProtocol,
+    Error as ProbeRsError,
 };
 
 use commands::{
@@ -45,6 +50,33 @@ use commands::{
 
 use std::time::Duration;
 
+/// The driver for CMSIS-DAP probes.
+pub struct CmsisDapDriver;
+
+impl ProbeDriver for CmsisDapDriver {
+    fn name(&self) -> &str {
+        "CMSIS-DAP"
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        ProbeCapabilities::new()
+            .swd()
+            .swo()
+            .pin_control()
+            .reset_control()
+            .batched_transfers()
+            .vendor_commands(VendorCommandOrdering::Flushed)
+    }
+
+    fn list_probes(&self) -> Vec<DebugProbeInfo> {
+        tools::list_cmsisdap_devices()
+    }
+
+    fn open(&self, selector: &DebugProbeSelector) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+        CmsisDap::new_from_selector(selector.clone()).map(DebugProbe::into_probe)
+    }
+}
+
 pub struct CmsisDap {
     pub device: CmsisDapDevice,
     _hw_version: u8,
@@ -54,14 +86,20 @@ pub struct CmsisDap {
     packet_size: u16,
     packet_count: u8,
     capabilities: Capabilities,
+    firmware_version: Option<String>,
     swo_buffer_size: Option<usize>,
     swo_active: bool,
     swo_streaming: bool,
+    /// An overrun reported with polled SWO data, which was not returned by `swo_status` yet.
+    swo_overrun: bool,
 
     /// Speed in kHz
     speed_khz: u32,
 
     batch: Vec<BatchCommand>,
+
+    /// The responses of the vendor commands which were sent, by their index.
+    vendor_responses: Vec<Vec<u8>>,
 }
 
 impl std::fmt::Debug for CmsisDap {
@@ -71,6 +109,7 @@ impl std::fmt::Debug for CmsisDap {
             .field("packet_size", &self.packet_size)
             .field("packet_count", &self.packet_count)
             .field("capabilities", &self.capabilities)
+            .field("firmware_version", &self.firmware_version)
             .field("swo_buffer_size", &self.swo_buffer_size)
             .field("swo_active", &self.swo_active)
             .field("swo_streaming", &self.swo_streaming)
@@ -79,6 +118,26 @@ impl std::fmt::Debug for CmsisDap {
     }
 }
 
+/// Returns the capabilities of a probe which reported `caps`, and exchanges packets of
+/// `packet_size` bytes.
+fn probe_capabilities(caps: &Capabilities, packet_size: u16) -> ProbeCapabilities {
+    let mut capabilities = ProbeCapabilities::new()
+        .pin_control()
+        .reset_control()
+        .batched_transfers()
+        // Bytes are transferred by the AP, blocks are limited by the words fitting in a packet.
+        .transfer_sizes(1, (packet_size as usize).saturating_sub(6) / 4 * 4)
+        // Vendor commands are sent in packets of their own, after the queued transfers.
+        .vendor_commands(VendorCommandOrdering::Flushed);
+
+    // JTAG is not implemented for CMSIS-DAP probes yet.
+    capabilities.swd = caps.swd_implemented;
+    capabilities.swo = caps.swo_uart_implemented || caps.swo_manchester_implemented;
+    capabilities.atomic_commands = caps.atomic_commands_implemented;
+
+    capabilities
+}
+
 impl CmsisDap {
     pub fn new_from_device(mut device: CmsisDapDevice) -> Result<Self, DebugProbeError> {
         // Discard anything left in buffer, as otherwise
@@ -93,6 +152,12 @@ impl CmsisDap {
         let packet_count = commands::send_command(&mut device, PacketCountCommand {})?;
         let caps: Capabilities = commands::send_command(&mut device, CapabilitiesCommand {})?;
         log::debug!("Detected probe capabilities: {:?}", caps);
+
+        // The firmware version is only used to describe the probe, so errors are ignored.
+        let firmware_version = commands::send_command(&mut device, FirmwareVersionCommand {})
+            .ok()
+            .flatten();
+
         let mut swo_buffer_size = None;
         if caps.swo_uart_implemented || caps.swo_manchester_implemented {
             let swo_size = commands::send_command(&mut device, SWOTraceBufferSizeCommand {})?;
@@ -108,14 +173,30 @@ impl CmsisDap {
             packet_count,
             packet_size,
             capabilities: caps,
+            firmware_version,
             swo_buffer_size,
             swo_active: false,
             swo_streaming: false,
+            swo_overrun: false,
             speed_khz: 1_000,
             batch: Vec::new(),
+            vendor_responses: Vec::new(),
         })
     }
 
+    /// Open a probe over a `transport` which was opened by the user.
+    ///
+    /// If `hid` is set, the transport exchanges CMSIS-DAP v1 HID reports, otherwise CMSIS-DAP
+    /// v2 packets.
+    pub(crate) fn new_from_transport(
+        transport: Box<dyn ProbeTransport>,
+        hid: bool,
+    ) -> Result<Box<Self>, DebugProbeError> {
+        let device = CmsisDapDevice::from_transport(transport, hid);
+
+        Ok(Box::new(Self::new_from_device(device)?))
+    }
+
     /// Set maximum JTAG/SWD clock frequency to use, in Hz.
     ///
     /// The actual clock frequency used by the device might be lower.
@@ -338,7 +419,6 @@ impl CmsisDap {
     }
 
     /// Fetch current SWO trace status.
-    #[allow(dead_code)]
     fn get_swo_status(&mut self) -> Result<swo::StatusResponse, DebugProbeError> {
         Ok(commands::send_command(
             &mut self.device,
@@ -370,6 +450,7 @@ impl CmsisDap {
 
                 let response: swo::DataResponse =
                     commands::send_command(&mut self.device, swo::DataRequest { max_count: n })?;
+                self.swo_overrun |= response.status.overrun;
                 if response.status.error {
                     Err(CmsisDapError::SwoTraceStreamError.into())
                 } else {
@@ -497,6 +578,28 @@ impl DebugProbe for CmsisDap {
         self.protocol
     }
 
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Swd]
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        let mut capabilities = probe_capabilities(&self.capabilities, self.packet_size);
+        crate::probe::firmware::apply(&mut capabilities, &self.firmware_adjustments());
+
+        capabilities
+    }
+
+    fn firmware_version(&self) -> Option<String> {
+        self.firmware_version.clone()
+    }
+
+    fn firmware_adjustments(&self) -> Vec<FirmwareAdjustment> {
+        self.firmware_version
+            .as_deref()
+            .map(firmware::adjustments)
+            .unwrap_or_default()
+    }
+
     /// Asserts the nRESET pin.
     fn target_reset(&mut self) -> Result<(), DebugProbeError> {
         commands::send_command(&mut self.device, ResetRequest).map(|v: ResetResponse| {
@@ -704,6 +807,28 @@ impl RawDapAccess for CmsisDap {
         Ok(())
     }
 
+    fn raw_vendor_command(
+        &mut self,
+        command: &VendorCommand,
+    ) -> Result<VendorCommandIndex, DebugProbeError> {
+        // The command is sent right away, so the queued transfers are sent before it.
+        self.process_batch()?;
+
+        let response = commands::send_vendor_command(&mut self.device, &command.bytes)?;
+        self.vendor_responses.push(response);
+
+        Ok(VendorCommandIndex(self.vendor_responses.len() - 1))
+    }
+
+    fn raw_vendor_command_response(
+        &mut self,
+        index: VendorCommandIndex,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        self.vendor_responses.get(index.0).cloned().ok_or_else(|| {
+            anyhow::anyhow!("No vendor command was sent with index {}", index.0).into()
+        })
+    }
+
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
         self
     }
@@ -734,6 +859,14 @@ impl DapProbe for CmsisDap {}
 
 impl SwoAccess for CmsisDap {
     fn enable_swo(&mut self, config: &SwoConfig) -> Result<(), ProbeRsError> {
+        if let Some(adjustment) = crate::probe::firmware::find(
+            &self.firmware_adjustments(),
+            FirmwareFeature::Swo,
+            FirmwareLimitation::Unavailable,
+        ) {
+            return Err(adjustment.error().into());
+        }
+
         let caps = self.capabilities;
 
         // Check requested mode is available in probe capabilities
@@ -836,6 +969,22 @@ impl SwoAccess for CmsisDap {
     fn swo_buffer_size(&mut self) -> Option<usize> {
         self.swo_buffer_size
     }
+
+    fn swo_status(&mut self) -> Result<SwoStatus, ProbeRsError> {
+        let mut status = SwoStatus {
+            buffer_size: self.swo_buffer_size,
+            overrun: std::mem::take(&mut self.swo_overrun),
+            ..Default::default()
+        };
+
+        if self.swo_active {
+            let response = self.get_swo_status()?;
+            status.buffered = Some(response.count as usize);
+            status.overrun |= response.status.overrun;
+        }
+
+        Ok(status)
+    }
 }
 
 impl Drop for CmsisDap {
@@ -853,3 +1002,123 @@ impl Drop for CmsisDap {
         let _ = self.detach();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capabilities_follow_the_reported_capabilities() {
+        let caps = Capabilities {
+            swd_implemented: true,
+            swo_uart_implemented: true,
+            ..Capabilities::default()
+        };
+
+        let capabilities = probe_capabilities(&caps, 64);
+
+        assert!(capabilities.swd);
+        assert!(!capabilities.jtag);
+        assert!(capabilities.swo);
+        assert!(capabilities.reset_control);
+        assert!(!capabilities.atomic_commands);
+        assert_eq!(capabilities.min_transfer_size, Some(1));
+        assert_eq!(capabilities.max_transfer_size, Some(56));
+        assert!(!capabilities.needs_word_transfers());
+
+        let capabilities = probe_capabilities(&Capabilities::default(), 64);
+        assert!(!capabilities.swo);
+    }
+
+    /// A transport to a simulated CMSIS-DAP v2 probe, which answers the commands needed to
+    /// open it, transfers, and the vendor command 0x81.
+    struct SimulatedProbe {
+        written: std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+        responses: std::collections::VecDeque<Vec<u8>>,
+    }
+
+    impl ProbeTransport for SimulatedProbe {
+        fn write(&mut self, data: &[u8], _timeout: Duration) -> std::io::Result<usize> {
+            self.written.lock().unwrap().push(data.to_vec());
+
+            let response = match data {
+                // DAP_Info: packet size, packet count and capabilities (SWD only).
+                [0x00, 0xff, ..] => vec![0x00, 2, 64, 0],
+                [0x00, 0xfe, ..] => vec![0x00, 1, 4],
+                [0x00, 0xf0, ..] => vec![0x00, 1, 0x01],
+                // DAP_Transfer: all transfers are acknowledged.
+                [0x05, _, count, ..] => vec![0x05, *count, 0x01],
+                // A vendor command which returns its argument incremented.
+                [0x81, argument] => vec![0x81, 0x00, argument + 1],
+                [command, ..] => vec![*command, 0xff],
+                [] => vec![],
+            };
+            self.responses.push_back(response);
+
+            Ok(data.len())
+        }
+
+        fn read(&mut self, buffer: &mut [u8], _timeout: Duration) -> std::io::Result<usize> {
+            match self.responses.pop_front() {
+                Some(response) => {
+                    buffer[..response.len()].copy_from_slice(&response);
+                    Ok(response.len())
+                }
+                None => Err(std::io::ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    #[test]
+    fn vendor_commands_are_sent_after_the_queued_transfers() {
+        let written = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let transport = SimulatedProbe {
+            written: written.clone(),
+            responses: Default::default(),
+        };
+        let mut probe = CmsisDap::new_from_transport(Box::new(transport), false).unwrap();
+
+        assert_eq!(
+            DebugProbe::capabilities(probe.as_ref()).vendor_commands,
+            VendorCommandOrdering::Flushed
+        );
+
+        written.lock().unwrap().clear();
+
+        // The write is queued, and only sent with the vendor command.
+        probe
+            .raw_write_register(PortType::DebugPort, 0x8, 0x0000_00f0)
+            .unwrap();
+        assert!(written.lock().unwrap().is_empty());
+
+        let first = probe
+            .raw_vendor_command(&VendorCommand::new([0x81, 0x41]))
+            .unwrap();
+        let second = probe
+            .raw_vendor_command(&VendorCommand::new([0x81, 0x01]))
+            .unwrap();
+
+        {
+            let written = written.lock().unwrap();
+            assert_eq!(written.len(), 3);
+            assert_eq!(written[0][0], 0x05);
+            assert_eq!(written[1], [0x81, 0x41]);
+            assert_eq!(written[2], [0x81, 0x01]);
+        }
+
+        assert_eq!(
+            probe.raw_vendor_command_response(first).unwrap(),
+            [0x81, 0x00, 0x42]
+        );
+        assert_eq!(
+            probe.raw_vendor_command_response(second).unwrap(),
+            [0x81, 0x00, 0x02]
+        );
+
+        // Only the IDs reserved for vendor commands can be sent.
+        assert!(probe
+            .raw_vendor_command(&VendorCommand::new([0x05, 0x00]))
+            .is_err());
+        assert_eq!(written.lock().unwrap().len(), 3);
+    }
+}
@@ -18,9 +18,15 @@ use crate::{
     DebugProbe, DebugProbeError, DebugProbeSelector, WireProtocol,
 };
 
-use self::protocol::ProtocolHandler;
+use self::protocol::{BitIter, ProtocolHandler, IN_EP_BUFFER_SIZE};
 
-use super::JTAGAccess;
+use super::{
+    plugin::ProbeCapabilities, BatchExecutionError, CommandResult, JTAGAccess, JtagWriteCommand,
+};
+
+/// The number of captured bits assumed for a single DR scan of a queued register access,
+/// which covers the DMI register of debug modules with up to 20 address bits.
+const QUEUED_SCAN_BITS: usize = 64;
 
 pub use protocol::list_espjtag_devices;
 
@@ -63,26 +69,11 @@ impl EspUsbJtag {
         // We have to stay in the idle cycle a bit
         tms.extend(iter::repeat(false).take(self.idle_cycles() as usize));
 
-        let mut response = self.protocol.jtag_io(tms, tdi, true)?;
+        let response = self.protocol.jtag_io(tms, tdi, true)?;
 
         log::trace!("Response: {:?}", response);
 
-        let _remainder = response.split_off(tms_enter_shift.len());
-
-        let mut remaining_bits = register_bits;
-
-        let mut result = Vec::new();
-
-        while remaining_bits >= 8 {
-            let byte = bits_to_byte(response.split_off(8)) as u8;
-            result.push(byte);
-            remaining_bits -= 8;
-        }
-
-        // Handle leftover bytes
-        if remaining_bits > 0 {
-            result.push(bits_to_byte(response.split_off(remaining_bits)) as u8);
-        }
+        let result = dr_response(response, register_bits);
 
         log::debug!("Read from DR: {:?}", result);
 
@@ -107,55 +98,7 @@ impl EspUsbJtag {
             todo!("Proper error for incorrect length");
         }
 
-        let tms_enter_ir_shift = [true, true, false, false];
-
-        // The last bit will be transmitted when exiting the shift state,
-        // so we need to stay in the shift state for one period less than
-        // we have bits to transmit.
-        let tms_data = iter::repeat(false).take(len - 1);
-
-        let tms_enter_idle = [true, true, false];
-
-        let mut tms = Vec::with_capacity(tms_enter_ir_shift.len() + len + tms_enter_idle.len());
-
-        tms.extend_from_slice(&tms_enter_ir_shift);
-        tms.extend(tms_data);
-        tms.extend_from_slice(&tms_enter_idle);
-
-        let tdi_enter_ir_shift = [false, false, false, false];
-
-        // This is one less than the enter idle for tms, because
-        // the last bit is transmitted when exiting the IR shift state
-        let tdi_enter_idle = [false, false];
-
-        let mut tdi = Vec::with_capacity(tdi_enter_ir_shift.len() + len + tdi_enter_idle.len());
-
-        tdi.extend_from_slice(&tdi_enter_ir_shift);
-
-        let num_bytes = len / 8;
-
-        let num_bits = len - (num_bytes * 8);
-
-        for bytes in &data[..num_bytes] {
-            let mut byte = *bytes;
-
-            for _ in 0..8 {
-                tdi.push(byte & 1 == 1);
-
-                byte >>= 1;
-            }
-        }
-
-        if num_bits > 0 {
-            let mut remaining_byte = data[num_bytes];
-
-            for _ in 0..num_bits {
-                tdi.push(remaining_byte & 1 == 1);
-                remaining_byte >>= 1;
-            }
-        }
-
-        tdi.extend_from_slice(&tdi_enter_idle);
+        let (tms, tdi) = ir_scan(data, len);
 
         log::trace!("tms: {:?}", tms);
         log::trace!("tdi: {:?}", tdi);
@@ -181,6 +124,22 @@ impl EspUsbJtag {
     fn write_dr(&mut self, data: &[u8], register_bits: usize) -> Result<Vec<u8>, DebugProbeError> {
         log::debug!("Write DR: {:?}, len={}", data, register_bits);
 
+        let (tms, tdi) = self.dr_scan(data, register_bits);
+
+        let response = self.protocol.jtag_io(tms, tdi, true)?;
+
+        log::trace!("Response: {:?}", response);
+
+        let result = dr_response(response, register_bits);
+
+        log::trace!("result: {:?}", result);
+
+        Ok(result)
+    }
+
+    /// The TMS and TDI bits to shift `data` through the DR register, followed by
+    /// the idle cycles.
+    fn dr_scan(&self, data: &[u8], register_bits: usize) -> (Vec<bool>, Vec<bool>) {
         let tms_enter_shift = [true, false, false];
 
         // Last bit of data is shifted out when we exi the SHIFT-DR State
@@ -198,66 +157,100 @@ impl EspUsbJtag {
 
         let tdi_enter_idle = [false, false];
 
-        // TODO: TDI data
         let mut tdi =
             Vec::with_capacity(tdi_enter_shift.len() + tdi_enter_idle.len() + register_bits);
 
         tdi.extend_from_slice(&tdi_enter_shift);
+        push_bits(&mut tdi, data, register_bits);
+        tdi.extend_from_slice(&tdi_enter_idle);
 
-        let num_bytes = register_bits / 8;
+        // We need to stay in the idle cycle a bit
+        tms.extend(iter::repeat(false).take(self.idle_cycles() as usize));
+        tdi.extend(iter::repeat(false).take(self.idle_cycles() as usize));
 
-        let num_bits = register_bits - (num_bytes * 8);
+        (tms, tdi)
+    }
+}
 
-        for bytes in &data[..num_bytes] {
-            let mut byte = *bytes;
+/// The TMS and TDI bits to shift `len` bits of `data` into the IR register.
+fn ir_scan(data: &[u8], len: usize) -> (Vec<bool>, Vec<bool>) {
+    let tms_enter_ir_shift = [true, true, false, false];
 
-            for _ in 0..8 {
-                tdi.push(byte & 1 == 1);
+    // The last bit will be transmitted when exiting the shift state,
+    // so we need to stay in the shift state for one period less than
+    // we have bits to transmit.
+    let tms_data = iter::repeat(false).take(len - 1);
 
-                byte >>= 1;
-            }
-        }
+    let tms_enter_idle = [true, true, false];
 
-        if num_bits > 0 {
-            let mut remaining_byte = data[num_bytes];
+    let mut tms = Vec::with_capacity(tms_enter_ir_shift.len() + len + tms_enter_idle.len());
 
-            for _ in 0..num_bits {
-                tdi.push(remaining_byte & 1 == 1);
-                remaining_byte >>= 1;
-            }
-        }
+    tms.extend_from_slice(&tms_enter_ir_shift);
+    tms.extend(tms_data);
+    tms.extend_from_slice(&tms_enter_idle);
 
-        tdi.extend_from_slice(&tdi_enter_idle);
+    let tdi_enter_ir_shift = [false, false, false, false];
 
-        // We need to stay in the idle cycle a bit
-        tms.extend(iter::repeat(false).take(self.idle_cycles() as usize));
-        tdi.extend(iter::repeat(false).take(self.idle_cycles() as usize));
+    // This is one less than the enter idle for tms, because
+    // the last bit is transmitted when exiting the IR shift state
+    let tdi_enter_idle = [false, false];
 
-        let mut response = self.protocol.jtag_io(tms, tdi, true)?;
+    let mut tdi = Vec::with_capacity(tdi_enter_ir_shift.len() + len + tdi_enter_idle.len());
 
-        log::trace!("Response: {:?}", response);
+    tdi.extend_from_slice(&tdi_enter_ir_shift);
+    push_bits(&mut tdi, data, len);
+    tdi.extend_from_slice(&tdi_enter_idle);
+
+    (tms, tdi)
+}
 
-        let _remainder = response.split_off(tms_enter_shift.len());
+/// Append the first `len` bits of `data` to `bits`, LSB first.
+fn push_bits(bits: &mut Vec<bool>, data: &[u8], len: usize) {
+    let num_bytes = len / 8;
 
-        let mut remaining_bits = register_bits;
+    let num_bits = len - (num_bytes * 8);
 
-        let mut result = Vec::new();
+    for bytes in &data[..num_bytes] {
+        let mut byte = *bytes;
 
-        while remaining_bits >= 8 {
-            let byte = bits_to_byte(response.split_off(8)) as u8;
-            result.push(byte);
-            remaining_bits -= 8;
+        for _ in 0..8 {
+            bits.push(byte & 1 == 1);
+
+            byte >>= 1;
         }
+    }
+
+    if num_bits > 0 {
+        let mut remaining_byte = data[num_bytes];
 
-        // Handle leftover bytes
-        if remaining_bits > 0 {
-            result.push(bits_to_byte(response.split_off(remaining_bits)) as u8);
+        for _ in 0..num_bits {
+            bits.push(remaining_byte & 1 == 1);
+            remaining_byte >>= 1;
         }
+    }
+}
 
-        log::trace!("result: {:?}", result);
+/// Extract the bits which were shifted out of the DR register from the `response` to a DR scan.
+fn dr_response(mut response: BitIter, register_bits: usize) -> Vec<u8> {
+    // Skip the bits captured while entering the SHIFT-DR state.
+    let _remainder = response.split_off(3);
 
-        Ok(result)
+    let mut remaining_bits = register_bits;
+
+    let mut result = Vec::new();
+
+    while remaining_bits >= 8 {
+        let byte = bits_to_byte(response.split_off(8)) as u8;
+        result.push(byte);
+        remaining_bits -= 8;
     }
+
+    // Handle leftover bytes
+    if remaining_bits > 0 {
+        result.push(bits_to_byte(response.split_off(remaining_bits)) as u8);
+    }
+
+    result
 }
 
 impl JTAGAccess for EspUsbJtag {
@@ -319,6 +312,64 @@ impl JTAGAccess for EspUsbJtag {
     fn get_idle_cycles(&self) -> u8 {
         self.jtag_idle_cycles
     }
+
+    fn max_queued_operations(&self) -> Option<usize> {
+        // The responses of a batch are read back in one go, and the adapter only buffers
+        // a single IN packet of captured bits.
+        Some((IN_EP_BUFFER_SIZE * 8 / (QUEUED_SCAN_BITS + self.idle_cycles() as usize)).max(1))
+    }
+
+    fn write_register_batch(
+        &mut self,
+        writes: &[JtagWriteCommand],
+    ) -> Result<Vec<CommandResult>, BatchExecutionError> {
+        let mut scans = Vec::with_capacity(writes.len());
+
+        for write in writes {
+            // TODO: This is limited to 5 bit addresses for now
+            if write.address > 0x1f {
+                return Err(BatchExecutionError::new(
+                    DebugProbeError::NotImplemented("JTAG Register addresses are fixed to 5 bits"),
+                    Vec::new(),
+                ));
+            }
+
+            if self.current_ir_reg != write.address {
+                // Only the DR scans are captured, so that the responses are as short as possible.
+                let (tms, tdi) = ir_scan(&write.address.to_le_bytes()[..1], 5);
+                self.protocol
+                    .schedule_jtag_io(tms, tdi, false)
+                    .map_err(|e| BatchExecutionError::new(e, Vec::new()))?;
+
+                self.current_ir_reg = write.address;
+            }
+
+            let (tms, tdi) = self.dr_scan(&write.data, write.len as usize);
+            scans.push(tms.len());
+
+            self.protocol
+                .schedule_jtag_io(tms, tdi, true)
+                .map_err(|e| BatchExecutionError::new(e, Vec::new()))?;
+        }
+
+        let mut response = self
+            .protocol
+            .flush()
+            .map_err(|e| BatchExecutionError::new(e, Vec::new()))?;
+
+        let mut results = Vec::with_capacity(writes.len());
+
+        for (write, scan_bits) in writes.iter().zip(scans) {
+            let data = dr_response(response.split_off(scan_bits), write.len as usize);
+
+            match (write.transform)(data) {
+                Ok(result) => results.push(result),
+                Err(e) => return Err(BatchExecutionError::new(e, results)),
+            }
+        }
+
+        Ok(results)
+    }
 }
 
 impl DebugProbe for EspUsbJtag {
@@ -347,6 +398,20 @@ impl DebugProbe for EspUsbJtag {
         Some(WireProtocol::Jtag)
     }
 
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Jtag]
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        let mut capabilities = ProbeCapabilities::new()
+            .jtag()
+            .reset_control()
+            .batched_transfers();
+        capabilities.max_speed_khz = self.protocol.max_speed_khz();
+
+        capabilities
+    }
+
     fn get_name(&self) -> &'static str {
         "Esp USB JTAG"
     }
@@ -12,7 +12,7 @@ const JTAG_PROTOCOL_CAPABILITIES_SPEED_APB_TYPE: u8 = 1;
 const MAX_COMMAND_REPETITIONS: usize = 1024;
 const OUT_BUFFER_SIZE: usize = OUT_EP_BUFFER_SIZE * 64;
 const OUT_EP_BUFFER_SIZE: usize = 64;
-const IN_EP_BUFFER_SIZE: usize = 64;
+pub(super) const IN_EP_BUFFER_SIZE: usize = 64;
 const USB_TIMEOUT: Duration = Duration::from_millis(5000);
 const USB_DEVICE_CLASS: u8 = 0xFF;
 const USB_DEVICE_SUBCLASS: u8 = 0xFF;
@@ -43,6 +43,9 @@ pub(super) struct ProtocolHandler {
 
     ep_out: u8,
     ep_in: u8,
+
+    // The highest JTAG speed, from the capabilities descriptor.
+    max_speed_khz: Option<u32>,
 }
 
 impl Debug for ProtocolHandler {
@@ -53,10 +56,47 @@ impl Debug for ProtocolHandler {
             .field("input_buffer", &self.input_buffer)
             .field("ep_out", &self.ep_out)
             .field("ep_in", &self.ep_in)
+            .field("max_speed_khz", &self.max_speed_khz)
             .finish()
     }
 }
 
+/// Returns the highest JTAG speed from the JTAG capabilities descriptor in `buffer`, if the
+/// descriptor contains a speed capability.
+fn parse_max_speed_khz(buffer: &[u8]) -> Result<Option<u32>, ProbeCreationError> {
+    let protocol_version = buffer[0];
+    log::debug!("{:?}", &buffer[..20]);
+    log::debug!("Protocol version: {}", protocol_version);
+    if protocol_version != JTAG_PROTOCOL_CAPABILITIES_VERSION {
+        return Err(ProbeCreationError::ProbeSpecific(
+            "Unknown capabilities descriptor version.".into(),
+        ));
+    }
+
+    let length = buffer[1] as usize;
+    let mut max_speed_khz = None;
+
+    let mut p = 2usize;
+    while p < length {
+        let typ = buffer[p];
+        let length = buffer[p + 1];
+
+        if typ == JTAG_PROTOCOL_CAPABILITIES_SPEED_APB_TYPE {
+            // The base speed is given in units of 10 kHz, for a clock which is divided by two.
+            let base_speed_khz = u16::from_le_bytes([buffer[p + 2], buffer[p + 3]]) as u32 * 10 / 2;
+            let div_min = u16::from_le_bytes([buffer[p + 4], buffer[p + 5]]).max(1) as u32;
+
+            max_speed_khz = Some(base_speed_khz / div_min);
+        } else {
+            log::warn!("Unknown capabilities type {:01X?}", typ);
+        }
+
+        p += length as usize;
+    }
+
+    Ok(max_speed_khz)
+}
+
 impl ProtocolHandler {
     pub fn new_from_selector(
         selector: impl Into<DebugProbeSelector>,
@@ -163,38 +203,7 @@ impl ProtocolHandler {
             USB_TIMEOUT,
         )?;
 
-        // TODO:
-        // let mut base_speed_khz = 1000;
-        // let mut div_min = 1;
-        // let mut div_max = 1;
-
-        let protocol_version = buffer[0];
-        log::debug!("{:?}", &buffer[..20]);
-        log::debug!("Protocol version: {}", protocol_version);
-        if protocol_version != JTAG_PROTOCOL_CAPABILITIES_VERSION {
-            return Err(ProbeCreationError::ProbeSpecific(
-                "Unknown capabilities descriptor version.".into(),
-            ));
-        }
-
-        let length = buffer[1] as usize;
-
-        let mut p = 2usize;
-        while p < length {
-            let typ = buffer[p];
-            let length = buffer[p + 1];
-
-            if typ == JTAG_PROTOCOL_CAPABILITIES_SPEED_APB_TYPE {
-                // TODO:
-                // base_speed_khz = (((buffer[p + 3] as u16) << 8) | buffer[p + 2] as u16) * 10 / 2;
-                // div_min = ((buffer[p + 5] as u16) << 8) | buffer[p + 4] as u16;
-                // div_max = ((buffer[p + 7] as u16) << 8) | buffer[p + 6] as u16;
-            } else {
-                log::warn!("Unknown capabilities type {:01X?}", typ);
-            }
-
-            p += length as usize;
-        }
+        let max_speed_khz = parse_max_speed_khz(&buffer)?;
 
         // TODO:
         // let hw_in_fifo_len = 4;
@@ -210,9 +219,15 @@ impl ProtocolHandler {
             ep_out: ep_out.expect("This is a bug. Please report it."),
             ep_in: ep_in.expect("This is a bug. Please report it."),
             pending_in_bits: 0,
+            max_speed_khz,
         })
     }
 
+    /// The highest JTAG speed of the adapter, if it reported its speed capabilities.
+    pub fn max_speed_khz(&self) -> Option<u32> {
+        self.max_speed_khz
+    }
+
     /// Put a bit on TDI and possibly read one from TDO.
     pub fn jtag_io(
         &mut self,
@@ -221,6 +236,20 @@ impl ProtocolHandler {
         cap: bool,
     ) -> Result<BitIter, DebugProbeError> {
         log::debug!("JTAG IO! {} ", cap);
+        self.schedule_jtag_io(tms, tdi, cap)?;
+
+        self.flush()
+    }
+
+    /// Queues the JTAG clocks, without flushing them to the adapter.
+    ///
+    /// If `cap` is set, the TDO bits are returned by the next [`ProtocolHandler::flush`].
+    pub fn schedule_jtag_io(
+        &mut self,
+        tms: impl IntoIterator<Item = bool>,
+        tdi: impl IntoIterator<Item = bool>,
+        cap: bool,
+    ) -> Result<(), DebugProbeError> {
         for (tms, tdi) in tms.into_iter().zip(tdi.into_iter()) {
             self.push_command(Command::Clock { cap, tdi, tms })?;
             if cap {
@@ -228,7 +257,7 @@ impl ProtocolHandler {
             }
         }
 
-        self.flush()
+        Ok(())
     }
 
     /// Sets the two different resets on the target.
@@ -367,7 +396,7 @@ impl ProtocolHandler {
         let mut bits_read = 0;
 
         while bits_read != self.pending_in_bits {
-            let count = ((self.pending_in_bits + 7) / 8).min(IN_EP_BUFFER_SIZE);
+            let count = ((self.pending_in_bits - bits_read + 7) / 8).min(IN_EP_BUFFER_SIZE);
             log::trace!("Receiveing {} bytes.", count);
 
             if count == 0 {
@@ -413,7 +442,7 @@ impl ProtocolHandler {
 
             log::trace!("Received {} bytes.", count);
 
-            let bits_in_buffer = self.pending_in_bits.min(count * 8);
+            let bits_in_buffer = (self.pending_in_bits - bits_read).min(count * 8);
             bits_read += bits_in_buffer;
         }
 
@@ -594,3 +623,20 @@ pub fn list_espjtag_devices() -> Vec<DebugProbeInfo> {
                 .collect::<Vec<_>>()
         })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn max_speed_is_parsed_from_the_capabilities() {
+        let mut buffer = [0; 255];
+        // Version 1, 10 bytes, a speed capability with a 80 MHz base speed and a divider of 2..=256.
+        buffer[..10].copy_from_slice(&[1, 10, 1, 8, 0x40, 0x1f, 2, 0, 0, 1]);
+
+        assert_eq!(parse_max_speed_khz(&buffer).unwrap(), Some(20_000));
+
+        buffer[0] = 2;
+        assert!(parse_max_speed_khz(&buffer).is_err());
+    }
+}
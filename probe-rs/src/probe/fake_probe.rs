@@ -1,17 +1,30 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Debug,
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use crate::{
     architecture::arm::{
-        ap::{memory_ap::mock::MockMemoryAp, AccessPort, MemoryAp},
+        ap::{memory_ap::mock::MockMemoryAp, AccessPort, ApAccess, MemoryAp, CSW, DRW, TAR, TAR2},
         communication_interface::{
-            ArmDebugState, Initialized, SwdSequence, Uninitialized, UninitializedArmProbe,
+            ArmDebugState, DapProbe, Initialized, SwdSequence, Uninitialized, UninitializedArmProbe,
         },
+        dp::{Ctrl, Select},
         memory::adi_v5_memory_interface::ADIMemoryInterface,
         sequences::ArmDebugSequence,
-        ApAddress, ArmProbeInterface, DapAccess, DpAddress, MemoryApInformation, PortType,
-        RawDapAccess, SwoAccess,
+        ApAddress, ApInformation, ArmProbeInterface, DapAccess, DapError, DpAddress,
+        MemoryApInformation, Pins, PortType, RawDapAccess, SwoAccess,
+    },
+    architecture::riscv::{
+        communication_interface::RiscvCommunicationInterface, mock::MockDebugModule,
     },
-    DebugProbe, DebugProbeError, DebugProbeSelector, Error, Memory, Probe, WireProtocol,
+    flashing::FlashAlgorithm,
+    probe::{BatchExecutionError, CommandResult, JTAGAccess, JtagWriteCommand},
+    DebugProbe, DebugProbeError, DebugProbeSelector, Error, Memory, Probe, ProbeCapabilities,
+    VendorCommand, WireProtocol,
 };
 
 /// This is a mock probe which can be used for mocking things in tests or for dry runs.
@@ -19,6 +32,40 @@ use crate::{
 pub struct FakeProbe {
     protocol: WireProtocol,
     speed: u32,
+    max_speed: Option<u32>,
+    /// The highest speed until the target was attached once at or below it.
+    slow_clock: Option<u32>,
+    mock_core: bool,
+    routine_delays: HashMap<u32, Duration>,
+    fpb_revision: u32,
+    ignored_halt_requests: u32,
+    capabilities: ProbeCapabilities,
+    write_faults: WriteFaults,
+    read_faults: ReadFaults,
+    ecc_faults: EccFaults,
+    write_log: WriteLog,
+    access_stalls: AccessStalls,
+    polls: ProbePolls,
+    breakpoint_hits: BreakpointHits,
+    transactions: ProbeTransactions,
+    target_resets: TargetResets,
+    foreign_resumes: ForeignResumes,
+    firmware_writes: FirmwareWrites,
+    vendor_commands: VendorCommands,
+    /// True while nRESET is driven low through [`RawDapAccess::swj_pins`].
+    reset_asserted: bool,
+    flash_algorithm: Option<FlashAlgorithm>,
+    execute_code: bool,
+
+    /// The DAP of the mocked core, created by the first register access.
+    dap: Option<MockDap>,
+
+    /// The memory AP of the mocked core while the ARM interface is closed, so that the core
+    /// keeps its state when the probe is switched to another debug interface.
+    memory_ap: Option<MockMemoryAp>,
+
+    /// The mocked RISC-V Debug Module, see [`FakeProbe::mock_riscv_debug_module`].
+    riscv_debug_module: Option<MockDebugModule>,
 
     dap_register_read_handler:
         Option<Box<dyn Fn(PortType, u8) -> Result<u32, DebugProbeError> + Send>>,
@@ -27,11 +74,496 @@ pub struct FakeProbe {
         Option<Box<dyn Fn(PortType, u8, u32) -> Result<(), DebugProbeError> + Send>>,
 }
 
+/// The addresses of the mocked core of a [`FakeProbe`] whose writes fail with a fault
+/// response, see [`FakeProbe::write_faults`].
+#[derive(Debug, Clone, Default)]
+pub struct WriteFaults(Arc<Mutex<HashSet<u32>>>);
+
+impl WriteFaults {
+    /// Make the writes to the word at `address` fail.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn insert(&self, address: u32) {
+        self.0.lock().unwrap().insert(address & !0b11);
+    }
+
+    /// Make the writes to the word at `address` succeed again.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn remove(&self, address: u32) {
+        self.0.lock().unwrap().remove(&(address & !0b11));
+    }
+
+    /// Returns true if the writes to the word at `address` fail.
+    pub(crate) fn contains(&self, address: u32) -> bool {
+        self.0.lock().unwrap().contains(&(address & !0b11))
+    }
+}
+
+/// Makes reads of the memory of the mocked core of a [`FakeProbe`] fail with a fault response
+/// at a fixed interval, like a flaky link, or in ranges of inaccessible memory, see
+/// [`FakeProbe::read_faults`].
+///
+/// Only reads below the private peripheral bus at `0xE000_0000` fail, so that the core can
+/// still be controlled.
+#[derive(Debug, Clone, Default)]
+pub struct ReadFaults(Arc<Mutex<ReadFaultState>>);
+
+#[derive(Debug, Default)]
+struct ReadFaultState {
+    /// The interval of the failing reads, and the number of reads counted so far.
+    interval: Option<(u32, u32)>,
+    /// The ranges whose reads always fail.
+    inaccessible: Vec<Range<u32>>,
+}
+
+impl ReadFaults {
+    /// Make every `interval`-th read fail, counting from now.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn fail_every(&self, interval: u32) {
+        self.0.lock().unwrap().interval = Some((interval.max(1), 0));
+    }
+
+    /// Make all reads of `range` fail, like reads of memory which doesn't exist.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn make_inaccessible(&self, range: Range<u32>) {
+        self.0.lock().unwrap().inaccessible.push(range);
+    }
+
+    /// Make all reads succeed again.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn stop(&self) {
+        *self.0.lock().unwrap() = ReadFaultState::default();
+    }
+
+    /// Count a read of `address`, and return true if it fails.
+    pub(crate) fn fails(&self, address: u32) -> bool {
+        if address >= 0xE000_0000 {
+            return false;
+        }
+
+        let mut state = self.0.lock().unwrap();
+
+        if state
+            .inaccessible
+            .iter()
+            .any(|range| range.contains(&address))
+        {
+            return true;
+        }
+
+        match &mut state.interval {
+            Some((interval, count)) => {
+                *count += 1;
+                *count % *interval == 0
+            }
+            None => false,
+        }
+    }
+}
+
+/// Makes ranges of the memory of the mocked core of a [`FakeProbe`] behave like memory which
+/// is protected by ECC, see [`FakeProbe::ecc_faults`].
+///
+/// The ECC of a word is only valid once the word was written as a whole. Until then, writes
+/// of less than the word and reads of the word fail with a fault response, like on a target
+/// whose ECC RAM wasn't initialized after power-up.
+#[derive(Debug, Clone, Default)]
+pub struct EccFaults(Arc<Mutex<EccFaultState>>);
+
+#[derive(Debug, Default)]
+struct EccFaultState {
+    /// The ranges which are protected by ECC.
+    ranges: Vec<Range<u32>>,
+    /// The words of the ranges which were written as a whole.
+    initialized: HashSet<u32>,
+}
+
+impl EccFaults {
+    /// Protect `range` with ECC. None of its words is initialized.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn protect(&self, range: Range<u32>) {
+        self.0.lock().unwrap().ranges.push(range);
+    }
+
+    /// Returns true if the word at `address` was written as a whole.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn is_initialized(&self, address: u32) -> bool {
+        self.0
+            .lock()
+            .unwrap()
+            .initialized
+            .contains(&(address & !0b11))
+    }
+
+    /// Returns true if the word at `address` is protected, but not initialized.
+    fn uninitialized(state: &EccFaultState, address: u32) -> bool {
+        state.ranges.iter().any(|range| range.contains(&address))
+            && !state.initialized.contains(&(address & !0b11))
+    }
+
+    /// Returns true if a read of the word at `address` fails.
+    pub(crate) fn fails_read(&self, address: u32) -> bool {
+        Self::uninitialized(&self.0.lock().unwrap(), address)
+    }
+
+    /// Record a write of `width` bytes at `address`, and return true if it fails.
+    pub(crate) fn fails_write(&self, address: u32, width: u32) -> bool {
+        let mut state = self.0.lock().unwrap();
+
+        if width < 4 {
+            return Self::uninitialized(&state, address);
+        }
+
+        state.initialized.insert(address & !0b11);
+        false
+    }
+}
+
+/// Makes accesses to the memory of the mocked core of a [`FakeProbe`] stall at a fixed
+/// interval, like a target whose bus is busy with DMA, see [`FakeProbe::access_stalls`].
+///
+/// Unlike the [`ReadFaults`], all accesses are counted, including the ones of the private
+/// peripheral bus.
+#[derive(Debug, Clone, Default)]
+pub struct AccessStalls(Arc<Mutex<Option<(u32, Duration, u32)>>>);
+
+impl AccessStalls {
+    /// Make every `interval`-th access take `duration` longer, counting from now. With an
+    /// interval of 1, every access has the latency `duration`, like a slow probe.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn stall_every(&self, interval: u32, duration: Duration) {
+        *self.0.lock().unwrap() = Some((interval.max(1), duration, 0));
+    }
+
+    /// Make all accesses take their normal time again.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn stop(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+
+    /// Count an access, and return the time it stalls.
+    pub(crate) fn next(&self) -> Duration {
+        match &mut *self.0.lock().unwrap() {
+            Some((interval, duration, count)) => {
+                *count += 1;
+
+                if *count % *interval == 0 {
+                    *duration
+                } else {
+                    Duration::ZERO
+                }
+            }
+            None => Duration::ZERO,
+        }
+    }
+}
+
+/// The writes to the memory of the mocked core of a [`FakeProbe`], see
+/// [`FakeProbe::write_log`].
+#[derive(Debug, Clone, Default)]
+pub struct WriteLog(Arc<Mutex<Vec<(u32, u32)>>>);
+
+impl WriteLog {
+    /// Returns the address and the value of each write since the log was last cleared, in
+    /// order. Writes of less than a word are logged with the value of the whole data register.
+    pub fn entries(&self) -> Vec<(u32, u32)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Returns true if no write was logged since the log was last cleared.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    /// Remove all writes from the log.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Log a write of `value` to `address`.
+    pub(crate) fn record(&self, address: u32, value: u32) {
+        self.0.lock().unwrap().push((address, value));
+    }
+}
+
+/// The vendor commands executed by a [`FakeProbe`], see [`FakeProbe::vendor_commands`].
+///
+/// The commands are only executed if the capabilities of the probe include
+/// [`vendor_commands`](ProbeCapabilities::vendor_commands), which can be set with
+/// [`FakeProbe::set_capabilities`]. The response of each command echoes its bytes.
+#[derive(Debug, Clone, Default)]
+pub struct VendorCommands(Arc<Mutex<Vec<(VendorCommand, usize)>>>);
+
+impl VendorCommands {
+    /// Returns each executed command, with the number of writes in the [`WriteLog`] at the
+    /// time it was executed, in order.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn entries(&self) -> Vec<(VendorCommand, usize)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Log the execution of `command` after `writes` writes, and return its index.
+    pub(crate) fn record(&self, command: VendorCommand, writes: usize) -> usize {
+        let mut commands = self.0.lock().unwrap();
+        commands.push((command, writes));
+        commands.len() - 1
+    }
+
+    /// The response of the command at `index`.
+    pub(crate) fn response(&self, index: usize) -> Option<Vec<u8>> {
+        self.0
+            .lock()
+            .unwrap()
+            .get(index)
+            .map(|(command, _)| command.bytes.clone())
+    }
+}
+
+/// The polls of the memory of the mocked core done by a [`FakeProbe`] on its own, see
+/// [`FakeProbe::polls`].
+///
+/// The polls are only done if the capabilities of the probe include
+/// [`poll_offload`](ProbeCapabilities::poll_offload), which can be set with
+/// [`FakeProbe::set_capabilities`].
+#[derive(Debug, Clone, Default)]
+pub struct ProbePolls(Arc<Mutex<PollState>>);
+
+#[derive(Debug, Default)]
+struct PollState {
+    polls: u32,
+    notifications: u32,
+    halts: u32,
+    resumes: u32,
+}
+
+impl ProbePolls {
+    /// Make the running core halt on its own during each of the next `count` polls, like a
+    /// core which hits a breakpoint while the probe waits for it.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn halt_during_polls(&self, count: u32) {
+        self.0.lock().unwrap().halts = count;
+    }
+
+    /// Make the core resume right after each of the next `count` notifications, like a core
+    /// which a debug monitor continues before the host reads its status.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn resume_after_notifications(&self, count: u32) {
+        self.0.lock().unwrap().resumes = count;
+    }
+
+    /// Returns the number of polls done by the probe.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn polls(&self) -> u32 {
+        self.0.lock().unwrap().polls
+    }
+
+    /// Returns the number of polls which matched, and notified the host.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn notifications(&self) -> u32 {
+        self.0.lock().unwrap().notifications
+    }
+
+    /// Count a poll, and return true if the core halts during it.
+    pub(crate) fn poll(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        state.polls += 1;
+
+        if state.halts > 0 {
+            state.halts -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Count a notification, and return true if the core resumes after it.
+    pub(crate) fn notify(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        state.notifications += 1;
+
+        if state.resumes > 0 {
+            state.resumes -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The breakpoints the mocked core of a [`FakeProbe`] hits when it is resumed, see
+/// [`FakeProbe::breakpoint_hits`].
+#[derive(Debug, Clone, Default)]
+pub struct BreakpointHits(Arc<Mutex<VecDeque<u32>>>);
+
+impl BreakpointHits {
+    /// Make the core halt at a breakpoint at `address` each time it is resumed, for the
+    /// next `count` times, after the hits which were added before.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn hit(&self, address: u32, count: u32) {
+        let mut hits = self.0.lock().unwrap();
+        hits.extend(std::iter::repeat(address).take(count as usize));
+    }
+
+    /// Returns the number of hits which didn't happen yet.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn remaining(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Returns the address of the next hit, if there is one left.
+    pub(crate) fn next(&self) -> Option<u32> {
+        self.0.lock().unwrap().pop_front()
+    }
+}
+
+/// The round trips of a [`FakeProbe`] to its mocked core, see [`FakeProbe::transactions`].
+///
+/// The probe is counted like a probe which queues its accesses: each read of the core is a
+/// round trip, and writes are sent with the next read. A block read of consecutive words is
+/// a single round trip. Writes which are flushed before anything is read are a round trip of
+/// their own.
+#[derive(Debug, Clone, Default)]
+pub struct ProbeTransactions(Arc<Mutex<TransactionState>>);
+
+#[derive(Debug, Default)]
+struct TransactionState {
+    count: u32,
+    pending_writes: bool,
+}
+
+impl ProbeTransactions {
+    /// Returns the number of round trips since the count was last reset.
+    pub fn count(&self) -> u32 {
+        self.0.lock().unwrap().count
+    }
+
+    /// Reset the count to zero, and drop the writes which are not sent yet.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn reset(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.count = 0;
+        state.pending_writes = false;
+    }
+
+    /// Count a read of the core, which also sends the queued writes.
+    pub(crate) fn read(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.count += 1;
+        state.pending_writes = false;
+    }
+
+    /// Count the reads done by `reads` as a single round trip, like a block read.
+    pub(crate) fn block<R>(&self, reads: impl FnOnce() -> R) -> R {
+        let count = self.count();
+        let result = reads();
+
+        let mut state = self.0.lock().unwrap();
+        state.count = state.count.min(count + 1);
+
+        result
+    }
+
+    /// Queue a write to the core.
+    pub(crate) fn write(&self) {
+        self.0.lock().unwrap().pending_writes = true;
+    }
+
+    /// Send the queued writes, if there are any.
+    pub(crate) fn flush(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        if state.pending_writes {
+            state.count += 1;
+            state.pending_writes = false;
+        }
+    }
+}
+
+/// The resets of the mocked core of a [`FakeProbe`] which are not issued by probe-rs, e.g.
+/// by a watchdog, see [`FakeProbe::target_resets`].
+#[derive(Debug, Clone, Default)]
+pub struct TargetResets(Arc<Mutex<u32>>);
+
+impl TargetResets {
+    /// Reset the core before it is read the next time.
+    pub fn reset(&self) {
+        *self.0.lock().unwrap() += 1;
+    }
+
+    /// Returns true if a reset is pending, and removes it.
+    pub(crate) fn take(&self) -> bool {
+        let mut pending = self.0.lock().unwrap();
+
+        if *pending > 0 {
+            *pending -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The resumes of the mocked core of a [`FakeProbe`] which are not issued by probe-rs, e.g.
+/// by another debugger, see [`FakeProbe::foreign_resumes`].
+#[derive(Debug, Clone, Default)]
+pub struct ForeignResumes(Arc<Mutex<u32>>);
+
+impl ForeignResumes {
+    /// Resume the core before it is read the next time, if it is halted. It halts again at
+    /// the next hit of the [`BreakpointHits`], or keeps running if there is none.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn resume(&self) {
+        *self.0.lock().unwrap() += 1;
+    }
+
+    /// Returns true if a resume is pending, and removes it.
+    pub(crate) fn take(&self) -> bool {
+        let mut pending = self.0.lock().unwrap();
+
+        if *pending > 0 {
+            *pending -= 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The writes of the firmware of the mocked core of a [`FakeProbe`], which race with the
+/// read-modify-writes of probe-rs, see [`FakeProbe::firmware_writes`].
+#[derive(Debug, Clone, Default)]
+pub struct FirmwareWrites(Arc<Mutex<Vec<(u32, u32)>>>);
+
+impl FirmwareWrites {
+    /// Set `bits` of the word at `address` right after the word is read the next time, by
+    /// the probe while the core runs, or by an `LDREX` of the core.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn set_bits_after_read(&self, address: u32, bits: u32) {
+        self.0.lock().unwrap().push((address, bits));
+    }
+
+    /// Returns the number of writes which are still pending.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn pending(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    /// Returns the bits of the first pending write to `address`, and removes it.
+    pub(crate) fn take(&self, address: u32) -> Option<u32> {
+        let mut writes = self.0.lock().unwrap();
+        let index = writes.iter().position(|(write, _)| *write == address)?;
+
+        Some(writes.remove(index).1)
+    }
+}
+
 impl Debug for FakeProbe {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("FakeProbe")
             .field("protocol", &self.protocol)
             .field("speed", &self.speed)
+            .field("mock_core", &self.mock_core)
             .finish()
     }
 }
@@ -42,12 +574,224 @@ impl FakeProbe {
         FakeProbe {
             protocol: WireProtocol::Swd,
             speed: 1000,
+            max_speed: None,
+            slow_clock: None,
+            mock_core: false,
+            routine_delays: HashMap::new(),
+            fpb_revision: 0,
+            ignored_halt_requests: 0,
+            // SWO and the reset line are not mocked.
+            capabilities: ProbeCapabilities::new().swd().jtag(),
+            write_faults: WriteFaults::default(),
+            read_faults: ReadFaults::default(),
+            ecc_faults: EccFaults::default(),
+            write_log: WriteLog::default(),
+            access_stalls: AccessStalls::default(),
+            polls: ProbePolls::default(),
+            breakpoint_hits: BreakpointHits::default(),
+            transactions: ProbeTransactions::default(),
+            target_resets: TargetResets::default(),
+            foreign_resumes: ForeignResumes::default(),
+            firmware_writes: FirmwareWrites::default(),
+            vendor_commands: VendorCommands::default(),
+            reset_asserted: false,
+            flash_algorithm: None,
+            execute_code: false,
+
+            dap: None,
+            memory_ap: None,
+            riscv_debug_module: None,
 
             dap_register_read_handler: None,
             dap_register_write_handler: None,
         }
     }
 
+    /// Creates a new [`FakeProbe`], which is connected to a mocked Cortex-M core.
+    ///
+    /// The core can be halted and run, and every routine it runs returns instantly, unless
+    /// it executes code, see [`FakeProbe::execute_code`].
+    /// This is enough to run flash algorithms, e.g. to test the flashing procedure.
+    ///
+    /// The core can also be reached through the DAP registers of [`RawDapAccess`], with a
+    /// single memory AP, like with a probe which gives raw access to the debug port.
+    pub fn with_mocked_core() -> Self {
+        FakeProbe {
+            mock_core: true,
+            ..FakeProbe::new()
+        }
+    }
+
+    /// Makes attaching fail with a missing acknowledge above `speed_khz`, like a target
+    /// which can't keep up with a fast protocol clock.
+    pub fn set_max_speed(&mut self, speed_khz: u32) {
+        self.max_speed = Some(speed_khz);
+    }
+
+    /// Makes attaching fail with a missing acknowledge above `speed_khz`, until the target
+    /// was attached once at or below it, like a target whose debug logic runs on a slow
+    /// clock until the debugger connected.
+    pub fn set_slow_clock(&mut self, speed_khz: u32) {
+        self.slow_clock = Some(speed_khz);
+    }
+
+    /// Makes the routines which the mocked core runs with `argument` in R0 take `delay`
+    /// to return, like a flash sector which is slow to erase.
+    pub fn set_routine_delay(&mut self, argument: u32, delay: Duration) {
+        self.routine_delays.insert(argument, delay);
+    }
+
+    /// Sets the revision of the breakpoint unit of the mocked core. With revision 0, the
+    /// default, breakpoints can only be set below `0x2000_0000`, with revision 1 at any address.
+    pub fn set_fpb_revision(&mut self, revision: u32) {
+        self.fpb_revision = revision;
+    }
+
+    /// Makes the mocked core ignore its first `count` halt requests, like a core which is
+    /// stuck with interrupts masked or with the clock of its debug logic gated. A reset with
+    /// the reset vector catch still halts it.
+    pub fn set_ignored_halt_requests(&mut self, count: u32) {
+        self.ignored_halt_requests = count;
+    }
+
+    /// Makes the mocked core emulate the erase and program routines of `algorithm`, so that
+    /// downloads change the flash like on a real target.
+    ///
+    /// The routines are recognized by their address, so `algorithm` has to be assembled for
+    /// the RAM the flash algorithm is loaded into, see [`FlashAlgorithm::assemble_from_raw`].
+    pub fn emulate_flash(&mut self, algorithm: FlashAlgorithm) {
+        self.flash_algorithm = Some(algorithm);
+    }
+
+    /// Makes the mocked core execute the code at PC when it is resumed, like a real core,
+    /// instead of returning from the routine instantly.
+    ///
+    /// Only a small subset of the Thumb instructions is supported, enough for simple
+    /// routines: `PUSH`, `POP`, `MOV`, `MOVS`, `ADDS`, `SUBS`, `ADD` to SP, `CMP`, `TST`, `ANDS`,
+    /// `ORRS`, `BICS`, `LSLS` with an immediate, word-sized `STR` and `LDR` with an immediate
+    /// offset, `LDR` from a literal, `STRB` and `LDRB` with a register offset, `LDREX`, `STREX`,
+    /// `CLREX`, `MRS` of MSP and PSP, the barriers, `B`, `BX`, `NOP` and `BKPT`. The
+    /// core halts at a `BKPT`, at a hardware breakpoint, or at an instruction which isn't
+    /// supported. A single step executes one instruction. If the code doesn't halt within
+    /// 100 000 instructions, e.g. in an endless loop, the core is left running until it is
+    /// halted.
+    pub fn execute_code(&mut self) {
+        self.execute_code = true;
+    }
+
+    /// Sets the capabilities the probe reports, e.g. to test how a probe without a feature
+    /// is handled. By default, the probe supports SWD and JTAG, but no SWO or reset control.
+    pub fn set_capabilities(&mut self, capabilities: ProbeCapabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Returns a handle to the addresses of the mocked core whose writes fail.
+    ///
+    /// The handle stays connected to the probe after it was used to attach, so that faults
+    /// can be injected into a running session, e.g. to test how a failing teardown is handled.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn write_faults(&self) -> WriteFaults {
+        self.write_faults.clone()
+    }
+
+    /// Returns a handle which makes reads of the memory of the mocked core fail.
+    ///
+    /// Failing every n-th read tests the retries over a flaky link, inaccessible ranges test
+    /// reads which stop at the end of a memory region.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn read_faults(&self) -> ReadFaults {
+        self.read_faults.clone()
+    }
+
+    /// Returns a handle which makes ranges of the memory of the mocked core behave like ECC
+    /// memory.
+    ///
+    /// The handle also tells which words of a protected range were initialized by a write.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn ecc_faults(&self) -> EccFaults {
+        self.ecc_faults.clone()
+    }
+
+    /// Returns a handle which makes accesses to the memory of the mocked core stall.
+    ///
+    /// The stalls add latency to the accesses, e.g. to test timeouts of long transfers.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn access_stalls(&self) -> AccessStalls {
+        self.access_stalls.clone()
+    }
+
+    /// Returns a handle to the log of the writes to the memory of the mocked core.
+    ///
+    /// The log can be cleared before an operation, so that a test can check which writes the
+    /// operation caused.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn write_log(&self) -> WriteLog {
+        self.write_log.clone()
+    }
+
+    /// Returns a handle to the polls of the memory of the mocked core, which the probe does
+    /// on its own.
+    ///
+    /// The handle counts the polls and notifications, and can halt or resume the core while
+    /// the probe polls it.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn polls(&self) -> ProbePolls {
+        self.polls.clone()
+    }
+
+    /// Returns a handle to the breakpoints the mocked core hits when it is resumed.
+    ///
+    /// Each queued hit halts the core at its address on the next resume, in order.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn breakpoint_hits(&self) -> BreakpointHits {
+        self.breakpoint_hits.clone()
+    }
+
+    /// Returns a handle to the count of the round trips to the mocked core.
+    ///
+    /// Resetting the count before an operation lets a test check how many round trips the
+    /// operation takes.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn transactions(&self) -> ProbeTransactions {
+        self.transactions.clone()
+    }
+
+    /// Returns a handle to reset the mocked core like a watchdog, without the probe.
+    ///
+    /// The core is reset before it is read the next time, and reports the reset in
+    /// `DHCSR.S_RESET_ST`.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn target_resets(&self) -> TargetResets {
+        self.target_resets.clone()
+    }
+
+    /// Returns a handle to resume the mocked core like another debugger, without the probe.
+    ///
+    /// A halted core resumes before it is read the next time, and runs until the next queued
+    /// breakpoint hit, if there is one.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn foreign_resumes(&self) -> ForeignResumes {
+        self.foreign_resumes.clone()
+    }
+
+    /// Returns a handle to make the firmware of the mocked core write words in memory, e.g.
+    /// to race with a read-modify-write.
+    ///
+    /// Each write sets its bits right after its address is read the next time.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn firmware_writes(&self) -> FirmwareWrites {
+        self.firmware_writes.clone()
+    }
+
+    /// Returns a handle to the log of the vendor commands the probe executed.
+    ///
+    /// Each entry records how many writes were logged before the command, so that a test can
+    /// check where the commands were ordered between the writes.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn vendor_commands(&self) -> VendorCommands {
+        self.vendor_commands.clone()
+    }
+
     /// This sets the read handler for DAP register reads.
     /// Can be used to hook into the read.
     pub fn set_dap_register_read_handler(
@@ -66,12 +810,161 @@ impl FakeProbe {
         self.dap_register_write_handler = Some(handler);
     }
 
+    /// Connects the probe to a mocked RISC-V Debug Module with a single halted hart, in
+    /// addition to the mocked ARM core, like the debug port of a target which mixes ARM and
+    /// RISC-V cores.
+    ///
+    /// The probe then offers a RISC-V interface, see [`Probe::try_into_riscv_interface`].
+    pub fn mock_riscv_debug_module(&mut self) {
+        let (debug_module, state) = MockDebugModule::new();
+
+        {
+            let mut state = state.lock().unwrap();
+
+            // dcsr: debug version 4, ebreak in all modes, machine mode
+            state.hart_registers.insert(0x7b0, 0x4000_b003);
+            // misa: RV32IMC
+            state.hart_registers.insert(0x301, 0x4000_1104);
+            // dpc
+            state.hart_registers.insert(0x7b1, 0);
+            // tinfo of the first trigger: no triggers
+            state.hart_registers.insert(0x7a4, 1);
+            // x0 to x31
+            for regno in 0x1000..0x1020 {
+                state.hart_registers.insert(regno, 0);
+            }
+        }
+
+        self.riscv_debug_module = Some(debug_module);
+    }
+
+    /// Delays every round trip to the mocked RISC-V Debug Module by `latency`, like a probe
+    /// with a high latency, see [`FakeProbe::mock_riscv_debug_module`].
+    pub fn set_riscv_latency(&mut self, latency: Duration) {
+        if let Some(debug_module) = &mut self.riscv_debug_module {
+            debug_module.set_latency(latency);
+        }
+    }
+
     /// Makes a generic probe out of the [`FakeProbe`]
     pub fn into_probe(self) -> Probe {
         Probe::from_specific_probe(Box::new(self))
     }
 }
 
+impl FakeProbe {
+    /// The DAP of the mocked core, if the probe has one.
+    fn mocked_dap(&mut self) -> Option<&mut MockDap> {
+        if !self.mock_core {
+            return None;
+        }
+
+        if self.dap.is_none() {
+            self.dap = Some(MockDap {
+                select: Select(0),
+                ctrl_stat: Ctrl(0),
+                memory_ap: MockMemoryAp::for_probe(self),
+            });
+        }
+
+        self.dap.as_mut()
+    }
+}
+
+/// The debug port of the mocked core, with a memory AP at APSEL 0.
+///
+/// The power-up requests in CTRL/STAT are acknowledged immediately. Other APs read as zero,
+/// which ends the AP discovery after the memory AP.
+#[derive(Debug)]
+struct MockDap {
+    select: Select,
+    ctrl_stat: Ctrl,
+    memory_ap: MockMemoryAp,
+}
+
+impl MockDap {
+    /// DPIDR of an ADIv5 DPv1 debug port.
+    const DPIDR: u32 = 0x2ba0_1477;
+    /// IDR of an AHB-AP.
+    const IDR: u32 = 0x2477_0011;
+    /// BASE, pointing to the ROM table at `0xf000_0000` in the ADIv5 format.
+    const BASE: u32 = 0xf000_0003;
+
+    fn memory_ap(&self) -> Option<MemoryAp> {
+        (self.select.ap_sel() == 0).then(|| {
+            MemoryAp::new(ApAddress {
+                dp: DpAddress::Default,
+                ap: 0,
+            })
+        })
+    }
+
+    /// The AP register address of the lowest 4 bits `addr`, in the selected bank.
+    fn ap_register(&self, addr: u8) -> u8 {
+        self.select.ap_bank_sel() << 4 | addr & 0xc
+    }
+
+    fn read(&mut self, port: PortType, addr: u8) -> Result<u32, DebugProbeError> {
+        match port {
+            PortType::DebugPort => Ok(match addr & 0xc {
+                0x0 => Self::DPIDR,
+                0x4 if self.select.dp_bank_sel() == 0 => {
+                    // The power-up requests are acknowledged immediately.
+                    let request = self.ctrl_stat.0 & 0x5000_0000;
+                    self.ctrl_stat.0 | request << 1
+                }
+                _ => 0,
+            }),
+            PortType::AccessPort => {
+                let port = match self.memory_ap() {
+                    Some(port) => port,
+                    None => return Ok(0),
+                };
+
+                match self.ap_register(addr) {
+                    CSW::ADDRESS => self.memory_ap.read_ap_register(port).map(CSW::into),
+                    TAR::ADDRESS => self.memory_ap.read_ap_register(port).map(TAR::into),
+                    TAR2::ADDRESS => self.memory_ap.read_ap_register(port).map(TAR2::into),
+                    DRW::ADDRESS => self.memory_ap.read_ap_register(port).map(DRW::into),
+                    0xf8 => Ok(Self::BASE),
+                    0xfc => Ok(Self::IDR),
+                    // CFG, BASE2 and the banked data registers.
+                    _ => Ok(0),
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, port: PortType, addr: u8, value: u32) -> Result<(), DebugProbeError> {
+        match port {
+            PortType::DebugPort => {
+                match addr & 0xc {
+                    0x4 if self.select.dp_bank_sel() == 0 => self.ctrl_stat = Ctrl(value),
+                    0x8 => self.select = Select(value),
+                    // ABORT, TARGETSEL and the other banks.
+                    _ => (),
+                }
+
+                Ok(())
+            }
+            PortType::AccessPort => {
+                let port = match self.memory_ap() {
+                    Some(port) => port,
+                    None => return Ok(()),
+                };
+
+                match self.ap_register(addr) {
+                    CSW::ADDRESS => self.memory_ap.write_ap_register(port, CSW::from(value)),
+                    TAR::ADDRESS => self.memory_ap.write_ap_register(port, TAR::from(value)),
+                    TAR2::ADDRESS => self.memory_ap.write_ap_register(port, TAR2::from(value)),
+                    DRW::ADDRESS => self.memory_ap.write_ap_register(port, DRW::from(value)),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+}
+
 impl Default for FakeProbe {
     fn default() -> Self {
         FakeProbe::new()
@@ -104,6 +997,15 @@ impl DebugProbe for FakeProbe {
     }
 
     fn attach(&mut self) -> Result<(), DebugProbeError> {
+        let limits = [self.slow_clock, self.max_speed];
+
+        if limits.iter().flatten().any(|&limit| self.speed > limit) {
+            return Err(DapError::NoAcknowledge.into());
+        }
+
+        // The debug clock is raised once the target was attached at a low speed.
+        self.slow_clock = None;
+
         Ok(())
     }
 
@@ -149,17 +1051,54 @@ impl DebugProbe for FakeProbe {
     fn has_arm_interface(&self) -> bool {
         true
     }
+
+    fn try_get_riscv_interface(
+        self: Box<Self>,
+    ) -> Result<RiscvCommunicationInterface, (Box<dyn DebugProbe>, DebugProbeError)> {
+        if self.riscv_debug_module.is_some() {
+            RiscvCommunicationInterface::new(self).map_err(|(probe, err)| (probe.into_probe(), err))
+        } else {
+            Err((
+                DebugProbe::into_probe(self),
+                DebugProbeError::InterfaceNotAvailable("RISCV"),
+            ))
+        }
+    }
+
+    fn has_riscv_interface(&self) -> bool {
+        self.riscv_debug_module.is_some()
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        // Only the DAP of the mocked core is emulated.
+        if self.mock_core {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        self.capabilities
+    }
 }
 
+impl DapProbe for FakeProbe {}
+
 impl RawDapAccess for FakeProbe {
-    fn select_dp(&mut self, _dp: DpAddress) -> Result<(), DebugProbeError> {
-        Err(DebugProbeError::CommandNotSupportedByProbe("select_dp"))
+    fn select_dp(&mut self, dp: DpAddress) -> Result<(), DebugProbeError> {
+        match dp {
+            DpAddress::Default if self.mock_core => Ok(()),
+            _ => Err(DebugProbeError::CommandNotSupportedByProbe("select_dp")),
+        }
     }
 
     /// Reads the DAP register on the specified port and address
     fn raw_read_register(&mut self, port: PortType, addr: u8) -> Result<u32, DebugProbeError> {
         if let Some(handler) = &self.dap_register_read_handler {
             handler(port, addr)
+        } else if let Some(dap) = self.mocked_dap() {
+            dap.read(port, addr)
         } else {
             Err(DebugProbeError::CommandNotSupportedByProbe(
                 "raw_read_register",
@@ -176,6 +1115,8 @@ impl RawDapAccess for FakeProbe {
     ) -> Result<(), DebugProbeError> {
         if let Some(handler) = &self.dap_register_write_handler {
             handler(port, addr, value)
+        } else if let Some(dap) = self.mocked_dap() {
+            dap.write(port, addr, value)
         } else {
             Err(DebugProbeError::CommandNotSupportedByProbe(
                 "raw_write_register",
@@ -184,16 +1125,31 @@ impl RawDapAccess for FakeProbe {
     }
 
     fn swj_sequence(&mut self, _bit_len: u8, _bits: u64) -> Result<(), DebugProbeError> {
-        todo!()
+        // The sequences only reset the wire protocol, which isn't mocked.
+        Ok(())
     }
 
     fn swj_pins(
         &mut self,
-        _pin_out: u32,
-        _pin_select: u32,
+        pin_out: u32,
+        pin_select: u32,
         _pin_wait: u32,
     ) -> Result<u32, DebugProbeError> {
-        todo!()
+        // Only nRESET is mocked, releasing it resets the mocked core.
+        let mut n_reset = Pins(0);
+        n_reset.set_nreset(true);
+        let n_reset = n_reset.0 as u32;
+
+        if pin_select & n_reset != 0 {
+            let asserted = pin_out & n_reset == 0;
+
+            if self.reset_asserted && !asserted {
+                self.target_resets.reset();
+            }
+            self.reset_asserted = asserted;
+        }
+
+        Ok(if self.reset_asserted { 0 } else { n_reset })
     }
 
     fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
@@ -201,6 +1157,63 @@ impl RawDapAccess for FakeProbe {
     }
 }
 
+impl FakeProbe {
+    /// The mocked RISC-V Debug Module, if the probe has one.
+    fn debug_module(&mut self) -> Result<&mut MockDebugModule, DebugProbeError> {
+        self.riscv_debug_module
+            .as_mut()
+            .ok_or(DebugProbeError::InterfaceNotAvailable("RISCV"))
+    }
+}
+
+impl JTAGAccess for FakeProbe {
+    fn read_register(&mut self, address: u32, len: u32) -> Result<Vec<u8>, DebugProbeError> {
+        self.debug_module()?.read_register(address, len)
+    }
+
+    fn set_idle_cycles(&mut self, idle_cycles: u8) {
+        if let Ok(debug_module) = self.debug_module() {
+            debug_module.set_idle_cycles(idle_cycles);
+        }
+    }
+
+    fn get_idle_cycles(&self) -> u8 {
+        self.riscv_debug_module
+            .as_ref()
+            .map_or(0, JTAGAccess::get_idle_cycles)
+    }
+
+    fn set_ir_len(&mut self, len: u32) {
+        if let Ok(debug_module) = self.debug_module() {
+            debug_module.set_ir_len(len);
+        }
+    }
+
+    fn max_queued_operations(&self) -> Option<usize> {
+        self.riscv_debug_module
+            .as_ref()
+            .and_then(JTAGAccess::max_queued_operations)
+    }
+
+    fn write_register(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        len: u32,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        self.debug_module()?.write_register(address, data, len)
+    }
+
+    fn write_register_batch(
+        &mut self,
+        writes: &[JtagWriteCommand],
+    ) -> Result<Vec<CommandResult>, BatchExecutionError> {
+        self.debug_module()
+            .map_err(|e| BatchExecutionError::new(e, Vec::new()))?
+            .write_register_batch(writes)
+    }
+}
+
 #[derive(Debug)]
 struct FakeArmInterface<S: ArmDebugState> {
     probe: Box<FakeProbe>,
@@ -211,11 +1224,14 @@ struct FakeArmInterface<S: ArmDebugState> {
 }
 
 impl<'interface> FakeArmInterface<Uninitialized> {
-    pub(crate) fn new(probe: Box<FakeProbe>) -> Self {
+    pub(crate) fn new(mut probe: Box<FakeProbe>) -> Self {
         let state = Uninitialized {
             use_overrun_detect: false,
         };
-        let memory_ap = MockMemoryAp::with_pattern();
+        let memory_ap = match probe.memory_ap.take() {
+            Some(memory_ap) => memory_ap,
+            None => MockMemoryAp::for_probe(&probe),
+        };
 
         Self {
             probe,
@@ -239,11 +1255,53 @@ impl FakeArmInterface<Initialized> {
         interface: FakeArmInterface<Uninitialized>,
         sequence: Arc<dyn ArmDebugSequence>,
     ) -> Self {
-        let memory_ap = MockMemoryAp::with_pattern();
         FakeArmInterface::<Initialized> {
             probe: interface.probe,
             _state: Initialized::new(sequence, false),
-            memory_ap,
+            memory_ap: interface.memory_ap,
+        }
+    }
+
+    /// The information of the mocked memory AP at `address`.
+    fn mocked_ap_information(&self, address: ApAddress) -> MemoryApInformation {
+        MemoryApInformation {
+            address,
+            only_32bit_data_size: self.probe.capabilities.needs_word_transfers(),
+            debug_base_address: 0xf000_0000,
+            supports_hnonsec: false,
+            has_large_data_extension: false,
+            has_large_address_extension: false,
+        }
+    }
+}
+
+impl MockMemoryAp {
+    fn for_probe(probe: &FakeProbe) -> Self {
+        if probe.mock_core {
+            let mut memory_ap = MockMemoryAp::with_mock_core();
+            memory_ap.set_routine_delays(probe.routine_delays.clone());
+            memory_ap.set_fpb_revision(probe.fpb_revision);
+            memory_ap.set_ignored_halt_requests(probe.ignored_halt_requests);
+            memory_ap.set_write_faults(probe.write_faults.clone());
+            memory_ap.set_read_faults(probe.read_faults.clone());
+            memory_ap.set_ecc_faults(probe.ecc_faults.clone());
+            memory_ap.set_write_log(probe.write_log.clone());
+            memory_ap.set_access_stalls(probe.access_stalls.clone());
+            memory_ap.set_polls(probe.polls.clone());
+            memory_ap.set_breakpoint_hits(probe.breakpoint_hits.clone());
+            memory_ap.set_transactions(probe.transactions.clone());
+            memory_ap.set_target_resets(probe.target_resets.clone());
+            memory_ap.set_foreign_resumes(probe.foreign_resumes.clone());
+            memory_ap.set_firmware_writes(probe.firmware_writes.clone());
+            memory_ap.set_vendor_commands(
+                probe.vendor_commands.clone(),
+                probe.capabilities.vendor_commands,
+            );
+            memory_ap.set_flash_algorithm(probe.flash_algorithm.clone());
+            memory_ap.set_execute_code(probe.execute_code);
+            memory_ap
+        } else {
+            MockMemoryAp::with_pattern()
         }
     }
 }
@@ -284,14 +1342,7 @@ impl UninitializedArmProbe for FakeArmInterface<Uninitialized> {
 
 impl ArmProbeInterface for FakeArmInterface<Initialized> {
     fn memory_interface(&mut self, access_port: MemoryAp) -> Result<Memory<'_>, Error> {
-        let ap_information = MemoryApInformation {
-            address: access_port.ap_address(),
-            only_32bit_data_size: false,
-            debug_base_address: 0xf000_0000,
-            supports_hnonsec: false,
-            has_large_data_extension: false,
-            has_large_address_extension: false,
-        };
+        let ap_information = self.mocked_ap_information(access_port.ap_address());
 
         let memory = ADIMemoryInterface::new(&mut self.memory_ap, &ap_information)?;
 
@@ -309,6 +1360,15 @@ impl ArmProbeInterface for FakeArmInterface<Initialized> {
         Ok(1)
     }
 
+    fn access_ports(&self) -> Vec<ApInformation> {
+        let address = ApAddress {
+            dp: DpAddress::Default,
+            ap: 0,
+        };
+
+        vec![ApInformation::MemoryAp(self.mocked_ap_information(address))]
+    }
+
     fn read_chip_info_from_rom_table(
         &mut self,
         _dp: DpAddress,
@@ -316,8 +1376,21 @@ impl ArmProbeInterface for FakeArmInterface<Initialized> {
         Ok(None)
     }
 
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.probe.active_protocol()
+    }
+
+    fn switch_protocol(&mut self, protocol: WireProtocol) -> Result<(), Error> {
+        self.probe.select_protocol(protocol)?;
+
+        Ok(())
+    }
+
     fn close(self: Box<Self>) -> Probe {
-        Probe::from_attached_probe(self.probe)
+        let mut probe = self.probe;
+        probe.memory_ap = Some(self.memory_ap);
+
+        Probe::from_attached_probe(probe)
     }
 }
 
@@ -0,0 +1,204 @@
+//! Adjustments of the capabilities of a probe to the version of its firmware.
+//!
+//! The drivers report the capabilities of their probes with current firmware. Older firmware
+//! lacks some features, or has known bugs which the driver works around. Each driver keeps
+//! a table of [`FirmwareRule`]s in its own `firmware` module, with the affected versions,
+//! from which the [`FirmwareAdjustment`]s of an opened probe are derived.
+
+use std::fmt;
+use std::ops::Range;
+
+use super::plugin::ProbeCapabilities;
+use crate::DebugProbeError;
+
+/// A feature of a probe which depends on the version of its firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum FirmwareFeature {
+    /// SWO capture.
+    Swo,
+    /// Accesses to other access ports than the first one.
+    MultipleAccessPorts,
+    /// Memory transfers of single bytes.
+    ///
+    /// Without them, byte accesses are assembled from word accesses.
+    ByteTransfers,
+}
+
+impl fmt::Display for FirmwareFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FirmwareFeature::Swo => "SWO capture",
+            FirmwareFeature::MultipleAccessPorts => "Access to multiple access ports",
+            FirmwareFeature::ByteTransfers => "8-bit memory transfers",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// How the firmware of a probe limits a [`FirmwareFeature`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareLimitation {
+    /// The feature is not available. It is removed from the capabilities of the probe, and
+    /// its use fails with [`DebugProbeError::FirmwareTooOld`].
+    Unavailable,
+    /// The feature is available, but a known bug of the firmware is worked around, e.g. with
+    /// smaller transfers.
+    WorkedAround,
+}
+
+/// An adjustment of the capabilities of a probe to the version of its firmware, see
+/// [`Probe::firmware_adjustments`](crate::Probe::firmware_adjustments).
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FirmwareAdjustment {
+    /// The affected feature.
+    pub feature: FirmwareFeature,
+    /// How the feature is limited.
+    pub limitation: FirmwareLimitation,
+    /// The problem of the firmware.
+    pub reason: String,
+    /// The kind of firmware, e.g. `ST-Link`.
+    pub firmware: String,
+    /// The version of the firmware of the probe, in the notation of its vendor, e.g. `V2J24`.
+    pub detected: String,
+    /// The first version of the firmware without the limitation.
+    pub minimum: String,
+}
+
+impl FirmwareAdjustment {
+    /// The error for a use of the feature, which names the detected and the minimum version.
+    pub fn error(&self) -> DebugProbeError {
+        DebugProbeError::FirmwareTooOld {
+            feature: self.feature,
+            firmware: self.firmware.clone(),
+            minimum: self.minimum.clone(),
+            detected: self.detected.clone(),
+        }
+    }
+}
+
+impl fmt::Display for FirmwareAdjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let limitation = match self.limitation {
+            FirmwareLimitation::Unavailable => "unavailable",
+            FirmwareLimitation::WorkedAround => "worked around",
+        };
+
+        write!(
+            f,
+            "{} {} with {} firmware {}, before {}: {}",
+            self.feature, limitation, self.firmware, self.detected, self.minimum, self.reason
+        )
+    }
+}
+
+/// The firmware versions `versions` limit `feature`, an entry of the table of a driver.
+pub(crate) struct FirmwareRule<V: 'static> {
+    pub(crate) versions: Range<V>,
+    pub(crate) feature: FirmwareFeature,
+    pub(crate) limitation: FirmwareLimitation,
+    pub(crate) reason: &'static str,
+}
+
+/// Returns the adjustments of the `rules` of `firmware` whose versions contain `version`,
+/// with the versions named by `name`.
+pub(crate) fn adjustments<V: Ord>(
+    firmware: &str,
+    rules: &[FirmwareRule<V>],
+    version: &V,
+    name: impl Fn(&V) -> String,
+) -> Vec<FirmwareAdjustment> {
+    rules
+        .iter()
+        .filter(|rule| rule.versions.contains(version))
+        .map(|rule| FirmwareAdjustment {
+            feature: rule.feature,
+            limitation: rule.limitation,
+            reason: rule.reason.to_owned(),
+            firmware: firmware.to_owned(),
+            detected: name(version),
+            minimum: name(&rule.versions.end),
+        })
+        .collect()
+}
+
+/// Returns the adjustment which limits `feature` with `limitation`, if there is one.
+pub(crate) fn find(
+    adjustments: &[FirmwareAdjustment],
+    feature: FirmwareFeature,
+    limitation: FirmwareLimitation,
+) -> Option<&FirmwareAdjustment> {
+    adjustments
+        .iter()
+        .find(|adjustment| adjustment.feature == feature && adjustment.limitation == limitation)
+}
+
+/// Remove the features which are unavailable with the firmware from `capabilities`.
+pub(crate) fn apply(capabilities: &mut ProbeCapabilities, adjustments: &[FirmwareAdjustment]) {
+    for adjustment in adjustments {
+        if adjustment.limitation != FirmwareLimitation::Unavailable {
+            continue;
+        }
+
+        match adjustment.feature {
+            FirmwareFeature::Swo => capabilities.swo = false,
+            FirmwareFeature::ByteTransfers => {
+                let min = capabilities.min_transfer_size.unwrap_or(1).max(4);
+                capabilities.min_transfer_size = Some(min);
+            }
+            FirmwareFeature::MultipleAccessPorts => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RULES: &[FirmwareRule<u32>] = &[
+        FirmwareRule {
+            versions: 0..20,
+            feature: FirmwareFeature::Swo,
+            limitation: FirmwareLimitation::Unavailable,
+            reason: "no SWO commands",
+        },
+        FirmwareRule {
+            versions: 10..15,
+            feature: FirmwareFeature::ByteTransfers,
+            limitation: FirmwareLimitation::Unavailable,
+            reason: "corrupted byte transfers",
+        },
+    ];
+
+    fn name(version: &u32) -> String {
+        format!("v{}", version)
+    }
+
+    #[test]
+    fn rules_apply_to_their_versions() {
+        assert_eq!(adjustments("Test", RULES, &12, name).len(), 2);
+        assert_eq!(adjustments("Test", RULES, &15, name).len(), 1);
+        assert!(adjustments("Test", RULES, &20, name).is_empty());
+
+        let adjustment = &adjustments("Test", RULES, &15, name)[0];
+        assert_eq!(adjustment.detected, "v15");
+        assert_eq!(adjustment.minimum, "v20");
+        assert_eq!(
+            adjustment.error().to_string(),
+            "SWO capture requires Test firmware v20 or later; detected v15"
+        );
+    }
+
+    #[test]
+    fn unavailable_features_are_removed_from_the_capabilities() {
+        let mut capabilities = ProbeCapabilities::new().swd().swo().transfer_sizes(1, 1024);
+        apply(&mut capabilities, &adjustments("Test", RULES, &12, name));
+
+        assert!(!capabilities.swo);
+        assert!(capabilities.needs_word_transfers());
+        assert_eq!(capabilities.max_transfer_size, Some(1024));
+    }
+}
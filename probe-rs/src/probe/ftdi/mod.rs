@@ -2,7 +2,7 @@ use crate::architecture::{
     arm::communication_interface::UninitializedArmProbe,
     riscv::communication_interface::RiscvCommunicationInterface,
 };
-use crate::probe::{JTAGAccess, ProbeCreationError};
+use crate::probe::{plugin::ProbeCapabilities, JTAGAccess, ProbeCreationError};
 use crate::{
     DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType, WireProtocol,
 };
@@ -533,6 +533,15 @@ impl DebugProbe for FtdiProbe {
         Some(WireProtocol::Jtag)
     }
 
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Jtag]
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        // The reset line is not driven yet.
+        ProbeCapabilities::new().jtag()
+    }
+
     fn try_get_riscv_interface(
         self: Box<Self>,
     ) -> Result<RiscvCommunicationInterface, (Box<dyn DebugProbe>, DebugProbeError)> {
@@ -1,12 +1,16 @@
-use std::{iter, time::Duration};
+use std::{
+    iter,
+    time::{Duration, Instant},
+};
 
 use crate::{
     architecture::arm::{
+        ap::DRW,
         dp::{Abort, Ctrl, RdBuff, DPIDR},
         DapError, DpAddress, Pins, PortType, RawDapAccess, Register,
     },
     probe::JTAGAccess,
-    DebugProbe, DebugProbeError,
+    DebugProbe, DebugProbeError, HealthEvent, HealthLog,
 };
 
 use super::{bits_to_byte, JLink};
@@ -583,6 +587,54 @@ impl DapTransfer {
             && self.address == RdBuff::ADDRESS
             && self.direction == TransferDirection::Read
     }
+
+    /// Returns true if the transfer can be repeated without side effects.
+    ///
+    /// Accesses to the data registers of a MEM-AP (DRW, BD0-BD3 and MBT) are passed on
+    /// to the bus, which might be connected to a peripheral, and they auto-increment TAR.
+    /// All other registers only hold debug state, so accessing them again is harmless.
+    fn is_idempotent(&self) -> bool {
+        match self.port {
+            PortType::DebugPort => true,
+            PortType::AccessPort => !matches!(self.address, DRW::ADDRESS | 0x10..=0x20),
+        }
+    }
+}
+
+/// Returns true if the error means that the probe and the target are out of sync,
+/// and a line reset is required before any further transfers.
+fn is_protocol_error(error: &DapError) -> bool {
+    matches!(
+        error,
+        DapError::SwdProtocol | DapError::NoAcknowledge | DapError::IncorrectParity
+    )
+}
+
+/// Recover from a protocol error.
+///
+/// A line reset is performed, which reads the DPIDR register, and afterwards all
+/// sticky error flags are cleared, so that the transfers can be issued again.
+fn resynchronize<P: RawProtocolIo + RawDapAccess>(
+    probe: &mut P,
+    operation: &'static str,
+    details: String,
+) -> Result<(), DebugProbeError> {
+    probe.line_reset()?;
+
+    let mut abort = Abort(0);
+
+    abort.set_orunerrclr(true);
+    abort.set_wderrclr(true);
+    abort.set_stkerrclr(true);
+    abort.set_stkcmpclr(true);
+
+    RawDapAccess::raw_write_register(probe, PortType::DebugPort, Abort::ADDRESS, abort.into())?;
+
+    probe
+        .health_log()
+        .record(HealthEvent::Resynchronized, None, operation, details);
+
+    Ok(())
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -833,6 +885,9 @@ pub trait RawProtocolIo {
 
     fn probe_statistics(&mut self) -> &mut ProbeStatistics;
 
+    /// The log in which recovered errors are recorded.
+    fn health_log(&self) -> &HealthLog;
+
     /// Try to perform a line reset, followed by a read of the DPIDR register.
     ///
     /// Returns Ok if the read of the DPIDR register was succesful, and Err
@@ -924,8 +979,15 @@ impl RawProtocolIo for JLink {
     fn probe_statistics(&mut self) -> &mut ProbeStatistics {
         &mut self.probe_statistics
     }
+
+    fn health_log(&self) -> &HealthLog {
+        &self.health_log
+    }
 }
 
+/// The number of reads of a register which the probe does for each command while polling it.
+const POLL_BURST_LEN: usize = 32;
+
 impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for Probe {
     fn select_dp(&mut self, dp: DpAddress) -> Result<(), DebugProbeError> {
         match dp {
@@ -949,6 +1011,18 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
 
             match transfers[0].status {
                 TransferStatus::Ok => {
+                    if retry > 0 {
+                        self.health_log().record(
+                            HealthEvent::RetrySucceeded,
+                            None,
+                            "DAP read",
+                            format!(
+                                "{:?} register {:#04x} after {} retries",
+                                port, address, retry
+                            ),
+                        );
+                    }
+
                     return Ok(transfers[0].value);
                 }
                 TransferStatus::Pending => {
@@ -1015,19 +1089,33 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
                             Abort::ADDRESS,
                             abort.into(),
                         )?;
+
+                        self.health_log().record(
+                            HealthEvent::StickyErrorCleared,
+                            None,
+                            "DAP read",
+                            format!("{:?}", ctrl),
+                        );
                     }
 
                     return Err(DapError::FaultResponse.into());
                 }
                 // The other errors mean that something went wrong with the protocol itself,
                 // so we try to perform a line reset, and recover.
-                TransferStatus::Failed(_) => {
+                TransferStatus::Failed(ref err) => {
                     log::debug!("DAP NACK");
 
                     // Because we clock the SWDCLK line after receving the WAIT response,
                     // the target might be in weird state. If we perform a line reset,
                     // we should be able to recover from this.
-                    self.line_reset()?;
+                    resynchronize(
+                        self,
+                        "DAP read",
+                        format!(
+                            "line reset after {:?} reading {:?} register {:#04x}",
+                            err, port, address
+                        ),
+                    )?;
 
                     // Retry operation again
                     continue;
@@ -1100,6 +1188,36 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
 
                             continue 'transfer;
                         }
+
+                        if is_protocol_error(err) {
+                            let err = err.clone();
+                            let replayable = transfers[index].is_idempotent();
+
+                            resynchronize(
+                                self,
+                                "DAP block read",
+                                format!(
+                                    "line reset after {:?} in access {}/{} to {:?} register {:#04x}",
+                                    err,
+                                    index_offset + index + 1,
+                                    values.len(),
+                                    port,
+                                    address
+                                ),
+                            )?;
+
+                            // The remaining accesses are only issued again if that has no side effects.
+                            if replayable {
+                                log::debug!(
+                                    "Replaying {} accesses",
+                                    values.len() - (index_offset + index)
+                                );
+
+                                continue 'transfer;
+                            }
+
+                            return Err(err.into());
+                        }
                         return Err(err.clone().into());
                     }
                     TransferStatus::Pending => {
@@ -1110,9 +1228,41 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
             }
         }
 
+        if succesful_transfers < values.len() {
+            // If we land here, the DAP operation timed out.
+            log::error!("DAP block read timeout.");
+            return Err(DebugProbeError::Timeout);
+        }
+
         Ok(())
     }
 
+    fn raw_poll_register(
+        &mut self,
+        port: PortType,
+        address: u8,
+        mask: u32,
+        expected: u32,
+        timeout: Duration,
+    ) -> Result<Option<u32>, DebugProbeError> {
+        let start = Instant::now();
+        let mut values = [0; POLL_BURST_LEN];
+
+        loop {
+            // The reads of a burst are sent to the probe with a single command, so the host
+            // only sees every `POLL_BURST_LEN`-th read.
+            self.raw_read_block(port, address, &mut values)?;
+
+            if let Some(value) = values.iter().find(|value| *value & mask == expected) {
+                return Ok(Some(*value));
+            }
+
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+        }
+    }
+
     fn raw_write_register(
         &mut self,
         port: PortType,
@@ -1131,6 +1281,18 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
 
             match transfers[0].status {
                 TransferStatus::Ok => {
+                    if retry > 0 {
+                        self.health_log().record(
+                            HealthEvent::RetrySucceeded,
+                            None,
+                            "DAP write",
+                            format!(
+                                "{:?} register {:#04x} after {} retries",
+                                port, address, retry
+                            ),
+                        );
+                    }
+
                     return Ok(());
                 }
                 TransferStatus::Pending => {
@@ -1198,19 +1360,41 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
                             Abort::ADDRESS,
                             abort.into(),
                         )?;
+
+                        self.health_log().record(
+                            HealthEvent::StickyErrorCleared,
+                            None,
+                            "DAP write",
+                            format!("{:?}", ctrl),
+                        );
                     }
 
                     return Err(DapError::FaultResponse.into());
                 }
                 // The other errors mean that something went wrong with the protocol itself,
                 // so we try to perform a line reset, and recover.
-                TransferStatus::Failed(_) => {
+                TransferStatus::Failed(ref err) => {
                     log::debug!("DAP NACK");
 
+                    let err = err.clone();
+
                     // Because we clock the SWDCLK line after receving the WAIT response,
                     // the target might be in weird state. If we perform a line reset,
                     // we should be able to recover from this.
-                    self.line_reset()?;
+                    resynchronize(
+                        self,
+                        "DAP write",
+                        format!(
+                            "line reset after {:?} writing {:?} register {:#04x}",
+                            err, port, address
+                        ),
+                    )?;
+
+                    // It is unknown if the write reached the target, so it can only be
+                    // repeated if that has no side effects.
+                    if !transfers[0].is_idempotent() {
+                        return Err(err.into());
+                    }
 
                     // Retry operation
                     continue;
@@ -1286,6 +1470,36 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
                             continue 'transfer;
                         }
 
+                        if is_protocol_error(err) {
+                            let err = err.clone();
+                            let replayable = transfers[index].is_idempotent();
+
+                            resynchronize(
+                                self,
+                                "DAP block write",
+                                format!(
+                                    "line reset after {:?} in access {}/{} to {:?} register {:#04x}",
+                                    err,
+                                    index_offset + index + 1,
+                                    values.len(),
+                                    port,
+                                    address
+                                ),
+                            )?;
+
+                            // The remaining accesses are only issued again if that has no side effects.
+                            if replayable {
+                                log::debug!(
+                                    "Replaying {} accesses",
+                                    values.len() - (index_offset + index)
+                                );
+
+                                continue 'transfer;
+                            }
+
+                            return Err(err.into());
+                        }
+
                         return Err(err.clone().into());
                     }
                     TransferStatus::Pending => {
@@ -1298,6 +1512,12 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
             return Ok(());
         }
 
+        if succesful_transfers < values.len() {
+            // If we land here, the DAP operation timed out.
+            log::error!("DAP block write timeout.");
+            return Err(DebugProbeError::Timeout);
+        }
+
         Ok(())
     }
 
@@ -1374,17 +1594,18 @@ impl<Probe: DebugProbe + RawProtocolIo + JTAGAccess + 'static> RawDapAccess for
 mod test {
 
     use std::iter;
+    use std::time::Duration;
 
     use crate::{
         architecture::arm::{PortType, RawDapAccess},
         probe::JTAGAccess,
-        DebugProbe, DebugProbeError,
+        DebugProbe, DebugProbeError, HealthEvent, HealthLog,
     };
 
     use super::{
         parse_jtag_response, ProbeStatistics, RawProtocolIo, SwdSettings, JTAG_ABORT_IR_VALUE,
         JTAG_ACCESS_PORT_IR_VALUE, JTAG_DEBUG_PORT_IR_VALUE, JTAG_DR_BIT_LENGTH, JTAG_STATUS_OK,
-        JTAG_STATUS_WAIT,
+        JTAG_STATUS_WAIT, POLL_BURST_LEN,
     };
 
     use bitvec::prelude::*;
@@ -1418,6 +1639,7 @@ mod test {
 
         swd_settings: SwdSettings,
         probe_statistics: ProbeStatistics,
+        health_log: HealthLog,
 
         protocol: crate::WireProtocol,
     }
@@ -1435,6 +1657,7 @@ mod test {
 
                 swd_settings: SwdSettings::default(),
                 probe_statistics: ProbeStatistics::default(),
+                health_log: HealthLog::default(),
 
                 protocol: crate::WireProtocol::Swd,
             }
@@ -1568,6 +1791,17 @@ mod test {
             last_transfer.extend(response);
         }
 
+        /// Add a read response with an incorrect parity bit.
+        fn add_corrupted_read_response(&mut self, value: u32) {
+            self.add_read_response(DapAcknowledge::Ok, value);
+
+            let last_transfer = self.transfer_responses.last_mut().unwrap();
+
+            // The parity bit is followed by the two turnaround bits.
+            let parity_index = last_transfer.len() - 3;
+            last_transfer[parity_index] = !last_transfer[parity_index];
+        }
+
         fn add_idle_cycles(&mut self, len: usize) {
             let last_transfer = self.transfer_responses.last_mut().unwrap();
 
@@ -1683,6 +1917,10 @@ mod test {
         fn probe_statistics(&mut self) -> &mut ProbeStatistics {
             &mut self.probe_statistics
         }
+
+        fn health_log(&self) -> &HealthLog {
+            &self.health_log
+        }
     }
 
     /// This is just a blanket impl that will crash if used (only relevant in tests,
@@ -1816,6 +2054,10 @@ mod test {
         let result = mock.raw_read_register(PortType::AccessPort, 4).unwrap();
 
         assert_eq!(result, read_value);
+
+        let entries = mock.health_log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event, HealthEvent::RetrySucceeded);
     }
 
     #[test]
@@ -1982,6 +2224,87 @@ mod test {
             .expect("Failed to write register");
     }
 
+    #[test]
+    fn read_block_is_replayed_after_parity_error() {
+        let mut mock = MockJaylink::new();
+
+        mock.add_read_response(DapAcknowledge::Ok, 1);
+        mock.add_corrupted_read_response(2);
+        mock.add_read_response(DapAcknowledge::Ok, 3);
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        // After the line reset, the sticky error flags are cleared.
+        mock.add_transfer();
+        mock.add_write_response(
+            DapAcknowledge::Ok,
+            mock.swd_settings.num_idle_cycles_between_writes,
+        );
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        // The remaining reads are replayed.
+        mock.add_transfer();
+        mock.add_read_response(DapAcknowledge::Ok, 2);
+        mock.add_read_response(DapAcknowledge::Ok, 3);
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        let mut values = [0; 3];
+
+        mock.raw_read_block(PortType::DebugPort, 4, &mut values)
+            .expect("Failed to read block");
+
+        assert_eq!(values, [1, 2, 3]);
+
+        let entries = mock.health_log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event, HealthEvent::Resynchronized);
+    }
+
+    #[test]
+    fn poll_register_returns_the_first_match() {
+        let mut mock = MockJaylink::new();
+
+        for index in 0..POLL_BURST_LEN as u32 {
+            let value = if index < 5 { index } else { 1 << 17 | index };
+            mock.add_read_response(DapAcknowledge::Ok, value);
+        }
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        let value = mock
+            .raw_poll_register(
+                PortType::DebugPort,
+                4,
+                1 << 17,
+                1 << 17,
+                Duration::from_secs(1),
+            )
+            .expect("Failed to poll register");
+
+        assert_eq!(value, Some(1 << 17 | 5));
+    }
+
+    #[test]
+    fn memory_write_block_is_not_replayed() {
+        let mut mock = MockJaylink::new();
+        let idle_cycles = mock.swd_settings.num_idle_cycles_between_writes;
+
+        mock.add_write_response(DapAcknowledge::Ok, idle_cycles);
+        mock.add_write_response(DapAcknowledge::NoAck, idle_cycles);
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_before_write_verify);
+        mock.add_read_response(DapAcknowledge::Ok, 0);
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        // The sticky error flags are cleared, but the writes to DRW are not repeated.
+        mock.add_transfer();
+        mock.add_write_response(DapAcknowledge::Ok, idle_cycles);
+        mock.add_idle_cycles(mock.swd_settings.idle_cycles_after_transfer);
+
+        mock.raw_write_block(PortType::AccessPort, 0x0C, &[1, 2])
+            .expect_err("A write to DRW must not be replayed");
+
+        assert_eq!(mock.performed_transfer_count, mock.expected_transfer_count);
+        assert_eq!(mock.health_log.entries().len(), 1);
+    }
+
     /// Test the correct handling of several transfers, with
     /// the appropriate extra reads added as necessary.
     mod transfer_handling {
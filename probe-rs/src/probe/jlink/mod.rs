@@ -10,15 +10,18 @@ use crate::architecture::arm::RawDapAccess;
 use crate::{
     architecture::{
         arm::{
-            communication_interface::DapProbe, communication_interface::UninitializedArmProbe,
-            swo::SwoConfig, ArmCommunicationInterface, SwoAccess,
+            communication_interface::DapProbe,
+            communication_interface::UninitializedArmProbe,
+            swo::{SwoConfig, SwoStatus},
+            ArmCommunicationInterface, SwoAccess,
         },
         riscv::communication_interface::RiscvCommunicationInterface,
     },
     probe::{
-        DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeType, JTAGAccess, WireProtocol,
+        plugin::ProbeCapabilities, DebugProbe, DebugProbeError, DebugProbeInfo, DebugProbeType,
+        JTAGAccess, WireProtocol,
     },
-    DebugProbeSelector, Error as ProbeRsError,
+    DebugProbeSelector, Error as ProbeRsError, HealthLog,
 };
 
 use self::arm::{ProbeStatistics, SwdSettings};
@@ -29,8 +32,13 @@ const SWO_BUFFER_SIZE: u16 = 128;
 
 #[derive(Debug)]
 pub(crate) struct JLink {
+    /// The connection to the probe. `jaylink` only exposes the JTAG and SWD bit I/O of the
+    /// probe, not its memory read and write commands, so memory is always accessed with DAP
+    /// transfers built here.
     handle: JayLink,
     swo_config: Option<SwoConfig>,
+    /// An overrun reported with SWO data, which was not returned by `swo_status` yet.
+    swo_overrun: bool,
 
     /// Idle cycles necessary between consecutive
     /// accesses to the DMI register
@@ -45,15 +53,55 @@ pub(crate) struct JLink {
     /// Protocols supported by the connected J-Link probe.
     supported_protocols: Vec<WireProtocol>,
 
+    /// Capabilities of the connected J-Link probe, read when it is opened.
+    capabilities: ProbeCapabilities,
+    firmware_version: Option<String>,
+
     current_ir_reg: u32,
 
     speed_khz: u32,
 
     probe_statistics: ProbeStatistics,
     swd_settings: SwdSettings,
+    health_log: HealthLog,
+}
+
+/// Returns the capabilities of a J-Link which supports `protocols`, reports SWO capture as
+/// supported if `swo` is set, and runs at `max_speed_khz` at most, if it is known.
+fn probe_capabilities(
+    protocols: &[WireProtocol],
+    swo: bool,
+    max_speed_khz: Option<u32>,
+) -> ProbeCapabilities {
+    let mut capabilities = ProbeCapabilities::new()
+        .reset_control()
+        .batched_transfers()
+        .poll_offload();
+
+    capabilities.swd = protocols.contains(&WireProtocol::Swd);
+    capabilities.jtag = protocols.contains(&WireProtocol::Jtag);
+    capabilities.swo = swo;
+    capabilities.max_speed_khz = max_speed_khz;
+    // Bytes are transferred by the AP.
+    capabilities.min_transfer_size = Some(1);
+
+    capabilities
 }
 
 impl JLink {
+    /// Read the capabilities and the firmware version of the probe.
+    fn read_capabilities(&mut self) {
+        let max_speed_khz = self
+            .handle
+            .read_speeds()
+            .ok()
+            .map(|speeds| speeds.max_speed_hz() / 1000);
+        let swo = self.handle.capabilities().contains(Capability::Swo);
+
+        self.capabilities = probe_capabilities(&self.supported_protocols, swo, max_speed_khz);
+        self.firmware_version = self.handle.read_firmware_version().ok();
+    }
+
     fn idle_cycles(&self) -> u8 {
         self.jtag_idle_cycles
     }
@@ -387,10 +435,13 @@ impl DebugProbe for JLink {
                 vec![WireProtocol::Jtag]
             };
 
-        Ok(Box::new(JLink {
+        let mut jlink = JLink {
             handle: jlink_handle,
             swo_config: None,
+            swo_overrun: false,
             supported_protocols,
+            capabilities: ProbeCapabilities::new(),
+            firmware_version: None,
             jtag_idle_cycles: 0,
             ir_len: 0,
             protocol: None,
@@ -398,7 +449,12 @@ impl DebugProbe for JLink {
             speed_khz: 0,
             swd_settings: SwdSettings::default(),
             probe_statistics: ProbeStatistics::default(),
-        }))
+            health_log: HealthLog::default(),
+        };
+
+        jlink.read_capabilities();
+
+        Ok(Box::new(jlink))
     }
 
     fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
@@ -419,6 +475,10 @@ impl DebugProbe for JLink {
         self.protocol
     }
 
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        self.supported_protocols.clone()
+    }
+
     fn get_name(&self) -> &'static str {
         "J-Link"
     }
@@ -617,6 +677,22 @@ impl DebugProbe for JLink {
         // Convert the integer millivolts value from self.handle to volts as an f32.
         Ok(Some((self.handle.read_target_voltage()? as f32) / 1000f32))
     }
+
+    fn set_health_log(&mut self, health_log: HealthLog) {
+        self.health_log = health_log;
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        self.capabilities
+    }
+
+    fn firmware_version(&self) -> Option<String> {
+        self.firmware_version.clone()
+    }
+
+    // There is no table of firmware versions, like for the other probes: a J-Link reports
+    // the commands its firmware implements as capabilities, which are checked instead, and
+    // its firmware version is a build date, e.g. `J-Link V11 compiled Mar 1 2023 10:00:00`.
 }
 
 impl JTAGAccess for JLink {
@@ -701,6 +777,15 @@ impl SwoAccess for JLink {
         Some(SWO_BUFFER_SIZE.into())
     }
 
+    fn swo_status(&mut self) -> Result<SwoStatus, ProbeRsError> {
+        // The J-Link only reports overruns with the data, not the fill level of its buffer.
+        Ok(SwoStatus {
+            buffer_size: self.swo_buffer_size(),
+            overrun: std::mem::take(&mut self.swo_overrun),
+            ..Default::default()
+        })
+    }
+
     fn read_swo_timeout(&mut self, timeout: std::time::Duration) -> Result<Vec<u8>, ProbeRsError> {
         let end = std::time::Instant::now() + timeout;
         let mut buf = vec![0; SWO_BUFFER_SIZE.into()];
@@ -714,6 +799,7 @@ impl SwoAccess for JLink {
             let data = self.handle.swo_read(&mut buf).map_err(|e| {
                 ProbeRsError::Probe(DebugProbeError::ArchitectureSpecific(Box::new(e)))
             })?;
+            self.swo_overrun |= data.did_overrun();
             bytes.extend(data.as_ref());
             let now = std::time::Instant::now();
             if now + poll_interval < end {
@@ -801,3 +887,21 @@ impl TryFrom<jaylink::Interface> for WireProtocol {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capabilities_follow_the_probe() {
+        let capabilities = probe_capabilities(&[WireProtocol::Jtag], false, Some(15_000));
+
+        assert!(!capabilities.swd);
+        assert!(capabilities.jtag);
+        assert!(!capabilities.swo);
+        assert!(capabilities.reset_control);
+        assert!(capabilities.poll_offload);
+        assert_eq!(capabilities.max_speed_khz, Some(15_000));
+        assert_eq!(capabilities.protocols(), [WireProtocol::Jtag]);
+    }
+}
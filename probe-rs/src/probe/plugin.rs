@@ -0,0 +1,338 @@
+//! Support for out-of-tree probe drivers.
+//!
+//! A driver for a new kind of debug probe does not have to live in probe-rs itself. It
+//! implements [`ProbeDriver`] and is registered with [`register_driver`]. Afterwards,
+//! the probes it finds are returned by [`Probe::list_all`](crate::Probe::list_all),
+//! and can be opened with [`Probe::open`](crate::Probe::open) like any built-in probe.
+//!
+//! The probe itself implements [`DebugProbe`]. Depending on the supported protocols, it
+//! additionally implements:
+//!
+//! - [`RawDapAccess`] and [`DapProbe`] for ARM targets. [`DebugProbe::try_get_arm_interface`]
+//!   then returns an [`ArmCommunicationInterface`] created with [`ArmCommunicationInterface::new`].
+//! - [`JTAGAccess`] for RISC-V targets. [`DebugProbe::try_get_riscv_interface`] then returns
+//!   a [`RiscvCommunicationInterface`].
+//! - [`SwoAccess`] to capture SWO data.
+//!
+//! A CMSIS-DAP probe which was opened by the user, e.g. through a permission broker, doesn't
+//! need a driver. Its connection implements [`ProbeTransport`] instead, and is passed to
+//! [`Probe::from_custom_transport`](crate::Probe::from_custom_transport).
+//!
+//! The built-in CMSIS-DAP driver is implemented using only the items in this module, and
+//! can serve as a reference.
+//!
+//! # Stability
+//!
+//! The items re-exported here follow semantic versioning: a change which requires
+//! existing drivers to be modified is only made in a release which is semver-incompatible
+//! with the previous one, and is listed in the changelog. New provided methods with a
+//! default implementation can be added to the traits in any release.
+
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+pub use crate::architecture::arm::{
+    swo::poll_interval_from_buf_size, ArmCommunicationInterface, DapError, DapProbe, DpAddress,
+    Pins, PortType, RawDapAccess, Register, SwdSequence, SwoAccess, SwoConfig, SwoMode, SwoStatus,
+    UninitializedArmProbe,
+};
+pub use crate::architecture::riscv::communication_interface::RiscvCommunicationInterface;
+pub use crate::probe::transport::{ProbeTransport, TransportKind};
+pub use crate::probe::{
+    BatchCommand, BatchExecutionError, CommandResult, DebugProbe, DebugProbeError, DebugProbeInfo,
+    DebugProbeSelector, DebugProbeType, JTAGAccess, JtagWriteCommand, ProbeCreationError,
+    VendorCommand, VendorCommandIndex, VendorCommandOrdering, WireProtocol,
+};
+
+/// The protocols and features supported by a probe, or by the probes of a [`ProbeDriver`].
+///
+/// The capabilities of an opened probe are returned by [`Probe::capabilities`](crate::Probe::capabilities).
+/// Limits which are `None` are unknown, and not checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[non_exhaustive]
+pub struct ProbeCapabilities {
+    /// The probes support the SWD protocol.
+    pub swd: bool,
+    /// The probes support the JTAG protocol.
+    pub jtag: bool,
+    /// The probes can capture SWO data.
+    pub swo: bool,
+    /// The probes can set the state of the debug pins directly.
+    pub pin_control: bool,
+    /// The probes can assert and deassert the reset line of the target.
+    pub reset_control: bool,
+    /// The probes can execute a sequence of commands atomically.
+    pub atomic_commands: bool,
+    /// The probes queue register transfers, and execute them in batches.
+    pub batched_transfers: bool,
+    /// The probes can poll a memory-mapped register of the target on their own, and notify
+    /// the host once it has an expected value.
+    ///
+    /// The halt of a core is then detected without a round trip to the host for every read
+    /// of its status register.
+    pub poll_offload: bool,
+    /// How the probes order [`VendorCommand`]s with their other commands, if they execute
+    /// them at all.
+    pub vendor_commands: VendorCommandOrdering,
+    /// The highest protocol speed in kHz.
+    pub max_speed_khz: Option<u32>,
+    /// The highest SWO baud rate.
+    pub swo_max_baud: Option<u32>,
+    /// The size in bytes of the smallest memory access the probes perform.
+    ///
+    /// For probes which can't access single bytes, byte accesses are assembled from word
+    /// accesses.
+    pub min_transfer_size: Option<usize>,
+    /// The largest number of bytes the probes transfer with a single command.
+    pub max_transfer_size: Option<usize>,
+}
+
+impl ProbeCapabilities {
+    /// Create a new set of capabilities, with no protocol or feature supported.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark the SWD protocol as supported.
+    #[must_use]
+    pub fn swd(self) -> Self {
+        Self { swd: true, ..self }
+    }
+
+    /// Mark the JTAG protocol as supported.
+    #[must_use]
+    pub fn jtag(self) -> Self {
+        Self { jtag: true, ..self }
+    }
+
+    /// Mark SWO capture as supported.
+    #[must_use]
+    pub fn swo(self) -> Self {
+        Self { swo: true, ..self }
+    }
+
+    /// Mark direct pin control as supported.
+    #[must_use]
+    pub fn pin_control(self) -> Self {
+        Self {
+            pin_control: true,
+            ..self
+        }
+    }
+
+    /// Mark control of the reset line as supported.
+    #[must_use]
+    pub fn reset_control(self) -> Self {
+        Self {
+            reset_control: true,
+            ..self
+        }
+    }
+
+    /// Mark atomic command sequences as supported.
+    #[must_use]
+    pub fn atomic_commands(self) -> Self {
+        Self {
+            atomic_commands: true,
+            ..self
+        }
+    }
+
+    /// Mark batched register transfers as supported.
+    #[must_use]
+    pub fn batched_transfers(self) -> Self {
+        Self {
+            batched_transfers: true,
+            ..self
+        }
+    }
+
+    /// Mark the polling of target registers by the probe as supported.
+    #[must_use]
+    pub fn poll_offload(self) -> Self {
+        Self {
+            poll_offload: true,
+            ..self
+        }
+    }
+
+    /// Set how vendor commands are ordered with the other commands.
+    #[must_use]
+    pub fn vendor_commands(self, ordering: VendorCommandOrdering) -> Self {
+        Self {
+            vendor_commands: ordering,
+            ..self
+        }
+    }
+
+    /// Set the highest protocol speed in kHz.
+    #[must_use]
+    pub fn max_speed_khz(self, speed_khz: u32) -> Self {
+        Self {
+            max_speed_khz: Some(speed_khz),
+            ..self
+        }
+    }
+
+    /// Set the highest SWO baud rate.
+    #[must_use]
+    pub fn swo_max_baud(self, baud: u32) -> Self {
+        Self {
+            swo_max_baud: Some(baud),
+            ..self
+        }
+    }
+
+    /// Set the sizes in bytes of the smallest and the largest memory transfer.
+    #[must_use]
+    pub fn transfer_sizes(self, min: usize, max: usize) -> Self {
+        Self {
+            min_transfer_size: Some(min),
+            max_transfer_size: Some(max),
+            ..self
+        }
+    }
+
+    /// Returns the supported protocols.
+    pub fn protocols(&self) -> Vec<WireProtocol> {
+        let mut protocols = Vec::new();
+
+        if self.swd {
+            protocols.push(WireProtocol::Swd);
+        }
+        if self.jtag {
+            protocols.push(WireProtocol::Jtag);
+        }
+
+        protocols
+    }
+
+    /// Returns true if byte accesses have to be assembled from word accesses.
+    pub fn needs_word_transfers(&self) -> bool {
+        self.min_transfer_size.map_or(false, |size| size > 1)
+    }
+}
+
+/// A driver for a kind of debug probe.
+pub trait ProbeDriver: Send + Sync {
+    /// The name of the driver, e.g. `CMSIS-DAP`.
+    fn name(&self) -> &str;
+
+    /// The protocols and features supported by the probes of this driver.
+    fn capabilities(&self) -> ProbeCapabilities;
+
+    /// Returns all probes of this driver which are currently connected.
+    fn list_probes(&self) -> Vec<DebugProbeInfo>;
+
+    /// Open the probe matching `selector`.
+    ///
+    /// If this driver has no matching probe, [`ProbeCreationError::NotFound`] has to be
+    /// returned, so that the next driver is tried.
+    fn open(&self, selector: &DebugProbeSelector) -> Result<Box<dyn DebugProbe>, DebugProbeError>;
+}
+
+static DRIVERS: Lazy<RwLock<Vec<Box<dyn ProbeDriver>>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Register an out-of-tree probe driver.
+///
+/// Registered drivers are queried in the order of registration. When a probe is opened, they
+/// are tried before the built-in drivers, so that a driver can also take over devices which
+/// would otherwise be handled by a built-in driver.
+pub fn register_driver(driver: Box<dyn ProbeDriver>) {
+    log::debug!("Registering probe driver {}", driver.name());
+
+    DRIVERS.write().unwrap().push(driver);
+}
+
+/// Returns the probes found by all registered drivers.
+pub(crate) fn list_registered_probes() -> Vec<DebugProbeInfo> {
+    DRIVERS
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|driver| driver.list_probes())
+        .collect()
+}
+
+/// Open the probe matching `selector` with the first registered driver which finds it.
+pub(crate) fn open_registered(
+    selector: &DebugProbeSelector,
+) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+    for driver in DRIVERS.read().unwrap().iter() {
+        match driver.open(selector) {
+            Err(DebugProbeError::ProbeCouldNotBeCreated(ProbeCreationError::NotFound)) => {}
+            result => return result,
+        }
+    }
+
+    Err(DebugProbeError::ProbeCouldNotBeCreated(
+        ProbeCreationError::NotFound,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::FakeProbe;
+
+    struct FakeDriver;
+
+    impl ProbeDriver for FakeDriver {
+        fn name(&self) -> &str {
+            "Fake"
+        }
+
+        fn capabilities(&self) -> ProbeCapabilities {
+            ProbeCapabilities::new().swd()
+        }
+
+        fn list_probes(&self) -> Vec<DebugProbeInfo> {
+            vec![DebugProbeInfo::new(
+                "Fake probe",
+                0xf00d,
+                0xcafe,
+                None,
+                DebugProbeType::Plugin("Fake".to_owned()),
+                None,
+            )]
+        }
+
+        fn open(
+            &self,
+            selector: &DebugProbeSelector,
+        ) -> Result<Box<dyn DebugProbe>, DebugProbeError> {
+            if (selector.vendor_id, selector.product_id) != (0xf00d, 0xcafe) {
+                return Err(DebugProbeError::ProbeCouldNotBeCreated(
+                    ProbeCreationError::NotFound,
+                ));
+            }
+
+            Ok(Box::new(FakeProbe::new()))
+        }
+    }
+
+    #[test]
+    fn registered_driver_is_used() {
+        register_driver(Box::new(FakeDriver));
+
+        let info = list_registered_probes()
+            .into_iter()
+            .find(|info| info.probe_type == DebugProbeType::Plugin("Fake".to_owned()))
+            .unwrap();
+
+        let probe = open_registered(&info.into()).unwrap();
+        assert_eq!(probe.get_name(), "Mock probe for testing");
+
+        assert!(matches!(
+            open_registered(&DebugProbeSelector {
+                vendor_id: 0x1234,
+                product_id: 0x5678,
+                serial_number: None,
+            }),
+            Err(DebugProbeError::ProbeCouldNotBeCreated(
+                ProbeCreationError::NotFound
+            ))
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+//! The firmware versions of ST-Links which limit their features.
+
+use crate::probe::firmware::{
+    self, FirmwareAdjustment, FirmwareFeature, FirmwareLimitation, FirmwareRule,
+};
+
+/// A firmware version of an ST-Link, as its hardware version and its JTAG version, e.g.
+/// `(2, 37)` for `V2J37`.
+pub(super) type StLinkFirmware = (u8, u8);
+
+/// The versions follow the feature flags OpenOCD sets for the firmware versions in
+/// `stlink_usb_version()` of `src/jtag/drivers/stlink_usb.c`.
+const RULES: &[FirmwareRule<StLinkFirmware>] = &[
+    // `STLINK_F_HAS_AP_INIT`, which is set from V2J28 on.
+    FirmwareRule {
+        versions: (2, 0)..(2, 28),
+        feature: FirmwareFeature::MultipleAccessPorts,
+        limitation: FirmwareLimitation::Unavailable,
+        reason: "the firmware has no commands to open other access ports than the first one",
+    },
+    // `STLINK_F_HAS_RW8_512BYTES`, which is set from V3J6 on. Without it, 8-bit transfers
+    // are limited to `STLINK_MAX_RW8`, 64 bytes.
+    FirmwareRule {
+        versions: (3, 0)..(3, 6),
+        feature: FirmwareFeature::ByteTransfers,
+        limitation: FirmwareLimitation::WorkedAround,
+        reason: "8-bit transfers of up to 512 bytes need V3J6, so writes are split into 64 bytes",
+    },
+];
+
+/// Returns the notation of ST for `version`, e.g. `V2J37`.
+pub(super) fn name(version: &StLinkFirmware) -> String {
+    format!("V{}J{}", version.0, version.1)
+}
+
+/// Returns the adjustments of the features of an ST-Link with the firmware `version`.
+pub(super) fn adjustments(version: StLinkFirmware) -> Vec<FirmwareAdjustment> {
+    firmware::adjustments("ST-Link", RULES, &version, name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn old_v2_firmware_only_accesses_the_first_access_port() {
+        let adjustments = adjustments((2, 26));
+
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(
+            adjustments[0].error().to_string(),
+            "Access to multiple access ports requires ST-Link firmware V2J28 or later; \
+             detected V2J26"
+        );
+
+        assert!(super::adjustments((2, 28)).is_empty());
+    }
+
+    #[test]
+    fn old_v3_firmware_writes_fewer_bytes() {
+        let adjustments = adjustments((3, 5));
+
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].feature, FirmwareFeature::ByteTransfers);
+        assert_eq!(adjustments[0].limitation, FirmwareLimitation::WorkedAround);
+        assert_eq!(adjustments[0].minimum, "V3J6");
+
+        assert!(super::adjustments((3, 6)).is_empty());
+    }
+}
@@ -1,9 +1,13 @@
 pub mod constants;
+mod firmware;
 pub mod tools;
 mod usb_interface;
 
 use self::usb_interface::{StLinkUsb, StLinkUsbDevice};
-use super::{DebugProbe, DebugProbeError, ProbeCreationError, WireProtocol};
+use super::{
+    plugin::ProbeCapabilities, DebugProbe, DebugProbeError, FirmwareAdjustment, FirmwareFeature,
+    FirmwareLimitation, ProbeCreationError, WireProtocol,
+};
 use crate::memory::valid_32_address;
 use crate::{
     architecture::arm::{
@@ -17,9 +21,8 @@ use crate::{
         ApAddress, ApInformation, ArmChipInfo, DapAccess, DpAddress, Pins, SwoAccess, SwoConfig,
         SwoMode,
     },
-    DebugProbeSelector, Error as ProbeRsError, Memory, Probe,
+    DebugProbeSelector, Error as ProbeRsError, HealthEvent, HealthLog, Memory, Probe,
 };
-use anyhow::anyhow;
 use constants::{commands, JTagFrequencyToDivider, Mode, Status, SwdFrequencyToDelayCount};
 use scroll::{Pread, Pwrite, BE, LE};
 use std::{cmp::Ordering, convert::TryInto, sync::Arc, time::Duration};
@@ -38,6 +41,12 @@ const STLINK_MAX_WRITE_LEN: usize = 0xFFFC;
 
 const DP_PORT: u16 = 0xFFFF;
 
+/// The highest SWO baud rate of an ST-Link V2.
+const STLINK_V2_MAX_SWO_BAUD: u32 = 2_000_000;
+
+/// The highest SWO baud rate of an ST-Link V3.
+const STLINK_V3_MAX_SWO_BAUD: u32 = 24_000_000;
+
 #[derive(Debug)]
 pub struct StLink<D: StLinkUsb> {
     device: D,
@@ -47,10 +56,15 @@ pub struct StLink<D: StLinkUsb> {
     protocol: WireProtocol,
     swd_speed_khz: u32,
     jtag_speed_khz: u32,
+    /// The highest SWD speed, as reported by an ST-Link V3.
+    max_swd_speed_khz: Option<u32>,
     swo_enabled: bool,
 
     /// List of opened APs
     opened_aps: Vec<u8>,
+
+    /// Log of errors the probe recovered from.
+    health_log: HealthLog,
 }
 
 impl DebugProbe for StLink<StLinkUsbDevice> {
@@ -66,9 +80,11 @@ impl DebugProbe for StLink<StLinkUsbDevice> {
             protocol: WireProtocol::Swd,
             swd_speed_khz: 1_800,
             jtag_speed_khz: 1_120,
+            max_swd_speed_khz: None,
             swo_enabled: false,
 
             opened_aps: vec![],
+            health_log: HealthLog::default(),
         };
 
         stlink.init()?;
@@ -280,6 +296,18 @@ impl DebugProbe for StLink<StLinkUsbDevice> {
         Ok(Box::new(UninitializedStLink { probe: self }))
     }
 
+    fn capabilities(&self) -> ProbeCapabilities {
+        self.probe_capabilities()
+    }
+
+    fn firmware_version(&self) -> Option<String> {
+        Some(self.firmware_version_name())
+    }
+
+    fn firmware_adjustments(&self) -> Vec<FirmwareAdjustment> {
+        self.probe_firmware_adjustments()
+    }
+
     fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
         let mut buf = [0; 8];
         self.device
@@ -296,6 +324,9 @@ impl DebugProbe for StLink<StLinkUsbDevice> {
                 }
             })
     }
+    fn set_health_log(&mut self, health_log: HealthLog) {
+        self.health_log = health_log;
+    }
 }
 
 impl<D: StLinkUsb> Drop for StLink<D> {
@@ -356,8 +387,68 @@ impl<D: StLinkUsb> StLink<D> {
     /// Version 2 of the firmware (V3J2M1) has problems switching communication protocols.
     const MIN_JTAG_VERSION_V3: u8 = 3;
 
-    /// Firmware version that adds multiple AP support.
-    const MIN_JTAG_VERSION_MULTI_AP: u8 = 28;
+    /// Returns the capabilities of the probe, which depend on its hardware version.
+    fn probe_capabilities(&self) -> ProbeCapabilities {
+        let (max_speed_khz, swo_max_baud) = if self.hw_version < 3 {
+            (
+                Some(SwdFrequencyToDelayCount::Hz4600000.to_khz()),
+                STLINK_V2_MAX_SWO_BAUD,
+            )
+        } else {
+            (self.max_swd_speed_khz, STLINK_V3_MAX_SWO_BAUD)
+        };
+
+        let mut capabilities = ProbeCapabilities::new()
+            .swd()
+            .jtag()
+            .swo()
+            .reset_control()
+            .swo_max_baud(swo_max_baud)
+            .transfer_sizes(1, STLINK_MAX_WRITE_LEN);
+        capabilities.max_speed_khz = max_speed_khz;
+        crate::probe::firmware::apply(&mut capabilities, &self.probe_firmware_adjustments());
+
+        capabilities
+    }
+
+    /// Returns the firmware version in the notation of ST, e.g. `V2J37`.
+    fn firmware_version_name(&self) -> String {
+        firmware::name(&(self.hw_version, self.jtag_version))
+    }
+
+    /// Returns the adjustments of the features to the firmware version of the probe.
+    fn probe_firmware_adjustments(&self) -> Vec<FirmwareAdjustment> {
+        firmware::adjustments((self.hw_version, self.jtag_version))
+    }
+
+    /// Returns the adjustment which limits `feature` with `limitation` on the firmware of
+    /// the probe, if there is one.
+    fn firmware_limitation(
+        &self,
+        feature: FirmwareFeature,
+        limitation: FirmwareLimitation,
+    ) -> Option<FirmwareAdjustment> {
+        crate::probe::firmware::find(&self.probe_firmware_adjustments(), feature, limitation)
+            .cloned()
+    }
+
+    /// Returns the largest number of bytes which are written with a single 8-bit transfer.
+    ///
+    /// Old ST-Link V3 firmware is limited to the 64 bytes of the ST-Link V2.
+    fn max_byte_write_len(&self) -> usize {
+        if self.hw_version < 3
+            || self
+                .firmware_limitation(
+                    FirmwareFeature::ByteTransfers,
+                    FirmwareLimitation::WorkedAround,
+                )
+                .is_some()
+        {
+            64
+        } else {
+            512
+        }
+    }
 
     /// Get the current mode of the ST-Link
     fn get_current_mode(&mut self) -> Result<Mode, DebugProbeError> {
@@ -481,8 +572,9 @@ impl<D: StLinkUsb> StLink<D> {
         log::debug!("STLink version: {:?}", version);
 
         if self.hw_version == 3 {
-            let (_, current) = self.get_communication_frequencies(WireProtocol::Swd)?;
+            let (available, current) = self.get_communication_frequencies(WireProtocol::Swd)?;
             self.swd_speed_khz = current;
+            self.max_swd_speed_khz = available.into_iter().max();
 
             let (_, current) = self.get_communication_frequencies(WireProtocol::Jtag)?;
             self.jtag_speed_khz = current;
@@ -598,9 +690,12 @@ impl<D: StLinkUsb> StLink<D> {
     fn select_ap(&mut self, ap: u8) -> Result<(), DebugProbeError> {
         // Check if we can use APs other an AP 0.
         // Older versions of the ST-Link software don't support this.
-        if self.hw_version < 3 && self.jtag_version < Self::MIN_JTAG_VERSION_MULTI_AP {
+        if let Some(adjustment) = self.firmware_limitation(
+            FirmwareFeature::MultipleAccessPorts,
+            FirmwareLimitation::Unavailable,
+        ) {
             if ap != 0 {
-                return Err(DebugProbeError::ProbeFirmwareOutdated);
+                return Err(adjustment.error());
             }
         } else if !self.opened_aps.contains(&ap) {
             log::debug!("Opening AP {}", ap);
@@ -613,11 +708,16 @@ impl<D: StLinkUsb> StLink<D> {
 
     /// Open a specific AP, which will be used for all future commands.
     ///
-    /// This is only supported on ST-Link V3, or older ST-Links with
-    /// a JTAG version >= `MIN_JTAG_VERSION_MULTI_AP`.
+    /// This is only supported on ST-Links whose firmware supports multiple APs.
     fn open_ap(&mut self, apsel: u8) -> Result<(), DebugProbeError> {
         // Ensure this command is actually supported
-        if self.hw_version < 3 && self.jtag_version < Self::MIN_JTAG_VERSION_MULTI_AP {
+        if self
+            .firmware_limitation(
+                FirmwareFeature::MultipleAccessPorts,
+                FirmwareLimitation::Unavailable,
+            )
+            .is_some()
+        {
             return Err(DebugProbeError::CommandNotSupportedByProbe("open_ap"));
         }
 
@@ -633,11 +733,16 @@ impl<D: StLinkUsb> StLink<D> {
 
     /// Close a specific AP, which was opened with `open_ap`.
     ///
-    /// This is only supported on ST-Link V3, or older ST-Links with
-    /// a JTAG version >= `MIN_JTAG_VERSION_MULTI_AP`.
+    /// This is only supported on ST-Links whose firmware supports multiple APs.
     fn _close_ap(&mut self, apsel: u8) -> Result<(), DebugProbeError> {
         // Ensure this command is actually supported
-        if self.hw_version < 3 && self.jtag_version < Self::MIN_JTAG_VERSION_MULTI_AP {
+        if self
+            .firmware_limitation(
+                FirmwareFeature::MultipleAccessPorts,
+                FirmwareLimitation::Unavailable,
+            )
+            .is_some()
+        {
             return Err(DebugProbeError::CommandNotSupportedByProbe("close_ap"));
         }
 
@@ -662,7 +767,18 @@ impl<D: StLinkUsb> StLink<D> {
             self.device.write(cmd, write_data, read_data, timeout)?;
 
             match Status::from(read_data[0]) {
-                Status::JtagOk => return Ok(()),
+                Status::JtagOk => {
+                    if attempt > 0 {
+                        self.health_log.record(
+                            HealthEvent::RetrySucceeded,
+                            None,
+                            "ST-Link command",
+                            format!("command {:#04x} after {} retries", cmd[0], attempt),
+                        );
+                    }
+
+                    return Ok(());
+                }
                 Status::SwdDpWait => {
                     log::warn!("send_jtag_command {} got SwdDpWait, retrying", cmd[0])
                 }
@@ -1253,7 +1369,7 @@ impl<'probe> ArmProbeInterface for StlinkArmDebug {
 
         match self.ap_information.get(addr.ap as usize) {
             Some(res) => Ok(res),
-            None => Err(anyhow!("AP {:#x?} does not exist", addr).into()),
+            None => Err(ProbeRsError::AccessPortNotFound(addr)),
         }
     }
 
@@ -1305,6 +1421,10 @@ impl<'probe> ArmProbeInterface for StlinkArmDebug {
         Ok(self.ap_information.len())
     }
 
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(self.probe.protocol)
+    }
+
     fn close(self: Box<Self>) -> Probe {
         Probe::from_attached_probe(self.probe)
     }
@@ -1493,11 +1613,7 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
         // The underlying STLink command is limited to a single USB frame at a time
         // so we must manually chunk it into multiple command if it exceeds
         // that size.
-        let chunk_size = if self.probe.probe.hw_version < 3 {
-            64
-        } else {
-            512
-        };
+        let chunk_size = self.probe.probe.max_byte_write_len();
 
         // If we write less than 64 bytes, just write it directly
         if data.len() < chunk_size {
@@ -1590,7 +1706,7 @@ impl ArmProbe for StLinkMemoryInterface<'_> {
 mod test {
 
     use super::{constants::commands, usb_interface::StLinkUsb, StLink};
-    use crate::{DebugProbeError, WireProtocol};
+    use crate::{DebugProbeError, FirmwareFeature, HealthLog, WireProtocol};
 
     use scroll::Pwrite;
 
@@ -1614,8 +1730,10 @@ mod test {
                 jtag_version: 0,
                 swd_speed_khz: 0,
                 jtag_speed_khz: 0,
+                max_swd_speed_khz: None,
                 swo_enabled: false,
                 opened_aps: vec![],
+                health_log: HealthLog::default(),
             }
         }
     }
@@ -1647,6 +1765,13 @@ mod test {
 
                     Ok(())
                 }
+                commands::GET_VERSION_EXT => {
+                    read_data[0] = self.hw_version;
+                    read_data[1] = self.swim_version;
+                    read_data[2] = self.jtag_version;
+
+                    Ok(())
+                }
                 commands::GET_TARGET_VOLTAGE => {
                     read_data.pwrite(self.target_voltage_a0, 0).unwrap();
                     read_data.pwrite(self.target_voltage_a0, 4).unwrap();
@@ -1717,9 +1842,15 @@ mod test {
         // Selecting AP 0 should still work
         probe.select_ap(0).expect("Select AP 0 failed.");
 
-        probe
-            .select_ap(1)
-            .expect_err("Selecting AP other than AP 0 should fail");
+        match probe.select_ap(1) {
+            Err(DebugProbeError::FirmwareTooOld {
+                minimum, detected, ..
+            }) => {
+                assert_eq!(minimum, "V2J28");
+                assert_eq!(detected, "V2J26");
+            }
+            other => panic!("Expected firmware too old error, got {:?}", other),
+        }
     }
 
     #[test]
@@ -1746,4 +1877,52 @@ mod test {
             .select_ap(1)
             .expect("Selecting AP other than AP 0 should work");
     }
+
+    #[test]
+    fn capabilities_depend_on_the_hardware_version() {
+        let usb_mock = MockUsb {
+            hw_version: 2,
+            jtag_version: 37,
+            swim_version: 0,
+            target_voltage_a0: 1.0,
+            _target_voltage_a1: 2.0,
+        };
+
+        let mut probe = usb_mock.build();
+
+        probe.init().expect("Init function failed");
+
+        let capabilities = probe.probe_capabilities();
+        assert!(capabilities.swd && capabilities.jtag && capabilities.swo);
+        assert!(!capabilities.pin_control);
+        assert_eq!(capabilities.max_speed_khz, Some(4600));
+        assert_eq!(capabilities.swo_max_baud, Some(2_000_000));
+        assert_eq!(probe.firmware_version_name(), "V2J37");
+    }
+
+    #[test]
+    fn old_v3_firmware_splits_byte_writes() {
+        let usb_mock = MockUsb {
+            hw_version: 3,
+            jtag_version: 5,
+            swim_version: 0,
+            target_voltage_a0: 1.0,
+            _target_voltage_a1: 2.0,
+        };
+
+        let mut probe = usb_mock.build();
+
+        probe.init().expect("Init function failed");
+
+        assert_eq!(probe.firmware_version_name(), "V3J5");
+        assert_eq!(probe.max_byte_write_len(), 64);
+
+        let adjustments = probe.probe_firmware_adjustments();
+        assert_eq!(adjustments.len(), 1);
+        assert_eq!(adjustments[0].feature, FirmwareFeature::ByteTransfers);
+
+        probe.jtag_version = 7;
+        assert_eq!(probe.max_byte_write_len(), 512);
+        assert!(probe.probe_firmware_adjustments().is_empty());
+    }
 }
@@ -0,0 +1,85 @@
+//! Debug probes connected through a transport which is managed by the user.
+//!
+//! Usually, probe-rs opens the USB device of a probe itself. Some environments require the
+//! device to be opened by someone else, e.g. a permission broker, the Android USB host API,
+//! or a USB forwarder on another machine. The opened connection can be passed to
+//! [`Probe::from_custom_transport`](crate::Probe::from_custom_transport) as a
+//! [`ProbeTransport`], and probe-rs drives the probe protocol over it.
+
+use std::io;
+use std::time::Duration;
+
+/// The packet size which is used if the transport doesn't know the maximum packet size.
+///
+/// This is the most common packet size of full speed USB probes.
+pub(crate) const DEFAULT_PACKET_SIZE: usize = 64;
+
+/// The protocol spoken over a [`ProbeTransport`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TransportKind {
+    /// CMSIS-DAP v1, which exchanges fixed size HID reports.
+    ///
+    /// Every packet written to the transport is a full report, without a report ID.
+    CmsisDapV1,
+    /// CMSIS-DAP v2, which exchanges variable size packets over bulk endpoints.
+    CmsisDapV2,
+}
+
+/// A packet based connection to a debug probe, which was opened by the user.
+///
+/// Every call to [`ProbeTransport::write`] sends one packet to the probe, and every call to
+/// [`ProbeTransport::read`] receives one packet from it, like the transfers to and from the
+/// USB endpoints of the probe.
+///
+/// Information which is usually read from the USB descriptors is provided by the transport
+/// as well. Transports which don't have this information use the defaults, which work for
+/// most probes.
+pub trait ProbeTransport: Send {
+    /// Send the packet `data` to the probe, and return the number of bytes which were sent.
+    fn write(&mut self, data: &[u8], timeout: Duration) -> io::Result<usize>;
+
+    /// Receive a packet from the probe into `buffer`, and return its size.
+    ///
+    /// If no packet arrives within `timeout`, either `Ok(0)` or an error of the kind
+    /// [`io::ErrorKind::TimedOut`] is returned.
+    fn read(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize>;
+
+    /// The maximum size of a packet, e.g. the maximum packet size of the USB endpoints.
+    ///
+    /// The default implementation returns `None`, in which case packets of 64 bytes are used
+    /// until the actual packet size was requested from the probe.
+    fn max_packet_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether the probe streams SWO data over a separate channel, like the SWO endpoint of
+    /// CMSIS-DAP v2 probes.
+    ///
+    /// The default implementation returns `false`, in which case SWO data is polled with
+    /// commands.
+    fn has_swo_stream(&self) -> bool {
+        false
+    }
+
+    /// Receive SWO data from the separate SWO channel into `buffer`, and return its size.
+    ///
+    /// This is only called if [`ProbeTransport::has_swo_stream`] returns `true`. Timeouts
+    /// are reported like for [`ProbeTransport::read`].
+    fn read_swo(&mut self, buffer: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        let _ = (buffer, timeout);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Whether `result` is a timeout of a [`ProbeTransport`] read.
+pub(crate) fn is_timeout(result: &io::Result<usize>) -> bool {
+    match result {
+        Ok(0) => true,
+        Err(error) => matches!(
+            error.kind(),
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+        ),
+        Ok(_) => false,
+    }
+}
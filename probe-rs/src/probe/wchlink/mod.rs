@@ -0,0 +1,449 @@
+//! Driver for the WCH-Link probes in their RISC-V mode.
+//!
+//! The probes don't expose the debug interface itself, but execute operations on the DMI of
+//! the debug module in their firmware. The driver maps the scans of the `dtmcs` and `dmi`
+//! JTAG registers, which the RISC-V stack uses, onto these operations.
+//!
+//! The probe uses the same operations for the two-wire interface of the CH32V2 and CH32V3
+//! families and for the single-wire interface of the CH32V003, so the chip family is only
+//! needed to configure the speed of the interface.
+
+mod protocol;
+
+use crate::{
+    architecture::{
+        arm::{
+            communication_interface::{DapProbe, UninitializedArmProbe},
+            SwoAccess,
+        },
+        riscv::communication_interface::RiscvCommunicationInterface,
+    },
+    DebugProbe, DebugProbeError, DebugProbeSelector, WireProtocol,
+};
+
+use self::protocol::{
+    Command, ProtocolHandler, WchLinkError, CONTROL_ATTACH_CHIP, CONTROL_DETACH_CHIP,
+    CONTROL_PROBE_INFO, RESET_AND_RUN,
+};
+
+use super::{plugin::ProbeCapabilities, JTAGAccess};
+
+pub use protocol::list_wchlink_devices;
+
+/// Address of the `dtmcs` JTAG register.
+const DTMCS_ADDRESS: u32 = 0x10;
+
+/// Address of the `dmi` JTAG register.
+const DMI_ADDRESS: u32 = 0x11;
+
+/// The number of address bits of the DMI operations of the probe.
+const DMI_ABITS: u32 = 7;
+
+/// The `dtmcs` register reported to the RISC-V stack: version 0.13 of the debug
+/// specification, with 7 address bits, and no idle cycles.
+const DTMCS_VALUE: u32 = DMI_ABITS << 4 | 1;
+
+/// The value of the `op` field for an operation which is still in progress.
+const DMI_OP_BUSY: u8 = 3;
+
+/// How often an operation which found the DMI busy is repeated by the driver.
+const DMI_BUSY_RETRIES: usize = 100;
+
+/// How often attaching to the chip is tried, before giving up.
+const ATTACH_RETRIES: usize = 3;
+
+/// The speeds of the debug interface, with their nominal frequency in kHz, fastest first.
+const SPEEDS: [(u32, u8); 3] = [(6000, 0x01), (4000, 0x02), (400, 0x03)];
+
+/// The families of RISC-V chips the probe can attach to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChipFamily {
+    Ch32v103,
+    Ch32v20x,
+    Ch32v30x,
+    /// Attached through the single-wire debug interface.
+    Ch32v003,
+    /// A family which is unknown to the driver, with the code reported by the probe.
+    Other(u8),
+}
+
+impl From<u8> for ChipFamily {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => ChipFamily::Ch32v103,
+            0x05 => ChipFamily::Ch32v20x,
+            0x06 => ChipFamily::Ch32v30x,
+            0x09 => ChipFamily::Ch32v003,
+            other => ChipFamily::Other(other),
+        }
+    }
+}
+
+impl From<ChipFamily> for u8 {
+    fn from(family: ChipFamily) -> Self {
+        match family {
+            ChipFamily::Ch32v103 => 0x01,
+            ChipFamily::Ch32v20x => 0x05,
+            ChipFamily::Ch32v30x => 0x06,
+            ChipFamily::Ch32v003 => 0x09,
+            ChipFamily::Other(code) => code,
+        }
+    }
+}
+
+/// Splits a scan of the `dmi` register into its address, data and operation.
+fn decode_dmi_scan(data: &[u8]) -> (u8, u32, u8) {
+    let value = data
+        .iter()
+        .take(8)
+        .enumerate()
+        .fold(0u64, |acc, (byte_offset, value)| {
+            acc | ((*value as u64) << (8 * byte_offset))
+        });
+
+    (
+        (value >> 34) as u8,
+        (value >> 2) as u32,
+        (value & 0x3) as u8,
+    )
+}
+
+/// Encodes the result of a DMI operation as it is shifted out of the `dmi` register.
+fn encode_dmi_result(address: u8, data: u32, op: u8) -> Vec<u8> {
+    let value = (address as u64) << 34 | (data as u64) << 2 | op as u64;
+
+    value.to_le_bytes()[..((DMI_ABITS + 34 + 7) / 8) as usize].to_vec()
+}
+
+#[derive(Debug)]
+pub(crate) struct WchLink {
+    protocol: ProtocolHandler,
+
+    /// The version of the firmware, as reported by the probe.
+    firmware_version: Option<String>,
+
+    /// The family of the attached chip, if the probe is attached.
+    chip_family: Option<ChipFamily>,
+
+    speed_khz: u32,
+
+    /// The result of the last DMI operation, which is returned by the next scan of the
+    /// `dmi` register, like the JTAG DTM does.
+    dmi_result: (u8, u32, u8),
+
+    idle_cycles: u8,
+}
+
+impl WchLink {
+    /// Executes a DMI operation, and returns its address, data and status.
+    fn dmi_op(&mut self, address: u8, data: u32, op: u8) -> Result<(u8, u32, u8), DebugProbeError> {
+        let mut payload = [0; 6];
+        payload[0] = address;
+        payload[1..5].copy_from_slice(&data.to_be_bytes());
+        payload[5] = op;
+
+        for _ in 0..DMI_BUSY_RETRIES {
+            let response = self.protocol.send(Command::DmiOp, &payload)?;
+
+            let result = match response[..] {
+                [address, d0, d1, d2, d3, status] => {
+                    (address, u32::from_be_bytes([d0, d1, d2, d3]), status)
+                }
+                _ => return Err(WchLinkError::InvalidResponse(Command::DmiOp as u8).into()),
+            };
+
+            // The operations aren't pipelined, so an operation which found the DMI busy
+            // can simply be repeated.
+            if result.2 != DMI_OP_BUSY {
+                return Ok(result);
+            }
+        }
+
+        Ok((address, 0, DMI_OP_BUSY))
+    }
+
+    fn configure_speed(&mut self) -> Result<(), DebugProbeError> {
+        let family = match self.chip_family {
+            Some(family) => family,
+            // The speed is configured when attaching.
+            None => return Ok(()),
+        };
+
+        let code = SPEEDS
+            .iter()
+            .find(|(speed_khz, _)| *speed_khz == self.speed_khz)
+            .map_or(SPEEDS[0].1, |(_, code)| *code);
+
+        let response = self
+            .protocol
+            .send(Command::SetSpeed, &[family.into(), code])?;
+
+        if response.first() != Some(&1) {
+            return Err(DebugProbeError::UnsupportedSpeed(self.speed_khz));
+        }
+
+        Ok(())
+    }
+}
+
+impl JTAGAccess for WchLink {
+    fn set_ir_len(&mut self, _len: u32) {
+        // There is no instruction register, the probe executes the DMI operations itself.
+    }
+
+    fn read_register(&mut self, address: u32, _len: u32) -> Result<Vec<u8>, DebugProbeError> {
+        match address {
+            DTMCS_ADDRESS => Ok(DTMCS_VALUE.to_le_bytes().to_vec()),
+            _ => Err(DebugProbeError::NotImplemented(
+                "Reading JTAG registers other than dtmcs",
+            )),
+        }
+    }
+
+    fn write_register(
+        &mut self,
+        address: u32,
+        data: &[u8],
+        _len: u32,
+    ) -> Result<Vec<u8>, DebugProbeError> {
+        match address {
+            // The only writable field is `dmireset`, and the probe doesn't report sticky errors.
+            DTMCS_ADDRESS => Ok(vec![0; 4]),
+            DMI_ADDRESS => {
+                let (address, value, op) = decode_dmi_scan(data);
+
+                let previous = self.dmi_result;
+
+                // A `nop` only shifts out the result of the previous operation.
+                if op != 0 {
+                    self.dmi_result = self.dmi_op(address, value, op)?;
+                }
+
+                Ok(encode_dmi_result(previous.0, previous.1, previous.2))
+            }
+            _ => Err(DebugProbeError::NotImplemented(
+                "Writing JTAG registers other than dtmcs and dmi",
+            )),
+        }
+    }
+
+    fn set_idle_cycles(&mut self, idle_cycles: u8) {
+        // The probe inserts the necessary delays itself.
+        self.idle_cycles = idle_cycles;
+    }
+
+    fn get_idle_cycles(&self) -> u8 {
+        self.idle_cycles
+    }
+}
+
+impl DebugProbe for WchLink {
+    fn new_from_selector(
+        selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError> {
+        let mut protocol = ProtocolHandler::new_from_selector(selector)?;
+
+        let info = protocol.send(Command::Control, &[CONTROL_PROBE_INFO])?;
+
+        let firmware_version = match info[..] {
+            [major, minor, ..] => Some(format!("{}.{}", major, minor)),
+            _ => None,
+        };
+
+        Ok(Box::new(WchLink {
+            protocol,
+            firmware_version,
+            chip_family: None,
+            speed_khz: SPEEDS[0].0,
+            dmi_result: (0, 0, 0),
+            idle_cycles: 0,
+        }))
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        // The wire protocol of the probe is hidden by the DMI operations, which take the
+        // place of the JTAG DTM.
+        if matches!(protocol, WireProtocol::Jtag) {
+            Ok(())
+        } else {
+            Err(DebugProbeError::UnsupportedProtocol(protocol))
+        }
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        Some(WireProtocol::Jtag)
+    }
+
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        vec![WireProtocol::Jtag]
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        ProbeCapabilities::new()
+            .jtag()
+            .reset_control()
+            .max_speed_khz(SPEEDS[0].0)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "WCH-Link"
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        // Use the fastest speed which doesn't exceed the requested one.
+        let (speed_khz, _) = SPEEDS
+            .iter()
+            .find(|(speed, _)| *speed <= speed_khz)
+            .ok_or(DebugProbeError::UnsupportedSpeed(speed_khz))?;
+
+        self.speed_khz = *speed_khz;
+        self.configure_speed()?;
+
+        Ok(self.speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        log::debug!("Attaching to WCH-Link");
+
+        let mut response: Result<Vec<u8>, DebugProbeError> = Err(WchLinkError::NoChip.into());
+
+        for _ in 0..ATTACH_RETRIES {
+            response = self.protocol.send(Command::Control, &[CONTROL_ATTACH_CHIP]);
+
+            if response.is_ok() {
+                break;
+            }
+        }
+
+        let (family, chip_id) = match response?[..] {
+            [family, id0, id1, id2, id3, ..] => (
+                ChipFamily::from(family),
+                u32::from_be_bytes([id0, id1, id2, id3]),
+            ),
+            _ => return Err(WchLinkError::NoChip.into()),
+        };
+
+        log::info!("Attached to {:?} chip with ID {:#010x}", family, chip_id);
+
+        if family == ChipFamily::Ch32v003 {
+            log::warn!("The program buffer of the CH32V003 only executes compressed instructions, which is not supported yet.");
+        }
+
+        self.chip_family = Some(family);
+        self.configure_speed()
+    }
+
+    fn detach(&mut self) -> Result<(), DebugProbeError> {
+        if self.chip_family.take().is_some() {
+            self.protocol
+                .send(Command::Control, &[CONTROL_DETACH_CHIP])?;
+        }
+
+        Ok(())
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        self.protocol.send(Command::Reset, &[RESET_AND_RUN])?;
+
+        Ok(())
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented("target_reset_assert"))
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        Err(DebugProbeError::NotImplemented("target_reset_deassert"))
+    }
+
+    fn try_get_riscv_interface(
+        self: Box<Self>,
+    ) -> Result<RiscvCommunicationInterface, (Box<dyn DebugProbe>, DebugProbeError)> {
+        // This probe is intended for RISC-V.
+        match RiscvCommunicationInterface::new(self) {
+            Ok(interface) => Ok(interface),
+            Err((probe, err)) => Err((probe.into_probe(), err)),
+        }
+    }
+
+    fn get_swo_interface(&self) -> Option<&dyn SwoAccess> {
+        // This probe cannot debug ARM targets in its RISC-V mode.
+        None
+    }
+
+    fn get_swo_interface_mut(&mut self) -> Option<&mut dyn SwoAccess> {
+        // This probe cannot debug ARM targets in its RISC-V mode.
+        None
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        // This probe cannot debug ARM targets in its RISC-V mode.
+        false
+    }
+
+    fn has_riscv_interface(&self) -> bool {
+        // This probe is intended for RISC-V.
+        true
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        // This is not a DAP capable probe.
+        None
+    }
+
+    fn try_get_arm_interface<'probe>(
+        self: Box<Self>,
+    ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
+    {
+        // This probe cannot debug ARM targets in its RISC-V mode.
+        Err((self, DebugProbeError::InterfaceNotAvailable("SWD/ARM")))
+    }
+
+    fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
+        // We cannot read the voltage on this probe, unfortunately.
+        Ok(None)
+    }
+
+    fn firmware_version(&self) -> Option<String> {
+        self.firmware_version.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dmi_scans_are_split_into_their_fields() {
+        // A write of 0x8000_0001 to `dmcontrol`.
+        let scan: u64 = 0x10 << 34 | 0x8000_0001 << 2 | 2;
+
+        assert_eq!(decode_dmi_scan(&scan.to_le_bytes()), (0x10, 0x8000_0001, 2));
+    }
+
+    #[test]
+    fn dmi_results_fill_the_dmi_register() {
+        let result = encode_dmi_result(0x11, 0x0040_0c82, 0);
+
+        // The register has 41 bits.
+        assert_eq!(result.len(), 6);
+        assert_eq!(decode_dmi_scan(&result), (0x11, 0x0040_0c82, 0));
+    }
+
+    #[test]
+    fn chip_families_round_trip() {
+        for code in [0x01, 0x05, 0x06, 0x09, 0x0d] {
+            assert_eq!(u8::from(ChipFamily::from(code)), code);
+        }
+
+        assert_eq!(ChipFamily::from(0x09), ChipFamily::Ch32v003);
+    }
+}
@@ -0,0 +1,281 @@
+use std::{fmt::Debug, time::Duration};
+
+use rusb::{Context, Device, UsbContext};
+
+use crate::{
+    DebugProbeError, DebugProbeInfo, DebugProbeSelector, DebugProbeType, ProbeCreationError,
+};
+
+const USB_TIMEOUT: Duration = Duration::from_millis(5000);
+
+const USB_VID: u16 = 0x1a86;
+/// The product ID of the probe in its RISC-V mode. In its ARM mode, the probe is a CMSIS-DAP probe.
+const USB_PID: u16 = 0x8010;
+
+const USB_INTERFACE: u8 = 0;
+
+/// The bulk endpoints for commands and their responses.
+const EP_COMMAND_OUT: u8 = 0x01;
+const EP_COMMAND_IN: u8 = 0x81;
+
+/// The first byte of a request.
+const REQUEST_HEADER: u8 = 0x81;
+/// The first byte of a response to a successful request.
+const RESPONSE_HEADER: u8 = 0x82;
+/// The first byte of a response to a failed request.
+const ERROR_HEADER: u8 = 0x81;
+
+/// The largest packet of the command endpoints.
+const PACKET_SIZE: usize = 64;
+
+/// An error reported by the probe, or a response which could not be understood.
+#[derive(thiserror::Error, Debug)]
+pub(crate) enum WchLinkError {
+    /// The probe rejected a command.
+    #[error("The probe rejected command {command:#04x} with reason {reason:#04x}.")]
+    Rejected {
+        /// The rejected command.
+        command: u8,
+        /// The reason given by the probe.
+        reason: u8,
+    },
+    /// The response of the probe is malformed, or belongs to another command.
+    #[error("The probe returned an invalid response to command {0:#04x}.")]
+    InvalidResponse(u8),
+    /// The probe could not attach to a chip.
+    #[error("The probe did not find a chip it supports.")]
+    NoChip,
+}
+
+impl From<WchLinkError> for DebugProbeError {
+    fn from(error: WchLinkError) -> Self {
+        DebugProbeError::ProbeSpecific(Box::new(error))
+    }
+}
+
+/// The commands of the probe, see [`ProtocolHandler::send`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Command {
+    /// An operation on the DMI, with the address, data and operation of the `dmi` JTAG register.
+    DmiOp = 0x08,
+    /// Reset the target.
+    Reset = 0x0b,
+    /// Set the speed of the debug interface.
+    SetSpeed = 0x0c,
+    /// Query the probe, and attach to or detach from the chip.
+    Control = 0x0d,
+}
+
+/// The sub-commands of [`Command::Control`].
+pub(super) const CONTROL_PROBE_INFO: u8 = 0x01;
+pub(super) const CONTROL_ATTACH_CHIP: u8 = 0x02;
+pub(super) const CONTROL_DETACH_CHIP: u8 = 0xff;
+
+/// The sub-command of [`Command::Reset`] which resets the chip, and lets it run afterwards.
+pub(super) const RESET_AND_RUN: u8 = 0x03;
+
+/// Encodes a request for `command` with `payload`.
+fn encode_request(command: Command, payload: &[u8]) -> Vec<u8> {
+    let mut request = Vec::with_capacity(payload.len() + 3);
+
+    request.extend_from_slice(&[REQUEST_HEADER, command as u8, payload.len() as u8]);
+    request.extend_from_slice(payload);
+
+    request
+}
+
+/// Returns the payload of the response to `command`.
+fn decode_response(command: Command, response: &[u8]) -> Result<&[u8], WchLinkError> {
+    let command = command as u8;
+
+    match response {
+        [RESPONSE_HEADER, cmd, len, payload @ ..]
+            if *cmd == command && payload.len() >= *len as usize =>
+        {
+            Ok(&payload[..*len as usize])
+        }
+        [ERROR_HEADER, reason, ..] => Err(WchLinkError::Rejected {
+            command,
+            reason: *reason,
+        }),
+        _ => Err(WchLinkError::InvalidResponse(command)),
+    }
+}
+
+pub(super) struct ProtocolHandler {
+    device_handle: rusb::DeviceHandle<rusb::Context>,
+}
+
+impl Debug for ProtocolHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProtocolHandler").finish()
+    }
+}
+
+impl ProtocolHandler {
+    pub fn new_from_selector(
+        selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Self, ProbeCreationError> {
+        let selector = selector.into();
+
+        let context = Context::new()?;
+
+        let device = context
+            .devices()?
+            .iter()
+            .filter(is_wchlink_device)
+            .find(|device| {
+                let descriptor = match device.device_descriptor() {
+                    Ok(descriptor) => descriptor,
+                    Err(_) => return false,
+                };
+
+                if selector.vendor_id != descriptor.vendor_id()
+                    || selector.product_id != descriptor.product_id()
+                {
+                    return false;
+                }
+
+                // If the VID & PID match, match the serial if one was given.
+                match &selector.serial_number {
+                    Some(serial) => {
+                        read_serial_number(device, &descriptor).ok().as_ref() == Some(serial)
+                    }
+                    None => true,
+                }
+            })
+            .ok_or(ProbeCreationError::NotFound)?;
+
+        let mut device_handle = device.open()?;
+        device_handle.claim_interface(USB_INTERFACE)?;
+
+        log::debug!("Succesfully attached to WCH-Link.");
+
+        Ok(Self { device_handle })
+    }
+
+    /// Sends `command` with `payload`, and returns the payload of the response.
+    pub fn send(&mut self, command: Command, payload: &[u8]) -> Result<Vec<u8>, DebugProbeError> {
+        let request = encode_request(command, payload);
+        log::trace!("WCH-Link request: {:02x?}", request);
+
+        self.device_handle
+            .write_bulk(EP_COMMAND_OUT, &request, USB_TIMEOUT)
+            .map_err(|e| DebugProbeError::Usb(Some(Box::new(e))))?;
+
+        let mut response = [0u8; PACKET_SIZE];
+        let count = self
+            .device_handle
+            .read_bulk(EP_COMMAND_IN, &mut response, USB_TIMEOUT)
+            .map_err(|e| DebugProbeError::Usb(Some(Box::new(e))))?;
+
+        log::trace!("WCH-Link response: {:02x?}", &response[..count]);
+
+        Ok(decode_response(command, &response[..count])?.to_vec())
+    }
+}
+
+/// Try to read the serial number of a USB device.
+fn read_serial_number<T: rusb::UsbContext>(
+    device: &rusb::Device<T>,
+    descriptor: &rusb::DeviceDescriptor,
+) -> Result<String, rusb::Error> {
+    let timeout = Duration::from_millis(100);
+
+    let handle = device.open()?;
+    let language = handle
+        .read_languages(timeout)?
+        .get(0)
+        .cloned()
+        .ok_or(rusb::Error::BadDescriptor)?;
+    handle.read_serial_number_string(language, descriptor, timeout)
+}
+
+fn is_wchlink_device<T: UsbContext>(device: &Device<T>) -> bool {
+    // Check the VID/PID.
+    if let Ok(descriptor) = device.device_descriptor() {
+        descriptor.vendor_id() == USB_VID && descriptor.product_id() == USB_PID
+    } else {
+        false
+    }
+}
+
+pub fn list_wchlink_devices() -> Vec<DebugProbeInfo> {
+    rusb::Context::new()
+        .and_then(|context| context.devices())
+        .map_or(vec![], |devices| {
+            devices
+                .iter()
+                .filter(is_wchlink_device)
+                .filter_map(|device| {
+                    let descriptor = device.device_descriptor().ok()?;
+
+                    let sn_str = match read_serial_number(&device, &descriptor) {
+                        Ok(serial_number) => Some(serial_number),
+                        Err(e) => {
+                            // As for the other probes, the probe is still listed if the
+                            // serial number can't be read, e.g. because of a missing driver.
+                            log::debug!(
+                                "Failed to read serial number of device {:04x}:{:04x} : {}",
+                                descriptor.vendor_id(),
+                                descriptor.product_id(),
+                                e
+                            );
+                            None
+                        }
+                    };
+
+                    Some(DebugProbeInfo::new(
+                        "WCH-Link".to_string(),
+                        descriptor.vendor_id(),
+                        descriptor.product_id(),
+                        sn_str,
+                        DebugProbeType::WchLink,
+                        None,
+                    ))
+                })
+                .collect::<Vec<_>>()
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests_are_framed() {
+        assert_eq!(
+            encode_request(Command::Control, &[CONTROL_ATTACH_CHIP]),
+            [0x81, 0x0d, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn responses_are_checked() {
+        let response = [0x82, 0x0d, 0x02, 0x05, 0x20, 0xaa];
+        assert_eq!(
+            decode_response(Command::Control, &response).unwrap(),
+            &[0x05, 0x20]
+        );
+
+        // The response belongs to another command.
+        assert!(matches!(
+            decode_response(Command::DmiOp, &response),
+            Err(WchLinkError::InvalidResponse(0x08))
+        ));
+
+        // The payload is shorter than its length.
+        assert!(matches!(
+            decode_response(Command::Control, &response[..4]),
+            Err(WchLinkError::InvalidResponse(0x0d))
+        ));
+
+        assert!(matches!(
+            decode_response(Command::Control, &[0x81, 0x55, 0x01, 0x02]),
+            Err(WchLinkError::Rejected {
+                command: 0x0d,
+                reason: 0x55
+            })
+        ));
+    }
+}
@@ -0,0 +1,429 @@
+//! The client side of a shared probe.
+
+use std::io::{BufReader, BufWriter};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::protocol::{
+    read_frame, write_frame, ProbeInfo, Request, Response, MAX_BLOCK_LEN, PROTOCOL_VERSION,
+};
+use super::RemoteError;
+use crate::architecture::arm::communication_interface::{DapProbe, UninitializedArmProbe};
+use crate::architecture::arm::{ArmCommunicationInterface, DpAddress, PortType, RawDapAccess};
+use crate::probe::{DebugProbe, DebugProbeError, DebugProbeSelector, ProbeCreationError};
+use crate::{ProbeCapabilities, VendorCommandOrdering, WireProtocol};
+
+/// A probe which is shared by a [`ProbeServer`](super::ProbeServer), see the
+/// [module](super) docs.
+///
+/// The probe reports the name and the capabilities of the probe of the server. It takes the
+/// lock of the probe when it attaches, and releases it when it detaches or is dropped.
+/// Until then, the speed and the protocol which are set are only applied once the lock
+/// was taken.
+#[derive(Debug)]
+pub struct RemoteProbe {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+    info: ProbeInfo,
+    locked: bool,
+    statistics: RemoteStatistics,
+}
+
+impl RemoteProbe {
+    /// Connect to the server at `address`, with the shared `token`.
+    pub fn connect(address: impl ToSocketAddrs, token: &str) -> Result<Self, DebugProbeError> {
+        let stream = TcpStream::connect(address).map_err(RemoteError::from)?;
+
+        // Most requests are small, and wait for the response of the previous one.
+        stream.set_nodelay(true).map_err(RemoteError::from)?;
+
+        let reader = BufReader::new(stream.try_clone().map_err(RemoteError::from)?);
+        let writer = BufWriter::new(stream);
+
+        let mut probe = Self {
+            reader,
+            writer,
+            info: ProbeInfo {
+                name: String::new(),
+                capabilities: ProbeCapabilities::new(),
+                speed_khz: 0,
+                protocol: None,
+                supported_protocols: Vec::new(),
+                firmware_version: None,
+                has_dap_access: false,
+            },
+            locked: false,
+            statistics: RemoteStatistics::default(),
+        };
+
+        match probe.call(Request::Hello {
+            version: PROTOCOL_VERSION,
+            token: token.to_owned(),
+        })? {
+            Response::Welcome(info) => probe.info = info,
+            _ => return Err(RemoteError::UnexpectedResponse.into()),
+        }
+
+        Ok(probe)
+    }
+
+    /// Returns a handle to the statistics of the requests to the server.
+    ///
+    /// The handle stays connected to the probe after it was used to attach, so that the
+    /// latency of a session can be observed.
+    pub fn statistics(&self) -> RemoteStatistics {
+        self.statistics.clone()
+    }
+
+    /// Wait until the probe is free, and lock it for this client.
+    ///
+    /// This is done when the probe attaches, so it is only needed to lock the probe before.
+    pub fn lock(&mut self) -> Result<(), DebugProbeError> {
+        if self.locked {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        self.send(Request::Lock)?;
+        self.statistics.record_lock_wait(start.elapsed());
+        self.locked = true;
+
+        // Apply the settings which were made while the probe wasn't locked.
+        if let Some(protocol) = self.info.protocol {
+            self.send(Request::SelectProtocol(protocol))?;
+        }
+
+        match self.call(Request::SetSpeed(self.info.speed_khz))? {
+            Response::Speed(speed_khz) => self.info.speed_khz = speed_khz,
+            _ => return Err(RemoteError::UnexpectedResponse.into()),
+        }
+
+        Ok(())
+    }
+
+    /// Release the lock of the probe, so that other clients can use it.
+    ///
+    /// This is done when the probe detaches, or when it is dropped.
+    pub fn unlock(&mut self) -> Result<(), DebugProbeError> {
+        self.locked = false;
+        self.send(Request::Unlock)
+    }
+
+    /// Send `request`, and return the response of the server.
+    fn call(&mut self, request: Request) -> Result<Response, DebugProbeError> {
+        let lock = matches!(request, Request::Lock);
+        let start = Instant::now();
+
+        write_frame(&mut self.writer, &request)?;
+        let response = read_frame(&mut self.reader)?;
+
+        // The time spent waiting for the lock is not part of the latency.
+        if !lock {
+            self.statistics.record_round_trip(start.elapsed());
+        }
+
+        match response {
+            Response::Error(error) => Err(error.into()),
+            response => Ok(response),
+        }
+    }
+
+    /// Send `request`, which the server only acknowledges.
+    fn send(&mut self, request: Request) -> Result<(), DebugProbeError> {
+        match self.call(request)? {
+            Response::Done => Ok(()),
+            _ => Err(RemoteError::UnexpectedResponse.into()),
+        }
+    }
+
+    fn call_value(&mut self, request: Request) -> Result<u32, DebugProbeError> {
+        match self.call(request)? {
+            Response::Value(value) => Ok(value),
+            _ => Err(RemoteError::UnexpectedResponse.into()),
+        }
+    }
+}
+
+impl DebugProbe for RemoteProbe {
+    fn new_from_selector(
+        _selector: impl Into<DebugProbeSelector>,
+    ) -> Result<Box<Self>, DebugProbeError>
+    where
+        Self: Sized,
+    {
+        // Remote probes are opened by their address, not by a selector.
+        Err(ProbeCreationError::NotFound.into())
+    }
+
+    fn get_name(&self) -> &str {
+        &self.info.name
+    }
+
+    fn speed_khz(&self) -> u32 {
+        self.info.speed_khz
+    }
+
+    fn set_speed(&mut self, speed_khz: u32) -> Result<u32, DebugProbeError> {
+        if self.locked {
+            match self.call(Request::SetSpeed(speed_khz))? {
+                Response::Speed(speed_khz) => self.info.speed_khz = speed_khz,
+                _ => return Err(RemoteError::UnexpectedResponse.into()),
+            }
+        } else {
+            self.info.speed_khz = speed_khz;
+        }
+
+        Ok(self.info.speed_khz)
+    }
+
+    fn attach(&mut self) -> Result<(), DebugProbeError> {
+        self.lock()?;
+        self.send(Request::Attach)
+    }
+
+    fn detach(&mut self) -> Result<(), DebugProbeError> {
+        if !self.locked {
+            return Ok(());
+        }
+
+        let result = self.send(Request::Detach);
+        self.unlock()?;
+        result
+    }
+
+    fn target_reset(&mut self) -> Result<(), DebugProbeError> {
+        self.send(Request::TargetReset)
+    }
+
+    fn target_reset_assert(&mut self) -> Result<(), DebugProbeError> {
+        self.send(Request::TargetResetAssert)
+    }
+
+    fn target_reset_deassert(&mut self) -> Result<(), DebugProbeError> {
+        self.send(Request::TargetResetDeassert)
+    }
+
+    fn select_protocol(&mut self, protocol: WireProtocol) -> Result<(), DebugProbeError> {
+        if self.locked {
+            self.send(Request::SelectProtocol(protocol))?;
+        } else if !self.info.supported_protocols.contains(&protocol) {
+            return Err(DebugProbeError::UnsupportedProtocol(protocol));
+        }
+
+        self.info.protocol = Some(protocol);
+
+        Ok(())
+    }
+
+    fn active_protocol(&self) -> Option<WireProtocol> {
+        self.info.protocol
+    }
+
+    fn supported_protocols(&self) -> Vec<WireProtocol> {
+        self.info.supported_protocols.clone()
+    }
+
+    fn has_arm_interface(&self) -> bool {
+        self.info.has_dap_access
+    }
+
+    fn try_get_arm_interface<'probe>(
+        self: Box<Self>,
+    ) -> Result<Box<dyn UninitializedArmProbe + 'probe>, (Box<dyn DebugProbe>, DebugProbeError)>
+    {
+        if self.info.has_dap_access {
+            Ok(Box::new(ArmCommunicationInterface::new(self, false)))
+        } else {
+            Err((
+                DebugProbe::into_probe(self),
+                DebugProbeError::InterfaceNotAvailable("ARM"),
+            ))
+        }
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+
+    fn try_as_dap_probe(&mut self) -> Option<&mut dyn DapProbe> {
+        if self.info.has_dap_access {
+            Some(self)
+        } else {
+            None
+        }
+    }
+
+    fn get_target_voltage(&mut self) -> Result<Option<f32>, DebugProbeError> {
+        match self.call(Request::TargetVoltage)? {
+            Response::Voltage(voltage) => Ok(voltage),
+            _ => Err(RemoteError::UnexpectedResponse.into()),
+        }
+    }
+
+    fn capabilities(&self) -> ProbeCapabilities {
+        // Polls are not forwarded, a poll by the probe of the server would hold it for
+        // the whole wait. Neither are vendor commands.
+        ProbeCapabilities {
+            poll_offload: false,
+            vendor_commands: VendorCommandOrdering::Unsupported,
+            ..self.info.capabilities
+        }
+    }
+
+    fn firmware_version(&self) -> Option<String> {
+        self.info.firmware_version.clone()
+    }
+}
+
+impl RawDapAccess for RemoteProbe {
+    fn select_dp(&mut self, dp: DpAddress) -> Result<(), DebugProbeError> {
+        self.send(Request::SelectDp(dp))
+    }
+
+    fn raw_read_register(&mut self, port: PortType, addr: u8) -> Result<u32, DebugProbeError> {
+        self.call_value(Request::ReadRegister {
+            port,
+            address: addr,
+        })
+    }
+
+    fn raw_read_block(
+        &mut self,
+        port: PortType,
+        addr: u8,
+        values: &mut [u32],
+    ) -> Result<(), DebugProbeError> {
+        // The server refuses larger blocks, so they are read in several requests.
+        for chunk in values.chunks_mut(MAX_BLOCK_LEN) {
+            let request = Request::ReadBlock {
+                port,
+                address: addr,
+                len: chunk.len(),
+            };
+
+            match self.call(request)? {
+                Response::Values(read) if read.len() == chunk.len() => chunk.copy_from_slice(&read),
+                _ => return Err(RemoteError::UnexpectedResponse.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn raw_write_register(
+        &mut self,
+        port: PortType,
+        addr: u8,
+        value: u32,
+    ) -> Result<(), DebugProbeError> {
+        self.send(Request::WriteRegister {
+            port,
+            address: addr,
+            value,
+        })
+    }
+
+    fn raw_write_block(
+        &mut self,
+        port: PortType,
+        addr: u8,
+        values: &[u32],
+    ) -> Result<(), DebugProbeError> {
+        self.send(Request::WriteBlock {
+            port,
+            address: addr,
+            values: values.to_vec(),
+        })
+    }
+
+    fn raw_flush(&mut self) -> Result<(), DebugProbeError> {
+        self.send(Request::Flush)
+    }
+
+    fn swj_sequence(&mut self, bit_len: u8, bits: u64) -> Result<(), DebugProbeError> {
+        self.send(Request::SwjSequence { bit_len, bits })
+    }
+
+    fn swj_pins(
+        &mut self,
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    ) -> Result<u32, DebugProbeError> {
+        self.call_value(Request::SwjPins {
+            pin_out,
+            pin_select,
+            pin_wait,
+        })
+    }
+
+    fn into_probe(self: Box<Self>) -> Box<dyn DebugProbe> {
+        self
+    }
+}
+
+impl DapProbe for RemoteProbe {}
+
+/// The statistics of the requests of a [`RemoteProbe`] to its server, see
+/// [`RemoteProbe::statistics`].
+#[derive(Debug, Clone, Default)]
+pub struct RemoteStatistics(Arc<Mutex<Counters>>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    round_trips: u64,
+    total_latency: Duration,
+    max_latency: Duration,
+    lock_wait: Duration,
+}
+
+impl RemoteStatistics {
+    /// The number of requests which were answered by the server, without the requests for
+    /// the lock.
+    pub fn round_trips(&self) -> u64 {
+        self.counters().round_trips
+    }
+
+    /// The mean time from sending a request until its response arrived, or `None` if no
+    /// request was sent yet.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let counters = self.counters();
+
+        match counters.round_trips {
+            0 => None,
+            round_trips => Some(counters.total_latency.div_f64(round_trips as f64)),
+        }
+    }
+
+    /// The longest time from sending a request until its response arrived.
+    pub fn max_latency(&self) -> Duration {
+        self.counters().max_latency
+    }
+
+    /// The total time spent waiting for the lock of the probe.
+    pub fn lock_wait(&self) -> Duration {
+        self.counters().lock_wait
+    }
+
+    /// Reset all statistics to zero.
+    pub fn reset(&self) {
+        *self.counters() = Counters::default();
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.0.lock().unwrap()
+    }
+
+    fn record_round_trip(&self, latency: Duration) {
+        let mut counters = self.counters();
+
+        counters.round_trips += 1;
+        counters.total_latency += latency;
+        counters.max_latency = counters.max_latency.max(latency);
+    }
+
+    fn record_lock_wait(&self, wait: Duration) {
+        self.counters().lock_wait += wait;
+    }
+}
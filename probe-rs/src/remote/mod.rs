@@ -0,0 +1,83 @@
+//! Sharing a debug probe over the network.
+//!
+//! A [`ProbeServer`] owns a probe, e.g. the probe of a bench board, and serves it to clients
+//! over TCP. A client opens the shared probe with [`Probe::open_remote`](crate::Probe::open_remote)
+//! or [`RemoteProbe::connect`], and uses it like a local probe: it attaches to the target,
+//! flashes and debugs it with the usual [`Session`](crate::Session).
+//!
+//! Only one client can use the probe at a time. A client locks the probe when it attaches,
+//! and unlocks it when it detaches or disconnects. Other clients which attach in the meantime
+//! wait for the lock, in the order they asked for it. A client which holds the lock without
+//! using the probe for longer than [`ServerConfig::idle_timeout`] loses it to the next waiting
+//! client, and with [`AccessPolicy::TimeSliced`], a client loses the lock to a waiting client
+//! once it held it for the length of a slice. Operations of a client which lost its lock fail
+//! with [`RemoteError::Revoked`].
+//!
+//! Clients authenticate with a token shared with the server. The connection itself is not
+//! encrypted, so the server should only be reachable from a trusted network.
+//!
+//! The probe operations are forwarded at the level of the DAP registers, so only probes
+//! which give raw access to the debug port of ARM targets, like CMSIS-DAP probes and
+//! J-Links, can be shared.
+//!
+//! # Protocol
+//!
+//! Every message is a frame of a 4 byte big-endian length, followed by the message encoded
+//! with `bincode`. The client sends a request, and the server answers it with a response.
+//! The first request of a connection carries the token, and is answered with the name and
+//! the capabilities of the probe.
+
+mod client;
+mod protocol;
+mod server;
+
+pub use client::{RemoteProbe, RemoteStatistics};
+pub use server::{AccessPolicy, ProbeServer, ServerConfig};
+
+use crate::DebugProbeError;
+
+/// An error of a shared probe, see the [module](self) docs.
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteError {
+    /// The connection to the server failed.
+    #[error("The connection to the probe server failed")]
+    Io(#[from] std::io::Error),
+    /// A message couldn't be encoded or decoded.
+    #[error("A message of the probe server couldn't be encoded or decoded")]
+    Encoding(#[from] bincode::Error),
+    /// A frame was larger than the largest supported message.
+    #[error("The message of {0} bytes is too large")]
+    FrameTooLarge(usize),
+    /// The server speaks another version of the protocol.
+    #[error(
+        "The probe server speaks version {server} of the protocol, the client version {client}"
+    )]
+    VersionMismatch {
+        /// The protocol version of the server.
+        server: u32,
+        /// The protocol version of the client.
+        client: u32,
+    },
+    /// The server rejected the token.
+    #[error("The probe server rejected the token")]
+    InvalidToken,
+    /// The probe was used without holding its lock.
+    #[error("The probe was used without holding its lock")]
+    NotLocked,
+    /// The server revoked the lock of the client, because it was idle for too long, or
+    /// because its time slice ended.
+    #[error("The lock on the probe was revoked by the server")]
+    Revoked,
+    /// The server answered with a response which doesn't match the request.
+    #[error("The probe server sent an unexpected response")]
+    UnexpectedResponse,
+    /// The probe of the server failed, with an error which can't be forwarded as it is.
+    #[error("The probe of the server failed: {0}")]
+    Server(String),
+}
+
+impl From<RemoteError> for DebugProbeError {
+    fn from(error: RemoteError) -> Self {
+        DebugProbeError::ProbeSpecific(Box::new(error))
+    }
+}
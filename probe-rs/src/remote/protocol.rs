@@ -0,0 +1,268 @@
+//! The messages exchanged between a [`RemoteProbe`](super::RemoteProbe) and a
+//! [`ProbeServer`](super::ProbeServer), and their framing.
+
+use std::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::RemoteError;
+use crate::architecture::arm::{DapError, DpAddress, PortType};
+use crate::{DebugProbeError, ProbeCapabilities, WireProtocol};
+
+/// The version of the protocol, which is checked when a client connects.
+pub(crate) const PROTOCOL_VERSION: u32 = 1;
+
+/// The size of the largest frame, which is far larger than any block transfer.
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// The number of words of the largest block read, whose response still fits into a frame.
+pub(crate) const MAX_BLOCK_LEN: usize = MAX_FRAME_SIZE / 8;
+
+/// A request of a client.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Request {
+    /// The first request of a connection.
+    Hello {
+        version: u32,
+        token: String,
+    },
+    /// Wait for the lock of the probe, and take it.
+    Lock,
+    /// Release the lock of the probe.
+    Unlock,
+    SetSpeed(u32),
+    SelectProtocol(WireProtocol),
+    Attach,
+    Detach,
+    TargetReset,
+    TargetResetAssert,
+    TargetResetDeassert,
+    TargetVoltage,
+    SelectDp(DpAddress),
+    ReadRegister {
+        port: PortType,
+        address: u8,
+    },
+    ReadBlock {
+        port: PortType,
+        address: u8,
+        len: usize,
+    },
+    WriteRegister {
+        port: PortType,
+        address: u8,
+        value: u32,
+    },
+    WriteBlock {
+        port: PortType,
+        address: u8,
+        values: Vec<u32>,
+    },
+    Flush,
+    SwjSequence {
+        bit_len: u8,
+        bits: u64,
+    },
+    SwjPins {
+        pin_out: u32,
+        pin_select: u32,
+        pin_wait: u32,
+    },
+}
+
+impl Request {
+    /// Whether the request needs the lock of the probe.
+    pub(crate) fn needs_lock(&self) -> bool {
+        !matches!(
+            self,
+            Request::Hello { .. } | Request::Lock | Request::Unlock | Request::TargetVoltage
+        )
+    }
+}
+
+/// The response of the server to a [`Request`].
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum Response {
+    /// The response to [`Request::Hello`].
+    Welcome(ProbeInfo),
+    Done,
+    Speed(u32),
+    Value(u32),
+    Values(Vec<u32>),
+    Voltage(Option<f32>),
+    Error(WireError),
+}
+
+/// The properties of the probe of a server, which a client reports as its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ProbeInfo {
+    pub name: String,
+    pub capabilities: ProbeCapabilities,
+    pub speed_khz: u32,
+    pub protocol: Option<WireProtocol>,
+    pub supported_protocols: Vec<WireProtocol>,
+    pub firmware_version: Option<String>,
+    /// Whether the probe gives raw access to the DAP registers.
+    pub has_dap_access: bool,
+}
+
+/// An error of a request, in a form which can be sent to the client.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum WireError {
+    VersionMismatch(u32),
+    InvalidToken,
+    NotLocked,
+    Revoked,
+    Dap(DapError),
+    Timeout,
+    NotAttached,
+    Attached,
+    TargetNotFound,
+    UnsupportedProtocol(WireProtocol),
+    UnsupportedSpeed(u32),
+    /// The probe doesn't give raw access to the DAP registers.
+    NoDapAccess,
+    Other(String),
+}
+
+impl From<DebugProbeError> for WireError {
+    fn from(error: DebugProbeError) -> Self {
+        match error {
+            DebugProbeError::ArchitectureSpecific(source) => match source.downcast::<DapError>() {
+                Ok(dap_error) => WireError::Dap(*dap_error),
+                Err(source) => WireError::Other(source.to_string()),
+            },
+            DebugProbeError::Timeout => WireError::Timeout,
+            DebugProbeError::NotAttached => WireError::NotAttached,
+            DebugProbeError::Attached => WireError::Attached,
+            DebugProbeError::TargetNotFound => WireError::TargetNotFound,
+            DebugProbeError::UnsupportedProtocol(protocol) => {
+                WireError::UnsupportedProtocol(protocol)
+            }
+            DebugProbeError::UnsupportedSpeed(speed) => WireError::UnsupportedSpeed(speed),
+            other => WireError::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<WireError> for DebugProbeError {
+    fn from(error: WireError) -> Self {
+        match error {
+            WireError::VersionMismatch(server) => RemoteError::VersionMismatch {
+                server,
+                client: PROTOCOL_VERSION,
+            }
+            .into(),
+            WireError::InvalidToken => RemoteError::InvalidToken.into(),
+            WireError::NotLocked => RemoteError::NotLocked.into(),
+            WireError::Revoked => RemoteError::Revoked.into(),
+            WireError::Dap(dap_error) => dap_error.into(),
+            WireError::Timeout => DebugProbeError::Timeout,
+            WireError::NotAttached => DebugProbeError::NotAttached,
+            WireError::Attached => DebugProbeError::Attached,
+            WireError::TargetNotFound => DebugProbeError::TargetNotFound,
+            WireError::UnsupportedProtocol(protocol) => {
+                DebugProbeError::UnsupportedProtocol(protocol)
+            }
+            WireError::UnsupportedSpeed(speed) => DebugProbeError::UnsupportedSpeed(speed),
+            WireError::NoDapAccess => DebugProbeError::InterfaceNotAvailable("ARM"),
+            WireError::Other(message) => RemoteError::Server(message).into(),
+        }
+    }
+}
+
+/// Write `message` to `stream` as a single frame.
+pub(crate) fn write_frame(
+    stream: &mut impl Write,
+    message: &impl Serialize,
+) -> Result<(), RemoteError> {
+    let payload = bincode::serialize(message)?;
+
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(RemoteError::FrameTooLarge(payload.len()));
+    }
+
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    // A single write, so that a frame is sent in as few segments as possible.
+    stream.write_all(&frame)?;
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Read a single frame from `stream`, and decode its message.
+pub(crate) fn read_frame<T: DeserializeOwned>(stream: &mut impl Read) -> Result<T, RemoteError> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(RemoteError::FrameTooLarge(length));
+    }
+
+    let mut payload = vec![0u8; length];
+    stream.read_exact(&mut payload)?;
+
+    Ok(bincode::deserialize(&payload)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(
+            &mut buffer,
+            &Request::WriteBlock {
+                port: PortType::AccessPort,
+                address: 0xc,
+                values: vec![1, 2, 3],
+            },
+        )
+        .unwrap();
+        write_frame(&mut buffer, &Request::Unlock).unwrap();
+
+        let mut stream = buffer.as_slice();
+
+        match read_frame(&mut stream).unwrap() {
+            Request::WriteBlock {
+                port: PortType::AccessPort,
+                address: 0xc,
+                values,
+            } => assert_eq!(values, [1, 2, 3]),
+            other => panic!("Unexpected request {:?}", other),
+        }
+        assert!(matches!(read_frame(&mut stream).unwrap(), Request::Unlock));
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn oversized_frame_is_rejected() {
+        let frame = ((MAX_FRAME_SIZE + 1) as u32).to_be_bytes();
+
+        assert!(matches!(
+            read_frame::<Request>(&mut frame.as_slice()),
+            Err(RemoteError::FrameTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn dap_errors_are_forwarded() {
+        let error: DebugProbeError = DapError::FaultResponse.into();
+
+        match DebugProbeError::from(WireError::from(error)) {
+            DebugProbeError::ArchitectureSpecific(source) => {
+                assert_eq!(
+                    source.downcast_ref::<DapError>(),
+                    Some(&DapError::FaultResponse)
+                );
+            }
+            other => panic!("Unexpected error {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,556 @@
+//! The server which shares a probe with its clients.
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::protocol::{
+    read_frame, write_frame, ProbeInfo, Request, Response, WireError, MAX_BLOCK_LEN,
+    PROTOCOL_VERSION,
+};
+use super::RemoteError;
+use crate::{DebugProbe, Probe};
+
+/// How the clients of a [`ProbeServer`] share the probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPolicy {
+    /// A client keeps the lock until it unlocks it, disconnects, or is idle for longer than
+    /// the idle timeout.
+    Exclusive,
+    /// Like [`AccessPolicy::Exclusive`], but a client loses the lock to a waiting client
+    /// once it held it for the given time.
+    TimeSliced(Duration),
+}
+
+/// The configuration of a [`ProbeServer`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ServerConfig {
+    /// The token the clients have to present.
+    pub token: String,
+    /// How the clients share the probe.
+    pub access: AccessPolicy,
+    /// The time after which an idle client loses the lock, if another client waits for it.
+    pub idle_timeout: Duration,
+}
+
+impl ServerConfig {
+    /// The configuration of a server which accepts clients with `token`, with exclusive access
+    /// and an idle timeout of 60 seconds.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+            access: AccessPolicy::Exclusive,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A server which shares a probe with the clients connecting to it, see the
+/// [module](super) docs.
+///
+/// ```no_run
+/// use probe_rs::remote::{ProbeServer, ServerConfig};
+/// use probe_rs::Probe;
+///
+/// let probe = Probe::list_all()[0].open()?;
+///
+/// let server = ProbeServer::bind("0.0.0.0:5555", probe, ServerConfig::new("secret"))?;
+/// server.run()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct ProbeServer {
+    listener: TcpListener,
+    shared: Arc<Shared>,
+}
+
+impl ProbeServer {
+    /// Listen for clients on `address`, to share `probe` with them.
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        probe: Probe,
+        config: ServerConfig,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+
+        Ok(Self {
+            listener,
+            shared: Arc::new(Shared {
+                config,
+                probe: Mutex::new(probe.into_inner()),
+                lock: Mutex::new(LockState::default()),
+                lock_changed: Condvar::new(),
+                next_client: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    /// The address the server listens on, e.g. to find the port if it was bound to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve the clients, each on its own thread.
+    ///
+    /// This only returns if accepting a connection fails.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let shared = self.shared.clone();
+
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+
+                if let Err(error) = serve_client(shared, stream) {
+                    log::warn!("Connection to {:?} failed: {}", peer, error);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The state shared by the connections of a server.
+#[derive(Debug)]
+struct Shared {
+    config: ServerConfig,
+    probe: Mutex<Box<dyn DebugProbe>>,
+    lock: Mutex<LockState>,
+    /// Notified whenever the lock is released.
+    lock_changed: Condvar,
+    next_client: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct LockState {
+    holder: Option<Holder>,
+    /// The clients waiting for the lock, in the order they asked for it.
+    waiting: VecDeque<u64>,
+    /// Whether the probe is attached, and has to be detached before the next client uses it.
+    attached: bool,
+}
+
+#[derive(Debug)]
+struct Holder {
+    client: u64,
+    since: Instant,
+    last_activity: Instant,
+}
+
+impl Shared {
+    fn probe(&self) -> MutexGuard<'_, Box<dyn DebugProbe>> {
+        // A client which panicked doesn't leave the probe in a worse state than one which
+        // disconnected.
+        self.probe.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock_state(&self) -> MutexGuard<'_, LockState> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn probe_info(&self) -> ProbeInfo {
+        let mut probe = self.probe();
+
+        ProbeInfo {
+            name: probe.get_name().to_owned(),
+            capabilities: probe.capabilities(),
+            speed_khz: probe.speed_khz(),
+            protocol: probe.active_protocol(),
+            supported_protocols: probe.supported_protocols(),
+            firmware_version: probe.firmware_version(),
+            has_dap_access: probe.try_as_dap_probe().is_some(),
+        }
+    }
+
+    /// The time at which the current holder of the lock loses it to a waiting client.
+    fn revocation_time(&self, holder: &Holder) -> Instant {
+        let idle = holder.last_activity + self.config.idle_timeout;
+
+        match self.config.access {
+            AccessPolicy::Exclusive => idle,
+            AccessPolicy::TimeSliced(slice) => idle.min(holder.since + slice),
+        }
+    }
+
+    /// Wait until `client` is the first waiting client and the lock is free, and take it.
+    fn acquire(&self, client: u64) {
+        let mut state = self.lock_state();
+
+        if matches!(&state.holder, Some(holder) if holder.client == client) {
+            return;
+        }
+
+        state.waiting.push_back(client);
+
+        loop {
+            let timeout = match &state.holder {
+                None if state.waiting.front() == Some(&client) => {
+                    let now = Instant::now();
+
+                    state.waiting.pop_front();
+                    state.holder = Some(Holder {
+                        client,
+                        since: now,
+                        last_activity: now,
+                    });
+
+                    return;
+                }
+                None => None,
+                Some(holder) => {
+                    let revocation = self.revocation_time(holder);
+
+                    if Instant::now() >= revocation {
+                        log::warn!(
+                            "Revoking the lock of client {} for client {}",
+                            holder.client,
+                            client
+                        );
+
+                        state.holder = None;
+                        self.lock_changed.notify_all();
+                        continue;
+                    }
+
+                    Some(revocation.saturating_duration_since(Instant::now()))
+                }
+            };
+
+            state = match timeout {
+                Some(timeout) => {
+                    self.lock_changed
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0
+                }
+                None => self
+                    .lock_changed
+                    .wait(state)
+                    .unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    /// Release the lock of `client`, if it holds it, and stop waiting for it.
+    fn release(&self, client: u64, probe: &mut dyn DebugProbe) {
+        let mut state = self.lock_state();
+
+        state.waiting.retain(|waiting| *waiting != client);
+
+        if matches!(&state.holder, Some(holder) if holder.client == client) {
+            self.detach_if_attached(&mut state, probe);
+            state.holder = None;
+            self.lock_changed.notify_all();
+        }
+    }
+
+    /// Detach the probe, which was left attached by the previous holder of the lock.
+    fn detach_if_attached(&self, state: &mut LockState, probe: &mut dyn DebugProbe) {
+        if state.attached {
+            if let Err(error) = probe.detach() {
+                log::warn!("Failed to detach the probe: {}", error);
+            }
+            state.attached = false;
+        }
+    }
+}
+
+/// A connection of a client.
+struct Connection {
+    shared: Arc<Shared>,
+    client: u64,
+    /// Whether the client was given the lock. If it doesn't hold the lock anymore, it was
+    /// revoked.
+    locked: bool,
+}
+
+impl Connection {
+    fn handle(&mut self, request: Request) -> Response {
+        match request {
+            Request::Hello { .. } => {
+                Response::Error(WireError::Other("The client already connected".to_owned()))
+            }
+            Request::Lock => {
+                self.shared.acquire(self.client);
+                self.locked = true;
+
+                // The previous holder might not have detached, e.g. if its lock was revoked.
+                let mut probe = self.shared.probe();
+                let mut state = self.shared.lock_state();
+                self.shared.detach_if_attached(&mut state, probe.as_mut());
+
+                Response::Done
+            }
+            Request::Unlock => {
+                self.locked = false;
+                self.shared
+                    .release(self.client, self.shared.probe().as_mut());
+
+                Response::Done
+            }
+            Request::TargetVoltage => match self.shared.probe().get_target_voltage() {
+                Ok(voltage) => Response::Voltage(voltage),
+                Err(error) => Response::Error(error.into()),
+            },
+            request => {
+                // The probe is locked first, so that the lock can't be revoked between the
+                // check and the operation.
+                let mut probe = self.shared.probe();
+
+                if let Err(error) = self.check_lock() {
+                    return Response::Error(error);
+                }
+
+                let attach = match request {
+                    Request::Attach => Some(true),
+                    Request::Detach => Some(false),
+                    _ => None,
+                };
+
+                match execute(probe.as_mut(), request) {
+                    Ok(response) => {
+                        if let Some(attached) = attach {
+                            self.shared.lock_state().attached = attached;
+                        }
+
+                        response
+                    }
+                    Err(error) => Response::Error(error),
+                }
+            }
+        }
+    }
+
+    /// Check that the client holds the lock, and record its activity.
+    fn check_lock(&mut self) -> Result<(), WireError> {
+        let mut state = self.shared.lock_state();
+
+        match &mut state.holder {
+            Some(holder) if holder.client == self.client => {
+                holder.last_activity = Instant::now();
+                Ok(())
+            }
+            // The client keeps getting this error until it takes the lock again.
+            _ if self.locked => Err(WireError::Revoked),
+            _ => Err(WireError::NotLocked),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let mut probe = self.shared.probe();
+        self.shared.release(self.client, probe.as_mut());
+    }
+}
+
+/// Execute a `request` which needs the lock.
+fn execute(probe: &mut dyn DebugProbe, request: Request) -> Result<Response, WireError> {
+    let response = match request {
+        Request::SetSpeed(speed_khz) => Response::Speed(probe.set_speed(speed_khz)?),
+        Request::SelectProtocol(protocol) => {
+            probe.select_protocol(protocol)?;
+            Response::Done
+        }
+        Request::Attach => {
+            probe.attach()?;
+            Response::Done
+        }
+        Request::Detach => {
+            probe.detach()?;
+            Response::Done
+        }
+        Request::TargetReset => {
+            probe.target_reset()?;
+            Response::Done
+        }
+        Request::TargetResetAssert => {
+            probe.target_reset_assert()?;
+            Response::Done
+        }
+        Request::TargetResetDeassert => {
+            probe.target_reset_deassert()?;
+            Response::Done
+        }
+        request => {
+            let dap = probe.try_as_dap_probe().ok_or(WireError::NoDapAccess)?;
+
+            match request {
+                Request::SelectDp(dp) => {
+                    dap.select_dp(dp)?;
+                    Response::Done
+                }
+                Request::ReadRegister { port, address } => {
+                    Response::Value(dap.raw_read_register(port, address)?)
+                }
+                Request::ReadBlock { port, address, len } => {
+                    // The length is only allocated once it is known to fit into a frame.
+                    if len > MAX_BLOCK_LEN {
+                        return Err(WireError::Other(format!(
+                            "Block reads are limited to {} words, {} were requested",
+                            MAX_BLOCK_LEN, len
+                        )));
+                    }
+
+                    let mut values = vec![0; len];
+                    dap.raw_read_block(port, address, &mut values)?;
+                    Response::Values(values)
+                }
+                Request::WriteRegister {
+                    port,
+                    address,
+                    value,
+                } => {
+                    dap.raw_write_register(port, address, value)?;
+                    Response::Done
+                }
+                Request::WriteBlock {
+                    port,
+                    address,
+                    values,
+                } => {
+                    dap.raw_write_block(port, address, &values)?;
+                    Response::Done
+                }
+                Request::Flush => {
+                    dap.raw_flush()?;
+                    Response::Done
+                }
+                Request::SwjSequence { bit_len, bits } => {
+                    dap.swj_sequence(bit_len, bits)?;
+                    Response::Done
+                }
+                Request::SwjPins {
+                    pin_out,
+                    pin_select,
+                    pin_wait,
+                } => Response::Value(dap.swj_pins(pin_out, pin_select, pin_wait)?),
+                other => unreachable!("{:?} doesn't need the lock", other),
+            }
+        }
+    };
+
+    Ok(response)
+}
+
+/// Compare the token of a client with the token of the server, in a time which doesn't
+/// depend on the position of the first difference. Only the length of the token can be
+/// learned from the timing.
+fn tokens_match(client: &str, server: &str) -> bool {
+    let difference = client
+        .bytes()
+        .zip(server.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+
+    client.len() == server.len() && difference == 0
+}
+
+/// Serve the client connected with `stream`, until it disconnects.
+fn serve_client(shared: Arc<Shared>, stream: TcpStream) -> Result<(), RemoteError> {
+    // Most requests are small, and wait for the response of the previous one.
+    stream.set_nodelay(true)?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    // The token is checked first, so that only authenticated clients learn the version.
+    match read_frame(&mut reader)? {
+        Request::Hello { token, .. } if !tokens_match(&token, &shared.config.token) => {
+            return write_frame(&mut writer, &Response::Error(WireError::InvalidToken));
+        }
+        Request::Hello { version, .. } if version != PROTOCOL_VERSION => {
+            let error = WireError::VersionMismatch(PROTOCOL_VERSION);
+            return write_frame(&mut writer, &Response::Error(error));
+        }
+        Request::Hello { .. } => write_frame(&mut writer, &Response::Welcome(shared.probe_info()))?,
+        _ => {
+            let error = WireError::Other("The connection has to start with a hello".to_owned());
+            return write_frame(&mut writer, &Response::Error(error));
+        }
+    }
+
+    let mut connection = Connection {
+        client: shared.next_client.fetch_add(1, Ordering::Relaxed),
+        shared,
+        locked: false,
+    };
+
+    loop {
+        let request = match read_frame(&mut reader) {
+            Ok(request) => request,
+            // The client disconnected.
+            Err(RemoteError::Io(error)) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(())
+            }
+            Err(error) => return Err(error),
+        };
+
+        let response = connection.handle(request);
+        write_frame(&mut writer, &response)?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::arm::PortType;
+    use crate::FakeProbe;
+
+    #[test]
+    fn tokens_only_match_in_full() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret", "secrets"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[test]
+    fn oversized_block_reads_are_refused() {
+        let mut probe: Box<dyn DebugProbe> = Box::new(FakeProbe::with_mocked_core());
+
+        let request = Request::ReadBlock {
+            port: PortType::AccessPort,
+            address: 0xc,
+            len: usize::MAX,
+        };
+
+        assert!(matches!(
+            execute(probe.as_mut(), request),
+            Err(WireError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn version_is_only_reported_to_authenticated_clients() {
+        let probe = FakeProbe::with_mocked_core().into_probe();
+        let server = ProbeServer::bind("127.0.0.1:0", probe, ServerConfig::new("secret")).unwrap();
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.run());
+
+        let hello = |token: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let request = Request::Hello {
+                version: PROTOCOL_VERSION + 1,
+                token: token.to_owned(),
+            };
+            write_frame(&mut stream, &request).unwrap();
+
+            read_frame::<Response>(&mut stream).unwrap()
+        };
+
+        assert!(matches!(
+            hello("guess"),
+            Response::Error(WireError::InvalidToken)
+        ));
+        assert!(matches!(
+            hello("secret"),
+            Response::Error(WireError::VersionMismatch(PROTOCOL_VERSION))
+        ));
+    }
+}
@@ -1,22 +1,68 @@
-use crate::architecture::arm::sequences::DefaultArmSequence;
-use crate::architecture::arm::{ApAddress, DpAddress};
-use crate::config::{ChipInfo, MemoryRegion, RegistryError, Target, TargetSelector};
-use crate::core::{Architecture, CoreState, SpecificCoreState};
+use crate::architecture::arm::sequences::{ArmDebugSequence, DefaultArmSequence};
+use crate::architecture::arm::{ApAddress, DpAddress, Pins};
+use crate::attach_plan::{AttachDeviation, AttachPlan, AttachReport, CoreDirective};
+use crate::config::{
+    ChipInfo, Keepalive, KeepaliveAction, MemoryRegion, RegistryError, Target, TargetSelector,
+};
+use crate::core::{
+    Architecture, CoreState, ForceHaltReport, HaltAttempt, HaltAttemptOutcome, HaltEscalation,
+    ResetHaltReport, RomRegion, SpecificCoreState,
+};
+use crate::drain::{CircularBuffer, DrainId, DrainSink, DrainStatus, Drains};
+use crate::errata::{self, ActiveErratum, CoreErrata};
+use crate::flashing::{FlashLoader, ImageIssue};
+use crate::freeze::{FreezeSelection, FrozenPeripheral, PeripheralFreezes};
+use crate::guard::{AttachGuard, GuardPolicy};
+use crate::health::DEFAULT_HEALTH_LOG_CAPACITY;
+use crate::interrupt::InterruptHandle;
+use crate::keepalive::KeepaliveState;
+use crate::link::{self, AutoSpeed, SlowClockAttach};
+use crate::operation_journal::{
+    self, JournalRecorder, OperationJournal, ReplayOptions, ReplayReport,
+};
+use crate::panic_hooks::{self, PanicBreakOptions, PanicHook, PANIC_BREAKPOINT_GROUP};
+use crate::probe::firmware;
+use crate::system_description::{
+    AccessPortDescriptor, CoreDescriptor, DebugInterfaceDescriptor, DebugModuleDescriptor,
+    ErratumDescriptor, ProbeDescriptor, RegionDescriptor, SessionSettings, SessionWarning,
+    SystemDescription, TargetIdentity, SYSTEM_DESCRIPTION_VERSION,
+};
+use crate::teardown::{CloseReport, CoreCloseReport, DetachMode, Teardown, TeardownStep};
+use crate::transfer::{TransferProgress, TransferStatistics, Transfers};
 use crate::{
     architecture::{
         arm::{
-            ap::{GenericAp, MemoryAp},
+            ap::{AccessPortError, GenericAp, MemoryAp, VendorAp, IDR},
             communication_interface::{ArmProbeInterface, MemoryApInformation},
             memory::{Component, CoresightComponent},
-            ApInformation, SwoConfig, SwoReader,
+            ApInformation, Register, SwoConfig, SwoReader,
         },
         riscv::communication_interface::RiscvCommunicationInterface,
+        route::InterfaceRoute,
+        settle::{DelayOrPoll, SettleStatistics},
     },
     config::DebugSequence,
 };
-use crate::{AttachMethod, Core, CoreType, Error, Probe};
-use anyhow::anyhow;
-use std::{fmt, time::Duration};
+use crate::{
+    AccessMediator, AttachMethod, Core, CoreType, DebugProbeError, Error, FirmwareAdjustment,
+    FirmwareFeature, FirmwareLimitation, HealthEvent, HealthLog, Intrusiveness, MediatedRegions,
+    MemoryInterface, Probe, ProbeCapabilities, RetryPolicy, TargetOperation, TargetWritableFlash,
+    TeardownFailure, VolatileRanges, WireProtocol,
+};
+use probe_rs_target::CoreAccessOptions;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::Range,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The time a core is given to halt when the session is closed with [`DetachMode::Halt`].
+///
+/// This is short, so that closing the session doesn't stall on a dead target.
+const DETACH_TIMEOUT: Duration = Duration::from_millis(100);
 
 /// The `Session` struct represents an active debug session.
 ///
@@ -40,12 +86,54 @@ use std::{fmt, time::Duration};
 pub struct Session {
     target: Target,
     interface: ArchitectureInterface,
+    /// The route of the active debug interface, see [`Session::switch_route`].
+    route: InterfaceRoute,
+    /// The routes whose debug interface was set up, on targets which mix architectures.
+    initialized_routes: Vec<InterfaceRoute>,
     cores: Vec<(SpecificCoreState, CoreState)>,
+    delay_or_poll: DelayOrPoll,
+    /// Whether the power-up of the system power domain is requested when an ARM debug port
+    /// is started, see [`AttachOptions::system_power_request`].
+    system_power_request: bool,
+    health_log: HealthLog,
+    keepalive: KeepaliveState,
+    interrupt: InterruptHandle,
+    /// The circular buffers which are drained, see [`Session::register_drain`].
+    drains: Drains,
+    /// The progress and statistics of the memory transfers, see
+    /// [`Session::set_transfer_progress`].
+    transfers: Transfers,
+    /// Records the operations of the cores while the operation journal is started, see
+    /// [`Session::start_operation_journal`].
+    journal: JournalRecorder,
+    swv_config: Option<SwoConfig>,
+    errata: Vec<ActiveErratum>,
+    negotiated_speed: Option<u32>,
+    slow_clock_attach: Option<SlowClockAttach>,
+    probe_capabilities: ProbeCapabilities,
+    probe_firmware_adjustments: Vec<FirmwareAdjustment>,
+    probe_description: String,
+    detach_mode: DetachMode,
+    /// Set once the session was torn down by [`Session::close`].
+    closed: bool,
+    permissions: Permissions,
+    max_intrusiveness: Intrusiveness,
+    volatile_ranges: VolatileRanges,
+    target_writable_flash: TargetWritableFlash,
+    mediated_regions: MediatedRegions,
+    peripheral_freezes: PeripheralFreezes,
+    /// What was done while attaching, see [`Session::attach_report`].
+    attach_report: AttachReport,
+    /// The cores which were not set up while attaching, by their directive. They are set up
+    /// when they are accessed for the first time.
+    pending_cores: BTreeMap<usize, CoreDirective>,
 }
 
 enum ArchitectureInterface {
     Arm(Box<dyn ArmProbeInterface + 'static>),
     Riscv(Box<RiscvCommunicationInterface>),
+    /// The probe was lost while it was switched to the debug interface of the route.
+    Lost(InterfaceRoute),
 }
 
 impl fmt::Debug for ArchitectureInterface {
@@ -56,6 +144,10 @@ impl fmt::Debug for ArchitectureInterface {
                 .debug_tuple("ArchitectureInterface::Riscv")
                 .field(iface)
                 .finish(),
+            ArchitectureInterface::Lost(route) => f
+                .debug_tuple("ArchitectureInterface::Lost")
+                .field(route)
+                .finish(),
         }
     }
 }
@@ -65,6 +157,7 @@ impl From<ArchitectureInterface> for Architecture {
         match value {
             ArchitectureInterface::Arm(_) => Architecture::Arm,
             ArchitectureInterface::Riscv(_) => Architecture::Riscv,
+            ArchitectureInterface::Lost(route) => route.architecture(),
         }
     }
 }
@@ -85,7 +178,10 @@ impl ArchitectureInterface {
                 let arm_core_access_options = match &config.core_access_options {
                     probe_rs_target::CoreAccessOptions::Arm(opt) => opt,
                     probe_rs_target::CoreAccessOptions::Riscv(_) => {
-                        unreachable!("This should never happen. Please file a bug if it does.")
+                        return Err(Error::CoreArchitectureMismatch {
+                            core: core_state.id(),
+                            architecture: Architecture::Arm,
+                        })
                     }
                 };
 
@@ -102,7 +198,8 @@ impl ArchitectureInterface {
 
                 core.attach_arm(core_state, memory, target)
             }
-            ArchitectureInterface::Riscv(state) => core.attach_riscv(core_state, state),
+            ArchitectureInterface::Riscv(state) => core.attach_riscv(core_state, state, target),
+            ArchitectureInterface::Lost(route) => Err(Error::DebugInterfaceLost(*route)),
         }
     }
 }
@@ -112,23 +209,209 @@ impl Session {
     pub(crate) fn new(
         probe: Probe,
         target: TargetSelector,
-        attach_method: AttachMethod,
+        plan: AttachPlan,
         permissions: Permissions,
+        options: AttachOptions,
     ) -> Result<Self, Error> {
-        let (mut probe, target) = get_target_from_selector(target, attach_method, probe)?;
+        let (mut probe, mut target) =
+            get_target_from_selector(target, plan.attach_method(), probe)?;
+
+        for (&core, core_override) in &options.core_overrides {
+            let config = target
+                .cores
+                .get_mut(core)
+                .ok_or(Error::CoreNotFound(core))?;
+
+            config.core_access_options = core_override.apply(core, &config.core_access_options)?;
+
+            log::info!(
+                "Overriding the access options of core {}: {:?}",
+                core,
+                config.core_access_options
+            );
+        }
+
+        plan.validate(&target)?;
+
+        let mut attach_report = AttachReport::new(&plan, &target);
+        let pending_cores: BTreeMap<_, _> = attach_report
+            .directives
+            .iter()
+            .enumerate()
+            .filter(|(_, directive)| !directive.initializes())
+            .map(|(core, directive)| (core, *directive))
+            .collect();
+
+        let delay_or_poll = DelayOrPoll::new(options.settle_time_factor);
+
+        let health_log = HealthLog::new(options.health_log_capacity);
+        probe.set_health_log(health_log.clone());
+
+        if let Some(pack) = &target.overridden_by {
+            health_log.record(
+                HealthEvent::TargetOverridden,
+                None,
+                "attach",
+                format!(
+                    "The description of {} comes from the target pack {}",
+                    target.name, pack
+                ),
+            );
+        }
+
+        let clock_sequence = match &target.debug_sequence {
+            DebugSequence::Arm(sequence) => Some(sequence.as_ref()),
+            DebugSequence::Riscv(_) => None,
+        };
+
+        let mut slow_clock_attach = if options
+            .slow_clock_attach
+            .unwrap_or(target.slow_clock_attach)
+        {
+            let auto_speed = options.auto_speed.unwrap_or(AutoSpeed {
+                start_khz: probe.speed_khz(),
+                min_khz: link::SLOW_CLOCK_SPEED_KHZ,
+            });
+
+            let (returned, result) = link::slow_clock_attach(
+                probe,
+                target.architecture(),
+                clock_sequence,
+                auto_speed,
+                &health_log,
+            );
+            probe = returned;
+
+            Some(result?)
+        } else {
+            None
+        };
+
+        let negotiated_speed = match (options.auto_speed, slow_clock_attach) {
+            (_, Some(slow_clock_attach)) => Some(slow_clock_attach.final_speed_khz),
+            (Some(auto_speed), None) => {
+                let (returned, result) =
+                    link::negotiate_speed(probe, target.architecture(), auto_speed, &health_log);
+                probe = returned;
+
+                match result {
+                    Ok(speed_khz) => Some(speed_khz),
+                    // Unless the slow clock attach was disabled explicitly, check whether the
+                    // target only communicates after it was attached at a low speed.
+                    Err(error @ Error::SpeedNegotiationFailed { .. })
+                        if options.slow_clock_attach.is_none() =>
+                    {
+                        let (returned, result) = link::detect_slow_clock(
+                            probe,
+                            &target.name,
+                            target.architecture(),
+                            clock_sequence,
+                            auto_speed,
+                            &health_log,
+                        );
+                        probe = returned;
+
+                        let detected = result.ok_or(error)?;
+                        slow_clock_attach = Some(detected);
+                        Some(detected.final_speed_khz)
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            (None, None) => None,
+        };
+
+        let probe_capabilities = probe.capabilities();
+        let probe_firmware_adjustments = probe.firmware_adjustments();
+        let probe_description = probe.description();
+
+        let keepalive = KeepaliveState::new(options.keepalive.or(target.keepalive));
+
+        let interrupt = InterruptHandle::new();
+        let drains = Drains::default();
+        let transfers = Transfers::default();
+        let journal = JournalRecorder::default();
+
+        let volatile_ranges = VolatileRanges::new(&target.memory_map);
+        let target_writable_flash = TargetWritableFlash::new(&target.memory_map);
+        let ecc_memory = EccMemory::new(&target.memory_map);
+        let mediated_regions = MediatedRegions::new(&target.mediated_regions);
+
+        let mut peripheral_freezes = PeripheralFreezes::new(&target.peripheral_freeze);
+        if let Some(selection) = options.freeze_peripherals_on_halt.clone() {
+            if plan.freezes_on_halt() {
+                peripheral_freezes.select(selection)?;
+            }
+        }
 
         let cores = target
             .cores
             .iter()
             .enumerate()
             .map(|(id, core)| {
+                let mut core_state = Core::create_state(id, core.core_access_options.clone());
+
+                core_state.set_volatile_ranges(volatile_ranges.clone());
+                core_state.set_ecc_memory(ecc_memory.clone());
+                core_state.set_mediated_regions(mediated_regions.clone());
+                core_state.set_peripheral_freezes(peripheral_freezes.clone());
+
+                core_state.set_interrupt_handle(interrupt.clone());
+                core_state.set_drains(drains.clone());
+                core_state.set_transfers(transfers.clone());
+                core_state.set_journal(journal.clone());
+                core_state.set_poll_offload(probe_capabilities.poll_offload);
+
+                core_state.set_reset_affects_other_cores(target.cores.iter().enumerate().any(
+                    |(other_id, other)| {
+                        other_id != id && core.reset_scope.affects(&other.reset_scope)
+                    },
+                ));
+
+                core_state.set_ram_ranges(
+                    target
+                        .memory_map
+                        .iter()
+                        .filter_map(|region| match region {
+                            MemoryRegion::Ram(region)
+                                if region.cores.is_empty() || region.cores.contains(&core.name) =>
+                            {
+                                Some(region.range.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect(),
+                );
+
+                core_state.set_nvm_ranges(
+                    target
+                        .memory_map
+                        .iter()
+                        .filter_map(|region| match region {
+                            MemoryRegion::Nvm(region)
+                                if region.cores.is_empty() || region.cores.contains(&core.name) =>
+                            {
+                                Some(region.range.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect(),
+                );
+
+                core_state.set_retry_policy(options.retry_policy);
+                core_state.set_health_log(health_log.clone());
+
                 (
                     SpecificCoreState::from_core_type(core.core_type),
-                    Core::create_state(id, core.core_access_options.clone()),
+                    core_state,
                 )
             })
             .collect();
 
+        // The target is attached through the debug interface of its first core, the others are
+        // set up when a core behind them is accessed for the first time.
+        let route = InterfaceRoute::of(&target.cores[0]);
+
         let mut session = match target.architecture() {
             Architecture::Arm => {
                 let config = target.cores[0].clone();
@@ -154,7 +437,19 @@ impl Session {
                     }
                 };
 
-                if AttachMethod::UnderReset == attach_method {
+                let mut hardware_reset = plan.resets_through_reset_line(&target, 0);
+                if hardware_reset && !probe_capabilities.reset_control {
+                    log::warn!(
+                        "{} can't control the reset line, core 0 is reset after attaching",
+                        probe.get_name()
+                    );
+                    attach_report
+                        .deviations
+                        .push(AttachDeviation::ResetLineUnavailable { core: 0 });
+                    hardware_reset = false;
+                }
+
+                if hardware_reset {
                     if let Some(dap_probe) = probe.try_as_dap_probe() {
                         sequence_handle.reset_hardware_assert(dap_probe)?;
                     } else {
@@ -164,6 +459,9 @@ impl Session {
                         );
                         log::info!("Falling back to standard probe reset.");
                         probe.target_reset_assert()?;
+                        attach_report
+                            .deviations
+                            .push(AttachDeviation::StandardProbeReset);
                     }
                 }
 
@@ -172,85 +470,108 @@ impl Session {
                 let interface = probe.try_into_arm_interface().map_err(|(_, err)| err)?;
 
                 let mut interface = interface.initialize(sequence_handle.clone())?;
+                interface.set_power_policy(delay_or_poll.clone(), options.system_power_request);
+
+                validate_ap_overrides(interface.as_mut(), &target, &options.core_overrides)?;
 
                 // Enable debug mode
-                sequence_handle.debug_device_unlock(
+                if plan.powers_up_debug_domains() {
+                    sequence_handle.debug_device_unlock(
+                        &mut interface,
+                        default_memory_ap,
+                        &permissions,
+                        &delay_or_poll,
+                    )?;
+                    attach_report.debug_domains_powered_up = true;
+                }
+
+                start_arm_cores(
                     &mut interface,
-                    default_memory_ap,
-                    &permissions,
+                    sequence_handle.as_ref(),
+                    &target,
+                    &pending_cores,
                 )?;
 
-                {
-                    // For each core, setup debugging
-                    for i in 0..target.cores.len() {
-                        let config = target.cores[i].clone();
-                        let arm_core_access_options = match config.core_access_options {
-                            probe_rs_target::CoreAccessOptions::Arm(opt) => opt,
-                            probe_rs_target::CoreAccessOptions::Riscv(_) => {
-                                unreachable!(
-                                    "This should never happen. Please file a bug if it does."
-                                )
-                            }
-                        };
-
-                        let mem_ap = MemoryAp::new(ApAddress {
-                            dp: match arm_core_access_options.psel {
-                                0 => DpAddress::Default,
-                                x => DpAddress::Multidrop(x),
-                            },
-                            ap: arm_core_access_options.ap,
-                        });
-
-                        let mut memory_interface = interface.memory_interface(mem_ap)?;
-
-                        // Enable debug mode
-                        sequence_handle.debug_core_start(
-                            &mut memory_interface,
-                            config.core_type,
-                            arm_core_access_options.debug_base,
-                            arm_core_access_options.cti_base,
-                        )?;
-                    }
-                }
+                let session = if hardware_reset {
+                    let vector_catch = plan.catches_vector();
 
-                let session = if attach_method == AttachMethod::UnderReset {
                     {
                         let mut memory_interface = interface.memory_interface(default_memory_ap)?;
                         // we need to halt the chip here
-                        sequence_handle.reset_catch_set(
-                            &mut memory_interface,
-                            config.core_type,
-                            arm_core_access_options.debug_base,
-                        )?;
+                        if vector_catch {
+                            sequence_handle.reset_catch_set(
+                                &mut memory_interface,
+                                config.core_type,
+                                arm_core_access_options.debug_base,
+                            )?;
+                        }
                         sequence_handle.reset_hardware_deassert(&mut memory_interface)?;
                     }
 
+                    attach_report.vector_caught = vector_catch;
+                    for core in plan.reset_with(&target, 0) {
+                        attach_report.record_reset(core, 0);
+                    }
+
                     let mut session = Session {
                         target,
                         interface: ArchitectureInterface::Arm(interface),
+                        route,
+                        initialized_routes: vec![route],
                         cores,
+                        delay_or_poll,
+                        system_power_request: options.system_power_request,
+                        health_log,
+                        keepalive,
+                        interrupt,
+                        drains,
+                        transfers,
+                        journal,
+                        swv_config: None,
+                        errata: Vec::new(),
+                        negotiated_speed,
+                        slow_clock_attach,
+                        probe_capabilities,
+                        probe_firmware_adjustments,
+                        probe_description,
+                        detach_mode: options.detach_mode,
+                        closed: false,
+                        permissions: permissions.clone(),
+                        max_intrusiveness: Intrusiveness::default(),
+                        volatile_ranges,
+                        target_writable_flash,
+                        mediated_regions,
+                        peripheral_freezes,
+                        attach_report,
+                        pending_cores,
                     };
 
-                    {
-                        // Wait for the core to be halted
-                        let mut core = session.core(0)?;
-                        core.wait_for_core_halted(Duration::from_millis(100))?;
-                    }
-
-                    {
-                        let interface = session.get_arm_interface()?;
-                        let mut memory_interface = interface.memory_interface(default_memory_ap)?;
-                        // we need to halt the chip here
-                        sequence_handle.reset_catch_clear(
-                            &mut memory_interface,
-                            config.core_type,
-                            arm_core_access_options.debug_base,
-                        )?;
-                    }
-
-                    {
-                        let mut core = session.core(0)?;
-                        core.wait_for_core_halted(Duration::from_millis(100))?;
+                    if vector_catch {
+                        {
+                            // Wait for the core to be halted
+                            let mut core = session.core(0)?;
+                            core.wait_for_core_halted(Duration::from_millis(100))?;
+                        }
+
+                        {
+                            let interface = session.get_arm_interface()?;
+                            let mut memory_interface =
+                                interface.memory_interface(default_memory_ap)?;
+                            // we need to halt the chip here
+                            sequence_handle.reset_catch_clear(
+                                &mut memory_interface,
+                                config.core_type,
+                                arm_core_access_options.debug_base,
+                            )?;
+                        }
+
+                        {
+                            let mut core = session.core(0)?;
+                            core.wait_for_core_halted(Duration::from_millis(100))?;
+                        }
+                    } else {
+                        // Without the vector catch, the core runs until it is halted.
+                        session.core(0)?.halt(Duration::from_millis(100))?;
                     }
 
                     session
@@ -258,10 +579,39 @@ impl Session {
                     Session {
                         target,
                         interface: ArchitectureInterface::Arm(interface),
+                        route,
+                        initialized_routes: vec![route],
                         cores,
+                        delay_or_poll,
+                        system_power_request: options.system_power_request,
+                        health_log,
+                        keepalive,
+                        interrupt,
+                        drains,
+                        transfers,
+                        journal,
+                        swv_config: None,
+                        errata: Vec::new(),
+                        negotiated_speed,
+                        slow_clock_attach,
+                        probe_capabilities,
+                        probe_firmware_adjustments,
+                        probe_description,
+                        detach_mode: options.detach_mode,
+                        closed: false,
+                        permissions: permissions.clone(),
+                        max_intrusiveness: Intrusiveness::default(),
+                        volatile_ranges,
+                        target_writable_flash,
+                        mediated_regions,
+                        peripheral_freezes,
+                        attach_report,
+                        pending_cores,
                     }
                 };
 
+                session.halt_initialized_cores()?;
+
                 session
             }
             Architecture::Riscv => {
@@ -276,34 +626,445 @@ impl Session {
 
                 probe.inner_attach()?;
 
-                let interface = probe
+                let mut interface = probe
                     .try_into_riscv_interface()
                     .map_err(|(_probe, err)| err)?;
 
+                // Todo: The quirks of the first core apply to the whole Debug Module for now.
+                if let Some(CoreAccessOptions::Riscv(options)) =
+                    target.cores.first().map(|core| &core.core_access_options)
+                {
+                    interface.apply_quirks(options.quirks)?;
+                }
+
                 let mut session = Session {
                     target,
                     interface: ArchitectureInterface::Riscv(Box::new(interface)),
+                    route,
+                    initialized_routes: vec![route],
                     cores,
+                    delay_or_poll,
+                    system_power_request: options.system_power_request,
+                    health_log,
+                    keepalive,
+                    interrupt,
+                    drains,
+                    transfers,
+                    journal,
+                    swv_config: None,
+                    errata: Vec::new(),
+                    negotiated_speed,
+                    slow_clock_attach,
+                    probe_capabilities,
+                    probe_firmware_adjustments,
+                    probe_description,
+                    detach_mode: options.detach_mode,
+                    closed: false,
+                    permissions,
+                    max_intrusiveness: Intrusiveness::default(),
+                    volatile_ranges,
+                    target_writable_flash,
+                    mediated_regions,
+                    peripheral_freezes,
+                    attach_report,
+                    pending_cores,
                 };
 
-                {
-                    // Todo: Add multicore support. How to deal with any cores that are not active and won't respond?
-                    let mut core = session.core(0)?;
-
-                    core.halt(Duration::from_millis(100))?;
-                }
+                // Todo: Add multicore support. How to deal with any cores that are not active and won't respond?
+                session.halt_initialized_cores()?;
 
-                sequence_handle.on_connect(session.get_riscv_interface()?)?;
+                let delay_or_poll = session.delay_or_poll.clone();
+                sequence_handle.on_connect(session.get_riscv_interface()?, &delay_or_poll)?;
 
                 session
             }
         };
 
-        session.clear_all_hw_breakpoints()?;
+        session.reset_planned_cores(&plan)?;
+
+        if session.permissions.guard_policy() == Some(GuardPolicy::NonIntrusiveReads) {
+            log::info!("Deferring the setup of the session until the firmware is verified");
+            session
+                .attach_report
+                .deviations
+                .push(AttachDeviation::DeferredByGuard);
+        } else {
+            session.complete_attach()?;
+        }
+
+        Ok(session)
+    }
+
+    /// Open a new session with the target `target` through `probe`, which attaches to the
+    /// cores as `plan` says, see [`AttachPlan`].
+    ///
+    /// The plan is validated against the target before anything is sent to it. What was
+    /// done while attaching, and where the session had to deviate from the plan, is returned
+    /// by [`Session::attach_report`].
+    pub fn attach_with_plan(
+        probe: Probe,
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        options: AttachOptions,
+        plan: AttachPlan,
+    ) -> Result<Session, Error> {
+        probe.attach_planned(target.into(), permissions, options, plan)
+    }
+
+    /// Returns what was done to the cores while attaching, and where the session had to
+    /// deviate from the [`AttachPlan`].
+    pub fn attach_report(&self) -> &AttachReport {
+        &self.attach_report
+    }
+
+    /// Halt the cores whose directive is [`CoreDirective::Initialize`] with `halt` set.
+    fn halt_initialized_cores(&mut self) -> Result<(), Error> {
+        for n in 0..self.cores.len() {
+            if self.attach_report.directives[n] == (CoreDirective::Initialize { halt: true }) {
+                self.core(n)?.halt(Duration::from_millis(100))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reset and halt the cores whose directive is [`CoreDirective::ResetAndHalt`], unless
+    /// they were reset through the reset line already.
+    fn reset_planned_cores(&mut self, plan: &AttachPlan) -> Result<(), Error> {
+        for n in 0..self.cores.len() {
+            let reset_line_used = plan.resets_through_reset_line(&self.target, n)
+                && !self
+                    .attach_report
+                    .deviations
+                    .contains(&AttachDeviation::ResetLineUnavailable { core: n });
+
+            if self.attach_report.directives[n] != CoreDirective::ResetAndHalt || reset_line_used {
+                continue;
+            }
+
+            if !plan.catches_vector() {
+                self.attach_report
+                    .deviations
+                    .push(AttachDeviation::VectorCaught { core: n });
+            }
+
+            self.core(n)?.reset_and_halt(Duration::from_millis(100))?;
+            self.attach_report.vector_caught = true;
+
+            for core in plan.reset_with(&self.target, n) {
+                self.attach_report.record_reset(core, n);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set up the session after attaching, which writes to the target.
+    fn complete_attach(&mut self) -> Result<(), Error> {
+        let initialized: Vec<_> = (0..self.cores.len())
+            .filter(|n| !self.pending_cores.contains_key(n))
+            .collect();
+
+        for &n in &initialized {
+            self.activate_errata(n)?;
+        }
+
+        // The cores behind other routes are set up when they are accessed the first time.
+        for n in self.cores_on_route(self.route) {
+            if initialized.contains(&n) {
+                self.core_unchecked(n)?.clear_all_hw_breakpoints()?;
+            }
+        }
+
+        if let Some(selection) = self.peripheral_freezes.selection() {
+            match initialized.first() {
+                Some(&n) => {
+                    log::info!("Freezing {:?} while the cores are halted", selection);
+
+                    let freezes = self.peripheral_freezes.clone();
+                    freezes.apply(&mut self.core_unchecked(n)?)?;
+                    self.attach_report.freeze_applied = true;
+                }
+                None => {
+                    log::warn!("No core was initialized to freeze {:?} through", selection);
+                    self.attach_report
+                        .deviations
+                        .push(AttachDeviation::FreezeNotApplied);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set up the core `n`, which was not set up while attaching, when it is accessed for
+    /// the first time.
+    fn set_up_pending_core(&mut self, n: usize, directive: CoreDirective) -> Result<(), Error> {
+        log::debug!("Setting up core {} at its first use ({:?})", n, directive);
+
+        self.activate_errata(n)?;
+
+        if directive != CoreDirective::DeferUntilFirstUse {
+            return Ok(());
+        }
+
+        if let (CoreAccessOptions::Arm(_), DebugSequence::Arm(sequence)) = (
+            &self.target.cores[n].core_access_options,
+            self.target.debug_sequence_for(Architecture::Arm),
+        ) {
+            if let ArchitectureInterface::Arm(interface) = &mut self.interface {
+                start_arm_core(interface, sequence.as_ref(), &self.target.cores[n])?;
+            }
+        }
+
+        self.attach_core(n)?.clear_all_hw_breakpoints()
+    }
+
+    /// Open a new session with the target `target` through `probe`, which is only usable if the
+    /// firmware of the target matches the fingerprints of `guard`.
+    ///
+    /// The fingerprinted regions are read without halting the cores. If any of them doesn't
+    /// match, [`Error::FirmwareMismatch`] is returned with the memory of the region, e.g. to
+    /// report which version of the firmware the target runs, and the session is closed without
+    /// writing to the target. Until the fingerprints are verified, any intrusive access fails
+    /// with [`Error::MissingPermissions`], and what the session does while attaching is limited
+    /// by the [`GuardPolicy`] of the guard.
+    ///
+    /// A guarded session can't be unlocked otherwise. To debug a target regardless of its
+    /// firmware, attach with [`Probe::attach`].
+    pub fn attach_guarded(
+        probe: Probe,
+        target: impl Into<TargetSelector>,
+        permissions: Permissions,
+        guard: AttachGuard,
+    ) -> Result<Session, Error> {
+        let mut session = Session::new(
+            probe,
+            target.into(),
+            AttachPlan::new(),
+            permissions.guarded(guard.guard_policy()),
+            AttachOptions::default(),
+        )?;
+
+        let mismatches = guard.verify(&mut session)?;
+        if !mismatches.is_empty() {
+            return Err(Error::FirmwareMismatch { mismatches });
+        }
+
+        log::info!(
+            "The firmware matches all {} fingerprints",
+            guard.fingerprints().len()
+        );
+
+        if session.permissions.unlock() == Some(GuardPolicy::NonIntrusiveReads) {
+            session.complete_attach()?;
+        }
 
         Ok(session)
     }
 
+    /// Activate the workarounds for the errata of the target which affect the core `n`.
+    fn activate_errata(&mut self, n: usize) -> Result<(), Error> {
+        if self.target.errata.is_empty() {
+            return Ok(());
+        }
+
+        let ids = self.target.errata.clone();
+
+        let nvm_ranges: Vec<_> = self
+            .target
+            .memory_map
+            .iter()
+            .filter_map(|region| match region {
+                MemoryRegion::Nvm(region) => Some(region.range.clone()),
+                _ => None,
+            })
+            .collect();
+
+        let errata = errata::affecting(&mut self.core_unchecked(n)?, &ids)?;
+
+        for erratum in &errata {
+            log::info!(
+                "Applying the workaround for erratum '{}' to core {}: {}",
+                erratum.id,
+                n,
+                erratum.description
+            );
+
+            self.errata.push(ActiveErratum { core: n, erratum });
+        }
+
+        self.cores[n]
+            .1
+            .set_errata(CoreErrata::new(errata, nvm_ranges));
+
+        Ok(())
+    }
+
+    /// Returns the protocol speed negotiated while attaching, if the session was attached
+    /// with [`AttachOptions::auto_speed`].
+    pub fn negotiated_speed_khz(&self) -> Option<u32> {
+        self.negotiated_speed
+    }
+
+    /// Returns the speeds of both phases of the attach, if the target was attached at a low
+    /// speed first, see [`AttachOptions::slow_clock_attach`].
+    pub fn slow_clock_attach(&self) -> Option<SlowClockAttach> {
+        self.slow_clock_attach
+    }
+
+    /// Returns the protocols, features and limits of the probe of the session, see
+    /// [`Probe::capabilities`].
+    pub fn probe_capabilities(&self) -> ProbeCapabilities {
+        self.probe_capabilities
+    }
+
+    /// Returns the adjustments of the capabilities of the probe of the session to the version
+    /// of its firmware, see [`Probe::firmware_adjustments`].
+    pub fn probe_firmware_adjustments(&self) -> &[FirmwareAdjustment] {
+        &self.probe_firmware_adjustments
+    }
+
+    /// Returns an error if the probe lacks `capability`, i.e. `available` is false.
+    fn require_capability(&self, available: bool, capability: String) -> Result<(), Error> {
+        if available {
+            Ok(())
+        } else {
+            Err(DebugProbeError::MissingCapability {
+                capability,
+                probe: self.probe_description.clone(),
+            }
+            .into())
+        }
+    }
+
+    /// Stop the peripherals of `selection` while the cores are halted, e.g. so that a
+    /// watchdog doesn't reset the target while it sits at a breakpoint.
+    ///
+    /// The freeze bits of the peripherals are listed in the target description. They are
+    /// written immediately, and again after every reset of a core, including a reset by the
+    /// target itself which is detected while the status of a core is read. Peripherals
+    /// which are not selected are returned to running while the cores are halted. The
+    /// selection can also be made while attaching, with
+    /// [`AttachOptions::freeze_peripherals_on_halt`].
+    ///
+    /// Fails with [`Error::UnknownPeripheralFreeze`] if the target has no freeze bit for a
+    /// peripheral of the selection. If the target has no freeze bits for the selection at
+    /// all, a warning is logged instead.
+    pub fn freeze_peripherals_on_halt(&mut self, selection: FreezeSelection) -> Result<(), Error> {
+        let mut freezes = self.peripheral_freezes.clone();
+        freezes.select(selection)?;
+        freezes.apply(&mut self.core(0)?)?;
+
+        for (_, core_state) in &mut self.cores {
+            core_state.set_peripheral_freezes(freezes.clone());
+        }
+        self.peripheral_freezes = freezes;
+
+        Ok(())
+    }
+
+    /// Read which peripherals are stopped while the cores are halted from the target, for
+    /// all peripherals with a freeze bit in the target description.
+    ///
+    /// The bits are read back, so this also shows bits which were set by the firmware, or
+    /// writes which were ignored by the target.
+    pub fn frozen_peripherals(&mut self) -> Result<Vec<FrozenPeripheral>, Error> {
+        let freezes = self.peripheral_freezes.clone();
+
+        freezes.read(&mut self.core(0)?)
+    }
+
+    /// Returns the errata whose workarounds are applied to the cores of the session.
+    ///
+    /// The errata of a target are listed in its target description. Their workarounds are
+    /// activated when the session is opened, for the cores and core revisions they affect.
+    pub fn active_errata(&self) -> &[ActiveErratum] {
+        &self.errata
+    }
+
+    /// Describes everything the session knows about the attached system, e.g. to hand it to
+    /// another tool, see [`SystemDescription`].
+    ///
+    /// The description is assembled from what was discovered while attaching, and nothing is
+    /// sent to the target.
+    pub fn system_description(&self) -> SystemDescription {
+        let debug_interface = match &self.interface {
+            ArchitectureInterface::Arm(interface) => {
+                let mut access_ports: Vec<_> = interface
+                    .access_ports()
+                    .iter()
+                    .map(AccessPortDescriptor::new)
+                    .collect();
+                access_ports.sort_by_key(AccessPortDescriptor::address);
+
+                DebugInterfaceDescriptor::Arm { access_ports }
+            }
+            ArchitectureInterface::Riscv(interface) => {
+                DebugInterfaceDescriptor::Riscv(interface.debug_module())
+            }
+            // Nothing is known about a debug interface which was lost.
+            ArchitectureInterface::Lost(InterfaceRoute::Arm) => DebugInterfaceDescriptor::Arm {
+                access_ports: Vec::new(),
+            },
+            ArchitectureInterface::Lost(InterfaceRoute::Riscv { .. }) => {
+                DebugInterfaceDescriptor::Riscv(DebugModuleDescriptor {
+                    version: "none".to_string(),
+                    progbuf_size: 0,
+                    implicit_ebreak: false,
+                    data_registers: 0,
+                    scratch_registers: 0,
+                    shadowed_data_words: 0,
+                    shadowed_data_address: 0,
+                    supports_autoexec: false,
+                    hartsellen: 0,
+                    harts: 0,
+                })
+            }
+        };
+
+        SystemDescription {
+            version: SYSTEM_DESCRIPTION_VERSION,
+            target: TargetIdentity::new(&self.target),
+            cores: self
+                .target
+                .cores
+                .iter()
+                .zip(&self.cores)
+                .map(|(config, (core, _))| {
+                    CoreDescriptor::new(config, core.registers(), core.attach_state())
+                })
+                .collect(),
+            memory_map: self
+                .target
+                .memory_map
+                .iter()
+                .map(RegionDescriptor::new)
+                .collect(),
+            debug_interface,
+            probe: ProbeDescriptor {
+                name: self.probe_description.clone(),
+                capabilities: self.probe_capabilities,
+                firmware_adjustments: self.probe_firmware_adjustments.clone(),
+            },
+            settings: SessionSettings {
+                protocol: self.active_protocol(),
+                negotiated_speed_khz: self.negotiated_speed,
+                keepalive_interval_ms: self
+                    .keepalive_interval()
+                    .map(|interval| interval.as_millis() as u64),
+                max_intrusiveness: self.max_intrusiveness,
+            },
+            errata: self.errata.iter().map(ErratumDescriptor::new).collect(),
+            warnings: self
+                .health_log
+                .entries()
+                .iter()
+                .map(SessionWarning::new)
+                .collect(),
+        }
+    }
+
     /// Automatically creates a session with the first connected probe found.
     pub fn auto_attach(
         target: impl Into<TargetSelector>,
@@ -346,74 +1107,492 @@ impl Session {
     ///
     /// The idea behind this is: You need the smallest common denominator which you can share between threads. Since you sometimes need the [Core], sometimes the [Probe] or sometimes the [Target], the [Session] is the only common ground and the only handle you should actively store in your code.
     ///
+    /// Fails with [`Error::MissingPermissions`] if the session was attached with
+    /// [`Session::attach_guarded`], and the firmware isn't verified.
     pub fn core(&mut self, n: usize) -> Result<Core<'_>, Error> {
-        let (core, core_state) = self.cores.get_mut(n).ok_or(Error::CoreNotFound(n))?;
-        self.interface.attach(core, core_state, &self.target)
+        self.permissions.intrusive_access()?;
+
+        self.core_unchecked(n)
     }
 
-    /// Read available data from the SWO interface without waiting.
-    ///
-    /// This method is only supported for ARM-based targets, and will
-    /// return [Error::ArchitectureRequired] otherwise.
-    pub fn read_swo(&mut self) -> Result<Vec<u8>, Error> {
-        let interface = self.get_arm_interface()?;
-        interface.read_swo()
+    /// Get access to the core `n`, even if the firmware of a guarded session isn't verified.
+    fn core_unchecked(&mut self, n: usize) -> Result<Core<'_>, Error> {
+        self.keep_alive()?;
+        self.keepalive.touch(Instant::now());
+
+        self.route_to_core(n)
+            .map_err(|e| self.health_log.attach_to(e))?;
+
+        if let Some(directive) = self.pending_cores.remove(&n) {
+            self.set_up_pending_core(n, directive)?;
+        }
+
+        self.attach_core(n)
     }
 
-    /// Returns an implementation of [std::io::Read] that wraps [SwoAccess::read_swo].
-    ///
-    /// The implementation buffers all available bytes from
-    /// [SwoAccess::read_swo] on each [std::io::Read::read],
-    /// minimizing the chance of a target-side overflow event on which
-    /// trace packets are lost.
-    ///
-    /// [SwoAccess::read_swo]: crate::architecture::arm::swo::SwoAccess
-    pub fn swo_reader(&mut self) -> Result<SwoReader, Error> {
-        let interface = self.get_arm_interface()?;
-        Ok(SwoReader::new(interface))
+    /// Attach to the core `n` through the active debug interface, without switching the route.
+    fn attach_core(&mut self, n: usize) -> Result<Core<'_>, Error> {
+        let (core, core_state) = self.cores.get_mut(n).ok_or(Error::CoreNotFound(n))?;
+        self.interface
+            .attach(core, core_state, &self.target)
+            .map_err(|e| self.health_log.attach_to(e))
     }
 
-    /// Get the Arm probe interface.
-    pub fn get_arm_interface(&mut self) -> Result<&mut Box<dyn ArmProbeInterface>, Error> {
-        let interface = match &mut self.interface {
-            ArchitectureInterface::Arm(state) => state,
-            _ => return Err(Error::ArchitectureRequired(&["ARMv7", "ARMv8"])),
-        };
+    /// The indices of the cores behind `route`.
+    fn cores_on_route(&self, route: InterfaceRoute) -> Vec<usize> {
+        self.target
+            .cores
+            .iter()
+            .enumerate()
+            .filter(|(_, core)| InterfaceRoute::of(core) == route)
+            .map(|(index, _)| index)
+            .collect()
+    }
 
-        Ok(interface)
+    /// Switch the probe to the debug interface of the core `n`, see [`Session::switch_route`].
+    fn route_to_core(&mut self, n: usize) -> Result<(), Error> {
+        let config = self.target.cores.get(n).ok_or(Error::CoreNotFound(n))?;
+
+        self.switch_route(InterfaceRoute::of(config))
     }
 
-    fn get_riscv_interface(&mut self) -> Result<&mut Box<RiscvCommunicationInterface>, Error> {
-        let interface = match &mut self.interface {
-            ArchitectureInterface::Riscv(interface) => interface,
-            _ => return Err(Error::ArchitectureRequired(&["Riscv"])),
-        };
+    /// Switch the probe to the debug interface of the first core with the architecture
+    /// `architecture`, unless the active debug interface is of that architecture already.
+    ///
+    /// Nothing is switched if the target has no such core.
+    fn route_to_architecture(&mut self, architecture: Architecture) -> Result<(), Error> {
+        if self.route.architecture() == architecture {
+            return Ok(());
+        }
 
-        Ok(interface)
+        match self
+            .target
+            .cores
+            .iter()
+            .position(|core| core.core_type.architecture() == architecture)
+        {
+            Some(n) => self.route_to_core(n),
+            None => Ok(()),
+        }
     }
 
-    /// Reads all the available ARM CoresightComponents of the currently attached target.
+    /// Switch the probe to the debug interface of `route`, on targets which mix ARM and
+    /// RISC-V cores on one debug port.
     ///
-    /// This will recursively parse the Romtable of the attached target
-    /// and create a list of all the contained components.
-    pub fn get_arm_components(&mut self) -> Result<Vec<CoresightComponent>, Error> {
-        let interface = self.get_arm_interface()?;
+    /// The active debug interface is closed, the [`RouteSequence`] of the target connects the
+    /// debug port to the route, and the probe is turned into the debug interface of the
+    /// route. The first time a route is used, its debug interface is set up like the one of
+    /// the first core while attaching. The cores keep their debug state while another route
+    /// is active.
+    ///
+    /// If the probe is lost on the way, e.g. because the debug interface of the route can't
+    /// be initialized, every further access fails with [`Error::DebugInterfaceLost`].
+    ///
+    /// [`RouteSequence`]: crate::architecture::route::RouteSequence
+    fn switch_route(&mut self, route: InterfaceRoute) -> Result<(), Error> {
+        if route == self.route {
+            return Ok(());
+        }
 
-        let mut components = Vec::new();
+        log::info!("Switching from the {} to the {}", self.route, route);
 
-        // TODO
-        let dp = DpAddress::Default;
+        let mut probe =
+            match std::mem::replace(&mut self.interface, ArchitectureInterface::Lost(route)) {
+                ArchitectureInterface::Arm(interface) => interface.close(),
+                ArchitectureInterface::Riscv(interface) => interface.close(),
+                ArchitectureInterface::Lost(_) => return Err(Error::DebugInterfaceLost(route)),
+            };
 
-        for ap_index in 0..(interface.num_access_ports(dp)? as u8) {
+        let from = std::mem::replace(&mut self.route, route);
+
+        self.target
+            .route_sequence
+            .clone()
+            .select_route(&mut probe, from, route)?;
+        self.interface = self.open_route(probe, route)?;
+
+        if !self.initialized_routes.contains(&route) {
+            self.initialize_route(route)?;
+            self.initialized_routes.push(route);
+
+            for n in self.cores_on_route(route) {
+                if !self.pending_cores.contains_key(&n) {
+                    self.attach_core(n)?.clear_all_hw_breakpoints()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Turn `probe` into the debug interface of `route`.
+    fn open_route(
+        &self,
+        probe: Probe,
+        route: InterfaceRoute,
+    ) -> Result<ArchitectureInterface, Error> {
+        match self.target.debug_sequence_for(route.architecture()) {
+            DebugSequence::Arm(sequence) => {
+                let interface = probe.try_into_arm_interface().map_err(|(_, err)| err)?;
+
+                let mut interface = interface.initialize(sequence)?;
+                interface.set_power_policy(self.delay_or_poll.clone(), self.system_power_request);
+
+                Ok(ArchitectureInterface::Arm(interface))
+            }
+            DebugSequence::Riscv(_) => {
+                let mut interface = probe.try_into_riscv_interface().map_err(|(_, err)| err)?;
+
+                // Like while attaching, the quirks of the first core behind the route apply
+                // to the whole Debug Module.
+                let quirks = self
+                    .target
+                    .cores
+                    .iter()
+                    .filter(|core| InterfaceRoute::of(core) == route)
+                    .find_map(|core| match &core.core_access_options {
+                        CoreAccessOptions::Riscv(options) => Some(options.quirks),
+                        CoreAccessOptions::Arm(_) => None,
+                    });
+                if let Some(quirks) = quirks {
+                    interface.apply_quirks(quirks)?;
+                }
+                interface.set_max_intrusiveness(self.max_intrusiveness);
+
+                Ok(ArchitectureInterface::Riscv(Box::new(interface)))
+            }
+        }
+    }
+
+    /// Set up the debug interface of `route`, the first time it is used.
+    fn initialize_route(&mut self, route: InterfaceRoute) -> Result<(), Error> {
+        match (
+            self.target.debug_sequence_for(route.architecture()),
+            &mut self.interface,
+        ) {
+            (DebugSequence::Arm(sequence), ArchitectureInterface::Arm(interface)) => {
+                let default_memory_ap = self
+                    .target
+                    .cores
+                    .iter()
+                    .find_map(|core| match &core.core_access_options {
+                        CoreAccessOptions::Arm(options) => Some(MemoryAp::new(ApAddress {
+                            dp: match options.psel {
+                                0 => DpAddress::Default,
+                                x => DpAddress::Multidrop(x),
+                            },
+                            ap: options.ap,
+                        })),
+                        CoreAccessOptions::Riscv(_) => None,
+                    })
+                    .ok_or(Error::DebugInterfaceLost(route))?;
+
+                sequence.debug_device_unlock(
+                    interface,
+                    default_memory_ap,
+                    &self.permissions,
+                    &self.delay_or_poll,
+                )?;
+
+                start_arm_cores(
+                    interface,
+                    sequence.as_ref(),
+                    &self.target,
+                    &self.pending_cores,
+                )
+            }
+            (DebugSequence::Riscv(sequence), ArchitectureInterface::Riscv(interface)) => {
+                sequence.on_connect(interface, &self.delay_or_poll)
+            }
+            _ => Err(Error::DebugInterfaceLost(route)),
+        }
+    }
+
+    /// Read `data.len()` bytes at `address` through the memory access port of the core with the
+    /// index `core_index`, without halting the core.
+    ///
+    /// This is a non-intrusive access, which is allowed even if the firmware of a session
+    /// attached with [`Session::attach_guarded`] isn't verified. It is only supported on ARM
+    /// targets.
+    ///
+    /// Intrusiveness: [`ReadMemory`](TargetOperation::ReadMemory), or
+    /// [`ReadDeviceMemory`](TargetOperation::ReadDeviceMemory) outside of RAM and flash.
+    pub fn read_non_intrusive(
+        &mut self,
+        core_index: usize,
+        address: u64,
+        data: &mut [u8],
+    ) -> Result<(), Error> {
+        let config = self
+            .target
+            .cores
+            .get(core_index)
+            .ok_or(Error::CoreNotFound(core_index))?;
+
+        self.require(self.cores[core_index].1.read_operation(address, data.len()))?;
+
+        if let CoreAccessOptions::Arm(_) = config.core_access_options {
+            self.route_to_core(core_index)?;
+        }
+
+        let config = &self.target.cores[core_index];
+        let result = match (&config.core_access_options, &mut self.interface) {
+            (CoreAccessOptions::Arm(options), ArchitectureInterface::Arm(interface)) => {
+                let ap = ApAddress {
+                    dp: match options.psel {
+                        0 => DpAddress::Default,
+                        x => DpAddress::Multidrop(x),
+                    },
+                    ap: options.ap,
+                };
+
+                interface
+                    .memory_interface(MemoryAp::new(ap))
+                    .and_then(|mut memory| memory.read_8(address, data))
+            }
+            _ => Err(Error::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        };
+
+        result.map_err(|e| self.health_log.attach_to(e))
+    }
+
+    /// Read available data from the SWO interface without waiting.
+    ///
+    /// This method is only supported for ARM-based targets, and will
+    /// return [Error::ArchitectureRequired] otherwise.
+    pub fn read_swo(&mut self) -> Result<Vec<u8>, Error> {
+        let interface = self.get_arm_interface()?;
+        let result = interface.read_swo();
+        result.map_err(|e| self.health_log.attach_to(e))
+    }
+
+    /// Returns an implementation of [std::io::Read] that wraps [SwoAccess::read_swo].
+    ///
+    /// The implementation buffers all available bytes from
+    /// [SwoAccess::read_swo] on each [std::io::Read::read],
+    /// minimizing the chance of a target-side overflow event on which
+    /// trace packets are lost.
+    ///
+    /// [SwoAccess::read_swo]: crate::architecture::arm::swo::SwoAccess
+    pub fn swo_reader(&mut self) -> Result<SwoReader, Error> {
+        let interface = self.get_arm_interface()?;
+        Ok(SwoReader::new(interface))
+    }
+
+    /// Get the Arm probe interface.
+    pub fn get_arm_interface(&mut self) -> Result<&mut Box<dyn ArmProbeInterface>, Error> {
+        self.permissions.intrusive_access()?;
+        self.keep_alive()?;
+        self.keepalive.touch(Instant::now());
+        self.route_to_architecture(Architecture::Arm)?;
+
+        let interface = match &mut self.interface {
+            ArchitectureInterface::Arm(state) => state,
+            _ => return Err(Error::ArchitectureRequired(&["ARMv7", "ARMv8"])),
+        };
+
+        Ok(interface)
+    }
+
+    /// Returns a typed wrapper of the vendor specific AP `A` of the target, e.g. of the
+    /// [`NordicCtrlAp`](crate::architecture::arm::ap::NordicCtrlAp).
+    ///
+    /// The APs of the default debug port are searched for the first one whose IDR identifies
+    /// it as `A`. Vendor specific APs without a wrapper can be accessed with the raw register
+    /// functions of the [`ArmProbeInterface`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use probe_rs::{Permissions, Session};
+    /// use probe_rs::architecture::arm::ap::NordicCtrlAp;
+    ///
+    /// let mut session = Session::auto_attach("nrf52832_xxAA", Permissions::default())?;
+    /// let mut ctrl_ap = session.vendor_ap::<NordicCtrlAp>()?;
+    ///
+    /// if ctrl_ap.approtect_status()?.is_enabled() {
+    ///     println!("The access port protection is enabled");
+    /// }
+    /// # Ok::<(), probe_rs::Error>(())
+    /// ```
+    ///
+    /// Intrusiveness: [`VendorAccessPort`](TargetOperation::VendorAccessPort).
+    pub fn vendor_ap<'session, A: VendorAp<'session>>(&'session mut self) -> Result<A, Error> {
+        self.require(TargetOperation::VendorAccessPort)?;
+
+        let interface = self.get_arm_interface()?;
+
+        // TODO: Search all debug ports of multidrop targets.
+        let dp = DpAddress::Default;
+
+        let mut found = None;
+
+        for ap in 0..(interface.num_access_ports(dp)? as u8) {
+            let address = ApAddress { dp, ap };
+
+            if A::matches_idr(interface.read_raw_ap_register(address, IDR::ADDRESS)?) {
+                found = Some(address);
+                break;
+            }
+        }
+
+        match found {
+            Some(address) => Ok(A::new_unchecked(interface.as_mut(), address)),
+            None => Err(AccessPortError::VendorApNotFound { name: A::NAME }.into()),
+        }
+    }
+
+    fn get_riscv_interface(&mut self) -> Result<&mut Box<RiscvCommunicationInterface>, Error> {
+        self.route_to_architecture(Architecture::Riscv)?;
+
+        let interface = match &mut self.interface {
+            ArchitectureInterface::Riscv(interface) => interface,
+            _ => return Err(Error::ArchitectureRequired(&["Riscv"])),
+        };
+
+        Ok(interface)
+    }
+
+    /// Returns the protocol used to communicate with the target, if it is known.
+    pub fn active_protocol(&self) -> Option<WireProtocol> {
+        match &self.interface {
+            ArchitectureInterface::Arm(interface) => interface.active_protocol(),
+            ArchitectureInterface::Riscv(_) => Some(WireProtocol::Jtag),
+            ArchitectureInterface::Lost(_) => None,
+        }
+    }
+
+    /// Switch the protocol used to communicate with the target, without detaching.
+    ///
+    /// The debug ports are set up again with the new protocol, but the target is not reset,
+    /// so the state of the cores, including their breakpoints, is preserved. Because the
+    /// connection is briefly lost, this is refused while a core is running, unless `force`
+    /// is set.
+    ///
+    /// Only ARM targets can switch the protocol, and only with probes which support both
+    /// protocols and can switch them while attached.
+    pub fn switch_protocol(&mut self, protocol: WireProtocol, force: bool) -> Result<(), Error> {
+        if self.active_protocol() == Some(protocol) {
+            return Ok(());
+        }
+
+        if let ArchitectureInterface::Riscv(_) = self.interface {
+            return Err(DebugProbeError::ProtocolNotSupported {
+                requested: protocol,
+                supported: vec![WireProtocol::Jtag],
+            }
+            .into());
+        }
+
+        if !force {
+            for n in 0..self.cores.len() {
+                if !self.core(n)?.core_halted()? {
+                    return Err(Error::CoreNotHalted(n));
+                }
+            }
+        }
+
+        log::info!("Switching the protocol to {}", protocol);
+
+        self.get_arm_interface()?.switch_protocol(protocol)?;
+
+        // The debug port was set up again, so the breakpoints have to be read again.
+        for (_, core_state) in &mut self.cores {
+            core_state.invalidate_hw_breakpoints();
+        }
+
+        Ok(())
+    }
+
+    /// Perform the keepalive access, if the session was idle for longer than the
+    /// configured keepalive interval.
+    ///
+    /// This is done automatically before the target is accessed through the session,
+    /// so it only has to be called explicitly for sessions which are idle for a long time.
+    /// If the access fails, the target dropped its debug state and [`Error::TargetLost`]
+    /// is returned.
+    pub fn keep_alive(&mut self) -> Result<(), Error> {
+        let action = match self.keepalive.due(Instant::now()) {
+            Some(action) => action,
+            None => return Ok(()),
+        };
+
+        log::debug!("Keeping the idle connection alive: {:?}", action);
+
+        self.perform_keepalive(action)
+            .map_err(|e| self.health_log.attach_to(Error::TargetLost(Box::new(e))))?;
+
+        self.keepalive.touch(Instant::now());
+
+        Ok(())
+    }
+
+    /// The interval after which an idle connection is kept alive, if the target needs it.
+    pub fn keepalive_interval(&self) -> Option<Duration> {
+        self.keepalive.interval()
+    }
+
+    fn perform_keepalive(&mut self, action: KeepaliveAction) -> Result<(), Error> {
+        match action {
+            KeepaliveAction::ReadDmstatus => {
+                self.route_to_architecture(Architecture::Riscv)?;
+
+                let interface = match &mut self.interface {
+                    ArchitectureInterface::Riscv(interface) => interface,
+                    _ => return Err(Error::ArchitectureRequired(&["Riscv"])),
+                };
+
+                interface.read_dmstatus()?;
+            }
+            KeepaliveAction::ReadDhcsr => {
+                self.route_to_core(0)?;
+                let mut core = self.attach_core(0)?;
+
+                // Reads DHCSR, as a status read which is allowed at any intrusiveness.
+                core.core_halted()?;
+            }
+            KeepaliveAction::WriteWord { address, value } => {
+                if self.require(TargetOperation::WriteMemory).is_err() {
+                    log::debug!(
+                        "Skipping the keepalive write to {:#010x}, it exceeds the maximum intrusiveness",
+                        address
+                    );
+                    return Ok(());
+                }
+
+                self.route_to_core(0)?;
+                let mut core = self.attach_core(0)?;
+
+                core.write_word_32(address, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads all the available ARM CoresightComponents of the currently attached target.
+    ///
+    /// This will recursively parse the Romtable of the attached target
+    /// and create a list of all the contained components.
+    pub fn get_arm_components(&mut self) -> Result<Vec<CoresightComponent>, Error> {
+        let interface = self.get_arm_interface()?;
+
+        let mut components = Vec::new();
+
+        // TODO
+        let dp = DpAddress::Default;
+
+        for ap_index in 0..(interface.num_access_ports(dp)? as u8) {
             let ap_information = interface
                 .ap_information(GenericAp::new(ApAddress { dp, ap: ap_index }))?
                 .clone();
 
             let component = match ap_information {
                 ApInformation::MemoryAp(MemoryApInformation {
+                    address,
                     debug_base_address: 0,
                     ..
-                }) => Err(Error::Other(anyhow!("AP has a base address of 0"))),
+                }) => Err(Error::NoDebugBaseAddress(address)),
                 ApInformation::MemoryAp(MemoryApInformation {
                     address,
                     debug_base_address,
@@ -427,10 +1606,7 @@ impl Session {
                 }
                 ApInformation::Other { address } => {
                     // Return an error, only possible to get Component from MemoryAP
-                    Err(Error::Other(anyhow!(
-                        "AP {:#x?} is not a MemoryAP, unable to get ARM component.",
-                        address
-                    )))
+                    Err(Error::NotAMemoryAccessPort(address))
                 }
             };
 
@@ -447,67 +1623,896 @@ impl Session {
         Ok(components)
     }
 
+    /// Returns how much time was spent waiting for the target to settle,
+    /// e.g. after power state changes during attach.
+    pub fn settle_statistics(&self) -> SettleStatistics {
+        self.delay_or_poll.statistics()
+    }
+
+    /// Returns the log of anomalies which were recovered from during this session.
+    ///
+    /// The most recent entries are also attached to errors returned by the session,
+    /// see [`Error::WithHealthLog`].
+    pub fn health_log(&self) -> &HealthLog {
+        &self.health_log
+    }
+
+    /// Returns a handle to interrupt the operations running on this session.
+    ///
+    /// The handle can be sent to another thread, e.g. a Ctrl-C handler, and interrupts the
+    /// operation which currently holds the session. See [`InterruptHandle`] for details.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Interrupt the current operation of this session.
+    ///
+    /// This is the same as calling [`InterruptHandle::interrupt`] on the handle returned by
+    /// [`Session::interrupt_handle`]. An interrupted operation stops at its next cancellation
+    /// point, and returns [`Error::Interrupted`].
+    pub fn interrupt_current_operation(&self) {
+        self.interrupt.interrupt();
+    }
+
+    /// Drain the circular buffer `buffer` into `sink` while the session is used.
+    ///
+    /// The drain is serviced whenever the session waits anyway, at most every `min_interval`:
+    /// while waiting for a core to halt, and between the chunks of large memory transfers.
+    /// These services are limited to the budget set with [`Session::set_drain_budget`], and
+    /// their errors are only recorded in the [`DrainStatus`] of the drain. All drains are
+    /// serviced on [`Session::service_drains`].
+    ///
+    /// Reading the buffer is [`TargetOperation::ReadMemory`]. The tail pointer of the
+    /// buffer is only written back if the maximum intrusiveness of the session allows
+    /// [`TargetOperation::WriteMemory`]. See the [`CircularBuffer`] for the pointer
+    /// protocols, and how overflows are detected.
+    pub fn register_drain(
+        &mut self,
+        buffer: CircularBuffer,
+        sink: impl DrainSink + 'static,
+        min_interval: Duration,
+    ) -> Result<DrainId, Error> {
+        if buffer.core >= self.cores.len() {
+            return Err(Error::CoreNotFound(buffer.core));
+        }
+
+        self.drains.register(buffer, Box::new(sink), min_interval)
+    }
+
+    /// Stop draining the buffer of the drain `id`, and return its final status.
+    pub fn unregister_drain(&mut self, id: DrainId) -> Option<DrainStatus> {
+        self.drains.unregister(id)
+    }
+
+    /// Returns the status of the drain `id`, if it is registered.
+    pub fn drain_status(&self, id: DrainId) -> Option<DrainStatus> {
+        self.drains.status(id)
+    }
+
+    /// Limit the time the drains may take at one cancellation point to `budget`, 2 ms by
+    /// default. Drains which are due once the budget is exhausted are serviced at the next
+    /// cancellation point first.
+    pub fn set_drain_budget(&mut self, budget: Duration) {
+        self.drains.set_budget(budget);
+    }
+
+    /// Read the new data of all drains, regardless of their interval and of the budget.
+    ///
+    /// Returns the first error of a drain, after it was recorded in its [`DrainStatus`].
+    pub fn service_drains(&mut self) -> Result<(), Error> {
+        for n in self.drains.cores() {
+            self.core(n)?.service_drains()?;
+        }
+
+        Ok(())
+    }
+
+    /// Report the progress of large memory transfers to `progress`, or stop reporting it
+    /// with `None`.
+    ///
+    /// Reported transfers are split into small chunks, after each of which a
+    /// [`TransferEvent`](crate::TransferEvent) with the estimated remaining time is emitted.
+    /// Where the interface of a core counts its low-level operations, like the DMI accesses
+    /// on RISC-V, the estimate is based on the completed operations instead of the
+    /// transferred bytes.
+    pub fn set_transfer_progress(&mut self, progress: Option<TransferProgress>) {
+        self.transfers.set_progress(progress);
+    }
+
+    /// Returns the totals of the memory transfers of this session, e.g. to benchmark the
+    /// cost of a word on each core.
+    pub fn transfer_statistics(&self) -> TransferStatistics {
+        self.transfers.statistics()
+    }
+
+    /// Start to record the operations performed on the cores of this session into an
+    /// [`OperationJournal`], discarding the operations of an earlier recording.
+    ///
+    /// Each operation called on a [`Core`] is recorded with its arguments, its outcome and
+    /// its duration, while the operations it performs on its own behalf are not, see the
+    /// [`OperationJournal`] for the operations which are recorded.
+    pub fn start_operation_journal(&mut self) {
+        self.journal.start();
+    }
+
+    /// Stop recording the operations of the cores, and return the journal of the operations
+    /// since [`Session::start_operation_journal`].
+    pub fn stop_operation_journal(&mut self) -> OperationJournal {
+        let mut journal = OperationJournal::new(self.target.name.clone());
+        journal.entries = self.journal.stop();
+        journal
+    }
+
+    /// Load the operation journal at `path`, and replay its operations on the cores of this
+    /// session, see [`Session::replay_operation_journal`].
+    pub fn replay_journal(
+        &mut self,
+        path: impl AsRef<Path>,
+        options: ReplayOptions,
+    ) -> Result<ReplayReport, Error> {
+        let journal = OperationJournal::load(path)?;
+        self.replay_operation_journal(&journal, options)
+    }
+
+    /// Replay the operations of `journal` on the cores of this session, in order.
+    ///
+    /// An operation diverges from the journal if it fails while it succeeded in the journal,
+    /// or the other way around, or if a read returns other values than in the journal.
+    /// Depending on `options`, the replay stops at the first divergence, or continues and
+    /// reports all of them in the [`ReplayReport`]. Only the outcome of an operation is
+    /// compared, not the error it failed with.
+    ///
+    /// The journal is checked before any operation is replayed. If a value refers to a
+    /// variable which isn't defined, or doesn't fit into the width of its access,
+    /// [`Error::InvalidOperationJournal`] is returned.
+    ///
+    /// The replayed operations are not recorded, even if the operation journal of this
+    /// session is started.
+    pub fn replay_operation_journal(
+        &mut self,
+        journal: &OperationJournal,
+        options: ReplayOptions,
+    ) -> Result<ReplayReport, Error> {
+        let recorder = self.journal.clone();
+
+        recorder.suspend();
+        let report = operation_journal::replay(self, journal, &options);
+        recorder.leave();
+
+        report
+    }
+
+    /// Limit the operations of this session to those which disturb the target at most as much
+    /// as `level`.
+    ///
+    /// The operations of the session and its cores state their [`Intrusiveness`] in their
+    /// documentation. Operations above `level` fail with [`Error::IntrusivenessExceeded`]
+    /// before anything is sent to the target. Software breakpoints are not planned, and
+    /// memory accesses through the program buffer of a RISC-V hart are refused, if `level`
+    /// doesn't allow them. Keepalive writes and, when the session is closed, teardown steps
+    /// above `level` are skipped.
+    ///
+    /// The raw interfaces, e.g. [`Session::get_arm_interface`], are not limited.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use probe_rs::{Intrusiveness, MemoryInterface, Permissions, Session};
+    /// let mut session = Session::auto_attach("nrf52832_xxAA", Permissions::default())?;
+    ///
+    /// // Monitor a counter in RAM, without any risk of disturbing the firmware.
+    /// session.set_max_intrusiveness(Intrusiveness::None);
+    /// let counter = session.core(0)?.read_word_32(0x2000_0000)?;
+    /// # Ok::<(), probe_rs::Error>(())
+    /// ```
+    pub fn set_max_intrusiveness(&mut self, level: Intrusiveness) {
+        self.max_intrusiveness = level;
+
+        for (_, core_state) in &mut self.cores {
+            core_state.set_max_intrusiveness(level);
+        }
+
+        if let ArchitectureInterface::Riscv(interface) = &mut self.interface {
+            interface.set_max_intrusiveness(level);
+        }
+    }
+
+    /// Returns the maximum intrusiveness of the operations of this session, see
+    /// [`Session::set_max_intrusiveness`].
+    pub fn max_intrusiveness(&self) -> Intrusiveness {
+        self.max_intrusiveness
+    }
+
+    /// Mark `range` as volatile, e.g. a mailbox in RAM which is shared with another bus
+    /// master.
+    ///
+    /// Accesses to volatile memory are never repeated by the [`RetryPolicy`] of a core, and
+    /// reads of it count as reads of device memory for the maximum intrusiveness of the
+    /// session. Pass [`Session::volatile_ranges`] to
+    /// [`WriteCoalescer::with_volatile_ranges`](crate::WriteCoalescer::with_volatile_ranges),
+    /// so that writes to it are never merged or reordered.
+    ///
+    /// Generic regions of the memory map, and RAM regions which are marked as `volatile` in
+    /// the target description, are always volatile.
+    pub fn mark_volatile(&mut self, range: Range<u64>) {
+        self.volatile_ranges.mark(range);
+
+        for (_, core_state) in &mut self.cores {
+            core_state.set_volatile_ranges(self.volatile_ranges.clone());
+        }
+    }
+
+    /// Returns the volatile memory of the target, see [`Session::mark_volatile`].
+    pub fn volatile_ranges(&self) -> &VolatileRanges {
+        &self.volatile_ranges
+    }
+
+    /// Mark `range` as flash which the firmware of the target writes itself, e.g. an EEPROM
+    /// emulation or a log in flash.
+    ///
+    /// Downloads with a journal always program the sectors which overlap it, and the
+    /// verification of an image which overlaps it is only a snapshot, see
+    /// [`ImageOutcome::point_in_time`](crate::flashing::ImageOutcome::point_in_time). NVM
+    /// regions which are marked as `target_writable` in the target description are always
+    /// target-writable.
+    pub fn mark_target_writable_flash(&mut self, range: Range<u64>) {
+        self.target_writable_flash.mark(range);
+    }
+
+    /// Report that the firmware of the target wrote `range` of its flash, e.g. when a
+    /// bootloader reports an update.
+    ///
+    /// The next download with a journal programs the sectors which overlap `range`, even if
+    /// the journal records them as verified.
+    pub fn notify_target_flash_write(&mut self, range: Range<u64>) {
+        self.target_writable_flash.notify_write(range);
+    }
+
+    /// Returns the flash which the firmware of the target writes, see
+    /// [`Session::mark_target_writable_flash`].
+    pub fn target_writable_flash(&self) -> &TargetWritableFlash {
+        &self.target_writable_flash
+    }
+
+    pub(crate) fn target_writable_flash_mut(&mut self) -> &mut TargetWritableFlash {
+        &mut self.target_writable_flash
+    }
+
+    /// Mediate the accesses of all cores to `range` with `mediator`, e.g. external flash
+    /// which is only readable while its controller is in memory-mapped mode.
+    ///
+    /// The mediator prepares the region before the first access to it, and its preparation
+    /// is kept for further accesses, see [`AccessMediator`]. Regions added here take
+    /// precedence over the mediated regions of the target description.
+    pub fn add_access_mediator(&mut self, range: Range<u64>, mediator: Arc<dyn AccessMediator>) {
+        self.mediated_regions.add(range, mediator);
+
+        for (_, core_state) in &mut self.cores {
+            core_state.set_mediated_regions(self.mediated_regions.clone());
+        }
+    }
+
+    /// Returns the mediated memory of the target, see [`Session::add_access_mediator`].
+    pub fn mediated_regions(&self) -> &MediatedRegions {
+        &self.mediated_regions
+    }
+
+    /// Returns an error if `operation` exceeds the maximum intrusiveness of the session.
+    fn require(&self, operation: TargetOperation) -> Result<(), Error> {
+        self.max_intrusiveness.permit(operation)
+    }
+
     /// Get the target description of the connected target.
     pub fn target(&self) -> &Target {
         &self.target
     }
 
+    /// Check if the data staged in `image` is likely to boot on the connected target.
+    ///
+    /// See [`FlashLoader::validate`] for the performed checks. The addresses of the image are
+    /// translated with the address map of core 0, see [`Core::set_load_offset`].
+    pub fn validate_image(&self, image: &FlashLoader) -> Vec<ImageIssue> {
+        match self.cores.first() {
+            Some((_, core_state)) => {
+                image.validate_with_address_map(&self.target, core_state.address_map())
+            }
+            None => image.validate(&self.target),
+        }
+    }
+
     /// Configure the target and probe for serial wire view (SWV) tracing.
+    ///
+    /// Fails with [`DebugProbeError::MissingCapability`] before the target is configured if
+    /// the probe can't capture SWO, or not at the baud rate of `config`, and with
+    /// [`DebugProbeError::FirmwareTooOld`] if the firmware of the probe is too old for it.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
     pub fn setup_swv(&mut self, core_index: usize, config: &SwoConfig) -> Result<(), Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        // Check the probe before the target is configured
+        if let Some(adjustment) = firmware::find(
+            &self.probe_firmware_adjustments,
+            FirmwareFeature::Swo,
+            FirmwareLimitation::Unavailable,
+        ) {
+            return Err(adjustment.error().into());
+        }
+        let capabilities = self.probe_capabilities;
+        self.require_capability(capabilities.swo, "SWO capture".to_owned())?;
+        self.require_capability(
+            capabilities
+                .swo_max_baud
+                .map_or(true, |max_baud| config.baud() <= max_baud),
+            format!("SWO capture at {} baud", config.baud()),
+        )?;
+
         // Configure SWO on the probe
         {
             let interface = self.get_arm_interface()?;
             interface.enable_swo(config)?;
         }
 
-        // Enable tracing on the target
-        {
-            let mut core = self.core(core_index)?;
-            crate::architecture::arm::component::enable_tracing(&mut core)?;
-        }
+        // Enable tracing on the target
+        {
+            let mut core = self.core(core_index)?;
+            crate::architecture::arm::component::enable_tracing(&mut core)?;
+        }
+
+        // Configure SWV on the target
+        let components = self.get_arm_components()?;
+        let interface = self.get_arm_interface()?;
+        crate::architecture::arm::component::setup_swv(interface, &components, config)?;
+
+        self.swv_config = Some(*config);
+
+        Ok(())
+    }
+
+    /// Configure the target to stop emitting SWV trace data.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn disable_swv(&mut self, core_index: usize) -> Result<(), Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        crate::architecture::arm::component::disable_swv(&mut self.core(core_index)?)?;
+
+        self.swv_config = None;
+
+        Ok(())
+    }
+
+    /// Returns the frequency in Hz of the clock of the ITM local timestamps, as configured
+    /// with [`Session::setup_swv`].
+    ///
+    /// Returns `None` if SWV is not set up, or local timestamps are disabled. The frequency
+    /// can be used with a [`TimestampCorrelator`] to convert timestamps to host time.
+    ///
+    /// [`TimestampCorrelator`]: crate::architecture::arm::swo::TimestampCorrelator
+    pub fn trace_timebase(&self) -> Option<u32> {
+        self.swv_config
+            .as_ref()
+            .and_then(SwoConfig::timestamp_frequency)
+    }
+
+    /// Begin tracing a memory address over SWV.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn add_swv_data_trace(&mut self, unit: usize, address: u32) -> Result<(), Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        let components = self.get_arm_components()?;
+        let interface = self.get_arm_interface()?;
+        crate::architecture::arm::component::add_swv_data_trace(
+            interface,
+            &components,
+            unit,
+            address,
+        )
+    }
+
+    /// Stop tracing from a given SWV unit
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn remove_swv_data_trace(&mut self, unit: usize) -> Result<(), Error> {
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        let components = self.get_arm_components()?;
+        let interface = self.get_arm_interface()?;
+        crate::architecture::arm::component::remove_swv_data_trace(interface, &components, unit)
+    }
+
+    /// Describes the trace sources, funnels and sinks of the target, with their capabilities
+    /// and current configuration.
+    ///
+    /// The components are found in the ROM tables. Their registers are only read, the trace
+    /// configuration is not changed.
+    ///
+    /// Intrusiveness: [`ReadDeviceMemory`](TargetOperation::ReadDeviceMemory).
+    pub fn trace_topology(
+        &mut self,
+    ) -> Result<crate::architecture::arm::component::TraceTopology, Error> {
+        self.require(TargetOperation::ReadDeviceMemory)?;
+
+        let components = self.get_arm_components()?;
+        let interface = self.get_arm_interface()?;
+        crate::architecture::arm::component::trace_topology(interface, &components)
+    }
+
+    /// Reads the register at `offset` of the CoreSight component at `base_address`.
+    ///
+    /// This gives access to registers which aren't described by [`Session::trace_topology`].
+    ///
+    /// Intrusiveness: [`ReadDeviceMemory`](TargetOperation::ReadDeviceMemory).
+    pub fn read_trace_component_register(
+        &mut self,
+        base_address: u64,
+        offset: u32,
+    ) -> Result<u32, Error> {
+        self.require(TargetOperation::ReadDeviceMemory)?;
+
+        let components = self.get_arm_components()?;
+        let component =
+            crate::architecture::arm::component::find_component_at(&components, base_address)?;
+        component.read_reg(self.get_arm_interface()?, offset)
+    }
+
+    /// Writes `value` to the register at `offset` of the CoreSight component at `base_address`.
+    ///
+    /// A locked component is unlocked through its lock access register for the write, and
+    /// locked again afterwards.
+    ///
+    /// Intrusiveness: [`ConfigureTrace`](TargetOperation::ConfigureTrace).
+    pub fn write_trace_component_register(
+        &mut self,
+        base_address: u64,
+        offset: u32,
+        value: u32,
+    ) -> Result<(), Error> {
+        use crate::architecture::arm::component::{
+            find_component_at, write_unlocked, LiveComponent,
+        };
+
+        self.require(TargetOperation::ConfigureTrace)?;
+
+        let components = self.get_arm_components()?;
+        let component = find_component_at(&components, base_address)?;
+        let interface = self.get_arm_interface()?;
+        write_unlocked(&mut LiveComponent::new(interface, component), offset, value)
+    }
+
+    /// Returns the memory map of the target.
+    #[deprecated = "Use the Session::target function instead"]
+    pub fn memory_map(&self) -> &[MemoryRegion] {
+        &self.target.memory_map
+    }
+
+    /// Return the `Architecture` of the currently connected chip.
+    ///
+    /// On targets which mix ARM and RISC-V cores, this is the architecture of the debug
+    /// interface which is active, i.e. of the core which was accessed last.
+    pub fn architecture(&self) -> Architecture {
+        self.route.architecture()
+    }
+
+    /// Reset the whole target, and halt all cores afterwards.
+    ///
+    /// In contrast to [`Core::reset_and_halt`], this takes into account that resetting a core
+    /// can also reset other cores. All cores are halted before the reset, and their hardware
+    /// breakpoints are restored after the reset.
+    ///
+    /// `timeout` applies to each halt of a core, which returns at most one inner-operation
+    /// quantum after it, see [`Deadline`](crate::Deadline).
+    ///
+    /// Intrusiveness: [`Reset`](TargetOperation::Reset).
+    pub fn reset_system(&mut self, timeout: Duration) -> Result<(), Error> {
+        self.require(TargetOperation::Reset)?;
+
+        self.reset_system_inner(timeout)
+            .map_err(|e| self.health_log.attach_to(e))
+    }
+
+    fn reset_system_inner(&mut self, timeout: Duration) -> Result<(), Error> {
+        let mut breakpoints = Vec::with_capacity(self.cores.len());
+
+        for n in 0..self.cores.len() {
+            let mut core = self.core(n)?;
+            core.halt(timeout)?;
+            breakpoints.push(core.hw_breakpoints()?);
+        }
+
+        // Only reset the cores which are not already reset by the reset of another core.
+        let mut reset_cores = vec![false; self.cores.len()];
+
+        for n in 0..self.cores.len() {
+            if reset_cores[n] {
+                continue;
+            }
+
+            log::debug!("Resetting core {}", n);
+            self.core(n)?.reset_and_halt_coordinated(timeout)?;
+
+            let reset_scope = self.target.cores[n].reset_scope;
+            for (other, other_core) in self.target.cores.iter().enumerate() {
+                if other == n || reset_scope.affects(&other_core.reset_scope) {
+                    reset_cores[other] = true;
+                }
+            }
+        }
+
+        for (n, breakpoints) in breakpoints.into_iter().enumerate() {
+            let mut core = self.core(n)?;
+
+            // Cores which were only reset indirectly are not halted automatically.
+            if !core.core_halted()? {
+                core.halt(timeout)?;
+            }
+
+            core.clear_all_hw_breakpoints()?;
+            for breakpoint in breakpoints.into_iter().flatten() {
+                core.set_hw_breakpoint(breakpoint)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reset a core and halt it, and report where it halted relative to its reset vector.
+    ///
+    /// Depending on the chip, a core might not halt at its reset vector, e.g. because a ROM
+    /// bootloader runs first, or because the debug sequence of the chip interferes with the
+    /// reset vector catch. The [`ResetHaltReport`] states whether the core halted at its reset
+    /// vector, inside a ROM of the memory map, or elsewhere, and which mechanism halted it.
+    ///
+    /// If the core was expected to halt at its reset vector but halted elsewhere, the reset is
+    /// repeated once with a hardware breakpoint at the reset vector.
+    ///
+    /// On Cortex-M cores, the reset vector is read from the vector table VTOR points to once the
+    /// core is halted. On RISC-V cores, the reset vectors of the target description are used.
+    ///
+    /// Intrusiveness: [`Reset`](TargetOperation::Reset).
+    pub fn reset_and_halt_core(
+        &mut self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<ResetHaltReport, Error> {
+        self.require(TargetOperation::Reset)?;
+
+        let core_name = self
+            .target
+            .cores
+            .get(core_index)
+            .map(|core| core.name.clone())
+            .ok_or(Error::CoreNotFound(core_index))?;
+
+        let accessible = |cores: &Vec<String>| cores.is_empty() || cores.contains(&core_name);
+
+        let mut roms = Vec::new();
+        for region in &self.target.memory_map {
+            match region {
+                MemoryRegion::Generic(region) if accessible(&region.cores) => {
+                    roms.push(RomRegion {
+                        name: region.name.clone(),
+                        range: region.range.clone(),
+                    })
+                }
+                MemoryRegion::Nvm(region) if accessible(&region.cores) => {
+                    roms.extend(region.boot_critical_ranges.iter().map(|range| RomRegion {
+                        name: region.name.clone(),
+                        range: range.clone(),
+                    }))
+                }
+                _ => (),
+            }
+        }
+
+        let result = self
+            .core(core_index)
+            .and_then(|mut core| core.reset_and_halt_report(timeout, &roms));
+
+        result.map_err(|e| self.health_log.attach_to(e))
+    }
+
+    /// Halt a core which ignores normal halt requests, escalating through the levels of
+    /// [`HaltEscalation`] up to `max_level`, see [`Core::force_halt`].
+    ///
+    /// In addition to the levels of [`Core::force_halt`], the target is reset through the reset
+    /// pin of the probe with the reset vector catch set, if `max_level` allows
+    /// [`HaltEscalation::HardwareReset`]. This needs an ARM target and a probe which can set its
+    /// pins, see [`ProbeCapabilities::pin_control`], and resets all cores of the target.
+    ///
+    /// Intrusiveness: [`Halt`](TargetOperation::Halt) for levels 1 and 2,
+    /// [`Reset`](TargetOperation::Reset) for the levels above.
+    pub fn force_halt_core(
+        &mut self,
+        core_index: usize,
+        timeout: Duration,
+        max_level: HaltEscalation,
+    ) -> Result<ForceHaltReport, Error> {
+        self.force_halt_core_inner(core_index, timeout, max_level)
+            .map_err(|e| self.health_log.attach_to(e))
+    }
+
+    fn force_halt_core_inner(
+        &mut self,
+        core_index: usize,
+        timeout: Duration,
+        max_level: HaltEscalation,
+    ) -> Result<ForceHaltReport, Error> {
+        let mut attempts = Vec::new();
+
+        let mut halted = self
+            .core(core_index)?
+            .escalate_halt(timeout, max_level, &mut attempts)?;
+
+        if halted.is_none() && max_level >= HaltEscalation::HardwareReset {
+            let level = HaltEscalation::HardwareReset;
+            self.require(level.operation())?;
+
+            let attempt = match self.hardware_reset_and_halt(core_index, timeout) {
+                Err(error @ (Error::Interrupted | Error::TargetLost(_))) => return Err(error),
+                result => HaltAttempt::from_result(level, result),
+            };
+
+            log::debug!("Force halt level {}: {:?}", level.level(), attempt.outcome);
+
+            if attempt.outcome == HaltAttemptOutcome::Halted {
+                halted = Some(level);
+            }
+            attempts.push(attempt);
+        }
+
+        match halted {
+            Some(level) => self.core(core_index)?.force_halt_report(level, attempts),
+            None => Err(Error::ForceHaltFailed { attempts }),
+        }
+    }
+
+    /// Reset the target through the reset pin of the probe, and halt the core `core_index`
+    /// with its reset vector catch.
+    ///
+    /// Returns `Ok(false)` if the target or the probe doesn't support it.
+    fn hardware_reset_and_halt(
+        &mut self,
+        core_index: usize,
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        let config = &self.target.cores[core_index];
+
+        let (options, sequence) = match (
+            &config.core_access_options,
+            self.target.debug_sequence_for(Architecture::Arm),
+        ) {
+            (CoreAccessOptions::Arm(options), DebugSequence::Arm(sequence))
+                if self.probe_capabilities.pin_control =>
+            {
+                (options.clone(), sequence)
+            }
+            _ => return Ok(false),
+        };
+
+        let core_type = config.core_type;
+        let memory_ap = MemoryAp::new(ApAddress {
+            dp: match options.psel {
+                0 => DpAddress::Default,
+                x => DpAddress::Multidrop(x),
+            },
+            ap: options.ap,
+        });
+
+        // The hardware reset resets all cores.
+        for (_, state) in &mut self.cores {
+            state.invalidate_hw_breakpoints();
+        }
+
+        {
+            let interface = self.get_arm_interface()?;
+
+            // Assert nRST like the default `ResetHardwareAssert` sequence, which needs
+            // direct access to the probe.
+            let mut n_reset = Pins(0);
+            n_reset.set_nreset(true);
+            interface.swj_pins(0, n_reset.0 as u32, 0)?;
+
+            let mut memory = interface.memory_interface(memory_ap)?;
+            sequence.reset_catch_set(&mut memory, core_type, options.debug_base)?;
+            sequence.reset_hardware_deassert(&mut memory)?;
+        }
+
+        let result = self.core(core_index)?.wait_for_core_halted(timeout);
+
+        let interface = self.get_arm_interface()?;
+        let mut memory = interface.memory_interface(memory_ap)?;
+        sequence.reset_catch_clear(&mut memory, core_type, options.debug_base)?;
+
+        result.map(|_| true)
+    }
+
+    /// Set hardware breakpoints on the panic handlers of the firmware in the ELF file `elf`.
+    ///
+    /// The symbols of [`PanicBreakOptions::symbols`] are looked up in the ELF file, and on
+    /// Cortex-M cores also the HardFault handler, which is read from the vector table if the
+    /// ELF file has no `HardFault` symbol. A core which halts at one of the breakpoints reports
+    /// [`HaltReason::Panic`](crate::HaltReason::Panic) as its status.
+    ///
+    /// The addresses of the symbols are translated with the address map of the core, see
+    /// [`Core::set_load_offset`]. Symbols which are missing, or for which no hardware
+    /// breakpoint is left, are skipped.
+    /// The returned hooks state which symbols were found and which breakpoints were set.
+    /// The breakpoints of a previous call are cleared first.
+    pub fn break_on_panic(
+        &mut self,
+        elf: &Path,
+        options: &PanicBreakOptions,
+    ) -> Result<Vec<PanicHook>, Error> {
+        let cortex_m = self
+            .target
+            .cores
+            .get(options.core)
+            .map(|core| core.core_type.is_cortex_m())
+            .ok_or(Error::CoreNotFound(options.core))?;
+
+        let data = std::fs::read(elf).map_err(|source| Error::FileRead {
+            path: elf.to_owned(),
+            source,
+        })?;
+        let mut hooks = panic_hooks::resolve(&data, options, cortex_m)?;
+
+        let result = self
+            .core(options.core)
+            .and_then(|mut core| panic_hooks::arm(&mut core, &mut hooks));
+        result.map_err(|e| self.health_log.attach_to(e))?;
+
+        let armed: Vec<_> = hooks
+            .iter()
+            .filter(|hook| hook.armed)
+            .map(|hook| hook.symbol.as_str())
+            .collect();
+        log::info!("Breaking on panics in {:?}", armed);
+
+        Ok(hooks)
+    }
+
+    /// Clear the breakpoints which were set by [`Session::break_on_panic`] on core `core_index`.
+    pub fn clear_break_on_panic(&mut self, core_index: usize) -> Result<(), Error> {
+        let result = self
+            .core(core_index)
+            .and_then(|mut core| core.clear_breakpoint_group(PANIC_BREAKPOINT_GROUP));
+
+        result.map_err(|e| self.health_log.attach_to(e))
+    }
+
+    /// Close the session, and release the probe.
+    ///
+    /// All breakpoints set by probe-rs are removed, tracing is disabled, the cores are resumed
+    /// or halted as selected with [`AttachOptions::detach_mode`], and the debug sequence of the
+    /// target is told that debugging stops. A failing step doesn't abort the teardown, but once
+    /// the link to the target is lost, the remaining steps are skipped.
+    ///
+    /// Steps which exceed the maximum intrusiveness of the session, see
+    /// [`Session::set_max_intrusiveness`], are skipped.
+    ///
+    /// If any step fails, the session is returned with [`Error::CloseFailed`], which lists the
+    /// failed steps, so that closing can be retried. Dropping a session performs the same
+    /// teardown, but only logs the failures, to the log and to the health log.
+    pub fn close(mut self) -> Result<CloseReport, (Self, Error)> {
+        let (report, failures) = self.teardown();
+
+        if !failures.is_empty() {
+            return Err((self, Error::CloseFailed { failures, report }));
+        }
+
+        self.closed = true;
+
+        Ok(report)
+    }
+
+    /// Tear down the session, see [`Session::close`].
+    fn teardown(&mut self) -> (CloseReport, Vec<TeardownFailure>) {
+        let mut teardown = Teardown::default();
+
+        for index in 0..self.cores.len() {
+            let mut report = CoreCloseReport::new(index);
+
+            // The cores behind a route which was never used, and the cores which were left
+            // untouched or deferred and never used, were never touched.
+            if !self
+                .initialized_routes
+                .contains(&InterfaceRoute::of(&self.target.cores[index]))
+                || self.pending_cores.contains_key(&index)
+            {
+                teardown.report.cores.push(report);
+                continue;
+            }
+
+            teardown.run(TeardownStep::ReleaseAccessMediators, Some(index), || {
+                self.core(index)?.release_access_mediators()
+            });
+
+            if let Some(removed) =
+                teardown.run(TeardownStep::ClearHwBreakpoints, Some(index), || {
+                    let mut core = self.core(index)?;
+                    let removed = core.hw_breakpoints()?.iter().flatten().count();
+                    core.clear_all_hw_breakpoints()?;
+                    Ok(removed)
+                })
+            {
+                report.hw_breakpoints_removed = removed;
+            }
+
+            if let Some(removed) =
+                teardown.run(TeardownStep::ClearSwBreakpoints, Some(index), || {
+                    let mut core = self.core(index)?;
+                    let removed = core.sw_breakpoints().len();
+                    core.clear_all_sw_breakpoints()?;
+                    Ok(removed)
+                })
+            {
+                report.sw_breakpoints_removed = removed;
+            }
+
+            if self.cores[index].0.core_type().is_cortex_m() {
+                report.trace_disabled = teardown
+                    .run(TeardownStep::DisableTrace, Some(index), || {
+                        self.disable_swv(index)
+                    })
+                    .is_some();
+            }
 
-        // Configure SWV on the target
-        let components = self.get_arm_components()?;
-        let interface = self.get_arm_interface()?;
-        crate::architecture::arm::component::setup_swv(interface, &components, config)
-    }
+            let detach_mode = self.detach_mode;
+            report.status = teardown.run(TeardownStep::DetachCore, Some(index), || {
+                let mut core = self.core(index)?;
 
-    /// Configure the target to stop emitting SWV trace data.
-    pub fn disable_swv(&mut self, core_index: usize) -> Result<(), Error> {
-        crate::architecture::arm::component::disable_swv(&mut self.core(core_index)?)
-    }
+                match detach_mode {
+                    DetachMode::LeaveAsIs => {}
+                    DetachMode::Resume => {
+                        if core.core_halted()? {
+                            core.run()?;
+                        }
+                    }
+                    DetachMode::Halt => {
+                        if !core.core_halted()? {
+                            core.halt(DETACH_TIMEOUT)?;
+                        }
+                    }
+                }
 
-    /// Begin tracing a memory address over SWV.
-    pub fn add_swv_data_trace(&mut self, unit: usize, address: u32) -> Result<(), Error> {
-        let components = self.get_arm_components()?;
-        let interface = self.get_arm_interface()?;
-        crate::architecture::arm::component::add_swv_data_trace(
-            interface,
-            &components,
-            unit,
-            address,
-        )
-    }
+                core.status()
+            });
 
-    /// Stop tracing from a given SWV unit
-    pub fn remove_swv_data_trace(&mut self, unit: usize) -> Result<(), Error> {
-        let components = self.get_arm_components()?;
-        let interface = self.get_arm_interface()?;
-        crate::architecture::arm::component::remove_swv_data_trace(interface, &components, unit)
-    }
+            teardown.report.cores.push(report);
+        }
 
-    /// Returns the memory map of the target.
-    #[deprecated = "Use the Session::target function instead"]
-    pub fn memory_map(&self) -> &[MemoryRegion] {
-        &self.target.memory_map
-    }
+        // Call any necessary deconfiguration/shutdown hooks.
+        let arm_sequence = match self.target.debug_sequence_for(Architecture::Arm) {
+            DebugSequence::Arm(sequence)
+                if self.initialized_routes.contains(&InterfaceRoute::Arm) =>
+            {
+                Some(sequence)
+            }
+            _ => None,
+        };
 
-    /// Return the `Architecture` of the currently connected chip.
-    pub fn architecture(&self) -> Architecture {
-        match self.interface {
-            ArchitectureInterface::Arm(_) => Architecture::Arm,
-            ArchitectureInterface::Riscv(_) => Architecture::Riscv,
+        if let Some(sequence) = arm_sequence {
+            let max_intrusiveness = self.max_intrusiveness;
+
+            teardown.report.debug_stopped = teardown
+                .run(TeardownStep::StopDebug, None, || {
+                    // Stopping the debug session lets halted cores run again.
+                    max_intrusiveness.permit(TargetOperation::Resume)?;
+                    self.route_to_architecture(Architecture::Arm)?;
+
+                    match &mut self.interface {
+                        ArchitectureInterface::Arm(interface) => {
+                            sequence.debug_core_stop(interface)
+                        }
+                        _ => Err(Error::DebugInterfaceLost(InterfaceRoute::Arm)),
+                    }
+                })
+                .is_some();
         }
+
+        (teardown.report, teardown.failures)
     }
 
     /// Clears all hardware breakpoints on all cores
@@ -522,42 +2527,71 @@ impl Session {
 // This test ensures that [Session] is fully [Send] + [Sync].
 static_assertions::assert_impl_all!(Session: Send);
 
-// TODO tiwalun: Enable again, after rework of Session::new is done.
 impl Drop for Session {
     fn drop(&mut self) {
-        if let Err(err) = { 0..self.cores.len() }.try_for_each(|i| {
-            self.core(i)
-                .and_then(|mut core| core.clear_all_hw_breakpoints())
-        }) {
-            log::warn!("Could not clear all hardware breakpoints: {:?}", err);
+        // A guarded session whose firmware wasn't verified must not write to the target.
+        if self.closed || self.permissions.guard_policy().is_some() {
+            return;
         }
 
-        // Disable tracing for all Cortex-M cores.
-        if let Err(err) = { 0..self.cores.len() }.try_for_each(|i| {
-            let is_cortex_m = self.core(i)?.core_type().is_cortex_m();
-
-            if is_cortex_m {
-                self.disable_swv(i)
-            } else {
-                Ok(())
-            }
-        }) {
-            log::warn!("Could not stop core tracing: {:?}", err);
-        }
+        let (_, failures) = self.teardown();
 
-        // Call any necessary deconfiguration/shutdown hooks.
-        if let DebugSequence::Arm(sequence) = &self.target.debug_sequence {
-            let sequence = sequence.clone();
+        for failure in failures {
+            let details = format!("{:?} failed: {}", failure.step, failure.error);
 
-            // Note(unwrap): We already verified we're using an arm debug sequence, so we should
-            // always be able to access the arm probe interface.
-            let interface = self.get_arm_interface().unwrap();
+            log::warn!("{}", details);
+            self.health_log
+                .record(HealthEvent::TeardownFailed, failure.core, "close", details);
+        }
+    }
+}
 
-            if sequence.debug_core_stop(interface).is_err() {
-                log::warn!("Failed to deconfigure device during shutdown");
-            }
+/// Enable debug mode on every ARM core of `target`, except the `pending` ones.
+///
+/// The cores of other architectures are skipped, they are set up through their own debug
+/// interface.
+fn start_arm_cores(
+    interface: &mut Box<dyn ArmProbeInterface>,
+    sequence: &dyn ArmDebugSequence,
+    target: &Target,
+    pending: &BTreeMap<usize, CoreDirective>,
+) -> Result<(), Error> {
+    for (index, config) in target.cores.iter().enumerate() {
+        if !pending.contains_key(&index) {
+            start_arm_core(interface, sequence, config)?;
         }
     }
+
+    Ok(())
+}
+
+/// Enable debug mode on the core `config`, if it is an ARM core.
+fn start_arm_core(
+    interface: &mut Box<dyn ArmProbeInterface>,
+    sequence: &dyn ArmDebugSequence,
+    config: &crate::config::Core,
+) -> Result<(), Error> {
+    let arm_core_access_options = match &config.core_access_options {
+        CoreAccessOptions::Arm(opt) => opt,
+        CoreAccessOptions::Riscv(_) => return Ok(()),
+    };
+
+    let mem_ap = MemoryAp::new(ApAddress {
+        dp: match arm_core_access_options.psel {
+            0 => DpAddress::Default,
+            x => DpAddress::Multidrop(x),
+        },
+        ap: arm_core_access_options.ap,
+    });
+
+    let mut memory_interface = interface.memory_interface(mem_ap)?;
+
+    sequence.debug_core_start(
+        &mut memory_interface,
+        config.core_type,
+        arm_core_access_options.debug_base,
+        arm_core_access_options.cti_base,
+    )
 }
 
 /// Determine the [Target] from a [TargetSelector].
@@ -661,6 +2695,9 @@ fn get_target_from_selector(
 pub struct Permissions {
     /// When set to true, all memory of the chip may be erased or reset to factory default
     erase_all: bool,
+    /// Set while the firmware of a session attached with [`Session::attach_guarded`] is not
+    /// verified, to the policy of the guard.
+    guard: Option<GuardPolicy>,
 }
 
 impl Permissions {
@@ -689,4 +2726,422 @@ impl Permissions {
             Err(crate::Error::MissingPermissions("erase_all".into()))
         }
     }
+
+    /// Lock all intrusive accesses until the firmware is verified with `policy`.
+    pub(crate) fn guarded(self, policy: GuardPolicy) -> Self {
+        Self {
+            guard: Some(policy),
+            ..self
+        }
+    }
+
+    /// Returns the policy of the guard, if the firmware is not verified yet.
+    pub(crate) fn guard_policy(&self) -> Option<GuardPolicy> {
+        self.guard
+    }
+
+    /// Allow intrusive accesses, and return the policy of the guard.
+    pub(crate) fn unlock(&mut self) -> Option<GuardPolicy> {
+        self.guard.take()
+    }
+
+    pub(crate) fn intrusive_access(&self) -> Result<(), crate::Error> {
+        if self.guard.is_none() {
+            Ok(())
+        } else {
+            Err(crate::Error::MissingPermissions(
+                "intrusive access before the firmware was verified".into(),
+            ))
+        }
+    }
+}
+
+/// The `AttachOptions` struct contains options which influence how a [Session] attaches to a target.
+///
+/// # Example
+///
+/// ```
+/// use probe_rs::AttachOptions;
+///
+/// // Give a slow target four times as long to settle after power state changes.
+/// let options = AttachOptions::new().settle_time_factor(4);
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct AttachOptions {
+    /// All settle times and poll timeouts of the debug sequences are multiplied by this factor.
+    settle_time_factor: u32,
+    /// The number of entries kept in the health log of the session.
+    health_log_capacity: usize,
+    /// Overrides the keepalive configuration of the target.
+    keepalive: Option<Keepalive>,
+    /// The protocol selected before attaching.
+    pub(crate) protocol: Option<WireProtocol>,
+    /// Overrides of the core access options of the target, by core index.
+    core_overrides: BTreeMap<usize, CoreAccessOptionsOverride>,
+    /// The speed range to negotiate the protocol speed in.
+    auto_speed: Option<AutoSpeed>,
+    /// Overrides whether the target is attached at a low speed first.
+    slow_clock_attach: Option<bool>,
+    /// What to do with the cores when the session is closed.
+    detach_mode: DetachMode,
+    /// The retry policy of the memory accesses of the cores.
+    retry_policy: RetryPolicy,
+    /// The peripherals which are stopped while the cores are halted.
+    freeze_peripherals_on_halt: Option<FreezeSelection>,
+    /// Whether the power-up of the system power domain is requested.
+    system_power_request: bool,
+}
+
+impl AttachOptions {
+    /// Constructs a new options object with the default values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Multiply all settle times and poll timeouts used by the debug sequences with `factor`.
+    ///
+    /// This can be used for boards which need more time than usual to settle,
+    /// e.g. after a debug power domain is enabled.
+    #[must_use]
+    pub fn settle_time_factor(self, factor: u32) -> Self {
+        Self {
+            settle_time_factor: factor,
+            ..self
+        }
+    }
+
+    /// Set the number of recovered anomalies which are kept in the health log of the session.
+    ///
+    /// See [`Session::health_log`].
+    #[must_use]
+    pub fn health_log_capacity(self, capacity: usize) -> Self {
+        Self {
+            health_log_capacity: capacity,
+            ..self
+        }
+    }
+
+    /// Keep the debug connection alive with `keepalive`, instead of the keepalive
+    /// configured in the target description.
+    ///
+    /// See [`Session::keep_alive`].
+    #[must_use]
+    pub fn keepalive(self, keepalive: Keepalive) -> Self {
+        Self {
+            keepalive: Some(keepalive),
+            ..self
+        }
+    }
+
+    /// Select `protocol` before attaching, instead of the protocol the probe uses by default.
+    ///
+    /// Attaching fails with [`DebugProbeError::ProtocolNotSupported`] if the probe doesn't
+    /// support the protocol.
+    #[must_use]
+    pub fn protocol(self, protocol: WireProtocol) -> Self {
+        Self {
+            protocol: Some(protocol),
+            ..self
+        }
+    }
+
+    /// Access the core with the index `core_index` with the options in `core_override`,
+    /// instead of the ones in the target description.
+    ///
+    /// Only the options which are set in the override are replaced. The effective options
+    /// can be read from the cores of [`Session::target`] after attaching.
+    ///
+    /// Attaching fails with [`Error::CoreNotFound`] if the target has no such core, and with
+    /// [`Error::InvalidCoreAccessOptions`] if the override doesn't match the architecture of
+    /// the core, or selects an access port which doesn't exist.
+    #[must_use]
+    pub fn core_overrides(
+        mut self,
+        core_index: usize,
+        core_override: CoreAccessOptionsOverride,
+    ) -> Self {
+        self.core_overrides.insert(core_index, core_override);
+        self
+    }
+
+    /// Negotiate the protocol speed while attaching, starting at `start_khz`.
+    ///
+    /// If the link to the target fails with a signature of a clock which is too fast, e.g. a
+    /// missing acknowledge or a parity error, the speed is halved and the link is tried again,
+    /// down to `min_khz`. The link is verified with a burst of reads of an ID register at
+    /// each speed. See [`Error::link_failure`] for the failures which reduce the speed.
+    ///
+    /// The negotiated speed is returned by [`Session::negotiated_speed_khz`], and a reduction
+    /// is recorded in the health log. If the link fails even at `min_khz`,
+    /// [`Error::SpeedNegotiationFailed`] is returned, which usually indicates a problem with
+    /// the wiring instead of the clock.
+    #[must_use]
+    pub fn auto_speed(self, start_khz: u32, min_khz: u32) -> Self {
+        Self {
+            auto_speed: Some(AutoSpeed { start_khz, min_khz }),
+            ..self
+        }
+    }
+
+    /// Attach at [`SLOW_CLOCK_SPEED_KHZ`](crate::SLOW_CLOCK_SPEED_KHZ) first, and negotiate
+    /// the speed upward afterwards, instead of only doing so for targets which are marked with
+    /// `slow_clock_attach` in their target description.
+    ///
+    /// This is needed for targets whose debug logic runs on a slow clock after a reset. The
+    /// link is verified at the low speed, after the
+    /// [`debug_clock_setup`](crate::architecture::arm::sequences::ArmDebugSequence::debug_clock_setup)
+    /// step of the debug sequence of the target. The speed is then negotiated like with
+    /// [`AttachOptions::auto_speed`], starting at its start speed if it is set, or at the
+    /// speed of the probe otherwise. Both speeds are returned by
+    /// [`Session::slow_clock_attach`].
+    ///
+    /// If the speed negotiation of [`AttachOptions::auto_speed`] fails, attaching at the low
+    /// speed is tried as well, unless `enabled` was set explicitly. If that succeeds,
+    /// [`HealthEvent::SlowClockDetected`] is recorded in the health log.
+    #[must_use]
+    pub fn slow_clock_attach(self, enabled: bool) -> Self {
+        Self {
+            slow_clock_attach: Some(enabled),
+            ..self
+        }
+    }
+
+    /// Resume or halt the cores when the session is closed with [`Session::close`] or
+    /// dropped, instead of leaving them as they are.
+    #[must_use]
+    pub fn detach_mode(self, detach_mode: DetachMode) -> Self {
+        Self {
+            detach_mode,
+            ..self
+        }
+    }
+
+    /// Set the retry policy of the memory accesses of all cores, which can be overridden for
+    /// single accesses with [`Core::with_retry_policy`]. By default, every access is attempted
+    /// once.
+    #[must_use]
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy,
+            ..self
+        }
+    }
+
+    /// Stop the peripherals of `selection` while the cores are halted, starting when the
+    /// session is attached.
+    ///
+    /// See [`Session::freeze_peripherals_on_halt`].
+    #[must_use]
+    pub fn freeze_peripherals_on_halt(self, selection: FreezeSelection) -> Self {
+        Self {
+            freeze_peripherals_on_halt: Some(selection),
+            ..self
+        }
+    }
+
+    /// ARM: Request the power-up of the system power domain with CSYSPWRUPREQ when a debug
+    /// port is started, which is the default. If `enabled` is `false`, only the debug power
+    /// domain is requested, for targets where asserting CSYSPWRUPREQ wakes up domains which
+    /// should stay asleep, e.g. while debugging a low-power mode.
+    ///
+    /// The acknowledges of the power requests are awaited with the settle time factor, see
+    /// [`AttachOptions::settle_time_factor`]. A missing acknowledge is reported as
+    /// [`DapError::PowerAckTimeout`](crate::architecture::arm::DapError::PowerAckTimeout).
+    #[must_use]
+    pub fn system_power_request(self, enabled: bool) -> Self {
+        Self {
+            system_power_request: enabled,
+            ..self
+        }
+    }
+}
+
+impl Default for AttachOptions {
+    fn default() -> Self {
+        Self {
+            settle_time_factor: 1,
+            health_log_capacity: DEFAULT_HEALTH_LOG_CAPACITY,
+            keepalive: None,
+            protocol: None,
+            core_overrides: BTreeMap::new(),
+            auto_speed: None,
+            slow_clock_attach: None,
+            detach_mode: DetachMode::LeaveAsIs,
+            retry_policy: RetryPolicy::none(),
+            freeze_peripherals_on_halt: None,
+            system_power_request: true,
+        }
+    }
+}
+
+/// Overrides the options used to access a core, see [`AttachOptions::core_overrides`].
+///
+/// This is useful during the bring-up of a chip, e.g. to try a debug base address found in the
+/// ROM table without editing the target description.
+///
+/// # Example
+///
+/// ```
+/// use probe_rs::{AttachOptions, CoreAccessOptionsOverride};
+///
+/// // Access core 0 through AP 3, with the debug registers at 0x8001_0000.
+/// let options = AttachOptions::new().core_overrides(
+///     0,
+///     CoreAccessOptionsOverride::new()
+///         .ap(3)
+///         .debug_base(0x8001_0000),
+/// );
+/// ```
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreAccessOptionsOverride {
+    ap: Option<u8>,
+    psel: Option<u32>,
+    debug_base: Option<u64>,
+    cti_base: Option<u64>,
+    reset_vectors: Option<Vec<u64>>,
+}
+
+impl CoreAccessOptionsOverride {
+    /// Constructs an override which doesn't change any option.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ARM: Access the core through the access port `ap`.
+    #[must_use]
+    pub fn ap(self, ap: u8) -> Self {
+        Self {
+            ap: Some(ap),
+            ..self
+        }
+    }
+
+    /// ARM: Access the core through the debug port with the port select number `psel`.
+    #[must_use]
+    pub fn psel(self, psel: u32) -> Self {
+        Self {
+            psel: Some(psel),
+            ..self
+        }
+    }
+
+    /// ARM: The base address of the debug registers of the core.
+    #[must_use]
+    pub fn debug_base(self, debug_base: u64) -> Self {
+        Self {
+            debug_base: Some(debug_base),
+            ..self
+        }
+    }
+
+    /// ARM: The base address of the cross trigger interface (CTI) of the core.
+    #[must_use]
+    pub fn cti_base(self, cti_base: u64) -> Self {
+        Self {
+            cti_base: Some(cti_base),
+            ..self
+        }
+    }
+
+    /// RISC-V: The addresses at which the core can start executing after a reset.
+    #[must_use]
+    pub fn reset_vectors(self, reset_vectors: Vec<u64>) -> Self {
+        Self {
+            reset_vectors: Some(reset_vectors),
+            ..self
+        }
+    }
+
+    fn sets_arm_options(&self) -> bool {
+        self.ap.is_some()
+            || self.psel.is_some()
+            || self.debug_base.is_some()
+            || self.cti_base.is_some()
+    }
+
+    /// Merge the override over the `options` of the core with the index `core`.
+    fn apply(&self, core: usize, options: &CoreAccessOptions) -> Result<CoreAccessOptions, Error> {
+        let invalid = |reason: &str| Error::InvalidCoreAccessOptions {
+            core,
+            reason: reason.to_owned(),
+        };
+
+        match options {
+            CoreAccessOptions::Arm(options) => {
+                if self.reset_vectors.is_some() {
+                    return Err(invalid(
+                        "reset vectors can only be overridden for RISC-V cores, but this is an ARM core",
+                    ));
+                }
+
+                let mut options = options.clone();
+
+                if let Some(ap) = self.ap {
+                    options.ap = ap;
+                }
+                if let Some(psel) = self.psel {
+                    options.psel = psel;
+                }
+                if let Some(debug_base) = self.debug_base {
+                    options.debug_base = Some(debug_base);
+                }
+                if let Some(cti_base) = self.cti_base {
+                    options.cti_base = Some(cti_base);
+                }
+
+                Ok(CoreAccessOptions::Arm(options))
+            }
+            CoreAccessOptions::Riscv(options) => {
+                if self.sets_arm_options() {
+                    return Err(invalid(
+                        "the AP, port select and debug addresses can only be overridden for ARM cores, but this is a RISC-V core",
+                    ));
+                }
+
+                let mut options = options.clone();
+
+                if let Some(reset_vectors) = &self.reset_vectors {
+                    options.reset_vectors = reset_vectors.clone();
+                }
+
+                Ok(CoreAccessOptions::Riscv(options))
+            }
+        }
+    }
+}
+
+/// Check that the access ports selected by the `overrides` exist.
+fn validate_ap_overrides(
+    interface: &mut dyn ArmProbeInterface,
+    target: &Target,
+    overrides: &BTreeMap<usize, CoreAccessOptionsOverride>,
+) -> Result<(), Error> {
+    for (&core, core_override) in overrides {
+        if core_override.ap.is_none() && core_override.psel.is_none() {
+            continue;
+        }
+
+        if let CoreAccessOptions::Arm(options) = &target.cores[core].core_access_options {
+            let dp = match options.psel {
+                0 => DpAddress::Default,
+                x => DpAddress::Multidrop(x),
+            };
+
+            let num_access_ports = interface.num_access_ports(dp)?;
+
+            if options.ap as usize >= num_access_ports {
+                return Err(Error::InvalidCoreAccessOptions {
+                    core,
+                    reason: format!(
+                        "AP {} does not exist, the debug port {:x?} has {} access ports",
+                        options.ap, dp, num_access_ports
+                    ),
+                });
+            }
+        }
+    }
+
+    Ok(())
 }
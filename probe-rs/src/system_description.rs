@@ -0,0 +1,598 @@
+//! A description of an attached system, which can be handed from one tool to another, see
+//! [`Session::system_description`](crate::Session::system_description).
+//!
+//! The description is a snapshot of what probe-rs knows about the system after attaching:
+//! the target, its cores and memory map, the debug interface, the probe and the settings of
+//! the session. It is serialized with serde, and its JSON form is versioned with
+//! [`SYSTEM_DESCRIPTION_VERSION`]. Fields are only added or changed together with a new
+//! version, so that tools which consume a description can rely on the names of its fields.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::architecture::arm::ApInformation;
+use crate::config::{CoreAccessOptions, MemoryRegion, TargetDescriptionSource};
+use crate::{
+    ActiveErratum, Architecture, CoreStatus, CoreType, Error, FirmwareAdjustment, HealthEvent,
+    HealthLogEntry, Intrusiveness, ProbeCapabilities, RegisterFile, Target, WireProtocol,
+};
+
+/// The version of the schema of a [`SystemDescription`].
+pub const SYSTEM_DESCRIPTION_VERSION: u64 = 2;
+
+/// Everything probe-rs knows about an attached system, returned by
+/// [`Session::system_description`](crate::Session::system_description).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemDescription {
+    /// The version of the schema, [`SYSTEM_DESCRIPTION_VERSION`].
+    pub version: u64,
+    /// The target.
+    pub target: TargetIdentity,
+    /// The cores of the target, by core index.
+    pub cores: Vec<CoreDescriptor>,
+    /// The memory map of the target.
+    pub memory_map: Vec<RegionDescriptor>,
+    /// What was discovered about the debug interface of the target.
+    pub debug_interface: DebugInterfaceDescriptor,
+    /// The probe the target is attached with.
+    pub probe: ProbeDescriptor,
+    /// The settings of the session.
+    pub settings: SessionSettings,
+    /// The errata whose workarounds are applied.
+    pub errata: Vec<ErratumDescriptor>,
+    /// The anomalies which were recovered from during the session, see
+    /// [`Session::health_log`](crate::Session::health_log).
+    pub warnings: Vec<SessionWarning>,
+}
+
+impl SystemDescription {
+    /// Load a description which was serialized to JSON, e.g. by another tool.
+    ///
+    /// Fails with [`Error::UnsupportedSystemDescriptionVersion`] if the description was written
+    /// with another version of the schema, and with [`Error::InvalidSystemDescription`] if it
+    /// doesn't match the schema.
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let value: serde_json::Value = serde_json::from_str(json)
+            .map_err(|error| Error::InvalidSystemDescription(error.to_string()))?;
+
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| Error::InvalidSystemDescription("missing version".to_string()))?;
+
+        if version != SYSTEM_DESCRIPTION_VERSION {
+            return Err(Error::UnsupportedSystemDescriptionVersion {
+                version,
+                supported: SYSTEM_DESCRIPTION_VERSION,
+            });
+        }
+
+        serde_json::from_value(value)
+            .map_err(|error| Error::InvalidSystemDescription(error.to_string()))
+    }
+
+    /// Serialize the description to JSON, e.g. to hand it to another tool.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A system description is always serializable")
+    }
+}
+
+/// The target of a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetIdentity {
+    /// The name of the target, e.g. `nRF51822_xxAC`.
+    pub name: String,
+    /// The architecture of the cores of the target.
+    pub architecture: Architecture,
+    /// Where the description of the target came from.
+    pub source: TargetDescriptionSource,
+}
+
+impl TargetIdentity {
+    pub(crate) fn new(target: &Target) -> Self {
+        Self {
+            name: target.name.clone(),
+            architecture: target.architecture(),
+            source: target.source.clone(),
+        }
+    }
+}
+
+/// A core in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreDescriptor {
+    /// The name of the core in the target description.
+    pub name: String,
+    /// The type of the core.
+    pub core_type: CoreType,
+    /// How the core is accessed.
+    pub access: CoreAccessDescriptor,
+    /// The register file of the core.
+    pub registers: RegisterFileSummary,
+    /// The state of the core, or `None` if it isn't tracked for the type of the core.
+    pub attach_state: Option<CoreAttachState>,
+}
+
+impl CoreDescriptor {
+    pub(crate) fn new(
+        core: &crate::config::Core,
+        registers: &RegisterFile,
+        attach_state: Option<(bool, CoreStatus)>,
+    ) -> Self {
+        Self {
+            name: core.name.clone(),
+            core_type: core.core_type,
+            access: CoreAccessDescriptor::new(&core.core_access_options),
+            registers: RegisterFileSummary::new(registers),
+            attach_state: attach_state.map(|(initialized, status)| CoreAttachState {
+                initialized,
+                status: CoreStatusDescriptor::new(status),
+            }),
+        }
+    }
+}
+
+/// How a core in a [`SystemDescription`] is accessed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "architecture", rename_all = "snake_case")]
+pub enum CoreAccessDescriptor {
+    /// An ARM core.
+    Arm {
+        /// The access port of the core.
+        ap: u8,
+        /// The port select number of the debug port of the core.
+        psel: u32,
+        /// The base address of the debug registers of the core.
+        debug_base: Option<u64>,
+        /// The base address of the cross trigger interface of the core.
+        cti_base: Option<u64>,
+    },
+    /// A RISC-V core.
+    Riscv {
+        /// The addresses at which the core can start executing after a reset.
+        reset_vectors: Vec<u64>,
+    },
+}
+
+impl CoreAccessDescriptor {
+    fn new(options: &CoreAccessOptions) -> Self {
+        match options {
+            CoreAccessOptions::Arm(options) => CoreAccessDescriptor::Arm {
+                ap: options.ap,
+                psel: options.psel,
+                debug_base: options.debug_base,
+                cti_base: options.cti_base,
+            },
+            CoreAccessOptions::Riscv(options) => CoreAccessDescriptor::Riscv {
+                reset_vectors: options.reset_vectors.clone(),
+            },
+        }
+    }
+}
+
+/// A summary of the register file of a core in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisterFileSummary {
+    /// The number of general purpose and platform registers.
+    pub platform_registers: usize,
+    /// The name of the program counter.
+    pub program_counter: String,
+    /// The name of the stack pointer.
+    pub stack_pointer: String,
+    /// The name of the return address register.
+    pub return_address: String,
+    /// The number of floating point registers, which are only available if the core has an
+    /// FPU.
+    pub fp_registers: usize,
+}
+
+impl RegisterFileSummary {
+    fn new(registers: &RegisterFile) -> Self {
+        Self {
+            platform_registers: registers.platform_registers.len(),
+            program_counter: registers.program_counter().name().to_string(),
+            stack_pointer: registers.stack_pointer().name().to_string(),
+            return_address: registers.return_address().name().to_string(),
+            fp_registers: registers
+                .fp_registers
+                .map_or(0, |registers| registers.len()),
+        }
+    }
+}
+
+/// The state of a core in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreAttachState {
+    /// True if probe-rs initialized the debug logic of the core.
+    pub initialized: bool,
+    /// The last known status of the core.
+    pub status: CoreStatusDescriptor,
+}
+
+/// The last known status of a core in a [`SystemDescription`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoreStatusDescriptor {
+    /// The core is running.
+    Running,
+    /// The core is halted.
+    Halted,
+    /// The core is locked up.
+    LockedUp,
+    /// The core is sleeping.
+    Sleeping,
+    /// The status of the core is unknown.
+    Unknown,
+}
+
+impl CoreStatusDescriptor {
+    fn new(status: CoreStatus) -> Self {
+        match status {
+            CoreStatus::Running => CoreStatusDescriptor::Running,
+            CoreStatus::Halted(_) => CoreStatusDescriptor::Halted,
+            CoreStatus::LockedUp => CoreStatusDescriptor::LockedUp,
+            CoreStatus::Sleeping => CoreStatusDescriptor::Sleeping,
+            CoreStatus::Unknown => CoreStatusDescriptor::Unknown,
+        }
+    }
+}
+
+/// A region of the memory map in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegionDescriptor {
+    /// The kind of memory.
+    pub kind: RegionKind,
+    /// The name of the region, if it has one.
+    pub name: Option<String>,
+    /// The addresses of the region.
+    pub range: Range<u64>,
+    /// True if the chip boots from this memory.
+    pub is_boot_memory: bool,
+    /// The names of the cores which can access the region, or an empty list if all can.
+    pub cores: Vec<String>,
+    /// Ranges which are critical for booting the chip, and are only erased if explicitly
+    /// allowed.
+    pub boot_critical_ranges: Vec<Range<u64>>,
+}
+
+impl RegionDescriptor {
+    pub(crate) fn new(region: &MemoryRegion) -> Self {
+        match region {
+            MemoryRegion::Ram(region) => Self {
+                kind: RegionKind::Ram,
+                name: region.name.clone(),
+                range: region.range.clone(),
+                is_boot_memory: region.is_boot_memory,
+                cores: region.cores.clone(),
+                boot_critical_ranges: Vec::new(),
+            },
+            MemoryRegion::Nvm(region) => Self {
+                kind: RegionKind::Nvm,
+                name: region.name.clone(),
+                range: region.range.clone(),
+                is_boot_memory: region.is_boot_memory,
+                cores: region.cores.clone(),
+                boot_critical_ranges: region.boot_critical_ranges.clone(),
+            },
+            MemoryRegion::Generic(region) => Self {
+                kind: RegionKind::Generic,
+                name: region.name.clone(),
+                range: region.range.clone(),
+                is_boot_memory: false,
+                cores: region.cores.clone(),
+                boot_critical_ranges: Vec::new(),
+            },
+        }
+    }
+}
+
+/// The kind of memory of a [`RegionDescriptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionKind {
+    /// RAM.
+    Ram,
+    /// Flash, EEPROM or other non-volatile memory.
+    Nvm,
+    /// Memory which is neither RAM nor non-volatile, e.g. peripherals.
+    Generic,
+}
+
+/// What was discovered about the debug interface of the target in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "architecture", rename_all = "snake_case")]
+pub enum DebugInterfaceDescriptor {
+    /// The debug interface of an ARM target.
+    Arm {
+        /// The access ports which were discovered while attaching.
+        access_ports: Vec<AccessPortDescriptor>,
+    },
+    /// The Debug Module of a RISC-V target.
+    Riscv(DebugModuleDescriptor),
+}
+
+/// An ARM access port in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AccessPortDescriptor {
+    /// A memory access port.
+    Memory {
+        /// The `TARGETSEL` value of the debug port on a multidrop bus, or `None` for the
+        /// default debug port.
+        dp: Option<u32>,
+        /// The number of the access port.
+        ap: u8,
+        /// The base address of the debug registers or the ROM table.
+        debug_base_address: u64,
+        /// True if the access port only supports 32-bit accesses.
+        only_32bit_data_size: bool,
+        /// True if the access port supports the HNONSEC bit.
+        supports_hnonsec: bool,
+        /// True if the access port supports 64-bit addresses.
+        has_large_address_extension: bool,
+        /// True if the access port supports 64-bit data accesses.
+        has_large_data_extension: bool,
+    },
+    /// An access port of another class.
+    Other {
+        /// The `TARGETSEL` value of the debug port on a multidrop bus, or `None` for the
+        /// default debug port.
+        dp: Option<u32>,
+        /// The number of the access port.
+        ap: u8,
+    },
+}
+
+impl AccessPortDescriptor {
+    pub(crate) fn new(information: &ApInformation) -> Self {
+        use crate::architecture::arm::DpAddress;
+
+        let dp = |dp: DpAddress| match dp {
+            DpAddress::Default => None,
+            DpAddress::Multidrop(targetsel) => Some(targetsel),
+        };
+
+        match information {
+            ApInformation::MemoryAp(information) => AccessPortDescriptor::Memory {
+                dp: dp(information.address.dp),
+                ap: information.address.ap,
+                debug_base_address: information.debug_base_address,
+                only_32bit_data_size: information.only_32bit_data_size,
+                supports_hnonsec: information.supports_hnonsec,
+                has_large_address_extension: information.has_large_address_extension,
+                has_large_data_extension: information.has_large_data_extension,
+            },
+            ApInformation::Other { address } => AccessPortDescriptor::Other {
+                dp: dp(address.dp),
+                ap: address.ap,
+            },
+        }
+    }
+
+    /// The debug port and the number of the access port, to sort them.
+    pub(crate) fn address(&self) -> (Option<u32>, u8) {
+        match self {
+            AccessPortDescriptor::Memory { dp, ap, .. }
+            | AccessPortDescriptor::Other { dp, ap } => (*dp, *ap),
+        }
+    }
+}
+
+/// The capabilities of a RISC-V Debug Module in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugModuleDescriptor {
+    /// The version of the debug specification the Debug Module conforms to, e.g. `0.13`.
+    pub version: String,
+    /// The size of the program buffer, in 32-bit words.
+    pub progbuf_size: u8,
+    /// True if there is an implicit `ebreak` after the program buffer.
+    pub implicit_ebreak: bool,
+    /// The number of data registers for abstract commands.
+    pub data_registers: u8,
+    /// The number of `dscratch` registers.
+    pub scratch_registers: u8,
+    /// The number of data registers which are shadowed in the memory map of the hart, zero if
+    /// they aren't.
+    #[serde(default)]
+    pub shadowed_data_words: u8,
+    /// The address of the data registers which are shadowed in the memory map of the hart,
+    /// relative to zero.
+    #[serde(default)]
+    pub shadowed_data_address: i16,
+    /// True if abstract commands can be executed automatically.
+    pub supports_autoexec: bool,
+    /// The width of the `hartsel` field.
+    pub hartsellen: u8,
+    /// The number of harts.
+    pub harts: u32,
+}
+
+/// The probe of a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeDescriptor {
+    /// The name of the probe, with its firmware version if it is known.
+    pub name: String,
+    /// The protocols, features and limits of the probe.
+    pub capabilities: ProbeCapabilities,
+    /// The adjustments of the capabilities to the firmware of the probe.
+    pub firmware_adjustments: Vec<FirmwareAdjustment>,
+}
+
+/// The settings of the session in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionSettings {
+    /// The protocol used to communicate with the target, if it is known.
+    pub protocol: Option<WireProtocol>,
+    /// The protocol speed negotiated while attaching, if the session was attached with
+    /// [`AttachOptions::auto_speed`](crate::AttachOptions::auto_speed).
+    pub negotiated_speed_khz: Option<u32>,
+    /// The interval at which the connection is kept alive, if the target needs it.
+    pub keepalive_interval_ms: Option<u64>,
+    /// The most intrusive operation the session allows.
+    pub max_intrusiveness: Intrusiveness,
+}
+
+/// An active erratum in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErratumDescriptor {
+    /// The index of the core the workaround is applied to.
+    pub core: usize,
+    /// The identifier of the erratum.
+    pub id: String,
+    /// What goes wrong, and how it is worked around.
+    pub description: String,
+}
+
+impl ErratumDescriptor {
+    pub(crate) fn new(erratum: &ActiveErratum) -> Self {
+        Self {
+            core: erratum.core,
+            id: erratum.erratum.id.to_string(),
+            description: erratum.erratum.description.to_string(),
+        }
+    }
+}
+
+/// An anomaly which was recovered from, in a [`SystemDescription`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionWarning {
+    /// The kind of anomaly.
+    pub event: HealthEvent,
+    /// The index of the affected core, if the anomaly is specific to a core.
+    pub core: Option<usize>,
+    /// The operation during which the anomaly happened.
+    pub operation: String,
+    /// Additional details about the anomaly.
+    pub details: String,
+}
+
+impl SessionWarning {
+    pub(crate) fn new(entry: &HealthLogEntry) -> Self {
+        Self {
+            event: entry.event,
+            core: entry.core,
+            operation: entry.operation.to_string(),
+            details: entry.details.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::riscv::communication_interface::RiscvCommunicationInterface;
+    use crate::architecture::riscv::mock::MockDebugModule;
+    use crate::architecture::riscv::RISCV_REGISTERS;
+    use crate::config::get_target_by_name;
+    use serde_json::Value;
+
+    /// Replaces all values in `value` by their type, so that only the schema is compared.
+    fn schema(value: &Value) -> Value {
+        match value {
+            Value::Null => Value::from("null"),
+            Value::Bool(_) => Value::from("bool"),
+            Value::Number(_) => Value::from("number"),
+            Value::String(_) => Value::from("string"),
+            Value::Array(values) => Value::Array(values.iter().map(schema).collect()),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), schema(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The description of a RISC-V target attached with the mocked Debug Module.
+    fn riscv_description() -> SystemDescription {
+        let target = get_target_by_name("fe310-g002").unwrap();
+
+        let (probe, _state) = MockDebugModule::new();
+        let interface = RiscvCommunicationInterface::new(Box::new(probe))
+            .map_err(|(_, e)| e)
+            .unwrap();
+
+        SystemDescription {
+            version: SYSTEM_DESCRIPTION_VERSION,
+            target: TargetIdentity::new(&target),
+            cores: target
+                .cores
+                .iter()
+                .map(|core| CoreDescriptor::new(core, &RISCV_REGISTERS, None))
+                .collect(),
+            memory_map: target
+                .memory_map
+                .iter()
+                .map(RegionDescriptor::new)
+                .collect(),
+            debug_interface: DebugInterfaceDescriptor::Riscv(interface.debug_module()),
+            probe: ProbeDescriptor {
+                name: "Mock RISC-V Debug Module".to_string(),
+                capabilities: ProbeCapabilities::new().jtag(),
+                firmware_adjustments: Vec::new(),
+            },
+            settings: SessionSettings {
+                protocol: Some(WireProtocol::Jtag),
+                negotiated_speed_khz: None,
+                keepalive_interval_ms: None,
+                max_intrusiveness: Intrusiveness::default(),
+            },
+            errata: Vec::new(),
+            warnings: vec![SessionWarning {
+                event: HealthEvent::RetrySucceeded,
+                core: Some(0),
+                operation: "read_word_32".to_string(),
+                details: "WAIT response".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn riscv_description_matches_the_golden_schema() {
+        let golden: Value =
+            serde_json::from_str(include_str!("../tests/system_description/riscv.json")).unwrap();
+
+        let description = riscv_description();
+        let actual = serde_json::to_value(&description).unwrap();
+
+        assert_eq!(schema(&actual), schema(&golden));
+
+        assert_eq!(actual["target"]["name"], "fe310-g002");
+        assert_eq!(actual["target"]["architecture"], "riscv");
+        assert_eq!(actual["cores"][0]["core_type"], "riscv");
+        assert_eq!(actual["debug_interface"]["architecture"], "riscv");
+        assert_eq!(actual["memory_map"][0]["kind"], "nvm");
+        assert_eq!(actual["settings"]["protocol"], "Jtag");
+        assert_eq!(actual["warnings"][0]["event"], "retry_succeeded");
+
+        // The golden description itself can be loaded, as an offline tool would.
+        assert!(SystemDescription::from_json(&golden.to_string()).is_ok());
+    }
+
+    #[test]
+    fn description_survives_json() {
+        let description = riscv_description();
+
+        let loaded = SystemDescription::from_json(&description.to_json()).unwrap();
+
+        assert_eq!(loaded, description);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut json = serde_json::to_value(&riscv_description()).unwrap();
+        json["version"] = Value::from(SYSTEM_DESCRIPTION_VERSION + 1);
+
+        assert!(matches!(
+            SystemDescription::from_json(&json.to_string()),
+            Err(Error::UnsupportedSystemDescriptionVersion { version, .. })
+                if version == SYSTEM_DESCRIPTION_VERSION + 1
+        ));
+
+        assert!(matches!(
+            SystemDescription::from_json("{\"version\": 1}"),
+            Err(Error::InvalidSystemDescription(_))
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+//! Tearing down a session, see [`Session::close`](crate::Session::close).
+//!
+//! When a session ends, the breakpoints set by probe-rs are removed, tracing is disabled and
+//! the debug sequence of the target is told that debugging stops. Each of these steps can
+//! fail, e.g. because the target was powered off. The failures are collected instead of
+//! aborting the teardown, so that as much of the target as possible is left in a clean state.
+
+use crate::{CoreStatus, Error};
+
+/// What to do with the cores when a session is closed, see
+/// [`AttachOptions::detach_mode`](crate::AttachOptions::detach_mode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DetachMode {
+    /// Leave the cores running or halted, as they are.
+    #[default]
+    LeaveAsIs,
+    /// Resume all halted cores.
+    Resume,
+    /// Halt all running cores.
+    Halt,
+}
+
+/// A step of the teardown of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeardownStep {
+    /// Restoring the controllers of the mediated regions which were prepared for a core.
+    ReleaseAccessMediators,
+    /// Removing the hardware breakpoints of a core.
+    ClearHwBreakpoints,
+    /// Removing the software breakpoints of a core.
+    ClearSwBreakpoints,
+    /// Disabling the tracing of a core.
+    DisableTrace,
+    /// Applying the [`DetachMode`] to a core, and reading its status.
+    DetachCore,
+    /// Running the debug sequence which ends the debug session of the target.
+    StopDebug,
+}
+
+/// A step of the teardown which failed.
+#[derive(Debug)]
+pub struct TeardownFailure {
+    /// The step which failed.
+    pub step: TeardownStep,
+    /// The index of the core the step failed for, if the step is specific to a core.
+    pub core: Option<usize>,
+    /// Why the step failed.
+    pub error: Error,
+}
+
+/// What was done to a single core when the session was closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreCloseReport {
+    /// The index of the core.
+    pub core: usize,
+    /// The number of hardware breakpoints which were removed.
+    pub hw_breakpoints_removed: usize,
+    /// The number of software breakpoints which were removed.
+    pub sw_breakpoints_removed: usize,
+    /// True if tracing was disabled. Tracing is only disabled on Cortex-M cores.
+    pub trace_disabled: bool,
+    /// The status of the core after the [`DetachMode`] was applied, or `None` if it couldn't
+    /// be read.
+    pub status: Option<CoreStatus>,
+}
+
+impl CoreCloseReport {
+    pub(crate) fn new(core: usize) -> Self {
+        Self {
+            core,
+            hw_breakpoints_removed: 0,
+            sw_breakpoints_removed: 0,
+            trace_disabled: false,
+            status: None,
+        }
+    }
+}
+
+/// What was done when a session was closed, returned by
+/// [`Session::close`](crate::Session::close).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloseReport {
+    /// The reports of the cores, by core index.
+    pub cores: Vec<CoreCloseReport>,
+    /// True if the debug sequence which ends the debug session of the target was run.
+    pub debug_stopped: bool,
+}
+
+/// Runs the steps of a teardown, and collects their failures.
+///
+/// Once a step fails because the link to the target is lost, all following steps are
+/// skipped, so that a dead target doesn't stall the teardown with one timeout per step.
+#[derive(Debug, Default)]
+pub(crate) struct Teardown {
+    pub(crate) report: CloseReport,
+    pub(crate) failures: Vec<TeardownFailure>,
+    link_lost: bool,
+}
+
+impl Teardown {
+    /// Run `step` for `core` with `f`.
+    ///
+    /// Returns `None` if the step failed, or was skipped because the link is lost or because
+    /// it exceeds the maximum intrusiveness of the session.
+    pub(crate) fn run<T>(
+        &mut self,
+        step: TeardownStep,
+        core: Option<usize>,
+        f: impl FnOnce() -> Result<T, Error>,
+    ) -> Option<T> {
+        if self.link_lost {
+            log::debug!("Skipping {:?} on core {:?}, the link is lost", step, core);
+            return None;
+        }
+
+        match f() {
+            Ok(value) => Some(value),
+            Err(Error::IntrusivenessExceeded { required, .. }) => {
+                log::debug!(
+                    "Skipping {:?} on core {:?}, it is {:?} intrusive",
+                    step,
+                    core,
+                    required
+                );
+                None
+            }
+            Err(error) => {
+                self.link_lost =
+                    matches!(error, Error::TargetLost(_)) || error.link_failure().is_some();
+
+                self.failures.push(TeardownFailure { step, core, error });
+                None
+            }
+        }
+    }
+}
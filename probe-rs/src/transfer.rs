@@ -0,0 +1,406 @@
+//! Progress and statistics of large memory transfers, see
+//! [`Session::set_transfer_progress`](crate::Session::set_transfer_progress).
+//!
+//! The cost of a memory transfer is not proportional to its bytes on all cores. On RISC-V,
+//! the program buffer accesses each word with several DMI operations, whose number depends on
+//! whether the Debug Module supports autoexec and how often `abstractcs` has to be polled,
+//! while the time of each operation depends on the latency of the probe. The progress of
+//! such transfers is therefore measured in the operations which the interface of the core
+//! completed, where it counts them, see [`TransferEvent::operations`].
+//!
+//! A reported transfer starts with a short calibration chunk, whose duration is the first
+//! estimate of the cost of a word. Afterwards, the transfer is split into small chunks, and
+//! the estimate is refined after each of them.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// The length in bytes of the calibration chunk, which starts a reported transfer.
+const CALIBRATION_LEN: usize = 128;
+
+/// The number of chunks into which a reported transfer is split at least, so that its
+/// progress advances steadily.
+const PROGRESS_STEPS: usize = 50;
+
+/// Transfers shorter than this are not reported by default.
+const DEFAULT_MIN_LEN: usize = 0x1000;
+
+/// The size of a word, in which the cost of a transfer is measured.
+const WORD_SIZE: f64 = 4.0;
+
+/// The direction of a memory transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    /// Memory is read from the target.
+    Read,
+    /// Memory is written to the target.
+    Write,
+}
+
+/// The estimated cost of the rest of a transfer, in a [`TransferEvent`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferEstimate {
+    /// The time one word of 4 bytes takes.
+    pub cost_per_word: Duration,
+    /// The number of operations one word takes, if the interface of the core counts them.
+    pub operations_per_word: Option<f64>,
+    /// The time until the transfer is finished.
+    pub remaining: Duration,
+}
+
+/// The progress of a memory transfer, which is reported after each of its chunks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferEvent {
+    /// The index of the core which transfers the memory.
+    pub core: usize,
+    /// The direction of the transfer.
+    pub direction: TransferDirection,
+    /// The start address of the transfer.
+    pub address: u64,
+    /// The length of the transfer in bytes.
+    pub len: usize,
+    /// The number of bytes which were transferred so far.
+    pub transferred: usize,
+    /// The number of low-level operations, e.g. DMI accesses on RISC-V, which were
+    /// completed so far, if the interface of the core counts them.
+    pub operations: Option<u64>,
+    /// The time since the transfer started.
+    pub elapsed: Duration,
+    /// The estimated cost of the rest of the transfer.
+    pub estimate: TransferEstimate,
+}
+
+impl TransferEvent {
+    /// Returns `true` if this is the last event of the transfer.
+    pub fn is_finished(&self) -> bool {
+        self.transferred == self.len
+    }
+
+    /// Returns the duration of the whole transfer, as it is estimated at this event.
+    pub fn estimated_duration(&self) -> Duration {
+        self.elapsed + self.estimate.remaining
+    }
+}
+
+/// Receives the progress of large memory transfers, see
+/// [`Session::set_transfer_progress`](crate::Session::set_transfer_progress).
+///
+/// # Example
+///
+/// ```
+/// use probe_rs::TransferProgress;
+///
+/// let progress = TransferProgress::new(|event| {
+///     println!(
+///         "{}/{} bytes, {:?} left",
+///         event.transferred, event.len, event.estimate.remaining
+///     )
+/// });
+/// ```
+#[derive(Clone)]
+pub struct TransferProgress {
+    handler: Arc<dyn Fn(&TransferEvent) + Send + Sync>,
+    min_len: usize,
+}
+
+impl TransferProgress {
+    /// Create a new `TransferProgress`, whose `handler` is called after each chunk of the
+    /// transfers of at least 4 KiB.
+    pub fn new(handler: impl Fn(&TransferEvent) + Send + Sync + 'static) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            min_len: DEFAULT_MIN_LEN,
+        }
+    }
+
+    /// Only report transfers of at least `min_len` bytes.
+    pub fn min_len(mut self, min_len: usize) -> Self {
+        self.min_len = min_len;
+        self
+    }
+}
+
+impl fmt::Debug for TransferProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransferProgress")
+            .field("min_len", &self.min_len)
+            .finish()
+    }
+}
+
+/// The totals of the memory transfers in one direction, in [`TransferStatistics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferTotals {
+    /// The number of transfers.
+    pub transfers: u64,
+    /// The number of transferred bytes.
+    pub bytes: u64,
+    /// The number of low-level operations of the transfers, where the interface of the core
+    /// counts them.
+    pub operations: u64,
+    /// The time the transfers took.
+    pub time: Duration,
+}
+
+impl TransferTotals {
+    /// Returns the average time one word of 4 bytes took, if any was transferred.
+    pub fn cost_per_word(&self) -> Option<Duration> {
+        let words = self.bytes as f64 / WORD_SIZE;
+
+        (words > 0.0).then(|| self.time.div_f64(words))
+    }
+}
+
+/// The totals of the memory transfers of a session, see
+/// [`Session::transfer_statistics`](crate::Session::transfer_statistics).
+///
+/// Only transfers of whole slices are counted, single words are not.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStatistics {
+    /// The totals of the reads.
+    pub reads: TransferTotals,
+    /// The totals of the writes.
+    pub writes: TransferTotals,
+}
+
+/// The transfer progress and statistics of a session, which are shared with its cores.
+#[derive(Clone, Default)]
+pub(crate) struct Transfers(Arc<Mutex<TransfersState>>);
+
+#[derive(Default)]
+struct TransfersState {
+    progress: Option<TransferProgress>,
+    statistics: TransferStatistics,
+}
+
+impl fmt::Debug for Transfers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+
+        f.debug_struct("Transfers")
+            .field("progress", &state.progress)
+            .field("statistics", &state.statistics)
+            .finish()
+    }
+}
+
+impl Transfers {
+    fn lock(&self) -> MutexGuard<'_, TransfersState> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn set_progress(&self, progress: Option<TransferProgress>) {
+        self.lock().progress = progress;
+    }
+
+    pub(crate) fn statistics(&self) -> TransferStatistics {
+        self.lock().statistics
+    }
+
+    /// Start a transfer of `len` bytes at `address`, when the interface of the core has
+    /// completed `operations` so far.
+    pub(crate) fn start(
+        &self,
+        core: usize,
+        direction: TransferDirection,
+        address: u64,
+        len: usize,
+        operations: Option<u64>,
+    ) -> TransferTracker {
+        let progress = self
+            .lock()
+            .progress
+            .clone()
+            .filter(|progress| len >= progress.min_len);
+
+        TransferTracker {
+            transfers: self.clone(),
+            progress,
+            core,
+            direction,
+            address,
+            len,
+            start: Instant::now(),
+            transferred: 0,
+            start_operations: operations,
+            operations: None,
+            calibration: None,
+        }
+    }
+}
+
+/// The part of a transfer which was completed at some point.
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    bytes: usize,
+    operations: Option<u64>,
+    time: Duration,
+}
+
+/// Tracks the chunks of a single transfer.
+pub(crate) struct TransferTracker {
+    transfers: Transfers,
+    progress: Option<TransferProgress>,
+    core: usize,
+    direction: TransferDirection,
+    address: u64,
+    len: usize,
+    start: Instant,
+    transferred: usize,
+    start_operations: Option<u64>,
+    operations: Option<u64>,
+    calibration: Option<Sample>,
+}
+
+impl TransferTracker {
+    /// Returns the length in bytes of the next chunk, which is at most `max_len`.
+    ///
+    /// Only reported transfers are split into more chunks than necessary.
+    pub(crate) fn chunk_len(&self, max_len: usize) -> usize {
+        match self.progress {
+            None => max_len,
+            Some(_) if self.calibration.is_none() => CALIBRATION_LEN.min(max_len),
+            Some(_) => (self.len / PROGRESS_STEPS).clamp(CALIBRATION_LEN.min(max_len), max_len),
+        }
+    }
+
+    /// Record that a chunk of `len` bytes was transferred, and that the interface of the
+    /// core has completed `operations` so far.
+    pub(crate) fn chunk_done(&mut self, len: usize, operations: Option<u64>) {
+        self.transferred += len;
+        self.operations = self
+            .start_operations
+            .zip(operations)
+            .map(|(start, now)| now.saturating_sub(start));
+
+        let progress = match &self.progress {
+            Some(progress) => progress.clone(),
+            None => return,
+        };
+
+        let current = Sample {
+            bytes: self.transferred,
+            operations: self.operations,
+            time: self.start.elapsed(),
+        };
+        let calibration = *self.calibration.get_or_insert(current);
+
+        let event = TransferEvent {
+            core: self.core,
+            direction: self.direction,
+            address: self.address,
+            len: self.len,
+            transferred: self.transferred,
+            operations: self.operations,
+            elapsed: current.time,
+            estimate: self.estimate(calibration, current),
+        };
+
+        (progress.handler)(&event);
+    }
+
+    /// Estimate the cost of the rest of the transfer from the `current` progress.
+    fn estimate(&self, calibration: Sample, current: Sample) -> TransferEstimate {
+        // The calibration chunk carries the setup of the transfer, so the words after it
+        // show the steady cost, once there are any.
+        let steady = if current.bytes > calibration.bytes {
+            Sample {
+                bytes: current.bytes - calibration.bytes,
+                operations: current
+                    .operations
+                    .zip(calibration.operations)
+                    .map(|(current, calibration)| current - calibration),
+                time: current.time.saturating_sub(calibration.time),
+            }
+        } else {
+            calibration
+        };
+        let words = steady.bytes as f64 / WORD_SIZE;
+
+        // The time of an operation is about constant for a probe, while the number of
+        // operations per word varies, so the operations are counted where possible.
+        let (cost_per_word, operations_per_word) = match (current.operations, steady.operations) {
+            (Some(total), Some(operations)) if total > 0 && operations > 0 => {
+                let per_operation = current.time.as_secs_f64() / total as f64;
+                let per_word = operations as f64 / words;
+
+                (
+                    Duration::from_secs_f64(per_operation * per_word),
+                    Some(per_word),
+                )
+            }
+            _ => (steady.time.div_f64(words), None),
+        };
+
+        let remaining_words = (self.len - current.bytes) as f64 / WORD_SIZE;
+
+        TransferEstimate {
+            cost_per_word,
+            operations_per_word,
+            remaining: cost_per_word.mul_f64(remaining_words),
+        }
+    }
+
+    /// Record the finished transfer in the statistics of the session.
+    pub(crate) fn finish(self) {
+        if self.transferred == 0 {
+            return;
+        }
+
+        let mut state = self.transfers.lock();
+        let totals = match self.direction {
+            TransferDirection::Read => &mut state.statistics.reads,
+            TransferDirection::Write => &mut state.statistics.writes,
+        };
+
+        totals.transfers += 1;
+        totals.bytes += self.transferred as u64;
+        totals.operations += self.operations.unwrap_or(0);
+        totals.time += self.start.elapsed();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reported_transfers_start_with_a_calibration_chunk() {
+        let transfers = Transfers::default();
+        transfers.set_progress(Some(TransferProgress::new(|_| ())));
+
+        let mut tracker = transfers.start(0, TransferDirection::Read, 0, 0x10000, None);
+        assert_eq!(tracker.chunk_len(0x1000), CALIBRATION_LEN);
+
+        tracker.chunk_done(CALIBRATION_LEN, None);
+        assert_eq!(tracker.chunk_len(0x1000), 0x10000 / PROGRESS_STEPS);
+
+        // Short transfers are neither reported nor split.
+        let tracker = transfers.start(0, TransferDirection::Read, 0, 0x100, None);
+        assert_eq!(tracker.chunk_len(0x1000), 0x1000);
+    }
+
+    #[test]
+    fn the_estimate_follows_the_operations_after_the_calibration() {
+        let transfers = Transfers::default();
+        let tracker = transfers.start(0, TransferDirection::Write, 0, 1000, Some(0));
+
+        let calibration = Sample {
+            bytes: 100,
+            operations: Some(200),
+            time: Duration::from_millis(200),
+        };
+        // 2 operations per word after the calibration, at 1 ms each.
+        let current = Sample {
+            bytes: 500,
+            operations: Some(400),
+            time: Duration::from_millis(400),
+        };
+
+        let estimate = tracker.estimate(calibration, current);
+        assert_eq!(estimate.operations_per_word, Some(2.0));
+        assert!((estimate.cost_per_word.as_secs_f64() - 0.002).abs() < 1e-6);
+        assert!((estimate.remaining.as_secs_f64() - 0.25).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,219 @@
+mod common;
+
+use std::ops::Range;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use probe_rs::{
+    test_utils::WriteLog, AccessMediator, Error, FakeProbe, Intrusiveness, MemoryInterface,
+    PreparedAccess, Session, Stm32Quadspi, TargetOperation,
+};
+
+/// The memory-mapped region of the external flash.
+const FLASH: Range<u64> = 0x9000_0000..0x9100_0000;
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// The registers of the QUADSPI controller.
+const QUADSPI: u64 = 0xA000_1000;
+const QUADSPI_SR: u64 = QUADSPI + 0x08;
+const QUADSPI_DLR: u64 = QUADSPI + 0x10;
+const QUADSPI_CCR: u64 = QUADSPI + 0x14;
+const QUADSPI_AR: u64 = QUADSPI + 0x18;
+
+/// Indirect read mode, with the instruction `0x03` on a single line.
+const INDIRECT_READ_CCR: u32 = 0x0400_0103;
+
+/// Memory-mapped mode, with the instruction `0xEB` and a 24-bit address on four lines.
+const MEMORY_MAPPED_CCR: u32 = 0x0F00_2FEB;
+
+fn attach() -> (Session, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let session = common::attach_probe(probe);
+
+    (session, write_log)
+}
+
+/// A mediator which counts how often it is prepared and released.
+#[derive(Debug, Default)]
+struct CountingMediator {
+    prepared: Arc<AtomicUsize>,
+    released: Arc<AtomicUsize>,
+}
+
+#[derive(Debug)]
+struct CountedAccess {
+    released: Arc<AtomicUsize>,
+}
+
+impl AccessMediator for CountingMediator {
+    fn prepare_read(
+        &self,
+        _memory: &mut dyn MemoryInterface,
+        _region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error> {
+        self.prepared.fetch_add(1, Ordering::SeqCst);
+
+        Ok(Box::new(CountedAccess {
+            released: self.released.clone(),
+        }))
+    }
+
+    fn prepare_write(
+        &self,
+        memory: &mut dyn MemoryInterface,
+        region: &Range<u64>,
+    ) -> Result<Box<dyn PreparedAccess>, Error> {
+        self.prepare_read(memory, region)
+    }
+}
+
+impl PreparedAccess for CountedAccess {
+    fn release(self: Box<Self>, _memory: &mut dyn MemoryInterface) -> Result<(), Error> {
+        self.released.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn mediator_is_prepared_once_for_many_chunks() {
+    let (mut session, _) = attach();
+
+    let mediator = CountingMediator::default();
+    let prepared = mediator.prepared.clone();
+    let released = mediator.released.clone();
+    session.add_access_mediator(FLASH, Arc::new(mediator));
+
+    let mut core = session.core(0).unwrap();
+
+    // Other memory is accessed without the mediator.
+    core.read_word_32(RAM).unwrap();
+    assert_eq!(prepared.load(Ordering::SeqCst), 0);
+
+    // A read which is split into several chunks, followed by a dump in many small reads.
+    let mut data = vec![0u8; 0x3000];
+    core.read_8(FLASH.start, &mut data).unwrap();
+
+    for chunk in 0..1000 {
+        let mut words = [0u32; 4];
+        core.read_32(FLASH.start + chunk * 16, &mut words).unwrap();
+    }
+
+    assert_eq!(prepared.load(Ordering::SeqCst), 1);
+
+    // Accesses to other memory leave the prepared region alone.
+    core.write_word_32(RAM, 0x1234_5678).unwrap();
+    core.read_word_32(RAM).unwrap();
+    assert_eq!(prepared.load(Ordering::SeqCst), 1);
+    assert_eq!(released.load(Ordering::SeqCst), 0);
+
+    // Another direction needs another preparation.
+    core.write_word_32(FLASH.start, 0).unwrap();
+    assert_eq!(prepared.load(Ordering::SeqCst), 2);
+    assert_eq!(released.load(Ordering::SeqCst), 1);
+
+    core.release_access_mediators().unwrap();
+    assert_eq!(released.load(Ordering::SeqCst), 2);
+
+    // Releasing again doesn't release anything.
+    core.release_access_mediators().unwrap();
+    assert_eq!(released.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn preparing_a_mediator_writes_memory() {
+    let (mut session, _) = attach();
+    session.add_access_mediator(FLASH, Arc::new(CountingMediator::default()));
+    session.set_max_intrusiveness(Intrusiveness::BusOnly);
+
+    let mut core = session.core(0).unwrap();
+
+    match core.read_word_32(FLASH.start) {
+        Err(Error::IntrusivenessExceeded { operation, .. }) => {
+            assert_eq!(operation, TargetOperation::WriteMemory)
+        }
+        other => panic!("Expected the read to be refused, got {:?}", other),
+    }
+}
+
+#[test]
+fn quadspi_is_switched_to_memory_mapped_mode_and_back() {
+    let (mut session, write_log) = attach();
+    session.add_access_mediator(
+        FLASH,
+        Arc::new(Stm32Quadspi {
+            registers: QUADSPI,
+            memory_mapped_ccr: MEMORY_MAPPED_CCR,
+            indirect_write_ccr: None,
+        }),
+    );
+
+    let mut core = session.core(0).unwrap();
+    core.write_word_32(QUADSPI_CCR, INDIRECT_READ_CCR).unwrap();
+    write_log.clear();
+
+    let mut data = [0u32; 64];
+    core.read_32(FLASH.start, &mut data).unwrap();
+    core.read_32(FLASH.start + 0x100, &mut data).unwrap();
+
+    assert_eq!(core.read_word_32(QUADSPI_CCR).unwrap(), MEMORY_MAPPED_CCR);
+    assert_eq!(
+        write_log
+            .entries()
+            .iter()
+            .filter(|(address, _)| *address as u64 == QUADSPI_CCR)
+            .count(),
+        1
+    );
+
+    // The memory-mapped region can't be written without a command for it.
+    match core.write_word_32(FLASH.start, 0) {
+        Err(Error::MediatedAccessUnsupported { address, .. }) => assert_eq!(address, FLASH.start),
+        other => panic!("Expected the write to be refused, got {:?}", other),
+    }
+
+    core.release_access_mediators().unwrap();
+    assert_eq!(core.read_word_32(QUADSPI_CCR).unwrap(), INDIRECT_READ_CCR);
+}
+
+#[test]
+fn quadspi_routes_writes_through_the_data_register() {
+    const PAGE_PROGRAM_CCR: u32 = 0x0000_2502;
+
+    let (mut session, write_log) = attach();
+    session.add_access_mediator(
+        FLASH,
+        Arc::new(Stm32Quadspi {
+            registers: QUADSPI,
+            memory_mapped_ccr: MEMORY_MAPPED_CCR,
+            indirect_write_ccr: Some(PAGE_PROGRAM_CCR),
+        }),
+    );
+
+    let mut core = session.core(0).unwrap();
+
+    // The FIFO always has space, and transfers complete instantly.
+    core.write_word_32(QUADSPI_SR, 0b110).unwrap();
+    core.write_word_32(QUADSPI_CCR, INDIRECT_READ_CCR).unwrap();
+    write_log.clear();
+
+    core.write_8(FLASH.start + 0x10, &[1, 2, 3]).unwrap();
+
+    let entries = write_log.entries();
+    assert!(entries.contains(&(QUADSPI_DLR as u32, 2)));
+    assert!(entries.contains(&(QUADSPI_CCR as u32, PAGE_PROGRAM_CCR)));
+    assert!(entries.contains(&(QUADSPI_AR as u32, 0x10)));
+
+    // The memory-mapped region itself was never written.
+    assert!(entries
+        .iter()
+        .all(|(address, _)| !FLASH.contains(&(*address as u64))));
+
+    core.release_access_mediators().unwrap();
+    assert_eq!(core.read_word_32(QUADSPI_CCR).unwrap(), INDIRECT_READ_CCR);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use probe_rs::{
+    AddressMapping, CoreStatus, Error, FakeProbe, HaltReason, MemoryInterface, PanicBreakOptions,
+    Session, PANIC_BREAKPOINT_GROUP,
+};
+use std::{path::Path, time::Duration};
+
+const DFSR: u64 = 0xE000_ED30;
+
+fn attach() -> Session {
+    // The breakpoint unit of e.g. a Cortex-M7, which can break outside of the code region.
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_fpb_revision(1);
+
+    common::attach_probe(probe)
+}
+
+#[test]
+fn overlapping_link_ranges_are_rejected() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.set_load_offset(0x0800_0000..0x0810_0000, 0x2400_0000)
+        .unwrap();
+
+    let error = core
+        .set_load_offset(0x080f_0000..0x0820_0000, 0x2500_0000)
+        .unwrap_err();
+    assert!(matches!(error, Error::InvalidAddressMapping { .. }));
+
+    assert_eq!(
+        core.address_map().mappings(),
+        [AddressMapping {
+            link_range: 0x0800_0000..0x0810_0000,
+            load_base: 0x2400_0000,
+        }]
+    );
+}
+
+#[test]
+fn breakpoint_at_link_address_is_hit_at_load_address() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.set_load_offset(0x0800_0000..0x0810_0000, 0x2400_0000)
+        .unwrap();
+
+    let load_address = core.set_hw_breakpoint_at_link_address(0x0800_0400).unwrap();
+    assert_eq!(load_address, 0x2400_0400);
+
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // Halt at the breakpoint, which is at the load address.
+    let pc = core.registers().program_counter();
+    core.write_core_reg(pc.into(), 0x2400_0400u32).unwrap();
+    core.write_word_32(DFSR, 0b10).unwrap();
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+
+    let pc: u64 = core.read_core_reg(pc).unwrap();
+    assert_eq!(core.breakpoint_link_address(pc), 0x0800_0400);
+}
+
+#[test]
+fn memory_accesses_are_translated_on_request() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.set_load_offset(0x0800_0000..0x0810_0000, 0x2400_0000)
+        .unwrap();
+
+    core.set_translate_memory_accesses(true);
+    core.write_word_32(0x0800_0100, 0xdead_beef).unwrap();
+
+    core.set_translate_memory_accesses(false);
+    assert_eq!(core.read_word_32(0x2400_0100).unwrap(), 0xdead_beef);
+    assert_eq!(core.read_word_32(0x0800_0100).unwrap(), 0);
+}
+
+#[test]
+fn panic_breakpoints_are_set_at_load_addresses() {
+    let mut session = attach();
+
+    session
+        .core(0)
+        .unwrap()
+        .set_load_offset(0x0000_0000..0x0001_0000, 0x2400_0000)
+        .unwrap();
+
+    let hooks = session
+        .break_on_panic(
+            Path::new("tests/inlined-function"),
+            &PanicBreakOptions::new(),
+        )
+        .unwrap();
+
+    let addresses: Vec<_> = hooks
+        .iter()
+        .map(|hook| (hook.address, hook.load_address))
+        .collect();
+
+    assert_eq!(
+        addresses,
+        [
+            (Some(0x1326), Some(0x2400_1326)),
+            (Some(0x1010), Some(0x2400_1010)),
+            (None, None),
+            (Some(0x1456), Some(0x2400_1456)),
+        ]
+    );
+
+    let mut core = session.core(0).unwrap();
+
+    // The breakpoints are reported at their link addresses.
+    assert_eq!(
+        core.breakpoint_group(PANIC_BREAKPOINT_GROUP),
+        [0x1326, 0x1010, 0x1456]
+    );
+
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    let pc = core.registers().program_counter();
+    core.write_core_reg(pc.into(), 0x2400_1326u32).unwrap();
+    core.write_word_32(DFSR, 0b10).unwrap();
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Panic)
+    );
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    BreakpointFailure, BreakpointMechanism, BreakpointOutcome, BreakpointRequest, CoreStatus,
+    FakeProbe, HaltReason, MemoryInterface, RegisterId, Session,
+};
+
+#[test]
+fn breakpoints_fall_back_to_software_breakpoints_in_ram() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    core.write_word_32(0x2000_0100, 0x4770_4770).unwrap();
+
+    let requests = [
+        BreakpointRequest::new(0x2000_0100),
+        BreakpointRequest::new(0x0800_0100),
+        BreakpointRequest::new(0x0800_0200),
+        BreakpointRequest::new(0x0800_0100),
+        BreakpointRequest::new(0x0800_0300),
+        BreakpointRequest::new(0x0800_0400),
+    ];
+
+    let plan = core.plan_breakpoints(&requests).unwrap();
+    assert_eq!(plan.software_breakpoints(), 1);
+
+    let report = core.apply_breakpoint_plan(&plan).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(
+        report.outcomes,
+        [
+            BreakpointOutcome::Applied {
+                mechanism: BreakpointMechanism::Software
+            },
+            BreakpointOutcome::Applied {
+                mechanism: BreakpointMechanism::Hardware { unit: 0 }
+            },
+            BreakpointOutcome::Applied {
+                mechanism: BreakpointMechanism::Hardware { unit: 1 }
+            },
+            BreakpointOutcome::Skipped { duplicate_of: 1 },
+            BreakpointOutcome::Applied {
+                mechanism: BreakpointMechanism::Hardware { unit: 2 }
+            },
+            BreakpointOutcome::Applied {
+                mechanism: BreakpointMechanism::Hardware { unit: 3 }
+            },
+        ]
+    );
+
+    // BKPT #0 replaces the first instruction.
+    assert_eq!(core.read_word_32(0x2000_0100).unwrap(), 0x4770_be00);
+
+    core.clear_sw_breakpoint(0x2000_0100).unwrap();
+    assert_eq!(core.read_word_32(0x2000_0100).unwrap(), 0x4770_4770);
+}
+
+#[test]
+fn nothing_is_applied_if_a_breakpoint_is_unsatisfiable() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    let requests: Vec<_> = (0..5)
+        .map(|n| BreakpointRequest::hardware(0x0800_0100 + 0x100 * n))
+        .collect();
+
+    let report = core.apply_breakpoints(&requests).unwrap();
+    assert!(!report.is_complete());
+    assert_eq!(
+        report.outcomes[0],
+        BreakpointOutcome::Failed {
+            reason: BreakpointFailure::Aborted
+        }
+    );
+    assert_eq!(
+        report.outcomes[4],
+        BreakpointOutcome::Failed {
+            reason: BreakpointFailure::NoComparatorLeft
+        }
+    );
+
+    assert_eq!(core.available_breakpoint_units().unwrap(), 4);
+    core.refresh_breakpoints().unwrap();
+    for n in 0..5 {
+        assert!(core.clear_hw_breakpoint(0x0800_0100 + 0x100 * n).is_err());
+    }
+}
+
+#[test]
+fn breakpoints_are_rolled_back_if_a_stale_plan_fails() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    core.write_word_32(0x2000_0100, 0x4770_4770).unwrap();
+    core.set_hw_breakpoint(0x0800_1000).unwrap();
+    core.set_hw_breakpoint(0x0800_2000).unwrap();
+
+    let requests = [
+        BreakpointRequest::new(0x2000_0100),
+        BreakpointRequest::new(0x0800_0100),
+        BreakpointRequest::new(0x0800_0200),
+    ];
+
+    let plan = core.plan_breakpoints(&requests).unwrap();
+
+    // Take the comparator the plan assigned to the second request.
+    core.set_hw_breakpoint(0x0800_3000).unwrap();
+
+    let report = core.apply_breakpoint_plan(&plan).unwrap();
+    assert!(matches!(
+        report.outcomes[..],
+        [
+            BreakpointOutcome::Failed {
+                reason: BreakpointFailure::Aborted
+            },
+            BreakpointOutcome::Failed {
+                reason: BreakpointFailure::Error(_)
+            },
+            BreakpointOutcome::Failed {
+                reason: BreakpointFailure::Aborted
+            },
+        ]
+    ));
+
+    assert!(core.sw_breakpoints().is_empty());
+    assert_eq!(core.read_word_32(0x2000_0100).unwrap(), 0x4770_4770);
+    assert!(core.clear_hw_breakpoint(0x0800_0100).is_err());
+    assert!(core.clear_hw_breakpoint(0x0800_0200).is_err());
+}
+
+/// Attach to a core which executes code, halted at `movs r0, #1; adds r0, #1; adds r0, #1;
+/// bkpt #0` at 0x2000_0100.
+fn attach_executing() -> Session {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+    let mut session = common::attach_probe(probe);
+
+    {
+        let mut core = session.core(0).unwrap();
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        let code: Vec<u8> = [0x2001u16, 0x3001, 0x3001, 0xbe00]
+            .iter()
+            .flat_map(|half| half.to_le_bytes())
+            .collect();
+        core.write_8(0x2000_0100, &code).unwrap();
+        core.write_core_reg(RegisterId(0b1_0000), 0x0100_0000u32)
+            .unwrap();
+        core.write_core_reg(RegisterId(15), 0x2000_0100u32).unwrap();
+    }
+
+    session
+}
+
+#[test]
+fn software_breakpoints_are_reported_as_such() {
+    let mut session = attach_executing();
+    let mut core = session.core(0).unwrap();
+
+    core.set_sw_breakpoint(0x2000_0102).unwrap();
+    core.run().unwrap();
+    core.wait_for_core_halted(Duration::from_millis(100))
+        .unwrap();
+
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::SoftwareBreakpoint)
+    );
+    assert_eq!(
+        core.read_core_reg::<u32>(RegisterId(15)).unwrap(),
+        0x2000_0102
+    );
+
+    // A breakpoint instruction of the firmware is no software breakpoint of probe-rs.
+    core.clear_all_sw_breakpoints().unwrap();
+    core.run().unwrap();
+    core.wait_for_core_halted(Duration::from_millis(100))
+        .unwrap();
+
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+    assert_eq!(
+        core.read_core_reg::<u32>(RegisterId(15)).unwrap(),
+        0x2000_0106
+    );
+    assert_eq!(core.read_core_reg::<u32>(RegisterId(0)).unwrap(), 3);
+}
+
+#[test]
+fn steps_execute_the_instruction_of_a_software_breakpoint() {
+    let mut session = attach_executing();
+    let mut core = session.core(0).unwrap();
+
+    core.set_sw_breakpoint(0x2000_0100).unwrap();
+    core.set_sw_breakpoint(0x2000_0102).unwrap();
+
+    assert_eq!(core.step().unwrap().pc, 0x2000_0102);
+    assert_eq!(core.step_fast().unwrap().pc, 0x2000_0104);
+    assert_eq!(core.read_core_reg::<u32>(RegisterId(0)).unwrap(), 2);
+
+    // The breakpoints are still set.
+    assert_eq!(core.sw_breakpoints(), [0x2000_0100, 0x2000_0102]);
+    assert_eq!(core.read_word_32(0x2000_0100).unwrap(), 0xbe00_be00);
+
+    core.clear_all_sw_breakpoints().unwrap();
+    assert_eq!(core.read_word_32(0x2000_0100).unwrap(), 0x3001_2001);
+}
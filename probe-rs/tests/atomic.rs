@@ -0,0 +1,195 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::FirmwareWrites, Error, FakeProbe, Intrusiveness, MemoryInterface, RegisterId,
+    Session, TargetOperation,
+};
+
+/// The address of the endless loop the firmware runs in.
+const LOOP: u64 = 0x2000_1000;
+
+/// The mailbox which the debugger shares with the firmware.
+const MAILBOX: u64 = 0x2000_2000;
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Attach to `target`, whose mocked core runs an endless loop, with `value` in the mailbox.
+fn attach(target: &str, value: u32) -> (Session, FirmwareWrites) {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+    let firmware_writes = probe.firmware_writes();
+
+    let mut session = common::attach_to(probe, target);
+
+    let mut core = session.core(0).unwrap();
+    core.halt(TIMEOUT).unwrap();
+    // b .
+    core.write_word_32(LOOP, 0xe7fe_e7fe).unwrap();
+    core.write_word_32(MAILBOX, value).unwrap();
+    core.write_core_reg(RegisterId(15), LOOP as u32).unwrap();
+    core.run().unwrap();
+    drop(core);
+
+    (session, firmware_writes)
+}
+
+#[test]
+fn plain_read_modify_write_loses_the_write_of_the_firmware() {
+    let (mut session, firmware_writes) = attach("stm32wb55ccux", 1);
+    let mut core = session.core(0).unwrap();
+
+    firmware_writes.set_bits_after_read(MAILBOX as u32, 0x100);
+
+    let value = core.read_word_32(MAILBOX).unwrap();
+    core.write_word_32(MAILBOX, value | 0x10).unwrap();
+
+    assert_eq!(firmware_writes.pending(), 0);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 0x11);
+}
+
+#[test]
+fn fetch_or_keeps_the_write_of_the_firmware() {
+    let (mut session, firmware_writes) = attach("stm32wb55ccux", 1);
+    let mut core = session.core(0).unwrap();
+
+    firmware_writes.set_bits_after_read(MAILBOX as u32, 0x100);
+
+    // The firmware sets its bit after the first LDREX, so the routine retries.
+    let previous = core.fetch_or_32(MAILBOX, 0x10).unwrap();
+
+    assert_eq!(previous, 0x101);
+    assert_eq!(firmware_writes.pending(), 0);
+    assert!(!core.core_halted().unwrap());
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 0x111);
+
+    let previous = core.fetch_and_32(MAILBOX, !0x100).unwrap();
+
+    assert_eq!(previous, 0x111);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 0x11);
+}
+
+#[test]
+fn compare_and_swap_does_not_overwrite_a_changed_word() {
+    let (mut session, firmware_writes) = attach("stm32wb55ccux", 1);
+    let mut core = session.core(0).unwrap();
+
+    firmware_writes.set_bits_after_read(MAILBOX as u32, 0x100);
+
+    let previous = core.compare_and_swap_32(MAILBOX, 1, 2).unwrap();
+
+    assert_eq!(previous, 0x101);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 0x101);
+
+    let previous = core.compare_and_swap_32(MAILBOX, 0x101, 2).unwrap();
+
+    assert_eq!(previous, 0x101);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 2);
+
+    // The core runs the firmware again, and its registers and the scratch RAM are restored.
+    assert!(!core.core_halted().unwrap());
+    core.halt(TIMEOUT).unwrap();
+    let pc: u32 = core.read_core_reg(RegisterId(15)).unwrap();
+    assert_eq!(u64::from(pc), LOOP);
+}
+
+#[test]
+fn halted_core_is_modified_in_place() {
+    let (mut session, _) = attach("stm32wb55ccux", 0xff);
+    let mut core = session.core(0).unwrap();
+    core.halt(TIMEOUT).unwrap();
+
+    assert_eq!(core.fetch_and_32(MAILBOX, 0x0f).unwrap(), 0xff);
+    assert_eq!(core.compare_and_swap_32(MAILBOX, 0x0f, 3).unwrap(), 0x0f);
+
+    assert!(core.core_halted().unwrap());
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 3);
+}
+
+#[test]
+fn cores_without_exclusive_accesses_need_to_be_forced() {
+    let (mut session, firmware_writes) = attach("nrf51822_xxAC", 1);
+    let mut core = session.core(0).unwrap();
+
+    assert!(matches!(
+        core.fetch_or_32(MAILBOX, 0x10),
+        Err(Error::AtomicUnsupported)
+    ));
+
+    // Forced, the word is modified with a plain read and write, which races with the
+    // firmware.
+    core.set_force_non_atomic(true);
+    firmware_writes.set_bits_after_read(MAILBOX as u32, 0x100);
+
+    assert_eq!(core.fetch_or_32(MAILBOX, 0x10).unwrap(), 1);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 0x11);
+}
+
+#[test]
+fn atomics_are_refused_without_writes() {
+    let (mut session, _) = attach("stm32wb55ccux", 1);
+    session.set_max_intrusiveness(Intrusiveness::HartResources);
+    let mut core = session.core(0).unwrap();
+
+    assert!(matches!(
+        core.fetch_or_32(MAILBOX, 0x10),
+        Err(Error::IntrusivenessExceeded {
+            operation: TargetOperation::WriteMemory,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn unaligned_words_are_rejected() {
+    let (mut session, _) = attach("stm32wb55ccux", 1);
+    let mut core = session.core(0).unwrap();
+
+    assert!(matches!(
+        core.compare_and_swap_32(MAILBOX + 2, 1, 2),
+        Err(Error::UnalignedAtomic(address)) if address == MAILBOX + 2
+    ));
+}
+
+#[test]
+fn faulting_routine_restores_the_core_and_the_scratch_ram() {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+    let write_faults = probe.write_faults();
+
+    let mut session = common::attach_to(probe, "stm32wb55ccux");
+    let mut core = session.core(0).unwrap();
+
+    core.halt(TIMEOUT).unwrap();
+    // The scratch RAM the routine is loaded into, at the start of the RAM.
+    core.write_8(0x2000_0000, &[0xa5; 40]).unwrap();
+    core.write_word_32(LOOP, 0xe7fe_e7fe).unwrap();
+    core.write_word_32(MAILBOX, 1).unwrap();
+    core.write_core_reg(RegisterId(15), LOOP as u32).unwrap();
+    core.write_core_reg(RegisterId(4), 0x1234_5678u32).unwrap();
+    core.run().unwrap();
+
+    // The STREX of the routine faults.
+    write_faults.insert(MAILBOX as u32);
+
+    assert!(matches!(
+        core.fetch_or_32(MAILBOX, 0x10),
+        Err(Error::RoutineCrashed { .. })
+    ));
+
+    // The core runs the firmware again, with its registers and the scratch RAM restored.
+    assert!(!core.core_halted().unwrap());
+    core.halt(TIMEOUT).unwrap();
+    let pc: u32 = core.read_core_reg(RegisterId(15)).unwrap();
+    let r4: u32 = core.read_core_reg(RegisterId(4)).unwrap();
+    assert_eq!(u64::from(pc), LOOP);
+    assert_eq!(r4, 0x1234_5678);
+
+    let mut scratch = [0; 40];
+    core.read_8(0x2000_0000, &mut scratch).unwrap();
+    assert_eq!(scratch, [0xa5; 40]);
+
+    write_faults.remove(MAILBOX as u32);
+    assert_eq!(core.read_word_32(MAILBOX).unwrap(), 1);
+}
@@ -0,0 +1,66 @@
+use probe_rs::{
+    AttachGuard, Error, FakeProbe, GuardPolicy, MemoryInterface, Permissions, Probe, Session,
+};
+
+/// The address of the version string of the firmware.
+const VERSION: u64 = 0x0800_0200;
+
+fn attach_guarded(guard: AttachGuard) -> Result<Session, Error> {
+    let probe = Probe::from_specific_probe(Box::new(FakeProbe::with_mocked_core()));
+
+    Session::attach_guarded(probe, "stm32wb55ccux", Permissions::default(), guard)
+}
+
+#[test]
+fn matching_firmware_unlocks_the_session() {
+    // The memory of the mocked core reads as zeros.
+    let mut session = attach_guarded(AttachGuard::new().fingerprint_bytes(VERSION, &[0; 6]))
+        .expect("Failed to attach to the expected firmware");
+
+    let mut core = session.core(0).unwrap();
+    core.write_word_32(0x2000_0000, 0xdead_beef).unwrap();
+    assert_eq!(core.read_word_32(0x2000_0000).unwrap(), 0xdead_beef);
+}
+
+#[test]
+fn other_firmware_is_reported_with_its_memory() {
+    for policy in [GuardPolicy::NonIntrusiveReads, GuardPolicy::Attach] {
+        let error = attach_guarded(
+            AttachGuard::new()
+                .fingerprint_bytes(VERSION, &[0; 6])
+                .fingerprint_bytes(VERSION + 0x10, b"2.3.0\0")
+                .policy(policy),
+        )
+        .unwrap_err();
+
+        match error {
+            Error::FirmwareMismatch { mismatches } => {
+                assert_eq!(mismatches.len(), 1);
+                assert_eq!(mismatches[0].fingerprint.address, VERSION + 0x10);
+                assert_eq!(mismatches[0].observed, [0; 6]);
+                assert_ne!(
+                    mismatches[0].observed_sha256,
+                    mismatches[0].fingerprint.sha256
+                );
+            }
+            other => panic!("Expected a firmware mismatch, got {}", other),
+        }
+    }
+}
+
+#[test]
+fn non_intrusive_reads_are_always_allowed() {
+    let mut session = attach_guarded(AttachGuard::new()).unwrap();
+
+    session
+        .core(0)
+        .unwrap()
+        .write_word_32(0x2000_0000, 0x0403_0201)
+        .unwrap();
+
+    let mut data = [0; 3];
+    session
+        .read_non_intrusive(0, 0x2000_0001, &mut data)
+        .unwrap();
+    assert_eq!(data, [2, 3, 4]);
+}
@@ -0,0 +1,222 @@
+use probe_rs::{
+    config::{
+        get_target_by_name, Core, CoreAccessOptions, CoreType, ResetScope, RiscvCoreAccessOptions,
+        Target,
+    },
+    test_utils::WriteLog,
+    AttachDeviation, AttachOptions, AttachPlan, CoreDirective, Error, FakeProbe, Permissions,
+    Probe, ProbeCapabilities, Session,
+};
+
+const DEMCR: u32 = 0xE000_EDFC;
+
+/// A STM32WB with a RISC-V coprocessor behind the same debug port.
+fn mixed_target() -> Target {
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+
+    target.cores.push(Core {
+        name: "coprocessor".into(),
+        core_type: CoreType::Riscv,
+        core_access_options: CoreAccessOptions::Riscv(RiscvCoreAccessOptions::default()),
+        reset_scope: ResetScope::Core,
+        hardware_breakpoints: None,
+        watchpoints: None,
+    });
+
+    target
+}
+
+/// A mocked core, whose reset line is controlled by the probe.
+fn probe_with_reset_line() -> FakeProbe {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_capabilities(ProbeCapabilities::new().swd().jtag().reset_control());
+
+    probe
+}
+
+fn attach_with_plan(probe: FakeProbe, target: Target, plan: AttachPlan) -> Result<Session, Error> {
+    Session::attach_with_plan(
+        Probe::from_specific_probe(Box::new(probe)),
+        target,
+        Permissions::default(),
+        AttachOptions::default(),
+        plan,
+    )
+}
+
+fn attach_log(attach: impl FnOnce(Probe) -> Session, probe: FakeProbe) -> WriteLog {
+    let log = probe.write_log();
+    drop(attach(Probe::from_specific_probe(Box::new(probe))));
+
+    log
+}
+
+#[test]
+fn the_default_plans_attach_like_the_probe() {
+    let attach = attach_log(
+        |probe| {
+            probe
+                .attach("stm32wb55ccux", Permissions::default())
+                .unwrap()
+        },
+        FakeProbe::with_mocked_core(),
+    );
+    let planned = attach_log(
+        |probe| {
+            Session::attach_with_plan(
+                probe,
+                "stm32wb55ccux",
+                Permissions::default(),
+                AttachOptions::default(),
+                AttachPlan::new(),
+            )
+            .unwrap()
+        },
+        FakeProbe::with_mocked_core(),
+    );
+    assert_eq!(attach.entries(), planned.entries());
+
+    let attach = attach_log(
+        |probe| {
+            probe
+                .attach_under_reset("stm32wb55ccux", Permissions::default())
+                .unwrap()
+        },
+        probe_with_reset_line(),
+    );
+    let planned = attach_log(
+        |probe| {
+            Session::attach_with_plan(
+                probe,
+                "stm32wb55ccux",
+                Permissions::default(),
+                AttachOptions::default(),
+                AttachPlan::under_reset(),
+            )
+            .unwrap()
+        },
+        probe_with_reset_line(),
+    );
+    assert_eq!(attach.entries(), planned.entries());
+
+    // The reset vector was caught while the reset line was released.
+    let demcr: Vec<_> = planned
+        .entries()
+        .into_iter()
+        .filter(|(address, _)| *address == DEMCR)
+        .map(|(_, value)| value & 1)
+        .collect();
+    let set = demcr.iter().position(|catch| *catch == 1).unwrap();
+    assert!(demcr[set..].contains(&0));
+}
+
+#[test]
+fn under_reset_the_first_core_is_halted_at_its_reset_vector() {
+    let mut session = attach_with_plan(
+        probe_with_reset_line(),
+        get_target_by_name("stm32wb55ccux").unwrap(),
+        AttachPlan::under_reset(),
+    )
+    .unwrap();
+
+    let report = session.attach_report();
+    assert_eq!(report.directives, [CoreDirective::ResetAndHalt]);
+    assert!(report.vector_caught);
+    assert!(report.deviations.is_empty());
+
+    assert!(session.core(0).unwrap().core_halted().unwrap());
+}
+
+#[test]
+fn without_a_reset_line_the_core_is_reset_through_its_debug_registers() {
+    let mut session = attach_with_plan(
+        FakeProbe::with_mocked_core(),
+        get_target_by_name("stm32wb55ccux").unwrap(),
+        AttachPlan::under_reset().vector_catch(false),
+    )
+    .unwrap();
+
+    assert_eq!(
+        session.attach_report().deviations,
+        [
+            AttachDeviation::ResetLineUnavailable { core: 0 },
+            AttachDeviation::VectorCaught { core: 0 },
+        ]
+    );
+    assert!(session.core(0).unwrap().core_halted().unwrap());
+}
+
+#[test]
+fn a_reset_which_reaches_an_untouched_core_is_rejected() {
+    let probe = probe_with_reset_line();
+    let log = probe.write_log();
+
+    let error = attach_with_plan(
+        probe,
+        mixed_target(),
+        AttachPlan::under_reset().core(1, CoreDirective::LeaveUntouched),
+    )
+    .unwrap_err();
+
+    assert!(matches!(error, Error::InvalidAttachPlan { core: 1, .. }));
+    // Nothing was sent to the target.
+    assert!(log.is_empty());
+}
+
+#[test]
+fn a_reset_which_reaches_an_initialized_core_is_reported() {
+    let session = attach_with_plan(
+        {
+            let mut probe = probe_with_reset_line();
+            probe.mock_riscv_debug_module();
+            probe
+        },
+        mixed_target(),
+        AttachPlan::under_reset(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        session.attach_report().deviations,
+        [AttachDeviation::ResetByOtherCore {
+            core: 1,
+            reset_by: 0
+        }]
+    );
+}
+
+#[test]
+fn untouched_cores_are_only_set_up_at_their_first_use() {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.mock_riscv_debug_module();
+
+    let mut session = attach_with_plan(
+        probe,
+        mixed_target(),
+        AttachPlan::new().core(1, CoreDirective::DeferUntilFirstUse),
+    )
+    .unwrap();
+
+    assert_eq!(
+        session.attach_report().directives,
+        [
+            CoreDirective::Initialize { halt: false },
+            CoreDirective::DeferUntilFirstUse
+        ]
+    );
+
+    // The coprocessor is reached once it is used.
+    assert!(session.core(1).unwrap().core_halted().unwrap());
+}
+
+#[test]
+fn an_unknown_core_is_rejected() {
+    let error = attach_with_plan(
+        FakeProbe::with_mocked_core(),
+        get_target_by_name("stm32wb55ccux").unwrap(),
+        AttachPlan::new().core(3, CoreDirective::LeaveUntouched),
+    )
+    .unwrap_err();
+
+    assert!(matches!(error, Error::InvalidAttachPlan { core: 3, .. }));
+}
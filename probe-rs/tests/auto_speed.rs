@@ -0,0 +1,54 @@
+mod common;
+
+use probe_rs::{AttachOptions, Error, FakeProbe, HealthEvent, LinkFailure, Permissions, Probe};
+
+fn probe_with_max_speed(speed_khz: u32) -> Probe {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_max_speed(speed_khz);
+
+    Probe::from_specific_probe(Box::new(probe))
+}
+
+#[test]
+fn speed_converges_to_the_highest_working_rate() {
+    let session = probe_with_max_speed(5_000)
+        .attach_with_options(
+            "stm32wb55ccux",
+            Permissions::default(),
+            AttachOptions::new().auto_speed(40_000, 100),
+        )
+        .expect("Failed to attach with 'fake' probe.");
+
+    assert_eq!(session.negotiated_speed_khz(), Some(5_000));
+
+    let entries = session.health_log().entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].event, HealthEvent::SpeedReduced);
+}
+
+#[test]
+fn failure_at_the_minimum_speed_is_reported() {
+    let error = probe_with_max_speed(50)
+        .attach_with_options(
+            "stm32wb55ccux",
+            Permissions::default(),
+            AttachOptions::new().auto_speed(4_000, 1_000),
+        )
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::SpeedNegotiationFailed {
+            speed_khz: 1_000,
+            failure: LinkFailure::NoResponse,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn speed_is_not_negotiated_by_default() {
+    let session = common::attach();
+
+    assert_eq!(session.negotiated_speed_khz(), None);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use probe_rs::{
+    CoreStatus, HaltReason, MemoryInterface, PanicBreakOptions, PANIC_BREAKPOINT_GROUP,
+};
+use std::{path::Path, time::Duration};
+
+const DFSR: u64 = 0xE000_ED30;
+
+#[test]
+fn panic_hooks_are_armed() {
+    let mut session = common::attach();
+
+    let mut options = PanicBreakOptions::new();
+    options.symbols.push("no_such_symbol".to_owned());
+
+    let hooks = session
+        .break_on_panic(Path::new("tests/inlined-function"), &options)
+        .unwrap();
+
+    let armed: Vec<_> = hooks
+        .iter()
+        .map(|hook| (hook.symbol.as_str(), hook.armed))
+        .collect();
+
+    assert_eq!(
+        armed,
+        [
+            ("rust_begin_unwind", true),
+            ("core::panicking::panic", true),
+            ("abort", false),
+            ("no_such_symbol", false),
+            ("HardFault", true),
+        ]
+    );
+
+    assert_eq!(
+        session
+            .core(0)
+            .unwrap()
+            .breakpoint_group(PANIC_BREAKPOINT_GROUP),
+        [0x1326, 0x1010, 0x1456]
+    );
+
+    session.clear_break_on_panic(0).unwrap();
+
+    assert!(session
+        .core(0)
+        .unwrap()
+        .breakpoint_group(PANIC_BREAKPOINT_GROUP)
+        .is_empty());
+}
+
+#[test]
+fn halt_in_panic_handler_is_reported() {
+    let mut session = common::attach();
+
+    session
+        .break_on_panic(
+            Path::new("tests/inlined-function"),
+            &PanicBreakOptions::new(),
+        )
+        .unwrap();
+
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // Halt at a breakpoint which is not on a panic handler.
+    let pc = core.registers().program_counter();
+    core.write_core_reg(pc.into(), 0x2000u32).unwrap();
+    core.write_word_32(DFSR, 0b10).unwrap();
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+
+    // Halt at the breakpoint on `rust_begin_unwind`.
+    core.write_core_reg(pc.into(), 0x1326u32).unwrap();
+    core.write_word_32(DFSR, 0b10).unwrap();
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Panic)
+    );
+}
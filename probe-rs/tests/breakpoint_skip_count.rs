@@ -0,0 +1,104 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::{BreakpointHits, ProbeTransactions},
+    BreakpointRequest, BreakpointSkipCount, Core, FakeProbe,
+};
+
+const BREAKPOINT: u64 = 0x0800_0100;
+
+/// Run the core until the breakpoint halts it after `skip_count` skipped hits, and return
+/// the number of round trips the wait took.
+fn run_to_breakpoint(
+    core: &mut Core,
+    hits: &BreakpointHits,
+    transactions: &ProbeTransactions,
+    skip_count: u32,
+) -> u32 {
+    let report = core
+        .apply_breakpoints(&[BreakpointRequest::hardware(BREAKPOINT).skip_count(skip_count)])
+        .unwrap();
+    assert!(report.is_complete());
+
+    hits.hit(BREAKPOINT as u32, skip_count + 1);
+
+    core.run().unwrap();
+    transactions.reset();
+    core.wait_for_core_halted(Duration::from_millis(500))
+        .unwrap();
+    let count = transactions.count();
+
+    assert_eq!(hits.remaining(), 0);
+
+    count
+}
+
+#[test]
+fn core_halts_after_the_skipped_hits() {
+    let probe = FakeProbe::with_mocked_core();
+    let hits = probe.breakpoint_hits();
+    let transactions = probe.transactions();
+    let mut session = common::attach_probe(probe);
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+    run_to_breakpoint(&mut core, &hits, &transactions, 3);
+
+    assert!(core.core_halted().unwrap());
+
+    let pc: u64 = core
+        .read_core_reg(core.registers().program_counter())
+        .unwrap();
+    assert_eq!(pc, BREAKPOINT);
+
+    assert_eq!(
+        core.breakpoint_skip_count(BREAKPOINT),
+        Some(BreakpointSkipCount {
+            remaining: 0,
+            skipped: 3
+        })
+    );
+}
+
+#[test]
+fn suppressed_hits_take_less_than_five_transactions() {
+    let probe = FakeProbe::with_mocked_core();
+    let hits = probe.breakpoint_hits();
+    let transactions = probe.transactions();
+    let mut session = common::attach_probe(probe);
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // The final halt costs the same in both runs, so the difference is the cost of the
+    // additional skipped hits.
+    let few = run_to_breakpoint(&mut core, &hits, &transactions, 2);
+    let many = run_to_breakpoint(&mut core, &hits, &transactions, 12);
+
+    let per_hit = f64::from(many - few) / 10.0;
+    assert!(per_hit < 5.0, "A skipped hit took {} transactions", per_hit);
+}
+
+#[test]
+fn clearing_the_breakpoint_drops_its_skip_count() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+    core.apply_breakpoints(&[BreakpointRequest::hardware(BREAKPOINT).skip_count(5)])
+        .unwrap();
+
+    assert_eq!(
+        core.breakpoint_skip_count(BREAKPOINT),
+        Some(BreakpointSkipCount {
+            remaining: 5,
+            skipped: 0
+        })
+    );
+
+    core.clear_hw_breakpoint(BREAKPOINT).unwrap();
+
+    assert_eq!(core.breakpoint_skip_count(BREAKPOINT), None);
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{CoreType, FpuSupport, InstructionSet, MemoryInterface};
+
+#[test]
+fn core_type_capabilities_are_known_without_a_target() {
+    let armv6m = CoreType::Armv6m.capabilities();
+    assert_eq!(armv6m.hardware_breakpoints, Some(4));
+    assert_eq!(armv6m.fpu, FpuSupport::None);
+    assert_eq!(armv6m.instruction_sets, vec![InstructionSet::Thumb2]);
+
+    assert_eq!(CoreType::Armv7em.capabilities().fpu, FpuSupport::Optional);
+    assert!(CoreType::Armv8a
+        .capabilities()
+        .native_access_widths
+        .contains(&64));
+
+    let riscv = CoreType::Riscv.capabilities();
+    assert_eq!(riscv.hardware_breakpoints, None);
+    assert!(!riscv.vector_catch);
+}
+
+#[test]
+fn measured_capabilities_fit_the_core_type() {
+    let mut session = common::attach();
+
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // DWT_CTRL of the mocked core, with 4 comparators
+    core.write_word_32(0xE000_1000, 4 << 28).unwrap();
+
+    let catalog = core.core_type().capabilities();
+    let measured = core.capabilities().unwrap();
+
+    // The mocked FPB has 4 code comparators, and the mocked core no FPU.
+    assert_eq!(measured.hardware_breakpoints, Some(4));
+    assert_eq!(measured.watchpoints, Some(4));
+    assert_eq!(measured.fpu, FpuSupport::None);
+    assert_eq!(measured.native_access_widths, vec![8, 16, 32]);
+
+    // Everything which is the same for all cores of the type is taken from the catalog.
+    assert_eq!(measured.instruction_sets, catalog.instruction_sets);
+    assert_eq!(measured.vector_catch, catalog.vector_catch);
+    assert!(measured.hardware_breakpoints <= catalog.hardware_breakpoints);
+    assert!(measured.watchpoints <= catalog.watchpoints);
+}
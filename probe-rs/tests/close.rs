@@ -0,0 +1,76 @@
+mod common;
+
+use probe_rs::{
+    test_utils::WriteFaults, AttachOptions, CoreStatus, DetachMode, Error, FakeProbe,
+    MemoryInterface, Session, TeardownStep,
+};
+
+/// The first comparator of the breakpoint unit of the mocked core.
+const FP_COMP0: u32 = 0xE000_2008;
+
+fn attach(options: AttachOptions) -> (Session, WriteFaults) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_faults = probe.write_faults();
+
+    let session = common::attach_with_options(probe, common::TARGET, options);
+
+    (session, write_faults)
+}
+
+#[test]
+fn close_reports_the_teardown_of_each_core() {
+    let (mut session, _) = attach(AttachOptions::new().detach_mode(DetachMode::Halt));
+
+    {
+        let mut core = session.core(0).unwrap();
+        core.set_hw_breakpoint(0x0800_1000).unwrap();
+
+        core.write_word_32(0x2000_0100, 0x4770_4770).unwrap();
+        core.set_sw_breakpoint(0x2000_0100).unwrap();
+    }
+
+    let report = session.close().expect("Failed to close the session");
+
+    assert!(report.debug_stopped);
+
+    let core = &report.cores[0];
+    assert_eq!(core.core, 0);
+    assert_eq!(core.hw_breakpoints_removed, 1);
+    assert_eq!(core.sw_breakpoints_removed, 1);
+    assert!(core.trace_disabled);
+    assert!(matches!(core.status, Some(CoreStatus::Halted(_))));
+}
+
+#[test]
+fn failed_close_returns_the_session() {
+    let (mut session, write_faults) = attach(AttachOptions::new());
+
+    session
+        .core(0)
+        .unwrap()
+        .set_hw_breakpoint(0x0800_1000)
+        .unwrap();
+
+    write_faults.insert(FP_COMP0);
+
+    let (session, error) = session.close().unwrap_err();
+
+    match error {
+        Error::CloseFailed { failures, report } => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].step, TeardownStep::ClearHwBreakpoints);
+            assert_eq!(failures[0].core, Some(0));
+
+            // The remaining steps were still performed.
+            assert!(report.cores[0].trace_disabled);
+            assert!(report.debug_stopped);
+        }
+        other => panic!("Expected a failed close, got {}", other),
+    }
+
+    // Closing can be retried once the fault is gone.
+    write_faults.remove(FP_COMP0);
+
+    let report = session.close().expect("Failed to close the session");
+    assert_eq!(report.cores[0].hw_breakpoints_removed, 1);
+}
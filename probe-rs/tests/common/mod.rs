@@ -0,0 +1,35 @@
+//! Fixtures shared by the integration tests.
+
+// Each test crate only uses some of the fixtures.
+#![allow(dead_code)]
+
+use probe_rs::{config::TargetSelector, AttachOptions, FakeProbe, Permissions, Probe, Session};
+
+/// The target the tests attach to, unless they need another one.
+pub const TARGET: &str = "stm32wb55ccux";
+
+/// Attach to [`TARGET`] with a fake probe connected to a mocked Cortex-M core.
+pub fn attach() -> Session {
+    attach_probe(FakeProbe::with_mocked_core())
+}
+
+/// Attach to [`TARGET`] with `probe`, e.g. a probe whose handles the test kept.
+pub fn attach_probe(probe: FakeProbe) -> Session {
+    attach_to(probe, TARGET)
+}
+
+/// Attach to `target` with `probe`.
+pub fn attach_to(probe: FakeProbe, target: impl Into<TargetSelector>) -> Session {
+    attach_with_options(probe, target, AttachOptions::default())
+}
+
+/// Attach to `target` with `probe`, using `options`.
+pub fn attach_with_options(
+    probe: FakeProbe,
+    target: impl Into<TargetSelector>,
+    options: AttachOptions,
+) -> Session {
+    Probe::from_specific_probe(Box::new(probe))
+        .attach_with_options(target, Permissions::default(), options)
+        .expect("Failed to attach with 'fake' probe.")
+}
@@ -0,0 +1,164 @@
+use std::ops::Range;
+
+use probe_rs::{
+    config::{get_target_by_name, MemoryRegion},
+    conformance::{Check, CheckOutcome, ConformanceSuite},
+    flashing::FlashAlgorithm,
+    test_utils::WriteFaults,
+    Error, FakeProbe, MemoryInterface, Permissions, Probe, Session,
+};
+
+const SCRATCH_RAM: Range<u64> = 0x2000_1000..0x2000_2000;
+const EXPENDABLE_FLASH: Range<u64> = 0x0803_0000..0x0803_0800;
+const VTOR: u64 = 0xE000_ED08;
+
+/// A fake probe whose core executes code, can break in RAM, and emulates the flash algorithm
+/// of the target.
+fn probe() -> FakeProbe {
+    let target = get_target_by_name("stm32wb55ccux").unwrap();
+    let ram = target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some(ram.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let algorithm = target
+        .flash_algorithms
+        .iter()
+        .find(|algorithm| {
+            algorithm
+                .flash_properties
+                .address_range
+                .contains(&EXPENDABLE_FLASH.start)
+        })
+        .unwrap();
+
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+    probe.set_fpb_revision(1);
+    probe.emulate_flash(FlashAlgorithm::assemble_from_raw(algorithm, &ram, &target).unwrap());
+
+    probe
+}
+
+fn attach(probe: FakeProbe) -> Result<Session, Error> {
+    let mut session = Probe::from_specific_probe(Box::new(probe))
+        .attach("stm32wb55ccux", Permissions::default())?;
+
+    {
+        // A vector table at the start of the flash, so that a reset halts at the reset vector.
+        let mut core = session.core(0)?;
+        core.write_word_32(VTOR, 0x0800_0000)?;
+        core.write_word_32(0x0800_0000, 0x2000_8000)?;
+        core.write_word_32(0x0800_0004, 0x0800_0101)?;
+    }
+
+    Ok(session)
+}
+
+#[test]
+fn all_checks_pass_on_a_conforming_target() {
+    let report = ConformanceSuite::new(SCRATCH_RAM)
+        .expendable_flash(EXPENDABLE_FLASH)
+        .run(|| attach(probe()))
+        .unwrap();
+
+    assert!(
+        report.passed(),
+        "{:?}",
+        report.failures().collect::<Vec<_>>()
+    );
+    assert_eq!(report.target.as_deref(), Some("stm32wb55ccux"));
+    assert_eq!(
+        report
+            .results
+            .iter()
+            .map(|result| result.check)
+            .collect::<Vec<_>>(),
+        Check::ALL
+    );
+    assert!(matches!(
+        report.result(Check::Watchpoints).unwrap().outcome,
+        CheckOutcome::Skip(_)
+    ));
+
+    let breakpoints = report.result(Check::Breakpoints).unwrap();
+    assert_eq!(breakpoints.outcome, CheckOutcome::Pass);
+    assert!(!breakpoints.measurements.is_empty());
+}
+
+#[test]
+fn checks_can_be_skipped() {
+    let report = ConformanceSuite::new(SCRATCH_RAM)
+        .skip(Check::Breakpoints)
+        .skip(Check::Reset)
+        .run(|| attach(probe()))
+        .unwrap();
+
+    assert!(report.passed());
+    for check in [Check::Breakpoints, Check::Reset] {
+        assert_eq!(
+            report.result(check).unwrap().outcome,
+            CheckOutcome::Skip("Skipped by the configuration".to_string())
+        );
+    }
+    // Without expendable flash, flashing isn't checked.
+    assert!(matches!(
+        report.result(Check::Flash).unwrap().outcome,
+        CheckOutcome::Skip(_)
+    ));
+}
+
+#[test]
+fn failed_attach_skips_all_other_checks() {
+    let report = ConformanceSuite::new(SCRATCH_RAM)
+        .run(|| Err::<Session, _>("No probe is connected"))
+        .unwrap();
+
+    assert!(!report.passed());
+    assert_eq!(report.target, None);
+    assert!(matches!(
+        report.result(Check::Attach).unwrap().outcome,
+        CheckOutcome::Fail(_)
+    ));
+    assert!(report.results[1..]
+        .iter()
+        .all(|result| matches!(result.outcome, CheckOutcome::Skip(_))));
+}
+
+#[test]
+fn scratch_ram_outside_of_ram_is_rejected() {
+    let error = ConformanceSuite::new(0x0800_0000..0x0800_1000)
+        .run(|| attach(probe()))
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::InvalidConformanceRange {
+            start: 0x0800_0000,
+            end: 0x0800_1000,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn write_faults_fail_the_memory_check() {
+    let probe = probe();
+    let write_faults: WriteFaults = probe.write_faults();
+    write_faults.insert(SCRATCH_RAM.start as u32 + 0x10);
+
+    let mut probe = Some(probe);
+    let report = ConformanceSuite::new(SCRATCH_RAM)
+        .skip(Check::Reattach)
+        .run(|| attach(probe.take().unwrap_or_else(self::probe)))
+        .unwrap();
+
+    assert!(matches!(
+        report.result(Check::Memory).unwrap().outcome,
+        CheckOutcome::Fail(_)
+    ));
+    assert!(!report.passed());
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    ContextSnapshot, Error, FakeProbe, MemoryInterface, RegisterId, RegisterValue, Session,
+};
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+const CPACR: u64 = 0xE000_ED88;
+const S0: RegisterId = RegisterId(0x40);
+
+fn attach(chip: &str) -> Session {
+    common::attach_to(FakeProbe::with_mocked_core(), chip)
+}
+
+#[test]
+fn context_is_restored() {
+    let mut session = attach("stm32wb55ccux");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    for register in 0..13 {
+        core.write_core_reg(RegisterId(register), 0x1000 + register as u32)
+            .unwrap();
+    }
+    core.write_core_reg(RegisterId(15), 0x0800_0100u32).unwrap();
+    core.write_32(RAM, &[0x1111_1111, 0x2222_2222]).unwrap();
+
+    let snapshot = core.save_context(&[RAM..RAM + 8]).unwrap();
+
+    // The snapshot can be persisted, e.g. between invocations of a tool.
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let snapshot: ContextSnapshot = serde_json::from_str(&json).unwrap();
+
+    // Experiment with the core.
+    for register in 0..13 {
+        core.write_core_reg(RegisterId(register), 0u32).unwrap();
+    }
+    core.write_core_reg(RegisterId(15), 0x0800_0200u32).unwrap();
+    core.write_word_32(RAM + 4, 0).unwrap();
+
+    let report = core.restore_context(&snapshot).unwrap();
+    assert!(report.is_complete(), "{:?}", report);
+    assert!(report.registers.is_empty());
+
+    for register in 0..13 {
+        assert_eq!(
+            core.read_core_reg::<u32>(RegisterId(register)).unwrap(),
+            0x1000 + register as u32
+        );
+    }
+    assert_eq!(
+        core.read_core_reg::<u32>(RegisterId(15)).unwrap(),
+        0x0800_0100
+    );
+
+    let mut data = [0; 2];
+    core.read_32(RAM, &mut data).unwrap();
+    assert_eq!(data, [0x1111_1111, 0x2222_2222]);
+}
+
+#[test]
+fn changed_snapshot_is_rejected() {
+    let mut session = attach("stm32wb55ccux");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    let snapshot = core.save_context(&[RAM..RAM + 4]).unwrap();
+
+    let json = serde_json::to_string(&snapshot).unwrap();
+    let changed = json.replacen("\"value\":", "\"value\":1", 1);
+    let changed: ContextSnapshot = serde_json::from_str(&changed).unwrap();
+
+    assert!(matches!(
+        core.restore_context(&changed),
+        Err(Error::ContextSnapshotCorrupted)
+    ));
+}
+
+#[test]
+fn snapshot_of_another_core_type_is_rejected() {
+    let mut session = attach("nrf51822_xxAC");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    let snapshot = core.save_context(&[]).unwrap();
+    drop(core);
+
+    let mut session = attach("stm32wb55ccux");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    match core.restore_context(&snapshot) {
+        Err(Error::ContextCoreMismatch { snapshot, core }) => assert_ne!(snapshot, core),
+        other => panic!("Expected the snapshot to be rejected, got {:?}", other),
+    }
+}
+
+#[test]
+fn core_snapshot_restores_the_registers_and_the_fpu() {
+    let mut session = attach("stm32wb55ccux");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // Full access to the FPU.
+    core.write_word_32(CPACR, 0x00f0_0000).unwrap();
+
+    for register in 0..13 {
+        core.write_core_reg(RegisterId(register), 0x1000 + register as u32)
+            .unwrap();
+    }
+    core.write_core_reg(RegisterId(15), 0x0800_0100u32).unwrap();
+    core.write_core_reg(S0, 0x3f80_0000u32).unwrap();
+
+    let snapshot = core.take_snapshot().unwrap();
+    assert_eq!(snapshot.pc, RegisterValue::U32(0x0800_0100));
+    // FPSCR and S0-S31.
+    assert_eq!(snapshot.fpu_registers.as_ref().map(Vec::len), Some(33));
+
+    // Call a function in the target, which uses the registers as scratch registers.
+    for register in 0..13 {
+        core.write_core_reg(RegisterId(register), 0u32).unwrap();
+    }
+    core.write_core_reg(RegisterId(15), 0x0800_0200u32).unwrap();
+    core.write_core_reg(S0, 0u32).unwrap();
+
+    core.restore_snapshot(&snapshot).unwrap();
+
+    for register in 0..13 {
+        assert_eq!(
+            core.read_core_reg::<u32>(RegisterId(register)).unwrap(),
+            0x1000 + register as u32
+        );
+    }
+    assert_eq!(
+        core.read_core_reg::<u32>(RegisterId(15)).unwrap(),
+        0x0800_0100
+    );
+    assert_eq!(core.read_core_reg::<u32>(S0).unwrap(), 0x3f80_0000);
+}
+
+#[test]
+fn core_snapshot_without_fpu() {
+    let mut session = attach("nrf51822_xxAC");
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    let snapshot = core.take_snapshot().unwrap();
+    assert_eq!(snapshot.fpu_registers, None);
+    assert!(!snapshot.registers.is_empty());
+
+    core.restore_snapshot(&snapshot).unwrap();
+}
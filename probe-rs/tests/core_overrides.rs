@@ -0,0 +1,65 @@
+use probe_rs::{
+    config::CoreAccessOptions, AttachOptions, CoreAccessOptionsOverride, Error, FakeProbe,
+    Permissions, Probe,
+};
+
+fn attach(options: AttachOptions) -> Result<probe_rs::Session, Error> {
+    let probe = Probe::from_specific_probe(Box::new(FakeProbe::new()));
+
+    probe.attach_with_options("stm32wb55ccux", Permissions::default(), options)
+}
+
+#[test]
+fn core_override_is_applied() {
+    let session = attach(
+        AttachOptions::new()
+            .core_overrides(0, CoreAccessOptionsOverride::new().debug_base(0xe000_e000)),
+    )
+    .expect("Failed to attach with 'fake' probe.");
+
+    match &session.target().cores[0].core_access_options {
+        CoreAccessOptions::Arm(options) => {
+            assert_eq!(options.ap, 0);
+            assert_eq!(options.debug_base, Some(0xe000_e000));
+        }
+        CoreAccessOptions::Riscv(_) => panic!("The core is not an ARM core"),
+    }
+}
+
+#[test]
+fn core_override_for_missing_core() {
+    let error =
+        attach(AttachOptions::new().core_overrides(3, CoreAccessOptionsOverride::new().ap(0)))
+            .expect_err("Attached with an override for a missing core");
+
+    assert!(matches!(error, Error::CoreNotFound(3)), "{:?}", error);
+}
+
+#[test]
+fn core_override_with_mismatched_architecture() {
+    let error = attach(
+        AttachOptions::new()
+            .core_overrides(0, CoreAccessOptionsOverride::new().reset_vectors(vec![0x0])),
+    )
+    .expect_err("Attached with a RISC-V override for an ARM core");
+
+    assert!(
+        matches!(error, Error::InvalidCoreAccessOptions { core: 0, .. }),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn core_override_with_missing_ap() {
+    // The fake probe only has a single access port.
+    let error =
+        attach(AttachOptions::new().core_overrides(0, CoreAccessOptionsOverride::new().ap(3)))
+            .expect_err("Attached through an access port which doesn't exist");
+
+    assert!(
+        matches!(error, Error::InvalidCoreAccessOptions { core: 0, .. }),
+        "{:?}",
+        error
+    );
+}
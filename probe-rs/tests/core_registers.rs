@@ -0,0 +1,78 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{Error, FakeProbe, RegisterId, RegisterValue};
+
+#[test]
+fn fpu_registers_are_not_available_without_fpu() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    // The mocked core reports no FPU in CPACR.
+    assert!(!core.fpu_support().unwrap());
+
+    let fpscr = RegisterId::from(core.registers().fpscr().unwrap());
+    let error = core.read_core_reg::<u32>(fpscr).unwrap_err();
+    assert!(matches!(
+        error,
+        Error::RegisterNotAvailable { name, core_variant }
+            if name == "FPSCR" && core_variant == "Armv7m"
+    ));
+
+    // Without the check, the register is accessed anyway.
+    core.read_core_reg_unchecked::<u32>(fpscr).unwrap();
+
+    core.read_core_reg::<u32>(RegisterId(0)).unwrap();
+}
+
+#[test]
+fn all_registers_are_read_in_order() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    for register in 0..13 {
+        core.write_core_reg(RegisterId(register), 0x1000 + u32::from(register))
+            .unwrap();
+    }
+
+    let registers = core.read_all_registers().unwrap();
+
+    let ids: Vec<RegisterId> = core.registers().registers().map(RegisterId::from).collect();
+    assert_eq!(registers.iter().map(|(id, _)| *id).collect::<Vec<_>>(), ids);
+    for register in 0..13 {
+        assert_eq!(
+            registers[usize::from(register)],
+            (
+                RegisterId(register),
+                RegisterValue::U32(0x1000 + u32::from(register))
+            )
+        );
+    }
+}
+
+/// ARM cores read the registers one by one through `DCRSR` and `DCRDR`, which must still
+/// return the values in the requested order, whatever it is.
+#[test]
+fn registers_are_read_in_the_requested_order_on_armv6_7_and_8_m() {
+    for target in ["nrf51822_xxAC", "stm32wb55ccux", "EFR32BG22C112F352"] {
+        let mut session = common::attach_to(FakeProbe::with_mocked_core(), target);
+        let mut core = session.core(0).unwrap();
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        for register in 0..13 {
+            core.write_core_reg(RegisterId(register), 0x1000 + u32::from(register))
+                .unwrap();
+        }
+
+        let ids = [12, 3, 0, 7, 7, 1].map(RegisterId);
+        let values = core.read_core_regs(&ids).unwrap();
+
+        let expected: Vec<_> = ids
+            .iter()
+            .map(|id| RegisterValue::U32(0x1000 + u32::from(id.0)))
+            .collect();
+        assert_eq!(values, expected, "{}", target);
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use probe_rs::{
+    plugin::{ProbeTransport, TransportKind},
+    Probe,
+};
+
+/// A transport to a simulated CMSIS-DAP probe, which answers the commands needed to open
+/// the probe and set its speed.
+struct SimulatedProbe {
+    packet_size: u16,
+    written: Arc<Mutex<Vec<Vec<u8>>>>,
+    responses: VecDeque<Vec<u8>>,
+}
+
+impl SimulatedProbe {
+    fn new(packet_size: u16) -> (Self, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+
+        let probe = Self {
+            packet_size,
+            written: written.clone(),
+            responses: VecDeque::new(),
+        };
+
+        (probe, written)
+    }
+
+    fn respond(&self, command: &[u8]) -> Vec<u8> {
+        match command {
+            // DAP_Info: packet size, packet count and capabilities (SWD only).
+            [0x00, 0xff, ..] => {
+                let [low, high] = self.packet_size.to_le_bytes();
+                vec![0x00, 2, low, high]
+            }
+            [0x00, 0xfe, ..] => vec![0x00, 1, 4],
+            [0x00, 0xf0, ..] => vec![0x00, 1, 0x01],
+            // DAP_SWJ_Clock
+            [0x11, ..] => vec![0x11, 0x00],
+            [command, ..] => vec![*command, 0xff],
+            [] => vec![],
+        }
+    }
+}
+
+impl ProbeTransport for SimulatedProbe {
+    fn write(&mut self, data: &[u8], _timeout: Duration) -> io::Result<usize> {
+        self.written.lock().unwrap().push(data.to_vec());
+        self.responses.push_back(self.respond(data));
+
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8], _timeout: Duration) -> io::Result<usize> {
+        match self.responses.pop_front() {
+            Some(response) => {
+                buffer[..response.len()].copy_from_slice(&response);
+                Ok(response.len())
+            }
+            None => Err(io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+#[test]
+fn cmsis_dap_v2_over_custom_transport() {
+    let (transport, written) = SimulatedProbe::new(512);
+
+    let mut probe = Probe::from_custom_transport(TransportKind::CmsisDapV2, Box::new(transport))
+        .expect("Failed to open the probe over the transport");
+
+    assert_eq!(probe.set_speed(4000).unwrap(), 4000);
+
+    // Packets are only as long as the command.
+    let written = written.lock().unwrap();
+    assert_eq!(written[0], [0x00, 0xff]);
+    assert_eq!(written.last().unwrap(), &[0x11, 0x00, 0x09, 0x3d, 0x00]);
+}
+
+#[test]
+fn cmsis_dap_v1_over_custom_transport() {
+    let (transport, written) = SimulatedProbe::new(64);
+
+    let mut probe = Probe::from_custom_transport(TransportKind::CmsisDapV1, Box::new(transport))
+        .expect("Failed to open the probe over the transport");
+
+    assert_eq!(probe.set_speed(1000).unwrap(), 1000);
+
+    // Every packet is a full HID report.
+    let written = written.lock().unwrap();
+    assert!(written.len() >= 4);
+    assert!(written.iter().all(|packet| packet.len() == 64));
+}
@@ -0,0 +1,110 @@
+use std::sync::{Arc, Mutex};
+
+use probe_rs::{
+    architecture::{
+        arm::{
+            dp::PowerDomain, sequences::DefaultArmSequence, ArmCommunicationInterface,
+            ArmProbeInterface, DapError, DpAddress, PortType, UninitializedArmProbe,
+        },
+        settle::DelayOrPoll,
+    },
+    DebugProbeError, Error, FakeProbe,
+};
+
+const CTRL_STAT: u8 = 0x4;
+
+const CDBGPWRUPREQ: u32 = 1 << 28;
+const CDBGPWRUPACK: u32 = 1 << 29;
+const CSYSPWRUPREQ: u32 = 1 << 30;
+
+/// An ARM interface on a mocked debug port whose power controller only acknowledges the
+/// debug power domain. The values written to CTRL/STAT are returned as well.
+fn interface_without_system_power() -> (Box<dyn ArmProbeInterface>, Arc<Mutex<Vec<u32>>>) {
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let ctrl_stat = Arc::new(Mutex::new(0));
+
+    let mut probe = FakeProbe::with_mocked_core();
+
+    let read_ctrl_stat = ctrl_stat.clone();
+    probe.set_dap_register_read_handler(Box::new(move |port, addr| {
+        Ok(match (port, addr) {
+            (PortType::DebugPort, CTRL_STAT) => {
+                let ctrl_stat = *read_ctrl_stat.lock().unwrap();
+                ctrl_stat | (ctrl_stat & CDBGPWRUPREQ) << 1
+            }
+            // DPIDR, and no APs.
+            _ => 0,
+        })
+    }));
+
+    let written = writes.clone();
+    probe.set_dap_register_write_handler(Box::new(move |port, addr, value| {
+        if (port, addr) == (PortType::DebugPort, CTRL_STAT) {
+            *ctrl_stat.lock().unwrap() = value;
+            written.lock().unwrap().push(value);
+        }
+        Ok(())
+    }));
+
+    let interface = Box::new(ArmCommunicationInterface::new(Box::new(probe), false))
+        .initialize(DefaultArmSequence::create())
+        .unwrap();
+
+    (interface, writes)
+}
+
+#[test]
+fn missing_acknowledge_is_reported_with_ctrl_stat() {
+    let (mut interface, _) = interface_without_system_power();
+
+    // Starting the debug port requests the power-up of both domains.
+    let error = interface.num_access_ports(DpAddress::Default).unwrap_err();
+
+    let source = match &error {
+        Error::Probe(DebugProbeError::ArchitectureSpecific(source)) => source,
+        other => panic!("Unexpected error: {:?}", other),
+    };
+
+    assert_eq!(
+        source.downcast_ref::<DapError>(),
+        Some(&DapError::PowerAckTimeout {
+            domain: PowerDomain::System,
+            expected: true,
+            ctrl_stat: CSYSPWRUPREQ | CDBGPWRUPACK | CDBGPWRUPREQ,
+        })
+    );
+}
+
+#[test]
+fn system_power_request_can_be_skipped() {
+    let (mut interface, writes) = interface_without_system_power();
+    interface.set_power_policy(DelayOrPoll::default(), false);
+
+    interface.num_access_ports(DpAddress::Default).unwrap();
+
+    let status = interface.dp_power_status(DpAddress::Default).unwrap();
+    assert!(status.debug.requested && status.debug.acknowledged);
+    assert!(!status.system.requested && !status.system.acknowledged);
+
+    let writes = writes.lock().unwrap();
+    assert!(!writes.is_empty());
+    assert!(writes.iter().all(|ctrl_stat| ctrl_stat & CSYSPWRUPREQ == 0));
+}
+
+#[test]
+fn debug_power_can_be_requested_explicitly() {
+    let (mut interface, _) = interface_without_system_power();
+    interface.set_power_policy(DelayOrPoll::default(), false);
+
+    interface
+        .request_debug_power(DpAddress::Default, false)
+        .unwrap();
+    let status = interface.dp_power_status(DpAddress::Default).unwrap();
+    assert!(!status.debug.requested && !status.debug.acknowledged);
+
+    interface
+        .request_debug_power(DpAddress::Default, true)
+        .unwrap();
+    let status = interface.dp_power_status(DpAddress::Default).unwrap();
+    assert!(status.debug.requested && status.debug.acknowledged);
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use probe_rs::{BufferPointers, CircularBuffer, Intrusiveness, MemoryInterface, Session};
+
+/// An RTT up channel, whose ring buffer of 64 bytes follows it.
+const CHANNEL: u64 = 0x2000_0000;
+const WRITE: u64 = CHANNEL + 12;
+const READ: u64 = CHANNEL + 16;
+const BUFFER: u64 = 0x2000_0100;
+
+/// Drain the RTT channel at [`CHANNEL`], into the returned receiver.
+fn drain_rtt_channel(session: &mut Session) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+
+    session
+        .register_drain(
+            CircularBuffer::new(BUFFER, 64, WRITE).tail(READ),
+            sender,
+            Duration::ZERO,
+        )
+        .unwrap();
+
+    receiver
+}
+
+/// Produce `data` into the RTT channel at [`CHANNEL`], starting at the offset `write`.
+fn produce(session: &mut Session, write: u64, data: &[u8]) {
+    let mut core = session.core(0).unwrap();
+
+    for (index, byte) in data.iter().enumerate() {
+        core.write_word_8(BUFFER + (write + index as u64) % 64, *byte)
+            .unwrap();
+    }
+    core.write_word_32(WRITE, ((write + data.len() as u64) % 64) as u32)
+        .unwrap();
+}
+
+fn received(receiver: &Receiver<Vec<u8>>) -> Vec<u8> {
+    receiver.try_iter().flatten().collect()
+}
+
+#[test]
+fn rtt_channels_are_drained_and_released() {
+    let mut session = common::attach();
+    let receiver = drain_rtt_channel(&mut session);
+
+    produce(&mut session, 0, b"hello");
+    session.service_drains().unwrap();
+
+    assert_eq!(received(&receiver), b"hello");
+    assert_eq!(session.core(0).unwrap().read_word_32(READ).unwrap(), 5);
+
+    // The data wraps at the end of the ring buffer.
+    produce(&mut session, 5, &[0x55; 59]);
+    session.service_drains().unwrap();
+    produce(&mut session, 0, b"wrapped");
+    session.service_drains().unwrap();
+
+    let data = received(&receiver);
+    assert_eq!(data.len(), 59 + 7);
+    assert!(data.ends_with(b"wrapped"));
+    assert_eq!(session.core(0).unwrap().read_word_32(READ).unwrap(), 7);
+}
+
+#[test]
+fn drains_are_serviced_while_waiting_for_a_halt() {
+    let mut session = common::attach();
+    let receiver = drain_rtt_channel(&mut session);
+
+    produce(&mut session, 0, b"while running");
+
+    let mut core = session.core(0).unwrap();
+    core.run().unwrap();
+    assert!(core
+        .wait_for_core_halted(Duration::from_millis(50))
+        .is_err());
+    drop(core);
+
+    assert_eq!(received(&receiver), b"while running");
+}
+
+#[test]
+fn drains_are_serviced_between_the_chunks_of_large_transfers() {
+    let mut session = common::attach();
+    let receiver = drain_rtt_channel(&mut session);
+
+    produce(&mut session, 0, b"between chunks");
+
+    let mut data = vec![0; 0x3000];
+    session
+        .core(0)
+        .unwrap()
+        .read(0x2000_1000, &mut data)
+        .unwrap();
+
+    assert_eq!(received(&receiver), b"between chunks");
+}
+
+#[test]
+fn without_write_access_the_tail_is_kept_by_the_drain() {
+    let mut session = common::attach();
+    let receiver = drain_rtt_channel(&mut session);
+
+    produce(&mut session, 0, b"peek");
+    session.set_max_intrusiveness(Intrusiveness::None);
+
+    session.service_drains().unwrap();
+    session.service_drains().unwrap();
+
+    // The data is drained once, and the target still sees it as unread.
+    assert_eq!(received(&receiver), b"peek");
+    assert_eq!(session.core(0).unwrap().read_word_32(READ).unwrap(), 0);
+}
+
+#[test]
+fn producers_which_lap_counting_drains_are_flagged() {
+    const HEAD: u64 = 0x2000_0200;
+    const TAIL: u64 = 0x2000_0204;
+    const SAMPLES: u64 = 0x2000_0300;
+
+    let mut session = common::attach();
+    let (sender, receiver) = mpsc::channel();
+
+    let id = session
+        .register_drain(
+            CircularBuffer::new(SAMPLES, 16, HEAD)
+                .tail(TAIL)
+                .element_size(4)
+                .pointers(BufferPointers::Counter),
+            sender,
+            Duration::ZERO,
+        )
+        .unwrap();
+
+    // 9 samples were produced into a buffer of 4, so 5 of them were lost.
+    {
+        let mut core = session.core(0).unwrap();
+        for sample in 5..9u32 {
+            core.write_word_32(SAMPLES + u64::from(sample % 4) * 4, sample)
+                .unwrap();
+        }
+        core.write_word_32(HEAD, 9).unwrap();
+    }
+
+    session.service_drains().unwrap();
+
+    let status = session.drain_status(id).unwrap();
+    assert_eq!(status.overflows, 1);
+    assert_eq!(status.lost, 5 * 4);
+    assert_eq!(status.drained, 4 * 4);
+
+    let samples: Vec<_> = received(&receiver)
+        .chunks(4)
+        .map(|sample| u32::from_le_bytes(sample.try_into().unwrap()))
+        .collect();
+    assert_eq!(samples, [5, 6, 7, 8]);
+    assert_eq!(session.core(0).unwrap().read_word_32(TAIL).unwrap(), 9);
+}
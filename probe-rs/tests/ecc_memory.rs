@@ -0,0 +1,128 @@
+mod common;
+
+use probe_rs::{
+    config::{get_target_by_name, MemoryRegion},
+    flashing::DownloadOptions,
+    test_utils::{EccFaults, WriteLog},
+    Error, FakeProbe, MemoryInterface, Session,
+};
+
+const RAM: u64 = 0x2000_0000;
+
+/// Attach to a target whose first RAM region is protected by ECC, and whose first 4 KiB of
+/// it fault until they are initialized.
+fn attach() -> (Session, WriteLog, EccFaults) {
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+    for region in &mut target.memory_map {
+        if let MemoryRegion::Ram(region) = region {
+            if region.range.contains(&RAM) {
+                region.ecc = true;
+            }
+        }
+    }
+
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+    let ecc_faults = probe.ecc_faults();
+    ecc_faults.protect(RAM as u32..RAM as u32 + 0x1000);
+
+    let session = common::attach_to(probe, target);
+    write_log.clear();
+
+    (session, write_log, ecc_faults)
+}
+
+#[test]
+fn partial_writes_to_uninitialized_words_are_refused() {
+    let (mut session, write_log, ecc_faults) = attach();
+    let mut core = session.core(0).unwrap();
+
+    match core.write_word_8(RAM + 1, 0x12) {
+        Err(Error::EccUninitialized { address, len }) => {
+            assert_eq!(address, RAM + 1);
+            assert_eq!(len, 1);
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(write_log.is_empty());
+
+    // Whole words don't need to be initialized, they initialize the ECC themselves.
+    core.write_8(RAM + 4, &[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+    assert_eq!(
+        write_log.entries(),
+        [(RAM as u32 + 4, 0x0403_0201), (RAM as u32 + 8, 0x0807_0605)]
+    );
+    assert!(ecc_faults.is_initialized(RAM as u32 + 8));
+}
+
+#[test]
+fn partial_writes_are_widened_once_the_memory_is_initialized() {
+    let (mut session, write_log, _) = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.ecc_initialize(RAM + 1..RAM + 7, 0x5555_5555).unwrap();
+    assert_eq!(
+        write_log.entries(),
+        [(RAM as u32, 0x5555_5555), (RAM as u32 + 4, 0x5555_5555)]
+    );
+    write_log.clear();
+
+    core.write_8(RAM + 2, &[0xaa, 0xbb, 0xcc]).unwrap();
+    assert_eq!(
+        write_log.entries(),
+        [(RAM as u32, 0xbbaa_5555), (RAM as u32 + 4, 0x5555_55cc)]
+    );
+
+    let mut data = [0; 8];
+    core.read_8(RAM, &mut data).unwrap();
+    assert_eq!(data, [0x55, 0x55, 0xaa, 0xbb, 0xcc, 0x55, 0x55, 0x55]);
+}
+
+#[test]
+fn faults_in_ecc_memory_are_annotated() {
+    let (mut session, _, _) = attach();
+    let mut core = session.core(0).unwrap();
+
+    let error = core.read_word_32(RAM + 0x20).unwrap_err();
+    assert!(
+        matches!(error, Error::EccFault { address, len: 4, .. } if address == RAM + 0x20),
+        "{:?}",
+        error
+    );
+    assert!(error.to_string().contains("ecc_initialize"), "{}", error);
+
+    // Words which were initialized by the firmware are read-modify-written, but if they
+    // weren't initialized after all, the fault is annotated as well.
+    core.ecc_mark_initialized(RAM + 0x20..RAM + 0x24);
+    assert!(matches!(
+        core.write_word_8(RAM + 0x21, 0),
+        Err(Error::EccFault { .. })
+    ));
+}
+
+#[test]
+fn ram_data_is_loaded_into_initialized_words() {
+    let (mut session, write_log, _) = attach();
+
+    let mut loader = session.target().flash_loader();
+    loader.add_data(RAM + 2, &[0xaa, 0xbb, 0xcc]).unwrap();
+    loader
+        .commit(&mut session, DownloadOptions::new())
+        .expect("Failed to load the RAM data");
+
+    // The partially written words are initialized before they are read-modify-written.
+    let ram_writes: Vec<_> = write_log
+        .entries()
+        .into_iter()
+        .filter(|(address, _)| (RAM..RAM + 0x1000).contains(&u64::from(*address)))
+        .collect();
+    assert_eq!(
+        ram_writes,
+        [
+            (RAM as u32, 0),
+            (RAM as u32 + 4, 0),
+            (RAM as u32, 0xbbaa_0000),
+            (RAM as u32 + 4, 0x0000_00cc),
+        ]
+    );
+}
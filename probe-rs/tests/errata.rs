@@ -0,0 +1,61 @@
+mod common;
+
+use probe_rs::{config::get_target_by_name, FakeProbe, MemoryInterface, Session};
+use std::time::Duration;
+
+const DBGMCU_CR: u64 = 0xE004_2004;
+const FP_CTRL: u64 = 0xE000_2000;
+
+fn attach(errata: &[&str]) -> Session {
+    let mut target = get_target_by_name(common::TARGET).unwrap();
+    target.errata = errata.iter().map(|id| id.to_string()).collect();
+
+    common::attach_to(FakeProbe::with_mocked_core(), target)
+}
+
+#[test]
+fn active_errata_are_listed() {
+    let session = attach(&[
+        "stm32-low-power-debug",
+        "riscv-sysbus-stale-read",
+        "no-such-erratum",
+    ]);
+
+    // Only the errata which affect the Cortex-M core are active.
+    let active: Vec<_> = session
+        .active_errata()
+        .iter()
+        .map(|active| (active.core, active.erratum.id))
+        .collect();
+
+    assert_eq!(active, [(0, "stm32-low-power-debug")]);
+}
+
+#[test]
+fn low_power_debug_is_enabled_after_reset() {
+    for (errata, expected) in [(&[][..], 0), (&["stm32-low-power-debug"][..], 0b111)] {
+        let mut session = attach(errata);
+        let mut core = session.core(0).unwrap();
+
+        core.reset_and_halt(Duration::from_millis(100)).unwrap();
+
+        assert_eq!(core.read_word_32(DBGMCU_CR).unwrap(), expected);
+    }
+}
+
+#[test]
+fn fpb_is_enabled_again_after_breakpoint() {
+    for (errata, expected) in [(&[][..], 0), (&["fpb-enable-lost"][..], 1)] {
+        let mut session = attach(errata);
+        let mut core = session.core(0).unwrap();
+
+        core.set_hw_breakpoint(0x0800_0100).unwrap();
+
+        // The FPB loses its enable bit, like the affected revisions do when a comparator
+        // is written.
+        core.write_word_32(FP_CTRL, 0b10).unwrap();
+        core.set_hw_breakpoint(0x0800_0200).unwrap();
+
+        assert_eq!(core.read_word_32(FP_CTRL).unwrap() & 1, expected);
+    }
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    architecture::{
+        arm::{ap::AccessPortError, DapError},
+        riscv::communication_interface::RiscvError,
+    },
+    config::RegistryError,
+    flashing::{FileDownloadError, FlashError},
+    DebugProbeError, Error, ProbeCreationError,
+};
+use static_assertions::assert_impl_all;
+
+assert_impl_all!(Error: std::error::Error, Send, Sync);
+assert_impl_all!(DebugProbeError: std::error::Error, Send, Sync);
+assert_impl_all!(ProbeCreationError: std::error::Error, Send, Sync);
+assert_impl_all!(FlashError: std::error::Error, Send, Sync);
+assert_impl_all!(FileDownloadError: std::error::Error, Send, Sync);
+assert_impl_all!(RegistryError: std::error::Error, Send, Sync);
+assert_impl_all!(AccessPortError: std::error::Error, Send, Sync);
+assert_impl_all!(DapError: std::error::Error, Send, Sync);
+assert_impl_all!(RiscvError: std::error::Error, Send, Sync);
+
+/// Only compiles if the error can be boxed into an error which outlives any borrow.
+fn boxed<E: std::error::Error + Send + Sync + 'static>(
+    error: E,
+) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+    Box::new(error)
+}
+
+#[test]
+fn errors_can_be_boxed() {
+    let error = boxed(Error::CoreNotHalted(0));
+
+    assert!(error.downcast_ref::<Error>().is_some());
+}
+
+#[test]
+fn breakpoint_errors_are_structured() {
+    let mut session = common::attach();
+
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    match core.clear_hw_breakpoint(0x0800_0100) {
+        Err(Error::BreakpointNotFound(address)) => assert_eq!(address, 0x0800_0100),
+        other => panic!("Expected no breakpoint to be found, got {:?}", other),
+    }
+}
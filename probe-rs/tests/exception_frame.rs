@@ -0,0 +1,122 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    architecture::arm::FrameStack, Core, Error, FakeProbe, MemoryInterface, RegisterId,
+};
+
+const LR: RegisterId = RegisterId(14);
+const XPSR: RegisterId = RegisterId(0b1_0000);
+const MSP: RegisterId = RegisterId(0b1_0001);
+const PSP: RegisterId = RegisterId(0b1_0010);
+
+/// The exception number of HardFault.
+const HARD_FAULT: u32 = 3;
+
+/// Halt the core at the start of the HardFault handler, with `exc_return` in LR.
+fn enter_hard_fault(core: &mut Core, exc_return: u32, msp: u32, psp: u32) {
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    core.write_core_reg(LR, exc_return).unwrap();
+    core.write_core_reg(XPSR, 0x0100_0000 | HARD_FAULT).unwrap();
+    core.write_core_reg(MSP, msp).unwrap();
+    core.write_core_reg(PSP, psp).unwrap();
+}
+
+/// The basic frame of a thread which faulted at `0x0800_0200`.
+fn basic_frame(xpsr: u32) -> [u32; 8] {
+    [1, 2, 3, 4, 12, 0x0800_0101, 0x0800_0200, xpsr]
+}
+
+#[test]
+fn basic_frame_on_the_process_stack() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    // Thread mode, process stack, basic frame. The stack was aligned with a padding word.
+    enter_hard_fault(&mut core, 0xffff_fffd, 0x2000_2000, 0x2000_1000);
+    core.write_32(0x2000_1000, &basic_frame(0x0100_0200))
+        .unwrap();
+
+    let frame = core.exception_frame().unwrap();
+
+    assert_eq!(frame.exception, HARD_FAULT);
+    assert_eq!(frame.stack, FrameStack::Process);
+    assert_eq!(frame.address, 0x2000_1000);
+    assert_eq!(frame.r0, Some(1));
+    assert_eq!(frame.r3, Some(4));
+    assert_eq!(frame.r12, Some(12));
+    assert_eq!(frame.lr, Some(0x0800_0101));
+    assert_eq!(frame.pc, Some(0x0800_0200));
+    assert!(frame.fp.is_none());
+    assert!(frame.stack_limit_violation.is_none());
+    assert!(frame.is_complete());
+    assert!(!frame.is_nested());
+    assert_eq!(frame.context_stack_pointer(), Some(0x2000_1024));
+}
+
+#[test]
+fn nested_extended_frame_on_the_main_stack() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    // Handler mode, main stack, extended frame.
+    enter_hard_fault(&mut core, 0xffff_ffe1, 0x2000_2000, 0x2000_1000);
+
+    let mut stack = basic_frame(0x0100_000f).to_vec();
+    stack.extend((0..16).map(|s| 0x3f80_0000 + s));
+    stack.extend([0x0300_0000, 0]);
+    core.write_32(0x2000_2000, &stack).unwrap();
+
+    let frame = core.exception_frame().unwrap();
+
+    assert_eq!(frame.stack, FrameStack::Main);
+    assert!(frame.is_nested());
+
+    let fp = frame.fp.as_ref().expect("The frame is an extended frame");
+    assert!(!fp.lazy);
+    assert_eq!(fp.s[0], Some(0x3f80_0000));
+    assert_eq!(fp.s[15], Some(0x3f80_000f));
+    assert_eq!(fp.fpscr, Some(0x0300_0000));
+
+    // The interrupted handler's stack is above the frame.
+    assert_eq!(frame.context_stack_pointer(), Some(0x2000_2068));
+}
+
+#[test]
+fn unreadable_stack_gives_a_partial_frame() {
+    let probe = FakeProbe::with_mocked_core();
+    let read_faults = probe.read_faults();
+    let mut session = common::attach_probe(probe);
+    let mut core = session.core(0).unwrap();
+
+    enter_hard_fault(&mut core, 0xffff_fffd, 0x2000_2000, 0x2000_3000);
+    core.write_32(0x2000_3000, &basic_frame(0x0100_0000))
+        .unwrap();
+
+    // The upper half of the frame is not readable.
+    read_faults.make_inaccessible(0x2000_3010..0x2000_3020);
+
+    let frame = core.exception_frame().unwrap();
+
+    assert_eq!(frame.r0, Some(1));
+    assert_eq!(frame.r3, Some(4));
+    assert_eq!(frame.missing_registers(), vec!["R12", "LR", "PC", "xPSR"]);
+    assert!(!frame.is_complete());
+    assert_eq!(frame.context_stack_pointer(), None);
+}
+
+#[test]
+fn frame_requires_exc_return_in_lr() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    // The handler already used LR.
+    enter_hard_fault(&mut core, 0x0800_0101, 0x2000_2000, 0x2000_1000);
+
+    match core.exception_frame() {
+        Err(Error::NoExceptionReturn(lr)) => assert_eq!(lr, 0x0800_0101),
+        other => panic!("Expected a missing EXC_RETURN, got {:?}", other),
+    }
+}
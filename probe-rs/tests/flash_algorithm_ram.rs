@@ -0,0 +1,104 @@
+mod common;
+
+use std::ops::Range;
+
+use probe_rs::{
+    config::{get_target_by_name, Target},
+    flashing::{
+        DownloadOptions, FlashError, FlashLoader, LayoutDirective, LayoutPolicy, ReservedRam,
+    },
+    test_utils::WriteLog,
+    FakeProbe, Session,
+};
+
+const RAM: Range<u64> = 0x2000_0000..0x2003_0000;
+/// RAM functions at the start of the RAM, where the flash algorithm is loaded by default.
+const RAM_FUNCTIONS: Range<u64> = 0x2000_0000..0x2000_0800;
+
+fn attach(target: Target) -> (Session, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let session = common::attach_to(probe, target);
+    write_log.clear();
+
+    (session, write_log)
+}
+
+/// An image with code in flash, and with RAM functions in [`RAM_FUNCTIONS`].
+fn image(session: &Session, ram_functions: bool) -> FlashLoader {
+    let mut loader = session.target().flash_loader();
+    loader.add_data(0x0800_0000, &[0xaa; 0x1000]).unwrap();
+
+    if ram_functions {
+        loader
+            .add_data(
+                RAM_FUNCTIONS.start,
+                &[0x5a; (RAM_FUNCTIONS.end - RAM_FUNCTIONS.start) as usize],
+            )
+            .unwrap();
+    }
+
+    loader
+}
+
+#[test]
+fn the_flash_algorithm_is_moved_away_from_ram_functions() {
+    let (mut session, write_log) = attach(get_target_by_name("stm32wb55ccux").unwrap());
+
+    image(&session, true)
+        .commit(&mut session, DownloadOptions::new())
+        .expect("Failed to flash");
+
+    // Only the RAM functions themselves were written to their RAM, not the flash algorithm.
+    let writes = write_log.entries();
+    assert!(writes
+        .iter()
+        .filter(|(address, _)| RAM_FUNCTIONS.contains(&(*address as u64)))
+        .all(|(_, value)| *value == 0x5a5a_5a5a));
+    assert!(writes
+        .iter()
+        .any(|(address, _)| (RAM_FUNCTIONS.end..RAM.end).contains(&(*address as u64))));
+}
+
+#[test]
+fn unsatisfiable_conflicts_fail_before_the_target_is_modified() {
+    // The whole RAM is kept, so there is no room for the flash algorithm.
+    let (mut session, write_log) = attach(get_target_by_name("stm32wb55ccux").unwrap());
+
+    let mut options = DownloadOptions::new();
+    options
+        .layout
+        .push(LayoutDirective::new(RAM, LayoutPolicy::Keep));
+
+    match image(&session, false).commit(&mut session, options) {
+        Err(FlashError::FlashAlgorithmRamConflict {
+            footprint,
+            reserved: ReservedRam::KeptRange(range),
+            ..
+        }) => {
+            assert_eq!(footprint.start, RAM.start);
+            assert_eq!(range, RAM);
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(write_log.is_empty());
+
+    // A flash algorithm with a fixed load address can't be moved.
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+    target.flash_algorithms[0].load_address = Some(RAM.start + 0x20);
+    let (mut session, write_log) = attach(target);
+
+    let error = image(&session, true)
+        .commit(&mut session, DownloadOptions::new())
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        FlashError::FlashAlgorithmRamConflict {
+            reserved: ReservedRam::ImageData(_),
+            ..
+        }
+    ));
+    assert!(error.to_string().contains("0x20000000..0x20000800"));
+    assert!(write_log.is_empty());
+}
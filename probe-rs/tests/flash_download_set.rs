@@ -0,0 +1,197 @@
+mod common;
+
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use probe_rs::{
+    config::{get_target_by_name, MemoryRegion},
+    flashing::{
+        DownloadOptions, FlashAlgorithm, FlashDownloadSet, FlashError, FlashProgress, ImageOutcome,
+        ImageStatus, ProgressEvent,
+    },
+    FakeProbe, MemoryInterface, Session,
+};
+
+const BOOTLOADER: Range<u64> = 0x0800_0000..0x0800_1800;
+const APPLICATION: Range<u64> = 0x0800_1800..0x0800_3000;
+/// The sector which contains the end of the bootloader and the start of the application.
+const SHARED_SECTOR: u64 = 0x0800_1000;
+
+/// Attach to a fake probe whose core emulates the flash algorithm of the target.
+fn attach() -> Session {
+    let target = get_target_by_name("stm32wb55ccux").unwrap();
+    let ram = target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some(ram.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let algorithm = target
+        .flash_algorithms
+        .iter()
+        .find(|algorithm| {
+            algorithm
+                .flash_properties
+                .address_range
+                .contains(&BOOTLOADER.start)
+        })
+        .unwrap();
+
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.emulate_flash(FlashAlgorithm::assemble_from_raw(algorithm, &ram, &target).unwrap());
+
+    common::attach_probe(probe)
+}
+
+fn image(range: &Range<u64>, seed: u8) -> Vec<u8> {
+    (0..range.end - range.start)
+        .map(|offset| (offset / 4) as u8 ^ seed)
+        .collect()
+}
+
+/// Download `set`, and return its result with the erased sectors and the reported images.
+fn download(
+    session: &mut Session,
+    set: &FlashDownloadSet,
+) -> (
+    Result<Vec<ImageOutcome>, FlashError>,
+    Vec<u64>,
+    Vec<ImageOutcome>,
+) {
+    let erased = Rc::new(RefCell::new(Vec::new()));
+    let finished = Rc::new(RefCell::new(Vec::new()));
+    let progress = {
+        let erased = erased.clone();
+        let finished = finished.clone();
+
+        FlashProgress::new(move |event| match event {
+            ProgressEvent::SectorErased { address, .. } => erased.borrow_mut().push(address),
+            ProgressEvent::ImageFinished { outcome } => finished.borrow_mut().push(outcome),
+            _ => {}
+        })
+    };
+
+    let mut options = DownloadOptions::new();
+    options.progress = Some(&progress);
+    options.verify = true;
+
+    let result = set.commit(session, options);
+    let erased = erased.borrow().clone();
+    let finished = finished.borrow().clone();
+
+    (result, erased, finished)
+}
+
+fn read(session: &mut Session, range: &Range<u64>) -> Vec<u8> {
+    let mut core = session.core(0).unwrap();
+    let mut data = vec![0; (range.end - range.start) as usize];
+    core.read(range.start, &mut data).unwrap();
+
+    data
+}
+
+#[test]
+fn images_sharing_a_sector_are_programmed_together() {
+    let mut session = attach();
+    let bootloader = image(&BOOTLOADER, 0x5a);
+    let application = image(&APPLICATION, 0xc3);
+
+    let mut set = session.target().flash_download_set();
+    set.add_data("bootloader", BOOTLOADER.start, &bootloader)
+        .unwrap();
+    set.add_data("application", APPLICATION.start, &application)
+        .unwrap();
+
+    let (result, erased, finished) = download(&mut session, &set);
+    let outcomes = result.unwrap();
+
+    assert_eq!(
+        erased
+            .iter()
+            .filter(|&&address| address == SHARED_SECTOR)
+            .count(),
+        1
+    );
+    assert_eq!(read(&mut session, &BOOTLOADER), bootloader);
+    assert_eq!(read(&mut session, &APPLICATION), application);
+
+    assert_eq!(
+        outcomes
+            .iter()
+            .map(|outcome| (outcome.name.as_str(), outcome.status))
+            .collect::<Vec<_>>(),
+        [
+            ("bootloader", ImageStatus::Verified),
+            ("application", ImageStatus::Verified)
+        ]
+    );
+    assert_eq!(outcomes[1].ranges, [APPLICATION]);
+    assert_eq!(finished, outcomes);
+}
+
+#[test]
+fn identical_overlaps_are_programmed_once() {
+    let mut session = attach();
+    let bootloader = image(&BOOTLOADER, 0x5a);
+
+    // The application image contains a copy of the end of the bootloader.
+    let overlap = 0x100;
+    let mut application = bootloader[bootloader.len() - overlap..].to_vec();
+    application.extend(image(&APPLICATION, 0xc3));
+
+    let mut set = session.target().flash_download_set();
+    set.add_data("bootloader", BOOTLOADER.start, &bootloader)
+        .unwrap();
+    set.add_data(
+        "application",
+        APPLICATION.start - overlap as u64,
+        &application,
+    )
+    .unwrap();
+
+    let (result, _, _) = download(&mut session, &set);
+
+    assert!(result
+        .unwrap()
+        .iter()
+        .all(|outcome| outcome.status == ImageStatus::Verified));
+    assert_eq!(
+        read(
+            &mut session,
+            &(APPLICATION.start - overlap as u64..APPLICATION.end)
+        ),
+        application
+    );
+}
+
+#[test]
+fn conflicting_images_are_rejected_before_erasing() {
+    let mut session = attach();
+    let bootloader = image(&BOOTLOADER, 0x5a);
+    let application = image(&APPLICATION, 0xc3);
+
+    let mut set = session.target().flash_download_set();
+    set.add_data("bootloader", BOOTLOADER.start, &bootloader)
+        .unwrap();
+    set.add_data("application", APPLICATION.start - 0x10, &application)
+        .unwrap();
+
+    let (result, erased, finished) = download(&mut session, &set);
+
+    match result {
+        Err(FlashError::ImagesConflict {
+            first,
+            second,
+            address,
+        }) => {
+            assert_eq!(first, "bootloader");
+            assert_eq!(second, "application");
+            assert!((APPLICATION.start - 0x10..APPLICATION.start).contains(&address));
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    assert!(erased.is_empty());
+    assert!(finished.is_empty());
+}
@@ -1,12 +1,10 @@
-use probe_rs::{flashing::DownloadOptions, FakeProbe, Permissions, Probe};
+mod common;
+
+use probe_rs::{flashing::DownloadOptions, FakeProbe};
 
 #[test]
 fn flash_dry_run() {
-    let probe = Probe::from_specific_probe(Box::new(FakeProbe::new()));
-
-    let mut session = probe
-        .attach("stm32wb55ccux", Permissions::default())
-        .expect("Failed to attach with 'fake' probe.");
+    let mut session = common::attach_probe(FakeProbe::new());
 
     let mut flasher = session.target().flash_loader();
 
@@ -0,0 +1,256 @@
+mod common;
+
+use std::{
+    cell::{Cell, RefCell},
+    ops::Range,
+    rc::Rc,
+};
+
+use probe_rs::{
+    config::{get_target_by_name, MemoryRegion},
+    flashing::{
+        DownloadOptions, FlashAlgorithm, FlashError, FlashProgress, JournalError, JournalLocation,
+        ProgressEvent,
+    },
+    FakeProbe, MemoryInterface, Session,
+};
+
+const IMAGE: Range<u64> = 0x0800_0000..0x0800_8000;
+const SECTOR_SIZE: u64 = 0x1000;
+const JOURNAL: Range<u64> = 0x0803_e000..0x0804_0000;
+
+/// Attach to a fake probe whose core emulates the flash algorithm of the target.
+fn attach() -> Session {
+    let target = get_target_by_name("stm32wb55ccux").unwrap();
+    let ram = target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Ram(ram) => Some(ram.clone()),
+            _ => None,
+        })
+        .unwrap();
+    let algorithm = target
+        .flash_algorithms
+        .iter()
+        .find(|algorithm| {
+            algorithm
+                .flash_properties
+                .address_range
+                .contains(&IMAGE.start)
+        })
+        .unwrap();
+
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.emulate_flash(FlashAlgorithm::assemble_from_raw(algorithm, &ram, &target).unwrap());
+
+    common::attach_probe(probe)
+}
+
+fn image(seed: u8) -> Vec<u8> {
+    (0..IMAGE.end - IMAGE.start)
+        .map(|offset| (offset / 4) as u8 ^ seed)
+        .collect()
+}
+
+/// Download `image` with the journal at `location`, and return the addresses of the erased
+/// sectors. The download is interrupted right after the `interrupt_at`-th sector erase.
+fn download(
+    session: &mut Session,
+    image: &[u8],
+    location: &JournalLocation,
+    interrupt_at: Option<usize>,
+) -> (Result<(), FlashError>, Vec<u64>) {
+    let interrupt = session.interrupt_handle();
+    let erased = Rc::new(RefCell::new(Vec::new()));
+    let progress = {
+        let erased = erased.clone();
+        let erases = Cell::new(0);
+
+        FlashProgress::new(move |event| {
+            if let ProgressEvent::SectorErased { address, .. } = event {
+                erased.borrow_mut().push(address);
+                erases.set(erases.get() + 1);
+
+                if Some(erases.get()) == interrupt_at {
+                    interrupt.interrupt();
+                }
+            }
+        })
+    };
+
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(IMAGE.start, image)
+        .expect("Failed to add flash");
+
+    let mut options = DownloadOptions::new();
+    options.progress = Some(&progress);
+    options.journal = Some(location.clone());
+
+    let result = loader.commit(session, options);
+    let erased = erased.borrow().clone();
+
+    (result, erased)
+}
+
+fn image_erases(erased: &[u64]) -> usize {
+    erased
+        .iter()
+        .filter(|address| IMAGE.contains(address))
+        .count()
+}
+
+/// The erased sectors of the image, without the sectors of the journal.
+fn image_sectors(erased: &[u64]) -> Vec<u64> {
+    erased
+        .iter()
+        .copied()
+        .filter(|address| IMAGE.contains(address))
+        .collect()
+}
+
+fn assert_flash_contains(session: &mut Session, image: &[u8]) {
+    let mut core = session.core(0).unwrap();
+    let mut flash = vec![0; image.len()];
+    core.read(IMAGE.start, &mut flash).unwrap();
+
+    assert!(flash == image, "The flash doesn't contain the image");
+}
+
+/// Download the image, interrupting it at pseudo-random points until it succeeds.
+///
+/// Returns the number of interrupted downloads, and the number of image sectors erased.
+fn download_until_done(
+    session: &mut Session,
+    image: &[u8],
+    location: &JournalLocation,
+) -> (usize, usize) {
+    let mut state = 0x2545_f491u32;
+    let mut interrupted = 0;
+    let mut erases = 0;
+
+    loop {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        let interrupt_at = 1 + (state >> 16) as usize % 4;
+
+        let (result, erased) = download(session, image, location, Some(interrupt_at));
+        erases += image_erases(&erased);
+
+        match result {
+            Ok(()) => return (interrupted, erases),
+            Err(error) => assert!(error.is_interrupted(), "unexpected error: {:?}", error),
+        }
+
+        interrupted += 1;
+        assert!(interrupted < 64, "The download makes no progress");
+    }
+}
+
+#[test]
+fn interrupted_download_continues_from_the_journal() {
+    let mut session = attach();
+    let image = image(0);
+    let location = JournalLocation::Flash(JOURNAL);
+
+    let (interrupted, erases) = download_until_done(&mut session, &image, &location);
+    let sectors = ((IMAGE.end - IMAGE.start) / SECTOR_SIZE) as usize;
+
+    assert!(interrupted > 0);
+    // Each interruption loses at most the sector which was being programmed.
+    assert!(
+        erases <= sectors + interrupted,
+        "{} erases for {} sectors with {} interruptions",
+        erases,
+        sectors,
+        interrupted
+    );
+    assert_flash_contains(&mut session, &image);
+
+    // All sectors are recorded as verified, so nothing is erased again.
+    let (result, erased) = download(&mut session, &image, &location, None);
+    result.unwrap();
+    assert!(erased.is_empty(), "erased {:#010x?}", erased);
+
+    // Another image is programmed completely.
+    let other = self::image(0x5a);
+    let (result, erased) = download(&mut session, &other, &location, None);
+    result.unwrap();
+    assert_eq!(image_erases(&erased), sectors);
+    assert_flash_contains(&mut session, &other);
+}
+
+#[test]
+fn journal_can_be_kept_on_the_host() {
+    let mut session = attach();
+    let image = image(0);
+    let path = std::env::temp_dir().join(format!("probe-rs-journal-{}", std::process::id()));
+    let location = JournalLocation::File(path.clone());
+
+    let (interrupted, _) = download_until_done(&mut session, &image, &location);
+    assert!(interrupted > 0);
+    assert_flash_contains(&mut session, &image);
+
+    let (result, erased) = download(&mut session, &image, &location, None);
+    result.unwrap();
+    assert!(erased.is_empty(), "erased {:#010x?}", erased);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn journal_must_not_overlap_the_image() {
+    let mut session = attach();
+    let location = JournalLocation::Flash(IMAGE.end - 2 * SECTOR_SIZE..IMAGE.end);
+
+    let (result, erased) = download(&mut session, &image(0), &location, None);
+
+    match result {
+        Err(FlashError::Journal(JournalError::DataInJournal(range))) => {
+            assert_eq!(range, IMAGE.end - 2 * SECTOR_SIZE..IMAGE.end)
+        }
+        other => panic!("Expected a journal error, got {:?}", other),
+    }
+    assert!(erased.is_empty());
+}
+
+#[test]
+fn target_writable_flash_is_always_programmed() {
+    let mut session = attach();
+    let image = image(0);
+    let location = JournalLocation::Flash(JOURNAL);
+
+    let log = IMAGE.start + 2 * SECTOR_SIZE..IMAGE.start + 3 * SECTOR_SIZE;
+    session.mark_target_writable_flash(log.clone());
+
+    let (result, _) = download(&mut session, &image, &location, None);
+    result.unwrap();
+
+    let (result, erased) = download(&mut session, &image, &location, None);
+    result.unwrap();
+    assert_eq!(image_sectors(&erased), vec![log.start]);
+    assert_flash_contains(&mut session, &image);
+}
+
+#[test]
+fn reported_target_writes_are_programmed_again() {
+    let mut session = attach();
+    let image = image(0);
+    let location = JournalLocation::Flash(JOURNAL);
+
+    let (result, _) = download(&mut session, &image, &location, None);
+    result.unwrap();
+
+    // The firmware updated a word in the second sector.
+    let written = IMAGE.start + SECTOR_SIZE + 0x10;
+    session.notify_target_flash_write(written..written + 4);
+
+    let (result, erased) = download(&mut session, &image, &location, None);
+    result.unwrap();
+    assert_eq!(image_sectors(&erased), vec![IMAGE.start + SECTOR_SIZE]);
+
+    // The journal records the programmed sector again.
+    let (result, erased) = download(&mut session, &image, &location, None);
+    result.unwrap();
+    assert!(erased.is_empty(), "erased {:#010x?}", erased);
+}
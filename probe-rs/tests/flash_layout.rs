@@ -0,0 +1,58 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use probe_rs::{
+    flashing::{
+        DownloadOptions, FlashError, FlashProgress, LayoutConflict, LayoutDirective, LayoutPolicy,
+        ProgressEvent,
+    },
+    FakeProbe,
+};
+
+#[test]
+fn layout_conflict_is_reported_before_erasing() {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let mut session = common::attach_probe(probe);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let progress = {
+        let events = events.clone();
+        FlashProgress::new(move |event| events.borrow_mut().push(event))
+    };
+
+    // The app overlaps the bootloader which should be kept.
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(0x0800_0800, &[0xaa; 4096])
+        .expect("Failed to add flash");
+
+    let mut options = DownloadOptions::new();
+    options.progress = Some(&progress);
+    options.layout = vec![LayoutDirective::new(
+        0x0800_0000..0x0800_1000,
+        LayoutPolicy::Keep,
+    )];
+
+    write_log.clear();
+
+    match loader.commit(&mut session, options) {
+        Err(FlashError::LayoutConflict { range, conflict }) => {
+            assert_eq!(range, 0x0800_0000..0x0800_1000);
+            assert_eq!(
+                conflict,
+                LayoutConflict::DataInKeptRange(0x0800_0800..0x0800_1000)
+            );
+        }
+        other => panic!("Expected a layout conflict, got {:?}", other),
+    }
+
+    // Neither the flash algorithm was loaded, nor anything erased.
+    assert!(write_log.is_empty());
+    assert!(!events
+        .borrow()
+        .iter()
+        .any(|event| matches!(event, ProgressEvent::SectorErased { .. })));
+}
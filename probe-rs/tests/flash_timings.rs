@@ -0,0 +1,90 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use probe_rs::{
+    flashing::{
+        DownloadOptions, FlashOperation, FlashProgress, ProgressEvent, SlowOperationThreshold,
+    },
+    FakeProbe, HealthEvent,
+};
+
+#[test]
+fn slow_sector_is_reported() {
+    let slow_sector = 0x0800_3000;
+    let delay = Duration::from_millis(200);
+
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_routine_delay(slow_sector, delay);
+
+    let mut session = common::attach_probe(probe);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let progress = {
+        let events = events.clone();
+        FlashProgress::new(move |event| events.borrow_mut().push(event))
+    };
+
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(0x0800_0000, &[0xaa; 4 * 4096])
+        .expect("Failed to add flash");
+
+    let mut options = DownloadOptions::new();
+    options.progress = Some(&progress);
+    options.slow_operation_threshold = Some(SlowOperationThreshold {
+        median_factor: 4.0,
+        timeout_fraction: None,
+    });
+
+    loader
+        .commit(&mut session, options)
+        .expect("Failed to flash");
+
+    let events = events.borrow();
+
+    let erased: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::SectorErased { address, time, .. } => Some((*address, *time)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(erased.len(), 4);
+    assert_eq!(erased[3].0, slow_sector as u64);
+    assert!(erased[3].1 >= delay);
+
+    let slow_erases: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::SlowOperation {
+                operation: FlashOperation::SectorErase,
+                address,
+                ..
+            } => Some(*address),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(slow_erases, [slow_sector as u64]);
+
+    let timings = events
+        .iter()
+        .find_map(|event| match event {
+            ProgressEvent::Timings { timings } => Some(*timings),
+            _ => None,
+        })
+        .expect("No timings were reported");
+
+    let sector_erase = timings.sector_erase.unwrap();
+    assert_eq!(sector_erase.count, 4);
+    assert!(sector_erase.min < delay);
+    assert!(sector_erase.median < delay);
+    assert_eq!(sector_erase.max, erased[3].1);
+    assert!(timings.page_program.is_some());
+
+    assert!(session
+        .health_log()
+        .entries()
+        .iter()
+        .any(|entry| entry.event == HealthEvent::SlowFlashOperation));
+}
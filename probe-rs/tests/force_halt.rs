@@ -0,0 +1,91 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    Error, FakeProbe, HaltAttempt, HaltAttemptOutcome, HaltEscalation, Intrusiveness,
+    MemoryInterface, Session, TargetOperation,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(20);
+
+/// The address of DEMCR, whose bit 0 is the reset vector catch.
+const DEMCR: u64 = 0xE000_EDFC;
+
+fn attach(ignored_halt_requests: u32) -> Session {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_ignored_halt_requests(ignored_halt_requests);
+
+    common::attach_probe(probe)
+}
+
+fn levels(attempts: &[HaltAttempt]) -> Vec<HaltEscalation> {
+    attempts.iter().map(|attempt| attempt.level).collect()
+}
+
+#[test]
+fn responsive_core_halts_with_a_halt_request() {
+    let mut session = attach(0);
+    let mut core = session.core(0).unwrap();
+
+    let report = core
+        .force_halt(TIMEOUT, HaltEscalation::HardwareReset)
+        .unwrap();
+
+    assert_eq!(report.level, HaltEscalation::HaltRequest);
+    assert_eq!(levels(&report.attempts), [HaltEscalation::HaltRequest]);
+    assert!(report.registers_preserved);
+}
+
+#[test]
+fn repeated_halt_requests_keep_the_registers() {
+    let mut session = attach(3);
+    let mut core = session.core(0).unwrap();
+
+    let report = core
+        .force_halt(TIMEOUT, HaltEscalation::PersistentHaltRequest)
+        .unwrap();
+
+    assert_eq!(report.level, HaltEscalation::PersistentHaltRequest);
+    assert_eq!(report.attempts[0].outcome, HaltAttemptOutcome::TimedOut);
+    assert_eq!(report.attempts[1].outcome, HaltAttemptOutcome::Halted);
+    assert!(report.registers_preserved);
+}
+
+#[test]
+fn stuck_core_is_only_reset_if_allowed() {
+    let mut session = attach(u32::MAX);
+    let mut core = session.core(0).unwrap();
+
+    match core.force_halt(TIMEOUT, HaltEscalation::PersistentHaltRequest) {
+        Err(Error::ForceHaltFailed { attempts }) => assert_eq!(
+            levels(&attempts),
+            [
+                HaltEscalation::HaltRequest,
+                HaltEscalation::PersistentHaltRequest
+            ]
+        ),
+        other => panic!("Expected the force halt to fail, got {:?}", other),
+    }
+
+    let report = core.force_halt(TIMEOUT, HaltEscalation::CoreReset).unwrap();
+
+    assert_eq!(report.level, HaltEscalation::CoreReset);
+    assert!(!report.registers_preserved);
+
+    // The reset vector catch is only set while the core is reset.
+    assert_eq!(core.read_word_32(DEMCR).unwrap() & 1, 0);
+}
+
+#[test]
+fn resets_are_refused_by_a_non_destructive_session() {
+    let mut session = attach(u32::MAX);
+    session.set_max_intrusiveness(Intrusiveness::ChangesState);
+
+    match session.force_halt_core(0, TIMEOUT, HaltEscalation::HardwareReset) {
+        Err(Error::IntrusivenessExceeded { operation, .. }) => {
+            assert_eq!(operation, TargetOperation::Reset)
+        }
+        other => panic!("Expected the reset to be refused, got {:?}", other),
+    }
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{Core, Error, FpuValueSource, MemoryInterface, RegisterId, RegisterValue};
+
+const LR: RegisterId = RegisterId(14);
+const XPSR: RegisterId = RegisterId(0b1_0000);
+const MSP: RegisterId = RegisterId(0b1_0001);
+const FPSCR: RegisterId = RegisterId(0x21);
+const S0: u16 = 0x40;
+
+const CPACR: u64 = 0xE000_ED88;
+const FPCCR: u64 = 0xE000_EF34;
+const FPCAR: u64 = 0xE000_EF38;
+
+/// FPCCR with ASPEN, LSPEN and LSPACT set.
+const LAZY_STATE_PENDING: u32 = 1 << 31 | 1 << 30 | 1;
+
+/// The main stack pointer in the HardFault handler.
+const STACK: u64 = 0x2000_2000;
+/// The address of the floating-point part of the frame, after the basic frame.
+const FP_FRAME: u64 = STACK + 8 * 4;
+
+/// Halt the core at the start of a HardFault, which interrupted a handler which used the
+/// FPU, so that it stacked an extended frame on the main stack.
+fn enter_hard_fault(core: &mut Core) {
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // Full access to the FPU.
+    core.write_word_32(CPACR, 0x00f0_0000).unwrap();
+
+    core.write_core_reg(LR, 0xffff_ffe1u32).unwrap();
+    core.write_core_reg(XPSR, 0x0100_0003u32).unwrap();
+    core.write_core_reg(MSP, STACK as u32).unwrap();
+
+    // The values of the interrupted handler: S0-S15 are 1.0 to 16.0, S16-S31 are 0x100 + n.
+    for s in 0..32 {
+        let value = if s < 16 { 0x3f80_0000 + s } else { 0x100 + s };
+        core.write_core_reg(RegisterId(S0 + s as u16), value)
+            .unwrap();
+    }
+    core.write_core_reg(FPSCR, 0x0300_0000u32).unwrap();
+}
+
+/// Let the HardFault handler clobber S0-S15 and FPSCR, after they were stacked.
+fn clobber_caller_saved_registers(core: &mut Core) {
+    for s in 0..16 {
+        core.write_core_reg(RegisterId(S0 + s), 0xdead_0000u32)
+            .unwrap();
+    }
+    core.write_core_reg(FPSCR, 0u32).unwrap();
+}
+
+#[test]
+fn stacked_values_of_the_interrupted_context_are_read_from_the_frame() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+    enter_hard_fault(&mut core);
+
+    let mut frame = vec![1, 2, 3, 4, 12, 0x0800_0101, 0x0800_0200, 0x0100_000f];
+    frame.extend((0..16).map(|s| 0x3f80_0000 + s));
+    frame.extend([0x0300_0000, 0]);
+    core.write_32(STACK, &frame).unwrap();
+
+    clobber_caller_saved_registers(&mut core);
+
+    let fpu = core.read_all_fpu_state().unwrap();
+
+    assert_eq!(fpu.status.id, FPSCR);
+    assert_eq!(fpu.status.value, RegisterValue::U32(0x0300_0000));
+    assert_eq!(
+        fpu.status.source,
+        FpuValueSource::ExceptionFrame {
+            address: FP_FRAME + 16 * 4
+        }
+    );
+
+    assert_eq!(fpu.registers.len(), 32);
+    assert_eq!(fpu.registers[1].value, RegisterValue::U32(0x3f80_0001));
+    assert_eq!(
+        fpu.registers[1].source,
+        FpuValueSource::ExceptionFrame {
+            address: FP_FRAME + 4
+        }
+    );
+
+    // The callee-saved registers are never stacked by the core.
+    assert_eq!(fpu.registers[16].id, RegisterId(S0 + 16));
+    assert_eq!(fpu.registers[16].value, RegisterValue::U32(0x110));
+    assert_eq!(fpu.registers[16].source, FpuValueSource::Register);
+}
+
+#[test]
+fn pending_lazy_state_is_read_from_the_registers() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+    enter_hard_fault(&mut core);
+
+    // The space for S0-S15 and FPSCR is reserved, but not written yet.
+    core.write_32(STACK, &[0xffff_ffff; 26]).unwrap();
+    core.write_word_32(FPCCR, LAZY_STATE_PENDING).unwrap();
+    core.write_word_32(FPCAR, FP_FRAME as u32).unwrap();
+
+    let fpu = core.read_all_fpu_state().unwrap();
+
+    assert_eq!(fpu.status.value, RegisterValue::U32(0x0300_0000));
+    assert_eq!(
+        fpu.status.source,
+        FpuValueSource::PendingLazyState {
+            address: FP_FRAME + 16 * 4
+        }
+    );
+    assert_eq!(fpu.registers[15].value, RegisterValue::U32(0x3f80_000f));
+    assert_eq!(
+        fpu.registers[15].source,
+        FpuValueSource::PendingLazyState {
+            address: FP_FRAME + 15 * 4
+        }
+    );
+    assert_eq!(fpu.registers[31].value, RegisterValue::U32(0x11f));
+    assert_eq!(fpu.registers[31].source, FpuValueSource::Register);
+}
+
+#[test]
+fn registers_are_read_outside_of_an_exception_frame() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+    enter_hard_fault(&mut core);
+
+    // The handler already used LR, so its frame can't be found.
+    core.write_core_reg(LR, 0x0800_0101u32).unwrap();
+
+    let fpu = core.read_all_fpu_state().unwrap();
+
+    assert_eq!(fpu.status.value, RegisterValue::U32(0x0300_0000));
+    assert!(std::iter::once(&fpu.status)
+        .chain(&fpu.registers)
+        .all(|value| value.source == FpuValueSource::Register));
+    assert_eq!(fpu.registers[0].value, RegisterValue::U32(0x3f80_0000));
+}
+
+#[test]
+fn cores_without_fpu_have_no_fpu_state() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // The mocked core reports no FPU in CPACR.
+    assert!(matches!(
+        core.read_all_fpu_state(),
+        Err(Error::FpuNotPresent)
+    ));
+}
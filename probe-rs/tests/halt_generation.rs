@@ -0,0 +1,80 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::{BreakpointHits, ForeignResumes},
+    CoreStatus, FakeProbe, HaltReason, Session,
+};
+
+const BREAKPOINT: u32 = 0x0800_0100;
+
+fn attach() -> (Session, BreakpointHits, ForeignResumes) {
+    let probe = FakeProbe::with_mocked_core();
+    let hits = probe.breakpoint_hits();
+    let resumes = probe.foreign_resumes();
+
+    let session = common::attach_probe(probe);
+
+    (session, hits, resumes)
+}
+
+#[test]
+fn halts_between_two_polls_start_a_new_generation() {
+    let (mut session, hits, resumes) = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+    let generation = core.halt_generation();
+
+    hits.hit(BREAKPOINT, 2);
+    core.run().unwrap();
+
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+    assert_eq!(core.halt_generation(), generation + 1);
+
+    // Polling a core which stays halted doesn't change the generation.
+    core.status().unwrap();
+    assert_eq!(core.halt_generation(), generation + 1);
+
+    // Another debugger resumes the core, and it halts at the same breakpoint again, all
+    // before the next poll. Only the sticky bits of DFSR show that it ran.
+    resumes.resume();
+
+    assert_eq!(
+        core.status().unwrap(),
+        CoreStatus::Halted(HaltReason::Breakpoint)
+    );
+    assert_eq!(core.halt_generation(), generation + 2);
+    assert_eq!(hits.remaining(), 0);
+
+    core.status().unwrap();
+    assert_eq!(core.halt_generation(), generation + 2);
+}
+
+#[test]
+fn halts_by_probe_rs_start_a_new_generation() {
+    let (mut session, _, _) = attach();
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+    let generation = core.halt_generation();
+
+    // Halting a halted core is no new halt.
+    core.halt(Duration::from_millis(100)).unwrap();
+    assert_eq!(core.halt_generation(), generation);
+
+    core.step().unwrap();
+    assert_eq!(core.halt_generation(), generation + 1);
+
+    core.run().unwrap();
+    core.wait_for_core_halted(Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(core.halt_generation(), generation + 2);
+
+    core.reset_and_halt(Duration::from_millis(100)).unwrap();
+    assert_eq!(core.halt_generation(), generation + 3);
+}
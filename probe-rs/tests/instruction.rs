@@ -0,0 +1,82 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::ReadFaults, Error, FakeProbe, InstructionFetch, InstructionSet, Intrusiveness,
+    MemoryInterface, Session,
+};
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+fn attach() -> (Session, ReadFaults) {
+    let probe = FakeProbe::with_mocked_core();
+    let read_faults = probe.read_faults();
+
+    let session = common::attach_probe(probe);
+
+    (session, read_faults)
+}
+
+#[test]
+fn thumb_instructions_are_read_with_their_length() {
+    let (mut session, _) = attach();
+    let mut core = session.core(0).unwrap();
+
+    // str.w r1, [r0, #4], followed by str r1, [r0, #4]
+    core.write_8(RAM, &[0xc0, 0xf8, 0x04, 0x10, 0x41, 0x60, 0x00, 0x00])
+        .unwrap();
+
+    assert_eq!(
+        core.read_instruction(RAM | 1).unwrap(),
+        InstructionFetch::Read {
+            address: RAM,
+            instruction_set: InstructionSet::Thumb2,
+            bytes: vec![0xc0, 0xf8, 0x04, 0x10],
+        }
+    );
+    assert_eq!(
+        core.read_instruction(RAM + 4).unwrap().bytes(),
+        Some(&[0x41, 0x60][..])
+    );
+}
+
+#[test]
+fn unreadable_instruction_is_reported() {
+    let (mut session, read_faults) = attach();
+    let mut core = session.core(0).unwrap();
+
+    read_faults.fail_every(1);
+
+    match core.read_instruction(RAM).unwrap() {
+        InstructionFetch::Unreadable { address, .. } => assert_eq!(address, RAM),
+        other => panic!("Expected the instruction to be unreadable, got {:?}", other),
+    }
+}
+
+#[test]
+fn instruction_is_not_read_above_the_intrusiveness() {
+    let (mut session, _) = attach();
+    session.set_max_intrusiveness(Intrusiveness::BusOnly);
+
+    let mut core = session.core(0).unwrap();
+
+    assert!(matches!(
+        core.read_instruction(RAM),
+        Err(Error::IntrusivenessExceeded { .. })
+    ));
+}
+
+#[test]
+fn halts_report_the_instruction_set() {
+    let (mut session, _) = attach();
+    let mut core = session.core(0).unwrap();
+
+    let halted = core.halt(Duration::from_millis(100)).unwrap();
+    assert_eq!(halted.instruction_set, Some(InstructionSet::Thumb2));
+
+    let stepped = core.step().unwrap();
+    assert_eq!(stepped.instruction_set, Some(InstructionSet::Thumb2));
+    assert_eq!(core.instruction_set().unwrap(), InstructionSet::Thumb2);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use std::{cell::RefCell, rc::Rc};
+
+use probe_rs::{
+    flashing::{DownloadOptions, FlashProgress, ProgressEvent},
+    MemoryInterface,
+};
+
+#[test]
+fn interrupt_flash_download() {
+    let mut session = common::attach();
+
+    // Interrupt the download after the first of four sectors was erased.
+    let interrupt = session.interrupt_handle();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let progress = {
+        let events = events.clone();
+
+        FlashProgress::new(move |event| {
+            if let ProgressEvent::SectorErased { .. } = event {
+                interrupt.interrupt();
+            }
+            events.borrow_mut().push(event);
+        })
+    };
+
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(0x8000000, &[0xaa; 4 * 4096])
+        .expect("Failed to add flash");
+
+    let mut options = DownloadOptions::new();
+    options.progress = Some(&progress);
+
+    let error = loader
+        .commit(&mut session, options)
+        .expect_err("The download was not interrupted");
+    assert!(error.is_interrupted(), "unexpected error: {:?}", error);
+
+    let events = events.borrow();
+    let erased = events
+        .iter()
+        .filter(|event| matches!(event, ProgressEvent::SectorErased { .. }))
+        .count();
+    assert_eq!(erased, 1);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, ProgressEvent::FailedErasing)));
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, ProgressEvent::StartedProgramming)));
+
+    // The interrupt was reported, and the session can still be used.
+    assert!(!session.interrupt_handle().is_interrupted());
+
+    let mut core = session.core(0).unwrap();
+    let mut data = [0u32; 4];
+    core.read_32(0x2000_0000, &mut data)
+        .expect("Failed to read memory after the interrupted download");
+}
@@ -0,0 +1,180 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    architecture::arm::{ap::NordicCtrlAp, SwoConfig},
+    test_utils::WriteLog,
+    BreakpointFailure, BreakpointOutcome, BreakpointRequest, Error, FakeProbe, Intrusiveness,
+    MemoryInterface, RegisterId, Session, TargetOperation,
+};
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// An address in the flash of the mocked core.
+const FLASH: u64 = 0x0800_0000;
+
+/// An address of a peripheral, which is device memory.
+const PERIPHERAL: u64 = 0x4000_0000;
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Attach to the mocked core, which is running, and limit the session to `level`.
+fn attach(level: Intrusiveness) -> (Session, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let mut session = common::attach_probe(probe);
+
+    let mut core = session.core(0).unwrap();
+    core.write_32(RAM, &[0x1111_1111, 0x2222_2222]).unwrap();
+    drop(core);
+
+    session.set_max_intrusiveness(level);
+    write_log.clear();
+
+    (session, write_log)
+}
+
+/// Returns the operation `result` was refused for.
+fn refused<T: std::fmt::Debug>(result: Result<T, Error>) -> TargetOperation {
+    match result {
+        Err(Error::IntrusivenessExceeded {
+            operation,
+            required,
+            allowed,
+        }) => {
+            assert_eq!(required, operation.intrusiveness());
+            assert!(required > allowed);
+            operation
+        }
+        other => panic!("Expected the operation to be refused, got {:?}", other),
+    }
+}
+
+#[test]
+fn strictest_level_never_writes_to_the_target() {
+    let (mut session, write_log) = attach(Intrusiveness::None);
+    assert_eq!(session.max_intrusiveness(), Intrusiveness::None);
+
+    let mut core = session.core(0).unwrap();
+
+    // Reads of RAM and flash, and of the status of the core, are allowed.
+    let mut data = [0; 2];
+    core.read_32(RAM, &mut data).unwrap();
+    assert_eq!(data, [0x1111_1111, 0x2222_2222]);
+    core.read_word_32(FLASH).unwrap();
+    assert!(!core.core_halted().unwrap());
+    core.status().unwrap();
+
+    assert_eq!(
+        refused(core.read_word_32(PERIPHERAL)),
+        TargetOperation::ReadDeviceMemory
+    );
+    assert_eq!(refused(core.halt(TIMEOUT)), TargetOperation::Halt);
+    assert_eq!(refused(core.run()), TargetOperation::Resume);
+    assert_eq!(refused(core.step()), TargetOperation::Step);
+    assert_eq!(
+        refused(core.read_core_reg::<u32>(RegisterId(0))),
+        TargetOperation::ReadRegister
+    );
+    assert_eq!(
+        refused(core.write_core_reg(RegisterId(0), 0u32)),
+        TargetOperation::WriteRegister
+    );
+    assert_eq!(
+        refused(core.write_word_32(RAM, 0)),
+        TargetOperation::WriteMemory
+    );
+    assert_eq!(
+        refused(core.write_8(RAM, &[0; 4])),
+        TargetOperation::WriteMemory
+    );
+    assert_eq!(
+        refused(core.set_hw_breakpoint(FLASH)),
+        TargetOperation::HardwareBreakpoint
+    );
+    assert_eq!(
+        refused(core.set_sw_breakpoint(RAM)),
+        TargetOperation::SoftwareBreakpoint
+    );
+    assert_eq!(
+        refused(core.apply_breakpoints(&[BreakpointRequest::new(FLASH)])),
+        TargetOperation::HardwareBreakpoint
+    );
+    assert_eq!(
+        refused(core.prepare_execution(RAM | 1, None)),
+        TargetOperation::WriteRegister
+    );
+    assert_eq!(refused(core.reset()), TargetOperation::Reset);
+    assert_eq!(
+        refused(core.reset_and_halt(TIMEOUT)),
+        TargetOperation::Reset
+    );
+    drop(core);
+
+    let mut data = [0; 8];
+    session.read_non_intrusive(0, RAM, &mut data).unwrap();
+    assert_eq!(
+        refused(session.read_non_intrusive(0, PERIPHERAL, &mut data)),
+        TargetOperation::ReadDeviceMemory
+    );
+    assert_eq!(
+        refused(session.setup_swv(0, &SwoConfig::new(64_000_000))),
+        TargetOperation::ConfigureTrace
+    );
+    assert_eq!(
+        refused(session.add_swv_data_trace(0, RAM as u32)),
+        TargetOperation::ConfigureTrace
+    );
+    assert_eq!(
+        refused(session.vendor_ap::<NordicCtrlAp>().map(|_| ())),
+        TargetOperation::VendorAccessPort
+    );
+    assert_eq!(
+        refused(session.reset_system(TIMEOUT)),
+        TargetOperation::Reset
+    );
+    assert_eq!(
+        refused(session.reset_and_halt_core(0, TIMEOUT)),
+        TargetOperation::Reset
+    );
+
+    // The teardown skips the steps which would change the target.
+    let report = session.close().expect("Failed to close the session");
+    assert!(!report.debug_stopped);
+    assert!(!report.cores[0].trace_disabled);
+
+    assert!(write_log.is_empty(), "{:x?}", write_log.entries());
+}
+
+#[test]
+fn software_breakpoints_are_not_planned_above_the_level() {
+    let (mut session, write_log) = attach(Intrusiveness::RequiresHalt);
+
+    let mut core = session.core(0).unwrap();
+
+    let plan = core
+        .plan_breakpoints(&[BreakpointRequest::new(RAM)])
+        .unwrap();
+    assert_eq!(plan.software_breakpoints(), 0);
+
+    // Fill all comparators, so that only a software breakpoint is left.
+    for unit in 0..4 {
+        core.set_hw_breakpoint(FLASH + unit * 0x100).unwrap();
+    }
+    write_log.clear();
+
+    let report = core
+        .apply_breakpoints(&[BreakpointRequest::new(RAM)])
+        .unwrap();
+    assert_eq!(
+        report.outcomes,
+        [BreakpointOutcome::Failed {
+            reason: BreakpointFailure::SoftwareNotAllowed
+        }]
+    );
+
+    assert!(write_log.is_empty(), "{:x?}", write_log.entries());
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use probe_rs::{Error, FakeProbe, MemoryInterface, SearchOptions, Session};
+
+const RAM: u64 = 0x2000_0000;
+const PATTERN: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+
+fn search(
+    session: &mut Session,
+    range: std::ops::Range<u64>,
+    pattern: &[u8],
+    options: SearchOptions,
+) -> Vec<u64> {
+    session
+        .core(0)
+        .unwrap()
+        .search_memory(range, pattern, options)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+#[test]
+fn matches_straddling_a_chunk_boundary_are_found() {
+    let mut session = common::attach();
+    session
+        .core(0)
+        .unwrap()
+        .write_8(RAM + 0x0ffe, &PATTERN)
+        .unwrap();
+
+    let matches = search(
+        &mut session,
+        RAM..RAM + 0x2000,
+        &PATTERN,
+        SearchOptions::new(),
+    );
+
+    assert_eq!(matches, [RAM + 0x0ffe]);
+}
+
+#[test]
+fn masked_bytes_match_anything() {
+    let mut session = common::attach();
+    {
+        let mut core = session.core(0).unwrap();
+        core.write_8(RAM + 0x10, &[0xde, 0xad, 0x12, 0xef]).unwrap();
+        core.write_8(RAM + 0x20, &[0xde, 0xad, 0x34, 0xef]).unwrap();
+        core.write_8(RAM + 0x30, &[0xde, 0xa0, 0x34, 0xef]).unwrap();
+    }
+
+    let matches = search(
+        &mut session,
+        RAM..RAM + 0x100,
+        &PATTERN,
+        SearchOptions::new().mask(&[0xff, 0xff, 0x00, 0xff]),
+    );
+
+    assert_eq!(matches, [RAM + 0x10, RAM + 0x20]);
+}
+
+#[test]
+fn mask_must_match_the_pattern() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    let mut matches = core.search_memory(
+        RAM..RAM + 0x100,
+        &PATTERN,
+        SearchOptions::new().mask(&[0xff]),
+    );
+
+    assert!(matches!(
+        matches.next(),
+        Some(Err(Error::SearchMaskMismatch {
+            pattern: 4,
+            mask: 1
+        }))
+    ));
+    assert!(matches.next().is_none());
+}
+
+#[test]
+fn unaligned_matches_are_skipped() {
+    let mut session = common::attach();
+    {
+        let mut core = session.core(0).unwrap();
+        core.write_8(RAM + 0x11, &PATTERN).unwrap();
+        core.write_8(RAM + 0x24, &PATTERN).unwrap();
+    }
+
+    let matches = search(
+        &mut session,
+        RAM..RAM + 0x100,
+        &PATTERN,
+        SearchOptions::new().alignment(4),
+    );
+
+    assert_eq!(matches, [RAM + 0x24]);
+}
+
+#[test]
+fn volatile_memory_is_skipped() {
+    let mut session = common::attach();
+    {
+        let mut core = session.core(0).unwrap();
+        core.write_8(RAM + 0x10, &PATTERN).unwrap();
+        core.write_8(RAM + 0x80, &PATTERN).unwrap();
+    }
+    session.mark_volatile(RAM + 0x40..RAM + 0x100);
+
+    let matches = search(
+        &mut session,
+        RAM..RAM + 0x200,
+        &PATTERN,
+        SearchOptions::new(),
+    );
+
+    assert_eq!(matches, [RAM + 0x10]);
+}
+
+#[test]
+fn search_stops_reading_after_the_first_match() {
+    let probe = FakeProbe::with_mocked_core();
+    let transactions = probe.transactions();
+    let mut session = common::attach_probe(probe);
+    let mut core = session.core(0).unwrap();
+    core.write_8(RAM + 0x10, &PATTERN).unwrap();
+    let range = RAM..RAM + 0x10000;
+
+    transactions.reset();
+    let first = core
+        .search_memory(range.clone(), &PATTERN, SearchOptions::new())
+        .next();
+    let lazy = transactions.count();
+
+    assert_eq!(first.unwrap().unwrap(), RAM + 0x10);
+
+    transactions.reset();
+    let all = core
+        .search_memory(range, &PATTERN, SearchOptions::new())
+        .count();
+    let full = transactions.count();
+
+    assert_eq!(all, 1);
+    assert!(
+        lazy * 8 < full,
+        "The first match took {} transactions, the whole range {}",
+        lazy,
+        full
+    );
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use probe_rs::{
+    architecture::route::{InterfaceRoute, RouteSequence},
+    config::{
+        get_target_by_name, Core, CoreAccessOptions, CoreType, ResetScope, RiscvCoreAccessOptions,
+        Target,
+    },
+    Architecture, Error, FakeProbe, MemoryInterface, Probe, Session,
+};
+
+const RISCV: InterfaceRoute = InterfaceRoute::Riscv { jtag_tap: None };
+
+/// Records the routes it is asked to select.
+#[derive(Default)]
+struct RecordingRouteSequence {
+    switches: Mutex<Vec<(InterfaceRoute, InterfaceRoute)>>,
+}
+
+impl RouteSequence for RecordingRouteSequence {
+    fn select_route(
+        &self,
+        _probe: &mut Probe,
+        from: InterfaceRoute,
+        to: InterfaceRoute,
+    ) -> Result<(), Error> {
+        self.switches.lock().unwrap().push((from, to));
+        Ok(())
+    }
+}
+
+/// A STM32WB with a RISC-V coprocessor behind the same debug port.
+fn mixed_target(routes: Arc<RecordingRouteSequence>) -> Target {
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+
+    target.cores.push(Core {
+        name: "coprocessor".into(),
+        core_type: CoreType::Riscv,
+        core_access_options: CoreAccessOptions::Riscv(RiscvCoreAccessOptions::default()),
+        reset_scope: ResetScope::Core,
+        hardware_breakpoints: None,
+        watchpoints: None,
+    });
+    target.route_sequence = routes;
+
+    target
+}
+
+fn attach(routes: Arc<RecordingRouteSequence>) -> Session {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.mock_riscv_debug_module();
+
+    common::attach_to(probe, mixed_target(routes))
+}
+
+#[test]
+fn the_route_is_switched_to_the_accessed_core() {
+    let routes = Arc::new(RecordingRouteSequence::default());
+    let mut session = attach(routes.clone());
+
+    assert_eq!(session.architecture(), Architecture::Arm);
+
+    session
+        .core(0)
+        .unwrap()
+        .write_word_32(0x2000_0000, 0xdead_beef)
+        .unwrap();
+
+    let mut core = session.core(1).unwrap();
+    assert_eq!(core.architecture(), Architecture::Riscv);
+    assert!(core.core_halted().unwrap());
+    drop(core);
+
+    assert_eq!(session.architecture(), Architecture::Riscv);
+
+    // The ARM core kept its state while the RISC-V Debug Module was connected.
+    assert_eq!(
+        session.core(0).unwrap().read_word_32(0x2000_0000).unwrap(),
+        0xdead_beef
+    );
+
+    assert_eq!(
+        *routes.switches.lock().unwrap(),
+        [(InterfaceRoute::Arm, RISCV), (RISCV, InterfaceRoute::Arm)]
+    );
+}
+
+#[test]
+fn the_route_is_kept_while_the_same_interface_is_used() {
+    let routes = Arc::new(RecordingRouteSequence::default());
+    let mut session = attach(routes.clone());
+
+    for _ in 0..3 {
+        session.core(0).unwrap().read_word_32(0x2000_0000).unwrap();
+    }
+
+    // The RISC-V Debug Module is only set up once it is needed.
+    assert!(routes.switches.lock().unwrap().is_empty());
+}
+
+#[test]
+fn the_arm_interface_is_reached_from_the_riscv_route() {
+    let routes = Arc::new(RecordingRouteSequence::default());
+    let mut session = attach(routes);
+
+    // The ARM interface is found even while the RISC-V Debug Module is connected.
+    session.core(1).unwrap();
+    session.get_arm_interface().unwrap();
+
+    assert_eq!(session.architecture(), Architecture::Arm);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use probe_rs::{
+    architecture::arm::MpuPermission, flashing::DownloadOptions, Error, MemoryInterface, Session,
+};
+
+const MPU_CTRL: u64 = 0xE000_ED94;
+const MPU_RNR: u64 = 0xE000_ED98;
+const MPU_RBAR: u64 = 0xE000_ED9C;
+const MPU_RASR: u64 = 0xE000_EDA0;
+const CFSR: u64 = 0xE000_ED28;
+const MMFAR: u64 = 0xE000_ED34;
+
+/// Set up the MPU like firmware which protects its RAM from unprivileged code.
+fn configure_mpu(session: &mut Session) {
+    let mut core = session.core(0).unwrap();
+
+    // Region 0: 1 MiB of flash at 0x0800_0000, read-only.
+    core.write_word_32(MPU_RNR, 0).unwrap();
+    core.write_word_32(MPU_RBAR, 0x0800_0000).unwrap();
+    core.write_word_32(MPU_RASR, 0x0600_0027).unwrap();
+
+    // Region 1: 64 KiB of RAM at 0x2000_0000, privileged access only, execute never.
+    core.write_word_32(MPU_RNR, 1).unwrap();
+    core.write_word_32(MPU_RBAR, 0x2000_0000).unwrap();
+    core.write_word_32(MPU_RASR, 0x1100_001f).unwrap();
+
+    core.write_word_32(MPU_RNR, 7).unwrap();
+    core.write_word_32(MPU_CTRL, 0b101).unwrap();
+}
+
+#[test]
+fn mpu_regions_are_decoded() {
+    let mut session = common::attach();
+    configure_mpu(&mut session);
+
+    let mut core = session.core(0).unwrap();
+    let regions = core.mpu_regions().unwrap();
+
+    assert_eq!(regions.len(), 8);
+
+    assert!(regions[0].enabled);
+    assert_eq!(regions[0].range, 0x0800_0000..0x0810_0000);
+    assert_eq!(regions[0].privileged, MpuPermission::ReadOnly);
+    assert_eq!(regions[0].unprivileged, MpuPermission::ReadOnly);
+
+    assert!(regions[1].enabled);
+    assert_eq!(regions[1].range, 0x2000_0000..0x2001_0000);
+    assert_eq!(regions[1].unprivileged, MpuPermission::NoAccess);
+    assert!(regions[1].execute_never);
+
+    assert!(regions[2..].iter().all(|region| !region.enabled));
+
+    // The region selected by the firmware is restored.
+    assert_eq!(core.read_word_32(MPU_RNR).unwrap(), 7);
+}
+
+#[test]
+fn mpu_is_restored_after_error() {
+    let mut session = common::attach();
+    configure_mpu(&mut session);
+
+    let mut core = session.core(0).unwrap();
+
+    let error = core
+        .with_mpu_disabled(|core| {
+            assert_eq!(core.read_word_32(MPU_CTRL)?, 0b100);
+
+            Err::<(), _>(Error::Other(anyhow::anyhow!("routine failed")))
+        })
+        .expect_err("The error of the closure was not returned");
+
+    assert!(matches!(error, Error::Other(_)), "{:?}", error);
+    assert_eq!(core.read_word_32(MPU_CTRL).unwrap(), 0b101);
+}
+
+#[test]
+fn mem_manage_fault_is_annotated_with_region() {
+    let mut session = common::attach();
+    configure_mpu(&mut session);
+
+    let mut core = session.core(0).unwrap();
+
+    assert_eq!(core.mem_manage_fault().unwrap(), None);
+
+    // An unprivileged write to the RAM, with a valid MMFAR.
+    core.write_word_32(CFSR, 0x82).unwrap();
+    core.write_word_32(MMFAR, 0x2000_1234).unwrap();
+
+    let fault = core.mem_manage_fault().unwrap().unwrap();
+
+    assert!(fault.data_access_violation());
+    assert_eq!(fault.address, Some(0x2000_1234));
+    assert_eq!(fault.region.as_ref().map(|region| region.number), Some(1));
+    assert_eq!(
+        fault.to_string(),
+        "MemManage data access violation at 0x20001234 in MPU region 1 \
+         (0x20000000..0x20010000, privileged read-write, unprivileged no access, execute never)"
+    );
+}
+
+#[test]
+fn mpu_is_restored_after_download() {
+    let mut session = common::attach();
+    configure_mpu(&mut session);
+
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(0x8000000, &[0xaa; 4096])
+        .expect("Failed to add flash");
+
+    let mut options = DownloadOptions::new();
+    options.disable_mpu = true;
+
+    loader
+        .commit(&mut session, options)
+        .expect("Failed to download with the MPU disabled");
+
+    let mut core = session.core(0).unwrap();
+    assert_eq!(core.read_word_32(MPU_CTRL).unwrap(), 0b101);
+}
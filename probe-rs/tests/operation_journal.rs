@@ -0,0 +1,206 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::WriteLog, DivergenceKind, Error, FakeProbe, JournalOperation, JournalOutcome,
+    JournalValue, JournalWidth, MemoryInterface, OperationJournal, ReplayOptions, Session,
+};
+
+const RAM: u64 = 0x2000_0000;
+
+fn attach() -> (Session, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let session = common::attach_probe(probe);
+    write_log.clear();
+
+    (session, write_log)
+}
+
+/// Record a short bring-up script.
+fn record() -> OperationJournal {
+    let (mut session, _) = attach();
+    session.start_operation_journal();
+
+    {
+        let mut core = session.core(0).unwrap();
+        core.halt(Duration::from_millis(100)).unwrap();
+        core.write_word_32(RAM + 0x10, 0x1234_5678).unwrap();
+        core.write_8(RAM + 0x20, &[1, 2, 3]).unwrap();
+
+        // The status is polled, and not recorded.
+        core.status().unwrap();
+
+        assert_eq!(core.read_word_32(RAM + 0x10).unwrap(), 0x1234_5678);
+        let mut data = [0; 3];
+        core.read_8(RAM + 0x20, &mut data).unwrap();
+    }
+
+    let journal = session.stop_operation_journal();
+
+    // Nothing is recorded after the journal was stopped.
+    session.core(0).unwrap().write_word_32(RAM, 0).unwrap();
+
+    journal
+}
+
+#[test]
+fn operations_are_recorded_with_their_outcome() {
+    let journal = record();
+
+    assert_eq!(journal.target, "stm32wb55ccux");
+    let operations: Vec<_> = journal
+        .entries
+        .iter()
+        .map(|entry| entry.operation.clone())
+        .collect();
+    assert_eq!(
+        operations,
+        [
+            JournalOperation::Halt {
+                core: 0,
+                timeout_ms: 100
+            },
+            JournalOperation::WriteMemory {
+                core: 0,
+                address: JournalValue::Literal(RAM + 0x10),
+                width: JournalWidth::U32,
+                values: vec![JournalValue::Literal(0x1234_5678)],
+            },
+            JournalOperation::WriteMemory {
+                core: 0,
+                address: JournalValue::Literal(RAM + 0x20),
+                width: JournalWidth::U8,
+                values: vec![
+                    JournalValue::Literal(1),
+                    JournalValue::Literal(2),
+                    JournalValue::Literal(3)
+                ],
+            },
+            JournalOperation::ReadMemory {
+                core: 0,
+                address: JournalValue::Literal(RAM + 0x10),
+                width: JournalWidth::U32,
+                count: 1,
+                values: Some(vec![JournalValue::Literal(0x1234_5678)]),
+            },
+            JournalOperation::ReadMemory {
+                core: 0,
+                address: JournalValue::Literal(RAM + 0x20),
+                width: JournalWidth::U8,
+                count: 3,
+                values: Some(vec![
+                    JournalValue::Literal(1),
+                    JournalValue::Literal(2),
+                    JournalValue::Literal(3)
+                ]),
+            },
+        ]
+    );
+    assert!(journal
+        .entries
+        .iter()
+        .all(|entry| entry.outcome == JournalOutcome::Ok));
+}
+
+#[test]
+fn replayed_reads_are_checked() {
+    let journal = record();
+
+    let (mut session, _) = attach();
+    let report = session
+        .replay_operation_journal(&journal, ReplayOptions::new())
+        .unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.executed, journal.entries.len());
+
+    // The same board, but with another value in the first write.
+    let mut edited = journal.clone();
+    edited.entries[1].operation = JournalOperation::WriteMemory {
+        core: 0,
+        address: JournalValue::Literal(RAM + 0x10),
+        width: JournalWidth::U32,
+        values: vec![JournalValue::Literal(0xdead_beef)],
+    };
+    edited.entries.push(edited.entries[3].clone());
+
+    let (mut session, _) = attach();
+    let report = session
+        .replay_operation_journal(&edited, ReplayOptions::new())
+        .unwrap();
+    assert_eq!(report.executed, 4);
+    assert_eq!(report.divergences.len(), 1);
+    assert_eq!(report.divergences[0].index, 3);
+    assert_eq!(
+        report.divergences[0].kind,
+        DivergenceKind::ValueMismatch {
+            expected: vec![0x1234_5678],
+            actual: vec![0xdead_beef],
+        }
+    );
+
+    let (mut session, _) = attach();
+    let report = session
+        .replay_operation_journal(&edited, ReplayOptions::new().continue_on_divergence())
+        .unwrap();
+    assert_eq!(report.executed, edited.entries.len());
+    let indices: Vec<_> = report
+        .divergences
+        .iter()
+        .map(|divergence| divergence.index)
+        .collect();
+    assert_eq!(indices, [3, 5]);
+
+    let (mut session, _) = attach();
+    let report = session
+        .replay_operation_journal(&edited, ReplayOptions::new().assert_reads(false))
+        .unwrap();
+    assert!(report.is_clean());
+}
+
+#[test]
+fn variables_are_substituted_when_replaying() {
+    let mut journal = record();
+    journal.define_variable("scratch", RAM..RAM + 0x100);
+
+    let path = std::env::temp_dir().join(format!(
+        "probe-rs-operation-journal-{}.yaml",
+        std::process::id()
+    ));
+    journal.save(&path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("scratch + 0x10"), "{}", text);
+
+    // The next revision of the board has its scratch memory elsewhere.
+    let (mut session, _) = attach();
+    let report = session
+        .replay_journal(
+            &path,
+            ReplayOptions::new().variable("scratch", RAM + 0x1000),
+        )
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(report.is_clean());
+
+    let mut core = session.core(0).unwrap();
+    assert_eq!(core.read_word_32(RAM + 0x1010).unwrap(), 0x1234_5678);
+    assert_eq!(core.read_word_32(RAM + 0x10).unwrap(), 0);
+}
+
+#[test]
+fn invalid_journals_are_rejected_before_replaying() {
+    let mut journal = record();
+    journal.define_variable("scratch", RAM..RAM + 0x100);
+    journal.variables.clear();
+
+    let (mut session, write_log) = attach();
+    match session.replay_operation_journal(&journal, ReplayOptions::new()) {
+        Err(Error::InvalidOperationJournal(message)) => {
+            assert!(message.contains("scratch"), "{}", message)
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(write_log.is_empty());
+}
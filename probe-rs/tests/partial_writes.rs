@@ -0,0 +1,86 @@
+mod common;
+
+use probe_rs::{
+    test_utils::{ReadFaults, WriteLog},
+    Error, FakeProbe, MemoryInterface, ProbeCapabilities, Session,
+};
+
+/// A peripheral register, which isn't part of the memory map and therefore device memory.
+const REGISTER: u64 = 0x4000_0400;
+const RAM: u64 = 0x2000_0000;
+
+/// Attach through a probe which can only do word transfers if `word_transfers` is set.
+///
+/// Reads of [`REGISTER`] fail, so that a hidden read during a byte write is detected.
+fn attach(word_transfers: bool) -> (Session, WriteLog, ReadFaults) {
+    let mut probe = FakeProbe::with_mocked_core();
+    if word_transfers {
+        probe.set_capabilities(ProbeCapabilities::new().swd().transfer_sizes(4, 1024));
+    }
+    let write_log = probe.write_log();
+    let read_faults = probe.read_faults();
+
+    let session = common::attach_probe(probe);
+
+    read_faults.make_inaccessible(REGISTER as u32..REGISTER as u32 + 4);
+    write_log.clear();
+
+    (session, write_log, read_faults)
+}
+
+#[test]
+fn byte_writes_to_device_memory_are_single_accesses() {
+    let (mut session, write_log, _) = attach(false);
+    let mut core = session.core(0).unwrap();
+
+    assert!(core.native_write_widths().bits_8);
+
+    core.write_word_8(REGISTER + 1, 0x5a).unwrap();
+    core.write_8(REGISTER + 1, &[1, 2, 3]).unwrap();
+
+    assert_eq!(
+        write_log.entries(),
+        [
+            (REGISTER as u32 + 1, 0x5a << 8),
+            (REGISTER as u32 + 1, 1 << 8),
+            (REGISTER as u32 + 2, 2 << 16),
+            (REGISTER as u32 + 3, 3 << 24),
+        ]
+    );
+}
+
+#[test]
+fn read_modify_writes_of_device_memory_are_refused() {
+    let (mut session, write_log, read_faults) = attach(true);
+    let mut core = session.core(0).unwrap();
+
+    assert!(!core.native_write_widths().bits_8);
+
+    match core.write_word_8(REGISTER + 1, 0x5a) {
+        Err(Error::WouldRequireReadModifyWrite { address, len }) => {
+            assert_eq!((address, len), (REGISTER + 1, 1));
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(matches!(
+        core.write_8(REGISTER, &[1, 2, 3, 4, 5]),
+        Err(Error::WouldRequireReadModifyWrite { len: 5, .. })
+    ));
+    assert!(write_log.is_empty());
+
+    // Whole words are written without reading them.
+    core.write_8(REGISTER, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(write_log.entries(), [(REGISTER as u32, 0x0403_0201)]);
+
+    // RAM has no side effects, so the read-modify-write is fine there.
+    core.write_word_32(RAM, 0x4433_2211).unwrap();
+    core.write_word_8(RAM + 1, 0xaa).unwrap();
+    assert_eq!(core.read_word_32(RAM).unwrap(), 0x4433_aa11);
+
+    // The explicit opt-in reads the register, which fails here.
+    assert!(core.write_word_8_rmw(REGISTER + 1, 0x5a).is_err());
+
+    read_faults.stop();
+    core.write_word_8_rmw(REGISTER + 1, 0x5a).unwrap();
+    assert_eq!(core.read_word_32(REGISTER).unwrap(), 0x0403_5a01);
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    AttachOptions, Error, FakeProbe, FreezeSelection, HealthEvent, MemoryInterface, Session,
+};
+
+/// DBGMCU_APB1FZR1 of the STM32WB.
+const APB1FZR1: u64 = 0xE004_203C;
+const WWDG_IWDG: u32 = 0b11 << 11;
+
+fn frozen(session: &mut Session) -> Vec<String> {
+    session
+        .frozen_peripherals()
+        .unwrap()
+        .into_iter()
+        .filter(|peripheral| peripheral.frozen)
+        .map(|peripheral| peripheral.peripheral)
+        .collect()
+}
+
+fn unexpected_resets(session: &Session) -> Vec<String> {
+    session
+        .health_log()
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.event == HealthEvent::UnexpectedReset)
+        .map(|entry| entry.details)
+        .collect()
+}
+
+#[test]
+fn watchdogs_are_frozen_while_attaching() {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+    let mut session = common::attach_with_options(
+        probe,
+        common::TARGET,
+        AttachOptions::new().freeze_peripherals_on_halt(FreezeSelection::Watchdogs),
+    );
+
+    assert!(write_log.entries().contains(&(APB1FZR1 as u32, WWDG_IWDG)));
+    assert_eq!(frozen(&mut session), ["WWDG", "IWDG"]);
+}
+
+#[test]
+fn nothing_is_frozen_unless_selected() {
+    let mut session = common::attach();
+
+    assert!(frozen(&mut session).is_empty());
+
+    session
+        .freeze_peripherals_on_halt(FreezeSelection::Peripherals(vec!["TIM2".to_owned()]))
+        .unwrap();
+    assert_eq!(frozen(&mut session), ["TIM2"]);
+
+    session
+        .freeze_peripherals_on_halt(FreezeSelection::None)
+        .unwrap();
+    assert!(frozen(&mut session).is_empty());
+}
+
+#[test]
+fn unknown_peripherals_are_rejected() {
+    let mut session = common::attach();
+
+    let result =
+        session.freeze_peripherals_on_halt(FreezeSelection::Peripherals(vec!["WDT".to_owned()]));
+
+    assert!(matches!(result, Err(Error::UnknownPeripheralFreeze(name)) if name == "WDT"));
+}
+
+#[test]
+fn freeze_is_applied_again_after_an_unexpected_reset() {
+    let probe = FakeProbe::with_mocked_core();
+    let resets = probe.target_resets();
+    let write_log = probe.write_log();
+    let mut session = common::attach_with_options(
+        probe,
+        common::TARGET,
+        AttachOptions::new().freeze_peripherals_on_halt(FreezeSelection::Watchdogs),
+    );
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // The mocked reset doesn't reset the memory, so the freeze bits are cleared by hand.
+    core.write_word_32(APB1FZR1, 0).unwrap();
+    write_log.clear();
+
+    resets.reset();
+    core.status().unwrap();
+
+    assert!(write_log.entries().contains(&(APB1FZR1 as u32, WWDG_IWDG)));
+    drop(core);
+
+    assert_eq!(
+        unexpected_resets(&session),
+        ["The core was reset while it was halted, although the watchdogs are frozen"]
+    );
+}
+
+#[test]
+fn watchdog_resets_are_reported_on_targets_without_freeze_bits() {
+    let probe = FakeProbe::with_mocked_core();
+    let resets = probe.target_resets();
+    let mut session = common::attach_to(probe, "nrf51822_xxAC");
+    let mut core = session.core(0).unwrap();
+
+    core.halt(Duration::from_millis(100)).unwrap();
+    resets.reset();
+    core.status().unwrap();
+
+    // The status is read again without a further reset.
+    core.status().unwrap();
+    drop(core);
+
+    assert_eq!(
+        unexpected_resets(&session),
+        ["The core was reset while it was halted, and the watchdogs can't be frozen"]
+    );
+}
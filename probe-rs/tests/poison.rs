@@ -0,0 +1,210 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    Core, HealthEvent, MemoryInterface, PoisonResetPolicy, Session, WatchpointConfig,
+    WatchpointKind,
+};
+
+const DWT_CTRL: u64 = 0xE000_1000;
+const VTOR: u64 = 0xE000_ED08;
+
+const GUARD: u64 = 0x2000_0100;
+
+/// The halted core of `session`, whose DWT has 4 comparators, and which can be reset.
+fn halted_core(session: &mut Session) -> Core<'_> {
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+    core.write_word_32(DWT_CTRL, 4 << 28).unwrap();
+
+    // A vector table at the start of the flash.
+    core.write_word_32(VTOR, 0x0800_0000).unwrap();
+    core.write_word_32(0x0800_0000, 0x2000_8000).unwrap();
+    core.write_word_32(0x0800_0004, 0x0800_0101).unwrap();
+
+    core
+}
+
+#[test]
+fn overwritten_guards_are_reported() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.poison_region(GUARD..GUARD + 0x40, 0xDEAD_BEEF)
+        .unwrap();
+    core.poison_region(0x2000_0202..0x2000_0206, 0x4433_2211)
+        .unwrap();
+
+    assert_eq!(core.read_word_32(GUARD + 0x3C).unwrap(), 0xDEAD_BEEF);
+    let mut bytes = [0; 4];
+    core.read_8(0x2000_0202, &mut bytes).unwrap();
+    assert_eq!(bytes, [0x33, 0x44, 0x11, 0x22]);
+    assert!(core.check_poison().unwrap().is_empty());
+
+    // A stack overflow into the guard.
+    core.write_word_32(GUARD + 0x38, 0).unwrap();
+
+    let violations = core.check_poison().unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].range, GUARD..GUARD + 0x40);
+    assert_eq!(violations[0].address(), GUARD + 0x38);
+    assert_eq!(
+        &violations[0].current[0x38..0x40],
+        [0, 0, 0, 0, 0xEF, 0xBE, 0xAD, 0xDE]
+    );
+
+    // The violation is recorded once, but reported by every check.
+    assert_eq!(core.check_poison().unwrap().len(), 1);
+    drop(core);
+
+    let entries = session.health_log().entries();
+    let violations: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.event == HealthEvent::PoisonViolated)
+        .collect();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].core, Some(0));
+}
+
+#[test]
+fn unpoisoned_memory_is_not_checked() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.poison_region(GUARD..GUARD + 0x40, 0xDEAD_BEEF)
+        .unwrap();
+    core.unpoison_region(GUARD..GUARD + 0x20).unwrap();
+    core.write_word_32(GUARD, 0).unwrap();
+
+    assert!(core.check_poison().unwrap().is_empty());
+
+    core.write_word_32(GUARD + 0x20, 0).unwrap();
+    let violations = core.check_poison().unwrap();
+    assert_eq!(violations[0].range, GUARD + 0x20..GUARD + 0x40);
+    assert_eq!(violations[0].offset, 0);
+}
+
+#[test]
+fn reset_policies() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.poison_region(GUARD..GUARD + 0x40, 0xDEAD_BEEF)
+        .unwrap();
+
+    core.write_word_32(GUARD, 0).unwrap();
+    core.reset_and_halt(Duration::from_millis(100)).unwrap();
+    assert!(core.check_poison().unwrap().is_empty());
+
+    core.set_poison_reset_policy(PoisonResetPolicy::VerifyOnly);
+    core.write_word_32(GUARD, 0).unwrap();
+    core.reset_and_halt(Duration::from_millis(100)).unwrap();
+    assert_eq!(core.check_poison().unwrap().len(), 1);
+
+    core.set_poison_reset_policy(PoisonResetPolicy::Forget);
+    core.reset_and_halt(Duration::from_millis(100)).unwrap();
+    assert!(core.check_poison().unwrap().is_empty());
+}
+
+#[test]
+fn the_latest_region_is_watched_while_comparators_are_free() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.poison_region(GUARD..GUARD + 0x40, 0xDEAD_BEEF)
+        .unwrap();
+    core.set_poison_watch(true).unwrap();
+    assert_eq!(core.watched_poison(), Some(GUARD..GUARD + 0x40));
+
+    // The watchpoint moves to the next region.
+    core.poison_region(0x2000_0200..0x2000_0220, 0xDEAD_BEEF)
+        .unwrap();
+    assert_eq!(core.watched_poison(), Some(0x2000_0200..0x2000_0220));
+    assert_eq!(core.watchpoints().len(), 1);
+    assert_eq!(core.watchpoints()[0].config.address, 0x2000_0200);
+    assert_eq!(core.watchpoints()[0].config.kind, WatchpointKind::Write);
+
+    // A region the DWT can't watch is only checked.
+    core.poison_region(0x2000_0301..0x2000_0304, 0xDEAD_BEEF)
+        .unwrap();
+    assert_eq!(core.watched_poison(), None);
+    assert!(core.watchpoints().is_empty());
+
+    // As is a region without a free comparator.
+    for unit in 0..4 {
+        core.set_watchpoint(WatchpointConfig::new(
+            0x2000_1000 + 0x10 * unit,
+            4,
+            WatchpointKind::Read,
+        ))
+        .unwrap();
+    }
+    core.poison_region(0x2000_0400..0x2000_0420, 0xDEAD_BEEF)
+        .unwrap();
+    assert_eq!(core.watched_poison(), None);
+    assert_eq!(core.watchpoints().len(), 4);
+    assert_eq!(core.check_poison().unwrap().len(), 0);
+
+    core.clear_all_watchpoints().unwrap();
+    core.poison_region(0x2000_0400..0x2000_0420, 0xDEAD_BEEF)
+        .unwrap();
+    core.set_poison_watch(false).unwrap();
+    assert_eq!(core.watched_poison(), None);
+    assert!(core.watchpoints().is_empty());
+}
+
+/// The stack guards of the tasks of an RTOS, each at the bottom of its stack, which grows
+/// down towards it.
+#[test]
+fn the_stack_guards_of_several_tasks() {
+    const STACK_SIZE: u64 = 0x200;
+    const GUARD_SIZE: u64 = 0x20;
+    const STACKS: [u64; 3] = [0x2000_1000, 0x2000_1200, 0x2000_1400];
+
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    for stack in STACKS {
+        core.poison_region(stack..stack + GUARD_SIZE, 0xDEAD_BEEF)
+            .unwrap();
+        // The task uses the top of its stack.
+        core.write_word_32(stack + STACK_SIZE - 4, 0x0800_0101)
+            .unwrap();
+    }
+    assert!(core.check_poison().unwrap().is_empty());
+
+    // The second and third task overflow their stacks, by a word and by the whole guard.
+    core.write_word_32(STACKS[1] + GUARD_SIZE - 4, 0).unwrap();
+    core.write_8(STACKS[2], &[0; GUARD_SIZE as usize]).unwrap();
+
+    let violations = core.check_poison().unwrap();
+    assert_eq!(violations.len(), 2);
+    assert_eq!(violations[0].range, STACKS[1]..STACKS[1] + GUARD_SIZE);
+    assert_eq!(violations[0].address(), STACKS[1] + GUARD_SIZE - 4);
+    assert_eq!(violations[1].range, STACKS[2]..STACKS[2] + GUARD_SIZE);
+    assert_eq!(violations[1].address(), STACKS[2]);
+
+    // The third task is deleted, and its stack is reused by the heap.
+    core.unpoison_region(STACKS[2]..STACKS[2] + STACK_SIZE)
+        .unwrap();
+    assert_eq!(core.check_poison().unwrap().len(), 1);
+
+    // A new task gets the stack, and its guard is poisoned again.
+    core.poison_region(STACKS[2]..STACKS[2] + GUARD_SIZE, 0xDEAD_BEEF)
+        .unwrap();
+    let violations = core.check_poison().unwrap();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].range, STACKS[1]..STACKS[1] + GUARD_SIZE);
+    drop(core);
+
+    // Each overflow is delivered once.
+    let entries = session.health_log().entries();
+    assert_eq!(
+        entries
+            .iter()
+            .filter(|entry| entry.event == HealthEvent::PoisonViolated)
+            .count(),
+        2
+    );
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::ProbePolls, DebugProbeError, Error, FakeProbe, Probe, ProbeCapabilities, Session,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Attach to a running core, with a probe which polls the core on its own if `poll_offload`
+/// is set.
+fn attach(poll_offload: bool) -> (Session, ProbePolls) {
+    let mut probe = FakeProbe::with_mocked_core();
+    let capabilities = ProbeCapabilities::new().swd().jtag();
+    probe.set_capabilities(if poll_offload {
+        capabilities.poll_offload()
+    } else {
+        capabilities
+    });
+    let polls = probe.polls();
+
+    let session = common::attach_probe(probe);
+
+    (session, polls)
+}
+
+#[test]
+fn halt_is_reported_by_the_probe() {
+    let (mut session, polls) = attach(true);
+    let mut core = session.core(0).unwrap();
+    assert!(!core.core_halted().unwrap());
+
+    polls.halt_during_polls(1);
+    core.wait_for_core_halted(TIMEOUT).unwrap();
+
+    assert!(core.core_halted().unwrap());
+    assert_eq!(polls.polls(), 1);
+    assert_eq!(polls.notifications(), 1);
+}
+
+#[test]
+fn halt_is_validated_after_the_report() {
+    let (mut session, polls) = attach(true);
+    let mut core = session.core(0).unwrap();
+
+    // The core is continued right after the first halt, so the wait goes on until the
+    // second one.
+    polls.halt_during_polls(2);
+    polls.resume_after_notifications(1);
+    core.wait_for_core_halted(TIMEOUT).unwrap();
+
+    assert!(core.core_halted().unwrap());
+    assert_eq!(polls.polls(), 2);
+    assert_eq!(polls.notifications(), 2);
+}
+
+#[test]
+fn wait_without_halt_times_out() {
+    let (mut session, polls) = attach(true);
+    let mut core = session.core(0).unwrap();
+
+    match core.wait_for_core_halted(TIMEOUT) {
+        Err(Error::Probe(DebugProbeError::Timeout)) => {}
+        other => panic!("Expected a timeout, got {:?}", other),
+    }
+
+    assert!(polls.polls() > 0);
+    assert_eq!(polls.notifications(), 0);
+}
+
+#[test]
+fn host_polls_without_the_capability() {
+    let (mut session, polls) = attach(false);
+    let mut core = session.core(0).unwrap();
+
+    core.halt(TIMEOUT).unwrap();
+    core.wait_for_core_halted(TIMEOUT).unwrap();
+
+    assert_eq!(polls.polls(), 0);
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use probe_rs::Error;
+
+#[test]
+fn prepare_execution_sets_up_cortex_m_state() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    core.prepare_execution(0x2000_0101, Some(0x2000_8000))
+        .expect("Failed to prepare the execution");
+
+    let regs = core.registers();
+
+    let pc: u32 = core.read_core_reg(regs.program_counter()).unwrap();
+    assert_eq!(pc, 0x2000_0100);
+
+    let msp: u32 = core.read_core_reg(regs.msp().unwrap()).unwrap();
+    assert_eq!(msp, 0x2000_8000);
+
+    let xpsr: u32 = core.read_core_reg(regs.psr().unwrap()).unwrap();
+    assert_ne!(xpsr & (1 << 24), 0, "EPSR.T is not set");
+}
+
+#[test]
+fn prepare_execution_rejects_arm_entry_on_cortex_m() {
+    let mut session = common::attach();
+    let mut core = session.core(0).unwrap();
+
+    let error = core
+        .prepare_execution(0x2000_0100, None)
+        .expect_err("Prepared the execution of ARM code on a Cortex-M core");
+
+    assert!(
+        matches!(
+            error,
+            Error::InvalidEntryPoint {
+                address: 0x2000_0100,
+                ..
+            }
+        ),
+        "{:?}",
+        error
+    );
+}
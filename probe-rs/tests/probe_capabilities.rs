@@ -0,0 +1,93 @@
+mod common;
+
+use probe_rs::{
+    architecture::arm::SwoConfig, AttachOptions, DebugProbeError, Error, FakeProbe,
+    MemoryInterface, Permissions, Probe, ProbeCapabilities, Session,
+};
+
+fn probe_with(capabilities: ProbeCapabilities) -> Probe {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_capabilities(capabilities);
+
+    Probe::from_specific_probe(Box::new(probe))
+}
+
+fn attach(capabilities: ProbeCapabilities) -> Session {
+    probe_with(capabilities)
+        .attach("stm32wb55ccux", Permissions::default())
+        .expect("Failed to attach with 'fake' probe.")
+}
+
+#[test]
+fn attach_under_reset_requires_reset_control() {
+    let error = probe_with(ProbeCapabilities::new().swd())
+        .attach_under_reset("stm32wb55ccux", Permissions::default())
+        .unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::Probe(DebugProbeError::MissingCapability { .. })
+    ));
+}
+
+#[test]
+fn swv_requires_swo_capture() {
+    let mut session = attach(ProbeCapabilities::new().swd());
+
+    let error = session
+        .setup_swv(0, &SwoConfig::new(64_000_000))
+        .unwrap_err();
+
+    match error {
+        Error::Probe(DebugProbeError::MissingCapability { capability, probe }) => {
+            assert_eq!(capability, "SWO capture");
+            assert_eq!(probe, "Mock probe for testing");
+        }
+        other => panic!("Expected a missing capability, got {}", other),
+    }
+}
+
+#[test]
+fn swv_baud_rate_is_limited_by_the_probe() {
+    let mut session = attach(ProbeCapabilities::new().swd().swo().swo_max_baud(2_000_000));
+
+    let config = SwoConfig::new(64_000_000).set_baud(4_000_000);
+    let error = session.setup_swv(0, &config).unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::Probe(DebugProbeError::MissingCapability { .. })
+    ));
+}
+
+#[test]
+fn speed_negotiation_starts_at_the_highest_speed_of_the_probe() {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_max_speed(5_000);
+    probe.set_capabilities(ProbeCapabilities::new().swd().max_speed_khz(5_000));
+
+    let session = common::attach_with_options(
+        probe,
+        common::TARGET,
+        AttachOptions::new().auto_speed(40_000, 100),
+    );
+
+    assert_eq!(session.negotiated_speed_khz(), Some(5_000));
+    assert!(session.health_log().entries().is_empty());
+}
+
+#[test]
+fn byte_accesses_are_assembled_from_words() {
+    let mut session = attach(ProbeCapabilities::new().swd().transfer_sizes(4, 1024));
+    assert!(session.probe_capabilities().needs_word_transfers());
+
+    let mut core = session.core(0).unwrap();
+
+    core.write_word_32(0x2000_0000, 0x4433_2211).unwrap();
+    core.write_8(0x2000_0001, &[0xaa, 0xbb]).unwrap();
+
+    let mut bytes = [0; 3];
+    core.read_8(0x2000_0001, &mut bytes).unwrap();
+    assert_eq!(bytes, [0xaa, 0xbb, 0x44]);
+    assert_eq!(core.read_word_32(0x2000_0000).unwrap(), 0x44bb_aa11);
+}
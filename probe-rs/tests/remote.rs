@@ -0,0 +1,195 @@
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use probe_rs::{
+    flashing::DownloadOptions,
+    remote::{AccessPolicy, ProbeServer, RemoteError, RemoteProbe, ServerConfig},
+    test_utils::WriteLog,
+    DebugProbeError, Error, FakeProbe, MemoryInterface, Permissions, Probe, Session,
+};
+
+const TOKEN: &str = "bench-board";
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// Share a probe with a mocked core on a local port.
+fn serve(configure: impl FnOnce(&mut ServerConfig)) -> (SocketAddr, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let mut config = ServerConfig::new(TOKEN);
+    configure(&mut config);
+
+    let server = ProbeServer::bind("127.0.0.1:0", probe.into_probe(), config).unwrap();
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    (address, write_log)
+}
+
+fn attach(address: SocketAddr) -> Session {
+    Probe::open_remote(address, TOKEN)
+        .unwrap()
+        .attach("stm32wb55ccux", Permissions::default())
+        .expect("Failed to attach through the probe server.")
+}
+
+/// Whether `error` was caused by a [`RemoteError`] for which `expected` returns `true`.
+fn is_remote_error(error: &Error, expected: fn(&RemoteError) -> bool) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+
+    while let Some(error) = source {
+        if let Some(remote) = error.downcast_ref::<RemoteError>() {
+            return expected(remote);
+        }
+        source = error.source();
+    }
+
+    false
+}
+
+#[test]
+fn attach_flash_and_debug_through_the_server() {
+    let (address, write_log) = serve(|_| ());
+
+    let probe = RemoteProbe::connect(address, TOKEN).unwrap();
+    let statistics = probe.statistics();
+
+    // The remote probe reports the properties of the probe of the server.
+    let probe = Probe::from_specific_probe(Box::new(probe));
+    let local = FakeProbe::with_mocked_core().into_probe();
+    assert_eq!(probe.get_name(), local.get_name());
+    assert_eq!(probe.capabilities(), local.capabilities());
+
+    let mut session = probe
+        .attach("stm32wb55ccux", Permissions::default())
+        .expect("Failed to attach through the probe server.");
+
+    let mut loader = session.target().flash_loader();
+    loader.add_data(0x0800_0000, &[0xaa; 4096]).unwrap();
+    loader
+        .commit(&mut session, DownloadOptions::new())
+        .expect("Failed to flash through the probe server.");
+
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    let pc = core.registers().program_counter();
+    core.write_core_reg(pc.into(), 0x0800_0100u32).unwrap();
+    let value: u32 = core.read_core_reg(pc).unwrap();
+    assert_eq!(value, 0x0800_0100);
+
+    core.write_word_32(RAM, 0x1234_5678).unwrap();
+    assert_eq!(core.read_word_32(RAM).unwrap(), 0x1234_5678);
+    core.run().unwrap();
+
+    // The accesses reached the probe of the server.
+    assert!(write_log.entries().contains(&(RAM as u32, 0x1234_5678)));
+
+    assert!(statistics.round_trips() > 0);
+    assert!(statistics.mean_latency().unwrap() <= statistics.max_latency());
+    assert!(statistics.max_latency() > Duration::ZERO);
+}
+
+#[test]
+fn wrong_token_is_rejected() {
+    let (address, _) = serve(|_| ());
+
+    match RemoteProbe::connect(address, "guess") {
+        Err(DebugProbeError::ProbeSpecific(source)) => assert!(matches!(
+            source.downcast_ref::<RemoteError>(),
+            Some(RemoteError::InvalidToken)
+        )),
+        other => panic!("Expected the token to be rejected, got {:?}", other),
+    }
+}
+
+#[test]
+fn second_client_waits_for_the_lock() {
+    let (address, _) = serve(|_| ());
+
+    let mut first = attach(address);
+    first.core(0).unwrap().write_word_32(RAM, 1).unwrap();
+
+    let (attached, attached_rx) = mpsc::channel();
+    let second = thread::spawn(move || {
+        let probe = RemoteProbe::connect(address, TOKEN).unwrap();
+        let statistics = probe.statistics();
+
+        let mut session = Probe::from_specific_probe(Box::new(probe))
+            .attach("stm32wb55ccux", Permissions::default())
+            .expect("Failed to attach through the probe server.");
+        attached.send(()).unwrap();
+
+        let value = session.core(0).unwrap().read_word_32(RAM).unwrap();
+        (value, statistics.lock_wait())
+    });
+
+    // The second client waits while the first one holds the lock.
+    let hold = Duration::from_millis(300);
+    assert!(attached_rx.recv_timeout(hold).is_err());
+    first.core(0).unwrap().write_word_32(RAM, 2).unwrap();
+
+    // Closing the session releases the lock, and the second client proceeds.
+    drop(first);
+    attached_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    let (value, lock_wait) = second.join().unwrap();
+    assert_eq!(value, 2);
+    assert!(lock_wait >= hold, "waited {:?}", lock_wait);
+}
+
+#[test]
+fn idle_client_loses_the_lock() {
+    let idle_timeout = Duration::from_millis(200);
+    let (address, _) = serve(|config| config.idle_timeout = idle_timeout);
+
+    let mut first = attach(address);
+    let mut core = first.core(0).unwrap();
+
+    // The lock is only revoked if another client waits for it.
+    thread::sleep(idle_timeout * 2);
+    core.read_word_32(RAM).unwrap();
+
+    let mut second = attach(address);
+    second.core(0).unwrap().write_word_32(RAM, 3).unwrap();
+
+    let error = core.read_word_32(RAM).unwrap_err();
+    assert!(
+        is_remote_error(&error, |e| matches!(e, RemoteError::Revoked)),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn time_slice_ends_while_another_client_waits() {
+    let slice = Duration::from_millis(200);
+    let (address, _) = serve(|config| config.access = AccessPolicy::TimeSliced(slice));
+
+    let mut first = attach(address);
+    let mut core = first.core(0).unwrap();
+
+    let second = thread::spawn(move || {
+        let mut session = attach(address);
+        session.core(0).unwrap().write_word_32(RAM, 4).unwrap();
+    });
+
+    // The first client keeps using the probe, but loses the lock at the end of its slice.
+    let error = loop {
+        match core.read_word_32(RAM) {
+            Ok(_) => thread::sleep(Duration::from_millis(10)),
+            Err(error) => break error,
+        }
+    };
+    assert!(
+        is_remote_error(&error, |e| matches!(e, RemoteError::Revoked)),
+        "{:?}",
+        error
+    );
+
+    second.join().unwrap();
+}
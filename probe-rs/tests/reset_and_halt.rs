@@ -0,0 +1,43 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{HaltLocation, MemoryInterface, ResetHaltMechanism};
+
+const VTOR: u64 = 0xE000_ED08;
+
+#[test]
+fn reset_and_halt_reports_reset_vector() {
+    let mut session = common::attach();
+
+    {
+        let mut core = session.core(0).unwrap();
+
+        // A vector table at the start of the flash.
+        core.write_word_32(VTOR, 0x0800_0000).unwrap();
+        core.write_word_32(0x0800_0000, 0x2000_8000).unwrap();
+        core.write_word_32(0x0800_0004, 0x0800_0101).unwrap();
+    }
+
+    let report = session
+        .reset_and_halt_core(0, Duration::from_millis(100))
+        .expect("Failed to reset and halt the core");
+
+    assert_eq!(report.pc, 0x0800_0100);
+    assert_eq!(report.reset_vector, Some(0x0800_0100));
+    assert_eq!(report.location, HaltLocation::ResetVector);
+    assert_eq!(report.mechanism, ResetHaltMechanism::ResetVectorCatch);
+    assert!(report.ineffective.is_empty());
+
+    assert!(session.core(0).unwrap().core_halted().unwrap());
+}
+
+#[test]
+fn reset_and_halt_rejects_unknown_core() {
+    let mut session = common::attach();
+
+    assert!(matches!(
+        session.reset_and_halt_core(1, Duration::from_millis(100)),
+        Err(probe_rs::Error::CoreNotFound(1))
+    ));
+}
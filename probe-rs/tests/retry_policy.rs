@@ -0,0 +1,100 @@
+mod common;
+
+use probe_rs::{
+    test_utils::ReadFaults, FakeProbe, HealthEvent, MemoryInterface, RetryPolicy, Session,
+};
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// An address of a peripheral, which is device memory.
+const PERIPHERAL: u64 = 0x4000_0000;
+
+fn attach() -> (Session, ReadFaults) {
+    let probe = FakeProbe::with_mocked_core();
+    let read_faults = probe.read_faults();
+
+    let mut session = common::attach_probe(probe);
+
+    let mut core = session.core(0).unwrap();
+    core.write_32(RAM, &[0x1111_1111, 0x2222_2222]).unwrap();
+    drop(core);
+
+    (session, read_faults)
+}
+
+#[test]
+fn watch_window_policy_surfaces_errors_fast() {
+    let (mut session, read_faults) = attach();
+    read_faults.fail_every(3);
+
+    let mut core = session.core(0).unwrap();
+
+    let failures = core.with_retry_policy(RetryPolicy::watch_window(), |core| {
+        (0..6).filter(|_| core.read_word_32(RAM).is_err()).count()
+    });
+
+    assert_eq!(failures, 2);
+    drop(core);
+
+    assert!(session.health_log().is_empty());
+}
+
+#[test]
+fn verify_policy_retries_failed_reads() {
+    let (mut session, read_faults) = attach();
+    read_faults.fail_every(3);
+
+    let mut core = session.core(0).unwrap();
+
+    core.with_retry_policy(RetryPolicy::verify(), |core| {
+        for _ in 0..6 {
+            assert_eq!(core.read_word_32(RAM).unwrap(), 0x1111_1111);
+
+            let mut data = [0; 2];
+            core.read_32(RAM, &mut data).unwrap();
+            assert_eq!(data, [0x1111_1111, 0x2222_2222]);
+        }
+    });
+
+    // The session policy applies again.
+    assert_eq!(core.retry_policy(), RetryPolicy::none());
+    drop(core);
+
+    let entries = session.health_log().entries();
+    assert!(!entries.is_empty());
+
+    for entry in entries {
+        assert_eq!(entry.event, HealthEvent::RetrySucceeded);
+        assert_eq!(entry.operation, "read");
+        assert!(entry.details.contains("after 2 attempts"));
+        assert!(entry.details.contains("up to 5 attempts"));
+    }
+}
+
+#[test]
+fn device_memory_is_not_retried() {
+    let (mut session, read_faults) = attach();
+    read_faults.fail_every(1);
+
+    let mut core = session.core(0).unwrap();
+
+    core.with_retry_policy(RetryPolicy::verify(), |core| {
+        core.read_word_32(PERIPHERAL).unwrap_err();
+    });
+    drop(core);
+
+    assert!(session.health_log().is_empty());
+
+    let mut core = session.core(0).unwrap();
+
+    core.with_retry_policy(RetryPolicy::verify(), |core| {
+        core.read_word_32(RAM).unwrap_err();
+    });
+    drop(core);
+
+    let entries = session.health_log().entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].event, HealthEvent::RetriesExhausted);
+    assert!(entries[0].details.contains("failed after 5 attempts"));
+}
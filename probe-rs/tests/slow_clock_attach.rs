@@ -0,0 +1,113 @@
+use probe_rs::{
+    AttachOptions, Error, FakeProbe, HealthEvent, Permissions, Probe, Session, SlowClockAttach,
+};
+
+/// A target whose family is marked with `slow_clock_attach`.
+const SLOW_CLOCK_TARGET: &str = "STM32L051C8Tx";
+
+/// Attach to a fake target whose debug logic runs at 100 kHz until it was attached once.
+fn attach(
+    target: &str,
+    options: AttachOptions,
+    max_speed_khz: Option<u32>,
+) -> Result<Session, Error> {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_slow_clock(100);
+    if let Some(max_speed_khz) = max_speed_khz {
+        probe.set_max_speed(max_speed_khz);
+    }
+
+    Probe::from_specific_probe(Box::new(probe)).attach_with_options(
+        target,
+        Permissions::default(),
+        options,
+    )
+}
+
+#[test]
+fn marked_targets_are_attached_slowly_first() {
+    let session = attach(SLOW_CLOCK_TARGET, AttachOptions::new(), None)
+        .expect("Failed to attach with 'fake' probe.");
+
+    assert_eq!(
+        session.slow_clock_attach(),
+        Some(SlowClockAttach {
+            initial_speed_khz: 100,
+            final_speed_khz: 1_000,
+            detected: false,
+        })
+    );
+    assert_eq!(session.negotiated_speed_khz(), Some(1_000));
+    assert!(session.health_log().entries().is_empty());
+}
+
+#[test]
+fn speed_stays_low_if_the_target_is_not_faster() {
+    let session = attach(SLOW_CLOCK_TARGET, AttachOptions::new(), Some(150))
+        .expect("Failed to attach with 'fake' probe.");
+
+    let slow_clock_attach = session.slow_clock_attach().unwrap();
+    assert_eq!(slow_clock_attach.initial_speed_khz, 100);
+    assert_eq!(slow_clock_attach.final_speed_khz, 100);
+}
+
+#[test]
+fn unmarked_targets_fail_at_the_configured_speed() {
+    assert!(attach("stm32wb55ccux", AttachOptions::new(), None).is_err());
+}
+
+#[test]
+fn slow_clock_attach_can_be_enabled() {
+    let session = attach(
+        "stm32wb55ccux",
+        AttachOptions::new().slow_clock_attach(true),
+        None,
+    )
+    .expect("Failed to attach with 'fake' probe.");
+
+    assert_eq!(
+        session
+            .slow_clock_attach()
+            .map(|attach| attach.final_speed_khz),
+        Some(1_000)
+    );
+}
+
+#[test]
+fn slow_clock_is_detected_during_speed_negotiation() {
+    let session = attach(
+        "stm32wb55ccux",
+        AttachOptions::new().auto_speed(4_000, 1_000),
+        None,
+    )
+    .expect("Failed to attach with 'fake' probe.");
+
+    assert_eq!(
+        session.slow_clock_attach(),
+        Some(SlowClockAttach {
+            initial_speed_khz: 100,
+            final_speed_khz: 4_000,
+            detected: true,
+        })
+    );
+    assert_eq!(session.negotiated_speed_khz(), Some(4_000));
+
+    let entries = session.health_log().entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].event, HealthEvent::SlowClockDetected);
+    assert!(entries[0].details.contains("slow_clock_attach: true"));
+}
+
+#[test]
+fn detection_is_skipped_if_slow_clock_attach_is_disabled() {
+    let error = attach(
+        SLOW_CLOCK_TARGET,
+        AttachOptions::new()
+            .auto_speed(4_000, 1_000)
+            .slow_clock_attach(false),
+        None,
+    )
+    .unwrap_err();
+
+    assert!(matches!(error, Error::SpeedNegotiationFailed { .. }));
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    test_utils::ProbeTransactions, BreakpointRequest, Core, CoreStatus, DebugProbeError, Error,
+    FakeProbe, MemoryInterface, Probe, RegisterId, Session,
+};
+
+const RAM: u64 = 0x2000_0000;
+const CODE: u64 = RAM + 0x100;
+const STACK: u32 = 0x2000_2000;
+
+const SP: RegisterId = RegisterId(13);
+const PC: RegisterId = RegisterId(15);
+const XPSR: RegisterId = RegisterId(0b1_0000);
+
+const FP_CTRL: u64 = 0xE000_2000;
+
+/// Writes the pattern in R2 to each word of the R1 bytes at R0, and counts the words in R5.
+const FILL: [u16; 9] = [
+    0xb570, //     push {r4, r5, r6, lr}
+    0x2500, //     movs r5, #0
+    0x428d, // 1:  cmp r5, r1
+    0xd203, //     bcs 2f
+    0x1946, //     adds r6, r0, r5
+    0x6032, //     str r2, [r6]
+    0x3504, //     adds r5, #4
+    0xe7f9, //     b 1b
+    0xbd70, // 2:  pop {r4, r5, r6, pc}
+];
+
+/// Attach to a core which executes code, halted at the start of the fill routine.
+fn attach_at_fill() -> (Session, ProbeTransactions) {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+    let transactions = probe.transactions();
+    let mut session = common::attach_probe(probe);
+
+    {
+        let mut core = session.core(0).unwrap();
+        core.halt(Duration::from_millis(100)).unwrap();
+
+        let bytes: Vec<u8> = FILL.iter().flat_map(|half| half.to_le_bytes()).collect();
+        core.write_8(CODE, &bytes).unwrap();
+
+        for (register, value) in [
+            (0, 0x2000_1000u32),
+            (1, 0x10),
+            (2, 0xa5a5_5a5a),
+            (14, 0x0800_0001),
+        ] {
+            core.write_core_reg(RegisterId(register), value).unwrap();
+        }
+        core.write_core_reg(SP, STACK).unwrap();
+        core.write_core_reg(XPSR, 0x0100_0000u32).unwrap();
+        core.write_core_reg(PC, CODE as u32).unwrap();
+    }
+
+    (session, transactions)
+}
+
+fn registers(core: &mut Core) -> Vec<u32> {
+    (0..16)
+        .chain([XPSR.0])
+        .map(|register| core.read_core_reg(RegisterId(register)).unwrap())
+        .collect()
+}
+
+#[test]
+fn fast_steps_match_regular_steps() {
+    let (mut regular, _) = attach_at_fill();
+    let (mut fast, _) = attach_at_fill();
+    let mut regular = regular.core(0).unwrap();
+    let mut fast = fast.core(0).unwrap();
+
+    // The whole routine, until it returns.
+    for step in 0..29 {
+        let expected = regular.step().unwrap();
+        let stepped = fast.step_fast().unwrap();
+
+        assert_eq!(stepped.pc, expected.pc, "step {}", step);
+        assert_eq!(stepped.instruction_set, expected.instruction_set);
+        assert_eq!(fast.status().unwrap(), regular.status().unwrap());
+        assert_eq!(
+            registers(&mut fast),
+            registers(&mut regular),
+            "step {}",
+            step
+        );
+    }
+
+    assert_eq!(fast.read_core_reg::<u32>(PC).unwrap(), 0x0800_0000);
+    assert_eq!(fast.halt_generation(), regular.halt_generation());
+}
+
+#[test]
+fn fast_step_takes_a_single_round_trip() {
+    let (mut session, transactions) = attach_at_fill();
+    let mut core = session.core(0).unwrap();
+
+    for _ in 0..4 {
+        transactions.reset();
+        core.step_fast().unwrap();
+        assert_eq!(transactions.count(), 1);
+    }
+
+    transactions.reset();
+    core.step().unwrap();
+    assert!(transactions.count() > 1);
+}
+
+#[test]
+fn fast_step_leaves_a_hardware_breakpoint() {
+    let (mut session, _) = attach_at_fill();
+    let mut core = session.core(0).unwrap();
+
+    let report = core
+        .apply_breakpoints(&[BreakpointRequest::hardware(CODE)])
+        .unwrap();
+    assert!(report.is_complete());
+
+    assert_eq!(core.step_fast().unwrap().pc, CODE + 2);
+
+    // The breakpoint unit is enabled again.
+    assert_eq!(core.read_word_32(FP_CTRL).unwrap() & 1, 1);
+}
+
+#[test]
+fn fast_step_falls_back_if_the_core_does_not_halt() {
+    let probe = FakeProbe::with_mocked_core();
+    let resumes = probe.foreign_resumes();
+    let mut session = common::attach_probe(probe);
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+
+    // Another debugger resumes the core right after the step.
+    resumes.resume();
+
+    assert!(matches!(
+        core.step_fast(),
+        Err(Error::Probe(DebugProbeError::Timeout))
+    ));
+    assert!(matches!(core.status(), Ok(CoreStatus::Running)));
+}
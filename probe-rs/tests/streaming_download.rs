@@ -0,0 +1,152 @@
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    io::{self, Read},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use probe_rs::{
+    config::{get_target_by_name, MemoryRegion, SectorDescription},
+    flashing::{
+        erase_and_program_streaming, FlashError, FlashProgress, ProgressEvent, StreamingOptions,
+    },
+    FakeProbe, Target,
+};
+
+/// Tracks the peak of the allocated memory, to check that the image is not buffered.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(allocated, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const QSPI_BASE: u64 = 0x9000_0000;
+const QSPI_SIZE: u64 = 32 * 1024 * 1024;
+const SECTOR_SIZE: u64 = 64 * 1024;
+
+/// A STM32WB with 32 MiB of external flash, programmed with a copy of the internal flash algorithm.
+fn target_with_qspi_flash() -> Target {
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+
+    let mut region = target
+        .memory_map
+        .iter()
+        .find_map(|region| match region {
+            MemoryRegion::Nvm(region) => Some(region.clone()),
+            _ => None,
+        })
+        .unwrap();
+    region.name = Some("QSPI".into());
+    region.range = QSPI_BASE..QSPI_BASE + QSPI_SIZE;
+    region.is_boot_memory = false;
+    region.boot_critical_ranges = vec![];
+    target.memory_map.push(MemoryRegion::Nvm(region));
+
+    let mut algorithm = target.flash_algorithms[0].clone();
+    algorithm.name = "qspi".into();
+    algorithm.flash_properties.address_range = QSPI_BASE..QSPI_BASE + QSPI_SIZE;
+    algorithm.flash_properties.page_size = 0x1000;
+    algorithm.flash_properties.sectors = vec![SectorDescription {
+        size: SECTOR_SIZE,
+        address: 0,
+    }];
+    target.flash_algorithms.push(algorithm);
+
+    target
+}
+
+/// A synthetic image, which fails with an error after `fail_at` bytes.
+struct SyntheticImage {
+    position: u64,
+    size: u64,
+    fail_at: u64,
+}
+
+impl Read for SyntheticImage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.fail_at {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "injected failure",
+            ));
+        }
+
+        let end = (self.position + buf.len() as u64)
+            .min(self.size)
+            .min(self.fail_at);
+        let len = (end - self.position) as usize;
+
+        for (offset, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = ((self.position + offset as u64) % 251) as u8;
+        }
+        self.position = end;
+
+        Ok(len)
+    }
+}
+
+#[test]
+fn streaming_download_reports_partial_programming() {
+    let mut session = common::attach_to(FakeProbe::with_mocked_core(), target_with_qspi_flash());
+
+    let fail_at = QSPI_SIZE * 6 / 10;
+    let image = SyntheticImage {
+        position: 0,
+        size: QSPI_SIZE,
+        fail_at,
+    };
+
+    let consumed = Rc::new(Cell::new(0));
+    let progress = {
+        let consumed = consumed.clone();
+        FlashProgress::new(move |event| {
+            if let ProgressEvent::DataConsumed { size } = event {
+                consumed.set(consumed.get() + size);
+            }
+        })
+    };
+
+    let mut options = StreamingOptions::new();
+    options.progress = Some(&progress);
+
+    let baseline = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(baseline, Ordering::SeqCst);
+
+    let error = erase_and_program_streaming(&mut session, QSPI_BASE, image, options)
+        .expect_err("The injected failure was not reported");
+
+    let peak = PEAK.load(Ordering::SeqCst) - baseline;
+
+    // Only the sectors which were read completely before the failure are programmed.
+    let programmed_end = QSPI_BASE + fail_at / SECTOR_SIZE * SECTOR_SIZE;
+
+    match error {
+        FlashError::StreamingFailed { programmed, source } => {
+            assert_eq!(programmed, QSPI_BASE..programmed_end);
+            assert!(matches!(*source, FlashError::StreamRead(_)), "{:?}", source);
+        }
+        other => panic!("Unexpected error: {:?}", other),
+    }
+    assert_eq!(consumed.get(), programmed_end - QSPI_BASE);
+
+    // Only a few sectors are in memory at the same time.
+    assert!(peak < 8 * SECTOR_SIZE as usize, "peak memory use: {}", peak);
+}
@@ -0,0 +1,20 @@
+mod common;
+
+use probe_rs::{AttachOptions, FakeProbe, WireProtocol};
+
+#[test]
+fn switch_protocol() {
+    let mut session = common::attach_with_options(
+        FakeProbe::new(),
+        common::TARGET,
+        AttachOptions::new().protocol(WireProtocol::Jtag),
+    );
+
+    assert_eq!(session.active_protocol(), Some(WireProtocol::Jtag));
+
+    session
+        .switch_protocol(WireProtocol::Swd, true)
+        .expect("Failed to switch the protocol.");
+
+    assert_eq!(session.active_protocol(), Some(WireProtocol::Swd));
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use probe_rs::{
+    architecture::arm::{Icsr, Shcsr},
+    Error, Intrusiveness, MemoryInterface, MemoryMappedRegister, RegisterId, TargetOperation,
+};
+
+/// SYST_RVR and SYST_CVR of the SysTick timer.
+const SYST_RVR: u64 = 0xE000_E014;
+const SYST_CVR: u64 = 0xE000_E018;
+
+/// CFSR, with a precise bus fault.
+const CFSR: u64 = 0xE000_ED28;
+
+#[test]
+fn snapshot_is_read_while_the_core_runs() {
+    let mut session = common::attach();
+
+    {
+        let mut core = session.core(0).unwrap();
+        assert!(!core.core_halted().unwrap());
+
+        // SysTick (15) is active, and an external interrupt is pending.
+        core.write_word_32(Icsr::ADDRESS, 0x0041_300f).unwrap();
+        core.write_word_32(Shcsr::ADDRESS, 0x0007_0800).unwrap();
+        core.write_word_32(CFSR, 0x0000_8200).unwrap();
+        core.write_word_32(SYST_RVR, 0xff00_ffff).unwrap();
+        core.write_word_32(SYST_CVR, 0x1234).unwrap();
+    }
+
+    // A dashboard only reads the bus.
+    session.set_max_intrusiveness(Intrusiveness::BusOnly);
+    let mut core = session.core(0).unwrap();
+
+    let snapshot = core.system_control_snapshot_live().unwrap();
+
+    assert_eq!(snapshot.active_exception(), Some(15));
+    assert_eq!(snapshot.pending_exception(), Some(19));
+    assert!(snapshot.shcsr.systickact());
+    assert!(snapshot.shcsr.busfaultena());
+    assert_eq!(snapshot.cfsr.unwrap().bfsr(), 0x82);
+    assert_eq!(snapshot.systick_reload, 0x00ff_ffff);
+    assert_eq!(snapshot.systick_current, 0x1234);
+
+    let icsr: Icsr = core.read_mm_register().unwrap();
+    assert_eq!(icsr.vectactive(), 15);
+
+    // Core registers need a halted core, which the session doesn't allow.
+    match core.read_core_reg::<u32>(RegisterId(15)) {
+        Err(Error::IntrusivenessExceeded { operation, .. }) => {
+            assert_eq!(operation, TargetOperation::ReadRegister)
+        }
+        other => panic!("Expected the register read to be refused, got {:?}", other),
+    }
+}
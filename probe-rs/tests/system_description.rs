@@ -0,0 +1,72 @@
+mod common;
+
+use probe_rs::{DebugInterfaceDescriptor, Error, FakeProbe, Session, SystemDescription};
+use serde_json::Value;
+
+fn attach() -> Session {
+    common::attach_to(FakeProbe::with_mocked_core(), "nrf51822_xxAC")
+}
+
+/// Replaces all values in `value` by their type, so that only the schema is compared.
+fn schema(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::from("null"),
+        Value::Bool(_) => Value::from("bool"),
+        Value::Number(_) => Value::from("number"),
+        Value::String(_) => Value::from("string"),
+        Value::Array(values) => Value::Array(values.iter().map(schema).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, value)| (name.clone(), schema(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[test]
+fn arm_description_matches_the_golden_schema() {
+    let session = attach();
+
+    let golden: Value = serde_json::from_str(include_str!("system_description/arm.json")).unwrap();
+
+    let description = session.system_description();
+    let actual = serde_json::to_value(&description).unwrap();
+
+    assert_eq!(schema(&actual), schema(&golden));
+
+    assert_eq!(actual["target"]["name"], "nRF51822_xxAC");
+    assert_eq!(actual["target"]["architecture"], "arm");
+    assert_eq!(actual["cores"][0]["core_type"], "armv6m");
+    assert_eq!(actual["cores"][0]["registers"]["program_counter"], "PC");
+    assert_eq!(actual["memory_map"][1]["kind"], "nvm");
+    assert_eq!(actual["probe"]["name"], "Mock probe for testing");
+    assert_eq!(actual["settings"]["protocol"], "Swd");
+}
+
+#[test]
+fn discovered_access_ports_are_described() {
+    let session = attach();
+
+    match session.system_description().debug_interface {
+        DebugInterfaceDescriptor::Arm { access_ports } => assert_eq!(access_ports.len(), 1),
+        other => panic!("Expected an ARM debug interface, got {:?}", other),
+    }
+}
+
+#[test]
+fn description_is_loaded_offline() {
+    let session = attach();
+    let description = session.system_description();
+
+    let loaded = SystemDescription::from_json(&description.to_json()).unwrap();
+    assert_eq!(loaded, description);
+
+    let golden = SystemDescription::from_json(include_str!("system_description/arm.json")).unwrap();
+    assert_eq!(golden.target.name, "nRF51822_xxAC");
+
+    assert!(matches!(
+        SystemDescription::from_json("{\"version\": 0}"),
+        Err(Error::UnsupportedSystemDescriptionVersion { version: 0, .. })
+    ));
+}
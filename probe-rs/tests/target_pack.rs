@@ -0,0 +1,134 @@
+mod common;
+
+use probe_rs::{
+    config::{
+        families, get_target_by_name, installed_packs, load_pack,
+        pack::{self, PackError},
+        ChipFamily, RegistryError,
+    },
+    flashing::DownloadOptions,
+    FakeProbe, HealthEvent,
+};
+
+const CHIP: &str = "STM32WB55CCUx";
+
+/// The built-in family of [`CHIP`], reduced to that chip.
+fn builtin_family() -> ChipFamily {
+    let mut family = families()
+        .unwrap()
+        .into_iter()
+        .find(|family| {
+            family
+                .variants
+                .iter()
+                .any(|variant| variant.name.eq_ignore_ascii_case(CHIP))
+        })
+        .unwrap();
+
+    family
+        .variants
+        .retain(|variant| variant.name.eq_ignore_ascii_case(CHIP));
+
+    family
+}
+
+#[test]
+fn packs_round_trip() {
+    let family = builtin_family();
+    let bytes = pack::create("round-trip", "1.2.3", &[family.clone()]).unwrap();
+
+    let pack = pack::read(&bytes).unwrap();
+    assert_eq!(pack.manifest.name, "round-trip");
+    assert_eq!(pack.manifest.version, "1.2.3");
+    assert_eq!(pack.manifest.format_version, pack::PACK_FORMAT_VERSION);
+
+    assert_eq!(pack.families.len(), 1);
+    assert_eq!(
+        serde_yaml::to_string(&pack.families[0]).unwrap(),
+        serde_yaml::to_string(&family).unwrap()
+    );
+    assert!(!pack.families[0].flash_algorithms[0].instructions.is_empty());
+}
+
+#[test]
+fn corrupted_packs_are_rejected() {
+    let bytes = pack::create("corrupted", "1.0.0", &[builtin_family()]).unwrap();
+
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert!(matches!(pack::read(&magic), Err(PackError::NotAPack)));
+
+    // The last entry holds the instructions of a flash algorithm.
+    let mut flipped = bytes.clone();
+    *flipped.last_mut().unwrap() ^= 0xff;
+    match pack::read(&flipped) {
+        Err(PackError::HashMismatch { entry }) => assert!(entry.contains('/'), "{}", entry),
+        other => panic!("Unexpected result {:?}", other.map(|pack| pack.manifest)),
+    }
+
+    let truncated = &bytes[..bytes.len() - 1];
+    assert!(matches!(
+        pack::read(truncated),
+        Err(PackError::EntryOutOfBounds { .. })
+    ));
+    assert!(matches!(
+        pack::read(&bytes[..20]),
+        Err(PackError::Truncated)
+    ));
+
+    // Nothing of a rejected pack is loaded.
+    assert!(matches!(
+        load_pack(flipped),
+        Err(RegistryError::Pack(PackError::HashMismatch { .. }))
+    ));
+    assert!(installed_packs()
+        .iter()
+        .all(|pack| pack.name != "corrupted"));
+}
+
+#[test]
+fn invalid_families_are_rejected_by_name() {
+    let mut family = builtin_family();
+    family.name = "broken".into();
+    family.variants[0].flash_algorithms = vec!["not_an_algorithm".into()];
+
+    match pack::create("broken", "1.0.0", &[family]) {
+        Err(PackError::InvalidFamily { family, .. }) => assert_eq!(family, "broken"),
+        other => panic!("Unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn a_pack_overrides_a_builtin_target_and_flashes_with_it() {
+    let mut family = builtin_family();
+    for algorithm in &mut family.flash_algorithms {
+        algorithm.description = "Tuned flash algorithm".into();
+    }
+
+    let installed =
+        load_pack(pack::create("tuned", "0.1.0", &[family]).unwrap()).expect("Failed to load");
+    assert_eq!(installed.overridden, [CHIP]);
+    assert_eq!(installed_packs(), [installed]);
+
+    let target = get_target_by_name(CHIP).unwrap();
+    assert_eq!(
+        target.flash_algorithms[0].description,
+        "Tuned flash algorithm"
+    );
+
+    let mut session = common::attach_to(FakeProbe::with_mocked_core(), CHIP);
+
+    let warnings = session.system_description().warnings;
+    assert!(warnings
+        .iter()
+        .any(|warning| warning.event == HealthEvent::TargetOverridden
+            && warning.details.contains("tuned")));
+
+    let mut loader = session.target().flash_loader();
+    loader
+        .add_data(0x0800_0000, &[0xaa; 4096])
+        .expect("Failed to add flash");
+    loader
+        .commit(&mut session, DownloadOptions::new())
+        .expect("Failed to flash");
+}
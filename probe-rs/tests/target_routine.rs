@@ -0,0 +1,173 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    Error, FakeProbe, MemoryInterface, RoutineArgument, RoutineCall, RoutineCompletion, Session,
+    TargetRoutine,
+};
+
+const RAM: u64 = 0x2000_0000;
+/// The routine is loaded behind the trap and the flag word.
+const CODE: u64 = RAM + 8;
+
+/// A memory test, which writes the pattern in R2 to each word of the R1 bytes at R0, and
+/// reads it back. The number of tested bytes and of failures is stored in the output block at
+/// R3, and the number of failures is returned.
+const MEMORY_TEST: [u16; 17] = [
+    0xb570, //     push {r4, r5, r6, lr}
+    0x2400, //     movs r4, #0
+    0x2500, //     movs r5, #0
+    0x428d, // 1:  cmp r5, r1
+    0xd207, //     bcs 3f
+    0x1946, //     adds r6, r0, r5
+    0x6032, //     str r2, [r6]
+    0x6836, //     ldr r6, [r6]
+    0x4296, //     cmp r6, r2
+    0xd000, //     beq 2f
+    0x3401, //     adds r4, #1
+    0x3504, // 2:  adds r5, #4
+    0xe7f5, //     b 1b
+    0x601d, // 3:  str r5, [r3]
+    0x605c, //     str r4, [r3, #4]
+    0x0020, //     movs r0, r4
+    0xbd70, //     pop {r4, r5, r6, pc}
+];
+
+fn routine(code: &[u16]) -> TargetRoutine {
+    let bytes: Vec<u8> = code.iter().flat_map(|half| half.to_le_bytes()).collect();
+
+    TargetRoutine::from_bytes(&bytes, 0)
+}
+
+fn attach() -> Session {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.execute_code();
+
+    common::attach_probe(probe)
+}
+
+#[test]
+fn memory_test_runs_to_completion() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+    let tested = 0x2000_8000;
+
+    let output = core
+        .run_routine(
+            &routine(&MEMORY_TEST),
+            &RoutineCall::new()
+                .argument(RoutineArgument::Value(tested))
+                .argument(RoutineArgument::Value(0x100))
+                .argument(RoutineArgument::Value(0xa5a5_5a5a))
+                .argument(RoutineArgument::OutputAddress)
+                .output(8),
+        )
+        .unwrap();
+
+    assert_eq!(output.result, 0);
+    assert_eq!(output.output, [0, 1, 0, 0, 0, 0, 0, 0]);
+
+    let mut words = [0; 0x40];
+    core.read_32(u64::from(tested), &mut words).unwrap();
+    assert!(words.iter().all(|&word| word == 0xa5a5_5a5a));
+}
+
+#[test]
+fn routine_which_faults_is_reported() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    // `udf #0`
+    let error = core
+        .run_routine(&routine(&[0xde00]), &RoutineCall::new())
+        .unwrap_err();
+
+    assert!(matches!(error, Error::RoutineCrashed { pc: CODE }));
+}
+
+#[test]
+fn routine_which_does_not_return_times_out() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    // `b .`
+    let error = core
+        .run_routine(
+            &routine(&[0xe7fe]),
+            &RoutineCall::new().timeout(Duration::from_millis(50)),
+        )
+        .unwrap_err();
+
+    match error {
+        Error::RoutineTimedOut { timeout, pc } => {
+            assert_eq!(timeout, Duration::from_millis(50));
+            assert_eq!(pc, CODE);
+        }
+        other => panic!("Unexpected error: {:?}", other),
+    }
+    assert!(core.core_halted().unwrap());
+}
+
+#[test]
+fn routine_can_complete_with_a_flag() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    let code = [
+        0x225a, // movs r2, #0x5a
+        0x6002, // str r2, [r0]
+        0x2007, // movs r0, #7
+        0xe7fe, // b .
+    ];
+    let output = core
+        .run_routine(
+            &routine(&code).completion(RoutineCompletion::MemoryFlag { value: 0x5a }),
+            &RoutineCall::new().argument(RoutineArgument::FlagAddress),
+        )
+        .unwrap();
+
+    assert_eq!(output.result, 7);
+    assert!(core.core_halted().unwrap());
+}
+
+#[test]
+fn routine_can_exit_with_semihosting() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    let code = [
+        0x2018, // movs r0, #0x18 (SYS_EXIT)
+        0x2102, // movs r1, #2
+        0x0409, // lsls r1, r1, #16
+        0x3126, // adds r1, #0x26 (ADP_Stopped_ApplicationExit)
+        0xbeab, // bkpt 0xab
+    ];
+    let output = core
+        .run_routine(
+            &routine(&code).completion(RoutineCompletion::SemihostingExit),
+            &RoutineCall::new(),
+        )
+        .unwrap();
+
+    assert_eq!(output.result, 0);
+}
+
+#[test]
+fn routine_with_too_many_arguments_is_rejected() {
+    let mut session = attach();
+    let mut core = session.core(0).unwrap();
+
+    let call = (0..5).fold(RoutineCall::new(), |call, value| {
+        call.argument(RoutineArgument::Value(value))
+    });
+    let error = core.run_routine(&routine(&MEMORY_TEST), &call).unwrap_err();
+
+    assert!(matches!(
+        error,
+        Error::TooManyRoutineArguments {
+            count: 5,
+            supported: 4
+        }
+    ));
+}
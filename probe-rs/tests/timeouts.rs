@@ -0,0 +1,109 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use probe_rs::{
+    test_utils::AccessStalls, Deadline, DebugProbeError, Error, FakeProbe, MemoryInterface, Probe,
+    Session,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(50);
+
+/// The latency of every access while the calls are timed.
+const LATENCY: Duration = Duration::from_millis(2);
+
+/// Allowance for the scheduling of the test threads, which is not part of the guarantee.
+const SLACK: Duration = Duration::from_millis(50);
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// Attach to a core which ignores all halt requests.
+fn attach() -> (Session, AccessStalls) {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_ignored_halt_requests(u32::MAX);
+    let stalls = probe.access_stalls();
+
+    let session = common::attach_probe(probe);
+
+    (session, stalls)
+}
+
+/// Check that a call which started at `start` timed out after `timeout`, and returned at most
+/// `quantum` later.
+fn assert_timed_out<T: std::fmt::Debug>(
+    result: Result<T, Error>,
+    start: Instant,
+    timeout: Duration,
+    quantum: Duration,
+) {
+    let elapsed = start.elapsed();
+
+    match result {
+        Err(Error::Probe(DebugProbeError::Timeout)) => {}
+        other => panic!("Expected a timeout, got {:?}", other),
+    }
+
+    assert!(elapsed >= timeout, "Gave up early, after {:?}", elapsed);
+    assert!(
+        elapsed <= timeout + quantum + SLACK,
+        "Overshot the timeout of {:?}, took {:?}",
+        timeout,
+        elapsed
+    );
+}
+
+#[test]
+fn halt_respects_the_timeout() {
+    let (mut session, stalls) = attach();
+    let mut core = session.core(0).unwrap();
+    stalls.stall_every(1, LATENCY);
+
+    let start = Instant::now();
+    let result = core.halt(TIMEOUT);
+
+    assert_timed_out(result, start, TIMEOUT, Deadline::MIN_BUDGET + LATENCY);
+}
+
+#[test]
+fn wait_for_core_halted_respects_the_timeout() {
+    let (mut session, stalls) = attach();
+    let mut core = session.core(0).unwrap();
+    assert!(!core.core_halted().unwrap());
+    stalls.stall_every(1, LATENCY);
+
+    let start = Instant::now();
+    let result = core.wait_for_core_halted(TIMEOUT);
+
+    assert_timed_out(result, start, TIMEOUT, Deadline::MIN_BUDGET + LATENCY);
+}
+
+#[test]
+fn enclosing_timeout_bounds_a_longer_halt() {
+    let (mut session, stalls) = attach();
+    let mut core = session.core(0).unwrap();
+    stalls.stall_every(1, LATENCY);
+
+    let start = Instant::now();
+    let result = core.with_timeout(TIMEOUT, |core| core.halt(Duration::from_secs(5)));
+
+    assert_timed_out(result, start, TIMEOUT, Deadline::MIN_BUDGET + LATENCY);
+}
+
+#[test]
+fn large_read_stops_after_the_chunk_at_the_timeout() {
+    /// The time the bus stalls once per chunk of 1024 words.
+    const STALL: Duration = Duration::from_millis(20);
+
+    let (mut session, stalls) = attach();
+    let mut core = session.core(0).unwrap();
+
+    // Without a timeout, the read would stall 64 times.
+    let mut data = vec![0u32; 64 * 1024];
+    stalls.stall_every(1024, STALL);
+
+    let start = Instant::now();
+    let result = core.with_timeout(TIMEOUT, |core| core.read_32(RAM, &mut data));
+
+    assert_timed_out(result, start, TIMEOUT, STALL);
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use probe_rs::{
+    config::{
+        get_target_by_name, Core, CoreAccessOptions, CoreType, ResetScope, RiscvCoreAccessOptions,
+        Target,
+    },
+    FakeProbe, MemoryInterface, Session, TransferDirection, TransferEvent, TransferProgress,
+};
+
+/// A STM32WB with a RISC-V coprocessor behind the same debug port.
+fn mixed_target() -> Target {
+    let mut target = get_target_by_name("stm32wb55ccux").unwrap();
+
+    target.cores.push(Core {
+        name: "coprocessor".into(),
+        core_type: CoreType::Riscv,
+        core_access_options: CoreAccessOptions::Riscv(RiscvCoreAccessOptions::default()),
+        reset_scope: ResetScope::Core,
+        hardware_breakpoints: None,
+        watchpoints: None,
+    });
+
+    target
+}
+
+/// Report the progress of the transfers of `session` into the returned events.
+fn record_progress(session: &mut Session) -> Arc<Mutex<Vec<TransferEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    session.set_transfer_progress(Some({
+        let events = events.clone();
+        TransferProgress::new(move |event| events.lock().unwrap().push(*event))
+    }));
+
+    events
+}
+
+#[test]
+fn the_estimate_of_a_slow_riscv_transfer_converges_early() {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.mock_riscv_debug_module();
+    probe.set_riscv_latency(Duration::from_micros(50));
+
+    let mut session = common::attach_to(probe, mixed_target());
+    let events = record_progress(&mut session);
+
+    let mut data = vec![0u32; 0x400];
+    session
+        .core(1)
+        .unwrap()
+        .read_32(0x2000_0000, &mut data)
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    let last = events.last().unwrap();
+    assert!(last.is_finished());
+    assert_eq!(last.direction, TransferDirection::Read);
+
+    // The progress only advances.
+    for pair in events.windows(2) {
+        assert!(pair[1].transferred > pair[0].transferred);
+        assert!(pair[1].elapsed >= pair[0].elapsed);
+        assert!(pair[1].operations.unwrap() > pair[0].operations.unwrap());
+    }
+
+    // From the 20% mark on, the estimated duration is within 10% of the actual one.
+    let actual = last.elapsed.as_secs_f64();
+    for event in events
+        .iter()
+        .filter(|event| event.transferred * 5 >= event.len)
+    {
+        let estimated = event.estimated_duration().as_secs_f64();
+        assert!(
+            (estimated - actual).abs() <= actual * 0.1,
+            "Estimated {:.3} s at {} of {} bytes, but the transfer took {:.3} s",
+            estimated,
+            event.transferred,
+            event.len,
+            actual
+        );
+        assert!(event.estimate.operations_per_word.is_some());
+    }
+
+    let statistics = session.transfer_statistics();
+    assert_eq!(statistics.reads.transfers, 1);
+    assert_eq!(statistics.reads.bytes, 0x1000);
+    assert_eq!(statistics.reads.operations, last.operations.unwrap());
+}
+
+#[test]
+fn short_transfers_are_only_counted() {
+    let mut session = common::attach();
+    let events = record_progress(&mut session);
+
+    let mut core = session.core(0).unwrap();
+    core.write_32(0x2000_0000, &[0; 0x10]).unwrap();
+    core.write_32(0x2000_0000, &[0; 0x400]).unwrap();
+    drop(core);
+
+    let events = events.lock().unwrap();
+    assert!(events.iter().all(|event| event.len == 0x1000));
+    assert!(events.last().unwrap().is_finished());
+    // The ARM core doesn't count its operations, so the estimate is based on the bytes.
+    assert!(events.iter().all(|event| event.operations.is_none()));
+
+    let writes = session.transfer_statistics().writes;
+    assert_eq!(writes.transfers, 2);
+    assert_eq!(writes.bytes, 0x1040);
+    assert!(writes.cost_per_word().is_some());
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use probe_rs::{
+    test_utils::{ProbeTransactions, VendorCommands, WriteLog},
+    DebugProbeError, Error, FakeProbe, Intrusiveness, MemoryInterface, Probe, ProbeCapabilities,
+    Session, TargetOperation, VendorCommand, VendorCommandOrdering,
+};
+
+const RAM: u64 = 0x2000_0000;
+
+/// Attach to the mocked core, with a probe which orders vendor commands as given by
+/// `ordering`.
+fn attach(
+    ordering: VendorCommandOrdering,
+) -> (Session, VendorCommands, WriteLog, ProbeTransactions) {
+    let mut probe = FakeProbe::with_mocked_core();
+    probe.set_capabilities(
+        ProbeCapabilities::new()
+            .swd()
+            .jtag()
+            .vendor_commands(ordering),
+    );
+    let commands = probe.vendor_commands();
+    let write_log = probe.write_log();
+    let transactions = probe.transactions();
+
+    let session = common::attach_probe(probe);
+
+    (session, commands, write_log, transactions)
+}
+
+/// Write a word, queue a command and write another word, and return the response of the
+/// command.
+fn write_around_command(
+    session: &mut Session,
+    write_log: &WriteLog,
+    transactions: &ProbeTransactions,
+) -> Vec<u8> {
+    let mut core = session.core(0).unwrap();
+    write_log.clear();
+    transactions.reset();
+
+    core.write_word_32(RAM, 0x1111_1111).unwrap();
+    let index = core
+        .enqueue_probe_command(VendorCommand::new([0x80, 0x01]))
+        .unwrap();
+    core.write_word_32(RAM + 4, 0x2222_2222).unwrap();
+
+    core.probe_command_response(index).unwrap()
+}
+
+#[test]
+fn batched_commands_keep_their_place_between_writes() {
+    let (mut session, commands, write_log, transactions) = attach(VendorCommandOrdering::Batched);
+    let response = write_around_command(&mut session, &write_log, &transactions);
+
+    assert_eq!(response, [0x80, 0x01]);
+    assert_eq!(commands.entries(), [(VendorCommand::new([0x80, 0x01]), 1)]);
+    assert_eq!(write_log.entries().len(), 2);
+
+    // The command is sent with the writes.
+    assert_eq!(transactions.count(), 1);
+}
+
+#[test]
+fn flushed_commands_send_the_queued_writes_first() {
+    let (mut session, commands, write_log, transactions) = attach(VendorCommandOrdering::Flushed);
+    let response = write_around_command(&mut session, &write_log, &transactions);
+
+    assert_eq!(response, [0x80, 0x01]);
+    assert_eq!(commands.entries(), [(VendorCommand::new([0x80, 0x01]), 1)]);
+    assert_eq!(write_log.entries().len(), 2);
+
+    // The first write, the command and the second write each take a round trip.
+    assert_eq!(transactions.count(), 3);
+}
+
+#[test]
+fn commands_are_refused_without_support_of_the_probe() {
+    let (mut session, commands, _, _) = attach(VendorCommandOrdering::Unsupported);
+    let mut core = session.core(0).unwrap();
+
+    assert!(matches!(
+        core.enqueue_probe_command(VendorCommand::new([0x80])),
+        Err(Error::Probe(DebugProbeError::CommandNotSupportedByProbe(_)))
+    ));
+    assert!(commands.entries().is_empty());
+}
+
+#[test]
+fn commands_require_the_destructive_level() {
+    let (mut session, commands, _, _) = attach(VendorCommandOrdering::Batched);
+    session.set_max_intrusiveness(Intrusiveness::ChangesState);
+    let mut core = session.core(0).unwrap();
+
+    match core.enqueue_probe_command(VendorCommand::new([0x80])) {
+        Err(Error::IntrusivenessExceeded { operation, .. }) => {
+            assert_eq!(operation, TargetOperation::VendorCommand)
+        }
+        other => panic!("Expected the command to be refused, got {:?}", other),
+    }
+    assert!(commands.entries().is_empty());
+}
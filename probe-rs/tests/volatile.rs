@@ -0,0 +1,71 @@
+mod common;
+
+use probe_rs::{
+    test_utils::WriteLog, Error, FakeProbe, Intrusiveness, MemoryInterface, Session,
+    TargetOperation, WriteCoalescer,
+};
+
+/// An address in the RAM of the mocked core.
+const RAM: u64 = 0x2000_0000;
+
+/// A mailbox in the RAM of the mocked core, which is shared with another bus master.
+const MAILBOX: u64 = 0x2000_0100;
+
+fn attach() -> (Session, WriteLog) {
+    let probe = FakeProbe::with_mocked_core();
+    let write_log = probe.write_log();
+
+    let session = common::attach_probe(probe);
+
+    (session, write_log)
+}
+
+#[test]
+fn volatile_writes_keep_their_order() {
+    let (mut session, write_log) = attach();
+    session.mark_volatile(MAILBOX..MAILBOX + 8);
+
+    let memory_map = session.target().memory_map.clone();
+    let volatile = session.volatile_ranges().clone();
+
+    let mut core = session.core(0).unwrap();
+    write_log.clear();
+
+    {
+        let mut coalescer = WriteCoalescer::with_volatile_ranges(&mut core, &memory_map, volatile);
+
+        coalescer.write_word_32(RAM + 4, 2).unwrap();
+        coalescer.write_word_32(RAM, 1).unwrap();
+        coalescer.write_word_32(MAILBOX + 4, 4).unwrap();
+        coalescer.write_word_32(MAILBOX, 3).unwrap();
+        coalescer.flush().unwrap();
+    }
+
+    assert_eq!(
+        write_log.entries(),
+        vec![
+            (RAM as u32, 1),
+            (RAM as u32 + 4, 2),
+            (MAILBOX as u32 + 4, 4),
+            (MAILBOX as u32, 3),
+        ]
+    );
+}
+
+#[test]
+fn volatile_reads_are_device_reads() {
+    let (mut session, _) = attach();
+    session.set_max_intrusiveness(Intrusiveness::None);
+    session.mark_volatile(MAILBOX..MAILBOX + 8);
+
+    let mut core = session.core(0).unwrap();
+
+    assert!(core.read_word_32(RAM).is_ok());
+
+    match core.read_word_32(MAILBOX + 4) {
+        Err(Error::IntrusivenessExceeded { operation, .. }) => {
+            assert_eq!(operation, TargetOperation::ReadDeviceMemory)
+        }
+        other => panic!("Expected the read to be refused, got {:?}", other),
+    }
+}
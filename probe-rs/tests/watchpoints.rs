@@ -0,0 +1,172 @@
+mod common;
+
+use std::time::Duration;
+
+use probe_rs::{
+    Core, Error, MemoryInterface, Session, WatchKind, WatchpointConfig, WatchpointKind,
+    WatchpointQualifier,
+};
+
+const DWT_CTRL: u64 = 0xE000_1000;
+
+fn comp(unit: u64) -> u64 {
+    DWT_CTRL + 0x20 + 0x10 * unit
+}
+
+fn mask(unit: u64) -> u64 {
+    DWT_CTRL + 0x24 + 0x10 * unit
+}
+
+fn function(unit: u64) -> u64 {
+    DWT_CTRL + 0x28 + 0x10 * unit
+}
+
+/// The halted core of `session`, whose DWT has 4 comparators.
+fn halted_core(session: &mut Session) -> Core<'_> {
+    let mut core = session.core(0).unwrap();
+    core.halt(Duration::from_millis(100)).unwrap();
+    core.write_word_32(DWT_CTRL, 4 << 28).unwrap();
+
+    core
+}
+
+#[test]
+fn watchpoints_program_the_dwt() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    assert_eq!(core.available_watchpoint_units().unwrap(), 4);
+
+    core.set_watchpoint(WatchpointConfig::new(
+        0x2000_0100,
+        4,
+        WatchpointKind::ReadWrite,
+    ))
+    .unwrap();
+
+    assert_eq!(core.read_word_32(comp(0)).unwrap(), 0x2000_0100);
+    assert_eq!(core.read_word_32(mask(0)).unwrap(), 2);
+    assert_eq!(core.read_word_32(function(0)).unwrap(), 0b0111);
+
+    // A value takes an address comparator, and a data value comparator which links to it.
+    core.set_watchpoint(
+        WatchpointConfig::new(0x2000_0200, 2, WatchpointKind::Write).value(0xbeef, 0xffff),
+    )
+    .unwrap();
+
+    let watchpoint = &core.watchpoints()[1];
+    assert_eq!(watchpoint.units, [2, 1]);
+    assert_eq!(core.read_word_32(comp(2)).unwrap(), 0x2000_0200);
+    assert_eq!(core.read_word_32(mask(2)).unwrap(), 1);
+    assert_eq!(core.read_word_32(function(2)).unwrap(), 0);
+    assert_eq!(core.read_word_32(comp(1)).unwrap(), 0xbeef_beef);
+    assert_eq!(core.read_word_32(function(1)).unwrap(), 0x0002_2506);
+
+    core.clear_watchpoint(0x2000_0200).unwrap();
+    assert_eq!(core.read_word_32(function(1)).unwrap(), 0);
+    assert_eq!(core.watchpoints().len(), 1);
+
+    assert!(matches!(
+        core.clear_watchpoint(0x2000_0200),
+        Err(Error::WatchpointNotFound(0x2000_0200))
+    ));
+}
+
+#[test]
+fn unsupported_qualifiers_are_rejected() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    let error = core
+        .set_watchpoint(WatchpointConfig::new(0x2000_0100, 4, WatchpointKind::Read).access_size(2))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::UnsupportedWatchpointQualifier {
+            qualifier: WatchpointQualifier::AccessSize,
+            ..
+        }
+    ));
+
+    let error = core
+        .set_watchpoint(WatchpointConfig::new(0x2000_0100, 4, WatchpointKind::Read).value(1, 0xff))
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        Error::UnsupportedWatchpointQualifier {
+            qualifier: WatchpointQualifier::ValueMask,
+            ..
+        }
+    ));
+
+    // Nothing was set, and no comparator was changed.
+    assert!(core.watchpoints().is_empty());
+    assert_eq!(core.read_word_32(function(0)).unwrap(), 0);
+}
+
+#[test]
+fn triggered_watchpoints_are_the_ones_whose_comparators_matched() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.set_watchpoint(WatchpointConfig::new(0x2000_0100, 4, WatchpointKind::Write))
+        .unwrap();
+    core.set_watchpoint(WatchpointConfig::new(0x2000_0200, 8, WatchpointKind::Read))
+        .unwrap();
+
+    // The DWT sets MATCHED in the function register of the comparator which matched.
+    let matched = core.read_word_32(function(1)).unwrap() | 1 << 24;
+    core.write_word_32(function(1), matched).unwrap();
+
+    let triggered = core.triggered_watchpoints().unwrap();
+    assert_eq!(triggered.len(), 1);
+    assert_eq!(triggered[0].config.address, 0x2000_0200);
+    assert_eq!(triggered[0].units, [1]);
+
+    core.clear_all_watchpoints().unwrap();
+    assert!(core.watchpoints().is_empty());
+    assert!(core.triggered_watchpoints().unwrap().is_empty());
+}
+
+#[test]
+fn hw_watchpoint_aliases_use_the_watchpoint_api() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    core.set_hw_watchpoint(0x4000_0010, 4, WatchKind::Write)
+        .unwrap();
+
+    assert_eq!(
+        core.hw_watchpoints()[0].config,
+        WatchpointConfig::new(0x4000_0010, 4, WatchpointKind::Write)
+    );
+    assert_eq!(core.read_word_32(comp(0)).unwrap(), 0x4000_0010);
+    assert_eq!(core.read_word_32(function(0)).unwrap(), 0b0110);
+
+    core.clear_hw_watchpoint(0x4000_0010).unwrap();
+    assert!(core.hw_watchpoints().is_empty());
+    assert_eq!(core.read_word_32(function(0)).unwrap(), 0);
+}
+
+#[test]
+fn watchpoints_report_when_no_comparator_is_free() {
+    let mut session = common::attach();
+    let mut core = halted_core(&mut session);
+
+    for register in 0..4 {
+        core.set_hw_watchpoint(0x4000_0000 + 4 * register, 4, WatchKind::Write)
+            .unwrap();
+    }
+
+    // A stray write to a peripheral register can't be caught without a free comparator.
+    assert!(matches!(
+        core.set_hw_watchpoint(0x4000_0010, 4, WatchKind::Write),
+        Err(Error::WatchpointsExhausted)
+    ));
+    assert_eq!(core.hw_watchpoints().len(), 4);
+
+    core.clear_hw_watchpoint(0x4000_0000).unwrap();
+    core.set_hw_watchpoint(0x4000_0010, 4, WatchKind::Write)
+        .unwrap();
+    assert_eq!(core.read_word_32(comp(0)).unwrap(), 0x4000_0010);
+}
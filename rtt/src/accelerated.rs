@@ -0,0 +1,1223 @@
+//! Accelerated RTT, which moves the data of an up channel into a large staging buffer on the target.
+//!
+//! In the classic mode, every poll of an up channel needs several memory accesses: the ring buffer
+//! pointers are read, then one or two blocks of data, and finally the read pointer is written
+//! back. Each access is a round trip to the probe, so at high log rates the host can't keep up
+//! and the channel overflows.
+//!
+//! In the accelerated mode, a small stub is installed in scratch RAM designated by the host. The
+//! stub copies the data of one up channel into a staging buffer, which is larger than the channel
+//! buffer and laid out so that the host can drain it with a single block read of the staging
+//! header and data, followed by a single write of the tail pointer.
+//!
+//! The stub runs as the DebugMonitor exception handler, at the lowest priority. A DWT watchpoint on
+//! the write pointer of the channel triggers it, so the stub only runs when the firmware writes to
+//! the channel, and the target never busy-waits for the host.
+//!
+//! ## Transactions per poll
+//!
+//! | Mode        | No data | Data available                     |
+//! |-------------|---------|------------------------------------|
+//! | Classic     | 1       | 3, or 4 if the ring buffer wrapped |
+//! | Accelerated | 1       | 2                                  |
+//!
+//! The accelerated read transfers the whole staging buffer, which makes it larger than the reads
+//! in the classic mode. As probes take about the same time for a block read as for a single word,
+//! this still halves the time per poll, and the larger buffer can absorb much longer bursts.
+//!
+//! ## Requirements
+//!
+//! The accelerated mode is only used if all of these are met, otherwise [`Rtt::attach_accelerated`]
+//! falls back to the classic mode:
+//!
+//! - The core is an ARMv7-M or ARMv8-M Mainline core, which has the DebugMonitor exception.
+//! - The core is running.
+//! - Scratch RAM, which is not used by the firmware, is set with [`AcceleratedOptions::scratch`],
+//!   and it is large enough for a copy of the vector table, the stub and the staging buffer.
+//! - The DWT has a free comparator.
+//! - The vector table is not in RAM. The stub is installed in a copy of the vector table, so
+//!   vectors which the firmware sets at runtime, e.g. with `NVIC_SetVector`, would be ignored.
+//!
+//! While the stub is installed, halting debug is disabled, because the DebugMonitor exception is
+//! only taken if it is. This is why [`AcceleratedRtt`] borrows the core until it is dropped.
+//! A `BKPT` instruction of the firmware can't halt the core in the meantime, so the stub skips it,
+//! and [`AcceleratedRtt::read`] logs a warning. The firmware must not write VTOR while the stub
+//! is installed, otherwise the stub no longer runs, and the channel fills up.
+
+use crate::{channel::Channel, Error, Rtt, ScanRegion};
+use probe_rs::{
+    architecture::arm::armv7m::{Demcr, Dhcsr},
+    config::MemoryRegion,
+    Core, CoreType, MemoryInterface, MemoryMappedRegister,
+};
+use scroll::{Pread, LE};
+use std::cmp::min;
+use std::fmt;
+use std::ops::Range;
+
+/// The stub which copies the channel data into the staging buffer, for ARMv7-M and ARMv8-M.
+///
+/// It only uses Thumb instructions, and is followed by its parameters, see [`StubParameters`].
+///
+/// Besides the watchpoint, a `BKPT` of the firmware and a halting step enter the DebugMonitor,
+/// because halting debug is disabled. Returning from a `BKPT` executes it again, so the stub
+/// skips it, by advancing the stacked return address, and counts it in the staging header.
+/// A halting step clears `DEMCR.MON_STEP`, so that the core isn't stuck in the stub either.
+///
+/// ```text
+/// 00: b4f0       push  {r4, r5, r6, r7}
+/// 02: 4820       ldr   r0, [pc, #128]     ; r0 = channel
+/// 04: 4920       ldr   r1, [pc, #128]     ; r1 = staging
+/// 06: 68c2       ldr   r2, [r0, #12]      ; r2 = channel write offset
+/// 08: 6903       ldr   r3, [r0, #16]      ; r3 = channel read offset
+/// 0a: 688c       ldr   r4, [r1, #8]       ; r4 = staging head
+/// 0c: 68cd       ldr   r5, [r1, #12]      ; r5 = staging tail
+/// 0e: 3118       adds  r1, #24            ; r1 = staging data
+/// 10: 4293 loop: cmp   r3, r2             ; channel empty?
+/// 12: d010       beq   done
+/// 14: 1c66       adds  r6, r4, #1         ; r6 = next head
+/// 16: 4f1d       ldr   r7, [pc, #116]     ; r7 = staging size
+/// 18: 42be       cmp   r6, r7
+/// 1a: d100       bne   1f
+/// 1c: 2600       movs  r6, #0
+/// 1e: 42ae 1:    cmp   r6, r5             ; staging full?
+/// 20: d009       beq   done
+/// 22: 6847       ldr   r7, [r0, #4]       ; r7 = channel buffer
+/// 24: 5cff       ldrb  r7, [r7, r3]
+/// 26: 550f       strb  r7, [r1, r4]
+/// 28: 0034       movs  r4, r6
+/// 2a: 1c5b       adds  r3, r3, #1
+/// 2c: 6887       ldr   r7, [r0, #8]       ; r7 = channel size
+/// 2e: 42bb       cmp   r3, r7
+/// 30: d1ee       bne   loop
+/// 32: 2300       movs  r3, #0
+/// 34: e7ec       b     loop
+/// 36: f3bf 8f5f done: dmb sy              ; data before pointers
+/// 3a: 6103       str   r3, [r0, #16]      ; release the channel data
+/// 3c: 3918       subs  r1, #24            ; r1 = staging
+/// 3e: 608c       str   r4, [r1, #8]       ; publish the staging data
+/// 40: 4e13       ldr   r6, [pc, #76]      ; r6 = DFSR
+/// 42: 6837       ldr   r7, [r6]           ; r7 = causes of the entry
+/// 44: 2202       movs  r2, #2             ; DFSR.BKPT
+/// 46: 4217       tst   r7, r2
+/// 48: d00d       beq   halted
+/// 4a: 4672       mov   r2, lr             ; EXC_RETURN
+/// 4c: 0752       lsls  r2, r2, #29        ; frame on the process stack?
+/// 4e: d401       bmi   1f
+/// 50: aa04       add   r2, sp, #16        ; r2 = frame, above the pushed registers
+/// 52: e001       b     2f
+/// 54: f3ef 8209 1: mrs r2, psp            ; r2 = frame
+/// 58: 6993 2:    ldr   r3, [r2, #24]      ; r3 = stacked PC, the BKPT
+/// 5a: 614b       str   r3, [r1, #20]      ; staging last breakpoint
+/// 5c: 3302       adds  r3, #2
+/// 5e: 6193       str   r3, [r2, #24]      ; return after the BKPT
+/// 60: 690b       ldr   r3, [r1, #16]
+/// 62: 3301       adds  r3, #1
+/// 64: 610b       str   r3, [r1, #16]      ; count the breakpoint
+/// 66: 2201 halted: movs r2, #1            ; DFSR.HALTED
+/// 68: 4217       tst   r7, r2
+/// 6a: d005       beq   clear
+/// 6c: 4a09       ldr   r2, [pc, #36]      ; r2 = DEMCR
+/// 6e: 6813       ldr   r3, [r2]
+/// 70: 2501       movs  r5, #1
+/// 72: 04ad       lsls  r5, r5, #18        ; DEMCR.MON_STEP
+/// 74: 43ab       bics  r3, r5
+/// 76: 6013       str   r3, [r2]           ; stop stepping
+/// 78: 2207 clear: movs r2, #7
+/// 7a: 4017       ands  r7, r2
+/// 7c: 6037       str   r7, [r6]           ; clear DWTTRAP, BKPT and HALTED
+/// 7e: bcf0       pop   {r4, r5, r6, r7}
+/// 80: 4770       bx    lr
+/// 82: bf00       nop
+/// ```
+const ARM_STUB: [u8; 132] = [
+    0xf0, 0xb4, 0x20, 0x48, 0x20, 0x49, 0xc2, 0x68, //
+    0x03, 0x69, 0x8c, 0x68, 0xcd, 0x68, 0x18, 0x31, //
+    0x93, 0x42, 0x10, 0xd0, 0x66, 0x1c, 0x1d, 0x4f, //
+    0xbe, 0x42, 0x00, 0xd1, 0x00, 0x26, 0xae, 0x42, //
+    0x09, 0xd0, 0x47, 0x68, 0xff, 0x5c, 0x0f, 0x55, //
+    0x34, 0x00, 0x5b, 0x1c, 0x87, 0x68, 0xbb, 0x42, //
+    0xee, 0xd1, 0x00, 0x23, 0xec, 0xe7, 0xbf, 0xf3, //
+    0x5f, 0x8f, 0x03, 0x61, 0x18, 0x39, 0x8c, 0x60, //
+    0x13, 0x4e, 0x37, 0x68, 0x02, 0x22, 0x17, 0x42, //
+    0x0d, 0xd0, 0x72, 0x46, 0x52, 0x07, 0x01, 0xd4, //
+    0x04, 0xaa, 0x01, 0xe0, 0xef, 0xf3, 0x09, 0x82, //
+    0x93, 0x69, 0x4b, 0x61, 0x02, 0x33, 0x93, 0x61, //
+    0x0b, 0x69, 0x01, 0x33, 0x0b, 0x61, 0x01, 0x22, //
+    0x17, 0x42, 0x05, 0xd0, 0x09, 0x4a, 0x13, 0x68, //
+    0x01, 0x25, 0xad, 0x04, 0xab, 0x43, 0x13, 0x60, //
+    0x07, 0x22, 0x17, 0x40, 0x37, 0x60, 0xf0, 0xbc, //
+    0x70, 0x47, 0x00, 0xbf,
+];
+
+/// The parameters which follow the stub code, in this order.
+struct StubParameters {
+    /// The address of the RTT channel structure.
+    channel: u32,
+    /// The address of the staging header.
+    staging: u32,
+    /// The size of the staging data.
+    size: u32,
+}
+
+impl StubParameters {
+    /// The size of the stub, including its parameters.
+    const STUB_SIZE: u32 = ARM_STUB.len() as u32 + 20;
+
+    fn words(&self) -> [u32; 5] {
+        [
+            self.channel,
+            self.staging,
+            self.size,
+            DFSR,
+            Demcr::ADDRESS as u32,
+        ]
+    }
+}
+
+// The staging buffer follows this layout in target memory:
+//
+// struct Staging {
+//     unsigned int magic; // STAGING_MAGIC, to detect a corrupted buffer.
+//     unsigned int size; // Size of the data buffer. The capacity is one byte less.
+//     unsigned int head; // Offset of the next byte written by the stub.
+//     unsigned int tail; // Offset of the next byte read by the host.
+//     unsigned int breakpoints; // Number of BKPT instructions the stub skipped.
+//     unsigned int breakpoint; // Address of the last BKPT instruction the stub skipped.
+//     char data[size];
+// }
+const STAGING_MAGIC: u32 = u32::from_le_bytes(*b"RTTS");
+const STAGING_HEADER_SIZE: u32 = 24;
+const O_STAGING_TAIL: u32 = 12;
+
+/// The smallest staging buffer which is useful.
+const MIN_STAGING_SIZE: u32 = 64;
+
+/// Debug Fault Status Register, whose DWTTRAP, BKPT and HALTED bits are handled by the stub.
+const DFSR: u32 = 0xE000_ED30;
+/// Vector Table Offset Register.
+const VTOR: u64 = 0xE000_ED08;
+/// System Handler Priority Register 3, whose lowest byte is the DebugMonitor priority.
+const SHPR3: u64 = 0xE000_ED20;
+/// Interrupt Controller Type Register, which determines the size of the vector table.
+const ICTR: u64 = 0xE000_E004;
+/// The base address of the DWT.
+const DWT_BASE: u64 = 0xE000_1000;
+/// The DebugMonitor entry of the vector table.
+const DEBUG_MONITOR_VECTOR: usize = 12;
+
+fn comp_address(unit: usize) -> u64 {
+    DWT_BASE + 0x20 + 0x10 * unit as u64
+}
+
+fn mask_address(unit: usize) -> u64 {
+    DWT_BASE + 0x24 + 0x10 * unit as u64
+}
+
+fn function_address(unit: usize) -> u64 {
+    DWT_BASE + 0x28 + 0x10 * unit as u64
+}
+
+/// Returns the DWT_FUNCTION value for a debug event on word writes, for cores with a DebugMonitor.
+fn watchpoint_function(core_type: CoreType) -> Option<u32> {
+    match core_type {
+        // MATCH = data address write, ACTION = debug event, DATAVSIZE = word.
+        CoreType::Armv8m => Some(0b10 << 10 | 0b01 << 4 | 0b0101),
+        // Watchpoint on write access.
+        CoreType::Armv7m | CoreType::Armv7em => Some(0b0110),
+        _ => None,
+    }
+}
+
+/// Options for [`Rtt::attach_accelerated`].
+#[non_exhaustive]
+#[derive(Debug, Clone)]
+pub struct AcceleratedOptions {
+    scan_region: ScanRegion,
+    scratch: Option<Range<u32>>,
+    staging_size: u32,
+    channel: usize,
+}
+
+impl AcceleratedOptions {
+    /// Constructs a new options object with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan `region` for the control block, instead of all RAM.
+    #[must_use]
+    pub fn scan_region(self, region: ScanRegion) -> Self {
+        Self {
+            scan_region: region,
+            ..self
+        }
+    }
+
+    /// Use `range` for the stub and the staging buffer.
+    ///
+    /// The firmware must not use this RAM. Its contents are restored when the stub is removed.
+    /// Without scratch RAM, the classic mode is used.
+    #[must_use]
+    pub fn scratch(self, range: Range<u32>) -> Self {
+        Self {
+            scratch: Some(range),
+            ..self
+        }
+    }
+
+    /// Set the size of the staging buffer in bytes. The default is 4096 bytes.
+    #[must_use]
+    pub fn staging_size(self, size: u32) -> Self {
+        Self {
+            staging_size: size,
+            ..self
+        }
+    }
+
+    /// Accelerate the up channel `number`, instead of channel 0.
+    #[must_use]
+    pub fn channel(self, number: usize) -> Self {
+        Self {
+            channel: number,
+            ..self
+        }
+    }
+}
+
+impl Default for AcceleratedOptions {
+    fn default() -> Self {
+        Self {
+            scan_region: ScanRegion::default(),
+            scratch: None,
+            staging_size: 4096,
+            channel: 0,
+        }
+    }
+}
+
+/// The layout of the scratch RAM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ScratchLayout {
+    vector_table: u32,
+    stub: u32,
+    staging: u32,
+    staging_size: u32,
+    end: u32,
+}
+
+impl ScratchLayout {
+    /// Place a vector table of `vector_table_size` bytes, the stub and the staging buffer
+    /// in `scratch`. Returns `None` if they don't fit.
+    fn new(scratch: &Range<u32>, vector_table_size: u32, staging_size: u32) -> Option<Self> {
+        // VTOR requires the table to be aligned to its size, rounded up to a power of two.
+        let alignment = vector_table_size.next_power_of_two().max(128);
+        let vector_table = scratch.start.checked_add(alignment - 1)? & !(alignment - 1);
+        let stub = vector_table.checked_add(vector_table_size)?;
+        let staging = stub.checked_add(StubParameters::STUB_SIZE)?;
+        let data = staging.checked_add(STAGING_HEADER_SIZE)?;
+
+        let available = scratch.end.checked_sub(data)? & !3;
+        let staging_size = min(staging_size & !3, available);
+        if staging_size < MIN_STAGING_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            vector_table,
+            stub,
+            staging,
+            staging_size,
+            end: data + staging_size,
+        })
+    }
+
+    /// The part of the scratch RAM which is overwritten.
+    fn used(&self) -> Range<u32> {
+        self.vector_table..self.end
+    }
+}
+
+/// The target state which is modified by the stub installation.
+#[derive(Debug)]
+struct InstalledStub {
+    layout: ScratchLayout,
+    /// The address of the accelerated RTT channel structure.
+    channel: u32,
+    /// The DWT comparator which triggers the stub, and its DWT_FUNCTION value.
+    unit: usize,
+    function: u32,
+    vector_table_size: u32,
+    /// The previous contents of the used scratch RAM.
+    scratch: Vec<u8>,
+    vtor: u32,
+    shpr3: u32,
+    demcr: u32,
+    dwt: [u32; 3],
+    /// The DEMCR value while the stub is installed.
+    active_demcr: u32,
+    /// The number of skipped `BKPT` instructions which were reported, see [`ARM_STUB`].
+    breakpoints: u32,
+}
+
+impl InstalledStub {
+    /// Save the state which is modified by [`InstalledStub::install`].
+    fn save(
+        core: &mut Core,
+        layout: ScratchLayout,
+        channel: u32,
+        unit: usize,
+        function: u32,
+        vector_table_size: u32,
+    ) -> Result<Self, Error> {
+        let used = layout.used();
+        let mut scratch = vec![0; used.len()];
+        core.read(used.start.into(), &mut scratch)?;
+
+        let demcr = core.read_word_32(Demcr::ADDRESS)?;
+
+        Ok(Self {
+            layout,
+            channel,
+            unit,
+            function,
+            vector_table_size,
+            scratch,
+            vtor: core.read_word_32(VTOR)?,
+            shpr3: core.read_word_32(SHPR3)?,
+            demcr,
+            dwt: [
+                core.read_word_32(comp_address(unit))?,
+                core.read_word_32(mask_address(unit))?,
+                core.read_word_32(function_address(unit))?,
+            ],
+            active_demcr: demcr,
+            breakpoints: 0,
+        })
+    }
+
+    /// Install the stub. Returns `false`, without modifying the target, if the core has no
+    /// DebugMonitor exception.
+    fn install(&mut self, core: &mut Core) -> Result<bool, Error> {
+        let mut demcr = Demcr::from(self.demcr);
+        demcr.set_trcena(true);
+        demcr.set_mon_en(true);
+        core.write_word_32(Demcr::ADDRESS, demcr.into())?;
+
+        // ARMv8-M Baseline cores have no DebugMonitor, and ignore MON_EN. Halting debug is still
+        // enabled, so MON_EN has no effect until the stub is installed.
+        if !Demcr::from(core.read_word_32(Demcr::ADDRESS)?).mon_en() {
+            core.write_word_32(Demcr::ADDRESS, self.demcr)?;
+            return Ok(false);
+        }
+        self.active_demcr = demcr.into();
+
+        let layout = &self.layout;
+
+        let header = [STAGING_MAGIC, layout.staging_size, 0, 0, 0, 0];
+        core.write_32(layout.staging.into(), &header)?;
+
+        let parameters = StubParameters {
+            channel: self.channel,
+            staging: layout.staging,
+            size: layout.staging_size,
+        };
+        core.write_8(layout.stub.into(), &ARM_STUB)?;
+        core.write_32(
+            (layout.stub + ARM_STUB.len() as u32).into(),
+            &parameters.words(),
+        )?;
+
+        // Copy the vector table, with the stub as DebugMonitor handler.
+        let mut vectors = vec![0; self.vector_table_size as usize / 4];
+        core.read_32(self.vtor.into(), &mut vectors)?;
+        vectors[DEBUG_MONITOR_VECTOR] = layout.stub | 1;
+        core.write_32(layout.vector_table.into(), &vectors)?;
+
+        // Run the stub at the lowest priority, so that it never delays the firmware.
+        core.write_word_32(SHPR3, self.shpr3 | 0xff)?;
+        core.write_word_32(VTOR, layout.vector_table)?;
+
+        core.write_word_32(function_address(self.unit), 0)?;
+        core.write_word_32(
+            comp_address(self.unit),
+            self.channel + Channel::O_WRITE as u32,
+        )?;
+        core.write_word_32(mask_address(self.unit), 0)?;
+        core.write_word_32(function_address(self.unit), self.function)?;
+
+        // Debug events are only turned into DebugMonitor exceptions without halting debug.
+        let mut dhcsr = Dhcsr::from(0);
+        dhcsr.enable_write();
+        core.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+        // Move the data which is already in the channel.
+        self.pend(core)?;
+
+        Ok(true)
+    }
+
+    /// Run the stub once, e.g. to continue after the staging buffer was full.
+    fn pend(&self, core: &mut Core) -> Result<(), Error> {
+        let mut demcr = Demcr::from(self.active_demcr);
+        demcr.set_mon_pend(true);
+        core.write_word_32(Demcr::ADDRESS, demcr.into())?;
+
+        Ok(())
+    }
+
+    /// Disable the watchpoint which triggers the stub, and restore its DWT comparator.
+    fn disarm(&self, core: &mut Core) -> Result<(), Error> {
+        let [comp, mask, function] = self.dwt;
+        core.write_word_32(function_address(self.unit), 0)?;
+        core.write_word_32(comp_address(self.unit), comp)?;
+        core.write_word_32(mask_address(self.unit), mask)?;
+        core.write_word_32(function_address(self.unit), function)?;
+
+        Ok(())
+    }
+
+    /// Remove the stub, and restore the state saved by [`InstalledStub::save`].
+    fn remove(&self, core: &mut Core) -> Result<(), Error> {
+        // The watchpoint has to be disabled before halting debug is enabled again,
+        // otherwise the next write to the channel halts the core.
+        self.disarm(core)?;
+
+        core.write_word_32(Demcr::ADDRESS, self.demcr)?;
+        core.write_word_32(VTOR, self.vtor)?;
+
+        // Only the DebugMonitor priority is restored, the firmware may have changed the
+        // priorities of PendSV and SysTick in the same register since.
+        let shpr3 = core.read_word_32(SHPR3)?;
+        core.write_word_32(SHPR3, shpr3 & !0xff | self.shpr3 & 0xff)?;
+
+        let mut dhcsr = Dhcsr::from(0);
+        dhcsr.enable_write();
+        dhcsr.set_c_debugen(true);
+        core.write_word_32(Dhcsr::ADDRESS, dhcsr.into())?;
+
+        core.write_8(self.layout.vector_table.into(), &self.scratch)?;
+
+        Ok(())
+    }
+}
+
+/// The result of [`drain_staging`].
+#[derive(Debug, PartialEq, Eq)]
+struct Drained {
+    /// The number of bytes read.
+    count: usize,
+    /// The staging buffer was full, so the stub stopped copying data.
+    full: bool,
+    /// The number of `BKPT` instructions the stub skipped so far.
+    breakpoints: u32,
+    /// The address of the last `BKPT` instruction the stub skipped.
+    last_breakpoint: u32,
+}
+
+/// Read up to `buf.len()` bytes from the staging buffer at `staging`, with one block read of the
+/// whole buffer and one write of the tail pointer.
+fn drain_staging<M: MemoryInterface>(
+    memory: &mut M,
+    staging: u32,
+    size: u32,
+    buf: &mut [u8],
+) -> Result<Drained, Error> {
+    let mut mem = vec![0; (STAGING_HEADER_SIZE + size) as usize];
+    memory.read(staging.into(), &mut mem)?;
+
+    let word = |offset: usize| mem.pread_with::<u32>(offset, LE).unwrap();
+    let (magic, head, tail) = (word(0), word(8), word(12));
+
+    if magic != STAGING_MAGIC || word(4) != size || head >= size || tail >= size {
+        return Err(Error::ControlBlockCorrupted(format!(
+            "Staging buffer at {:08x} is corrupted: magic={:08x} head={} tail={}",
+            staging, magic, head, tail
+        )));
+    }
+
+    let full = (head + 1) % size == tail;
+    let data = &mem[STAGING_HEADER_SIZE as usize..];
+
+    let mut read = tail;
+    let mut total = 0;
+
+    // Read while the staging buffer contains data and `buf` has space (at most twice).
+    while total < buf.len() && read != head {
+        let end = if read > head { size } else { head };
+        let count = min((end - read) as usize, buf.len() - total);
+
+        buf[total..total + count].copy_from_slice(&data[read as usize..read as usize + count]);
+
+        total += count;
+        read = (read + count as u32) % size;
+    }
+
+    if total > 0 {
+        memory.write_word_32((staging + O_STAGING_TAIL).into(), read)?;
+    }
+
+    Ok(Drained {
+        count: total,
+        full,
+        breakpoints: word(16),
+        last_breakpoint: word(20),
+    })
+}
+
+/// RTT with an up channel which is drained through a staging buffer, see the [module
+/// documentation](self).
+///
+/// If the accelerated mode is not available, all channels are accessed in the classic mode.
+/// When this is dropped, the stub is removed, and the modified target state is restored.
+pub struct AcceleratedRtt<'core, 'probe> {
+    core: &'core mut Core<'probe>,
+    rtt: Rtt,
+    channel: usize,
+    stub: Option<InstalledStub>,
+}
+
+impl Rtt {
+    /// Attaches to RTT like [`Rtt::attach_region`], and installs a stub which accelerates
+    /// reading one up channel.
+    ///
+    /// If the accelerated mode is not available on the target, the classic mode is used.
+    pub fn attach_accelerated<'core, 'probe>(
+        core: &'core mut Core<'probe>,
+        memory_map: &[MemoryRegion],
+        options: AcceleratedOptions,
+    ) -> Result<AcceleratedRtt<'core, 'probe>, Error> {
+        let mut rtt = Rtt::attach_region(core, memory_map, &options.scan_region)?;
+
+        let stub = match prepare_stub(core, memory_map, &mut rtt, &options)? {
+            Some(mut stub) => match stub.install(core) {
+                Ok(true) => {
+                    log::debug!(
+                        "Installed RTT stub at {:#010x}, staging {} bytes at {:#010x}",
+                        stub.layout.stub,
+                        stub.layout.staging_size,
+                        stub.layout.staging
+                    );
+
+                    Some(stub)
+                }
+                Ok(false) => {
+                    log::info!("Using classic RTT, because the core has no DebugMonitor exception");
+
+                    None
+                }
+                Err(error) => {
+                    if let Err(remove_error) = stub.remove(core) {
+                        log::warn!("Failed to remove the RTT stub: {}", remove_error);
+                    }
+
+                    return Err(error);
+                }
+            },
+            None => None,
+        };
+
+        Ok(AcceleratedRtt {
+            core,
+            rtt,
+            channel: options.channel,
+            stub,
+        })
+    }
+}
+
+/// Check whether the stub can be installed, and save the state it modifies.
+///
+/// Returns `None` if the stub can't be installed.
+fn prepare_stub(
+    core: &mut Core,
+    memory_map: &[MemoryRegion],
+    rtt: &mut Rtt,
+    options: &AcceleratedOptions,
+) -> Result<Option<InstalledStub>, Error> {
+    let classic = |reason| {
+        log::info!("Using classic RTT, because {}", reason);
+        Ok(None)
+    };
+
+    let scratch = match &options.scratch {
+        Some(scratch) => scratch,
+        None => return classic("no scratch RAM is available"),
+    };
+
+    let function = match watchpoint_function(core.core_type()) {
+        Some(function) => function,
+        None => return classic("the core has no DebugMonitor exception"),
+    };
+
+    let channel = match rtt.up_channels().get(options.channel) {
+        Some(channel) => channel.0.ptr(),
+        None => return classic("the up channel does not exist"),
+    };
+
+    if core.core_halted()? {
+        return classic("the core is halted");
+    }
+
+    // The stub runs from a copy of the vector table, so vectors which the firmware changes
+    // later, e.g. with `NVIC_SetVector`, would be ignored.
+    let vtor = u64::from(core.read_word_32(VTOR)?);
+    if memory_map
+        .iter()
+        .any(|region| matches!(region, MemoryRegion::Ram(ram) if ram.range.contains(&vtor)))
+    {
+        return classic("the vector table is in RAM, where the firmware may change it");
+    }
+
+    let interrupt_lines = (core.read_word_32(ICTR)? & 0xf) + 1;
+    let vector_table_size = 4 * (16 + 32 * interrupt_lines);
+
+    let layout = match ScratchLayout::new(scratch, vector_table_size, options.staging_size) {
+        Some(layout) => layout,
+        None => return classic("the scratch RAM is too small"),
+    };
+
+    let comparators = (core.read_word_32(DWT_BASE)? >> 28) as usize;
+    let mut unit = None;
+    for candidate in 0..comparators {
+        if core.read_word_32(function_address(candidate))? & 0xf == 0 {
+            unit = Some(candidate);
+            break;
+        }
+    }
+
+    let unit = match unit {
+        Some(unit) => unit,
+        None => return classic("no DWT comparator is free"),
+    };
+
+    let stub = InstalledStub::save(core, layout, channel, unit, function, vector_table_size)?;
+
+    Ok(Some(stub))
+}
+
+impl<'core, 'probe> AcceleratedRtt<'core, 'probe> {
+    /// Returns `true` if the stub is installed, and `false` if the classic mode is used.
+    pub fn is_accelerated(&self) -> bool {
+        self.stub.is_some()
+    }
+
+    /// Returns the number of the accelerated up channel.
+    pub fn accelerated_channel(&self) -> usize {
+        self.channel
+    }
+
+    /// Gets the detected channels.
+    ///
+    /// Channels which are taken from the returned [`Rtt`] can no longer be accessed with
+    /// [`AcceleratedRtt::read`] and [`AcceleratedRtt::write`]. The accelerated channel must
+    /// only be read with [`AcceleratedRtt::read`], because its data is moved by the stub.
+    pub fn rtt(&mut self) -> &mut Rtt {
+        &mut self.rtt
+    }
+
+    /// Reads some bytes from the up channel `number` into `buf`, and returns how many bytes
+    /// were read.
+    ///
+    /// Like [`UpChannel::read`](crate::UpChannel::read), this doesn't wait for data.
+    pub fn read(&mut self, number: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let accelerated = number == self.channel;
+
+        if let Some(stub) = self.stub.as_mut().filter(|_| accelerated) {
+            let drained = drain_staging(
+                self.core,
+                stub.layout.staging,
+                stub.layout.staging_size,
+                buf,
+            )?;
+
+            if drained.breakpoints != stub.breakpoints {
+                log::warn!(
+                    "The firmware executed {} BKPT instructions, the last at {:#010x}, which were \
+                     skipped because halting debug is disabled while the RTT stub is installed",
+                    drained.breakpoints.wrapping_sub(stub.breakpoints),
+                    drained.last_breakpoint
+                );
+                stub.breakpoints = drained.breakpoints;
+            }
+
+            // The stub stops when the staging buffer is full, and only runs again when the
+            // firmware writes to the channel, which it might not do before it has space.
+            if drained.full && drained.count > 0 {
+                stub.pend(self.core)?;
+            }
+
+            return Ok(drained.count);
+        }
+
+        match self.rtt.up_channels().get(number) {
+            Some(channel) => channel.read(self.core, buf),
+            None => Err(Error::ChannelNotFound(number)),
+        }
+    }
+
+    /// Writes some bytes from `buf` into the down channel `number`, and returns how many bytes
+    /// were written.
+    ///
+    /// Like [`DownChannel::write`](crate::DownChannel::write), this doesn't wait for space.
+    pub fn write(&mut self, number: usize, buf: &[u8]) -> Result<usize, Error> {
+        match self.rtt.down_channels().get(number) {
+            Some(channel) => channel.write(self.core, buf),
+            None => Err(Error::ChannelNotFound(number)),
+        }
+    }
+
+    /// Removes the stub, and restores the state of the target.
+    ///
+    /// Returns the data which the stub moved out of the channel, but which was not read yet.
+    /// Dropping an [`AcceleratedRtt`] also removes the stub, but discards this data and
+    /// only logs errors.
+    pub fn detach(mut self) -> Result<Vec<u8>, Error> {
+        let stub = match self.stub.take() {
+            Some(stub) => stub,
+            None => return Ok(Vec::new()),
+        };
+
+        // Without the watchpoint, the stub stops moving data out of the channel, so nothing
+        // is moved into the staging buffer after it was drained. The rest of the data stays
+        // in the channel.
+        let mut data = vec![0; stub.layout.staging_size as usize];
+        let result = stub.disarm(self.core).and_then(|()| {
+            drain_staging(
+                self.core,
+                stub.layout.staging,
+                stub.layout.staging_size,
+                &mut data,
+            )
+        });
+
+        stub.remove(self.core)?;
+
+        let drained = result?;
+        data.truncate(drained.count);
+
+        Ok(data)
+    }
+}
+
+impl fmt::Debug for AcceleratedRtt<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AcceleratedRtt")
+            .field("rtt", &self.rtt)
+            .field("channel", &self.channel)
+            .field("stub", &self.stub)
+            .finish()
+    }
+}
+
+impl Drop for AcceleratedRtt<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(stub) = self.stub.take() {
+            if let Err(error) = stub.remove(self.core) {
+                log::warn!("Failed to remove the RTT stub: {}", error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use probe_rs::{FakeProbe, Permissions, Probe, RegisterId, Session};
+    use std::time::Duration;
+
+    /// Target memory, which counts the accesses by the host.
+    struct SimulatedMemory {
+        base: u32,
+        memory: Vec<u8>,
+        transactions: usize,
+    }
+
+    impl SimulatedMemory {
+        fn new(base: u32, size: usize) -> Self {
+            Self {
+                base,
+                memory: vec![0; size],
+                transactions: 0,
+            }
+        }
+
+        fn range(&mut self, address: u64, len: usize) -> &mut [u8] {
+            let start = address as usize - self.base as usize;
+            &mut self.memory[start..start + len]
+        }
+
+        fn word(&mut self, address: u32) -> u32 {
+            self.range(address.into(), 4).pread_with(0, LE).unwrap()
+        }
+
+        fn set_word(&mut self, address: u32, value: u32) {
+            self.range(address.into(), 4)
+                .copy_from_slice(&value.to_le_bytes());
+        }
+
+        /// Runs the stub on the target: a model of [`ARM_STUB`].
+        fn run_stub(&mut self, parameters: &StubParameters) {
+            let channel = parameters.channel;
+            let (write, mut read) = (self.word(channel + 12), self.word(channel + 16));
+            let (buffer, channel_size) = (self.word(channel + 4), self.word(channel + 8));
+
+            let staging = parameters.staging;
+            let (mut head, tail) = (self.word(staging + 8), self.word(staging + 12));
+
+            while read != write {
+                let next = (head + 1) % parameters.size;
+                if next == tail {
+                    break;
+                }
+
+                let byte = self.range((buffer + read).into(), 1)[0];
+                self.range((staging + STAGING_HEADER_SIZE + head).into(), 1)[0] = byte;
+
+                head = next;
+                read = (read + 1) % channel_size;
+            }
+
+            self.set_word(channel + 16, read);
+            self.set_word(staging + 8, head);
+        }
+    }
+
+    impl MemoryInterface for SimulatedMemory {
+        fn supports_native_64bit_access(&mut self) -> bool {
+            false
+        }
+
+        fn read_word_64(&mut self, _address: u64) -> Result<u64, probe_rs::Error> {
+            unimplemented!()
+        }
+
+        fn read_word_32(&mut self, address: u64) -> Result<u32, probe_rs::Error> {
+            let mut data = [0];
+            self.read_32(address, &mut data)?;
+            Ok(data[0])
+        }
+
+        fn read_word_8(&mut self, address: u64) -> Result<u8, probe_rs::Error> {
+            let mut data = [0];
+            self.read_8(address, &mut data)?;
+            Ok(data[0])
+        }
+
+        fn read_64(&mut self, _address: u64, _data: &mut [u64]) -> Result<(), probe_rs::Error> {
+            unimplemented!()
+        }
+
+        fn read_32(&mut self, address: u64, data: &mut [u32]) -> Result<(), probe_rs::Error> {
+            self.transactions += 1;
+            let range = self.range(address, data.len() * 4);
+            for (word, bytes) in data.iter_mut().zip(range.chunks_exact(4)) {
+                *word = bytes.pread_with(0, LE).unwrap();
+            }
+            Ok(())
+        }
+
+        fn read_8(&mut self, address: u64, data: &mut [u8]) -> Result<(), probe_rs::Error> {
+            self.transactions += 1;
+            data.copy_from_slice(self.range(address, data.len()));
+            Ok(())
+        }
+
+        fn write_word_64(&mut self, _address: u64, _data: u64) -> Result<(), probe_rs::Error> {
+            unimplemented!()
+        }
+
+        fn write_word_32(&mut self, address: u64, data: u32) -> Result<(), probe_rs::Error> {
+            self.write_32(address, &[data])
+        }
+
+        fn write_word_8(&mut self, address: u64, data: u8) -> Result<(), probe_rs::Error> {
+            self.write_8(address, &[data])
+        }
+
+        fn write_64(&mut self, _address: u64, _data: &[u64]) -> Result<(), probe_rs::Error> {
+            unimplemented!()
+        }
+
+        fn write_32(&mut self, address: u64, data: &[u32]) -> Result<(), probe_rs::Error> {
+            self.transactions += 1;
+            let range = self.range(address, data.len() * 4);
+            for (word, bytes) in data.iter().zip(range.chunks_exact_mut(4)) {
+                bytes.copy_from_slice(&word.to_le_bytes());
+            }
+            Ok(())
+        }
+
+        fn write_8(&mut self, address: u64, data: &[u8]) -> Result<(), probe_rs::Error> {
+            self.transactions += 1;
+            self.range(address, data.len()).copy_from_slice(data);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), probe_rs::Error> {
+            Ok(())
+        }
+    }
+
+    const CHANNEL: u32 = 0x2000_0000;
+    const CHANNEL_BUFFER: u32 = 0x2000_0100;
+    const CHANNEL_SIZE: u32 = 32;
+    const STAGING: u32 = 0x2000_0200;
+    const STAGING_SIZE: u32 = 128;
+
+    fn target() -> (SimulatedMemory, StubParameters) {
+        let mut memory = SimulatedMemory::new(0x2000_0000, 0x400);
+
+        memory.set_word(CHANNEL + 4, CHANNEL_BUFFER);
+        memory.set_word(CHANNEL + 8, CHANNEL_SIZE);
+
+        memory.set_word(STAGING, STAGING_MAGIC);
+        memory.set_word(STAGING + 4, STAGING_SIZE);
+
+        let parameters = StubParameters {
+            channel: CHANNEL,
+            staging: STAGING,
+            size: STAGING_SIZE,
+        };
+
+        (memory, parameters)
+    }
+
+    /// The firmware writes `data` to the channel, which triggers the stub.
+    fn firmware_write(memory: &mut SimulatedMemory, parameters: &StubParameters, data: &[u8]) {
+        let (mut write, read) = (memory.word(CHANNEL + 12), memory.word(CHANNEL + 16));
+
+        // Like the NoBlockTrim mode, only write what fits into the channel.
+        let free = (read + CHANNEL_SIZE - write - 1) % CHANNEL_SIZE;
+        for byte in &data[..min(data.len(), free as usize)] {
+            memory.range((CHANNEL_BUFFER + write).into(), 1)[0] = *byte;
+            write = (write + 1) % CHANNEL_SIZE;
+        }
+        memory.set_word(CHANNEL + 12, write);
+
+        memory.run_stub(parameters);
+    }
+
+    #[test]
+    fn drain_uses_one_read_and_one_write() {
+        let (mut memory, parameters) = target();
+
+        // More than fits into the channel buffer at once, so it wraps around.
+        let messages: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 20]).collect();
+        for message in &messages {
+            firmware_write(&mut memory, &parameters, message);
+        }
+
+        memory.transactions = 0;
+
+        let mut buf = [0; 256];
+        let Drained { count, full, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+
+        assert_eq!(&buf[..count], &messages.concat()[..]);
+        assert!(!full);
+        assert_eq!(memory.transactions, 2);
+
+        // Nothing left, so only the staging buffer is read.
+        let Drained { count, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert_eq!(count, 0);
+        assert_eq!(memory.transactions, 3);
+    }
+
+    #[test]
+    fn stub_stops_when_staging_is_full() {
+        let (mut memory, parameters) = target();
+
+        let data: Vec<u8> = (0..=255).collect();
+        for chunk in data.chunks(16) {
+            firmware_write(&mut memory, &parameters, chunk);
+        }
+
+        let mut buf = [0; 256];
+        let Drained { count, full, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert!(full);
+        assert_eq!(count, STAGING_SIZE as usize - 1);
+        assert_eq!(&buf[..count], &data[..count]);
+
+        // The data which didn't fit stays in the channel, until the stub runs again.
+        memory.run_stub(&parameters);
+        let Drained { count: rest, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert_eq!(&buf[..rest], &data[count..count + rest]);
+    }
+
+    #[test]
+    fn partial_reads_keep_the_remaining_data() {
+        let (mut memory, parameters) = target();
+        firmware_write(&mut memory, &parameters, b"Hello, world!");
+
+        let mut buf = [0; 5];
+        let Drained { count, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert_eq!(&buf[..count], b"Hello");
+
+        let mut buf = [0; 32];
+        let Drained { count, .. } =
+            drain_staging(&mut memory, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert_eq!(&buf[..count], b", world!");
+    }
+
+    #[test]
+    fn scratch_layout() {
+        // 16 system vectors and 32 interrupts need 192 bytes, aligned to 256 bytes.
+        let layout = ScratchLayout::new(&(0x2000_0010..0x2000_1000), 192, 1024).unwrap();
+
+        assert_eq!(layout.vector_table, 0x2000_0100);
+        assert_eq!(layout.stub, 0x2000_01c0);
+        assert_eq!(layout.staging, 0x2000_01c0 + StubParameters::STUB_SIZE);
+        assert_eq!(layout.staging_size, 1024);
+
+        // The staging buffer is shrunk to the available space.
+        let layout = ScratchLayout::new(&(0x2000_0000..0x2000_0200), 192, 1024).unwrap();
+        assert_eq!(layout.end, 0x2000_0200);
+
+        assert_eq!(
+            ScratchLayout::new(&(0x2000_0000..0x2000_0100), 192, 1024),
+            None
+        );
+    }
+
+    /// Where the stub is placed on the mocked core.
+    const STUB: u32 = 0x2000_0400;
+    /// The exception frame of the DebugMonitor entry.
+    const FRAME: u32 = 0x2000_0800;
+    /// The address the stub returns to, where the mocked core stops at an undefined instruction.
+    const RETURN: u32 = 0x2000_0900;
+    /// The address of a `BKPT` of the firmware, which entered the DebugMonitor.
+    const FIRMWARE_BKPT: u32 = 0x0800_0400;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Attach to a mocked core, which executes the code at PC when it is resumed.
+    fn attach() -> Session {
+        let mut probe = FakeProbe::with_mocked_core();
+        probe.execute_code();
+
+        Probe::from_specific_probe(Box::new(probe))
+            .attach("stm32wb55ccux", Permissions::default())
+            .expect("Failed to attach with 'fake' probe.")
+    }
+
+    /// Run [`ARM_STUB`] on the mocked core, as if the DebugMonitor was entered with `dfsr` and
+    /// `demcr`, while the channel holds `Hello, world!`. The exception frame is on the process
+    /// stack if `process_stack` is set.
+    fn execute_stub(core: &mut Core, dfsr: u32, demcr: u32, process_stack: bool) {
+        core.halt(TIMEOUT).unwrap();
+
+        let data = b"Hello, world!";
+        core.write_32(
+            CHANNEL.into(),
+            &[0, CHANNEL_BUFFER, CHANNEL_SIZE, data.len() as u32, 0],
+        )
+        .unwrap();
+        core.write_8(CHANNEL_BUFFER.into(), data).unwrap();
+        core.write_32(STAGING.into(), &[STAGING_MAGIC, STAGING_SIZE, 0, 0, 0, 0])
+            .unwrap();
+
+        let parameters = StubParameters {
+            channel: CHANNEL,
+            staging: STAGING,
+            size: STAGING_SIZE,
+        };
+        core.write_8(STUB.into(), &ARM_STUB).unwrap();
+        core.write_32((STUB + ARM_STUB.len() as u32).into(), &parameters.words())
+            .unwrap();
+
+        // The stacked PC is the address of the instruction which caused the entry.
+        let mut frame = [0; 8];
+        frame[6] = FIRMWARE_BKPT;
+        core.write_32(FRAME.into(), &frame).unwrap();
+
+        // udf #0xfe
+        core.write_32(RETURN.into(), &[0xdefe_defe; 2]).unwrap();
+        core.write_word_32(DFSR.into(), dfsr).unwrap();
+        core.write_word_32(Demcr::ADDRESS, demcr).unwrap();
+
+        // Bit 2 of EXC_RETURN selects the process stack. Here, the stub returns to `RETURN`
+        // instead, or the word after it.
+        let (lr, sp) = if process_stack {
+            core.write_core_reg(RegisterId(18), FRAME).unwrap();
+            (RETURN | 0b101, FRAME - 0x100)
+        } else {
+            (RETURN | 1, FRAME)
+        };
+        core.write_core_reg(RegisterId(13), sp).unwrap();
+        core.write_core_reg(RegisterId(14), lr).unwrap();
+        core.write_core_reg(RegisterId(15), STUB).unwrap();
+
+        // Resume the core, which runs the stub until it returns.
+        let mut dhcsr = Dhcsr::from(0);
+        dhcsr.enable_write();
+        dhcsr.set_c_debugen(true);
+        core.write_word_32(Dhcsr::ADDRESS, dhcsr.into()).unwrap();
+    }
+
+    #[test]
+    fn stub_skips_breakpoints_of_the_firmware() {
+        for process_stack in [false, true] {
+            let mut session = attach();
+            let mut core = session.core(0).unwrap();
+
+            // DWTTRAP and BKPT
+            execute_stub(&mut core, 0b110, 0, process_stack);
+
+            let mut buf = [0; 32];
+            let drained = drain_staging(&mut core, STAGING, STAGING_SIZE, &mut buf).unwrap();
+            assert_eq!(&buf[..drained.count], b"Hello, world!");
+            assert_eq!(drained.breakpoints, 1);
+            assert_eq!(drained.last_breakpoint, FIRMWARE_BKPT);
+
+            // The data was released, the firmware continues after the BKPT, and the causes
+            // of the entry are cleared.
+            assert_eq!(core.read_word_32((CHANNEL + 16).into()).unwrap(), 13);
+            assert_eq!(
+                core.read_word_32((FRAME + 24).into()).unwrap(),
+                FIRMWARE_BKPT + 2
+            );
+            assert_eq!(core.read_word_32(DFSR.into()).unwrap() & 0b111, 0);
+
+            core.halt(TIMEOUT).unwrap();
+            let pc: u32 = core.read_core_reg(RegisterId(15)).unwrap();
+            assert_eq!(pc & !0b100, RETURN);
+            let sp: u32 = core.read_core_reg(RegisterId(13)).unwrap();
+            assert_eq!(sp, if process_stack { FRAME - 0x100 } else { FRAME });
+        }
+    }
+
+    #[test]
+    fn stub_ends_a_halting_step() {
+        let mut session = attach();
+        let mut core = session.core(0).unwrap();
+
+        let mut demcr = Demcr::from(0);
+        demcr.set_trcena(true);
+        demcr.set_mon_en(true);
+        demcr.set_mon_step(true);
+
+        // HALTED
+        execute_stub(&mut core, 0b1, demcr.into(), false);
+
+        let demcr = Demcr::from(core.read_word_32(Demcr::ADDRESS).unwrap());
+        assert!(!demcr.mon_step());
+        assert!(demcr.mon_en());
+        assert_eq!(core.read_word_32(DFSR.into()).unwrap() & 0b111, 0);
+
+        // The stacked PC is only changed for a BKPT.
+        assert_eq!(
+            core.read_word_32((FRAME + 24).into()).unwrap(),
+            FIRMWARE_BKPT
+        );
+
+        let mut buf = [0; 32];
+        let drained = drain_staging(&mut core, STAGING, STAGING_SIZE, &mut buf).unwrap();
+        assert_eq!(&buf[..drained.count], b"Hello, world!");
+        assert_eq!(drained.breakpoints, 0);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::Error;
-use probe_rs::{config::MemoryRegion, Core, MemoryInterface};
+use probe_rs::{config::MemoryRegion, CircularBuffer, Core, MemoryInterface};
 use scroll::{Pread, LE};
 use std::cmp::min;
 
@@ -47,7 +47,7 @@ impl Channel {
     const O_NAME: usize = 0;
     const O_BUFFER_PTR: usize = 4;
     const O_SIZE: usize = 8;
-    const O_WRITE: usize = 12;
+    pub(crate) const O_WRITE: usize = 12;
     const O_READ: usize = 16;
     const O_FLAGS: usize = 20;
 
@@ -98,6 +98,11 @@ impl Channel {
         }
     }
 
+    /// Returns the address of the channel structure in target memory.
+    pub(crate) fn ptr(&self) -> u32 {
+        self.ptr
+    }
+
     pub fn name(&self) -> Option<&str> {
         self.name.as_ref().map(|s| s.as_ref())
     }
@@ -158,6 +163,21 @@ impl UpChannel {
         self.0.buffer_size()
     }
 
+    /// Returns the layout of the ring buffer of the channel, to drain it while the session is
+    /// used, e.g. while the core is halted at a breakpoint. See
+    /// [`Session::register_drain`](probe_rs::Session::register_drain).
+    ///
+    /// The channel must not be read with [`UpChannel::read`] while it is drained.
+    pub fn drain_buffer(&self) -> CircularBuffer {
+        CircularBuffer::new(
+            self.0.buffer_ptr.into(),
+            self.0.size.into(),
+            (self.0.ptr + Channel::O_WRITE as u32).into(),
+        )
+        .core(self.0.core_id)
+        .tail((self.0.ptr + Channel::O_READ as u32).into())
+    }
+
     /// Reads the current channel mode from the target and returns its.
     ///
     /// See [`ChannelMode`] for more information on what the modes mean.
@@ -43,6 +43,9 @@
 
 use thiserror::Error;
 
+mod accelerated;
+pub use accelerated::*;
+
 mod channel;
 pub use channel::*;
 
@@ -77,6 +80,10 @@ pub enum Error {
     #[error("Incorrect Core number specified for this operation. Expected {0}, and found {1}")]
     IncorrectCoreSpecified(usize, usize),
 
+    /// The channel with this number does not exist.
+    #[error("RTT channel {0} does not exist")]
+    ChannelNotFound(usize),
+
     /// Wraps errors propagated up from probe-rs.
     #[error("Error communicating with probe: {0}")]
     Probe(#[from] probe_rs::Error),
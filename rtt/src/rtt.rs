@@ -1,6 +1,6 @@
 use crate::channel::*;
 use crate::{Channels, Error};
-use probe_rs::{config::MemoryRegion, Core, MemoryInterface};
+use probe_rs::{config::MemoryRegion, Core, MemoryInterface, SearchOptions};
 use scroll::{Pread, LE};
 use std::borrow::Cow;
 use std::collections::BTreeMap;
@@ -205,7 +205,6 @@ impl Rtt {
             }
         };
 
-        let mut mem: Vec<u8> = Vec::new();
         let mut instances: Vec<Rtt> = Vec::new();
 
         for range in ranges.iter() {
@@ -213,18 +212,14 @@ impl Rtt {
                 continue;
             }
 
-            mem.resize(range.len(), 0);
-            {
-                core.read(range.start.into(), mem.as_mut())?;
-            }
+            // The control block contains 32 bit fields, so it is aligned to 4 bytes.
+            let range = u64::from(range.start)..u64::from(range.end);
+            let candidates = core
+                .search_memory(range, &Self::RTT_ID, SearchOptions::new().alignment(4))
+                .collect::<Result<Vec<_>, _>>()?;
 
-            for offset in 0..(mem.len() - Self::MIN_SIZE) {
-                if let Some(rtt) = Rtt::from(
-                    core,
-                    memory_map,
-                    range.start + offset as u32,
-                    Some(&mem[offset..]),
-                )? {
+            for address in candidates {
+                if let Some(rtt) = Rtt::from(core, memory_map, address as u32, None)? {
                     instances.push(rtt);
 
                     if instances.len() >= 5 {
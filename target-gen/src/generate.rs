@@ -10,7 +10,9 @@ use probe_rs::config::{
     Chip, ChipFamily, Core as ProbeCore, MemoryRegion, NvmRegion, RamRegion, RawFlashAlgorithm,
 };
 use probe_rs::{Architecture, CoreType};
-use probe_rs_target::{ArmCoreAccessOptions, CoreAccessOptions, RiscvCoreAccessOptions};
+use probe_rs_target::{
+    ArmCoreAccessOptions, CoreAccessOptions, ResetScope, RiscvCoreAccessOptions,
+};
 use tokio::runtime::Builder;
 
 pub(crate) enum Kind<'a, T>
@@ -47,6 +49,8 @@ where
                 manufacturer: None,
                 variants: Vec::new(),
                 flash_algorithms: Vec::new(),
+                peripheral_freeze: Vec::new(),
+                slow_clock_attach: false,
                 source: probe_rs::config::TargetDescriptionSource::BuiltIn,
             });
             // This unwrap is always safe as we insert at least one item previously.
@@ -138,6 +142,9 @@ where
             cores,
             memory_map,
             flash_algorithms: flash_algorithm_names,
+            keepalive: None,
+            errata: vec![],
+            mediated_regions: vec![],
         });
     }
 
@@ -160,8 +167,11 @@ fn create_core(processor: &Processor) -> Result<ProbeCore> {
                 debug_base: None,
                 cti_base: None,
             }),
-            Architecture::Riscv => CoreAccessOptions::Riscv(RiscvCoreAccessOptions {}),
+            Architecture::Riscv => CoreAccessOptions::Riscv(RiscvCoreAccessOptions::default()),
         },
+        reset_scope: ResetScope::default(),
+        hardware_breakpoints: None,
+        watchpoints: None,
     })
 }
 
@@ -394,6 +404,8 @@ pub(crate) fn get_ram(device: &Device) -> Option<RamRegion> {
                 is_boot_memory: memory.startup,
                 cores: vec!["main".to_owned()],
                 name: None,
+                volatile: false,
+                ecc: false,
             });
         }
     }
@@ -431,6 +443,8 @@ pub(crate) fn get_flash(device: &Device) -> Option<NvmRegion> {
                 is_boot_memory: memory.startup,
                 cores: vec!["main".to_owned()],
                 name: None,
+                boot_critical_ranges: vec![],
+                target_writable: false,
             });
         }
     }
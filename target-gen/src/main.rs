@@ -19,7 +19,7 @@ use probe_rs::{
     },
     CoreType,
 };
-use probe_rs_target::{ArmCoreAccessOptions, CoreAccessOptions};
+use probe_rs_target::{ArmCoreAccessOptions, CoreAccessOptions, ResetScope};
 use simplelog::*;
 
 use parser::extract_flash_algo;
@@ -169,6 +169,9 @@ fn cmd_elf(
                         debug_base: None,
                         cti_base: None,
                     }),
+                    reset_scope: ResetScope::default(),
+                    hardware_breakpoints: None,
+                    watchpoints: None,
                 }],
                 part: None,
                 name: "<chip name>".to_owned(),
@@ -178,17 +181,26 @@ fn cmd_elf(
                         range: 0..0x2000,
                         cores: vec!["main".to_owned()],
                         name: None,
+                        boot_critical_ranges: vec![],
+                        target_writable: false,
                     }),
                     MemoryRegion::Ram(RamRegion {
                         is_boot_memory: true,
                         range: 0x1_0000..0x2_0000,
                         cores: vec!["main".to_owned()],
                         name: None,
+                        volatile: false,
+                        ecc: false,
                     }),
                 ],
                 flash_algorithms: vec![algorithm_name],
+                keepalive: None,
+                errata: vec![],
+                mediated_regions: vec![],
             }],
             flash_algorithms: vec![algorithm],
+            peripheral_freeze: vec![],
+            slow_clock_attach: false,
             source: BuiltIn,
         };
 
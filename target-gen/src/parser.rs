@@ -91,6 +91,9 @@ pub fn extract_flash_algo(
             "EraseChip" => algo.pc_erase_all = Some(sym.st_value - code_section_offset as u64),
             "EraseSector" => algo.pc_erase_sector = sym.st_value - code_section_offset as u64,
             "ProgramPage" => algo.pc_program_page = sym.st_value - code_section_offset as u64,
+            "ProgramPageCompressed" => {
+                algo.pc_program_page_compressed = Some(sym.st_value - code_section_offset as u64)
+            }
             _ => {}
         }
     }